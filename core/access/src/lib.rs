@@ -0,0 +1,165 @@
+//! AuthorWorks Access
+//!
+//! Request checks shared by every service, so a credential means the same
+//! thing whichever service it reaches.
+//!
+//! The gateway resolves each bearer token through the user service's
+//! `POST /auth/introspect` and forwards the caller as `X-User-Id`. API keys,
+//! share tokens and collaborator roles are delegated credentials: their scopes
+//! come along as the space-separated `X-Scopes` header, and the gateway drops
+//! any `X-Scopes` or `X-User-Id` the client sent itself. A request without
+//! `X-Scopes` is a first-party session and keeps full access.
+//!
+//! A delegated request may only reach routes the service maps to a scope it
+//! holds. Anything a service leaves unmapped is refused, so new routes stay
+//! closed to API keys until someone decides which scope covers them.
+//!
+//! ```ignore
+//! fn route_scope(method: &Method, path: &str) -> Option<Route> {
+//!     match (method, path) {
+//!         (Method::Get, p) if p.starts_with("/books/") => Some(Route::Scope(scopes::BOOKS_READ)),
+//!         _ => None,
+//!     }
+//! }
+//!
+//! if let Err(e) = authorworks_access::authorize(&req, route_scope) {
+//!     return Ok(ServiceError::from(e).into_response());
+//! }
+//! ```
+//!
+//! Service-to-service endpoints are for no user at all, delegated or not.
+//! [`require_internal`] admits only callers presenting the
+//! `internal_service_token` Spin variable as `X-Internal-Token`; the gateway
+//! never proxies those routes.
+//!
+//! Admin routes take a signed-in user holding one of [`ADMIN_ROLES`] in
+//! `users.user_roles`; [`require_admin`] is the one check every service uses,
+//! so a support user gets the same answer everywhere.
+
+use spin_sdk::http::{Method, Request};
+use spin_sdk::pg::{Connection, ParameterValue};
+use spin_sdk::variables;
+use uuid::Uuid;
+
+/// Platform roles that open admin routes
+pub const ADMIN_ROLES: [&str; 2] = ["admin", "support"];
+
+/// Scope names, as stored on API keys and carried in `X-Scopes`
+pub mod scopes {
+    pub const BOOKS_READ: &str = "books:read";
+    pub const BOOKS_WRITE: &str = "books:write";
+    pub const CHAPTERS_READ: &str = "chapters:read";
+    pub const CHAPTERS_WRITE: &str = "chapters:write";
+    pub const COMMENTS_READ: &str = "comments:read";
+    pub const COMMENTS_WRITE: &str = "comments:write";
+    pub const EXPORTS_CREATE: &str = "exports:create";
+    pub const ANALYTICS_READ: &str = "analytics:read";
+}
+
+/// How a delegated credential may reach a route
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    /// Reachable by anyone; the route has its own gate (public pages,
+    /// signed share links, feedback tokens, webhook signatures)
+    Open,
+    /// Needs this scope
+    Scope(&'static str),
+}
+
+impl Route {
+    /// `read` for GET, `write` for everything else
+    pub fn read_write(method: &Method, read: &'static str, write: &'static str) -> Route {
+        Route::Scope(if matches!(method, Method::Get | Method::Head) { read } else { write })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AccessError {
+    /// The route has no scope mapping
+    #[error("Route not available to scoped credentials")]
+    NotDelegable,
+
+    #[error("Missing scope: {0}")]
+    MissingScope(&'static str),
+
+    /// The caller is not another service
+    #[error("Internal service credential required")]
+    NotInternal,
+
+    /// The caller holds none of `ADMIN_ROLES`
+    #[error("Admin role required")]
+    NotAdmin,
+
+    /// The role lookup itself failed; not the caller's fault
+    #[error("Role lookup failed: {0}")]
+    RoleLookup(String),
+}
+
+/// Scopes the request was delegated, or `None` for a first-party session
+pub fn granted_scopes(req: &Request) -> Option<&str> {
+    req.header("X-Scopes").and_then(|h| h.as_str())
+}
+
+/// Reject a delegated request whose scopes do not cover the route.
+/// `route_scope` maps the service's routes; `None` means not delegable.
+pub fn authorize(req: &Request, route_scope: fn(&Method, &str) -> Option<Route>) -> Result<(), AccessError> {
+    let granted = match granted_scopes(req) {
+        Some(granted) => granted,
+        None => return Ok(()),
+    };
+
+    let method = req.method();
+    let path = req.path().split('?').next().unwrap_or_default();
+    if matches!(method, Method::Options) || path == "/health" || path == "/" {
+        return Ok(());
+    }
+
+    match route_scope(method, path) {
+        Some(Route::Open) => Ok(()),
+        Some(Route::Scope(required)) if granted.split_whitespace().any(|s| s == required) => Ok(()),
+        Some(Route::Scope(required)) => Err(AccessError::MissingScope(required)),
+        None => Err(AccessError::NotDelegable),
+    }
+}
+
+/// Reject a caller without the internal service credential. With
+/// `internal_service_token` unset every caller is refused.
+pub fn require_internal(req: &Request) -> Result<(), AccessError> {
+    let expected = variables::get("internal_service_token")
+        .ok()
+        .filter(|token| !token.is_empty())
+        .ok_or(AccessError::NotInternal)?;
+    let presented = req.header("X-Internal-Token")
+        .and_then(|h| h.as_str())
+        .unwrap_or_default();
+
+    if constant_time_eq(presented.as_bytes(), expected.as_bytes()) {
+        Ok(())
+    } else {
+        Err(AccessError::NotInternal)
+    }
+}
+
+/// Whether the user holds one of `ADMIN_ROLES`
+pub fn is_admin(conn: &Connection, user_id: &Uuid) -> Result<bool, AccessError> {
+    let query = "SELECT 1 FROM users.user_roles WHERE user_id = $1 AND role = ANY(string_to_array($2, ','))";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(ADMIN_ROLES.join(",")),
+    ]).map_err(|e| AccessError::RoleLookup(e.to_string()))?;
+
+    Ok(!rows.rows.is_empty())
+}
+
+/// Reject a caller without one of `ADMIN_ROLES`
+pub fn require_admin(conn: &Connection, user_id: &Uuid) -> Result<(), AccessError> {
+    if is_admin(conn, user_id)? {
+        Ok(())
+    } else {
+        Err(AccessError::NotAdmin)
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -19,6 +19,9 @@ pub struct Chapter {
     #[serde(bound(deserialize = "Scene: Deserialize<'de>"))]
     pub scenes: Vec<Scene>,
     pub content: String,
+    /// Numbers of conceptually related chapters, rendered as cross-links
+    #[serde(default)]
+    pub related_chapters: Vec<usize>,
 }
 
 impl Chapter {
@@ -142,6 +145,7 @@ impl Chapter {
             outline,
             scenes: Vec::new(),
             content: output.generation,
+            related_chapters: Vec::new(),
         })
     }
 
@@ -172,6 +176,7 @@ impl Chapter {
             outline: ChapterOutline::default(),
             scenes: Vec::new(),
             content: String::new(),
+            related_chapters: Vec::new(),
         }
     }
 }
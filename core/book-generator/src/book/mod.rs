@@ -540,6 +540,7 @@ impl Book {
                             outline: chapter_outline,
                             scenes: Vec::new(),
                             content,
+                            related_chapters: Vec::new(),
                         }
                     };
                     
@@ -29,7 +29,7 @@ pub enum Error {
 /// Client for LLM API interactions
 pub struct Client {
     anthropic: Option<client::Client>,
-    _openai: Option<reqwest::Client>,
+    openai: Option<reqwest::Client>,
 }
 
 /// Response from LLM generation
@@ -67,7 +67,7 @@ pub fn create_client() -> std::result::Result<Client, Error> {
     
     Ok(Client {
         anthropic,
-        _openai: openai,
+        openai,
     })
 }
 
@@ -134,6 +134,58 @@ impl Client {
             })
         }
     }
+
+    /// Embed each input with an OpenAI-compatible `/embeddings` endpoint.
+    ///
+    /// The endpoint comes from `EMBEDDINGS_API_URL` (default
+    /// `https://api.openai.com/v1`), so Ollama's `/v1` API works as well; the
+    /// key comes from `EMBEDDINGS_API_KEY` or `OPENAI_API_KEY` when set.
+    pub async fn embed(&self, model: &str, inputs: &[String]) -> std::result::Result<Vec<Vec<f32>>, Error> {
+        let client = self.openai.as_ref()
+            .ok_or_else(|| Error::ClientError("No HTTP client available for embeddings".to_string()))?;
+
+        let base_url = std::env::var("EMBEDDINGS_API_URL")
+            .unwrap_or_else(|_| "https://api.openai.com/v1".to_string());
+        let api_key = std::env::var("EMBEDDINGS_API_KEY")
+            .or_else(|_| std::env::var("OPENAI_API_KEY"))
+            .ok();
+
+        let mut request = client
+            .post(format!("{}/embeddings", base_url.trim_end_matches('/')))
+            .json(&serde_json::json!({ "model": model, "input": inputs }));
+        if let Some(key) = api_key.filter(|k| !k.is_empty()) {
+            request = request.bearer_auth(key);
+        }
+
+        let response = request.send()
+            .await
+            .map_err(|e| Error::ApiError(e.to_string()))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(Error::ApiError(format!("Embeddings request failed with status {}: {}", status, body)));
+        }
+
+        let body: serde_json::Value = response.json()
+            .await
+            .map_err(|e| Error::ApiError(e.to_string()))?;
+
+        // Results carry their input index; don't rely on response order
+        let mut embeddings = vec![Vec::new(); inputs.len()];
+        for item in body["data"].as_array().into_iter().flatten() {
+            let index = item["index"].as_u64().unwrap_or(0) as usize;
+            if let Some(slot) = embeddings.get_mut(index) {
+                *slot = item["embedding"].as_array()
+                    .map(|values| values.iter().filter_map(|v| v.as_f64()).map(|v| v as f32).collect())
+                    .unwrap_or_default();
+            }
+        }
+        if embeddings.iter().any(|e| e.is_empty()) {
+            return Err(Error::ApiError("Embeddings response was missing vectors".to_string()));
+        }
+
+        Ok(embeddings)
+    }
 }
 
 pub fn create_llm(config: &Config) -> Result<Box<dyn langchain_rust::language_models::llm::LLM>> {
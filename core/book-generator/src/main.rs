@@ -554,6 +554,7 @@ async fn handle_resume_command(book_name: &str) -> Result<(), Box<dyn std::error
                                 outline: chapter_outline.clone(),
                                 scenes: Vec::new(),
                                 content: String::new(),
+                                related_chapters: Vec::new(),
                             };
                             book.add_chapter(chapter);
                         }
@@ -72,7 +72,7 @@ impl BookRenderer {
 
         // Write chapter files (both to book and src directories)
         for (i, chapter) in book.chapters.iter().enumerate() {
-            let chapter_content = self.format_chapter(chapter);
+            let chapter_content = self.format_chapter(book, chapter);
             let chapter_filename = format!("chapter_{}.md", i + 1);
             
             // Write to book directory
@@ -166,12 +166,37 @@ impl BookRenderer {
         summary
     }
 
-    fn format_chapter(&self, chapter: &Chapter) -> String {
+    fn format_chapter(&self, book: &Book, chapter: &Chapter) -> String {
         let mut content = format!("# {}\n\n", chapter.title);
         content.push_str(&format!("*{}*\n\n", chapter.outline.description));
         for scene in chapter.scenes.iter() {
             content.push_str(&self.format_scene(scene));
         }
+        content.push_str(&self.format_related_chapters(book, chapter));
+        content
+    }
+
+    /// Cross-links to related chapters. Links point at the chapter files so
+    /// mdBook resolves them to pages; `generate_pdf_and_epub` rewrites them
+    /// to in-document anchors.
+    fn format_related_chapters(&self, book: &Book, chapter: &Chapter) -> String {
+        let links: Vec<String> = chapter.related_chapters.iter()
+            .filter(|&&number| number != chapter.number)
+            .filter_map(|&number| {
+                let index = book.chapters.iter().position(|c| c.number == number)?;
+                let related = &book.chapters[index];
+                Some(format!("- [Chapter {}: {}](chapter_{}.md)\n", number, related.title, index + 1))
+            })
+            .collect();
+
+        if links.is_empty() {
+            return String::new();
+        }
+
+        // A bold label rather than a heading keeps it out of the table of contents
+        let mut content = String::from("**Related chapters**\n\n");
+        content.extend(links);
+        content.push('\n');
         content
     }
 
@@ -332,10 +357,12 @@ pub fn generate_pdf_and_epub(output_dir: &Path, book_title: &str, author: &str)
             
             // Format chapter heading properly with consistent numbering
             // Check if the clean title already starts with "Chapter X" to avoid duplication
+            // The explicit id is the target of related-chapter links
+            let anchor = chapter_anchor(chapter_file_name);
             let chapter_heading = if clean_chapter_title.starts_with(&format!("Chapter {}", chapter_num)) {
-                format!("# {}\n\n", clean_chapter_title)
+                format!("# {} {{#{}}}\n\n", clean_chapter_title, anchor)
             } else {
-                format!("# Chapter {}: {}\n\n", chapter_num, clean_chapter_title)
+                format!("# Chapter {}: {} {{#{}}}\n\n", chapter_num, clean_chapter_title, anchor)
             };
             full_content.push_str(&chapter_heading);
             
@@ -378,6 +405,15 @@ pub fn generate_pdf_and_epub(output_dir: &Path, book_title: &str, author: &str)
         }
     }
     
+    // Chapter files are merged into one document, so related-chapter links
+    // point at the chapter headings instead
+    for (_, chapter_file_name) in &chapters {
+        full_content = full_content.replace(
+            &format!("]({})", chapter_file_name),
+            &format!("](#{})", chapter_anchor(chapter_file_name)),
+        );
+    }
+
     // Write the full content to a temporary file
     println!("Writing content to temporary file: {:?}", temp_file_path);
     fs::write(&temp_file_path, full_content)?;
@@ -748,6 +784,11 @@ div.copyright-page p {
     Ok(())
 }
 
+/// Heading id for a chapter file in the combined PDF/EPUB document
+fn chapter_anchor(chapter_file_name: &str) -> String {
+    chapter_file_name.trim_end_matches(".md").replace('_', "-")
+}
+
 /// Process chapter content to remove duplicate headings and format appropriately
 fn process_chapter_content(content: &str) -> String {
     // Helper function to extract clean scene title
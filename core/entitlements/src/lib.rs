@@ -0,0 +1,158 @@
+//! AuthorWorks Entitlements
+//!
+//! Client for the subscription service's `POST /entitlements/check`, so
+//! services gate features on the same plan rules, including the grace period
+//! for `past_due` accounts, instead of reading `subscriptions` themselves.
+//!
+//! ```ignore
+//! match authorworks_entitlements::require(&user_id, features::BOOKS, 1) {
+//!     Ok(_) => {}
+//!     Err(EntitlementError::Denied(e)) => return Err(ServiceError::PaymentRequired(e.denial_message())),
+//!     Err(EntitlementError::Unavailable(_)) => {} // fail open, or closed, as the caller decides
+//! }
+//! ```
+//!
+//! The subscription service is reached at the `subscription_service_url`
+//! Spin variable.
+
+use serde::{Deserialize, Serialize};
+use spin_sdk::outbound_http::{self, Method as HttpMethod, Request as OutboundRequest};
+use spin_sdk::variables;
+use uuid::Uuid;
+
+const DEFAULT_SUBSCRIPTION_URL: &str = "http://subscription-service:3105";
+
+/// Feature keys understood by the subscription service. Limit features take
+/// a quantity; anything else is a plan flag.
+pub mod features {
+    pub const BOOKS: &str = "books";
+    /// Per book: pass the book's chapter count as current usage
+    pub const CHAPTERS_PER_BOOK: &str = "chapters_per_book";
+    pub const AI_WORDS: &str = "ai_words";
+    pub const STORAGE_BYTES: &str = "storage_bytes";
+    /// Per book: pass the book's collaborator count as current usage
+    pub const COLLABORATORS: &str = "collaborators";
+
+    pub const COLLABORATION: &str = "collaboration";
+    pub const VERSION_HISTORY: &str = "version_history";
+    pub const EXPORT_ALL_FORMATS: &str = "export_all_formats";
+    pub const API_ACCESS: &str = "api_access";
+    pub const SSO: &str = "sso";
+
+    /// Granted unless the account is read-only (paused); for writes with no
+    /// limit of their own
+    pub const WRITE: &str = "write";
+}
+
+/// Answer from the subscription service
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entitlement {
+    pub feature: String,
+    pub allowed: bool,
+    /// `granted`, `limit_exceeded`, `not_in_plan`, `grace_expired` or `paused`
+    pub reason: String,
+    pub quantity: i64,
+    /// -1 for unlimited; absent for flags
+    pub limit: Option<i64>,
+    pub used: Option<i64>,
+    pub remaining: Option<i64>,
+    #[serde(default)]
+    pub overage: bool,
+    pub plan_id: String,
+    pub effective_plan_id: String,
+    pub subscription_status: String,
+    #[serde(default)]
+    pub in_grace_period: bool,
+    pub grace_period_ends_at: Option<String>,
+}
+
+impl Entitlement {
+    /// User-facing explanation of a refusal
+    pub fn denial_message(&self) -> String {
+        match self.reason.as_str() {
+            "grace_expired" => format!(
+                "Your payment is overdue, so '{}' is limited to the free plan. Update your payment method to restore it.",
+                self.feature
+            ),
+            "paused" => "Your subscription is paused, so your account is read-only until it resumes.".to_string(),
+            "limit_exceeded" => format!(
+                "Your {} plan allows {} {}. Upgrade to add more.",
+                self.effective_plan_id,
+                self.limit.unwrap_or(0),
+                self.feature.replace('_', " ")
+            ),
+            _ => format!("'{}' is not included in your {} plan", self.feature, self.effective_plan_id),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EntitlementError {
+    /// The plan does not cover the request
+    #[error("Not entitled: {}", .0.denial_message())]
+    Denied(Entitlement),
+
+    /// The subscription service could not answer
+    #[error("Entitlement check unavailable: {0}")]
+    Unavailable(String),
+}
+
+/// Check a feature for a user; a refusal is a normal `Ok` answer
+pub fn check(user_id: &Uuid, feature: &str, quantity: i64) -> Result<Entitlement, EntitlementError> {
+    send_check(user_id, feature, quantity, None)
+}
+
+/// Like [`check`] for per-book limits, where the caller knows current usage
+pub fn check_with_usage(user_id: &Uuid, feature: &str, quantity: i64, current_usage: i64) -> Result<Entitlement, EntitlementError> {
+    send_check(user_id, feature, quantity, Some(current_usage))
+}
+
+/// Check a feature and turn a refusal into [`EntitlementError::Denied`]
+pub fn require(user_id: &Uuid, feature: &str, quantity: i64) -> Result<Entitlement, EntitlementError> {
+    allowed(check(user_id, feature, quantity)?)
+}
+
+/// Like [`require`] for per-book limits, where the caller knows current usage
+pub fn require_with_usage(user_id: &Uuid, feature: &str, quantity: i64, current_usage: i64) -> Result<Entitlement, EntitlementError> {
+    allowed(check_with_usage(user_id, feature, quantity, current_usage)?)
+}
+
+fn allowed(entitlement: Entitlement) -> Result<Entitlement, EntitlementError> {
+    if entitlement.allowed {
+        Ok(entitlement)
+    } else {
+        Err(EntitlementError::Denied(entitlement))
+    }
+}
+
+fn send_check(user_id: &Uuid, feature: &str, quantity: i64, current_usage: Option<i64>) -> Result<Entitlement, EntitlementError> {
+    let subscription_url = variables::get("subscription_service_url")
+        .unwrap_or_else(|_| DEFAULT_SUBSCRIPTION_URL.to_string());
+
+    let body = serde_json::json!({
+        "feature": feature,
+        "quantity": quantity,
+        "current_usage": current_usage
+    });
+
+    let request = OutboundRequest::builder()
+        .method(HttpMethod::Post)
+        .uri(format!("{}/entitlements/check", subscription_url))
+        .header("Content-Type", "application/json")
+        .header("X-User-Id", user_id.to_string())
+        .body(serde_json::to_vec(&body).unwrap_or_default())
+        .build();
+
+    let response = outbound_http::send(request)
+        .map_err(|e| EntitlementError::Unavailable(format!("Request failed: {}", e)))?;
+    if response.status().as_u16() != 200 {
+        return Err(EntitlementError::Unavailable(format!(
+            "Subscription service returned {}: {}",
+            response.status().as_u16(),
+            String::from_utf8_lossy(response.body())
+        )));
+    }
+
+    serde_json::from_slice(response.body())
+        .map_err(|e| EntitlementError::Unavailable(format!("Invalid response: {}", e)))
+}
@@ -0,0 +1,36 @@
+-- Migration: 003 - Subscription Dunning
+-- Description: Tracks failed subscription payments through the reminder/downgrade schedule
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- DUNNING TABLES
+--=============================================================================
+
+-- One row per failed-payment episode; at most one 'active' row per user
+CREATE TABLE IF NOT EXISTS subscriptions.dunning (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    stripe_subscription_id VARCHAR(255) NOT NULL,
+    stripe_invoice_id VARCHAR(255),
+    status VARCHAR(50) NOT NULL DEFAULT 'active',  -- 'active', 'recovered', 'downgraded'
+    failure_count INTEGER NOT NULL DEFAULT 1,
+    current_step INTEGER NOT NULL DEFAULT 0,
+    started_at TIMESTAMPTZ DEFAULT NOW(),
+    last_failure_at TIMESTAMPTZ DEFAULT NOW(),
+    next_action_at TIMESTAMPTZ,
+    resolved_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_dunning_user_active ON subscriptions.dunning(user_id) WHERE status = 'active';
+CREATE INDEX IF NOT EXISTS idx_dunning_next_action ON subscriptions.dunning(next_action_at) WHERE status = 'active';
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 003_subscription_dunning.sql completed successfully';
+END $$;
@@ -0,0 +1,44 @@
+-- Migration: 004 - Editor Block IDs
+-- Description: Stable per-paragraph IDs for anchoring comments, reactions, and analytics
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- BLOCK IDS
+--=============================================================================
+
+-- Ordered array of block IDs, one per '\n'-separated block of content
+ALTER TABLE editor.documents
+ADD COLUMN IF NOT EXISTS block_ids JSONB DEFAULT '[]';
+
+ALTER TABLE editor.checkpoints
+ADD COLUMN IF NOT EXISTS block_ids JSONB DEFAULT '[]';
+
+-- Comments anchor to a block; offsets remain for in-block highlighting
+ALTER TABLE editor.comments
+ADD COLUMN IF NOT EXISTS block_id VARCHAR(32);
+
+--=============================================================================
+-- BLOCK REACTIONS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS editor.block_reactions (
+    document_id UUID NOT NULL REFERENCES editor.documents(id) ON DELETE CASCADE,
+    block_id VARCHAR(32) NOT NULL,
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    reaction VARCHAR(32) NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (document_id, block_id, user_id, reaction)
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_comments_block ON editor.comments(document_id, block_id);
+CREATE INDEX IF NOT EXISTS idx_block_reactions_block ON editor.block_reactions(document_id, block_id);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 004_editor_block_ids.sql completed successfully';
+END $$;
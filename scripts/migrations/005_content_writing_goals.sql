@@ -0,0 +1,39 @@
+-- Migration: 005 - Writing Goals
+-- Description: Per-book word targets and daily writing sessions for streak tracking
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- WRITING GOALS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS content.writing_goals (
+    book_id UUID PRIMARY KEY REFERENCES content.books(id) ON DELETE CASCADE,
+    target_word_count INTEGER NOT NULL,
+    deadline DATE,
+    daily_target INTEGER,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- One row per user, book, and UTC day; populated on chapter create/update
+CREATE TABLE IF NOT EXISTS content.writing_sessions (
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    book_id UUID NOT NULL REFERENCES content.books(id) ON DELETE CASCADE,
+    session_date DATE NOT NULL,
+    words_added INTEGER NOT NULL DEFAULT 0,
+    words_removed INTEGER NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (user_id, book_id, session_date)
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_writing_sessions_book ON content.writing_sessions(book_id, session_date DESC);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 005_content_writing_goals.sql completed successfully';
+END $$;
@@ -0,0 +1,25 @@
+-- Migration: 006 - Editor Undo/Redo
+-- Description: Stores operation inverses and undo/redo links for server-side undo
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- OPERATION UNDO METADATA
+--=============================================================================
+
+ALTER TABLE editor.operations
+ADD COLUMN IF NOT EXISTS inverse JSONB,  -- NULL for operations that cannot be undone (reverts)
+ADD COLUMN IF NOT EXISTS undo_of UUID REFERENCES editor.operations(id) ON DELETE SET NULL,
+ADD COLUMN IF NOT EXISTS redo_of UUID REFERENCES editor.operations(id) ON DELETE SET NULL,
+ADD COLUMN IF NOT EXISTS undone BOOLEAN DEFAULT FALSE;
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_operations_user ON editor.operations(document_id, user_id, version DESC);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 006_editor_undo_redo.sql completed successfully';
+END $$;
@@ -0,0 +1,46 @@
+-- Migration: 007 - Messaging Announcements
+-- Description: Audit table for broadcast announcements and per-user dismissal state
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- ANNOUNCEMENTS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS messaging.announcements (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    created_by UUID REFERENCES users.users(id) ON DELETE SET NULL,
+    title VARCHAR(255) NOT NULL,
+    body TEXT NOT NULL,
+    segment VARCHAR(50) NOT NULL,  -- all, plan, active
+    plan_id VARCHAR(50),
+    data JSONB DEFAULT '{}',
+    recipient_count BIGINT DEFAULT 0,
+    expires_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    completed_at TIMESTAMPTZ
+);
+
+CREATE TABLE IF NOT EXISTS messaging.announcement_dismissals (
+    announcement_id UUID NOT NULL REFERENCES messaging.announcements(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    dismissed_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (announcement_id, user_id)
+);
+
+-- Link fanned-out notifications back to their announcement
+ALTER TABLE messaging.notifications
+ADD COLUMN IF NOT EXISTS announcement_id UUID REFERENCES messaging.announcements(id) ON DELETE CASCADE;
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_announcements_created ON messaging.announcements(created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_notifications_announcement ON messaging.notifications(announcement_id, user_id)
+    WHERE announcement_id IS NOT NULL;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 007_messaging_announcements.sql completed successfully';
+END $$;
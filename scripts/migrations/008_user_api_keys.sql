@@ -0,0 +1,32 @@
+-- Migration: 008 - Scoped API Keys
+-- Description: API keys carrying a restricted scope list (books:read, chapters:write, ...)
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- API KEYS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS users.api_keys (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    prefix VARCHAR(20) NOT NULL,           -- Shown in listings to tell keys apart
+    key_hash VARCHAR(64) NOT NULL UNIQUE,  -- SHA-256 of the full key
+    scopes JSONB NOT NULL DEFAULT '[]',
+    expires_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_api_keys_user ON users.api_keys(user_id) WHERE revoked_at IS NULL;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 008_user_api_keys.sql completed successfully';
+END $$;
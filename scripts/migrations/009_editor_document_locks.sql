@@ -0,0 +1,26 @@
+-- Migration: 009 - Editor Document Locks
+-- Description: Advisory exclusive-editing locks with TTL
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- DOCUMENT LOCKS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS editor.document_locks (
+    document_id UUID PRIMARY KEY REFERENCES editor.documents(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    acquired_at TIMESTAMPTZ DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL  -- Renewed by heartbeat; expired rows count as unlocked
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_document_locks_user ON editor.document_locks(user_id);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 009_editor_document_locks.sql completed successfully';
+END $$;
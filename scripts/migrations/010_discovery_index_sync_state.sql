@@ -0,0 +1,19 @@
+-- Migration: 010 - Discovery Index Sync State
+-- Description: Tracks the last successful write per Elasticsearch index for drift reporting
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- INDEX SYNC STATE
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS discovery.index_sync_state (
+    index_name VARCHAR(100) PRIMARY KEY,
+    last_synced_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    documents_synced BIGINT DEFAULT 0
+);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 010_discovery_index_sync_state.sql completed successfully';
+END $$;
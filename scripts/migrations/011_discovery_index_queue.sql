@@ -0,0 +1,36 @@
+-- Migration: 011 - Discovery Index Queue
+-- Description: Persistent queue of Elasticsearch writes flushed in _bulk batches
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- INDEX QUEUE
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS discovery.index_queue (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    index_name VARCHAR(100) NOT NULL,
+    doc_id VARCHAR(255) NOT NULL,
+    action VARCHAR(20) NOT NULL CHECK (action IN ('index', 'delete')),
+    payload JSONB,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'processing', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+-- At most one pending write per document; newer writes replace it
+CREATE UNIQUE INDEX IF NOT EXISTS idx_index_queue_pending_doc
+    ON discovery.index_queue(index_name, doc_id) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_index_queue_due ON discovery.index_queue(status, next_attempt_at);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 011_discovery_index_queue.sql completed successfully';
+END $$;
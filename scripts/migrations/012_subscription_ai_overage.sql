@@ -0,0 +1,39 @@
+-- Migration: 012 - Subscription AI Overage
+-- Description: Opt-in metered billing for Pro AI words beyond the monthly allowance
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- OVERAGE SETTINGS
+--=============================================================================
+
+ALTER TABLE subscriptions.subscriptions
+ADD COLUMN IF NOT EXISTS overage_enabled BOOLEAN DEFAULT FALSE;
+
+-- Stripe subscription item for the metered overage price; kept when disabled
+ALTER TABLE subscriptions.subscriptions
+ADD COLUMN IF NOT EXISTS overage_stripe_item_id VARCHAR(255);
+
+--=============================================================================
+-- OVERAGE REPORTS
+--=============================================================================
+
+-- Overage words already reported to Stripe per usage period
+CREATE TABLE IF NOT EXISTS subscriptions.overage_reports (
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    period_start TIMESTAMPTZ NOT NULL,
+    words_reported BIGINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (user_id, period_start)
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_subscriptions_overage ON subscriptions.subscriptions(plan_id) WHERE overage_enabled = TRUE;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 012_subscription_ai_overage.sql completed successfully';
+END $$;
@@ -0,0 +1,31 @@
+-- Migration: 013 - Discovery Book Neighbors
+-- Description: Item-item collaborative filtering neighbors from reading co-occurrence
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- BOOK NEIGHBORS
+--=============================================================================
+
+-- Top "readers also read" books per book, rebuilt periodically
+CREATE TABLE IF NOT EXISTS discovery.book_neighbors (
+    book_id UUID NOT NULL REFERENCES content.books(id) ON DELETE CASCADE,
+    neighbor_id UUID NOT NULL REFERENCES content.books(id) ON DELETE CASCADE,
+    score DOUBLE PRECISION NOT NULL,
+    co_readers INTEGER NOT NULL,
+    computed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (book_id, neighbor_id)
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_book_neighbors_score ON discovery.book_neighbors(book_id, score DESC);
+CREATE INDEX IF NOT EXISTS idx_book_neighbors_computed ON discovery.book_neighbors(computed_at);
+CREATE INDEX IF NOT EXISTS idx_reading_history_user_book ON discovery.reading_history(user_id, book_id);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 013_discovery_book_neighbors.sql completed successfully';
+END $$;
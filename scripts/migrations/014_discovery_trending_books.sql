@@ -0,0 +1,27 @@
+-- Migration: 014 - Discovery Trending Books
+-- Description: Per-book seven-day read activity, refreshed periodically and on events
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- TRENDING BOOKS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS discovery.trending_books (
+    book_id UUID PRIMARY KEY REFERENCES content.books(id) ON DELETE CASCADE,
+    activity BIGINT NOT NULL DEFAULT 0,
+    refreshed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_trending_books_activity ON discovery.trending_books(activity DESC);
+CREATE INDEX IF NOT EXISTS idx_reading_history_book_time ON discovery.reading_history(book_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_book_neighbors_neighbor ON discovery.book_neighbors(neighbor_id);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 014_discovery_trending_books.sql completed successfully';
+END $$;
@@ -0,0 +1,32 @@
+-- Migration: 015 - Content Analysis Reports
+-- Description: Manuscript analysis reports (continuity checks) produced by the content worker
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- ANALYSIS REPORTS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS content.analysis_reports (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    book_id UUID NOT NULL REFERENCES content.books(id) ON DELETE CASCADE,
+    job_id UUID,
+    report_type VARCHAR(50) NOT NULL,
+    status VARCHAR(50) NOT NULL DEFAULT 'pending',
+    findings JSONB NOT NULL DEFAULT '[]',
+    summary TEXT,
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_analysis_reports_book ON content.analysis_reports(book_id, created_at DESC);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 015_content_analysis_reports.sql completed successfully';
+END $$;
@@ -0,0 +1,54 @@
+-- Migration: 016 - Discovery Reader Segments
+-- Description: Book purchases, saved author reader segments, and segment export/notify audit
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- BOOK PURCHASES
+--=============================================================================
+
+-- Recorded from book_purchased events posted to the discovery service
+CREATE TABLE IF NOT EXISTS discovery.book_purchases (
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    book_id UUID NOT NULL REFERENCES content.books(id) ON DELETE CASCADE,
+    amount_cents BIGINT,
+    purchased_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, book_id)
+);
+
+--=============================================================================
+-- SEGMENTS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS discovery.segments (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    author_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    definition JSONB NOT NULL,
+    last_notified_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Every export and notification sent through a segment
+CREATE TABLE IF NOT EXISTS discovery.segment_actions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    segment_id UUID NOT NULL REFERENCES discovery.segments(id) ON DELETE CASCADE,
+    action VARCHAR(50) NOT NULL,
+    recipient_count BIGINT NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_book_purchases_book ON discovery.book_purchases(book_id);
+CREATE INDEX IF NOT EXISTS idx_segments_author ON discovery.segments(author_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_segment_actions_segment ON discovery.segment_actions(segment_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_reading_history_book_user ON discovery.reading_history(book_id, user_id, progress);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 016_discovery_reader_segments.sql completed successfully';
+END $$;
@@ -0,0 +1,40 @@
+-- Migration: 017 - User Consents
+-- Description: Per-purpose consent (analytics, marketing emails, AI training) with change history
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- CONSENTS
+--=============================================================================
+
+-- Users without a row get the service defaults: analytics on, marketing and AI training off
+CREATE TABLE IF NOT EXISTS users.consents (
+    user_id UUID PRIMARY KEY REFERENCES users.users(id) ON DELETE CASCADE,
+    analytics BOOLEAN NOT NULL DEFAULT TRUE,
+    marketing_emails BOOLEAN NOT NULL DEFAULT FALSE,
+    ai_training BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Append-only audit of every consent change
+CREATE TABLE IF NOT EXISTS users.consent_history (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    purpose VARCHAR(50) NOT NULL,
+    granted BOOLEAN NOT NULL,
+    source VARCHAR(50) NOT NULL,
+    user_agent TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_consent_history_user ON users.consent_history(user_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_consents_marketing ON users.consents(user_id) WHERE marketing_emails = TRUE;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 017_user_consents.sql completed successfully';
+END $$;
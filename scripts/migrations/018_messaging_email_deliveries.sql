@@ -0,0 +1,38 @@
+-- Migration: 018 - Messaging Email Deliveries
+-- Description: Email delivery queue and status tracking for notifications
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- EMAIL DELIVERIES
+--=============================================================================
+
+-- One row per emailed notification: pending -> sending -> sent | failed
+CREATE TABLE IF NOT EXISTS messaging.email_deliveries (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    notification_id UUID NOT NULL UNIQUE REFERENCES messaging.notifications(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    notification_type VARCHAR(50) NOT NULL,
+    to_address VARCHAR(255) NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    provider_message_id VARCHAR(255),
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    sent_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_email_deliveries_due ON messaging.email_deliveries(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_email_deliveries_user ON messaging.email_deliveries(user_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_notifications_created ON messaging.notifications(created_at);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 018_messaging_email_deliveries.sql completed successfully';
+END $$;
@@ -0,0 +1,27 @@
+-- Migration: 019 - Content Book Opt-Outs
+-- Description: Per-book flags to disable AI features and exclude the book from search indexing
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- BOOK FLAGS
+--=============================================================================
+
+-- Enforced by generation endpoints and the content worker
+ALTER TABLE content.books ADD COLUMN IF NOT EXISTS ai_disabled BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Enforced by the discovery indexing path
+ALTER TABLE content.books ADD COLUMN IF NOT EXISTS index_excluded BOOLEAN NOT NULL DEFAULT FALSE;
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_books_index_excluded ON content.books(id) WHERE index_excluded;
+
+--=============================================================================
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 019_content_book_opt_outs.sql completed successfully';
+END $$;
@@ -0,0 +1,38 @@
+-- Migration: 020 - Editor Operation Snapshots
+-- Description: Compaction of the operation log into snapshot rows behind the latest checkpoint
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- DOCUMENTS
+--=============================================================================
+
+-- Operations at or below this version have been folded into a snapshot
+ALTER TABLE editor.documents
+ADD COLUMN IF NOT EXISTS compacted_version BIGINT NOT NULL DEFAULT 0;
+
+--=============================================================================
+-- SNAPSHOTS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS editor.snapshots (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    document_id UUID NOT NULL REFERENCES editor.documents(id) ON DELETE CASCADE,
+    from_version BIGINT NOT NULL,          -- Exclusive lower bound of the folded range
+    version BIGINT NOT NULL,               -- Checkpoint version the range was folded into
+    content TEXT NOT NULL,
+    block_ids JSONB NOT NULL DEFAULT '[]',
+    operation_count BIGINT NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_snapshots_document ON editor.snapshots(document_id, version DESC);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 020_editor_operation_snapshots.sql completed successfully';
+END $$;
@@ -0,0 +1,38 @@
+-- Migration: 021 - Storage Private Vault
+-- Description: Client-encrypted notes with per-item version history
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- VAULT
+--=============================================================================
+
+-- Holds no plaintext; kept apart from storage.files so file and indexing paths never see it
+CREATE TABLE IF NOT EXISTS storage.vault_items (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    current_version BIGINT NOT NULL DEFAULT 1,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS storage.vault_versions (
+    item_id UUID NOT NULL REFERENCES storage.vault_items(id) ON DELETE CASCADE,
+    version BIGINT NOT NULL,
+    ciphertext TEXT NOT NULL,              -- Base64, opaque to the server
+    header JSONB NOT NULL,                 -- Client decryption parameters, opaque to the server
+    size BIGINT NOT NULL,                  -- Decoded ciphertext bytes, counted against the plan quota
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (item_id, version)
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_vault_items_user ON storage.vault_items(user_id, updated_at DESC);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 021_storage_private_vault.sql completed successfully';
+END $$;
@@ -0,0 +1,41 @@
+-- Migration: 022 - Subscription Admin Audit
+-- Description: Audit log for support overrides of plans, billing periods and credit grants
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- ROLES
+--=============================================================================
+
+-- Support access is granted by an 'admin' or 'support' row here
+CREATE TABLE IF NOT EXISTS users.user_roles (
+    user_id UUID REFERENCES users.users(id) ON DELETE CASCADE,
+    role VARCHAR(50) NOT NULL,
+    PRIMARY KEY (user_id, role)
+);
+
+--=============================================================================
+-- AUDIT LOG
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS subscriptions.admin_audit_log (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    actor_id UUID NOT NULL REFERENCES users.users(id),
+    target_user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    action VARCHAR(50) NOT NULL,           -- 'subscription_override', 'credit_grant'
+    changes JSONB NOT NULL DEFAULT '{}',   -- Before/after snapshot or grant details
+    reason TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_admin_audit_target ON subscriptions.admin_audit_log(target_user_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_admin_audit_actor ON subscriptions.admin_audit_log(actor_id, created_at DESC);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 022_subscription_admin_audit.sql completed successfully';
+END $$;
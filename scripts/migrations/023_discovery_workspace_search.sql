@@ -0,0 +1,23 @@
+-- Migration: 023 - Discovery Workspace Search
+-- Description: Full-text indexes backing owner-scoped search over drafts, notes, comments and messages
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+-- Expressions must match the tsvector expressions in discovery's workspace_search.rs
+CREATE INDEX IF NOT EXISTS idx_chapters_fts ON content.chapters
+    USING GIN (to_tsvector('simple', title || ' ' || COALESCE(content, '')));
+
+CREATE INDEX IF NOT EXISTS idx_comments_fts ON editor.comments
+    USING GIN (to_tsvector('simple', content));
+
+CREATE INDEX IF NOT EXISTS idx_messages_fts ON messaging.messages
+    USING GIN (to_tsvector('simple', body));
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 023_discovery_workspace_search.sql completed successfully';
+END $$;
@@ -0,0 +1,53 @@
+-- Migration: 024 - Content Related Chapters
+-- Description: Chapter embeddings and "related chapters" cross-link suggestions for non-fiction books
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- CHAPTER EMBEDDINGS
+--=============================================================================
+
+-- One vector per chapter, reused by later analysis runs while the chapter
+-- text (content_md5) and embedding model are unchanged
+CREATE TABLE IF NOT EXISTS content.chapter_embeddings (
+    chapter_id UUID PRIMARY KEY REFERENCES content.chapters(id) ON DELETE CASCADE,
+    model VARCHAR(255) NOT NULL,
+    content_md5 VARCHAR(32) NOT NULL,
+    embedding DOUBLE PRECISION[] NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+--=============================================================================
+-- RELATED CHAPTERS
+--=============================================================================
+
+-- source = 'auto' rows come from the worker and are replaced on each run;
+-- source = 'author' rows are added by the author and never touched by the
+-- worker. Removing an auto suggestion hides it so re-analysis does not
+-- bring it back.
+CREATE TABLE IF NOT EXISTS content.related_chapters (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    book_id UUID NOT NULL REFERENCES content.books(id) ON DELETE CASCADE,
+    chapter_id UUID NOT NULL REFERENCES content.chapters(id) ON DELETE CASCADE,
+    related_chapter_id UUID NOT NULL REFERENCES content.chapters(id) ON DELETE CASCADE,
+    score DOUBLE PRECISION,
+    source VARCHAR(20) NOT NULL DEFAULT 'auto' CHECK (source IN ('auto', 'author')),
+    hidden BOOLEAN NOT NULL DEFAULT false,
+    position INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE(chapter_id, related_chapter_id),
+    CHECK (chapter_id <> related_chapter_id)
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_related_chapters_chapter ON content.related_chapters(chapter_id, position) WHERE hidden = false;
+CREATE INDEX IF NOT EXISTS idx_related_chapters_book ON content.related_chapters(book_id);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 024_content_related_chapters.sql completed successfully';
+END $$;
@@ -0,0 +1,65 @@
+-- Migration: 025 - Content Snapshots and Exports
+-- Description: Immutable content snapshots pinned to export artifacts so exported files can be compared and reproduced
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- CONTENT SNAPSHOTS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS content.content_snapshots (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    book_id UUID NOT NULL REFERENCES content.books(id) ON DELETE CASCADE,
+    created_by UUID,
+    reason VARCHAR(20) NOT NULL DEFAULT 'manual' CHECK (reason IN ('manual', 'export')),
+    title VARCHAR(500) NOT NULL,
+    -- [{chapter_id, chapter_number, title, content, content_sha256}] in reading order
+    chapters JSONB NOT NULL,
+    chapter_count INTEGER NOT NULL,
+    word_count INTEGER NOT NULL DEFAULT 0,
+    content_sha256 VARCHAR(64) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Snapshots are write-once; exports rely on them never changing
+CREATE OR REPLACE FUNCTION content.reject_snapshot_update()
+RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'content snapshots are immutable';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS content_snapshots_immutable ON content.content_snapshots;
+CREATE TRIGGER content_snapshots_immutable
+    BEFORE UPDATE ON content.content_snapshots
+    FOR EACH ROW EXECUTE FUNCTION content.reject_snapshot_update();
+
+--=============================================================================
+-- EXPORTS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS content.exports (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    book_id UUID NOT NULL REFERENCES content.books(id) ON DELETE CASCADE,
+    snapshot_id UUID NOT NULL REFERENCES content.content_snapshots(id) ON DELETE CASCADE,
+    reexport_of UUID REFERENCES content.exports(id) ON DELETE SET NULL,
+    format VARCHAR(20) NOT NULL,
+    filename VARCHAR(500) NOT NULL,
+    artifact_sha256 VARCHAR(64) NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    created_by UUID,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_content_snapshots_book ON content.content_snapshots(book_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_exports_book ON content.exports(book_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_exports_snapshot ON content.exports(snapshot_id);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 025_content_snapshots_exports.sql completed successfully';
+END $$;
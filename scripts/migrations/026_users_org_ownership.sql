@@ -0,0 +1,76 @@
+-- Migration: 026 - Organization Ownership
+-- Description: Organizations, persisted book collaborator grants and an audit trail for bulk ownership transfers
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- ORGANIZATIONS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS users.organizations (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    name VARCHAR(255) NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS users.organization_members (
+    org_id UUID REFERENCES users.organizations(id) ON DELETE CASCADE,
+    user_id UUID REFERENCES users.users(id) ON DELETE CASCADE,
+    role VARCHAR(20) NOT NULL DEFAULT 'member' CHECK (role IN ('owner', 'admin', 'member')),
+    joined_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (org_id, user_id)
+);
+
+-- Books created outside an organization keep a NULL org_id
+ALTER TABLE content.books ADD COLUMN IF NOT EXISTS org_id UUID REFERENCES users.organizations(id) ON DELETE SET NULL;
+
+--=============================================================================
+-- COLLABORATOR GRANTS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS content.book_collaborators (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    book_id UUID NOT NULL REFERENCES content.books(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    role VARCHAR(20) NOT NULL CHECK (role IN ('viewer', 'commenter', 'editor')),
+    granted_by UUID REFERENCES users.users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    revoked_at TIMESTAMPTZ,
+    revoked_by UUID REFERENCES users.users(id) ON DELETE SET NULL
+);
+
+-- One active grant per user and book; revoked rows are kept for history
+CREATE UNIQUE INDEX IF NOT EXISTS idx_book_collaborators_active
+    ON content.book_collaborators(book_id, user_id) WHERE revoked_at IS NULL;
+
+--=============================================================================
+-- OWNERSHIP TRANSFER AUDIT
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS users.ownership_transfers (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    org_id UUID NOT NULL REFERENCES users.organizations(id) ON DELETE CASCADE,
+    actor_id UUID NOT NULL REFERENCES users.users(id),
+    from_user_id UUID NOT NULL,            -- Not a foreign key so the record survives account deletion
+    to_user_id UUID NOT NULL REFERENCES users.users(id),
+    book_ids JSONB NOT NULL DEFAULT '[]',          -- Books moved to to_user_id
+    revoked_grants JSONB NOT NULL DEFAULT '[]',    -- [{id, book_id, role}] revoked from from_user_id
+    reason TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_org_members_user ON users.organization_members(user_id);
+CREATE INDEX IF NOT EXISTS idx_books_org_author ON content.books(org_id, author_id);
+CREATE INDEX IF NOT EXISTS idx_book_collaborators_user ON content.book_collaborators(user_id) WHERE revoked_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_ownership_transfers_org ON users.ownership_transfers(org_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_ownership_transfers_from ON users.ownership_transfers(from_user_id, created_at DESC);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 026_users_org_ownership.sql completed successfully';
+END $$;
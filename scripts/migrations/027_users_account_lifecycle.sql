@@ -0,0 +1,37 @@
+-- Migration: 027 - Account Lifecycle
+-- Description: Tracks inactive accounts through notice, anonymization or deletion under the retention policy
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- ACCOUNT LIFECYCLE
+--=============================================================================
+
+-- One row per account the policy has acted on. Rows are removed when a
+-- notified user signs in again; completed rows are kept as the record of what
+-- was done, so user_id is deliberately not a foreign key.
+CREATE TABLE IF NOT EXISTS users.account_lifecycle (
+    user_id UUID PRIMARY KEY,
+    state VARCHAR(20) NOT NULL CHECK (state IN ('notified', 'anonymized', 'deleted')),
+    action VARCHAR(20) NOT NULL CHECK (action IN ('anonymize', 'delete')),
+    last_active_at TIMESTAMPTZ,
+    notified_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    action_due_at TIMESTAMPTZ NOT NULL,
+    completed_at TIMESTAMPTZ,
+    steps JSONB NOT NULL DEFAULT '{}',     -- Outcome of each cleanup step from the last attempt
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_account_lifecycle_due ON users.account_lifecycle(action_due_at) WHERE state = 'notified';
+CREATE INDEX IF NOT EXISTS idx_users_last_login ON users.users(last_login_at);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 027_users_account_lifecycle.sql completed successfully';
+END $$;
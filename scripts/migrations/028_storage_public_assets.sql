@@ -0,0 +1,30 @@
+-- Migration: 028 - Storage Public Assets
+-- Description: Revocable public links so published covers and other book assets can be served and cached without auth
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- PUBLIC ASSETS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS storage.public_assets (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    file_id UUID NOT NULL REFERENCES storage.files(id) ON DELETE CASCADE,
+    token VARCHAR(64) NOT NULL UNIQUE,     -- Unguessable path segment of GET /public/:token
+    created_by UUID NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    revoked_at TIMESTAMPTZ
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+-- At most one live link per file, so publishing twice returns the same URL
+CREATE UNIQUE INDEX IF NOT EXISTS idx_public_assets_active_file
+    ON storage.public_assets(file_id) WHERE revoked_at IS NULL;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 028_storage_public_assets.sql completed successfully';
+END $$;
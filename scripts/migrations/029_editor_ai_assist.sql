@@ -0,0 +1,23 @@
+-- Migration: 029 - Editor AI Assist
+-- Description: Records which operations were produced by the inline AI assistant so history can attribute them
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- OPERATION ATTRIBUTION
+--=============================================================================
+
+-- NULL for edits typed by the user; 'ai_assistant' when applied from POST /documents/:id/assist
+ALTER TABLE editor.operations ADD COLUMN IF NOT EXISTS attribution VARCHAR(50);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_operations_attribution
+    ON editor.operations(document_id, version) WHERE attribution IS NOT NULL;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 029_editor_ai_assist.sql completed successfully';
+END $$;
@@ -0,0 +1,46 @@
+-- Migration: 030 - Writing Events
+-- Description: Group writing events (NaNoWriMo-style) with per-participant word tallies for live leaderboards
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- WRITING EVENTS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS content.writing_events (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    name VARCHAR(200) NOT NULL,
+    description TEXT,
+    created_by UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    starts_at TIMESTAMPTZ NOT NULL,
+    ends_at TIMESTAMPTZ NOT NULL,
+    target_words INTEGER,                  -- Per-participant goal, e.g. 50000
+    milestones JSONB NOT NULL DEFAULT '[]', -- Net word counts that trigger a notification
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+    CHECK (ends_at > starts_at)
+);
+
+-- Tallies only count chapter edits made while the event is running
+CREATE TABLE IF NOT EXISTS content.writing_event_participants (
+    event_id UUID REFERENCES content.writing_events(id) ON DELETE CASCADE,
+    user_id UUID REFERENCES users.users(id) ON DELETE CASCADE,
+    words_added INTEGER NOT NULL DEFAULT 0,
+    words_removed INTEGER NOT NULL DEFAULT 0,
+    last_milestone INTEGER NOT NULL DEFAULT 0, -- Highest milestone notified, so dips and recoveries don't re-notify
+    joined_at TIMESTAMPTZ DEFAULT NOW(),
+    last_activity_at TIMESTAMPTZ,
+    PRIMARY KEY (event_id, user_id)
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_writing_events_window ON content.writing_events(starts_at, ends_at);
+CREATE INDEX IF NOT EXISTS idx_writing_event_participants_user ON content.writing_event_participants(user_id);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 030_content_writing_events.sql completed successfully';
+END $$;
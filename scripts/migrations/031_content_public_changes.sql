@@ -0,0 +1,106 @@
+-- Migration: 031 - Public Change Feed
+-- Description: Ordered log of publish/update/removal events for public books and chapters, served by GET /public/changes
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- PUBLIC CHANGES
+--=============================================================================
+
+-- Written by triggers in the same transaction as the content change, so no
+-- writer (API, worker, admin tooling) can change public content without a
+-- feed entry. resource_id is not a foreign key: tombstones outlive the row.
+CREATE TABLE IF NOT EXISTS content.public_changes (
+    seq BIGSERIAL PRIMARY KEY,             -- Cursor position; strictly increasing
+    resource_type VARCHAR(20) NOT NULL CHECK (resource_type IN ('book', 'chapter')),
+    resource_id UUID NOT NULL,
+    book_id UUID NOT NULL,
+    action VARCHAR(20) NOT NULL CHECK (action IN ('publish', 'update', 'unpublish', 'delete')),
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp()
+);
+
+--=============================================================================
+-- TRIGGERS
+--=============================================================================
+
+-- A book is public while published and not excluded from discovery. Leaving
+-- that state records a single book tombstone, which covers its chapters.
+CREATE OR REPLACE FUNCTION content.record_book_public_change()
+RETURNS TRIGGER AS $$
+DECLARE
+    was_public BOOLEAN := TG_OP <> 'INSERT' AND OLD.status = 'published' AND NOT COALESCE(OLD.index_excluded, false);
+    is_public BOOLEAN := TG_OP <> 'DELETE' AND NEW.status = 'published' AND NOT COALESCE(NEW.index_excluded, false);
+BEGIN
+    IF is_public AND NOT was_public THEN
+        INSERT INTO content.public_changes (resource_type, resource_id, book_id, action)
+        VALUES ('book', NEW.id, NEW.id, 'publish');
+        INSERT INTO content.public_changes (resource_type, resource_id, book_id, action)
+        SELECT 'chapter', c.id, NEW.id, 'publish' FROM content.chapters c
+        WHERE c.book_id = NEW.id ORDER BY c.chapter_number;
+    ELSIF was_public AND NOT is_public THEN
+        INSERT INTO content.public_changes (resource_type, resource_id, book_id, action)
+        VALUES ('book', OLD.id, OLD.id, CASE WHEN TG_OP = 'DELETE' THEN 'delete' ELSE 'unpublish' END);
+    ELSIF was_public AND is_public AND (
+        NEW.title IS DISTINCT FROM OLD.title
+        OR NEW.description IS DISTINCT FROM OLD.description
+        OR NEW.genre IS DISTINCT FROM OLD.genre
+        OR NEW.cover_image_url IS DISTINCT FROM OLD.cover_image_url
+        OR NEW.author_id IS DISTINCT FROM OLD.author_id
+    ) THEN
+        INSERT INTO content.public_changes (resource_type, resource_id, book_id, action)
+        VALUES ('book', NEW.id, NEW.id, 'update');
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Chapter changes only matter while their book is public; chapters removed
+-- along with their book are covered by the book tombstone
+CREATE OR REPLACE FUNCTION content.record_chapter_public_change()
+RETURNS TRIGGER AS $$
+DECLARE
+    target_book UUID := CASE WHEN TG_OP = 'DELETE' THEN OLD.book_id ELSE NEW.book_id END;
+    chapter UUID := CASE WHEN TG_OP = 'DELETE' THEN OLD.id ELSE NEW.id END;
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM content.books b
+        WHERE b.id = target_book AND b.status = 'published' AND NOT COALESCE(b.index_excluded, false)
+    ) THEN
+        RETURN NULL;
+    END IF;
+
+    IF TG_OP = 'INSERT' THEN
+        INSERT INTO content.public_changes (resource_type, resource_id, book_id, action)
+        VALUES ('chapter', chapter, target_book, 'publish');
+    ELSIF TG_OP = 'DELETE' THEN
+        INSERT INTO content.public_changes (resource_type, resource_id, book_id, action)
+        VALUES ('chapter', chapter, target_book, 'delete');
+    ELSIF NEW.title IS DISTINCT FROM OLD.title
+        OR NEW.content IS DISTINCT FROM OLD.content
+        OR NEW.chapter_number IS DISTINCT FROM OLD.chapter_number
+    THEN
+        INSERT INTO content.public_changes (resource_type, resource_id, book_id, action)
+        VALUES ('chapter', chapter, target_book, 'update');
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER record_book_public_change_trigger
+    AFTER INSERT OR UPDATE OR DELETE ON content.books
+    FOR EACH ROW EXECUTE FUNCTION content.record_book_public_change();
+
+CREATE OR REPLACE TRIGGER record_chapter_public_change_trigger
+    AFTER INSERT OR UPDATE OR DELETE ON content.chapters
+    FOR EACH ROW EXECUTE FUNCTION content.record_chapter_public_change();
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_public_changes_resource ON content.public_changes(resource_type, resource_id);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 031_content_public_changes.sql completed successfully';
+END $$;
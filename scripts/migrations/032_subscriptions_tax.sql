@@ -0,0 +1,50 @@
+-- Migration: 032 - Subscription Tax
+-- Description: Billing address and tax ID per user for Stripe Tax, and tax breakdowns on recorded invoices
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- BILLING DETAILS
+--=============================================================================
+
+-- Mirrors what PUT /billing/details last pushed to the Stripe customer
+CREATE TABLE IF NOT EXISTS subscriptions.billing_details (
+    user_id UUID PRIMARY KEY REFERENCES users.users(id) ON DELETE CASCADE,
+    stripe_customer_id VARCHAR(255) NOT NULL,
+    name VARCHAR(255),
+    address_line1 VARCHAR(255) NOT NULL,
+    address_line2 VARCHAR(255),
+    city VARCHAR(255),
+    state VARCHAR(255),
+    postal_code VARCHAR(32),
+    country CHAR(2) NOT NULL,              -- ISO 3166-1 alpha-2
+    tax_id_type VARCHAR(20),               -- Stripe tax ID type, e.g. eu_vat, gb_vat, au_abn
+    tax_id_value VARCHAR(64),
+    stripe_tax_id VARCHAR(255),            -- txi_... on the Stripe customer
+    tax_id_verification VARCHAR(20),       -- pending, verified, unverified, unavailable
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+--=============================================================================
+-- INVOICE TAX
+--=============================================================================
+
+-- NULL on invoices recorded before tax collection was enabled
+ALTER TABLE subscriptions.invoices ADD COLUMN IF NOT EXISTS subtotal BIGINT;
+ALTER TABLE subscriptions.invoices ADD COLUMN IF NOT EXISTS tax BIGINT;
+ALTER TABLE subscriptions.invoices ADD COLUMN IF NOT EXISTS total BIGINT;
+ALTER TABLE subscriptions.invoices ADD COLUMN IF NOT EXISTS currency VARCHAR(3);
+ALTER TABLE subscriptions.invoices ADD COLUMN IF NOT EXISTS tax_breakdown JSONB;
+ALTER TABLE subscriptions.invoices ADD COLUMN IF NOT EXISTS customer_tax_ids JSONB;
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_billing_details_stripe_tax_id ON subscriptions.billing_details(stripe_tax_id);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 032_subscriptions_tax.sql completed successfully';
+END $$;
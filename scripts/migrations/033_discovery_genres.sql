@@ -0,0 +1,69 @@
+-- Migration: 033 - Discovery Genre Taxonomy
+-- Description: Canonical hierarchical genres with aliases used to normalize free-form book genres at index time
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- GENRES
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS discovery.genres (
+    slug VARCHAR(100) PRIMARY KEY,
+    name VARCHAR(100) NOT NULL,
+    parent_slug VARCHAR(100) REFERENCES discovery.genres(slug) ON DELETE CASCADE,
+    aliases TEXT[] NOT NULL DEFAULT '{}',  -- Lowercased alternative spellings matched at index time
+    sort_order INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    CHECK (parent_slug IS NULL OR parent_slug <> slug)
+);
+
+--=============================================================================
+-- SEED DATA: TAXONOMY
+--=============================================================================
+
+INSERT INTO discovery.genres (slug, name, parent_slug, aliases, sort_order) VALUES
+('fantasy', 'Fantasy', NULL, '{}', 1),
+('science-fiction', 'Science Fiction', NULL, '{"sci-fi","scifi","sf","science fiction"}', 2),
+('mystery', 'Mystery', NULL, '{"mysteries","crime","detective"}', 3),
+('thriller', 'Thriller', NULL, '{"thrillers","suspense"}', 4),
+('romance', 'Romance', NULL, '{"romantic"}', 5),
+('horror', 'Horror', NULL, '{}', 6),
+('literary-fiction', 'Literary Fiction', NULL, '{"literary","literature","general fiction","fiction"}', 7),
+('historical-fiction', 'Historical Fiction', NULL, '{"historical"}', 8),
+('young-adult', 'Young Adult', NULL, '{"ya","teen"}', 9),
+('childrens', 'Children''s', NULL, '{"children","kids","middle grade"}', 10),
+('nonfiction', 'Nonfiction', NULL, '{"non-fiction","non fiction"}', 11),
+('poetry', 'Poetry', NULL, '{"poems"}', 12)
+ON CONFLICT DO NOTHING;
+
+INSERT INTO discovery.genres (slug, name, parent_slug, aliases, sort_order) VALUES
+('epic-fantasy', 'Epic Fantasy', 'fantasy', '{"high fantasy"}', 1),
+('urban-fantasy', 'Urban Fantasy', 'fantasy', '{}', 2),
+('dark-fantasy', 'Dark Fantasy', 'fantasy', '{"grimdark"}', 3),
+('cozy-fantasy', 'Cozy Fantasy', 'fantasy', '{}', 4),
+('space-opera', 'Space Opera', 'science-fiction', '{}', 1),
+('cyberpunk', 'Cyberpunk', 'science-fiction', '{}', 2),
+('dystopian', 'Dystopian', 'science-fiction', '{"dystopia","post-apocalyptic"}', 3),
+('hard-science-fiction', 'Hard Science Fiction', 'science-fiction', '{"hard sf","hard sci-fi"}', 4),
+('cozy-mystery', 'Cozy Mystery', 'mystery', '{"cozy"}', 1),
+('police-procedural', 'Police Procedural', 'mystery', '{"procedural"}', 2),
+('psychological-thriller', 'Psychological Thriller', 'thriller', '{}', 1),
+('techno-thriller', 'Techno-Thriller', 'thriller', '{"technothriller"}', 2),
+('contemporary-romance', 'Contemporary Romance', 'romance', '{}', 1),
+('historical-romance', 'Historical Romance', 'romance', '{"regency romance","regency"}', 2),
+('paranormal-romance', 'Paranormal Romance', 'romance', '{}', 3),
+('memoir', 'Memoir', 'nonfiction', '{"memoirs","autobiography"}', 1),
+('biography', 'Biography', 'nonfiction', '{"biographies"}', 2),
+('self-help', 'Self-Help', 'nonfiction', '{"self help","personal development"}', 3)
+ON CONFLICT DO NOTHING;
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_genres_parent ON discovery.genres(parent_slug);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 033_discovery_genres.sql completed successfully';
+END $$;
@@ -0,0 +1,53 @@
+-- Migration: 034 - Content Beta-Reader Feedback
+-- Description: Per-book beta-reader tokens and structured chapter feedback (rating, comments, tags)
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- FEEDBACK TOKENS
+--=============================================================================
+
+-- One token per beta reader (or group); revoking it stops further submissions
+CREATE TABLE IF NOT EXISTS content.feedback_tokens (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    book_id UUID NOT NULL REFERENCES content.books(id) ON DELETE CASCADE,
+    token VARCHAR(64) NOT NULL UNIQUE,
+    label VARCHAR(255),
+    created_by UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+--=============================================================================
+-- FEEDBACK
+--=============================================================================
+
+-- One response per token and chapter; resubmitting replaces it
+CREATE TABLE IF NOT EXISTS content.feedback (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    token_id UUID NOT NULL REFERENCES content.feedback_tokens(id) ON DELETE CASCADE,
+    book_id UUID NOT NULL REFERENCES content.books(id) ON DELETE CASCADE,
+    chapter_id UUID NOT NULL REFERENCES content.chapters(id) ON DELETE CASCADE,
+    reader_user_id UUID REFERENCES users.users(id) ON DELETE SET NULL,
+    reader_name VARCHAR(255),
+    rating SMALLINT NOT NULL CHECK (rating BETWEEN 1 AND 5),
+    comments TEXT,
+    tags TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE (token_id, chapter_id)
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_feedback_tokens_book ON content.feedback_tokens(book_id);
+CREATE INDEX IF NOT EXISTS idx_feedback_book ON content.feedback(book_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_feedback_chapter ON content.feedback(chapter_id);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 034_content_feedback.sql completed successfully';
+END $$;
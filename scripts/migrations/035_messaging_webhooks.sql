@@ -0,0 +1,58 @@
+-- Migration: 035 - Messaging Outbound Webhooks
+-- Description: User-registered webhook endpoints (Slack, Discord, generic JSON) and their signed delivery log
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- WEBHOOKS
+--=============================================================================
+
+-- One endpoint per event type; register the same URL again for more types
+CREATE TABLE IF NOT EXISTS messaging.webhooks (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    event_type VARCHAR(50) NOT NULL,       -- book.published, chapter.generated, comment.created
+    format VARCHAR(20) NOT NULL DEFAULT 'json',  -- json, slack, discord
+    secret VARCHAR(64) NOT NULL,           -- HMAC-SHA256 signing key
+    active BOOLEAN NOT NULL DEFAULT true,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (user_id, url, event_type)
+);
+
+--=============================================================================
+-- WEBHOOK DELIVERIES
+--=============================================================================
+
+-- One row per webhook and source event: pending -> sending -> delivered | failed
+CREATE TABLE IF NOT EXISTS messaging.webhook_deliveries (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    webhook_id UUID NOT NULL REFERENCES messaging.webhooks(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    event_type VARCHAR(50) NOT NULL,
+    source_id UUID NOT NULL,               -- messaging.events or messaging.notifications row
+    payload JSONB NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    response_status INTEGER,
+    last_error TEXT,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (webhook_id, source_id)
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_webhooks_user_event ON messaging.webhooks(user_id, event_type) WHERE active;
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due ON messaging.webhook_deliveries(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook ON messaging.webhook_deliveries(webhook_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_events_created ON messaging.events(created_at);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 035_messaging_webhooks.sql completed successfully';
+END $$;
@@ -0,0 +1,40 @@
+-- Migration: 036 - Storage Collections
+-- Description: Nested folders for organizing files, optionally linked to a book
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- COLLECTIONS
+--=============================================================================
+
+-- A tree per user; paths ("Books/The Long Road/Covers") are derived from parent links
+CREATE TABLE IF NOT EXISTS storage.collections (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    parent_id UUID REFERENCES storage.collections(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    book_id UUID REFERENCES content.books(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- Files outside any collection stay at the root (NULL)
+ALTER TABLE storage.files
+    ADD COLUMN IF NOT EXISTS collection_id UUID REFERENCES storage.collections(id) ON DELETE SET NULL;
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+-- Sibling names are unique regardless of case; top-level collections share the nil parent
+CREATE UNIQUE INDEX IF NOT EXISTS idx_collections_sibling_name ON storage.collections(
+    user_id, COALESCE(parent_id, '00000000-0000-0000-0000-000000000000'::uuid), lower(name)
+);
+CREATE INDEX IF NOT EXISTS idx_collections_parent ON storage.collections(parent_id);
+CREATE INDEX IF NOT EXISTS idx_collections_book ON storage.collections(book_id) WHERE book_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_files_collection ON storage.files(collection_id);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 036_storage_collections.sql completed successfully';
+END $$;
@@ -0,0 +1,32 @@
+-- Migration: 037 - Editor Share Links
+-- Description: Expiring, revocable read-only links to a live draft for people without an account
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- SHARE LINKS
+--=============================================================================
+
+-- The token itself is never stored: it is the link id and expiry signed with share_link_secret
+CREATE TABLE IF NOT EXISTS editor.share_links (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    document_id UUID NOT NULL REFERENCES content.chapters(id) ON DELETE CASCADE,
+    created_by UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    label VARCHAR(255),
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ,
+    view_count INTEGER NOT NULL DEFAULT 0,
+    last_viewed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_share_links_document ON editor.share_links(document_id, created_at DESC);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 037_editor_share_links.sql completed successfully';
+END $$;
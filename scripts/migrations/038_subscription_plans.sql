@@ -0,0 +1,62 @@
+-- Migration: 038 - Subscription Plans
+-- Description: Plan catalogue (prices, features, limits, Stripe prices) moved from code into the database
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- PLANS
+--=============================================================================
+
+-- Plans are never deleted, only deactivated: subscriptions keep referencing them by id
+CREATE TABLE IF NOT EXISTS subscriptions.plans (
+    id VARCHAR(50) PRIMARY KEY,
+    name VARCHAR(100) NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    price_monthly BIGINT NOT NULL DEFAULT 0,      -- In cents
+    price_yearly BIGINT NOT NULL DEFAULT 0,
+    features JSONB NOT NULL DEFAULT '[]',
+    max_books INTEGER NOT NULL DEFAULT 1,         -- -1 for unlimited, as for every limit
+    max_chapters_per_book INTEGER NOT NULL DEFAULT 10,
+    ai_words_per_month BIGINT NOT NULL DEFAULT 5000,
+    storage_gb INTEGER NOT NULL DEFAULT 1,
+    collaborators INTEGER NOT NULL DEFAULT 0,
+    stripe_price_id VARCHAR(255),                 -- NULL falls back to the stripe_price_<id> variable
+    active BOOLEAN NOT NULL DEFAULT true,
+    sort_order INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- The plans previously hard-coded in the subscription service
+INSERT INTO subscriptions.plans
+    (id, name, description, price_monthly, price_yearly, features,
+     max_books, max_chapters_per_book, ai_words_per_month, storage_gb, collaborators, sort_order)
+VALUES
+    ('free', 'Free', 'Perfect for getting started', 0, 0,
+     '["1 book project", "5,000 AI words/month", "Basic editor", "Community support"]',
+     1, 10, 5000, 1, 0, 0),
+    ('pro', 'Professional', 'For serious authors', 1999, 19990,
+     '["Unlimited book projects", "100,000 AI words/month", "Advanced editor with collaboration", "Priority support", "Export to all formats", "Version history"]',
+     -1, -1, 100000, 50, 5, 1),
+    ('enterprise', 'Enterprise', 'For publishing teams', 9999, 99990,
+     '["Everything in Professional", "Unlimited AI words", "Unlimited collaborators", "Custom AI training", "API access", "Dedicated support", "SSO integration"]',
+     -1, -1, -1, 500, -1, 2)
+ON CONFLICT (id) DO NOTHING;
+
+--=============================================================================
+-- AUDIT LOG
+--=============================================================================
+
+-- Plan changes are audited too but have no target user
+ALTER TABLE subscriptions.admin_audit_log ALTER COLUMN target_user_id DROP NOT NULL;
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_plans_active ON subscriptions.plans(sort_order) WHERE active;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 038_subscription_plans.sql completed successfully';
+END $$;
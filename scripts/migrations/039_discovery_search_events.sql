@@ -0,0 +1,40 @@
+-- Migration: 039 - Discovery Search Events
+-- Description: Search, click-through and dwell events used to tune search relevance
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- SEARCH EVENTS
+--=============================================================================
+
+-- One row per search shown ('search') and per result opened ('click').
+-- Clicks share the search_id of the search they came from when the client sends it.
+CREATE TABLE IF NOT EXISTS discovery.search_events (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    event_type VARCHAR(20) NOT NULL CHECK (event_type IN ('search', 'click')),
+    search_id UUID,
+    user_id UUID REFERENCES users.users(id) ON DELETE SET NULL,
+    query TEXT NOT NULL,
+    normalized_query TEXT NOT NULL,        -- Lowercased, whitespace collapsed; what reports group by
+    search_type VARCHAR(20) NOT NULL DEFAULT 'all',
+    result_count INTEGER,                  -- Searches only
+    result_id VARCHAR(255),                -- Clicks only
+    result_type VARCHAR(20),
+    position INTEGER CHECK (position IS NULL OR position >= 1),  -- 1-based rank of the clicked result
+    dwell_ms BIGINT CHECK (dwell_ms IS NULL OR dwell_ms >= 0),
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_search_events_time ON discovery.search_events(created_at);
+CREATE INDEX IF NOT EXISTS idx_search_events_query_time ON discovery.search_events(normalized_query, created_at);
+CREATE INDEX IF NOT EXISTS idx_search_events_zero_results ON discovery.search_events(created_at)
+    WHERE event_type = 'search' AND result_count = 0;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 039_discovery_search_events.sql completed successfully';
+END $$;
@@ -0,0 +1,43 @@
+-- Migration: 040 - Content Series
+-- Description: Link books into ordered series (trilogies, sagas) for aggregate views and series-aware generation
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- SERIES
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS content.series (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    author_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    title VARCHAR(255) NOT NULL,
+    description TEXT,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- A book belongs to at most one series; series_order is its 1-based position
+ALTER TABLE content.books
+    ADD COLUMN IF NOT EXISTS series_id UUID REFERENCES content.series(id) ON DELETE SET NULL,
+    ADD COLUMN IF NOT EXISTS series_order INTEGER CHECK (series_order IS NULL OR series_order >= 1);
+
+-- Deferrable so inserting a book mid-series can shift the later books in one statement
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_constraint WHERE conname = 'books_series_order_unique') THEN
+        ALTER TABLE content.books
+            ADD CONSTRAINT books_series_order_unique UNIQUE (series_id, series_order) DEFERRABLE INITIALLY IMMEDIATE;
+    END IF;
+END $$;
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_series_author ON content.series(author_id);
+CREATE INDEX IF NOT EXISTS idx_books_series ON content.books(series_id, series_order) WHERE series_id IS NOT NULL;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 040_content_series.sql completed successfully';
+END $$;
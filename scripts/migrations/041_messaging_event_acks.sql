@@ -0,0 +1,31 @@
+-- Migration: 041 - Messaging Event Acknowledgments
+-- Description: At-least-once real-time event delivery with client acks, redelivery and a dead-letter state
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- EVENT DELIVERY STATE
+--=============================================================================
+
+-- `delivered` now means acknowledged by the client (or legacy rows marked on read).
+-- Unacked events are redelivered after the ack timeout until the attempt limit,
+-- after which they are dead-lettered and no longer sent.
+ALTER TABLE messaging.events
+    ADD COLUMN IF NOT EXISTS delivery_attempts INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS last_delivered_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS acked_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS dead_lettered_at TIMESTAMPTZ;
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_events_pending ON messaging.events(user_id, created_at)
+    WHERE delivered = false AND dead_lettered_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_events_dead_lettered ON messaging.events(dead_lettered_at DESC)
+    WHERE dead_lettered_at IS NOT NULL;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 041_messaging_event_acks.sql completed successfully';
+END $$;
@@ -0,0 +1,26 @@
+-- Migration: 042 - Editor Rich Text
+-- Description: Structured rich-text content (Quill delta) for documents and checkpoints
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- DOCUMENTS
+--=============================================================================
+
+-- Delta of attributed inserts; NULL until the document's first rich-text edit.
+-- `content` stays the plain-text projection used for word counts and blocks.
+ALTER TABLE editor.documents
+ADD COLUMN IF NOT EXISTS rich_content JSONB;
+
+--=============================================================================
+-- CHECKPOINTS
+--=============================================================================
+
+-- Restored on revert so formatting survives a checkpoint round trip
+ALTER TABLE editor.checkpoints
+ADD COLUMN IF NOT EXISTS rich_content JSONB;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 042_editor_rich_text.sql completed successfully';
+END $$;
@@ -0,0 +1,50 @@
+-- Migration: 043 - Subscription Referrals
+-- Description: Per-user referral codes, referred signups, and credit rewards on conversion to a paid plan
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- REFERRAL CODES
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS subscriptions.referral_codes (
+    user_id UUID PRIMARY KEY REFERENCES users.users(id) ON DELETE CASCADE,
+    code VARCHAR(16) NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+--=============================================================================
+-- REFERRALS
+--=============================================================================
+
+-- One row per referred user. Status moves signed_up -> rewarded, or
+-- signed_up -> rejected when a fraud check fails at conversion.
+CREATE TABLE IF NOT EXISTS subscriptions.referrals (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    referrer_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    referred_id UUID NOT NULL UNIQUE REFERENCES users.users(id) ON DELETE CASCADE,
+    code VARCHAR(16) NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'signed_up'
+        CHECK (status IN ('signed_up', 'rewarded', 'rejected')),
+    rejection_reason VARCHAR(50),
+    card_fingerprint VARCHAR(255),          -- Card of the converting payment, for same-card checks
+    stripe_invoice_id VARCHAR(255),         -- Invoice that converted the referred user
+    referrer_credits INTEGER NOT NULL DEFAULT 0,
+    referred_credits INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    converted_at TIMESTAMPTZ,
+    CHECK (referrer_id <> referred_id)
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_referrals_referrer ON subscriptions.referrals(referrer_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_referrals_fingerprint ON subscriptions.referrals(referrer_id, card_fingerprint)
+    WHERE card_fingerprint IS NOT NULL;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 043_subscription_referrals.sql completed successfully';
+END $$;
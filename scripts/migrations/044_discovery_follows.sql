@@ -0,0 +1,29 @@
+-- Migration: 044 - Discovery Follows
+-- Description: Readers following authors, for the following feed
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- FOLLOWS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS discovery.follows (
+    follower_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    author_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (follower_id, author_id),
+    CHECK (follower_id <> author_id)
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_follows_author ON discovery.follows(author_id);
+CREATE INDEX IF NOT EXISTS idx_books_author_published ON content.books(author_id, published_at DESC)
+    WHERE status = 'published';
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 044_discovery_follows.sql completed successfully';
+END $$;
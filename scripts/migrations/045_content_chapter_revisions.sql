@@ -0,0 +1,41 @@
+-- Migration: 045 - Content Chapter Revisions
+-- Description: Automatic per-chapter revision history, stored as line diffs with periodic full copies
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- CHAPTER REVISIONS
+--=============================================================================
+
+-- One row per version of a chapter replaced by an update or restore. A row
+-- holds either the full content or a diff against the previous revision;
+-- the oldest retained revision always holds full content.
+CREATE TABLE IF NOT EXISTS content.chapter_revisions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    chapter_id UUID NOT NULL REFERENCES content.chapters(id) ON DELETE CASCADE,
+    revision_number INTEGER NOT NULL,
+    title TEXT NOT NULL DEFAULT '',
+    word_count INTEGER NOT NULL DEFAULT 0,
+    content TEXT,                           -- Full content (keyframe)
+    diff JSONB,                             -- Line diff from the previous revision
+    content_sha256 VARCHAR(64) NOT NULL,
+    reason VARCHAR(20) NOT NULL DEFAULT 'update'
+        CHECK (reason IN ('update', 'restore')),
+    saved_at TIMESTAMPTZ,                   -- When this version was originally written
+    created_by UUID REFERENCES users.users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE (chapter_id, revision_number),
+    CHECK (content IS NOT NULL OR diff IS NOT NULL)
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_chapter_revisions_keyframes ON content.chapter_revisions(chapter_id, revision_number)
+    WHERE content IS NOT NULL;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 045_content_chapter_revisions.sql completed successfully';
+END $$;
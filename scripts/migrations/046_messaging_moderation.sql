@@ -0,0 +1,68 @@
+-- Migration: 046 - Messaging Moderation
+-- Description: Message reports, user blocks, and messaging suspensions for abuse handling
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- BLOCKS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS messaging.blocks (
+    blocker_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    blocked_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    conversation_id UUID REFERENCES messaging.conversations(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (blocker_id, blocked_id),
+    CHECK (blocker_id <> blocked_id)
+);
+
+--=============================================================================
+-- REPORTS (moderation queue)
+--=============================================================================
+
+-- The reported message is copied so the report survives the sender deleting it
+CREATE TABLE IF NOT EXISTS messaging.message_reports (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    message_id UUID REFERENCES messaging.messages(id) ON DELETE SET NULL,
+    conversation_id UUID REFERENCES messaging.conversations(id) ON DELETE SET NULL,
+    reporter_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    reported_user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    message_body TEXT NOT NULL,
+    reason VARCHAR(20) NOT NULL
+        CHECK (reason IN ('spam', 'harassment', 'hate', 'sexual', 'scam', 'other')),
+    details TEXT,
+    status VARCHAR(20) NOT NULL DEFAULT 'open'
+        CHECK (status IN ('open', 'actioned', 'dismissed')),
+    resolution_note TEXT,
+    reviewed_by UUID REFERENCES users.users(id) ON DELETE SET NULL,
+    reviewed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE (message_id, reporter_id)
+);
+
+--=============================================================================
+-- SUSPENSIONS
+--=============================================================================
+
+-- A suspended user cannot send messages until suspended_until (NULL = indefinitely)
+CREATE TABLE IF NOT EXISTS messaging.suspensions (
+    user_id UUID PRIMARY KEY REFERENCES users.users(id) ON DELETE CASCADE,
+    reason TEXT NOT NULL,
+    report_id UUID REFERENCES messaging.message_reports(id) ON DELETE SET NULL,
+    suspended_by UUID REFERENCES users.users(id) ON DELETE SET NULL,
+    suspended_until TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_blocks_blocked ON messaging.blocks(blocked_id);
+CREATE INDEX IF NOT EXISTS idx_message_reports_queue ON messaging.message_reports(status, created_at);
+CREATE INDEX IF NOT EXISTS idx_message_reports_reported ON messaging.message_reports(reported_user_id, status);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 046_messaging_moderation.sql completed successfully';
+END $$;
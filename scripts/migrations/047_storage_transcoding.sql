@@ -0,0 +1,48 @@
+-- Migration: 047 - Storage Transcoding
+-- Description: Streamable opus/mp4 derivatives of uploaded audio and video, produced by the media worker
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- FILE DERIVATIVES
+--=============================================================================
+
+-- Each row is one encoded variant of a source file. The encoded output is an
+-- ordinary storage.files row so it can be downloaded and published like any
+-- other file.
+CREATE TABLE IF NOT EXISTS storage.file_derivatives (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    source_file_id UUID NOT NULL REFERENCES storage.files(id) ON DELETE CASCADE,
+    derivative_file_id UUID NOT NULL REFERENCES storage.files(id) ON DELETE CASCADE,
+    job_id UUID REFERENCES media.jobs(id) ON DELETE SET NULL,
+    format VARCHAR(16) NOT NULL,            -- 'opus' | 'mp4'
+    bitrate_kbps INTEGER NOT NULL,
+    content_type VARCHAR(100) NOT NULL,
+    duration_seconds DOUBLE PRECISION,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE (source_file_id, format, bitrate_kbps)
+);
+
+--=============================================================================
+-- MEDIA JOBS
+--=============================================================================
+
+-- The worker stamps progress updates with updated_at
+ALTER TABLE media.jobs ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ;
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_file_derivatives_derivative
+    ON storage.file_derivatives(derivative_file_id);
+
+-- Transcode jobs are looked up by the file they encode
+CREATE INDEX IF NOT EXISTS idx_media_jobs_transcode_source
+    ON media.jobs((input->>'source_file_id'), created_at DESC)
+    WHERE job_type = 'transcode';
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 047_storage_transcoding.sql completed successfully';
+END $$;
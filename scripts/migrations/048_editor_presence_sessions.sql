@@ -0,0 +1,45 @@
+-- Migration: 048 - Editor Presence Sessions
+-- Description: Per-tab presence sessions with assigned cursor colors, explicit leave and server-side heartbeat expiry
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- PRESENCE SESSIONS
+--=============================================================================
+
+-- Replaces editor.presence, which kept one position row per user and could not
+-- tell a client that a collaborator had left
+CREATE TABLE IF NOT EXISTS editor.presence_sessions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    document_id UUID NOT NULL REFERENCES editor.documents(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    color VARCHAR(7) NOT NULL,             -- '#rrggbb', shared by all of a user's live sessions
+    client_label VARCHAR(100),             -- e.g. 'Firefox on macOS'
+    cursor_position INTEGER,
+    selection_start INTEGER,
+    selection_end INTEGER,
+    joined_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    left_at TIMESTAMPTZ,
+    left_reason VARCHAR(10) CHECK (left_reason IN ('leave', 'timeout'))
+);
+
+DROP TABLE IF EXISTS editor.presence;
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_presence_sessions_live
+    ON editor.presence_sessions(document_id, joined_at) WHERE left_at IS NULL;
+
+CREATE INDEX IF NOT EXISTS idx_presence_sessions_departed
+    ON editor.presence_sessions(document_id, left_at DESC) WHERE left_at IS NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_presence_sessions_user
+    ON editor.presence_sessions(document_id, user_id, joined_at DESC);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 048_editor_presence_sessions.sql completed successfully';
+END $$;
@@ -0,0 +1,24 @@
+-- Migration: 049 - Subscription Entitlements
+-- Description: Machine-readable feature flags per plan, checked through POST /entitlements/check
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- PLAN FLAGS
+--=============================================================================
+
+-- `features` holds marketing copy; `flags` holds the keys services gate on
+ALTER TABLE subscriptions.plans ADD COLUMN IF NOT EXISTS flags JSONB NOT NULL DEFAULT '[]';
+
+UPDATE subscriptions.plans
+SET flags = '["collaboration", "version_history", "export_all_formats", "ai_overage"]'
+WHERE id = 'pro' AND flags = '[]';
+
+UPDATE subscriptions.plans
+SET flags = '["collaboration", "version_history", "export_all_formats", "api_access", "custom_ai_training", "sso"]'
+WHERE id = 'enterprise' AND flags = '[]';
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 049_subscription_entitlements.sql completed successfully';
+END $$;
@@ -0,0 +1,71 @@
+-- Migration: 050 - Discovery Duplicates
+-- Description: MinHash signatures computed at index time and a review queue of near-duplicate books and chapters
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- SIGNATURES
+--=============================================================================
+
+-- One MinHash signature per indexed book or chapter. A book's signature is the
+-- element-wise minimum of its chapters' (the MinHash of all its text).
+CREATE TABLE IF NOT EXISTS discovery.content_signatures (
+    doc_type VARCHAR(10) NOT NULL CHECK (doc_type IN ('book', 'chapter')),
+    doc_id UUID NOT NULL,
+    book_id UUID NOT NULL,
+    signature JSONB NOT NULL,              -- Array of 128 u32 minimums
+    shingle_count INTEGER NOT NULL,
+    computed_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (doc_type, doc_id)
+);
+
+-- Locality-sensitive hashing bands: documents sharing any band are candidates
+CREATE TABLE IF NOT EXISTS discovery.signature_bands (
+    doc_type VARCHAR(10) NOT NULL,
+    band SMALLINT NOT NULL,
+    band_hash BIGINT NOT NULL,
+    doc_id UUID NOT NULL,
+    PRIMARY KEY (doc_type, band, band_hash, doc_id)
+);
+
+--=============================================================================
+-- DUPLICATE FLAGS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS discovery.duplicate_flags (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    doc_type VARCHAR(10) NOT NULL CHECK (doc_type IN ('book', 'chapter')),
+    doc_id UUID NOT NULL,                  -- Document whose indexing raised the flag
+    book_id UUID NOT NULL,
+    match_doc_id UUID NOT NULL,            -- Earlier document it resembles
+    match_book_id UUID NOT NULL,
+    similarity DOUBLE PRECISION NOT NULL,  -- Estimated Jaccard similarity of 5-word shingles
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'confirmed', 'dismissed')),
+    review_note TEXT,
+    reviewed_by UUID,
+    reviewed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_content_signatures_book
+    ON discovery.content_signatures(book_id);
+
+CREATE INDEX IF NOT EXISTS idx_signature_bands_doc
+    ON discovery.signature_bands(doc_type, doc_id);
+
+-- A pair is flagged once, whichever side was indexed last
+CREATE UNIQUE INDEX IF NOT EXISTS idx_duplicate_flags_pair
+    ON discovery.duplicate_flags(doc_type, LEAST(doc_id, match_doc_id), GREATEST(doc_id, match_doc_id));
+
+CREATE INDEX IF NOT EXISTS idx_duplicate_flags_status
+    ON discovery.duplicate_flags(status, similarity DESC);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 050_discovery_duplicates.sql completed successfully';
+END $$;
@@ -0,0 +1,25 @@
+-- Migration: 051 - Content Outlines
+-- Description: Editable hierarchical book outlines (parts, chapters, scenes) with version stamps
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- OUTLINES
+--=============================================================================
+
+-- One outline per book. The tree is stored whole because the editor saves it
+-- whole after every drag-and-drop; `version` increases on each save so
+-- concurrent editors cannot overwrite each other silently.
+CREATE TABLE IF NOT EXISTS content.outlines (
+    book_id UUID PRIMARY KEY REFERENCES content.books(id) ON DELETE CASCADE,
+    outline JSONB NOT NULL DEFAULT '{"parts": []}',
+    version INTEGER NOT NULL DEFAULT 1,
+    updated_by UUID,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 051_content_outlines.sql completed successfully';
+END $$;
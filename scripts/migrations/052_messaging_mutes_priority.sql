@@ -0,0 +1,42 @@
+-- Migration: 052 - Messaging Mutes and Priority
+-- Description: Per-conversation notification muting and priority levels on notifications and events
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- PRIORITY
+--=============================================================================
+
+-- low < normal < high < urgent. Subscribers receive higher priorities first.
+ALTER TABLE messaging.notifications
+    ADD COLUMN IF NOT EXISTS priority VARCHAR(10) NOT NULL DEFAULT 'normal'
+        CHECK (priority IN ('low', 'normal', 'high', 'urgent'));
+
+ALTER TABLE messaging.events
+    ADD COLUMN IF NOT EXISTS priority VARCHAR(10) NOT NULL DEFAULT 'normal'
+        CHECK (priority IN ('low', 'normal', 'high', 'urgent'));
+
+--=============================================================================
+-- CONVERSATION MUTES
+--=============================================================================
+
+-- A member is muted while muted_at is set and muted_until is NULL (until
+-- unmuted) or in the future. Messages at or above mute_min_priority still
+-- notify; NULL silences everything.
+ALTER TABLE messaging.conversation_members
+    ADD COLUMN IF NOT EXISTS muted_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS muted_until TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS mute_min_priority VARCHAR(10)
+        CHECK (mute_min_priority IN ('low', 'normal', 'high', 'urgent'));
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_notifications_user_priority
+    ON messaging.notifications(user_id, priority) WHERE read = false;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 052_messaging_mutes_priority.sql completed successfully';
+END $$;
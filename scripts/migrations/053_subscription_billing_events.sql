@@ -0,0 +1,36 @@
+-- Migration: 053 - Subscription Billing Events
+-- Description: Append-only record of subscription changes and Stripe webhook events, read by the admin billing timeline
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- BILLING EVENTS
+--=============================================================================
+
+-- Credit movements and invoices keep their own tables; the timeline merges them
+-- with these rows. user_id is NULL for webhooks that match no subscription.
+CREATE TABLE IF NOT EXISTS subscriptions.billing_events (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID REFERENCES users.users(id) ON DELETE CASCADE,
+    event_type VARCHAR(100) NOT NULL,
+    source VARCHAR(20) NOT NULL CHECK (source IN ('user', 'admin', 'stripe', 'system')),
+    actor_id UUID,
+    stripe_event_id VARCHAR(255) UNIQUE,
+    details JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_billing_events_user
+    ON subscriptions.billing_events(user_id, created_at DESC);
+
+CREATE INDEX IF NOT EXISTS idx_credit_transactions_user_created
+    ON subscriptions.credit_transactions(user_id, created_at DESC);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 053_subscription_billing_events.sql completed successfully';
+END $$;
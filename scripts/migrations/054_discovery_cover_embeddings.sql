@@ -0,0 +1,25 @@
+-- Migration: 054 - Discovery Cover Embeddings
+-- Description: Image embeddings of book covers, used to weight visual similarity in GET /similar/:book_id
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- COVER EMBEDDINGS
+--=============================================================================
+
+-- One dense vector per book, recomputed when the cover URL or model changes.
+-- Vectors are L2-normalised on write so cosine similarity is a dot product.
+CREATE TABLE IF NOT EXISTS discovery.cover_embeddings (
+    book_id UUID PRIMARY KEY,
+    cover_url TEXT NOT NULL,
+    model VARCHAR(255) NOT NULL,
+    dimensions INTEGER NOT NULL,
+    embedding REAL[] NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 054_discovery_cover_embeddings.sql completed successfully';
+END $$;
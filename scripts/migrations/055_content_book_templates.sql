@@ -0,0 +1,138 @@
+-- Migration: 055 - Content Book Templates
+-- Description: Template library for POST /books/from-template, and front/back matter sections on chapters
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- CHAPTER SECTIONS
+--=============================================================================
+
+-- Front and back matter are ordinary chapters kept apart from the body
+ALTER TABLE content.chapters ADD COLUMN IF NOT EXISTS section VARCHAR(20) NOT NULL DEFAULT 'body';
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_constraint WHERE conname = 'chapters_section_check') THEN
+        ALTER TABLE content.chapters ADD CONSTRAINT chapters_section_check
+            CHECK (section IN ('front_matter', 'body', 'back_matter'));
+    END IF;
+END $$;
+
+--=============================================================================
+-- BOOK TEMPLATES
+--=============================================================================
+
+-- structure: {"front_matter": [{title, content}], "parts": [{title, summary,
+-- chapters: [{title, summary, target_words}]}], "back_matter": [...]}
+CREATE TABLE IF NOT EXISTS content.book_templates (
+    id VARCHAR(64) PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    description TEXT,
+    category VARCHAR(50) NOT NULL DEFAULT 'fiction',
+    genre VARCHAR(100),
+    structure JSONB NOT NULL,
+    metadata JSONB NOT NULL DEFAULT '{}',
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    sort_order INTEGER NOT NULL DEFAULT 0,
+    created_by UUID,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO content.book_templates (id, name, description, category, genre, structure, sort_order) VALUES
+('nonfiction-how-to', 'Non-fiction how-to',
+ 'A practical guide: the problem, the method in steps, and how to keep going.',
+ 'non-fiction', 'Self-Help',
+ '{
+   "front_matter": [
+     {"title": "Title Page", "content": "[Title]\n[Subtitle]\n[Author name]"},
+     {"title": "Copyright", "content": "Copyright © [Year] [Author name]. All rights reserved."},
+     {"title": "Introduction", "content": "[Who this book is for, the problem it solves, and what the reader will be able to do by the end.]"}
+   ],
+   "parts": [
+     {"title": "Part I: Foundations", "summary": "Why the problem matters and the principles behind the method.", "chapters": [
+       {"title": "The Problem", "summary": "Describe the reader''s pain point with a concrete story.", "target_words": 3000},
+       {"title": "Core Principles", "summary": "The ideas the rest of the book builds on.", "target_words": 4000}
+     ]},
+     {"title": "Part II: The Method", "summary": "The step-by-step process.", "chapters": [
+       {"title": "Step 1", "summary": "First step, with an example and an exercise.", "target_words": 4000},
+       {"title": "Step 2", "summary": "Second step, with an example and an exercise.", "target_words": 4000},
+       {"title": "Step 3", "summary": "Third step, with an example and an exercise.", "target_words": 4000}
+     ]},
+     {"title": "Part III: Putting It Into Practice", "summary": "Troubleshooting and making it stick.", "chapters": [
+       {"title": "Common Pitfalls", "summary": "Mistakes readers will make and how to recover.", "target_words": 3000},
+       {"title": "Next Steps", "summary": "A plan for the first 30 days.", "target_words": 2500}
+     ]}
+   ],
+   "back_matter": [
+     {"title": "Resources", "content": "[Tools, further reading and templates.]"},
+     {"title": "Acknowledgements", "content": "[Thank the people who helped.]"},
+     {"title": "About the Author", "content": "[Short author bio.]"}
+   ]
+ }', 10),
+('three-act-novel', 'Three-act novel',
+ 'Classic setup, confrontation and resolution, with the major turning points as chapters.',
+ 'fiction', 'Fiction',
+ '{
+   "front_matter": [
+     {"title": "Title Page", "content": "[Title]\n[Author name]"},
+     {"title": "Copyright", "content": "Copyright © [Year] [Author name]. All rights reserved. This is a work of fiction."},
+     {"title": "Dedication", "content": "[For ...]"}
+   ],
+   "parts": [
+     {"title": "Act I: Setup", "summary": "Introduce the protagonist, their world and what they want.", "chapters": [
+       {"title": "Opening Image", "summary": "The protagonist''s ordinary world and its flaw.", "target_words": 3000},
+       {"title": "Inciting Incident", "summary": "The event that upsets the status quo.", "target_words": 3000},
+       {"title": "First Plot Point", "summary": "The protagonist commits and there is no going back.", "target_words": 3500}
+     ]},
+     {"title": "Act II: Confrontation", "summary": "Rising obstacles test the protagonist.", "chapters": [
+       {"title": "Rising Action", "summary": "New allies, enemies and rules of the new world.", "target_words": 4000},
+       {"title": "Midpoint", "summary": "A reversal raises the stakes.", "target_words": 3500},
+       {"title": "Closing In", "summary": "The antagonist gains ground; plans fall apart.", "target_words": 4000},
+       {"title": "All Is Lost", "summary": "The lowest point and the lesson it forces.", "target_words": 3000}
+     ]},
+     {"title": "Act III: Resolution", "summary": "The protagonist acts on what they have learned.", "chapters": [
+       {"title": "Climax", "summary": "The final confrontation.", "target_words": 4500},
+       {"title": "Resolution", "summary": "The new normal and closing image.", "target_words": 2500}
+     ]}
+   ],
+   "back_matter": [
+     {"title": "Acknowledgements", "content": "[Thank the people who helped.]"},
+     {"title": "About the Author", "content": "[Short author bio.]"}
+   ]
+ }', 20),
+('short-story-collection', 'Short-story collection',
+ 'A set of standalone stories with a shared introduction.',
+ 'fiction', 'Short Stories',
+ '{
+   "front_matter": [
+     {"title": "Title Page", "content": "[Title]\n[Author name]"},
+     {"title": "Copyright", "content": "Copyright © [Year] [Author name]. All rights reserved."},
+     {"title": "Introduction", "content": "[What ties these stories together.]"}
+   ],
+   "parts": [
+     {"title": "", "chapters": [
+       {"title": "Story One", "summary": "Premise, protagonist and twist.", "target_words": 5000},
+       {"title": "Story Two", "summary": "Premise, protagonist and twist.", "target_words": 5000},
+       {"title": "Story Three", "summary": "Premise, protagonist and twist.", "target_words": 5000},
+       {"title": "Story Four", "summary": "Premise, protagonist and twist.", "target_words": 5000},
+       {"title": "Story Five", "summary": "Premise, protagonist and twist.", "target_words": 5000}
+     ]}
+   ],
+   "back_matter": [
+     {"title": "Story Notes", "content": "[Where each story came from.]"},
+     {"title": "About the Author", "content": "[Short author bio.]"}
+   ]
+ }', 30)
+ON CONFLICT (id) DO NOTHING;
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_book_templates_active ON content.book_templates(active, sort_order);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 055_content_book_templates.sql completed successfully';
+END $$;
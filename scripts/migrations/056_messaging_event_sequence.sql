@@ -0,0 +1,26 @@
+-- Migration: 056 - Messaging Event Sequence
+-- Description: Monotonically increasing event ids for SSE Last-Event-ID resumption
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- EVENT SEQUENCE
+--=============================================================================
+
+-- Sent as the SSE `id:` field. Existing rows are numbered in table order,
+-- new rows in insert order, so a reconnecting client's Last-Event-ID tells
+-- which events it has already seen.
+ALTER TABLE messaging.events ADD COLUMN IF NOT EXISTS seq BIGSERIAL;
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_events_seq ON messaging.events(seq);
+CREATE INDEX IF NOT EXISTS idx_events_user_seq ON messaging.events(user_id, seq)
+    WHERE delivered = false;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 056_messaging_event_sequence.sql completed successfully';
+END $$;
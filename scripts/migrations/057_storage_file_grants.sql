@@ -0,0 +1,39 @@
+-- Migration: 057 - Storage File Grants
+-- Description: Per-file read access for other users or for a book's collaborators
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- FILE GRANTS
+--=============================================================================
+
+-- A grant names either one user or a book; book grants cover the book's
+-- author and its active collaborators as they change over time
+CREATE TABLE IF NOT EXISTS storage.file_grants (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    file_id UUID NOT NULL REFERENCES storage.files(id) ON DELETE CASCADE,
+    grantee_id UUID,
+    book_id UUID,
+    permission VARCHAR(20) NOT NULL DEFAULT 'read' CHECK (permission IN ('read')),
+    granted_by UUID NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    revoked_at TIMESTAMPTZ,
+    CHECK ((grantee_id IS NULL) <> (book_id IS NULL))
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+-- One live grant per file and grantee, so granting twice is idempotent
+CREATE UNIQUE INDEX IF NOT EXISTS idx_file_grants_active_user
+    ON storage.file_grants(file_id, grantee_id) WHERE revoked_at IS NULL AND grantee_id IS NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_file_grants_active_book
+    ON storage.file_grants(file_id, book_id) WHERE revoked_at IS NULL AND book_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_file_grants_grantee ON storage.file_grants(grantee_id) WHERE revoked_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_file_grants_book ON storage.file_grants(book_id) WHERE revoked_at IS NULL;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 057_storage_file_grants.sql completed successfully';
+END $$;
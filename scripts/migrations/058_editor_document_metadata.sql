@@ -0,0 +1,22 @@
+-- Migration: 058 - Editor Document Metadata
+-- Description: Collaboratively edited title, synopsis and notes with per-field version vectors
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- DOCUMENT METADATA
+--=============================================================================
+
+-- {"title": {"value": ..., "clock": {"<user_id>": n}, "updated_by": ..., "updated_at": ...}, ...}
+ALTER TABLE editor.documents
+    ADD COLUMN IF NOT EXISTS metadata JSONB NOT NULL DEFAULT '{}'::jsonb;
+
+-- NULL for checkpoints taken before this migration; reverting to one leaves
+-- the current metadata in place
+ALTER TABLE editor.checkpoints
+    ADD COLUMN IF NOT EXISTS metadata JSONB;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 058_editor_document_metadata.sql completed successfully';
+END $$;
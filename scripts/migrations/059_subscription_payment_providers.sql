@@ -0,0 +1,33 @@
+-- Migration: 059 - Subscription Payment Providers
+-- Description: Record which payment provider (Stripe or LemonSqueezy) owns each subscription and invoice
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- PROVIDER COLUMNS
+--=============================================================================
+
+-- stripe_subscription_id / stripe_customer_id / stripe_invoice_id hold the
+-- ids of whichever provider is named here
+ALTER TABLE subscriptions.subscriptions
+    ADD COLUMN IF NOT EXISTS payment_provider VARCHAR(20) NOT NULL DEFAULT 'stripe'
+    CHECK (payment_provider IN ('stripe', 'lemonsqueezy'));
+
+ALTER TABLE subscriptions.invoices
+    ADD COLUMN IF NOT EXISTS payment_provider VARCHAR(20) NOT NULL DEFAULT 'stripe'
+    CHECK (payment_provider IN ('stripe', 'lemonsqueezy'));
+
+--=============================================================================
+-- BILLING EVENTS
+--=============================================================================
+
+-- Webhooks are attributed to the provider that sent them
+ALTER TABLE subscriptions.billing_events DROP CONSTRAINT IF EXISTS billing_events_source_check;
+ALTER TABLE subscriptions.billing_events
+    ADD CONSTRAINT billing_events_source_check
+    CHECK (source IN ('user', 'admin', 'stripe', 'lemonsqueezy', 'system'));
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 059_subscription_payment_providers.sql completed successfully';
+END $$;
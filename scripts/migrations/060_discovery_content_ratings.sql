@@ -0,0 +1,34 @@
+-- Migration: 060 - Discovery Content Ratings
+-- Description: Age ratings and content warnings on books, and per-reader safe search preferences
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- BOOK RATINGS
+--=============================================================================
+
+-- NULL is unrated; discovery shows unrated books unless safe search is strict
+ALTER TABLE content.books
+    ADD COLUMN IF NOT EXISTS content_rating VARCHAR(20)
+    CHECK (content_rating IN ('everyone', 'teen', 'mature', 'adult'));
+
+ALTER TABLE content.books
+    ADD COLUMN IF NOT EXISTS content_warnings JSONB NOT NULL DEFAULT '[]';
+
+--=============================================================================
+-- SEARCH PREFERENCES
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS discovery.search_preferences (
+    user_id UUID PRIMARY KEY REFERENCES users.users(id) ON DELETE CASCADE,
+    safe_search VARCHAR(20) NOT NULL DEFAULT 'moderate'
+        CHECK (safe_search IN ('strict', 'moderate', 'off')),
+    -- Normalized warning slugs hidden at every safe search level
+    hidden_warnings JSONB NOT NULL DEFAULT '[]',
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 060_discovery_content_ratings.sql completed successfully';
+END $$;
@@ -0,0 +1,30 @@
+-- Migration: 061 - Messaging Notification Templates
+-- Description: Named, localized notification templates with declared variables
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- TEMPLATES
+--=============================================================================
+
+-- One row per template name and language. Title and body use {{variable}}
+-- placeholders, each of which must appear in variables.
+CREATE TABLE IF NOT EXISTS messaging.templates (
+    id UUID PRIMARY KEY,
+    name VARCHAR(100) NOT NULL,
+    language VARCHAR(10) NOT NULL DEFAULT 'en',
+    type VARCHAR(50) NOT NULL,
+    title VARCHAR(255) NOT NULL,
+    body TEXT NOT NULL,
+    variables JSONB NOT NULL DEFAULT '[]',
+    -- NULL uses the default priority for the notification type
+    priority VARCHAR(10) CHECK (priority IN ('low', 'normal', 'high', 'urgent')),
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE (name, language)
+);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 061_messaging_templates.sql completed successfully';
+END $$;
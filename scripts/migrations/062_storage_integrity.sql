@@ -0,0 +1,46 @@
+-- Migration: 062 - Storage Integrity
+-- Description: Checksum verification results on files and a history of integrity checks
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- FILE STATUS
+--=============================================================================
+
+-- NULL until the file is first verified
+ALTER TABLE storage.files
+    ADD COLUMN IF NOT EXISTS integrity_status VARCHAR(20)
+    CHECK (integrity_status IN ('ok', 'checksum_mismatch', 'size_mismatch', 'missing'));
+
+ALTER TABLE storage.files
+    ADD COLUMN IF NOT EXISTS integrity_checked_at TIMESTAMPTZ;
+
+-- The audit picks never-checked files first, then the oldest checks
+CREATE INDEX IF NOT EXISTS idx_files_integrity_checked
+    ON storage.files(integrity_checked_at NULLS FIRST);
+
+--=============================================================================
+-- CHECK HISTORY
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS storage.integrity_checks (
+    id UUID PRIMARY KEY,
+    file_id UUID NOT NULL REFERENCES storage.files(id) ON DELETE CASCADE,
+    status VARCHAR(20) NOT NULL
+        CHECK (status IN ('ok', 'checksum_mismatch', 'size_mismatch', 'missing')),
+    expected_size BIGINT NOT NULL,
+    actual_size BIGINT,
+    expected_checksum VARCHAR(64) NOT NULL,
+    -- Only computed when the sizes match
+    actual_checksum VARCHAR(64),
+    source VARCHAR(20) NOT NULL CHECK (source IN ('manual', 'audit')),
+    checked_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_integrity_checks_file
+    ON storage.integrity_checks(file_id, checked_at DESC);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 062_storage_integrity.sql completed successfully';
+END $$;
@@ -0,0 +1,254 @@
+-- Migration: 063 - Credit Expiry and Bonus Tiers
+-- Description: Per-grant credit expiry with soonest-expiring-first consumption, package bonus credits, and expiry warnings
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- PACKAGE TIERS
+--=============================================================================
+
+-- Purchased credits expire expiry_days after purchase (NULL never expires);
+-- bonus credits are granted alongside and usually expire sooner
+ALTER TABLE subscriptions.credit_packages
+    ADD COLUMN IF NOT EXISTS bonus_credits INTEGER NOT NULL DEFAULT 0 CHECK (bonus_credits >= 0),
+    ADD COLUMN IF NOT EXISTS expiry_days INTEGER CHECK (expiry_days > 0),
+    ADD COLUMN IF NOT EXISTS bonus_expiry_days INTEGER CHECK (bonus_expiry_days > 0);
+
+ALTER TABLE subscriptions.credit_orders
+    ADD COLUMN IF NOT EXISTS bonus_credits INTEGER NOT NULL DEFAULT 0;
+
+UPDATE subscriptions.credit_packages SET expiry_days = 365 WHERE expiry_days IS NULL;
+UPDATE subscriptions.credit_packages SET bonus_credits = 500, bonus_expiry_days = 90 WHERE name = 'Writer Pack';
+UPDATE subscriptions.credit_packages SET bonus_credits = 2500, bonus_expiry_days = 90 WHERE name = 'Author Pack';
+UPDATE subscriptions.credit_packages SET bonus_credits = 10000, bonus_expiry_days = 180 WHERE name = 'Publisher Pack';
+
+--=============================================================================
+-- CREDIT GRANTS
+--=============================================================================
+
+-- Every credit addition is a grant; consumption draws from the grants that
+-- expire soonest. The sum of unexpired remaining equals credits.balance.
+CREATE TABLE IF NOT EXISTS subscriptions.credit_grants (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    transaction_id UUID REFERENCES subscriptions.credit_transactions(id) ON DELETE SET NULL,
+    transaction_type VARCHAR(50) NOT NULL,
+    amount INTEGER NOT NULL CHECK (amount > 0),
+    remaining INTEGER NOT NULL CHECK (remaining >= 0),
+    expires_at TIMESTAMPTZ,
+    expired_at TIMESTAMPTZ,
+    expiry_warned_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_credit_grants_user_open
+    ON subscriptions.credit_grants(user_id, expires_at NULLS LAST, created_at)
+    WHERE remaining > 0;
+
+CREATE INDEX IF NOT EXISTS idx_credit_grants_expiring
+    ON subscriptions.credit_grants(expires_at)
+    WHERE remaining > 0 AND expires_at IS NOT NULL;
+
+-- Existing balances predate expiry and never expire
+INSERT INTO subscriptions.credit_grants (user_id, transaction_type, amount, remaining, created_at)
+SELECT c.user_id, 'legacy_balance', c.balance, c.balance, c.created_at
+FROM subscriptions.credits c
+WHERE c.balance > 0
+  AND NOT EXISTS (SELECT 1 FROM subscriptions.credit_grants g WHERE g.user_id = c.user_id);
+
+--=============================================================================
+-- FUNCTIONS: CREDIT MANAGEMENT
+--=============================================================================
+
+-- Replaced by the version taking an expiry below
+DROP FUNCTION IF EXISTS subscriptions.add_credits(UUID, INTEGER, VARCHAR, VARCHAR, UUID, VARCHAR);
+
+CREATE OR REPLACE FUNCTION subscriptions.add_credits(
+    p_user_id UUID,
+    p_amount INTEGER,
+    p_transaction_type VARCHAR(50),
+    p_reason VARCHAR(255) DEFAULT NULL,
+    p_reference_id UUID DEFAULT NULL,
+    p_reference_type VARCHAR(50) DEFAULT NULL,
+    p_expires_at TIMESTAMPTZ DEFAULT NULL
+)
+RETURNS UUID AS $$
+DECLARE
+    v_transaction_id UUID;
+    v_new_balance INTEGER;
+BEGIN
+    INSERT INTO subscriptions.credits (user_id, balance, total_purchased)
+    VALUES (p_user_id, p_amount, p_amount)
+    ON CONFLICT (user_id)
+    DO UPDATE SET
+        balance = subscriptions.credits.balance + p_amount,
+        total_purchased = subscriptions.credits.total_purchased + p_amount,
+        updated_at = NOW()
+    RETURNING balance INTO v_new_balance;
+
+    INSERT INTO subscriptions.credit_transactions (
+        user_id, amount, balance_after, transaction_type,
+        reason, reference_id, reference_type
+    ) VALUES (
+        p_user_id, p_amount, v_new_balance, p_transaction_type,
+        p_reason, p_reference_id, p_reference_type
+    ) RETURNING id INTO v_transaction_id;
+
+    IF p_amount > 0 THEN
+        INSERT INTO subscriptions.credit_grants (
+            user_id, transaction_id, transaction_type, amount, remaining, expires_at
+        ) VALUES (
+            p_user_id, v_transaction_id, p_transaction_type, p_amount, p_amount, p_expires_at
+        );
+    END IF;
+
+    RETURN v_transaction_id;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Zero out expired grants for one user (or everyone when NULL), debiting the
+-- balance with an 'expiration' transaction per grant. Returns credits expired.
+CREATE OR REPLACE FUNCTION subscriptions.expire_credits(p_user_id UUID DEFAULT NULL)
+RETURNS INTEGER AS $$
+DECLARE
+    v_grant RECORD;
+    v_new_balance INTEGER;
+    v_total INTEGER := 0;
+BEGIN
+    FOR v_grant IN
+        SELECT id, user_id, remaining
+        FROM subscriptions.credit_grants
+        WHERE remaining > 0
+          AND expires_at <= NOW()
+          AND (p_user_id IS NULL OR user_id = p_user_id)
+        ORDER BY user_id, expires_at
+        FOR UPDATE
+    LOOP
+        UPDATE subscriptions.credit_grants
+        SET remaining = 0, expired_at = NOW()
+        WHERE id = v_grant.id;
+
+        UPDATE subscriptions.credits
+        SET balance = GREATEST(balance - v_grant.remaining, 0),
+            updated_at = NOW()
+        WHERE user_id = v_grant.user_id
+        RETURNING balance INTO v_new_balance;
+
+        INSERT INTO subscriptions.credit_transactions (
+            user_id, amount, balance_after, transaction_type,
+            reason, reference_id, reference_type
+        ) VALUES (
+            v_grant.user_id, -v_grant.remaining, COALESCE(v_new_balance, 0), 'expiration',
+            'Credits expired', v_grant.id, 'credit_grant'
+        );
+
+        v_total := v_total + v_grant.remaining;
+    END LOOP;
+
+    RETURN v_total;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Take credits from the soonest-expiring grants first (never-expiring last).
+-- Returns the transaction id, or NULL when the unexpired balance is too low.
+CREATE OR REPLACE FUNCTION subscriptions.deduct_credits(
+    p_user_id UUID,
+    p_amount INTEGER,
+    p_transaction_type VARCHAR(50),
+    p_reason VARCHAR(255),
+    p_reference_id UUID DEFAULT NULL,
+    p_reference_type VARCHAR(50) DEFAULT NULL
+)
+RETURNS UUID AS $$
+DECLARE
+    v_current_balance INTEGER;
+    v_new_balance INTEGER;
+    v_left INTEGER := p_amount;
+    v_grant RECORD;
+    v_take INTEGER;
+    v_transaction_id UUID;
+BEGIN
+    PERFORM subscriptions.expire_credits(p_user_id);
+
+    SELECT balance INTO v_current_balance
+    FROM subscriptions.credits
+    WHERE user_id = p_user_id
+    FOR UPDATE;
+
+    IF v_current_balance IS NULL OR v_current_balance < p_amount THEN
+        RETURN NULL;
+    END IF;
+
+    FOR v_grant IN
+        SELECT id, remaining
+        FROM subscriptions.credit_grants
+        WHERE user_id = p_user_id AND remaining > 0
+        ORDER BY expires_at ASC NULLS LAST, created_at ASC
+        FOR UPDATE
+    LOOP
+        EXIT WHEN v_left = 0;
+        v_take := LEAST(v_grant.remaining, v_left);
+        UPDATE subscriptions.credit_grants SET remaining = remaining - v_take WHERE id = v_grant.id;
+        v_left := v_left - v_take;
+    END LOOP;
+
+    UPDATE subscriptions.credits
+    SET balance = balance - p_amount,
+        total_consumed = total_consumed + CASE WHEN p_transaction_type = 'consumption' THEN p_amount ELSE 0 END,
+        updated_at = NOW()
+    WHERE user_id = p_user_id
+    RETURNING balance INTO v_new_balance;
+
+    INSERT INTO subscriptions.credit_transactions (
+        user_id, amount, balance_after, transaction_type,
+        reason, reference_id, reference_type
+    ) VALUES (
+        p_user_id, -p_amount, v_new_balance, p_transaction_type,
+        p_reason, p_reference_id, p_reference_type
+    ) RETURNING id INTO v_transaction_id;
+
+    RETURN v_transaction_id;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION subscriptions.consume_credits(
+    p_user_id UUID,
+    p_amount INTEGER,
+    p_reason VARCHAR(255),
+    p_reference_id UUID DEFAULT NULL,
+    p_reference_type VARCHAR(50) DEFAULT NULL
+)
+RETURNS BOOLEAN AS $$
+BEGIN
+    RETURN subscriptions.deduct_credits(
+        p_user_id, p_amount, 'consumption', p_reason, p_reference_id, p_reference_type
+    ) IS NOT NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Expired grants not yet swept by expire_credits do not count
+CREATE OR REPLACE FUNCTION subscriptions.has_sufficient_credits(
+    p_user_id UUID,
+    p_required_amount INTEGER
+)
+RETURNS BOOLEAN AS $$
+DECLARE
+    v_balance INTEGER;
+    v_expired INTEGER;
+BEGIN
+    SELECT balance INTO v_balance
+    FROM subscriptions.credits
+    WHERE user_id = p_user_id;
+
+    SELECT COALESCE(SUM(remaining), 0) INTO v_expired
+    FROM subscriptions.credit_grants
+    WHERE user_id = p_user_id AND remaining > 0 AND expires_at <= NOW();
+
+    RETURN COALESCE(v_balance, 0) - v_expired >= p_required_amount;
+END;
+$$ LANGUAGE plpgsql;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 063_subscription_credit_expiry.sql completed successfully';
+END $$;
@@ -0,0 +1,51 @@
+-- Migration: 064 - Discovery Ranking Experiments
+-- Description: A/B experiments over ranking formulas, with per-reader exposure logging
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- EXPERIMENTS
+--=============================================================================
+
+-- variants: [{"name": "control", "weight": 50}, {"name": "recency", "weight": 50}]
+CREATE TABLE IF NOT EXISTS discovery.experiments (
+    id VARCHAR(64) PRIMARY KEY,
+    surface VARCHAR(30) NOT NULL CHECK (surface IN ('search_books', 'recommendations')),
+    description TEXT,
+    variants JSONB NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'running' CHECK (status IN ('running', 'stopped')),
+    created_by UUID REFERENCES users.users(id) ON DELETE SET NULL,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    stopped_at TIMESTAMPTZ
+);
+
+-- One running experiment per surface, so a reader is never in two at once
+CREATE UNIQUE INDEX IF NOT EXISTS idx_experiments_running_surface
+    ON discovery.experiments(surface) WHERE status = 'running';
+
+--=============================================================================
+-- EXPOSURES
+--=============================================================================
+
+-- One row per ranked response served under an experiment
+CREATE TABLE IF NOT EXISTS discovery.experiment_exposures (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    experiment_id VARCHAR(64) NOT NULL REFERENCES discovery.experiments(id) ON DELETE CASCADE,
+    variant VARCHAR(50) NOT NULL,
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    query TEXT,
+    result_count INTEGER,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_experiment_exposures_user
+    ON discovery.experiment_exposures(experiment_id, user_id, created_at);
+
+-- The results report looks up each exposed reader's events
+CREATE INDEX IF NOT EXISTS idx_search_events_user_time
+    ON discovery.search_events(user_id, created_at) WHERE user_id IS NOT NULL;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 064_discovery_experiments.sql completed successfully';
+END $$;
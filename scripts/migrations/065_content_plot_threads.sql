@@ -0,0 +1,50 @@
+-- Migration: 065 - Plot Threads
+-- Description: Named plot threads per book, tagged on chapters and scenes, for continuity tracking
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- PLOT THREADS
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS content.plot_threads (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    book_id UUID NOT NULL REFERENCES content.books(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    description TEXT,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_plot_threads_book_name
+    ON content.plot_threads(book_id, lower(name));
+
+--=============================================================================
+-- THREAD APPEARANCES
+--=============================================================================
+
+-- A thread appearing in a chapter, or in one scene of it. `role` records what
+-- the chapter does with the thread; a 'resolved' appearance closes it.
+CREATE TABLE IF NOT EXISTS content.plot_thread_appearances (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    thread_id UUID NOT NULL REFERENCES content.plot_threads(id) ON DELETE CASCADE,
+    chapter_id UUID NOT NULL REFERENCES content.chapters(id) ON DELETE CASCADE,
+    scene_id UUID REFERENCES content.scenes(id) ON DELETE CASCADE,
+    role VARCHAR(20) NOT NULL DEFAULT 'advanced'
+        CHECK (role IN ('introduced', 'advanced', 'mentioned', 'resolved')),
+    note TEXT,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_plot_thread_appearances_unique
+    ON content.plot_thread_appearances(
+        thread_id, chapter_id, COALESCE(scene_id, '00000000-0000-0000-0000-000000000000'::uuid)
+    );
+
+CREATE INDEX IF NOT EXISTS idx_plot_thread_appearances_chapter
+    ON content.plot_thread_appearances(chapter_id);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 065_content_plot_threads.sql completed successfully';
+END $$;
@@ -0,0 +1,38 @@
+-- Migration: 066 - Messaging Data Exports
+-- Description: Asynchronous export of a user's conversations, notifications and events for data portability requests
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- DATA EXPORTS
+--=============================================================================
+
+-- One row per requested export: pending -> processing -> completed | failed.
+-- The finished file lives in the storage service, owned by the user.
+CREATE TABLE IF NOT EXISTS messaging.data_exports (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    format VARCHAR(10) NOT NULL CHECK (format IN ('json', 'csv')),
+    status VARCHAR(20) NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'processing', 'completed', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    file_id UUID,
+    size_bytes BIGINT,
+    record_counts JSONB,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_data_exports_pending ON messaging.data_exports(created_at) WHERE status IN ('pending', 'processing');
+CREATE INDEX IF NOT EXISTS idx_data_exports_user ON messaging.data_exports(user_id, created_at DESC);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 066_messaging_data_exports.sql completed successfully';
+END $$;
@@ -0,0 +1,50 @@
+-- Migration: 067 - Resumable Uploads
+-- Description: tus protocol upload state, so interrupted uploads resume from the last stored byte
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- TUS UPLOADS
+--=============================================================================
+
+-- One row per resumable upload. upload_offset only advances once a chunk is
+-- safely in the object store; file_id is set when the assembled file is stored.
+CREATE TABLE IF NOT EXISTS storage.tus_uploads (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    upload_length BIGINT NOT NULL CHECK (upload_length >= 0),
+    upload_offset BIGINT NOT NULL DEFAULT 0 CHECK (upload_offset >= 0),
+    filename VARCHAR(255) NOT NULL,
+    content_type VARCHAR(255) NOT NULL,
+    file_type VARCHAR(50) NOT NULL,
+    collection_id UUID REFERENCES storage.collections(id) ON DELETE SET NULL,
+    metadata JSONB NOT NULL DEFAULT '{}',
+    file_id UUID REFERENCES storage.files(id) ON DELETE SET NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ,
+    CHECK (upload_offset <= upload_length)
+);
+
+-- Each PATCH is stored as its own object until the upload is complete
+CREATE TABLE IF NOT EXISTS storage.tus_upload_chunks (
+    upload_id UUID NOT NULL REFERENCES storage.tus_uploads(id) ON DELETE CASCADE,
+    chunk_offset BIGINT NOT NULL,
+    size BIGINT NOT NULL CHECK (size > 0),
+    s3_key TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (upload_id, chunk_offset)
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_tus_uploads_expires ON storage.tus_uploads(expires_at);
+CREATE INDEX IF NOT EXISTS idx_tus_uploads_user ON storage.tus_uploads(user_id, created_at DESC);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 067_storage_tus_uploads.sql completed successfully';
+END $$;
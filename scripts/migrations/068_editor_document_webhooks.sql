@@ -0,0 +1,56 @@
+-- Migration: 068 - Editor Document Webhooks
+-- Description: Per-document webhooks for checkpoints, word-count milestones and comments, with a signed delivery log
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- DOCUMENT WEBHOOKS
+--=============================================================================
+
+-- events is a subset of checkpoint.created, version.milestone, comment.added;
+-- word_milestones are the word counts that fire version.milestone
+CREATE TABLE IF NOT EXISTS editor.document_webhooks (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    document_id UUID NOT NULL REFERENCES content.chapters(id) ON DELETE CASCADE,
+    created_by UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    events TEXT[] NOT NULL,
+    word_milestones INTEGER[] NOT NULL DEFAULT '{}',
+    secret VARCHAR(64) NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT true,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (document_id, url)
+);
+
+-- One row per event per webhook: pending -> sending -> delivered | failed.
+-- dedupe_key keeps a milestone from firing twice when an undo/redo crosses it again.
+CREATE TABLE IF NOT EXISTS editor.document_webhook_deliveries (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    webhook_id UUID NOT NULL REFERENCES editor.document_webhooks(id) ON DELETE CASCADE,
+    event_type VARCHAR(50) NOT NULL,
+    dedupe_key TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'sending', 'delivered', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    response_status INTEGER,
+    last_error TEXT,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMPTZ,
+    UNIQUE (webhook_id, dedupe_key)
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_document_webhooks_document ON editor.document_webhooks(document_id) WHERE active;
+CREATE INDEX IF NOT EXISTS idx_document_webhook_deliveries_due ON editor.document_webhook_deliveries(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_document_webhook_deliveries_webhook ON editor.document_webhook_deliveries(webhook_id, created_at DESC);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 068_editor_document_webhooks.sql completed successfully';
+END $$;
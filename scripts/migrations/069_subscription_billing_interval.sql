@@ -0,0 +1,26 @@
+-- Migration: 069 - Subscription Billing Interval
+-- Description: Monthly or yearly billing per subscription, with a yearly Stripe price per plan
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- PLANS
+--=============================================================================
+
+-- stripe_price_id stays the monthly price; plans without a yearly price fall
+-- back to the stripe_price_<plan id>_yearly variable
+ALTER TABLE subscriptions.plans
+    ADD COLUMN IF NOT EXISTS stripe_price_id_yearly VARCHAR(255);
+
+--=============================================================================
+-- SUBSCRIPTIONS
+--=============================================================================
+
+ALTER TABLE subscriptions.subscriptions
+    ADD COLUMN IF NOT EXISTS billing_interval VARCHAR(10) NOT NULL DEFAULT 'month'
+    CHECK (billing_interval IN ('month', 'year'));
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 069_subscription_billing_interval.sql completed successfully';
+END $$;
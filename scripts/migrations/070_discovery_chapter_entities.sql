@@ -0,0 +1,31 @@
+-- Migration: 070 - Discovery Chapter Entities
+-- Description: Characters, places and other named entities extracted from chapters at index time
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- CHAPTER ENTITIES
+--=============================================================================
+
+-- Replaced wholesale each time a chapter is indexed. entity_key is the
+-- lowercased name, so "the Citadel" and "The Citadel" are one entity.
+CREATE TABLE IF NOT EXISTS discovery.chapter_entities (
+    chapter_id UUID NOT NULL,
+    book_id UUID NOT NULL,
+    entity_key VARCHAR(100) NOT NULL,
+    name VARCHAR(100) NOT NULL,
+    mentions INTEGER NOT NULL CHECK (mentions > 0),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (chapter_id, entity_key)
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_chapter_entities_book ON discovery.chapter_entities(book_id, entity_key);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 070_discovery_chapter_entities.sql completed successfully';
+END $$;
@@ -0,0 +1,47 @@
+-- Migration: 071 - Whole-Book Generation
+-- Description: Parent generation jobs that run outline, chapter, scene and content phases in order
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- CHILD JOBS
+--=============================================================================
+
+-- Jobs queued by a book generation point back at it and record their phase.
+-- The parent itself is never 'pending', so the worker does not pick it up.
+ALTER TABLE content.generation_jobs
+ADD COLUMN IF NOT EXISTS parent_job_id UUID REFERENCES content.generation_jobs(id) ON DELETE CASCADE,
+ADD COLUMN IF NOT EXISTS phase VARCHAR(20);
+
+--=============================================================================
+-- PHASES
+--=============================================================================
+
+-- One row per phase of a book generation. A phase starts once every earlier
+-- phase is completed or skipped; total_items is the number of child jobs it
+-- queued, one per chapter after the outline.
+CREATE TABLE IF NOT EXISTS content.generation_phases (
+    job_id UUID NOT NULL REFERENCES content.generation_jobs(id) ON DELETE CASCADE,
+    phase VARCHAR(20) NOT NULL CHECK (phase IN ('outline', 'chapters', 'scenes', 'content')),
+    position SMALLINT NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'running', 'completed', 'skipped', 'failed', 'cancelled')),
+    total_items INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    started_at TIMESTAMPTZ,
+    completed_at TIMESTAMPTZ,
+    PRIMARY KEY (job_id, phase)
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_generation_jobs_parent ON content.generation_jobs(parent_job_id, phase)
+    WHERE parent_job_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_generation_jobs_book_type ON content.generation_jobs(job_type, status);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 071_content_book_generation.sql completed successfully';
+END $$;
@@ -0,0 +1,26 @@
+-- Migration: 072 - Notification Groups
+-- Description: Grouping keys so related notifications collapse to one row in the notification list
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- GROUP KEYS
+--=============================================================================
+
+-- Notifications sharing a group_key, e.g. every "new comment" on one
+-- chapter, are listed as a single row carrying the latest member and a
+-- count. NULL keeps a notification on its own.
+ALTER TABLE messaging.notifications
+    ADD COLUMN IF NOT EXISTS group_key VARCHAR(255);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_notifications_user_group
+    ON messaging.notifications(user_id, group_key, created_at DESC) WHERE group_key IS NOT NULL;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 072_messaging_notification_groups.sql completed successfully';
+END $$;
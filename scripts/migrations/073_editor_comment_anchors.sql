@@ -0,0 +1,26 @@
+-- Migration: 073 - Sticky Comment Anchors
+-- Description: Track comments whose anchored text has been deleted
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- ORPHANED COMMENTS
+--=============================================================================
+
+-- position_start/position_end now move with every applied operation. Once a
+-- comment's whole range is deleted it is orphaned and its positions freeze.
+ALTER TABLE editor.comments
+ADD COLUMN IF NOT EXISTS orphaned BOOLEAN NOT NULL DEFAULT false,
+ADD COLUMN IF NOT EXISTS orphaned_at TIMESTAMPTZ;
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_comments_live ON editor.comments(document_id)
+    WHERE orphaned = false;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 073_editor_comment_anchors.sql completed successfully';
+END $$;
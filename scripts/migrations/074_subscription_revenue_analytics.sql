@@ -0,0 +1,21 @@
+-- Migration: 074 - Subscription Revenue Analytics
+-- Description: Indexes for the admin MRR, churn and revenue report
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+-- The report scans paid invoices by month and joins each to its subscription
+-- for the billing interval
+CREATE INDEX IF NOT EXISTS idx_invoices_paid_created
+    ON subscriptions.invoices(created_at) WHERE status = 'paid';
+
+CREATE INDEX IF NOT EXISTS idx_subscriptions_customer
+    ON subscriptions.subscriptions(stripe_customer_id);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 074_subscription_revenue_analytics.sql completed successfully';
+END $$;
@@ -0,0 +1,46 @@
+-- Migration: 075 - Discovery Keyword Alerts
+-- Description: Author keyword alerts percolated against newly published books
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- KEYWORD ALERTS
+--=============================================================================
+
+-- The query itself lives in the authorworks-keyword-alerts percolator index
+-- under the same id
+CREATE TABLE IF NOT EXISTS discovery.keyword_alerts (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    keywords VARCHAR(200) NOT NULL,
+    genre VARCHAR(100),
+    match_count INTEGER NOT NULL DEFAULT 0,
+    last_matched_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+--=============================================================================
+-- MATCHES
+--=============================================================================
+
+-- One row per book an alert matched, so re-indexing a book never notifies twice
+CREATE TABLE IF NOT EXISTS discovery.keyword_alert_matches (
+    alert_id UUID NOT NULL REFERENCES discovery.keyword_alerts(id) ON DELETE CASCADE,
+    book_id UUID NOT NULL REFERENCES content.books(id) ON DELETE CASCADE,
+    matched_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    notified_at TIMESTAMPTZ,
+    PRIMARY KEY (alert_id, book_id)
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_keyword_alerts_user ON discovery.keyword_alerts(user_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_keyword_alert_matches_pending ON discovery.keyword_alert_matches(book_id)
+    WHERE notified_at IS NULL;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 075_discovery_keyword_alerts.sql completed successfully';
+END $$;
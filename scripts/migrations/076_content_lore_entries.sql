@@ -0,0 +1,45 @@
+-- Migration: 076 - World Lore
+-- Description: Wiki entries for the places, items and factions of a book's world, shared across its series and referenced from chapters
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- LORE ENTRIES
+--=============================================================================
+
+-- `aliases` is a JSON array of other names; generation matches them like the
+-- name when picking the lore relevant to a chapter.
+CREATE TABLE IF NOT EXISTS content.lore_entries (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    book_id UUID NOT NULL REFERENCES content.books(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    kind VARCHAR(20) NOT NULL CHECK (kind IN ('place', 'item', 'faction')),
+    summary TEXT,
+    details TEXT,
+    aliases JSONB NOT NULL DEFAULT '[]',
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_lore_entries_book_name
+    ON content.lore_entries(book_id, lower(name));
+
+--=============================================================================
+-- CHAPTER REFERENCES
+--=============================================================================
+
+CREATE TABLE IF NOT EXISTS content.lore_references (
+    entry_id UUID NOT NULL REFERENCES content.lore_entries(id) ON DELETE CASCADE,
+    chapter_id UUID NOT NULL REFERENCES content.chapters(id) ON DELETE CASCADE,
+    note TEXT,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (entry_id, chapter_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_lore_references_chapter
+    ON content.lore_references(chapter_id);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 076_content_lore_entries.sql completed successfully';
+END $$;
@@ -0,0 +1,40 @@
+-- Migration: 077 - Scheduled Messages
+-- Description: Messages held for delivery at a later time, dispatched by a sweep and cancellable until sent
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- SCHEDULED MESSAGES
+--=============================================================================
+
+-- A message waits here as 'pending' until its time comes. The dispatch sweep
+-- claims it ('sending'), posts it to messaging.messages and records the
+-- posted message, or marks it 'failed' when the sender may no longer send it.
+CREATE TABLE IF NOT EXISTS messaging.scheduled_messages (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    conversation_id UUID NOT NULL REFERENCES messaging.conversations(id) ON DELETE CASCADE,
+    sender_id UUID NOT NULL,
+    body TEXT NOT NULL,
+    attachments JSONB NOT NULL DEFAULT '[]',
+    priority VARCHAR(10) NOT NULL DEFAULT 'normal',
+    status VARCHAR(20) NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'sending', 'sent', 'cancelled', 'failed')),
+    scheduled_at TIMESTAMPTZ NOT NULL,
+    claimed_at TIMESTAMPTZ,
+    sent_at TIMESTAMPTZ,
+    cancelled_at TIMESTAMPTZ,
+    message_id UUID REFERENCES messaging.messages(id) ON DELETE SET NULL,
+    error TEXT,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_scheduled_messages_due
+    ON messaging.scheduled_messages(scheduled_at) WHERE status = 'pending';
+
+CREATE INDEX IF NOT EXISTS idx_scheduled_messages_sender
+    ON messaging.scheduled_messages(sender_id, status, scheduled_at);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 077_messaging_scheduled_messages.sql completed successfully';
+END $$;
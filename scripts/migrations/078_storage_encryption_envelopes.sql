@@ -0,0 +1,21 @@
+-- Migration: 078 - Client-Side Encryption Envelopes
+-- Description: Encryption parameters for files uploaded as client-side encrypted ciphertext
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- FILE ENVELOPES
+--=============================================================================
+
+-- Algorithm, wrapped data key, IV, optional key id and plaintext content type.
+-- NULL for ordinary files; the service never stores an unwrapped key.
+ALTER TABLE storage.files
+    ADD COLUMN IF NOT EXISTS encryption JSONB;
+
+CREATE INDEX IF NOT EXISTS idx_files_encrypted
+    ON storage.files(user_id) WHERE encryption IS NOT NULL;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 078_storage_encryption_envelopes.sql completed successfully';
+END $$;
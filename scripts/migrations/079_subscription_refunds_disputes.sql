@@ -0,0 +1,104 @@
+-- Migration: 079 - Refunds and Disputes
+-- Description: Refund tracking on invoices, credit reversal for refunded or charged-back payments, and reversed referrals
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- INVOICES
+--=============================================================================
+
+-- Refunds and disputes name the payment intent, not the invoice. Status moves
+-- paid -> refunded once fully refunded, or paid -> disputed -> paid | charged_back.
+ALTER TABLE subscriptions.invoices
+    ADD COLUMN IF NOT EXISTS stripe_payment_intent_id VARCHAR(255),
+    ADD COLUMN IF NOT EXISTS amount_refunded BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS refunded_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_invoices_payment_intent
+    ON subscriptions.invoices(stripe_payment_intent_id) WHERE stripe_payment_intent_id IS NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_invoices_stripe_invoice
+    ON subscriptions.invoices(stripe_invoice_id);
+
+--=============================================================================
+-- CREDIT ORDERS
+--=============================================================================
+
+-- Credits owed back by refunds so far, whether or not they were still unspent;
+-- later partial refunds take only the difference
+ALTER TABLE subscriptions.credit_orders
+    ADD COLUMN IF NOT EXISTS credits_revoked INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS idx_credit_orders_payment_intent
+    ON subscriptions.credit_orders(stripe_payment_intent_id) WHERE stripe_payment_intent_id IS NOT NULL;
+
+--=============================================================================
+-- REFERRALS
+--=============================================================================
+
+-- rewarded -> reversed when the converting invoice is refunded or charged back
+ALTER TABLE subscriptions.referrals DROP CONSTRAINT IF EXISTS referrals_status_check;
+ALTER TABLE subscriptions.referrals ADD CONSTRAINT referrals_status_check
+    CHECK (status IN ('signed_up', 'rewarded', 'rejected', 'reversed'));
+
+--=============================================================================
+-- FUNCTIONS: CREDIT REVERSAL
+--=============================================================================
+
+-- Take back up to p_amount unspent, unexpired credits from the grants made
+-- with a reference (an order or referral), latest-expiring first, with a
+-- 'refund_reversal' transaction per grant. Returns credits taken back.
+CREATE OR REPLACE FUNCTION subscriptions.revoke_credits(
+    p_reference_id UUID,
+    p_reference_type VARCHAR(50),
+    p_amount INTEGER,
+    p_reason VARCHAR(255)
+)
+RETURNS INTEGER AS $$
+DECLARE
+    v_grant RECORD;
+    v_left INTEGER := p_amount;
+    v_take INTEGER;
+    v_new_balance INTEGER;
+BEGIN
+    FOR v_grant IN
+        SELECT g.id, g.user_id, g.remaining
+        FROM subscriptions.credit_grants g
+        JOIN subscriptions.credit_transactions t ON t.id = g.transaction_id
+        WHERE t.reference_id = p_reference_id
+          AND t.reference_type = p_reference_type
+          AND g.remaining > 0
+          AND (g.expires_at IS NULL OR g.expires_at > NOW())
+        ORDER BY g.expires_at DESC NULLS FIRST, g.created_at DESC
+        FOR UPDATE OF g
+    LOOP
+        EXIT WHEN v_left <= 0;
+        v_take := LEAST(v_grant.remaining, v_left);
+
+        UPDATE subscriptions.credit_grants SET remaining = remaining - v_take WHERE id = v_grant.id;
+
+        UPDATE subscriptions.credits
+        SET balance = GREATEST(balance - v_take, 0),
+            updated_at = NOW()
+        WHERE user_id = v_grant.user_id
+        RETURNING balance INTO v_new_balance;
+
+        INSERT INTO subscriptions.credit_transactions (
+            user_id, amount, balance_after, transaction_type,
+            reason, reference_id, reference_type
+        ) VALUES (
+            v_grant.user_id, -v_take, COALESCE(v_new_balance, 0), 'refund_reversal',
+            p_reason, p_reference_id, p_reference_type
+        );
+
+        v_left := v_left - v_take;
+    END LOOP;
+
+    RETURN p_amount - GREATEST(v_left, 0);
+END;
+$$ LANGUAGE plpgsql;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 079_subscription_refunds_disputes.sql completed successfully';
+END $$;
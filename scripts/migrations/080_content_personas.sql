@@ -0,0 +1,35 @@
+-- Migration: 080 - Co-writing Personas
+-- Description: Per-book AI personas (tone, vocabulary, banned words, standing memory) carried by every generation job
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- PERSONAS
+--=============================================================================
+
+-- `banned_words` is a JSON array of words and phrases generation must avoid;
+-- `memory` holds standing instructions repeated into every prompt.
+CREATE TABLE IF NOT EXISTS content.personas (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    book_id UUID NOT NULL REFERENCES content.books(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    tone TEXT,
+    vocabulary TEXT,
+    banned_words JSONB NOT NULL DEFAULT '[]',
+    memory TEXT,
+    is_active BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_personas_book_name
+    ON content.personas(book_id, lower(name));
+
+-- At most one active persona per book
+CREATE UNIQUE INDEX IF NOT EXISTS idx_personas_book_active
+    ON content.personas(book_id) WHERE is_active;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 080_content_personas.sql completed successfully';
+END $$;
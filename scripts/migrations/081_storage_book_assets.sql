@@ -0,0 +1,25 @@
+-- Migration: 081 - Storage Book Assets
+-- Description: Link files to a book and chapter so a book's assets can be listed and orphans found once it is deleted
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- FILE LINKS
+--=============================================================================
+
+-- No foreign keys: a link to a book that no longer exists is what marks a
+-- file as orphaned for `POST /files/orphans`
+ALTER TABLE storage.files
+    ADD COLUMN IF NOT EXISTS book_id UUID,
+    ADD COLUMN IF NOT EXISTS chapter_id UUID;
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_files_book ON storage.files(book_id, chapter_id) WHERE book_id IS NOT NULL;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 081_storage_book_assets.sql completed successfully';
+END $$;
@@ -0,0 +1,23 @@
+-- Migration: 082 - Subscription Pauses
+-- Description: Pause billing for a fixed number of days with read-only access, resumed early or by an expiry sweep
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- SUBSCRIPTIONS
+--=============================================================================
+
+-- status = 'paused' while billing is stopped. `pause_resumes_at` outlives the
+-- provider's own resume so the expiry sweep can notify the author, and is
+-- cleared once it has.
+ALTER TABLE subscriptions.subscriptions
+    ADD COLUMN IF NOT EXISTS paused_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS pause_resumes_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_subscriptions_pause_resumes
+    ON subscriptions.subscriptions(pause_resumes_at) WHERE pause_resumes_at IS NOT NULL;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 082_subscription_pauses.sql completed successfully';
+END $$;
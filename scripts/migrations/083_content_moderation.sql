@@ -0,0 +1,57 @@
+-- Migration: 083 - Content Moderation
+-- Description: Scan books with a moderation API on publish and hold flagged books in a human review queue
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- MODERATION REPORTS
+--=============================================================================
+
+-- One row per publish attempt while moderation is enabled. The book stays in
+-- `pending_review` while its report is `queued` (scan not done yet, retried
+-- by `POST /moderation/process`) or `flagged` (waiting for a moderator).
+-- `flagged_sections` lists the title, description or chapter passages the
+-- API flagged, with their categories and a short excerpt for the reviewer.
+CREATE TABLE IF NOT EXISTS content.moderation_reports (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    book_id UUID NOT NULL REFERENCES content.books(id) ON DELETE CASCADE,
+    author_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    status VARCHAR(20) NOT NULL DEFAULT 'queued'
+        CHECK (status IN ('queued', 'passed', 'flagged', 'approved', 'rejected', 'withdrawn')),
+    model VARCHAR(100),
+    categories JSONB NOT NULL DEFAULT '[]',
+    category_scores JSONB NOT NULL DEFAULT '{}',
+    flagged_sections JSONB NOT NULL DEFAULT '[]',
+    sections_scanned INTEGER NOT NULL DEFAULT 0,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_attempt_at TIMESTAMPTZ,
+    last_error TEXT,
+    scanned_at TIMESTAMPTZ,
+    reviewed_by UUID REFERENCES users.users(id) ON DELETE SET NULL,
+    reviewed_at TIMESTAMPTZ,
+    review_note TEXT,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_moderation_reports_book
+    ON content.moderation_reports(book_id, created_at DESC);
+
+-- The review queue and the retry sweep only look at open reports
+CREATE INDEX IF NOT EXISTS idx_moderation_reports_open
+    ON content.moderation_reports(status, created_at)
+    WHERE status IN ('queued', 'flagged');
+
+-- At most one open report per book
+CREATE UNIQUE INDEX IF NOT EXISTS idx_moderation_reports_book_open
+    ON content.moderation_reports(book_id)
+    WHERE status IN ('queued', 'flagged');
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 083_content_moderation.sql completed successfully';
+END $$;
@@ -0,0 +1,18 @@
+-- Migration: 084 - Messaging Event Type Filters
+-- Description: Index pending events by type for `GET /events/subscribe?types=`
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+-- Filtered subscribers claim, acknowledge and redeliver only their own types,
+-- so pending events are looked up by user and type together
+CREATE INDEX IF NOT EXISTS idx_events_user_type_pending ON messaging.events(user_id, type, seq)
+    WHERE delivered = false AND dead_lettered_at IS NULL;
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 084_messaging_event_type_filters.sql completed successfully';
+END $$;
@@ -0,0 +1,34 @@
+-- Migration: 085 - Storage Upload Intents
+-- Description: Record the size and content type a presigned upload was issued for so confirmation can enforce them
+-- Date: 2026-10-16
+-- Author: AuthorWorks Team
+
+--=============================================================================
+-- UPLOAD INTENTS
+--=============================================================================
+
+-- Written by `POST /upload/presigned` and removed once the upload is
+-- confirmed. `content_type` is the declared type of the file; encrypted
+-- uploads are stored as application/octet-stream ciphertext instead.
+CREATE TABLE IF NOT EXISTS storage.upload_intents (
+    file_id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users.users(id) ON DELETE CASCADE,
+    s3_key TEXT NOT NULL,
+    file_type VARCHAR(50) NOT NULL,
+    content_type VARCHAR(255) NOT NULL,
+    size BIGINT NOT NULL CHECK (size > 0),
+    encrypted BOOLEAN NOT NULL DEFAULT FALSE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+--=============================================================================
+-- INDEXES
+--=============================================================================
+
+CREATE INDEX IF NOT EXISTS idx_upload_intents_user_expiry ON storage.upload_intents(user_id, expires_at);
+
+DO $$
+BEGIN
+    RAISE NOTICE 'Migration 085_storage_upload_intents.sql completed successfully';
+END $$;
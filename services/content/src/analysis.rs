@@ -0,0 +1,114 @@
+//! Manuscript analysis reports
+//!
+//! Analysis jobs run through the content worker like generation jobs. The
+//! service creates a pending report alongside the job; the worker fills in
+//! the findings (each anchored to a chapter) and marks it completed.
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::Utc;
+use uuid::Uuid;
+
+pub const REPORT_CONTINUITY: &str = "continuity";
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize, Default)]
+pub struct AnalyzeContinuityRequest {
+    /// Limit the check to these chapters; defaults to the whole book
+    pub chapter_ids: Option<Vec<Uuid>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Finding {
+    /// `character`, `timeline`, `plot_hole`, or `setting`
+    pub category: String,
+    /// `low`, `medium`, or `high`
+    pub severity: String,
+    pub chapter_id: Option<Uuid>,
+    pub chapter_number: Option<i32>,
+    pub description: String,
+    pub excerpt: Option<String>,
+    pub suggestion: Option<String>,
+    /// Earlier chapter the finding conflicts with, if any
+    pub related_chapter_number: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnalysisReport {
+    pub id: Uuid,
+    pub book_id: Uuid,
+    pub job_id: Option<Uuid>,
+    pub report_type: String,
+    pub status: String,
+    pub findings: Vec<Finding>,
+    pub summary: Option<String>,
+    pub error: Option<String>,
+    pub created_at: String,
+    pub completed_at: Option<String>,
+}
+
+//=============================================================================
+// Reports
+//=============================================================================
+
+/// Create the pending report that the worker completes for `job_id`
+pub fn create_report(conn: &Connection, book_id: &Uuid, job_id: &Uuid, report_type: &str) -> Result<Uuid, ServiceError> {
+    let id = Uuid::new_v4();
+    let insert = "INSERT INTO content.analysis_reports (id, book_id, job_id, report_type, status, findings, created_at)
+                  VALUES ($1, $2, $3, $4, 'pending', '[]', $5)";
+    conn.execute(insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(report_type.to_string()),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    Ok(id)
+}
+
+/// GET /books/:id/reports - Analysis reports, newest first, with findings
+/// ordered by severity and chapter
+pub fn list_reports(conn: &Connection, book_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT id, job_id, report_type, status, findings::text, summary, error, created_at, completed_at
+                 FROM content.analysis_reports
+                 WHERE book_id = $1
+                 ORDER BY created_at DESC
+                 LIMIT 20";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let reports: Vec<AnalysisReport> = rows.rows.iter().map(|row| {
+        let mut findings: Vec<Finding> = serde_json::from_str(&String::decode(&row[4]).unwrap_or_else(|_| "[]".into()))
+            .unwrap_or_default();
+        findings.sort_by_key(|f| (severity_rank(&f.severity), f.chapter_number.unwrap_or(i32::MAX)));
+
+        AnalysisReport {
+            id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+            book_id: *book_id,
+            job_id: String::decode(&row[1]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+            report_type: String::decode(&row[2]).unwrap_or_default(),
+            status: String::decode(&row[3]).unwrap_or_default(),
+            findings,
+            summary: String::decode(&row[5]).ok(),
+            error: String::decode(&row[6]).ok(),
+            created_at: String::decode(&row[7]).unwrap_or_default(),
+            completed_at: String::decode(&row[8]).ok(),
+        }
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({ "reports": reports }))
+}
+
+fn severity_rank(severity: &str) -> u8 {
+    match severity {
+        "high" => 0,
+        "medium" => 1,
+        "low" => 2,
+        _ => 3,
+    }
+}
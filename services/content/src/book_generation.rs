@@ -0,0 +1,724 @@
+//! Whole-book generation
+//!
+//! `POST /generate/book` creates a parent job that drives the content worker
+//! through four phases, each waiting for the one before it:
+//!
+//! 1. `outline`  - one outline job, which creates the chapters (skipped when
+//!    the book already has planned chapters)
+//! 2. `chapters` - a plan per chapter, expanding its outline into beats
+//! 3. `scenes`   - a scene breakdown per chapter, stored as `content.scenes`
+//! 4. `content`  - the prose of each chapter, written from its plan and scenes
+//!
+//! The parent is never `pending`, so the worker only ever sees the child jobs.
+//! Advancing is idempotent and runs on creation, on resume and from the
+//! internal `POST /generate/book/advance` sweep. Credits are charged per child
+//! job as its phase is queued. When a child fails, its phase and the parent
+//! fail and later phases stay unqueued; resuming re-queues the failed children
+//! and queues anything the phase is still missing. Cancelling drops queued
+//! children and every phase not yet finished.
+
+use crate::credits;
+use crate::error::ServiceError;
+use crate::{lore, personas, series, threads};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+pub const JOB_TYPE: &str = "book";
+
+const PHASES: [&str; 4] = ["outline", "chapters", "scenes", "content"];
+
+const DEFAULT_CHAPTER_COUNT: i32 = 10;
+const MAX_CHAPTER_COUNT: i32 = 60;
+const DEFAULT_SCENES_PER_CHAPTER: i32 = 3;
+const MAX_SCENES_PER_CHAPTER: i32 = 12;
+const DEFAULT_CHAPTER_LENGTH: i32 = 2500;
+const MIN_CHAPTER_LENGTH: i32 = 500;
+const MAX_CHAPTER_LENGTH: i32 = 10000;
+
+/// Estimated words generated per job, for credit charging
+const OUTLINE_WORDS: i32 = 300;
+const CHAPTER_PLAN_WORDS: i32 = 400;
+const SCENES_WORDS: i32 = 300;
+
+/// Children still `processing` this long after starting are presumed lost
+/// with their worker and failed, so the book can be resumed
+const STALE_CHILD_HOURS: i32 = 2;
+const ADVANCE_BATCH_SIZE: i64 = 50;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct GenerateBookRequest {
+    pub book_id: Uuid,
+    pub prompt: String,
+    pub genre: Option<String>,
+    pub style: Option<String>,
+    pub chapter_count: Option<i32>,
+    pub scenes_per_chapter: Option<i32>,
+    /// Target words per chapter in the content phase
+    pub target_chapter_length: Option<i32>,
+}
+
+/// Settings stored in the parent job's input, read back by every phase
+#[derive(Debug, Serialize, Deserialize)]
+struct BookJobInput {
+    book_id: Uuid,
+    user_id: Uuid,
+    prompt: String,
+    genre: Option<String>,
+    style: Option<String>,
+    chapter_count: i32,
+    scenes_per_chapter: i32,
+    target_chapter_length: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PhaseProgress {
+    pub phase: String,
+    pub status: String,
+    pub total: i32,
+    pub completed: i64,
+    pub failed: i64,
+    pub in_progress: i64,
+    pub queued: i64,
+    pub error: Option<String>,
+    pub started_at: Option<String>,
+    pub completed_at: Option<String>,
+}
+
+struct ParentJob {
+    status: String,
+    input: BookJobInput,
+}
+
+struct PlannedChapter {
+    id: Uuid,
+    title: String,
+    chapter_number: i32,
+    outline: String,
+    plan: String,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /generate/book - Queue generation of a whole book. The book must not
+/// have written chapters yet, and only one generation may run per book.
+pub fn start(conn: &Connection, user_id: &Uuid, body: GenerateBookRequest) -> Result<Response, ServiceError> {
+    if body.prompt.trim().is_empty() {
+        return Err(ServiceError::BadRequest("prompt is required".into()));
+    }
+    let chapter_count = body.chapter_count.unwrap_or(DEFAULT_CHAPTER_COUNT);
+    if !(1..=MAX_CHAPTER_COUNT).contains(&chapter_count) {
+        return Err(ServiceError::BadRequest(format!("chapter_count must be between 1 and {}", MAX_CHAPTER_COUNT)));
+    }
+    let scenes_per_chapter = body.scenes_per_chapter.unwrap_or(DEFAULT_SCENES_PER_CHAPTER);
+    if !(1..=MAX_SCENES_PER_CHAPTER).contains(&scenes_per_chapter) {
+        return Err(ServiceError::BadRequest(format!("scenes_per_chapter must be between 1 and {}", MAX_SCENES_PER_CHAPTER)));
+    }
+    let target_chapter_length = body.target_chapter_length.unwrap_or(DEFAULT_CHAPTER_LENGTH);
+    if !(MIN_CHAPTER_LENGTH..=MAX_CHAPTER_LENGTH).contains(&target_chapter_length) {
+        return Err(ServiceError::BadRequest(format!(
+            "target_chapter_length must be between {} and {}", MIN_CHAPTER_LENGTH, MAX_CHAPTER_LENGTH
+        )));
+    }
+
+    let active = "SELECT id FROM content.generation_jobs
+                  WHERE book_id = $1 AND job_type = $2 AND status IN ('processing', 'failed')";
+    let rows = conn.query(active, &[
+        ParameterValue::Str(body.book_id.to_string()),
+        ParameterValue::Str(JOB_TYPE.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if let Some(row) = rows.rows.first() {
+        return Err(ServiceError::Conflict(format!(
+            "Book generation {} is already running or awaiting resume; cancel it first",
+            String::decode(&row[0]).unwrap_or_default()
+        )));
+    }
+
+    let written = "SELECT COUNT(*) FROM content.chapters WHERE book_id = $1 AND COALESCE(word_count, 0) > 0";
+    let rows = conn.query(written, &[ParameterValue::Str(body.book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0) > 0 {
+        return Err(ServiceError::Conflict("Book already has written chapters".into()));
+    }
+
+    // Check the whole run up front; each job is still charged as it is queued
+    let per_chapter = credits::estimate_generation_cost("chapter_plan", CHAPTER_PLAN_WORDS)
+        + credits::estimate_generation_cost("scenes", SCENES_WORDS)
+        + credits::estimate_generation_cost("chapter", target_chapter_length);
+    let estimated_credits = credits::estimate_generation_cost("outline", OUTLINE_WORDS) + per_chapter * chapter_count;
+    if !credits::check_user_credits(user_id, estimated_credits)? {
+        let current_balance = credits::get_user_balance(user_id)?;
+        return Err(ServiceError::PaymentRequired(format!(
+            "Insufficient credits. Required: {}, Available: {}",
+            estimated_credits, current_balance
+        )));
+    }
+
+    let job_id = Uuid::new_v4();
+    let input = BookJobInput {
+        book_id: body.book_id,
+        user_id: *user_id,
+        prompt: body.prompt,
+        genre: body.genre,
+        style: body.style,
+        chapter_count,
+        scenes_per_chapter,
+        target_chapter_length,
+    };
+    let input = serde_json::to_value(&input)
+        .map_err(|e| ServiceError::Internal(format!("JSON error: {}", e)))?;
+
+    let insert = "INSERT INTO content.generation_jobs (id, book_id, job_type, status, input, created_at, started_at)
+                  VALUES ($1, $2, $3, 'processing', $4, $5, $5)";
+    conn.execute(insert, &[
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(body.book_id.to_string()),
+        ParameterValue::Str(JOB_TYPE.to_string()),
+        ParameterValue::Str(input.to_string()),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Job creation failed: {}", e)))?;
+
+    let insert_phase = "INSERT INTO content.generation_phases (job_id, phase, position) VALUES ($1, $2, $3)";
+    for (position, phase) in PHASES.iter().enumerate() {
+        conn.execute(insert_phase, &[
+            ParameterValue::Str(job_id.to_string()),
+            ParameterValue::Str(phase.to_string()),
+            ParameterValue::Int16(position as i16),
+        ]).map_err(|e| ServiceError::Internal(format!("Job creation failed: {}", e)))?;
+    }
+
+    let status = advance(conn, &job_id)?;
+
+    crate::json_response(202, serde_json::json!({
+        "job_id": job_id,
+        "status": status,
+        "message": "Book generation queued",
+        "phases": PHASES,
+        "estimated_credits": estimated_credits,
+        "check_status": format!("/jobs/{}", job_id)
+    }))
+}
+
+/// Per-phase progress of a book generation, in phase order
+pub fn phases(conn: &Connection, job_id: &Uuid) -> Result<Vec<PhaseProgress>, ServiceError> {
+    let query = "SELECT p.phase, p.status, p.total_items, p.error, p.started_at, p.completed_at,
+                        COUNT(j.id) FILTER (WHERE j.status = 'completed'),
+                        COUNT(j.id) FILTER (WHERE j.status = 'failed'),
+                        COUNT(j.id) FILTER (WHERE j.status = 'processing'),
+                        COUNT(j.id) FILTER (WHERE j.status = 'pending')
+                 FROM content.generation_phases p
+                 LEFT JOIN content.generation_jobs j ON j.parent_job_id = p.job_id AND j.phase = p.phase
+                 WHERE p.job_id = $1
+                 GROUP BY p.phase, p.status, p.total_items, p.error, p.started_at, p.completed_at, p.position
+                 ORDER BY p.position";
+    let rows = conn.query(query, &[ParameterValue::Str(job_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.iter().map(|row| PhaseProgress {
+        phase: String::decode(&row[0]).unwrap_or_default(),
+        status: String::decode(&row[1]).unwrap_or_default(),
+        total: i32::decode(&row[2]).unwrap_or(0),
+        error: String::decode(&row[3]).ok(),
+        started_at: String::decode(&row[4]).ok(),
+        completed_at: String::decode(&row[5]).ok(),
+        completed: i64::decode(&row[6]).unwrap_or(0),
+        failed: i64::decode(&row[7]).unwrap_or(0),
+        in_progress: i64::decode(&row[8]).unwrap_or(0),
+        queued: i64::decode(&row[9]).unwrap_or(0),
+    }).collect())
+}
+
+/// POST /jobs/:id/resume - Re-queue the failed phase's failed jobs, queue
+/// whatever it is still missing, and carry on with the later phases
+pub fn resume(conn: &Connection, job_id: &Uuid) -> Result<Response, ServiceError> {
+    let parent = load_parent(conn, job_id)?
+        .ok_or_else(|| ServiceError::NotFound("Job not found".into()))?;
+    if parent.status != "failed" {
+        return Err(ServiceError::Conflict(format!("Only failed jobs can be resumed; this one is {}", parent.status)));
+    }
+
+    let requeue = "UPDATE content.generation_jobs
+                   SET status = 'pending', error = NULL, started_at = NULL, completed_at = NULL
+                   WHERE parent_job_id = $1 AND status = 'failed'
+                     AND phase IN (SELECT phase FROM content.generation_phases WHERE job_id = $1 AND status = 'failed')";
+    let requeued = conn.execute(requeue, &[ParameterValue::Str(job_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let reopen = "UPDATE content.generation_phases
+                  SET status = 'pending', error = NULL, completed_at = NULL
+                  WHERE job_id = $1 AND status = 'failed'";
+    conn.execute(reopen, &[ParameterValue::Str(job_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let restart = "UPDATE content.generation_jobs
+                   SET status = 'processing', error = NULL, completed_at = NULL
+                   WHERE id = $1 AND status = 'failed'";
+    conn.execute(restart, &[ParameterValue::Str(job_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let status = advance(conn, job_id)?;
+
+    crate::json_response(202, serde_json::json!({
+        "job_id": job_id,
+        "status": status,
+        "requeued_jobs": requeued,
+        "phases": phases(conn, job_id)?
+    }))
+}
+
+/// POST /jobs/:id/cancel - Stop a book generation. Queued jobs are dropped and
+/// unfinished phases never start; jobs already being written are left to finish.
+pub fn cancel(conn: &Connection, job_id: &Uuid) -> Result<Response, ServiceError> {
+    let update = "UPDATE content.generation_jobs
+                  SET status = 'cancelled', completed_at = NOW()
+                  WHERE id = $1 AND status IN ('processing', 'failed')
+                  RETURNING id";
+    let rows = conn.query(update, &[ParameterValue::Str(job_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    if rows.rows.is_empty() {
+        let status = load_parent(conn, job_id)?
+            .map(|parent| parent.status)
+            .ok_or_else(|| ServiceError::NotFound("Job not found".into()))?;
+        return Err(ServiceError::Conflict(format!("Job is already {}", status)));
+    }
+
+    let drop_children = "UPDATE content.generation_jobs
+                         SET status = 'cancelled', error = 'Book generation cancelled', completed_at = NOW()
+                         WHERE parent_job_id = $1 AND status = 'pending'";
+    let cancelled_jobs = conn.execute(drop_children, &[ParameterValue::Str(job_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let stop_phases = "UPDATE content.generation_phases
+                       SET status = 'cancelled', completed_at = NOW()
+                       WHERE job_id = $1 AND status IN ('pending', 'running', 'failed')";
+    conn.execute(stop_phases, &[ParameterValue::Str(job_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let running = "SELECT COUNT(*) FROM content.generation_jobs WHERE parent_job_id = $1 AND status = 'processing'";
+    let rows = conn.query(running, &[ParameterValue::Str(job_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let still_running = rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0);
+
+    crate::json_response(200, serde_json::json!({
+        "job_id": job_id,
+        "status": "cancelled",
+        "cancelled_jobs": cancelled_jobs,
+        "still_running": still_running,
+        "phases": phases(conn, job_id)?
+    }))
+}
+
+/// POST /generate/book/advance - Move running book generations on to their
+/// next phase, failing children whose worker went away (internal)
+pub fn advance_all(conn: &Connection) -> Result<Response, ServiceError> {
+    let expire = "UPDATE content.generation_jobs
+                  SET status = 'failed', error = 'Job timed out', completed_at = NOW()
+                  WHERE parent_job_id IS NOT NULL AND status = 'processing'
+                    AND started_at < NOW() - make_interval(hours => $1)";
+    let timed_out = conn.execute(expire, &[ParameterValue::Int32(STALE_CHILD_HOURS)])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let query = "SELECT id FROM content.generation_jobs
+                 WHERE job_type = $1 AND status = 'processing'
+                 ORDER BY created_at
+                 LIMIT $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(JOB_TYPE.to_string()),
+        ParameterValue::Int64(ADVANCE_BATCH_SIZE),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let (mut completed, mut failed, mut errors) = (0, 0, 0);
+    for row in &rows.rows {
+        let job_id = match Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()) {
+            Ok(id) => id,
+            Err(_) => continue,
+        };
+        match advance(conn, &job_id) {
+            Ok(status) if status == "completed" => completed += 1,
+            Ok(status) if status == "failed" => failed += 1,
+            Ok(_) => {}
+            Err(_) => errors += 1,
+        }
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "checked": rows.rows.len(),
+        "completed": completed,
+        "failed": failed,
+        "errors": errors,
+        "timed_out_jobs": timed_out
+    }))
+}
+
+//=============================================================================
+// Orchestration
+//=============================================================================
+
+/// Move a book generation as far forward as its children allow and return
+/// its status. Safe to call concurrently: phases are claimed before queuing.
+pub fn advance(conn: &Connection, job_id: &Uuid) -> Result<String, ServiceError> {
+    loop {
+        let parent = match load_parent(conn, job_id)? {
+            Some(parent) => parent,
+            None => return Err(ServiceError::NotFound("Job not found".into())),
+        };
+        if parent.status != "processing" {
+            return Ok(parent.status);
+        }
+
+        let next = phases(conn, job_id)?
+            .into_iter()
+            .find(|p| p.status != "completed" && p.status != "skipped");
+        let phase = match next {
+            Some(phase) => phase,
+            None => {
+                complete(conn, job_id, &parent.input)?;
+                return Ok("completed".into());
+            }
+        };
+
+        match phase.status.as_str() {
+            "pending" => {
+                if !claim_phase(conn, job_id, &phase.phase)? {
+                    return Ok(parent.status);
+                }
+                match queue_phase(conn, job_id, &phase.phase, &parent.input) {
+                    // Nothing to do in this phase; go straight on to the next
+                    Ok(0) => finish_phase(conn, job_id, &phase.phase, "skipped")?,
+                    Ok(_) => return Ok(parent.status),
+                    Err(e) => {
+                        fail(conn, job_id, &phase.phase, &e.to_string())?;
+                        return Ok("failed".into());
+                    }
+                }
+            }
+            // total is written once queuing is done; zero means still queuing
+            "running" if phase.total == 0 || phase.queued + phase.in_progress > 0 => {
+                return Ok(parent.status);
+            }
+            "running" if phase.failed > 0 => {
+                let error = first_child_error(conn, job_id, &phase.phase)?;
+                fail(conn, job_id, &phase.phase, &format!("{} of {} jobs failed: {}", phase.failed, phase.total, error))?;
+                return Ok("failed".into());
+            }
+            "running" => finish_phase(conn, job_id, &phase.phase, "completed")?,
+            _ => return Ok(parent.status),
+        }
+    }
+}
+
+fn load_parent(conn: &Connection, job_id: &Uuid) -> Result<Option<ParentJob>, ServiceError> {
+    let query = "SELECT status, input::text FROM content.generation_jobs WHERE id = $1 AND job_type = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(JOB_TYPE.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let row = match rows.rows.first() {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let input: BookJobInput = serde_json::from_str(&String::decode(&row[1]).unwrap_or_default())
+        .map_err(|e| ServiceError::Internal(format!("Invalid job input: {}", e)))?;
+    Ok(Some(ParentJob {
+        status: String::decode(&row[0]).unwrap_or_default(),
+        input,
+    }))
+}
+
+fn claim_phase(conn: &Connection, job_id: &Uuid, phase: &str) -> Result<bool, ServiceError> {
+    let claim = "UPDATE content.generation_phases
+                 SET status = 'running', total_items = 0, started_at = COALESCE(started_at, NOW())
+                 WHERE job_id = $1 AND phase = $2 AND status = 'pending'
+                 RETURNING phase";
+    let rows = conn.query(claim, &[
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(phase.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(!rows.rows.is_empty())
+}
+
+fn finish_phase(conn: &Connection, job_id: &Uuid, phase: &str, status: &str) -> Result<(), ServiceError> {
+    let update = "UPDATE content.generation_phases
+                  SET status = $3, completed_at = NOW()
+                  WHERE job_id = $1 AND phase = $2 AND status = 'running'";
+    conn.execute(update, &[
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(phase.to_string()),
+        ParameterValue::Str(status.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+/// Fail the phase and the parent; later phases stay pending for a resume
+fn fail(conn: &Connection, job_id: &Uuid, phase: &str, error: &str) -> Result<(), ServiceError> {
+    let update = "UPDATE content.generation_phases
+                  SET status = 'failed', error = $3, completed_at = NOW()
+                  WHERE job_id = $1 AND phase = $2";
+    conn.execute(update, &[
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(phase.to_string()),
+        ParameterValue::Str(error.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let update = "UPDATE content.generation_jobs
+                  SET status = 'failed', error = $2, completed_at = NOW()
+                  WHERE id = $1 AND status = 'processing'";
+    conn.execute(update, &[
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(format!("{} phase failed: {}", phase, error)),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+fn complete(conn: &Connection, job_id: &Uuid, input: &BookJobInput) -> Result<(), ServiceError> {
+    let totals = "SELECT COUNT(*), COALESCE(SUM(word_count), 0) FROM content.chapters WHERE book_id = $1";
+    let rows = conn.query(totals, &[ParameterValue::Str(input.book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let (chapters, words) = rows.rows.first()
+        .map(|row| (i64::decode(&row[0]).unwrap_or(0), i64::decode(&row[1]).unwrap_or(0)))
+        .unwrap_or((0, 0));
+
+    let output = serde_json::json!({ "chapters": chapters, "word_count": words });
+    let update = "UPDATE content.generation_jobs
+                  SET status = 'completed', output = $2, completed_at = NOW()
+                  WHERE id = $1 AND status = 'processing'";
+    conn.execute(update, &[
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(output.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+fn first_child_error(conn: &Connection, job_id: &Uuid, phase: &str) -> Result<String, ServiceError> {
+    let query = "SELECT COALESCE(error, 'unknown error') FROM content.generation_jobs
+                 WHERE parent_job_id = $1 AND phase = $2 AND status = 'failed'
+                 ORDER BY completed_at LIMIT 1";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(phase.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    Ok(rows.rows.first().and_then(|row| String::decode(&row[0]).ok()).unwrap_or_default())
+}
+
+//=============================================================================
+// Queuing
+//=============================================================================
+
+/// Queue the phase's jobs that do not exist yet and record the phase total.
+/// Returns the total, counting jobs queued by an earlier attempt.
+fn queue_phase(conn: &Connection, job_id: &Uuid, phase: &str, input: &BookJobInput) -> Result<i32, ServiceError> {
+    let book_id = &input.book_id;
+
+    if phase == "outline" {
+        // A book planned elsewhere (template, outline editor) keeps its chapters
+        if chapter_count(conn, book_id)? == 0 && !has_child(conn, job_id, phase)? {
+            let child_id = Uuid::new_v4();
+            let job = serde_json::json!({
+                "type": "GenerateOutline",
+                "job_id": child_id,
+                "book_id": book_id,
+                "prompt": input.prompt,
+                "genre": input.genre,
+                "style": input.style,
+                "chapter_count": input.chapter_count,
+                "series": series::generation_context(conn, book_id)?,
+                "plot_threads": threads::generation_context(conn, book_id, None)?,
+                "lore": lore::generation_context(conn, book_id, None, &input.prompt)?,
+                "persona": personas::generation_context(conn, book_id)?
+            });
+            queue_child(conn, job_id, input, &child_id, phase, "outline", OUTLINE_WORDS, &job)?;
+        }
+    } else {
+        let chapters = unqueued_chapters(conn, job_id, phase, book_id)?;
+        if chapters.is_empty() && chapter_count(conn, book_id)? == 0 {
+            return Err(ServiceError::Internal("The outline produced no chapters".into()));
+        }
+        let synopsis = book_synopsis(conn, book_id)?;
+        let persona = personas::generation_context(conn, book_id)?;
+
+        for chapter in chapters {
+            let child_id = Uuid::new_v4();
+            let plan = if chapter.plan.is_empty() { &chapter.outline } else { &chapter.plan };
+            let (job_type, words, job) = match phase {
+                "chapters" => ("chapter_plan", CHAPTER_PLAN_WORDS, serde_json::json!({
+                    "type": "PlanChapter",
+                    "job_id": child_id,
+                    "book_id": book_id,
+                    "chapter_id": chapter.id,
+                    "title": chapter.title,
+                    "chapter_number": chapter.chapter_number,
+                    "outline": chapter.outline,
+                    "synopsis": synopsis,
+                    "style": input.style,
+                    "persona": persona
+                })),
+                "scenes" => ("scenes", SCENES_WORDS, serde_json::json!({
+                    "type": "PlanScenes",
+                    "job_id": child_id,
+                    "book_id": book_id,
+                    "chapter_id": chapter.id,
+                    "title": chapter.title,
+                    "chapter_number": chapter.chapter_number,
+                    "plan": plan,
+                    "scene_count": input.scenes_per_chapter,
+                    "style": input.style,
+                    "persona": persona
+                })),
+                _ => {
+                    let brief = chapter_brief(conn, &chapter)?;
+                    ("chapter", input.target_chapter_length, serde_json::json!({
+                        "type": "GenerateChapter",
+                        "job_id": child_id,
+                        "book_id": book_id,
+                        "chapter_id": chapter.id,
+                        "outline": brief,
+                        "context": synopsis,
+                        "style": input.style,
+                        "target_length": input.target_chapter_length,
+                        "series": series::generation_context(conn, book_id)?,
+                        "plot_threads": threads::generation_context(conn, book_id, Some(&chapter.id))?,
+                        "lore": lore::generation_context(conn, book_id, Some(&chapter.id), &brief)?,
+                        "persona": persona
+                    }))
+                }
+            };
+            queue_child(conn, job_id, input, &child_id, phase, job_type, words, &job)?;
+        }
+    }
+
+    let update = "UPDATE content.generation_phases
+                  SET total_items = (SELECT COUNT(*) FROM content.generation_jobs WHERE parent_job_id = $1 AND phase = $2)
+                  WHERE job_id = $1 AND phase = $2
+                  RETURNING total_items";
+    let rows = conn.query(update, &[
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(phase.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(rows.rows.first().map(|row| i32::decode(&row[0]).unwrap_or(0)).unwrap_or(0))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn queue_child(
+    conn: &Connection,
+    parent_id: &Uuid,
+    input: &BookJobInput,
+    child_id: &Uuid,
+    phase: &str,
+    job_type: &str,
+    estimated_words: i32,
+    job: &serde_json::Value,
+) -> Result<(), ServiceError> {
+    // CREDIT ENFORCEMENT: Each job is charged as its phase is queued
+    credits::enforce_credits_for_generation(
+        conn,
+        &input.user_id,
+        child_id,
+        &input.book_id,
+        job_type,
+        estimated_words,
+    )?;
+
+    let query = "INSERT INTO content.generation_jobs (id, book_id, job_type, status, input, created_at, parent_job_id, phase)
+                 VALUES ($1, $2, $3, 'pending', $4, $5, $6, $7)";
+    conn.execute(query, &[
+        ParameterValue::Str(child_id.to_string()),
+        ParameterValue::Str(input.book_id.to_string()),
+        ParameterValue::Str(job_type.to_string()),
+        ParameterValue::Str(job.to_string()),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+        ParameterValue::Str(parent_id.to_string()),
+        ParameterValue::Str(phase.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Job creation failed: {}", e)))?;
+    Ok(())
+}
+
+fn has_child(conn: &Connection, job_id: &Uuid, phase: &str) -> Result<bool, ServiceError> {
+    let query = "SELECT 1 FROM content.generation_jobs WHERE parent_job_id = $1 AND phase = $2 LIMIT 1";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(phase.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    Ok(!rows.rows.is_empty())
+}
+
+fn chapter_count(conn: &Connection, book_id: &Uuid) -> Result<i64, ServiceError> {
+    let query = "SELECT COUNT(*) FROM content.chapters WHERE book_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    Ok(rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0))
+}
+
+/// Chapters in reading order without a job in this phase yet
+fn unqueued_chapters(conn: &Connection, job_id: &Uuid, phase: &str, book_id: &Uuid) -> Result<Vec<PlannedChapter>, ServiceError> {
+    let query = "SELECT c.id::text, c.title, c.chapter_number,
+                        COALESCE(c.metadata->>'outline', ''), COALESCE(c.metadata->>'plan', '')
+                 FROM content.chapters c
+                 WHERE c.book_id = $1
+                   AND NOT EXISTS (
+                       SELECT 1 FROM content.generation_jobs j
+                       WHERE j.parent_job_id = $2 AND j.phase = $3 AND j.input->>'chapter_id' = c.id::text
+                   )
+                 ORDER BY c.chapter_number";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(phase.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.iter().filter_map(|row| {
+        Some(PlannedChapter {
+            id: Uuid::parse_str(&String::decode(&row[0]).ok()?).ok()?,
+            title: String::decode(&row[1]).unwrap_or_default(),
+            chapter_number: i32::decode(&row[2]).unwrap_or(0),
+            outline: String::decode(&row[3]).unwrap_or_default(),
+            plan: String::decode(&row[4]).unwrap_or_default(),
+        })
+    }).collect())
+}
+
+fn book_synopsis(conn: &Connection, book_id: &Uuid) -> Result<String, ServiceError> {
+    let query = "SELECT COALESCE(NULLIF(metadata->>'synopsis', ''), description, '') FROM content.books WHERE id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    Ok(rows.rows.first().and_then(|row| String::decode(&row[0]).ok()).unwrap_or_default())
+}
+
+/// The outline a chapter is written from: its plan, then its scenes in order
+fn chapter_brief(conn: &Connection, chapter: &PlannedChapter) -> Result<String, ServiceError> {
+    let mut brief = format!("Chapter {}: {}\n\n", chapter.chapter_number, chapter.title);
+    brief.push_str(if chapter.plan.is_empty() { &chapter.outline } else { &chapter.plan });
+
+    let query = "SELECT COALESCE(title, ''), COALESCE(notes, ''), COALESCE(pov_character, ''), COALESCE(location, '')
+                 FROM content.scenes WHERE chapter_id = $1 ORDER BY scene_number";
+    let rows = conn.query(query, &[ParameterValue::Str(chapter.id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if !rows.rows.is_empty() {
+        brief.push_str("\n\nScenes:\n");
+    }
+    for (i, row) in rows.rows.iter().enumerate() {
+        let title = String::decode(&row[0]).unwrap_or_default();
+        let notes = String::decode(&row[1]).unwrap_or_default();
+        let pov = String::decode(&row[2]).unwrap_or_default();
+        let location = String::decode(&row[3]).unwrap_or_default();
+
+        brief.push_str(&format!("{}. {}", i + 1, title));
+        if !pov.is_empty() || !location.is_empty() {
+            let details: Vec<&str> = [pov.as_str(), location.as_str()].into_iter().filter(|s| !s.is_empty()).collect();
+            brief.push_str(&format!(" ({})", details.join(", ")));
+        }
+        if !notes.is_empty() {
+            brief.push_str(&format!(": {}", notes));
+        }
+        brief.push('\n');
+    }
+    Ok(brief)
+}
@@ -0,0 +1,144 @@
+//! Public change feed
+//!
+//! `GET /public/changes` lets third-party reader apps sync incrementally.
+//! Entries come from `content.public_changes`, which database triggers fill
+//! whenever a public book or chapter is published, updated or removed (see
+//! migration 031), and are returned in strictly increasing `seq` order.
+//!
+//! Cursor semantics: `since` must be a `next_cursor` from an earlier page (or
+//! omitted for a full sync). A page contains exactly the entries after the
+//! cursor, so replaying a cursor returns the same entries plus any appended
+//! since. Entries younger than the settle window are held back so a slow
+//! concurrent write can never commit behind a cursor already handed out.
+//! Removals are tombstones (`unpublish`, `delete`); a book tombstone also
+//! removes all of that book's chapters.
+
+use crate::error::ServiceError;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 100;
+pub const MAX_PAGE_SIZE: i64 = 500;
+
+/// How long an entry waits before it is served
+const DEFAULT_SETTLE_SECS: i64 = 5;
+
+/// GET /public/changes?since=&limit= - Changes to public content after a cursor
+pub fn list(conn: &Connection, since: Option<&str>, limit: i64) -> Result<Response, ServiceError> {
+    let limit = limit.clamp(1, MAX_PAGE_SIZE);
+    let settle = settle_secs();
+
+    let cursor = match since {
+        Some(value) => parse_cursor(value)?,
+        None => 0,
+    };
+    let newest_query = "SELECT COALESCE(MAX(seq), 0) FROM content.public_changes";
+    let newest = conn.query(newest_query, &[])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?
+        .rows.first()
+        .map(|row| i64::decode(&row[0]).unwrap_or(0))
+        .unwrap_or(0);
+    if cursor > newest {
+        return Err(ServiceError::BadRequest("Cursor is ahead of the feed; use a next_cursor from a previous page".into()));
+    }
+
+    let query = "SELECT pc.seq, pc.resource_type, pc.resource_id, pc.book_id, pc.action, pc.recorded_at,
+                        b.title, b.description, b.genre, b.cover_image_url, b.word_count, b.published_at,
+                        b.author_id, u.name,
+                        c.title, c.chapter_number, c.word_count, c.content, c.updated_at
+                 FROM content.public_changes pc
+                 LEFT JOIN content.books b ON b.id = pc.book_id
+                     AND b.status = 'published' AND NOT COALESCE(b.index_excluded, false)
+                 LEFT JOIN users.users u ON u.id = b.author_id
+                 LEFT JOIN content.chapters c ON pc.resource_type = 'chapter' AND c.id = pc.resource_id
+                 WHERE pc.seq > $1 AND pc.recorded_at <= clock_timestamp() - $2 * INTERVAL '1 second'
+                 ORDER BY pc.seq ASC
+                 LIMIT $3";
+    let rows = conn.query(query, &[
+        ParameterValue::Int64(cursor),
+        ParameterValue::Int64(settle),
+        ParameterValue::Int64(limit + 1),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let has_more = rows.rows.len() as i64 > limit;
+    let changes: Vec<serde_json::Value> = rows.rows.iter()
+        .take(limit as usize)
+        .map(|row| change_from_row(row))
+        .collect();
+    let next_cursor = rows.rows.iter()
+        .take(limit as usize)
+        .last()
+        .map(|row| i64::decode(&row[0]).unwrap_or(cursor))
+        .unwrap_or(cursor);
+
+    crate::json_response(200, serde_json::json!({
+        "changes": changes,
+        "next_cursor": next_cursor.to_string(),
+        "has_more": has_more,
+        "settle_seconds": settle
+    }))
+}
+
+/// Tombstones carry no data. Live entries carry the resource's current public
+/// state, or `null` if it has since been removed (a later tombstone follows).
+fn change_from_row(row: &[spin_sdk::pg::DbValue]) -> serde_json::Value {
+    let resource_type = String::decode(&row[1]).unwrap_or_default();
+    let action = String::decode(&row[4]).unwrap_or_default();
+    let tombstone = action == "unpublish" || action == "delete";
+    let book_public = String::decode(&row[6]).is_ok();
+
+    let data = if tombstone || !book_public {
+        serde_json::Value::Null
+    } else if resource_type == "book" {
+        serde_json::json!({
+            "title": String::decode(&row[6]).unwrap_or_default(),
+            "description": String::decode(&row[7]).ok(),
+            "genre": String::decode(&row[8]).ok(),
+            "cover_image_url": String::decode(&row[9]).ok(),
+            "word_count": i32::decode(&row[10]).unwrap_or(0),
+            "published_at": String::decode(&row[11]).ok(),
+            "author": {
+                "id": String::decode(&row[12]).unwrap_or_default(),
+                "name": String::decode(&row[13]).ok()
+            }
+        })
+    } else {
+        match String::decode(&row[14]) {
+            Ok(title) => serde_json::json!({
+                "title": title,
+                "chapter_number": i32::decode(&row[15]).unwrap_or(0),
+                "word_count": i32::decode(&row[16]).unwrap_or(0),
+                "content": String::decode(&row[17]).unwrap_or_default(),
+                "updated_at": String::decode(&row[18]).unwrap_or_default()
+            }),
+            Err(_) => serde_json::Value::Null,
+        }
+    };
+
+    serde_json::json!({
+        "cursor": i64::decode(&row[0]).unwrap_or(0).to_string(),
+        "type": resource_type,
+        "id": String::decode(&row[2]).unwrap_or_default(),
+        "book_id": String::decode(&row[3]).unwrap_or_default(),
+        "action": action,
+        "tombstone": tombstone,
+        "recorded_at": String::decode(&row[5]).unwrap_or_default(),
+        "data": data
+    })
+}
+
+fn parse_cursor(value: &str) -> Result<i64, ServiceError> {
+    value.parse::<i64>()
+        .ok()
+        .filter(|cursor| *cursor >= 0)
+        .ok_or_else(|| ServiceError::BadRequest("Invalid cursor".into()))
+}
+
+fn settle_secs() -> i64 {
+    variables::get("public_changes_settle_secs")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v| *v >= 0)
+        .unwrap_or(DEFAULT_SETTLE_SECS)
+}
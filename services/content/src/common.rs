@@ -0,0 +1,69 @@
+//! Helpers shared by the book data modules
+//!
+//! Text field validation for request bodies, per-book name uniqueness for
+//! lore entries and personas, and the SHA-256 checksums stored with
+//! revisions and snapshots.
+
+use crate::error::ServiceError;
+use sha2::{Digest, Sha256};
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+/// Trimmed text of 1 to `max_chars` characters
+pub fn required_text(value: &str, field: &str, max_chars: usize) -> Result<String, ServiceError> {
+    let value = value.trim();
+    if value.is_empty() || value.chars().count() > max_chars {
+        return Err(ServiceError::BadRequest(format!("{} must be 1-{} characters", field, max_chars)));
+    }
+    Ok(value.to_string())
+}
+
+/// Trimmed text of at most `max_chars` characters; blank becomes `None`
+pub fn optional_text(value: Option<String>, field: &str, max_chars: usize) -> Result<Option<String>, ServiceError> {
+    let value = value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    if value.as_ref().is_some_and(|v| v.chars().count() > max_chars) {
+        return Err(ServiceError::BadRequest(format!("{} must be at most {} characters", field, max_chars)));
+    }
+    Ok(value)
+}
+
+/// An update value for a nullable text column: `None` leaves it, "" clears it
+pub fn clearable_text(value: Option<String>, field: &str, max_chars: usize) -> Result<Option<ParameterValue>, ServiceError> {
+    match value {
+        None => Ok(None),
+        Some(v) => Ok(Some(
+            optional_text(Some(v), field, max_chars)?
+                .map(ParameterValue::Str)
+                .unwrap_or(ParameterValue::DbNull),
+        )),
+    }
+}
+
+/// Names are unique per book in `table`, ignoring case; `except` is the row being renamed
+pub fn ensure_unique_name(
+    conn: &Connection,
+    table: &str,
+    noun: &str,
+    book_id: &Uuid,
+    name: &str,
+    except: Option<&Uuid>,
+) -> Result<(), ServiceError> {
+    let query = format!(
+        "SELECT COUNT(*) FROM {}
+         WHERE book_id = $1 AND lower(name) = lower($2) AND ($3::uuid IS NULL OR id <> $3::uuid)",
+        table
+    );
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(name.to_string()),
+        except.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0) > 0 {
+        return Err(ServiceError::Conflict(format!("The book already has a {} named '{}'", noun, name)));
+    }
+    Ok(())
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
@@ -26,11 +26,25 @@ pub fn estimate_generation_cost(generation_type: &str, estimated_words: i32) ->
             // Cost: 1 credit per 10 words = 200-300 credits
             (estimated_words as f32 * 0.1) as i32
         },
+        "chapter_plan" | "scenes" => {
+            // Planning for one chapter of a book generation, flat cost
+            15
+        },
         "enhance" => {
             // Enhancement is iterative, charge based on content length
             // Cost: 1 credit per 20 words (cheaper than generation)
             (estimated_words as f32 * 0.05) as i32
         },
+        "continuity" => {
+            // Analysis reads the manuscript but writes little
+            // Cost: 1 credit per 50 words analyzed, minimum 10
+            ((estimated_words as f32 * 0.02) as i32).max(10)
+        },
+        "related_chapters" => {
+            // Embedding only, no generated text
+            // Cost: 1 credit per 200 words embedded, minimum 5
+            ((estimated_words as f32 * 0.005) as i32).max(5)
+        },
         _ => {
             // Default: 1 credit per 10 words
             (estimated_words as f32 * 0.1) as i32
@@ -28,6 +28,26 @@ pub enum ServiceError {
 
     #[error("Service unavailable: {0}")]
     ServiceUnavailable(String),
+
+    #[error("Feature disabled: {0}")]
+    FeatureDisabled(String),
+
+    #[error("Validation failed: {} issue(s)", .0.len())]
+    Validation(Vec<ValidationIssue>),
+}
+
+/// One failed check, reported alongside the others rather than one at a time
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationIssue {
+    pub field: String,
+    pub code: String,
+    pub message: String,
+}
+
+impl ValidationIssue {
+    pub fn new(field: &str, code: &str, message: impl Into<String>) -> Self {
+        Self { field: field.to_string(), code: code.to_string(), message: message.into() }
+    }
 }
 
 #[derive(Serialize)]
@@ -36,6 +56,8 @@ struct ErrorResponse {
     code: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     details: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errors: Option<Vec<ValidationIssue>>,
 }
 
 impl ServiceError {
@@ -49,6 +71,8 @@ impl ServiceError {
             ServiceError::PaymentRequired(_) => 402,
             ServiceError::Internal(_) => 500,
             ServiceError::ServiceUnavailable(_) => 503,
+            ServiceError::FeatureDisabled(_) => 403,
+            ServiceError::Validation(_) => 422,
         }
     }
 
@@ -62,6 +86,8 @@ impl ServiceError {
             ServiceError::PaymentRequired(_) => "PAYMENT_REQUIRED",
             ServiceError::Internal(_) => "INTERNAL_ERROR",
             ServiceError::ServiceUnavailable(_) => "SERVICE_UNAVAILABLE",
+            ServiceError::FeatureDisabled(_) => "FEATURE_DISABLED",
+            ServiceError::Validation(_) => "VALIDATION_FAILED",
         }
     }
 
@@ -73,9 +99,14 @@ impl ServiceError {
             details: match &self {
                 ServiceError::BadRequest(d) => Some(d.clone()),
                 ServiceError::PaymentRequired(d) => Some(d.clone()),
+                ServiceError::FeatureDisabled(d) => Some(d.clone()),
                 ServiceError::Internal(d) => Some(d.clone()),
                 _ => None,
             },
+            errors: match &self {
+                ServiceError::Validation(issues) => Some(issues.clone()),
+                _ => None,
+            },
         };
 
         let json = serde_json::to_string(&body).unwrap_or_else(|_| {
@@ -90,3 +121,12 @@ impl ServiceError {
             .build()
     }
 }
+
+impl From<authorworks_access::AccessError> for ServiceError {
+    fn from(e: authorworks_access::AccessError) -> Self {
+        match e {
+            authorworks_access::AccessError::RoleLookup(msg) => ServiceError::Internal(format!("Query failed: {}", msg)),
+            e => ServiceError::Forbidden(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,474 @@
+//! Group writing events
+//!
+//! A writing event (NaNoWriMo-style sprint) has a date range and a set of
+//! participants. Every chapter word-count change a participant makes while an
+//! event is running is added to their tally, broadcast to the other
+//! participants through the messaging event queue so leaderboards update
+//! live, and checked against the event's milestones, which trigger an in-app
+//! notification the first time each is passed.
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::outbound_http;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+/// Used when an event is created without explicit milestones
+const DEFAULT_MILESTONES: [i32; 4] = [1_000, 10_000, 25_000, 50_000];
+
+const MAX_MILESTONES: usize = 20;
+const MAX_PARTICIPANTS: usize = 500;
+const MAX_DURATION_DAYS: i64 = 92;
+
+const EVENT_COLUMNS: &str = "e.id, e.name, e.description, e.created_by, e.starts_at, e.ends_at,
+    CASE WHEN NOW() < e.starts_at THEN 'upcoming' WHEN NOW() < e.ends_at THEN 'active' ELSE 'ended' END,
+    e.target_words, e.milestones::text,
+    (SELECT COUNT(*) FROM content.writing_event_participants c WHERE c.event_id = e.id)";
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct CreateEventRequest {
+    pub name: String,
+    pub description: Option<String>,
+    /// RFC 3339 timestamps
+    pub starts_at: String,
+    pub ends_at: String,
+    pub target_words: Option<i32>,
+    pub milestones: Option<Vec<i32>>,
+    /// Invited alongside the creator, who always takes part
+    #[serde(default)]
+    pub participant_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddParticipantsRequest {
+    pub user_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WritingEvent {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_by: String,
+    pub starts_at: String,
+    pub ends_at: String,
+    pub status: String,
+    pub target_words: Option<i32>,
+    pub milestones: Vec<i32>,
+    pub participant_count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LeaderboardEntry {
+    pub rank: i64,
+    pub user_id: String,
+    pub name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub words_added: i32,
+    pub words_removed: i32,
+    pub net_words: i32,
+    pub percent_of_target: Option<f64>,
+    pub last_activity_at: Option<String>,
+}
+
+//=============================================================================
+// Event Endpoints
+//=============================================================================
+
+/// POST /events - Create an event; the creator joins automatically
+pub fn create(conn: &Connection, user_id: &Uuid, body: CreateEventRequest) -> Result<Response, ServiceError> {
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(ServiceError::BadRequest("name is required".into()));
+    }
+    let starts_at = parse_timestamp("starts_at", &body.starts_at)?;
+    let ends_at = parse_timestamp("ends_at", &body.ends_at)?;
+    if ends_at <= starts_at {
+        return Err(ServiceError::BadRequest("ends_at must be after starts_at".into()));
+    }
+    if ends_at - starts_at > Duration::days(MAX_DURATION_DAYS) {
+        return Err(ServiceError::BadRequest(format!("Events can run for at most {} days", MAX_DURATION_DAYS)));
+    }
+    if ends_at <= Utc::now() {
+        return Err(ServiceError::BadRequest("ends_at must be in the future".into()));
+    }
+    if body.target_words.map(|t| t <= 0).unwrap_or(false) {
+        return Err(ServiceError::BadRequest("target_words must be positive".into()));
+    }
+    if body.participant_ids.len() > MAX_PARTICIPANTS {
+        return Err(ServiceError::BadRequest(format!("At most {} participants", MAX_PARTICIPANTS)));
+    }
+
+    let mut milestones = body.milestones.unwrap_or_else(|| DEFAULT_MILESTONES.to_vec());
+    if milestones.iter().any(|m| *m <= 0) {
+        return Err(ServiceError::BadRequest("milestones must be positive".into()));
+    }
+    // Reaching the target is always worth a notification
+    milestones.extend(body.target_words);
+    milestones.sort_unstable();
+    milestones.dedup();
+    if milestones.len() > MAX_MILESTONES {
+        return Err(ServiceError::BadRequest(format!("At most {} milestones", MAX_MILESTONES)));
+    }
+
+    let event_id = Uuid::new_v4();
+    let insert = "INSERT INTO content.writing_events
+                  (id, name, description, created_by, starts_at, ends_at, target_words, milestones)
+                  VALUES ($1, $2, $3, $4, $5::timestamptz, $6::timestamptz, $7, $8::jsonb)";
+    conn.execute(insert, &[
+        ParameterValue::Str(event_id.to_string()),
+        ParameterValue::Str(name.to_string()),
+        body.description.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(starts_at.to_rfc3339()),
+        ParameterValue::Str(ends_at.to_rfc3339()),
+        body.target_words.map(ParameterValue::Int32).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(serde_json::to_string(&milestones).unwrap_or_else(|_| "[]".into())),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    let mut participant_ids = body.participant_ids;
+    participant_ids.push(*user_id);
+    let added = insert_participants(conn, &event_id, &participant_ids)?;
+
+    let event = load_event(conn, &event_id)?
+        .ok_or_else(|| ServiceError::Internal("Event was not created".into()))?;
+    crate::json_response(201, serde_json::json!({
+        "event": event,
+        "participants_added": added
+    }))
+}
+
+/// GET /events - Events the caller takes part in, newest first
+pub fn list(conn: &Connection, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = format!(
+        "SELECT {}, p.words_added - p.words_removed
+         FROM content.writing_events e
+         JOIN content.writing_event_participants p ON p.event_id = e.id AND p.user_id = $1
+         ORDER BY e.starts_at DESC LIMIT 100",
+        EVENT_COLUMNS
+    );
+    let rows = conn.query(&query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let events: Vec<serde_json::Value> = rows.rows.iter().map(|row| {
+        serde_json::json!({
+            "event": event_from_row(row),
+            "net_words": i32::decode(&row[10]).unwrap_or(0)
+        })
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "events": events,
+        "total": events.len()
+    }))
+}
+
+/// GET /events/:id - Event details with the caller's own tally
+pub fn get(conn: &Connection, event_id: &Uuid, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let event = require_participant(conn, event_id, user_id)?;
+
+    let query = "SELECT words_added, words_removed, last_milestone, joined_at
+                 FROM content.writing_event_participants WHERE event_id = $1 AND user_id = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(event_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let own = rows.rows.first().map(|row| {
+        let added = i32::decode(&row[0]).unwrap_or(0);
+        let removed = i32::decode(&row[1]).unwrap_or(0);
+        serde_json::json!({
+            "words_added": added,
+            "words_removed": removed,
+            "net_words": added - removed,
+            "last_milestone": i32::decode(&row[2]).ok().filter(|m| *m > 0),
+            "joined_at": String::decode(&row[3]).unwrap_or_default()
+        })
+    });
+
+    crate::json_response(200, serde_json::json!({
+        "event": event,
+        "progress": own
+    }))
+}
+
+/// GET /events/:id/leaderboard - Participants ranked by net words written
+pub fn leaderboard(conn: &Connection, event_id: &Uuid, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let event = require_participant(conn, event_id, user_id)?;
+
+    let query = "SELECT RANK() OVER (ORDER BY p.words_added - p.words_removed DESC),
+                        p.user_id, u.name, u.avatar_url, p.words_added, p.words_removed, p.last_activity_at
+                 FROM content.writing_event_participants p
+                 LEFT JOIN users.users u ON u.id = p.user_id
+                 WHERE p.event_id = $1
+                 ORDER BY 1, u.name";
+    let rows = conn.query(query, &[ParameterValue::Str(event_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let entries: Vec<LeaderboardEntry> = rows.rows.iter().map(|row| {
+        let words_added = i32::decode(&row[4]).unwrap_or(0);
+        let words_removed = i32::decode(&row[5]).unwrap_or(0);
+        let net_words = words_added - words_removed;
+        LeaderboardEntry {
+            rank: i64::decode(&row[0]).unwrap_or(0),
+            user_id: String::decode(&row[1]).unwrap_or_default(),
+            name: String::decode(&row[2]).ok(),
+            avatar_url: String::decode(&row[3]).ok(),
+            words_added,
+            words_removed,
+            net_words,
+            percent_of_target: event.target_words
+                .map(|target| (net_words.max(0) as f64 / target.max(1) as f64 * 100.0).min(100.0)),
+            last_activity_at: String::decode(&row[6]).ok(),
+        }
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "event": event,
+        "leaderboard": entries,
+        "generated_at": Utc::now().to_rfc3339()
+    }))
+}
+
+/// POST /events/:id/participants - Creator invites more participants
+pub fn add_participants(
+    conn: &Connection,
+    event_id: &Uuid,
+    user_id: &Uuid,
+    body: AddParticipantsRequest,
+) -> Result<Response, ServiceError> {
+    let event = require_participant(conn, event_id, user_id)?;
+    if event.created_by != user_id.to_string() {
+        return Err(ServiceError::Forbidden("Only the event creator can add participants".into()));
+    }
+    if event.status == "ended" {
+        return Err(ServiceError::Conflict("Event has ended".into()));
+    }
+    if body.user_ids.is_empty() {
+        return Err(ServiceError::BadRequest("user_ids is required".into()));
+    }
+    if event.participant_count as usize + body.user_ids.len() > MAX_PARTICIPANTS {
+        return Err(ServiceError::BadRequest(format!("At most {} participants", MAX_PARTICIPANTS)));
+    }
+
+    let added = insert_participants(conn, event_id, &body.user_ids)?;
+    crate::json_response(200, serde_json::json!({
+        "event_id": event_id,
+        "participants_added": added
+    }))
+}
+
+/// DELETE /events/:id/participants/:user_id - Leave, or creator removes someone
+pub fn remove_participant(
+    conn: &Connection,
+    event_id: &Uuid,
+    user_id: &Uuid,
+    target_id: &Uuid,
+) -> Result<Response, ServiceError> {
+    let event = require_participant(conn, event_id, user_id)?;
+    let is_creator = event.created_by == user_id.to_string();
+    if target_id != user_id && !is_creator {
+        return Err(ServiceError::Forbidden("Only the event creator can remove other participants".into()));
+    }
+    if event.created_by == target_id.to_string() {
+        return Err(ServiceError::BadRequest("The event creator cannot leave their own event".into()));
+    }
+
+    let delete = "DELETE FROM content.writing_event_participants WHERE event_id = $1 AND user_id = $2";
+    let removed = conn.execute(delete, &[
+        ParameterValue::Str(event_id.to_string()),
+        ParameterValue::Str(target_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    if removed == 0 {
+        return Err(ServiceError::NotFound("Participant not found".into()));
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "event_id": event_id,
+        "removed": target_id
+    }))
+}
+
+//=============================================================================
+// Word Tracking
+//=============================================================================
+
+/// Add a word-count change to the user's tally in every running event,
+/// broadcast the new totals and notify any milestone passed. Failures are
+/// swallowed; the chapter save already succeeded.
+pub fn record_word_delta(conn: &Connection, user_id: &Uuid, delta: i32) {
+    let update = "UPDATE content.writing_event_participants p SET
+                  words_added = p.words_added + $2,
+                  words_removed = p.words_removed + $3,
+                  last_activity_at = NOW()
+                  FROM content.writing_events e
+                  WHERE p.event_id = e.id AND p.user_id = $1
+                    AND NOW() >= e.starts_at AND NOW() < e.ends_at
+                  RETURNING e.id, e.name, p.words_added - p.words_removed, p.last_milestone, e.milestones::text";
+    let rows = match conn.query(update, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Int32(delta.max(0)),
+        ParameterValue::Int32((-delta).max(0)),
+    ]) {
+        Ok(rows) => rows,
+        Err(_) => return,
+    };
+
+    for row in &rows.rows {
+        let event_id = String::decode(&row[0]).unwrap_or_default();
+        let event_name = String::decode(&row[1]).unwrap_or_default();
+        let net_words = i32::decode(&row[2]).unwrap_or(0);
+        let last_milestone = i32::decode(&row[3]).unwrap_or(0);
+        let milestones: Vec<i32> = String::decode(&row[4])
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+
+        broadcast(conn, &event_id, "writing_event.progress", serde_json::json!({
+            "event_id": event_id,
+            "user_id": user_id,
+            "delta": delta,
+            "net_words": net_words
+        }));
+
+        // Only the highest newly passed milestone is announced
+        let reached = milestones.iter()
+            .copied()
+            .filter(|m| *m > last_milestone && *m <= net_words)
+            .max();
+        if let Some(milestone) = reached {
+            if claim_milestone(conn, &event_id, user_id, milestone) {
+                notify_milestone(user_id, &event_id, &event_name, milestone);
+                broadcast(conn, &event_id, "writing_event.milestone", serde_json::json!({
+                    "event_id": event_id,
+                    "user_id": user_id,
+                    "milestone": milestone
+                }));
+            }
+        }
+    }
+}
+
+/// Record the milestone unless a concurrent save already did
+fn claim_milestone(conn: &Connection, event_id: &str, user_id: &Uuid, milestone: i32) -> bool {
+    let update = "UPDATE content.writing_event_participants SET last_milestone = $3
+                  WHERE event_id = $1 AND user_id = $2 AND last_milestone < $3";
+    conn.execute(update, &[
+        ParameterValue::Str(event_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Int32(milestone),
+    ]).map(|updated| updated > 0).unwrap_or(false)
+}
+
+/// Queue a real-time event for every participant, delivered over the
+/// messaging service's `/events/subscribe` stream
+fn broadcast(conn: &Connection, event_id: &str, event_type: &str, data: serde_json::Value) {
+    let insert = "INSERT INTO messaging.events (id, user_id, type, data, created_at)
+                  SELECT uuid_generate_v4(), p.user_id, $2, $3::jsonb, NOW()
+                  FROM content.writing_event_participants p WHERE p.event_id = $1";
+    let _ = conn.execute(insert, &[
+        ParameterValue::Str(event_id.to_string()),
+        ParameterValue::Str(event_type.to_string()),
+        ParameterValue::Str(data.to_string()),
+    ]);
+}
+
+/// Fire-and-forget in-app notification through the messaging service
+fn notify_milestone(user_id: &Uuid, event_id: &str, event_name: &str, milestone: i32) {
+    let messaging_url = variables::get("messaging_service_url")
+        .unwrap_or_else(|_| "http://messaging-service:3106".to_string());
+
+    let body = serde_json::json!({
+        "user_id": user_id,
+        "type": "writing_milestone",
+        "title": format!("{} words in {}", milestone, event_name),
+        "body": format!("You've passed {} words in {}. Keep going!", milestone, event_name),
+        "data": {
+            "event_id": event_id,
+            "milestone": milestone,
+            "url": format!("/events/{}", event_id)
+        }
+    });
+
+    let request = outbound_http::Request::builder()
+        .method(outbound_http::Method::Post)
+        .uri(format!("{}/notifications", messaging_url))
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .build();
+
+    let _ = outbound_http::send(request);
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+/// Unknown user IDs are skipped; returns how many were newly added
+fn insert_participants(conn: &Connection, event_id: &Uuid, user_ids: &[Uuid]) -> Result<u64, ServiceError> {
+    let ids: Vec<String> = user_ids.iter().map(|id| id.to_string()).collect();
+    let insert = "INSERT INTO content.writing_event_participants (event_id, user_id)
+                  SELECT $1, u.id FROM users.users u
+                  WHERE u.id::text = ANY(string_to_array($2, ','))
+                  ON CONFLICT (event_id, user_id) DO NOTHING";
+    conn.execute(insert, &[
+        ParameterValue::Str(event_id.to_string()),
+        ParameterValue::Str(ids.join(",")),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))
+}
+
+/// Events are invisible to non-participants
+fn require_participant(conn: &Connection, event_id: &Uuid, user_id: &Uuid) -> Result<WritingEvent, ServiceError> {
+    let query = format!(
+        "SELECT {} FROM content.writing_events e
+         JOIN content.writing_event_participants p ON p.event_id = e.id AND p.user_id = $2
+         WHERE e.id = $1",
+        EVENT_COLUMNS
+    );
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(event_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    rows.rows.first()
+        .map(|row| event_from_row(row))
+        .ok_or_else(|| ServiceError::NotFound("Event not found".into()))
+}
+
+fn load_event(conn: &Connection, event_id: &Uuid) -> Result<Option<WritingEvent>, ServiceError> {
+    let query = format!("SELECT {} FROM content.writing_events e WHERE e.id = $1", EVENT_COLUMNS);
+    let rows = conn.query(&query, &[ParameterValue::Str(event_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    Ok(rows.rows.first().map(|row| event_from_row(row)))
+}
+
+/// Decode the columns selected by `EVENT_COLUMNS`
+fn event_from_row(row: &[spin_sdk::pg::DbValue]) -> WritingEvent {
+    WritingEvent {
+        id: String::decode(&row[0]).unwrap_or_default(),
+        name: String::decode(&row[1]).unwrap_or_default(),
+        description: String::decode(&row[2]).ok(),
+        created_by: String::decode(&row[3]).unwrap_or_default(),
+        starts_at: String::decode(&row[4]).unwrap_or_default(),
+        ends_at: String::decode(&row[5]).unwrap_or_default(),
+        status: String::decode(&row[6]).unwrap_or_default(),
+        target_words: i32::decode(&row[7]).ok(),
+        milestones: String::decode(&row[8])
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default(),
+        participant_count: i64::decode(&row[9]).unwrap_or(0),
+    }
+}
+
+fn parse_timestamp(field: &str, value: &str) -> Result<DateTime<Utc>, ServiceError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|_| ServiceError::BadRequest(format!("{} must be an RFC 3339 timestamp", field)))
+}
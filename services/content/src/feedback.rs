@@ -0,0 +1,329 @@
+//! Beta-reader feedback
+//!
+//! Authors hand out feedback tokens (one per reader or reading group) for a
+//! book. Whoever holds a live token can submit a structured response for any
+//! of the book's chapters with `POST /chapters/:id/feedback` — a 1-5 rating,
+//! free-text comments and tags from a fixed list — without an account or
+//! access to the collaborative editor. Resubmitting for the same chapter
+//! replaces the earlier response. The author sees the aggregate in
+//! `GET /books/:id/feedback/summary`; sentiment is derived from the rating.
+
+use crate::error::ServiceError;
+use serde::Deserialize;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+/// Tags a feedback form offers; anything else is rejected
+pub const FEEDBACK_TAGS: [&str; 12] = [
+    "gripping", "slow_pacing", "rushed", "confusing", "great_characters", "flat_characters",
+    "strong_dialogue", "stilted_dialogue", "vivid_setting", "plot_hole", "typos", "want_more",
+];
+
+const MAX_COMMENT_CHARS: usize = 10_000;
+const MAX_EXPIRY_DAYS: i64 = 365;
+const RECENT_COMMENTS: i64 = 20;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTokenRequest {
+    /// Who the token is for, e.g. "Critique group"
+    pub label: Option<String>,
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubmitFeedbackRequest {
+    pub rating: i32,
+    pub comments: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub reader_name: Option<String>,
+}
+
+//=============================================================================
+// Tokens (author)
+//=============================================================================
+
+/// POST /books/:id/feedback/tokens - Issue a beta-reader token
+pub fn create_token(conn: &Connection, book_id: &Uuid, user_id: &Uuid, body: CreateTokenRequest) -> Result<Response, ServiceError> {
+    if let Some(days) = body.expires_in_days {
+        if days <= 0 || days > MAX_EXPIRY_DAYS {
+            return Err(ServiceError::BadRequest(format!("expires_in_days must be between 1 and {}", MAX_EXPIRY_DAYS)));
+        }
+    }
+    let label = body.label.map(|l| l.trim().to_string()).filter(|l| !l.is_empty());
+    let expires_at = body.expires_in_days.map(|days| (Utc::now() + Duration::days(days)).to_rfc3339());
+
+    let id = Uuid::new_v4();
+    let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let insert = "INSERT INTO content.feedback_tokens (id, book_id, token, label, created_by, expires_at)
+                  VALUES ($1, $2, $3, $4, $5, $6::timestamptz)";
+    conn.execute(insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(token.clone()),
+        label.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(user_id.to_string()),
+        expires_at.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    crate::json_response(201, serde_json::json!({
+        "id": id,
+        "book_id": book_id,
+        "token": token,
+        "label": label,
+        "expires_at": expires_at
+    }))
+}
+
+/// GET /books/:id/feedback/tokens - Tokens with their response counts
+pub fn list_tokens(conn: &Connection, book_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT t.id, t.token, t.label, t.expires_at::text, t.revoked_at::text, t.created_at::text,
+                        (t.revoked_at IS NULL AND (t.expires_at IS NULL OR t.expires_at > NOW())),
+                        (SELECT COUNT(*) FROM content.feedback f WHERE f.token_id = t.id)
+                 FROM content.feedback_tokens t
+                 WHERE t.book_id = $1
+                 ORDER BY t.created_at DESC";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let tokens: Vec<serde_json::Value> = rows.rows.iter().map(|row| serde_json::json!({
+        "id": String::decode(&row[0]).unwrap_or_default(),
+        "token": String::decode(&row[1]).unwrap_or_default(),
+        "label": String::decode(&row[2]).ok(),
+        "expires_at": String::decode(&row[3]).ok(),
+        "revoked_at": String::decode(&row[4]).ok(),
+        "created_at": String::decode(&row[5]).unwrap_or_default(),
+        "active": bool::decode(&row[6]).unwrap_or(false),
+        "responses": i64::decode(&row[7]).unwrap_or(0)
+    })).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "tokens": tokens,
+        "total": tokens.len()
+    }))
+}
+
+/// DELETE /books/:id/feedback/tokens/:token_id - Revoke a token; its responses are kept
+pub fn revoke_token(conn: &Connection, book_id: &Uuid, token_id: &Uuid) -> Result<Response, ServiceError> {
+    let update = "UPDATE content.feedback_tokens SET revoked_at = NOW()
+                  WHERE id = $1 AND book_id = $2 AND revoked_at IS NULL";
+    let revoked = conn.execute(update, &[
+        ParameterValue::Str(token_id.to_string()),
+        ParameterValue::Str(book_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    if revoked == 0 {
+        return Err(ServiceError::NotFound("Active token not found".into()));
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "id": token_id,
+        "revoked": true
+    }))
+}
+
+//=============================================================================
+// Submission (token holder)
+//=============================================================================
+
+/// POST /chapters/:id/feedback - Submit or replace a reader's feedback on a chapter
+pub fn submit(
+    conn: &Connection,
+    token: &str,
+    chapter_id: &Uuid,
+    reader_user_id: Option<Uuid>,
+    body: SubmitFeedbackRequest,
+) -> Result<Response, ServiceError> {
+    if !(1..=5).contains(&body.rating) {
+        return Err(ServiceError::BadRequest("rating must be between 1 and 5".into()));
+    }
+    let comments = body.comments.map(|c| c.trim().to_string()).filter(|c| !c.is_empty());
+    if comments.as_ref().map(|c| c.chars().count() > MAX_COMMENT_CHARS).unwrap_or(false) {
+        return Err(ServiceError::BadRequest(format!("comments must be at most {} characters", MAX_COMMENT_CHARS)));
+    }
+    let mut tags = body.tags;
+    if let Some(unknown) = tags.iter().find(|t| !FEEDBACK_TAGS.contains(&t.as_str())) {
+        return Err(ServiceError::BadRequest(format!(
+            "Unknown tag '{}'; expected any of {}", unknown, FEEDBACK_TAGS.join(", ")
+        )));
+    }
+    tags.sort();
+    tags.dedup();
+    let reader_name = body.reader_name.map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
+
+    // A token only covers chapters of the book it was issued for. Unknown,
+    // revoked and expired tokens get the same answer.
+    let query = "SELECT t.id, t.book_id FROM content.feedback_tokens t
+                 JOIN content.chapters c ON c.book_id = t.book_id
+                 WHERE t.token = $1 AND c.id = $2
+                   AND t.revoked_at IS NULL AND (t.expires_at IS NULL OR t.expires_at > NOW())";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(token.to_string()),
+        ParameterValue::Str(chapter_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::Forbidden("Feedback token is not valid for this chapter".into()))?;
+    let token_id = String::decode(&row[0]).unwrap_or_default();
+    let book_id = String::decode(&row[1]).unwrap_or_default();
+
+    let upsert = "INSERT INTO content.feedback
+                  (id, token_id, book_id, chapter_id, reader_user_id, reader_name, rating, comments, tags)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7, $8, string_to_array($9, ','))
+                  ON CONFLICT (token_id, chapter_id) DO UPDATE SET
+                  reader_user_id = COALESCE(EXCLUDED.reader_user_id, content.feedback.reader_user_id),
+                  reader_name = COALESCE(EXCLUDED.reader_name, content.feedback.reader_name),
+                  rating = EXCLUDED.rating, comments = EXCLUDED.comments, tags = EXCLUDED.tags,
+                  updated_at = NOW()
+                  RETURNING id, (xmax = 0)";
+    let rows = conn.query(upsert, &[
+        ParameterValue::Str(Uuid::new_v4().to_string()),
+        ParameterValue::Str(token_id),
+        ParameterValue::Str(book_id.clone()),
+        ParameterValue::Str(chapter_id.to_string()),
+        reader_user_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+        reader_name.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Int16(body.rating as i16),
+        comments.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(tags.join(",")),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::Internal("Feedback was not saved".into()))?;
+    let created = bool::decode(&row[1]).unwrap_or(true);
+
+    crate::json_response(if created { 201 } else { 200 }, serde_json::json!({
+        "id": String::decode(&row[0]).unwrap_or_default(),
+        "book_id": book_id,
+        "chapter_id": chapter_id,
+        "rating": body.rating,
+        "sentiment": sentiment(body.rating),
+        "tags": tags,
+        "replaced": !created
+    }))
+}
+
+//=============================================================================
+// Summary (author)
+//=============================================================================
+
+/// GET /books/:id/feedback/summary - Ratings, sentiment, common tags and recent comments
+pub fn summary(conn: &Connection, book_id: &Uuid) -> Result<Response, ServiceError> {
+    let book_param = [ParameterValue::Str(book_id.to_string())];
+
+    let totals_query = "SELECT COUNT(*), COUNT(DISTINCT token_id), AVG(rating)::float8,
+                               COUNT(*) FILTER (WHERE rating >= 4),
+                               COUNT(*) FILTER (WHERE rating = 3),
+                               COUNT(*) FILTER (WHERE rating <= 2),
+                               COUNT(*) FILTER (WHERE rating = 1), COUNT(*) FILTER (WHERE rating = 2),
+                               COUNT(*) FILTER (WHERE rating = 3), COUNT(*) FILTER (WHERE rating = 4),
+                               COUNT(*) FILTER (WHERE rating = 5)
+                        FROM content.feedback WHERE book_id = $1";
+    let totals = conn.query(totals_query, &book_param)
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = totals.rows.first()
+        .ok_or_else(|| ServiceError::Internal("Summary query returned no rows".into()))?;
+    let count = |i: usize| i64::decode(&row[i]).unwrap_or(0);
+    let responses = count(0);
+
+    let tags_query = "SELECT tag, COUNT(*) AS uses FROM content.feedback, unnest(tags) AS tag
+                      WHERE book_id = $1
+                      GROUP BY tag ORDER BY uses DESC, tag";
+    let tag_rows = conn.query(tags_query, &book_param)
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let common_notes: Vec<serde_json::Value> = tag_rows.rows.iter().map(|row| {
+        let uses = i64::decode(&row[1]).unwrap_or(0);
+        serde_json::json!({
+            "tag": String::decode(&row[0]).unwrap_or_default(),
+            "count": uses,
+            "share": if responses > 0 { uses as f64 / responses as f64 } else { 0.0 }
+        })
+    }).collect();
+
+    let chapters_query = "SELECT c.id, c.chapter_number, c.title, COUNT(f.id), AVG(f.rating)::float8,
+                                 COUNT(f.id) FILTER (WHERE f.rating >= 4),
+                                 COUNT(f.id) FILTER (WHERE f.rating = 3),
+                                 COUNT(f.id) FILTER (WHERE f.rating <= 2)
+                          FROM content.chapters c
+                          LEFT JOIN content.feedback f ON f.chapter_id = c.id
+                          WHERE c.book_id = $1
+                          GROUP BY c.id, c.chapter_number, c.title
+                          ORDER BY c.chapter_number";
+    let chapter_rows = conn.query(chapters_query, &book_param)
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let chapters: Vec<serde_json::Value> = chapter_rows.rows.iter().map(|row| serde_json::json!({
+        "chapter_id": String::decode(&row[0]).unwrap_or_default(),
+        "chapter_number": i32::decode(&row[1]).unwrap_or(0),
+        "title": String::decode(&row[2]).unwrap_or_default(),
+        "responses": i64::decode(&row[3]).unwrap_or(0),
+        "average_rating": f64::decode(&row[4]).ok(),
+        "sentiment": {
+            "positive": i64::decode(&row[5]).unwrap_or(0),
+            "neutral": i64::decode(&row[6]).unwrap_or(0),
+            "negative": i64::decode(&row[7]).unwrap_or(0)
+        }
+    })).collect();
+
+    let comments_query = "SELECT f.chapter_id, c.chapter_number, f.rating, f.comments, f.reader_name,
+                                 array_to_string(f.tags, ','), f.updated_at::text
+                          FROM content.feedback f
+                          JOIN content.chapters c ON c.id = f.chapter_id
+                          WHERE f.book_id = $1 AND f.comments IS NOT NULL
+                          ORDER BY f.updated_at DESC
+                          LIMIT $2";
+    let comment_rows = conn.query(comments_query, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Int64(RECENT_COMMENTS),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let recent_comments: Vec<serde_json::Value> = comment_rows.rows.iter().map(|row| {
+        let rating = i16::decode(&row[2]).unwrap_or(0) as i32;
+        serde_json::json!({
+            "chapter_id": String::decode(&row[0]).unwrap_or_default(),
+            "chapter_number": i32::decode(&row[1]).unwrap_or(0),
+            "rating": rating,
+            "sentiment": sentiment(rating),
+            "comments": String::decode(&row[3]).unwrap_or_default(),
+            "reader_name": String::decode(&row[4]).ok(),
+            "tags": String::decode(&row[5]).unwrap_or_default()
+                .split(',')
+                .filter(|t| !t.is_empty())
+                .collect::<Vec<_>>(),
+            "updated_at": String::decode(&row[6]).unwrap_or_default()
+        })
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "book_id": book_id,
+        "responses": responses,
+        "readers": count(1),
+        "average_rating": f64::decode(&row[2]).ok(),
+        "sentiment": {
+            "positive": count(3),
+            "neutral": count(4),
+            "negative": count(5)
+        },
+        "rating_distribution": {
+            "1": count(6),
+            "2": count(7),
+            "3": count(8),
+            "4": count(9),
+            "5": count(10)
+        },
+        "common_notes": common_notes,
+        "chapters": chapters,
+        "recent_comments": recent_comments
+    }))
+}
+
+fn sentiment(rating: i32) -> &'static str {
+    match rating {
+        4..=5 => "positive",
+        3 => "neutral",
+        _ => "negative",
+    }
+}
@@ -0,0 +1,253 @@
+//! Writing goals and streak tracking
+//!
+//! Authors set a per-book word target, deadline, and daily target. Daily
+//! progress is recorded in `content.writing_sessions` whenever a chapter's
+//! word count changes, which also drives the consecutive-day streak.
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::{Duration, NaiveDate, Utc};
+use uuid::Uuid;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct SetGoalRequest {
+    pub target_word_count: i32,
+    /// Deadline as `YYYY-MM-DD`
+    pub deadline: Option<String>,
+    pub daily_target: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WritingGoal {
+    pub book_id: Uuid,
+    pub target_word_count: i32,
+    pub deadline: Option<String>,
+    pub daily_target: Option<i32>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DailyProgress {
+    pub date: String,
+    pub words_added: i32,
+    pub words_removed: i32,
+    pub net_words: i32,
+}
+
+//=============================================================================
+// Goal Endpoints
+//=============================================================================
+
+/// POST /books/:id/goals - Create or replace the book's writing goal
+pub fn set_goal(conn: &Connection, book_id: Uuid, body: SetGoalRequest) -> Result<Response, ServiceError> {
+    if body.target_word_count <= 0 {
+        return Err(ServiceError::BadRequest("target_word_count must be positive".into()));
+    }
+    if body.daily_target.map(|t| t <= 0).unwrap_or(false) {
+        return Err(ServiceError::BadRequest("daily_target must be positive".into()));
+    }
+    if let Some(ref deadline) = body.deadline {
+        NaiveDate::parse_from_str(deadline, "%Y-%m-%d")
+            .map_err(|_| ServiceError::BadRequest("deadline must be YYYY-MM-DD".into()))?;
+    }
+
+    let now = Utc::now();
+    let upsert = "INSERT INTO content.writing_goals
+                  (book_id, target_word_count, deadline, daily_target, created_at, updated_at)
+                  VALUES ($1, $2, $3, $4, $5, $5)
+                  ON CONFLICT (book_id) DO UPDATE SET
+                  target_word_count = $2, deadline = $3, daily_target = $4, updated_at = $5";
+
+    let params = [
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Int32(body.target_word_count),
+        body.deadline.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        body.daily_target.map(ParameterValue::Int32).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(now.to_rfc3339()),
+    ];
+
+    conn.execute(upsert, &params)
+        .map_err(|e| ServiceError::Internal(format!("Upsert failed: {}", e)))?;
+
+    crate::json_response(201, WritingGoal {
+        book_id,
+        target_word_count: body.target_word_count,
+        deadline: body.deadline,
+        daily_target: body.daily_target,
+        created_at: now.to_rfc3339(),
+        updated_at: now.to_rfc3339(),
+    })
+}
+
+/// GET /books/:id/goals/progress - Progress against the goal plus streaks
+pub fn get_progress(conn: &Connection, user_id: Uuid, book_id: Uuid) -> Result<Response, ServiceError> {
+    let goal_query = "SELECT g.target_word_count, g.deadline::text, g.daily_target, g.created_at, g.updated_at,
+                      b.word_count
+                      FROM content.writing_goals g
+                      JOIN content.books b ON b.id = g.book_id
+                      WHERE g.book_id = $1";
+    let rows = conn.query(goal_query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    if rows.rows.is_empty() {
+        return Err(ServiceError::NotFound("No goal set for this book".into()));
+    }
+
+    let row = &rows.rows[0];
+    let goal = WritingGoal {
+        book_id,
+        target_word_count: i32::decode(&row[0]).unwrap_or(0),
+        deadline: String::decode(&row[1]).ok(),
+        daily_target: i32::decode(&row[2]).ok(),
+        created_at: String::decode(&row[3]).unwrap_or_default(),
+        updated_at: String::decode(&row[4]).unwrap_or_default(),
+    };
+    let current_words = i32::decode(&row[5]).unwrap_or(0);
+
+    let history_query = "SELECT session_date::text, words_added, words_removed
+                         FROM content.writing_sessions
+                         WHERE book_id = $1 AND user_id = $2
+                         ORDER BY session_date DESC LIMIT 366";
+    let history_rows = conn.query(history_query, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let history: Vec<DailyProgress> = history_rows.rows.iter().map(|row| {
+        let words_added = i32::decode(&row[1]).unwrap_or(0);
+        let words_removed = i32::decode(&row[2]).unwrap_or(0);
+        DailyProgress {
+            date: String::decode(&row[0]).unwrap_or_default(),
+            words_added,
+            words_removed,
+            net_words: words_added - words_removed,
+        }
+    }).collect();
+
+    let today = Utc::now().date_naive();
+    let active_days: Vec<NaiveDate> = history.iter()
+        .filter(|d| d.words_added > 0)
+        .filter_map(|d| NaiveDate::parse_from_str(&d.date, "%Y-%m-%d").ok())
+        .collect();
+    let (current_streak, longest_streak) = compute_streaks(&active_days, today);
+
+    let today_str = today.format("%Y-%m-%d").to_string();
+    let words_today = history.iter()
+        .find(|d| d.date == today_str)
+        .map(|d| d.net_words)
+        .unwrap_or(0);
+
+    let remaining_words = (goal.target_word_count - current_words).max(0);
+    let days_remaining = goal.deadline.as_deref()
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+        .map(|deadline| (deadline - today).num_days() + 1)
+        .map(|days| days.max(0));
+    let required_daily_pace = days_remaining
+        .filter(|days| *days > 0)
+        .map(|days| (remaining_words as i64 + days - 1) / days);
+
+    crate::json_response(200, serde_json::json!({
+        "goal": goal,
+        "current_word_count": current_words,
+        "remaining_words": remaining_words,
+        "percent_complete": (current_words as f64 / goal.target_word_count.max(1) as f64 * 100.0).min(100.0),
+        "words_today": words_today,
+        "daily_target_met": goal.daily_target.map(|t| words_today >= t),
+        "days_remaining": days_remaining,
+        "required_daily_pace": required_daily_pace,
+        "on_track": match (required_daily_pace, goal.daily_target) {
+            (Some(pace), Some(target)) => Some(pace <= target as i64),
+            _ => None,
+        },
+        "streak": {
+            "current": current_streak,
+            "longest": longest_streak
+        },
+        "history": history.iter().take(30).collect::<Vec<_>>()
+    }))
+}
+
+//=============================================================================
+// Session Tracking
+//=============================================================================
+
+/// Record a chapter word-count change against today's writing session
+pub fn record_word_count_change(
+    conn: &Connection,
+    user_id: &Uuid,
+    book_id: &Uuid,
+    old_word_count: i32,
+    new_word_count: i32,
+) -> Result<(), ServiceError> {
+    let delta = new_word_count - old_word_count;
+    if delta == 0 {
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    let upsert = "INSERT INTO content.writing_sessions
+                  (user_id, book_id, session_date, words_added, words_removed, updated_at)
+                  VALUES ($1, $2, $3::date, $4, $5, $6)
+                  ON CONFLICT (user_id, book_id, session_date) DO UPDATE SET
+                  words_added = content.writing_sessions.words_added + $4,
+                  words_removed = content.writing_sessions.words_removed + $5,
+                  updated_at = $6";
+
+    let params = [
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(now.date_naive().format("%Y-%m-%d").to_string()),
+        ParameterValue::Int32(delta.max(0)),
+        ParameterValue::Int32((-delta).max(0)),
+        ParameterValue::Str(now.to_rfc3339()),
+    ];
+
+    conn.execute(upsert, &params)
+        .map_err(|e| ServiceError::Internal(format!("Session update failed: {}", e)))?;
+
+    // The same change counts towards any writing event the user is in
+    crate::events::record_word_delta(conn, user_id, delta);
+    Ok(())
+}
+
+/// Current and longest runs of consecutive writing days.
+///
+/// `active_days` must be sorted newest first. The current streak survives
+/// until the end of the day after the last session.
+fn compute_streaks(active_days: &[NaiveDate], today: NaiveDate) -> (i32, i32) {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+
+    for day in active_days {
+        run = match previous {
+            Some(prev) if prev - *day == Duration::days(1) => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        previous = Some(*day);
+    }
+
+    let mut current = 0;
+    if let Some(latest) = active_days.first() {
+        if today - *latest <= Duration::days(1) {
+            current = 1;
+            for pair in active_days.windows(2) {
+                if pair[0] - pair[1] == Duration::days(1) {
+                    current += 1;
+                } else {
+                    break;
+                }
+            }
+        }
+    }
+
+    (current, longest)
+}
@@ -6,22 +6,90 @@
 //! - GET /health - Health check
 //! - GET /books - List user's books
 //! - POST /books - Create new book
+//! - POST /books/from-template - Create a book with chapters, front/back matter and outline from a template
+//! - GET /templates - List active book templates
 //! - GET /books/:id - Get book details
 //! - PUT /books/:id - Update book
 //! - DELETE /books/:id - Delete book
+//! - POST /books/:id/publish - Validate and publish a book, then queue it for search indexing
+//! - POST /books/:id/unpublish - Return a published book to draft and remove it from search, or withdraw it from moderation review
+//! - GET /books/:id/moderation - Latest moderation report for the book
+//! - POST /books/:id/goals - Set writing goal
+//! - GET /books/:id/goals/progress - Get goal progress and writing streak
+//! - POST /series - Create a book series
+//! - GET /series/:id - Get a series with its books in order and aggregate word counts
+//! - PUT /books/:id/series - Add a book to a series at a position, move it, or remove it
+//! - POST /events - Create a group writing event
+//! - GET /events - List events the caller takes part in
+//! - GET /events/:id - Get event details and own progress
+//! - GET /events/:id/leaderboard - Rank participants by words written during the event
+//! - POST /events/:id/participants - Add participants (creator)
+//! - DELETE /events/:id/participants/:user_id - Leave or remove a participant
+//! - POST /books/:id/feedback/tokens - Issue a beta-reader feedback token
+//! - GET /books/:id/feedback/tokens - List feedback tokens
+//! - DELETE /books/:id/feedback/tokens/:token_id - Revoke a feedback token
+//! - GET /books/:id/feedback/summary - Aggregate beta-reader ratings, sentiment and notes
+//! - POST /chapters/:id/feedback - Submit beta-reader feedback (X-Feedback-Token, no account needed)
+//! - POST /books/:id/analyze/continuity - Queue an AI continuity check
+//! - GET /books/:id/reports - List analysis reports and findings
+//! - POST /books/:id/analyze/related - Queue related-chapter detection (non-fiction)
+//! - GET /books/:id/analysis - Prose style statistics for the whole book, with a line per chapter
+//! - GET /chapters/:id/analysis - Adverb density, passive voice, sentence lengths, repeated phrases and dialogue ratio
+//! - POST /books/:id/snapshots - Pin the book's current content in an immutable snapshot
+//! - GET /books/:id/snapshots - List content snapshots
+//! - GET /snapshots/:id/compare - Compare a snapshot with the current content
+//! - POST /books/:id/exports - Export from a new or existing snapshot
+//! - GET /books/:id/exports - List exports with their snapshots and checksums
+//! - GET /exports/:id/download - Download an export, rebuilt from its snapshot
+//! - POST /exports/:id/reexport - Re-export the same snapshot byte for byte
+//! - GET /books/:id/outline - Get the outline (parts, chapters, scenes) with drafted-vs-planned diff
+//! - PUT /books/:id/outline - Replace the outline, guarded by its version stamp
+//! - POST /books/:id/threads - Name a plot thread
+//! - GET /books/:id/threads/graph - Thread presence per chapter, flagging dropped plotlines
+//! - PUT /chapters/:id/threads - Tag a chapter and its scenes with the threads they carry
+//! - POST /books/:id/lore - Add a place, item or faction to the book's world
+//! - GET /books/:id/lore?kind= - List the book's lore, entries shared across its series included
+//! - GET /books/:id/lore/:entry_id - Get a lore entry with the chapters referencing it
+//! - PUT /books/:id/lore/:entry_id - Update a lore entry
+//! - DELETE /books/:id/lore/:entry_id - Delete a lore entry
+//! - PUT /chapters/:id/lore - Replace the lore entries a chapter references
+//! - POST /books/:id/personas - Add a co-writing persona (tone, vocabulary, banned words, memory)
+//! - GET /books/:id/personas - List the book's personas, active first
+//! - GET /books/:id/personas/:persona_id - Get a persona
+//! - PUT /books/:id/personas/:persona_id - Update a persona or make it the active one
+//! - DELETE /books/:id/personas/:persona_id - Delete a persona
 //! - GET /books/:id/chapters - List chapters
 //! - POST /books/:id/chapters - Create chapter
 //! - GET /chapters/:id - Get chapter
 //! - PUT /chapters/:id - Update chapter
 //! - DELETE /chapters/:id - Delete chapter
+//! - GET /chapters/:id/revisions - List earlier versions of a chapter, captured on every update
+//! - GET /chapters/:id/revisions/:n - Get a revision with its full content
+//! - POST /chapters/:id/revisions/:n/restore - Restore a revision, keeping the current version as a new one
+//! - GET /chapters/:id/related - List related chapters
+//! - PUT /chapters/:id/related - Replace related chapters
 //! - POST /generate/outline - Generate book outline
 //! - POST /generate/chapter - Generate chapter content
 //! - POST /generate/enhance - Enhance existing content
+//! - POST /generate/book - Generate a whole book: outline, chapter plans, scenes, then content
+//! - POST /generate/book/advance - Move book generations on to their next phase (internal, X-Internal-Token)
+//! - GET /jobs/:id - Get job status, with per-phase progress for book generations
+//! - POST /jobs/:id/resume - Resume a failed book generation from its failed phase
+//! - POST /jobs/:id/cancel - Cancel a book generation and its remaining phases
+//! - GET /public/changes?since=&limit= - Public feed of publish/update/removal events (no auth)
+//! - GET /admin/templates - List all book templates (admin)
+//! - POST /admin/templates - Create a book template (admin)
+//! - PUT /admin/templates/:id - Update a book template (admin)
+//! - DELETE /admin/templates/:id - Delete a book template (admin)
+//! - GET /admin/moderation/queue?status=&limit= - Books held for moderation review, oldest first (admin)
+//! - POST /admin/moderation/:id/review - Approve (publish) or reject a book held for review (admin)
+//! - POST /moderation/process - Retry moderation scans that could not run on publish (internal, X-Internal-Token)
 
 use spin_sdk::http::{IntoResponse, Request, Response, Method};
 use spin_sdk::http_component;
 use spin_sdk::pg::{Connection, Decode, ParameterValue};
 use spin_sdk::variables;
+use spin_sdk::outbound_http;
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
@@ -29,8 +97,28 @@ use uuid::Uuid;
 mod models;
 mod handlers;
 mod error;
+mod common;
 mod generation;
 mod credits;
+mod goals;
+mod scopes;
+mod analysis;
+mod related;
+mod snapshots;
+mod publishing;
+mod events;
+mod changes;
+mod feedback;
+mod series;
+mod revisions;
+mod outline;
+mod templates;
+mod style;
+mod threads;
+mod lore;
+mod personas;
+mod book_generation;
+mod moderation;
 
 use error::ServiceError;
 use models::*;
@@ -40,11 +128,196 @@ fn handle_request(req: Request) -> anyhow::Result<impl IntoResponse> {
     let path = req.path();
     let method = req.method();
 
+    if let Err(e) = authorworks_access::authorize(&req, scopes::route_scope) {
+        return Ok(ServiceError::from(e).into_response());
+    }
+
     let result = match (method, path) {
         // Health
         (Method::Get, "/health") => health_handler(),
         (Method::Get, "/") => service_info(),
 
+        // Public change feed
+        (Method::Get, "/public/changes") => list_public_changes(&req),
+
+        // Writing goals
+        (Method::Post, path) if path.starts_with("/books/") && path.ends_with("/goals") => {
+            set_writing_goal(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/books/") && path.ends_with("/goals/progress") => {
+            get_goal_progress(&req, path)
+        }
+
+        // Series
+        (Method::Post, "/series") => create_series(&req),
+        (Method::Get, path) if path.starts_with("/series/") => get_series(&req, path),
+        (Method::Put, path) if path.starts_with("/books/") && path.ends_with("/series") => {
+            set_book_series(&req, path)
+        }
+
+        // Writing events
+        (Method::Post, "/events") => create_writing_event(&req),
+        (Method::Get, "/events") => list_writing_events(&req),
+        (Method::Get, path) if path.starts_with("/events/") && path.ends_with("/leaderboard") => {
+            get_event_leaderboard(&req, path)
+        }
+        (Method::Post, path) if path.starts_with("/events/") && path.ends_with("/participants") => {
+            add_event_participants(&req, path)
+        }
+        (Method::Delete, path) if path.starts_with("/events/") && path.contains("/participants/") => {
+            remove_event_participant(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/events/") && path.matches('/').count() == 2 => {
+            get_writing_event(&req, path)
+        }
+
+        // Beta-reader feedback
+        (Method::Post, path) if path.starts_with("/books/") && path.ends_with("/feedback/tokens") => {
+            create_feedback_token(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/books/") && path.ends_with("/feedback/tokens") => {
+            list_feedback_tokens(&req, path)
+        }
+        (Method::Delete, path) if path.starts_with("/books/") && path.contains("/feedback/tokens/") => {
+            revoke_feedback_token(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/books/") && path.ends_with("/feedback/summary") => {
+            get_feedback_summary(&req, path)
+        }
+        (Method::Post, path) if path.starts_with("/chapters/") && path.ends_with("/feedback") => {
+            submit_chapter_feedback(&req, path)
+        }
+
+        // Analysis
+        (Method::Post, path) if path.starts_with("/books/") && path.ends_with("/analyze/continuity") => {
+            analyze_continuity(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/books/") && path.ends_with("/reports") => {
+            list_analysis_reports(&req, path)
+        }
+        (Method::Post, path) if path.starts_with("/books/") && path.ends_with("/analyze/related") => {
+            analyze_related_chapters(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/books/") && path.ends_with("/analysis") => {
+            get_book_analysis(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/chapters/") && path.ends_with("/analysis") => {
+            get_chapter_analysis(&req, path)
+        }
+
+        // Related chapters
+        (Method::Get, path) if path.starts_with("/chapters/") && path.ends_with("/related") => {
+            get_related_chapters(&req, path)
+        }
+        (Method::Put, path) if path.starts_with("/chapters/") && path.ends_with("/related") => {
+            set_related_chapters(&req, path)
+        }
+
+        // Snapshots & exports
+        (Method::Post, path) if path.starts_with("/books/") && path.ends_with("/snapshots") => {
+            create_snapshot(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/books/") && path.ends_with("/snapshots") => {
+            list_snapshots(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/snapshots/") && path.ends_with("/compare") => {
+            compare_snapshot(&req, path)
+        }
+        (Method::Post, path) if path.starts_with("/books/") && path.ends_with("/exports") => {
+            create_export(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/books/") && path.ends_with("/exports") => {
+            list_exports(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/exports/") && path.ends_with("/download") => {
+            download_export(&req, path)
+        }
+        (Method::Post, path) if path.starts_with("/exports/") && path.ends_with("/reexport") => {
+            reexport(&req, path)
+        }
+
+        // Publishing
+        (Method::Post, path) if path.starts_with("/books/") && path.ends_with("/publish") => {
+            publish_book(&req, path)
+        }
+        (Method::Post, path) if path.starts_with("/books/") && path.ends_with("/unpublish") => {
+            unpublish_book(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/books/") && path.ends_with("/moderation") => {
+            get_book_moderation(&req, path)
+        }
+
+        // Moderation
+        (Method::Get, "/admin/moderation/queue") => admin_moderation_queue(&req),
+        (Method::Post, path) if path.starts_with("/admin/moderation/") && path.ends_with("/review") => {
+            admin_review_moderation(&req, path)
+        }
+        (Method::Post, "/moderation/process") => process_moderation(&req),
+
+        // Plot threads
+        (Method::Post, path) if path.starts_with("/books/") && path.ends_with("/threads") => {
+            create_plot_thread(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/books/") && path.ends_with("/threads/graph") => {
+            get_plot_thread_graph(&req, path)
+        }
+        (Method::Put, path) if path.starts_with("/chapters/") && path.ends_with("/threads") => {
+            set_chapter_threads(&req, path)
+        }
+
+        // World lore
+        (Method::Post, path) if path.starts_with("/books/") && path.ends_with("/lore") => {
+            create_lore_entry(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/books/") && path.ends_with("/lore") => {
+            list_lore_entries(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/books/") && path.contains("/lore/") => {
+            get_lore_entry(&req, path)
+        }
+        (Method::Put, path) if path.starts_with("/books/") && path.contains("/lore/") => {
+            update_lore_entry(&req, path)
+        }
+        (Method::Delete, path) if path.starts_with("/books/") && path.contains("/lore/") => {
+            delete_lore_entry(&req, path)
+        }
+        (Method::Put, path) if path.starts_with("/chapters/") && path.ends_with("/lore") => {
+            set_chapter_lore(&req, path)
+        }
+
+        // Co-writing personas
+        (Method::Post, path) if path.starts_with("/books/") && path.ends_with("/personas") => {
+            create_persona(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/books/") && path.ends_with("/personas") => {
+            list_personas(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/books/") && path.contains("/personas/") => {
+            get_persona(&req, path)
+        }
+        (Method::Put, path) if path.starts_with("/books/") && path.contains("/personas/") => {
+            update_persona(&req, path)
+        }
+        (Method::Delete, path) if path.starts_with("/books/") && path.contains("/personas/") => {
+            delete_persona(&req, path)
+        }
+
+        // Outline
+        (Method::Get, path) if path.starts_with("/books/") && path.ends_with("/outline") => {
+            get_book_outline(&req, path)
+        }
+        (Method::Put, path) if path.starts_with("/books/") && path.ends_with("/outline") => {
+            save_book_outline(&req, path)
+        }
+
+        // Templates
+        (Method::Get, "/templates") => list_templates(&req),
+        (Method::Post, "/books/from-template") => create_book_from_template(&req),
+        (Method::Get, "/admin/templates") => admin_list_templates(&req),
+        (Method::Post, "/admin/templates") => admin_create_template(&req),
+        (Method::Put, path) if path.starts_with("/admin/templates/") => admin_update_template(&req, path),
+        (Method::Delete, path) if path.starts_with("/admin/templates/") => admin_delete_template(&req, path),
+
         // Books CRUD
         (Method::Get, "/books") => list_books(&req),
         (Method::Post, "/books") => create_book(&req),
@@ -58,6 +331,17 @@ fn handle_request(req: Request) -> anyhow::Result<impl IntoResponse> {
             delete_book(&req, path)
         }
 
+        // Chapter revisions
+        (Method::Get, path) if path.starts_with("/chapters/") && path.ends_with("/revisions") => {
+            list_chapter_revisions(&req, path)
+        }
+        (Method::Post, path) if path.starts_with("/chapters/") && path.contains("/revisions/") && path.ends_with("/restore") => {
+            restore_chapter_revision(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/chapters/") && path.contains("/revisions/") => {
+            get_chapter_revision(&req, path)
+        }
+
         // Chapters
         (Method::Get, path) if path.ends_with("/chapters") => list_chapters(&req, path),
         (Method::Post, path) if path.ends_with("/chapters") => create_chapter(&req, path),
@@ -69,6 +353,17 @@ fn handle_request(req: Request) -> anyhow::Result<impl IntoResponse> {
         (Method::Post, "/generate/outline") => generate_outline(&req),
         (Method::Post, "/generate/chapter") => generate_chapter_content(&req),
         (Method::Post, "/generate/enhance") => enhance_content(&req),
+        (Method::Post, "/generate/book") => generate_book(&req),
+        (Method::Post, "/generate/book/advance") => advance_book_generations(&req),
+
+        // Jobs
+        (Method::Post, path) if path.starts_with("/jobs/") && path.ends_with("/resume") => {
+            resume_job(&req, path)
+        }
+        (Method::Post, path) if path.starts_with("/jobs/") && path.ends_with("/cancel") => {
+            cancel_job(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/jobs/") => get_job(&req, path),
 
         // CORS
         (Method::Options, _) => cors_preflight(),
@@ -129,8 +424,26 @@ fn service_info() -> Result<Response, ServiceError> {
         "version": env!("CARGO_PKG_VERSION"),
         "endpoints": {
             "books": ["GET /books", "POST /books", "GET /books/:id", "PUT /books/:id", "DELETE /books/:id"],
+            "templates": ["GET /templates", "POST /books/from-template", "GET /admin/templates", "POST /admin/templates", "PUT /admin/templates/:id", "DELETE /admin/templates/:id"],
+            "publishing": ["POST /books/:id/publish", "POST /books/:id/unpublish"],
+            "moderation": ["GET /books/:id/moderation", "GET /admin/moderation/queue", "POST /admin/moderation/:id/review", "POST /moderation/process"],
+            "outline": ["GET /books/:id/outline", "PUT /books/:id/outline"],
+            "threads": ["POST /books/:id/threads", "GET /books/:id/threads/graph", "PUT /chapters/:id/threads"],
+            "lore": ["POST /books/:id/lore", "GET /books/:id/lore", "GET /books/:id/lore/:entry_id", "PUT /books/:id/lore/:entry_id", "DELETE /books/:id/lore/:entry_id", "PUT /chapters/:id/lore"],
+            "personas": ["POST /books/:id/personas", "GET /books/:id/personas", "GET /books/:id/personas/:persona_id", "PUT /books/:id/personas/:persona_id", "DELETE /books/:id/personas/:persona_id"],
             "chapters": ["GET /books/:id/chapters", "POST /books/:id/chapters", "GET /chapters/:id", "PUT /chapters/:id", "DELETE /chapters/:id"],
-            "generation": ["POST /generate/outline", "POST /generate/chapter", "POST /generate/enhance"]
+            "goals": ["POST /books/:id/goals", "GET /books/:id/goals/progress"],
+            "series": ["POST /series", "GET /series/:id", "PUT /books/:id/series"],
+            "events": ["POST /events", "GET /events", "GET /events/:id", "GET /events/:id/leaderboard", "POST /events/:id/participants", "DELETE /events/:id/participants/:user_id"],
+            "feedback": ["POST /books/:id/feedback/tokens", "GET /books/:id/feedback/tokens", "DELETE /books/:id/feedback/tokens/:token_id", "GET /books/:id/feedback/summary", "POST /chapters/:id/feedback"],
+            "analysis": ["POST /books/:id/analyze/continuity", "GET /books/:id/reports", "POST /books/:id/analyze/related"],
+            "related": ["GET /chapters/:id/related", "PUT /chapters/:id/related"],
+            "revisions": ["GET /chapters/:id/revisions", "GET /chapters/:id/revisions/:n", "POST /chapters/:id/revisions/:n/restore"],
+            "snapshots": ["POST /books/:id/snapshots", "GET /books/:id/snapshots", "GET /snapshots/:id/compare"],
+            "exports": ["POST /books/:id/exports", "GET /books/:id/exports", "GET /exports/:id/download", "POST /exports/:id/reexport"],
+            "generation": ["POST /generate/outline", "POST /generate/chapter", "POST /generate/enhance", "POST /generate/book"],
+            "jobs": ["GET /jobs/:id", "POST /jobs/:id/resume", "POST /jobs/:id/cancel"],
+            "public": ["GET /public/changes"]
         }
     }))
 }
@@ -140,7 +453,7 @@ fn cors_preflight() -> Result<Response, ServiceError> {
         .status(204)
         .header("Access-Control-Allow-Origin", "*")
         .header("Access-Control-Allow-Methods", "GET, POST, PUT, DELETE, OPTIONS")
-        .header("Access-Control-Allow-Headers", "Content-Type, Authorization, X-User-Id")
+        .header("Access-Control-Allow-Headers", "Content-Type, Authorization, X-User-Id, X-Scopes, X-Feedback-Token")
         .header("Access-Control-Max-Age", "86400")
         .body(())
         .build())
@@ -155,7 +468,8 @@ fn list_books(req: &Request) -> Result<Response, ServiceError> {
     let conn = get_db_connection()?;
 
     let query = "SELECT id, title, description, genre, status, cover_image_url, word_count, 
-                 created_at, updated_at, published_at 
+                 created_at, updated_at, published_at,
+                 COALESCE(ai_disabled, false), COALESCE(index_excluded, false)
                  FROM content.books WHERE author_id = $1 ORDER BY updated_at DESC";
     
     let params = [ParameterValue::Str(user_id.to_string())];
@@ -173,6 +487,8 @@ fn list_books(req: &Request) -> Result<Response, ServiceError> {
             word_count: i32::decode(&row[6]).unwrap_or(0),
             created_at: String::decode(&row[7]).unwrap_or_default(),
             updated_at: String::decode(&row[8]).unwrap_or_default(),
+            ai_disabled: bool::decode(&row[10]).unwrap_or(false),
+            index_excluded: bool::decode(&row[11]).unwrap_or(false),
         }
     }).collect();
 
@@ -187,11 +503,16 @@ fn create_book(req: &Request) -> Result<Response, ServiceError> {
     let body: CreateBookRequest = parse_json_body(req)?;
     let conn = get_db_connection()?;
 
+    check_book_entitlement(&user_id)?;
+    let content_rating = body.content_rating.as_deref().map(validate_content_rating).transpose()?;
+    let content_warnings = validate_content_warnings(body.content_warnings.as_deref().unwrap_or_default())?;
+
     let book_id = Uuid::new_v4();
     let now = Utc::now();
 
-    let query = "INSERT INTO content.books (id, author_id, title, description, genre, status, metadata, created_at, updated_at)
-                 VALUES ($1, $2, $3, $4, $5, 'draft', $6, $7, $7)
+    let query = "INSERT INTO content.books (id, author_id, title, description, genre, status, metadata,
+                 ai_disabled, index_excluded, content_rating, content_warnings, created_at, updated_at)
+                 VALUES ($1, $2, $3, $4, $5, 'draft', $6, $8, $9, $10, $11, $7, $7)
                  RETURNING id";
 
     let metadata = serde_json::to_string(&body.metadata.unwrap_or_default())
@@ -205,6 +526,10 @@ fn create_book(req: &Request) -> Result<Response, ServiceError> {
         ParameterValue::Str(body.genre.clone().unwrap_or_default()),
         ParameterValue::Str(metadata),
         ParameterValue::Str(now.to_rfc3339()),
+        ParameterValue::Boolean(body.ai_disabled.unwrap_or(false)),
+        ParameterValue::Boolean(body.index_excluded.unwrap_or(false)),
+        content_rating.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(serde_json::to_string(&content_warnings).unwrap_or_else(|_| "[]".into())),
     ];
 
     conn.execute(query, &params)
@@ -216,18 +541,81 @@ fn create_book(req: &Request) -> Result<Response, ServiceError> {
         "description": body.description,
         "genre": body.genre,
         "status": "draft",
+        "ai_disabled": body.ai_disabled.unwrap_or(false),
+        "index_excluded": body.index_excluded.unwrap_or(false),
+        "content_rating": content_rating,
+        "content_warnings": content_warnings,
         "created_at": now.to_rfc3339(),
         "message": "Book created successfully"
     }))
 }
 
+/// Age ratings discovery filters search on, least to most restricted
+const CONTENT_RATINGS: &[&str] = &["everyone", "teen", "mature", "adult"];
+const MAX_CONTENT_WARNINGS: usize = 20;
+const MAX_CONTENT_WARNING_LEN: usize = 40;
+
+fn validate_content_rating(rating: &str) -> Result<String, ServiceError> {
+    let rating = rating.trim().to_lowercase();
+    if !CONTENT_RATINGS.contains(&rating.as_str()) {
+        return Err(ServiceError::BadRequest(format!(
+            "content_rating must be one of: {}", CONTENT_RATINGS.join(", ")
+        )));
+    }
+    Ok(rating)
+}
+
+/// Trimmed and deduplicated; discovery normalizes them further for filtering
+fn validate_content_warnings(warnings: &[String]) -> Result<Vec<String>, ServiceError> {
+    let mut validated: Vec<String> = Vec::new();
+    for warning in warnings.iter().map(|w| w.trim()).filter(|w| !w.is_empty()) {
+        if warning.len() > MAX_CONTENT_WARNING_LEN {
+            return Err(ServiceError::BadRequest(format!(
+                "content warnings must be at most {} characters", MAX_CONTENT_WARNING_LEN
+            )));
+        }
+        if !validated.iter().any(|w| w.eq_ignore_ascii_case(warning)) {
+            validated.push(warning.to_string());
+        }
+    }
+    if validated.len() > MAX_CONTENT_WARNINGS {
+        return Err(ServiceError::BadRequest(format!(
+            "At most {} content warnings are allowed", MAX_CONTENT_WARNINGS
+        )));
+    }
+    Ok(validated)
+}
+
+fn check_book_entitlement(user_id: &Uuid) -> Result<(), ServiceError> {
+    enforce_entitlement(authorworks_entitlements::require(user_id, authorworks_entitlements::features::BOOKS, 1))
+}
+
+/// Paused accounts are read-only: no chapter writes or AI generation
+fn check_write_entitlement(user_id: &Uuid) -> Result<(), ServiceError> {
+    enforce_entitlement(authorworks_entitlements::require(user_id, authorworks_entitlements::features::WRITE, 0))
+}
+
+/// A billing outage should not stop anyone from writing
+fn enforce_entitlement(
+    result: Result<authorworks_entitlements::Entitlement, authorworks_entitlements::EntitlementError>,
+) -> Result<(), ServiceError> {
+    match result {
+        Err(authorworks_entitlements::EntitlementError::Denied(e)) => {
+            Err(ServiceError::PaymentRequired(e.denial_message()))
+        }
+        Err(authorworks_entitlements::EntitlementError::Unavailable(_)) | Ok(_) => Ok(()),
+    }
+}
+
 fn get_book(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
     let book_id = extract_id_from_path(path, "/books/")?;
     let conn = get_db_connection()?;
 
     let query = "SELECT id, title, description, genre, status, cover_image_url, word_count,
-                 metadata, created_at, updated_at, published_at
+                 metadata, created_at, updated_at, published_at,
+                 COALESCE(ai_disabled, false), COALESCE(index_excluded, false),
+                 series_id, series_order, content_rating, COALESCE(content_warnings, '[]'::jsonb)::text
                  FROM content.books WHERE id = $1 AND author_id = $2";
 
     let params = [
@@ -256,6 +644,12 @@ fn get_book(req: &Request, path: &str) -> Result<Response, ServiceError> {
         created_at: String::decode(&row[8]).unwrap_or_default(),
         updated_at: String::decode(&row[9]).unwrap_or_default(),
         published_at: String::decode(&row[10]).ok(),
+        ai_disabled: bool::decode(&row[11]).unwrap_or(false),
+        index_excluded: bool::decode(&row[12]).unwrap_or(false),
+        series_id: String::decode(&row[13]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+        series_order: i32::decode(&row[14]).ok(),
+        content_rating: String::decode(&row[15]).ok(),
+        content_warnings: serde_json::from_str(&String::decode(&row[16]).unwrap_or_default()).unwrap_or_default(),
     };
 
     json_response(200, book)
@@ -267,32 +661,45 @@ fn update_book(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let body: UpdateBookRequest = parse_json_body(req)?;
     let conn = get_db_connection()?;
 
+    if let Some(status) = &body.status {
+        verify_book_ownership(&conn, &book_id, &user_id)?;
+        publishing::check_status_update(&conn, &book_id, status)?;
+    }
+    let content_rating = body.content_rating.as_deref().map(validate_content_rating).transpose()?;
+    let content_warnings = body.content_warnings.as_deref().map(validate_content_warnings).transpose()?;
+
     let now = Utc::now();
 
     // Build dynamic update query
-    let mut updates = vec!["updated_at = $3"];
-    let mut param_idx = 4;
-    
-    if body.title.is_some() { updates.push("title = $4"); param_idx += 1; }
-    if body.description.is_some() { updates.push(&format!("description = ${}", param_idx)); param_idx += 1; }
-    if body.genre.is_some() { updates.push(&format!("genre = ${}", param_idx)); param_idx += 1; }
-    if body.status.is_some() { updates.push(&format!("status = ${}", param_idx)); }
-
-    let query = format!(
-        "UPDATE content.books SET {} WHERE id = $1 AND author_id = $2",
-        updates.join(", ")
-    );
-
+    let mut updates = vec!["updated_at = $3".to_string()];
     let mut params: Vec<ParameterValue> = vec![
         ParameterValue::Str(book_id.to_string()),
         ParameterValue::Str(user_id.to_string()),
         ParameterValue::Str(now.to_rfc3339()),
     ];
 
-    if let Some(ref title) = body.title { params.push(ParameterValue::Str(title.clone())); }
-    if let Some(ref desc) = body.description { params.push(ParameterValue::Str(desc.clone())); }
-    if let Some(ref genre) = body.genre { params.push(ParameterValue::Str(genre.clone())); }
-    if let Some(ref status) = body.status { params.push(ParameterValue::Str(status.clone())); }
+    let fields = [
+        ("title", body.title.clone().map(ParameterValue::Str)),
+        ("description", body.description.clone().map(ParameterValue::Str)),
+        ("genre", body.genre.clone().map(ParameterValue::Str)),
+        ("status", body.status.clone().map(ParameterValue::Str)),
+        ("ai_disabled", body.ai_disabled.map(ParameterValue::Boolean)),
+        ("index_excluded", body.index_excluded.map(ParameterValue::Boolean)),
+        ("content_rating", content_rating.clone().map(ParameterValue::Str)),
+        ("content_warnings", content_warnings.as_ref()
+            .map(|w| ParameterValue::Str(serde_json::to_string(w).unwrap_or_else(|_| "[]".into())))),
+    ];
+    for (column, value) in fields {
+        if let Some(value) = value {
+            params.push(value);
+            updates.push(format!("{} = ${}", column, params.len()));
+        }
+    }
+
+    let query = format!(
+        "UPDATE content.books SET {} WHERE id = $1 AND author_id = $2",
+        updates.join(", ")
+    );
 
     let result = conn.execute(&query, &params)
         .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
@@ -301,12 +708,85 @@ fn update_book(req: &Request, path: &str) -> Result<Response, ServiceError> {
         return Err(ServiceError::NotFound("Book not found".into()));
     }
 
+    // Excluding a book also takes down anything already indexed. Discovery
+    // refuses new index writes for it either way, so a failed removal only
+    // leaves stale results until the next attempt.
+    let index_removal = match body.index_excluded {
+        Some(true) => Some(if remove_from_search_index(&book_id).is_ok() { "queued" } else { "failed" }),
+        _ => None,
+    };
+
+    // Search filters on the rating, so a published book is reindexed as soon
+    // as it changes rather than at its next publish
+    let reindex = if content_rating.is_some() || content_warnings.is_some() {
+        publishing::reindex(&conn, &book_id)?
+    } else {
+        None
+    };
+
     json_response(200, serde_json::json!({
         "message": "Book updated successfully",
-        "updated_at": now.to_rfc3339()
+        "updated_at": now.to_rfc3339(),
+        "index_removal": index_removal,
+        "reindex": reindex
     }))
 }
 
+fn publish_book(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    publishing::publish(&conn, &book_id)
+}
+
+fn unpublish_book(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    publishing::unpublish(&conn, &book_id)
+}
+
+fn get_book_moderation(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    moderation::get_for_book(&conn, &book_id)
+}
+
+fn admin_moderation_queue(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let status = get_query_param(req.query(), "status");
+    let limit = get_query_param(req.query(), "limit")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(moderation::DEFAULT_QUEUE_LIMIT);
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    moderation::queue(&conn, status.as_deref(), limit)
+}
+
+fn admin_review_moderation(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let report_id = extract_id_from_path(path, "/admin/moderation/")?;
+    let body: moderation::ReviewRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    moderation::review(&conn, &user_id, &report_id, body)
+}
+
+fn process_moderation(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let conn = get_db_connection()?;
+    moderation::process(&conn)
+}
+
 fn delete_book(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
     let book_id = extract_id_from_path(path, "/books/")?;
@@ -342,7 +822,7 @@ fn list_chapters(req: &Request, path: &str) -> Result<Response, ServiceError> {
     // Verify book ownership
     verify_book_ownership(&conn, &book_id, &user_id)?;
 
-    let query = "SELECT id, title, chapter_number, word_count, status, created_at, updated_at
+    let query = "SELECT id, title, chapter_number, word_count, status, created_at, updated_at, section
                  FROM content.chapters WHERE book_id = $1 ORDER BY chapter_number ASC";
 
     let params = [ParameterValue::Str(book_id.to_string())];
@@ -358,6 +838,7 @@ fn list_chapters(req: &Request, path: &str) -> Result<Response, ServiceError> {
             status: String::decode(&row[4]).unwrap_or_else(|_| "draft".into()),
             created_at: String::decode(&row[5]).unwrap_or_default(),
             updated_at: String::decode(&row[6]).unwrap_or_default(),
+            section: String::decode(&row[7]).unwrap_or_else(|_| "body".into()),
         }
     }).collect();
 
@@ -369,6 +850,7 @@ fn list_chapters(req: &Request, path: &str) -> Result<Response, ServiceError> {
 
 fn create_chapter(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
+    check_write_entitlement(&user_id)?;
     let book_id = extract_id_from_path(path, "/books/")?;
     let body: CreateChapterRequest = parse_json_body(req)?;
     let conn = get_db_connection()?;
@@ -397,6 +879,7 @@ fn create_chapter(req: &Request, path: &str) -> Result<Response, ServiceError> {
 
     // Update book word count
     update_book_word_count(&conn, &book_id)?;
+    goals::record_word_count_change(&conn, &user_id, &book_id, 0, word_count)?;
 
     json_response(201, serde_json::json!({
         "id": chapter_id,
@@ -449,6 +932,7 @@ fn get_chapter(req: &Request, path: &str) -> Result<Response, ServiceError> {
 
 fn update_chapter(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
+    check_write_entitlement(&user_id)?;
     let chapter_id = extract_id_from_path(path, "/chapters/")?;
     let body: UpdateChapterRequest = parse_json_body(req)?;
     let conn = get_db_connection()?;
@@ -456,8 +940,19 @@ fn update_chapter(req: &Request, path: &str) -> Result<Response, ServiceError> {
     // Get book_id for ownership check and word count update
     let book_id = get_chapter_book_id(&conn, &chapter_id, &user_id)?;
 
+    // Keep the version this update replaces
+    let previous = revisions::current_state(&conn, &chapter_id)?;
+    let revision = if body.title.as_ref().is_some_and(|t| *t != previous.title)
+        || body.content.as_ref().is_some_and(|c| *c != previous.content)
+    {
+        revisions::capture(&conn, &chapter_id, &user_id, &previous, "update")?
+    } else {
+        None
+    };
+
     let now = Utc::now();
     let word_count = body.content.as_ref().map(|c| c.split_whitespace().count() as i32);
+    let previous_word_count = word_count.map(|_| previous.word_count);
 
     let query = "UPDATE content.chapters SET 
                  title = COALESCE($3, title),
@@ -470,10 +965,10 @@ fn update_chapter(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let params = [
         ParameterValue::Str(chapter_id.to_string()),
         ParameterValue::Str(user_id.to_string()),
-        ParameterValue::Str(body.title.unwrap_or_default()),
-        ParameterValue::Str(body.content.unwrap_or_default()),
-        ParameterValue::Int32(word_count.unwrap_or(0)),
-        ParameterValue::Str(body.status.unwrap_or_default()),
+        body.title.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        body.content.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        word_count.map(ParameterValue::Int32).unwrap_or(ParameterValue::DbNull),
+        body.status.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
         ParameterValue::Str(now.to_rfc3339()),
     ];
 
@@ -482,15 +977,20 @@ fn update_chapter(req: &Request, path: &str) -> Result<Response, ServiceError> {
 
     // Update book word count
     update_book_word_count(&conn, &book_id)?;
+    if let (Some(old), Some(new)) = (previous_word_count, word_count) {
+        goals::record_word_count_change(&conn, &user_id, &book_id, old, new)?;
+    }
 
     json_response(200, serde_json::json!({
         "message": "Chapter updated successfully",
-        "updated_at": now.to_rfc3339()
+        "updated_at": now.to_rfc3339(),
+        "revision": revision
     }))
 }
 
 fn delete_chapter(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
+    check_write_entitlement(&user_id)?;
     let chapter_id = extract_id_from_path(path, "/chapters/")?;
     let conn = get_db_connection()?;
 
@@ -510,122 +1010,878 @@ fn delete_chapter(req: &Request, path: &str) -> Result<Response, ServiceError> {
 }
 
 //=============================================================================
-// Content Generation
+// Chapter Revisions
 //=============================================================================
 
-fn generate_outline(req: &Request) -> Result<Response, ServiceError> {
+fn list_chapter_revisions(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
-    let body: GenerateOutlineRequest = parse_json_body(req)?;
+    let chapter_id = extract_id_from_path(path, "/chapters/")?;
     let conn = get_db_connection()?;
 
-    verify_book_ownership(&conn, &body.book_id, &user_id)?;
-
-    let job_id = Uuid::new_v4();
-
-    // CREDIT ENFORCEMENT: Check and consume credits before generation
-    let estimated_words = 300; // Typical outline length
-    let credit_cost = credits::enforce_credits_for_generation(
-        &conn,
-        &user_id,
-        &job_id,
-        &body.book_id,
-        "outline",
-        estimated_words,
-    )?;
-
-    // Queue the generation job via RabbitMQ
-    let job = serde_json::json!({
-        "type": "GenerateOutline",
-        "job_id": job_id,
-        "book_id": body.book_id,
-        "prompt": body.prompt,
-        "genre": body.genre,
-        "style": body.style,
-        "chapter_count": body.chapter_count.unwrap_or(10)
-    });
-
-    // In production, this would publish to RabbitMQ
-    // For now, store in a jobs table
-    let query = "INSERT INTO content.generation_jobs (id, book_id, job_type, status, input, created_at)
-                 VALUES ($1, $2, 'outline', 'pending', $3, $4)";
-
-    let params = [
-        ParameterValue::Str(job_id.to_string()),
-        ParameterValue::Str(body.book_id.to_string()),
-        ParameterValue::Str(job.to_string()),
-        ParameterValue::Str(Utc::now().to_rfc3339()),
-    ];
+    get_chapter_book_id(&conn, &chapter_id, &user_id)?;
+    json_response(200, revisions::list_revisions(&conn, &chapter_id)?)
+}
 
-    conn.execute(query, &params)
-        .map_err(|e| ServiceError::Internal(format!("Job creation failed: {}", e)))?;
+fn get_chapter_revision(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let chapter_id = extract_id_from_path(path, "/chapters/")?;
+    let revision_number = parse_revision_number(path)?;
+    let conn = get_db_connection()?;
 
-    json_response(202, serde_json::json!({
-        "job_id": job_id,
-        "status": "pending",
-        "message": "Outline generation queued",
-        "credits_charged": credit_cost,
-        "check_status": format!("/jobs/{}", job_id)
-    }))
+    get_chapter_book_id(&conn, &chapter_id, &user_id)?;
+    json_response(200, revisions::get_revision(&conn, &chapter_id, revision_number)?)
 }
 
-fn generate_chapter_content(req: &Request) -> Result<Response, ServiceError> {
+fn restore_chapter_revision(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
-    let body: GenerateChapterRequest = parse_json_body(req)?;
+    check_write_entitlement(&user_id)?;
+    let chapter_id = extract_id_from_path(path, "/chapters/")?;
+    let revision_number = parse_revision_number(path)?;
     let conn = get_db_connection()?;
 
-    let book_id = get_chapter_book_id(&conn, &body.chapter_id, &user_id)?;
-    let job_id = Uuid::new_v4();
+    let book_id = get_chapter_book_id(&conn, &chapter_id, &user_id)?;
+    let outcome = revisions::restore_revision(&conn, &chapter_id, &user_id, revision_number)?;
 
-    // CREDIT ENFORCEMENT: Check and consume credits before generation
-    let estimated_words = body.target_length.unwrap_or(2500); // Default chapter length
-    let credit_cost = credits::enforce_credits_for_generation(
-        &conn,
-        &user_id,
-        &job_id,
-        &book_id,
-        "chapter",
-        estimated_words,
-    )?;
+    update_book_word_count(&conn, &book_id)?;
+    goals::record_word_count_change(&conn, &user_id, &book_id, outcome.previous_word_count, outcome.word_count)?;
 
-    let job = serde_json::json!({
-        "type": "GenerateChapter",
-        "job_id": job_id,
-        "book_id": book_id,
-        "chapter_id": body.chapter_id,
-        "outline": body.outline,
-        "context": body.context,
-        "style": body.style
-    });
+    json_response(200, outcome)
+}
 
-    let query = "INSERT INTO content.generation_jobs (id, book_id, job_type, status, input, created_at)
-                 VALUES ($1, $2, 'chapter', 'pending', $3, $4)";
+/// Revision number from /chapters/:id/revisions/:n[/restore]
+fn parse_revision_number(path: &str) -> Result<i32, ServiceError> {
+    path.split("/revisions/")
+        .nth(1)
+        .and_then(|rest| rest.split('/').next())
+        .and_then(|n| n.parse::<i32>().ok())
+        .filter(|n| *n > 0)
+        .ok_or_else(|| ServiceError::BadRequest("Invalid revision number".into()))
+}
 
-    let params = [
-        ParameterValue::Str(job_id.to_string()),
-        ParameterValue::Str(book_id.to_string()),
-        ParameterValue::Str(job.to_string()),
-        ParameterValue::Str(Utc::now().to_rfc3339()),
-    ];
+//=============================================================================
+// Public Change Feed
+//=============================================================================
 
-    conn.execute(query, &params)
-        .map_err(|e| ServiceError::Internal(format!("Job creation failed: {}", e)))?;
+/// No X-User-Id: the feed only exposes content that is already public
+fn list_public_changes(req: &Request) -> Result<Response, ServiceError> {
+    let query = req.query();
+    let since = get_query_param(query, "since");
+    let limit = match get_query_param(query, "limit") {
+        Some(value) => value.parse::<i64>()
+            .map_err(|_| ServiceError::BadRequest("limit must be an integer".into()))?,
+        None => changes::DEFAULT_PAGE_SIZE,
+    };
+    let conn = get_db_connection()?;
 
-    json_response(202, serde_json::json!({
-        "job_id": job_id,
-        "status": "pending",
-        "message": "Chapter generation queued",
-        "credits_charged": credit_cost,
-        "estimated_words": estimated_words
-    }))
+    changes::list(&conn, since.as_deref(), limit)
 }
 
-fn enhance_content(req: &Request) -> Result<Response, ServiceError> {
+//=============================================================================
+// Writing Goals
+//=============================================================================
+
+fn set_writing_goal(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
-    let body: EnhanceContentRequest = parse_json_body(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let body: goals::SetGoalRequest = parse_json_body(req)?;
     let conn = get_db_connection()?;
 
-    let book_id = get_chapter_book_id(&conn, &body.chapter_id, &user_id)?;
-    let job_id = Uuid::new_v4();
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    goals::set_goal(&conn, book_id, body)
+}
+
+fn get_goal_progress(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    goals::get_progress(&conn, user_id, book_id)
+}
+
+//=============================================================================
+// Series
+//=============================================================================
+
+fn create_series(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: series::CreateSeriesRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    series::create(&conn, &user_id, body)
+}
+
+fn get_series(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let series_id = extract_id_from_path(path, "/series/")?;
+    let conn = get_db_connection()?;
+
+    series::get(&conn, &series_id, &user_id)
+}
+
+fn set_book_series(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let body: series::SetBookSeriesRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    series::set_book_series(&conn, &book_id, &user_id, body)
+}
+
+//=============================================================================
+// Plot Threads
+//=============================================================================
+
+fn create_plot_thread(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let body: threads::CreateThreadRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    threads::create(&conn, &book_id, body)
+}
+
+fn get_plot_thread_graph(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    threads::graph(&conn, &book_id)
+}
+
+fn set_chapter_threads(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let chapter_id = extract_id_from_path(path, "/chapters/")?;
+    let body: threads::SetChapterThreadsRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    let book_id = get_chapter_book_id(&conn, &chapter_id, &user_id)?;
+    threads::set_chapter_threads(&conn, &book_id, &chapter_id, body)
+}
+
+//=============================================================================
+// World Lore
+//=============================================================================
+
+fn create_lore_entry(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let body: lore::CreateEntryRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    lore::create(&conn, &book_id, body)
+}
+
+fn list_lore_entries(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let kind = get_query_param(req.query(), "kind");
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    lore::list(&conn, &book_id, kind)
+}
+
+fn get_lore_entry(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let entry_id = lore_entry_id(path)?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    lore::get(&conn, &book_id, &entry_id)
+}
+
+fn update_lore_entry(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let entry_id = lore_entry_id(path)?;
+    let body: lore::UpdateEntryRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    lore::update(&conn, &book_id, &entry_id, body)
+}
+
+fn delete_lore_entry(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let entry_id = lore_entry_id(path)?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    lore::delete(&conn, &book_id, &entry_id)
+}
+
+fn set_chapter_lore(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let chapter_id = extract_id_from_path(path, "/chapters/")?;
+    let body: lore::SetChapterLoreRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    let book_id = get_chapter_book_id(&conn, &chapter_id, &user_id)?;
+    lore::set_chapter_lore(&conn, &book_id, &chapter_id, body)
+}
+
+fn lore_entry_id(path: &str) -> Result<Uuid, ServiceError> {
+    path.split("/lore/").nth(1)
+        .and_then(|id| Uuid::parse_str(id.trim_end_matches('/')).ok())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid lore entry ID".into()))
+}
+
+//=============================================================================
+// Co-writing Personas
+//=============================================================================
+
+fn create_persona(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let body: personas::CreatePersonaRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    personas::create(&conn, &book_id, body)
+}
+
+fn list_personas(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    personas::list(&conn, &book_id)
+}
+
+fn get_persona(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let persona_id = persona_id(path)?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    personas::get(&conn, &book_id, &persona_id)
+}
+
+fn update_persona(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let persona_id = persona_id(path)?;
+    let body: personas::UpdatePersonaRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    personas::update(&conn, &book_id, &persona_id, body)
+}
+
+fn delete_persona(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let persona_id = persona_id(path)?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    personas::delete(&conn, &book_id, &persona_id)
+}
+
+fn persona_id(path: &str) -> Result<Uuid, ServiceError> {
+    path.split("/personas/").nth(1)
+        .and_then(|id| Uuid::parse_str(id.trim_end_matches('/')).ok())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid persona ID".into()))
+}
+
+//=============================================================================
+// Outline
+//=============================================================================
+
+fn get_book_outline(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    outline::get(&conn, &book_id)
+}
+
+fn save_book_outline(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let body: outline::SaveOutlineRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    outline::save(&conn, &user_id, &book_id, body)
+}
+
+//=============================================================================
+// Book Templates
+//=============================================================================
+
+fn list_templates(req: &Request) -> Result<Response, ServiceError> {
+    get_user_id(req)?;
+    let conn = get_db_connection()?;
+    templates::list(&conn, false)
+}
+
+fn create_book_from_template(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: templates::FromTemplateRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    check_book_entitlement(&user_id)?;
+    templates::instantiate(&conn, &user_id, body)
+}
+
+fn admin_list_templates(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    templates::list(&conn, true)
+}
+
+fn admin_create_template(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: templates::CreateTemplateRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    templates::create(&conn, &user_id, body)
+}
+
+fn admin_update_template(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let template_id = extract_template_id(path)?;
+    let body: templates::UpdateTemplateRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    templates::update(&conn, template_id, body)
+}
+
+fn admin_delete_template(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let template_id = extract_template_id(path)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    templates::delete(&conn, template_id)
+}
+
+/// Template ids are slugs rather than UUIDs
+fn extract_template_id(path: &str) -> Result<&str, ServiceError> {
+    path.strip_prefix("/admin/templates/")
+        .filter(|id| !id.is_empty() && !id.contains('/'))
+        .ok_or_else(|| ServiceError::BadRequest("Invalid path".into()))
+}
+
+//=============================================================================
+// Writing Events
+//=============================================================================
+
+fn create_writing_event(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: events::CreateEventRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    events::create(&conn, &user_id, body)
+}
+
+fn list_writing_events(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+
+    events::list(&conn, &user_id)
+}
+
+fn get_writing_event(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let event_id = extract_id_from_path(path, "/events/")?;
+    let conn = get_db_connection()?;
+
+    events::get(&conn, &event_id, &user_id)
+}
+
+fn get_event_leaderboard(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let event_id = extract_id_from_path(path, "/events/")?;
+    let conn = get_db_connection()?;
+
+    events::leaderboard(&conn, &event_id, &user_id)
+}
+
+fn add_event_participants(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let event_id = extract_id_from_path(path, "/events/")?;
+    let body: events::AddParticipantsRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    events::add_participants(&conn, &event_id, &user_id, body)
+}
+
+fn remove_event_participant(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let event_id = extract_id_from_path(path, "/events/")?;
+    let target_id = path.rsplit('/').next()
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid participant ID".into()))?;
+    let conn = get_db_connection()?;
+
+    events::remove_participant(&conn, &event_id, &user_id, &target_id)
+}
+
+//=============================================================================
+// Beta-Reader Feedback
+//=============================================================================
+
+fn create_feedback_token(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let body: feedback::CreateTokenRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    feedback::create_token(&conn, &book_id, &user_id, body)
+}
+
+fn list_feedback_tokens(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    feedback::list_tokens(&conn, &book_id)
+}
+
+fn revoke_feedback_token(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let token_id = path.rsplit('/').next()
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid token ID".into()))?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    feedback::revoke_token(&conn, &book_id, &token_id)
+}
+
+fn get_feedback_summary(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    feedback::summary(&conn, &book_id)
+}
+
+/// Gated by the feedback token rather than the caller's identity; a signed-in
+/// reader's user ID is recorded when the gateway supplies one
+fn submit_chapter_feedback(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let chapter_id = extract_id_from_path(path, "/chapters/")?;
+    let token = req.header("X-Feedback-Token")
+        .and_then(|h| h.as_str())
+        .map(|t| t.to_string())
+        .or_else(|| get_query_param(req.query(), "token"))
+        .filter(|t| !t.is_empty() && t.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| ServiceError::Unauthorized("Missing or malformed feedback token".into()))?;
+    let body: feedback::SubmitFeedbackRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    feedback::submit(&conn, &token, &chapter_id, get_user_id(req).ok(), body)
+}
+
+//=============================================================================
+// Analysis
+//=============================================================================
+
+fn analyze_continuity(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let body: analysis::AnalyzeContinuityRequest = if req.body().is_empty() {
+        Default::default()
+    } else {
+        parse_json_body(req)?
+    };
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    ensure_ai_enabled(&conn, &book_id)?;
+
+    // Only chapters with content are worth analyzing
+    let query = "SELECT id, word_count FROM content.chapters
+                 WHERE book_id = $1 AND COALESCE(word_count, 0) > 0";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let chapters: Vec<(Uuid, i32)> = rows.rows.iter()
+        .filter_map(|row| {
+            let id = Uuid::parse_str(&String::decode(&row[0]).ok()?).ok()?;
+            Some((id, i32::decode(&row[1]).unwrap_or(0)))
+        })
+        .filter(|(id, _)| body.chapter_ids.as_ref().map(|ids| ids.contains(id)).unwrap_or(true))
+        .collect();
+
+    if chapters.is_empty() {
+        return Err(ServiceError::BadRequest("No chapters with content to analyze".into()));
+    }
+
+    let job_id = Uuid::new_v4();
+
+    // CREDIT ENFORCEMENT: Charge by the amount of text analyzed
+    let estimated_words = chapters.iter().map(|(_, words)| *words).sum();
+    let credit_cost = credits::enforce_credits_for_generation(
+        &conn,
+        &user_id,
+        &job_id,
+        &book_id,
+        analysis::REPORT_CONTINUITY,
+        estimated_words,
+    )?;
+
+    let report_id = analysis::create_report(&conn, &book_id, &job_id, analysis::REPORT_CONTINUITY)?;
+
+    let job = serde_json::json!({
+        "type": "CheckContinuity",
+        "job_id": job_id,
+        "book_id": book_id,
+        "report_id": report_id,
+        "chapter_ids": chapters.iter().map(|(id, _)| id).collect::<Vec<_>>()
+    });
+
+    let query = "INSERT INTO content.generation_jobs (id, book_id, job_type, status, input, created_at)
+                 VALUES ($1, $2, 'continuity', 'pending', $3, $4)";
+
+    let params = [
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(job.to_string()),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ];
+
+    conn.execute(query, &params)
+        .map_err(|e| ServiceError::Internal(format!("Job creation failed: {}", e)))?;
+
+    json_response(202, serde_json::json!({
+        "job_id": job_id,
+        "report_id": report_id,
+        "status": "pending",
+        "message": "Continuity check queued",
+        "chapters": chapters.len(),
+        "credits_charged": credit_cost,
+        "check_status": format!("/books/{}/reports", book_id)
+    }))
+}
+
+fn list_analysis_reports(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    analysis::list_reports(&conn, &book_id)
+}
+
+fn get_book_analysis(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    style::book_analysis(&conn, &book_id)
+}
+
+fn get_chapter_analysis(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let chapter_id = extract_id_from_path(path, "/chapters/")?;
+    let conn = get_db_connection()?;
+
+    get_chapter_book_id(&conn, &chapter_id, &user_id)?;
+    style::chapter_analysis(&conn, &chapter_id)
+}
+
+fn analyze_related_chapters(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    ensure_ai_enabled(&conn, &book_id)?;
+    related::ensure_non_fiction(&conn, &book_id)?;
+
+    let query = "SELECT COUNT(*), COALESCE(SUM(word_count), 0) FROM content.chapters
+                 WHERE book_id = $1 AND COALESCE(word_count, 0) > 0";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let (chapter_count, total_words) = rows.rows.first()
+        .map(|row| (i64::decode(&row[0]).unwrap_or(0), i64::decode(&row[1]).unwrap_or(0)))
+        .unwrap_or((0, 0));
+
+    if chapter_count < 2 {
+        return Err(ServiceError::BadRequest("At least two chapters with content are needed".into()));
+    }
+
+    let job_id = Uuid::new_v4();
+
+    // CREDIT ENFORCEMENT: Charge by the amount of text embedded
+    let credit_cost = credits::enforce_credits_for_generation(
+        &conn,
+        &user_id,
+        &job_id,
+        &book_id,
+        related::ANALYSIS_RELATED_CHAPTERS,
+        total_words.min(i32::MAX as i64) as i32,
+    )?;
+
+    let job = serde_json::json!({
+        "type": "FindRelatedChapters",
+        "job_id": job_id,
+        "book_id": book_id
+    });
+
+    let query = "INSERT INTO content.generation_jobs (id, book_id, job_type, status, input, created_at)
+                 VALUES ($1, $2, 'related_chapters', 'pending', $3, $4)";
+
+    let params = [
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(job.to_string()),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ];
+
+    conn.execute(query, &params)
+        .map_err(|e| ServiceError::Internal(format!("Job creation failed: {}", e)))?;
+
+    json_response(202, serde_json::json!({
+        "job_id": job_id,
+        "status": "pending",
+        "message": "Related chapter analysis queued",
+        "chapters": chapter_count,
+        "credits_charged": credit_cost,
+        "check_status": format!("/jobs/{}", job_id)
+    }))
+}
+
+fn get_related_chapters(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let chapter_id = extract_id_from_path(path, "/chapters/")?;
+    let conn = get_db_connection()?;
+
+    get_chapter_book_id(&conn, &chapter_id, &user_id)?;
+    related::list_related(&conn, &chapter_id)
+}
+
+fn set_related_chapters(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let chapter_id = extract_id_from_path(path, "/chapters/")?;
+    let body: related::SetRelatedChaptersRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    let book_id = get_chapter_book_id(&conn, &chapter_id, &user_id)?;
+    related::set_related(&conn, &book_id, &chapter_id, body)
+}
+
+//=============================================================================
+// Snapshots & Exports
+//=============================================================================
+
+fn create_snapshot(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    snapshots::snapshot_now(&conn, &book_id, &user_id)
+}
+
+fn list_snapshots(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    snapshots::list_snapshots(&conn, &book_id)
+}
+
+fn compare_snapshot(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let snapshot_id = extract_id_from_path(path, "/snapshots/")?;
+    let conn = get_db_connection()?;
+
+    snapshots::snapshot_book_id(&conn, &snapshot_id, &user_id)?;
+    snapshots::compare(&conn, &snapshot_id)
+}
+
+fn create_export(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let body: snapshots::CreateExportRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    snapshots::create_export(&conn, &book_id, &user_id, body)
+}
+
+fn list_exports(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &book_id, &user_id)?;
+    snapshots::list_exports(&conn, &book_id)
+}
+
+fn download_export(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let export_id = extract_id_from_path(path, "/exports/")?;
+    let conn = get_db_connection()?;
+
+    snapshots::export_book_id(&conn, &export_id, &user_id)?;
+    snapshots::download(&conn, &export_id)
+}
+
+fn reexport(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let export_id = extract_id_from_path(path, "/exports/")?;
+    let conn = get_db_connection()?;
+
+    snapshots::export_book_id(&conn, &export_id, &user_id)?;
+    snapshots::reexport(&conn, &export_id, &user_id)
+}
+
+//=============================================================================
+// Content Generation
+//=============================================================================
+
+fn generate_outline(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    check_write_entitlement(&user_id)?;
+    let body: GenerateOutlineRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &body.book_id, &user_id)?;
+    ensure_ai_enabled(&conn, &body.book_id)?;
+
+    let job_id = Uuid::new_v4();
+
+    // CREDIT ENFORCEMENT: Check and consume credits before generation
+    let estimated_words = 300; // Typical outline length
+    let credit_cost = credits::enforce_credits_for_generation(
+        &conn,
+        &user_id,
+        &job_id,
+        &body.book_id,
+        "outline",
+        estimated_words,
+    )?;
+
+    // Queue the generation job via RabbitMQ
+    let job = serde_json::json!({
+        "type": "GenerateOutline",
+        "job_id": job_id,
+        "book_id": body.book_id,
+        "prompt": body.prompt,
+        "genre": body.genre,
+        "style": body.style,
+        "chapter_count": body.chapter_count.unwrap_or(10),
+        "series": series::generation_context(&conn, &body.book_id)?,
+        "plot_threads": threads::generation_context(&conn, &body.book_id, None)?,
+        "lore": lore::generation_context(&conn, &body.book_id, None, &body.prompt)?,
+        "persona": personas::generation_context(&conn, &body.book_id)?
+    });
+
+    // In production, this would publish to RabbitMQ
+    // For now, store in a jobs table
+    let query = "INSERT INTO content.generation_jobs (id, book_id, job_type, status, input, created_at)
+                 VALUES ($1, $2, 'outline', 'pending', $3, $4)";
+
+    let params = [
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(body.book_id.to_string()),
+        ParameterValue::Str(job.to_string()),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ];
+
+    conn.execute(query, &params)
+        .map_err(|e| ServiceError::Internal(format!("Job creation failed: {}", e)))?;
+
+    json_response(202, serde_json::json!({
+        "job_id": job_id,
+        "status": "pending",
+        "message": "Outline generation queued",
+        "credits_charged": credit_cost,
+        "check_status": format!("/jobs/{}", job_id)
+    }))
+}
+
+fn generate_chapter_content(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    check_write_entitlement(&user_id)?;
+    let body: GenerateChapterRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    let book_id = get_chapter_book_id(&conn, &body.chapter_id, &user_id)?;
+    ensure_ai_enabled(&conn, &book_id)?;
+    let job_id = Uuid::new_v4();
+
+    // CREDIT ENFORCEMENT: Check and consume credits before generation
+    let estimated_words = body.target_length.unwrap_or(2500); // Default chapter length
+    let credit_cost = credits::enforce_credits_for_generation(
+        &conn,
+        &user_id,
+        &job_id,
+        &book_id,
+        "chapter",
+        estimated_words,
+    )?;
+
+    let job = serde_json::json!({
+        "type": "GenerateChapter",
+        "job_id": job_id,
+        "book_id": book_id,
+        "chapter_id": body.chapter_id,
+        "outline": body.outline,
+        "context": body.context,
+        "style": body.style,
+        "series": series::generation_context(&conn, &book_id)?,
+        "plot_threads": threads::generation_context(&conn, &book_id, Some(&body.chapter_id))?,
+        "lore": lore::generation_context(&conn, &book_id, Some(&body.chapter_id), body.outline.as_deref().unwrap_or(""))?,
+        "persona": personas::generation_context(&conn, &book_id)?
+    });
+
+    let query = "INSERT INTO content.generation_jobs (id, book_id, job_type, status, input, created_at)
+                 VALUES ($1, $2, 'chapter', 'pending', $3, $4)";
+
+    let params = [
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(job.to_string()),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ];
+
+    conn.execute(query, &params)
+        .map_err(|e| ServiceError::Internal(format!("Job creation failed: {}", e)))?;
+
+    json_response(202, serde_json::json!({
+        "job_id": job_id,
+        "status": "pending",
+        "message": "Chapter generation queued",
+        "credits_charged": credit_cost,
+        "estimated_words": estimated_words
+    }))
+}
+
+fn enhance_content(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    check_write_entitlement(&user_id)?;
+    let body: EnhanceContentRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    let book_id = get_chapter_book_id(&conn, &body.chapter_id, &user_id)?;
+    ensure_ai_enabled(&conn, &book_id)?;
+    let job_id = Uuid::new_v4();
 
     // CREDIT ENFORCEMENT: Check and consume credits before enhancement
     let content_word_count = body.content.split_whitespace().count() as i32;
@@ -645,7 +1901,8 @@ fn enhance_content(req: &Request) -> Result<Response, ServiceError> {
         "chapter_id": body.chapter_id,
         "content": body.content,
         "enhancement_type": body.enhancement_type,
-        "instructions": body.instructions
+        "instructions": body.instructions,
+        "persona": personas::generation_context(&conn, &book_id)?
     });
 
     let query = "INSERT INTO content.generation_jobs (id, book_id, job_type, status, input, created_at)
@@ -669,6 +1926,111 @@ fn enhance_content(req: &Request) -> Result<Response, ServiceError> {
     }))
 }
 
+fn generate_book(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    check_write_entitlement(&user_id)?;
+    let body: book_generation::GenerateBookRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    verify_book_ownership(&conn, &body.book_id, &user_id)?;
+    ensure_ai_enabled(&conn, &body.book_id)?;
+    book_generation::start(&conn, &user_id, body)
+}
+
+fn advance_book_generations(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let conn = get_db_connection()?;
+    book_generation::advance_all(&conn)
+}
+
+//=============================================================================
+// Jobs
+//=============================================================================
+
+fn get_job(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let job_id = extract_id_from_path(path, "/jobs/")?;
+    let conn = get_db_connection()?;
+
+    let query = "SELECT j.book_id, j.job_type, j.status, j.output::text, j.error, j.created_at, j.started_at,
+                        j.completed_at, COALESCE(j.credits_cost, 0), j.parent_job_id, j.phase
+                 FROM content.generation_jobs j
+                 JOIN content.books b ON b.id = j.book_id
+                 WHERE j.id = $1 AND b.author_id = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Job not found".into()))?;
+
+    let job_type = String::decode(&row[1]).unwrap_or_default();
+    let mut job = serde_json::json!({
+        "id": job_id,
+        "book_id": String::decode(&row[0]).ok(),
+        "job_type": job_type,
+        "status": String::decode(&row[2]).unwrap_or_default(),
+        "output": String::decode(&row[3]).ok().and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok()),
+        "error": String::decode(&row[4]).ok(),
+        "created_at": String::decode(&row[5]).ok(),
+        "started_at": String::decode(&row[6]).ok(),
+        "completed_at": String::decode(&row[7]).ok(),
+        "credits_cost": i32::decode(&row[8]).unwrap_or(0),
+        "parent_job_id": String::decode(&row[9]).ok(),
+        "phase": String::decode(&row[10]).ok()
+    });
+
+    if job_type == book_generation::JOB_TYPE {
+        let phases = book_generation::phases(&conn, &job_id)?;
+        let total: i64 = phases.iter().map(|p| p.total as i64).sum();
+        let completed: i64 = phases.iter().map(|p| p.completed).sum();
+        job["current_phase"] = serde_json::json!(phases.iter()
+            .find(|p| p.status != "completed" && p.status != "skipped")
+            .map(|p| p.phase.clone()));
+        job["progress"] = serde_json::json!({ "completed_jobs": completed, "queued_jobs": total });
+        job["phases"] = serde_json::json!(phases);
+    }
+
+    json_response(200, job)
+}
+
+fn resume_job(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    check_write_entitlement(&user_id)?;
+    let job_id = extract_id_from_path(path, "/jobs/")?;
+    let conn = get_db_connection()?;
+
+    let book_id = get_book_job_book_id(&conn, &job_id, &user_id)?;
+    ensure_ai_enabled(&conn, &book_id)?;
+    book_generation::resume(&conn, &job_id)
+}
+
+fn cancel_job(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let job_id = extract_id_from_path(path, "/jobs/")?;
+    let conn = get_db_connection()?;
+
+    get_book_job_book_id(&conn, &job_id, &user_id)?;
+    book_generation::cancel(&conn, &job_id)
+}
+
+/// Book of a book-generation job the user owns; only those can be resumed or cancelled
+fn get_book_job_book_id(conn: &Connection, job_id: &Uuid, user_id: &Uuid) -> Result<Uuid, ServiceError> {
+    let query = "SELECT j.book_id FROM content.generation_jobs j
+                 JOIN content.books b ON b.id = j.book_id
+                 WHERE j.id = $1 AND b.author_id = $2 AND j.job_type = $3";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(book_generation::JOB_TYPE.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    rows.rows.first()
+        .and_then(|row| String::decode(&row[0]).ok())
+        .and_then(|id| Uuid::parse_str(&id).ok())
+        .ok_or_else(|| ServiceError::NotFound("Book generation job not found".into()))
+}
+
 //=============================================================================
 // Helper Functions
 //=============================================================================
@@ -689,6 +2051,40 @@ fn verify_book_ownership(conn: &Connection, book_id: &Uuid, user_id: &Uuid) -> R
     Ok(())
 }
 
+/// Reject AI generation and analysis for books the author opted out of AI
+fn ensure_ai_enabled(conn: &Connection, book_id: &Uuid) -> Result<(), ServiceError> {
+    let query = "SELECT COALESCE(ai_disabled, false) FROM content.books WHERE id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let disabled = rows.rows.first().map(|row| bool::decode(&row[0]).unwrap_or(false)).unwrap_or(false);
+    if disabled {
+        return Err(ServiceError::FeatureDisabled(
+            "AI features are disabled for this book. Set ai_disabled to false in the book settings to use them.".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Ask the discovery service to drop the book and its chapters from search
+fn remove_from_search_index(book_id: &Uuid) -> Result<(), ServiceError> {
+    let discovery_url = variables::get("discovery_service_url")
+        .unwrap_or_else(|_| "http://discovery-service:3107".to_string());
+
+    let request = outbound_http::Request::builder()
+        .method(outbound_http::Method::Delete)
+        .uri(format!("{}/index/book/{}", discovery_url, book_id))
+        .build();
+
+    let response = outbound_http::send(request)
+        .map_err(|e| ServiceError::Internal(format!("Index removal failed: {}", e)))?;
+
+    if response.status().as_u16() >= 400 {
+        return Err(ServiceError::Internal(format!("Index removal failed with status {}", response.status().as_u16())));
+    }
+    Ok(())
+}
+
 fn get_chapter_book_id(conn: &Connection, chapter_id: &Uuid, user_id: &Uuid) -> Result<Uuid, ServiceError> {
     let query = "SELECT c.book_id FROM content.chapters c
                  JOIN content.books b ON c.book_id = b.id
@@ -737,6 +2133,17 @@ fn extract_id_from_path(path: &str, prefix: &str) -> Result<Uuid, ServiceError>
         .map_err(|_| ServiceError::BadRequest("Invalid UUID".into()))
 }
 
+fn get_query_param(query: &str, key: &str) -> Option<String> {
+    query.split('&')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let k = parts.next()?;
+            let v = parts.next()?;
+            if k == key { Some(v.to_string()) } else { None }
+        })
+        .next()
+}
+
 fn json_response<T: Serialize>(status: u16, body: T) -> Result<Response, ServiceError> {
     let json = serde_json::to_string(&body)
         .map_err(|e| ServiceError::Internal(format!("JSON error: {}", e)))?;
@@ -0,0 +1,452 @@
+//! World lore
+//!
+//! A wiki of the places, items and factions of a book's world. Entries belong
+//! to one book, but books of the same series share a world: each sees the
+//! others' entries when listing lore, tagging chapters and generating, while
+//! only the owning book edits them. Chapters cross-reference the entries they
+//! feature, and an entry lists the chapters that reference it.
+//!
+//! Generation jobs carry the lore relevant to what is being written: entries
+//! referenced by the chapter, then entries whose name or an alias appears as
+//! a whole word in the outline or prompt. Matching is plain case-insensitive
+//! keyword search, nothing smarter.
+
+use crate::common::{self, clearable_text, optional_text};
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+pub const KINDS: [&str; 3] = ["place", "item", "faction"];
+
+const MAX_ENTRIES_PER_BOOK: i64 = 500;
+const MAX_REFERENCES_PER_CHAPTER: usize = 100;
+const MAX_NAME_CHARS: usize = 255;
+const MAX_SUMMARY_CHARS: usize = 1000;
+const MAX_DETAILS_CHARS: usize = 20000;
+const MAX_ALIASES: usize = 10;
+const MAX_ALIAS_CHARS: usize = 100;
+const MAX_NOTE_CHARS: usize = 1000;
+
+/// Entries carried by one generation job at most, referenced ones first
+const MAX_CONTEXT_ENTRIES: usize = 20;
+
+/// Per-entry summary cap in generation context, in characters
+const MAX_CONTEXT_SUMMARY_CHARS: usize = 500;
+
+/// Entries of the book and of every other book in its series
+const WORLD_FILTER: &str = "(e.book_id = $1 OR e.book_id IN (
+                                SELECT s.id FROM content.books s
+                                WHERE s.series_id = (SELECT series_id FROM content.books WHERE id = $1)))";
+
+const ENTRY_COLUMNS: &str = "e.id, e.book_id, e.name, e.kind, e.summary, e.details, e.aliases::text,
+                             e.created_at::text, e.updated_at::text";
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct CreateEntryRequest {
+    pub name: String,
+    pub kind: String,
+    /// Short description, the part generation prompts carry
+    pub summary: Option<String>,
+    pub details: Option<String>,
+    /// Other names the entry goes by, matched like the name
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateEntryRequest {
+    pub name: Option<String>,
+    pub kind: Option<String>,
+    /// An empty string clears the field
+    pub summary: Option<String>,
+    pub details: Option<String>,
+    pub aliases: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetChapterLoreRequest {
+    /// Replaces every lore reference of the chapter
+    pub entries: Vec<LoreReference>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoreReference {
+    pub entry_id: Uuid,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoreEntry {
+    pub id: Uuid,
+    /// The book that owns the entry; another book of the series for shared lore
+    pub book_id: Uuid,
+    pub name: String,
+    pub kind: String,
+    pub summary: Option<String>,
+    pub details: Option<String>,
+    pub aliases: Vec<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /books/:id/lore - Add a lore entry to the book's world
+pub fn create(conn: &Connection, book_id: &Uuid, body: CreateEntryRequest) -> Result<Response, ServiceError> {
+    let name = common::required_text(&body.name, "name", MAX_NAME_CHARS)?;
+    validate_kind(&body.kind)?;
+    let summary = optional_text(body.summary, "summary", MAX_SUMMARY_CHARS)?;
+    let details = optional_text(body.details, "details", MAX_DETAILS_CHARS)?;
+    let aliases = validate_aliases(body.aliases)?;
+
+    let query = "SELECT COUNT(*) FROM content.lore_entries WHERE book_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0) >= MAX_ENTRIES_PER_BOOK {
+        return Err(ServiceError::BadRequest(format!("A book can have at most {} lore entries", MAX_ENTRIES_PER_BOOK)));
+    }
+    common::ensure_unique_name(conn, "content.lore_entries", "lore entry", book_id, &name, None)?;
+
+    let id = Uuid::new_v4();
+    let insert = "INSERT INTO content.lore_entries (id, book_id, name, kind, summary, details, aliases, created_at, updated_at)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7::jsonb, NOW(), NOW())";
+    conn.execute(insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(name),
+        ParameterValue::Str(body.kind),
+        summary.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        details.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(serde_json::to_string(&aliases).unwrap_or_else(|_| "[]".into())),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    crate::json_response(201, load_entry(conn, book_id, &id)?)
+}
+
+/// GET /books/:id/lore - The book's world, shared series entries included;
+/// `kind` narrows it to one kind
+pub fn list(conn: &Connection, book_id: &Uuid, kind: Option<String>) -> Result<Response, ServiceError> {
+    if let Some(kind) = &kind {
+        validate_kind(kind)?;
+    }
+
+    let query = format!(
+        "SELECT {}, (SELECT COUNT(*) FROM content.lore_references r WHERE r.entry_id = e.id)
+         FROM content.lore_entries e
+         WHERE {} AND ($2::text IS NULL OR e.kind = $2)
+         ORDER BY e.kind, lower(e.name)",
+        ENTRY_COLUMNS, WORLD_FILTER
+    );
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(book_id.to_string()),
+        kind.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let entries: Vec<serde_json::Value> = rows.rows.iter().map(|row| {
+        let entry = entry_from_row(row);
+        let shared = entry.book_id != *book_id;
+        let mut value = serde_json::to_value(entry).unwrap_or_default();
+        value["shared"] = serde_json::json!(shared);
+        value["chapter_count"] = serde_json::json!(i64::decode(&row[9]).unwrap_or(0));
+        value
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "book_id": book_id,
+        "entries": entries
+    }))
+}
+
+/// GET /books/:id/lore/:entry_id - An entry with the chapters referencing it
+pub fn get(conn: &Connection, book_id: &Uuid, entry_id: &Uuid) -> Result<Response, ServiceError> {
+    let entry = load_entry(conn, book_id, entry_id)?;
+
+    let query = "SELECT c.id, c.book_id, c.chapter_number, c.title, r.note
+                 FROM content.lore_references r
+                 JOIN content.chapters c ON c.id = r.chapter_id
+                 WHERE r.entry_id = $1
+                 ORDER BY c.book_id = $2 DESC, c.chapter_number";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(entry_id.to_string()),
+        ParameterValue::Str(book_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let chapters: Vec<serde_json::Value> = rows.rows.iter().map(|row| serde_json::json!({
+        "chapter_id": String::decode(&row[0]).unwrap_or_default(),
+        "book_id": String::decode(&row[1]).unwrap_or_default(),
+        "chapter_number": i32::decode(&row[2]).unwrap_or(0),
+        "title": String::decode(&row[3]).unwrap_or_default(),
+        "note": String::decode(&row[4]).ok()
+    })).collect();
+
+    let shared = entry.book_id != *book_id;
+    let mut value = serde_json::to_value(entry).unwrap_or_default();
+    value["shared"] = serde_json::json!(shared);
+    value["chapters"] = serde_json::json!(chapters);
+    crate::json_response(200, value)
+}
+
+/// PUT /books/:id/lore/:entry_id - Edit an entry the book owns
+pub fn update(conn: &Connection, book_id: &Uuid, entry_id: &Uuid, body: UpdateEntryRequest) -> Result<Response, ServiceError> {
+    ensure_owned(conn, book_id, entry_id)?;
+
+    let name = body.name.as_deref().map(|name| common::required_text(name, "name", MAX_NAME_CHARS)).transpose()?;
+    if let Some(name) = &name {
+        common::ensure_unique_name(conn, "content.lore_entries", "lore entry", book_id, name, Some(entry_id))?;
+    }
+    if let Some(kind) = &body.kind {
+        validate_kind(kind)?;
+    }
+    let aliases = body.aliases.map(validate_aliases).transpose()?;
+
+    let mut updates = vec!["updated_at = NOW()".to_string()];
+    let mut params: Vec<ParameterValue> = vec![ParameterValue::Str(entry_id.to_string())];
+
+    let fields = [
+        ("name", name.map(ParameterValue::Str), ""),
+        ("kind", body.kind.map(ParameterValue::Str), ""),
+        ("summary", clearable_text(body.summary, "summary", MAX_SUMMARY_CHARS)?, ""),
+        ("details", clearable_text(body.details, "details", MAX_DETAILS_CHARS)?, ""),
+        ("aliases", aliases.map(|a| ParameterValue::Str(serde_json::to_string(&a).unwrap_or_else(|_| "[]".into()))), "::jsonb"),
+    ];
+    for (column, value, cast) in fields {
+        if let Some(value) = value {
+            params.push(value);
+            updates.push(format!("{} = ${}{}", column, params.len(), cast));
+        }
+    }
+
+    let query = format!("UPDATE content.lore_entries SET {} WHERE id = $1", updates.join(", "));
+    conn.execute(&query, &params)
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    crate::json_response(200, load_entry(conn, book_id, entry_id)?)
+}
+
+/// DELETE /books/:id/lore/:entry_id - Remove an entry the book owns, and its
+/// chapter references
+pub fn delete(conn: &Connection, book_id: &Uuid, entry_id: &Uuid) -> Result<Response, ServiceError> {
+    ensure_owned(conn, book_id, entry_id)?;
+
+    conn.execute(
+        "DELETE FROM content.lore_entries WHERE id = $1",
+        &[ParameterValue::Str(entry_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    crate::json_response(200, serde_json::json!({
+        "id": entry_id,
+        "deleted": true
+    }))
+}
+
+/// PUT /chapters/:id/lore - Replace the lore entries a chapter references
+pub fn set_chapter_lore(
+    conn: &Connection,
+    book_id: &Uuid,
+    chapter_id: &Uuid,
+    body: SetChapterLoreRequest,
+) -> Result<Response, ServiceError> {
+    if body.entries.len() > MAX_REFERENCES_PER_CHAPTER {
+        return Err(ServiceError::BadRequest(format!(
+            "At most {} lore references per chapter", MAX_REFERENCES_PER_CHAPTER
+        )));
+    }
+    let mut references: Vec<LoreReference> = Vec::new();
+    for reference in body.entries {
+        if reference.note.as_ref().is_some_and(|n| n.chars().count() > MAX_NOTE_CHARS) {
+            return Err(ServiceError::BadRequest(format!("note must be at most {} characters", MAX_NOTE_CHARS)));
+        }
+        if references.iter().any(|r| r.entry_id == reference.entry_id) {
+            return Err(ServiceError::BadRequest("Each lore entry can be referenced once per chapter".into()));
+        }
+        references.push(reference);
+    }
+
+    if !references.is_empty() {
+        let query = format!(
+            "SELECT COUNT(*) FROM content.lore_entries e
+             WHERE {} AND e.id::text = ANY(string_to_array($2, ','))",
+            WORLD_FILTER
+        );
+        let rows = conn.query(&query, &[
+            ParameterValue::Str(book_id.to_string()),
+            ParameterValue::Str(references.iter().map(|r| r.entry_id.to_string()).collect::<Vec<_>>().join(",")),
+        ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+        let found = rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0);
+        if found != references.len() as i64 {
+            return Err(ServiceError::BadRequest("Lore entries must belong to the chapter's book or its series".into()));
+        }
+    }
+
+    let delete = "DELETE FROM content.lore_references WHERE chapter_id = $1";
+    conn.execute(delete, &[ParameterValue::Str(chapter_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    let insert = "INSERT INTO content.lore_references (entry_id, chapter_id, note, created_at)
+                  VALUES ($1, $2, $3, NOW())";
+    for reference in &references {
+        conn.execute(insert, &[
+            ParameterValue::Str(reference.entry_id.to_string()),
+            ParameterValue::Str(chapter_id.to_string()),
+            reference.note.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "chapter_id": chapter_id,
+        "entries": references
+    }))
+}
+
+//=============================================================================
+// Generation Context
+//=============================================================================
+
+/// Lore for a generation job: entries the chapter references, then entries
+/// named in `text` or in the chapter's title and outline; `None` when
+/// nothing is relevant
+pub fn generation_context(
+    conn: &Connection,
+    book_id: &Uuid,
+    chapter_id: Option<&Uuid>,
+    text: &str,
+) -> Result<Option<serde_json::Value>, ServiceError> {
+    let query = format!("SELECT {} FROM content.lore_entries e WHERE {} ORDER BY lower(e.name)", ENTRY_COLUMNS, WORLD_FILTER);
+    let rows = conn.query(&query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let entries: Vec<LoreEntry> = rows.rows.iter().map(|row| entry_from_row(row)).collect();
+    if entries.is_empty() {
+        return Ok(None);
+    }
+
+    let mut haystack = text.to_lowercase();
+    let mut referenced: Vec<Uuid> = Vec::new();
+    if let Some(chapter_id) = chapter_id {
+        let query = "SELECT COALESCE(title, ''), COALESCE(metadata->>'outline', '') FROM content.chapters WHERE id = $1";
+        let rows = conn.query(query, &[ParameterValue::Str(chapter_id.to_string())])
+            .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+        for row in &rows.rows {
+            haystack.push('\n');
+            haystack.push_str(&String::decode(&row[0]).unwrap_or_default().to_lowercase());
+            haystack.push('\n');
+            haystack.push_str(&String::decode(&row[1]).unwrap_or_default().to_lowercase());
+        }
+
+        let query = "SELECT entry_id FROM content.lore_references WHERE chapter_id = $1";
+        let rows = conn.query(query, &[ParameterValue::Str(chapter_id.to_string())])
+            .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+        referenced = rows.rows.iter()
+            .filter_map(|row| String::decode(&row[0]).ok().and_then(|s| Uuid::parse_str(&s).ok()))
+            .collect();
+    }
+
+    let (mut chosen, rest): (Vec<LoreEntry>, Vec<LoreEntry>) = entries.into_iter()
+        .partition(|e| referenced.contains(&e.id));
+    chosen.extend(rest.into_iter().filter(|e| {
+        std::iter::once(&e.name).chain(e.aliases.iter()).any(|name| mentions(&haystack, &name.to_lowercase()))
+    }));
+    chosen.truncate(MAX_CONTEXT_ENTRIES);
+    if chosen.is_empty() {
+        return Ok(None);
+    }
+
+    let context: Vec<serde_json::Value> = chosen.into_iter().map(|entry| {
+        let summary: Option<String> = entry.summary.or(entry.details)
+            .map(|s| s.chars().take(MAX_CONTEXT_SUMMARY_CHARS).collect());
+        serde_json::json!({
+            "name": entry.name,
+            "kind": entry.kind,
+            "aliases": entry.aliases,
+            "summary": summary,
+            "referenced": referenced.contains(&entry.id)
+        })
+    }).collect();
+    Ok(Some(serde_json::json!({ "entries": context })))
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+/// Whether `needle` occurs in `haystack` as a whole word; both lowercased
+fn mentions(haystack: &str, needle: &str) -> bool {
+    if needle.is_empty() {
+        return false;
+    }
+    haystack.match_indices(needle).any(|(start, _)| {
+        let before = haystack[..start].chars().next_back();
+        let after = haystack[start + needle.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+fn load_entry(conn: &Connection, book_id: &Uuid, entry_id: &Uuid) -> Result<LoreEntry, ServiceError> {
+    let query = format!("SELECT {} FROM content.lore_entries e WHERE {} AND e.id = $2", ENTRY_COLUMNS, WORLD_FILTER);
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(entry_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    rows.rows.first()
+        .map(|row| entry_from_row(row))
+        .ok_or_else(|| ServiceError::NotFound("Lore entry not found".into()))
+}
+
+/// Shared series entries are read-only from other books
+fn ensure_owned(conn: &Connection, book_id: &Uuid, entry_id: &Uuid) -> Result<(), ServiceError> {
+    let entry = load_entry(conn, book_id, entry_id)?;
+    if entry.book_id != *book_id {
+        return Err(ServiceError::Forbidden("Shared lore can only be edited from the book that owns it".into()));
+    }
+    Ok(())
+}
+
+fn entry_from_row(row: &[spin_sdk::pg::DbValue]) -> LoreEntry {
+    LoreEntry {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        book_id: Uuid::parse_str(&String::decode(&row[1]).unwrap_or_default()).unwrap_or_default(),
+        name: String::decode(&row[2]).unwrap_or_default(),
+        kind: String::decode(&row[3]).unwrap_or_default(),
+        summary: String::decode(&row[4]).ok(),
+        details: String::decode(&row[5]).ok(),
+        aliases: serde_json::from_str(&String::decode(&row[6]).unwrap_or_default()).unwrap_or_default(),
+        created_at: String::decode(&row[7]).unwrap_or_default(),
+        updated_at: String::decode(&row[8]).unwrap_or_default(),
+    }
+}
+
+fn validate_kind(kind: &str) -> Result<(), ServiceError> {
+    if !KINDS.contains(&kind) {
+        return Err(ServiceError::BadRequest(format!("kind must be one of: {}", KINDS.join(", "))));
+    }
+    Ok(())
+}
+
+fn validate_aliases(aliases: Vec<String>) -> Result<Vec<String>, ServiceError> {
+    let mut cleaned: Vec<String> = Vec::new();
+    for alias in aliases {
+        let alias = alias.trim().to_string();
+        if alias.is_empty() {
+            continue;
+        }
+        if alias.chars().count() > MAX_ALIAS_CHARS {
+            return Err(ServiceError::BadRequest(format!("aliases must be at most {} characters each", MAX_ALIAS_CHARS)));
+        }
+        if !cleaned.iter().any(|a| a.to_lowercase() == alias.to_lowercase()) {
+            cleaned.push(alias);
+        }
+    }
+    if cleaned.len() > MAX_ALIASES {
+        return Err(ServiceError::BadRequest(format!("At most {} aliases per entry", MAX_ALIASES)));
+    }
+    Ok(cleaned)
+}
@@ -27,6 +27,22 @@ pub struct Book {
     pub updated_at: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub published_at: Option<String>,
+    /// Author opted the book out of all AI generation and analysis
+    #[serde(default)]
+    pub ai_disabled: bool,
+    /// Author opted the book out of search indexing
+    #[serde(default)]
+    pub index_excluded: bool,
+    /// Age rating search filters on; unrated when absent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_rating: Option<String>,
+    #[serde(default)]
+    pub content_warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub series_id: Option<Uuid>,
+    /// 1-based position within the series
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub series_order: Option<i32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,6 +59,8 @@ pub struct BookSummary {
     pub word_count: i32,
     pub created_at: String,
     pub updated_at: String,
+    pub ai_disabled: bool,
+    pub index_excluded: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -51,6 +69,10 @@ pub struct CreateBookRequest {
     pub description: Option<String>,
     pub genre: Option<String>,
     pub metadata: Option<HashMap<String, serde_json::Value>>,
+    pub ai_disabled: Option<bool>,
+    pub index_excluded: Option<bool>,
+    pub content_rating: Option<String>,
+    pub content_warnings: Option<Vec<String>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -61,6 +83,10 @@ pub struct UpdateBookRequest {
     pub status: Option<String>,
     pub cover_image_url: Option<String>,
     pub metadata: Option<HashMap<String, serde_json::Value>>,
+    pub ai_disabled: Option<bool>,
+    pub index_excluded: Option<bool>,
+    pub content_rating: Option<String>,
+    pub content_warnings: Option<Vec<String>>,
 }
 
 //=============================================================================
@@ -90,6 +116,8 @@ pub struct ChapterSummary {
     pub status: String,
     pub created_at: String,
     pub updated_at: String,
+    /// front_matter, body or back_matter
+    pub section: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -0,0 +1,613 @@
+//! Content moderation before publish
+//!
+//! When `moderation_api_url` is set, `POST /books/:id/publish` scans the book
+//! before it goes live. The title, description and every written chapter (in
+//! pieces of at most `MAX_INPUT_CHARS`) go to the moderation API, which
+//! answers in the OpenAI moderation format: one result per input with
+//! `flagged`, `categories` and `category_scores`. With
+//! `moderation_flag_threshold` set, any category score at or above it also
+//! flags the passage.
+//!
+//! Every scan is recorded in `content.moderation_reports`. While a report is
+//! open the book sits in `pending_review`: it is not published, and it cannot
+//! be published again or moved to another status by `PUT /books/:id`.
+//! - A clean scan publishes the book straight away.
+//! - A flagged scan waits in the review queue (`GET /admin/moderation/queue`)
+//!   until a moderator approves it, which publishes the book, or rejects it,
+//!   which returns it to draft. The author is notified either way.
+//! - A scan that could not run (API down, bad response) stays `queued`.
+//!   `POST /moderation/process` retries it, up to `MAX_ATTEMPTS` times.
+//!   Moderators can decide a queued report by hand.
+//!
+//! The author can withdraw a book under review with
+//! `POST /books/:id/unpublish`. Approval publishes the book as it is at that
+//! moment. It is not scanned again.
+
+use crate::error::ServiceError;
+use crate::publishing;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::outbound_http;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Book status while a moderation report is open
+pub const STATUS_PENDING_REVIEW: &str = "pending_review";
+
+const REPORT_QUEUED: &str = "queued";
+pub const REPORT_PASSED: &str = "passed";
+const REPORT_FLAGGED: &str = "flagged";
+const REPORT_APPROVED: &str = "approved";
+const REPORT_REJECTED: &str = "rejected";
+const REPORT_WITHDRAWN: &str = "withdrawn";
+
+/// Longest passage sent as one input
+const MAX_INPUT_CHARS: usize = 8000;
+/// Inputs per moderation API call
+const INPUTS_PER_REQUEST: usize = 32;
+const EXCERPT_CHARS: usize = 280;
+/// Scans of a queued report before it is left for a moderator
+const MAX_ATTEMPTS: i32 = 5;
+/// Reports retried per `POST /moderation/process` run
+const PROCESS_BATCH: i64 = 20;
+pub const DEFAULT_QUEUE_LIMIT: i64 = 50;
+const MAX_QUEUE_LIMIT: i64 = 200;
+const MAX_NOTE_CHARS: usize = 2000;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewRequest {
+    /// `approve` or `reject`
+    pub decision: String,
+    /// Shown to the author
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModerationReport {
+    pub id: String,
+    pub book_id: String,
+    pub book_title: Option<String>,
+    pub author_id: String,
+    pub author_name: Option<String>,
+    pub status: String,
+    pub model: Option<String>,
+    pub categories: Vec<String>,
+    pub category_scores: serde_json::Value,
+    pub flagged_sections: serde_json::Value,
+    pub sections_scanned: i32,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub scanned_at: Option<String>,
+    pub reviewed_by: Option<String>,
+    pub reviewed_at: Option<String>,
+    pub review_note: Option<String>,
+    pub created_at: String,
+}
+
+/// Outcome of sending a book to moderation on publish
+pub struct Submission {
+    pub report_id: Uuid,
+    /// `passed`, `flagged` or `queued`
+    pub status: &'static str,
+}
+
+struct ModerationConfig {
+    api_url: String,
+    api_key: Option<String>,
+    model: Option<String>,
+    threshold: Option<f64>,
+}
+
+struct Section {
+    label: String,
+    chapter_id: Option<String>,
+    text: String,
+}
+
+struct ScanResult {
+    model: Option<String>,
+    categories: Vec<String>,
+    category_scores: BTreeMap<String, f64>,
+    flagged_sections: Vec<serde_json::Value>,
+    sections_scanned: usize,
+}
+
+//=============================================================================
+// Publish Hook
+//=============================================================================
+
+/// Moderation runs only when an API is configured
+pub fn enabled() -> bool {
+    config().is_some()
+}
+
+/// Hold the book in `pending_review` and scan it. A scan that fails is left
+/// queued for `POST /moderation/process` rather than failing the publish.
+pub fn submit(conn: &Connection, book_id: &Uuid, author_id: &str, current_status: &str) -> Result<Submission, ServiceError> {
+    let hold = "UPDATE content.books SET status = $2, updated_at = NOW()
+                WHERE id = $1 AND COALESCE(status, 'draft') = $3";
+    let held = conn.execute(hold, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(STATUS_PENDING_REVIEW.to_string()),
+        ParameterValue::Str(current_status.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    if held == 0 {
+        return Err(ServiceError::Conflict("Book status changed while publishing; try again".into()));
+    }
+
+    let report_id = Uuid::new_v4();
+    let insert = "INSERT INTO content.moderation_reports
+                  (id, book_id, author_id, status, attempts, last_attempt_at, created_at, updated_at)
+                  VALUES ($1, $2, $3, $4, 1, NOW(), NOW(), NOW())";
+    conn.execute(insert, &[
+        ParameterValue::Str(report_id.to_string()),
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(author_id.to_string()),
+        ParameterValue::Str(REPORT_QUEUED.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    let status = match scan_report(conn, &report_id, book_id) {
+        Ok(Some(status)) => status,
+        Ok(None) => REPORT_QUEUED,
+        Err(e) => {
+            record_error(conn, &report_id, &e.to_string())?;
+            REPORT_QUEUED
+        }
+    };
+    Ok(Submission { report_id, status })
+}
+
+/// Close the book's open report and return it to draft; false when the book
+/// was not under review
+pub fn withdraw(conn: &Connection, book_id: &Uuid) -> Result<bool, ServiceError> {
+    let update = "UPDATE content.books SET status = 'draft', updated_at = NOW()
+                  WHERE id = $1 AND status = $2";
+    let updated = conn.execute(update, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(STATUS_PENDING_REVIEW.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    if updated == 0 {
+        return Ok(false);
+    }
+
+    let close = "UPDATE content.moderation_reports SET status = $2, updated_at = NOW()
+                 WHERE book_id = $1 AND status IN ('queued', 'flagged')";
+    conn.execute(close, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(REPORT_WITHDRAWN.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(true)
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// GET /books/:id/moderation - The book's latest moderation report
+pub fn get_for_book(conn: &Connection, book_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = format!("{} WHERE r.book_id = $1 ORDER BY r.created_at DESC LIMIT 1", REPORT_SELECT);
+    let rows = conn.query(&query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let report = rows.rows.first()
+        .map(|row| report_from_row(row))
+        .ok_or_else(|| ServiceError::NotFound("No moderation report for this book".into()))?;
+
+    crate::json_response(200, report)
+}
+
+/// GET /admin/moderation/queue - Open reports, oldest first (admin)
+pub fn queue(conn: &Connection, status: Option<&str>, limit: i64) -> Result<Response, ServiceError> {
+    let statuses = match status {
+        None => vec![REPORT_FLAGGED, REPORT_QUEUED],
+        Some(s) if s == REPORT_FLAGGED || s == REPORT_QUEUED => vec![s],
+        Some(other) => return Err(ServiceError::BadRequest(format!(
+            "status must be {} or {}, not '{}'", REPORT_FLAGGED, REPORT_QUEUED, other
+        ))),
+    };
+    let limit = limit.clamp(1, MAX_QUEUE_LIMIT);
+
+    let query = format!(
+        "{} WHERE r.status = ANY(string_to_array($1, ',')) ORDER BY r.created_at ASC LIMIT $2",
+        REPORT_SELECT
+    );
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(statuses.join(",")),
+        ParameterValue::Int64(limit),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let reports: Vec<ModerationReport> = rows.rows.iter().map(|row| report_from_row(row)).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "reports": reports,
+        "total": reports.len()
+    }))
+}
+
+/// POST /admin/moderation/:id/review - Approve (publish) or reject a book under review (admin)
+pub fn review(conn: &Connection, admin_id: &Uuid, report_id: &Uuid, body: ReviewRequest) -> Result<Response, ServiceError> {
+    let status = match body.decision.as_str() {
+        "approve" => REPORT_APPROVED,
+        "reject" => REPORT_REJECTED,
+        other => return Err(ServiceError::BadRequest(format!("decision must be approve or reject, not '{}'", other))),
+    };
+    let note = body.note.map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
+    if note.as_ref().is_some_and(|n| n.chars().count() > MAX_NOTE_CHARS) {
+        return Err(ServiceError::BadRequest(format!("note must be at most {} characters", MAX_NOTE_CHARS)));
+    }
+
+    // Claim the report so two moderators cannot both decide it
+    let claim = "UPDATE content.moderation_reports
+                 SET status = $2, reviewed_by = $3, reviewed_at = NOW(), review_note = $4, updated_at = NOW()
+                 WHERE id = $1 AND status IN ('queued', 'flagged')
+                 RETURNING book_id::text, author_id::text";
+    let rows = conn.query(claim, &[
+        ParameterValue::Str(report_id.to_string()),
+        ParameterValue::Str(status.to_string()),
+        ParameterValue::Str(admin_id.to_string()),
+        note.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let row = match rows.rows.first() {
+        Some(row) => row,
+        None => {
+            let exists = conn.query(
+                "SELECT 1 FROM content.moderation_reports WHERE id = $1",
+                &[ParameterValue::Str(report_id.to_string())],
+            ).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+            return Err(if exists.rows.is_empty() {
+                ServiceError::NotFound("Moderation report not found".into())
+            } else {
+                ServiceError::Conflict("Moderation report has already been closed".into())
+            });
+        }
+    };
+    let book_id = Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default())
+        .map_err(|_| ServiceError::Internal("Invalid book ID".into()))?;
+    let author_id = String::decode(&row[1]).unwrap_or_default();
+
+    let book = if status == REPORT_APPROVED {
+        let published = publishing::go_live(conn, &book_id, STATUS_PENDING_REVIEW)?;
+        notify_author(&author_id, "moderation_approved", "Your book is published",
+            "A moderator reviewed your book and it is now live.", &book_id, note.as_deref());
+        serde_json::json!({
+            "status": publishing::STATUS_PUBLISHED,
+            "published_at": published.as_ref().map(|p| p.published_at.clone()),
+            "indexing": published.as_ref().map(|p| p.indexing)
+        })
+    } else {
+        let update = "UPDATE content.books SET status = 'draft', updated_at = NOW()
+                      WHERE id = $1 AND status = $2";
+        conn.execute(update, &[
+            ParameterValue::Str(book_id.to_string()),
+            ParameterValue::Str(STATUS_PENDING_REVIEW.to_string()),
+        ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+        notify_author(&author_id, "moderation_rejected", "Your book was not published",
+            "A moderator reviewed your book and could not publish it. It is back in draft.", &book_id, note.as_deref());
+        serde_json::json!({ "status": publishing::STATUS_DRAFT })
+    };
+
+    crate::json_response(200, serde_json::json!({
+        "id": report_id,
+        "status": status,
+        "book_id": book_id,
+        "book": book,
+        "review_note": note
+    }))
+}
+
+/// POST /moderation/process - Retry scans that could not run on publish (internal)
+pub fn process(conn: &Connection) -> Result<Response, ServiceError> {
+    if !enabled() {
+        return crate::json_response(200, serde_json::json!({ "enabled": false, "scanned": 0 }));
+    }
+
+    // Claim with a lease so overlapping runs do not scan the same book
+    let claim = "UPDATE content.moderation_reports
+                 SET attempts = attempts + 1, last_attempt_at = NOW(), updated_at = NOW()
+                 WHERE id IN (
+                     SELECT id FROM content.moderation_reports
+                     WHERE status = 'queued' AND attempts < $1
+                       AND (last_attempt_at IS NULL OR last_attempt_at < NOW() - INTERVAL '5 minutes')
+                     ORDER BY created_at
+                     LIMIT $2
+                 )
+                 RETURNING id::text, book_id::text, author_id::text";
+    let rows = conn.query(claim, &[
+        ParameterValue::Int32(MAX_ATTEMPTS),
+        ParameterValue::Int64(PROCESS_BATCH),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let (mut published, mut flagged, mut failed) = (0, 0, 0);
+    for row in &rows.rows {
+        let parsed = (
+            Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()),
+            Uuid::parse_str(&String::decode(&row[1]).unwrap_or_default()),
+        );
+        let (report_id, book_id) = match parsed {
+            (Ok(report_id), Ok(book_id)) => (report_id, book_id),
+            _ => continue,
+        };
+        let author_id = String::decode(&row[2]).unwrap_or_default();
+
+        match scan_report(conn, &report_id, &book_id) {
+            Ok(Some(REPORT_PASSED)) => {
+                if publishing::go_live(conn, &book_id, STATUS_PENDING_REVIEW)?.is_some() {
+                    notify_author(&author_id, "book_published", "Your book is published",
+                        "Your book passed its content check and is now live.", &book_id, None);
+                }
+                published += 1;
+            }
+            Ok(Some(_)) => flagged += 1,
+            Ok(None) => {}
+            Err(e) => {
+                record_error(conn, &report_id, &e.to_string())?;
+                failed += 1;
+            }
+        }
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "enabled": true,
+        "scanned": rows.rows.len(),
+        "published": published,
+        "flagged": flagged,
+        "failed": failed
+    }))
+}
+
+//=============================================================================
+// Scanning
+//=============================================================================
+
+/// Scan the book and close the report as passed or flagged. `None` when the
+/// report was closed (withdrawn or decided) while the scan ran.
+fn scan_report(conn: &Connection, report_id: &Uuid, book_id: &Uuid) -> Result<Option<&'static str>, ServiceError> {
+    let config = config().ok_or_else(|| ServiceError::Internal("Moderation API not configured".into()))?;
+    let sections = load_sections(conn, book_id)?;
+    let scan = scan(&config, &sections)?;
+    let status = if scan.flagged_sections.is_empty() { REPORT_PASSED } else { REPORT_FLAGGED };
+
+    let update = "UPDATE content.moderation_reports
+                  SET status = $2, model = $3, categories = $4::jsonb, category_scores = $5::jsonb,
+                      flagged_sections = $6::jsonb, sections_scanned = $7, scanned_at = NOW(),
+                      last_error = NULL, updated_at = NOW()
+                  WHERE id = $1 AND status = 'queued'
+                  RETURNING author_id::text";
+    let rows = conn.query(update, &[
+        ParameterValue::Str(report_id.to_string()),
+        ParameterValue::Str(status.to_string()),
+        scan.model.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(serde_json::json!(scan.categories).to_string()),
+        ParameterValue::Str(serde_json::json!(scan.category_scores).to_string()),
+        ParameterValue::Str(serde_json::json!(scan.flagged_sections).to_string()),
+        ParameterValue::Int32(scan.sections_scanned as i32),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let author_id = match rows.rows.first() {
+        Some(row) => String::decode(&row[0]).unwrap_or_default(),
+        None => return Ok(None),
+    };
+    if status == REPORT_FLAGGED {
+        notify_author(&author_id, "moderation_flagged", "Your book is being reviewed",
+            "Part of your book needs a moderator's review before it can be published. We'll let you know the outcome.",
+            book_id, None);
+    }
+    Ok(Some(status))
+}
+
+fn record_error(conn: &Connection, report_id: &Uuid, error: &str) -> Result<(), ServiceError> {
+    conn.execute(
+        "UPDATE content.moderation_reports SET last_error = $2, updated_at = NOW() WHERE id = $1",
+        &[
+            ParameterValue::Str(report_id.to_string()),
+            ParameterValue::Str(error.to_string()),
+        ],
+    ).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+/// Title, description and written chapters, split into API-sized passages
+fn load_sections(conn: &Connection, book_id: &Uuid) -> Result<Vec<Section>, ServiceError> {
+    let rows = conn.query(
+        "SELECT title, description FROM content.books WHERE id = $1",
+        &[ParameterValue::Str(book_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first().ok_or_else(|| ServiceError::NotFound("Book not found".into()))?;
+
+    let mut sections = vec![Section { label: "title".into(), chapter_id: None, text: String::decode(&row[0]).unwrap_or_default() }];
+    if let Some(description) = String::decode(&row[1]).ok().filter(|d| !d.trim().is_empty()) {
+        sections.push(Section { label: "description".into(), chapter_id: None, text: description });
+    }
+
+    let query = "SELECT id::text, chapter_number, title, COALESCE(content, '')
+                 FROM content.chapters
+                 WHERE book_id = $1 AND COALESCE(word_count, 0) > 0
+                 ORDER BY chapter_number";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    for row in &rows.rows {
+        let chapter_id = String::decode(&row[0]).ok();
+        let number = i32::decode(&row[1]).unwrap_or(0);
+        let text = format!("{}\n\n{}", String::decode(&row[2]).unwrap_or_default(), String::decode(&row[3]).unwrap_or_default());
+        let chars: Vec<char> = text.chars().collect();
+        let parts = chars.len().div_ceil(MAX_INPUT_CHARS).max(1);
+        for (i, part) in chars.chunks(MAX_INPUT_CHARS).enumerate() {
+            let label = if parts == 1 {
+                format!("chapter {}", number)
+            } else {
+                format!("chapter {} (part {} of {})", number, i + 1, parts)
+            };
+            sections.push(Section { label, chapter_id: chapter_id.clone(), text: part.iter().collect() });
+        }
+    }
+    Ok(sections)
+}
+
+fn scan(config: &ModerationConfig, sections: &[Section]) -> Result<ScanResult, ServiceError> {
+    let mut result = ScanResult {
+        model: config.model.clone(),
+        categories: Vec::new(),
+        category_scores: BTreeMap::new(),
+        flagged_sections: Vec::new(),
+        sections_scanned: 0,
+    };
+
+    for batch in sections.chunks(INPUTS_PER_REQUEST) {
+        let response = request_moderation(config, batch)?;
+        if result.model.is_none() {
+            result.model = response.get("model").and_then(|m| m.as_str()).map(String::from);
+        }
+        let outputs = response.get("results").and_then(|r| r.as_array())
+            .filter(|r| r.len() == batch.len())
+            .ok_or_else(|| ServiceError::Internal("Moderation API returned the wrong number of results".into()))?;
+
+        for (section, output) in batch.iter().zip(outputs) {
+            let mut categories: Vec<String> = output.get("categories").and_then(|c| c.as_object())
+                .map(|c| c.iter().filter(|(_, v)| v.as_bool() == Some(true)).map(|(k, _)| k.clone()).collect())
+                .unwrap_or_default();
+            if let Some(scores) = output.get("category_scores").and_then(|s| s.as_object()) {
+                for (category, score) in scores.iter().filter_map(|(k, v)| v.as_f64().map(|s| (k, s))) {
+                    let max = result.category_scores.entry(category.clone()).or_insert(0.0);
+                    *max = max.max(score);
+                    if config.threshold.is_some_and(|t| score >= t) && !categories.contains(category) {
+                        categories.push(category.clone());
+                    }
+                }
+            }
+
+            let flagged = output.get("flagged").and_then(|f| f.as_bool()).unwrap_or(false) || !categories.is_empty();
+            if flagged {
+                for category in &categories {
+                    if !result.categories.contains(category) {
+                        result.categories.push(category.clone());
+                    }
+                }
+                result.flagged_sections.push(serde_json::json!({
+                    "section": section.label,
+                    "chapter_id": section.chapter_id,
+                    "categories": categories,
+                    "excerpt": section.text.chars().take(EXCERPT_CHARS).collect::<String>()
+                }));
+            }
+        }
+        result.sections_scanned += batch.len();
+    }
+
+    result.categories.sort();
+    Ok(result)
+}
+
+fn request_moderation(config: &ModerationConfig, batch: &[Section]) -> Result<serde_json::Value, ServiceError> {
+    let mut body = serde_json::json!({
+        "input": batch.iter().map(|s| s.text.as_str()).collect::<Vec<_>>()
+    });
+    if let Some(model) = &config.model {
+        body["model"] = serde_json::json!(model);
+    }
+
+    let request = match &config.api_key {
+        Some(key) => outbound_http::Request::builder()
+            .method(outbound_http::Method::Post)
+            .uri(&config.api_url)
+            .header("Content-Type", "application/json")
+            .header("Authorization", format!("Bearer {}", key))
+            .body(body.to_string())
+            .build(),
+        None => outbound_http::Request::builder()
+            .method(outbound_http::Method::Post)
+            .uri(&config.api_url)
+            .header("Content-Type", "application/json")
+            .body(body.to_string())
+            .build(),
+    };
+
+    let response = outbound_http::send(request)
+        .map_err(|e| ServiceError::Internal(format!("Moderation API unreachable: {}", e)))?;
+    let status = response.status().as_u16();
+    if !(200..300).contains(&status) {
+        return Err(ServiceError::Internal(format!("Moderation API returned status {}", status)));
+    }
+
+    serde_json::from_slice(response.body())
+        .map_err(|e| ServiceError::Internal(format!("Failed to parse moderation response: {}", e)))
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+fn config() -> Option<ModerationConfig> {
+    let api_url = variables::get("moderation_api_url").ok().filter(|u| !u.trim().is_empty())?;
+    Some(ModerationConfig {
+        api_url,
+        api_key: variables::get("moderation_api_key").ok().filter(|k| !k.is_empty()),
+        model: variables::get("moderation_model").ok().filter(|m| !m.is_empty()),
+        threshold: variables::get("moderation_flag_threshold").ok()
+            .and_then(|t| t.parse::<f64>().ok())
+            .filter(|t| (0.0..=1.0).contains(t)),
+    })
+}
+
+const REPORT_SELECT: &str = "SELECT r.id::text, r.book_id::text, b.title, r.author_id::text, u.name, r.status, r.model,
+                                    r.categories::text, r.category_scores::text, r.flagged_sections::text,
+                                    r.sections_scanned, r.attempts, r.last_error, r.scanned_at::text,
+                                    r.reviewed_by::text, r.reviewed_at::text, r.review_note, r.created_at::text
+                             FROM content.moderation_reports r
+                             LEFT JOIN content.books b ON b.id = r.book_id
+                             LEFT JOIN users.users u ON u.id = r.author_id";
+
+fn report_from_row(row: &[spin_sdk::pg::DbValue]) -> ModerationReport {
+    ModerationReport {
+        id: String::decode(&row[0]).unwrap_or_default(),
+        book_id: String::decode(&row[1]).unwrap_or_default(),
+        book_title: String::decode(&row[2]).ok(),
+        author_id: String::decode(&row[3]).unwrap_or_default(),
+        author_name: String::decode(&row[4]).ok(),
+        status: String::decode(&row[5]).unwrap_or_default(),
+        model: String::decode(&row[6]).ok(),
+        categories: serde_json::from_str(&String::decode(&row[7]).unwrap_or_default()).unwrap_or_default(),
+        category_scores: serde_json::from_str(&String::decode(&row[8]).unwrap_or_default()).unwrap_or_else(|_| serde_json::json!({})),
+        flagged_sections: serde_json::from_str(&String::decode(&row[9]).unwrap_or_default()).unwrap_or_else(|_| serde_json::json!([])),
+        sections_scanned: i32::decode(&row[10]).unwrap_or(0),
+        attempts: i32::decode(&row[11]).unwrap_or(0),
+        last_error: String::decode(&row[12]).ok(),
+        scanned_at: String::decode(&row[13]).ok(),
+        reviewed_by: String::decode(&row[14]).ok(),
+        reviewed_at: String::decode(&row[15]).ok(),
+        review_note: String::decode(&row[16]).ok(),
+        created_at: String::decode(&row[17]).unwrap_or_default(),
+    }
+}
+
+/// Fire-and-forget in-app notification through the messaging service
+fn notify_author(author_id: &str, kind: &str, title: &str, body: &str, book_id: &Uuid, note: Option<&str>) {
+    let messaging_url = variables::get("messaging_service_url")
+        .unwrap_or_else(|_| "http://messaging-service:3106".to_string());
+
+    let body = serde_json::json!({
+        "user_id": author_id,
+        "type": kind,
+        "title": title,
+        "body": body,
+        "data": {
+            "book_id": book_id,
+            "note": note,
+            "url": format!("/books/{}", book_id)
+        }
+    });
+
+    let request = outbound_http::Request::builder()
+        .method(outbound_http::Method::Post)
+        .uri(format!("{}/notifications", messaging_url))
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .build();
+
+    let _ = outbound_http::send(request);
+}
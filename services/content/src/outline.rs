@@ -0,0 +1,415 @@
+//! Book outlines
+//!
+//! The outline is the planned shape of a book: parts holding chapters
+//! holding scenes. The editor saves the whole tree after every drag-and-drop,
+//! so `PUT /books/:id/outline` replaces it, guarded by a version stamp: a
+//! save names the version it started from and gets 409 if someone saved in
+//! between.
+//!
+//! Outline chapters link to real chapters through `chapter_id`; unlinked
+//! ones are matched by title. Both endpoints return the outline with a diff
+//! against the chapter list: what is drafted, what is only planned, and which
+//! chapters the outline does not mention.
+
+use crate::common::{optional_text, required_text};
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+const MAX_PARTS: usize = 50;
+const MAX_CHAPTERS: usize = 500;
+const MAX_SCENES_PER_CHAPTER: usize = 100;
+const MAX_TITLE_CHARS: usize = 500;
+const MAX_SUMMARY_CHARS: usize = 10_000;
+const MAX_SYNOPSIS_CHARS: usize = 20_000;
+const MAX_TARGET_WORDS: i32 = 1_000_000;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Outline {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub synopsis: Option<String>,
+    #[serde(default)]
+    pub parts: Vec<OutlinePart>,
+}
+
+/// Node ids are kept stable across saves so the editor can track nodes while
+/// they are dragged; new nodes may omit them and are assigned one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutlinePart {
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
+    /// May be empty for books that are not divided into parts
+    #[serde(default)]
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub chapters: Vec<OutlineChapter>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutlineChapter {
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
+    /// Drafted chapter this node plans, once there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chapter_id: Option<Uuid>,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_words: Option<i32>,
+    #[serde(default)]
+    pub scenes: Vec<OutlineScene>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutlineScene {
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pov_character: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaveOutlineRequest {
+    pub outline: Outline,
+    /// Version the edit started from; 0 when the book has no outline yet
+    pub version: i32,
+}
+
+/// An outline chapter compared with the drafted chapters
+#[derive(Debug, Serialize)]
+pub struct PlannedChapter {
+    pub node_id: Uuid,
+    pub part_id: Uuid,
+    /// 1-based position across the whole outline
+    pub position: usize,
+    pub title: String,
+    pub target_words: Option<i32>,
+    pub scene_count: usize,
+    /// `drafted`, `started` (no words yet), `planned` or `missing` (linked
+    /// chapter was deleted)
+    pub status: &'static str,
+    /// `link` or `title`
+    pub matched_by: Option<&'static str>,
+    pub chapter: Option<DraftedChapter>,
+    /// False when the drafted chapter comes before one planned earlier
+    pub in_order: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DraftedChapter {
+    pub id: Uuid,
+    pub chapter_number: i32,
+    pub title: String,
+    pub word_count: i32,
+    pub status: String,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// GET /books/:id/outline - The outline with its drafted-vs-planned diff
+pub fn get(conn: &Connection, book_id: &Uuid) -> Result<Response, ServiceError> {
+    let (outline, version, updated_at, updated_by) = match load(conn, book_id)? {
+        Some(stored) => stored,
+        None => (Outline::default(), 0, None, None),
+    };
+    let chapters = drafted_chapters(conn, book_id)?;
+    let (planned, unplanned) = diff(&outline, chapters);
+
+    let drafted = planned.iter().filter(|c| c.status == "drafted").count();
+    let started = planned.iter().filter(|c| c.status == "started").count();
+    let missing = planned.iter().filter(|c| c.status == "missing").count();
+    let target_words: i64 = planned.iter().filter_map(|c| c.target_words).map(i64::from).sum();
+    let drafted_words: i64 = planned.iter()
+        .filter_map(|c| c.chapter.as_ref())
+        .map(|c| i64::from(c.word_count))
+        .sum();
+
+    crate::json_response(200, serde_json::json!({
+        "book_id": book_id,
+        "version": version,
+        "updated_at": updated_at,
+        "updated_by": updated_by,
+        "outline": outline,
+        "diff": {
+            "chapters": planned,
+            "unplanned": unplanned
+        },
+        "progress": {
+            "parts": outline.parts.len(),
+            "planned_chapters": planned.len(),
+            "drafted": drafted,
+            "started": started,
+            "planned_only": planned.len() - drafted - started - missing,
+            "missing": missing,
+            "unplanned": unplanned.len(),
+            "target_words": target_words,
+            "drafted_words": drafted_words
+        }
+    }))
+}
+
+/// PUT /books/:id/outline - Replace the outline, if nobody saved since `version`
+pub fn save(conn: &Connection, user_id: &Uuid, book_id: &Uuid, body: SaveOutlineRequest) -> Result<Response, ServiceError> {
+    let outline = normalize(body.outline)?;
+    validate_links(conn, book_id, &outline)?;
+    let json = serde_json::to_string(&outline)
+        .map_err(|e| ServiceError::Internal(format!("JSON error: {}", e)))?;
+
+    let saved = if body.version == 0 {
+        let insert = "INSERT INTO content.outlines (book_id, outline, version, updated_by, created_at, updated_at)
+                      VALUES ($1, $2::jsonb, 1, $3, NOW(), NOW())
+                      ON CONFLICT (book_id) DO NOTHING
+                      RETURNING version";
+        conn.query(insert, &[
+            ParameterValue::Str(book_id.to_string()),
+            ParameterValue::Str(json),
+            ParameterValue::Str(user_id.to_string()),
+        ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?
+    } else {
+        let update = "UPDATE content.outlines
+                      SET outline = $2::jsonb, version = version + 1, updated_by = $3, updated_at = NOW()
+                      WHERE book_id = $1 AND version = $4
+                      RETURNING version";
+        conn.query(update, &[
+            ParameterValue::Str(book_id.to_string()),
+            ParameterValue::Str(json),
+            ParameterValue::Str(user_id.to_string()),
+            ParameterValue::Int32(body.version),
+        ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?
+    };
+
+    if saved.rows.is_empty() {
+        let current = load(conn, book_id)?.map(|(_, version, _, _)| version).unwrap_or(0);
+        return Err(ServiceError::Conflict(format!(
+            "Outline has changed since version {} (now version {}); reload it and reapply your edit",
+            body.version, current
+        )));
+    }
+
+    // Series generation context reads the synopsis from book metadata
+    if let Some(synopsis) = &outline.synopsis {
+        let update = "UPDATE content.books
+                      SET metadata = jsonb_set(COALESCE(metadata, '{}'::jsonb), '{synopsis}', to_jsonb($2::text)),
+                          updated_at = NOW()
+                      WHERE id = $1";
+        conn.execute(update, &[
+            ParameterValue::Str(book_id.to_string()),
+            ParameterValue::Str(synopsis.clone()),
+        ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    }
+
+    get(conn, book_id)
+}
+
+//=============================================================================
+// Validation
+//=============================================================================
+
+/// Trim text, drop empty optionals and enforce the size limits
+fn normalize(mut outline: Outline) -> Result<Outline, ServiceError> {
+    outline.synopsis = optional_text(outline.synopsis, "synopsis", MAX_SYNOPSIS_CHARS)?;
+
+    if outline.parts.len() > MAX_PARTS {
+        return Err(ServiceError::BadRequest(format!("At most {} parts", MAX_PARTS)));
+    }
+    let chapter_count: usize = outline.parts.iter().map(|p| p.chapters.len()).sum();
+    if chapter_count > MAX_CHAPTERS {
+        return Err(ServiceError::BadRequest(format!("At most {} chapters", MAX_CHAPTERS)));
+    }
+
+    let mut node_ids: HashSet<Uuid> = HashSet::new();
+    let mut linked: HashSet<Uuid> = HashSet::new();
+    let mut check_id = |id: Uuid| {
+        if node_ids.insert(id) {
+            Ok(())
+        } else {
+            Err(ServiceError::BadRequest(format!("Duplicate outline node id {}", id)))
+        }
+    };
+
+    for part in &mut outline.parts {
+        check_id(part.id)?;
+        part.title = part.title.trim().to_string();
+        if part.title.chars().count() > MAX_TITLE_CHARS {
+            return Err(ServiceError::BadRequest(format!("Part titles are limited to {} characters", MAX_TITLE_CHARS)));
+        }
+        part.summary = optional_text(part.summary.take(), "part summary", MAX_SUMMARY_CHARS)?;
+
+        for chapter in &mut part.chapters {
+            check_id(chapter.id)?;
+            chapter.title = required_text(&chapter.title, "Chapter titles", MAX_TITLE_CHARS)?;
+            chapter.summary = optional_text(chapter.summary.take(), "chapter summary", MAX_SUMMARY_CHARS)?;
+            if chapter.target_words.is_some_and(|w| !(0..=MAX_TARGET_WORDS).contains(&w)) {
+                return Err(ServiceError::BadRequest(format!("target_words must be between 0 and {}", MAX_TARGET_WORDS)));
+            }
+            if let Some(chapter_id) = chapter.chapter_id {
+                if !linked.insert(chapter_id) {
+                    return Err(ServiceError::BadRequest(format!(
+                        "Chapter {} is linked from more than one outline chapter", chapter_id
+                    )));
+                }
+            }
+            if chapter.scenes.len() > MAX_SCENES_PER_CHAPTER {
+                return Err(ServiceError::BadRequest(format!(
+                    "At most {} scenes per chapter", MAX_SCENES_PER_CHAPTER
+                )));
+            }
+
+            for scene in &mut chapter.scenes {
+                check_id(scene.id)?;
+                scene.title = required_text(&scene.title, "Scene titles", MAX_TITLE_CHARS)?;
+                scene.summary = optional_text(scene.summary.take(), "scene summary", MAX_SUMMARY_CHARS)?;
+                scene.pov_character = optional_text(scene.pov_character.take(), "pov_character", 255)?;
+                scene.location = optional_text(scene.location.take(), "location", 255)?;
+            }
+        }
+    }
+
+    Ok(outline)
+}
+
+/// Linked chapters must exist in this book
+fn validate_links(conn: &Connection, book_id: &Uuid, outline: &Outline) -> Result<(), ServiceError> {
+    let linked: Vec<String> = outline.parts.iter()
+        .flat_map(|p| &p.chapters)
+        .filter_map(|c| c.chapter_id.map(|id| id.to_string()))
+        .collect();
+    if linked.is_empty() {
+        return Ok(());
+    }
+
+    let query = "SELECT COUNT(*) FROM content.chapters
+                 WHERE book_id = $1 AND id::text = ANY(string_to_array($2, ','))";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(linked.join(",")),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let found = rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0);
+    if found != linked.len() as i64 {
+        return Err(ServiceError::BadRequest("Linked chapters must belong to this book".into()));
+    }
+    Ok(())
+}
+
+//=============================================================================
+// Diff
+//=============================================================================
+
+/// Match outline chapters to drafted ones: by link first, then by exact
+/// (case-insensitive) title when exactly one unmatched chapter has it
+fn diff(outline: &Outline, mut chapters: Vec<DraftedChapter>) -> (Vec<PlannedChapter>, Vec<DraftedChapter>) {
+    let nodes: Vec<(&OutlinePart, &OutlineChapter)> = outline.parts.iter()
+        .flat_map(|part| part.chapters.iter().map(move |chapter| (part, chapter)))
+        .collect();
+
+    let linked: HashSet<Uuid> = nodes.iter().filter_map(|(_, c)| c.chapter_id).collect();
+    let mut unclaimed: Vec<DraftedChapter> = chapters.iter().filter(|c| !linked.contains(&c.id)).cloned().collect();
+    let mut latest_number = i32::MIN;
+
+    let planned = nodes.iter().enumerate().map(|(index, (part, node))| {
+        let (chapter, matched_by) = match node.chapter_id {
+            Some(id) => (chapters.iter().find(|c| c.id == id).cloned(), Some("link")),
+            None => {
+                let key = node.title.to_lowercase();
+                let candidates: Vec<usize> = unclaimed.iter().enumerate()
+                    .filter(|(_, c)| c.title.trim().to_lowercase() == key)
+                    .map(|(i, _)| i)
+                    .collect();
+                match candidates.as_slice() {
+                    [only] => (Some(unclaimed.remove(*only)), Some("title")),
+                    _ => (None, None),
+                }
+            }
+        };
+
+        let status = match (&chapter, node.chapter_id) {
+            (Some(c), _) if c.word_count > 0 => "drafted",
+            (Some(_), _) => "started",
+            (None, Some(_)) => "missing",
+            (None, None) => "planned",
+        };
+        let in_order = match &chapter {
+            Some(c) if c.chapter_number < latest_number => false,
+            Some(c) => {
+                latest_number = c.chapter_number;
+                true
+            }
+            None => true,
+        };
+
+        PlannedChapter {
+            node_id: node.id,
+            part_id: part.id,
+            position: index + 1,
+            title: node.title.clone(),
+            target_words: node.target_words,
+            scene_count: node.scenes.len(),
+            status,
+            matched_by: chapter.as_ref().and(matched_by),
+            chapter,
+            in_order,
+        }
+    }).collect::<Vec<_>>();
+
+    let matched: HashSet<Uuid> = planned.iter().filter_map(|p| p.chapter.as_ref().map(|c| c.id)).collect();
+    chapters.retain(|c| !matched.contains(&c.id));
+    (planned, chapters)
+}
+
+//=============================================================================
+// Storage
+//=============================================================================
+
+type StoredOutline = (Outline, i32, Option<String>, Option<String>);
+
+fn load(conn: &Connection, book_id: &Uuid) -> Result<Option<StoredOutline>, ServiceError> {
+    let query = "SELECT outline::text, version, updated_at::text, updated_by::text
+                 FROM content.outlines WHERE book_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first().map(|row| {
+        let outline = String::decode(&row[0]).ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        (
+            outline,
+            i32::decode(&row[1]).unwrap_or(0),
+            String::decode(&row[2]).ok(),
+            String::decode(&row[3]).ok(),
+        )
+    }))
+}
+
+fn drafted_chapters(conn: &Connection, book_id: &Uuid) -> Result<Vec<DraftedChapter>, ServiceError> {
+    let query = "SELECT id, chapter_number, title, COALESCE(word_count, 0), COALESCE(status, 'draft')
+                 FROM content.chapters WHERE book_id = $1
+                 ORDER BY chapter_number";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.iter().map(|row| DraftedChapter {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        chapter_number: i32::decode(&row[1]).unwrap_or(0),
+        title: String::decode(&row[2]).unwrap_or_default(),
+        word_count: i32::decode(&row[3]).unwrap_or(0),
+        status: String::decode(&row[4]).unwrap_or_default(),
+    }).collect())
+}
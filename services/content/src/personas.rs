@@ -0,0 +1,281 @@
+//! Co-writing personas
+//!
+//! A persona is the voice the AI writes in for a book: a tone, vocabulary
+//! guidance, words it must never use, and a free-form memory of standing
+//! instructions ("British spelling", "Mara never swears") that would
+//! otherwise have to be repeated in every prompt. A book can keep several
+//! personas but at most one is active; the first one created becomes active.
+//!
+//! Every generation job queued for the book (outlines, chapter and scene
+//! plans, chapters, enhancements) carries the active persona, so whichever
+//! job writes the text, it sounds the same.
+
+use crate::common::{self, clearable_text, optional_text};
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+const MAX_PERSONAS_PER_BOOK: i64 = 20;
+const MAX_NAME_CHARS: usize = 100;
+const MAX_TONE_CHARS: usize = 500;
+const MAX_VOCABULARY_CHARS: usize = 2000;
+const MAX_MEMORY_CHARS: usize = 8000;
+const MAX_BANNED_WORDS: usize = 200;
+const MAX_BANNED_WORD_CHARS: usize = 50;
+
+const PERSONA_COLUMNS: &str = "id, book_id, name, tone, vocabulary, banned_words::text, memory, is_active,
+                               created_at::text, updated_at::text";
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct CreatePersonaRequest {
+    pub name: String,
+    /// e.g. "wry and understated, dry humour"
+    pub tone: Option<String>,
+    /// Vocabulary constraints, e.g. "plain words, no modern slang"
+    pub vocabulary: Option<String>,
+    /// Words and phrases generation must not use
+    #[serde(default)]
+    pub banned_words: Vec<String>,
+    /// Standing instructions remembered across every generation
+    pub memory: Option<String>,
+    /// Make this the book's active persona
+    pub active: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdatePersonaRequest {
+    pub name: Option<String>,
+    /// An empty string clears the field
+    pub tone: Option<String>,
+    pub vocabulary: Option<String>,
+    pub banned_words: Option<Vec<String>>,
+    pub memory: Option<String>,
+    /// `true` makes it the active persona; `false` leaves the book without one
+    pub active: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Persona {
+    pub id: Uuid,
+    pub book_id: Uuid,
+    pub name: String,
+    pub tone: Option<String>,
+    pub vocabulary: Option<String>,
+    pub banned_words: Vec<String>,
+    pub memory: Option<String>,
+    pub active: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /books/:id/personas - Add a persona to the book
+pub fn create(conn: &Connection, book_id: &Uuid, body: CreatePersonaRequest) -> Result<Response, ServiceError> {
+    let name = common::required_text(&body.name, "name", MAX_NAME_CHARS)?;
+    let tone = optional_text(body.tone, "tone", MAX_TONE_CHARS)?;
+    let vocabulary = optional_text(body.vocabulary, "vocabulary", MAX_VOCABULARY_CHARS)?;
+    let memory = optional_text(body.memory, "memory", MAX_MEMORY_CHARS)?;
+    let banned_words = validate_banned_words(body.banned_words)?;
+
+    let query = "SELECT COUNT(*), COUNT(*) FILTER (WHERE is_active) FROM content.personas WHERE book_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let (count, active_count) = rows.rows.first()
+        .map(|row| (i64::decode(&row[0]).unwrap_or(0), i64::decode(&row[1]).unwrap_or(0)))
+        .unwrap_or((0, 0));
+    if count >= MAX_PERSONAS_PER_BOOK {
+        return Err(ServiceError::BadRequest(format!("A book can have at most {} personas", MAX_PERSONAS_PER_BOOK)));
+    }
+    common::ensure_unique_name(conn, "content.personas", "persona", book_id, &name, None)?;
+
+    let active = body.active.unwrap_or(active_count == 0);
+    if active {
+        deactivate_all(conn, book_id)?;
+    }
+
+    let id = Uuid::new_v4();
+    let insert = "INSERT INTO content.personas
+                  (id, book_id, name, tone, vocabulary, banned_words, memory, is_active, created_at, updated_at)
+                  VALUES ($1, $2, $3, $4, $5, $6::jsonb, $7, $8, NOW(), NOW())";
+    conn.execute(insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(name),
+        tone.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        vocabulary.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(serde_json::to_string(&banned_words).unwrap_or_else(|_| "[]".into())),
+        memory.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Boolean(active),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    crate::json_response(201, load_persona(conn, book_id, &id)?)
+}
+
+/// GET /books/:id/personas - The book's personas, active first
+pub fn list(conn: &Connection, book_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = format!(
+        "SELECT {} FROM content.personas WHERE book_id = $1 ORDER BY is_active DESC, lower(name)",
+        PERSONA_COLUMNS
+    );
+    let rows = conn.query(&query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let personas: Vec<Persona> = rows.rows.iter().map(|row| persona_from_row(row)).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "book_id": book_id,
+        "active_persona_id": personas.iter().find(|p| p.active).map(|p| p.id),
+        "personas": personas
+    }))
+}
+
+/// GET /books/:id/personas/:persona_id - One persona
+pub fn get(conn: &Connection, book_id: &Uuid, persona_id: &Uuid) -> Result<Response, ServiceError> {
+    crate::json_response(200, load_persona(conn, book_id, persona_id)?)
+}
+
+/// PUT /books/:id/personas/:persona_id - Edit a persona or switch the active one
+pub fn update(conn: &Connection, book_id: &Uuid, persona_id: &Uuid, body: UpdatePersonaRequest) -> Result<Response, ServiceError> {
+    load_persona(conn, book_id, persona_id)?;
+    let name = body.name.as_deref().map(|name| common::required_text(name, "name", MAX_NAME_CHARS)).transpose()?;
+    if let Some(name) = &name {
+        common::ensure_unique_name(conn, "content.personas", "persona", book_id, name, Some(persona_id))?;
+    }
+    let banned_words = body.banned_words.map(validate_banned_words).transpose()?;
+
+    let mut updates = vec!["updated_at = NOW()".to_string()];
+    let mut params: Vec<ParameterValue> = vec![ParameterValue::Str(persona_id.to_string())];
+
+    let fields = [
+        ("name", name.map(ParameterValue::Str), ""),
+        ("tone", clearable_text(body.tone, "tone", MAX_TONE_CHARS)?, ""),
+        ("vocabulary", clearable_text(body.vocabulary, "vocabulary", MAX_VOCABULARY_CHARS)?, ""),
+        ("banned_words", banned_words.map(|w| ParameterValue::Str(serde_json::to_string(&w).unwrap_or_else(|_| "[]".into()))), "::jsonb"),
+        ("memory", clearable_text(body.memory, "memory", MAX_MEMORY_CHARS)?, ""),
+        ("is_active", body.active.map(ParameterValue::Boolean), ""),
+    ];
+    for (column, value, cast) in fields {
+        if let Some(value) = value {
+            params.push(value);
+            updates.push(format!("{} = ${}{}", column, params.len(), cast));
+        }
+    }
+
+    if body.active == Some(true) {
+        deactivate_all(conn, book_id)?;
+    }
+    let query = format!("UPDATE content.personas SET {} WHERE id = $1", updates.join(", "));
+    conn.execute(&query, &params)
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    crate::json_response(200, load_persona(conn, book_id, persona_id)?)
+}
+
+/// DELETE /books/:id/personas/:persona_id - Remove a persona; deleting the
+/// active one leaves the book without a persona
+pub fn delete(conn: &Connection, book_id: &Uuid, persona_id: &Uuid) -> Result<Response, ServiceError> {
+    let deleted = conn.execute(
+        "DELETE FROM content.personas WHERE id = $1 AND book_id = $2",
+        &[
+            ParameterValue::Str(persona_id.to_string()),
+            ParameterValue::Str(book_id.to_string()),
+        ],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    if deleted == 0 {
+        return Err(ServiceError::NotFound("Persona not found".into()));
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "id": persona_id,
+        "deleted": true
+    }))
+}
+
+//=============================================================================
+// Generation Context
+//=============================================================================
+
+/// The book's active persona for a generation job; `None` when it has none
+pub fn generation_context(conn: &Connection, book_id: &Uuid) -> Result<Option<serde_json::Value>, ServiceError> {
+    let query = "SELECT name, tone, vocabulary, banned_words::text, memory
+                 FROM content.personas WHERE book_id = $1 AND is_active";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first().map(|row| serde_json::json!({
+        "name": String::decode(&row[0]).unwrap_or_default(),
+        "tone": String::decode(&row[1]).ok(),
+        "vocabulary": String::decode(&row[2]).ok(),
+        "banned_words": serde_json::from_str::<Vec<String>>(&String::decode(&row[3]).unwrap_or_default()).unwrap_or_default(),
+        "memory": String::decode(&row[4]).ok()
+    })))
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+fn load_persona(conn: &Connection, book_id: &Uuid, persona_id: &Uuid) -> Result<Persona, ServiceError> {
+    let query = format!("SELECT {} FROM content.personas WHERE id = $1 AND book_id = $2", PERSONA_COLUMNS);
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(persona_id.to_string()),
+        ParameterValue::Str(book_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    rows.rows.first()
+        .map(|row| persona_from_row(row))
+        .ok_or_else(|| ServiceError::NotFound("Persona not found".into()))
+}
+
+fn deactivate_all(conn: &Connection, book_id: &Uuid) -> Result<(), ServiceError> {
+    conn.execute(
+        "UPDATE content.personas SET is_active = false, updated_at = NOW() WHERE book_id = $1 AND is_active",
+        &[ParameterValue::Str(book_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+fn persona_from_row(row: &[spin_sdk::pg::DbValue]) -> Persona {
+    Persona {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        book_id: Uuid::parse_str(&String::decode(&row[1]).unwrap_or_default()).unwrap_or_default(),
+        name: String::decode(&row[2]).unwrap_or_default(),
+        tone: String::decode(&row[3]).ok(),
+        vocabulary: String::decode(&row[4]).ok(),
+        banned_words: serde_json::from_str(&String::decode(&row[5]).unwrap_or_default()).unwrap_or_default(),
+        memory: String::decode(&row[6]).ok(),
+        active: bool::decode(&row[7]).unwrap_or(false),
+        created_at: String::decode(&row[8]).unwrap_or_default(),
+        updated_at: String::decode(&row[9]).unwrap_or_default(),
+    }
+}
+
+fn validate_banned_words(words: Vec<String>) -> Result<Vec<String>, ServiceError> {
+    let mut cleaned: Vec<String> = Vec::new();
+    for word in words {
+        let word = word.split_whitespace().collect::<Vec<_>>().join(" ");
+        if word.is_empty() {
+            continue;
+        }
+        if word.chars().count() > MAX_BANNED_WORD_CHARS {
+            return Err(ServiceError::BadRequest(format!(
+                "banned_words must be at most {} characters each", MAX_BANNED_WORD_CHARS
+            )));
+        }
+        if !cleaned.iter().any(|w| w.to_lowercase() == word.to_lowercase()) {
+            cleaned.push(word);
+        }
+    }
+    if cleaned.len() > MAX_BANNED_WORDS {
+        return Err(ServiceError::BadRequest(format!("At most {} banned words per persona", MAX_BANNED_WORDS)));
+    }
+    Ok(cleaned)
+}
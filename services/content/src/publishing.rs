@@ -0,0 +1,349 @@
+//! Publish / unpublish workflow
+//!
+//! `published` is only reachable through `POST /books/:id/publish`, which
+//! checks the book is complete enough to go live and reports every failed
+//! check at once as a 422 with structured `errors`. With moderation enabled
+//! the book is scanned first and may wait in `pending_review` (see
+//! `moderation`). Publishing stamps `published_at` and sends the book and its
+//! chapters to discovery for indexing; unpublishing returns the book to
+//! `draft` and removes it from search, or withdraws it from review. Plain
+//! `PUT /books/:id` updates cannot move a book into or out of `published` or
+//! `pending_review`.
+
+use crate::error::{ServiceError, ValidationIssue};
+use crate::moderation::{self, STATUS_PENDING_REVIEW};
+use spin_sdk::http::Response;
+use spin_sdk::outbound_http;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use chrono::Utc;
+use uuid::Uuid;
+
+pub const STATUS_PUBLISHED: &str = "published";
+pub const STATUS_DRAFT: &str = "draft";
+
+/// Chapters with content a book needs before it can be published
+const DEFAULT_MIN_CHAPTERS: i64 = 1;
+
+/// A book that has just gone live
+pub struct Published {
+    pub published_at: String,
+    /// `queued`, `failed` or `excluded`
+    pub indexing: &'static str,
+}
+
+struct BookState {
+    author_id: String,
+    title: String,
+    description: Option<String>,
+    genre: Option<String>,
+    status: String,
+    cover_image_url: Option<String>,
+    word_count: i32,
+    created_at: String,
+    index_excluded: bool,
+    content_rating: Option<String>,
+    content_warnings: Vec<String>,
+}
+
+//=============================================================================
+// Transitions
+//=============================================================================
+
+/// POST /books/:id/publish - Validate and publish the book
+pub fn publish(conn: &Connection, book_id: &Uuid) -> Result<Response, ServiceError> {
+    let book = load_book(conn, book_id)?;
+
+    if book.status == STATUS_PUBLISHED {
+        return Err(ServiceError::Validation(vec![ValidationIssue::new(
+            "status", "invalid_transition", "Book is already published",
+        )]));
+    }
+    if book.status == STATUS_PENDING_REVIEW {
+        return Err(ServiceError::Validation(vec![ValidationIssue::new(
+            "status", "invalid_transition", "Book is awaiting moderation review",
+        )]));
+    }
+
+    let issues = validate_for_publish(conn, book_id, &book)?;
+    if !issues.is_empty() {
+        return Err(ServiceError::Validation(issues));
+    }
+
+    let (from_status, moderation) = if moderation::enabled() {
+        let submission = moderation::submit(conn, book_id, &book.author_id, &book.status)?;
+        if submission.status != moderation::REPORT_PASSED {
+            return crate::json_response(202, serde_json::json!({
+                "id": book_id,
+                "status": STATUS_PENDING_REVIEW,
+                "previous_status": book.status,
+                "moderation": {"report_id": submission.report_id, "status": submission.status}
+            }));
+        }
+        (STATUS_PENDING_REVIEW, Some(submission))
+    } else {
+        (book.status.as_str(), None)
+    };
+
+    let published = go_live(conn, book_id, from_status)?
+        .ok_or_else(|| ServiceError::Conflict("Book status changed while publishing; try again".into()))?;
+
+    crate::json_response(200, serde_json::json!({
+        "id": book_id,
+        "status": STATUS_PUBLISHED,
+        "previous_status": book.status,
+        "published_at": published.published_at,
+        "indexing": published.indexing,
+        "moderation": moderation.map(|m| serde_json::json!({"report_id": m.report_id, "status": m.status}))
+    }))
+}
+
+/// Publish a book that is still in `from_status` and queue it for indexing;
+/// `None` if its status changed in the meantime
+pub fn go_live(conn: &Connection, book_id: &Uuid, from_status: &str) -> Result<Option<Published>, ServiceError> {
+    let book = load_book(conn, book_id)?;
+
+    let now = Utc::now().to_rfc3339();
+    let update = "UPDATE content.books SET status = $2, published_at = $3, updated_at = $3
+                  WHERE id = $1 AND COALESCE(status, 'draft') = $4";
+    let updated = conn.execute(update, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(STATUS_PUBLISHED.to_string()),
+        ParameterValue::Str(now.clone()),
+        ParameterValue::Str(from_status.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    if updated == 0 {
+        return Ok(None);
+    }
+
+    // The book is published either way; a failed index request leaves it
+    // out of search until the next reindex, so it is reported, not fatal
+    let indexing = if book.index_excluded {
+        "excluded"
+    } else if request_indexing(conn, book_id, &book, &now).is_ok() {
+        "queued"
+    } else {
+        "failed"
+    };
+
+    Ok(Some(Published { published_at: now, indexing }))
+}
+
+/// POST /books/:id/unpublish - Take a published book back to draft
+pub fn unpublish(conn: &Connection, book_id: &Uuid) -> Result<Response, ServiceError> {
+    let book = load_book(conn, book_id)?;
+
+    if book.status == STATUS_PENDING_REVIEW && moderation::withdraw(conn, book_id)? {
+        return crate::json_response(200, serde_json::json!({
+            "id": book_id,
+            "status": STATUS_DRAFT,
+            "withdrawn_from_review": true
+        }));
+    }
+    if book.status != STATUS_PUBLISHED {
+        return Err(ServiceError::Validation(vec![ValidationIssue::new(
+            "status",
+            "invalid_transition",
+            format!("Only published books or books under review can be unpublished (current status: {})", book.status),
+        )]));
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let update = "UPDATE content.books SET status = $2, published_at = NULL, updated_at = $3 WHERE id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(STATUS_DRAFT.to_string()),
+        ParameterValue::Str(now.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let index_removal = if crate::remove_from_search_index(book_id).is_ok() { "queued" } else { "failed" };
+
+    crate::json_response(200, serde_json::json!({
+        "id": book_id,
+        "status": STATUS_DRAFT,
+        "unpublished_at": now,
+        "index_removal": index_removal
+    }))
+}
+
+/// Send a published book to discovery again after a change search depends
+/// on; `None` when the book is not in search
+pub fn reindex(conn: &Connection, book_id: &Uuid) -> Result<Option<&'static str>, ServiceError> {
+    let book = load_book(conn, book_id)?;
+    if book.status != STATUS_PUBLISHED || book.index_excluded {
+        return Ok(None);
+    }
+
+    let now = Utc::now().to_rfc3339();
+    Ok(Some(if request_indexing(conn, book_id, &book, &now).is_ok() { "queued" } else { "failed" }))
+}
+
+/// Reject `PUT /books/:id` status changes that would bypass the workflow
+pub fn check_status_update(conn: &Connection, book_id: &Uuid, new_status: &str) -> Result<(), ServiceError> {
+    if new_status == STATUS_PUBLISHED || new_status == STATUS_PENDING_REVIEW {
+        return Err(ServiceError::Validation(vec![ValidationIssue::new(
+            "status", "invalid_transition", "Use POST /books/:id/publish to publish a book",
+        )]));
+    }
+
+    let query = "SELECT COALESCE(status, 'draft') FROM content.books WHERE id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let current = rows.rows.first().map(|row| String::decode(&row[0]).unwrap_or_default());
+
+    if current.as_deref() == Some(STATUS_PUBLISHED) {
+        return Err(ServiceError::Validation(vec![ValidationIssue::new(
+            "status", "invalid_transition", "Use POST /books/:id/unpublish to take a published book down",
+        )]));
+    }
+    if current.as_deref() == Some(STATUS_PENDING_REVIEW) {
+        return Err(ServiceError::Validation(vec![ValidationIssue::new(
+            "status", "invalid_transition", "Book is awaiting moderation review; use POST /books/:id/unpublish to withdraw it",
+        )]));
+    }
+    Ok(())
+}
+
+//=============================================================================
+// Validation
+//=============================================================================
+
+fn validate_for_publish(conn: &Connection, book_id: &Uuid, book: &BookState) -> Result<Vec<ValidationIssue>, ServiceError> {
+    let mut issues = Vec::new();
+
+    if book.title.trim().is_empty() {
+        issues.push(ValidationIssue::new("title", "required", "Title is required"));
+    }
+    if book.description.as_deref().map(|d| d.trim().is_empty()).unwrap_or(true) {
+        issues.push(ValidationIssue::new("description", "required", "A description is required to publish"));
+    }
+    if book.cover_image_url.as_deref().map(|c| c.trim().is_empty()).unwrap_or(true) {
+        issues.push(ValidationIssue::new("cover_image_url", "required", "A cover image is required to publish"));
+    }
+
+    let min_chapters = variables::get("publish_min_chapters")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_MIN_CHAPTERS);
+
+    let query = "SELECT COUNT(*) FILTER (WHERE COALESCE(word_count, 0) > 0), COUNT(*)
+                 FROM content.chapters WHERE book_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let (written, total) = rows.rows.first()
+        .map(|row| (i64::decode(&row[0]).unwrap_or(0), i64::decode(&row[1]).unwrap_or(0)))
+        .unwrap_or((0, 0));
+
+    if written < min_chapters {
+        issues.push(ValidationIssue::new(
+            "chapters",
+            "min_chapters",
+            format!("At least {} chapter(s) with content are required; found {}", min_chapters, written),
+        ));
+    }
+    if total > written {
+        issues.push(ValidationIssue::new(
+            "chapters",
+            "empty_chapters",
+            format!("{} chapter(s) have no content", total - written),
+        ));
+    }
+
+    Ok(issues)
+}
+
+fn load_book(conn: &Connection, book_id: &Uuid) -> Result<BookState, ServiceError> {
+    let query = "SELECT author_id, title, description, genre, COALESCE(status, 'draft'), cover_image_url,
+                        COALESCE(word_count, 0), created_at, COALESCE(index_excluded, false),
+                        content_rating, COALESCE(content_warnings, '[]'::jsonb)::text
+                 FROM content.books WHERE id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Book not found".into()))?;
+
+    Ok(BookState {
+        author_id: String::decode(&row[0]).unwrap_or_default(),
+        title: String::decode(&row[1]).unwrap_or_default(),
+        description: String::decode(&row[2]).ok(),
+        genre: String::decode(&row[3]).ok(),
+        status: String::decode(&row[4]).unwrap_or_default(),
+        cover_image_url: String::decode(&row[5]).ok(),
+        word_count: i32::decode(&row[6]).unwrap_or(0),
+        created_at: String::decode(&row[7]).unwrap_or_default(),
+        index_excluded: bool::decode(&row[8]).unwrap_or(false),
+        content_rating: String::decode(&row[9]).ok(),
+        content_warnings: serde_json::from_str(&String::decode(&row[10]).unwrap_or_default()).unwrap_or_default(),
+    })
+}
+
+//=============================================================================
+// Discovery Indexing
+//=============================================================================
+
+/// Queue the book and its written chapters with the discovery service
+fn request_indexing(conn: &Connection, book_id: &Uuid, book: &BookState, now: &str) -> Result<(), ServiceError> {
+    let query = "SELECT name FROM users.users WHERE id = $1";
+    let author_name = conn.query(query, &[ParameterValue::Str(book.author_id.clone())])
+        .ok()
+        .and_then(|rows| rows.rows.first().and_then(|row| String::decode(&row[0]).ok()));
+
+    send_to_discovery("/index/book", &serde_json::json!({
+        "id": book_id.to_string(),
+        "title": book.title,
+        "description": book.description,
+        "author_id": book.author_id,
+        "author_name": author_name,
+        "genre": book.genre,
+        "status": STATUS_PUBLISHED,
+        "cover_url": book.cover_image_url,
+        "word_count": book.word_count,
+        "content_rating": book.content_rating,
+        "content_warnings": book.content_warnings,
+        "created_at": book.created_at,
+        "updated_at": now
+    }))?;
+
+    let query = "SELECT id, title, content, chapter_number, COALESCE(word_count, 0), created_at, updated_at
+                 FROM content.chapters
+                 WHERE book_id = $1 AND COALESCE(word_count, 0) > 0
+                 ORDER BY chapter_number";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    for row in &rows.rows {
+        send_to_discovery("/index/chapter", &serde_json::json!({
+            "id": String::decode(&row[0]).unwrap_or_default(),
+            "book_id": book_id.to_string(),
+            "title": String::decode(&row[1]).unwrap_or_default(),
+            "content": String::decode(&row[2]).ok(),
+            "chapter_number": i32::decode(&row[3]).unwrap_or(0),
+            "word_count": i32::decode(&row[4]).unwrap_or(0),
+            "content_rating": book.content_rating,
+            "content_warnings": book.content_warnings,
+            "created_at": String::decode(&row[5]).unwrap_or_default(),
+            "updated_at": String::decode(&row[6]).unwrap_or_default()
+        }))?;
+    }
+    Ok(())
+}
+
+fn send_to_discovery(path: &str, body: &serde_json::Value) -> Result<(), ServiceError> {
+    let discovery_url = variables::get("discovery_service_url")
+        .unwrap_or_else(|_| "http://discovery-service:3107".to_string());
+
+    let request = outbound_http::Request::builder()
+        .method(outbound_http::Method::Post)
+        .uri(format!("{}{}", discovery_url, path))
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .build();
+
+    let response = outbound_http::send(request)
+        .map_err(|e| ServiceError::Internal(format!("Index request failed: {}", e)))?;
+
+    if response.status().as_u16() >= 400 {
+        return Err(ServiceError::Internal(format!("Index request failed with status {}", response.status().as_u16())));
+    }
+    Ok(())
+}
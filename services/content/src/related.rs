@@ -0,0 +1,186 @@
+//! Related chapters
+//!
+//! Non-fiction books can ask the content worker to embed every chapter and
+//! suggest conceptually related ones (`source = 'auto'`). Authors curate the
+//! list per chapter: suggestions they drop are hidden rather than deleted, so
+//! the next analysis run does not bring them back, and chapters they add are
+//! kept as `source = 'author'` links the worker never touches. The curated
+//! lists are what the book renderer turns into cross-links.
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::Utc;
+use uuid::Uuid;
+
+pub const ANALYSIS_RELATED_CHAPTERS: &str = "related_chapters";
+
+/// Upper bound on links an author can attach to one chapter
+pub const MAX_RELATED_PER_CHAPTER: usize = 10;
+
+/// Genres (normalized to lowercase, hyphen-separated) treated as non-fiction
+const NON_FICTION_GENRES: [&str; 5] = ["non-fiction", "nonfiction", "biography", "memoir", "self-help"];
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct SetRelatedChaptersRequest {
+    /// Related chapters in display order; replaces the chapter's visible list
+    pub chapter_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RelatedChapter {
+    pub chapter_id: Uuid,
+    pub chapter_number: i32,
+    pub title: String,
+    /// Similarity from the last analysis; absent for links the author added
+    pub score: Option<f64>,
+    /// `auto` or `author`
+    pub source: String,
+}
+
+//=============================================================================
+// Analysis
+//=============================================================================
+
+pub fn is_non_fiction(genre: &str) -> bool {
+    let normalized = genre.trim().to_lowercase().replace([' ', '_'], "-");
+    NON_FICTION_GENRES.contains(&normalized.as_str())
+}
+
+/// Related-chapter analysis only makes sense for non-fiction; fiction
+/// chapters are meant to be read in order
+pub fn ensure_non_fiction(conn: &Connection, book_id: &Uuid) -> Result<(), ServiceError> {
+    let query = "SELECT COALESCE(genre, '') FROM content.books WHERE id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let genre = rows.rows.first()
+        .map(|row| String::decode(&row[0]).unwrap_or_default())
+        .ok_or_else(|| ServiceError::NotFound("Book not found".into()))?;
+
+    if !is_non_fiction(&genre) {
+        return Err(ServiceError::BadRequest(format!(
+            "Related chapters are only available for non-fiction books (genre: {})",
+            if genre.is_empty() { "not set" } else { genre.as_str() }
+        )));
+    }
+    Ok(())
+}
+
+//=============================================================================
+// Per-chapter Lists
+//=============================================================================
+
+/// GET /chapters/:id/related - Visible related chapters in display order
+pub fn list_related(conn: &Connection, chapter_id: &Uuid) -> Result<Response, ServiceError> {
+    let related = load_related(conn, chapter_id)?;
+    crate::json_response(200, serde_json::json!({
+        "chapter_id": chapter_id,
+        "related": related
+    }))
+}
+
+/// PUT /chapters/:id/related - Replace the chapter's related list
+pub fn set_related(
+    conn: &Connection,
+    book_id: &Uuid,
+    chapter_id: &Uuid,
+    body: SetRelatedChaptersRequest,
+) -> Result<Response, ServiceError> {
+    let mut chapter_ids: Vec<Uuid> = Vec::new();
+    for id in body.chapter_ids {
+        if id == *chapter_id {
+            return Err(ServiceError::BadRequest("A chapter cannot be related to itself".into()));
+        }
+        if !chapter_ids.contains(&id) {
+            chapter_ids.push(id);
+        }
+    }
+    if chapter_ids.len() > MAX_RELATED_PER_CHAPTER {
+        return Err(ServiceError::BadRequest(format!(
+            "At most {} related chapters per chapter", MAX_RELATED_PER_CHAPTER
+        )));
+    }
+
+    let id_list = chapter_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",");
+
+    // Every linked chapter must belong to the same book
+    let query = "SELECT COUNT(*) FROM content.chapters
+                 WHERE book_id = $1 AND id::text = ANY(string_to_array($2, ','))";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(id_list.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let found = rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0);
+    if found != chapter_ids.len() as i64 {
+        return Err(ServiceError::BadRequest("Related chapters must belong to the same book".into()));
+    }
+
+    let now = Utc::now().to_rfc3339();
+
+    // Dropped suggestions are hidden so re-analysis keeps them dropped
+    let hide = "UPDATE content.related_chapters SET hidden = true, updated_at = $3
+                WHERE chapter_id = $1 AND source = 'auto' AND hidden = false
+                  AND NOT (related_chapter_id::text = ANY(string_to_array($2, ',')))";
+    conn.execute(hide, &[
+        ParameterValue::Str(chapter_id.to_string()),
+        ParameterValue::Str(id_list.clone()),
+        ParameterValue::Str(now.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let delete = "DELETE FROM content.related_chapters
+                  WHERE chapter_id = $1 AND source = 'author'
+                    AND NOT (related_chapter_id::text = ANY(string_to_array($2, ',')))";
+    conn.execute(delete, &[
+        ParameterValue::Str(chapter_id.to_string()),
+        ParameterValue::Str(id_list),
+    ]).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    // Kept suggestions stay `auto` with their score; new ones become `author`
+    let upsert = "INSERT INTO content.related_chapters
+                  (id, book_id, chapter_id, related_chapter_id, source, hidden, position, created_at, updated_at)
+                  VALUES ($1, $2, $3, $4, 'author', false, $5, $6, $6)
+                  ON CONFLICT (chapter_id, related_chapter_id) DO UPDATE
+                  SET hidden = false, position = EXCLUDED.position, updated_at = EXCLUDED.updated_at";
+    for (position, related_id) in chapter_ids.iter().enumerate() {
+        conn.execute(upsert, &[
+            ParameterValue::Str(Uuid::new_v4().to_string()),
+            ParameterValue::Str(book_id.to_string()),
+            ParameterValue::Str(chapter_id.to_string()),
+            ParameterValue::Str(related_id.to_string()),
+            ParameterValue::Int32(position as i32),
+            ParameterValue::Str(now.clone()),
+        ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    }
+
+    let related = load_related(conn, chapter_id)?;
+    crate::json_response(200, serde_json::json!({
+        "chapter_id": chapter_id,
+        "related": related
+    }))
+}
+
+fn load_related(conn: &Connection, chapter_id: &Uuid) -> Result<Vec<RelatedChapter>, ServiceError> {
+    let query = "SELECT r.related_chapter_id, c.chapter_number, c.title, r.score, r.source
+                 FROM content.related_chapters r
+                 JOIN content.chapters c ON c.id = r.related_chapter_id
+                 WHERE r.chapter_id = $1 AND r.hidden = false
+                 ORDER BY r.position, r.score DESC NULLS LAST, c.chapter_number";
+    let rows = conn.query(query, &[ParameterValue::Str(chapter_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.iter().map(|row| {
+        RelatedChapter {
+            chapter_id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+            chapter_number: i32::decode(&row[1]).unwrap_or(0),
+            title: String::decode(&row[2]).unwrap_or_default(),
+            score: f64::decode(&row[3]).ok(),
+            source: String::decode(&row[4]).unwrap_or_default(),
+        }
+    }).collect())
+}
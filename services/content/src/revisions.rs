@@ -0,0 +1,482 @@
+//! Chapter revision history
+//!
+//! Every `PUT /chapters/:id` that changes a chapter's title or content first
+//! records the version it is about to replace in `content.chapter_revisions`,
+//! so chapter edits can be reviewed and undone without the editor service.
+//! Revisions are numbered per chapter from 1; restoring one records the
+//! current version first, so a restore can itself be undone.
+//!
+//! Content is stored as a line diff against the previous revision, with a
+//! full copy every `KEYFRAME_INTERVAL` revisions and whenever a diff would not
+//! be smaller. Reading a revision replays diffs forward from the nearest full
+//! copy at or before it, and the result is checked against the revision's
+//! SHA-256 so a broken chain is reported rather than served.
+//!
+//! Retention is per plan tier. When a chapter has more revisions than its
+//! author's plan keeps, the oldest are pruned and the new oldest revision is
+//! rewritten as a full copy, so every chain still starts from one.
+
+use crate::common::sha256_hex;
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::Utc;
+use uuid::Uuid;
+
+/// Revisions between full copies
+const KEYFRAME_INTERVAL: i32 = 20;
+
+/// Largest LCS table a diff may build; bigger changes are stored in full
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+/// A chapter's title and content at one point in time
+#[derive(Debug, Clone)]
+pub struct ChapterState {
+    pub title: String,
+    pub content: String,
+    pub word_count: i32,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevisionSummary {
+    pub revision_number: i32,
+    pub title: String,
+    pub word_count: i32,
+    /// `update` or `restore`: what replaced this version
+    pub reason: String,
+    pub saved_at: Option<String>,
+    pub created_by: Option<Uuid>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Revision {
+    #[serde(flatten)]
+    pub summary: RevisionSummary,
+    pub content: String,
+    pub content_sha256: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RestoreOutcome {
+    pub restored_revision: i32,
+    /// Revision holding the version the restore replaced
+    pub captured_revision: Option<i32>,
+    pub title: String,
+    pub word_count: i32,
+    pub previous_word_count: i32,
+    pub updated_at: String,
+}
+
+/// One step of a line diff. `Insert` holds the inserted lines verbatim,
+/// line endings included.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Edit {
+    Keep(usize),
+    Delete(usize),
+    Insert(String),
+}
+
+//=============================================================================
+// Retention
+//=============================================================================
+
+/// Revisions kept per chapter; authors without a subscription are on `free`
+fn plan_retention(plan_id: &str) -> i64 {
+    match plan_id {
+        "pro" => 200,
+        "enterprise" => 1000,
+        _ => 20,
+    }
+}
+
+fn author_plan(conn: &Connection, chapter_id: &Uuid) -> Result<String, ServiceError> {
+    let query = "SELECT COALESCE((SELECT s.plan_id FROM subscriptions.subscriptions s WHERE s.user_id = b.author_id), 'free')
+                 FROM content.chapters c JOIN content.books b ON b.id = c.book_id
+                 WHERE c.id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(chapter_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    Ok(rows.rows.first()
+        .and_then(|row| String::decode(&row[0]).ok())
+        .unwrap_or_else(|| "free".into()))
+}
+
+/// Drop revisions beyond the plan's limit, oldest first
+fn prune(conn: &Connection, chapter_id: &Uuid) -> Result<(), ServiceError> {
+    let keep = plan_retention(&author_plan(conn, chapter_id)?);
+
+    let query = "SELECT revision_number FROM content.chapter_revisions
+                 WHERE chapter_id = $1
+                 ORDER BY revision_number DESC
+                 OFFSET $2 LIMIT 1";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(chapter_id.to_string()),
+        ParameterValue::Int64(keep),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let cutoff = match rows.rows.first().and_then(|row| i32::decode(&row[0]).ok()) {
+        Some(cutoff) => cutoff,
+        None => return Ok(()),
+    };
+
+    // The new oldest revision may be a diff against one about to go
+    let query = "SELECT revision_number, content IS NULL FROM content.chapter_revisions
+                 WHERE chapter_id = $1 AND revision_number > $2
+                 ORDER BY revision_number LIMIT 1";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(chapter_id.to_string()),
+        ParameterValue::Int32(cutoff),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if let Some(row) = rows.rows.first() {
+        let oldest = i32::decode(&row[0]).unwrap_or(0);
+        if bool::decode(&row[1]).unwrap_or(false) {
+            let content = load_content(conn, chapter_id, oldest)?;
+            conn.execute(
+                "UPDATE content.chapter_revisions SET content = $3, diff = NULL
+                 WHERE chapter_id = $1 AND revision_number = $2",
+                &[
+                    ParameterValue::Str(chapter_id.to_string()),
+                    ParameterValue::Int32(oldest),
+                    ParameterValue::Str(content),
+                ],
+            ).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+        }
+    }
+
+    conn.execute(
+        "DELETE FROM content.chapter_revisions WHERE chapter_id = $1 AND revision_number <= $2",
+        &[
+            ParameterValue::Str(chapter_id.to_string()),
+            ParameterValue::Int32(cutoff),
+        ],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    Ok(())
+}
+
+//=============================================================================
+// Capture
+//=============================================================================
+
+pub fn current_state(conn: &Connection, chapter_id: &Uuid) -> Result<ChapterState, ServiceError> {
+    let query = "SELECT COALESCE(title, ''), COALESCE(content, ''), COALESCE(word_count, 0), updated_at::text
+                 FROM content.chapters WHERE id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(chapter_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first().ok_or_else(|| ServiceError::NotFound("Chapter not found".into()))?;
+
+    Ok(ChapterState {
+        title: String::decode(&row[0]).unwrap_or_default(),
+        content: String::decode(&row[1]).unwrap_or_default(),
+        word_count: i32::decode(&row[2]).unwrap_or(0),
+        updated_at: String::decode(&row[3]).ok(),
+    })
+}
+
+/// Record `state` as the chapter's next revision. Returns the revision
+/// number, or `None` when it matches the latest revision already.
+pub fn capture(
+    conn: &Connection,
+    chapter_id: &Uuid,
+    user_id: &Uuid,
+    state: &ChapterState,
+    reason: &str,
+) -> Result<Option<i32>, ServiceError> {
+    let content_sha256 = sha256_hex(state.content.as_bytes());
+
+    let query = "SELECT revision_number, title, content_sha256 FROM content.chapter_revisions
+                 WHERE chapter_id = $1
+                 ORDER BY revision_number DESC LIMIT 1";
+    let rows = conn.query(query, &[ParameterValue::Str(chapter_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let latest = rows.rows.first().map(|row| (
+        i32::decode(&row[0]).unwrap_or(0),
+        String::decode(&row[1]).unwrap_or_default(),
+        String::decode(&row[2]).unwrap_or_default(),
+    ));
+
+    if let Some((_, title, sha)) = &latest {
+        if *title == state.title && *sha == content_sha256 {
+            return Ok(None);
+        }
+    }
+
+    let revision_number = latest.as_ref().map(|(n, _, _)| n + 1).unwrap_or(1);
+    let diff = match &latest {
+        Some((previous, _, _)) if revision_number % KEYFRAME_INTERVAL != 0 => {
+            // An unreadable chain just means this revision starts a new one
+            load_content(conn, chapter_id, *previous).ok()
+                .and_then(|base| diff_lines(&base, &state.content))
+                .and_then(|edits| serde_json::to_string(&edits).ok())
+                .filter(|diff| diff.len() < state.content.len())
+        }
+        _ => None,
+    };
+
+    let insert = "INSERT INTO content.chapter_revisions
+                  (id, chapter_id, revision_number, title, word_count, content, diff, content_sha256,
+                   reason, saved_at, created_by, created_at)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7::jsonb, $8, $9, $10::timestamptz, $11, $12)
+                  ON CONFLICT (chapter_id, revision_number) DO NOTHING";
+    let params = [
+        ParameterValue::Str(Uuid::new_v4().to_string()),
+        ParameterValue::Str(chapter_id.to_string()),
+        ParameterValue::Int32(revision_number),
+        ParameterValue::Str(state.title.clone()),
+        ParameterValue::Int32(state.word_count),
+        match diff {
+            Some(_) => ParameterValue::DbNull,
+            None => ParameterValue::Str(state.content.clone()),
+        },
+        diff.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(content_sha256),
+        ParameterValue::Str(reason.to_string()),
+        state.updated_at.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ];
+    // A concurrent update took this number; its capture of the same version stands
+    let inserted = conn.execute(insert, &params)
+        .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    if inserted == 0 {
+        return Ok(None);
+    }
+
+    prune(conn, chapter_id)?;
+    Ok(Some(revision_number))
+}
+
+//=============================================================================
+// Reading & Restoring
+//=============================================================================
+
+/// GET /chapters/:id/revisions - Newest first
+pub fn list_revisions(conn: &Connection, chapter_id: &Uuid) -> Result<serde_json::Value, ServiceError> {
+    let query = "SELECT revision_number, title, word_count, reason, saved_at::text, created_by::text, created_at::text
+                 FROM content.chapter_revisions
+                 WHERE chapter_id = $1
+                 ORDER BY revision_number DESC";
+    let rows = conn.query(query, &[ParameterValue::Str(chapter_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let revisions: Vec<RevisionSummary> = rows.rows.iter().map(|row| summary_from_row(row)).collect();
+
+    let plan_id = author_plan(conn, chapter_id)?;
+    Ok(serde_json::json!({
+        "revisions": revisions,
+        "retention": {
+            "plan_id": plan_id,
+            "max_revisions": plan_retention(&plan_id)
+        }
+    }))
+}
+
+/// GET /chapters/:id/revisions/:n - One revision with its full content
+pub fn get_revision(conn: &Connection, chapter_id: &Uuid, revision_number: i32) -> Result<Revision, ServiceError> {
+    let query = "SELECT revision_number, title, word_count, reason, saved_at::text, created_by::text, created_at::text,
+                        content_sha256
+                 FROM content.chapter_revisions
+                 WHERE chapter_id = $1 AND revision_number = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(chapter_id.to_string()),
+        ParameterValue::Int32(revision_number),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound(format!("Revision {} not found", revision_number)))?;
+
+    Ok(Revision {
+        summary: summary_from_row(row),
+        content: load_content(conn, chapter_id, revision_number)?,
+        content_sha256: String::decode(&row[7]).unwrap_or_default(),
+    })
+}
+
+/// POST /chapters/:id/revisions/:n/restore - Put a revision's title and
+/// content back on the chapter, recording the current version first
+pub fn restore_revision(
+    conn: &Connection,
+    chapter_id: &Uuid,
+    user_id: &Uuid,
+    revision_number: i32,
+) -> Result<RestoreOutcome, ServiceError> {
+    let revision = get_revision(conn, chapter_id, revision_number)?;
+    let current = current_state(conn, chapter_id)?;
+    let captured_revision = capture(conn, chapter_id, user_id, &current, "restore")?;
+
+    let now = Utc::now().to_rfc3339();
+    let word_count = revision.content.split_whitespace().count() as i32;
+    conn.execute(
+        "UPDATE content.chapters SET title = $2, content = $3, word_count = $4, updated_at = $5 WHERE id = $1",
+        &[
+            ParameterValue::Str(chapter_id.to_string()),
+            ParameterValue::Str(revision.summary.title.clone()),
+            ParameterValue::Str(revision.content),
+            ParameterValue::Int32(word_count),
+            ParameterValue::Str(now.clone()),
+        ],
+    ).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    Ok(RestoreOutcome {
+        restored_revision: revision_number,
+        captured_revision,
+        title: revision.summary.title,
+        word_count,
+        previous_word_count: current.word_count,
+        updated_at: now,
+    })
+}
+
+fn summary_from_row(row: &[spin_sdk::pg::DbValue]) -> RevisionSummary {
+    RevisionSummary {
+        revision_number: i32::decode(&row[0]).unwrap_or(0),
+        title: String::decode(&row[1]).unwrap_or_default(),
+        word_count: i32::decode(&row[2]).unwrap_or(0),
+        reason: String::decode(&row[3]).unwrap_or_else(|_| "update".into()),
+        saved_at: String::decode(&row[4]).ok(),
+        created_by: String::decode(&row[5]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+        created_at: String::decode(&row[6]).unwrap_or_default(),
+    }
+}
+
+/// Rebuild a revision's content from the nearest full copy at or before it
+fn load_content(conn: &Connection, chapter_id: &Uuid, revision_number: i32) -> Result<String, ServiceError> {
+    let query = "SELECT revision_number, content, diff::text, content_sha256 FROM content.chapter_revisions
+                 WHERE chapter_id = $1 AND revision_number <= $2
+                   AND revision_number >= (
+                       SELECT MAX(revision_number) FROM content.chapter_revisions
+                       WHERE chapter_id = $1 AND revision_number <= $2 AND content IS NOT NULL
+                   )
+                 ORDER BY revision_number";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(chapter_id.to_string()),
+        ParameterValue::Int32(revision_number),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let broken = || ServiceError::Internal(format!("Revision {} could not be reconstructed", revision_number));
+    let mut content: Option<String> = None;
+    let mut expected = 0;
+    let mut sha = String::new();
+
+    for row in &rows.rows {
+        let number = i32::decode(&row[0]).unwrap_or(0);
+        if content.is_some() && number != expected {
+            return Err(broken());
+        }
+        content = match (String::decode(&row[1]).ok(), content) {
+            (Some(full), _) => Some(full),
+            (None, Some(base)) => {
+                let edits: Vec<Edit> = String::decode(&row[2]).ok()
+                    .and_then(|diff| serde_json::from_str(&diff).ok())
+                    .ok_or_else(broken)?;
+                Some(apply_edits(&base, &edits).ok_or_else(broken)?)
+            }
+            (None, None) => return Err(broken()),
+        };
+        expected = number + 1;
+        sha = String::decode(&row[3]).unwrap_or_default();
+    }
+
+    match content {
+        Some(content) if expected == revision_number + 1 && sha_matches(&content, &sha) => Ok(content),
+        Some(_) => Err(broken()),
+        None => Err(ServiceError::NotFound(format!("Revision {} not found", revision_number))),
+    }
+}
+
+fn sha_matches(content: &str, sha: &str) -> bool {
+    sha256_hex(content.as_bytes()) == sha
+}
+
+//=============================================================================
+// Line Diff
+//=============================================================================
+
+/// Edits turning `old` into `new`, or `None` when the changed region is too
+/// large to diff cheaply
+fn diff_lines(old: &str, new: &str) -> Option<Vec<Edit>> {
+    let a: Vec<&str> = old.split_inclusive('\n').collect();
+    let b: Vec<&str> = new.split_inclusive('\n').collect();
+
+    // Most saves touch a small region; only that part needs the LCS table
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+
+    let (n, m) = (a_mid.len(), b_mid.len());
+    if (n + 1).saturating_mul(m + 1) > MAX_DIFF_CELLS {
+        return None;
+    }
+
+    // lcs[i * (m + 1) + j] = LCS length of a_mid[i..] and b_mid[j..]
+    let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i * (m + 1) + j] = if a_mid[i] == b_mid[j] {
+                lcs[(i + 1) * (m + 1) + j + 1] + 1
+            } else {
+                lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::new();
+    push_edit(&mut edits, Edit::Keep(prefix));
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && a_mid[i] == b_mid[j] {
+            push_edit(&mut edits, Edit::Keep(1));
+            i += 1;
+            j += 1;
+        } else if j < m && (i == n || lcs[i * (m + 1) + j + 1] >= lcs[(i + 1) * (m + 1) + j]) {
+            push_edit(&mut edits, Edit::Insert(b_mid[j].to_string()));
+            j += 1;
+        } else {
+            push_edit(&mut edits, Edit::Delete(1));
+            i += 1;
+        }
+    }
+    push_edit(&mut edits, Edit::Keep(suffix));
+    Some(edits)
+}
+
+/// Append an edit, merging it into the last one when they are the same kind
+fn push_edit(edits: &mut Vec<Edit>, edit: Edit) {
+    match (edits.last_mut(), edit) {
+        (_, Edit::Keep(0)) | (_, Edit::Delete(0)) => {}
+        (Some(Edit::Keep(n)), Edit::Keep(more)) => *n += more,
+        (Some(Edit::Delete(n)), Edit::Delete(more)) => *n += more,
+        (Some(Edit::Insert(text)), Edit::Insert(more)) => text.push_str(&more),
+        (_, edit) => edits.push(edit),
+    }
+}
+
+/// Replay edits over `base`; `None` if they do not fit it exactly
+fn apply_edits(base: &str, edits: &[Edit]) -> Option<String> {
+    let mut lines = base.split_inclusive('\n');
+    let mut out = String::with_capacity(base.len());
+
+    for edit in edits {
+        match edit {
+            Edit::Keep(n) => {
+                for _ in 0..*n {
+                    out.push_str(lines.next()?);
+                }
+            }
+            Edit::Delete(n) => {
+                for _ in 0..*n {
+                    lines.next()?;
+                }
+            }
+            Edit::Insert(text) => out.push_str(text),
+        }
+    }
+
+    match lines.next() {
+        Some(_) => None,
+        None => Some(out),
+    }
+}
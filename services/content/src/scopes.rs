@@ -0,0 +1,41 @@
+//! Content routes open to delegated credentials
+//!
+//! Enforcement lives in `authorworks-access`; this is the content service's
+//! map. Chapters and generation jobs follow the chapter scopes, exports and
+//! snapshots need `exports:create` to start, and the rest of a book, its
+//! series and the template catalogue follow the book scopes.
+
+use authorworks_access::{scopes, Route};
+use spin_sdk::http::Method;
+
+pub fn route_scope(method: &Method, path: &str) -> Option<Route> {
+    // The public feed is open to everyone, scoped credentials included
+    if path.starts_with("/public/") {
+        return Some(Route::Open);
+    }
+    // Feedback submissions are gated by their own feedback token
+    if matches!(method, Method::Post) && path.starts_with("/chapters/") && path.ends_with("/feedback") {
+        return Some(Route::Open);
+    }
+
+    // Internal sweep, not for delegated credentials
+    if path == "/generate/book/advance" {
+        return None;
+    }
+    if path.starts_with("/chapters/") || path.ends_with("/chapters") || path.starts_with("/generate/")
+        || path.starts_with("/jobs/")
+    {
+        return Some(Route::read_write(method, scopes::CHAPTERS_READ, scopes::CHAPTERS_WRITE));
+    }
+    if path.starts_with("/exports/") || path.ends_with("/exports")
+        || path.starts_with("/snapshots/") || path.ends_with("/snapshots")
+    {
+        return Some(Route::read_write(method, scopes::BOOKS_READ, scopes::EXPORTS_CREATE));
+    }
+    if path == "/books" || path.starts_with("/books/") || path == "/series" || path.starts_with("/series/")
+        || path == "/templates"
+    {
+        return Some(Route::read_write(method, scopes::BOOKS_READ, scopes::BOOKS_WRITE));
+    }
+    None
+}
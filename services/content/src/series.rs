@@ -0,0 +1,278 @@
+//! Book series
+//!
+//! Books can be grouped into an ordered series owned by one author. Besides
+//! the aggregate series view, the series gives AI generation jobs the story
+//! so far: outline and chapter jobs for book N carry the synopses of the
+//! books before it, taken from the outline synopsis stored in the book's
+//! metadata or, failing that, its description.
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::Utc;
+use uuid::Uuid;
+
+/// Earlier books passed to generation jobs, nearest first
+const MAX_CONTEXT_BOOKS: i64 = 3;
+/// Per-book synopsis cap in generation context, in characters
+const MAX_SYNOPSIS_CHARS: usize = 4000;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSeriesRequest {
+    pub title: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetBookSeriesRequest {
+    /// `null` removes the book from its series
+    pub series_id: Option<Uuid>,
+    /// 1-based position; defaults to the end of the series. Books at or after
+    /// the position move up one.
+    pub order_index: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SeriesBook {
+    pub id: Uuid,
+    pub title: String,
+    pub order_index: i32,
+    pub status: String,
+    pub word_count: i32,
+    pub chapter_count: i64,
+    pub updated_at: String,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /series - Create an empty series
+pub fn create(conn: &Connection, user_id: &Uuid, body: CreateSeriesRequest) -> Result<Response, ServiceError> {
+    let title = body.title.trim();
+    if title.is_empty() || title.chars().count() > 255 {
+        return Err(ServiceError::BadRequest("title must be 1-255 characters".into()));
+    }
+
+    let series_id = Uuid::new_v4();
+    let now = Utc::now().to_rfc3339();
+    let insert = "INSERT INTO content.series (id, author_id, title, description, created_at, updated_at)
+                  VALUES ($1, $2, $3, $4, $5, $5)";
+    conn.execute(insert, &[
+        ParameterValue::Str(series_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(title.to_string()),
+        body.description.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(now.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    crate::json_response(201, serde_json::json!({
+        "id": series_id,
+        "title": title,
+        "description": body.description,
+        "books": [],
+        "created_at": now
+    }))
+}
+
+/// GET /series/:id - Series with its books in order and aggregate counts
+pub fn get(conn: &Connection, series_id: &Uuid, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT title, description, created_at, updated_at
+                 FROM content.series WHERE id = $1 AND author_id = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(series_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first().ok_or_else(|| ServiceError::NotFound("Series not found".into()))?;
+    let title = String::decode(&row[0]).unwrap_or_default();
+    let description = String::decode(&row[1]).ok();
+    let created_at = String::decode(&row[2]).unwrap_or_default();
+    let updated_at = String::decode(&row[3]).unwrap_or_default();
+
+    let books_query = "SELECT b.id, b.title, b.series_order, b.status, b.word_count,
+                              (SELECT COUNT(*) FROM content.chapters c WHERE c.book_id = b.id),
+                              b.updated_at
+                       FROM content.books b
+                       WHERE b.series_id = $1
+                       ORDER BY b.series_order";
+    let rows = conn.query(books_query, &[ParameterValue::Str(series_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let books: Vec<SeriesBook> = rows.rows.iter().map(|row| SeriesBook {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        title: String::decode(&row[1]).unwrap_or_default(),
+        order_index: i32::decode(&row[2]).unwrap_or(0),
+        status: String::decode(&row[3]).unwrap_or_else(|_| "draft".into()),
+        word_count: i32::decode(&row[4]).unwrap_or(0),
+        chapter_count: i64::decode(&row[5]).unwrap_or(0),
+        updated_at: String::decode(&row[6]).unwrap_or_default(),
+    }).collect();
+
+    let total_word_count: i64 = books.iter().map(|b| b.word_count as i64).sum();
+    let total_chapters: i64 = books.iter().map(|b| b.chapter_count).sum();
+
+    crate::json_response(200, serde_json::json!({
+        "id": series_id,
+        "title": title,
+        "description": description,
+        "books": books,
+        "book_count": books.len(),
+        "total_word_count": total_word_count,
+        "total_chapters": total_chapters,
+        "created_at": created_at,
+        "updated_at": updated_at
+    }))
+}
+
+/// PUT /books/:id/series - Place a book in a series, move it, or remove it
+pub fn set_book_series(conn: &Connection, book_id: &Uuid, user_id: &Uuid, body: SetBookSeriesRequest) -> Result<Response, ServiceError> {
+    if body.order_index.is_some_and(|i| i < 1) {
+        return Err(ServiceError::BadRequest("order_index must be 1 or greater".into()));
+    }
+
+    // Take the book out of its current series first, closing the gap it leaves
+    detach(conn, book_id)?;
+
+    let series_id = match body.series_id {
+        Some(series_id) => series_id,
+        None => {
+            return crate::json_response(200, serde_json::json!({
+                "book_id": book_id,
+                "series_id": null,
+                "order_index": null
+            }));
+        }
+    };
+
+    let owned = conn.query(
+        "SELECT 1 FROM content.series WHERE id = $1 AND author_id = $2",
+        &[ParameterValue::Str(series_id.to_string()), ParameterValue::Str(user_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if owned.rows.is_empty() {
+        return Err(ServiceError::NotFound("Series not found".into()));
+    }
+
+    let count = conn.query(
+        "SELECT COUNT(*) FROM content.books WHERE series_id = $1",
+        &[ParameterValue::Str(series_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?
+        .rows.first()
+        .map(|row| i64::decode(&row[0]).unwrap_or(0))
+        .unwrap_or(0) as i32;
+    // Positions stay contiguous, so anything past the end means "last"
+    let order_index = body.order_index.map(|i| i.min(count + 1)).unwrap_or(count + 1);
+
+    let shift = "UPDATE content.books SET series_order = series_order + 1
+                 WHERE series_id = $1 AND series_order >= $2";
+    conn.execute(shift, &[
+        ParameterValue::Str(series_id.to_string()),
+        ParameterValue::Int32(order_index),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let now = Utc::now().to_rfc3339();
+    let update = "UPDATE content.books SET series_id = $2, series_order = $3, updated_at = $4 WHERE id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(series_id.to_string()),
+        ParameterValue::Int32(order_index),
+        ParameterValue::Str(now.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    conn.execute(
+        "UPDATE content.series SET updated_at = $2 WHERE id = $1",
+        &[ParameterValue::Str(series_id.to_string()), ParameterValue::Str(now)],
+    ).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    crate::json_response(200, serde_json::json!({
+        "book_id": book_id,
+        "series_id": series_id,
+        "order_index": order_index
+    }))
+}
+
+//=============================================================================
+// Generation Context
+//=============================================================================
+
+/// Series context for a generation job, or `None` for standalone books and
+/// the first book of a series
+pub fn generation_context(conn: &Connection, book_id: &Uuid) -> Result<Option<serde_json::Value>, ServiceError> {
+    let query = "SELECT s.title, b.series_order, b.series_id
+                 FROM content.books b
+                 JOIN content.series s ON s.id = b.series_id
+                 WHERE b.id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = match rows.rows.first() {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let series_title = String::decode(&row[0]).unwrap_or_default();
+    let book_number = i32::decode(&row[1]).unwrap_or(1);
+    let series_id = String::decode(&row[2]).unwrap_or_default();
+
+    let previous_query = "SELECT title, series_order,
+                                 COALESCE(NULLIF(metadata->>'synopsis', ''), description, '')
+                          FROM content.books
+                          WHERE series_id = $1 AND series_order < $2
+                          ORDER BY series_order DESC
+                          LIMIT $3";
+    let rows = conn.query(previous_query, &[
+        ParameterValue::Str(series_id),
+        ParameterValue::Int32(book_number),
+        ParameterValue::Int64(MAX_CONTEXT_BOOKS),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if rows.rows.is_empty() {
+        return Ok(None);
+    }
+
+    // Oldest first reads naturally in a prompt
+    let previous_books: Vec<serde_json::Value> = rows.rows.iter().rev().map(|row| {
+        let synopsis: String = String::decode(&row[2]).unwrap_or_default()
+            .chars()
+            .take(MAX_SYNOPSIS_CHARS)
+            .collect();
+        serde_json::json!({
+            "title": String::decode(&row[0]).unwrap_or_default(),
+            "book_number": i32::decode(&row[1]).unwrap_or(0),
+            "synopsis": synopsis
+        })
+    }).collect();
+
+    Ok(Some(serde_json::json!({
+        "series_title": series_title,
+        "book_number": book_number,
+        "previous_books": previous_books
+    })))
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+fn detach(conn: &Connection, book_id: &Uuid) -> Result<(), ServiceError> {
+    let query = "WITH old AS (
+                     SELECT id, series_id, series_order FROM content.books
+                     WHERE id = $1 AND series_id IS NOT NULL
+                 )
+                 UPDATE content.books b SET series_id = NULL, series_order = NULL
+                 FROM old WHERE b.id = old.id
+                 RETURNING old.series_id, old.series_order";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    if let Some(row) = rows.rows.first() {
+        let close_gap = "UPDATE content.books SET series_order = series_order - 1
+                         WHERE series_id = $1 AND series_order > $2";
+        conn.execute(close_gap, &[
+            ParameterValue::Str(String::decode(&row[0]).unwrap_or_default()),
+            ParameterValue::Int32(i32::decode(&row[1]).unwrap_or(0)),
+        ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,511 @@
+//! Content snapshots and pinned exports
+//!
+//! A snapshot is an immutable copy of a book's chapters taken at a point in
+//! time (rows are protected from updates by a trigger in migration 025).
+//! Every export renders from a snapshot, never from live chapters, and
+//! records the snapshot alongside the artifact's checksum. Rendering is
+//! deterministic, so downloading or re-exporting an export reproduces the
+//! original file byte for byte, and the snapshot can be compared against
+//! the current chapters to see what changed since the file was sent out.
+
+use crate::common::sha256_hex;
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::Utc;
+use uuid::Uuid;
+
+pub const EXPORT_FORMATS: [&str; 2] = ["markdown", "json"];
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct CreateExportRequest {
+    /// `markdown` or `json`
+    pub format: String,
+    /// Export an existing snapshot instead of taking a new one
+    pub snapshot_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotChapter {
+    pub chapter_id: Uuid,
+    pub chapter_number: i32,
+    pub title: String,
+    pub content: String,
+    pub content_sha256: String,
+}
+
+#[derive(Debug)]
+pub struct Snapshot {
+    pub id: Uuid,
+    pub book_id: Uuid,
+    pub title: String,
+    pub chapters: Vec<SnapshotChapter>,
+    pub content_sha256: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotSummary {
+    pub id: Uuid,
+    pub reason: String,
+    pub title: String,
+    pub chapter_count: i32,
+    pub word_count: i32,
+    pub content_sha256: String,
+    pub created_by: Option<Uuid>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportRecord {
+    pub id: Uuid,
+    pub book_id: Uuid,
+    pub snapshot_id: Uuid,
+    pub reexport_of: Option<Uuid>,
+    pub format: String,
+    pub filename: String,
+    pub artifact_sha256: String,
+    pub size_bytes: i64,
+    pub created_by: Option<Uuid>,
+    pub created_at: String,
+    pub download_url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChapterComparison {
+    pub chapter_id: Uuid,
+    pub chapter_number: i32,
+    pub title: String,
+    /// `unchanged`, `modified`, `added`, or `removed`
+    pub status: String,
+    pub snapshot_word_count: Option<i32>,
+    pub current_word_count: Option<i32>,
+}
+
+//=============================================================================
+// Snapshots
+//=============================================================================
+
+/// Copy the book's current chapters into a new immutable snapshot
+pub fn create_snapshot(conn: &Connection, book_id: &Uuid, user_id: &Uuid, reason: &str) -> Result<Snapshot, ServiceError> {
+    let query = "SELECT title FROM content.books WHERE id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let title = rows.rows.first()
+        .map(|row| String::decode(&row[0]).unwrap_or_default())
+        .ok_or_else(|| ServiceError::NotFound("Book not found".into()))?;
+
+    let chapters = current_chapters(conn, book_id)?;
+    if chapters.is_empty() {
+        return Err(ServiceError::BadRequest("Book has no chapters to snapshot".into()));
+    }
+    let content_sha256 = chapters_sha256(&chapters)?;
+    let chapters_json = serde_json::to_string(&chapters)
+        .map_err(|e| ServiceError::Internal(format!("JSON error: {}", e)))?;
+    let word_count: usize = chapters.iter().map(|c| c.content.split_whitespace().count()).sum();
+
+    let id = Uuid::new_v4();
+    let insert = "INSERT INTO content.content_snapshots
+                  (id, book_id, created_by, reason, title, chapters, chapter_count, word_count, content_sha256, created_at)
+                  VALUES ($1, $2, $3, $4, $5, $6::jsonb, $7, $8, $9, $10)";
+    conn.execute(insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(reason.to_string()),
+        ParameterValue::Str(title.clone()),
+        ParameterValue::Str(chapters_json),
+        ParameterValue::Int32(chapters.len() as i32),
+        ParameterValue::Int32(word_count as i32),
+        ParameterValue::Str(content_sha256.clone()),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    Ok(Snapshot { id, book_id: *book_id, title, chapters, content_sha256 })
+}
+
+pub fn load_snapshot(conn: &Connection, snapshot_id: &Uuid) -> Result<Snapshot, ServiceError> {
+    let query = "SELECT book_id, title, chapters::text, content_sha256
+                 FROM content.content_snapshots WHERE id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(snapshot_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Snapshot not found".into()))?;
+
+    let chapters: Vec<SnapshotChapter> = serde_json::from_str(&String::decode(&row[2]).unwrap_or_else(|_| "[]".into()))
+        .map_err(|e| ServiceError::Internal(format!("Corrupt snapshot: {}", e)))?;
+
+    Ok(Snapshot {
+        id: *snapshot_id,
+        book_id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        title: String::decode(&row[1]).unwrap_or_default(),
+        chapters,
+        content_sha256: String::decode(&row[3]).unwrap_or_default(),
+    })
+}
+
+/// Book the snapshot belongs to, if the user is its author
+pub fn snapshot_book_id(conn: &Connection, snapshot_id: &Uuid, user_id: &Uuid) -> Result<Uuid, ServiceError> {
+    let query = "SELECT s.book_id FROM content.content_snapshots s
+                 JOIN content.books b ON b.id = s.book_id
+                 WHERE s.id = $1 AND b.author_id = $2";
+    owned_book_id(conn, query, snapshot_id, user_id, "Snapshot not found")
+}
+
+/// POST /books/:id/snapshots - Pin the current content without exporting it
+pub fn snapshot_now(conn: &Connection, book_id: &Uuid, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let snapshot = create_snapshot(conn, book_id, user_id, "manual")?;
+    crate::json_response(201, serde_json::json!({
+        "id": snapshot.id,
+        "book_id": snapshot.book_id,
+        "chapter_count": snapshot.chapters.len(),
+        "content_sha256": snapshot.content_sha256
+    }))
+}
+
+/// GET /books/:id/snapshots - Snapshots newest first, without chapter content
+pub fn list_snapshots(conn: &Connection, book_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT id, reason, title, chapter_count, word_count, content_sha256, created_by, created_at
+                 FROM content.content_snapshots
+                 WHERE book_id = $1
+                 ORDER BY created_at DESC
+                 LIMIT 100";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let snapshots: Vec<SnapshotSummary> = rows.rows.iter().map(|row| {
+        SnapshotSummary {
+            id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+            reason: String::decode(&row[1]).unwrap_or_default(),
+            title: String::decode(&row[2]).unwrap_or_default(),
+            chapter_count: i32::decode(&row[3]).unwrap_or(0),
+            word_count: i32::decode(&row[4]).unwrap_or(0),
+            content_sha256: String::decode(&row[5]).unwrap_or_default(),
+            created_by: String::decode(&row[6]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+            created_at: String::decode(&row[7]).unwrap_or_default(),
+        }
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({ "snapshots": snapshots }))
+}
+
+/// GET /snapshots/:id/compare - Per-chapter differences between a snapshot
+/// and the book's current content
+pub fn compare(conn: &Connection, snapshot_id: &Uuid) -> Result<Response, ServiceError> {
+    let snapshot = load_snapshot(conn, snapshot_id)?;
+    let current = current_chapters(conn, &snapshot.book_id)?;
+    let current_sha256 = chapters_sha256(&current)?;
+
+    let word_count = |c: &SnapshotChapter| c.content.split_whitespace().count() as i32;
+    let mut chapters: Vec<ChapterComparison> = Vec::new();
+
+    for pinned in &snapshot.chapters {
+        let live = current.iter().find(|c| c.chapter_id == pinned.chapter_id);
+        let status = match live {
+            None => "removed",
+            Some(c) if c.content_sha256 == pinned.content_sha256
+                && c.title == pinned.title
+                && c.chapter_number == pinned.chapter_number => "unchanged",
+            Some(_) => "modified",
+        };
+        chapters.push(ChapterComparison {
+            chapter_id: pinned.chapter_id,
+            chapter_number: live.map(|c| c.chapter_number).unwrap_or(pinned.chapter_number),
+            title: live.map(|c| c.title.clone()).unwrap_or_else(|| pinned.title.clone()),
+            status: status.to_string(),
+            snapshot_word_count: Some(word_count(pinned)),
+            current_word_count: live.map(word_count),
+        });
+    }
+    for live in current.iter().filter(|c| !snapshot.chapters.iter().any(|p| p.chapter_id == c.chapter_id)) {
+        chapters.push(ChapterComparison {
+            chapter_id: live.chapter_id,
+            chapter_number: live.chapter_number,
+            title: live.title.clone(),
+            status: "added".to_string(),
+            snapshot_word_count: None,
+            current_word_count: Some(word_count(live)),
+        });
+    }
+    chapters.sort_by_key(|c| c.chapter_number);
+
+    let count = |status: &str| chapters.iter().filter(|c| c.status == status).count();
+    let summary = serde_json::json!({
+        "unchanged": count("unchanged"),
+        "modified": count("modified"),
+        "added": count("added"),
+        "removed": count("removed")
+    });
+
+    crate::json_response(200, serde_json::json!({
+        "snapshot_id": snapshot.id,
+        "book_id": snapshot.book_id,
+        "snapshot_sha256": snapshot.content_sha256,
+        "current_sha256": current_sha256,
+        "changed": snapshot.content_sha256 != current_sha256,
+        "summary": summary,
+        "chapters": chapters
+    }))
+}
+
+fn current_chapters(conn: &Connection, book_id: &Uuid) -> Result<Vec<SnapshotChapter>, ServiceError> {
+    let query = "SELECT id, chapter_number, title, COALESCE(content, '')
+                 FROM content.chapters
+                 WHERE book_id = $1
+                 ORDER BY chapter_number ASC, created_at ASC";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.iter().map(|row| {
+        let content = String::decode(&row[3]).unwrap_or_default();
+        SnapshotChapter {
+            chapter_id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+            chapter_number: i32::decode(&row[1]).unwrap_or(0),
+            title: String::decode(&row[2]).unwrap_or_default(),
+            content_sha256: sha256_hex(content.as_bytes()),
+            content,
+        }
+    }).collect())
+}
+
+/// Book-level checksum over the serialized chapter list
+fn chapters_sha256(chapters: &[SnapshotChapter]) -> Result<String, ServiceError> {
+    let json = serde_json::to_vec(chapters)
+        .map_err(|e| ServiceError::Internal(format!("JSON error: {}", e)))?;
+    Ok(sha256_hex(&json))
+}
+
+//=============================================================================
+// Exports
+//=============================================================================
+
+/// POST /books/:id/exports - Render an export from a new or given snapshot
+pub fn create_export(
+    conn: &Connection,
+    book_id: &Uuid,
+    user_id: &Uuid,
+    body: CreateExportRequest,
+) -> Result<Response, ServiceError> {
+    validate_format(&body.format)?;
+
+    let snapshot = match body.snapshot_id {
+        Some(snapshot_id) => {
+            let snapshot = load_snapshot(conn, &snapshot_id)?;
+            if snapshot.book_id != *book_id {
+                return Err(ServiceError::NotFound("Snapshot not found".into()));
+            }
+            snapshot
+        }
+        None => create_snapshot(conn, book_id, user_id, "export")?,
+    };
+
+    let export = record_export(conn, &snapshot, &body.format, user_id, None)?;
+    crate::json_response(201, export)
+}
+
+/// POST /exports/:id/reexport - Export the same snapshot and format again.
+/// The new artifact is identical to the original.
+pub fn reexport(conn: &Connection, export_id: &Uuid, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let (snapshot_id, format, artifact_sha256) = export_source(conn, export_id)?;
+    let snapshot = load_snapshot(conn, &snapshot_id)?;
+
+    let (bytes, _, _) = render(&snapshot, &format)?;
+    if sha256_hex(&bytes) != artifact_sha256 {
+        return Err(ServiceError::Internal("Re-export does not match the original artifact".into()));
+    }
+
+    let export = record_export(conn, &snapshot, &format, user_id, Some(export_id))?;
+    crate::json_response(201, export)
+}
+
+/// GET /books/:id/exports - Exports newest first
+pub fn list_exports(conn: &Connection, book_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT id, snapshot_id, reexport_of, format, filename, artifact_sha256, size_bytes, created_by, created_at
+                 FROM content.exports
+                 WHERE book_id = $1
+                 ORDER BY created_at DESC
+                 LIMIT 100";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let exports: Vec<ExportRecord> = rows.rows.iter().map(|row| {
+        let id = Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default();
+        ExportRecord {
+            id,
+            book_id: *book_id,
+            snapshot_id: Uuid::parse_str(&String::decode(&row[1]).unwrap_or_default()).unwrap_or_default(),
+            reexport_of: String::decode(&row[2]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+            format: String::decode(&row[3]).unwrap_or_default(),
+            filename: String::decode(&row[4]).unwrap_or_default(),
+            artifact_sha256: String::decode(&row[5]).unwrap_or_default(),
+            size_bytes: i64::decode(&row[6]).unwrap_or(0),
+            created_by: String::decode(&row[7]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+            created_at: String::decode(&row[8]).unwrap_or_default(),
+            download_url: download_url(&id),
+        }
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({ "exports": exports }))
+}
+
+/// GET /exports/:id/download - The export artifact, rebuilt from its
+/// snapshot and checked against the recorded checksum
+pub fn download(conn: &Connection, export_id: &Uuid) -> Result<Response, ServiceError> {
+    let (snapshot_id, format, artifact_sha256) = export_source(conn, export_id)?;
+    let snapshot = load_snapshot(conn, &snapshot_id)?;
+
+    let (bytes, content_type, filename) = render(&snapshot, &format)?;
+    let checksum = sha256_hex(&bytes);
+    if checksum != artifact_sha256 {
+        return Err(ServiceError::Internal("Export artifact does not match its recorded checksum".into()));
+    }
+
+    Ok(Response::builder()
+        .status(200)
+        .header("Content-Type", content_type)
+        .header("Content-Disposition", format!("attachment; filename=\"{}\"", filename))
+        .header("X-Content-SHA256", checksum)
+        .header("X-Snapshot-Id", snapshot.id.to_string())
+        .header("Access-Control-Allow-Origin", "*")
+        .body(bytes)
+        .build())
+}
+
+/// Book the export belongs to, if the user is its author
+pub fn export_book_id(conn: &Connection, export_id: &Uuid, user_id: &Uuid) -> Result<Uuid, ServiceError> {
+    let query = "SELECT e.book_id FROM content.exports e
+                 JOIN content.books b ON b.id = e.book_id
+                 WHERE e.id = $1 AND b.author_id = $2";
+    owned_book_id(conn, query, export_id, user_id, "Export not found")
+}
+
+fn record_export(
+    conn: &Connection,
+    snapshot: &Snapshot,
+    format: &str,
+    user_id: &Uuid,
+    reexport_of: Option<&Uuid>,
+) -> Result<ExportRecord, ServiceError> {
+    let (bytes, _, filename) = render(snapshot, format)?;
+    let artifact_sha256 = sha256_hex(&bytes);
+
+    let id = Uuid::new_v4();
+    let created_at = Utc::now().to_rfc3339();
+    let insert = "INSERT INTO content.exports
+                  (id, book_id, snapshot_id, reexport_of, format, filename, artifact_sha256, size_bytes, created_by, created_at)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)";
+    conn.execute(insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(snapshot.book_id.to_string()),
+        ParameterValue::Str(snapshot.id.to_string()),
+        reexport_of.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(format.to_string()),
+        ParameterValue::Str(filename.clone()),
+        ParameterValue::Str(artifact_sha256.clone()),
+        ParameterValue::Int64(bytes.len() as i64),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(created_at.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    Ok(ExportRecord {
+        id,
+        book_id: snapshot.book_id,
+        snapshot_id: snapshot.id,
+        reexport_of: reexport_of.copied(),
+        format: format.to_string(),
+        filename,
+        artifact_sha256,
+        size_bytes: bytes.len() as i64,
+        created_by: Some(*user_id),
+        created_at,
+        download_url: download_url(&id),
+    })
+}
+
+fn export_source(conn: &Connection, export_id: &Uuid) -> Result<(Uuid, String, String), ServiceError> {
+    let query = "SELECT snapshot_id, format, artifact_sha256 FROM content.exports WHERE id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(export_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Export not found".into()))?;
+
+    Ok((
+        Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        String::decode(&row[1]).unwrap_or_default(),
+        String::decode(&row[2]).unwrap_or_default(),
+    ))
+}
+
+fn validate_format(format: &str) -> Result<(), ServiceError> {
+    if EXPORT_FORMATS.contains(&format) {
+        Ok(())
+    } else {
+        Err(ServiceError::BadRequest(format!(
+            "Unsupported export format '{}'; expected one of: {}", format, EXPORT_FORMATS.join(", ")
+        )))
+    }
+}
+
+fn download_url(export_id: &Uuid) -> String {
+    format!("/exports/{}/download", export_id)
+}
+
+//=============================================================================
+// Rendering
+//=============================================================================
+
+/// Render a snapshot to (bytes, content type, filename). Output depends only
+/// on the snapshot, never on the clock or live content.
+fn render(snapshot: &Snapshot, format: &str) -> Result<(Vec<u8>, &'static str, String), ServiceError> {
+    let stem = format!("{}-{}", slugify(&snapshot.title), &snapshot.id.simple().to_string()[..8]);
+
+    match format {
+        "markdown" => {
+            let mut out = format!("# {}\n\n", snapshot.title);
+            for chapter in &snapshot.chapters {
+                out.push_str(&format!("## Chapter {}: {}\n\n", chapter.chapter_number, chapter.title));
+                out.push_str(chapter.content.trim_end());
+                out.push_str("\n\n");
+            }
+            Ok((out.into_bytes(), "text/markdown; charset=utf-8", format!("{}.md", stem)))
+        }
+        "json" => {
+            let manuscript = serde_json::json!({
+                "book_id": snapshot.book_id,
+                "snapshot_id": snapshot.id,
+                "title": snapshot.title,
+                "content_sha256": snapshot.content_sha256,
+                "chapters": snapshot.chapters
+            });
+            let bytes = serde_json::to_vec_pretty(&manuscript)
+                .map_err(|e| ServiceError::Internal(format!("JSON error: {}", e)))?;
+            Ok((bytes, "application/json", format!("{}.json", stem)))
+        }
+        other => Err(ServiceError::BadRequest(format!("Unsupported export format '{}'", other))),
+    }
+}
+
+fn slugify(title: &str) -> String {
+    let slug: String = title.to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let slug = slug.split('-').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("-");
+    if slug.is_empty() { "book".to_string() } else { slug }
+}
+
+fn owned_book_id(conn: &Connection, query: &str, id: &Uuid, user_id: &Uuid, not_found: &str) -> Result<Uuid, ServiceError> {
+    let rows = conn.query(query, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    rows.rows.first()
+        .and_then(|row| String::decode(&row[0]).ok())
+        .and_then(|s| Uuid::parse_str(&s).ok())
+        .ok_or_else(|| ServiceError::NotFound(not_found.into()))
+}
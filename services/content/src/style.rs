@@ -0,0 +1,403 @@
+//! Prose style analytics
+//!
+//! `GET /chapters/:id/analysis` and `GET /books/:id/analysis` compute prose
+//! statistics from the stored text on every request, so authors get them
+//! without exporting anything. All of it is heuristic and English-only:
+//! - adverbs are `-ly` words outside a list of common non-adverbs, plus a few
+//!   filler intensifiers
+//! - a sentence counts as passive when a form of "to be" is followed by a
+//!   word ending in `-ed` or a common irregular participle, with at most two
+//!   words in between
+//! - dialogue is anything inside straight or curly double quotes
+//! - repeated phrases are two- to four-word sequences that are not made up
+//!   only of stop words
+//!
+//! Book figures are built from the chapters' raw counts rather than by
+//! averaging chapter ratios, so long chapters weigh what they should.
+
+use crate::error::ServiceError;
+use serde::Serialize;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// A phrase has to appear this often to be reported
+const MIN_PHRASE_REPEATS: u32 = 3;
+const MAX_REPEATED_PHRASES: usize = 20;
+const PHRASE_LENGTHS: std::ops::RangeInclusive<usize> = 2..=4;
+
+/// Upper bounds of the sentence-length buckets, in words; the last is open
+const SENTENCE_BUCKETS: &[(usize, &str)] = &[
+    (5, "1-5"),
+    (10, "6-10"),
+    (15, "11-15"),
+    (20, "16-20"),
+    (30, "21-30"),
+    (40, "31-40"),
+    (usize::MAX, "41+"),
+];
+
+const BE_FORMS: &[&str] = &["am", "is", "are", "was", "were", "be", "been", "being", "isn't", "aren't", "wasn't", "weren't"];
+
+const IRREGULAR_PARTICIPLES: &[&str] = &[
+    "beaten", "begun", "bitten", "born", "bought", "bound", "broken", "brought", "built", "caught",
+    "chosen", "done", "drawn", "driven", "eaten", "fallen", "felt", "flown", "forbidden", "forgiven",
+    "forgotten", "found", "frozen", "given", "gone", "grown", "heard", "held", "hidden", "kept",
+    "known", "laid", "led", "left", "lost", "made", "meant", "met", "paid", "ridden", "risen", "said",
+    "seen", "sent", "shaken", "shot", "shown", "sold", "sought", "spent", "spoken", "stolen", "struck",
+    "sworn", "taken", "taught", "thought", "thrown", "told", "torn", "understood", "woken", "won",
+    "worn", "written",
+];
+
+/// `-ly` words that are not adverbs
+const LY_EXCEPTIONS: &[&str] = &[
+    "ally", "anomaly", "apply", "assembly", "belly", "bully", "butterfly", "chilly", "costly",
+    "curly", "deadly", "elderly", "family", "friendly", "holy", "homely", "italy", "jelly", "jolly",
+    "july", "lily", "lively", "lonely", "lovely", "melancholy", "monopoly", "oily", "only", "rally",
+    "reply", "silly", "sly", "supply", "ugly", "unlikely", "likely", "woolly", "wrinkly",
+];
+
+const FILLER_ADVERBS: &[&str] = &["very", "quite", "rather", "somewhat", "almost", "just", "too"];
+
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "from", "had", "has", "have", "he",
+    "her", "his", "i", "in", "is", "it", "its", "me", "my", "of", "on", "or", "she", "so", "that",
+    "the", "their", "them", "they", "this", "to", "was", "we", "were", "with", "you", "your",
+];
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Serialize)]
+pub struct StyleReport {
+    pub word_count: usize,
+    pub sentence_count: usize,
+    pub adverbs: Density,
+    pub passive_voice: PassiveVoice,
+    pub sentence_length: SentenceLength,
+    /// Share of words that appear inside dialogue, 0.0 - 1.0
+    pub dialogue_ratio: f64,
+    pub repeated_phrases: Vec<RepeatedPhrase>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Density {
+    pub count: usize,
+    pub per_1000_words: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PassiveVoice {
+    /// Sentences estimated to be in the passive voice
+    pub sentences: usize,
+    /// Share of all sentences, 0.0 - 1.0
+    pub ratio: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SentenceLength {
+    pub mean: f64,
+    pub median: usize,
+    pub longest: usize,
+    pub distribution: Vec<Bucket>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Bucket {
+    pub words: &'static str,
+    pub sentences: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RepeatedPhrase {
+    pub phrase: String,
+    pub count: u32,
+}
+
+/// Per-chapter line of the book report
+#[derive(Debug, Serialize)]
+pub struct ChapterStyle {
+    pub chapter_id: Uuid,
+    pub chapter_number: i32,
+    pub title: String,
+    pub word_count: usize,
+    pub adverbs_per_1000_words: f64,
+    pub passive_ratio: f64,
+    pub mean_sentence_length: f64,
+    pub dialogue_ratio: f64,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// GET /chapters/:id/analysis
+pub fn chapter_analysis(conn: &Connection, chapter_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT title, chapter_number, COALESCE(content, '') FROM content.chapters WHERE id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(chapter_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Chapter not found".into()))?;
+
+    let counts = Counts::from_text(&String::decode(&row[2]).unwrap_or_default());
+
+    crate::json_response(200, serde_json::json!({
+        "chapter_id": chapter_id,
+        "title": String::decode(&row[0]).unwrap_or_default(),
+        "chapter_number": i32::decode(&row[1]).unwrap_or(0),
+        "analysis": counts.report()
+    }))
+}
+
+/// GET /books/:id/analysis - Whole-book figures plus a line per chapter
+pub fn book_analysis(conn: &Connection, book_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT id, title, chapter_number, COALESCE(content, '')
+                 FROM content.chapters WHERE book_id = $1
+                 ORDER BY chapter_number";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut book = Counts::default();
+    let mut chapters = Vec::with_capacity(rows.rows.len());
+    for row in &rows.rows {
+        let counts = Counts::from_text(&String::decode(&row[3]).unwrap_or_default());
+        chapters.push(ChapterStyle {
+            chapter_id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+            chapter_number: i32::decode(&row[2]).unwrap_or(0),
+            title: String::decode(&row[1]).unwrap_or_default(),
+            word_count: counts.words,
+            adverbs_per_1000_words: counts.adverb_density(),
+            passive_ratio: counts.passive_ratio(),
+            mean_sentence_length: counts.mean_sentence_length(),
+            dialogue_ratio: counts.dialogue_ratio(),
+        });
+        book.merge(counts);
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "book_id": book_id,
+        "analysis": book.report(),
+        "chapters": chapters
+    }))
+}
+
+//=============================================================================
+// Counting
+//=============================================================================
+
+#[derive(Debug, Default)]
+struct Counts {
+    words: usize,
+    adverbs: usize,
+    passive_sentences: usize,
+    dialogue_words: usize,
+    sentence_lengths: Vec<usize>,
+    phrases: HashMap<String, u32>,
+}
+
+impl Counts {
+    fn from_text(text: &str) -> Counts {
+        let mut counts = Counts::default();
+        for sentence in sentences(text) {
+            counts.add_sentence(&sentence);
+        }
+        counts
+    }
+
+    fn add_sentence(&mut self, sentence: &[Word]) {
+        if sentence.is_empty() {
+            return;
+        }
+        self.words += sentence.len();
+        self.sentence_lengths.push(sentence.len());
+        self.dialogue_words += sentence.iter().filter(|w| w.in_dialogue).count();
+        self.adverbs += sentence.iter().filter(|w| is_adverb(&w.text)).count();
+        if is_passive(sentence) {
+            self.passive_sentences += 1;
+        }
+
+        for n in PHRASE_LENGTHS {
+            for window in sentence.windows(n) {
+                if window.iter().all(|w| STOP_WORDS.contains(&w.text.as_str())) {
+                    continue;
+                }
+                let phrase = window.iter().map(|w| w.text.as_str()).collect::<Vec<_>>().join(" ");
+                *self.phrases.entry(phrase).or_insert(0) += 1;
+            }
+        }
+    }
+
+    fn merge(&mut self, other: Counts) {
+        self.words += other.words;
+        self.adverbs += other.adverbs;
+        self.passive_sentences += other.passive_sentences;
+        self.dialogue_words += other.dialogue_words;
+        self.sentence_lengths.extend(other.sentence_lengths);
+        for (phrase, count) in other.phrases {
+            *self.phrases.entry(phrase).or_insert(0) += count;
+        }
+    }
+
+    fn adverb_density(&self) -> f64 {
+        ratio(self.adverbs * 1000, self.words)
+    }
+
+    fn passive_ratio(&self) -> f64 {
+        ratio(self.passive_sentences, self.sentence_lengths.len())
+    }
+
+    fn mean_sentence_length(&self) -> f64 {
+        ratio(self.words, self.sentence_lengths.len())
+    }
+
+    fn dialogue_ratio(&self) -> f64 {
+        ratio(self.dialogue_words, self.words)
+    }
+
+    fn report(&self) -> StyleReport {
+        let mut lengths = self.sentence_lengths.clone();
+        lengths.sort_unstable();
+
+        let distribution = SENTENCE_BUCKETS.iter().enumerate().map(|(i, (max, label))| {
+            let min = if i == 0 { 1 } else { SENTENCE_BUCKETS[i - 1].0 + 1 };
+            Bucket {
+                words: label,
+                sentences: lengths.iter().filter(|len| **len >= min && **len <= *max).count(),
+            }
+        }).collect();
+
+        StyleReport {
+            word_count: self.words,
+            sentence_count: lengths.len(),
+            adverbs: Density {
+                count: self.adverbs,
+                per_1000_words: self.adverb_density(),
+            },
+            passive_voice: PassiveVoice {
+                sentences: self.passive_sentences,
+                ratio: self.passive_ratio(),
+            },
+            sentence_length: SentenceLength {
+                mean: self.mean_sentence_length(),
+                median: lengths.get(lengths.len() / 2).copied().unwrap_or(0),
+                longest: lengths.last().copied().unwrap_or(0),
+                distribution,
+            },
+            dialogue_ratio: self.dialogue_ratio(),
+            repeated_phrases: self.repeated_phrases(),
+        }
+    }
+
+    /// Most repeated phrases, dropping any phrase that only ever appears as
+    /// part of a longer reported one
+    fn repeated_phrases(&self) -> Vec<RepeatedPhrase> {
+        let mut candidates: Vec<(&String, u32)> = self.phrases.iter()
+            .filter(|(_, count)| **count >= MIN_PHRASE_REPEATS)
+            .map(|(phrase, count)| (phrase, *count))
+            .collect();
+        // Most frequent first, longer phrases ahead of their own fragments
+        candidates.sort_by(|a, b| {
+            b.1.cmp(&a.1)
+                .then_with(|| b.0.len().cmp(&a.0.len()))
+                .then_with(|| a.0.cmp(b.0))
+        });
+
+        let mut selected: Vec<RepeatedPhrase> = Vec::new();
+        for (phrase, count) in candidates {
+            let covered = selected.iter()
+                .any(|s| s.count == count && format!(" {} ", s.phrase).contains(&format!(" {} ", phrase)));
+            if covered {
+                continue;
+            }
+            selected.push(RepeatedPhrase { phrase: phrase.clone(), count });
+            if selected.len() == MAX_REPEATED_PHRASES {
+                break;
+            }
+        }
+        selected
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        return 0.0;
+    }
+    let value = numerator as f64 / denominator as f64;
+    (value * 1000.0).round() / 1000.0
+}
+
+//=============================================================================
+// Tokenizing
+//=============================================================================
+
+#[derive(Debug)]
+struct Word {
+    /// Lowercase, without surrounding punctuation
+    text: String,
+    in_dialogue: bool,
+}
+
+/// Split text into sentences of normalized words. Sentences end at `.`, `!`
+/// or `?` and at line breaks; dialogue state carries across sentences within
+/// a paragraph.
+fn sentences(text: &str) -> Vec<Vec<Word>> {
+    let mut sentences = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut in_dialogue = false;
+        let mut sentence: Vec<Word> = Vec::new();
+
+        for token in paragraph.split_whitespace() {
+            // A word takes the dialogue state it starts in; quotes inside
+            // the token switch it for the words after
+            let starts_in_dialogue = in_dialogue || token.starts_with(['"', '“']);
+            for c in token.chars() {
+                match c {
+                    '“' => in_dialogue = true,
+                    '”' => in_dialogue = false,
+                    '"' => in_dialogue = !in_dialogue,
+                    _ => {}
+                }
+            }
+
+            let text = token
+                .trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase()
+                .replace('’', "'");
+            if !text.is_empty() {
+                sentence.push(Word { text, in_dialogue: starts_in_dialogue });
+            }
+
+            let ends_sentence = token
+                .trim_end_matches(['"', '”', '’', '\'', ')'])
+                .ends_with(['.', '!', '?']);
+            if ends_sentence && !sentence.is_empty() {
+                sentences.push(std::mem::take(&mut sentence));
+            }
+        }
+        if !sentence.is_empty() {
+            sentences.push(sentence);
+        }
+    }
+    sentences
+}
+
+fn is_adverb(word: &str) -> bool {
+    if FILLER_ADVERBS.contains(&word) {
+        return true;
+    }
+    word.len() > 4 && word.ends_with("ly") && !LY_EXCEPTIONS.contains(&word)
+}
+
+fn is_participle(word: &str) -> bool {
+    (word.len() > 3 && word.ends_with("ed")) || IRREGULAR_PARTICIPLES.contains(&word)
+}
+
+fn is_passive(sentence: &[Word]) -> bool {
+    sentence.iter().enumerate().any(|(i, word)| {
+        BE_FORMS.contains(&word.text.as_str())
+            && sentence[i + 1..].iter().take(3).any(|next| is_participle(&next.text))
+    })
+}
@@ -0,0 +1,430 @@
+//! Book templates
+//!
+//! `POST /books/from-template` starts a book from an entry in
+//! `content.book_templates`: the book gets the template's genre and metadata,
+//! one draft chapter per front matter page, planned chapter and back matter
+//! page (numbered in that order and tagged with their `section`), and an
+//! outline whose chapters link to the created body chapters. Front and back
+//! matter pages carry placeholder text for the author to replace; body
+//! chapters start empty, with their summary and target length in the outline.
+//!
+//! Templates are managed by admins. Deleting one does not touch books
+//! already created from it; they keep its id in `metadata.template_id`.
+
+use crate::common::required_text;
+use crate::error::ServiceError;
+use crate::outline::{Outline, OutlineChapter, OutlinePart};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const MAX_ID_CHARS: usize = 64;
+const MAX_NAME_CHARS: usize = 255;
+const MAX_CHAPTERS: usize = 200;
+const MAX_TARGET_WORDS: i32 = 1_000_000;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TemplateStructure {
+    #[serde(default)]
+    pub front_matter: Vec<TemplatePage>,
+    #[serde(default)]
+    pub parts: Vec<TemplatePart>,
+    #[serde(default)]
+    pub back_matter: Vec<TemplatePage>,
+}
+
+/// A front or back matter page such as a dedication or author bio
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplatePage {
+    pub title: String,
+    /// Placeholder text the chapter starts with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplatePart {
+    /// May be empty for templates that are not divided into parts
+    #[serde(default)]
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub chapters: Vec<TemplateChapter>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateChapter {
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_words: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FromTemplateRequest {
+    pub template_id: String,
+    pub title: String,
+    pub description: Option<String>,
+    /// Defaults to the template's genre
+    pub genre: Option<String>,
+    /// Merged over the template's metadata
+    pub metadata: Option<HashMap<String, serde_json::Value>>,
+    pub ai_disabled: Option<bool>,
+    pub index_excluded: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTemplateRequest {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    pub genre: Option<String>,
+    pub structure: TemplateStructure,
+    pub metadata: Option<HashMap<String, serde_json::Value>>,
+    pub active: Option<bool>,
+    pub sort_order: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateTemplateRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    pub genre: Option<String>,
+    pub structure: Option<TemplateStructure>,
+    pub metadata: Option<HashMap<String, serde_json::Value>>,
+    pub active: Option<bool>,
+    pub sort_order: Option<i32>,
+}
+
+//=============================================================================
+// Library
+//=============================================================================
+
+/// GET /templates (active only) and GET /admin/templates (all)
+pub fn list(conn: &Connection, include_inactive: bool) -> Result<Response, ServiceError> {
+    let query = "SELECT id, name, description, category, genre, structure::text, metadata::text,
+                        active, sort_order, created_at::text, updated_at::text
+                 FROM content.book_templates
+                 WHERE active OR $1
+                 ORDER BY sort_order ASC, name ASC";
+    let rows = conn.query(query, &[ParameterValue::Boolean(include_inactive)])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let templates: Vec<serde_json::Value> = rows.rows.iter().map(|row| {
+        let structure: TemplateStructure = serde_json::from_str(&String::decode(&row[5]).unwrap_or_default())
+            .unwrap_or_default();
+        let chapter_count: usize = structure.parts.iter().map(|p| p.chapters.len()).sum();
+        serde_json::json!({
+            "id": String::decode(&row[0]).unwrap_or_default(),
+            "name": String::decode(&row[1]).unwrap_or_default(),
+            "description": String::decode(&row[2]).ok(),
+            "category": String::decode(&row[3]).unwrap_or_default(),
+            "genre": String::decode(&row[4]).ok(),
+            "chapter_count": chapter_count,
+            "structure": structure,
+            "metadata": serde_json::from_str::<serde_json::Value>(&String::decode(&row[6]).unwrap_or_default())
+                .unwrap_or_else(|_| serde_json::json!({})),
+            "active": bool::decode(&row[7]).unwrap_or(true),
+            "sort_order": i32::decode(&row[8]).unwrap_or(0),
+            "created_at": String::decode(&row[9]).unwrap_or_default(),
+            "updated_at": String::decode(&row[10]).unwrap_or_default()
+        })
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "templates": templates,
+        "total": templates.len()
+    }))
+}
+
+//=============================================================================
+// Instantiation
+//=============================================================================
+
+/// POST /books/from-template - Create the book, its chapters and its outline.
+/// The caller has already checked the book entitlement.
+pub fn instantiate(conn: &Connection, user_id: &Uuid, body: FromTemplateRequest) -> Result<Response, ServiceError> {
+    let title = body.title.trim().to_string();
+    if title.is_empty() {
+        return Err(ServiceError::BadRequest("title is required".into()));
+    }
+
+    let query = "SELECT genre, structure::text, metadata::text
+                 FROM content.book_templates WHERE id = $1 AND active";
+    let rows = conn.query(query, &[ParameterValue::Str(body.template_id.clone())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Template not found".into()))?;
+
+    let structure: TemplateStructure = serde_json::from_str(&String::decode(&row[1]).unwrap_or_default())
+        .map_err(|e| ServiceError::Internal(format!("Invalid template structure: {}", e)))?;
+    let mut metadata: HashMap<String, serde_json::Value> =
+        serde_json::from_str(&String::decode(&row[2]).unwrap_or_default()).unwrap_or_default();
+    metadata.extend(body.metadata.unwrap_or_default());
+    metadata.insert("template_id".into(), serde_json::json!(body.template_id));
+
+    let genre = body.genre.or_else(|| String::decode(&row[0]).ok()).unwrap_or_default();
+    let book_id = Uuid::new_v4();
+    let now = Utc::now();
+
+    let insert_book = "INSERT INTO content.books (id, author_id, title, description, genre, status, metadata,
+                       ai_disabled, index_excluded, created_at, updated_at)
+                       VALUES ($1, $2, $3, $4, $5, 'draft', $6, $8, $9, $7, $7)";
+    conn.execute(insert_book, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(title.clone()),
+        ParameterValue::Str(body.description.clone().unwrap_or_default()),
+        ParameterValue::Str(genre.clone()),
+        ParameterValue::Str(serde_json::to_string(&metadata).unwrap_or_else(|_| "{}".into())),
+        ParameterValue::Str(now.to_rfc3339()),
+        ParameterValue::Boolean(body.ai_disabled.unwrap_or(false)),
+        ParameterValue::Boolean(body.index_excluded.unwrap_or(false)),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    // (section, title, content) in reading order
+    let mut chapters: Vec<(&str, &str, &str)> = Vec::new();
+    for page in &structure.front_matter {
+        chapters.push(("front_matter", &page.title, page.content.as_deref().unwrap_or("")));
+    }
+    for chapter in structure.parts.iter().flat_map(|p| &p.chapters) {
+        chapters.push(("body", &chapter.title, ""));
+    }
+    for page in &structure.back_matter {
+        chapters.push(("back_matter", &page.title, page.content.as_deref().unwrap_or("")));
+    }
+    let chapter_ids: Vec<Uuid> = chapters.iter().map(|_| Uuid::new_v4()).collect();
+
+    if !chapters.is_empty() {
+        let mut values = Vec::with_capacity(chapters.len());
+        let mut params = vec![
+            ParameterValue::Str(book_id.to_string()),
+            ParameterValue::Str(now.to_rfc3339()),
+        ];
+        for (index, (section, chapter_title, content)) in chapters.iter().enumerate() {
+            let base = params.len();
+            values.push(format!(
+                "(${}, $1, ${}, ${}, ${}, ${}, ${}, 'draft', $2, $2)",
+                base + 1, base + 2, base + 3, base + 4, base + 5, base + 6
+            ));
+            params.push(ParameterValue::Str(chapter_ids[index].to_string()));
+            params.push(ParameterValue::Str(chapter_title.to_string()));
+            params.push(ParameterValue::Str(content.to_string()));
+            params.push(ParameterValue::Int32(index as i32 + 1));
+            params.push(ParameterValue::Int32(content.split_whitespace().count() as i32));
+            params.push(ParameterValue::Str(section.to_string()));
+        }
+        let insert_chapters = format!(
+            "INSERT INTO content.chapters (id, book_id, title, content, chapter_number, word_count, section,
+             status, created_at, updated_at) VALUES {}",
+            values.join(", ")
+        );
+        conn.execute(&insert_chapters, &params)
+            .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+        crate::update_book_word_count(conn, &book_id)?;
+    }
+
+    // Body chapters follow the front matter in `chapter_ids`
+    let mut body_ids = chapter_ids.iter().skip(structure.front_matter.len());
+    let outline = Outline {
+        synopsis: None,
+        parts: structure.parts.iter().map(|part| OutlinePart {
+            id: Uuid::new_v4(),
+            title: part.title.clone(),
+            summary: part.summary.clone(),
+            chapters: part.chapters.iter().map(|chapter| OutlineChapter {
+                id: Uuid::new_v4(),
+                chapter_id: body_ids.next().copied(),
+                title: chapter.title.clone(),
+                summary: chapter.summary.clone(),
+                target_words: chapter.target_words,
+                scenes: Vec::new(),
+            }).collect(),
+        }).collect(),
+    };
+    if !outline.parts.is_empty() {
+        let json = serde_json::to_string(&outline)
+            .map_err(|e| ServiceError::Internal(format!("JSON error: {}", e)))?;
+        let insert_outline = "INSERT INTO content.outlines (book_id, outline, version, updated_by, created_at, updated_at)
+                              VALUES ($1, $2::jsonb, 1, $3, NOW(), NOW())
+                              ON CONFLICT (book_id) DO NOTHING";
+        conn.execute(insert_outline, &[
+            ParameterValue::Str(book_id.to_string()),
+            ParameterValue::Str(json),
+            ParameterValue::Str(user_id.to_string()),
+        ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    }
+
+    let created: Vec<serde_json::Value> = chapters.iter().zip(&chapter_ids).enumerate()
+        .map(|(index, ((section, chapter_title, _), id))| serde_json::json!({
+            "id": id,
+            "title": chapter_title,
+            "chapter_number": index + 1,
+            "section": section
+        }))
+        .collect();
+
+    crate::json_response(201, serde_json::json!({
+        "id": book_id,
+        "title": title,
+        "description": body.description,
+        "genre": genre,
+        "status": "draft",
+        "template_id": body.template_id,
+        "metadata": metadata,
+        "ai_disabled": body.ai_disabled.unwrap_or(false),
+        "index_excluded": body.index_excluded.unwrap_or(false),
+        "chapters": created,
+        "outline_version": if outline.parts.is_empty() { None } else { Some(1) },
+        "created_at": now.to_rfc3339(),
+        "message": "Book created from template"
+    }))
+}
+
+//=============================================================================
+// Admin
+//=============================================================================
+
+/// POST /admin/templates
+pub fn create(conn: &Connection, admin_id: &Uuid, body: CreateTemplateRequest) -> Result<Response, ServiceError> {
+    let id = body.id.trim().to_lowercase();
+    if id.is_empty() || id.len() > MAX_ID_CHARS
+        || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return Err(ServiceError::BadRequest(format!(
+            "id must be 1-{} lowercase letters, digits or hyphens", MAX_ID_CHARS
+        )));
+    }
+    required_text(&body.name, "name", MAX_NAME_CHARS)?;
+    validate_structure(&body.structure)?;
+
+    let insert = "INSERT INTO content.book_templates
+                  (id, name, description, category, genre, structure, metadata, active, sort_order,
+                   created_by, created_at, updated_at)
+                  VALUES ($1, $2, $3, $4, $5, $6::jsonb, $7::jsonb, $8, $9, $10::uuid, NOW(), NOW())
+                  ON CONFLICT (id) DO NOTHING";
+    let inserted = conn.execute(insert, &[
+        ParameterValue::Str(id.clone()),
+        ParameterValue::Str(body.name.trim().to_string()),
+        body.description.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(body.category.unwrap_or_else(|| "fiction".into())),
+        body.genre.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(to_json(&body.structure)?),
+        ParameterValue::Str(to_json(&body.metadata.unwrap_or_default())?),
+        ParameterValue::Boolean(body.active.unwrap_or(true)),
+        ParameterValue::Int32(body.sort_order.unwrap_or(0)),
+        ParameterValue::Str(admin_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    if inserted == 0 {
+        return Err(ServiceError::Conflict(format!("Template {} already exists", id)));
+    }
+
+    crate::json_response(201, serde_json::json!({
+        "id": id,
+        "message": "Template created"
+    }))
+}
+
+/// PUT /admin/templates/:id
+pub fn update(conn: &Connection, template_id: &str, body: UpdateTemplateRequest) -> Result<Response, ServiceError> {
+    if let Some(name) = &body.name {
+        required_text(name, "name", MAX_NAME_CHARS)?;
+    }
+    if let Some(structure) = &body.structure {
+        validate_structure(structure)?;
+    }
+
+    let mut updates = vec!["updated_at = NOW()".to_string()];
+    let mut params: Vec<ParameterValue> = vec![ParameterValue::Str(template_id.to_string())];
+
+    let fields = [
+        ("name", body.name.map(|n| ParameterValue::Str(n.trim().to_string())), ""),
+        ("description", body.description.map(ParameterValue::Str), ""),
+        ("category", body.category.map(ParameterValue::Str), ""),
+        ("genre", body.genre.map(ParameterValue::Str), ""),
+        ("structure", body.structure.as_ref().map(to_json).transpose()?.map(ParameterValue::Str), "::jsonb"),
+        ("metadata", body.metadata.as_ref().map(to_json).transpose()?.map(ParameterValue::Str), "::jsonb"),
+        ("active", body.active.map(ParameterValue::Boolean), ""),
+        ("sort_order", body.sort_order.map(ParameterValue::Int32), ""),
+    ];
+    for (column, value, cast) in fields {
+        if let Some(value) = value {
+            params.push(value);
+            updates.push(format!("{} = ${}{}", column, params.len(), cast));
+        }
+    }
+
+    let query = format!("UPDATE content.book_templates SET {} WHERE id = $1", updates.join(", "));
+    let updated = conn.execute(&query, &params)
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    if updated == 0 {
+        return Err(ServiceError::NotFound("Template not found".into()));
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "id": template_id,
+        "message": "Template updated"
+    }))
+}
+
+/// DELETE /admin/templates/:id
+pub fn delete(conn: &Connection, template_id: &str) -> Result<Response, ServiceError> {
+    let deleted = conn.execute(
+        "DELETE FROM content.book_templates WHERE id = $1",
+        &[ParameterValue::Str(template_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    if deleted == 0 {
+        return Err(ServiceError::NotFound("Template not found".into()));
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "id": template_id,
+        "message": "Template deleted"
+    }))
+}
+
+fn validate_structure(structure: &TemplateStructure) -> Result<(), ServiceError> {
+    let body_chapters = structure.parts.iter().map(|p| p.chapters.len()).sum::<usize>();
+    let total = structure.front_matter.len() + body_chapters + structure.back_matter.len();
+    if total == 0 {
+        return Err(ServiceError::BadRequest("Template must define at least one chapter".into()));
+    }
+    if total > MAX_CHAPTERS {
+        return Err(ServiceError::BadRequest(format!("At most {} chapters per template", MAX_CHAPTERS)));
+    }
+
+    let titles = structure.front_matter.iter().map(|p| &p.title)
+        .chain(structure.parts.iter().flat_map(|p| &p.chapters).map(|c| &c.title))
+        .chain(structure.back_matter.iter().map(|p| &p.title));
+    for title in titles {
+        required_text(title, "Chapter titles", MAX_NAME_CHARS)?;
+    }
+
+    let targets_valid = structure.parts.iter().flat_map(|p| &p.chapters)
+        .filter_map(|c| c.target_words)
+        .all(|t| (1..=MAX_TARGET_WORDS).contains(&t));
+    if !targets_valid {
+        return Err(ServiceError::BadRequest(format!(
+            "target_words must be between 1 and {}", MAX_TARGET_WORDS
+        )));
+    }
+    Ok(())
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, ServiceError> {
+    serde_json::to_string(value).map_err(|e| ServiceError::Internal(format!("JSON error: {}", e)))
+}
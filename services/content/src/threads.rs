@@ -0,0 +1,419 @@
+//! Plot threads
+//!
+//! Authors name the plotlines of a book and tag each chapter (or a scene of
+//! it) with the threads it touches: where a thread is introduced, advanced,
+//! merely mentioned, or resolved. The graph endpoint lays the tags out as
+//! thread-per-chapter presence so a plotline that goes quiet stands out: an
+//! unresolved thread absent from the last `DROPPED_AFTER_CHAPTERS` chapters
+//! is reported as dropped.
+//!
+//! Outline and chapter generation jobs carry the open threads, with where
+//! each was last seen, so generated text keeps them going.
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::Utc;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub const ROLES: [&str; 4] = ["introduced", "advanced", "mentioned", "resolved"];
+
+const MAX_THREADS_PER_BOOK: i64 = 100;
+const MAX_APPEARANCES_PER_CHAPTER: usize = 100;
+const MAX_NAME_CHARS: usize = 255;
+const MAX_DESCRIPTION_CHARS: usize = 5000;
+const MAX_NOTE_CHARS: usize = 1000;
+
+/// Chapters without a sighting, at the end of the book, before an unresolved
+/// thread counts as dropped
+const DROPPED_AFTER_CHAPTERS: i32 = 5;
+
+/// Per-thread description cap in generation context, in characters
+const MAX_CONTEXT_DESCRIPTION_CHARS: usize = 500;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct CreateThreadRequest {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetChapterThreadsRequest {
+    /// Replaces every thread tag on the chapter and its scenes
+    pub threads: Vec<ThreadTag>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadTag {
+    pub thread_id: Uuid,
+    /// Narrows the tag to one scene of the chapter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scene_id: Option<Uuid>,
+    #[serde(default = "default_role")]
+    pub role: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+fn default_role() -> String {
+    "advanced".into()
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlotThread {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ThreadPresence {
+    pub chapter_id: Uuid,
+    pub chapter_number: i32,
+    /// Distinct roles across the chapter and its scenes
+    pub roles: Vec<String>,
+    pub scene_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ThreadLine {
+    #[serde(flatten)]
+    pub thread: PlotThread,
+    /// `untagged`, `open`, `resolved` or `dropped`
+    pub status: &'static str,
+    pub first_chapter: Option<i32>,
+    pub last_chapter: Option<i32>,
+    /// Most consecutive chapters without the thread between two sightings
+    pub longest_gap: i32,
+    pub presence: Vec<ThreadPresence>,
+}
+
+struct Appearance {
+    thread_id: Uuid,
+    chapter_id: Uuid,
+    chapter_number: i32,
+    scene_id: Option<Uuid>,
+    role: String,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /books/:id/threads - Name a plot thread
+pub fn create(conn: &Connection, book_id: &Uuid, body: CreateThreadRequest) -> Result<Response, ServiceError> {
+    let name = body.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_CHARS {
+        return Err(ServiceError::BadRequest(format!("name must be 1-{} characters", MAX_NAME_CHARS)));
+    }
+    let description = body.description.map(|d| d.trim().to_string()).filter(|d| !d.is_empty());
+    if description.as_ref().is_some_and(|d| d.chars().count() > MAX_DESCRIPTION_CHARS) {
+        return Err(ServiceError::BadRequest(format!("description must be at most {} characters", MAX_DESCRIPTION_CHARS)));
+    }
+
+    let query = "SELECT COUNT(*), COUNT(*) FILTER (WHERE lower(name) = lower($2))
+                 FROM content.plot_threads WHERE book_id = $1";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(name.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let (count, same_name) = rows.rows.first()
+        .map(|row| (i64::decode(&row[0]).unwrap_or(0), i64::decode(&row[1]).unwrap_or(0)))
+        .unwrap_or((0, 0));
+    if same_name > 0 {
+        return Err(ServiceError::Conflict(format!("The book already has a thread named '{}'", name)));
+    }
+    if count >= MAX_THREADS_PER_BOOK {
+        return Err(ServiceError::BadRequest(format!("A book can have at most {} plot threads", MAX_THREADS_PER_BOOK)));
+    }
+
+    let id = Uuid::new_v4();
+    let now = Utc::now().to_rfc3339();
+    let insert = "INSERT INTO content.plot_threads (id, book_id, name, description, created_at, updated_at)
+                  VALUES ($1, $2, $3, $4, $5, $5)";
+    conn.execute(insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(name.to_string()),
+        description.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(now.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    crate::json_response(201, PlotThread {
+        id,
+        name: name.to_string(),
+        description,
+        created_at: now,
+    })
+}
+
+/// PUT /chapters/:id/threads - Replace the thread tags of a chapter and its scenes
+pub fn set_chapter_threads(
+    conn: &Connection,
+    book_id: &Uuid,
+    chapter_id: &Uuid,
+    body: SetChapterThreadsRequest,
+) -> Result<Response, ServiceError> {
+    if body.threads.len() > MAX_APPEARANCES_PER_CHAPTER {
+        return Err(ServiceError::BadRequest(format!(
+            "At most {} thread tags per chapter", MAX_APPEARANCES_PER_CHAPTER
+        )));
+    }
+
+    let mut tags: Vec<ThreadTag> = Vec::new();
+    for tag in body.threads {
+        if !ROLES.contains(&tag.role.as_str()) {
+            return Err(ServiceError::BadRequest(format!("role must be one of: {}", ROLES.join(", "))));
+        }
+        if tag.note.as_ref().is_some_and(|n| n.chars().count() > MAX_NOTE_CHARS) {
+            return Err(ServiceError::BadRequest(format!("note must be at most {} characters", MAX_NOTE_CHARS)));
+        }
+        if tags.iter().any(|t| t.thread_id == tag.thread_id && t.scene_id == tag.scene_id) {
+            return Err(ServiceError::BadRequest("Each thread can be tagged once per chapter or scene".into()));
+        }
+        tags.push(tag);
+    }
+
+    let thread_ids = join_ids(tags.iter().map(|t| t.thread_id));
+    let query = "SELECT COUNT(DISTINCT id) FROM content.plot_threads
+                 WHERE book_id = $1 AND id::text = ANY(string_to_array($2, ','))";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(thread_ids.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let found = rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0);
+    let mut distinct_threads: Vec<Uuid> = tags.iter().map(|t| t.thread_id).collect();
+    distinct_threads.sort();
+    distinct_threads.dedup();
+    if found != distinct_threads.len() as i64 {
+        return Err(ServiceError::BadRequest("Threads must belong to the chapter's book".into()));
+    }
+
+    let mut scene_ids: Vec<Uuid> = tags.iter().filter_map(|t| t.scene_id).collect();
+    scene_ids.sort();
+    scene_ids.dedup();
+    if !scene_ids.is_empty() {
+        let query = "SELECT COUNT(*) FROM content.scenes
+                     WHERE chapter_id = $1 AND id::text = ANY(string_to_array($2, ','))";
+        let rows = conn.query(query, &[
+            ParameterValue::Str(chapter_id.to_string()),
+            ParameterValue::Str(join_ids(scene_ids.iter().copied())),
+        ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+        let found = rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0);
+        if found != scene_ids.len() as i64 {
+            return Err(ServiceError::BadRequest("Scenes must belong to the chapter".into()));
+        }
+    }
+
+    let delete = "DELETE FROM content.plot_thread_appearances WHERE chapter_id = $1";
+    conn.execute(delete, &[ParameterValue::Str(chapter_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    let insert = "INSERT INTO content.plot_thread_appearances (id, thread_id, chapter_id, scene_id, role, note)
+                  VALUES ($1, $2, $3, $4::uuid, $5, $6)";
+    for tag in &tags {
+        conn.execute(insert, &[
+            ParameterValue::Str(Uuid::new_v4().to_string()),
+            ParameterValue::Str(tag.thread_id.to_string()),
+            ParameterValue::Str(chapter_id.to_string()),
+            tag.scene_id.map(|s| ParameterValue::Str(s.to_string())).unwrap_or(ParameterValue::DbNull),
+            ParameterValue::Str(tag.role.clone()),
+            tag.note.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "chapter_id": chapter_id,
+        "threads": tags
+    }))
+}
+
+/// GET /books/:id/threads/graph - Thread presence per chapter, with dropped plotlines flagged
+pub fn graph(conn: &Connection, book_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT id, chapter_number, title FROM content.chapters
+                 WHERE book_id = $1 ORDER BY chapter_number";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let chapters: Vec<(Uuid, i32, String)> = rows.rows.iter().map(|row| (
+        parse_uuid(&row[0]),
+        i32::decode(&row[1]).unwrap_or(0),
+        String::decode(&row[2]).unwrap_or_default(),
+    )).collect();
+    let last_chapter = chapters.last().map(|c| c.1).unwrap_or(0);
+
+    let threads = load_threads(conn, book_id)?;
+    let appearances = load_appearances(conn, book_id)?;
+
+    let mut chapter_threads: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+    for a in &appearances {
+        let ids = chapter_threads.entry(a.chapter_id).or_default();
+        if !ids.contains(&a.thread_id) {
+            ids.push(a.thread_id);
+        }
+    }
+
+    let lines: Vec<ThreadLine> = threads.into_iter()
+        .map(|thread| thread_line(thread, &appearances, last_chapter))
+        .collect();
+    let dropped = lines.iter().filter(|l| l.status == "dropped").count();
+
+    let chapters: Vec<serde_json::Value> = chapters.iter().map(|(id, number, title)| serde_json::json!({
+        "id": id,
+        "chapter_number": number,
+        "title": title,
+        "thread_ids": chapter_threads.get(id).cloned().unwrap_or_default()
+    })).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "book_id": book_id,
+        "chapters": chapters,
+        "threads": lines,
+        "dropped_threads": dropped,
+        "dropped_after_chapters": DROPPED_AFTER_CHAPTERS
+    }))
+}
+
+//=============================================================================
+// Generation Context
+//=============================================================================
+
+/// Unresolved threads for a generation job, plus any tagged on the chapter
+/// being generated; `None` when the book has no threads
+pub fn generation_context(
+    conn: &Connection,
+    book_id: &Uuid,
+    chapter_id: Option<&Uuid>,
+) -> Result<Option<serde_json::Value>, ServiceError> {
+    let threads = load_threads(conn, book_id)?;
+    if threads.is_empty() {
+        return Ok(None);
+    }
+    let appearances = load_appearances(conn, book_id)?;
+
+    let context: Vec<serde_json::Value> = threads.into_iter().filter_map(|thread| {
+        let own: Vec<&Appearance> = appearances.iter().filter(|a| a.thread_id == thread.id).collect();
+        let in_chapter: Vec<&str> = own.iter()
+            .filter(|a| Some(&a.chapter_id) == chapter_id)
+            .map(|a| a.role.as_str())
+            .collect();
+        let resolved = own.iter().any(|a| a.role == "resolved");
+        if resolved && in_chapter.is_empty() {
+            return None;
+        }
+
+        let description: Option<String> = thread.description
+            .map(|d| d.chars().take(MAX_CONTEXT_DESCRIPTION_CHARS).collect());
+        Some(serde_json::json!({
+            "name": thread.name,
+            "description": description,
+            "resolved": resolved,
+            "last_chapter": own.iter().map(|a| a.chapter_number).max(),
+            "roles_in_chapter": in_chapter
+        }))
+    }).collect();
+
+    if context.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::json!({ "threads": context })))
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+fn thread_line(thread: PlotThread, appearances: &[Appearance], last_chapter: i32) -> ThreadLine {
+    let mut presence: Vec<ThreadPresence> = Vec::new();
+    for a in appearances.iter().filter(|a| a.thread_id == thread.id) {
+        // Appearances arrive in chapter order
+        if presence.last().map(|p| p.chapter_id) != Some(a.chapter_id) {
+            presence.push(ThreadPresence {
+                chapter_id: a.chapter_id,
+                chapter_number: a.chapter_number,
+                roles: Vec::new(),
+                scene_ids: Vec::new(),
+            });
+        }
+        if let Some(p) = presence.last_mut() {
+            if !p.roles.contains(&a.role) {
+                p.roles.push(a.role.clone());
+            }
+            if let Some(scene_id) = a.scene_id {
+                p.scene_ids.push(scene_id);
+            }
+        }
+    }
+
+    let first = presence.first().map(|p| p.chapter_number);
+    let last = presence.last().map(|p| p.chapter_number);
+    let longest_gap = presence.windows(2)
+        .map(|w| w[1].chapter_number - w[0].chapter_number - 1)
+        .max()
+        .unwrap_or(0)
+        .max(0);
+    let resolved = presence.iter().any(|p| p.roles.iter().any(|r| r == "resolved"));
+
+    let status = match last {
+        None => "untagged",
+        Some(_) if resolved => "resolved",
+        Some(last) if last_chapter - last >= DROPPED_AFTER_CHAPTERS => "dropped",
+        Some(_) => "open",
+    };
+
+    ThreadLine {
+        thread,
+        status,
+        first_chapter: first,
+        last_chapter: last,
+        longest_gap,
+        presence,
+    }
+}
+
+fn load_threads(conn: &Connection, book_id: &Uuid) -> Result<Vec<PlotThread>, ServiceError> {
+    let query = "SELECT id, name, description, created_at FROM content.plot_threads
+                 WHERE book_id = $1 ORDER BY created_at, name";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.iter().map(|row| PlotThread {
+        id: parse_uuid(&row[0]),
+        name: String::decode(&row[1]).unwrap_or_default(),
+        description: String::decode(&row[2]).ok(),
+        created_at: String::decode(&row[3]).unwrap_or_default(),
+    }).collect())
+}
+
+fn load_appearances(conn: &Connection, book_id: &Uuid) -> Result<Vec<Appearance>, ServiceError> {
+    let query = "SELECT a.thread_id, a.chapter_id, c.chapter_number, a.scene_id, a.role
+                 FROM content.plot_thread_appearances a
+                 JOIN content.plot_threads t ON t.id = a.thread_id
+                 JOIN content.chapters c ON c.id = a.chapter_id
+                 WHERE t.book_id = $1
+                 ORDER BY c.chapter_number, a.created_at";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.iter().map(|row| Appearance {
+        thread_id: parse_uuid(&row[0]),
+        chapter_id: parse_uuid(&row[1]),
+        chapter_number: i32::decode(&row[2]).unwrap_or(0),
+        scene_id: String::decode(&row[3]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+        role: String::decode(&row[4]).unwrap_or_default(),
+    }).collect())
+}
+
+fn parse_uuid(value: &spin_sdk::pg::DbValue) -> Uuid {
+    Uuid::parse_str(&String::decode(value).unwrap_or_default()).unwrap_or_default()
+}
+
+fn join_ids(ids: impl Iterator<Item = Uuid>) -> String {
+    ids.map(|id| id.to_string()).collect::<Vec<_>>().join(",")
+}
@@ -0,0 +1,175 @@
+//! Book cards
+//!
+//! `GET /books/:id/card` saves frontends from joining a search hit with
+//! database stats themselves. It returns the indexed book document together
+//! with live stats from Postgres: readers all time and this week, the book's
+//! place in the trending table, and a few similar books as teasers.
+//!
+//! Drafts only have a card for their author. Teasers are limited to published
+//! books and follow the caller's safe search level. The card is not cached
+//! here. It carries an ETag hashed from the body, and a request whose
+//! `If-None-Match` matches gets a 304 with no body, so pollers skip the
+//! download when nothing changed.
+
+use crate::content_rating::SafeSearch;
+use crate::error::ServiceError;
+use crate::models::BookSearchResult;
+use serde::Serialize;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+const BOOKS_INDEX: &str = "/authorworks-books/_search";
+const TEASER_LIMIT: usize = 4;
+
+#[derive(Debug, Serialize)]
+pub struct BookStats {
+    pub reader_count: i64,
+    pub readers_7d: i64,
+    /// 1-based place among books with activity this week, if any
+    pub trending_rank: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Teaser {
+    pub id: String,
+    pub title: String,
+    pub author_name: Option<String>,
+    pub cover_url: Option<String>,
+    pub genre: Option<String>,
+}
+
+impl From<BookSearchResult> for Teaser {
+    fn from(book: BookSearchResult) -> Self {
+        Teaser {
+            id: book.id,
+            title: book.title,
+            author_name: book.author_name,
+            cover_url: book.cover_url,
+            genre: book.genre,
+        }
+    }
+}
+
+//=============================================================================
+// Endpoint
+//=============================================================================
+
+/// GET /books/:id/card - Indexed book with live stats and similar-book teasers
+pub fn get(
+    conn: &Connection,
+    es_url: &str,
+    book_id: &Uuid,
+    user_id: Option<&Uuid>,
+    safe_search: &SafeSearch,
+    if_none_match: Option<&str>,
+) -> Result<Response, ServiceError> {
+    let book = load_document(es_url, book_id)?;
+    let is_author = user_id.is_some_and(|uid| book["author_id"].as_str() == Some(uid.to_string().as_str()));
+    if book["status"].as_str() != Some("published") && !is_author {
+        return Err(ServiceError::NotFound("Book not found".into()));
+    }
+
+    let card = serde_json::json!({
+        "book": book,
+        "stats": load_stats(conn, book_id)?,
+        "similar": load_teasers(es_url, book_id, safe_search)?
+    });
+    let json = serde_json::to_string(&card)
+        .map_err(|e| ServiceError::Internal(format!("JSON error: {}", e)))?;
+    let etag = format!("\"{:016x}\"", crate::duplicates::fnv1a(json.as_bytes()));
+
+    if let Some(presented) = if_none_match {
+        if presented.split(',').any(|candidate| candidate.trim() == etag || candidate.trim() == "*") {
+            return Ok(Response::builder()
+                .status(304)
+                .header("ETag", etag.as_str())
+                .header("Cache-Control", "private, no-cache")
+                .header("Access-Control-Allow-Origin", "*")
+                .header("Access-Control-Expose-Headers", "ETag")
+                .body(())
+                .build());
+        }
+    }
+
+    Ok(Response::builder()
+        .status(200)
+        .header("Content-Type", "application/json")
+        .header("ETag", etag.as_str())
+        .header("Cache-Control", "private, no-cache")
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Access-Control-Expose-Headers", "ETag")
+        .body(json)
+        .build())
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+/// The book's `_source` from the index
+fn load_document(es_url: &str, book_id: &Uuid) -> Result<serde_json::Value, ServiceError> {
+    let search_body = serde_json::json!({
+        "query": {"ids": {"values": [book_id]}},
+        "size": 1
+    });
+    let response = crate::elasticsearch_request(es_url, "GET", BOOKS_INDEX, &search_body)?;
+
+    response["hits"]["hits"].as_array()
+        .and_then(|hits| hits.first())
+        .and_then(|hit| hit.get("_source"))
+        .cloned()
+        .ok_or_else(|| ServiceError::NotFound("Book not found".into()))
+}
+
+fn load_stats(conn: &Connection, book_id: &Uuid) -> Result<BookStats, ServiceError> {
+    let query = "WITH ranked AS (
+                     SELECT book_id, activity, RANK() OVER (ORDER BY activity DESC) AS rank
+                     FROM discovery.trending_books
+                     WHERE activity > 0
+                 )
+                 SELECT
+                     (SELECT COUNT(DISTINCT user_id) FROM discovery.reading_history WHERE book_id = $1),
+                     (SELECT COUNT(DISTINCT user_id) FROM discovery.reading_history
+                      WHERE book_id = $1 AND created_at > NOW() - INTERVAL '7 days'),
+                     (SELECT rank FROM ranked WHERE book_id = $1)";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::Internal("Stats query returned no rows".into()))?;
+
+    Ok(BookStats {
+        reader_count: i64::decode(&row[0]).unwrap_or(0),
+        readers_7d: i64::decode(&row[1]).unwrap_or(0),
+        trending_rank: i64::decode(&row[2]).ok(),
+    })
+}
+
+/// A few published books like this one, as `/similar/:book_id` finds them
+fn load_teasers(es_url: &str, book_id: &Uuid, safe_search: &SafeSearch) -> Result<Vec<Teaser>, ServiceError> {
+    let mut filter = vec![serde_json::json!({"term": {"status": "published"}})];
+    filter.extend(safe_search.filter());
+
+    let search_body = serde_json::json!({
+        "query": {
+            "bool": {
+                "must": [{
+                    "more_like_this": {
+                        "fields": ["title", "description", "genre"],
+                        "like": [{"_index": "authorworks-books", "_id": book_id}],
+                        "min_term_freq": 1,
+                        "min_doc_freq": 1,
+                        "max_query_terms": 25
+                    }
+                }],
+                "filter": filter
+            }
+        },
+        "size": TEASER_LIMIT
+    });
+    let response = crate::elasticsearch_request(es_url, "GET", BOOKS_INDEX, &search_body)?;
+
+    Ok(response["hits"]["hits"].as_array()
+        .map(|hits| hits.iter().filter_map(crate::book_from_hit).map(Teaser::from).collect())
+        .unwrap_or_default())
+}
@@ -0,0 +1,291 @@
+//! Item-item collaborative filtering
+//!
+//! "Readers of X also read Y": books are scored against each other by the
+//! cosine similarity of their reader sets in `discovery.reading_history`.
+//! A periodic job stores the top neighbors per book in
+//! `discovery.book_neighbors`; recommendations sum neighbor scores over the
+//! user's recent books and blend them with the genre-based results. Single
+//! books are refreshed between rebuilds when events call for it (see
+//! `events`).
+
+use crate::error::ServiceError;
+use crate::models::BookSearchResult;
+use serde::Serialize;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use chrono::Utc;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+//=============================================================================
+// Configuration
+//=============================================================================
+
+const DEFAULT_LOOKBACK_DAYS: i32 = 180;
+const DEFAULT_MIN_CO_READERS: i32 = 2;
+const DEFAULT_NEIGHBORS_PER_BOOK: i32 = 20;
+const DEFAULT_COLLABORATIVE_WEIGHT: f64 = 0.7;
+const DEFAULT_GENRE_WEIGHT: f64 = 0.3;
+
+/// Recent books of the user that seed collaborative candidates
+const SEED_BOOKS: i32 = 20;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CollaborativeConfig {
+    pub lookback_days: i32,
+    pub min_co_readers: i32,
+    pub neighbors_per_book: i32,
+    pub collaborative_weight: f64,
+    pub genre_weight: f64,
+}
+
+/// Load `cf_lookback_days`, `cf_min_co_readers`, `cf_neighbors_per_book`,
+/// `recommendation_cf_weight`, and `recommendation_genre_weight`, falling
+/// back to the defaults.
+pub fn get_config() -> CollaborativeConfig {
+    fn positive_i32(name: &str, default: i32) -> i32 {
+        variables::get(name).ok()
+            .and_then(|s| s.parse::<i32>().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(default)
+    }
+    fn weight(name: &str, default: f64) -> f64 {
+        variables::get(name).ok()
+            .and_then(|s| s.parse::<f64>().ok())
+            .filter(|w| *w >= 0.0)
+            .unwrap_or(default)
+    }
+
+    CollaborativeConfig {
+        lookback_days: positive_i32("cf_lookback_days", DEFAULT_LOOKBACK_DAYS),
+        min_co_readers: positive_i32("cf_min_co_readers", DEFAULT_MIN_CO_READERS),
+        neighbors_per_book: positive_i32("cf_neighbors_per_book", DEFAULT_NEIGHBORS_PER_BOOK),
+        collaborative_weight: weight("recommendation_cf_weight", DEFAULT_COLLABORATIVE_WEIGHT),
+        genre_weight: weight("recommendation_genre_weight", DEFAULT_GENRE_WEIGHT),
+    }
+}
+
+//=============================================================================
+// Neighbor Computation
+//=============================================================================
+
+#[derive(Debug, Serialize)]
+pub struct RebuildSummary {
+    pub neighbors_written: u64,
+    pub neighbors_removed: u64,
+    pub computed_at: String,
+}
+
+/// Recompute every book's neighbor list. Rows are upserted first and stale
+/// rows removed afterwards, so readers never see an empty table.
+pub fn rebuild_neighbors(conn: &Connection, config: &CollaborativeConfig) -> Result<RebuildSummary, ServiceError> {
+    let computed_at = Utc::now().to_rfc3339();
+
+    let upsert = "WITH readers AS (
+                      SELECT DISTINCT user_id, book_id FROM discovery.reading_history
+                      WHERE created_at > NOW() - make_interval(days => $1)
+                  ),
+                  reader_counts AS (
+                      SELECT book_id, COUNT(*) AS readers FROM readers GROUP BY book_id
+                  ),
+                  pairs AS (
+                      SELECT a.book_id, b.book_id AS neighbor_id, COUNT(*) AS co_readers
+                      FROM readers a
+                      JOIN readers b ON a.user_id = b.user_id AND a.book_id <> b.book_id
+                      GROUP BY a.book_id, b.book_id
+                      HAVING COUNT(*) >= $2
+                  ),
+                  scored AS (
+                      SELECT p.book_id, p.neighbor_id, p.co_readers,
+                             p.co_readers / SQRT(ca.readers::float8 * cb.readers::float8) AS score
+                      FROM pairs p
+                      JOIN reader_counts ca ON ca.book_id = p.book_id
+                      JOIN reader_counts cb ON cb.book_id = p.neighbor_id
+                  ),
+                  ranked AS (
+                      SELECT *, ROW_NUMBER() OVER (PARTITION BY book_id ORDER BY score DESC, co_readers DESC) AS rank
+                      FROM scored
+                  )
+                  INSERT INTO discovery.book_neighbors (book_id, neighbor_id, score, co_readers, computed_at)
+                  SELECT book_id, neighbor_id, score, co_readers, $4 FROM ranked WHERE rank <= $3
+                  ON CONFLICT (book_id, neighbor_id) DO UPDATE SET
+                  score = EXCLUDED.score,
+                  co_readers = EXCLUDED.co_readers,
+                  computed_at = EXCLUDED.computed_at";
+    let neighbors_written = conn.execute(upsert, &[
+        ParameterValue::Int32(config.lookback_days),
+        ParameterValue::Int32(config.min_co_readers),
+        ParameterValue::Int32(config.neighbors_per_book),
+        ParameterValue::Str(computed_at.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    let delete = "DELETE FROM discovery.book_neighbors WHERE computed_at < $1";
+    let neighbors_removed = conn.execute(delete, &[ParameterValue::Str(computed_at.clone())])
+        .map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    Ok(RebuildSummary { neighbors_written, neighbors_removed, computed_at })
+}
+
+/// Recompute the neighbors of a single book after an event, along with the
+/// reverse edges pointing at it. Other books' lists are trimmed back to
+/// `neighbors_per_book` if the new edge pushed them over.
+pub fn refresh_book_neighbors(conn: &Connection, config: &CollaborativeConfig, book_id: &Uuid) -> Result<u64, ServiceError> {
+    let computed_at = Utc::now().to_rfc3339();
+
+    let upsert = "WITH readers AS (
+                      SELECT DISTINCT user_id, book_id FROM discovery.reading_history
+                      WHERE created_at > NOW() - make_interval(days => $2)
+                  ),
+                  pairs AS (
+                      SELECT r.book_id AS neighbor_id, COUNT(*) AS co_readers
+                      FROM readers r
+                      JOIN readers t ON t.user_id = r.user_id AND t.book_id = $1
+                      WHERE r.book_id <> $1
+                      GROUP BY r.book_id
+                      HAVING COUNT(*) >= $3
+                  ),
+                  reader_counts AS (
+                      SELECT book_id, COUNT(*) AS readers FROM readers
+                      WHERE book_id = $1 OR book_id IN (SELECT neighbor_id FROM pairs)
+                      GROUP BY book_id
+                  ),
+                  scored AS (
+                      SELECT p.neighbor_id, p.co_readers,
+                             p.co_readers / SQRT(ca.readers::float8 * cb.readers::float8) AS score
+                      FROM pairs p
+                      JOIN reader_counts ca ON ca.book_id = $1
+                      JOIN reader_counts cb ON cb.book_id = p.neighbor_id
+                  ),
+                  edges AS (
+                      SELECT $1::uuid AS book_id, neighbor_id, score, co_readers FROM (
+                          SELECT *, ROW_NUMBER() OVER (ORDER BY score DESC, co_readers DESC) AS rank FROM scored
+                      ) ranked WHERE rank <= $4
+                      UNION ALL
+                      SELECT neighbor_id, $1::uuid, score, co_readers FROM scored
+                  )
+                  INSERT INTO discovery.book_neighbors (book_id, neighbor_id, score, co_readers, computed_at)
+                  SELECT book_id, neighbor_id, score, co_readers, $5 FROM edges
+                  ON CONFLICT (book_id, neighbor_id) DO UPDATE SET
+                  score = EXCLUDED.score,
+                  co_readers = EXCLUDED.co_readers,
+                  computed_at = EXCLUDED.computed_at";
+    let written = conn.execute(upsert, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Int32(config.lookback_days),
+        ParameterValue::Int32(config.min_co_readers),
+        ParameterValue::Int32(config.neighbors_per_book),
+        ParameterValue::Str(computed_at.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    // Edges touching this book that no longer qualify
+    let stale = "DELETE FROM discovery.book_neighbors
+                 WHERE (book_id = $1 OR neighbor_id = $1) AND computed_at < $2";
+    conn.execute(stale, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(computed_at),
+    ]).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    let trim = "DELETE FROM discovery.book_neighbors d USING (
+                    SELECT book_id, neighbor_id,
+                           ROW_NUMBER() OVER (PARTITION BY book_id ORDER BY score DESC, co_readers DESC) AS rank
+                    FROM discovery.book_neighbors
+                    WHERE book_id IN (SELECT book_id FROM discovery.book_neighbors WHERE neighbor_id = $1)
+                ) r
+                WHERE d.book_id = r.book_id AND d.neighbor_id = r.neighbor_id AND r.rank > $2";
+    conn.execute(trim, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Int32(config.neighbors_per_book),
+    ]).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    Ok(written)
+}
+
+/// Drop every edge touching a book that is no longer recommendable
+pub fn remove_book(conn: &Connection, book_id: &Uuid) -> Result<u64, ServiceError> {
+    let delete = "DELETE FROM discovery.book_neighbors WHERE book_id = $1 OR neighbor_id = $1";
+    conn.execute(delete, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))
+}
+
+/// Whether the book's neighbors were computed in the last `seconds`
+pub fn recently_computed(conn: &Connection, book_id: &Uuid, seconds: i64) -> Result<bool, ServiceError> {
+    let query = "SELECT EXISTS (
+                     SELECT 1 FROM discovery.book_neighbors
+                     WHERE book_id = $1 AND computed_at > NOW() - make_interval(secs => $2)
+                 )";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Int64(seconds),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    Ok(rows.rows.first().map(|row| bool::decode(&row[0]).unwrap_or(false)).unwrap_or(false))
+}
+
+//=============================================================================
+// Recommendations
+//=============================================================================
+
+/// Candidate books for the user with summed neighbor scores, excluding
+/// anything they have already read
+pub fn user_candidates(conn: &Connection, user_id: &Uuid, limit: i32) -> Result<Vec<(String, f64)>, ServiceError> {
+    let query = "WITH seeds AS (
+                     SELECT book_id FROM discovery.reading_history
+                     WHERE user_id = $1
+                     GROUP BY book_id
+                     ORDER BY MAX(created_at) DESC
+                     LIMIT $2
+                 )
+                 SELECT n.neighbor_id::text, SUM(n.score) AS score
+                 FROM discovery.book_neighbors n
+                 JOIN seeds s ON s.book_id = n.book_id
+                 WHERE NOT EXISTS (
+                     SELECT 1 FROM discovery.reading_history h
+                     WHERE h.user_id = $1 AND h.book_id = n.neighbor_id
+                 )
+                 GROUP BY n.neighbor_id
+                 ORDER BY score DESC
+                 LIMIT $3";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Int32(SEED_BOOKS),
+        ParameterValue::Int32(limit),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.iter()
+        .filter_map(|row| Some((String::decode(&row[0]).ok()?, f64::decode(&row[1]).ok()?)))
+        .collect())
+}
+
+/// Merge genre-based results with collaborative candidates. Each source is
+/// normalised to its own top score before weighting, so neither dominates
+/// just by scale. `books` holds the documents for collaborative candidates.
+pub fn blend(
+    config: &CollaborativeConfig,
+    genre_results: Vec<BookSearchResult>,
+    candidates: &[(String, f64)],
+    books: Vec<BookSearchResult>,
+    limit: usize,
+) -> Vec<BookSearchResult> {
+    let max_genre = genre_results.iter().map(|b| b.score).fold(0.0, f64::max);
+    let max_cf = candidates.iter().map(|(_, s)| *s).fold(0.0, f64::max);
+
+    let mut merged: HashMap<String, BookSearchResult> = HashMap::new();
+    for mut book in genre_results {
+        let normalised = if max_genre > 0.0 { book.score / max_genre } else { 0.0 };
+        book.score = config.genre_weight * normalised;
+        merged.insert(book.id.clone(), book);
+    }
+
+    let cf_scores: HashMap<&str, f64> = candidates.iter().map(|(id, s)| (id.as_str(), *s)).collect();
+    for book in books {
+        let raw = cf_scores.get(book.id.as_str()).copied().unwrap_or(0.0);
+        let weighted = config.collaborative_weight * if max_cf > 0.0 { raw / max_cf } else { 0.0 };
+        merged.entry(book.id.clone())
+            .and_modify(|existing| existing.score += weighted)
+            .or_insert(BookSearchResult { score: weighted, ..book });
+    }
+
+    let mut blended: Vec<BookSearchResult> = merged.into_values().collect();
+    blended.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    blended.truncate(limit);
+    blended
+}
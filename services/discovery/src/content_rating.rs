@@ -0,0 +1,293 @@
+//! Age ratings, content warnings and safe search
+//!
+//! Books carry an age rating (`everyone`, `teen`, `mature` or `adult`) and a
+//! list of content warnings set by their author in the content service, which
+//! sends both with every book and chapter it indexes. Search results are
+//! filtered by a safe search level:
+//! - `strict` - only `everyone` books with no content warnings
+//! - `moderate` (the default) - everything except `mature` and `adult`
+//! - `off` - no rating filter
+//!
+//! The `safe_search` query parameter picks the level for one request; without
+//! it the reader's saved preference applies, then `moderate`. Warnings a
+//! reader has chosen to hide stay hidden at every level. Unrated books are
+//! shown unless the level is `strict`.
+//!
+//! Surfaces that read books from Postgres rather than Elasticsearch apply the
+//! same rules with `sql_condition`, and author search drops authors whose
+//! published books are all filtered out.
+
+use crate::error::ServiceError;
+use serde::Deserialize;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// Age ratings from least to most restricted
+pub const RATINGS: &[&str] = &["everyone", "teen", "mature", "adult"];
+
+/// Ratings `moderate` leaves out
+const MATURE_RATINGS: &[&str] = &["mature", "adult"];
+
+const MAX_WARNINGS: usize = 20;
+const MAX_WARNING_LEN: usize = 40;
+
+const RATING_FIELD: &str = "content_rating.keyword";
+const WARNINGS_FIELD: &str = "content_warnings.keyword";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Strict,
+    Moderate,
+    Off,
+}
+
+impl Level {
+    /// `on` is accepted as an alias for the default level
+    pub fn parse(value: &str) -> Result<Level, ServiceError> {
+        match value.to_lowercase().as_str() {
+            "strict" => Ok(Level::Strict),
+            "moderate" | "on" => Ok(Level::Moderate),
+            "off" => Ok(Level::Off),
+            other => Err(ServiceError::BadRequest(format!(
+                "safe_search must be strict, moderate, on or off, not '{}'", other
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Strict => "strict",
+            Level::Moderate => "moderate",
+            Level::Off => "off",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SafeSearch {
+    pub level: Level,
+    pub hidden_warnings: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreferencesRequest {
+    pub safe_search: Option<String>,
+    pub hidden_warnings: Option<Vec<String>>,
+}
+
+//=============================================================================
+// Indexing
+//=============================================================================
+
+/// A known rating in lowercase; anything else indexes as unrated
+pub fn normalize_rating(rating: Option<&str>) -> Option<String> {
+    let rating = rating?.trim().to_lowercase();
+    RATINGS.contains(&rating.as_str()).then_some(rating)
+}
+
+/// Warnings as lowercase slugs, deduplicated, e.g. "Self Harm" -> "self-harm"
+pub fn normalize_warnings(warnings: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for warning in warnings {
+        let slug = warning.trim()
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+        if slug.is_empty() || slug.len() > MAX_WARNING_LEN || normalized.contains(&slug) {
+            continue;
+        }
+        normalized.push(slug);
+        if normalized.len() == MAX_WARNINGS {
+            break;
+        }
+    }
+    normalized
+}
+
+//=============================================================================
+// Filtering
+//=============================================================================
+
+/// Level and hidden warnings for a search: the explicit parameter, else the
+/// reader's preference, else `moderate`
+pub fn resolve(conn: &Connection, user_id: Option<&Uuid>, param: Option<&str>) -> Result<SafeSearch, ServiceError> {
+    let saved = match user_id {
+        Some(uid) => load(conn, uid)?,
+        None => None,
+    };
+    let level = match param {
+        Some(value) => Level::parse(value)?,
+        None => saved.as_ref().map(|s| s.level).unwrap_or(Level::Moderate),
+    };
+
+    Ok(SafeSearch {
+        level,
+        hidden_warnings: saved.map(|s| s.hidden_warnings).unwrap_or_default(),
+    })
+}
+
+impl SafeSearch {
+    /// Clause for a bool `filter`, or `None` when nothing is filtered
+    pub fn filter(&self) -> Option<serde_json::Value> {
+        let mut filter = Vec::new();
+        let mut must_not = Vec::new();
+
+        match self.level {
+            Level::Strict => {
+                filter.push(serde_json::json!({"term": {(RATING_FIELD): "everyone"}}));
+                must_not.push(serde_json::json!({"exists": {"field": "content_warnings"}}));
+            }
+            Level::Moderate => {
+                must_not.push(serde_json::json!({"terms": {(RATING_FIELD): MATURE_RATINGS}}));
+            }
+            Level::Off => {}
+        }
+        if !self.hidden_warnings.is_empty() {
+            must_not.push(serde_json::json!({"terms": {(WARNINGS_FIELD): self.hidden_warnings}}));
+        }
+
+        if filter.is_empty() && must_not.is_empty() {
+            return None;
+        }
+        Some(serde_json::json!({
+            "bool": {
+                "filter": filter,
+                "must_not": must_not
+            }
+        }))
+    }
+
+    /// Like `filter`, for searches spanning indices where only `rated`
+    /// carries ratings; documents in other indices always pass
+    pub fn filter_indices(&self, rated: &[&str]) -> Option<serde_json::Value> {
+        let clause = self.filter()?;
+        Some(serde_json::json!({
+            "bool": {
+                "should": [
+                    clause,
+                    {"bool": {"must_not": [{"terms": {"_index": rated}}]}}
+                ],
+                "minimum_should_match": 1
+            }
+        }))
+    }
+
+    /// Level and hidden warnings to bind for `sql_condition`
+    pub fn sql_params(&self) -> [ParameterValue; 2] {
+        [
+            ParameterValue::Str(self.level.as_str().to_string()),
+            ParameterValue::Str(serde_json::to_string(&self.hidden_warnings).unwrap_or_else(|_| "[]".into())),
+        ]
+    }
+}
+
+/// SQL equivalent of `SafeSearch::filter` for `content.books` aliased as
+/// `books`, with `sql_params` bound from `$first`. Stored warnings are
+/// free text, so they are slugged the way `normalize_warnings` does.
+pub fn sql_condition(books: &str, first: usize) -> String {
+    let (level, hidden) = (first, first + 1);
+    format!(
+        "((${level} <> 'strict' OR ({b}.content_rating = 'everyone' AND jsonb_array_length({b}.content_warnings) = 0))
+         AND (${level} <> 'moderate' OR {b}.content_rating IS NULL OR {b}.content_rating NOT IN ('mature', 'adult'))
+         AND NOT EXISTS (
+             SELECT 1 FROM jsonb_array_elements_text({b}.content_warnings) w
+             WHERE trim(both '-' from regexp_replace(lower(w), '[^[:alnum:]]+', '-', 'g'))
+                   IN (SELECT jsonb_array_elements_text(${hidden}::jsonb))
+         ))",
+        b = books,
+    )
+}
+
+/// Authors among `author_ids` with published books, none of which pass safe search
+pub fn hidden_authors(conn: &Connection, safe_search: &SafeSearch, author_ids: &[String]) -> Result<HashSet<String>, ServiceError> {
+    if author_ids.is_empty() || safe_search.filter().is_none() {
+        return Ok(HashSet::new());
+    }
+
+    let query = format!(
+        "SELECT b.author_id::text FROM content.books b
+         WHERE b.author_id::text = ANY(string_to_array($1, ',')) AND b.status = 'published'
+         GROUP BY b.author_id
+         HAVING NOT bool_or({})",
+        sql_condition("b", 2)
+    );
+    let [level, hidden] = safe_search.sql_params();
+    let rows = conn.query(&query, &[ParameterValue::Str(author_ids.join(",")), level, hidden])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.iter().filter_map(|row| String::decode(&row[0]).ok()).collect())
+}
+
+//=============================================================================
+// Preferences
+//=============================================================================
+
+fn load(conn: &Connection, user_id: &Uuid) -> Result<Option<SafeSearch>, ServiceError> {
+    let query = "SELECT safe_search, hidden_warnings::text FROM discovery.search_preferences WHERE user_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first().map(|row| SafeSearch {
+        level: String::decode(&row[0]).ok()
+            .and_then(|s| Level::parse(&s).ok())
+            .unwrap_or(Level::Moderate),
+        hidden_warnings: String::decode(&row[1]).ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default(),
+    }))
+}
+
+fn preferences_json(preferences: &SafeSearch, saved: bool) -> serde_json::Value {
+    serde_json::json!({
+        "safe_search": preferences.level.as_str(),
+        "hidden_warnings": preferences.hidden_warnings,
+        "saved": saved
+    })
+}
+
+/// GET /preferences/safe-search
+pub fn get_preferences(conn: &Connection, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let saved = load(conn, user_id)?;
+    let preferences = saved.clone().unwrap_or(SafeSearch {
+        level: Level::Moderate,
+        hidden_warnings: Vec::new(),
+    });
+
+    crate::json_response(200, preferences_json(&preferences, saved.is_some()))
+}
+
+/// PUT /preferences/safe-search - Fields left out keep their saved value
+pub fn update_preferences(conn: &Connection, user_id: &Uuid, body: PreferencesRequest) -> Result<Response, ServiceError> {
+    let current = load(conn, user_id)?.unwrap_or(SafeSearch {
+        level: Level::Moderate,
+        hidden_warnings: Vec::new(),
+    });
+    let preferences = SafeSearch {
+        level: match body.safe_search.as_deref() {
+            Some(value) => Level::parse(value)?,
+            None => current.level,
+        },
+        hidden_warnings: match body.hidden_warnings {
+            Some(warnings) => normalize_warnings(&warnings),
+            None => current.hidden_warnings,
+        },
+    };
+
+    let upsert = "INSERT INTO discovery.search_preferences (user_id, safe_search, hidden_warnings, updated_at)
+                  VALUES ($1, $2, $3::jsonb, NOW())
+                  ON CONFLICT (user_id) DO UPDATE
+                  SET safe_search = EXCLUDED.safe_search,
+                      hidden_warnings = EXCLUDED.hidden_warnings,
+                      updated_at = NOW()";
+    conn.execute(upsert, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(preferences.level.as_str().to_string()),
+        ParameterValue::Str(serde_json::to_string(&preferences.hidden_warnings).unwrap_or_else(|_| "[]".into())),
+    ]).map_err(|e| ServiceError::Internal(format!("Upsert failed: {}", e)))?;
+
+    crate::json_response(200, preferences_json(&preferences, true))
+}
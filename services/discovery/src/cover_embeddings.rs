@@ -0,0 +1,203 @@
+//! Cover image similarity
+//!
+//! When a book is indexed with a cover, the cover URL is sent to the vision
+//! embedding API at `cover_embedding_api_url` and the returned vector is stored
+//! in `discovery.cover_embeddings`. The API receives
+//! `{"model": ..., "image_url": ...}` and may answer with `{"embedding": [...]}`
+//! or the OpenAI-style `{"data": [{"embedding": [...]}]}`. Without a configured
+//! URL covers are simply not embedded.
+//!
+//! `GET /similar/:book_id?visual_weight=` blends cosine similarity of covers
+//! into the text ranking, which suits mood-based browsing ("books that look
+//! like this one") better than descriptions alone.
+
+use crate::error::ServiceError;
+use spin_sdk::outbound_http::{self, Method as HttpMethod, Request as OutboundRequest};
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use std::collections::HashMap;
+
+const DEFAULT_MODEL: &str = "clip-vit-base-patch32";
+
+/// Text matches fetched for visual re-ranking before the top 10 are kept
+pub const CANDIDATE_POOL: usize = 50;
+
+/// Guards against a misconfigured API filling the table with huge arrays
+const MAX_DIMENSIONS: usize = 4096;
+
+//=============================================================================
+// Indexing
+//=============================================================================
+
+/// Embed the cover unless the stored vector already matches its URL and the
+/// configured model. A book without a cover loses any stored vector.
+pub fn embed_cover(conn: &Connection, book_id: &str, cover_url: Option<&str>) -> Result<(), ServiceError> {
+    let cover_url = match cover_url.map(str::trim).filter(|u| !u.is_empty()) {
+        Some(url) => url,
+        None => return remove_book(conn, book_id),
+    };
+    let api_url = match variables::get("cover_embedding_api_url").ok().filter(|u| !u.is_empty()) {
+        Some(url) => url,
+        None => return Ok(()),
+    };
+    let model = variables::get("cover_embedding_model")
+        .ok()
+        .filter(|m| !m.is_empty())
+        .unwrap_or_else(|| DEFAULT_MODEL.to_string());
+
+    let current = conn.query(
+        "SELECT 1 FROM discovery.cover_embeddings WHERE book_id = $1::uuid AND cover_url = $2 AND model = $3",
+        &[
+            ParameterValue::Str(book_id.to_string()),
+            ParameterValue::Str(cover_url.to_string()),
+            ParameterValue::Str(model.clone()),
+        ],
+    ).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if !current.rows.is_empty() {
+        return Ok(());
+    }
+
+    let embedding = normalize(request_embedding(&api_url, &model, cover_url)?)
+        .ok_or_else(|| ServiceError::Internal("Cover embedding is all zeros".into()))?;
+
+    let upsert = "INSERT INTO discovery.cover_embeddings
+                  (book_id, cover_url, model, dimensions, embedding, created_at, updated_at)
+                  VALUES ($1::uuid, $2, $3, $4, $5::real[], NOW(), NOW())
+                  ON CONFLICT (book_id) DO UPDATE SET
+                    cover_url = EXCLUDED.cover_url, model = EXCLUDED.model,
+                    dimensions = EXCLUDED.dimensions, embedding = EXCLUDED.embedding,
+                    updated_at = NOW()";
+    conn.execute(upsert, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(cover_url.to_string()),
+        ParameterValue::Str(model),
+        ParameterValue::Int32(embedding.len() as i32),
+        ParameterValue::Str(vector_literal(&embedding)),
+    ]).map_err(|e| ServiceError::Internal(format!("Upsert failed: {}", e)))?;
+
+    Ok(())
+}
+
+/// Drop a book's cover vector, e.g. when it leaves the index
+pub fn remove_book(conn: &Connection, book_id: &str) -> Result<(), ServiceError> {
+    conn.execute(
+        "DELETE FROM discovery.cover_embeddings WHERE book_id::text = $1",
+        &[ParameterValue::Str(book_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    Ok(())
+}
+
+fn request_embedding(api_url: &str, model: &str, cover_url: &str) -> Result<Vec<f32>, ServiceError> {
+    let body = serde_json::to_vec(&serde_json::json!({
+        "model": model,
+        "image_url": cover_url
+    })).unwrap_or_default();
+
+    let request = match variables::get("cover_embedding_api_key").ok().filter(|k| !k.is_empty()) {
+        Some(key) => OutboundRequest::builder()
+            .method(HttpMethod::Post)
+            .uri(api_url)
+            .header("Content-Type", "application/json")
+            .header("Authorization", format!("Bearer {}", key))
+            .body(body)
+            .build(),
+        None => OutboundRequest::builder()
+            .method(HttpMethod::Post)
+            .uri(api_url)
+            .header("Content-Type", "application/json")
+            .body(body)
+            .build(),
+    };
+
+    let response = outbound_http::send(request)
+        .map_err(|e| ServiceError::Internal(format!("Cover embedding API unreachable: {}", e)))?;
+    let status = response.status().as_u16();
+    if !(200..300).contains(&status) {
+        return Err(ServiceError::Internal(format!("Cover embedding API returned status {}", status)));
+    }
+
+    let parsed: serde_json::Value = serde_json::from_slice(response.body())
+        .map_err(|e| ServiceError::Internal(format!("Failed to parse cover embedding: {}", e)))?;
+    let values = parsed.get("embedding")
+        .or_else(|| parsed.get("data").and_then(|d| d.get(0)).and_then(|d| d.get("embedding")))
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| ServiceError::Internal("Cover embedding response has no embedding".into()))?;
+    if values.is_empty() || values.len() > MAX_DIMENSIONS {
+        return Err(ServiceError::Internal(format!("Cover embedding has {} dimensions", values.len())));
+    }
+
+    values.iter()
+        .map(|v| v.as_f64().map(|f| f as f32))
+        .collect::<Option<Vec<f32>>>()
+        .ok_or_else(|| ServiceError::Internal("Cover embedding contains non-numeric values".into()))
+}
+
+//=============================================================================
+// Similarity
+//=============================================================================
+
+/// Cosine similarity between `book_id`'s cover and each candidate's, for
+/// candidates embedded with the same model. Empty if the book has no vector.
+pub fn similarities(conn: &Connection, book_id: &str, candidate_ids: &[String]) -> Result<HashMap<String, f64>, ServiceError> {
+    if candidate_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let query = "SELECT c.book_id::text, c.embedding::text
+                 FROM discovery.cover_embeddings s
+                 JOIN discovery.cover_embeddings c
+                   ON c.model = s.model AND c.dimensions = s.dimensions
+                 WHERE s.book_id::text = $1
+                   AND (c.book_id::text = $1 OR c.book_id::text = ANY(string_to_array($2, ',')))";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(candidate_ids.join(",")),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut vectors: HashMap<String, Vec<f32>> = rows.rows.iter()
+        .filter_map(|row| {
+            let id = String::decode(&row[0]).ok()?;
+            let vector = parse_vector(&String::decode(&row[1]).ok()?)?;
+            Some((id, vector))
+        })
+        .collect();
+
+    let source = match vectors.remove(book_id) {
+        Some(vector) => vector,
+        None => return Ok(HashMap::new()),
+    };
+
+    Ok(vectors.into_iter()
+        .map(|(id, vector)| {
+            // Stored vectors are unit length, so the dot product is the cosine
+            let dot: f32 = source.iter().zip(&vector).map(|(a, b)| a * b).sum();
+            (id, dot as f64)
+        })
+        .collect())
+}
+
+//=============================================================================
+// Vectors
+//=============================================================================
+
+fn normalize(mut vector: Vec<f32>) -> Option<Vec<f32>> {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm == 0.0 || !norm.is_finite() {
+        return None;
+    }
+    vector.iter_mut().for_each(|v| *v /= norm);
+    Some(vector)
+}
+
+/// Postgres array literal, e.g. `{0.1,-0.2}`
+fn vector_literal(vector: &[f32]) -> String {
+    format!("{{{}}}", vector.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(","))
+}
+
+fn parse_vector(literal: &str) -> Option<Vec<f32>> {
+    literal.trim_start_matches('{')
+        .trim_end_matches('}')
+        .split(',')
+        .map(|v| v.trim().parse::<f32>().ok())
+        .collect()
+}
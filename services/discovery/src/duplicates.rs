@@ -0,0 +1,456 @@
+//! Near-duplicate detection
+//!
+//! When a chapter is indexed its text is broken into 5-word shingles and
+//! summarised as a 128-value MinHash signature; the share of equal values
+//! between two signatures estimates the Jaccard similarity of their shingle
+//! sets. A book's signature is the element-wise minimum of its chapters',
+//! which is exactly the MinHash of the whole book.
+//!
+//! Signatures are split into 32 bands of 4 and stored as band hashes, so
+//! candidates are found with an index lookup instead of a scan: documents
+//! sharing any band are compared, and pairs at or above
+//! `duplicate_similarity_threshold` are flagged into
+//! `discovery.duplicate_flags` for moderators. Chapters of the same book are
+//! never compared with each other.
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+const NUM_HASHES: usize = 128;
+const BANDS: usize = 32;
+const ROWS_PER_BAND: usize = NUM_HASHES / BANDS;
+
+const SHINGLE_WORDS: usize = 5;
+
+/// Short texts share boilerplate too easily to be compared
+const MIN_SHINGLES: usize = 50;
+
+const DEFAULT_THRESHOLD: f64 = 0.8;
+
+/// Bounds the work done per indexed document
+const MAX_CANDIDATES: i32 = 200;
+
+pub const DEFAULT_LIST_LIMIT: i64 = 50;
+pub const MAX_LIST_LIMIT: i64 = 200;
+
+const REVIEW_STATUSES: [&str; 3] = ["pending", "confirmed", "dismissed"];
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewRequest {
+    /// `confirmed`, `dismissed`, or `pending` to reopen
+    pub status: String,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DuplicateFlag {
+    pub id: Uuid,
+    pub doc_type: String,
+    pub similarity: f64,
+    pub status: String,
+    pub same_author: bool,
+    pub document: FlaggedDocument,
+    pub matched: FlaggedDocument,
+    pub review_note: Option<String>,
+    pub reviewed_by: Option<Uuid>,
+    pub reviewed_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FlaggedDocument {
+    pub id: Uuid,
+    pub book_id: Uuid,
+    pub book_title: Option<String>,
+    pub chapter_title: Option<String>,
+    pub author_id: Option<Uuid>,
+    pub author_name: Option<String>,
+}
+
+struct Signature {
+    values: Vec<u32>,
+    shingle_count: usize,
+}
+
+//=============================================================================
+// Index-time Checks
+//=============================================================================
+
+/// Record a chapter's signature, flag near-duplicate chapters, then refresh
+/// the book's signature. Returns the number of pairs flagged or updated.
+pub fn check_chapter(conn: &Connection, chapter_id: &str, book_id: &str, content: &str) -> Result<usize, ServiceError> {
+    let flagged = match signature(content) {
+        Some(sig) => {
+            store(conn, "chapter", chapter_id, book_id, &sig)?;
+            flag_matches(conn, "chapter", chapter_id, book_id, &sig)?
+        }
+        // Too short to judge now; forget whatever it used to say
+        None => {
+            remove(conn, "chapter", chapter_id)?;
+            0
+        }
+    };
+    Ok(flagged + check_book(conn, book_id)?)
+}
+
+/// Rebuild a book's signature from its chapters and flag near-duplicate books
+pub fn check_book(conn: &Connection, book_id: &str) -> Result<usize, ServiceError> {
+    let query = "SELECT signature::text, shingle_count FROM discovery.content_signatures
+                 WHERE doc_type = 'chapter' AND book_id = $1::uuid";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut combined: Option<Signature> = None;
+    for row in &rows.rows {
+        let values: Vec<u32> = match String::decode(&row[0]).ok().and_then(|s| serde_json::from_str(&s).ok()) {
+            Some(values) => values,
+            None => continue,
+        };
+        if values.len() != NUM_HASHES {
+            continue;
+        }
+        let count = i32::decode(&row[1]).unwrap_or(0).max(0) as usize;
+        combined = Some(match combined {
+            None => Signature { values, shingle_count: count },
+            Some(mut sig) => {
+                for (current, value) in sig.values.iter_mut().zip(values) {
+                    *current = (*current).min(value);
+                }
+                sig.shingle_count += count;
+                sig
+            }
+        });
+    }
+
+    match combined {
+        Some(sig) => {
+            store(conn, "book", book_id, book_id, &sig)?;
+            flag_matches(conn, "book", book_id, book_id, &sig)
+        }
+        None => {
+            remove(conn, "book", book_id)?;
+            Ok(0)
+        }
+    }
+}
+
+/// Forget a book and its chapters, e.g. when it leaves the index. Existing
+/// flags are kept for the moderation record.
+pub fn remove_book(conn: &Connection, book_id: &str) -> Result<(), ServiceError> {
+    let delete = "DELETE FROM discovery.signature_bands b
+                  USING discovery.content_signatures s
+                  WHERE s.book_id = $1::uuid AND b.doc_type = s.doc_type AND b.doc_id = s.doc_id";
+    conn.execute(delete, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    conn.execute(
+        "DELETE FROM discovery.content_signatures WHERE book_id = $1::uuid",
+        &[ParameterValue::Str(book_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    Ok(())
+}
+
+//=============================================================================
+// Admin Endpoints
+//=============================================================================
+
+/// GET /admin/duplicates - Flagged pairs, most similar first
+pub fn list(conn: &Connection, status: &str, doc_type: Option<&str>, limit: i64, offset: i64) -> Result<Response, ServiceError> {
+    if !REVIEW_STATUSES.contains(&status) {
+        return Err(ServiceError::BadRequest(format!("status must be one of {}", REVIEW_STATUSES.join(", "))));
+    }
+    if doc_type.is_some_and(|t| t != "book" && t != "chapter") {
+        return Err(ServiceError::BadRequest("type must be book or chapter".into()));
+    }
+    let doc_type_param = doc_type.map(|t| ParameterValue::Str(t.to_string())).unwrap_or(ParameterValue::DbNull);
+
+    let query = "SELECT f.id::text, f.doc_type, f.similarity, f.status, f.review_note, f.reviewed_by::text,
+                        f.reviewed_at::text, f.created_at::text, f.updated_at::text,
+                        f.doc_id::text, f.book_id::text, b.title, c.title, b.author_id::text, u.name,
+                        f.match_doc_id::text, f.match_book_id::text, mb.title, mc.title, mb.author_id::text, mu.name
+                 FROM discovery.duplicate_flags f
+                 LEFT JOIN content.books b ON b.id = f.book_id
+                 LEFT JOIN content.chapters c ON f.doc_type = 'chapter' AND c.id = f.doc_id
+                 LEFT JOIN users.users u ON u.id = b.author_id
+                 LEFT JOIN content.books mb ON mb.id = f.match_book_id
+                 LEFT JOIN content.chapters mc ON f.doc_type = 'chapter' AND mc.id = f.match_doc_id
+                 LEFT JOIN users.users mu ON mu.id = mb.author_id
+                 WHERE f.status = $1 AND ($2::text IS NULL OR f.doc_type = $2)
+                 ORDER BY f.similarity DESC, f.created_at DESC
+                 LIMIT $3 OFFSET $4";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(status.to_string()),
+        doc_type_param.clone(),
+        ParameterValue::Int64(limit),
+        ParameterValue::Int64(offset),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let parse_uuid = |value: &spin_sdk::pg::DbValue| String::decode(value).ok().and_then(|s| Uuid::parse_str(&s).ok());
+    let flags: Vec<DuplicateFlag> = rows.rows.iter().map(|row| {
+        let document = FlaggedDocument {
+            id: parse_uuid(&row[9]).unwrap_or_default(),
+            book_id: parse_uuid(&row[10]).unwrap_or_default(),
+            book_title: String::decode(&row[11]).ok(),
+            chapter_title: String::decode(&row[12]).ok(),
+            author_id: parse_uuid(&row[13]),
+            author_name: String::decode(&row[14]).ok(),
+        };
+        let matched = FlaggedDocument {
+            id: parse_uuid(&row[15]).unwrap_or_default(),
+            book_id: parse_uuid(&row[16]).unwrap_or_default(),
+            book_title: String::decode(&row[17]).ok(),
+            chapter_title: String::decode(&row[18]).ok(),
+            author_id: parse_uuid(&row[19]),
+            author_name: String::decode(&row[20]).ok(),
+        };
+        DuplicateFlag {
+            id: parse_uuid(&row[0]).unwrap_or_default(),
+            doc_type: String::decode(&row[1]).unwrap_or_default(),
+            similarity: f64::decode(&row[2]).unwrap_or(0.0),
+            status: String::decode(&row[3]).unwrap_or_default(),
+            same_author: document.author_id.is_some() && document.author_id == matched.author_id,
+            document,
+            matched,
+            review_note: String::decode(&row[4]).ok(),
+            reviewed_by: parse_uuid(&row[5]),
+            reviewed_at: String::decode(&row[6]).ok(),
+            created_at: String::decode(&row[7]).unwrap_or_default(),
+            updated_at: String::decode(&row[8]).unwrap_or_default(),
+        }
+    }).collect();
+
+    let total = conn.query(
+        "SELECT COUNT(*) FROM discovery.duplicate_flags WHERE status = $1 AND ($2::text IS NULL OR doc_type = $2)",
+        &[ParameterValue::Str(status.to_string()), doc_type_param],
+    ).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?
+        .rows.first()
+        .map(|row| i64::decode(&row[0]).unwrap_or(0))
+        .unwrap_or(0);
+
+    crate::json_response(200, serde_json::json!({
+        "duplicates": flags,
+        "status": status,
+        "total": total,
+        "limit": limit,
+        "offset": offset
+    }))
+}
+
+/// POST /admin/duplicates/:id/review - Confirm, dismiss or reopen a flag
+pub fn review(conn: &Connection, reviewer_id: &Uuid, flag_id: &Uuid, body: ReviewRequest) -> Result<Response, ServiceError> {
+    if !REVIEW_STATUSES.contains(&body.status.as_str()) {
+        return Err(ServiceError::BadRequest(format!("status must be one of {}", REVIEW_STATUSES.join(", "))));
+    }
+    let note = body.note.map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
+
+    let update = "UPDATE discovery.duplicate_flags SET
+                  status = $2, review_note = COALESCE($3, review_note),
+                  reviewed_by = $4::uuid, reviewed_at = NOW(), updated_at = NOW()
+                  WHERE id = $1::uuid
+                  RETURNING status, reviewed_at::text";
+    let rows = conn.query(update, &[
+        ParameterValue::Str(flag_id.to_string()),
+        ParameterValue::Str(body.status.clone()),
+        note.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(reviewer_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Duplicate flag not found".into()))?;
+
+    crate::json_response(200, serde_json::json!({
+        "id": flag_id,
+        "status": String::decode(&row[0]).unwrap_or_default(),
+        "reviewed_by": reviewer_id,
+        "reviewed_at": String::decode(&row[1]).unwrap_or_default()
+    }))
+}
+
+//=============================================================================
+// Storage
+//=============================================================================
+
+fn store(conn: &Connection, doc_type: &str, doc_id: &str, book_id: &str, sig: &Signature) -> Result<(), ServiceError> {
+    let upsert = "INSERT INTO discovery.content_signatures (doc_type, doc_id, book_id, signature, shingle_count, computed_at)
+                  VALUES ($1, $2::uuid, $3::uuid, $4::jsonb, $5, NOW())
+                  ON CONFLICT (doc_type, doc_id) DO UPDATE SET
+                  book_id = EXCLUDED.book_id,
+                  signature = EXCLUDED.signature,
+                  shingle_count = EXCLUDED.shingle_count,
+                  computed_at = NOW()";
+    conn.execute(upsert, &[
+        ParameterValue::Str(doc_type.to_string()),
+        ParameterValue::Str(doc_id.to_string()),
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(serde_json::to_string(&sig.values).unwrap_or_else(|_| "[]".into())),
+        ParameterValue::Int32(sig.shingle_count.min(i32::MAX as usize) as i32),
+    ]).map_err(|e| ServiceError::Internal(format!("Upsert failed: {}", e)))?;
+
+    conn.execute(
+        "DELETE FROM discovery.signature_bands WHERE doc_type = $1 AND doc_id = $2::uuid",
+        &[ParameterValue::Str(doc_type.to_string()), ParameterValue::Str(doc_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    let (bands, hashes) = band_params(&sig.values);
+    let insert = "INSERT INTO discovery.signature_bands (doc_type, band, band_hash, doc_id)
+                  SELECT $1, t.band, t.band_hash, $2::uuid
+                  FROM unnest(string_to_array($3, ',')::smallint[], string_to_array($4, ',')::bigint[]) AS t(band, band_hash)
+                  ON CONFLICT DO NOTHING";
+    conn.execute(insert, &[
+        ParameterValue::Str(doc_type.to_string()),
+        ParameterValue::Str(doc_id.to_string()),
+        ParameterValue::Str(bands),
+        ParameterValue::Str(hashes),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    Ok(())
+}
+
+fn remove(conn: &Connection, doc_type: &str, doc_id: &str) -> Result<(), ServiceError> {
+    let params = [ParameterValue::Str(doc_type.to_string()), ParameterValue::Str(doc_id.to_string())];
+    conn.execute("DELETE FROM discovery.signature_bands WHERE doc_type = $1 AND doc_id = $2::uuid", &params)
+        .map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    conn.execute("DELETE FROM discovery.content_signatures WHERE doc_type = $1 AND doc_id = $2::uuid", &params)
+        .map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    Ok(())
+}
+
+/// Compare against documents sharing a band and flag those over the threshold
+fn flag_matches(conn: &Connection, doc_type: &str, doc_id: &str, book_id: &str, sig: &Signature) -> Result<usize, ServiceError> {
+    let (bands, hashes) = band_params(&sig.values);
+    let query = "SELECT DISTINCT s.doc_id::text, s.book_id::text, s.signature::text
+                 FROM discovery.signature_bands b
+                 JOIN discovery.content_signatures s ON s.doc_type = b.doc_type AND s.doc_id = b.doc_id
+                 WHERE b.doc_type = $1 AND s.book_id <> $2::uuid
+                   AND (b.band, b.band_hash) IN (
+                       SELECT * FROM unnest(string_to_array($3, ',')::smallint[], string_to_array($4, ',')::bigint[])
+                   )
+                 LIMIT $5";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(doc_type.to_string()),
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(bands),
+        ParameterValue::Str(hashes),
+        ParameterValue::Int32(MAX_CANDIDATES),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let threshold = threshold();
+    let mut flagged = 0;
+    for row in &rows.rows {
+        let values: Vec<u32> = match String::decode(&row[2]).ok().and_then(|s| serde_json::from_str(&s).ok()) {
+            Some(values) => values,
+            None => continue,
+        };
+        let similarity = similarity(&sig.values, &values);
+        if similarity < threshold {
+            continue;
+        }
+
+        let upsert = "INSERT INTO discovery.duplicate_flags (doc_type, doc_id, book_id, match_doc_id, match_book_id, similarity)
+                      VALUES ($1, $2::uuid, $3::uuid, $4::uuid, $5::uuid, $6)
+                      ON CONFLICT (doc_type, LEAST(doc_id, match_doc_id), GREATEST(doc_id, match_doc_id))
+                      DO UPDATE SET similarity = EXCLUDED.similarity, updated_at = NOW()";
+        conn.execute(upsert, &[
+            ParameterValue::Str(doc_type.to_string()),
+            ParameterValue::Str(doc_id.to_string()),
+            ParameterValue::Str(book_id.to_string()),
+            ParameterValue::Str(String::decode(&row[0]).unwrap_or_default()),
+            ParameterValue::Str(String::decode(&row[1]).unwrap_or_default()),
+            ParameterValue::Floating64(similarity),
+        ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+        flagged += 1;
+    }
+    Ok(flagged)
+}
+
+fn threshold() -> f64 {
+    variables::get("duplicate_similarity_threshold")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|t: &f64| *t > 0.0 && *t <= 1.0)
+        .unwrap_or(DEFAULT_THRESHOLD)
+}
+
+//=============================================================================
+// MinHash
+//=============================================================================
+
+/// Signature of a text, or None when it has too few shingles to compare
+fn signature(text: &str) -> Option<Signature> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect();
+    if words.len() < SHINGLE_WORDS {
+        return None;
+    }
+
+    let shingles: HashSet<u64> = words.windows(SHINGLE_WORDS)
+        .map(|window| fnv1a(window.join(" ").as_bytes()))
+        .collect();
+    if shingles.len() < MIN_SHINGLES {
+        return None;
+    }
+
+    let seeds: Vec<u64> = (0..NUM_HASHES as u64).map(|i| splitmix64(i.wrapping_add(0x9E37_79B9_7F4A_7C15))).collect();
+    let mut values = vec![u32::MAX; NUM_HASHES];
+    for shingle in &shingles {
+        for (value, seed) in values.iter_mut().zip(&seeds) {
+            let hash = (splitmix64(shingle ^ seed) >> 32) as u32;
+            if hash < *value {
+                *value = hash;
+            }
+        }
+    }
+
+    Some(Signature { values, shingle_count: shingles.len() })
+}
+
+/// Estimated Jaccard similarity: the share of positions with equal minimums
+fn similarity(a: &[u32], b: &[u32]) -> f64 {
+    if a.len() != NUM_HASHES || b.len() != NUM_HASHES {
+        return 0.0;
+    }
+    a.iter().zip(b).filter(|(x, y)| x == y).count() as f64 / NUM_HASHES as f64
+}
+
+/// Band numbers and hashes as comma-separated lists for `unnest`
+fn band_params(values: &[u32]) -> (String, String) {
+    let mut bands = Vec::with_capacity(BANDS);
+    let mut hashes = Vec::with_capacity(BANDS);
+    for (band, rows) in values.chunks(ROWS_PER_BAND).enumerate() {
+        let mut bytes = Vec::with_capacity(2 + rows.len() * 4);
+        bytes.extend_from_slice(&(band as u16).to_le_bytes());
+        for value in rows {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bands.push(band.to_string());
+        hashes.push((fnv1a(&bytes) as i64).to_string());
+    }
+    (bands.join(","), hashes.join(","))
+}
+
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,278 @@
+//! Chapter entities
+//!
+//! When a chapter is indexed, the characters, places and other proper nouns
+//! it mentions are pulled out with a rule-based pass: runs of capitalised
+//! words, minus sentence-initial function words and calendar names. A word
+//! that is only ever capitalised at the start of a sentence ("Suddenly") is
+//! not a name, so single words must also appear mid-sentence. Entities
+//! mentioned fewer than `MIN_MENTIONS` times are dropped as noise.
+//!
+//! Entities go into the chapter document's `entities` field, for filtered
+//! chapter search, and into `discovery.chapter_entities`, which backs
+//! `GET /books/:id/entities`: each entity with the chapters it appears in,
+//! in reading order, so readers can jump to the next chapter featuring a
+//! character.
+
+use crate::error::ServiceError;
+use serde::Serialize;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// Indices use dynamic mapping, so exact-match queries go to the keyword subfield
+pub const ENTITIES_FIELD: &str = "entities.keyword";
+
+const MIN_MENTIONS: u32 = 2;
+const MAX_ENTITIES_PER_CHAPTER: usize = 50;
+const MAX_ENTITY_WORDS: usize = 4;
+const MAX_ENTITY_CHARS: usize = 100;
+const DEFAULT_BOOK_LIMIT: i64 = 100;
+const MAX_BOOK_LIMIT: i64 = 500;
+
+/// Capitalised only because they start a sentence, or never names on their own
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "as", "at", "but", "by", "for", "from", "he", "her", "here", "his",
+    "how", "i", "if", "in", "it", "its", "my", "no", "not", "of", "oh", "on", "or", "our",
+    "she", "so", "that", "the", "their", "then", "there", "these", "they", "this", "those",
+    "to", "we", "what", "when", "where", "while", "who", "why", "with", "yes", "you", "your",
+    "chapter", "part", "prologue", "epilogue",
+    "monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday",
+    "january", "february", "march", "april", "june", "july", "august", "september",
+    "october", "november", "december",
+];
+
+/// Abbreviations whose period does not end a sentence
+const TITLES: [&str; 7] = ["mr", "mrs", "ms", "dr", "st", "prof", "sir"];
+
+/// Lowercase words allowed inside a multi-word name, e.g. "House of Lords"
+const CONNECTORS: [&str; 4] = ["of", "de", "van", "von"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entity {
+    pub key: String,
+    pub name: String,
+    pub mentions: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EntityChapter {
+    pub id: Uuid,
+    pub title: String,
+    pub chapter_number: i32,
+    pub mentions: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BookEntity {
+    pub name: String,
+    pub mentions: i64,
+    pub chapter_count: usize,
+    pub chapters: Vec<EntityChapter>,
+}
+
+//=============================================================================
+// Extraction
+//=============================================================================
+
+/// Named entities in a chapter, most mentioned first
+pub fn extract(content: &str) -> Vec<Entity> {
+    let mut counts: HashMap<String, (String, u32)> = HashMap::new();
+    let mut seen_mid_sentence: HashSet<String> = HashSet::new();
+
+    let mut run: Vec<&str> = Vec::new();
+    let mut run_starts_sentence = false;
+    let mut sentence_start = true;
+
+    let mut flush = |run: &mut Vec<&str>, starts_sentence: bool| {
+        // Trailing connectors belong to the following text, not the name
+        while run.last().is_some_and(|w| CONNECTORS.contains(w)) {
+            run.pop();
+        }
+        let mut words: &[&str] = run;
+        let mut starts_sentence = starts_sentence;
+        while let Some(first) = words.first() {
+            if STOPWORDS.contains(&first.to_lowercase().as_str()) {
+                words = &words[1..];
+                starts_sentence = false;
+            } else {
+                break;
+            }
+        }
+        if !words.is_empty() && words.len() <= MAX_ENTITY_WORDS {
+            let name = words.join(" ");
+            let key = name.to_lowercase();
+            if name.chars().count() <= MAX_ENTITY_CHARS {
+                if !starts_sentence || words.len() > 1 {
+                    seen_mid_sentence.insert(key.clone());
+                }
+                counts.entry(key).or_insert_with(|| (name, 0)).1 += 1;
+            }
+        }
+        run.clear();
+    };
+
+    for raw in content.split_whitespace() {
+        let opens_quote = raw.starts_with(['"', '“', '\'', '‘', '(']);
+        let word = raw.trim_matches(|c: char| !c.is_alphanumeric() && c != '\'' && c != '’' && c != '-');
+        let word = word.trim_matches(|c| c == '\'' || c == '’' || c == '-');
+        let word = word.strip_suffix("'s").or_else(|| word.strip_suffix("’s")).unwrap_or(word);
+        let ends_sentence = raw.trim_end_matches(['"', '”', '\'', '’', ')']).ends_with(['.', '!', '?', ':', ';'])
+            && !TITLES.contains(&word.to_lowercase().as_str());
+        let breaks_run = ends_sentence || raw.trim_end_matches(['"', '”', '\'', '’', ')']).ends_with([',', '—']);
+
+        if opens_quote && !run.is_empty() {
+            flush(&mut run, run_starts_sentence);
+        }
+        let starts_sentence = sentence_start || opens_quote;
+
+        if is_capitalised(word) {
+            if run.is_empty() {
+                run_starts_sentence = starts_sentence;
+            }
+            run.push(word);
+        } else if !run.is_empty() && CONNECTORS.contains(&word) {
+            run.push(word);
+        } else if !run.is_empty() {
+            flush(&mut run, run_starts_sentence);
+        }
+
+        if breaks_run && !run.is_empty() {
+            flush(&mut run, run_starts_sentence);
+        }
+        sentence_start = ends_sentence;
+    }
+    if !run.is_empty() {
+        flush(&mut run, run_starts_sentence);
+    }
+
+    let mut entities: Vec<Entity> = counts.into_iter()
+        .filter(|(key, (_, mentions))| *mentions >= MIN_MENTIONS && seen_mid_sentence.contains(key))
+        .map(|(key, (name, mentions))| Entity { key, name, mentions })
+        .collect();
+    entities.sort_by(|a, b| b.mentions.cmp(&a.mentions).then_with(|| a.key.cmp(&b.key)));
+    entities.truncate(MAX_ENTITIES_PER_CHAPTER);
+    entities
+}
+
+fn is_capitalised(word: &str) -> bool {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.is_uppercase() && word.chars().count() > 1 && chars.all(|c| c.is_alphabetic() || c == '\'' || c == '’' || c == '-'),
+        None => false,
+    }
+}
+
+//=============================================================================
+// Storage
+//=============================================================================
+
+/// Replace a chapter's stored entities
+pub fn store(conn: &Connection, chapter_id: &str, book_id: &str, entities: &[Entity]) -> Result<(), ServiceError> {
+    remove_chapter(conn, chapter_id)?;
+
+    let insert = "INSERT INTO discovery.chapter_entities (chapter_id, book_id, entity_key, name, mentions)
+                  VALUES ($1::uuid, $2::uuid, $3, $4, $5)";
+    for entity in entities {
+        conn.execute(insert, &[
+            ParameterValue::Str(chapter_id.to_string()),
+            ParameterValue::Str(book_id.to_string()),
+            ParameterValue::Str(entity.key.clone()),
+            ParameterValue::Str(entity.name.clone()),
+            ParameterValue::Int32(entity.mentions as i32),
+        ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    }
+    Ok(())
+}
+
+pub fn remove_chapter(conn: &Connection, chapter_id: &str) -> Result<(), ServiceError> {
+    conn.execute(
+        "DELETE FROM discovery.chapter_entities WHERE chapter_id = $1::uuid",
+        &[ParameterValue::Str(chapter_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    Ok(())
+}
+
+pub fn remove_book(conn: &Connection, book_id: &str) -> Result<(), ServiceError> {
+    conn.execute(
+        "DELETE FROM discovery.chapter_entities WHERE book_id = $1::uuid",
+        &[ParameterValue::Str(book_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    Ok(())
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// GET /books/:id/entities - Entities across a book with the chapters featuring
+/// each, in reading order. Published books are open to everyone; drafts only
+/// to their author.
+pub fn list_for_book(
+    conn: &Connection,
+    book_id: &Uuid,
+    viewer: Option<&Uuid>,
+    min_chapters: i64,
+    limit: Option<i64>,
+) -> Result<Response, ServiceError> {
+    let limit = limit.unwrap_or(DEFAULT_BOOK_LIMIT).clamp(1, MAX_BOOK_LIMIT);
+
+    let visible = "SELECT 1 FROM content.books WHERE id = $1 AND (status = 'published' OR author_id::text = $2)";
+    let rows = conn.query(visible, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(viewer.map(|v| v.to_string()).unwrap_or_default()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if rows.rows.is_empty() {
+        return Err(ServiceError::NotFound("Book not found".into()));
+    }
+
+    // Entities appearing in enough chapters, then every appearance of each
+    let query = "WITH ranked AS (
+                     SELECT entity_key, SUM(mentions) AS total, COUNT(*) AS chapters
+                     FROM discovery.chapter_entities
+                     WHERE book_id = $1::uuid
+                     GROUP BY entity_key
+                     HAVING COUNT(*) >= $2
+                     ORDER BY COUNT(*) DESC, SUM(mentions) DESC, entity_key
+                     LIMIT $3
+                 )
+                 SELECT r.entity_key, e.name, r.total, c.id::text, c.title, c.chapter_number, e.mentions
+                 FROM ranked r
+                 JOIN discovery.chapter_entities e ON e.book_id = $1::uuid AND e.entity_key = r.entity_key
+                 JOIN content.chapters c ON c.id = e.chapter_id
+                 ORDER BY r.chapters DESC, r.total DESC, r.entity_key, c.chapter_number";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Int64(min_chapters.max(1)),
+        ParameterValue::Int64(limit),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut entities: Vec<BookEntity> = Vec::new();
+    let mut current_key = String::new();
+    for row in &rows.rows {
+        let key = String::decode(&row[0]).unwrap_or_default();
+        if key != current_key || entities.is_empty() {
+            entities.push(BookEntity {
+                name: String::decode(&row[1]).unwrap_or_default(),
+                mentions: i64::decode(&row[2]).unwrap_or(0),
+                chapter_count: 0,
+                chapters: Vec::new(),
+            });
+            current_key = key;
+        }
+        if let Some(entity) = entities.last_mut() {
+            entity.chapters.push(EntityChapter {
+                id: Uuid::parse_str(&String::decode(&row[3]).unwrap_or_default()).unwrap_or_default(),
+                title: String::decode(&row[4]).unwrap_or_default(),
+                chapter_number: i32::decode(&row[5]).unwrap_or(0),
+                mentions: i32::decode(&row[6]).unwrap_or(0),
+            });
+            entity.chapter_count = entity.chapters.len();
+        }
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "book_id": book_id,
+        "entities": entities
+    }))
+}
@@ -11,9 +11,15 @@ pub enum ServiceError {
     #[error("Unauthorized: {0}")]
     Unauthorized(String),
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
     #[error("Not found: {0}")]
     NotFound(String),
 
+    #[error("Too many requests: {0}")]
+    TooManyRequests(String),
+
     #[error("Internal error: {0}")]
     Internal(String),
 
@@ -32,7 +38,9 @@ impl ServiceError {
         match self {
             ServiceError::BadRequest(_) => 400,
             ServiceError::Unauthorized(_) => 401,
+            ServiceError::Forbidden(_) => 403,
             ServiceError::NotFound(_) => 404,
+            ServiceError::TooManyRequests(_) => 429,
             ServiceError::Internal(_) => 500,
             ServiceError::ElasticsearchError(_) => 502,
         }
@@ -42,7 +50,9 @@ impl ServiceError {
         match self {
             ServiceError::BadRequest(_) => "BAD_REQUEST",
             ServiceError::Unauthorized(_) => "UNAUTHORIZED",
+            ServiceError::Forbidden(_) => "FORBIDDEN",
             ServiceError::NotFound(_) => "NOT_FOUND",
+            ServiceError::TooManyRequests(_) => "TOO_MANY_REQUESTS",
             ServiceError::Internal(_) => "INTERNAL_ERROR",
             ServiceError::ElasticsearchError(_) => "ELASTICSEARCH_ERROR",
         }
@@ -50,6 +60,7 @@ impl ServiceError {
 
     pub fn into_response(self) -> Response {
         let status = self.status_code();
+        let throttled = matches!(self, ServiceError::TooManyRequests(_));
         let body = ErrorResponse {
             error: self.to_string(),
             code: self.error_code().to_string(),
@@ -59,12 +70,23 @@ impl ServiceError {
             r#"{"error":"Internal error","code":"INTERNAL_ERROR"}"#.to_string()
         });
 
-        Response::builder()
+        let mut builder = Response::builder();
+        builder
             .status(status)
             .header("Content-Type", "application/json")
-            .header("Access-Control-Allow-Origin", "*")
-            .body(json)
-            .build()
+            .header("Access-Control-Allow-Origin", "*");
+        if throttled {
+            builder.header("Retry-After", "30");
+        }
+        builder.body(json).build()
     }
 }
 
+impl From<authorworks_access::AccessError> for ServiceError {
+    fn from(e: authorworks_access::AccessError) -> Self {
+        match e {
+            authorworks_access::AccessError::RoleLookup(msg) => ServiceError::Internal(format!("Query failed: {}", msg)),
+            e => ServiceError::Forbidden(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Event-triggered aggregate refresh
+//!
+//! Other services post notable events so trending and "readers also read"
+//! data reflect them within seconds instead of at the next periodic rebuild.
+//! Only the affected book is recomputed. Read events refresh trending every
+//! time (a single indexed count) but only recompute neighbors during a read
+//! surge, and no more often than `neighbor_refresh_interval_seconds`.
+//! Purchase events are also recorded for reader segmentation.
+
+use crate::collaborative;
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use chrono::Utc;
+use uuid::Uuid;
+
+const DEFAULT_SURGE_MIN_READS: i64 = 20;
+const DEFAULT_SURGE_FACTOR: f64 = 3.0;
+const DEFAULT_NEIGHBOR_REFRESH_INTERVAL_SECONDS: i64 = 300;
+
+#[derive(Debug, Deserialize)]
+pub struct DiscoveryEvent {
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub book_id: Uuid,
+    /// Buyer, required for `book_purchased`
+    pub user_id: Option<Uuid>,
+    pub amount_cents: Option<i64>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RefreshOutcome {
+    pub trending_refreshed: bool,
+    pub neighbors_refreshed: bool,
+    pub removed: bool,
+    pub surge: bool,
+    pub purchase_recorded: bool,
+}
+
+/// Apply `book_published`, `book_unpublished`, `book_read`, or `book_purchased`
+pub fn handle_event(conn: &Connection, event: &DiscoveryEvent) -> Result<RefreshOutcome, ServiceError> {
+    let config = collaborative::get_config();
+    let mut outcome = RefreshOutcome::default();
+
+    match event.event_type.as_str() {
+        "book_published" => {
+            crate::trending::refresh_book(conn, &event.book_id)?;
+            collaborative::refresh_book_neighbors(conn, &config, &event.book_id)?;
+            outcome.trending_refreshed = true;
+            outcome.neighbors_refreshed = true;
+        }
+        "book_unpublished" => {
+            crate::trending::remove_book(conn, &event.book_id)?;
+            collaborative::remove_book(conn, &event.book_id)?;
+            outcome.removed = true;
+        }
+        "book_read" => {
+            crate::trending::refresh_book(conn, &event.book_id)?;
+            outcome.trending_refreshed = true;
+
+            outcome.surge = is_surging(conn, &event.book_id)?;
+            if outcome.surge && neighbors_due(conn, &event.book_id)? {
+                collaborative::refresh_book_neighbors(conn, &config, &event.book_id)?;
+                outcome.neighbors_refreshed = true;
+            }
+        }
+        "book_purchased" => {
+            let user_id = event.user_id
+                .ok_or_else(|| ServiceError::BadRequest("user_id is required for book_purchased".into()))?;
+            record_purchase(conn, &user_id, &event.book_id, event.amount_cents)?;
+            outcome.purchase_recorded = true;
+        }
+        other => return Err(ServiceError::BadRequest(format!("Unknown event type: {}", other))),
+    }
+
+    Ok(outcome)
+}
+
+/// Reads in the last hour are at least `surge_min_reads` and `surge_factor`
+/// times the book's hourly average over the previous week
+fn is_surging(conn: &Connection, book_id: &Uuid) -> Result<bool, ServiceError> {
+    let min_reads = variables::get("surge_min_reads").ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_SURGE_MIN_READS);
+    let factor = variables::get("surge_factor").ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .unwrap_or(DEFAULT_SURGE_FACTOR);
+
+    let query = "SELECT
+                    COUNT(*) FILTER (WHERE created_at > NOW() - INTERVAL '1 hour'),
+                    COUNT(*) FILTER (WHERE created_at <= NOW() - INTERVAL '1 hour')
+                 FROM discovery.reading_history
+                 WHERE book_id = $1 AND created_at > NOW() - INTERVAL '7 days 1 hour'";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let (last_hour, previous_week) = rows.rows.first()
+        .map(|row| (i64::decode(&row[0]).unwrap_or(0), i64::decode(&row[1]).unwrap_or(0)))
+        .unwrap_or((0, 0));
+
+    let hourly_average = previous_week as f64 / (7.0 * 24.0);
+    Ok(last_hour >= min_reads && last_hour as f64 >= factor * hourly_average)
+}
+
+fn record_purchase(conn: &Connection, user_id: &Uuid, book_id: &Uuid, amount_cents: Option<i64>) -> Result<(), ServiceError> {
+    let insert = "INSERT INTO discovery.book_purchases (user_id, book_id, amount_cents, purchased_at)
+                  VALUES ($1, $2, $3, $4)
+                  ON CONFLICT (user_id, book_id) DO NOTHING";
+    conn.execute(insert, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(book_id.to_string()),
+        amount_cents.map(ParameterValue::Int64).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    Ok(())
+}
+
+fn neighbors_due(conn: &Connection, book_id: &Uuid) -> Result<bool, ServiceError> {
+    let interval = variables::get("neighbor_refresh_interval_seconds").ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_NEIGHBOR_REFRESH_INTERVAL_SECONDS);
+
+    Ok(!collaborative::recently_computed(conn, book_id, interval)?)
+}
@@ -0,0 +1,318 @@
+//! Ranking experiments
+//!
+//! An experiment splits signed-in readers of one surface (`search_books` or
+//! `recommendations`) between weighted variants, each naming a ranking
+//! formula from [`RANKINGS`]. A reader's bucket is the FNV-1a hash of
+//! `"{experiment_id}:{user_id}"` modulo the total weight, so it is stable for
+//! the life of the experiment without storing assignments, and independent
+//! between experiments. Signed-out readers always get the current ranking
+//! and are not logged.
+//!
+//! Every ranked response served under an experiment is logged as an
+//! exposure. The results report takes each reader's first exposure to a
+//! variant and joins their search analytics events from then until the
+//! experiment stopped: searches, clicks, click-through, click position and
+//! dwell per variant. Only events of the surface's `search_type` count
+//! (`books` and `recommendations`).
+//!
+//! At most one experiment runs per surface. Serving never fails because of
+//! an experiment: a lookup error ranks as control.
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+pub const SURFACES: [&str; 2] = ["search_books", "recommendations"];
+
+/// `control` is the current ranking; the others rescore its matches
+pub const RANKINGS: [&str; 3] = ["control", "recency", "length"];
+
+const MAX_VARIANTS: usize = 5;
+const MAX_WEIGHT: u64 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateExperimentRequest {
+    pub id: String,
+    pub surface: String,
+    pub description: Option<String>,
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Variant {
+    pub name: String,
+    pub weight: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VariantResult {
+    pub variant: String,
+    pub users: i64,
+    pub exposures: i64,
+    pub searches: i64,
+    pub clicks: i64,
+    /// Clicks per search; can exceed 1 when readers open several results
+    pub click_through_rate: f64,
+    pub avg_click_position: Option<f64>,
+    pub avg_dwell_ms: Option<f64>,
+}
+
+/// The variant a reader is bucketed into
+#[derive(Debug, Clone)]
+pub struct Assignment {
+    pub experiment_id: String,
+    pub variant: String,
+}
+
+//=============================================================================
+// Bucketing
+//=============================================================================
+
+/// Assignment for a reader on a surface, if an experiment is running there
+pub fn assign(conn: &Connection, surface: &str, user_id: Option<&Uuid>) -> Option<Assignment> {
+    let user_id = user_id?;
+    let query = "SELECT id, variants::text FROM discovery.experiments WHERE surface = $1 AND status = 'running'";
+    let rows = conn.query(query, &[ParameterValue::Str(surface.to_string())]).ok()?;
+    let row = rows.rows.first()?;
+
+    let experiment_id = String::decode(&row[0]).ok()?;
+    let variants: Vec<Variant> = serde_json::from_str(&String::decode(&row[1]).ok()?).ok()?;
+    let variant = bucket(&experiment_id, user_id, &variants)?;
+    Some(Assignment { experiment_id, variant })
+}
+
+fn bucket(experiment_id: &str, user_id: &Uuid, variants: &[Variant]) -> Option<String> {
+    let total: u64 = variants.iter().map(|v| v.weight).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut point = crate::duplicates::fnv1a(format!("{}:{}", experiment_id, user_id).as_bytes()) % total;
+    for variant in variants {
+        if point < variant.weight {
+            return Some(variant.name.clone());
+        }
+        point -= variant.weight;
+    }
+    None
+}
+
+impl Assignment {
+    /// Rescore an Elasticsearch body's query with this variant's ranking
+    pub fn rank(&self, search_body: &mut serde_json::Value) {
+        let functions = match self.variant.as_str() {
+            // Favour recently updated books, halving the score at 60 days
+            "recency" => serde_json::json!([
+                {"gauss": {"updated_at": {"origin": "now", "scale": "60d", "decay": 0.5}}}
+            ]),
+            // Favour longer books, logarithmically
+            "length" => serde_json::json!([
+                {"field_value_factor": {"field": "word_count", "modifier": "log2p", "missing": 0}}
+            ]),
+            _ => return,
+        };
+        let query = search_body["query"].take();
+        search_body["query"] = serde_json::json!({
+            "function_score": {
+                "query": query,
+                "functions": functions,
+                "boost_mode": "multiply"
+            }
+        });
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({ "id": self.experiment_id, "variant": self.variant })
+    }
+
+    /// Log that a reader was served this variant; failures are ignored
+    pub fn log_exposure(&self, conn: &Connection, user_id: &Uuid, query: Option<&str>, result_count: usize) {
+        let insert = "INSERT INTO discovery.experiment_exposures (id, experiment_id, variant, user_id, query, result_count)
+                      VALUES ($1, $2, $3, $4, $5, $6)";
+        let _ = conn.execute(insert, &[
+            ParameterValue::Str(Uuid::new_v4().to_string()),
+            ParameterValue::Str(self.experiment_id.clone()),
+            ParameterValue::Str(self.variant.clone()),
+            ParameterValue::Str(user_id.to_string()),
+            query.map(|q| ParameterValue::Str(q.to_string())).unwrap_or(ParameterValue::DbNull),
+            ParameterValue::Int32(result_count as i32),
+        ]);
+    }
+}
+
+//=============================================================================
+// Administration
+//=============================================================================
+
+/// GET /admin/experiments - All experiments, running first
+pub fn list(conn: &Connection) -> Result<Response, ServiceError> {
+    let query = "SELECT id, surface, description, variants::text, status, started_at, stopped_at
+                 FROM discovery.experiments
+                 ORDER BY status = 'running' DESC, started_at DESC";
+    let rows = conn.query(query, &[])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let experiments: Vec<serde_json::Value> = rows.rows.iter().map(|row| experiment_json(row)).collect();
+    crate::json_response(200, serde_json::json!({ "experiments": experiments }))
+}
+
+/// POST /admin/experiments - Start an experiment
+pub fn create(conn: &Connection, user_id: &Uuid, body: CreateExperimentRequest) -> Result<Response, ServiceError> {
+    let id = body.id.trim();
+    if id.is_empty() || id.len() > 64 || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(ServiceError::BadRequest("id must be 1-64 letters, digits, '-' or '_'".into()));
+    }
+    if !SURFACES.contains(&body.surface.as_str()) {
+        return Err(ServiceError::BadRequest(format!("surface must be one of: {}", SURFACES.join(", "))));
+    }
+    if body.variants.len() < 2 || body.variants.len() > MAX_VARIANTS {
+        return Err(ServiceError::BadRequest(format!("An experiment needs 2-{} variants", MAX_VARIANTS)));
+    }
+    for (i, variant) in body.variants.iter().enumerate() {
+        if !RANKINGS.contains(&variant.name.as_str()) {
+            return Err(ServiceError::BadRequest(format!("Variant names must be one of: {}", RANKINGS.join(", "))));
+        }
+        if body.variants[..i].iter().any(|v| v.name == variant.name) {
+            return Err(ServiceError::BadRequest(format!("Variant '{}' is listed twice", variant.name)));
+        }
+        if variant.weight == 0 || variant.weight > MAX_WEIGHT {
+            return Err(ServiceError::BadRequest(format!("Variant weights must be between 1 and {}", MAX_WEIGHT)));
+        }
+    }
+
+    let existing = "SELECT id, status FROM discovery.experiments WHERE id = $1 OR (surface = $2 AND status = 'running')";
+    let rows = conn.query(existing, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(body.surface.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if let Some(row) = rows.rows.first() {
+        let other = String::decode(&row[0]).unwrap_or_default();
+        return Err(ServiceError::BadRequest(if other == id {
+            format!("Experiment '{}' already exists", id)
+        } else {
+            format!("Experiment '{}' is already running on {}", other, body.surface)
+        }));
+    }
+
+    let variants = serde_json::to_string(&body.variants)
+        .map_err(|e| ServiceError::Internal(format!("Serialization failed: {}", e)))?;
+    let insert = "INSERT INTO discovery.experiments (id, surface, description, variants, created_by)
+                  VALUES ($1, $2, $3, $4::jsonb, $5)";
+    conn.execute(insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(body.surface.clone()),
+        body.description.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(variants),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    crate::json_response(201, serde_json::json!({
+        "id": id,
+        "surface": body.surface,
+        "variants": body.variants,
+        "status": "running"
+    }))
+}
+
+/// POST /admin/experiments/:id/stop - Stop an experiment; its readers return to control
+pub fn stop(conn: &Connection, id: &str) -> Result<Response, ServiceError> {
+    let update = "UPDATE discovery.experiments SET status = 'stopped', stopped_at = NOW()
+                  WHERE id = $1 AND status = 'running'
+                  RETURNING id";
+    let rows = conn.query(update, &[ParameterValue::Str(id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    if rows.rows.is_empty() {
+        return Err(ServiceError::NotFound("No running experiment with that ID".into()));
+    }
+
+    crate::json_response(200, serde_json::json!({ "id": id, "status": "stopped" }))
+}
+
+//=============================================================================
+// Results
+//=============================================================================
+
+/// GET /admin/experiments/:id/results - Search analytics per variant
+pub fn results(conn: &Connection, id: &str) -> Result<Response, ServiceError> {
+    let query = "SELECT id, surface, description, variants::text, status, started_at, stopped_at
+                 FROM discovery.experiments WHERE id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let experiment = rows.rows.first()
+        .map(|row| experiment_json(row))
+        .ok_or_else(|| ServiceError::NotFound("Experiment not found".into()))?;
+    let search_type = match experiment["surface"].as_str() {
+        Some("recommendations") => "recommendations",
+        _ => "books",
+    };
+
+    let query = "WITH firsts AS (
+                     SELECT variant, user_id, MIN(created_at) AS first_seen, COUNT(*) AS exposures
+                     FROM discovery.experiment_exposures
+                     WHERE experiment_id = $1
+                     GROUP BY variant, user_id
+                 )
+                 SELECT f.variant,
+                        COUNT(DISTINCT f.user_id),
+                        COALESCE(SUM(f.exposures), 0)::bigint,
+                        COALESCE(SUM(ev.searches), 0)::bigint,
+                        COALESCE(SUM(ev.clicks), 0)::bigint,
+                        (SUM(ev.position_sum) / NULLIF(SUM(ev.clicks), 0))::float8,
+                        (SUM(ev.dwell_sum) / NULLIF(SUM(ev.dwells), 0))::float8
+                 FROM firsts f
+                 CROSS JOIN LATERAL (
+                     SELECT COUNT(*) FILTER (WHERE s.event_type = 'search') AS searches,
+                            COUNT(*) FILTER (WHERE s.event_type = 'click') AS clicks,
+                            SUM(s.position) FILTER (WHERE s.event_type = 'click') AS position_sum,
+                            SUM(s.dwell_ms) FILTER (WHERE s.event_type = 'click') AS dwell_sum,
+                            COUNT(s.dwell_ms) FILTER (WHERE s.event_type = 'click') AS dwells
+                     FROM discovery.search_events s
+                     JOIN discovery.experiments e ON e.id = $1
+                     WHERE s.user_id = f.user_id
+                       AND s.search_type = $2
+                       AND s.created_at >= f.first_seen
+                       AND (e.stopped_at IS NULL OR s.created_at <= e.stopped_at)
+                 ) ev
+                 GROUP BY f.variant
+                 ORDER BY f.variant";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(search_type.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let variants: Vec<VariantResult> = rows.rows.iter().map(|row| {
+        let searches = i64::decode(&row[3]).unwrap_or(0);
+        let clicks = i64::decode(&row[4]).unwrap_or(0);
+        VariantResult {
+            variant: String::decode(&row[0]).unwrap_or_default(),
+            users: i64::decode(&row[1]).unwrap_or(0),
+            exposures: i64::decode(&row[2]).unwrap_or(0),
+            searches,
+            clicks,
+            click_through_rate: if searches > 0 { clicks as f64 / searches as f64 } else { 0.0 },
+            avg_click_position: f64::decode(&row[5]).ok(),
+            avg_dwell_ms: f64::decode(&row[6]).ok(),
+        }
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "experiment": experiment,
+        "variants": variants
+    }))
+}
+
+fn experiment_json(row: &[spin_sdk::pg::DbValue]) -> serde_json::Value {
+    let variants: serde_json::Value = String::decode(&row[3]).ok()
+        .and_then(|v| serde_json::from_str(&v).ok())
+        .unwrap_or_else(|| serde_json::json!([]));
+    serde_json::json!({
+        "id": String::decode(&row[0]).unwrap_or_default(),
+        "surface": String::decode(&row[1]).unwrap_or_default(),
+        "description": String::decode(&row[2]).ok(),
+        "variants": variants,
+        "status": String::decode(&row[4]).unwrap_or_default(),
+        "started_at": String::decode(&row[5]).unwrap_or_default(),
+        "stopped_at": String::decode(&row[6]).ok()
+    })
+}
@@ -0,0 +1,380 @@
+//! Author follows and the following feed
+//!
+//! Readers follow authors in `discovery.follows`. `/feed` lists what the
+//! followed authors have put out recently, newest first: books when they are
+//! published, and new chapters of books that are already out. The first page
+//! is topped up with trending books, so a reader who follows nobody yet, or
+//! whose authors have been quiet, still gets something to read.
+//! `/authors/:id/books` is the "more from this author" shelf. Both honour
+//! the reader's safe search level.
+
+use crate::content_rating::{self, SafeSearch};
+use crate::error::ServiceError;
+use crate::trending;
+use serde::Serialize;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use chrono::DateTime;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+const DEFAULT_FEED_WINDOW_DAYS: i32 = 30;
+pub const DEFAULT_FEED_LIMIT: i64 = 20;
+const MAX_FEED_LIMIT: i64 = 50;
+pub const DEFAULT_AUTHOR_BOOKS_LIMIT: i64 = 12;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Serialize)]
+pub struct FeedBook {
+    pub id: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub genre: Option<String>,
+    pub cover_url: Option<String>,
+    pub word_count: i32,
+    pub published_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeedAuthor {
+    pub id: String,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeedChapter {
+    pub id: String,
+    pub title: Option<String>,
+    pub chapter_number: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeedItem {
+    /// `published`, `new_chapter` or `trending`
+    #[serde(rename = "type")]
+    pub item_type: String,
+    pub occurred_at: Option<String>,
+    pub book: FeedBook,
+    pub author: FeedAuthor,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chapter: Option<FeedChapter>,
+}
+
+/// How far back the feed looks, from `feed_window_days`
+fn feed_window_days() -> i32 {
+    variables::get("feed_window_days")
+        .ok()
+        .and_then(|v| v.parse::<i32>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_FEED_WINDOW_DAYS)
+}
+
+//=============================================================================
+// Following
+//=============================================================================
+
+/// POST /authors/:id/follow
+pub fn follow(conn: &Connection, follower_id: &Uuid, author_id: &Uuid) -> Result<Response, ServiceError> {
+    if follower_id == author_id {
+        return Err(ServiceError::BadRequest("You cannot follow yourself".into()));
+    }
+    author_name(conn, author_id)?;
+
+    let insert = "INSERT INTO discovery.follows (follower_id, author_id, created_at)
+                  VALUES ($1, $2, NOW())
+                  ON CONFLICT (follower_id, author_id) DO NOTHING";
+    conn.execute(insert, &[
+        ParameterValue::Str(follower_id.to_string()),
+        ParameterValue::Str(author_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    crate::json_response(200, serde_json::json!({
+        "author_id": author_id,
+        "following": true,
+        "followers": follower_count(conn, author_id)?
+    }))
+}
+
+/// DELETE /authors/:id/follow - Unfollowing someone not followed is a no-op
+pub fn unfollow(conn: &Connection, follower_id: &Uuid, author_id: &Uuid) -> Result<Response, ServiceError> {
+    conn.execute(
+        "DELETE FROM discovery.follows WHERE follower_id = $1 AND author_id = $2",
+        &[
+            ParameterValue::Str(follower_id.to_string()),
+            ParameterValue::Str(author_id.to_string()),
+        ],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    crate::json_response(200, serde_json::json!({
+        "author_id": author_id,
+        "following": false,
+        "followers": follower_count(conn, author_id)?
+    }))
+}
+
+fn author_name(conn: &Connection, author_id: &Uuid) -> Result<Option<String>, ServiceError> {
+    let rows = conn.query("SELECT name FROM users.users WHERE id = $1", &[ParameterValue::Str(author_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first().ok_or_else(|| ServiceError::NotFound("Author not found".into()))?;
+    Ok(String::decode(&row[0]).ok())
+}
+
+fn follower_count(conn: &Connection, author_id: &Uuid) -> Result<i64, ServiceError> {
+    let rows = conn.query("SELECT COUNT(*) FROM discovery.follows WHERE author_id = $1", &[ParameterValue::Str(author_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    Ok(rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0))
+}
+
+fn is_following(conn: &Connection, follower_id: &Uuid, author_id: &Uuid) -> Result<bool, ServiceError> {
+    let query = "SELECT EXISTS (SELECT 1 FROM discovery.follows WHERE follower_id = $1 AND author_id = $2)";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(follower_id.to_string()),
+        ParameterValue::Str(author_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    Ok(rows.rows.first().map(|row| bool::decode(&row[0]).unwrap_or(false)).unwrap_or(false))
+}
+
+//=============================================================================
+// More From This Author
+//=============================================================================
+
+/// GET /authors/:id/books - The author's published books, newest first
+pub fn author_books(
+    conn: &Connection,
+    viewer_id: Option<&Uuid>,
+    author_id: &Uuid,
+    exclude: Option<&Uuid>,
+    limit: i64,
+    safe_search: &SafeSearch,
+) -> Result<Response, ServiceError> {
+    let name = author_name(conn, author_id)?;
+    let limit = limit.clamp(1, MAX_FEED_LIMIT);
+
+    let query = format!(
+        "SELECT id::text, title, description, genre, cover_image_url, COALESCE(word_count, 0), published_at::text
+         FROM content.books
+         WHERE author_id = $1 AND status = 'published'
+           AND ($2::text IS NULL OR id::text <> $2::text)
+           AND {}
+         ORDER BY published_at DESC NULLS LAST, updated_at DESC
+         LIMIT $3",
+        content_rating::sql_condition("content.books", 4)
+    );
+    let [level, hidden] = safe_search.sql_params();
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(author_id.to_string()),
+        exclude.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Int64(limit),
+        level,
+        hidden,
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let books: Vec<FeedBook> = rows.rows.iter().filter_map(|row| book_from_row(row, 0)).collect();
+    let following = match viewer_id {
+        Some(viewer_id) if viewer_id != author_id => is_following(conn, viewer_id, author_id)?,
+        _ => false,
+    };
+
+    crate::json_response(200, serde_json::json!({
+        "author": {
+            "id": author_id,
+            "name": name,
+            "followers": follower_count(conn, author_id)?,
+            "following": following
+        },
+        "books": books,
+        "safe_search": safe_search.level.as_str()
+    }))
+}
+
+//=============================================================================
+// Feed
+//=============================================================================
+
+/// GET /feed - Recent activity from followed authors, newest first
+///
+/// Pages with `before`, the `occurred_at` of the last item seen. Trending
+/// books only fill the first page; later pages are followed authors only.
+pub fn feed(
+    conn: &Connection,
+    user_id: &Uuid,
+    before: Option<&str>,
+    limit: i64,
+    safe_search: &SafeSearch,
+) -> Result<Response, ServiceError> {
+    if let Some(before) = before {
+        if !is_timestamp(before) {
+            return Err(ServiceError::BadRequest("before must be a timestamp".into()));
+        }
+    }
+    let limit = limit.clamp(1, MAX_FEED_LIMIT);
+
+    // Chapters count as news only when added after the book came out, so a
+    // newly published book does not also flood the feed with its chapters
+    let rated = content_rating::sql_condition("b", 5);
+    let query = format!(
+        "SELECT item_type, occurred_at::text, book_id, title, description, genre, cover_image_url,
+                word_count, published_at, author_id, author_name,
+                chapter_id, chapter_title, chapter_number
+         FROM (
+             SELECT 'published' AS item_type, b.published_at AS occurred_at,
+                    b.id::text AS book_id, b.title, b.description, b.genre, b.cover_image_url,
+                    COALESCE(b.word_count, 0) AS word_count, b.published_at::text AS published_at,
+                    b.author_id::text AS author_id, u.name AS author_name,
+                    NULL::text AS chapter_id, NULL::text AS chapter_title, NULL::int AS chapter_number
+             FROM discovery.follows f
+             JOIN content.books b ON b.author_id = f.author_id
+             JOIN users.users u ON u.id = b.author_id
+             WHERE f.follower_id = $1 AND b.status = 'published' AND {rated}
+               AND b.published_at > NOW() - make_interval(days => $2)
+             UNION ALL
+             SELECT 'new_chapter', c.created_at,
+                    b.id::text, b.title, b.description, b.genre, b.cover_image_url,
+                    COALESCE(b.word_count, 0), b.published_at::text,
+                    b.author_id::text, u.name,
+                    c.id::text, c.title, c.chapter_number
+             FROM discovery.follows f
+             JOIN content.books b ON b.author_id = f.author_id
+             JOIN content.chapters c ON c.book_id = b.id
+             JOIN users.users u ON u.id = b.author_id
+             WHERE f.follower_id = $1 AND b.status = 'published' AND {rated}
+               AND c.created_at > b.published_at
+               AND c.created_at > NOW() - make_interval(days => $2)
+         ) items
+         WHERE $3::timestamptz IS NULL OR occurred_at < $3::timestamptz
+         ORDER BY occurred_at DESC
+         LIMIT $4",
+    );
+    let [level, hidden] = safe_search.sql_params();
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Int32(feed_window_days()),
+        before.map(|b| ParameterValue::Str(b.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Int64(limit),
+        level,
+        hidden,
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut items: Vec<FeedItem> = rows.rows.iter().filter_map(|row| {
+        let book = book_from_row(row, 2)?;
+        let chapter = String::decode(&row[11]).ok().map(|id| FeedChapter {
+            id,
+            title: String::decode(&row[12]).ok(),
+            chapter_number: i32::decode(&row[13]).unwrap_or(0),
+        });
+        Some(FeedItem {
+            item_type: String::decode(&row[0]).ok()?,
+            occurred_at: String::decode(&row[1]).ok(),
+            book,
+            author: FeedAuthor {
+                id: String::decode(&row[9]).ok()?,
+                name: String::decode(&row[10]).ok(),
+            },
+            chapter,
+        })
+    }).collect();
+
+    // A full page may have more behind it; the trending fill never does
+    let next_before = if items.len() as i64 == limit {
+        items.last().and_then(|item| item.occurred_at.clone())
+    } else {
+        None
+    };
+
+    if before.is_none() && (items.len() as i64) < limit {
+        let seen: HashSet<String> = items.iter().map(|item| item.book.id.clone()).collect();
+        let fill = trending_fill(conn, user_id, &seen, limit - items.len() as i64, safe_search)?;
+        items.extend(fill);
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "items": items,
+        "following": following_count(conn, user_id)?,
+        "next_before": next_before,
+        "safe_search": safe_search.level.as_str()
+    }))
+}
+
+/// Trending published books not already in the feed, skipping the reader's own
+fn trending_fill(
+    conn: &Connection,
+    user_id: &Uuid,
+    seen: &HashSet<String>,
+    count: i64,
+    safe_search: &SafeSearch,
+) -> Result<Vec<FeedItem>, ServiceError> {
+    let ids: Vec<String> = trending::top_books(conn, (count + seen.len() as i64) as i32)?
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|id| !seen.contains(id))
+        .collect();
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let query = format!(
+        "SELECT b.id::text, b.title, b.description, b.genre, b.cover_image_url,
+                COALESCE(b.word_count, 0), b.published_at::text,
+                b.author_id::text, u.name
+         FROM content.books b
+         JOIN users.users u ON u.id = b.author_id
+         WHERE b.id::text = ANY(string_to_array($1, ','))
+           AND b.status = 'published' AND b.author_id <> $2
+           AND {}",
+        content_rating::sql_condition("b", 3)
+    );
+    let [level, hidden] = safe_search.sql_params();
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(ids.join(",")),
+        ParameterValue::Str(user_id.to_string()),
+        level,
+        hidden,
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut items: Vec<FeedItem> = rows.rows.iter().filter_map(|row| {
+        Some(FeedItem {
+            item_type: "trending".into(),
+            occurred_at: None,
+            book: book_from_row(row, 0)?,
+            author: FeedAuthor {
+                id: String::decode(&row[7]).ok()?,
+                name: String::decode(&row[8]).ok(),
+            },
+            chapter: None,
+        })
+    }).collect();
+
+    // Keep trending order rather than whatever order Postgres returned
+    items.sort_by_key(|item| ids.iter().position(|id| *id == item.book.id).unwrap_or(usize::MAX));
+    items.truncate(count.max(0) as usize);
+    Ok(items)
+}
+
+fn following_count(conn: &Connection, user_id: &Uuid) -> Result<i64, ServiceError> {
+    let rows = conn.query("SELECT COUNT(*) FROM discovery.follows WHERE follower_id = $1", &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    Ok(rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0))
+}
+
+/// Book columns id, title, description, genre, cover, word count, published_at from `start`
+fn book_from_row(row: &[spin_sdk::pg::DbValue], start: usize) -> Option<FeedBook> {
+    Some(FeedBook {
+        id: String::decode(&row[start]).ok()?,
+        title: String::decode(&row[start + 1]).ok()?,
+        description: String::decode(&row[start + 2]).ok(),
+        genre: String::decode(&row[start + 3]).ok(),
+        cover_url: String::decode(&row[start + 4]).ok(),
+        word_count: i32::decode(&row[start + 5]).unwrap_or(0),
+        published_at: String::decode(&row[start + 6]).ok(),
+    })
+}
+
+/// RFC 3339, or Postgres' own text form as returned in `occurred_at`
+fn is_timestamp(value: &str) -> bool {
+    DateTime::parse_from_rfc3339(value).is_ok()
+        || DateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f%#z").is_ok()
+}
@@ -0,0 +1,196 @@
+//! Genre taxonomy
+//!
+//! Canonical genres live in `discovery.genres` as a tree (Fantasy > Epic
+//! Fantasy) with aliases for common spellings. Free-form genres are
+//! normalized against it when a book is indexed: the document keeps the
+//! canonical name in `genre` plus `genre_slug` and `genre_path` (the slug and
+//! all its ancestors), so filtering on a parent also matches its subgenres
+//! and a terms aggregation on `genre_path` yields hierarchical facets.
+//! Genres that match nothing are indexed as given with an empty path.
+
+use crate::error::ServiceError;
+use spin_sdk::pg::{Connection, Decode};
+use std::collections::HashMap;
+
+/// Indices use dynamic mapping, so exact-match queries go to the keyword subfield
+pub const GENRE_PATH_FIELD: &str = "genre_path.keyword";
+
+/// Guards path walks against a cycle introduced by hand-edited rows
+const MAX_DEPTH: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct Genre {
+    pub slug: String,
+    pub name: String,
+    pub parent: Option<String>,
+    pub aliases: Vec<String>,
+}
+
+pub struct Taxonomy {
+    genres: Vec<Genre>,
+}
+
+/// Genre fields added to an indexed book document
+pub struct GenreFields {
+    pub genre: Option<String>,
+    pub genre_slug: Option<String>,
+    pub genre_path: Vec<String>,
+}
+
+impl Taxonomy {
+    pub fn load(conn: &Connection) -> Result<Self, ServiceError> {
+        let query = "SELECT slug, name, parent_slug, array_to_string(aliases, ',')
+                     FROM discovery.genres ORDER BY sort_order, name";
+        let rows = conn.query(query, &[])
+            .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+        let genres = rows.rows.iter().map(|row| Genre {
+            slug: String::decode(&row[0]).unwrap_or_default(),
+            name: String::decode(&row[1]).unwrap_or_default(),
+            parent: String::decode(&row[2]).ok(),
+            aliases: String::decode(&row[3]).unwrap_or_default()
+                .split(',')
+                .filter(|a| !a.is_empty())
+                .map(|a| a.to_string())
+                .collect(),
+        }).collect();
+
+        Ok(Self { genres })
+    }
+
+    pub fn get(&self, slug: &str) -> Option<&Genre> {
+        self.genres.iter().find(|g| g.slug == slug)
+    }
+
+    /// Match a free-form value by slug, name or alias, ignoring case and punctuation
+    pub fn normalize(&self, raw: &str) -> Option<&Genre> {
+        let key = slugify(raw);
+        if key.is_empty() {
+            return None;
+        }
+        self.get(&key)
+            .or_else(|| self.genres.iter().find(|g| slugify(&g.name) == key))
+            .or_else(|| self.genres.iter().find(|g| g.aliases.iter().any(|a| slugify(a) == key)))
+    }
+
+    /// The genre and its ancestors, root first
+    pub fn path(&self, slug: &str) -> Vec<&Genre> {
+        let mut path = Vec::new();
+        let mut current = self.get(slug);
+        while let Some(genre) = current {
+            if path.len() >= MAX_DEPTH {
+                break;
+            }
+            path.push(genre);
+            current = genre.parent.as_deref().and_then(|p| self.get(p));
+        }
+        path.reverse();
+        path
+    }
+
+    pub fn index_fields(&self, raw: Option<&str>) -> GenreFields {
+        let raw = raw.map(|g| g.trim()).filter(|g| !g.is_empty());
+        match raw.and_then(|g| self.normalize(g)) {
+            Some(genre) => GenreFields {
+                genre: Some(genre.name.clone()),
+                genre_slug: Some(genre.slug.clone()),
+                genre_path: self.path(&genre.slug).iter().map(|g| g.slug.clone()).collect(),
+            },
+            None => GenreFields {
+                genre: raw.map(|g| g.to_string()),
+                genre_slug: None,
+                genre_path: vec![],
+            },
+        }
+    }
+
+    fn children(&self, parent: Option<&str>) -> Vec<&Genre> {
+        self.genres.iter().filter(|g| g.parent.as_deref() == parent).collect()
+    }
+
+    /// The whole tree, with book counts where `counts` has them
+    pub fn tree_json(&self, counts: &HashMap<String, i64>) -> Vec<serde_json::Value> {
+        self.subtree_json(None, counts, 0, false)
+    }
+
+    /// Facet tree from `genre_path` bucket counts; genres without books are omitted
+    pub fn facets_json(&self, counts: &HashMap<String, i64>) -> Vec<serde_json::Value> {
+        self.subtree_json(None, counts, 0, true)
+    }
+
+    fn subtree_json(
+        &self,
+        parent: Option<&str>,
+        counts: &HashMap<String, i64>,
+        depth: usize,
+        only_counted: bool,
+    ) -> Vec<serde_json::Value> {
+        if depth >= MAX_DEPTH {
+            return vec![];
+        }
+        self.children(parent).into_iter()
+            .filter(|g| !only_counted || counts.get(&g.slug).copied().unwrap_or(0) > 0)
+            .map(|g| serde_json::json!({
+                "slug": g.slug,
+                "name": g.name,
+                "book_count": counts.get(&g.slug).copied().unwrap_or(0),
+                "children": self.subtree_json(Some(&g.slug), counts, depth + 1, only_counted)
+            }))
+            .collect()
+    }
+
+    pub fn genre_json(&self, genre: &Genre) -> serde_json::Value {
+        serde_json::json!({
+            "slug": genre.slug,
+            "name": genre.name,
+            "parent": genre.parent,
+            "path": self.path(&genre.slug).iter().map(|g| serde_json::json!({
+                "slug": g.slug,
+                "name": g.name
+            })).collect::<Vec<_>>(),
+            "children": self.children(Some(&genre.slug)).iter().map(|g| serde_json::json!({
+                "slug": g.slug,
+                "name": g.name
+            })).collect::<Vec<_>>()
+        })
+    }
+}
+
+/// Terms aggregation over `genre_path`, named `genres` in the response
+pub fn facet_aggregation() -> serde_json::Value {
+    serde_json::json!({
+        "genres": {
+            "terms": { "field": GENRE_PATH_FIELD, "size": 500 }
+        }
+    })
+}
+
+/// Bucket counts from a response that ran `facet_aggregation`
+pub fn facet_counts(response: &serde_json::Value) -> HashMap<String, i64> {
+    response.get("aggregations")
+        .and_then(|a| a.get("genres"))
+        .and_then(|g| g.get("buckets"))
+        .and_then(|b| b.as_array())
+        .map(|buckets| buckets.iter().filter_map(|bucket| {
+            Some((
+                bucket.get("key")?.as_str()?.to_string(),
+                bucket.get("doc_count")?.as_i64()?,
+            ))
+        }).collect())
+        .unwrap_or_default()
+}
+
+/// Lowercase letters and digits joined by single hyphens; apostrophes are dropped
+pub fn slugify(raw: &str) -> String {
+    let mut slug = String::new();
+    for c in raw.trim().to_lowercase().chars() {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if c == '\'' || c == '\u{2019}' {
+            continue;
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
@@ -0,0 +1,167 @@
+//! Elasticsearch index status
+//!
+//! Compares each search index against its Postgres source so operators can
+//! spot drift (missing documents, stale syncs, outdated mappings) before
+//! users notice missing search results. Sync times are recorded in
+//! `discovery.index_sync_state` as the indexing queue is flushed.
+
+use crate::error::ServiceError;
+use serde::Serialize;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::Utc;
+
+/// Mapping version the service expects in each index's `_meta.mapping_version`
+pub const EXPECTED_MAPPING_VERSION: i64 = 1;
+
+struct IndexSource {
+    index: &'static str,
+    /// Count of rows that should be searchable
+    count_query: &'static str,
+    /// Most recent change to those rows
+    updated_query: &'static str,
+}
+
+const INDICES: [IndexSource; 3] = [
+    IndexSource {
+        index: "authorworks-books",
+        count_query: "SELECT COUNT(*) FROM content.books WHERE NOT COALESCE(index_excluded, false)",
+        updated_query: "SELECT MAX(updated_at)::text FROM content.books WHERE NOT COALESCE(index_excluded, false)",
+    },
+    IndexSource {
+        index: "authorworks-chapters",
+        count_query: "SELECT COUNT(*) FROM content.chapters c
+                      JOIN content.books b ON b.id = c.book_id
+                      WHERE NOT COALESCE(b.index_excluded, false)",
+        updated_query: "SELECT MAX(c.updated_at)::text FROM content.chapters c
+                        JOIN content.books b ON b.id = c.book_id
+                        WHERE NOT COALESCE(b.index_excluded, false)",
+    },
+    IndexSource {
+        index: "authorworks-authors",
+        count_query: "SELECT COUNT(DISTINCT author_id) FROM content.books",
+        updated_query: "SELECT MAX(u.updated_at)::text FROM users.users u
+                        WHERE EXISTS (SELECT 1 FROM content.books b WHERE b.author_id = u.id)",
+    },
+];
+
+#[derive(Debug, Serialize)]
+pub struct IndexStatus {
+    pub index: String,
+    pub exists: bool,
+    pub health: Option<String>,
+    pub doc_count: Option<i64>,
+    pub source_count: i64,
+    /// Source rows minus indexed documents; positive means documents are missing
+    pub lag: Option<i64>,
+    pub mapping_version: Option<i64>,
+    pub mapping_current: bool,
+    pub last_synced_at: Option<String>,
+    pub source_updated_at: Option<String>,
+    pub drift: bool,
+}
+
+/// GET /index/status - Per-index document counts, mapping versions, and sync lag
+pub fn get_index_status(conn: &Connection, es_url: &str) -> Result<Response, ServiceError> {
+    let health = crate::elasticsearch_request(es_url, "GET", "/_cluster/health", &serde_json::json!({})).ok();
+    let index_health = crate::elasticsearch_request(es_url, "GET", "/_cat/indices/authorworks-*?format=json", &serde_json::json!({}))
+        .ok()
+        .and_then(|v| v.as_array().cloned())
+        .unwrap_or_default();
+
+    let mut statuses = Vec::new();
+    for source in INDICES.iter() {
+        let source_count = conn.query(source.count_query, &[])
+            .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?
+            .rows.first()
+            .map(|row| i64::decode(&row[0]).unwrap_or(0))
+            .unwrap_or(0);
+        let source_updated_at = conn.query(source.updated_query, &[])
+            .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?
+            .rows.first()
+            .and_then(|row| String::decode(&row[0]).ok());
+        let last_synced_at = last_sync(conn, source.index)?;
+
+        let cat = index_health.iter()
+            .find(|entry| entry.get("index").and_then(|i| i.as_str()) == Some(source.index));
+        let exists = cat.is_some();
+
+        let doc_count = if exists {
+            crate::elasticsearch_request(es_url, "GET", &format!("/{}/_count", source.index), &serde_json::json!({}))
+                .ok()
+                .and_then(|v| v.get("count").and_then(|c| c.as_i64()))
+        } else {
+            None
+        };
+
+        let mapping_version = if exists {
+            crate::elasticsearch_request(es_url, "GET", &format!("/{}/_mapping", source.index), &serde_json::json!({}))
+                .ok()
+                .and_then(|v| {
+                    v.get(source.index)?
+                        .get("mappings")?
+                        .get("_meta")?
+                        .get("mapping_version")?
+                        .as_i64()
+                })
+        } else {
+            None
+        };
+        let mapping_current = mapping_version == Some(EXPECTED_MAPPING_VERSION);
+
+        let lag = doc_count.map(|count| source_count - count);
+        // Timestamps share the Postgres text format, so they compare lexically
+        let stale = match (&source_updated_at, &last_synced_at) {
+            (Some(updated), Some(synced)) => updated > synced,
+            (Some(_), None) => true,
+            _ => false,
+        };
+
+        statuses.push(IndexStatus {
+            index: source.index.to_string(),
+            exists,
+            health: cat.and_then(|c| c.get("health")).and_then(|h| h.as_str()).map(|s| s.to_string()),
+            doc_count,
+            source_count,
+            lag,
+            mapping_version,
+            mapping_current,
+            last_synced_at,
+            source_updated_at,
+            drift: !exists || lag != Some(0) || !mapping_current || stale,
+        });
+    }
+
+    let drifting = statuses.iter().filter(|s| s.drift).count();
+
+    crate::json_response(200, serde_json::json!({
+        "cluster": {
+            "status": health.as_ref().and_then(|h| h.get("status")).and_then(|s| s.as_str()),
+            "reachable": health.is_some()
+        },
+        "expected_mapping_version": EXPECTED_MAPPING_VERSION,
+        "indices": statuses,
+        "drifting_indices": drifting,
+        "queue": crate::indexing_queue::queue_stats(conn)?,
+        "checked_at": Utc::now().to_rfc3339()
+    }))
+}
+
+/// Record `documents` successful writes to `index`
+pub fn record_sync(conn: &Connection, index: &str, documents: i64) -> Result<(), ServiceError> {
+    let upsert = "INSERT INTO discovery.index_sync_state (index_name, last_synced_at, documents_synced)
+                  VALUES ($1, NOW(), $2)
+                  ON CONFLICT (index_name) DO UPDATE SET
+                  last_synced_at = NOW(),
+                  documents_synced = discovery.index_sync_state.documents_synced + EXCLUDED.documents_synced";
+    conn.execute(upsert, &[ParameterValue::Str(index.to_string()), ParameterValue::Int64(documents)])
+        .map_err(|e| ServiceError::Internal(format!("Sync state update failed: {}", e)))?;
+    Ok(())
+}
+
+fn last_sync(conn: &Connection, index: &str) -> Result<Option<String>, ServiceError> {
+    let query = "SELECT last_synced_at::text FROM discovery.index_sync_state WHERE index_name = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(index.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    Ok(rows.rows.first().and_then(|row| String::decode(&row[0]).ok()))
+}
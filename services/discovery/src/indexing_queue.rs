@@ -0,0 +1,371 @@
+//! Persistent indexing queue
+//!
+//! Index writes are recorded in `discovery.index_queue` and flushed to
+//! Elasticsearch in `_bulk` batches capped by payload size. Items that fail
+//! inside an otherwise successful bulk request are retried individually with
+//! exponential backoff. When the pending backlog grows past
+//! `index_queue_max_depth`, the `/index/*` endpoints answer 429 so producers
+//! slow down instead of piling up work.
+
+use crate::error::ServiceError;
+use serde::Serialize;
+use spin_sdk::outbound_http;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use std::collections::HashMap;
+
+pub const DEFAULT_MAX_DEPTH: i64 = 10_000;
+pub const DEFAULT_BULK_MAX_BYTES: usize = 5 * 1024 * 1024;
+/// Rows claimed per flush
+pub const CLAIM_LIMIT: i32 = 1_000;
+/// Attempts before an item is parked as `failed`
+pub const MAX_ATTEMPTS: i32 = 5;
+const BACKOFF_BASE_SECONDS: i32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueueAction {
+    Index,
+    Delete,
+}
+
+impl QueueAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueueAction::Index => "index",
+            QueueAction::Delete => "delete",
+        }
+    }
+}
+
+struct QueueItem {
+    id: String,
+    index: String,
+    doc_id: String,
+    action: String,
+    payload: Option<String>,
+    attempts: i32,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct FlushSummary {
+    pub claimed: usize,
+    pub batches: usize,
+    pub indexed: usize,
+    pub retrying: usize,
+    pub failed: usize,
+    /// Set when Elasticsearch rejected a whole batch and the flush stopped early
+    pub throttled: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueueStats {
+    pub pending: i64,
+    pub processing: i64,
+    pub failed: i64,
+    pub max_depth: i64,
+    pub oldest_pending_at: Option<String>,
+}
+
+//=============================================================================
+// Producers
+//=============================================================================
+
+/// Reject new work while the pending backlog is over the configured depth
+pub fn check_backpressure(conn: &Connection) -> Result<(), ServiceError> {
+    let max_depth = max_depth();
+    let query = "SELECT COUNT(*) FROM discovery.index_queue WHERE status IN ('pending', 'processing')";
+    let depth = conn.query(query, &[])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?
+        .rows.first()
+        .map(|row| i64::decode(&row[0]).unwrap_or(0))
+        .unwrap_or(0);
+
+    if depth >= max_depth {
+        return Err(ServiceError::TooManyRequests(format!(
+            "Indexing queue is full ({} of {} pending)", depth, max_depth
+        )));
+    }
+    Ok(())
+}
+
+/// Queue a write. A pending write for the same document is replaced, so only
+/// the latest version is sent.
+pub fn enqueue(
+    conn: &Connection,
+    index: &str,
+    doc_id: &str,
+    action: QueueAction,
+    payload: Option<&serde_json::Value>,
+) -> Result<(), ServiceError> {
+    let upsert = "INSERT INTO discovery.index_queue (index_name, doc_id, action, payload)
+                  VALUES ($1, $2, $3, $4)
+                  ON CONFLICT (index_name, doc_id) WHERE status = 'pending' DO UPDATE SET
+                  action = EXCLUDED.action,
+                  payload = EXCLUDED.payload,
+                  attempts = 0,
+                  last_error = NULL,
+                  next_attempt_at = NOW(),
+                  updated_at = NOW()";
+    conn.execute(upsert, &[
+        ParameterValue::Str(index.to_string()),
+        ParameterValue::Str(doc_id.to_string()),
+        ParameterValue::Str(action.as_str().to_string()),
+        payload.map(|p| ParameterValue::Str(p.to_string())).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Enqueue failed: {}", e)))?;
+    Ok(())
+}
+
+/// Drop pending chapter writes for a book that is being removed from the index
+pub fn discard_pending_chapters(conn: &Connection, book_id: &str) -> Result<u64, ServiceError> {
+    let delete = "DELETE FROM discovery.index_queue
+                  WHERE index_name = 'authorworks-chapters' AND status = 'pending'
+                    AND payload->>'book_id' = $1";
+    conn.execute(delete, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))
+}
+
+//=============================================================================
+// Consumer
+//=============================================================================
+
+/// POST /index/flush - Send due queue items to Elasticsearch in `_bulk` batches
+pub fn process_queue(conn: &Connection, es_url: &str) -> Result<FlushSummary, ServiceError> {
+    let items = claim(conn)?;
+    let mut summary = FlushSummary { claimed: items.len(), ..Default::default() };
+    let max_bytes = bulk_max_bytes();
+
+    let mut batch: Vec<(&QueueItem, String)> = Vec::new();
+    let mut batch_bytes = 0;
+    let mut pending = items.iter();
+
+    loop {
+        let next = pending.next().map(|item| (item, bulk_lines(item)));
+        let full = match &next {
+            Some((_, lines)) => !batch.is_empty() && batch_bytes + lines.len() > max_bytes,
+            None => !batch.is_empty(),
+        };
+
+        if full {
+            summary.batches += 1;
+            if !send_batch(conn, es_url, &batch, &mut summary)? {
+                // Elasticsearch is pushing back; hand everything else back untouched
+                summary.throttled = true;
+                let rest: Vec<&QueueItem> = next.iter().map(|(item, _)| *item).chain(pending).collect();
+                for item in rest {
+                    release(conn, item)?;
+                }
+                break;
+            }
+            batch.clear();
+            batch_bytes = 0;
+        }
+
+        match next {
+            Some((item, lines)) => {
+                batch_bytes += lines.len();
+                batch.push((item, lines));
+            }
+            None => break,
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Queue depth for the status endpoint
+pub fn queue_stats(conn: &Connection) -> Result<QueueStats, ServiceError> {
+    let query = "SELECT
+                    COUNT(*) FILTER (WHERE status = 'pending'),
+                    COUNT(*) FILTER (WHERE status = 'processing'),
+                    COUNT(*) FILTER (WHERE status = 'failed'),
+                    MIN(created_at) FILTER (WHERE status = 'pending')::text
+                 FROM discovery.index_queue";
+    let rows = conn.query(query, &[])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::Internal("Queue stats returned no rows".into()))?;
+
+    Ok(QueueStats {
+        pending: i64::decode(&row[0]).unwrap_or(0),
+        processing: i64::decode(&row[1]).unwrap_or(0),
+        failed: i64::decode(&row[2]).unwrap_or(0),
+        max_depth: max_depth(),
+        oldest_pending_at: String::decode(&row[3]).ok(),
+    })
+}
+
+/// Claim due rows. Rows stuck in `processing` from an interrupted flush are
+/// picked up again after five minutes.
+fn claim(conn: &Connection) -> Result<Vec<QueueItem>, ServiceError> {
+    let update = "UPDATE discovery.index_queue SET status = 'processing', updated_at = NOW()
+                  WHERE id IN (
+                      SELECT id FROM discovery.index_queue
+                      WHERE (status = 'pending' AND next_attempt_at <= NOW())
+                         OR (status = 'processing' AND updated_at < NOW() - INTERVAL '5 minutes')
+                      ORDER BY created_at
+                      LIMIT $1
+                      FOR UPDATE SKIP LOCKED
+                  )
+                  RETURNING id::text, index_name, doc_id, action, payload::text, attempts";
+    let rows = conn.query(update, &[ParameterValue::Int32(CLAIM_LIMIT)])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.iter().map(|row| QueueItem {
+        id: String::decode(&row[0]).unwrap_or_default(),
+        index: String::decode(&row[1]).unwrap_or_default(),
+        doc_id: String::decode(&row[2]).unwrap_or_default(),
+        action: String::decode(&row[3]).unwrap_or_default(),
+        payload: String::decode(&row[4]).ok(),
+        attempts: i32::decode(&row[5]).unwrap_or(0),
+    }).collect())
+}
+
+/// NDJSON action (and source) lines for one item
+fn bulk_lines(item: &QueueItem) -> String {
+    let meta = serde_json::json!({ "_index": item.index, "_id": item.doc_id });
+    if item.action == QueueAction::Delete.as_str() {
+        format!("{}\n", serde_json::json!({ "delete": meta }))
+    } else {
+        format!(
+            "{}\n{}\n",
+            serde_json::json!({ "index": meta }),
+            item.payload.as_deref().unwrap_or("{}")
+        )
+    }
+}
+
+/// Send one batch and settle each item from the per-item results. Returns
+/// false if the whole request was rejected and nothing was settled.
+fn send_batch(
+    conn: &Connection,
+    es_url: &str,
+    batch: &[(&QueueItem, String)],
+    summary: &mut FlushSummary,
+) -> Result<bool, ServiceError> {
+    let body: String = batch.iter().map(|(_, lines)| lines.as_str()).collect();
+    let request = outbound_http::Request::builder()
+        .method("POST")
+        .uri(&format!("{}/_bulk", es_url))
+        .header("Content-Type", "application/x-ndjson")
+        .body(body)
+        .build();
+
+    let response = match outbound_http::send(request) {
+        Ok(response) if response.status() < 400 => response,
+        Ok(response) if response.status() != 429 && response.status() < 500 => {
+            // The request itself was malformed; count it against every item
+            let error = format!("Bulk request rejected: {}", response.status());
+            for (item, _) in batch {
+                settle_failure(conn, item, &error, summary)?;
+            }
+            return Ok(true);
+        }
+        // Throttled, cluster error, or unreachable: retry the batch later
+        _ => {
+            for (item, _) in batch {
+                release(conn, item)?;
+            }
+            return Ok(false);
+        }
+    };
+
+    let result: serde_json::Value = serde_json::from_slice(response.body())
+        .map_err(|e| ServiceError::Internal(format!("Failed to parse Elasticsearch response: {}", e)))?;
+    let results = result.get("items").and_then(|i| i.as_array()).cloned().unwrap_or_default();
+
+    let mut synced: HashMap<&str, i64> = HashMap::new();
+    for (position, (item, _)) in batch.iter().enumerate() {
+        let outcome = results.get(position)
+            .and_then(|r| r.as_object())
+            .and_then(|r| r.values().next());
+        let status = outcome.and_then(|o| o.get("status")).and_then(|s| s.as_u64()).unwrap_or(0);
+        // Deleting a document that is already gone is still a success
+        let ok = (200..300).contains(&status) || (status == 404 && item.action == QueueAction::Delete.as_str());
+
+        if ok {
+            complete(conn, item)?;
+            summary.indexed += 1;
+            *synced.entry(item.index.as_str()).or_insert(0) += 1;
+        } else {
+            let error = outcome
+                .and_then(|o| o.get("error"))
+                .map(|e| e.to_string())
+                .unwrap_or_else(|| format!("Bulk item status {}", status));
+            settle_failure(conn, item, &error, summary)?;
+        }
+    }
+
+    // Sync bookkeeping must never fail the flush itself
+    for (index, count) in synced {
+        crate::index_status::record_sync(conn, index, count).ok();
+    }
+
+    Ok(true)
+}
+
+fn complete(conn: &Connection, item: &QueueItem) -> Result<(), ServiceError> {
+    conn.execute("DELETE FROM discovery.index_queue WHERE id = $1", &[ParameterValue::Str(item.id.clone())])
+        .map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    Ok(())
+}
+
+/// Return an item to the queue without counting an attempt
+fn release(conn: &Connection, item: &QueueItem) -> Result<(), ServiceError> {
+    requeue(conn, item, item.attempts, None, BACKOFF_BASE_SECONDS)
+}
+
+fn settle_failure(conn: &Connection, item: &QueueItem, error: &str, summary: &mut FlushSummary) -> Result<(), ServiceError> {
+    let attempts = item.attempts + 1;
+    if attempts >= MAX_ATTEMPTS {
+        summary.failed += 1;
+    } else {
+        summary.retrying += 1;
+    }
+    let delay = BACKOFF_BASE_SECONDS.saturating_mul(1 << attempts.min(16));
+    requeue(conn, item, attempts, Some(error), delay)
+}
+
+/// Put a claimed item back as pending (or failed once out of attempts). If a
+/// newer write for the same document was queued meanwhile, it supersedes this
+/// one and the claimed row is dropped.
+fn requeue(conn: &Connection, item: &QueueItem, attempts: i32, error: Option<&str>, delay_seconds: i32) -> Result<(), ServiceError> {
+    let update = "UPDATE discovery.index_queue SET
+                  status = CASE WHEN $2 >= $3 THEN 'failed' ELSE 'pending' END,
+                  attempts = $2,
+                  last_error = COALESCE($4, last_error),
+                  next_attempt_at = NOW() + make_interval(secs => $5),
+                  updated_at = NOW()
+                  WHERE id = $1
+                    AND NOT EXISTS (
+                        SELECT 1 FROM discovery.index_queue newer
+                        WHERE newer.index_name = $6 AND newer.doc_id = $7 AND newer.status = 'pending'
+                    )";
+    let count = conn.execute(update, &[
+        ParameterValue::Str(item.id.clone()),
+        ParameterValue::Int32(attempts),
+        ParameterValue::Int32(MAX_ATTEMPTS),
+        error.map(|e| ParameterValue::Str(e.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Int32(delay_seconds),
+        ParameterValue::Str(item.index.clone()),
+        ParameterValue::Str(item.doc_id.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    if count == 0 {
+        complete(conn, item)?;
+    }
+    Ok(())
+}
+
+fn max_depth() -> i64 {
+    variables::get("index_queue_max_depth")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_DEPTH)
+}
+
+fn bulk_max_bytes() -> usize {
+    variables::get("index_bulk_max_bytes")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_BULK_MAX_BYTES)
+}
@@ -0,0 +1,284 @@
+//! Keyword alerts
+//!
+//! Authors register keywords (optionally within a genre) to hear about newly
+//! published books that match, for competitive research. Each alert is stored
+//! in `discovery.keyword_alerts` and as a percolator query in the
+//! `authorworks-keyword-alerts` index. When a published book is indexed it is
+//! percolated against every registered query, and each matching alert gets a
+//! `keyword_alert` notification. `discovery.keyword_alert_matches` records
+//! what has been sent, so a book re-indexed after edits notifies only once,
+//! and authors are never alerted about their own books.
+//!
+//! The book indices use dynamic mapping, but percolator fields cannot be
+//! mapped dynamically, so the alerts index is created with an explicit
+//! mapping of the book fields alerts query.
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+pub const ALERTS_INDEX: &str = "authorworks-keyword-alerts";
+
+const MAX_ALERTS_PER_USER: i64 = 25;
+const MAX_KEYWORDS_CHARS: usize = 200;
+
+/// Bounds the notifications one indexed book can send
+const MAX_MATCHES_PER_BOOK: usize = 1000;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAlertRequest {
+    /// Every word must appear in the title, description or author name
+    pub keywords: String,
+    /// Genre slug; subgenres match too
+    pub genre: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeywordAlert {
+    pub id: Uuid,
+    pub keywords: String,
+    pub genre: Option<String>,
+    pub match_count: i32,
+    pub last_matched_at: Option<String>,
+    pub created_at: String,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /alerts/keywords - Register a keyword alert
+pub fn create(conn: &Connection, es_url: &str, user_id: &Uuid, body: CreateAlertRequest) -> Result<Response, ServiceError> {
+    let keywords = body.keywords.split_whitespace().collect::<Vec<_>>().join(" ");
+    if keywords.is_empty() {
+        return Err(ServiceError::BadRequest("keywords are required".into()));
+    }
+    if keywords.chars().count() > MAX_KEYWORDS_CHARS {
+        return Err(ServiceError::BadRequest(format!("keywords must be at most {} characters", MAX_KEYWORDS_CHARS)));
+    }
+    let genre = match body.genre.as_deref().map(str::trim).filter(|g| !g.is_empty()) {
+        Some(raw) => Some(
+            crate::genres::Taxonomy::load(conn)?
+                .index_fields(Some(raw))
+                .genre_slug
+                .ok_or_else(|| ServiceError::BadRequest(format!("Unknown genre '{}'", raw)))?,
+        ),
+        None => None,
+    };
+
+    let count = conn.query(
+        "SELECT COUNT(*) FROM discovery.keyword_alerts WHERE user_id = $1",
+        &[ParameterValue::Str(user_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if count.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0) >= MAX_ALERTS_PER_USER {
+        return Err(ServiceError::BadRequest(format!("You can have at most {} keyword alerts", MAX_ALERTS_PER_USER)));
+    }
+
+    let alert_id = Uuid::new_v4();
+    ensure_index(es_url)?;
+    crate::elasticsearch_request(
+        es_url,
+        "PUT",
+        &format!("/{}/_doc/{}?refresh=true", ALERTS_INDEX, alert_id),
+        &serde_json::json!({
+            "query": percolator_query(&keywords, genre.as_deref()),
+            "alert_id": alert_id,
+            "user_id": user_id
+        }),
+    )?;
+
+    let insert = "INSERT INTO discovery.keyword_alerts (id, user_id, keywords, genre, created_at)
+                  VALUES ($1, $2, $3, $4, NOW())
+                  RETURNING created_at::text";
+    let rows = conn.query(insert, &[
+        ParameterValue::Str(alert_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(keywords.clone()),
+        genre.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    crate::json_response(201, KeywordAlert {
+        id: alert_id,
+        keywords,
+        genre,
+        match_count: 0,
+        last_matched_at: None,
+        created_at: rows.rows.first().and_then(|row| String::decode(&row[0]).ok()).unwrap_or_default(),
+    })
+}
+
+/// GET /alerts/keywords - The caller's keyword alerts, newest first
+pub fn list(conn: &Connection, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT id, keywords, genre, match_count, last_matched_at::text, created_at::text
+                 FROM discovery.keyword_alerts WHERE user_id = $1
+                 ORDER BY created_at DESC";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let alerts: Vec<KeywordAlert> = rows.rows.iter().map(|row| KeywordAlert {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        keywords: String::decode(&row[1]).unwrap_or_default(),
+        genre: String::decode(&row[2]).ok(),
+        match_count: i32::decode(&row[3]).unwrap_or(0),
+        last_matched_at: String::decode(&row[4]).ok(),
+        created_at: String::decode(&row[5]).unwrap_or_default(),
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({ "alerts": alerts }))
+}
+
+/// DELETE /alerts/keywords/:id - Remove one of the caller's alerts
+pub fn delete(conn: &Connection, es_url: &str, user_id: &Uuid, alert_id: &Uuid) -> Result<Response, ServiceError> {
+    let deleted = conn.execute(
+        "DELETE FROM discovery.keyword_alerts WHERE id = $1 AND user_id = $2",
+        &[
+            ParameterValue::Str(alert_id.to_string()),
+            ParameterValue::Str(user_id.to_string()),
+        ],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    if deleted == 0 {
+        return Err(ServiceError::NotFound("Keyword alert not found".into()));
+    }
+
+    // A query left behind only costs a lookup; matches check the table first
+    crate::elasticsearch_request(
+        es_url,
+        "DELETE",
+        &format!("/{}/_doc/{}", ALERTS_INDEX, alert_id),
+        &serde_json::json!({}),
+    ).ok();
+
+    crate::json_response(200, serde_json::json!({
+        "id": alert_id,
+        "deleted": true
+    }))
+}
+
+//=============================================================================
+// Indexing
+//=============================================================================
+
+/// Percolate a published book and notify every alert it newly matches.
+/// Returns the number of notifications sent.
+pub fn check_book(conn: &Connection, es_url: &str, book: &serde_json::Value) -> Result<u64, ServiceError> {
+    let book_id = book["id"].as_str().unwrap_or_default();
+    let author_id = book["author_id"].as_str().unwrap_or_default();
+
+    let response = crate::elasticsearch_request(
+        es_url,
+        "POST",
+        &format!("/{}/_search", ALERTS_INDEX),
+        &serde_json::json!({
+            "size": MAX_MATCHES_PER_BOOK,
+            "_source": ["alert_id"],
+            "query": {
+                "percolate": {
+                    "field": "query",
+                    "document": {
+                        "title": book["title"],
+                        "description": book["description"],
+                        "author_name": book["author_name"],
+                        "genre_path": book["genre_path"]
+                    }
+                }
+            }
+        }),
+    )?;
+    let alert_ids: Vec<String> = response["hits"]["hits"].as_array()
+        .map(|hits| hits.iter().filter_map(|h| h["_source"]["alert_id"].as_str().map(String::from)).collect())
+        .unwrap_or_default();
+    if alert_ids.is_empty() {
+        return Ok(0);
+    }
+
+    // Claim each (alert, book) pair once; only newly claimed pairs notify
+    let claim = "INSERT INTO discovery.keyword_alert_matches (alert_id, book_id, matched_at)
+                 SELECT a.id, $2::uuid, NOW() FROM discovery.keyword_alerts a
+                 WHERE a.id::text = ANY(string_to_array($1, ','))
+                   AND a.user_id::text <> $3
+                 ON CONFLICT (alert_id, book_id) DO NOTHING";
+    conn.execute(claim, &[
+        ParameterValue::Str(alert_ids.join(",")),
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(author_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    let notify = "WITH fresh AS (
+                      UPDATE discovery.keyword_alert_matches m SET notified_at = NOW()
+                      WHERE m.book_id = $1::uuid AND m.notified_at IS NULL
+                      RETURNING m.alert_id
+                  ),
+                  counted AS (
+                      UPDATE discovery.keyword_alerts a
+                      SET match_count = a.match_count + 1, last_matched_at = NOW()
+                      FROM fresh WHERE a.id = fresh.alert_id
+                      RETURNING a.id, a.user_id, a.keywords
+                  )
+                  INSERT INTO messaging.notifications (user_id, type, title, body, data, created_at)
+                  SELECT c.user_id, 'keyword_alert',
+                         'New book matching \"' || c.keywords || '\"',
+                         $2::text,
+                         jsonb_build_object('alert_id', c.id, 'keywords', c.keywords,
+                                            'book_id', $1::text, 'author_id', $3::text),
+                         NOW()
+                  FROM counted c";
+    let title = book["title"].as_str().unwrap_or("A new book");
+    let author_name = book["author_name"].as_str().filter(|n| !n.is_empty());
+    let body = match author_name {
+        Some(name) => format!("{} by {} was just published", title, name),
+        None => format!("{} was just published", title),
+    };
+    conn.execute(notify, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(body),
+        ParameterValue::Str(author_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))
+}
+
+fn percolator_query(keywords: &str, genre: Option<&str>) -> serde_json::Value {
+    let mut filter = Vec::new();
+    if let Some(slug) = genre {
+        filter.push(serde_json::json!({"term": {"genre_path": slug}}));
+    }
+    serde_json::json!({
+        "bool": {
+            "must": [{
+                "multi_match": {
+                    "query": keywords,
+                    "fields": ["title^2", "description", "author_name"],
+                    "operator": "and"
+                }
+            }],
+            "filter": filter
+        }
+    })
+}
+
+/// Create the alerts index with its percolator mapping unless it exists
+fn ensure_index(es_url: &str) -> Result<(), ServiceError> {
+    let mapping = serde_json::json!({
+        "mappings": {
+            "_meta": { "mapping_version": crate::index_status::EXPECTED_MAPPING_VERSION },
+            "properties": {
+                "query": { "type": "percolator" },
+                "alert_id": { "type": "keyword" },
+                "user_id": { "type": "keyword" },
+                "title": { "type": "text" },
+                "description": { "type": "text" },
+                "author_name": { "type": "text" },
+                "genre_path": { "type": "keyword" }
+            }
+        }
+    });
+    match crate::elasticsearch_request(es_url, "PUT", &format!("/{}", ALERTS_INDEX), &mapping) {
+        Ok(_) => Ok(()),
+        Err(ServiceError::Internal(message)) if message.contains("resource_already_exists_exception") => Ok(()),
+        Err(e) => Err(e),
+    }
+}
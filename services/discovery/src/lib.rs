@@ -4,16 +4,60 @@
 //!
 //! ## Endpoints
 //! - GET /health - Health check
-//! - GET /search - Full-text search across content
-//! - GET /search/books - Search books
-//! - GET /search/chapters - Search chapters
-//! - GET /search/authors - Search authors
-//! - POST /index/book - Index a book (internal)
-//! - POST /index/chapter - Index a chapter (internal)
+//! - GET /search?correct=auto|suggest|off&safe_search= - Full-text search across content, with "did you mean" suggestions
+//! - GET /search/books?correct=auto|suggest|off&safe_search= - Search books, with "did you mean" suggestions and genre facets
+//! - GET /search/chapters?q=&entity=&book_id=&safe_search= - Search chapters, optionally only those featuring an entity
+//! - GET /search/authors?safe_search= - Search authors, leaving out those whose books are all filtered
+//! - GET /search/mine?q=&types= - Search the caller's own books, chapters, notes, comments and messages
+//! - GET /preferences/safe-search - Get the caller's default safe search level and hidden content warnings
+//! - PUT /preferences/safe-search - Set the caller's default safe search level and hidden content warnings
+//! - POST /index/book - Queue a book for indexing (internal)
+//! - POST /index/chapter - Queue a chapter for indexing (internal)
 //! - DELETE /index/book/:id - Remove book from index
-//! - GET /recommendations - Get personalized recommendations
-//! - GET /trending - Get trending content
-//! - GET /similar/:book_id - Get similar books
+//! - POST /index/flush - Send queued index writes to Elasticsearch in bulk (internal, X-Internal-Token)
+//! - GET /index/status - Index doc counts, mapping versions, queue depth, and drift vs Postgres (internal, X-Internal-Token)
+//!
+//! The `/index/*` write endpoints return 429 when the indexing queue is over
+//! `index_queue_max_depth`.
+//! - GET /recommendations?safe_search= - Get personalized recommendations (genre + collaborative filtering)
+//! - POST /recommendations/neighbors/rebuild - Recompute "readers also read" neighbors (internal, X-Internal-Token)
+//! - GET /trending?safe_search= - Get trending content
+//! - POST /trending/rebuild - Recompute trending activity for all books (internal, X-Internal-Token)
+//! - POST /events - Refresh trending and neighbors for a book after an event (internal, X-Internal-Token)
+//! - GET /similar/:book_id?visual_weight=&safe_search= - Get similar books, optionally weighting cover art similarity
+//! - GET /books/:id/card?safe_search= - Indexed book with reader stats, trending rank and similar-book teasers (ETag)
+//! - GET /books/:id/entities?min_chapters=&limit= - Characters and places in a book with the chapters featuring each
+//! - POST /authors/:id/follow - Follow an author
+//! - DELETE /authors/:id/follow - Unfollow an author
+//! - GET /authors/:id/books?exclude=&limit=&safe_search= - More from this author: their published books
+//! - GET /feed?before=&limit=&safe_search= - New books and chapters from followed authors, topped up with trending
+//! - POST /alerts/keywords - Get notified when a newly published book matches keywords
+//! - GET /alerts/keywords - List the caller's keyword alerts
+//! - DELETE /alerts/keywords/:id - Remove a keyword alert
+//! - GET /genres - Canonical genre taxonomy with book counts
+//! - GET /genres/:slug/books?safe_search= - Browse published books in a genre and its subgenres
+//! - GET /segments - List the author's saved reader segments
+//! - POST /segments - Save a reader segment definition
+//! - POST /segments/estimate - Estimate the size of a segment definition
+//! - GET /segments/:id - Get a segment with its current size
+//! - PUT /segments/:id - Update a segment
+//! - DELETE /segments/:id - Delete a segment
+//! - POST /segments/:id/export - Export readers who consented to author marketing
+//! - POST /segments/:id/notify - Notify readers who consented to author marketing
+//! - POST /analytics/search-event - Record a search shown or a result clicked, with position and dwell
+//! - GET /analytics/search/top-queries?days=&limit= - Most searched queries with click-through (admin)
+//! - GET /analytics/search/zero-results?days=&limit= - Most frequent queries that found nothing (admin)
+//! - GET /admin/duplicates?status=&type=&limit=&offset= - Near-duplicate books and chapters flagged at index time (admin)
+//! - POST /admin/duplicates/:id/review - Confirm, dismiss or reopen a duplicate flag (admin)
+//! - GET /admin/experiments - List ranking experiments (admin)
+//! - POST /admin/experiments - Start a ranking experiment on book search or recommendations (admin)
+//! - POST /admin/experiments/:id/stop - Stop a ranking experiment (admin)
+//! - GET /admin/experiments/:id/results - Search analytics per experiment variant (admin)
+//! - POST /admin/index/snapshot - Start a snapshot of the search indices to the S3 repository (admin)
+//! - GET /admin/index/snapshots - List snapshots with their state (admin)
+//! - GET /admin/index/snapshots/:name - Snapshot progress and failures (admin)
+//! - POST /admin/index/restore - Restore search indices from a completed snapshot (admin)
+//! - GET /admin/index/restore?indices= - Restore progress per index (admin)
 
 use spin_sdk::http::{IntoResponse, Request, Response, Method};
 use spin_sdk::http_component;
@@ -26,6 +70,26 @@ use uuid::Uuid;
 
 mod models;
 mod error;
+mod index_status;
+mod indexing_queue;
+mod collaborative;
+mod trending;
+mod events;
+mod segments;
+mod workspace_search;
+mod spelling;
+mod genres;
+mod search_analytics;
+mod follows;
+mod duplicates;
+mod cover_embeddings;
+mod content_rating;
+mod experiments;
+mod entities;
+mod keyword_alerts;
+mod snapshots;
+mod book_cards;
+mod scopes;
 
 use error::ServiceError;
 use models::*;
@@ -35,6 +99,10 @@ fn handle_request(req: Request) -> anyhow::Result<impl IntoResponse> {
     let path = req.path();
     let method = req.method();
 
+    if let Err(e) = authorworks_access::authorize(&req, scopes::route_scope) {
+        return Ok(ServiceError::from(e).into_response());
+    }
+
     let result = match (method, path) {
         // Health
         (Method::Get, "/health") => health_handler(),
@@ -45,16 +113,95 @@ fn handle_request(req: Request) -> anyhow::Result<impl IntoResponse> {
         (Method::Get, "/search/books") => search_books(&req),
         (Method::Get, "/search/chapters") => search_chapters(&req),
         (Method::Get, "/search/authors") => search_authors(&req),
+        (Method::Get, "/search/mine") => search_mine(&req),
+        (Method::Get, "/preferences/safe-search") => get_safe_search_preferences(&req),
+        (Method::Put, "/preferences/safe-search") => update_safe_search_preferences(&req),
 
         // Indexing (internal)
         (Method::Post, "/index/book") => index_book(&req),
         (Method::Post, "/index/chapter") => index_chapter(&req),
         (Method::Delete, path) if path.starts_with("/index/book/") => delete_book_index(&req, path),
+        (Method::Post, "/index/flush") => flush_index_queue(&req),
+        (Method::Get, "/index/status") => get_index_status(&req),
 
         // Discovery
         (Method::Get, "/recommendations") => get_recommendations(&req),
+        (Method::Post, "/recommendations/neighbors/rebuild") => rebuild_book_neighbors(&req),
         (Method::Get, "/trending") => get_trending(&req),
+        (Method::Post, "/trending/rebuild") => rebuild_trending(&req),
+        (Method::Post, "/events") => handle_discovery_event(&req),
         (Method::Get, path) if path.starts_with("/similar/") => get_similar(&req, path),
+        (Method::Get, path) if path.starts_with("/books/") && path.ends_with("/entities") => {
+            get_book_entities(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/books/") && path.split('?').next().unwrap_or(path).ends_with("/card") => {
+            get_book_card(&req, path)
+        }
+
+        // Following
+        (Method::Post, path) if path.starts_with("/authors/") && path.ends_with("/follow") => {
+            follow_author(&req, path)
+        }
+        (Method::Delete, path) if path.starts_with("/authors/") && path.ends_with("/follow") => {
+            unfollow_author(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/authors/") && path.ends_with("/books") => {
+            get_author_books(&req, path)
+        }
+        (Method::Get, "/feed") => get_feed(&req),
+
+        // Keyword alerts
+        (Method::Post, "/alerts/keywords") => create_keyword_alert(&req),
+        (Method::Get, "/alerts/keywords") => list_keyword_alerts(&req),
+        (Method::Delete, path) if path.starts_with("/alerts/keywords/") => delete_keyword_alert(&req, path),
+
+        // Genres
+        (Method::Get, "/genres") => list_genres(),
+        (Method::Get, path) if path.starts_with("/genres/") && path.ends_with("/books") => {
+            get_genre_books(&req, path)
+        }
+
+        // Reader segments
+        (Method::Get, "/segments") => list_segments(&req),
+        (Method::Post, "/segments") => create_segment(&req),
+        (Method::Post, "/segments/estimate") => estimate_segment(&req),
+        (Method::Post, path) if path.starts_with("/segments/") && path.ends_with("/export") => {
+            export_segment(&req, path)
+        }
+        (Method::Post, path) if path.starts_with("/segments/") && path.ends_with("/notify") => {
+            notify_segment(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/segments/") => get_segment(&req, path),
+        (Method::Put, path) if path.starts_with("/segments/") => update_segment(&req, path),
+        (Method::Delete, path) if path.starts_with("/segments/") => delete_segment(&req, path),
+
+        // Search analytics
+        (Method::Post, "/analytics/search-event") => record_search_event(&req),
+        (Method::Get, "/analytics/search/top-queries") => get_top_search_queries(&req),
+        (Method::Get, "/analytics/search/zero-results") => get_zero_result_queries(&req),
+
+        // Duplicate detection
+        (Method::Get, "/admin/duplicates") => list_duplicates(&req),
+        (Method::Post, path) if path.starts_with("/admin/duplicates/") && path.ends_with("/review") => {
+            review_duplicate(&req, path)
+        }
+
+        // Ranking experiments
+        (Method::Get, "/admin/experiments") => list_experiments(&req),
+        (Method::Post, "/admin/experiments") => create_experiment(&req),
+        (Method::Post, path) if path.starts_with("/admin/experiments/") && path.ends_with("/stop") => {
+            stop_experiment(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/admin/experiments/") && path.ends_with("/results") => {
+            get_experiment_results(&req, path)
+        }
+
+        // Index snapshots
+        (Method::Post, "/admin/index/snapshot") => create_index_snapshot(&req),
+        (Method::Get, "/admin/index/snapshots") => list_index_snapshots(&req),
+        (Method::Get, path) if path.starts_with("/admin/index/snapshots/") => get_index_snapshot(&req, path),
+        (Method::Post, "/admin/index/restore") => restore_index_snapshot(&req),
+        (Method::Get, "/admin/index/restore") => get_index_restore_status(&req),
 
         // CORS
         (Method::Options, _) => cors_preflight(),
@@ -139,7 +286,7 @@ fn service_info() -> Result<Response, ServiceError> {
     json_response(200, serde_json::json!({
         "service": "AuthorWorks Discovery Service",
         "version": env!("CARGO_PKG_VERSION"),
-        "features": ["full-text-search", "recommendations", "similar-content"]
+        "features": ["full-text-search", "recommendations", "similar-content", "reader-segments", "workspace-search", "spell-correction", "genre-taxonomy", "search-analytics", "author-follows", "duplicate-detection", "cover-similarity", "chapter-entities", "keyword-alerts", "index-snapshots", "book-cards"]
     }))
 }
 
@@ -147,8 +294,8 @@ fn cors_preflight() -> Result<Response, ServiceError> {
     Ok(Response::builder()
         .status(204)
         .header("Access-Control-Allow-Origin", "*")
-        .header("Access-Control-Allow-Methods", "GET, POST, DELETE, OPTIONS")
-        .header("Access-Control-Allow-Headers", "Content-Type, Authorization, X-User-Id")
+        .header("Access-Control-Allow-Methods", "GET, POST, PUT, DELETE, OPTIONS")
+        .header("Access-Control-Allow-Headers", "Content-Type, Authorization, X-User-Id, If-None-Match")
         .header("Access-Control-Max-Age", "86400")
         .body(())
         .build())
@@ -163,31 +310,51 @@ fn search_all(req: &Request) -> Result<Response, ServiceError> {
         .ok_or_else(|| ServiceError::BadRequest("Query parameter 'q' is required".into()))?;
     let from = get_query_param(req, "from").and_then(|s| s.parse().ok()).unwrap_or(0);
     let size = get_query_param(req, "size").and_then(|s| s.parse().ok()).unwrap_or(20);
+    let correct = spelling::CorrectionMode::parse(get_query_param(req, "correct").as_deref())?;
 
     let es_url = get_elasticsearch_url()?;
-    
-    // Multi-index search
-    let search_body = serde_json::json!({
-        "query": {
-            "multi_match": {
-                "query": query,
-                "fields": ["title^3", "description^2", "content", "author_name", "genre"],
-                "type": "best_fields",
-                "fuzziness": "AUTO"
-            }
-        },
-        "highlight": {
-            "fields": {
-                "title": {},
-                "description": {},
-                "content": { "fragment_size": 150 }
-            }
-        },
-        "from": from,
-        "size": size
-    });
+    let conn = get_db_connection()?;
+    let safe_search = safe_search_for(req, &conn)?;
+
+    // Authors carry no rating and are never filtered
+    let filter: Vec<serde_json::Value> = safe_search
+        .filter_indices(&["authorworks-books", "authorworks-chapters"])
+        .into_iter()
+        .collect();
+
+    let search = spelling::search_with_correction(correct, &query, "title", |query, suggest| {
+        // Multi-index search
+        let mut search_body = serde_json::json!({
+            "query": {
+                "bool": {
+                    "must": [{
+                        "multi_match": {
+                            "query": query,
+                            "fields": ["title^3", "description^2", "content", "author_name", "genre"],
+                            "type": "best_fields",
+                            "fuzziness": "AUTO"
+                        }
+                    }],
+                    "filter": filter
+                }
+            },
+            "highlight": {
+                "fields": {
+                    "title": {},
+                    "description": {},
+                    "content": { "fragment_size": 150 }
+                }
+            },
+            "from": from,
+            "size": size
+        });
+        if let Some(suggest) = suggest {
+            search_body["suggest"] = suggest;
+        }
 
-    let response = elasticsearch_request(&es_url, "GET", "/authorworks-*/_search", &search_body)?;
+        elasticsearch_request(&es_url, "GET", "/authorworks-*/_search", &search_body)
+    })?;
+    let response = search.response;
     
     let hits = response.get("hits").and_then(|h| h.get("hits")).and_then(|h| h.as_array());
     let total = response.get("hits").and_then(|h| h.get("total")).and_then(|t| t.get("value")).and_then(|v| v.as_i64()).unwrap_or(0);
@@ -213,7 +380,10 @@ fn search_all(req: &Request) -> Result<Response, ServiceError> {
         "results": results,
         "total": total,
         "from": from,
-        "size": size
+        "size": size,
+        "safe_search": safe_search.level.as_str(),
+        "did_you_mean": search.did_you_mean,
+        "corrected_query": search.corrected_query
     }))
 }
 
@@ -224,101 +394,136 @@ fn search_books(req: &Request) -> Result<Response, ServiceError> {
     let status = get_query_param(req, "status");
     let from = get_query_param(req, "from").and_then(|s| s.parse().ok()).unwrap_or(0);
     let size = get_query_param(req, "size").and_then(|s| s.parse().ok()).unwrap_or(20);
+    let correct = spelling::CorrectionMode::parse(get_query_param(req, "correct").as_deref())?;
 
     let es_url = get_elasticsearch_url()?;
+    let conn = get_db_connection()?;
+    let taxonomy = genres::Taxonomy::load(&conn)?;
 
-    // Build query with filters
-    let mut must = vec![serde_json::json!({
-        "multi_match": {
-            "query": query,
-            "fields": ["title^3", "description^2", "genre", "author_name"],
-            "fuzziness": "AUTO"
-        }
-    })];
-
+    // Canonical genres also match their subgenres; unknown values match as given
     let mut filter = Vec::new();
     if let Some(g) = genre {
-        filter.push(serde_json::json!({"term": {"genre": g}}));
+        match taxonomy.normalize(&g) {
+            Some(canonical) => filter.push(serde_json::json!({"term": {(genres::GENRE_PATH_FIELD): canonical.slug}})),
+            None => filter.push(serde_json::json!({"term": {"genre": g}})),
+        }
     }
     if let Some(s) = status {
         filter.push(serde_json::json!({"term": {"status": s}}));
     }
+    let safe_search = safe_search_for(req, &conn)?;
+    filter.extend(safe_search.filter());
+    let user_id = get_optional_user_id(req);
+    let experiment = experiments::assign(&conn, "search_books", user_id.as_ref());
 
-    let search_body = serde_json::json!({
-        "query": {
-            "bool": {
-                "must": must,
-                "filter": filter
-            }
-        },
-        "highlight": {
-            "fields": {
-                "title": {},
-                "description": {}
+    let search = spelling::search_with_correction(correct, &query, "title", |query, suggest| {
+        // Build query with filters
+        let must = vec![serde_json::json!({
+            "multi_match": {
+                "query": query,
+                "fields": ["title^3", "description^2", "genre", "author_name"],
+                "fuzziness": "AUTO"
             }
-        },
-        "sort": [
-            "_score",
-            {"updated_at": "desc"}
-        ],
-        "from": from,
-        "size": size
-    });
+        })];
 
-    let response = elasticsearch_request(&es_url, "GET", "/authorworks-books/_search", &search_body)?;
+        let mut search_body = serde_json::json!({
+            "query": {
+                "bool": {
+                    "must": must,
+                    "filter": filter
+                }
+            },
+            "highlight": {
+                "fields": {
+                    "title": {},
+                    "description": {}
+                }
+            },
+            "sort": [
+                "_score",
+                {"updated_at": "desc"}
+            ],
+            "aggs": genres::facet_aggregation(),
+            "from": from,
+            "size": size
+        });
+        if let Some(suggest) = suggest {
+            search_body["suggest"] = suggest;
+        }
+        if let Some(ref experiment) = experiment {
+            experiment.rank(&mut search_body);
+        }
+
+        elasticsearch_request(&es_url, "GET", "/authorworks-books/_search", &search_body)
+    })?;
+    let response = search.response;
     
     let hits = response.get("hits").and_then(|h| h.get("hits")).and_then(|h| h.as_array());
     let total = response.get("hits").and_then(|h| h.get("total")).and_then(|t| t.get("value")).and_then(|v| v.as_i64()).unwrap_or(0);
 
-    let books: Vec<BookSearchResult> = hits.map(|arr| {
-        arr.iter().filter_map(|hit| {
-            let source = hit.get("_source")?;
-            Some(BookSearchResult {
-                id: source.get("id")?.as_str()?.to_string(),
-                title: source.get("title")?.as_str()?.to_string(),
-                description: source.get("description").and_then(|v| v.as_str()).map(|s| s.to_string()),
-                author_name: source.get("author_name").and_then(|v| v.as_str()).map(|s| s.to_string()),
-                genre: source.get("genre").and_then(|v| v.as_str()).map(|s| s.to_string()),
-                status: source.get("status").and_then(|v| v.as_str()).unwrap_or("draft").to_string(),
-                cover_url: source.get("cover_url").and_then(|v| v.as_str()).map(|s| s.to_string()),
-                word_count: source.get("word_count").and_then(|v| v.as_i64()).unwrap_or(0) as i32,
-                score: hit.get("_score").and_then(|s| s.as_f64()).unwrap_or(0.0),
-            })
-        }).collect()
-    }).unwrap_or_default();
+    let books: Vec<BookSearchResult> = hits
+        .map(|arr| arr.iter().filter_map(book_from_hit).collect())
+        .unwrap_or_default();
+
+    let facets = taxonomy.facets_json(&genres::facet_counts(&response));
+
+    if let (Some(experiment), Some(uid)) = (&experiment, &user_id) {
+        experiment.log_exposure(&conn, uid, Some(query.as_str()), books.len());
+    }
 
     json_response(200, serde_json::json!({
         "books": books,
         "total": total,
         "from": from,
-        "size": size
+        "size": size,
+        "facets": {
+            "genres": facets
+        },
+        "safe_search": safe_search.level.as_str(),
+        "did_you_mean": search.did_you_mean,
+        "corrected_query": search.corrected_query,
+        "experiment": experiment.as_ref().map(|e| e.to_json())
     }))
 }
 
 fn search_chapters(req: &Request) -> Result<Response, ServiceError> {
-    let query = get_query_param(req, "q")
-        .ok_or_else(|| ServiceError::BadRequest("Query parameter 'q' is required".into()))?;
+    let query = get_query_param(req, "q").filter(|q| !q.trim().is_empty());
+    let entity = get_query_param(req, "entity").map(|e| e.trim().to_string()).filter(|e| !e.is_empty());
+    if query.is_none() && entity.is_none() {
+        return Err(ServiceError::BadRequest("Query parameter 'q' or 'entity' is required".into()));
+    }
     let book_id = get_query_param(req, "book_id");
     let from = get_query_param(req, "from").and_then(|s| s.parse().ok()).unwrap_or(0);
     let size = get_query_param(req, "size").and_then(|s| s.parse().ok()).unwrap_or(20);
 
     let es_url = get_elasticsearch_url()?;
+    let conn = get_db_connection()?;
+    let safe_search = safe_search_for(req, &conn)?;
 
     let mut filter = Vec::new();
     if let Some(bid) = book_id {
         filter.push(serde_json::json!({"term": {"book_id": bid}}));
     }
+    if let Some(ref name) = entity {
+        filter.push(serde_json::json!({"term": {(entities::ENTITIES_FIELD): name}}));
+    }
+    filter.extend(safe_search.filter());
 
-    let search_body = serde_json::json!({
+    // Entity-only browsing lists matching chapters in reading order
+    let must = match &query {
+        Some(q) => vec![serde_json::json!({
+            "multi_match": {
+                "query": q,
+                "fields": ["title^2", "content"],
+                "fuzziness": "AUTO"
+            }
+        })],
+        None => vec![serde_json::json!({"match_all": {}})],
+    };
+    let mut search_body = serde_json::json!({
         "query": {
             "bool": {
-                "must": [{
-                    "multi_match": {
-                        "query": query,
-                        "fields": ["title^2", "content"],
-                        "fuzziness": "AUTO"
-                    }
-                }],
+                "must": must,
                 "filter": filter
             }
         },
@@ -331,6 +536,9 @@ fn search_chapters(req: &Request) -> Result<Response, ServiceError> {
         "from": from,
         "size": size
     });
+    if query.is_none() {
+        search_body["sort"] = serde_json::json!([{"book_id.keyword": "asc"}, {"chapter_number": "asc"}]);
+    }
 
     let response = elasticsearch_request(&es_url, "GET", "/authorworks-chapters/_search", &search_body)?;
     
@@ -357,7 +565,9 @@ fn search_chapters(req: &Request) -> Result<Response, ServiceError> {
         "chapters": chapters,
         "total": total,
         "from": from,
-        "size": size
+        "size": size,
+        "entity": entity,
+        "safe_search": safe_search.level.as_str()
     }))
 }
 
@@ -367,7 +577,9 @@ fn search_authors(req: &Request) -> Result<Response, ServiceError> {
     let from = get_query_param(req, "from").and_then(|s| s.parse().ok()).unwrap_or(0);
     let size = get_query_param(req, "size").and_then(|s| s.parse().ok()).unwrap_or(20);
 
+    let conn = get_db_connection()?;
     let es_url = get_elasticsearch_url()?;
+    let safe_search = safe_search_for(req, &conn)?;
 
     let search_body = serde_json::json!({
         "query": {
@@ -400,21 +612,80 @@ fn search_authors(req: &Request) -> Result<Response, ServiceError> {
         }).collect()
     }).unwrap_or_default();
 
+    // Author documents carry no ratings, so check their books instead
+    let author_ids: Vec<String> = authors.iter().map(|author| author.id.clone()).collect();
+    let hidden = content_rating::hidden_authors(&conn, &safe_search, &author_ids)?;
+    let authors: Vec<AuthorSearchResult> = authors.into_iter()
+        .filter(|author| !hidden.contains(&author.id))
+        .collect();
+
     json_response(200, serde_json::json!({
         "authors": authors,
         "total": total,
         "from": from,
-        "size": size
+        "size": size,
+        "safe_search": safe_search.level.as_str()
     }))
 }
 
+fn search_mine(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let query = get_query_param(req, "q")
+        .ok_or_else(|| ServiceError::BadRequest("Query parameter 'q' is required".into()))?;
+    let mut types: Vec<String> = get_query_param(req, "types")
+        .map(|t| t.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    types.sort();
+    types.dedup();
+    let from = get_query_param(req, "from").and_then(|s| s.parse().ok()).unwrap_or(0);
+    let size = get_query_param(req, "size").and_then(|s| s.parse().ok()).unwrap_or(20);
+
+    let conn = get_db_connection()?;
+    workspace_search::search(&conn, &user_id, &query, &types, from, size)
+}
+
+/// Safe search for a request, from `safe_search` or the caller's preference
+fn safe_search_for(req: &Request, conn: &Connection) -> Result<content_rating::SafeSearch, ServiceError> {
+    let user_id = get_optional_user_id(req);
+    content_rating::resolve(conn, user_id.as_ref(), get_query_param(req, "safe_search").as_deref())
+}
+
+fn get_safe_search_preferences(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+    content_rating::get_preferences(&conn, &user_id)
+}
+
+fn update_safe_search_preferences(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: content_rating::PreferencesRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+    content_rating::update_preferences(&conn, &user_id, body)
+}
+
 //=============================================================================
 // Indexing
 //=============================================================================
 
 fn index_book(req: &Request) -> Result<Response, ServiceError> {
     let body: IndexBookRequest = parse_json_body(req)?;
-    let es_url = get_elasticsearch_url()?;
+    let conn = get_db_connection()?;
+    indexing_queue::check_backpressure(&conn)?;
+
+    // Authors can keep a book out of search; anything already indexed goes too
+    if is_index_excluded(&conn, &body.id)? {
+        indexing_queue::enqueue(&conn, "authorworks-books", &body.id, indexing_queue::QueueAction::Delete, None)?;
+        indexing_queue::discard_pending_chapters(&conn, &body.id)?;
+        duplicates::remove_book(&conn, &body.id)?;
+        cover_embeddings::remove_book(&conn, &body.id)?;
+        return json_response(200, serde_json::json!({
+            "queued": false,
+            "excluded": true,
+            "message": "Book is excluded from search indexing"
+        }));
+    }
+
+    let genre = genres::Taxonomy::load(&conn)?.index_fields(body.genre.as_deref());
 
     let doc = serde_json::json!({
         "id": body.id,
@@ -422,49 +693,97 @@ fn index_book(req: &Request) -> Result<Response, ServiceError> {
         "description": body.description,
         "author_id": body.author_id,
         "author_name": body.author_name,
-        "genre": body.genre,
+        "genre": genre.genre,
+        "genre_slug": genre.genre_slug,
+        "genre_path": genre.genre_path,
         "status": body.status,
         "cover_url": body.cover_url,
         "word_count": body.word_count,
+        "content_rating": content_rating::normalize_rating(body.content_rating.as_deref()),
+        "content_warnings": content_rating::normalize_warnings(&body.content_warnings),
         "created_at": body.created_at,
         "updated_at": body.updated_at
     });
 
-    elasticsearch_request(&es_url, "PUT", &format!("/authorworks-books/_doc/{}", body.id), &doc)?;
+    indexing_queue::enqueue(&conn, "authorworks-books", &body.id, indexing_queue::QueueAction::Index, Some(&doc))?;
+
+    // Similarity and alerts are advisory; they must never hold up indexing
+    duplicates::check_book(&conn, &body.id).ok();
+    cover_embeddings::embed_cover(&conn, &body.id, body.cover_url.as_deref()).ok();
+    if body.status == "published" {
+        keyword_alerts::check_book(&conn, &get_elasticsearch_url()?, &doc).ok();
+    }
 
-    json_response(200, serde_json::json!({"indexed": true}))
+    json_response(202, serde_json::json!({"queued": true}))
 }
 
 fn index_chapter(req: &Request) -> Result<Response, ServiceError> {
     let body: IndexChapterRequest = parse_json_body(req)?;
-    let es_url = get_elasticsearch_url()?;
+    let conn = get_db_connection()?;
+    indexing_queue::check_backpressure(&conn)?;
+
+    if is_index_excluded(&conn, &body.book_id)? {
+        indexing_queue::enqueue(&conn, "authorworks-chapters", &body.id, indexing_queue::QueueAction::Delete, None)?;
+        entities::remove_chapter(&conn, &body.id)?;
+        return json_response(200, serde_json::json!({
+            "queued": false,
+            "excluded": true,
+            "message": "Book is excluded from search indexing"
+        }));
+    }
+
+    let chapter_entities = body.content.as_deref().map(entities::extract).unwrap_or_default();
 
     let doc = serde_json::json!({
         "id": body.id,
         "book_id": body.book_id,
         "title": body.title,
         "content": body.content,
+        "entities": chapter_entities.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(),
         "chapter_number": body.chapter_number,
         "word_count": body.word_count,
+        "content_rating": content_rating::normalize_rating(body.content_rating.as_deref()),
+        "content_warnings": content_rating::normalize_warnings(&body.content_warnings),
         "created_at": body.created_at,
         "updated_at": body.updated_at
     });
 
-    elasticsearch_request(&es_url, "PUT", &format!("/authorworks-chapters/_doc/{}", body.id), &doc)?;
+    indexing_queue::enqueue(&conn, "authorworks-chapters", &body.id, indexing_queue::QueueAction::Index, Some(&doc))?;
+
+    if let Some(content) = &body.content {
+        duplicates::check_chapter(&conn, &body.id, &body.book_id, content).ok();
+    }
+    entities::store(&conn, &body.id, &body.book_id, &chapter_entities)?;
+
+    json_response(202, serde_json::json!({
+        "queued": true,
+        "entities": chapter_entities.len()
+    }))
+}
 
-    json_response(200, serde_json::json!({"indexed": true}))
+fn is_index_excluded(conn: &Connection, book_id: &str) -> Result<bool, ServiceError> {
+    let query = "SELECT COALESCE(index_excluded, false) FROM content.books WHERE id::text = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    Ok(rows.rows.first().map(|row| bool::decode(&row[0]).unwrap_or(false)).unwrap_or(false))
 }
 
 fn delete_book_index(_req: &Request, path: &str) -> Result<Response, ServiceError> {
     let book_id = path.strip_prefix("/index/book/")
         .ok_or_else(|| ServiceError::BadRequest("Invalid path".into()))?;
 
+    let conn = get_db_connection()?;
+    indexing_queue::check_backpressure(&conn)?;
     let es_url = get_elasticsearch_url()?;
 
-    // Delete book document
-    elasticsearch_request(&es_url, "DELETE", &format!("/authorworks-books/_doc/{}", book_id), &serde_json::json!({}))?;
+    // Book document goes through the queue so it supersedes any pending write
+    indexing_queue::enqueue(&conn, "authorworks-books", book_id, indexing_queue::QueueAction::Delete, None)?;
+    indexing_queue::discard_pending_chapters(&conn, book_id)?;
+    duplicates::remove_book(&conn, book_id)?;
+    cover_embeddings::remove_book(&conn, book_id)?;
+    entities::remove_book(&conn, book_id)?;
 
-    // Delete associated chapters
+    // _bulk has no delete-by-query, so chapters are removed directly
     let delete_query = serde_json::json!({
         "query": {
             "term": {"book_id": book_id}
@@ -472,7 +791,24 @@ fn delete_book_index(_req: &Request, path: &str) -> Result<Response, ServiceErro
     });
     elasticsearch_request(&es_url, "POST", "/authorworks-chapters/_delete_by_query", &delete_query)?;
 
-    json_response(200, serde_json::json!({"deleted": true}))
+    json_response(202, serde_json::json!({"deleted": true}))
+}
+
+fn flush_index_queue(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let conn = get_db_connection()?;
+    let es_url = get_elasticsearch_url()?;
+
+    let summary = indexing_queue::process_queue(&conn, &es_url)?;
+    json_response(200, summary)
+}
+
+fn get_index_status(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let conn = get_db_connection()?;
+    let es_url = get_elasticsearch_url()?;
+
+    index_status::get_index_status(&conn, &es_url)
 }
 
 //=============================================================================
@@ -483,6 +819,9 @@ fn get_recommendations(req: &Request) -> Result<Response, ServiceError> {
     let user_id = get_optional_user_id(req);
     let conn = get_db_connection()?;
     let es_url = get_elasticsearch_url()?;
+    let safe_search = safe_search_for(req, &conn)?;
+    let mut filter = vec![serde_json::json!({"term": {"status": "published"}})];
+    filter.extend(safe_search.filter());
 
     // Get user's reading history and preferences
     let genres = if let Some(uid) = user_id {
@@ -501,17 +840,22 @@ fn get_recommendations(req: &Request) -> Result<Response, ServiceError> {
         vec![]
     };
 
+    // Only the genre query is scored; the fallback is ordered by recency
+    let experiment = if genres.is_empty() {
+        None
+    } else {
+        experiments::assign(&conn, "recommendations", user_id.as_ref())
+    };
+
     // Build recommendation query
-    let search_body = if !genres.is_empty() {
+    let mut search_body = if !genres.is_empty() {
         serde_json::json!({
             "query": {
                 "bool": {
                     "should": genres.iter().map(|g| {
                         serde_json::json!({"term": {"genre": g}})
                     }).collect::<Vec<_>>(),
-                    "filter": [
-                        {"term": {"status": "published"}}
-                    ],
+                    "filter": filter,
                     "minimum_should_match": 1
                 }
             },
@@ -526,9 +870,7 @@ fn get_recommendations(req: &Request) -> Result<Response, ServiceError> {
         serde_json::json!({
             "query": {
                 "bool": {
-                    "filter": [
-                        {"term": {"status": "published"}}
-                    ]
+                    "filter": filter
                 }
             },
             "sort": [
@@ -537,6 +879,9 @@ fn get_recommendations(req: &Request) -> Result<Response, ServiceError> {
             "size": 20
         })
     };
+    if let Some(ref experiment) = experiment {
+        experiment.rank(&mut search_body);
+    }
 
     let response = elasticsearch_request(&es_url, "GET", "/authorworks-books/_search", &search_body)?;
     
@@ -554,47 +899,105 @@ fn get_recommendations(req: &Request) -> Result<Response, ServiceError> {
                 status: source.get("status").and_then(|v| v.as_str()).unwrap_or("draft").to_string(),
                 cover_url: source.get("cover_url").and_then(|v| v.as_str()).map(|s| s.to_string()),
                 word_count: source.get("word_count").and_then(|v| v.as_i64()).unwrap_or(0) as i32,
+                content_rating: source.get("content_rating").and_then(|v| v.as_str()).map(|s| s.to_string()),
+                content_warnings: content_warnings(source),
                 score: hit.get("_score").and_then(|s| s.as_f64()).unwrap_or(0.0),
             })
         }).collect()
     }).unwrap_or_default();
 
+    // Blend in "readers also read" candidates from the user's recent books
+    let candidates = match user_id {
+        Some(uid) => collaborative::user_candidates(&conn, &uid, 20)?,
+        None => vec![],
+    };
+
+    let recommendations = if candidates.is_empty() {
+        recommendations
+    } else {
+        let ids: Vec<&str> = candidates.iter().map(|(id, _)| id.as_str()).collect();
+        let mut cf_filter = vec![serde_json::json!({"ids": {"values": ids}})];
+        cf_filter.extend(filter);
+        let cf_body = serde_json::json!({
+            "query": {
+                "bool": {
+                    "filter": cf_filter
+                }
+            },
+            "size": ids.len()
+        });
+        let cf_response = elasticsearch_request(&es_url, "GET", "/authorworks-books/_search", &cf_body)?;
+        let cf_books: Vec<BookSearchResult> = cf_response.get("hits")
+            .and_then(|h| h.get("hits"))
+            .and_then(|h| h.as_array())
+            .map(|arr| arr.iter().filter_map(book_from_hit).collect())
+            .unwrap_or_default();
+
+        collaborative::blend(&collaborative::get_config(), recommendations, &candidates, cf_books, 20)
+    };
+
+    if let (Some(experiment), Some(uid)) = (&experiment, &user_id) {
+        experiment.log_exposure(&conn, uid, None, recommendations.len());
+    }
+
     json_response(200, serde_json::json!({
         "recommendations": recommendations,
-        "personalized": user_id.is_some()
+        "personalized": user_id.is_some(),
+        "collaborative": !candidates.is_empty(),
+        "experiment": experiment.as_ref().map(|e| e.to_json()),
+        "safe_search": safe_search.level.as_str()
     }))
 }
 
-fn get_trending(_req: &Request) -> Result<Response, ServiceError> {
+fn rebuild_book_neighbors(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
     let conn = get_db_connection()?;
-    let es_url = get_elasticsearch_url()?;
-
-    // Get books with most activity in last 7 days
-    let query = "SELECT book_id, COUNT(*) as activity
-                 FROM discovery.reading_history
-                 WHERE created_at > NOW() - INTERVAL '7 days'
-                 GROUP BY book_id
-                 ORDER BY activity DESC
-                 LIMIT 20";
+    let config = collaborative::get_config();
 
-    let rows = conn.query(query, &[])
-        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let summary = collaborative::rebuild_neighbors(&conn, &config)?;
+    json_response(200, summary)
+}
 
-    let book_ids: Vec<String> = rows.rows.iter()
-        .filter_map(|r| String::decode(&r[0]).ok())
-        .collect();
+fn get_trending(req: &Request) -> Result<Response, ServiceError> {
+    let conn = get_db_connection()?;
+    let es_url = get_elasticsearch_url()?;
+    let safe_search = safe_search_for(req, &conn)?;
+
+    // Get books with most activity in last 7 days, aggregating live until
+    // the trending table has been built
+    let book_ids: Vec<String> = match trending::top_books(&conn, 20)? {
+        Some(ids) => ids,
+        None => {
+            let query = "SELECT book_id, COUNT(*) as activity
+                         FROM discovery.reading_history
+                         WHERE created_at > NOW() - INTERVAL '7 days'
+                         GROUP BY book_id
+                         ORDER BY activity DESC
+                         LIMIT 20";
+
+            let rows = conn.query(query, &[])
+                .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+            rows.rows.iter()
+                .filter_map(|r| String::decode(&r[0]).ok())
+                .collect()
+        }
+    };
 
     if book_ids.is_empty() {
         return json_response(200, serde_json::json!({
             "trending": [],
-            "period": "7d"
+            "period": "7d",
+            "safe_search": safe_search.level.as_str()
         }));
     }
 
     // Fetch book details from Elasticsearch
+    let mut filter = vec![serde_json::json!({"ids": {"values": book_ids}})];
+    filter.extend(safe_search.filter());
     let search_body = serde_json::json!({
         "query": {
-            "ids": {"values": book_ids}
+            "bool": {"filter": filter}
         },
         "size": 20
     });
@@ -615,6 +1018,8 @@ fn get_trending(_req: &Request) -> Result<Response, ServiceError> {
                 status: source.get("status").and_then(|v| v.as_str()).unwrap_or("draft").to_string(),
                 cover_url: source.get("cover_url").and_then(|v| v.as_str()).map(|s| s.to_string()),
                 word_count: source.get("word_count").and_then(|v| v.as_i64()).unwrap_or(0) as i32,
+                content_rating: source.get("content_rating").and_then(|v| v.as_str()).map(|s| s.to_string()),
+                content_warnings: content_warnings(source),
                 score: 0.0,
             })
         }).collect()
@@ -622,37 +1027,72 @@ fn get_trending(_req: &Request) -> Result<Response, ServiceError> {
 
     json_response(200, serde_json::json!({
         "trending": trending,
-        "period": "7d"
+        "period": "7d",
+        "safe_search": safe_search.level.as_str()
     }))
 }
 
-fn get_similar(_req: &Request, path: &str) -> Result<Response, ServiceError> {
+fn rebuild_trending(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let conn = get_db_connection()?;
+
+    let summary = trending::rebuild(&conn)?;
+    json_response(200, summary)
+}
+
+fn handle_discovery_event(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let event: events::DiscoveryEvent = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    let outcome = events::handle_event(&conn, &event)?;
+    json_response(200, outcome)
+}
+
+fn get_similar(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let book_id = path.strip_prefix("/similar/")
+        .map(|rest| rest.split('?').next().unwrap_or(rest))
         .ok_or_else(|| ServiceError::BadRequest("Invalid path".into()))?;
+    let visual_weight = match get_query_param(req, "visual_weight") {
+        Some(value) => value.parse::<f64>().ok()
+            .filter(|w| (0.0..=1.0).contains(w))
+            .ok_or_else(|| ServiceError::BadRequest("visual_weight must be between 0 and 1".into()))?,
+        None => 0.0,
+    };
 
+    let conn = get_db_connection()?;
     let es_url = get_elasticsearch_url()?;
+    let safe_search = safe_search_for(req, &conn)?;
+
+    // Visual re-ranking needs a wider pool of text candidates to choose from
+    let size = if visual_weight > 0.0 { cover_embeddings::CANDIDATE_POOL } else { 10 };
 
     // Use More Like This query
     let search_body = serde_json::json!({
         "query": {
-            "more_like_this": {
-                "fields": ["title", "description", "genre"],
-                "like": [
-                    {"_index": "authorworks-books", "_id": book_id}
-                ],
-                "min_term_freq": 1,
-                "min_doc_freq": 1,
-                "max_query_terms": 25
+            "bool": {
+                "must": {
+                    "more_like_this": {
+                        "fields": ["title", "description", "genre"],
+                        "like": [
+                            {"_index": "authorworks-books", "_id": book_id}
+                        ],
+                        "min_term_freq": 1,
+                        "min_doc_freq": 1,
+                        "max_query_terms": 25
+                    }
+                },
+                "filter": safe_search.filter().into_iter().collect::<Vec<_>>()
             }
         },
-        "size": 10
+        "size": size
     });
 
     let response = elasticsearch_request(&es_url, "GET", "/authorworks-books/_search", &search_body)?;
     
     let hits = response.get("hits").and_then(|h| h.get("hits")).and_then(|h| h.as_array());
 
-    let similar: Vec<BookSearchResult> = hits.map(|arr| {
+    let mut similar: Vec<BookSearchResult> = hits.map(|arr| {
         arr.iter().filter_map(|hit| {
             let source = hit.get("_source")?;
             Some(BookSearchResult {
@@ -664,17 +1104,505 @@ fn get_similar(_req: &Request, path: &str) -> Result<Response, ServiceError> {
                 status: source.get("status").and_then(|v| v.as_str()).unwrap_or("draft").to_string(),
                 cover_url: source.get("cover_url").and_then(|v| v.as_str()).map(|s| s.to_string()),
                 word_count: source.get("word_count").and_then(|v| v.as_i64()).unwrap_or(0) as i32,
+                content_rating: source.get("content_rating").and_then(|v| v.as_str()).map(|s| s.to_string()),
+                content_warnings: content_warnings(source),
                 score: hit.get("_score").and_then(|s| s.as_f64()).unwrap_or(0.0),
             })
         }).collect()
     }).unwrap_or_default();
 
+    if visual_weight == 0.0 {
+        return json_response(200, serde_json::json!({
+            "similar": similar,
+            "book_id": book_id,
+            "safe_search": safe_search.level.as_str()
+        }));
+    }
+
+    // Blend the text score, scaled to 0..1 by the best hit, with cover
+    // cosine similarity. Candidates without a cover vector get no visual
+    // credit; if the book itself has none, the text ranking stands.
+    let candidate_ids: Vec<String> = similar.iter().map(|book| book.id.clone()).collect();
+    let visual = cover_embeddings::similarities(&conn, book_id, &candidate_ids)?;
+    let visual_applied = !visual.is_empty();
+
+    if visual_applied {
+        let max_text = similar.iter().map(|book| book.score).fold(0.0_f64, f64::max);
+        for book in similar.iter_mut() {
+            let text = if max_text > 0.0 { book.score / max_text } else { 0.0 };
+            let cover = visual.get(&book.id).copied().unwrap_or(0.0).max(0.0);
+            book.score = (1.0 - visual_weight) * text + visual_weight * cover;
+        }
+        similar.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    }
+    similar.truncate(10);
+
+    let similar: Vec<serde_json::Value> = similar.into_iter().map(|book| {
+        let visual_similarity = visual.get(&book.id).copied();
+        let mut entry = serde_json::to_value(book).unwrap_or_default();
+        entry["visual_similarity"] = serde_json::json!(visual_similarity);
+        entry
+    }).collect();
+
     json_response(200, serde_json::json!({
         "similar": similar,
-        "book_id": book_id
+        "book_id": book_id,
+        "visual_weight": visual_weight,
+        "visual_applied": visual_applied,
+        "safe_search": safe_search.level.as_str()
+    }))
+}
+
+//=============================================================================
+// Following
+//=============================================================================
+
+fn follow_author(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let author_id = parse_author_id(path)?;
+    let conn = get_db_connection()?;
+
+    follows::follow(&conn, &user_id, &author_id)
+}
+
+fn unfollow_author(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let author_id = parse_author_id(path)?;
+    let conn = get_db_connection()?;
+
+    follows::unfollow(&conn, &user_id, &author_id)
+}
+
+fn get_author_books(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let author_id = parse_author_id(path)?;
+    let exclude = get_query_param(req, "exclude").and_then(|s| Uuid::parse_str(&s).ok());
+    let limit = get_query_param(req, "limit").and_then(|s| s.parse().ok()).unwrap_or(follows::DEFAULT_AUTHOR_BOOKS_LIMIT);
+    let conn = get_db_connection()?;
+    let safe_search = safe_search_for(req, &conn)?;
+
+    follows::author_books(&conn, get_optional_user_id(req).as_ref(), &author_id, exclude.as_ref(), limit, &safe_search)
+}
+
+fn get_book_card(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let book_id = path.strip_prefix("/books/")
+        .and_then(|rest| rest.split('/').next())
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid book ID".into()))?;
+    let if_none_match = req.header("If-None-Match").and_then(|h| h.as_str());
+
+    let conn = get_db_connection()?;
+    let es_url = get_elasticsearch_url()?;
+    let safe_search = safe_search_for(req, &conn)?;
+
+    book_cards::get(&conn, &es_url, &book_id, get_optional_user_id(req).as_ref(), &safe_search, if_none_match)
+}
+
+fn get_book_entities(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let book_id = path.strip_prefix("/books/")
+        .and_then(|rest| rest.split('/').next())
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid book ID".into()))?;
+    let min_chapters = get_query_param(req, "min_chapters").and_then(|s| s.parse().ok()).unwrap_or(1);
+    let limit = get_query_param(req, "limit").and_then(|s| s.parse().ok());
+    let conn = get_db_connection()?;
+
+    entities::list_for_book(&conn, &book_id, get_optional_user_id(req).as_ref(), min_chapters, limit)
+}
+
+fn get_feed(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let before = get_query_param(req, "before");
+    let limit = get_query_param(req, "limit").and_then(|s| s.parse().ok()).unwrap_or(follows::DEFAULT_FEED_LIMIT);
+    let conn = get_db_connection()?;
+    let safe_search = safe_search_for(req, &conn)?;
+
+    follows::feed(&conn, &user_id, before.as_deref(), limit, &safe_search)
+}
+
+fn create_keyword_alert(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: keyword_alerts::CreateAlertRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+    let es_url = get_elasticsearch_url()?;
+
+    keyword_alerts::create(&conn, &es_url, &user_id, body)
+}
+
+fn list_keyword_alerts(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+
+    keyword_alerts::list(&conn, &user_id)
+}
+
+fn delete_keyword_alert(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let alert_id = path.strip_prefix("/alerts/keywords/")
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid alert ID".into()))?;
+    let conn = get_db_connection()?;
+    let es_url = get_elasticsearch_url()?;
+
+    keyword_alerts::delete(&conn, &es_url, &user_id, &alert_id)
+}
+
+fn parse_author_id(path: &str) -> Result<Uuid, ServiceError> {
+    let id = path.strip_prefix("/authors/")
+        .and_then(|rest| rest.split('/').next())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid path".into()))?;
+    Uuid::parse_str(id)
+        .map_err(|_| ServiceError::BadRequest("Invalid author ID".into()))
+}
+
+//=============================================================================
+// Genres
+//=============================================================================
+
+fn list_genres() -> Result<Response, ServiceError> {
+    let conn = get_db_connection()?;
+    let es_url = get_elasticsearch_url()?;
+    let taxonomy = genres::Taxonomy::load(&conn)?;
+
+    // Counts are best-effort; the taxonomy is still served if search is down
+    let count_body = serde_json::json!({
+        "query": {
+            "bool": {
+                "filter": [
+                    {"term": {"status": "published"}}
+                ]
+            }
+        },
+        "aggs": genres::facet_aggregation(),
+        "size": 0
+    });
+    let counts = elasticsearch_request(&es_url, "GET", "/authorworks-books/_search", &count_body)
+        .map(|response| genres::facet_counts(&response))
+        .unwrap_or_default();
+
+    json_response(200, serde_json::json!({
+        "genres": taxonomy.tree_json(&counts)
     }))
 }
 
+fn get_genre_books(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let slug = path.strip_prefix("/genres/")
+        .and_then(|rest| rest.strip_suffix("/books"))
+        .filter(|slug| !slug.is_empty() && !slug.contains('/'))
+        .ok_or_else(|| ServiceError::BadRequest("Invalid path".into()))?;
+    let from = get_query_param(req, "from").and_then(|s| s.parse().ok()).unwrap_or(0);
+    let size = get_query_param(req, "size").and_then(|s| s.parse().ok()).unwrap_or(20);
+
+    let conn = get_db_connection()?;
+    let es_url = get_elasticsearch_url()?;
+    let taxonomy = genres::Taxonomy::load(&conn)?;
+
+    // Aliases and display names resolve too, e.g. /genres/sci-fi/books
+    let genre = taxonomy.get(slug)
+        .or_else(|| taxonomy.normalize(&urlencoded_decode(slug)))
+        .ok_or_else(|| ServiceError::NotFound(format!("Genre not found: {}", slug)))?;
+    let safe_search = safe_search_for(req, &conn)?;
+
+    let mut filter = vec![
+        serde_json::json!({"term": {(genres::GENRE_PATH_FIELD): genre.slug}}),
+        serde_json::json!({"term": {"status": "published"}}),
+    ];
+    filter.extend(safe_search.filter());
+
+    let search_body = serde_json::json!({
+        "query": {
+            "bool": {
+                "filter": filter
+            }
+        },
+        "sort": [
+            {"updated_at": "desc"}
+        ],
+        "aggs": genres::facet_aggregation(),
+        "from": from,
+        "size": size
+    });
+
+    let response = elasticsearch_request(&es_url, "GET", "/authorworks-books/_search", &search_body)?;
+
+    let total = response.get("hits").and_then(|h| h.get("total")).and_then(|t| t.get("value")).and_then(|v| v.as_i64()).unwrap_or(0);
+    let books: Vec<BookSearchResult> = response.get("hits")
+        .and_then(|h| h.get("hits"))
+        .and_then(|h| h.as_array())
+        .map(|arr| arr.iter().filter_map(book_from_hit).collect())
+        .unwrap_or_default();
+
+    // Subgenre facets under the requested genre
+    let facets = taxonomy.facets_json(&genres::facet_counts(&response))
+        .into_iter()
+        .find_map(|node| find_facet(node, &genre.slug))
+        .and_then(|node| node.get("children").cloned())
+        .unwrap_or_else(|| serde_json::json!([]));
+
+    json_response(200, serde_json::json!({
+        "genre": taxonomy.genre_json(genre),
+        "books": books,
+        "total": total,
+        "from": from,
+        "size": size,
+        "facets": {
+            "genres": facets
+        },
+        "safe_search": safe_search.level.as_str()
+    }))
+}
+
+/// Depth-first search of a facet tree for `slug`
+fn find_facet(node: serde_json::Value, slug: &str) -> Option<serde_json::Value> {
+    if node.get("slug").and_then(|s| s.as_str()) == Some(slug) {
+        return Some(node);
+    }
+    node.get("children")
+        .and_then(|c| c.as_array())
+        .cloned()
+        .unwrap_or_default()
+        .into_iter()
+        .find_map(|child| find_facet(child, slug))
+}
+
+//=============================================================================
+// Search Analytics
+//=============================================================================
+
+fn record_search_event(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_optional_user_id(req);
+    let body: search_analytics::SearchEventRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    search_analytics::record(&conn, user_id.as_ref(), body)
+}
+
+fn get_top_search_queries(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let (days, limit) = search_report_window(req);
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    search_analytics::top_queries(&conn, days, limit)
+}
+
+fn get_zero_result_queries(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let (days, limit) = search_report_window(req);
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    search_analytics::zero_result_queries(&conn, days, limit)
+}
+
+fn list_duplicates(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let status = get_query_param(req, "status").unwrap_or_else(|| "pending".to_string());
+    let doc_type = get_query_param(req, "type");
+    let limit = get_query_param(req, "limit")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(duplicates::DEFAULT_LIST_LIMIT)
+        .clamp(1, duplicates::MAX_LIST_LIMIT);
+    let offset = get_query_param(req, "offset").and_then(|s| s.parse().ok()).unwrap_or(0i64).max(0);
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    duplicates::list(&conn, &status, doc_type.as_deref(), limit, offset)
+}
+
+fn review_duplicate(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let flag_id = path.strip_prefix("/admin/duplicates/")
+        .and_then(|rest| rest.strip_suffix("/review"))
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid duplicate flag ID".into()))?;
+    let body: duplicates::ReviewRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    duplicates::review(&conn, &user_id, &flag_id, body)
+}
+
+fn list_experiments(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    experiments::list(&conn)
+}
+
+fn create_experiment(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: experiments::CreateExperimentRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    experiments::create(&conn, &user_id, body)
+}
+
+fn stop_experiment(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let experiment_id = path.strip_prefix("/admin/experiments/")
+        .and_then(|rest| rest.strip_suffix("/stop"))
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid experiment ID".into()))?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    experiments::stop(&conn, experiment_id)
+}
+
+fn get_experiment_results(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let experiment_id = path.strip_prefix("/admin/experiments/")
+        .and_then(|rest| rest.strip_suffix("/results"))
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid experiment ID".into()))?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    experiments::results(&conn, experiment_id)
+}
+
+fn create_index_snapshot(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: snapshots::SnapshotRequest = if req.body().is_empty() {
+        snapshots::SnapshotRequest::default()
+    } else {
+        parse_json_body(req)?
+    };
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    snapshots::create_snapshot(&get_elasticsearch_url()?, &user_id, body)
+}
+
+fn list_index_snapshots(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    snapshots::list_snapshots(&get_elasticsearch_url()?)
+}
+
+fn get_index_snapshot(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let name = path.strip_prefix("/admin/index/snapshots/")
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid snapshot name".into()))?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    snapshots::get_snapshot(&get_elasticsearch_url()?, name)
+}
+
+fn restore_index_snapshot(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: snapshots::RestoreRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    snapshots::restore(&get_elasticsearch_url()?, body)
+}
+
+fn get_index_restore_status(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let indices = get_query_param(req, "indices");
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &user_id)?;
+    snapshots::restore_status(&get_elasticsearch_url()?, indices.as_deref())
+}
+
+fn search_report_window(req: &Request) -> (i32, i64) {
+    let days = get_query_param(req, "days")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(search_analytics::DEFAULT_REPORT_DAYS)
+        .clamp(1, search_analytics::MAX_REPORT_DAYS);
+    let limit = get_query_param(req, "limit")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(search_analytics::DEFAULT_REPORT_LIMIT)
+        .clamp(1, search_analytics::MAX_REPORT_LIMIT);
+    (days, limit)
+}
+
+//=============================================================================
+// Reader Segments
+//=============================================================================
+
+fn list_segments(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+
+    segments::list_segments(&conn, &user_id)
+}
+
+fn create_segment(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: segments::SaveSegmentRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    segments::create_segment(&conn, &user_id, body)
+}
+
+fn estimate_segment(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let definition: segments::SegmentDefinition = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    segments::estimate(&conn, &user_id, definition)
+}
+
+fn get_segment(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let segment_id = parse_segment_id(path)?;
+    let conn = get_db_connection()?;
+
+    segments::get_segment(&conn, &user_id, &segment_id)
+}
+
+fn update_segment(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let segment_id = parse_segment_id(path)?;
+    let body: segments::SaveSegmentRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    segments::update_segment(&conn, &user_id, &segment_id, body)
+}
+
+fn delete_segment(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let segment_id = parse_segment_id(path)?;
+    let conn = get_db_connection()?;
+
+    segments::delete_segment(&conn, &user_id, &segment_id)
+}
+
+fn export_segment(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let segment_id = parse_segment_id(path)?;
+    let conn = get_db_connection()?;
+
+    segments::export_segment(&conn, &user_id, &segment_id)
+}
+
+fn notify_segment(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let segment_id = parse_segment_id(path)?;
+    let body: segments::NotifySegmentRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    segments::notify_segment(&conn, &user_id, &segment_id, body)
+}
+
+/// Segment ID from `/segments/:id` or `/segments/:id/<action>`
+fn parse_segment_id(path: &str) -> Result<Uuid, ServiceError> {
+    let id = path.strip_prefix("/segments/")
+        .and_then(|rest| rest.split('/').next())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid path".into()))?;
+    Uuid::parse_str(id)
+        .map_err(|_| ServiceError::BadRequest("Invalid segment ID".into()))
+}
+
 //=============================================================================
 // Elasticsearch Helpers
 //=============================================================================
@@ -704,6 +1632,30 @@ fn elasticsearch_request(es_url: &str, method: &str, path: &str, body: &serde_js
         .map_err(|e| ServiceError::Internal(format!("Failed to parse Elasticsearch response: {}", e)))
 }
 
+fn book_from_hit(hit: &serde_json::Value) -> Option<BookSearchResult> {
+    let source = hit.get("_source")?;
+    Some(BookSearchResult {
+        id: source.get("id")?.as_str()?.to_string(),
+        title: source.get("title")?.as_str()?.to_string(),
+        description: source.get("description").and_then(|v| v.as_str()).map(|s| s.to_string()),
+        author_name: source.get("author_name").and_then(|v| v.as_str()).map(|s| s.to_string()),
+        genre: source.get("genre").and_then(|v| v.as_str()).map(|s| s.to_string()),
+        status: source.get("status").and_then(|v| v.as_str()).unwrap_or("draft").to_string(),
+        cover_url: source.get("cover_url").and_then(|v| v.as_str()).map(|s| s.to_string()),
+        word_count: source.get("word_count").and_then(|v| v.as_i64()).unwrap_or(0) as i32,
+        content_rating: source.get("content_rating").and_then(|v| v.as_str()).map(|s| s.to_string()),
+        content_warnings: content_warnings(source),
+        score: hit.get("_score").and_then(|s| s.as_f64()).unwrap_or(0.0),
+    })
+}
+
+fn content_warnings(source: &serde_json::Value) -> Vec<String> {
+    source.get("content_warnings")
+        .and_then(|v| v.as_array())
+        .map(|arr| arr.iter().filter_map(|w| w.as_str().map(|s| s.to_string())).collect())
+        .unwrap_or_default()
+}
+
 //=============================================================================
 // Helper Functions
 //=============================================================================
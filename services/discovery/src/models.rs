@@ -27,6 +27,8 @@ pub struct BookSearchResult {
     pub status: String,
     pub cover_url: Option<String>,
     pub word_count: i32,
+    pub content_rating: Option<String>,
+    pub content_warnings: Vec<String>,
     pub score: f64,
 }
 
@@ -65,6 +67,10 @@ pub struct IndexBookRequest {
     pub status: String,
     pub cover_url: Option<String>,
     pub word_count: i32,
+    /// `everyone`, `teen`, `mature` or `adult`; unrated when absent
+    pub content_rating: Option<String>,
+    #[serde(default)]
+    pub content_warnings: Vec<String>,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -77,6 +83,10 @@ pub struct IndexChapterRequest {
     pub content: Option<String>,
     pub chapter_number: i32,
     pub word_count: i32,
+    /// The book's rating and warnings, so chapter search can filter on them
+    pub content_rating: Option<String>,
+    #[serde(default)]
+    pub content_warnings: Vec<String>,
     pub created_at: String,
     pub updated_at: String,
 }
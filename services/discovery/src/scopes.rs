@@ -0,0 +1,27 @@
+//! Discovery routes open to delegated credentials
+//!
+//! Browsing the catalogue (search, recommendations, trending, genres, book
+//! cards) needs `books:read`, and chapter search `chapters:read`. Reader
+//! segments are the author's audience analytics: a credential with
+//! `analytics:read` may list and size them, but exporting or messaging a
+//! segment reaches readers and stays first-party. Follows, alerts, workspace
+//! search and preferences act for the account and are not delegated.
+
+use authorworks_access::{scopes, Route};
+use spin_sdk::http::Method;
+
+pub fn route_scope(method: &Method, path: &str) -> Option<Route> {
+    let books = Some(Route::Scope(scopes::BOOKS_READ));
+    match method {
+        Method::Get if path == "/search/mine" => None,
+        Method::Get if path == "/search/chapters" => Some(Route::Scope(scopes::CHAPTERS_READ)),
+        Method::Get if path == "/search" || path.starts_with("/search/") => books,
+        Method::Get if matches!(path, "/recommendations" | "/trending" | "/feed" | "/genres") => books,
+        Method::Get if path.starts_with("/similar/") || path.starts_with("/genres/") => books,
+        Method::Get if path.starts_with("/books/") => books,
+        Method::Get if path.starts_with("/authors/") && path.ends_with("/books") => books,
+        Method::Get if path == "/segments" || path.starts_with("/segments/") => Some(Route::Scope(scopes::ANALYTICS_READ)),
+        Method::Post if path == "/segments/estimate" => Some(Route::Scope(scopes::ANALYTICS_READ)),
+        _ => None,
+    }
+}
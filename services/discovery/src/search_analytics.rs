@@ -0,0 +1,204 @@
+//! Search analytics
+//!
+//! Clients report each search they show (`search`, with its result count)
+//! and each result the reader opens (`click`, with its 1-based position and,
+//! once the reader comes back, how long they stayed). Reports group queries
+//! by a normalized form so "Dragons " and "dragons" count together. Events
+//! from signed-out readers are kept without a user.
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+const SEARCH_TYPES: [&str; 6] = ["all", "books", "chapters", "authors", "mine", "recommendations"];
+const RESULT_TYPES: [&str; 3] = ["book", "chapter", "author"];
+
+const MAX_QUERY_LENGTH: usize = 200;
+const MAX_POSITION: i32 = 1000;
+/// Dwell beyond this is a tab left open, not reading; it is capped rather than rejected
+const MAX_DWELL_MS: i64 = 30 * 60 * 1000;
+
+pub const DEFAULT_REPORT_DAYS: i32 = 7;
+pub const MAX_REPORT_DAYS: i32 = 90;
+pub const DEFAULT_REPORT_LIMIT: i64 = 50;
+pub const MAX_REPORT_LIMIT: i64 = 500;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchEventRequest {
+    /// `search` or `click`
+    pub event_type: String,
+    pub query: String,
+    /// Ties clicks to the search that produced them
+    pub search_id: Option<Uuid>,
+    #[serde(default = "default_search_type")]
+    pub search_type: String,
+    pub result_count: Option<i32>,
+    pub result_id: Option<String>,
+    pub result_type: Option<String>,
+    pub position: Option<i32>,
+    pub dwell_ms: Option<i64>,
+}
+
+fn default_search_type() -> String {
+    "all".into()
+}
+
+#[derive(Debug, Serialize)]
+pub struct TopQuery {
+    pub query: String,
+    pub searches: i64,
+    pub clicks: i64,
+    /// Clicks per search; can exceed 1 when readers open several results
+    pub click_through_rate: f64,
+    pub zero_result_searches: i64,
+    pub avg_click_position: Option<f64>,
+    pub avg_dwell_ms: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ZeroResultQuery {
+    pub query: String,
+    pub searches: i64,
+    pub users: i64,
+    pub last_searched_at: String,
+}
+
+//=============================================================================
+// Capture
+//=============================================================================
+
+/// POST /analytics/search-event - Record a search or a result click
+pub fn record(conn: &Connection, user_id: Option<&Uuid>, body: SearchEventRequest) -> Result<Response, ServiceError> {
+    let query = body.query.trim();
+    if query.is_empty() || query.chars().count() > MAX_QUERY_LENGTH {
+        return Err(ServiceError::BadRequest(format!("query must be 1-{} characters", MAX_QUERY_LENGTH)));
+    }
+    if !SEARCH_TYPES.contains(&body.search_type.as_str()) {
+        return Err(ServiceError::BadRequest(format!("search_type must be one of: {}", SEARCH_TYPES.join(", "))));
+    }
+
+    let (result_count, result_id, result_type, position, dwell_ms) = match body.event_type.as_str() {
+        "search" => {
+            let count = body.result_count
+                .filter(|c| *c >= 0)
+                .ok_or_else(|| ServiceError::BadRequest("result_count is required for search events".into()))?;
+            (Some(count), None, None, None, None)
+        }
+        "click" => {
+            let position = body.position
+                .filter(|p| (1..=MAX_POSITION).contains(p))
+                .ok_or_else(|| ServiceError::BadRequest(format!("position must be between 1 and {}", MAX_POSITION)))?;
+            let result_id = body.result_id
+                .filter(|id| !id.is_empty() && id.len() <= 255)
+                .ok_or_else(|| ServiceError::BadRequest("result_id is required for click events".into()))?;
+            if let Some(ref t) = body.result_type {
+                if !RESULT_TYPES.contains(&t.as_str()) {
+                    return Err(ServiceError::BadRequest(format!("result_type must be one of: {}", RESULT_TYPES.join(", "))));
+                }
+            }
+            if body.dwell_ms.is_some_and(|d| d < 0) {
+                return Err(ServiceError::BadRequest("dwell_ms cannot be negative".into()));
+            }
+            (None, Some(result_id), body.result_type, Some(position), body.dwell_ms.map(|d| d.min(MAX_DWELL_MS)))
+        }
+        _ => return Err(ServiceError::BadRequest("event_type must be search or click".into())),
+    };
+
+    let id = Uuid::new_v4();
+    let insert = "INSERT INTO discovery.search_events
+                  (id, event_type, search_id, user_id, query, normalized_query, search_type,
+                   result_count, result_id, result_type, position, dwell_ms)
+                  VALUES ($1, $2, $3::uuid, $4::uuid, $5, $6, $7, $8, $9, $10, $11, $12)";
+    conn.execute(insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(body.event_type.clone()),
+        body.search_id.map(|s| ParameterValue::Str(s.to_string())).unwrap_or(ParameterValue::DbNull),
+        user_id.map(|u| ParameterValue::Str(u.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(query.to_string()),
+        ParameterValue::Str(normalize(query)),
+        ParameterValue::Str(body.search_type.clone()),
+        result_count.map(ParameterValue::Int32).unwrap_or(ParameterValue::DbNull),
+        result_id.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        result_type.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        position.map(ParameterValue::Int32).unwrap_or(ParameterValue::DbNull),
+        dwell_ms.map(ParameterValue::Int64).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    crate::json_response(201, serde_json::json!({ "id": id }))
+}
+
+//=============================================================================
+// Reports
+//=============================================================================
+
+/// GET /analytics/search/top-queries - Most searched queries with click-through and dwell
+pub fn top_queries(conn: &Connection, days: i32, limit: i64) -> Result<Response, ServiceError> {
+    let query = "SELECT normalized_query,
+                        COUNT(*) FILTER (WHERE event_type = 'search') AS searches,
+                        COUNT(*) FILTER (WHERE event_type = 'click') AS clicks,
+                        COUNT(*) FILTER (WHERE event_type = 'search' AND result_count = 0),
+                        AVG(position) FILTER (WHERE event_type = 'click')::float8,
+                        AVG(dwell_ms) FILTER (WHERE event_type = 'click')::float8
+                 FROM discovery.search_events
+                 WHERE created_at > NOW() - make_interval(days => $1)
+                 GROUP BY normalized_query
+                 HAVING COUNT(*) FILTER (WHERE event_type = 'search') > 0
+                 ORDER BY searches DESC, clicks DESC, normalized_query
+                 LIMIT $2";
+    let rows = conn.query(query, &[ParameterValue::Int32(days), ParameterValue::Int64(limit)])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let queries: Vec<TopQuery> = rows.rows.iter().map(|row| {
+        let searches = i64::decode(&row[1]).unwrap_or(0);
+        let clicks = i64::decode(&row[2]).unwrap_or(0);
+        TopQuery {
+            query: String::decode(&row[0]).unwrap_or_default(),
+            searches,
+            clicks,
+            click_through_rate: if searches > 0 { clicks as f64 / searches as f64 } else { 0.0 },
+            zero_result_searches: i64::decode(&row[3]).unwrap_or(0),
+            avg_click_position: f64::decode(&row[4]).ok(),
+            avg_dwell_ms: f64::decode(&row[5]).ok(),
+        }
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "days": days,
+        "queries": queries
+    }))
+}
+
+/// GET /analytics/search/zero-results - Queries that found nothing, most frequent first
+pub fn zero_result_queries(conn: &Connection, days: i32, limit: i64) -> Result<Response, ServiceError> {
+    let query = "SELECT normalized_query, COUNT(*) AS searches, COUNT(DISTINCT user_id), MAX(created_at)
+                 FROM discovery.search_events
+                 WHERE event_type = 'search' AND result_count = 0
+                   AND created_at > NOW() - make_interval(days => $1)
+                 GROUP BY normalized_query
+                 ORDER BY searches DESC, MAX(created_at) DESC
+                 LIMIT $2";
+    let rows = conn.query(query, &[ParameterValue::Int32(days), ParameterValue::Int64(limit)])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let queries: Vec<ZeroResultQuery> = rows.rows.iter().map(|row| ZeroResultQuery {
+        query: String::decode(&row[0]).unwrap_or_default(),
+        searches: i64::decode(&row[1]).unwrap_or(0),
+        users: i64::decode(&row[2]).unwrap_or(0),
+        last_searched_at: String::decode(&row[3]).unwrap_or_default(),
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "days": days,
+        "queries": queries
+    }))
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+fn normalize(query: &str) -> String {
+    query.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
@@ -0,0 +1,431 @@
+//! Reader cohort segmentation
+//!
+//! Authors define segments over their own readers, e.g. "finished book 1 but
+//! not book 2". A definition is a list of rules, all of which must hold; each
+//! rule tests one of the author's books against reading progress in
+//! `discovery.reading_history` or purchases in `discovery.book_purchases`.
+//! Cohorts are evaluated at query time, so saved segments never go stale.
+//!
+//! Consent comes from `users.consents`. Readers who opted out of analytics
+//! are left out of every cohort; export and notify only reach readers who
+//! opted in to marketing emails.
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use chrono::Utc;
+use uuid::Uuid;
+
+const DEFAULT_FINISHED_PROGRESS: f64 = 0.95;
+const DEFAULT_NOTIFY_COOLDOWN_HOURS: i64 = 24;
+const MAX_RULES: usize = 10;
+const EXPORT_LIMIT: i64 = 10_000;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    Started,
+    NotStarted,
+    Finished,
+    NotFinished,
+    Purchased,
+    NotPurchased,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SegmentRule {
+    pub book_id: Uuid,
+    pub condition: Condition,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SegmentDefinition {
+    pub rules: Vec<SegmentRule>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaveSegmentRequest {
+    pub name: String,
+    pub definition: SegmentDefinition,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotifySegmentRequest {
+    pub title: String,
+    pub body: String,
+    #[serde(default)]
+    pub data: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SegmentSize {
+    pub readers: i64,
+    /// Readers who can be exported or notified
+    pub reachable: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Segment {
+    pub id: Uuid,
+    pub name: String,
+    pub definition: SegmentDefinition,
+    pub created_at: String,
+    pub updated_at: String,
+    pub last_notified_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportedReader {
+    pub user_id: Uuid,
+    pub name: Option<String>,
+    pub email: String,
+}
+
+//=============================================================================
+// Cohort Query
+//=============================================================================
+
+/// Build the cohort query for `author_id` ($1). Rule books are bound from $2,
+/// followed by the finished-progress threshold (see `cohort_params`).
+fn cohort_query(definition: &SegmentDefinition, reachable_only: bool) -> String {
+    let threshold_param = definition.rules.len() + 2;
+
+    // Analytics is opt-out, so only an explicit opt-out excludes a reader
+    let mut filters = vec![
+        "NOT EXISTS (SELECT 1 FROM users.consents c WHERE c.user_id = r.user_id AND c.analytics = false)".to_string(),
+    ];
+    filters.extend(definition.rules.iter().enumerate().map(|(i, rule)| {
+        let book = i + 2;
+        let read = format!(
+            "SELECT 1 FROM discovery.reading_history h WHERE h.user_id = r.user_id AND h.book_id = ${}",
+            book
+        );
+        let purchased = format!(
+            "SELECT 1 FROM discovery.book_purchases p WHERE p.user_id = r.user_id AND p.book_id = ${}",
+            book
+        );
+        match rule.condition {
+            Condition::Started => format!("EXISTS ({})", read),
+            Condition::NotStarted => format!("NOT EXISTS ({})", read),
+            Condition::Finished => format!("EXISTS ({} AND h.progress >= ${})", read, threshold_param),
+            Condition::NotFinished => format!("NOT EXISTS ({} AND h.progress >= ${})", read, threshold_param),
+            Condition::Purchased => format!("EXISTS ({})", purchased),
+            Condition::NotPurchased => format!("NOT EXISTS ({})", purchased),
+        }
+    }));
+
+    if reachable_only {
+        filters.push(
+            "EXISTS (SELECT 1 FROM users.consents c WHERE c.user_id = r.user_id AND c.marketing_emails = true)".to_string(),
+        );
+    }
+
+    // Readers are anyone who has read or bought one of the author's books,
+    // never the author themselves
+    format!(
+        "WITH readers AS (
+             SELECT h.user_id FROM discovery.reading_history h
+             JOIN content.books b ON b.id = h.book_id WHERE b.author_id = $1
+             UNION
+             SELECT p.user_id FROM discovery.book_purchases p
+             JOIN content.books b ON b.id = p.book_id WHERE b.author_id = $1
+         )
+         SELECT r.user_id FROM readers r
+         WHERE r.user_id <> $1 AND {}",
+        filters.join(" AND ")
+    )
+}
+
+fn cohort_params(author_id: &Uuid, definition: &SegmentDefinition) -> Vec<ParameterValue> {
+    let finished_progress = variables::get("segment_finished_progress")
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .unwrap_or(DEFAULT_FINISHED_PROGRESS);
+
+    let mut params = vec![ParameterValue::Str(author_id.to_string())];
+    params.extend(definition.rules.iter().map(|rule| ParameterValue::Str(rule.book_id.to_string())));
+    params.push(ParameterValue::Floating64(finished_progress));
+    params
+}
+
+/// Every rule must reference one of the author's books
+fn validate(conn: &Connection, author_id: &Uuid, definition: &SegmentDefinition) -> Result<(), ServiceError> {
+    if definition.rules.is_empty() {
+        return Err(ServiceError::BadRequest("A segment needs at least one rule".into()));
+    }
+    if definition.rules.len() > MAX_RULES {
+        return Err(ServiceError::BadRequest(format!("A segment can have at most {} rules", MAX_RULES)));
+    }
+
+    let mut book_ids: Vec<String> = definition.rules.iter().map(|r| r.book_id.to_string()).collect();
+    book_ids.sort();
+    book_ids.dedup();
+
+    let query = "SELECT COUNT(*) FROM content.books WHERE author_id = $1 AND id::text = ANY(string_to_array($2, ','))";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(author_id.to_string()),
+        ParameterValue::Str(book_ids.join(",")),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let owned = rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0);
+
+    if owned != book_ids.len() as i64 {
+        return Err(ServiceError::NotFound("Book not found".into()));
+    }
+    Ok(())
+}
+
+pub fn estimate_size(conn: &Connection, author_id: &Uuid, definition: &SegmentDefinition) -> Result<SegmentSize, ServiceError> {
+    let params = cohort_params(author_id, definition);
+    let count = |reachable_only: bool| -> Result<i64, ServiceError> {
+        let cohort = cohort_query(definition, reachable_only);
+        let rows = conn.query(&format!("SELECT COUNT(*) FROM ({}) cohort", cohort), &params)
+            .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+        Ok(rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0))
+    };
+
+    Ok(SegmentSize { readers: count(false)?, reachable: count(true)? })
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /segments/estimate - Size of an unsaved definition
+pub fn estimate(conn: &Connection, author_id: &Uuid, definition: SegmentDefinition) -> Result<Response, ServiceError> {
+    validate(conn, author_id, &definition)?;
+    let size = estimate_size(conn, author_id, &definition)?;
+    crate::json_response(200, serde_json::json!({ "size": size }))
+}
+
+/// GET /segments - The author's saved segments
+pub fn list_segments(conn: &Connection, author_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT id, name, definition::text, created_at, updated_at, last_notified_at
+                 FROM discovery.segments WHERE author_id = $1
+                 ORDER BY created_at DESC";
+    let rows = conn.query(query, &[ParameterValue::Str(author_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let segments: Vec<Segment> = rows.rows.iter().filter_map(|row| {
+        Some(Segment {
+            id: Uuid::parse_str(&String::decode(&row[0]).ok()?).ok()?,
+            name: String::decode(&row[1]).unwrap_or_default(),
+            definition: serde_json::from_str(&String::decode(&row[2]).ok()?).ok()?,
+            created_at: String::decode(&row[3]).unwrap_or_default(),
+            updated_at: String::decode(&row[4]).unwrap_or_default(),
+            last_notified_at: String::decode(&row[5]).ok(),
+        })
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({ "segments": segments }))
+}
+
+/// GET /segments/:id - A saved segment with its current size
+pub fn get_segment(conn: &Connection, author_id: &Uuid, segment_id: &Uuid) -> Result<Response, ServiceError> {
+    let segment = load_segment(conn, author_id, segment_id)?;
+    let size = estimate_size(conn, author_id, &segment.definition)?;
+    crate::json_response(200, serde_json::json!({ "segment": segment, "size": size }))
+}
+
+/// POST /segments - Save a definition
+pub fn create_segment(conn: &Connection, author_id: &Uuid, body: SaveSegmentRequest) -> Result<Response, ServiceError> {
+    if body.name.trim().is_empty() {
+        return Err(ServiceError::BadRequest("name is required".into()));
+    }
+    validate(conn, author_id, &body.definition)?;
+
+    let id = Uuid::new_v4();
+    let now = Utc::now().to_rfc3339();
+    let insert = "INSERT INTO discovery.segments (id, author_id, name, definition, created_at, updated_at)
+                  VALUES ($1, $2, $3, $4::jsonb, $5, $5)";
+    conn.execute(insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(author_id.to_string()),
+        ParameterValue::Str(body.name.trim().to_string()),
+        ParameterValue::Str(serde_json::to_string(&body.definition).unwrap_or_else(|_| "{}".into())),
+        ParameterValue::Str(now),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    let size = estimate_size(conn, author_id, &body.definition)?;
+    crate::json_response(201, serde_json::json!({ "id": id, "name": body.name.trim(), "size": size }))
+}
+
+/// PUT /segments/:id - Rename or redefine a saved segment
+pub fn update_segment(conn: &Connection, author_id: &Uuid, segment_id: &Uuid, body: SaveSegmentRequest) -> Result<Response, ServiceError> {
+    if body.name.trim().is_empty() {
+        return Err(ServiceError::BadRequest("name is required".into()));
+    }
+    validate(conn, author_id, &body.definition)?;
+
+    let update = "UPDATE discovery.segments SET name = $3, definition = $4::jsonb, updated_at = $5
+                  WHERE id = $1 AND author_id = $2";
+    let updated = conn.execute(update, &[
+        ParameterValue::Str(segment_id.to_string()),
+        ParameterValue::Str(author_id.to_string()),
+        ParameterValue::Str(body.name.trim().to_string()),
+        ParameterValue::Str(serde_json::to_string(&body.definition).unwrap_or_else(|_| "{}".into())),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    if updated == 0 {
+        return Err(ServiceError::NotFound("Segment not found".into()));
+    }
+    get_segment(conn, author_id, segment_id)
+}
+
+/// DELETE /segments/:id
+pub fn delete_segment(conn: &Connection, author_id: &Uuid, segment_id: &Uuid) -> Result<Response, ServiceError> {
+    let deleted = conn.execute(
+        "DELETE FROM discovery.segments WHERE id = $1 AND author_id = $2",
+        &[
+            ParameterValue::Str(segment_id.to_string()),
+            ParameterValue::Str(author_id.to_string()),
+        ],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    if deleted == 0 {
+        return Err(ServiceError::NotFound("Segment not found".into()));
+    }
+    crate::json_response(200, serde_json::json!({ "deleted": true }))
+}
+
+/// POST /segments/:id/export - Contact details of reachable readers
+pub fn export_segment(conn: &Connection, author_id: &Uuid, segment_id: &Uuid) -> Result<Response, ServiceError> {
+    let segment = load_segment(conn, author_id, segment_id)?;
+    let cohort = cohort_query(&segment.definition, true);
+
+    let mut params = cohort_params(author_id, &segment.definition);
+    let limit_param = params.len() + 1;
+    params.push(ParameterValue::Int64(EXPORT_LIMIT));
+
+    let query = format!(
+        "SELECT u.id, u.name, u.email FROM users.users u
+         WHERE u.status = 'active' AND u.id IN ({})
+         ORDER BY u.id LIMIT ${}",
+        cohort, limit_param
+    );
+    let rows = conn.query(&query, &params)
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let readers: Vec<ExportedReader> = rows.rows.iter().filter_map(|row| {
+        Some(ExportedReader {
+            user_id: Uuid::parse_str(&String::decode(&row[0]).ok()?).ok()?,
+            name: String::decode(&row[1]).ok(),
+            email: String::decode(&row[2]).ok()?,
+        })
+    }).collect();
+
+    record_action(conn, segment_id, "export", readers.len() as i64)?;
+
+    crate::json_response(200, serde_json::json!({
+        "segment_id": segment_id,
+        "readers": readers,
+        "truncated": readers.len() as i64 >= EXPORT_LIMIT
+    }))
+}
+
+/// POST /segments/:id/notify - Send an in-app notification to reachable readers
+pub fn notify_segment(conn: &Connection, author_id: &Uuid, segment_id: &Uuid, body: NotifySegmentRequest) -> Result<Response, ServiceError> {
+    if body.title.trim().is_empty() || body.body.trim().is_empty() {
+        return Err(ServiceError::BadRequest("title and body are required".into()));
+    }
+    let segment = load_segment(conn, author_id, segment_id)?;
+
+    let cooldown_hours = variables::get("segment_notify_cooldown_hours")
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_NOTIFY_COOLDOWN_HOURS);
+    if let Some(ref last) = segment.last_notified_at {
+        let recent = chrono::DateTime::parse_from_rfc3339(last)
+            .map(|t| Utc::now().signed_duration_since(t).num_hours() < cooldown_hours)
+            .unwrap_or(false);
+        if recent {
+            return Err(ServiceError::TooManyRequests(format!(
+                "This segment was notified within the last {} hours",
+                cooldown_hours
+            )));
+        }
+    }
+
+    let cohort = cohort_query(&segment.definition, true);
+    let mut params = cohort_params(author_id, &segment.definition);
+    let first = params.len() + 1;
+
+    let mut data = body.data.clone();
+    data.insert("author_id".into(), serde_json::json!(author_id));
+    data.insert("segment_id".into(), serde_json::json!(segment_id));
+    params.extend([
+        ParameterValue::Str(body.title.clone()),
+        ParameterValue::Str(body.body.clone()),
+        ParameterValue::Str(serde_json::Value::Object(data).to_string()),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ]);
+
+    let insert = format!(
+        "INSERT INTO messaging.notifications (user_id, type, title, body, data, created_at)
+         SELECT u.id, 'author_message', ${}, ${}, ${}::jsonb, ${} FROM users.users u
+         WHERE u.status = 'active' AND u.id IN ({})",
+        first, first + 1, first + 2, first + 3, cohort
+    );
+    let recipients = conn.execute(&insert, &params)
+        .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))? as i64;
+
+    record_action(conn, segment_id, "notify", recipients)?;
+    conn.execute(
+        "UPDATE discovery.segments SET last_notified_at = $2 WHERE id = $1",
+        &[
+            ParameterValue::Str(segment_id.to_string()),
+            ParameterValue::Str(Utc::now().to_rfc3339()),
+        ],
+    ).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    crate::json_response(200, serde_json::json!({
+        "segment_id": segment_id,
+        "recipient_count": recipients
+    }))
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+fn load_segment(conn: &Connection, author_id: &Uuid, segment_id: &Uuid) -> Result<Segment, ServiceError> {
+    let query = "SELECT name, definition::text, created_at, updated_at, last_notified_at
+                 FROM discovery.segments WHERE id = $1 AND author_id = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(segment_id.to_string()),
+        ParameterValue::Str(author_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Segment not found".into()))?;
+    let definition = serde_json::from_str(&String::decode(&row[1]).unwrap_or_default())
+        .map_err(|e| ServiceError::Internal(format!("Invalid segment definition: {}", e)))?;
+
+    Ok(Segment {
+        id: *segment_id,
+        name: String::decode(&row[0]).unwrap_or_default(),
+        definition,
+        created_at: String::decode(&row[2]).unwrap_or_default(),
+        updated_at: String::decode(&row[3]).unwrap_or_default(),
+        last_notified_at: String::decode(&row[4]).ok(),
+    })
+}
+
+/// Audit trail of who was reached through a segment
+fn record_action(conn: &Connection, segment_id: &Uuid, action: &str, recipients: i64) -> Result<(), ServiceError> {
+    let insert = "INSERT INTO discovery.segment_actions (segment_id, action, recipient_count, created_at)
+                  VALUES ($1, $2, $3, $4)";
+    conn.execute(insert, &[
+        ParameterValue::Str(segment_id.to_string()),
+        ParameterValue::Str(action.to_string()),
+        ParameterValue::Int64(recipients),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    Ok(())
+}
@@ -0,0 +1,347 @@
+//! Search index snapshots
+//!
+//! Lets operators back up and recover the `authorworks-*` indices through
+//! the service instead of calling Elasticsearch directly. Snapshots go to an
+//! S3 snapshot repository (`es_snapshot_repository`, default
+//! `authorworks-s3`), which is registered on first use from
+//! `es_snapshot_bucket` and `es_snapshot_base_path` if the cluster does not
+//! already have it.
+//!
+//! Snapshot and restore both start in the background and answer 202; their
+//! progress is polled through the snapshot and restore status endpoints.
+//! Restoring closes the target indices first, as Elasticsearch requires.
+//! Index writes flushed while a restore runs fail and are retried by the
+//! indexing queue; anything written after the snapshot was taken shows up as
+//! drift in `/index/status` and needs reindexing.
+
+use crate::error::ServiceError;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::variables;
+use uuid::Uuid;
+
+pub const DEFAULT_REPOSITORY: &str = "authorworks-s3";
+const DEFAULT_BASE_PATH: &str = "discovery";
+
+/// Only the service's own indices can be snapshotted or restored
+const INDEX_PREFIX: &str = "authorworks-";
+const MAX_NAME_CHARS: usize = 100;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SnapshotRequest {
+    /// Defaults to `authorworks-<UTC timestamp>`
+    pub name: Option<String>,
+    /// Defaults to every `authorworks-*` index
+    pub indices: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RestoreRequest {
+    pub snapshot: String,
+    /// Defaults to every index in the snapshot
+    pub indices: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotSummary {
+    pub snapshot: String,
+    pub state: String,
+    pub indices: Vec<String>,
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    pub duration_ms: Option<i64>,
+    pub shards_total: i64,
+    pub shards_failed: i64,
+    pub requested_by: Option<String>,
+}
+
+struct SnapshotConfig {
+    repository: String,
+    bucket: Option<String>,
+    base_path: String,
+}
+
+fn get_config() -> SnapshotConfig {
+    SnapshotConfig {
+        repository: variables::get("es_snapshot_repository")
+            .ok()
+            .filter(|r| !r.is_empty())
+            .unwrap_or_else(|| DEFAULT_REPOSITORY.to_string()),
+        bucket: variables::get("es_snapshot_bucket").ok().filter(|b| !b.is_empty()),
+        base_path: variables::get("es_snapshot_base_path")
+            .ok()
+            .filter(|p| !p.is_empty())
+            .unwrap_or_else(|| DEFAULT_BASE_PATH.to_string()),
+    }
+}
+
+//=============================================================================
+// Snapshots
+//=============================================================================
+
+/// POST /admin/index/snapshot - Start a snapshot of the search indices
+pub fn create_snapshot(es_url: &str, user_id: &Uuid, body: SnapshotRequest) -> Result<Response, ServiceError> {
+    let config = get_config();
+    let name = match body.name.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+        Some(name) => validate_name(name)?,
+        None => format!("{}{}", INDEX_PREFIX, Utc::now().format("%Y%m%d-%H%M%S")),
+    };
+    let indices = match body.indices {
+        Some(indices) => validate_indices(indices)?,
+        None => vec![format!("{}*", INDEX_PREFIX)],
+    };
+
+    ensure_repository(es_url, &config)?;
+    es(es_url, "PUT", &format!("/_snapshot/{}/{}?wait_for_completion=false", config.repository, name), &serde_json::json!({
+        "indices": indices.join(","),
+        "include_global_state": false,
+        "metadata": { "requested_by": user_id }
+    }))?;
+
+    crate::json_response(202, serde_json::json!({
+        "snapshot": name,
+        "repository": config.repository,
+        "indices": indices,
+        "state": "IN_PROGRESS",
+        "status_url": format!("/admin/index/snapshots/{}", name)
+    }))
+}
+
+/// GET /admin/index/snapshots - Snapshots in the repository, newest first
+pub fn list_snapshots(es_url: &str) -> Result<Response, ServiceError> {
+    let config = get_config();
+    let response = match es(es_url, "GET", &format!("/_snapshot/{}/_all", config.repository), &serde_json::json!({})) {
+        Ok(response) => response,
+        // Nothing has been snapshotted yet
+        Err(ServiceError::NotFound(_)) => serde_json::json!({ "snapshots": [] }),
+        Err(e) => return Err(e),
+    };
+
+    let mut snapshots: Vec<SnapshotSummary> = response["snapshots"].as_array()
+        .map(|list| list.iter().map(summarize).collect())
+        .unwrap_or_default();
+    snapshots.sort_by(|a, b| b.start_time.cmp(&a.start_time));
+
+    crate::json_response(200, serde_json::json!({
+        "repository": config.repository,
+        "snapshots": snapshots
+    }))
+}
+
+/// GET /admin/index/snapshots/:name - A snapshot's state and byte progress
+pub fn get_snapshot(es_url: &str, name: &str) -> Result<Response, ServiceError> {
+    let config = get_config();
+    let name = validate_name(name)?;
+    let info = es(es_url, "GET", &format!("/_snapshot/{}/{}", config.repository, name), &serde_json::json!({}))?;
+    let snapshot = info["snapshots"].get(0)
+        .ok_or_else(|| ServiceError::NotFound(format!("Snapshot '{}' not found", name)))?;
+
+    // Byte-level progress is only reported while the snapshot is running
+    let status = es(es_url, "GET", &format!("/_snapshot/{}/{}/_status", config.repository, name), &serde_json::json!({}))
+        .ok()
+        .and_then(|s| s["snapshots"].get(0).cloned())
+        .unwrap_or_default();
+    let total_bytes = status["stats"]["total"]["size_in_bytes"].as_i64();
+    let processed_bytes = status["stats"]["processed"]["size_in_bytes"].as_i64();
+
+    crate::json_response(200, serde_json::json!({
+        "repository": config.repository,
+        "snapshot": summarize(snapshot),
+        "shards": status["shards_stats"],
+        "total_bytes": total_bytes,
+        "processed_bytes": processed_bytes,
+        "failures": snapshot["failures"]
+    }))
+}
+
+//=============================================================================
+// Restore
+//=============================================================================
+
+/// POST /admin/index/restore - Close the target indices and restore them
+/// from a completed snapshot
+pub fn restore(es_url: &str, body: RestoreRequest) -> Result<Response, ServiceError> {
+    let config = get_config();
+    let name = validate_name(body.snapshot.trim())?;
+    let info = es(es_url, "GET", &format!("/_snapshot/{}/{}", config.repository, name), &serde_json::json!({}))?;
+    let snapshot = info["snapshots"].get(0)
+        .map(summarize)
+        .ok_or_else(|| ServiceError::NotFound(format!("Snapshot '{}' not found", name)))?;
+    if snapshot.state != "SUCCESS" {
+        return Err(ServiceError::BadRequest(format!(
+            "Snapshot '{}' is {}; only completed snapshots can be restored", name, snapshot.state
+        )));
+    }
+
+    let available: Vec<String> = snapshot.indices.into_iter().filter(|i| i.starts_with(INDEX_PREFIX)).collect();
+    let indices = match body.indices {
+        Some(requested) => {
+            let requested = validate_indices(requested)?;
+            if let Some(missing) = requested.iter().find(|i| !available.contains(i)) {
+                return Err(ServiceError::BadRequest(format!("Snapshot '{}' does not contain index '{}'", name, missing)));
+            }
+            requested
+        }
+        None => available,
+    };
+    if indices.is_empty() {
+        return Err(ServiceError::BadRequest(format!("Snapshot '{}' holds no search indices", name)));
+    }
+
+    // Open indices cannot be restored over; missing ones are simply created
+    let existing: Vec<String> = es(es_url, "GET", &format!("/_cat/indices/{}*?format=json&h=index", INDEX_PREFIX), &serde_json::json!({}))
+        .ok()
+        .and_then(|v| v.as_array().cloned())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|row| row["index"].as_str().map(String::from))
+        .collect();
+    let to_close: Vec<&String> = indices.iter().filter(|i| existing.contains(i)).collect();
+    for index in &to_close {
+        es(es_url, "POST", &format!("/{}/_close", index), &serde_json::json!({}))?;
+    }
+
+    es(es_url, "POST", &format!("/_snapshot/{}/{}/_restore?wait_for_completion=false", config.repository, name), &serde_json::json!({
+        "indices": indices.join(","),
+        "include_global_state": false,
+        "include_aliases": true
+    }))?;
+
+    crate::json_response(202, serde_json::json!({
+        "snapshot": name,
+        "repository": config.repository,
+        "indices": indices,
+        "closed": to_close,
+        "status_url": format!("/admin/index/restore?indices={}", indices.join(","))
+    }))
+}
+
+/// GET /admin/index/restore?indices= - Recovery progress of indices being
+/// restored from a snapshot
+pub fn restore_status(es_url: &str, indices: Option<&str>) -> Result<Response, ServiceError> {
+    let target = match indices.filter(|i| !i.is_empty()) {
+        Some(list) => validate_indices(list.split(',').map(|i| i.trim().to_string()).collect())?.join(","),
+        None => format!("{}*", INDEX_PREFIX),
+    };
+    let recovery = es(es_url, "GET", &format!("/{}/_recovery", target), &serde_json::json!({}))?;
+
+    let mut restoring = Vec::new();
+    if let Some(map) = recovery.as_object() {
+        for (index, detail) in map {
+            let shards: Vec<&serde_json::Value> = detail["shards"].as_array()
+                .map(|s| s.iter().filter(|shard| shard["type"] == "SNAPSHOT").collect())
+                .unwrap_or_default();
+            if shards.is_empty() {
+                continue;
+            }
+            let done = shards.iter().filter(|shard| shard["stage"] == "DONE").count();
+            let total_bytes: i64 = shards.iter().filter_map(|s| s["index"]["size"]["total_in_bytes"].as_i64()).sum();
+            let recovered_bytes: i64 = shards.iter().filter_map(|s| s["index"]["size"]["recovered_in_bytes"].as_i64()).sum();
+            restoring.push(serde_json::json!({
+                "index": index,
+                "snapshot": shards[0]["source"]["snapshot"],
+                "shards_total": shards.len(),
+                "shards_done": done,
+                "total_bytes": total_bytes,
+                "recovered_bytes": recovered_bytes,
+                "complete": done == shards.len()
+            }));
+        }
+    }
+    restoring.sort_by(|a, b| a["index"].as_str().cmp(&b["index"].as_str()));
+
+    let complete = restoring.iter().all(|r| r["complete"] == true);
+    crate::json_response(200, serde_json::json!({
+        "indices": restoring,
+        "complete": complete
+    }))
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+/// Register the S3 repository unless the cluster already has it
+fn ensure_repository(es_url: &str, config: &SnapshotConfig) -> Result<(), ServiceError> {
+    match es(es_url, "GET", &format!("/_snapshot/{}", config.repository), &serde_json::json!({})) {
+        Ok(_) => return Ok(()),
+        Err(ServiceError::NotFound(_)) => {}
+        Err(e) => return Err(e),
+    }
+    let bucket = config.bucket.as_deref().ok_or_else(|| ServiceError::Internal(format!(
+        "Snapshot repository '{}' is not registered and es_snapshot_bucket is not configured", config.repository
+    )))?;
+    es(es_url, "PUT", &format!("/_snapshot/{}", config.repository), &serde_json::json!({
+        "type": "s3",
+        "settings": {
+            "bucket": bucket,
+            "base_path": config.base_path
+        }
+    }))?;
+    Ok(())
+}
+
+/// Elasticsearch request that reports missing snapshots and repositories as 404
+fn es(es_url: &str, method: &str, path: &str, body: &serde_json::Value) -> Result<serde_json::Value, ServiceError> {
+    match crate::elasticsearch_request(es_url, method, path, body) {
+        Err(ServiceError::Internal(message))
+            if message.contains("snapshot_missing_exception") || message.contains("repository_missing_exception") =>
+        {
+            Err(ServiceError::NotFound(message))
+        }
+        other => other,
+    }
+}
+
+fn summarize(snapshot: &serde_json::Value) -> SnapshotSummary {
+    SnapshotSummary {
+        snapshot: snapshot["snapshot"].as_str().unwrap_or_default().to_string(),
+        state: snapshot["state"].as_str().unwrap_or("UNKNOWN").to_string(),
+        indices: snapshot["indices"].as_array()
+            .map(|list| list.iter().filter_map(|i| i.as_str().map(String::from)).collect())
+            .unwrap_or_default(),
+        start_time: snapshot["start_time"].as_str().map(String::from),
+        end_time: snapshot["end_time"].as_str().map(String::from),
+        duration_ms: snapshot["duration_in_millis"].as_i64(),
+        shards_total: snapshot["shards"]["total"].as_i64().unwrap_or(0),
+        shards_failed: snapshot["shards"]["failed"].as_i64().unwrap_or(0),
+        requested_by: snapshot["metadata"]["requested_by"].as_str().map(String::from),
+    }
+}
+
+/// Snapshot names share Elasticsearch's index naming rules
+fn validate_name(name: &str) -> Result<String, ServiceError> {
+    let valid = !name.is_empty()
+        && name.chars().count() <= MAX_NAME_CHARS
+        && !name.starts_with(['-', '_', '.'])
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(ServiceError::BadRequest(format!(
+            "Snapshot names must be 1-{} lowercase letters, digits, '-', '_' or '.', not starting with a symbol",
+            MAX_NAME_CHARS
+        )));
+    }
+    Ok(name.to_string())
+}
+
+fn validate_indices(indices: Vec<String>) -> Result<Vec<String>, ServiceError> {
+    let mut valid = Vec::new();
+    for index in indices.into_iter().map(|i| i.trim().to_string()).filter(|i| !i.is_empty()) {
+        let suffix = index.strip_prefix(INDEX_PREFIX).unwrap_or_default();
+        if suffix.is_empty() || !suffix.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_' | '*')) {
+            return Err(ServiceError::BadRequest(format!("'{}' is not an {}* index", index, INDEX_PREFIX)));
+        }
+        if !valid.contains(&index) {
+            valid.push(index);
+        }
+    }
+    if valid.is_empty() {
+        return Err(ServiceError::BadRequest("indices must not be empty".into()));
+    }
+    Ok(valid)
+}
@@ -0,0 +1,114 @@
+//! Search spell correction
+//!
+//! `search_all` and `search_books` attach an Elasticsearch phrase suggester
+//! to their query. The `correct` query parameter decides what happens with
+//! its best suggestion:
+//! - `suggest` (default) returns it as `did_you_mean`
+//! - `auto` also re-runs the search with the suggestion when the original
+//!   query found nothing, reporting it as `corrected_query`
+//! - `off` skips the suggester
+
+use crate::error::ServiceError;
+
+const SUGGESTION_NAME: &str = "did_you_mean";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CorrectionMode {
+    Auto,
+    Suggest,
+    Off,
+}
+
+impl CorrectionMode {
+    pub fn parse(value: Option<&str>) -> Result<Self, ServiceError> {
+        match value.unwrap_or("suggest") {
+            "auto" => Ok(Self::Auto),
+            "suggest" => Ok(Self::Suggest),
+            "off" => Ok(Self::Off),
+            other => Err(ServiceError::BadRequest(format!(
+                "Invalid correct value '{}'; expected auto, suggest or off", other
+            ))),
+        }
+    }
+}
+
+pub struct CorrectedSearch {
+    /// Elasticsearch response for the query that was finally run
+    pub response: serde_json::Value,
+    pub did_you_mean: Option<String>,
+    /// Set when `auto` retried with the suggestion
+    pub corrected_query: Option<String>,
+}
+
+/// Run a search with spell correction. `run` executes the search for a query
+/// text, adding the given suggester block to the request body when present.
+pub fn search_with_correction<F>(
+    mode: CorrectionMode,
+    query: &str,
+    field: &str,
+    run: F,
+) -> Result<CorrectedSearch, ServiceError>
+where
+    F: Fn(&str, Option<serde_json::Value>) -> Result<serde_json::Value, ServiceError>,
+{
+    let suggest = (mode != CorrectionMode::Off).then(|| suggester(query, field));
+    let response = run(query, suggest)?;
+    let did_you_mean = best_suggestion(&response, query);
+
+    if mode == CorrectionMode::Auto && total_hits(&response) == 0 {
+        if let Some(corrected) = did_you_mean.clone() {
+            let response = run(&corrected, None)?;
+            return Ok(CorrectedSearch { response, did_you_mean, corrected_query: Some(corrected) });
+        }
+    }
+
+    Ok(CorrectedSearch { response, did_you_mean, corrected_query: None })
+}
+
+/// Phrase suggester over `field`. The collate query drops suggestions that
+/// would not match any document.
+fn suggester(query: &str, field: &str) -> serde_json::Value {
+    serde_json::json!({
+        "text": query,
+        SUGGESTION_NAME: {
+            "phrase": {
+                "field": field,
+                "size": 1,
+                "max_errors": 2,
+                "direct_generator": [{
+                    "field": field,
+                    "suggest_mode": "always",
+                    "min_word_length": 3
+                }],
+                "collate": {
+                    "query": {
+                        "source": { "match": { field: "{{suggestion}}" } }
+                    },
+                    "prune": true
+                }
+            }
+        }
+    })
+}
+
+fn best_suggestion(response: &serde_json::Value, query: &str) -> Option<String> {
+    response.get("suggest")?
+        .get(SUGGESTION_NAME)?
+        .as_array()?
+        .first()?
+        .get("options")?
+        .as_array()?
+        .iter()
+        .filter(|option| option.get("collate_match").and_then(|m| m.as_bool()).unwrap_or(true))
+        .filter_map(|option| option.get("text").and_then(|t| t.as_str()))
+        .find(|text| !text.eq_ignore_ascii_case(query.trim()))
+        .map(|text| text.to_string())
+}
+
+fn total_hits(response: &serde_json::Value) -> i64 {
+    response.get("hits")
+        .and_then(|h| h.get("total"))
+        .and_then(|t| t.get("value"))
+        .and_then(|v| v.as_i64())
+        .unwrap_or(0)
+}
@@ -0,0 +1,79 @@
+//! Trending aggregates
+//!
+//! Seven-day read activity per book, kept in `discovery.trending_books` so
+//! `/trending` does not aggregate `discovery.reading_history` on every call.
+//! A periodic rebuild recomputes every row; events refresh single books in
+//! between.
+
+use crate::error::ServiceError;
+use serde::Serialize;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::Utc;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+pub struct TrendingRebuildSummary {
+    pub books: u64,
+    pub removed: u64,
+    pub refreshed_at: String,
+}
+
+/// Recompute activity for every book read in the last week
+pub fn rebuild(conn: &Connection) -> Result<TrendingRebuildSummary, ServiceError> {
+    let refreshed_at = Utc::now().to_rfc3339();
+
+    let upsert = "INSERT INTO discovery.trending_books (book_id, activity, refreshed_at)
+                  SELECT book_id, COUNT(*), $1 FROM discovery.reading_history
+                  WHERE created_at > NOW() - INTERVAL '7 days'
+                  GROUP BY book_id
+                  ON CONFLICT (book_id) DO UPDATE SET
+                  activity = EXCLUDED.activity,
+                  refreshed_at = EXCLUDED.refreshed_at";
+    let books = conn.execute(upsert, &[ParameterValue::Str(refreshed_at.clone())])
+        .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    let delete = "DELETE FROM discovery.trending_books WHERE refreshed_at < $1";
+    let removed = conn.execute(delete, &[ParameterValue::Str(refreshed_at.clone())])
+        .map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    Ok(TrendingRebuildSummary { books, removed, refreshed_at })
+}
+
+/// Recompute activity for one book
+pub fn refresh_book(conn: &Connection, book_id: &Uuid) -> Result<i64, ServiceError> {
+    let upsert = "INSERT INTO discovery.trending_books (book_id, activity, refreshed_at)
+                  SELECT $1::uuid, COUNT(*), NOW() FROM discovery.reading_history
+                  WHERE book_id = $1 AND created_at > NOW() - INTERVAL '7 days'
+                  ON CONFLICT (book_id) DO UPDATE SET
+                  activity = EXCLUDED.activity,
+                  refreshed_at = EXCLUDED.refreshed_at
+                  RETURNING activity";
+    let rows = conn.query(upsert, &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Upsert failed: {}", e)))?;
+    Ok(rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0))
+}
+
+pub fn remove_book(conn: &Connection, book_id: &Uuid) -> Result<u64, ServiceError> {
+    conn.execute("DELETE FROM discovery.trending_books WHERE book_id = $1", &[ParameterValue::Str(book_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))
+}
+
+/// Most active book IDs, or `None` before the first rebuild has run
+pub fn top_books(conn: &Connection, limit: i32) -> Result<Option<Vec<String>>, ServiceError> {
+    let populated = conn.query("SELECT EXISTS (SELECT 1 FROM discovery.trending_books)", &[])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?
+        .rows.first()
+        .map(|row| bool::decode(&row[0]).unwrap_or(false))
+        .unwrap_or(false);
+    if !populated {
+        return Ok(None);
+    }
+
+    let query = "SELECT book_id::text FROM discovery.trending_books
+                 WHERE activity > 0
+                 ORDER BY activity DESC
+                 LIMIT $1";
+    let rows = conn.query(query, &[ParameterValue::Int32(limit)])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    Ok(Some(rows.rows.iter().filter_map(|row| String::decode(&row[0]).ok()).collect()))
+}
@@ -0,0 +1,155 @@
+//! Workspace search
+//!
+//! One search box over everything a user owns or takes part in, whatever its
+//! publication status: books, chapters, scene notes, editor comments and
+//! conversation messages. The Elasticsearch indices only hold what is meant
+//! to be public (and skip books excluded from indexing), so this runs on
+//! Postgres full-text search instead, scoped to the caller in every branch.
+//!
+//! Private vault items are client-encrypted and deliberately not searchable.
+
+use crate::error::ServiceError;
+use serde::Serialize;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+pub const MAX_PAGE_SIZE: i64 = 50;
+
+pub const TYPES: [&str; 5] = ["book", "chapter", "note", "comment", "message"];
+
+/// Every searchable row the caller may see, as (type, id, title, body,
+/// parent_id, updated_at, doc). `$1` is the caller. The chapter, comment and
+/// message `doc` expressions match the GIN indexes from migration 023.
+const MATCHES: &str = "
+    SELECT 'book' AS type, b.id::text AS id, b.title AS title,
+           COALESCE(b.description, '') AS body, NULL::text AS parent_id, b.updated_at,
+           to_tsvector('simple', b.title || ' ' || COALESCE(b.description, '')) AS doc
+    FROM content.books b
+    WHERE b.author_id = $1
+    UNION ALL
+    SELECT 'chapter', c.id::text, c.title, COALESCE(c.content, ''), c.book_id::text, c.updated_at,
+           to_tsvector('simple', c.title || ' ' || COALESCE(c.content, ''))
+    FROM content.chapters c
+    JOIN content.books b ON b.id = c.book_id
+    WHERE b.author_id = $1
+    UNION ALL
+    SELECT 'note', s.id::text, COALESCE(s.title, c.title), s.notes, s.chapter_id::text, s.updated_at,
+           to_tsvector('simple', s.notes)
+    FROM content.scenes s
+    JOIN content.chapters c ON c.id = s.chapter_id
+    JOIN content.books b ON b.id = c.book_id
+    WHERE b.author_id = $1 AND s.notes IS NOT NULL
+    UNION ALL
+    SELECT 'comment', cm.id::text, c.title, cm.content, cm.document_id::text, cm.created_at,
+           to_tsvector('simple', cm.content)
+    FROM editor.comments cm
+    JOIN content.chapters c ON c.id = cm.document_id
+    JOIN content.books b ON b.id = c.book_id
+    WHERE b.author_id = $1 OR cm.user_id = $1
+    UNION ALL
+    SELECT 'message', m.id::text, COALESCE(cv.name, 'Conversation'), m.body, m.conversation_id::text, m.created_at,
+           to_tsvector('simple', m.body)
+    FROM messaging.messages m
+    JOIN messaging.conversations cv ON cv.id = m.conversation_id
+    JOIN messaging.conversation_members mb ON mb.conversation_id = m.conversation_id AND mb.user_id = $1";
+
+#[derive(Debug, Serialize)]
+pub struct WorkspaceResult {
+    #[serde(rename = "type")]
+    pub result_type: String,
+    pub id: String,
+    pub title: String,
+    pub snippet: String,
+    /// Book for chapters, chapter for notes and comments, conversation for messages
+    pub parent_id: Option<String>,
+    pub updated_at: String,
+    pub score: f64,
+}
+
+/// GET /search/mine - Ranked matches across the caller's own content
+pub fn search(
+    conn: &Connection,
+    user_id: &Uuid,
+    query: &str,
+    types: &[String],
+    from: i64,
+    size: i64,
+) -> Result<Response, ServiceError> {
+    if query.trim().is_empty() {
+        return Err(ServiceError::BadRequest("Query parameter 'q' is required".into()));
+    }
+    if let Some(unknown) = types.iter().find(|t| !TYPES.contains(&t.as_str())) {
+        return Err(ServiceError::BadRequest(format!(
+            "Unknown type '{}'; expected one of: {}", unknown, TYPES.join(", ")
+        )));
+    }
+    let size = size.clamp(1, MAX_PAGE_SIZE);
+    let from = from.max(0);
+
+    // Facets always cover every type so the client can show counts for tabs
+    let facet_query = format!(
+        "WITH q AS (SELECT websearch_to_tsquery('simple', $2) AS query)
+         SELECT m.type, COUNT(*) FROM ({}) m, q WHERE m.doc @@ q.query GROUP BY m.type",
+        MATCHES
+    );
+    let facet_rows = conn.query(&facet_query, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(query.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut facets = serde_json::Map::new();
+    for t in TYPES {
+        facets.insert(t.to_string(), serde_json::json!(0));
+    }
+    for row in &facet_rows.rows {
+        let t = String::decode(&row[0]).unwrap_or_default();
+        facets.insert(t, serde_json::json!(i64::decode(&row[1]).unwrap_or(0)));
+    }
+
+    let results_query = format!(
+        "WITH q AS (SELECT websearch_to_tsquery('simple', $2) AS query)
+         SELECT m.type, m.id, m.title,
+                ts_headline('simple', m.body, q.query, 'MaxFragments=1, MaxWords=30, MinWords=10'),
+                m.parent_id, m.updated_at::text, ts_rank(m.doc, q.query)::float8 AS score
+         FROM ({}) m, q
+         WHERE m.doc @@ q.query
+           AND ($3 = '' OR m.type = ANY(string_to_array($3, ',')))
+         ORDER BY score DESC, m.updated_at DESC
+         LIMIT $4 OFFSET $5",
+        MATCHES
+    );
+    let rows = conn.query(&results_query, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(query.to_string()),
+        ParameterValue::Str(types.join(",")),
+        ParameterValue::Int64(size),
+        ParameterValue::Int64(from),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let results: Vec<WorkspaceResult> = rows.rows.iter().map(|row| {
+        WorkspaceResult {
+            result_type: String::decode(&row[0]).unwrap_or_default(),
+            id: String::decode(&row[1]).unwrap_or_default(),
+            title: String::decode(&row[2]).unwrap_or_default(),
+            snippet: String::decode(&row[3]).unwrap_or_default(),
+            parent_id: String::decode(&row[4]).ok(),
+            updated_at: String::decode(&row[5]).unwrap_or_default(),
+            score: f64::decode(&row[6]).unwrap_or(0.0),
+        }
+    }).collect();
+
+    let total: i64 = if types.is_empty() {
+        facets.values().filter_map(|v| v.as_i64()).sum()
+    } else {
+        types.iter().filter_map(|t| facets.get(t).and_then(|v| v.as_i64())).sum()
+    };
+
+    crate::json_response(200, serde_json::json!({
+        "results": results,
+        "facets": facets,
+        "total": total,
+        "from": from,
+        "size": size
+    }))
+}
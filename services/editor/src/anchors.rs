@@ -0,0 +1,116 @@
+//! Comment anchoring
+//!
+//! Comment ranges are UTF-16 offsets into the document's plain text, so each
+//! applied operation moves them the way it moves the text: an insert before a
+//! comment shifts it, one inside widens it, and a delete pulls it in. Text
+//! typed at either edge of a range stays outside it. A comment whose whole
+//! range is deleted is marked orphaned; its positions stop moving, but it
+//! keeps its block and stays listed so the thread is not lost.
+//!
+//! Reverts replace the text wholesale, so they only clamp ranges to the
+//! restored document's length.
+
+use crate::error::ServiceError;
+use crate::models::Operation;
+use crate::text;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Anchor {
+    start: i32,
+    end: i32,
+    orphaned: bool,
+}
+
+/// Move every live comment on the document through `op`, which has already
+/// been applied and is in the form it was logged
+pub fn transform_comments(conn: &Connection, document_id: &Uuid, op: &Operation) -> Result<(), ServiceError> {
+    if let Operation::Revert { .. } = op {
+        return Ok(());
+    }
+
+    let query = "SELECT id, position_start, position_end FROM editor.comments
+                 WHERE document_id = $1 AND orphaned = false";
+    let rows = conn.query(query, &[ParameterValue::Str(document_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let update = "UPDATE editor.comments
+                  SET position_start = $2, position_end = $3, orphaned = $4,
+                      orphaned_at = CASE WHEN $4 THEN NOW() ELSE orphaned_at END
+                  WHERE id = $1";
+    for row in &rows.rows {
+        let anchor = Anchor {
+            start: i32::decode(&row[1]).unwrap_or(0),
+            end: i32::decode(&row[2]).unwrap_or(0),
+            orphaned: false,
+        };
+        let moved = transform(anchor, op);
+        if moved == anchor {
+            continue;
+        }
+        conn.execute(update, &[
+            ParameterValue::Str(String::decode(&row[0]).unwrap_or_default()),
+            ParameterValue::Int32(moved.start),
+            ParameterValue::Int32(moved.end),
+            ParameterValue::Boolean(moved.orphaned),
+        ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    }
+    Ok(())
+}
+
+/// Pull live comment ranges back inside a document `length` units long
+pub fn clamp_comments(conn: &Connection, document_id: &Uuid, length: i32) -> Result<(), ServiceError> {
+    let update = "UPDATE editor.comments
+                  SET position_start = LEAST(position_start, $2), position_end = LEAST(position_end, $2)
+                  WHERE document_id = $1 AND orphaned = false AND position_end > $2";
+    conn.execute(update, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Int32(length),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+fn transform(anchor: Anchor, op: &Operation) -> Anchor {
+    if anchor.orphaned {
+        return anchor;
+    }
+    match op {
+        Operation::Insert { position, text } => insert(anchor, *position, text::len16(text) as i32),
+        Operation::Delete { position, length } => delete(anchor, *position, *length),
+        Operation::Replace { position, length, text } => {
+            insert(delete(anchor, *position, *length), *position, text::len16(text) as i32)
+        }
+        Operation::Delta { plain, .. } => plain.iter().fold(anchor, transform),
+        Operation::Revert { .. } => anchor,
+    }
+}
+
+fn insert(anchor: Anchor, position: i32, length: i32) -> Anchor {
+    if anchor.orphaned || length <= 0 {
+        return anchor;
+    }
+    let start = if position <= anchor.start { anchor.start + length } else { anchor.start };
+    let end = if position < anchor.end { anchor.end + length } else { anchor.end };
+    Anchor { start, end: end.max(start), orphaned: false }
+}
+
+fn delete(anchor: Anchor, position: i32, length: i32) -> Anchor {
+    if anchor.orphaned || length <= 0 {
+        return anchor;
+    }
+    let deleted_end = position + length;
+    if anchor.start < anchor.end && position <= anchor.start && anchor.end <= deleted_end {
+        return Anchor { orphaned: true, ..anchor };
+    }
+    let map = |offset: i32| {
+        if offset <= position {
+            offset
+        } else if offset >= deleted_end {
+            offset - length
+        } else {
+            position
+        }
+    };
+    Anchor { start: map(anchor.start), end: map(anchor.end), orphaned: false }
+}
@@ -0,0 +1,338 @@
+//! Inline AI assist
+//!
+//! Rewrites, shortens, grammar-fixes or continues a selection using an
+//! OpenAI-compatible `/chat/completions` endpoint (`assist_api_url`, so an
+//! Ollama `/v1` base works too). The selection is mapped from the caller's
+//! `base_version` to the current document before its text is read. When
+//! `apply` is set the candidate goes through the same transform-and-commit
+//! path as undo, recorded with `ai_assistant` attribution so history shows it
+//! as the assistant's edit made on the caller's behalf.
+
+use crate::blocks;
+use crate::error::ServiceError;
+use crate::models::{AssistInstruction, AssistRequest, Operation};
+use crate::ot;
+use crate::text;
+use crate::undo;
+use spin_sdk::outbound_http::{self, Method as HttpMethod, Request as OutboundRequest};
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use std::ops::Range;
+use uuid::Uuid;
+
+/// Stored in `editor.operations.attribution` for applied suggestions
+pub const ATTRIBUTION: &str = "ai_assistant";
+
+/// Shown in place of the user's name for attributed operations
+pub const ATTRIBUTION_LABEL: &str = "AI assistant";
+
+/// Larger selections should be split by the client
+const MAX_SELECTION_BYTES: usize = 16 * 1024;
+
+/// How much text before the cursor is sent as context for `continue`
+const CONTINUE_CONTEXT_BYTES: usize = 4 * 1024;
+
+const CONTINUE_WORDS: usize = 150;
+const DEFAULT_MODEL: &str = "gpt-4o-mini";
+
+const SYSTEM_PROMPT: &str = "You are an editing assistant inside a book manuscript editor. \
+    Reply with only the resulting text: no preamble, no quotation marks, no commentary.";
+
+/// POST /documents/:id/assist - Generate a candidate and optionally apply it
+pub fn assist(
+    conn: &Connection,
+    document_id: &Uuid,
+    user_id: &Uuid,
+    body: AssistRequest,
+) -> Result<serde_json::Value, ServiceError> {
+    ensure_ai_enabled(conn, document_id)?;
+
+    let (content, version) = load_document(conn, document_id, body.base_version)?;
+    let (start, end, bytes) = current_selection(conn, document_id, &body, &content)?;
+
+    let passage = match body.instruction {
+        AssistInstruction::Continue => context_before(&content, bytes.end),
+        _ => &content[bytes.clone()],
+    };
+    if passage.trim().is_empty() {
+        return Err(ServiceError::BadRequest("Selection is empty".into()));
+    }
+
+    let cost = estimate_cost(body.instruction, passage);
+    if !has_credits(user_id, cost)? {
+        return Err(ServiceError::PaymentRequired(format!("AI assist requires {} credits", cost)));
+    }
+
+    let mut candidate = complete(&prompt(body.instruction, passage, body.guidance.as_deref()))?;
+    if body.instruction == AssistInstruction::Continue && needs_separator(&content[..bytes.end], &candidate) {
+        candidate.insert(0, ' ');
+    }
+
+    if !consume_credits(user_id, cost, document_id, body.instruction)? {
+        return Err(ServiceError::PaymentRequired(format!("AI assist requires {} credits", cost)));
+    }
+
+    let mut response = serde_json::json!({
+        "instruction": body.instruction,
+        "candidate": candidate,
+        "selection": { "start": start, "end": end },
+        "version": version,
+        "credits_used": cost,
+        "applied": false
+    });
+
+    if body.apply {
+        let op = match body.instruction {
+            AssistInstruction::Continue => Operation::Insert { position: end as i32, text: candidate },
+            _ => Operation::Replace { position: start as i32, length: (end - start) as i32, text: candidate },
+        };
+        // Edits that landed while the model was generating are transformed over
+        let result = undo::commit_transformed(conn, document_id, user_id, op, version, None, None, Some(ATTRIBUTION))?;
+        crate::compaction::maybe_compact(conn, document_id, result.version);
+
+        response["applied"] = serde_json::json!(true);
+        response["operation_id"] = serde_json::json!(result.operation_id);
+        response["operation"] = serde_json::json!(result.operation);
+        response["version"] = serde_json::json!(result.version);
+        response["blocks"] = serde_json::json!(blocks::blocks_with_ids(&result.content, &result.block_ids));
+        response["content"] = serde_json::json!(result.content);
+        response["rich_content"] = serde_json::json!(result.rich_content);
+    }
+
+    Ok(response)
+}
+
+//=============================================================================
+// Selection
+//=============================================================================
+
+fn load_document(conn: &Connection, document_id: &Uuid, base_version: i64) -> Result<(String, i64), ServiceError> {
+    let query = "SELECT content, version, compacted_version FROM editor.documents WHERE id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(document_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Document not found".into()))?;
+
+    let content = String::decode(&row[0]).unwrap_or_default();
+    let version = i64::decode(&row[1]).unwrap_or(0);
+    let compacted_version = i64::decode(&row[2]).unwrap_or(0);
+
+    if base_version < compacted_version {
+        return Err(ServiceError::Conflict(format!(
+            "base_version {} predates compacted history (version {}); reload the document",
+            base_version, compacted_version
+        )));
+    }
+    if base_version > version {
+        return Err(ServiceError::BadRequest(format!("base_version {} is ahead of the document", base_version)));
+    }
+    Ok((content, version))
+}
+
+/// Map the selection from `base_version` onto the current content, as UTF-16
+/// offsets and the byte range they cover
+fn current_selection(
+    conn: &Connection,
+    document_id: &Uuid,
+    body: &AssistRequest,
+    content: &str,
+) -> Result<(usize, usize, Range<usize>), ServiceError> {
+    let (start, end) = (body.selection.start, body.selection.end);
+    if start < 0 || end < start {
+        return Err(ServiceError::BadRequest("Invalid selection range".into()));
+    }
+
+    // Track the range as a delete so concurrent edits shift and shrink it
+    let mut range = Operation::Delete { position: start, length: end - start };
+    let query = "SELECT operation FROM editor.operations
+                 WHERE document_id = $1 AND version > $2 ORDER BY version ASC";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Int64(body.base_version),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    for row in &rows.rows {
+        let later: Operation = serde_json::from_str(&String::decode(&row[0]).unwrap_or_default())
+            .map_err(|e| ServiceError::Internal(format!("Parse failed: {}", e)))?;
+        if let Operation::Revert { .. } = later {
+            return Err(ServiceError::Conflict("Document was reverted since base_version; reload the document".into()));
+        }
+        range = ot::transform_against(&range, &later);
+    }
+
+    let (position, length) = match range {
+        Operation::Delete { position, length } => (position.max(0), length.max(0)),
+        _ => return Err(ServiceError::Internal("Selection could not be mapped".into())),
+    };
+    let bytes = text::byte_range(content, position, length)
+        .map_err(|_| ServiceError::BadRequest("Selection is out of bounds".into()))?;
+    if bytes.len() > MAX_SELECTION_BYTES {
+        return Err(ServiceError::PayloadTooLarge(format!(
+            "Selection exceeds {} KB; assist a smaller passage", MAX_SELECTION_BYTES / 1024
+        )));
+    }
+    Ok((position as usize, (position + length) as usize, bytes))
+}
+
+/// Up to `CONTINUE_CONTEXT_BYTES` of text ending at `end`, cut on a char boundary
+fn context_before(content: &str, end: usize) -> &str {
+    let mut from = end.saturating_sub(CONTINUE_CONTEXT_BYTES);
+    while !content.is_char_boundary(from) {
+        from += 1;
+    }
+    &content[from..end]
+}
+
+fn needs_separator(before: &str, candidate: &str) -> bool {
+    let prev_is_text = before.chars().last().map(|c| !c.is_whitespace()).unwrap_or(false);
+    let next_is_word = candidate.chars().next().map(|c| c.is_alphanumeric()).unwrap_or(false);
+    prev_is_text && next_is_word
+}
+
+//=============================================================================
+// Generation
+//=============================================================================
+
+fn prompt(instruction: AssistInstruction, passage: &str, guidance: Option<&str>) -> String {
+    let task = match instruction {
+        AssistInstruction::Rewrite => "Rewrite the passage below, keeping its meaning, point of view and tense.".to_string(),
+        AssistInstruction::Shorten => "Shorten the passage below to roughly half its length without losing key details or changing its voice.".to_string(),
+        AssistInstruction::FixGrammar => "Correct spelling, grammar and punctuation in the passage below. Change nothing else.".to_string(),
+        AssistInstruction::Continue => format!(
+            "Continue the story from exactly where the passage below ends, matching its voice and style. \
+             Write about {} words and do not repeat the passage.",
+            CONTINUE_WORDS
+        ),
+    };
+    match guidance.map(str::trim).filter(|g| !g.is_empty()) {
+        Some(guidance) => format!("{}\nAdditional guidance: {}\n\nPassage:\n{}", task, guidance, passage),
+        None => format!("{}\n\nPassage:\n{}", task, passage),
+    }
+}
+
+fn complete(user_prompt: &str) -> Result<String, ServiceError> {
+    let base_url = variables::get("assist_api_url")
+        .unwrap_or_else(|_| "https://api.openai.com/v1".to_string());
+    let model = variables::get("assist_model")
+        .ok()
+        .filter(|m| !m.is_empty())
+        .unwrap_or_else(|| DEFAULT_MODEL.to_string());
+    let api_key = variables::get("assist_api_key").ok().filter(|k| !k.is_empty());
+
+    let request_body = serde_json::json!({
+        "model": model,
+        "messages": [
+            { "role": "system", "content": SYSTEM_PROMPT },
+            { "role": "user", "content": user_prompt }
+        ],
+        "temperature": 0.7
+    });
+    let uri = format!("{}/chat/completions", base_url.trim_end_matches('/'));
+    let body = serde_json::to_vec(&request_body).unwrap_or_default();
+
+    let request = match api_key {
+        Some(key) => OutboundRequest::builder()
+            .method(HttpMethod::Post)
+            .uri(uri)
+            .header("Content-Type", "application/json")
+            .header("Authorization", format!("Bearer {}", key))
+            .body(body)
+            .build(),
+        None => OutboundRequest::builder()
+            .method(HttpMethod::Post)
+            .uri(uri)
+            .header("Content-Type", "application/json")
+            .body(body)
+            .build(),
+    };
+
+    let response = outbound_http::send(request)
+        .map_err(|e| ServiceError::ServiceUnavailable(format!("Assist backend unreachable: {}", e)))?;
+    let status = response.status().as_u16();
+    if !(200..300).contains(&status) {
+        return Err(ServiceError::ServiceUnavailable(format!("Assist backend returned status {}", status)));
+    }
+
+    let parsed: serde_json::Value = serde_json::from_slice(response.body())
+        .map_err(|e| ServiceError::Internal(format!("Failed to parse assist response: {}", e)))?;
+    let text = parsed["choices"][0]["message"]["content"].as_str().unwrap_or_default().trim();
+    if text.is_empty() {
+        return Err(ServiceError::ServiceUnavailable("Assist backend returned no text".into()));
+    }
+    Ok(text.to_string())
+}
+
+//=============================================================================
+// Policy & Credits
+//=============================================================================
+
+/// Authors can opt a book out of AI features entirely
+fn ensure_ai_enabled(conn: &Connection, document_id: &Uuid) -> Result<(), ServiceError> {
+    let query = "SELECT COALESCE(b.ai_disabled, false) FROM content.chapters c
+                 JOIN content.books b ON c.book_id = b.id
+                 WHERE c.id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(document_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let disabled = rows.rows.first().map(|row| bool::decode(&row[0]).unwrap_or(false)).unwrap_or(false);
+
+    if disabled {
+        return Err(ServiceError::FeatureDisabled("AI features are disabled for this book".into()));
+    }
+    Ok(())
+}
+
+/// Priced like content enhancement: 1 credit per 20 words of input, minimum 1
+fn estimate_cost(instruction: AssistInstruction, passage: &str) -> i32 {
+    let words = match instruction {
+        AssistInstruction::Continue => CONTINUE_WORDS,
+        _ => passage.split_whitespace().count(),
+    };
+    ((words as f32 * 0.05) as i32).max(1)
+}
+
+fn subscription_url() -> String {
+    variables::get("subscription_service_url")
+        .unwrap_or_else(|_| "http://subscription-service:3105".to_string())
+}
+
+/// Checked before generating so unfunded requests don't reach the model
+fn has_credits(user_id: &Uuid, amount: i32) -> Result<bool, ServiceError> {
+    let request = OutboundRequest::builder()
+        .method(HttpMethod::Post)
+        .uri(format!("{}/credits/check", subscription_url()))
+        .header("Content-Type", "application/json")
+        .header("X-User-Id", user_id.to_string())
+        .body(serde_json::to_vec(&serde_json::json!({ "required_amount": amount })).unwrap_or_default())
+        .build();
+
+    let response = outbound_http::send(request)
+        .map_err(|e| ServiceError::Internal(format!("Failed to check credits: {}", e)))?;
+    if response.status().as_u16() != 200 {
+        return Err(ServiceError::Internal("Credit check failed".into()));
+    }
+
+    let parsed: serde_json::Value = serde_json::from_slice(response.body())
+        .map_err(|e| ServiceError::Internal(format!("Failed to parse credit check response: {}", e)))?;
+    Ok(parsed["has_sufficient_credits"].as_bool().unwrap_or(false))
+}
+
+/// Charged only once a candidate has been produced
+fn consume_credits(user_id: &Uuid, amount: i32, document_id: &Uuid, instruction: AssistInstruction) -> Result<bool, ServiceError> {
+    let request_body = serde_json::json!({
+        "amount": amount,
+        "reason": format!("AI assist ({})", serde_json::json!(instruction).as_str().unwrap_or("assist")),
+        "reference_id": document_id.to_string(),
+        "reference_type": "editor_document"
+    });
+
+    let request = OutboundRequest::builder()
+        .method(HttpMethod::Post)
+        .uri(format!("{}/credits/consume", subscription_url()))
+        .header("Content-Type", "application/json")
+        .header("X-User-Id", user_id.to_string())
+        .body(serde_json::to_vec(&request_body).unwrap_or_default())
+        .build();
+
+    let response = outbound_http::send(request)
+        .map_err(|e| ServiceError::Internal(format!("Failed to consume credits: {}", e)))?;
+    Ok(response.status().as_u16() == 200)
+}
@@ -0,0 +1,218 @@
+//! Document blame
+//!
+//! Replays the operation log from the compacted base to attribute every
+//! character of the current text to the user who wrote it. Assistant edits are kept
+//! apart from the requesting user's own typing, as in playback. Text that
+//! predates the retained history (the compacted base, or a revert to a
+//! checkpoint older than it) is returned with a `null` author.
+//!
+//! Ranges are UTF-16 offsets into `content`, matching operation positions,
+//! and adjacent spans by the same author are merged.
+
+use crate::error::ServiceError;
+use crate::models::Operation;
+use crate::text::len16;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    len: usize,
+    author: Option<usize>,
+}
+
+/// GET /documents/:id/blame - Attributed ranges of the current text
+pub fn get_blame(conn: &Connection, document_id: &Uuid) -> Result<Response, ServiceError> {
+    let doc_query = "SELECT version, compacted_version FROM editor.documents WHERE id = $1";
+    let doc_rows = conn.query(doc_query, &[ParameterValue::Str(document_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let (current_version, base_version) = doc_rows.rows.first()
+        .map(|row| (i64::decode(&row[0]).unwrap_or(0), i64::decode(&row[1]).unwrap_or(0)))
+        .ok_or_else(|| ServiceError::NotFound("Document not found".into()))?;
+
+    let mut content = crate::playback::content_at_version(conn, document_id, base_version)?;
+    let mut spans = unattributed(len16(&content));
+
+    // Reverts restore a checkpoint's text, so keep the attribution as of
+    // every checkpoint that falls inside the replayed history
+    let cp_query = "SELECT id::text, version FROM editor.checkpoints WHERE document_id = $1 AND version >= $2";
+    let cp_rows = conn.query(cp_query, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Int64(base_version),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let checkpoint_versions: HashMap<String, i64> = cp_rows.rows.iter()
+        .map(|row| (String::decode(&row[0]).unwrap_or_default(), i64::decode(&row[1]).unwrap_or(-1)))
+        .collect();
+    let mut snapshots: HashMap<i64, Vec<Span>> = HashMap::new();
+    if checkpoint_versions.values().any(|v| *v == base_version) {
+        snapshots.insert(base_version, spans.clone());
+    }
+
+    let ops_query = "SELECT o.version, o.operation, o.user_id, u.name, o.attribution, c.content
+                     FROM editor.operations o
+                     LEFT JOIN users.users u ON o.user_id = u.id
+                     LEFT JOIN editor.checkpoints c ON c.id::text = o.operation->>'checkpoint_id'
+                     WHERE o.document_id = $1 AND o.version > $2
+                     ORDER BY o.version ASC";
+    let rows = conn.query(ops_query, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Int64(base_version),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut authors: Vec<serde_json::Value> = Vec::new();
+    let mut author_keys: Vec<String> = Vec::new();
+
+    for row in &rows.rows {
+        let version = i64::decode(&row[0]).unwrap_or(0);
+        let operation: Operation = serde_json::from_str(&String::decode(&row[1]).unwrap_or_default())
+            .map_err(|e| ServiceError::Internal(format!("Parse failed: {}", e)))?;
+
+        let author_id = String::decode(&row[2]).unwrap_or_default();
+        let attribution = String::decode(&row[4]).ok();
+        let author_key = match attribution.as_deref() {
+            Some(attribution) => format!("{}:{}", attribution, author_id),
+            None => author_id.clone(),
+        };
+        let author = match author_keys.iter().position(|key| *key == author_key) {
+            Some(index) => index,
+            None => {
+                let name = match attribution.as_deref() {
+                    Some(crate::assist::ATTRIBUTION) => Some(crate::assist::ATTRIBUTION_LABEL.to_string()),
+                    _ => String::decode(&row[3]).ok(),
+                };
+                authors.push(serde_json::json!({
+                    "id": author_id,
+                    "name": name,
+                    "attribution": attribution
+                }));
+                author_keys.push(author_key);
+                author_keys.len() - 1
+            }
+        };
+
+        match operation {
+            Operation::Revert { checkpoint_id } => {
+                content = String::decode(&row[5]).unwrap_or_default();
+                spans = checkpoint_versions.get(&checkpoint_id.to_string())
+                    .and_then(|v| snapshots.get(v))
+                    .filter(|snapshot| total_len(snapshot) == len16(&content))
+                    .cloned()
+                    .unwrap_or_else(|| unattributed(len16(&content)));
+            }
+            Operation::Delta { plain, .. } => {
+                for step in &plain {
+                    content = replay(&content, &mut spans, step, author)?;
+                }
+            }
+            op => content = replay(&content, &mut spans, &op, author)?,
+        }
+
+        if checkpoint_versions.values().any(|v| *v == version) {
+            snapshots.insert(version, spans.clone());
+        }
+    }
+
+    let mut characters = vec![0usize; authors.len()];
+    let mut unattributed_characters = 0usize;
+    let mut ranges = Vec::with_capacity(spans.len());
+    let mut offset = 0;
+    for span in &spans {
+        match span.author {
+            Some(author) => characters[author] += span.len,
+            None => unattributed_characters += span.len,
+        }
+        ranges.push(serde_json::json!({
+            "start": offset,
+            "end": offset + span.len,
+            "author": span.author
+        }));
+        offset += span.len;
+    }
+
+    let total = len16(&content).max(1) as f64;
+    for (author, count) in authors.iter_mut().zip(&characters) {
+        author["characters"] = serde_json::json!(count);
+        author["share"] = serde_json::json!((*count as f64 / total * 1000.0).round() / 1000.0);
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "document_id": document_id,
+        "version": current_version,
+        "base_version": base_version,
+        "content": content,
+        "authors": authors,
+        "unattributed_characters": unattributed_characters,
+        "ranges": ranges
+    }))
+}
+
+/// Apply a plain-text operation to both the text and its attribution
+fn replay(content: &str, spans: &mut Vec<Span>, op: &Operation, author: usize) -> Result<String, ServiceError> {
+    let updated = crate::apply_operation(content, op)
+        .map_err(|_| ServiceError::Internal("Operation log is inconsistent".into()))?;
+    match op {
+        Operation::Insert { position, text } => splice(spans, *position as usize, 0, len16(text), author),
+        Operation::Delete { position, length } => splice(spans, *position as usize, *length as usize, 0, author),
+        Operation::Replace { position, length, text } => {
+            splice(spans, *position as usize, *length as usize, len16(text), author)
+        }
+        Operation::Revert { .. } | Operation::Delta { .. } => {}
+    }
+    Ok(updated)
+}
+
+/// Remove `removed` units at `position` and insert `inserted` units by `author`
+fn splice(spans: &mut Vec<Span>, position: usize, removed: usize, inserted: usize, author: usize) {
+    let end = position + removed;
+    let mut result: Vec<Span> = Vec::with_capacity(spans.len() + 2);
+    let mut offset = 0;
+    let mut placed = inserted == 0;
+
+    for span in spans.iter() {
+        let (start, stop) = (offset, offset + span.len);
+        offset = stop;
+
+        // Kept text before and after the edited range
+        let before = position.saturating_sub(start).min(span.len);
+        let after = stop.saturating_sub(end.max(start)).min(span.len);
+
+        if before > 0 {
+            push(&mut result, Span { len: before, author: span.author });
+        }
+        if !placed && stop >= position {
+            push(&mut result, Span { len: inserted, author: Some(author) });
+            placed = true;
+        }
+        if after > 0 && stop > end {
+            push(&mut result, Span { len: after, author: span.author });
+        }
+    }
+    if !placed {
+        push(&mut result, Span { len: inserted, author: Some(author) });
+    }
+
+    *spans = result;
+}
+
+/// Append, merging with the previous span when the author matches
+fn push(spans: &mut Vec<Span>, span: Span) {
+    if span.len == 0 {
+        return;
+    }
+    match spans.last_mut() {
+        Some(last) if last.author == span.author => last.len += span.len,
+        _ => spans.push(span),
+    }
+}
+
+fn unattributed(len: usize) -> Vec<Span> {
+    let mut spans = Vec::new();
+    push(&mut spans, Span { len, author: None });
+    spans
+}
+
+fn total_len(spans: &[Span]) -> usize {
+    spans.iter().map(|span| span.len).sum()
+}
@@ -0,0 +1,135 @@
+//! Stable block (paragraph) identifiers
+//!
+//! A document is split into blocks on `\n`. Each block carries an ID that is
+//! kept stable across operations so comments, reactions, and analytics can
+//! anchor to a paragraph instead of a raw offset. Splitting a block keeps the
+//! ID on the half that retains the original text; merging keeps the ID of the
+//! surviving block.
+
+use crate::models::Operation;
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Block {
+    pub id: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// UTF-16 ranges of each block in `content`, excluding the trailing newline
+pub fn block_ranges(content: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    for c in content.chars() {
+        if c == '\n' {
+            ranges.push((start, offset));
+            start = offset + 1;
+        }
+        offset += c.len_utf16();
+    }
+    ranges.push((start, offset));
+    ranges
+}
+
+/// Pair block IDs with their current ranges
+pub fn blocks_with_ids(content: &str, ids: &[String]) -> Vec<Block> {
+    let ids = reconcile_ids(content, ids);
+    block_ranges(content)
+        .into_iter()
+        .zip(ids)
+        .map(|((start, end), id)| Block { id, start, end })
+        .collect()
+}
+
+/// Make sure there is exactly one ID per block, minting IDs where missing
+pub fn reconcile_ids(content: &str, ids: &[String]) -> Vec<String> {
+    let count = block_ranges(content).len();
+    let mut ids: Vec<String> = ids.iter().take(count).cloned().collect();
+    while ids.len() < count {
+        ids.push(new_block_id());
+    }
+    ids
+}
+
+/// Index of the block containing UTF-16 offset `position`
+pub fn block_index_at(content: &str, position: usize) -> usize {
+    let mut offset = 0;
+    let mut index = 0;
+    for c in content.chars() {
+        if offset >= position {
+            break;
+        }
+        if c == '\n' {
+            index += 1;
+        }
+        offset += c.len_utf16();
+    }
+    index
+}
+
+/// Compute the block IDs after applying `op` to `content`.
+///
+/// Must be called with the pre-operation content; the operation is assumed to
+/// have already been validated by `apply_operation`.
+pub fn update_block_ids(content: &str, ids: &[String], op: &Operation) -> Vec<String> {
+    let mut ids = reconcile_ids(content, ids);
+
+    let (position, length, text) = match op {
+        Operation::Insert { position, text } => (*position, 0, text.as_str()),
+        Operation::Delete { position, length } => (*position, *length, ""),
+        Operation::Replace { position, length, text } => (*position, *length, text.as_str()),
+        Operation::Revert { .. } => return ids,
+        Operation::Delta { plain, .. } => {
+            // Step through the delta's plain-text equivalent
+            let mut content = content.to_string();
+            for step in plain {
+                ids = update_block_ids(&content, &ids, step);
+                match crate::apply_operation(&content, step) {
+                    Ok(next) => content = next,
+                    Err(_) => break,
+                }
+            }
+            return ids;
+        }
+    };
+
+    let range = match crate::text::byte_range(content, position, length) {
+        Ok(range) => range,
+        Err(_) => return ids,
+    };
+    let index = content[..range.start].matches('\n').count();
+    let at_block_start = range.start == 0 || content[..range.start].ends_with('\n');
+
+    // Merge: every newline removed folds the following block into this one
+    let deleted = &content[range];
+    let merged = deleted.matches('\n').count();
+    if merged > 0 {
+        if at_block_start && deleted.ends_with('\n') {
+            // Whole blocks removed from the front; the block after survives
+            ids.drain(index..index + merged);
+        } else {
+            ids.drain(index + 1..=index + merged);
+        }
+    }
+
+    // Split: every newline inserted creates a new block
+    let split = text.matches('\n').count();
+    if split > 0 {
+        let new_ids: Vec<String> = (0..split).map(|_| new_block_id()).collect();
+        if at_block_start && text.ends_with('\n') {
+            // New blocks pushed in above; existing text keeps its ID
+            ids.splice(index..index, new_ids);
+        } else {
+            ids.splice(index + 1..index + 1, new_ids);
+        }
+    }
+
+    ids
+}
+
+fn new_block_id() -> String {
+    // Short IDs keep the stored array compact; collisions only matter per document
+    Uuid::new_v4().simple().to_string()[..12].to_string()
+}
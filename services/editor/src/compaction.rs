@@ -0,0 +1,123 @@
+//! Operation log compaction
+//!
+//! Operations at or before the latest checkpoint are never needed for
+//! transforms: clients sync from the current version, and playback can start
+//! from the checkpoint's content. Compaction records those operations as a
+//! single snapshot row, deletes them, and advances the document's
+//! `compacted_version`. Checkpoints themselves are never touched, so revert
+//! and checkpoint-based playback keep working.
+//!
+//! Runs on demand through `POST /documents/:id/compact`, and automatically
+//! from `submit_operation` once enough operations have piled up behind the
+//! latest checkpoint.
+
+use crate::error::ServiceError;
+use serde::Serialize;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use chrono::Utc;
+use uuid::Uuid;
+
+/// Operations behind the latest checkpoint before automatic compaction kicks in
+const DEFAULT_AUTO_THRESHOLD: i64 = 1000;
+
+/// Automatic compaction is only considered every this many versions
+const AUTO_CHECK_INTERVAL: i64 = 100;
+
+#[derive(Debug, Serialize)]
+pub struct CompactionResult {
+    pub snapshot_id: Option<Uuid>,
+    pub compacted_version: i64,
+    pub operations_removed: i64,
+}
+
+/// Fold every operation at or before the latest checkpoint into a snapshot
+pub fn compact(conn: &Connection, document_id: &Uuid) -> Result<CompactionResult, ServiceError> {
+    let query = "SELECT d.compacted_version,
+                 (SELECT c.version FROM editor.checkpoints c
+                  WHERE c.document_id = d.id ORDER BY c.version DESC LIMIT 1),
+                 (SELECT c.content FROM editor.checkpoints c
+                  WHERE c.document_id = d.id ORDER BY c.version DESC LIMIT 1),
+                 (SELECT c.block_ids FROM editor.checkpoints c
+                  WHERE c.document_id = d.id ORDER BY c.version DESC LIMIT 1)
+                 FROM editor.documents d WHERE d.id = $1 FOR UPDATE";
+    let rows = conn.query(query, &[ParameterValue::Str(document_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Document not found".into()))?;
+
+    let compacted_version = i64::decode(&row[0]).unwrap_or(0);
+    let checkpoint_version = match i64::decode(&row[1]) {
+        Ok(version) if version > compacted_version => version,
+        _ => return Ok(CompactionResult { snapshot_id: None, compacted_version, operations_removed: 0 }),
+    };
+    let content = String::decode(&row[2]).unwrap_or_default();
+    let block_ids = String::decode(&row[3]).unwrap_or_else(|_| "[]".into());
+
+    let delete = "DELETE FROM editor.operations WHERE document_id = $1 AND version <= $2";
+    let removed = conn.execute(delete, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Int64(checkpoint_version),
+    ]).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))? as i64;
+
+    let snapshot_id = Uuid::new_v4();
+    let insert = "INSERT INTO editor.snapshots
+                  (id, document_id, from_version, version, content, block_ids, operation_count, created_at)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7, $8)";
+    conn.execute(insert, &[
+        ParameterValue::Str(snapshot_id.to_string()),
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Int64(compacted_version),
+        ParameterValue::Int64(checkpoint_version),
+        ParameterValue::Str(content),
+        ParameterValue::Str(block_ids),
+        ParameterValue::Int64(removed),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    let update = "UPDATE editor.documents SET compacted_version = GREATEST(compacted_version, $2) WHERE id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Int64(checkpoint_version),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    Ok(CompactionResult {
+        snapshot_id: Some(snapshot_id),
+        compacted_version: checkpoint_version,
+        operations_removed: removed,
+    })
+}
+
+/// Compact after a write if the backlog behind the latest checkpoint exceeds
+/// `editor_compaction_threshold`. Failures are swallowed; the edit already
+/// succeeded and the next check will retry.
+pub fn maybe_compact(conn: &Connection, document_id: &Uuid, version: i64) {
+    if version % AUTO_CHECK_INTERVAL != 0 {
+        return;
+    }
+
+    let threshold = variables::get("editor_compaction_threshold")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_AUTO_THRESHOLD);
+
+    let query = "SELECT COUNT(*) FROM editor.operations o
+                 WHERE o.document_id = $1
+                   AND o.version <= (SELECT MAX(c.version) FROM editor.checkpoints c WHERE c.document_id = $1)";
+    let backlog = conn.query(query, &[ParameterValue::Str(document_id.to_string())])
+        .ok()
+        .and_then(|rows| rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)))
+        .unwrap_or(0);
+
+    if backlog >= threshold {
+        let _ = compact(conn, document_id);
+    }
+}
+
+/// Versions at or below this have no operations left to transform against or replay
+pub fn compacted_version(conn: &Connection, document_id: &Uuid) -> Result<i64, ServiceError> {
+    let query = "SELECT compacted_version FROM editor.documents WHERE id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(document_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    Ok(rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0))
+}
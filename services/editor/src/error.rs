@@ -20,6 +20,18 @@ pub enum ServiceError {
     #[error("Conflict: {0}")]
     Conflict(String),
 
+    #[error("Payload too large: {0}")]
+    PayloadTooLarge(String),
+
+    #[error("Payment required: {0}")]
+    PaymentRequired(String),
+
+    #[error("Feature disabled: {0}")]
+    FeatureDisabled(String),
+
+    #[error("Service unavailable: {0}")]
+    ServiceUnavailable(String),
+
     #[error("Internal error: {0}")]
     Internal(String),
 }
@@ -38,6 +50,10 @@ impl ServiceError {
             ServiceError::Forbidden(_) => 403,
             ServiceError::NotFound(_) => 404,
             ServiceError::Conflict(_) => 409,
+            ServiceError::PayloadTooLarge(_) => 413,
+            ServiceError::PaymentRequired(_) => 402,
+            ServiceError::FeatureDisabled(_) => 403,
+            ServiceError::ServiceUnavailable(_) => 503,
             ServiceError::Internal(_) => 500,
         }
     }
@@ -49,6 +65,10 @@ impl ServiceError {
             ServiceError::Forbidden(_) => "FORBIDDEN",
             ServiceError::NotFound(_) => "NOT_FOUND",
             ServiceError::Conflict(_) => "CONFLICT",
+            ServiceError::PayloadTooLarge(_) => "PAYLOAD_TOO_LARGE",
+            ServiceError::PaymentRequired(_) => "PAYMENT_REQUIRED",
+            ServiceError::FeatureDisabled(_) => "FEATURE_DISABLED",
+            ServiceError::ServiceUnavailable(_) => "SERVICE_UNAVAILABLE",
             ServiceError::Internal(_) => "INTERNAL_ERROR",
         }
     }
@@ -73,3 +93,8 @@ impl ServiceError {
     }
 }
 
+impl From<authorworks_access::AccessError> for ServiceError {
+    fn from(e: authorworks_access::AccessError) -> Self {
+        ServiceError::Forbidden(e.to_string())
+    }
+}
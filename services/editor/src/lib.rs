@@ -5,17 +5,42 @@
 //!
 //! ## Endpoints
 //! - GET /health - Health check
-//! - GET /documents/:id - Get document state
-//! - POST /documents/:id/operations - Submit edit operation
-//! - GET /documents/:id/history - Get edit history
+//! - GET /documents/:id?fields= - Get document state, optionally without content, rich_content or blocks
+//! - GET /documents/:id/content?from=&to= - A range of the content, for windowed editors
+//! - POST /documents/:id/operations - Submit edit operation (plain-text or rich-text delta)
+//! - GET /documents/:id/history?before=&limit= - Get edit history, newest first, one page at a time
+//! - POST /documents/:id/undo - Undo caller's last operation
+//! - POST /documents/:id/redo - Redo caller's last undone operation
+//! - GET /documents/:id/undo - Depth of the caller's undo and redo stacks
+//! - POST /documents/:id/assist - AI rewrite/shorten/fix/continue a selection, optionally applied as an edit
+//! - GET /documents/:id/playback?from=&to= - Replay operations as timed frames
+//! - GET /documents/:id/blame - Attribute each span of the current text to its author
+//! - POST /documents/:id/lock - Acquire or renew an exclusive editing lock
+//! - DELETE /documents/:id/lock - Release the lock
+//! - GET /documents/:id/metadata - Title, synopsis and notes with their version vectors
+//! - PUT /documents/:id/metadata - Write metadata fields (last-writer-wins, stale writes dropped)
 //! - POST /documents/:id/checkpoint - Create checkpoint
 //! - GET /documents/:id/checkpoints - List checkpoints
+//! - POST /documents/:id/compact - Fold operations before the latest checkpoint into a snapshot
 //! - POST /documents/:id/revert - Revert to checkpoint
-//! - GET /documents/:id/presence - Get active collaborators
-//! - POST /documents/:id/presence - Update presence
-//! - GET /documents/:id/comments - Get comments
+//! - GET /documents/:id/presence?since= - Live sessions with colors, plus recent departures
+//! - POST /documents/:id/presence/join - Open a presence session and get a cursor color
+//! - POST /documents/:id/presence - Heartbeat and cursor update for a session
+//! - POST /documents/:id/presence/leave - End a presence session
+//! - GET /documents/:id/comments - Get comments, with ranges kept current through edits
 //! - POST /documents/:id/comments - Add comment
 //! - DELETE /comments/:id - Delete comment
+//! - GET /documents/:id/blocks - List stable block IDs with comment/reaction counts
+//! - POST /documents/:id/reactions - React to a block
+//! - POST /documents/:id/share-links - Create an expiring read-only share link
+//! - GET /documents/:id/share-links - List share links with view counts
+//! - DELETE /documents/:id/share-links/:link_id - Revoke a share link
+//! - GET /shared/:token - Read-only document view for share link holders (no auth)
+//! - POST /documents/:id/webhooks - Subscribe a URL to checkpoint, word milestone and comment events
+//! - GET /documents/:id/webhooks - List a document's webhooks
+//! - DELETE /documents/:id/webhooks/:webhook_id - Remove a webhook
+//! - GET /documents/:id/webhooks/:webhook_id/deliveries - Delivery log
+//! - POST /webhooks/deliver - Send due webhook deliveries with retries (internal, X-Internal-Token)
 
 use spin_sdk::http::{IntoResponse, Request, Response, Method};
 use spin_sdk::http_component;
@@ -28,6 +53,21 @@ use uuid::Uuid;
 mod models;
 mod error;
 mod ot;
+mod blocks;
+mod undo;
+mod playback;
+mod scopes;
+mod locks;
+mod compaction;
+mod assist;
+mod share_links;
+mod rich_text;
+mod presence;
+mod blame;
+mod metadata;
+mod text;
+mod webhooks;
+mod anchors;
 
 use error::ServiceError;
 use models::*;
@@ -37,34 +77,83 @@ fn handle_request(req: Request) -> anyhow::Result<impl IntoResponse> {
     let path = req.path();
     let method = req.method();
 
+    if let Err(e) = authorworks_access::authorize(&req, scopes::route_scope) {
+        return Ok(ServiceError::from(e).into_response());
+    }
+
     let result = match (method, path) {
         // Health
         (Method::Get, "/health") => health_handler(),
         (Method::Get, "/") => service_info(),
 
         // Document state
-        (Method::Get, path) if path.starts_with("/documents/") && !path.contains('/') => {
+        (Method::Get, path) if path.starts_with("/documents/") && path.matches('/').count() == 2 => {
             get_document(&req, path)
         }
+        (Method::Get, path) if path.starts_with("/documents/") && path.ends_with("/content") => {
+            get_document_content(&req, path)
+        }
+
+        // Shared documents (public)
+        (Method::Get, path) if path.starts_with("/shared/") => view_shared_document(path),
+
+        // Share links
+        (Method::Post, path) if path.ends_with("/share-links") => create_share_link(&req, path),
+        (Method::Get, path) if path.ends_with("/share-links") => list_share_links(&req, path),
+        (Method::Delete, path) if path.starts_with("/documents/") && path.contains("/share-links/") => {
+            revoke_share_link(&req, path)
+        }
+
+        // Webhooks
+        (Method::Post, "/webhooks/deliver") => deliver_webhooks(&req),
+        (Method::Post, path) if path.ends_with("/webhooks") => register_webhook(&req, path),
+        (Method::Get, path) if path.ends_with("/webhooks") => list_webhooks(&req, path),
+        (Method::Get, path) if path.contains("/webhooks/") && path.ends_with("/deliveries") => {
+            list_webhook_deliveries(&req, path)
+        }
+        (Method::Delete, path) if path.starts_with("/documents/") && path.contains("/webhooks/") => {
+            delete_webhook(&req, path)
+        }
 
         // Operations
         (Method::Post, path) if path.ends_with("/operations") => submit_operation(&req, path),
         (Method::Get, path) if path.ends_with("/history") => get_history(&req, path),
+        (Method::Post, path) if path.ends_with("/undo") => undo_operation(&req, path),
+        (Method::Post, path) if path.ends_with("/redo") => redo_operation(&req, path),
+        (Method::Get, path) if path.ends_with("/undo") => get_undo_stack(&req, path),
+        (Method::Post, path) if path.ends_with("/assist") => assist_selection(&req, path),
+        (Method::Get, path) if path.ends_with("/playback") => get_playback(&req, path),
+        (Method::Get, path) if path.ends_with("/blame") => get_blame(&req, path),
+
+        // Metadata
+        (Method::Get, path) if path.ends_with("/metadata") => get_metadata(&req, path),
+        (Method::Put, path) if path.ends_with("/metadata") => update_metadata(&req, path),
+
+        // Locks
+        (Method::Post, path) if path.ends_with("/lock") => acquire_lock(&req, path),
+        (Method::Delete, path) if path.ends_with("/lock") => release_lock(&req, path),
 
         // Checkpoints
         (Method::Post, path) if path.ends_with("/checkpoint") => create_checkpoint(&req, path),
         (Method::Get, path) if path.ends_with("/checkpoints") => list_checkpoints(&req, path),
         (Method::Post, path) if path.ends_with("/revert") => revert_to_checkpoint(&req, path),
+        (Method::Post, path) if path.ends_with("/compact") => compact_document(&req, path),
 
         // Presence
         (Method::Get, path) if path.ends_with("/presence") => get_presence(&req, path),
         (Method::Post, path) if path.ends_with("/presence") => update_presence(&req, path),
+        (Method::Post, path) if path.ends_with("/presence/join") => join_presence(&req, path),
+        (Method::Post, path) if path.ends_with("/presence/leave") => leave_presence(&req, path),
 
         // Comments
         (Method::Get, path) if path.ends_with("/comments") => get_comments(&req, path),
         (Method::Post, path) if path.ends_with("/comments") => add_comment(&req, path),
         (Method::Delete, path) if path.starts_with("/comments/") => delete_comment(&req, path),
 
+        // Blocks
+        (Method::Get, path) if path.ends_with("/blocks") => get_blocks(&req, path),
+        (Method::Post, path) if path.ends_with("/reactions") => add_reaction(&req, path),
+
         // CORS
         (Method::Options, _) => cors_preflight(),
 
@@ -120,7 +209,7 @@ fn service_info() -> Result<Response, ServiceError> {
     json_response(200, serde_json::json!({
         "service": "AuthorWorks Editor Service",
         "version": env!("CARGO_PKG_VERSION"),
-        "features": ["operational-transformation", "real-time-sync", "versioning", "comments"]
+        "features": ["operational-transformation", "real-time-sync", "versioning", "comments", "playback", "history-compaction", "ai-assist", "share-links", "rich-text", "presence-sessions", "blame", "document-metadata", "document-webhooks", "sticky-comment-anchors", "history-pagination", "windowed-content", "server-undo"]
     }))
 }
 
@@ -129,7 +218,7 @@ fn cors_preflight() -> Result<Response, ServiceError> {
         .status(204)
         .header("Access-Control-Allow-Origin", "*")
         .header("Access-Control-Allow-Methods", "GET, POST, PUT, DELETE, OPTIONS")
-        .header("Access-Control-Allow-Headers", "Content-Type, Authorization, X-User-Id")
+        .header("Access-Control-Allow-Headers", "Content-Type, Authorization, X-User-Id, X-Scopes")
         .header("Access-Control-Max-Age", "86400")
         .body(())
         .build())
@@ -139,49 +228,148 @@ fn cors_preflight() -> Result<Response, ServiceError> {
 // Document Operations
 //=============================================================================
 
+/// Parts of a document that `GET /documents/:id?fields=` can leave out
+const DOCUMENT_FIELDS: [&str; 3] = ["content", "rich_content", "blocks"];
+
+/// History page size when `limit` is not given, and the most a page may hold
+const DEFAULT_HISTORY_LIMIT: i64 = 100;
+const MAX_HISTORY_LIMIT: i64 = 500;
+
 fn get_document(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
     let document_id = extract_document_id(path)?;
+    let fields = parse_document_fields(req.query())?;
     let conn = get_db_connection()?;
 
     verify_document_access(&conn, &document_id, &user_id)?;
 
-    let query = "SELECT d.id, d.content, d.version, d.updated_at,
-                 (SELECT COUNT(*) FROM editor.operations WHERE document_id = d.id) as op_count
+    // Large documents are only read out of the row when the caller asked for them
+    let wants = |field: &str| fields.contains(&field);
+    let query = "SELECT d.id, CASE WHEN $2 THEN d.content ELSE '' END, d.version, d.updated_at,
+                 (SELECT COUNT(*) FROM editor.operations WHERE document_id = d.id) as op_count,
+                 d.block_ids, d.compacted_version, CASE WHEN $3 THEN d.rich_content::text END
                  FROM editor.documents d WHERE d.id = $1";
 
-    let params = [ParameterValue::Str(document_id.to_string())];
+    let params = [
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Boolean(wants("content") || wants("blocks")),
+        ParameterValue::Boolean(wants("rich_content")),
+    ];
     let rows = conn.query(query, &params)
         .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
 
     if rows.rows.is_empty() {
         // Create new document state
         let now = Utc::now();
-        let insert = "INSERT INTO editor.documents (id, content, version, created_at, updated_at)
-                      VALUES ($1, '', 0, $2, $2)";
+        let block_ids = blocks::reconcile_ids("", &[]);
+        let insert = "INSERT INTO editor.documents (id, content, version, block_ids, created_at, updated_at)
+                      VALUES ($1, '', 0, $2, $3, $3)";
         let insert_params = [
             ParameterValue::Str(document_id.to_string()),
+            ParameterValue::Str(serde_json::to_string(&block_ids).unwrap_or_default()),
             ParameterValue::Str(now.to_rfc3339()),
         ];
         conn.execute(insert, &insert_params)
             .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
 
-        return json_response(200, serde_json::json!({
+        let mut body = serde_json::json!({
             "id": document_id,
-            "content": "",
             "version": 0,
             "operations": 0,
+            "compacted_version": 0,
+            "lock": null,
             "updated_at": now.to_rfc3339()
-        }));
+        });
+        if wants("blocks") {
+            body["blocks"] = serde_json::json!(blocks::blocks_with_ids("", &block_ids));
+        }
+        if wants("content") {
+            body["content"] = serde_json::json!("");
+            body["length"] = serde_json::json!(0);
+        }
+        if wants("rich_content") {
+            body["rich_content"] = serde_json::Value::Null;
+        }
+        return json_response(200, body);
     }
 
     let row = &rows.rows[0];
-    json_response(200, serde_json::json!({
+    let content = String::decode(&row[1]).unwrap_or_default();
+    let lock = locks::active_lock(&conn, &document_id)?;
+    let mut body = serde_json::json!({
         "id": document_id,
-        "content": String::decode(&row[1]).unwrap_or_default(),
         "version": i64::decode(&row[2]).unwrap_or(0),
         "operations": i64::decode(&row[4]).unwrap_or(0),
+        "compacted_version": i64::decode(&row[6]).unwrap_or(0),
+        "lock": lock,
         "updated_at": String::decode(&row[3]).unwrap_or_default()
+    });
+    if wants("blocks") {
+        body["blocks"] = serde_json::json!(blocks::blocks_with_ids(&content, &decode_block_ids(&row[5])));
+    }
+    if wants("content") {
+        body["length"] = serde_json::json!(text::len16(&content));
+        body["content"] = serde_json::json!(content);
+    }
+    if wants("rich_content") {
+        body["rich_content"] = serde_json::json!(decode_rich_content(&row[7]));
+    }
+    json_response(200, body)
+}
+
+/// The requested subset of `DOCUMENT_FIELDS`; all of them when `fields` is absent
+fn parse_document_fields(query: &str) -> Result<Vec<&'static str>, ServiceError> {
+    let Some(raw) = get_query_param(query, "fields") else {
+        return Ok(DOCUMENT_FIELDS.to_vec());
+    };
+    let raw = raw.replace("%2C", ",").replace("%2c", ",");
+    let mut fields = Vec::new();
+    for name in raw.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+        let field = DOCUMENT_FIELDS.iter().find(|f| **f == name).ok_or_else(|| ServiceError::BadRequest(format!(
+            "Unknown field '{}'; fields may include: {}", name, DOCUMENT_FIELDS.join(", ")
+        )))?;
+        if !fields.contains(field) {
+            fields.push(*field);
+        }
+    }
+    Ok(fields)
+}
+
+/// GET /documents/:id/content?from=&to= - Content between two UTF-16 offsets.
+/// `to` is clamped to the document length; both default to the whole text.
+fn get_document_content(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/content")?;
+    let query = req.query();
+    let from = parse_offset_param(query, "from")?.unwrap_or(0);
+    let to = parse_offset_param(query, "to")?;
+    if to.is_some_and(|to| to < from) {
+        return Err(ServiceError::BadRequest("to must not be before from".into()));
+    }
+    let conn = get_db_connection()?;
+
+    verify_document_access(&conn, &document_id, &user_id)?;
+
+    let rows = conn.query(
+        "SELECT content, version FROM editor.documents WHERE id = $1",
+        &[ParameterValue::Str(document_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let (content, version) = rows.rows.first()
+        .map(|row| (String::decode(&row[0]).unwrap_or_default(), i64::decode(&row[1]).unwrap_or(0)))
+        .unwrap_or_default();
+
+    let length = text::len16(&content);
+    let from = from.min(length);
+    let to = to.unwrap_or(length).min(length);
+    let range = text::byte_range(&content, from as i32, (to - from) as i32)?;
+
+    json_response(200, serde_json::json!({
+        "id": document_id,
+        "from": from,
+        "to": to,
+        "length": length,
+        "version": version,
+        "content": &content[range]
     }))
 }
 
@@ -189,25 +377,39 @@ fn submit_operation(req: &Request, path: &str) -> Result<Response, ServiceError>
     let user_id = get_user_id(req)?;
     let document_id = extract_document_id_from_sub_path(path, "/operations")?;
     let body: OperationRequest = parse_json_body(req)?;
+    text::validate_operation(&body.operation)?;
     let conn = get_db_connection()?;
 
     verify_document_access(&conn, &document_id, &user_id)?;
+    locks::ensure_can_edit(&conn, &document_id, &user_id)?;
 
     // Get current document state
-    let doc_query = "SELECT content, version FROM editor.documents WHERE id = $1 FOR UPDATE";
+    let doc_query = "SELECT content, version, block_ids, compacted_version, rich_content::text
+                     FROM editor.documents WHERE id = $1 FOR UPDATE";
     let doc_params = [ParameterValue::Str(document_id.to_string())];
     let doc_rows = conn.query(doc_query, &doc_params)
         .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
 
-    let (current_content, current_version) = if doc_rows.rows.is_empty() {
-        ("".to_string(), 0i64)
+    let (current_content, current_version, current_block_ids, compacted_version, current_rich_content) = if doc_rows.rows.is_empty() {
+        ("".to_string(), 0i64, Vec::new(), 0i64, None)
     } else {
         (
             String::decode(&doc_rows.rows[0][0]).unwrap_or_default(),
             i64::decode(&doc_rows.rows[0][1]).unwrap_or(0),
+            decode_block_ids(&doc_rows.rows[0][2]),
+            i64::decode(&doc_rows.rows[0][3]).unwrap_or(0),
+            decode_rich_content(&doc_rows.rows[0][4]),
         )
     };
 
+    // The operations needed to transform from here have been compacted away
+    if body.base_version < compacted_version {
+        return Err(ServiceError::Conflict(format!(
+            "base_version {} predates compacted history (version {}); reload the document",
+            body.base_version, compacted_version
+        )));
+    }
+
     // Check for version conflict
     if body.base_version != current_version {
         // Need to transform operation against concurrent operations
@@ -226,84 +428,112 @@ fn submit_operation(req: &Request, path: &str) -> Result<Response, ServiceError>
             let concurrent_op: Operation = serde_json::from_str(
                 &String::decode(&op_row[0]).unwrap_or_default()
             ).map_err(|e| ServiceError::Internal(format!("Parse failed: {}", e)))?;
+            if !rich_text::can_transform(&transformed_op, &concurrent_op) {
+                return Err(ServiceError::Conflict(
+                    "Document switched to rich text after base_version; reload the document".into()
+                ));
+            }
             transformed_op = transform_operation(&transformed_op, &concurrent_op);
         }
 
         // Apply transformed operation
-        let new_content = apply_operation(&current_content, &transformed_op)?;
+        let applied = rich_text::apply(&current_content, current_rich_content.as_deref(), transformed_op)?;
+        let new_content = applied.content;
+        let transformed_op = applied.operation;
+        let new_block_ids = blocks::update_block_ids(&current_content, &current_block_ids, &transformed_op);
         let new_version = current_version + 1;
         let now = Utc::now();
 
         // Store operation
         let op_id = Uuid::new_v4();
-        let op_insert = "INSERT INTO editor.operations (id, document_id, user_id, version, operation, created_at)
-                         VALUES ($1, $2, $3, $4, $5, $6)";
+        let op_insert = "INSERT INTO editor.operations (id, document_id, user_id, version, operation, inverse, created_at)
+                         VALUES ($1, $2, $3, $4, $5, $6, $7)";
         let op_params = [
             ParameterValue::Str(op_id.to_string()),
             ParameterValue::Str(document_id.to_string()),
             ParameterValue::Str(user_id.to_string()),
             ParameterValue::Int64(new_version),
             ParameterValue::Str(serde_json::to_string(&transformed_op).unwrap_or_default()),
+            encode_operation(applied.inverse.as_ref()),
             ParameterValue::Str(now.to_rfc3339()),
         ];
         conn.execute(op_insert, &op_params)
             .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
 
         // Update document
-        let doc_update = "UPDATE editor.documents SET content = $2, version = $3, updated_at = $4 WHERE id = $1";
+        let doc_update = "UPDATE editor.documents SET content = $2, version = $3, updated_at = $4, block_ids = $5,
+                          rich_content = $6::jsonb WHERE id = $1";
         let update_params = [
             ParameterValue::Str(document_id.to_string()),
             ParameterValue::Str(new_content.clone()),
             ParameterValue::Int64(new_version),
             ParameterValue::Str(now.to_rfc3339()),
+            ParameterValue::Str(serde_json::to_string(&new_block_ids).unwrap_or_default()),
+            encode_rich_content(applied.rich_content.as_deref()),
         ];
         conn.execute(doc_update, &update_params)
             .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+        anchors::transform_comments(&conn, &document_id, &transformed_op)?;
+        compaction::maybe_compact(&conn, &document_id, new_version);
+        webhooks::emit_milestones(&conn, &document_id, &current_content, &new_content, new_version);
 
         return json_response(200, serde_json::json!({
             "version": new_version,
             "transformed_operation": transformed_op,
-            "content": new_content
+            "blocks": blocks::blocks_with_ids(&new_content, &new_block_ids),
+            "content": new_content,
+            "rich_content": applied.rich_content
         }));
     }
 
     // No conflict - apply directly
-    let new_content = apply_operation(&current_content, &body.operation)?;
+    let applied = rich_text::apply(&current_content, current_rich_content.as_deref(), body.operation)?;
+    let new_content = applied.content;
+    let new_block_ids = blocks::update_block_ids(&current_content, &current_block_ids, &applied.operation);
     let new_version = current_version + 1;
     let now = Utc::now();
 
     // Store operation
     let op_id = Uuid::new_v4();
-    let op_insert = "INSERT INTO editor.operations (id, document_id, user_id, version, operation, created_at)
-                     VALUES ($1, $2, $3, $4, $5, $6)";
+    let op_insert = "INSERT INTO editor.operations (id, document_id, user_id, version, operation, inverse, created_at)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7)";
     let op_params = [
         ParameterValue::Str(op_id.to_string()),
         ParameterValue::Str(document_id.to_string()),
         ParameterValue::Str(user_id.to_string()),
         ParameterValue::Int64(new_version),
-        ParameterValue::Str(serde_json::to_string(&body.operation).unwrap_or_default()),
+        ParameterValue::Str(serde_json::to_string(&applied.operation).unwrap_or_default()),
+        encode_operation(applied.inverse.as_ref()),
         ParameterValue::Str(now.to_rfc3339()),
     ];
     conn.execute(op_insert, &op_params)
         .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
 
     // Update or insert document
-    let doc_upsert = "INSERT INTO editor.documents (id, content, version, created_at, updated_at)
-                      VALUES ($1, $2, $3, $4, $4)
-                      ON CONFLICT (id) DO UPDATE SET content = $2, version = $3, updated_at = $4";
+    let doc_upsert = "INSERT INTO editor.documents (id, content, version, block_ids, rich_content, created_at, updated_at)
+                      VALUES ($1, $2, $3, $5, $6::jsonb, $4, $4)
+                      ON CONFLICT (id) DO UPDATE SET content = $2, version = $3, block_ids = $5,
+                          rich_content = $6::jsonb, updated_at = $4";
     let doc_params = [
         ParameterValue::Str(document_id.to_string()),
         ParameterValue::Str(new_content.clone()),
         ParameterValue::Int64(new_version),
         ParameterValue::Str(now.to_rfc3339()),
+        ParameterValue::Str(serde_json::to_string(&new_block_ids).unwrap_or_default()),
+        encode_rich_content(applied.rich_content.as_deref()),
     ];
     conn.execute(doc_upsert, &doc_params)
         .map_err(|e| ServiceError::Internal(format!("Upsert failed: {}", e)))?;
+    anchors::transform_comments(&conn, &document_id, &applied.operation)?;
+    compaction::maybe_compact(&conn, &document_id, new_version);
+    webhooks::emit_milestones(&conn, &document_id, &current_content, &new_content, new_version);
 
     json_response(200, serde_json::json!({
         "version": new_version,
-        "operation": body.operation,
-        "content": new_content
+        "operation": applied.operation,
+        "blocks": blocks::blocks_with_ids(&new_content, &new_block_ids),
+        "content": new_content,
+        "rich_content": applied.rich_content
     }))
 }
 
@@ -314,34 +544,330 @@ fn get_history(req: &Request, path: &str) -> Result<Response, ServiceError> {
 
     verify_document_access(&conn, &document_id, &user_id)?;
 
-    let query = "SELECT o.id, o.user_id, o.version, o.operation, o.created_at, u.name
+    let query_string = req.query();
+    let before = parse_version_param(query_string, "before")?;
+    let limit = get_query_param(query_string, "limit")
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .clamp(1, MAX_HISTORY_LIMIT);
+
+    // Keyset on version so deep pages cost the same as the first; one extra row tells whether more remain
+    let query = "SELECT o.id, o.user_id, o.version, o.operation, o.created_at, u.name, o.attribution,
+                        o.undo_of, o.redo_of, o.undone
                  FROM editor.operations o
                  LEFT JOIN users.users u ON o.user_id = u.id
-                 WHERE o.document_id = $1 ORDER BY o.version DESC LIMIT 100";
+                 WHERE o.document_id = $1 AND ($2::bigint IS NULL OR o.version < $2)
+                 ORDER BY o.version DESC LIMIT $3";
 
-    let params = [ParameterValue::Str(document_id.to_string())];
+    let params = [
+        ParameterValue::Str(document_id.to_string()),
+        before.map(ParameterValue::Int64).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Int64(limit + 1),
+    ];
     let rows = conn.query(query, &params)
         .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
-
-    let history: Vec<serde_json::Value> = rows.rows.iter().map(|row| {
+    let has_more = rows.rows.len() as i64 > limit;
+
+    let history: Vec<serde_json::Value> = rows.rows.iter().take(limit as usize).map(|row| {
+        // Assistant edits keep the requesting user's id but are labelled as the assistant's
+        let attribution = String::decode(&row[6]).ok();
+        let user_name = match attribution.as_deref() {
+            Some(assist::ATTRIBUTION) => Some(assist::ATTRIBUTION_LABEL.to_string()),
+            _ => String::decode(&row[5]).ok(),
+        };
+        let undo_of = String::decode(&row[7]).ok();
+        let redo_of = String::decode(&row[8]).ok();
+        let kind = match (&undo_of, &redo_of) {
+            (Some(_), _) => "undo",
+            (_, Some(_)) => "redo",
+            _ => "edit",
+        };
         serde_json::json!({
             "id": String::decode(&row[0]).unwrap_or_default(),
             "user_id": String::decode(&row[1]).unwrap_or_default(),
-            "user_name": String::decode(&row[5]).ok(),
+            "user_name": user_name,
+            "attribution": attribution,
             "version": i64::decode(&row[2]).unwrap_or(0),
             "operation": serde_json::from_str::<serde_json::Value>(
                 &String::decode(&row[3]).unwrap_or_else(|_| "{}".into())
             ).unwrap_or_default(),
+            "kind": kind,
+            "undo_of": undo_of,
+            "redo_of": redo_of,
+            "undone": bool::decode(&row[9]).unwrap_or(false),
             "created_at": String::decode(&row[4]).unwrap_or_default()
         })
     }).collect();
 
+    let next_before = if has_more { history.last().map(|op| op["version"].clone()) } else { None };
+
     json_response(200, serde_json::json!({
         "history": history,
-        "total": history.len()
+        "total": history.len(),
+        "next_before": next_before,
+        "compacted_version": compaction::compacted_version(&conn, &document_id)?
     }))
 }
 
+fn undo_operation(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/undo")?;
+    let conn = get_db_connection()?;
+
+    verify_document_access(&conn, &document_id, &user_id)?;
+    locks::ensure_can_edit(&conn, &document_id, &user_id)?;
+
+    let result = undo::undo(&conn, &document_id, &user_id)?;
+    let stack = undo::stack(&conn, &document_id, &user_id)?;
+    undo_response(result, stack, "undone_operation_id")
+}
+
+fn redo_operation(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/redo")?;
+    let conn = get_db_connection()?;
+
+    verify_document_access(&conn, &document_id, &user_id)?;
+    locks::ensure_can_edit(&conn, &document_id, &user_id)?;
+
+    let result = undo::redo(&conn, &document_id, &user_id)?;
+    let stack = undo::stack(&conn, &document_id, &user_id)?;
+    undo_response(result, stack, "redone_undo_id")
+}
+
+fn get_undo_stack(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/undo")?;
+    let conn = get_db_connection()?;
+
+    verify_document_access(&conn, &document_id, &user_id)?;
+
+    json_response(200, undo::stack(&conn, &document_id, &user_id)?)
+}
+
+fn assist_selection(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/assist")?;
+    let body: AssistRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    verify_document_access(&conn, &document_id, &user_id)?;
+    if body.apply {
+        locks::ensure_can_edit(&conn, &document_id, &user_id)?;
+    }
+
+    let result = assist::assist(&conn, &document_id, &user_id, body)?;
+    json_response(200, result)
+}
+
+fn undo_response(result: undo::UndoResult, stack: undo::UndoStack, target_key: &str) -> Result<Response, ServiceError> {
+    let mut body = serde_json::json!({
+        "id": result.operation_id,
+        "version": result.version,
+        "undo_stack": stack,
+        "operation": result.operation,
+        "blocks": blocks::blocks_with_ids(&result.content, &result.block_ids),
+        "content": result.content,
+        "rich_content": result.rich_content
+    });
+    body[target_key] = serde_json::json!(result.target_id);
+    json_response(200, body)
+}
+
+fn get_playback(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/playback")?;
+    let conn = get_db_connection()?;
+
+    verify_document_readable(&conn, &document_id, &user_id)?;
+
+    let query = req.query();
+    let from = parse_version_param(query, "from")?;
+    let to = parse_version_param(query, "to")?;
+
+    playback::get_playback(&conn, &document_id, from, to)
+}
+
+fn get_blame(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/blame")?;
+    let conn = get_db_connection()?;
+
+    verify_document_readable(&conn, &document_id, &user_id)?;
+
+    blame::get_blame(&conn, &document_id)
+}
+
+//=============================================================================
+// Metadata
+//=============================================================================
+
+fn get_metadata(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/metadata")?;
+    let conn = get_db_connection()?;
+
+    verify_document_access(&conn, &document_id, &user_id)?;
+
+    let (fields, version) = metadata::load(&conn, &document_id)?;
+    json_response(200, serde_json::json!({
+        "id": document_id,
+        "version": version,
+        "metadata": metadata::with_all_fields(fields)
+    }))
+}
+
+fn update_metadata(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/metadata")?;
+    let body: MetadataUpdateRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    verify_document_access(&conn, &document_id, &user_id)?;
+    locks::ensure_can_edit(&conn, &document_id, &user_id)?;
+
+    let result = metadata::update(&conn, &document_id, &user_id, body)?;
+    json_response(200, result)
+}
+
+//=============================================================================
+// Share Links
+//=============================================================================
+
+fn create_share_link(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/share-links")?;
+    let body: ShareLinkRequest = if req.body().is_empty() {
+        ShareLinkRequest::default()
+    } else {
+        parse_json_body(req)?
+    };
+    let conn = get_db_connection()?;
+
+    verify_document_access(&conn, &document_id, &user_id)?;
+
+    share_links::create(&conn, &document_id, &user_id, body)
+}
+
+fn list_share_links(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/share-links")?;
+    let conn = get_db_connection()?;
+
+    verify_document_access(&conn, &document_id, &user_id)?;
+
+    share_links::list(&conn, &document_id)
+}
+
+fn revoke_share_link(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_id_from_path(path, "/documents/")?;
+    let link_id = path.rsplit('/').next()
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid share link ID".into()))?;
+    let conn = get_db_connection()?;
+
+    verify_document_access(&conn, &document_id, &user_id)?;
+
+    share_links::revoke(&conn, &document_id, &link_id)
+}
+
+fn view_shared_document(path: &str) -> Result<Response, ServiceError> {
+    let conn = get_db_connection()?;
+    share_links::view(&conn, path)
+}
+
+//=============================================================================
+// Webhooks
+//=============================================================================
+
+fn register_webhook(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/webhooks")?;
+    let body: WebhookRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    verify_document_access(&conn, &document_id, &user_id)?;
+
+    webhooks::register(&conn, &document_id, &user_id, body)
+}
+
+fn list_webhooks(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/webhooks")?;
+    let conn = get_db_connection()?;
+
+    verify_document_access(&conn, &document_id, &user_id)?;
+
+    webhooks::list(&conn, &document_id)
+}
+
+fn delete_webhook(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_id_from_path(path, "/documents/")?;
+    let webhook_id = extract_webhook_id(path)?;
+    let conn = get_db_connection()?;
+
+    verify_document_access(&conn, &document_id, &user_id)?;
+
+    webhooks::delete(&conn, &document_id, &webhook_id)
+}
+
+fn list_webhook_deliveries(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_id_from_path(path, "/documents/")?;
+    let webhook_id = extract_webhook_id(path)?;
+    let conn = get_db_connection()?;
+
+    verify_document_access(&conn, &document_id, &user_id)?;
+
+    webhooks::list_deliveries(&conn, &document_id, &webhook_id)
+}
+
+fn deliver_webhooks(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let conn = get_db_connection()?;
+    webhooks::deliver(&conn)
+}
+
+fn extract_webhook_id(path: &str) -> Result<Uuid, ServiceError> {
+    path.split("/webhooks/").nth(1)
+        .and_then(|rest| rest.split('/').next())
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid webhook ID".into()))
+}
+
+//=============================================================================
+// Locks
+//=============================================================================
+
+fn acquire_lock(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/lock")?;
+    let body: LockRequest = if req.body().is_empty() {
+        LockRequest::default()
+    } else {
+        parse_json_body(req)?
+    };
+    let conn = get_db_connection()?;
+
+    verify_document_access(&conn, &document_id, &user_id)?;
+
+    let lock = locks::acquire(&conn, &document_id, &user_id, body.ttl_seconds)?;
+    json_response(200, serde_json::json!({ "lock": lock }))
+}
+
+fn release_lock(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/lock")?;
+    let conn = get_db_connection()?;
+
+    verify_document_access(&conn, &document_id, &user_id)?;
+
+    locks::release(&conn, &document_id, &user_id)?;
+    json_response(200, serde_json::json!({ "released": true }))
+}
+
 //=============================================================================
 // Checkpoints
 //=============================================================================
@@ -355,7 +881,7 @@ fn create_checkpoint(req: &Request, path: &str) -> Result<Response, ServiceError
     verify_document_access(&conn, &document_id, &user_id)?;
 
     // Get current document state
-    let doc_query = "SELECT content, version FROM editor.documents WHERE id = $1";
+    let doc_query = "SELECT content, version, block_ids, rich_content::text, metadata::text FROM editor.documents WHERE id = $1";
     let doc_params = [ParameterValue::Str(document_id.to_string())];
     let doc_rows = conn.query(doc_query, &doc_params)
         .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
@@ -366,12 +892,15 @@ fn create_checkpoint(req: &Request, path: &str) -> Result<Response, ServiceError
 
     let content = String::decode(&doc_rows.rows[0][0]).unwrap_or_default();
     let version = i64::decode(&doc_rows.rows[0][1]).unwrap_or(0);
+    let block_ids = blocks::reconcile_ids(&content, &decode_block_ids(&doc_rows.rows[0][2]));
+    let rich_content = decode_rich_content(&doc_rows.rows[0][3]);
+    let fields = metadata::decode(&doc_rows.rows[0][4]).unwrap_or_default();
 
     let checkpoint_id = Uuid::new_v4();
     let now = Utc::now();
 
-    let insert = "INSERT INTO editor.checkpoints (id, document_id, user_id, name, content, version, created_at)
-                  VALUES ($1, $2, $3, $4, $5, $6, $7)";
+    let insert = "INSERT INTO editor.checkpoints (id, document_id, user_id, name, content, version, block_ids, rich_content, metadata, created_at)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7, $8::jsonb, $9::jsonb, $10)";
     let params = [
         ParameterValue::Str(checkpoint_id.to_string()),
         ParameterValue::Str(document_id.to_string()),
@@ -379,11 +908,20 @@ fn create_checkpoint(req: &Request, path: &str) -> Result<Response, ServiceError
         ParameterValue::Str(body.name.clone()),
         ParameterValue::Str(content),
         ParameterValue::Int64(version),
+        ParameterValue::Str(serde_json::to_string(&block_ids).unwrap_or_default()),
+        encode_rich_content(rich_content.as_deref()),
+        metadata::encode(&fields),
         ParameterValue::Str(now.to_rfc3339()),
     ];
 
     conn.execute(insert, &params)
         .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    webhooks::emit(&conn, &document_id, webhooks::CHECKPOINT_CREATED, &checkpoint_id.to_string(), serde_json::json!({
+        "checkpoint_id": checkpoint_id,
+        "name": body.name,
+        "version": version,
+        "created_by": user_id
+    }));
 
     json_response(201, serde_json::json!({
         "id": checkpoint_id,
@@ -431,9 +969,11 @@ fn revert_to_checkpoint(req: &Request, path: &str) -> Result<Response, ServiceEr
     let conn = get_db_connection()?;
 
     verify_document_access(&conn, &document_id, &user_id)?;
+    locks::ensure_can_edit(&conn, &document_id, &user_id)?;
 
     // Get checkpoint
-    let cp_query = "SELECT content, version FROM editor.checkpoints WHERE id = $1 AND document_id = $2";
+    let cp_query = "SELECT content, version, block_ids, rich_content::text, metadata::text
+                    FROM editor.checkpoints WHERE id = $1 AND document_id = $2";
     let cp_params = [
         ParameterValue::Str(body.checkpoint_id.to_string()),
         ParameterValue::Str(document_id.to_string()),
@@ -446,28 +986,44 @@ fn revert_to_checkpoint(req: &Request, path: &str) -> Result<Response, ServiceEr
     }
 
     let content = String::decode(&cp_rows.rows[0][0]).unwrap_or_default();
+    let block_ids = blocks::reconcile_ids(&content, &decode_block_ids(&cp_rows.rows[0][2]));
+    let rich_content = decode_rich_content(&cp_rows.rows[0][3]);
+    let checkpoint_fields = metadata::decode(&cp_rows.rows[0][4]);
     let now = Utc::now();
 
     // Get current version and increment
-    let doc_query = "SELECT version FROM editor.documents WHERE id = $1";
+    let doc_query = "SELECT version, metadata::text FROM editor.documents WHERE id = $1";
     let doc_params = [ParameterValue::Str(document_id.to_string())];
     let doc_rows = conn.query(doc_query, &doc_params)
         .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
 
-    let current_version = if doc_rows.rows.is_empty() {
-        0i64
+    let (current_version, current_fields) = if doc_rows.rows.is_empty() {
+        (0i64, metadata::Metadata::new())
     } else {
-        i64::decode(&doc_rows.rows[0][0]).unwrap_or(0)
+        (
+            i64::decode(&doc_rows.rows[0][0]).unwrap_or(0),
+            metadata::decode(&doc_rows.rows[0][1]).unwrap_or_default(),
+        )
     };
     let new_version = current_version + 1;
 
+    // Checkpoints taken before metadata existed leave the fields untouched
+    let fields = match &checkpoint_fields {
+        Some(snapshot) => metadata::restore(current_fields, snapshot, &user_id),
+        None => current_fields,
+    };
+
     // Update document
-    let update = "UPDATE editor.documents SET content = $2, version = $3, updated_at = $4 WHERE id = $1";
+    let update = "UPDATE editor.documents SET content = $2, version = $3, updated_at = $4, block_ids = $5,
+                  rich_content = $6::jsonb, metadata = $7::jsonb WHERE id = $1";
     let update_params = [
         ParameterValue::Str(document_id.to_string()),
         ParameterValue::Str(content.clone()),
         ParameterValue::Int64(new_version),
         ParameterValue::Str(now.to_rfc3339()),
+        ParameterValue::Str(serde_json::to_string(&block_ids).unwrap_or_default()),
+        encode_rich_content(rich_content.as_deref()),
+        metadata::encode(&fields),
     ];
     conn.execute(update, &update_params)
         .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
@@ -487,14 +1043,29 @@ fn revert_to_checkpoint(req: &Request, path: &str) -> Result<Response, ServiceEr
     ];
     conn.execute(op_insert, &op_params)
         .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    anchors::clamp_comments(&conn, &document_id, text::len16(&content) as i32)?;
 
     json_response(200, serde_json::json!({
         "version": new_version,
+        "blocks": blocks::blocks_with_ids(&content, &block_ids),
         "content": content,
+        "rich_content": rich_content,
+        "metadata": metadata::with_all_fields(fields),
         "reverted_at": now.to_rfc3339()
     }))
 }
 
+fn compact_document(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/compact")?;
+    let conn = get_db_connection()?;
+
+    verify_document_access(&conn, &document_id, &user_id)?;
+
+    let result = compaction::compact(&conn, &document_id)?;
+    json_response(200, result)
+}
+
 //=============================================================================
 // Presence
 //=============================================================================
@@ -502,72 +1073,78 @@ fn revert_to_checkpoint(req: &Request, path: &str) -> Result<Response, ServiceEr
 fn get_presence(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
     let document_id = extract_document_id_from_sub_path(path, "/presence")?;
+    let since = get_query_param(req.query(), "since")
+        .map(|s| s.replace("%3A", ":").replace("%2B", "+"))
+        .map(|s| {
+            chrono::DateTime::parse_from_rfc3339(&s)
+                .map(|t| t.with_timezone(&Utc).to_rfc3339())
+                .map_err(|_| ServiceError::BadRequest("since must be an RFC 3339 timestamp".into()))
+        })
+        .transpose()?;
     let conn = get_db_connection()?;
 
     verify_document_access(&conn, &document_id, &user_id)?;
 
-    // Get active users (updated in last 30 seconds)
-    let query = "SELECT p.user_id, p.cursor_position, p.selection_start, p.selection_end, 
-                 p.updated_at, u.name, u.avatar_url
-                 FROM editor.presence p
-                 LEFT JOIN users.users u ON p.user_id = u.id
-                 WHERE p.document_id = $1 AND p.updated_at > NOW() - INTERVAL '30 seconds'";
-
-    let params = [ParameterValue::Str(document_id.to_string())];
-    let rows = conn.query(query, &params)
-        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    // Read the clock first so a departure recorded during the queries is
+    // still reported when the client passes server_time back as `since`
+    let server_time = Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    let (sessions, departures) = presence::list(&conn, &document_id, since.as_deref())?;
 
-    let presence: Vec<serde_json::Value> = rows.rows.iter().map(|row| {
-        serde_json::json!({
-            "user_id": String::decode(&row[0]).unwrap_or_default(),
-            "cursor_position": i32::decode(&row[1]).ok(),
-            "selection": {
-                "start": i32::decode(&row[2]).ok(),
-                "end": i32::decode(&row[3]).ok()
-            },
-            "updated_at": String::decode(&row[4]).unwrap_or_default(),
-            "user": {
-                "name": String::decode(&row[5]).ok(),
-                "avatar_url": String::decode(&row[6]).ok()
-            }
-        })
-    }).collect();
+    let mut active_users: Vec<Uuid> = sessions.iter().map(|s| s.user_id).collect();
+    active_users.sort();
+    active_users.dedup();
 
     json_response(200, serde_json::json!({
-        "presence": presence,
-        "active_users": presence.len()
+        "presence": sessions,
+        "departed": departures,
+        "active_sessions": sessions.len(),
+        "active_users": active_users.len(),
+        "server_time": server_time
     }))
 }
 
+fn join_presence(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/presence/join")?;
+    let body: PresenceJoinRequest = if req.body().is_empty() {
+        PresenceJoinRequest::default()
+    } else {
+        parse_json_body(req)?
+    };
+    let conn = get_db_connection()?;
+
+    verify_document_access(&conn, &document_id, &user_id)?;
+
+    let session = presence::join(&conn, &document_id, &user_id, body)?;
+    json_response(201, session)
+}
+
 fn update_presence(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
     let document_id = extract_document_id_from_sub_path(path, "/presence")?;
-    let body: PresenceUpdate = parse_json_body(req)?;
+    let body: PresenceUpdate = if req.body().is_empty() {
+        PresenceUpdate::default()
+    } else {
+        parse_json_body(req)?
+    };
     let conn = get_db_connection()?;
 
     verify_document_access(&conn, &document_id, &user_id)?;
 
-    let now = Utc::now();
-    let upsert = "INSERT INTO editor.presence (document_id, user_id, cursor_position, selection_start, selection_end, updated_at)
-                  VALUES ($1, $2, $3, $4, $5, $6)
-                  ON CONFLICT (document_id, user_id) DO UPDATE SET 
-                  cursor_position = $3, selection_start = $4, selection_end = $5, updated_at = $6";
+    let heartbeat = presence::heartbeat(&conn, &document_id, &user_id, body)?;
+    json_response(200, heartbeat)
+}
 
-    let params = [
-        ParameterValue::Str(document_id.to_string()),
-        ParameterValue::Str(user_id.to_string()),
-        ParameterValue::Int32(body.cursor_position.unwrap_or(0)),
-        ParameterValue::Int32(body.selection.as_ref().map(|s| s.start).unwrap_or(0)),
-        ParameterValue::Int32(body.selection.as_ref().map(|s| s.end).unwrap_or(0)),
-        ParameterValue::Str(now.to_rfc3339()),
-    ];
+fn leave_presence(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/presence/leave")?;
+    let body: PresenceLeaveRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
 
-    conn.execute(upsert, &params)
-        .map_err(|e| ServiceError::Internal(format!("Upsert failed: {}", e)))?;
+    verify_document_access(&conn, &document_id, &user_id)?;
 
-    json_response(200, serde_json::json!({
-        "updated_at": now.to_rfc3339()
-    }))
+    presence::leave(&conn, &document_id, &user_id, &body.session_id)?;
+    json_response(200, serde_json::json!({ "left": true, "session_id": body.session_id }))
 }
 
 //=============================================================================
@@ -581,11 +1158,12 @@ fn get_comments(req: &Request, path: &str) -> Result<Response, ServiceError> {
 
     verify_document_access(&conn, &document_id, &user_id)?;
 
+    // Ranges are kept current by `anchors`; orphaned comments list last
     let query = "SELECT c.id, c.user_id, c.content, c.position_start, c.position_end, 
-                 c.resolved, c.created_at, u.name, u.avatar_url
+                 c.resolved, c.created_at, u.name, u.avatar_url, c.block_id, c.orphaned, c.orphaned_at
                  FROM editor.comments c
                  LEFT JOIN users.users u ON c.user_id = u.id
-                 WHERE c.document_id = $1 ORDER BY c.position_start ASC";
+                 WHERE c.document_id = $1 ORDER BY c.orphaned ASC, c.position_start ASC";
 
     let params = [ParameterValue::Str(document_id.to_string())];
     let rows = conn.query(query, &params)
@@ -600,6 +1178,9 @@ fn get_comments(req: &Request, path: &str) -> Result<Response, ServiceError> {
                 "start": i32::decode(&row[3]).unwrap_or(0),
                 "end": i32::decode(&row[4]).unwrap_or(0)
             },
+            "block_id": String::decode(&row[9]).ok(),
+            "orphaned": bool::decode(&row[10]).unwrap_or(false),
+            "orphaned_at": String::decode(&row[11]).ok(),
             "resolved": bool::decode(&row[5]).unwrap_or(false),
             "created_at": String::decode(&row[6]).unwrap_or_default(),
             "user": {
@@ -609,7 +1190,14 @@ fn get_comments(req: &Request, path: &str) -> Result<Response, ServiceError> {
         })
     }).collect();
 
+    // Live ranges are valid against this version of the content
+    let version_query = "SELECT version FROM editor.documents WHERE id = $1";
+    let version_rows = conn.query(version_query, &params)
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let version = version_rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0);
+
     json_response(200, serde_json::json!({
+        "version": version,
         "comments": comments
     }))
 }
@@ -622,11 +1210,20 @@ fn add_comment(req: &Request, path: &str) -> Result<Response, ServiceError> {
 
     verify_document_access(&conn, &document_id, &user_id)?;
 
+    // Anchor to the block containing the comment unless the client named one
+    let block_id = match body.block_id.clone() {
+        Some(block_id) => {
+            verify_block_exists(&conn, &document_id, &block_id)?;
+            Some(block_id)
+        }
+        None => block_id_at(&conn, &document_id, body.position.start.max(0) as usize)?,
+    };
+
     let comment_id = Uuid::new_v4();
     let now = Utc::now();
 
-    let insert = "INSERT INTO editor.comments (id, document_id, user_id, content, position_start, position_end, created_at)
-                  VALUES ($1, $2, $3, $4, $5, $6, $7)";
+    let insert = "INSERT INTO editor.comments (id, document_id, user_id, content, position_start, position_end, block_id, created_at)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7, $8)";
 
     let params = [
         ParameterValue::Str(comment_id.to_string()),
@@ -635,16 +1232,24 @@ fn add_comment(req: &Request, path: &str) -> Result<Response, ServiceError> {
         ParameterValue::Str(body.content.clone()),
         ParameterValue::Int32(body.position.start),
         ParameterValue::Int32(body.position.end),
+        block_id.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
         ParameterValue::Str(now.to_rfc3339()),
     ];
 
     conn.execute(insert, &params)
         .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    webhooks::emit(&conn, &document_id, webhooks::COMMENT_ADDED, &comment_id.to_string(), serde_json::json!({
+        "comment_id": comment_id,
+        "content": body.content,
+        "block_id": block_id,
+        "author_id": user_id
+    }));
 
     json_response(201, serde_json::json!({
         "id": comment_id,
         "content": body.content,
         "position": body.position,
+        "block_id": block_id,
         "created_at": now.to_rfc3339()
     }))
 }
@@ -677,15 +1282,159 @@ fn delete_comment(req: &Request, path: &str) -> Result<Response, ServiceError> {
     }))
 }
 
+//=============================================================================
+// Blocks & Reactions
+//=============================================================================
+
+fn get_blocks(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/blocks")?;
+    let conn = get_db_connection()?;
+
+    verify_document_readable(&conn, &document_id, &user_id)?;
+
+    let (content, block_ids) = load_document_blocks(&conn, &document_id)?;
+
+    // Per-block activity counts double as the heatmap source
+    let counts_query = "SELECT block_id, 'comment' AS kind, COUNT(*) FROM editor.comments
+                        WHERE document_id = $1 AND block_id IS NOT NULL GROUP BY block_id
+                        UNION ALL
+                        SELECT block_id, reaction AS kind, COUNT(*) FROM editor.block_reactions
+                        WHERE document_id = $1 GROUP BY block_id, reaction";
+    let params = [ParameterValue::Str(document_id.to_string())];
+    let rows = conn.query(counts_query, &params)
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut comment_counts: std::collections::HashMap<String, i64> = std::collections::HashMap::new();
+    let mut reaction_counts: std::collections::HashMap<String, serde_json::Map<String, serde_json::Value>> =
+        std::collections::HashMap::new();
+    for row in &rows.rows {
+        let block_id = String::decode(&row[0]).unwrap_or_default();
+        let kind = String::decode(&row[1]).unwrap_or_default();
+        let count = i64::decode(&row[2]).unwrap_or(0);
+        if kind == "comment" {
+            comment_counts.insert(block_id, count);
+        } else {
+            reaction_counts.entry(block_id).or_default().insert(kind, count.into());
+        }
+    }
+
+    let blocks: Vec<serde_json::Value> = blocks::blocks_with_ids(&content, &block_ids).into_iter().map(|block| {
+        serde_json::json!({
+            "id": block.id,
+            "start": block.start,
+            "end": block.end,
+            "comments": comment_counts.get(&block.id).copied().unwrap_or(0),
+            "reactions": reaction_counts.remove(&block.id).unwrap_or_default()
+        })
+    }).collect();
+
+    json_response(200, serde_json::json!({
+        "document_id": document_id,
+        "blocks": blocks
+    }))
+}
+
+fn add_reaction(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let document_id = extract_document_id_from_sub_path(path, "/reactions")?;
+    let body: ReactionRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    verify_document_readable(&conn, &document_id, &user_id)?;
+    verify_block_exists(&conn, &document_id, &body.block_id)?;
+
+    if body.reaction.is_empty() || body.reaction.len() > 32 {
+        return Err(ServiceError::BadRequest("Reaction must be 1-32 characters".into()));
+    }
+
+    let now = Utc::now();
+    let upsert = "INSERT INTO editor.block_reactions (document_id, block_id, user_id, reaction, created_at)
+                  VALUES ($1, $2, $3, $4, $5)
+                  ON CONFLICT (document_id, block_id, user_id, reaction) DO NOTHING";
+    let params = [
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(body.block_id.clone()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(body.reaction.clone()),
+        ParameterValue::Str(now.to_rfc3339()),
+    ];
+    conn.execute(upsert, &params)
+        .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    json_response(201, serde_json::json!({
+        "block_id": body.block_id,
+        "reaction": body.reaction,
+        "created_at": now.to_rfc3339()
+    }))
+}
+
+fn load_document_blocks(conn: &Connection, document_id: &Uuid) -> Result<(String, Vec<String>), ServiceError> {
+    let query = "SELECT content, block_ids FROM editor.documents WHERE id = $1";
+    let params = [ParameterValue::Str(document_id.to_string())];
+    let rows = conn.query(query, &params)
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    if rows.rows.is_empty() {
+        return Err(ServiceError::NotFound("Document not found".into()));
+    }
+
+    let content = String::decode(&rows.rows[0][0]).unwrap_or_default();
+    let block_ids = blocks::reconcile_ids(&content, &decode_block_ids(&rows.rows[0][1]));
+    Ok((content, block_ids))
+}
+
+fn verify_block_exists(conn: &Connection, document_id: &Uuid, block_id: &str) -> Result<(), ServiceError> {
+    let (_, block_ids) = load_document_blocks(conn, document_id)?;
+    if !block_ids.iter().any(|id| id == block_id) {
+        return Err(ServiceError::NotFound("Block not found".into()));
+    }
+    Ok(())
+}
+
+fn block_id_at(conn: &Connection, document_id: &Uuid, position: usize) -> Result<Option<String>, ServiceError> {
+    match load_document_blocks(conn, document_id) {
+        Ok((content, block_ids)) => Ok(block_ids.get(blocks::block_index_at(&content, position)).cloned()),
+        Err(ServiceError::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn decode_rich_content(value: &spin_sdk::pg::DbValue) -> Option<Vec<DeltaOp>> {
+    String::decode(value)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+}
+
+fn encode_rich_content(rich_content: Option<&[DeltaOp]>) -> ParameterValue {
+    rich_content
+        .map(|doc| ParameterValue::Str(serde_json::to_string(doc).unwrap_or_default()))
+        .unwrap_or(ParameterValue::DbNull)
+}
+
+fn decode_block_ids(value: &spin_sdk::pg::DbValue) -> Vec<String> {
+    String::decode(value)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
 //=============================================================================
 // Operational Transformation
 //=============================================================================
 
 fn transform_operation(op: &Operation, against: &Operation) -> Operation {
     match (op, against) {
+        (Operation::Delta { ops, .. }, Operation::Delta { ops: other_ops, .. }) => {
+            Operation::Delta { ops: rich_text::transform(ops, other_ops), plain: Vec::new() }
+        }
+        // Plain-text edits move through the delta's plain-text equivalent
+        (_, Operation::Delta { plain, .. }) => {
+            plain.iter().fold(op.clone(), |op, step| ot::transform_against(&op, step))
+        }
         (Operation::Insert { position, text }, Operation::Insert { position: other_pos, text: other_text }) => {
             let new_pos = if *position >= *other_pos {
-                position + other_text.len() as i32
+                position + text::len16(other_text) as i32
             } else {
                 *position
             };
@@ -702,7 +1451,7 @@ fn transform_operation(op: &Operation, against: &Operation) -> Operation {
         }
         (Operation::Delete { position, length }, Operation::Insert { position: other_pos, text }) => {
             let new_pos = if *position >= *other_pos {
-                position + text.len() as i32
+                position + text::len16(text) as i32
             } else {
                 *position
             };
@@ -729,41 +1478,40 @@ fn transform_operation(op: &Operation, against: &Operation) -> Operation {
     }
 }
 
+fn encode_operation(op: Option<&Operation>) -> ParameterValue {
+    op.map(|op| ParameterValue::Str(serde_json::to_string(op).unwrap_or_default()))
+        .unwrap_or(ParameterValue::DbNull)
+}
+
+/// Positions and lengths are UTF-16 offsets; see `text`
 fn apply_operation(content: &str, op: &Operation) -> Result<String, ServiceError> {
     match op {
         Operation::Insert { position, text } => {
-            let pos = *position as usize;
-            if pos > content.len() {
-                return Err(ServiceError::BadRequest("Position out of bounds".into()));
-            }
+            let range = text::byte_range(content, *position, 0)?;
             let mut new_content = content.to_string();
-            new_content.insert_str(pos, text);
+            new_content.insert_str(range.start, text);
             Ok(new_content)
         }
         Operation::Delete { position, length } => {
-            let pos = *position as usize;
-            let len = *length as usize;
-            if pos + len > content.len() {
-                return Err(ServiceError::BadRequest("Delete range out of bounds".into()));
-            }
+            let range = text::byte_range(content, *position, *length)?;
             let mut new_content = content.to_string();
-            new_content.replace_range(pos..pos + len, "");
+            new_content.replace_range(range, "");
             Ok(new_content)
         }
         Operation::Replace { position, length, text } => {
-            let pos = *position as usize;
-            let len = *length as usize;
-            if pos + len > content.len() {
-                return Err(ServiceError::BadRequest("Replace range out of bounds".into()));
-            }
+            let range = text::byte_range(content, *position, *length)?;
             let mut new_content = content.to_string();
-            new_content.replace_range(pos..pos + len, text);
+            new_content.replace_range(range, text);
             Ok(new_content)
         }
         Operation::Revert { .. } => {
             // Revert is handled specially
             Ok(content.to_string())
         }
+        // Only stored deltas carry their plain-text steps; see `rich_text::apply`
+        Operation::Delta { plain, .. } => {
+            plain.iter().try_fold(content.to_string(), |content, step| apply_operation(&content, step))
+        }
     }
 }
 
@@ -790,6 +1538,25 @@ fn verify_document_access(conn: &Connection, document_id: &Uuid, user_id: &Uuid)
     Ok(())
 }
 
+/// Authors always have access; anyone signed in may read published books
+fn verify_document_readable(conn: &Connection, document_id: &Uuid, user_id: &Uuid) -> Result<(), ServiceError> {
+    let query = "SELECT 1 FROM content.chapters c
+                 JOIN content.books b ON c.book_id = b.id
+                 WHERE c.id = $1 AND (b.author_id = $2 OR b.status = 'published')";
+    let params = [
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ];
+
+    let rows = conn.query(query, &params)
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    if rows.rows.is_empty() {
+        return Err(ServiceError::Forbidden("Access denied".into()));
+    }
+    Ok(())
+}
+
 fn extract_document_id(path: &str) -> Result<Uuid, ServiceError> {
     let id_str = path.strip_prefix("/documents/")
         .ok_or_else(|| ServiceError::BadRequest("Invalid path".into()))?;
@@ -818,6 +1585,33 @@ fn extract_id_from_path(path: &str, prefix: &str) -> Result<Uuid, ServiceError>
         .map_err(|_| ServiceError::BadRequest("Invalid UUID".into()))
 }
 
+fn get_query_param(query: &str, key: &str) -> Option<String> {
+    query.split('&')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let k = parts.next()?;
+            let v = parts.next()?;
+            if k == key { Some(v.to_string()) } else { None }
+        })
+        .next()
+}
+
+fn parse_version_param(query: &str, key: &str) -> Result<Option<i64>, ServiceError> {
+    get_query_param(query, key)
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse::<i64>()
+            .map_err(|_| ServiceError::BadRequest(format!("{} must be a version number", key))))
+        .transpose()
+}
+
+fn parse_offset_param(query: &str, key: &str) -> Result<Option<usize>, ServiceError> {
+    get_query_param(query, key)
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse::<usize>()
+            .map_err(|_| ServiceError::BadRequest(format!("{} must be a non-negative offset", key))))
+        .transpose()
+}
+
 fn json_response<T: Serialize>(status: u16, body: T) -> Result<Response, ServiceError> {
     let json = serde_json::to_string(&body)
         .map_err(|e| ServiceError::Internal(format!("JSON error: {}", e)))?;
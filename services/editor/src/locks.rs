@@ -0,0 +1,100 @@
+//! Advisory document locks
+//!
+//! A lock gives one user exclusive write access for a TTL. The holder renews
+//! it by re-posting (heartbeat) before it expires; an expired lock is treated
+//! as absent and can be taken by anyone. Reads are never blocked.
+
+use crate::error::ServiceError;
+use serde::Serialize;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+pub const DEFAULT_TTL_SECONDS: i64 = 120;
+pub const MAX_TTL_SECONDS: i64 = 900;
+
+#[derive(Debug, Serialize)]
+pub struct DocumentLock {
+    pub user_id: Uuid,
+    pub acquired_at: String,
+    pub expires_at: String,
+}
+
+/// Current unexpired lock on the document, if any
+pub fn active_lock(conn: &Connection, document_id: &Uuid) -> Result<Option<DocumentLock>, ServiceError> {
+    let query = "SELECT user_id, acquired_at, expires_at FROM editor.document_locks
+                 WHERE document_id = $1 AND expires_at > NOW()";
+    let rows = conn.query(query, &[ParameterValue::Str(document_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first().map(|row| DocumentLock {
+        user_id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        acquired_at: String::decode(&row[1]).unwrap_or_default(),
+        expires_at: String::decode(&row[2]).unwrap_or_default(),
+    }))
+}
+
+/// Acquire the lock, or renew it if the caller already holds it
+pub fn acquire(conn: &Connection, document_id: &Uuid, user_id: &Uuid, ttl_seconds: Option<i64>) -> Result<DocumentLock, ServiceError> {
+    let ttl = ttl_seconds.unwrap_or(DEFAULT_TTL_SECONDS);
+    if !(1..=MAX_TTL_SECONDS).contains(&ttl) {
+        return Err(ServiceError::BadRequest(format!("ttl_seconds must be between 1 and {}", MAX_TTL_SECONDS)));
+    }
+
+    let now = Utc::now();
+    let expires_at = now + Duration::seconds(ttl);
+
+    // Only take over a row that is ours or has expired; renewal keeps acquired_at
+    let upsert = "INSERT INTO editor.document_locks (document_id, user_id, acquired_at, expires_at)
+                  VALUES ($1, $2, $3, $4)
+                  ON CONFLICT (document_id) DO UPDATE SET
+                  acquired_at = CASE WHEN editor.document_locks.user_id = EXCLUDED.user_id
+                                     AND editor.document_locks.expires_at > NOW()
+                                     THEN editor.document_locks.acquired_at ELSE EXCLUDED.acquired_at END,
+                  user_id = EXCLUDED.user_id,
+                  expires_at = EXCLUDED.expires_at
+                  WHERE editor.document_locks.user_id = EXCLUDED.user_id
+                     OR editor.document_locks.expires_at <= NOW()";
+    let count = conn.execute(upsert, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(now.to_rfc3339()),
+        ParameterValue::Str(expires_at.to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Upsert failed: {}", e)))?;
+
+    if count == 0 {
+        return Err(held_by_other(conn, document_id)?);
+    }
+
+    active_lock(conn, document_id)?
+        .ok_or_else(|| ServiceError::Internal("Lock not found after acquire".into()))
+}
+
+/// Release the caller's lock
+pub fn release(conn: &Connection, document_id: &Uuid, user_id: &Uuid) -> Result<(), ServiceError> {
+    let delete = "DELETE FROM editor.document_locks WHERE document_id = $1 AND user_id = $2";
+    let count = conn.execute(delete, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    if count == 0 {
+        return Err(ServiceError::NotFound("No lock held on this document".into()));
+    }
+    Ok(())
+}
+
+/// Reject writes from anyone but the holder while a lock is active
+pub fn ensure_can_edit(conn: &Connection, document_id: &Uuid, user_id: &Uuid) -> Result<(), ServiceError> {
+    match active_lock(conn, document_id)? {
+        Some(lock) if lock.user_id != *user_id => Err(held_by_other(conn, document_id)?),
+        _ => Ok(()),
+    }
+}
+
+fn held_by_other(conn: &Connection, document_id: &Uuid) -> Result<ServiceError, ServiceError> {
+    let until = active_lock(conn, document_id)?
+        .map(|lock| lock.expires_at)
+        .unwrap_or_default();
+    Ok(ServiceError::Conflict(format!("Document is locked by another user until {}", until)))
+}
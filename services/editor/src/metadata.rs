@@ -0,0 +1,204 @@
+//! Document metadata fields
+//!
+//! Chapter title, synopsis and notes live beside the OT-managed body as
+//! independent last-writer-wins registers. Each field carries a version
+//! vector counting writes per user. A client echoes the vector it last saw
+//! with every write: a write whose vector is strictly behind the stored one
+//! is stale and dropped, anything else wins and the vectors are merged. No
+//! metadata write is ever rejected with a conflict, and clients converge by
+//! adopting the returned state.
+
+use crate::error::ServiceError;
+use crate::models::{MetadataUpdateRequest, VersionVector};
+use serde::{Deserialize, Serialize};
+use spin_sdk::pg::{Connection, DbValue, Decode, ParameterValue};
+use chrono::Utc;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Editable fields and the longest value each accepts, in bytes
+pub const FIELDS: &[(&str, usize)] = &[
+    ("title", 500),
+    ("synopsis", 5_000),
+    ("notes", 50_000),
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FieldState {
+    pub value: Option<String>,
+    #[serde(default)]
+    pub clock: VersionVector,
+    pub updated_by: Option<Uuid>,
+    pub updated_at: Option<String>,
+}
+
+/// Field name to register, as stored in `editor.documents.metadata`
+pub type Metadata = BTreeMap<String, FieldState>;
+
+#[derive(Debug, PartialEq)]
+enum Causality {
+    Before,
+    Equal,
+    After,
+    Concurrent,
+}
+
+//=============================================================================
+// Storage
+//=============================================================================
+
+pub fn decode(value: &DbValue) -> Option<Metadata> {
+    String::decode(value)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+}
+
+pub fn encode(metadata: &Metadata) -> ParameterValue {
+    ParameterValue::Str(serde_json::to_string(metadata).unwrap_or_else(|_| "{}".into()))
+}
+
+/// Every known field, with unset ones present as empty registers
+pub fn with_all_fields(mut metadata: Metadata) -> Metadata {
+    for (name, _) in FIELDS {
+        metadata.entry(name.to_string()).or_default();
+    }
+    metadata
+}
+
+pub fn load(conn: &Connection, document_id: &Uuid) -> Result<(Metadata, i64), ServiceError> {
+    let query = "SELECT metadata::text, version FROM editor.documents WHERE id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(document_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Document not found".into()))?;
+    Ok((decode(&row[0]).unwrap_or_default(), i64::decode(&row[1]).unwrap_or(0)))
+}
+
+//=============================================================================
+// Writes
+//=============================================================================
+
+/// Apply a batch of field writes and report which were applied or stale
+pub fn update(
+    conn: &Connection,
+    document_id: &Uuid,
+    user_id: &Uuid,
+    body: MetadataUpdateRequest,
+) -> Result<serde_json::Value, ServiceError> {
+    if body.fields.is_empty() {
+        return Err(ServiceError::BadRequest("fields must not be empty".into()));
+    }
+    for (name, write) in &body.fields {
+        let max_len = FIELDS.iter()
+            .find(|(field, _)| *field == name.as_str())
+            .map(|(_, max_len)| *max_len)
+            .ok_or_else(|| ServiceError::BadRequest(format!("Unknown metadata field: {}", name)))?;
+        if write.value.as_ref().is_some_and(|v| v.len() > max_len) {
+            return Err(ServiceError::BadRequest(format!("{} must be at most {} bytes", name, max_len)));
+        }
+    }
+
+    let query = "SELECT metadata::text FROM editor.documents WHERE id = $1 FOR UPDATE";
+    let rows = conn.query(query, &[ParameterValue::Str(document_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let mut metadata = rows.rows.first()
+        .map(|row| decode(&row[0]).unwrap_or_default())
+        .ok_or_else(|| ServiceError::NotFound("Document not found".into()))?;
+
+    let now = Utc::now().to_rfc3339();
+    let mut applied = Vec::new();
+    let mut stale = Vec::new();
+
+    for (name, write) in body.fields {
+        let current = metadata.entry(name.clone()).or_default();
+        match compare(&write.clock, &current.clock) {
+            Causality::Before => stale.push(name),
+            // Equal or newer is a normal edit; concurrent resolves to the
+            // later arrival, which is this one
+            Causality::Equal | Causality::After | Causality::Concurrent => {
+                *current = FieldState {
+                    value: write.value,
+                    clock: advance(merge(&write.clock, &current.clock), user_id),
+                    updated_by: Some(*user_id),
+                    updated_at: Some(now.clone()),
+                };
+                applied.push(name);
+            }
+        }
+    }
+
+    save(conn, document_id, &metadata, &now)?;
+
+    Ok(serde_json::json!({
+        "id": document_id,
+        "metadata": with_all_fields(metadata),
+        "applied": applied,
+        "stale": stale
+    }))
+}
+
+/// Bring back a checkpoint's values as a fresh write by `user_id`, so the
+/// restored values dominate whatever clients currently hold
+pub fn restore(mut metadata: Metadata, snapshot: &Metadata, user_id: &Uuid) -> Metadata {
+    let now = Utc::now().to_rfc3339();
+
+    for (name, _) in FIELDS {
+        let value = snapshot.get(*name).and_then(|field| field.value.clone());
+        let current = metadata.entry(name.to_string()).or_default();
+        if current.value == value {
+            continue;
+        }
+        *current = FieldState {
+            value,
+            clock: advance(current.clock.clone(), user_id),
+            updated_by: Some(*user_id),
+            updated_at: Some(now.clone()),
+        };
+    }
+    metadata
+}
+
+pub fn save(conn: &Connection, document_id: &Uuid, metadata: &Metadata, now: &str) -> Result<(), ServiceError> {
+    let update = "UPDATE editor.documents SET metadata = $2::jsonb, updated_at = $3 WHERE id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(document_id.to_string()),
+        encode(metadata),
+        ParameterValue::Str(now.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+//=============================================================================
+// Version Vectors
+//=============================================================================
+
+fn compare(a: &VersionVector, b: &VersionVector) -> Causality {
+    let mut a_ahead = false;
+    let mut b_ahead = false;
+    for user in a.keys().chain(b.keys()) {
+        let (x, y) = (a.get(user).copied().unwrap_or(0), b.get(user).copied().unwrap_or(0));
+        a_ahead |= x > y;
+        b_ahead |= y > x;
+    }
+    match (a_ahead, b_ahead) {
+        (false, false) => Causality::Equal,
+        (true, false) => Causality::After,
+        (false, true) => Causality::Before,
+        (true, true) => Causality::Concurrent,
+    }
+}
+
+fn merge(a: &VersionVector, b: &VersionVector) -> VersionVector {
+    let mut merged = a.clone();
+    for (user, count) in b {
+        let entry = merged.entry(*user).or_insert(0);
+        *entry = (*entry).max(*count);
+    }
+    merged
+}
+
+fn advance(mut clock: VersionVector, user_id: &Uuid) -> VersionVector {
+    *clock.entry(*user_id).or_insert(0) += 1;
+    clock
+}
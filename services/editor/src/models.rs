@@ -1,12 +1,14 @@
 //! Data models for the Editor Service
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use uuid::Uuid;
 
 //=============================================================================
 // Operation Models
 //=============================================================================
 
+/// Positions and lengths count UTF-16 code units; see `text`
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Operation {
@@ -14,6 +16,34 @@ pub enum Operation {
     Delete { position: i32, length: i32 },
     Replace { position: i32, length: i32, text: String },
     Revert { checkpoint_id: Uuid },
+    /// Rich-text change in Quill delta form; see `rich_text`
+    Delta {
+        ops: Vec<DeltaOp>,
+        /// Plain-text equivalent, filled in when the delta is stored
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        plain: Vec<Operation>,
+    },
+}
+
+/// Formatting attributes, e.g. `{"bold": true, "header": 2}`. In a retain a
+/// `null` value removes the attribute.
+pub type Attributes = serde_json::Map<String, serde_json::Value>;
+
+/// One component of a Quill-compatible delta. Lengths count UTF-16 code units.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DeltaOp {
+    Insert {
+        insert: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        attributes: Option<Attributes>,
+    },
+    Retain {
+        retain: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        attributes: Option<Attributes>,
+    },
+    Delete { delete: usize },
 }
 
 #[derive(Debug, Deserialize)]
@@ -36,6 +66,90 @@ pub struct RevertRequest {
     pub checkpoint_id: Uuid,
 }
 
+//=============================================================================
+// Metadata Models
+//=============================================================================
+
+/// Writes seen per user for one metadata field
+pub type VersionVector = BTreeMap<Uuid, i64>;
+
+#[derive(Debug, Deserialize)]
+pub struct MetadataUpdateRequest {
+    /// Field name (`title`, `synopsis`, `notes`) to new value
+    pub fields: BTreeMap<String, MetadataWrite>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MetadataWrite {
+    /// `null` clears the field
+    pub value: Option<String>,
+    /// The field's clock as last returned to this client; omit for a first write
+    #[serde(default)]
+    pub clock: VersionVector,
+}
+
+//=============================================================================
+// Lock Models
+//=============================================================================
+
+#[derive(Debug, Default, Deserialize)]
+pub struct LockRequest {
+    /// Lock lifetime; re-post before it lapses to renew
+    pub ttl_seconds: Option<i64>,
+}
+
+//=============================================================================
+// AI Assist Models
+//=============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssistInstruction {
+    Rewrite,
+    Shorten,
+    Continue,
+    FixGrammar,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssistRequest {
+    /// Version the selection offsets refer to
+    pub base_version: i64,
+    pub selection: Selection,
+    pub instruction: AssistInstruction,
+    /// Optional steer for the model, e.g. "more formal"
+    pub guidance: Option<String>,
+    /// Apply the candidate as an edit instead of only returning it
+    #[serde(default)]
+    pub apply: bool,
+}
+
+//=============================================================================
+// Share Link Models
+//=============================================================================
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ShareLinkRequest {
+    /// Link lifetime; defaults to `share_links::DEFAULT_TTL_HOURS`
+    pub expires_in_hours: Option<i64>,
+    /// Who the link was made for, e.g. "Agent - Jane"
+    pub label: Option<String>,
+}
+
+//=============================================================================
+// Webhook Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookRequest {
+    pub url: String,
+    /// Any of `checkpoint.created`, `version.milestone`, `comment.added`
+    pub events: Vec<String>,
+    /// Word counts that fire `version.milestone`, e.g. `[10000, 50000]`
+    #[serde(default)]
+    pub word_milestones: Vec<i32>,
+}
+
 //=============================================================================
 // Presence Models
 //=============================================================================
@@ -46,12 +160,28 @@ pub struct Selection {
     pub end: i32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct PresenceUpdate {
+    /// Session from `POST /presence/join`; without it the caller's latest
+    /// live session is used, or one is opened
+    pub session_id: Option<Uuid>,
     pub cursor_position: Option<i32>,
     pub selection: Option<Selection>,
 }
 
+#[derive(Debug, Default, Deserialize)]
+pub struct PresenceJoinRequest {
+    /// Shown to collaborators, e.g. "Firefox on macOS"
+    pub client: Option<String>,
+    pub cursor_position: Option<i32>,
+    pub selection: Option<Selection>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PresenceLeaveRequest {
+    pub session_id: Uuid,
+}
+
 //=============================================================================
 // Comment Models
 //=============================================================================
@@ -66,5 +196,17 @@ pub struct Position {
 pub struct CommentRequest {
     pub content: String,
     pub position: Position,
+    /// Stable block to anchor to; derived from `position` when omitted
+    pub block_id: Option<String>,
+}
+
+//=============================================================================
+// Block Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct ReactionRequest {
+    pub block_id: String,
+    pub reaction: String,
 }
 
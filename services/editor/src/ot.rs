@@ -1,4 +1,51 @@
 //! Operational Transformation utilities
 //!
-//! Core OT logic is implemented in lib.rs
+//! Core OT logic is implemented in lib.rs; this module adds inversion and
+//! transformation helpers used by undo/redo.
 
+use crate::models::Operation;
+use crate::text;
+
+/// Inverse of `op` given the content it was applied to, or `None` for
+/// operations that cannot be inverted (checkpoint reverts)
+pub fn invert_operation(content: &str, op: &Operation) -> Option<Operation> {
+    match op {
+        Operation::Insert { position, text } => Some(Operation::Delete {
+            position: *position,
+            length: text::len16(text) as i32,
+        }),
+        Operation::Delete { position, length } => {
+            let removed = &content[text::byte_range(content, *position, *length).ok()?];
+            Some(Operation::Insert { position: *position, text: removed.to_string() })
+        }
+        Operation::Replace { position, length, text } => {
+            let removed = &content[text::byte_range(content, *position, *length).ok()?];
+            Some(Operation::Replace {
+                position: *position,
+                length: text::len16(text) as i32,
+                text: removed.to_string(),
+            })
+        }
+        // Deltas are inverted against the rich document; see `rich_text::apply`
+        Operation::Revert { .. } | Operation::Delta { .. } => None,
+    }
+}
+
+/// Transform `op` so it applies after `against`, treating a replace as a
+/// delete followed by an insert at the same position
+pub fn transform_against(op: &Operation, against: &Operation) -> Operation {
+    if let Operation::Replace { position, length, text } = against {
+        let after_delete = transform_against(op, &Operation::Delete { position: *position, length: *length });
+        return transform_against(&after_delete, &Operation::Insert { position: *position, text: text.clone() });
+    }
+
+    match op {
+        Operation::Replace { position, length, text } => {
+            match crate::transform_operation(&Operation::Delete { position: *position, length: *length }, against) {
+                Operation::Delete { position, length } => Operation::Replace { position, length, text: text.clone() },
+                other => other,
+            }
+        }
+        _ => crate::transform_operation(op, against),
+    }
+}
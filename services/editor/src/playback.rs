@@ -0,0 +1,193 @@
+//! Document playback
+//!
+//! Replays a document's operation log as a compact frame sequence so a client
+//! can animate the text being written. The response carries the content at
+//! `from` plus one frame per operation up to `to`:
+//!
+//! - `[dt_ms, author, "i", position, text]` - insert
+//! - `[dt_ms, author, "d", position, length]` - delete
+//! - `[dt_ms, author, "r", position, length, text]` - replace
+//! - `[dt_ms, author, "s", content]` - snapshot (checkpoint revert)
+//!
+//! Playback is plain text: a rich-text delta plays as its plain-text steps,
+//! the first carrying the delay and the rest following immediately.
+//!
+//! `dt_ms` is the delay since the previous frame (the first frame is relative
+//! to `start_time`) and `author` indexes into `authors`.
+
+use crate::error::ServiceError;
+use crate::models::Operation;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::{DateTime, FixedOffset};
+use uuid::Uuid;
+
+/// Upper bound on frames per response; clients page with `next_from`
+const MAX_FRAMES: i64 = 2000;
+
+/// GET /documents/:id/playback - Operation frames between two versions
+pub fn get_playback(conn: &Connection, document_id: &Uuid, from: Option<i64>, to: Option<i64>) -> Result<Response, ServiceError> {
+    let doc_query = "SELECT version, compacted_version FROM editor.documents WHERE id = $1";
+    let doc_rows = conn.query(doc_query, &[ParameterValue::Str(document_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let (current_version, compacted_version) = doc_rows.rows.first()
+        .map(|row| (i64::decode(&row[0]).unwrap_or(0), i64::decode(&row[1]).unwrap_or(0)))
+        .ok_or_else(|| ServiceError::NotFound("Document not found".into()))?;
+
+    // Operations before the compacted version no longer exist to replay
+    let from = from.unwrap_or(compacted_version);
+    let to = to.unwrap_or(current_version).min(current_version);
+    if from < compacted_version {
+        return Err(ServiceError::BadRequest(format!(
+            "History before version {} has been compacted", compacted_version
+        )));
+    }
+    if from > to {
+        return Err(ServiceError::BadRequest("from must be between 0 and to".into()));
+    }
+    let page_end = to.min(from + MAX_FRAMES);
+
+    let base_content = content_at_version(conn, document_id, from)?;
+
+    let ops_query = "SELECT o.version, o.operation, o.created_at, o.user_id, u.name, c.content, o.attribution
+                     FROM editor.operations o
+                     LEFT JOIN users.users u ON o.user_id = u.id
+                     LEFT JOIN editor.checkpoints c ON c.id::text = o.operation->>'checkpoint_id'
+                     WHERE o.document_id = $1 AND o.version > $2 AND o.version <= $3
+                     ORDER BY o.version ASC";
+    let rows = conn.query(ops_query, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Int64(from),
+        ParameterValue::Int64(page_end),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut authors: Vec<serde_json::Value> = Vec::new();
+    let mut author_keys: Vec<String> = Vec::new();
+    let mut frames: Vec<serde_json::Value> = Vec::with_capacity(rows.rows.len());
+    let mut start_time: Option<String> = None;
+    let mut previous: Option<DateTime<FixedOffset>> = None;
+
+    for row in &rows.rows {
+        let operation: Operation = serde_json::from_str(&String::decode(&row[1]).unwrap_or_default())
+            .map_err(|e| ServiceError::Internal(format!("Parse failed: {}", e)))?;
+        let created_at = String::decode(&row[2]).unwrap_or_default();
+        let timestamp = parse_timestamp(&created_at);
+
+        let author_id = String::decode(&row[3]).unwrap_or_default();
+        // Assistant edits get their own author entry so playback can tell them apart
+        let attribution = String::decode(&row[6]).ok();
+        let author_key = match attribution.as_deref() {
+            Some(attribution) => format!("{}:{}", attribution, author_id),
+            None => author_id.clone(),
+        };
+        let author = match author_keys.iter().position(|key| *key == author_key) {
+            Some(index) => index,
+            None => {
+                let name = match attribution.as_deref() {
+                    Some(crate::assist::ATTRIBUTION) => Some(crate::assist::ATTRIBUTION_LABEL.to_string()),
+                    _ => String::decode(&row[4]).ok(),
+                };
+                authors.push(serde_json::json!({
+                    "id": author_id,
+                    "name": name,
+                    "attribution": attribution
+                }));
+                author_keys.push(author_key);
+                author_keys.len() - 1
+            }
+        };
+
+        if start_time.is_none() {
+            start_time = Some(created_at.clone());
+        }
+        let dt_ms = match (previous, timestamp) {
+            (Some(prev), Some(ts)) => (ts - prev).num_milliseconds().max(0),
+            _ => 0,
+        };
+        if timestamp.is_some() {
+            previous = timestamp;
+        }
+
+        match operation {
+            Operation::Revert { .. } => {
+                frames.push(serde_json::json!([dt_ms, author, "s", String::decode(&row[5]).unwrap_or_default()]));
+            }
+            Operation::Delta { plain, .. } => {
+                for (i, step) in plain.iter().enumerate() {
+                    frames.extend(plain_frame(if i == 0 { dt_ms } else { 0 }, author, step));
+                }
+            }
+            op => frames.extend(plain_frame(dt_ms, author, &op)),
+        }
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "document_id": document_id,
+        "from": from,
+        "to": page_end,
+        "next_from": if page_end < to { Some(page_end) } else { None },
+        "base_content": base_content,
+        "start_time": start_time,
+        "authors": authors,
+        "frames": frames
+    }))
+}
+
+fn plain_frame(dt_ms: i64, author: usize, op: &Operation) -> Option<serde_json::Value> {
+    match op {
+        Operation::Insert { position, text } => Some(serde_json::json!([dt_ms, author, "i", position, text])),
+        Operation::Delete { position, length } => Some(serde_json::json!([dt_ms, author, "d", position, length])),
+        Operation::Replace { position, length, text } => Some(serde_json::json!([dt_ms, author, "r", position, length, text])),
+        Operation::Revert { .. } | Operation::Delta { .. } => None,
+    }
+}
+
+/// Rebuild the document text as of `version`, starting from the nearest
+/// checkpoint at or before it
+pub(crate) fn content_at_version(conn: &Connection, document_id: &Uuid, version: i64) -> Result<String, ServiceError> {
+    let cp_query = "SELECT content, version FROM editor.checkpoints
+                    WHERE document_id = $1 AND version <= $2
+                    ORDER BY version DESC LIMIT 1";
+    let cp_rows = conn.query(cp_query, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Int64(version),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let (mut content, base_version) = match cp_rows.rows.first() {
+        Some(row) => (String::decode(&row[0]).unwrap_or_default(), i64::decode(&row[1]).unwrap_or(0)),
+        None => (String::new(), 0),
+    };
+    if base_version == version {
+        return Ok(content);
+    }
+
+    let ops_query = "SELECT o.operation, c.content
+                     FROM editor.operations o
+                     LEFT JOIN editor.checkpoints c ON c.id::text = o.operation->>'checkpoint_id'
+                     WHERE o.document_id = $1 AND o.version > $2 AND o.version <= $3
+                     ORDER BY o.version ASC";
+    let rows = conn.query(ops_query, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Int64(base_version),
+        ParameterValue::Int64(version),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    for row in &rows.rows {
+        let operation: Operation = serde_json::from_str(&String::decode(&row[0]).unwrap_or_default())
+            .map_err(|e| ServiceError::Internal(format!("Parse failed: {}", e)))?;
+        content = match operation {
+            Operation::Revert { .. } => String::decode(&row[1]).unwrap_or_default(),
+            op => crate::apply_operation(&content, &op)
+                .map_err(|_| ServiceError::Internal("Operation log is inconsistent".into()))?,
+        };
+    }
+
+    Ok(content)
+}
+
+/// Accept both RFC 3339 and Postgres' default `timestamptz` text output
+fn parse_timestamp(value: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(value)
+        .or_else(|_| DateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f%#z"))
+        .ok()
+}
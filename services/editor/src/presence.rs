@@ -0,0 +1,341 @@
+//! Collaborator presence
+//!
+//! Each open editor tab joins the document as a session and gets a cursor
+//! color. A user keeps one color across all their live sessions, and gets the
+//! same color back on rejoining while no one else has taken it. Sessions
+//! stay live while heartbeats (`POST /presence`) arrive within the TTL; the
+//! server marks silent sessions as timed out, and `POST /presence/leave`
+//! ends one at once. Ended sessions are reported as departures so clients
+//! can drop the cursor.
+
+use crate::error::ServiceError;
+use crate::models::{PresenceJoinRequest, PresenceUpdate, Selection};
+use serde::Serialize;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use uuid::Uuid;
+
+const DEFAULT_TTL_SECONDS: i32 = 30;
+
+/// Departures are reported for this long unless the client passes `since`
+const DEPARTURE_WINDOW_SECONDS: i32 = 300;
+
+const MAX_CLIENT_LABEL_LENGTH: usize = 100;
+
+/// Distinct on light and dark backgrounds; assigned in order
+const PALETTE: &[&str] = &[
+    "#e6194b", "#3cb44b", "#4363d8", "#f58231", "#911eb4", "#42d4f4",
+    "#f032e6", "#469990", "#9a6324", "#800000", "#808000", "#000075",
+];
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Serialize)]
+pub struct JoinedSession {
+    pub session_id: Uuid,
+    pub color: String,
+    pub joined_at: String,
+    pub expires_at: String,
+    pub ttl_seconds: i32,
+    /// Heartbeat at least this often to stay live
+    pub heartbeat_interval_seconds: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Heartbeat {
+    pub session_id: Uuid,
+    pub color: String,
+    pub updated_at: String,
+    pub expires_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PresenceSession {
+    pub session_id: Uuid,
+    pub user_id: Uuid,
+    pub color: String,
+    pub client: Option<String>,
+    pub cursor_position: Option<i32>,
+    pub selection: Option<Selection>,
+    pub joined_at: String,
+    pub last_seen_at: String,
+    pub expires_at: String,
+    pub user: PresenceUser,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PresenceUser {
+    pub name: Option<String>,
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Departure {
+    pub session_id: Uuid,
+    pub user_id: Uuid,
+    pub color: String,
+    pub left_at: String,
+    /// `leave` or `timeout`
+    pub reason: String,
+}
+
+//=============================================================================
+// Sessions
+//=============================================================================
+
+/// Open a session for the caller and assign its color
+pub fn join(conn: &Connection, document_id: &Uuid, user_id: &Uuid, body: PresenceJoinRequest) -> Result<JoinedSession, ServiceError> {
+    let ttl = ttl_seconds();
+    expire_stale(conn, document_id, ttl)?;
+
+    let client = body.client.map(|c| c.trim().to_string()).filter(|c| !c.is_empty());
+    if client.as_ref().is_some_and(|c| c.chars().count() > MAX_CLIENT_LABEL_LENGTH) {
+        return Err(ServiceError::BadRequest(format!("client must be at most {} characters", MAX_CLIENT_LABEL_LENGTH)));
+    }
+
+    let color = assign_color(conn, document_id, user_id)?;
+    let session_id = Uuid::new_v4();
+
+    let insert = "INSERT INTO editor.presence_sessions
+                  (id, document_id, user_id, color, client_label, cursor_position, selection_start, selection_end)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                  RETURNING joined_at::text, (joined_at + $9 * INTERVAL '1 second')::text";
+    let rows = conn.query(insert, &[
+        ParameterValue::Str(session_id.to_string()),
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(color.clone()),
+        client.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        optional_int(body.cursor_position),
+        optional_int(body.selection.as_ref().map(|s| s.start)),
+        optional_int(body.selection.as_ref().map(|s| s.end)),
+        ParameterValue::Int32(ttl),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::Internal("Presence session was not created".into()))?;
+
+    Ok(JoinedSession {
+        session_id,
+        color,
+        joined_at: String::decode(&row[0]).unwrap_or_default(),
+        expires_at: String::decode(&row[1]).unwrap_or_default(),
+        ttl_seconds: ttl,
+        heartbeat_interval_seconds: (ttl / 3).max(1),
+    })
+}
+
+/// Refresh a live session and update its cursor. Fields left out of the
+/// update keep their previous values.
+pub fn heartbeat(conn: &Connection, document_id: &Uuid, user_id: &Uuid, body: PresenceUpdate) -> Result<Heartbeat, ServiceError> {
+    let ttl = ttl_seconds();
+    expire_stale(conn, document_id, ttl)?;
+
+    let session_id = match body.session_id {
+        Some(id) => id,
+        None => match latest_live_session(conn, document_id, user_id)? {
+            Some(id) => id,
+            None => {
+                let join_body = PresenceJoinRequest {
+                    client: None,
+                    cursor_position: body.cursor_position,
+                    selection: body.selection.clone(),
+                };
+                join(conn, document_id, user_id, join_body)?.session_id
+            }
+        },
+    };
+
+    let update = "UPDATE editor.presence_sessions SET
+                  cursor_position = COALESCE($4, cursor_position),
+                  selection_start = COALESCE($5, selection_start),
+                  selection_end = COALESCE($6, selection_end),
+                  last_seen_at = NOW()
+                  WHERE id = $1 AND document_id = $2 AND user_id = $3 AND left_at IS NULL
+                  RETURNING color, last_seen_at::text, (last_seen_at + $7 * INTERVAL '1 second')::text";
+    let rows = conn.query(update, &[
+        ParameterValue::Str(session_id.to_string()),
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        optional_int(body.cursor_position),
+        optional_int(body.selection.as_ref().map(|s| s.start)),
+        optional_int(body.selection.as_ref().map(|s| s.end)),
+        ParameterValue::Int32(ttl),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Presence session has ended; join again".into()))?;
+
+    Ok(Heartbeat {
+        session_id,
+        color: String::decode(&row[0]).unwrap_or_default(),
+        updated_at: String::decode(&row[1]).unwrap_or_default(),
+        expires_at: String::decode(&row[2]).unwrap_or_default(),
+    })
+}
+
+/// End one of the caller's sessions
+pub fn leave(conn: &Connection, document_id: &Uuid, user_id: &Uuid, session_id: &Uuid) -> Result<(), ServiceError> {
+    let update = "UPDATE editor.presence_sessions SET left_at = NOW(), left_reason = 'leave'
+                  WHERE id = $1 AND document_id = $2 AND user_id = $3 AND left_at IS NULL";
+    let count = conn.execute(update, &[
+        ParameterValue::Str(session_id.to_string()),
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    if count == 0 {
+        return Err(ServiceError::NotFound("Presence session not found or already ended".into()));
+    }
+    Ok(())
+}
+
+/// Live sessions, and sessions that ended after `since` (RFC 3339) or within
+/// the departure window
+pub fn list(conn: &Connection, document_id: &Uuid, since: Option<&str>) -> Result<(Vec<PresenceSession>, Vec<Departure>), ServiceError> {
+    let ttl = ttl_seconds();
+    expire_stale(conn, document_id, ttl)?;
+
+    let query = "SELECT s.id, s.user_id, s.color, s.client_label, s.cursor_position, s.selection_start,
+                        s.selection_end, s.joined_at::text, s.last_seen_at::text,
+                        (s.last_seen_at + $2 * INTERVAL '1 second')::text, u.name, u.avatar_url
+                 FROM editor.presence_sessions s
+                 LEFT JOIN users.users u ON s.user_id = u.id
+                 WHERE s.document_id = $1 AND s.left_at IS NULL
+                 ORDER BY s.joined_at";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Int32(ttl),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let sessions = rows.rows.iter().map(|row| {
+        let selection = match (i32::decode(&row[5]).ok(), i32::decode(&row[6]).ok()) {
+            (Some(start), Some(end)) => Some(Selection { start, end }),
+            _ => None,
+        };
+        PresenceSession {
+            session_id: parse_uuid(&row[0]),
+            user_id: parse_uuid(&row[1]),
+            color: String::decode(&row[2]).unwrap_or_default(),
+            client: String::decode(&row[3]).ok(),
+            cursor_position: i32::decode(&row[4]).ok(),
+            selection,
+            joined_at: String::decode(&row[7]).unwrap_or_default(),
+            last_seen_at: String::decode(&row[8]).unwrap_or_default(),
+            expires_at: String::decode(&row[9]).unwrap_or_default(),
+            user: PresenceUser {
+                name: String::decode(&row[10]).ok(),
+                avatar_url: String::decode(&row[11]).ok(),
+            },
+        }
+    }).collect();
+
+    let query = "SELECT id, user_id, color, left_at::text, left_reason
+                 FROM editor.presence_sessions
+                 WHERE document_id = $1 AND left_at IS NOT NULL
+                   AND left_at > COALESCE($2::timestamptz, NOW() - $3 * INTERVAL '1 second')
+                 ORDER BY left_at DESC";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(document_id.to_string()),
+        since.map(|s| ParameterValue::Str(s.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Int32(DEPARTURE_WINDOW_SECONDS),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let departures = rows.rows.iter().map(|row| Departure {
+        session_id: parse_uuid(&row[0]),
+        user_id: parse_uuid(&row[1]),
+        color: String::decode(&row[2]).unwrap_or_default(),
+        left_at: String::decode(&row[3]).unwrap_or_default(),
+        reason: String::decode(&row[4]).unwrap_or_default(),
+    }).collect();
+
+    Ok((sessions, departures))
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+fn ttl_seconds() -> i32 {
+    variables::get("presence_ttl_seconds")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|ttl| (5..=600).contains(ttl))
+        .unwrap_or(DEFAULT_TTL_SECONDS)
+}
+
+/// Time out sessions whose heartbeats stopped, stamping the moment they
+/// lapsed, and drop ended sessions nobody needs for color history any more
+fn expire_stale(conn: &Connection, document_id: &Uuid, ttl: i32) -> Result<(), ServiceError> {
+    let update = "UPDATE editor.presence_sessions
+                  SET left_at = last_seen_at + $2 * INTERVAL '1 second', left_reason = 'timeout'
+                  WHERE document_id = $1 AND left_at IS NULL
+                    AND last_seen_at <= NOW() - $2 * INTERVAL '1 second'";
+    conn.execute(update, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Int32(ttl),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let delete = "DELETE FROM editor.presence_sessions
+                  WHERE document_id = $1 AND left_at < NOW() - INTERVAL '7 days'";
+    conn.execute(delete, &[ParameterValue::Str(document_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    Ok(())
+}
+
+/// The user's current or previous color if no one else holds it, otherwise
+/// the first free palette color; a full palette falls back to a color
+/// derived from the user ID
+fn assign_color(conn: &Connection, document_id: &Uuid, user_id: &Uuid) -> Result<String, ServiceError> {
+    let query = "SELECT color FROM editor.presence_sessions
+                 WHERE document_id = $1 AND user_id <> $2 AND left_at IS NULL";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let taken: Vec<String> = rows.rows.iter()
+        .filter_map(|row| String::decode(&row[0]).ok())
+        .collect();
+
+    let query = "SELECT color FROM editor.presence_sessions
+                 WHERE document_id = $1 AND user_id = $2
+                 ORDER BY (left_at IS NULL) DESC, joined_at DESC
+                 LIMIT 1";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let previous = rows.rows.first().and_then(|row| String::decode(&row[0]).ok());
+
+    if let Some(color) = previous.filter(|c| !taken.contains(c)) {
+        return Ok(color);
+    }
+
+    let free = PALETTE.iter().find(|c| !taken.iter().any(|t| t == *c));
+    Ok(free.copied().unwrap_or_else(|| {
+        let index = user_id.as_bytes().iter().map(|b| *b as usize).sum::<usize>() % PALETTE.len();
+        PALETTE[index]
+    }).to_string())
+}
+
+fn latest_live_session(conn: &Connection, document_id: &Uuid, user_id: &Uuid) -> Result<Option<Uuid>, ServiceError> {
+    let query = "SELECT id FROM editor.presence_sessions
+                 WHERE document_id = $1 AND user_id = $2 AND left_at IS NULL
+                 ORDER BY last_seen_at DESC
+                 LIMIT 1";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first().map(|row| parse_uuid(&row[0])))
+}
+
+fn optional_int(value: Option<i32>) -> ParameterValue {
+    value.map(ParameterValue::Int32).unwrap_or(ParameterValue::DbNull)
+}
+
+fn parse_uuid(value: &spin_sdk::pg::DbValue) -> Uuid {
+    Uuid::parse_str(&String::decode(value).unwrap_or_default()).unwrap_or_default()
+}
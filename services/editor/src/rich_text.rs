@@ -0,0 +1,479 @@
+//! Rich-text operations
+//!
+//! Formatted text is edited with Quill-compatible deltas: a list of `insert`,
+//! `retain` and `delete` components, where inserts and retains carry an
+//! optional attribute map (`{"bold": true}`, `{"header": 2}`). Lengths count
+//! UTF-16 code units as in Quill; only text inserts are supported, not embeds.
+//!
+//! A document's structure is itself a delta made only of inserts, stored as
+//! JSON in `editor.documents.rich_content`. `content` remains its plain-text
+//! projection, so word counts, blocks, comments and playback keep working on
+//! plain text. A document turns rich on its first delta; from then on
+//! plain-text operations are converted to deltas before they are applied.
+//!
+//! Stored deltas carry their plain-text equivalent (`plain`), so a
+//! stale plain-text operation can still be transformed past them. A stale
+//! delta cannot be transformed past a plain-text operation, which only happens
+//! across the switch to rich text, and is rejected as a conflict.
+
+use crate::error::ServiceError;
+use crate::models::{Attributes, DeltaOp, Operation};
+use crate::ot;
+use crate::text::{self, len16};
+
+/// Document state after an operation, with the operation as it is logged
+pub struct Applied {
+    pub content: String,
+    /// `None` while the document is still plain text
+    pub rich_content: Option<Vec<DeltaOp>>,
+    pub operation: Operation,
+    pub inverse: Option<Operation>,
+}
+
+//=============================================================================
+// Applying Operations
+//=============================================================================
+
+/// Apply `op` to a document given its plain text and, if it is rich, its
+/// structure. `op` must already be transformed to the current version.
+pub fn apply(content: &str, rich_content: Option<&[DeltaOp]>, op: Operation) -> Result<Applied, ServiceError> {
+    let change = match (&op, rich_content) {
+        (Operation::Delta { ops, .. }, _) => normalize(ops.clone()),
+        (Operation::Revert { .. }, _) | (_, None) => {
+            // Plain text stays plain text; reverts are applied by the caller
+            let new_content = crate::apply_operation(content, &op)?;
+            text::validate_document(&new_content)?;
+            return Ok(Applied {
+                content: new_content,
+                rich_content: rich_content.map(|doc| doc.to_vec()),
+                inverse: ot::invert_operation(content, &op),
+                operation: op,
+            });
+        }
+        (_, Some(_)) => {
+            crate::apply_operation(content, &op)?;
+            from_plain_operation(content, &op)?
+        }
+    };
+
+    let document = rich_content.map(|doc| doc.to_vec()).unwrap_or_else(|| from_plain(content));
+    let new_document = compose(&document, &change)?;
+    let new_content = plain_text(&new_document);
+    text::validate_document(&new_content)?;
+    let inverse = invert(&change, &document);
+    let plain = match op {
+        Operation::Delta { .. } => to_plain_operations(content, &change),
+        plain_op => vec![plain_op],
+    };
+
+    Ok(Applied {
+        content: new_content,
+        rich_content: Some(new_document),
+        operation: Operation::Delta { ops: change, plain },
+        inverse: Some(Operation::Delta { ops: inverse, plain: Vec::new() }),
+    })
+}
+
+/// Whether `op` can be transformed past `against`. Deltas only move through
+/// other deltas; plain-text operations move through anything.
+pub fn can_transform(op: &Operation, against: &Operation) -> bool {
+    !matches!(
+        (op, against),
+        (Operation::Delta { .. }, Operation::Insert { .. } | Operation::Delete { .. } | Operation::Replace { .. })
+    )
+}
+
+/// A single-insert document holding `text` without formatting
+pub fn from_plain(text: &str) -> Vec<DeltaOp> {
+    normalize(vec![DeltaOp::Insert { insert: text.to_string(), attributes: None }])
+}
+
+/// The text of a document, without formatting
+pub fn plain_text(document: &[DeltaOp]) -> String {
+    document.iter()
+        .filter_map(|op| match op {
+            DeltaOp::Insert { insert, .. } => Some(insert.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+//=============================================================================
+// Delta Algebra
+//=============================================================================
+
+/// Apply `change` to `document`, a delta of inserts
+pub fn compose(document: &[DeltaOp], change: &[DeltaOp]) -> Result<Vec<DeltaOp>, ServiceError> {
+    let consumed: usize = change.iter()
+        .map(|op| match op {
+            DeltaOp::Insert { .. } => 0,
+            other => op_len(other),
+        })
+        .sum();
+    if consumed > length(document) {
+        return Err(ServiceError::BadRequest("Delta retains or deletes past the end of the document".into()));
+    }
+
+    let mut result = Vec::new();
+    let mut iter = OpIter::new(document);
+    for op in change {
+        match op {
+            DeltaOp::Insert { .. } => push(&mut result, op.clone()),
+            DeltaOp::Retain { retain, attributes } => {
+                let mut remaining = *retain;
+                while remaining > 0 && iter.has_next() {
+                    let piece = iter.next(remaining);
+                    remaining = remaining.saturating_sub(op_len(&piece));
+                    if let DeltaOp::Insert { insert, attributes: existing } = piece {
+                        let attributes = compose_attributes(existing.as_ref(), attributes.as_ref());
+                        push(&mut result, DeltaOp::Insert { insert, attributes });
+                    }
+                }
+            }
+            DeltaOp::Delete { delete } => {
+                let mut remaining = *delete;
+                while remaining > 0 && iter.has_next() {
+                    remaining = remaining.saturating_sub(op_len(&iter.next(remaining)));
+                }
+            }
+        }
+    }
+    while iter.has_next() {
+        push(&mut result, iter.next(usize::MAX));
+    }
+    Ok(result)
+}
+
+/// Transform `op` to apply after `against`, which was applied first and wins
+/// ties between inserts at the same position
+pub fn transform(op: &[DeltaOp], against: &[DeltaOp]) -> Vec<DeltaOp> {
+    // Empty components would never advance the iterators
+    let (op, against) = (normalize(op.to_vec()), normalize(against.to_vec()));
+    let mut result = Vec::new();
+    let mut theirs = OpIter::new(&against);
+    let mut ours = OpIter::new(&op);
+
+    while theirs.has_next() || ours.has_next() {
+        if theirs.peek_is_insert() {
+            let inserted = op_len(&theirs.next(usize::MAX));
+            push(&mut result, DeltaOp::Retain { retain: inserted, attributes: None });
+        } else if ours.peek_is_insert() {
+            push(&mut result, ours.next(usize::MAX));
+        } else {
+            let len = theirs.peek_len().min(ours.peek_len());
+            let their_op = theirs.next(len);
+            let our_op = ours.next(len);
+            match (their_op, our_op) {
+                // Text they deleted is gone, whatever we wanted to do with it
+                (DeltaOp::Delete { .. }, _) => {}
+                (_, our_op @ DeltaOp::Delete { .. }) => push(&mut result, our_op),
+                (their_op, our_op) => {
+                    let attributes = transform_attributes(attributes_of(&their_op), attributes_of(&our_op));
+                    push(&mut result, DeltaOp::Retain { retain: len, attributes });
+                }
+            }
+        }
+    }
+    chop(result)
+}
+
+/// The delta that undoes `change` when applied after it. `document` is the
+/// document `change` was applied to.
+pub fn invert(change: &[DeltaOp], document: &[DeltaOp]) -> Vec<DeltaOp> {
+    let mut result = Vec::new();
+    let mut base = OpIter::new(document);
+
+    for op in change {
+        match op {
+            DeltaOp::Insert { insert, .. } => {
+                push(&mut result, DeltaOp::Delete { delete: len16(insert) });
+            }
+            DeltaOp::Retain { retain, attributes: None } => {
+                advance(&mut base, *retain);
+                push(&mut result, DeltaOp::Retain { retain: *retain, attributes: None });
+            }
+            DeltaOp::Retain { retain, attributes: Some(attributes) } => {
+                let mut remaining = *retain;
+                while remaining > 0 && base.has_next() {
+                    let piece = base.next(remaining);
+                    let piece_len = op_len(&piece);
+                    remaining = remaining.saturating_sub(piece_len);
+                    let restored = invert_attributes(attributes, attributes_of(&piece));
+                    push(&mut result, DeltaOp::Retain { retain: piece_len, attributes: restored });
+                }
+            }
+            DeltaOp::Delete { delete } => {
+                let mut remaining = *delete;
+                while remaining > 0 && base.has_next() {
+                    let piece = base.next(remaining);
+                    remaining = remaining.saturating_sub(op_len(&piece));
+                    push(&mut result, piece);
+                }
+            }
+        }
+    }
+    chop(result)
+}
+
+/// Merge adjacent components and drop empty ones and a trailing plain retain
+pub fn normalize(ops: Vec<DeltaOp>) -> Vec<DeltaOp> {
+    let mut result = Vec::new();
+    for op in ops {
+        push(&mut result, op);
+    }
+    chop(result)
+}
+
+fn length(ops: &[DeltaOp]) -> usize {
+    ops.iter().map(op_len).sum()
+}
+
+fn op_len(op: &DeltaOp) -> usize {
+    match op {
+        DeltaOp::Insert { insert, .. } => len16(insert),
+        DeltaOp::Retain { retain, .. } => *retain,
+        DeltaOp::Delete { delete } => *delete,
+    }
+}
+
+fn attributes_of(op: &DeltaOp) -> Option<&Attributes> {
+    match op {
+        DeltaOp::Insert { attributes, .. } | DeltaOp::Retain { attributes, .. } => attributes.as_ref(),
+        DeltaOp::Delete { .. } => None,
+    }
+}
+
+/// Append `op`, merging it into the last component where possible. Inserts
+/// go before an adjacent delete so equal changes have one representation.
+fn push(ops: &mut Vec<DeltaOp>, op: DeltaOp) {
+    if op_len(&op) == 0 {
+        return;
+    }
+    let before_delete = matches!((&op, ops.last()), (DeltaOp::Insert { .. }, Some(DeltaOp::Delete { .. })));
+    if before_delete {
+        if let Some(delete) = ops.pop() {
+            push(ops, op);
+            ops.push(delete);
+            return;
+        }
+    }
+
+    let merged = match (ops.last_mut(), &op) {
+        (Some(DeltaOp::Delete { delete }), DeltaOp::Delete { delete: more }) => {
+            *delete += more;
+            true
+        }
+        (Some(DeltaOp::Insert { insert, attributes }), DeltaOp::Insert { insert: more, attributes: other }) if *attributes == *other => {
+            insert.push_str(more);
+            true
+        }
+        (Some(DeltaOp::Retain { retain, attributes }), DeltaOp::Retain { retain: more, attributes: other }) if *attributes == *other => {
+            *retain += more;
+            true
+        }
+        _ => false,
+    };
+    if !merged {
+        ops.push(op);
+    }
+}
+
+fn chop(mut ops: Vec<DeltaOp>) -> Vec<DeltaOp> {
+    if let Some(DeltaOp::Retain { attributes: None, .. }) = ops.last() {
+        ops.pop();
+    }
+    ops
+}
+
+fn advance(iter: &mut OpIter, mut count: usize) {
+    while count > 0 && iter.has_next() {
+        count = count.saturating_sub(op_len(&iter.next(count)));
+    }
+}
+
+//=============================================================================
+// Attributes
+//=============================================================================
+
+/// `change` applied over `base`; `null` values remove attributes
+fn compose_attributes(base: Option<&Attributes>, change: Option<&Attributes>) -> Option<Attributes> {
+    let mut result = base.cloned().unwrap_or_default();
+    for (key, value) in change.into_iter().flatten() {
+        if value.is_null() {
+            result.remove(key);
+        } else {
+            result.insert(key.clone(), value.clone());
+        }
+    }
+    Some(result).filter(|attributes| !attributes.is_empty())
+}
+
+/// Our attribute changes minus the keys the earlier operation already set
+fn transform_attributes(theirs: Option<&Attributes>, ours: Option<&Attributes>) -> Option<Attributes> {
+    let ours = ours?;
+    let theirs = match theirs {
+        Some(theirs) => theirs,
+        None => return Some(ours.clone()),
+    };
+    let result: Attributes = ours.iter()
+        .filter(|(key, _)| !theirs.contains_key(*key))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    Some(result).filter(|attributes| !attributes.is_empty())
+}
+
+/// Attribute changes that restore `base` after `change` was applied to it
+fn invert_attributes(change: &Attributes, base: Option<&Attributes>) -> Option<Attributes> {
+    let mut result = Attributes::new();
+    for key in change.keys() {
+        let previous = base.and_then(|base| base.get(key));
+        if previous != change.get(key) {
+            result.insert(key.clone(), previous.cloned().unwrap_or(serde_json::Value::Null));
+        }
+    }
+    Some(result).filter(|attributes| !attributes.is_empty())
+}
+
+//=============================================================================
+// Plain-Text Conversion
+//=============================================================================
+
+/// Express a plain-text operation on `content` as a delta. Both count UTF-16
+/// units, so this only checks the range.
+fn from_plain_operation(content: &str, op: &Operation) -> Result<Vec<DeltaOp>, ServiceError> {
+    let ops = match op {
+        Operation::Insert { position, text } => {
+            text::byte_range(content, *position, 0)?;
+            vec![
+                DeltaOp::Retain { retain: *position as usize, attributes: None },
+                DeltaOp::Insert { insert: text.clone(), attributes: None },
+            ]
+        }
+        Operation::Delete { position, length } => {
+            text::byte_range(content, *position, *length)?;
+            vec![
+                DeltaOp::Retain { retain: *position as usize, attributes: None },
+                DeltaOp::Delete { delete: *length as usize },
+            ]
+        }
+        Operation::Replace { position, length, text } => {
+            text::byte_range(content, *position, *length)?;
+            vec![
+                DeltaOp::Retain { retain: *position as usize, attributes: None },
+                DeltaOp::Delete { delete: *length as usize },
+                DeltaOp::Insert { insert: text.clone(), attributes: None },
+            ]
+        }
+        Operation::Revert { .. } | Operation::Delta { .. } => Vec::new(),
+    };
+    Ok(normalize(ops))
+}
+
+/// Plain-text operations equivalent to `change` on `content`, each relative
+/// to the text left by the one before
+fn to_plain_operations(content: &str, change: &[DeltaOp]) -> Vec<Operation> {
+    let mut operations = Vec::new();
+    let mut position = 0usize;
+    let mut rest = content;
+
+    for op in change {
+        match op {
+            DeltaOp::Insert { insert, .. } => {
+                operations.push(Operation::Insert { position: position as i32, text: insert.clone() });
+                position += len16(insert);
+            }
+            DeltaOp::Retain { retain, .. } => {
+                let (kept, after) = split16(rest, *retain);
+                position += len16(kept);
+                rest = after;
+            }
+            DeltaOp::Delete { delete } => {
+                let (removed, after) = split16(rest, *delete);
+                if !removed.is_empty() {
+                    operations.push(Operation::Delete { position: position as i32, length: len16(removed) as i32 });
+                }
+                rest = after;
+            }
+        }
+    }
+    operations
+}
+
+/// Split after `units` UTF-16 code units, rounding up rather than splitting a
+/// surrogate pair
+fn split16(text: &str, units: usize) -> (&str, &str) {
+    let mut counted = 0;
+    for (index, c) in text.char_indices() {
+        if counted >= units {
+            return text.split_at(index);
+        }
+        counted += c.len_utf16();
+    }
+    (text, "")
+}
+
+//=============================================================================
+// Iteration
+//=============================================================================
+
+/// Walks a delta in pieces of a requested length
+struct OpIter<'a> {
+    ops: &'a [DeltaOp],
+    index: usize,
+    offset: usize,
+}
+
+impl<'a> OpIter<'a> {
+    fn new(ops: &'a [DeltaOp]) -> Self {
+        Self { ops, index: 0, offset: 0 }
+    }
+
+    fn has_next(&self) -> bool {
+        self.index < self.ops.len()
+    }
+
+    fn peek_is_insert(&self) -> bool {
+        matches!(self.ops.get(self.index), Some(DeltaOp::Insert { .. }))
+    }
+
+    /// Remaining length of the current component; unbounded past the end
+    fn peek_len(&self) -> usize {
+        self.ops.get(self.index)
+            .map(|op| op_len(op) - self.offset)
+            .unwrap_or(usize::MAX)
+    }
+
+    /// Up to `max` units of the current component. Past the end the delta
+    /// implicitly retains everything.
+    fn next(&mut self, max: usize) -> DeltaOp {
+        let op = match self.ops.get(self.index) {
+            Some(op) => op,
+            None => return DeltaOp::Retain { retain: max, attributes: None },
+        };
+        let remaining = op_len(op) - self.offset;
+        let (piece, taken) = match op {
+            DeltaOp::Insert { insert, attributes } => {
+                let (_, rest) = split16(insert, self.offset);
+                let (text, _) = split16(rest, max.min(remaining));
+                let taken = len16(text);
+                (DeltaOp::Insert { insert: text.to_string(), attributes: attributes.clone() }, taken)
+            }
+            DeltaOp::Retain { attributes, .. } => {
+                let taken = max.min(remaining);
+                (DeltaOp::Retain { retain: taken, attributes: attributes.clone() }, taken)
+            }
+            DeltaOp::Delete { .. } => {
+                let taken = max.min(remaining);
+                (DeltaOp::Delete { delete: taken }, taken)
+            }
+        };
+
+        if taken >= remaining {
+            self.index += 1;
+            self.offset = 0;
+        } else {
+            self.offset += taken;
+        }
+        piece
+    }
+}
@@ -0,0 +1,26 @@
+//! Editor routes open to delegated credentials
+//!
+//! Comments and reactions are governed by the comment scopes; everything else
+//! on a document except its webhooks is chapter content. Shared documents are
+//! gated by their own signed token.
+
+use authorworks_access::{scopes, Route};
+use spin_sdk::http::Method;
+
+pub fn route_scope(method: &Method, path: &str) -> Option<Route> {
+    if path.starts_with("/shared/") {
+        return Some(Route::Open);
+    }
+
+    // Webhooks hold signing secrets and post document content off-site
+    if path.contains("/webhooks") {
+        return None;
+    }
+    if path.starts_with("/comments/") || path.ends_with("/comments") || path.ends_with("/reactions") {
+        return Some(Route::read_write(method, scopes::COMMENTS_READ, scopes::COMMENTS_WRITE));
+    }
+    if path.starts_with("/documents/") {
+        return Some(Route::read_write(method, scopes::CHAPTERS_READ, scopes::CHAPTERS_WRITE));
+    }
+    None
+}
@@ -0,0 +1,236 @@
+//! Read-only share links
+//!
+//! An author can hand a live draft to someone without an account. A link's
+//! token is `<link id>.<expiry unix>.<hex HMAC-SHA256>` signed with
+//! `share_link_secret`, so forged or expired tokens are rejected before the
+//! database is touched; the `editor.share_links` row carries revocation and
+//! view counts. Viewers always see the current content, never history,
+//! comments or presence.
+
+use crate::error::ServiceError;
+use crate::models::ShareLinkRequest;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const DEFAULT_TTL_HOURS: i64 = 72;
+pub const MAX_TTL_HOURS: i64 = 30 * 24;
+
+/// Links per document, live or not; revoked ones still count until deleted with the chapter
+const MAX_LINKS_PER_DOCUMENT: i64 = 50;
+
+#[derive(Debug, Serialize)]
+pub struct ShareLink {
+    pub id: Uuid,
+    pub label: Option<String>,
+    /// Only returned while the link is usable
+    pub token: Option<String>,
+    pub url_path: Option<String>,
+    pub expires_at: String,
+    pub revoked_at: Option<String>,
+    pub active: bool,
+    pub view_count: i32,
+    pub last_viewed_at: Option<String>,
+    pub created_at: String,
+}
+
+//=============================================================================
+// Owner Endpoints
+//=============================================================================
+
+/// POST /documents/:id/share-links - Create an expiring read-only link
+pub fn create(conn: &Connection, document_id: &Uuid, user_id: &Uuid, body: ShareLinkRequest) -> Result<Response, ServiceError> {
+    let ttl_hours = body.expires_in_hours.unwrap_or(DEFAULT_TTL_HOURS);
+    if !(1..=MAX_TTL_HOURS).contains(&ttl_hours) {
+        return Err(ServiceError::BadRequest(format!("expires_in_hours must be between 1 and {}", MAX_TTL_HOURS)));
+    }
+    let label = body.label.map(|l| l.trim().to_string()).filter(|l| !l.is_empty());
+    if label.as_ref().is_some_and(|l| l.chars().count() > 255) {
+        return Err(ServiceError::BadRequest("label must be at most 255 characters".into()));
+    }
+
+    let count_query = "SELECT COUNT(*) FROM editor.share_links WHERE document_id = $1";
+    let count = conn.query(count_query, &[ParameterValue::Str(document_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?
+        .rows.first()
+        .map(|row| i64::decode(&row[0]).unwrap_or(0))
+        .unwrap_or(0);
+    if count >= MAX_LINKS_PER_DOCUMENT {
+        return Err(ServiceError::Conflict(format!("A document can have at most {} share links", MAX_LINKS_PER_DOCUMENT)));
+    }
+
+    let secret = signing_secret()?;
+    let id = Uuid::new_v4();
+    let now = Utc::now();
+    // Whole seconds, so the stored expiry matches the one in the token
+    let expires_at = DateTime::from_timestamp((now + Duration::hours(ttl_hours)).timestamp(), 0)
+        .ok_or_else(|| ServiceError::Internal("Invalid expiry".into()))?;
+
+    let insert = "INSERT INTO editor.share_links (id, document_id, created_by, label, expires_at, created_at)
+                  VALUES ($1, $2, $3, $4, $5, $6)";
+    conn.execute(insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        label.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(expires_at.to_rfc3339()),
+        ParameterValue::Str(now.to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    let token = sign(&secret, &id, expires_at.timestamp());
+    crate::json_response(201, ShareLink {
+        id,
+        label,
+        url_path: Some(format!("/shared/{}", token)),
+        token: Some(token),
+        expires_at: expires_at.to_rfc3339(),
+        revoked_at: None,
+        active: true,
+        view_count: 0,
+        last_viewed_at: None,
+        created_at: now.to_rfc3339(),
+    })
+}
+
+/// GET /documents/:id/share-links - All links for the document, newest first
+pub fn list(conn: &Connection, document_id: &Uuid) -> Result<Response, ServiceError> {
+    let secret = signing_secret()?;
+    let query = "SELECT id, label, expires_at, EXTRACT(EPOCH FROM expires_at)::bigint, revoked_at,
+                        view_count, last_viewed_at, created_at,
+                        revoked_at IS NULL AND expires_at > NOW()
+                 FROM editor.share_links WHERE document_id = $1
+                 ORDER BY created_at DESC";
+    let rows = conn.query(query, &[ParameterValue::Str(document_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let links: Vec<ShareLink> = rows.rows.iter().map(|row| {
+        let id = Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default();
+        let active = bool::decode(&row[8]).unwrap_or(false);
+        let token = active.then(|| sign(&secret, &id, i64::decode(&row[3]).unwrap_or(0)));
+        ShareLink {
+            id,
+            label: String::decode(&row[1]).ok(),
+            url_path: token.as_ref().map(|t| format!("/shared/{}", t)),
+            token,
+            expires_at: String::decode(&row[2]).unwrap_or_default(),
+            revoked_at: String::decode(&row[4]).ok(),
+            active,
+            view_count: i32::decode(&row[5]).unwrap_or(0),
+            last_viewed_at: String::decode(&row[6]).ok(),
+            created_at: String::decode(&row[7]).unwrap_or_default(),
+        }
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({ "share_links": links }))
+}
+
+/// DELETE /documents/:id/share-links/:link_id - Revoke a link immediately
+pub fn revoke(conn: &Connection, document_id: &Uuid, link_id: &Uuid) -> Result<Response, ServiceError> {
+    let update = "UPDATE editor.share_links SET revoked_at = COALESCE(revoked_at, NOW())
+                  WHERE id = $1 AND document_id = $2";
+    let updated = conn.execute(update, &[
+        ParameterValue::Str(link_id.to_string()),
+        ParameterValue::Str(document_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    if updated == 0 {
+        return Err(ServiceError::NotFound("Share link not found".into()));
+    }
+    crate::json_response(200, serde_json::json!({ "id": link_id, "revoked": true }))
+}
+
+//=============================================================================
+// Public Endpoint
+//=============================================================================
+
+/// GET /shared/:token - Current content and version of a shared document
+pub fn view(conn: &Connection, path: &str) -> Result<Response, ServiceError> {
+    let not_found = || ServiceError::NotFound("Share link not found or expired".into());
+
+    let token = path.strip_prefix("/shared/").ok_or_else(not_found)?;
+    let (link_id, expires) = verify(&signing_secret()?, token).ok_or_else(not_found)?;
+    if expires <= Utc::now().timestamp() {
+        return Err(not_found());
+    }
+
+    // Counting the view doubles as the revocation and expiry check
+    let update = "UPDATE editor.share_links SET view_count = view_count + 1, last_viewed_at = NOW()
+                  WHERE id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+                  RETURNING document_id, expires_at";
+    let rows = conn.query(update, &[ParameterValue::Str(link_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    let row = rows.rows.first().ok_or_else(not_found)?;
+    let document_id = String::decode(&row[0]).unwrap_or_default();
+    let expires_at = String::decode(&row[1]).unwrap_or_default();
+
+    let query = "SELECT ch.title, b.title, d.content, d.version, d.updated_at, d.rich_content::text
+                 FROM content.chapters ch
+                 JOIN content.books b ON b.id = ch.book_id
+                 LEFT JOIN editor.documents d ON d.id = ch.id
+                 WHERE ch.id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(document_id.clone())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first().ok_or_else(not_found)?;
+
+    let body = serde_json::json!({
+        "id": document_id,
+        "chapter_title": String::decode(&row[0]).ok(),
+        "book_title": String::decode(&row[1]).ok(),
+        "content": String::decode(&row[2]).unwrap_or_default(),
+        "version": i64::decode(&row[3]).unwrap_or(0),
+        "updated_at": String::decode(&row[4]).ok(),
+        "rich_content": String::decode(&row[5]).ok()
+            .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok()),
+        "read_only": true,
+        "link_expires_at": expires_at
+    });
+
+    Ok(Response::builder()
+        .status(200)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Cache-Control", "no-store")
+        .header("X-Robots-Tag", "noindex")
+        .body(body.to_string())
+        .build())
+}
+
+//=============================================================================
+// Tokens
+//=============================================================================
+
+fn signing_secret() -> Result<String, ServiceError> {
+    variables::get("share_link_secret")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| ServiceError::Internal("share_link_secret not configured".into()))
+}
+
+fn mac(secret: &str, link_id: &Uuid, expires: i64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{}.{}", link_id.simple(), expires).as_bytes());
+    mac
+}
+
+fn sign(secret: &str, link_id: &Uuid, expires: i64) -> String {
+    let signature = hex::encode(mac(secret, link_id, expires).finalize().into_bytes());
+    format!("{}.{}.{}", link_id.simple(), expires, signature)
+}
+
+/// Link id and expiry of a well-formed, correctly signed token
+fn verify(secret: &str, token: &str) -> Option<(Uuid, i64)> {
+    let mut parts = token.splitn(3, '.');
+    let link_id = Uuid::parse_str(parts.next()?).ok()?;
+    let expires = parts.next()?.parse::<i64>().ok()?;
+    let signature = hex::decode(parts.next()?).ok()?;
+
+    mac(secret, &link_id, expires).verify_slice(&signature).ok()?;
+    Some((link_id, expires))
+}
@@ -0,0 +1,130 @@
+//! Text offsets and edit limits
+//!
+//! Operation positions and lengths count UTF-16 code units, like rich-text
+//! deltas and JavaScript strings, so an emoji is two units wide. Offsets are
+//! converted to byte ranges here before any string is sliced; an offset past
+//! the end or between the two halves of a surrogate pair is a bad request
+//! rather than a panic.
+//!
+//! A single operation may insert at most `editor_max_operation_units` units
+//! (64K by default) and a document may hold at most
+//! `editor_max_document_units` (2M by default). Both fail with 413.
+
+use crate::error::ServiceError;
+use crate::models::{DeltaOp, Operation};
+use spin_sdk::variables;
+use std::ops::Range;
+
+const DEFAULT_MAX_DOCUMENT_UNITS: usize = 2_000_000;
+const DEFAULT_MAX_OPERATION_UNITS: usize = 64 * 1024;
+
+//=============================================================================
+// Offsets
+//=============================================================================
+
+/// Length of `text` in UTF-16 code units
+pub fn len16(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Byte offset of the UTF-16 offset `units`, or `None` when it lies past the
+/// end or inside a surrogate pair
+pub fn byte_offset(text: &str, units: usize) -> Option<usize> {
+    let mut counted = 0;
+    for (index, c) in text.char_indices() {
+        if counted >= units {
+            return (counted == units).then_some(index);
+        }
+        counted += c.len_utf16();
+    }
+    (counted == units).then_some(text.len())
+}
+
+/// Byte range of `length` units starting at `position`
+pub fn byte_range(text: &str, position: i32, length: i32) -> Result<Range<usize>, ServiceError> {
+    if position < 0 || length < 0 {
+        return Err(ServiceError::BadRequest("Position and length must not be negative".into()));
+    }
+    let start = byte_offset(text, position as usize).ok_or_else(|| ServiceError::BadRequest(format!(
+        "Position {} is out of bounds or splits a character", position
+    )))?;
+    let end = byte_offset(&text[start..], length as usize).map(|end| start + end).ok_or_else(|| {
+        ServiceError::BadRequest(format!(
+            "Range {}..{} is out of bounds or splits a character", position, position as i64 + length as i64
+        ))
+    })?;
+    Ok(start..end)
+}
+
+//=============================================================================
+// Limits
+//=============================================================================
+
+fn limit(name: &str, default: usize) -> usize {
+    variables::get(name)
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(default)
+}
+
+/// Reject a submitted operation that is malformed or inserts too much text.
+/// Bounds against the document itself are checked when it is applied.
+pub fn validate_operation(op: &Operation) -> Result<(), ServiceError> {
+    let max_document = limit("editor_max_document_units", DEFAULT_MAX_DOCUMENT_UNITS);
+    let inserted = match op {
+        Operation::Insert { position, text } => {
+            check_range(*position, 0, max_document)?;
+            len16(text)
+        }
+        Operation::Delete { position, length } => {
+            check_range(*position, *length, max_document)?;
+            0
+        }
+        Operation::Replace { position, length, text } => {
+            check_range(*position, *length, max_document)?;
+            len16(text)
+        }
+        Operation::Revert { .. } => 0,
+        Operation::Delta { ops, .. } => ops.iter()
+            .map(|op| match op {
+                DeltaOp::Insert { insert, .. } => len16(insert),
+                _ => 0,
+            })
+            .sum(),
+    };
+
+    let max = limit("editor_max_operation_units", DEFAULT_MAX_OPERATION_UNITS);
+    if inserted > max {
+        return Err(ServiceError::PayloadTooLarge(format!(
+            "Operation inserts {} characters; the limit is {}", inserted, max
+        )));
+    }
+    Ok(())
+}
+
+/// Reject content that has grown past the document size limit
+pub fn validate_document(content: &str) -> Result<(), ServiceError> {
+    let max = limit("editor_max_document_units", DEFAULT_MAX_DOCUMENT_UNITS);
+    let size = len16(content);
+    if size > max {
+        return Err(ServiceError::PayloadTooLarge(format!(
+            "Document would be {} characters; the limit is {}", size, max
+        )));
+    }
+    Ok(())
+}
+
+/// Ranges no document can contain are rejected up front, which also keeps
+/// position arithmetic in transforms from overflowing
+fn check_range(position: i32, length: i32, max_document: usize) -> Result<(), ServiceError> {
+    if position < 0 || length < 0 {
+        return Err(ServiceError::BadRequest("Position and length must not be negative".into()));
+    }
+    if position as usize + length as usize > max_document {
+        return Err(ServiceError::BadRequest(format!(
+            "Range {}..{} is beyond the maximum document size", position, position as usize + length as usize
+        )));
+    }
+    Ok(())
+}
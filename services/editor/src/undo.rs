@@ -0,0 +1,262 @@
+//! Server-side undo/redo
+//!
+//! Each stored operation carries its inverse. Undo picks the caller's most
+//! recent operation that has not been undone, transforms its inverse through
+//! everything applied since, and commits the result as a normal operation so
+//! collaborators receive it like any other edit. Redo does the same with the
+//! inverse of the undo.
+//!
+//! The stack is per user and lives in `editor.operations` itself: undo and
+//! redo ops point at what they reverse (`undo_of`, `redo_of`), and a reversed
+//! op is marked `undone`. An op is claimed by flipping that flag before its
+//! inverse is committed, so two concurrent undos never reverse the same edit;
+//! the claim is released if the commit fails. A fresh edit clears the redo
+//! side, as in any editor.
+
+use crate::anchors;
+use crate::blocks;
+use crate::error::ServiceError;
+use crate::models::{DeltaOp, Operation};
+use crate::ot;
+use crate::rich_text;
+use serde::Serialize;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::Utc;
+use uuid::Uuid;
+
+/// Outcome of an undo or redo, mirrored in the HTTP response
+pub struct UndoResult {
+    pub operation_id: Uuid,
+    pub target_id: Uuid,
+    pub operation: Operation,
+    pub version: i64,
+    pub content: String,
+    pub rich_content: Option<Vec<DeltaOp>>,
+    pub block_ids: Vec<String>,
+}
+
+/// Ops the user can undo: their own edits and redos not yet reversed
+const UNDOABLE: &str = "document_id = $1 AND user_id = $2
+                        AND undo_of IS NULL AND undone = false AND inverse IS NOT NULL";
+
+/// Undos the user can redo: those made since their last fresh edit
+const REDOABLE: &str = "document_id = $1 AND user_id = $2
+                        AND undo_of IS NOT NULL AND undone = false
+                        AND version > COALESCE((
+                            SELECT MAX(version) FROM editor.operations
+                            WHERE document_id = $1 AND user_id = $2
+                              AND undo_of IS NULL AND redo_of IS NULL
+                        ), -1)";
+
+/// How deep the user's undo and redo stacks are
+#[derive(Debug, Serialize)]
+pub struct UndoStack {
+    pub undo_depth: i64,
+    pub redo_depth: i64,
+    /// Operation the next undo would reverse
+    pub next_undo: Option<Uuid>,
+    /// Undo the next redo would reverse
+    pub next_redo: Option<Uuid>,
+}
+
+/// Undo the user's latest operation that has not been undone
+pub fn undo(conn: &Connection, document_id: &Uuid, user_id: &Uuid) -> Result<UndoResult, ServiceError> {
+    let query = format!(
+        "SELECT id, version, inverse FROM editor.operations WHERE {} ORDER BY version DESC LIMIT 1",
+        UNDOABLE
+    );
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::Conflict("Nothing to undo".into()))?;
+    let target_id = decode_uuid(&row[0]);
+    let target_version = i64::decode(&row[1]).unwrap_or(0);
+    let inverse = decode_operation(&row[2])?;
+
+    claim(conn, &target_id)?;
+    let result = commit_transformed(conn, document_id, user_id, inverse, target_version, Some(target_id), None, None)
+        .inspect_err(|_| release(conn, &target_id))?;
+
+    Ok(UndoResult { target_id, ..result })
+}
+
+/// Redo the user's latest undo, provided they have not made a fresh edit since
+pub fn redo(conn: &Connection, document_id: &Uuid, user_id: &Uuid) -> Result<UndoResult, ServiceError> {
+    let query = format!(
+        "SELECT id, version, inverse, undo_of FROM editor.operations WHERE {} ORDER BY version DESC LIMIT 1",
+        REDOABLE
+    );
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::Conflict("Nothing to redo".into()))?;
+    let undo_id = decode_uuid(&row[0]);
+    let undo_version = i64::decode(&row[1]).unwrap_or(0);
+    let inverse = decode_operation(&row[2])?;
+    let original_id = decode_uuid(&row[3]);
+
+    // The undo itself is spent, and the original edit becomes undoable again via the redo op
+    claim(conn, &undo_id)?;
+    let result = commit_transformed(conn, document_id, user_id, inverse, undo_version, None, Some(original_id), None)
+        .inspect_err(|_| release(conn, &undo_id))?;
+
+    Ok(UndoResult { target_id: undo_id, ..result })
+}
+
+/// The user's undo and redo stacks for a document
+pub fn stack(conn: &Connection, document_id: &Uuid, user_id: &Uuid) -> Result<UndoStack, ServiceError> {
+    let query = format!(
+        "SELECT (SELECT COUNT(*) FROM editor.operations WHERE {undoable}),
+                (SELECT COUNT(*) FROM editor.operations WHERE {redoable}),
+                (SELECT id FROM editor.operations WHERE {undoable} ORDER BY version DESC LIMIT 1),
+                (SELECT id FROM editor.operations WHERE {redoable} ORDER BY version DESC LIMIT 1)",
+        undoable = UNDOABLE,
+        redoable = REDOABLE
+    );
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::Internal("Undo stack query returned no rows".into()))?;
+    Ok(UndoStack {
+        undo_depth: i64::decode(&row[0]).unwrap_or(0),
+        redo_depth: i64::decode(&row[1]).unwrap_or(0),
+        next_undo: String::decode(&row[2]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+        next_redo: String::decode(&row[3]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+    })
+}
+
+/// Mark an op as reversed; fails if a concurrent undo or redo got there first
+fn claim(conn: &Connection, op_id: &Uuid) -> Result<(), ServiceError> {
+    let mark = "UPDATE editor.operations SET undone = true WHERE id = $1 AND undone = false";
+    let claimed = conn.execute(mark, &[ParameterValue::Str(op_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    if claimed == 0 {
+        return Err(ServiceError::Conflict("Operation was already reversed by a concurrent request".into()));
+    }
+    Ok(())
+}
+
+/// Give back a claim whose inverse could not be committed
+fn release(conn: &Connection, op_id: &Uuid) {
+    let unmark = "UPDATE editor.operations SET undone = false WHERE id = $1";
+    let _ = conn.execute(unmark, &[ParameterValue::Str(op_id.to_string())]);
+}
+
+/// Transform `op` (expressed against `base_version`) to the current document
+/// state, apply it, and record it as a regular operation. `attribution` marks
+/// operations not typed by the user, such as applied AI assist suggestions.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn commit_transformed(
+    conn: &Connection,
+    document_id: &Uuid,
+    user_id: &Uuid,
+    op: Operation,
+    base_version: i64,
+    undo_of: Option<Uuid>,
+    redo_of: Option<Uuid>,
+    attribution: Option<&str>,
+) -> Result<UndoResult, ServiceError> {
+    let doc_query = "SELECT content, version, block_ids, rich_content::text FROM editor.documents WHERE id = $1 FOR UPDATE";
+    let doc_rows = conn.query(doc_query, &[ParameterValue::Str(document_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let doc = doc_rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Document not found".into()))?;
+    let content = String::decode(&doc[0]).unwrap_or_default();
+    let version = i64::decode(&doc[1]).unwrap_or(0);
+    let block_ids: Vec<String> = String::decode(&doc[2])
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+    let rich_content: Option<Vec<DeltaOp>> = String::decode(&doc[3])
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok());
+
+    let later_query = "SELECT operation FROM editor.operations
+                       WHERE document_id = $1 AND version > $2 ORDER BY version ASC";
+    let later_rows = conn.query(later_query, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Int64(base_version),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut transformed = op;
+    for row in &later_rows.rows {
+        let later = decode_operation(&row[0])?;
+        if let Operation::Revert { .. } = later {
+            return Err(ServiceError::Conflict("Cannot apply across a checkpoint revert".into()));
+        }
+        if !rich_text::can_transform(&transformed, &later) {
+            return Err(ServiceError::Conflict("Cannot apply across the switch to rich text".into()));
+        }
+        transformed = ot::transform_against(&transformed, &later);
+    }
+
+    let applied = rich_text::apply(&content, rich_content.as_deref(), transformed)
+        .map_err(|_| ServiceError::Conflict("Operation can no longer be applied".into()))?;
+    let transformed = applied.operation;
+    let new_content = applied.content;
+    let new_block_ids = blocks::update_block_ids(&content, &block_ids, &transformed);
+    let inverse = applied.inverse;
+    let new_version = version + 1;
+    let now = Utc::now();
+
+    let op_id = Uuid::new_v4();
+    let op_insert = "INSERT INTO editor.operations
+                     (id, document_id, user_id, version, operation, inverse, undo_of, redo_of, attribution, created_at)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)";
+    conn.execute(op_insert, &[
+        ParameterValue::Str(op_id.to_string()),
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Int64(new_version),
+        ParameterValue::Str(serde_json::to_string(&transformed).unwrap_or_default()),
+        inverse.as_ref()
+            .map(|inv| ParameterValue::Str(serde_json::to_string(inv).unwrap_or_default()))
+            .unwrap_or(ParameterValue::DbNull),
+        undo_of.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+        redo_of.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+        attribution.map(|a| ParameterValue::Str(a.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(now.to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    let doc_update = "UPDATE editor.documents SET content = $2, version = $3, updated_at = $4, block_ids = $5,
+                      rich_content = $6::jsonb WHERE id = $1";
+    conn.execute(doc_update, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(new_content.clone()),
+        ParameterValue::Int64(new_version),
+        ParameterValue::Str(now.to_rfc3339()),
+        ParameterValue::Str(serde_json::to_string(&new_block_ids).unwrap_or_default()),
+        applied.rich_content.as_ref()
+            .map(|doc| ParameterValue::Str(serde_json::to_string(doc).unwrap_or_default()))
+            .unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    anchors::transform_comments(conn, document_id, &transformed)?;
+
+    Ok(UndoResult {
+        operation_id: op_id,
+        target_id: Uuid::nil(),
+        operation: transformed,
+        version: new_version,
+        content: new_content,
+        rich_content: applied.rich_content,
+        block_ids: new_block_ids,
+    })
+}
+
+fn decode_operation(value: &spin_sdk::pg::DbValue) -> Result<Operation, ServiceError> {
+    serde_json::from_str(&String::decode(value).unwrap_or_default())
+        .map_err(|e| ServiceError::Internal(format!("Parse failed: {}", e)))
+}
+
+fn decode_uuid(value: &spin_sdk::pg::DbValue) -> Uuid {
+    Uuid::parse_str(&String::decode(value).unwrap_or_default()).unwrap_or_default()
+}
@@ -0,0 +1,497 @@
+//! Per-document webhooks
+//!
+//! Authors subscribe an HTTPS endpoint to a document's `checkpoint.created`,
+//! `version.milestone` (the word count reaching one of the webhook's
+//! `word_milestones`) and `comment.added` events. Events are queued in
+//! `editor.document_webhook_deliveries` as they happen; `POST /webhooks/deliver`
+//! sends due deliveries in batches. Queueing never fails the edit that
+//! triggered it.
+//!
+//! Every request carries `X-AuthorWorks-Signature: t=<unix>,v1=<hex>`, an
+//! HMAC-SHA256 of `<t>.<body>` keyed with the webhook's secret. Network
+//! errors, 429 and 5xx are retried with exponential backoff; other rejections
+//! fail the delivery, and 404/410 also deactivate the webhook.
+
+use crate::error::ServiceError;
+use crate::models::WebhookRequest;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use spin_sdk::http::Response;
+use spin_sdk::outbound_http::{self, Method as HttpMethod, Request as OutboundRequest};
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use chrono::Utc;
+use std::net::IpAddr;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const CHECKPOINT_CREATED: &str = "checkpoint.created";
+pub const VERSION_MILESTONE: &str = "version.milestone";
+pub const COMMENT_ADDED: &str = "comment.added";
+const EVENT_TYPES: [&str; 3] = [CHECKPOINT_CREATED, VERSION_MILESTONE, COMMENT_ADDED];
+
+const MAX_WEBHOOKS_PER_DOCUMENT: i64 = 10;
+const MAX_MILESTONES: usize = 20;
+const DEFAULT_BATCH_SIZE: i64 = 50;
+const DEFAULT_MAX_ATTEMPTS: i32 = 6;
+const RETRY_BASE_SECONDS: i64 = 30;
+const RETRY_MAX_SECONDS: i64 = 3600;
+/// Deliveries stuck in `sending` this long were interrupted and are retried
+const STALE_SENDING_MINUTES: i32 = 10;
+/// Response bodies are only kept as error context
+const MAX_ERROR_BODY: usize = 500;
+
+#[derive(Debug, Serialize)]
+pub struct DocumentWebhook {
+    pub id: Uuid,
+    pub url: String,
+    pub events: Vec<String>,
+    pub word_milestones: Vec<i32>,
+    pub active: bool,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub event_type: String,
+    pub status: String,
+    pub attempts: i32,
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub payload: serde_json::Value,
+    pub created_at: String,
+    pub delivered_at: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DeliverySummary {
+    pub delivered: u64,
+    pub retrying: u64,
+    pub failed: u64,
+}
+
+/// Outcome of one POST to a webhook endpoint
+enum SendError {
+    /// Worth retrying: network failure, rate limiting, or a 5xx
+    Transient(Option<i32>, String),
+    /// The endpoint rejected the request; `gone` when it no longer exists
+    Permanent { status: Option<i32>, error: String, gone: bool },
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /documents/:id/webhooks - Subscribe a URL to document events; the
+/// signing secret is only returned here
+pub fn register(conn: &Connection, document_id: &Uuid, user_id: &Uuid, body: WebhookRequest) -> Result<Response, ServiceError> {
+    let url = body.url.trim().to_string();
+    validate_url(&url)?;
+
+    let mut events = body.events;
+    events.sort();
+    events.dedup();
+    if events.is_empty() {
+        return Err(ServiceError::BadRequest(format!("events must list at least one of {}", EVENT_TYPES.join(", "))));
+    }
+    if let Some(unknown) = events.iter().find(|e| !EVENT_TYPES.contains(&e.as_str())) {
+        return Err(ServiceError::BadRequest(format!(
+            "Unknown event '{}'; expected one of {}", unknown, EVENT_TYPES.join(", ")
+        )));
+    }
+
+    let mut milestones = body.word_milestones;
+    milestones.sort_unstable();
+    milestones.dedup();
+    let wants_milestones = events.iter().any(|e| e == VERSION_MILESTONE);
+    if wants_milestones && milestones.is_empty() {
+        return Err(ServiceError::BadRequest(format!("{} requires word_milestones", VERSION_MILESTONE)));
+    }
+    if !wants_milestones && !milestones.is_empty() {
+        return Err(ServiceError::BadRequest(format!("word_milestones only apply to {}", VERSION_MILESTONE)));
+    }
+    if milestones.len() > MAX_MILESTONES || milestones.iter().any(|m| *m <= 0) {
+        return Err(ServiceError::BadRequest(format!(
+            "word_milestones must be at most {} positive word counts", MAX_MILESTONES
+        )));
+    }
+
+    let count_query = "SELECT COUNT(*) FROM editor.document_webhooks WHERE document_id = $1";
+    let count = conn.query(count_query, &[ParameterValue::Str(document_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?
+        .rows.first()
+        .map(|row| i64::decode(&row[0]).unwrap_or(0))
+        .unwrap_or(0);
+    if count >= MAX_WEBHOOKS_PER_DOCUMENT {
+        return Err(ServiceError::BadRequest(format!("At most {} webhooks per document", MAX_WEBHOOKS_PER_DOCUMENT)));
+    }
+
+    let id = Uuid::new_v4();
+    let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let now = Utc::now().to_rfc3339();
+    let insert = "INSERT INTO editor.document_webhooks (id, document_id, created_by, url, events, word_milestones, secret, created_at)
+                  VALUES ($1, $2, $3, $4, string_to_array($5, ','), string_to_array($6, ',')::integer[], $7, $8)
+                  ON CONFLICT (document_id, url) DO NOTHING";
+    let inserted = conn.execute(insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(url.clone()),
+        ParameterValue::Str(events.join(",")),
+        ParameterValue::Str(milestones.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(",")),
+        ParameterValue::Str(secret.clone()),
+        ParameterValue::Str(now.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    if inserted == 0 {
+        return Err(ServiceError::Conflict("This URL is already registered for the document".into()));
+    }
+
+    crate::json_response(201, serde_json::json!({
+        "webhook": DocumentWebhook {
+            id,
+            url,
+            events,
+            word_milestones: milestones,
+            active: true,
+            created_at: now,
+        },
+        "secret": secret
+    }))
+}
+
+/// GET /documents/:id/webhooks - The document's webhooks
+pub fn list(conn: &Connection, document_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT id, url, array_to_string(events, ','), array_to_string(word_milestones, ','), active, created_at
+                 FROM editor.document_webhooks WHERE document_id = $1
+                 ORDER BY created_at DESC";
+    let rows = conn.query(query, &[ParameterValue::Str(document_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let webhooks: Vec<DocumentWebhook> = rows.rows.iter().map(|row| DocumentWebhook {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        url: String::decode(&row[1]).unwrap_or_default(),
+        events: split_list(&String::decode(&row[2]).unwrap_or_default()),
+        word_milestones: split_list(&String::decode(&row[3]).unwrap_or_default()),
+        active: bool::decode(&row[4]).unwrap_or(false),
+        created_at: String::decode(&row[5]).unwrap_or_default(),
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({ "webhooks": webhooks }))
+}
+
+/// DELETE /documents/:id/webhooks/:webhook_id - Remove a webhook and its delivery log
+pub fn delete(conn: &Connection, document_id: &Uuid, webhook_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "DELETE FROM editor.document_webhooks WHERE id = $1 AND document_id = $2";
+    let deleted = conn.execute(query, &[
+        ParameterValue::Str(webhook_id.to_string()),
+        ParameterValue::Str(document_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    if deleted == 0 {
+        return Err(ServiceError::NotFound("Webhook not found".into()));
+    }
+    crate::json_response(200, serde_json::json!({ "deleted": true }))
+}
+
+/// GET /documents/:id/webhooks/:webhook_id/deliveries - Recent deliveries, newest first
+pub fn list_deliveries(conn: &Connection, document_id: &Uuid, webhook_id: &Uuid) -> Result<Response, ServiceError> {
+    let exists = conn.query(
+        "SELECT 1 FROM editor.document_webhooks WHERE id = $1 AND document_id = $2",
+        &[ParameterValue::Str(webhook_id.to_string()), ParameterValue::Str(document_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if exists.rows.is_empty() {
+        return Err(ServiceError::NotFound("Webhook not found".into()));
+    }
+
+    let query = "SELECT id, event_type, status, attempts, response_status, last_error,
+                        payload::text, created_at, delivered_at
+                 FROM editor.document_webhook_deliveries
+                 WHERE webhook_id = $1
+                 ORDER BY created_at DESC LIMIT 50";
+    let rows = conn.query(query, &[ParameterValue::Str(webhook_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let deliveries: Vec<WebhookDelivery> = rows.rows.iter().map(|row| WebhookDelivery {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        event_type: String::decode(&row[1]).unwrap_or_default(),
+        status: String::decode(&row[2]).unwrap_or_default(),
+        attempts: i32::decode(&row[3]).unwrap_or(0),
+        response_status: i32::decode(&row[4]).ok(),
+        last_error: String::decode(&row[5]).ok(),
+        payload: serde_json::from_str(&String::decode(&row[6]).unwrap_or_default()).unwrap_or_default(),
+        created_at: String::decode(&row[7]).unwrap_or_default(),
+        delivered_at: String::decode(&row[8]).ok(),
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({ "deliveries": deliveries }))
+}
+
+/// POST /webhooks/deliver - Send a batch of due deliveries (internal, X-Internal-Token)
+pub fn deliver(conn: &Connection) -> Result<Response, ServiceError> {
+    let batch_size = variables::get("document_webhook_batch_size").ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_BATCH_SIZE);
+    let max_attempts = variables::get("document_webhook_max_attempts").ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_MAX_ATTEMPTS);
+    let mut summary = DeliverySummary::default();
+
+    // Recover deliveries interrupted mid-send
+    let reset = "UPDATE editor.document_webhook_deliveries SET status = 'pending', updated_at = NOW()
+                 WHERE status = 'sending' AND updated_at < NOW() - make_interval(mins => $1)";
+    conn.execute(reset, &[ParameterValue::Int32(STALE_SENDING_MINUTES)])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let claim = "UPDATE editor.document_webhook_deliveries SET status = 'sending', attempts = attempts + 1, updated_at = NOW()
+                 WHERE id IN (
+                     SELECT id FROM editor.document_webhook_deliveries
+                     WHERE status = 'pending' AND next_attempt_at <= NOW()
+                     ORDER BY next_attempt_at
+                     LIMIT $1
+                     FOR UPDATE SKIP LOCKED
+                 )
+                 RETURNING id, webhook_id, event_type, payload::text, attempts";
+    let claimed = conn.query(claim, &[ParameterValue::Int64(batch_size)])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    for row in &claimed.rows {
+        let delivery_id = String::decode(&row[0]).unwrap_or_default();
+        let webhook_id = String::decode(&row[1]).unwrap_or_default();
+        let event_type = String::decode(&row[2]).unwrap_or_default();
+        let mut payload: serde_json::Value =
+            serde_json::from_str(&String::decode(&row[3]).unwrap_or_default()).unwrap_or_default();
+        payload["id"] = serde_json::Value::String(delivery_id.clone());
+        let attempts = i32::decode(&row[4]).unwrap_or(1);
+
+        let result = match load_endpoint(conn, &webhook_id)? {
+            Some((url, secret)) => send(&url, &secret, &delivery_id, &event_type, &payload.to_string()),
+            None => Err(SendError::Permanent { status: None, error: "Webhook is inactive".into(), gone: false }),
+        };
+
+        match result {
+            Ok(status) => {
+                finish(conn, &delivery_id, "delivered", Some(status), None)?;
+                summary.delivered += 1;
+            }
+            Err(SendError::Transient(status, error)) if attempts < max_attempts => {
+                schedule_retry(conn, &delivery_id, attempts, status, &error)?;
+                summary.retrying += 1;
+            }
+            Err(SendError::Transient(status, error)) => {
+                finish(conn, &delivery_id, "failed", status, Some(&error))?;
+                summary.failed += 1;
+            }
+            Err(SendError::Permanent { status, error, gone }) => {
+                finish(conn, &delivery_id, "failed", status, Some(&error))?;
+                if gone {
+                    deactivate(conn, &webhook_id)?;
+                }
+                summary.failed += 1;
+            }
+        }
+    }
+
+    crate::json_response(200, summary)
+}
+
+//=============================================================================
+// Events
+//=============================================================================
+
+/// Queue `event_type` for every active webhook on the document subscribed to
+/// it. Failures are swallowed; the write that raised the event already succeeded.
+pub fn emit(conn: &Connection, document_id: &Uuid, event_type: &str, dedupe_key: &str, data: serde_json::Value) {
+    let payload = serde_json::json!({
+        "event": event_type,
+        "document_id": document_id,
+        "occurred_at": Utc::now().to_rfc3339(),
+        "data": data
+    });
+    let insert = "INSERT INTO editor.document_webhook_deliveries (webhook_id, event_type, dedupe_key, payload)
+                  SELECT id, $2, $3, $4::jsonb FROM editor.document_webhooks
+                  WHERE document_id = $1 AND active AND $2 = ANY(events)
+                  ON CONFLICT (webhook_id, dedupe_key) DO NOTHING";
+    let _ = conn.execute(insert, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(event_type.to_string()),
+        ParameterValue::Str(dedupe_key.to_string()),
+        ParameterValue::Str(payload.to_string()),
+    ]);
+}
+
+/// Queue `version.milestone` for each webhook milestone the edit from
+/// `before` to `after` reached. A milestone fires once per webhook, however
+/// often edits cross it afterwards.
+pub fn emit_milestones(conn: &Connection, document_id: &Uuid, before: &str, after: &str, version: i64) {
+    let words_before = word_count(before) as i32;
+    let words_after = word_count(after) as i32;
+    if words_after <= words_before {
+        return;
+    }
+
+    let insert = "INSERT INTO editor.document_webhook_deliveries (webhook_id, event_type, dedupe_key, payload)
+                  SELECT w.id, $2, 'milestone:' || m.words,
+                         jsonb_build_object('event', $2, 'document_id', w.document_id, 'occurred_at', NOW(),
+                             'data', jsonb_build_object('milestone', m.words, 'word_count', $4, 'version', $5))
+                  FROM editor.document_webhooks w
+                  CROSS JOIN LATERAL unnest(w.word_milestones) AS m(words)
+                  WHERE w.document_id = $1 AND w.active AND $2 = ANY(w.events)
+                    AND m.words > $3 AND m.words <= $4
+                  ON CONFLICT (webhook_id, dedupe_key) DO NOTHING";
+    let _ = conn.execute(insert, &[
+        ParameterValue::Str(document_id.to_string()),
+        ParameterValue::Str(VERSION_MILESTONE.to_string()),
+        ParameterValue::Int32(words_before),
+        ParameterValue::Int32(words_after),
+        ParameterValue::Int64(version),
+    ]);
+}
+
+fn word_count(content: &str) -> usize {
+    content.split_whitespace().count()
+}
+
+//=============================================================================
+// Delivery
+//=============================================================================
+
+fn load_endpoint(conn: &Connection, webhook_id: &str) -> Result<Option<(String, String)>, ServiceError> {
+    let query = "SELECT url, secret FROM editor.document_webhooks WHERE id = $1 AND active";
+    let rows = conn.query(query, &[ParameterValue::Str(webhook_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first().map(|row| (
+        String::decode(&row[0]).unwrap_or_default(),
+        String::decode(&row[1]).unwrap_or_default(),
+    )))
+}
+
+fn finish(
+    conn: &Connection,
+    delivery_id: &str,
+    status: &str,
+    response_status: Option<i32>,
+    error: Option<&str>,
+) -> Result<(), ServiceError> {
+    let update = "UPDATE editor.document_webhook_deliveries
+                  SET status = $2, response_status = $3, last_error = $4, updated_at = NOW(),
+                      delivered_at = CASE WHEN $2 = 'delivered' THEN NOW() ELSE delivered_at END
+                  WHERE id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(delivery_id.to_string()),
+        ParameterValue::Str(status.to_string()),
+        response_status.map(ParameterValue::Int32).unwrap_or(ParameterValue::DbNull),
+        error.map(|e| ParameterValue::Str(e.to_string())).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+/// Exponential backoff from `RETRY_BASE_SECONDS`, capped at an hour
+fn schedule_retry(
+    conn: &Connection,
+    delivery_id: &str,
+    attempts: i32,
+    response_status: Option<i32>,
+    error: &str,
+) -> Result<(), ServiceError> {
+    let delay = (RETRY_BASE_SECONDS << (attempts - 1).clamp(0, 16)).min(RETRY_MAX_SECONDS);
+    let update = "UPDATE editor.document_webhook_deliveries
+                  SET status = 'pending', response_status = $2, last_error = $3,
+                      next_attempt_at = NOW() + make_interval(secs => $4), updated_at = NOW()
+                  WHERE id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(delivery_id.to_string()),
+        response_status.map(ParameterValue::Int32).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(error.to_string()),
+        ParameterValue::Int64(delay),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+fn deactivate(conn: &Connection, webhook_id: &str) -> Result<(), ServiceError> {
+    conn.execute("UPDATE editor.document_webhooks SET active = false WHERE id = $1", &[ParameterValue::Str(webhook_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+fn send(url: &str, secret: &str, delivery_id: &str, event_type: &str, body: &str) -> Result<i32, SendError> {
+    let timestamp = Utc::now().timestamp();
+    let signature = sign(secret, timestamp, body)
+        .map_err(|error| SendError::Permanent { status: None, error, gone: false })?;
+
+    let request = OutboundRequest::builder()
+        .method(HttpMethod::Post)
+        .uri(url)
+        .header("Content-Type", "application/json")
+        .header("User-Agent", "AuthorWorks-Webhooks/1.0")
+        .header("X-AuthorWorks-Event", event_type)
+        .header("X-AuthorWorks-Delivery", delivery_id)
+        .header("X-AuthorWorks-Signature", format!("t={},v1={}", timestamp, signature))
+        .body(body.to_string())
+        .build();
+
+    let response = outbound_http::send(request)
+        .map_err(|e| SendError::Transient(None, format!("Webhook request failed: {}", e)))?;
+    let status = response.status().as_u16();
+    if status < 300 {
+        return Ok(status as i32);
+    }
+
+    let body = String::from_utf8_lossy(response.body());
+    let error = format!("Endpoint returned {} - {}", status, body.chars().take(MAX_ERROR_BODY).collect::<String>());
+    if status == 429 || status >= 500 {
+        Err(SendError::Transient(Some(status as i32), error))
+    } else {
+        Err(SendError::Permanent { status: Some(status as i32), error, gone: status == 404 || status == 410 })
+    }
+}
+
+/// Hex HMAC-SHA256 of `<timestamp>.<body>`
+fn sign(secret: &str, timestamp: i64, body: &str) -> Result<String, String> {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .map_err(|e| format!("HMAC error: {}", e))?;
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+/// HTTPS only, and never to loopback, private or link-local addresses
+fn validate_url(url: &str) -> Result<(), ServiceError> {
+    let invalid = || ServiceError::BadRequest("url must be a public https:// URL".into());
+
+    let rest = url.strip_prefix("https://").ok_or_else(invalid)?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host_port = authority.rsplit('@').next().unwrap_or_default();
+    let host = if let Some(v6) = host_port.strip_prefix('[') {
+        v6.split(']').next().unwrap_or_default()
+    } else {
+        host_port.split(':').next().unwrap_or_default()
+    };
+    let host = host.to_ascii_lowercase();
+
+    if host.is_empty() || host == "localhost" || host.ends_with(".localhost")
+        || host.ends_with(".local") || host.ends_with(".internal") || !host.contains(['.', ':'])
+    {
+        return Err(invalid());
+    }
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        let private = match ip {
+            IpAddr::V4(v4) => v4.is_private() || v4.is_loopback() || v4.is_link_local()
+                || v4.is_unspecified() || v4.is_broadcast(),
+            IpAddr::V6(v6) => v6.is_loopback() || v6.is_unspecified()
+                || (v6.segments()[0] & 0xfe00) == 0xfc00 || (v6.segments()[0] & 0xffc0) == 0xfe80,
+        };
+        if private {
+            return Err(invalid());
+        }
+    }
+    Ok(())
+}
+
+fn split_list<T: std::str::FromStr>(value: &str) -> Vec<T> {
+    value.split(',').filter_map(|v| v.trim().parse().ok()).collect()
+}
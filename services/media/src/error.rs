@@ -11,6 +11,9 @@ pub enum ServiceError {
     #[error("Unauthorized: {0}")]
     Unauthorized(String),
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
     #[error("Not found: {0}")]
     NotFound(String),
 
@@ -29,6 +32,7 @@ impl ServiceError {
         match self {
             ServiceError::BadRequest(_) => 400,
             ServiceError::Unauthorized(_) => 401,
+            ServiceError::Forbidden(_) => 403,
             ServiceError::NotFound(_) => 404,
             ServiceError::Internal(_) => 500,
         }
@@ -38,6 +42,7 @@ impl ServiceError {
         match self {
             ServiceError::BadRequest(_) => "BAD_REQUEST",
             ServiceError::Unauthorized(_) => "UNAUTHORIZED",
+            ServiceError::Forbidden(_) => "FORBIDDEN",
             ServiceError::NotFound(_) => "NOT_FOUND",
             ServiceError::Internal(_) => "INTERNAL_ERROR",
         }
@@ -63,3 +68,8 @@ impl ServiceError {
     }
 }
 
+impl From<authorworks_access::AccessError> for ServiceError {
+    fn from(e: authorworks_access::AccessError) -> Self {
+        ServiceError::Forbidden(e.to_string())
+    }
+}
@@ -24,6 +24,7 @@ use uuid::Uuid;
 
 mod models;
 mod error;
+mod scopes;
 
 use error::ServiceError;
 use models::*;
@@ -33,6 +34,10 @@ fn handle_request(req: Request) -> anyhow::Result<impl IntoResponse> {
     let path = req.path();
     let method = req.method();
 
+    if let Err(e) = authorworks_access::authorize(&req, scopes::route_scope) {
+        return Ok(ServiceError::from(e).into_response());
+    }
+
     let result = match (method, path) {
         // Health
         (Method::Get, "/health") => health_handler(),
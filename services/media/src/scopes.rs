@@ -0,0 +1,11 @@
+//! Media routes open to delegated credentials
+//!
+//! None: processing jobs and transforms work on the account's files, which no
+//! scope delegates.
+
+use authorworks_access::Route;
+use spin_sdk::http::Method;
+
+pub fn route_scope(_method: &Method, _path: &str) -> Option<Route> {
+    None
+}
@@ -0,0 +1,303 @@
+//! Broadcast announcements
+//!
+//! An announcement is recorded once in `messaging.announcements` for audit and
+//! fanned out as a `system_announcement` notification to every user in the
+//! target segment. Fan-out walks the segment in user-id order and inserts one
+//! batch per round trip so large segments never build a single giant insert.
+
+use crate::error::ServiceError;
+use crate::models::NotificationType;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use chrono::Utc;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const DEFAULT_BATCH_SIZE: i64 = 500;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Segment {
+    /// Every active user
+    All,
+    /// Users on a given plan (`plan_id` required; users without a subscription are `free`)
+    Plan,
+    /// Users who logged in within the last 30 days
+    Active,
+}
+
+impl Segment {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Segment::All => "all",
+            Segment::Plan => "plan",
+            Segment::Active => "active",
+        }
+    }
+
+    /// Filter appended to the user query, with the plan ID bound at `$N` where
+    /// `N` is `plan_param`
+    fn filter(&self, plan_param: usize) -> String {
+        match self {
+            Segment::All => String::new(),
+            Segment::Plan => format!(
+                " AND COALESCE((SELECT s.plan_id FROM subscriptions.subscriptions s WHERE s.user_id = u.id), 'free') = ${}",
+                plan_param
+            ),
+            Segment::Active => " AND u.last_login_at >= NOW() - INTERVAL '30 days'".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAnnouncementRequest {
+    pub title: String,
+    pub body: String,
+    pub segment: Segment,
+    pub plan_id: Option<String>,
+    #[serde(default)]
+    pub data: HashMap<String, serde_json::Value>,
+    /// RFC 3339 timestamp after which the announcement is hidden
+    pub expires_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Announcement {
+    pub id: Uuid,
+    pub title: String,
+    pub body: String,
+    pub data: HashMap<String, serde_json::Value>,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub dismissed: bool,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /announcements - Record an announcement and fan it out to the segment
+pub fn create_announcement(conn: &Connection, created_by: &Uuid, body: CreateAnnouncementRequest) -> Result<Response, ServiceError> {
+    if body.title.trim().is_empty() || body.body.trim().is_empty() {
+        return Err(ServiceError::BadRequest("title and body are required".into()));
+    }
+    if body.segment == Segment::Plan && body.plan_id.is_none() {
+        return Err(ServiceError::BadRequest("plan_id is required for the plan segment".into()));
+    }
+    if let Some(ref expires_at) = body.expires_at {
+        chrono::DateTime::parse_from_rfc3339(expires_at)
+            .map_err(|_| ServiceError::BadRequest("expires_at must be an RFC 3339 timestamp".into()))?;
+    }
+
+    let announcement_id = Uuid::new_v4();
+    let now = Utc::now();
+
+    let insert = "INSERT INTO messaging.announcements
+                  (id, created_by, title, body, segment, plan_id, data, expires_at, created_at)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)";
+    conn.execute(insert, &[
+        ParameterValue::Str(announcement_id.to_string()),
+        ParameterValue::Str(created_by.to_string()),
+        ParameterValue::Str(body.title.clone()),
+        ParameterValue::Str(body.body.clone()),
+        ParameterValue::Str(body.segment.as_str().to_string()),
+        body.plan_id.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(serde_json::to_string(&body.data).unwrap_or_else(|_| "{}".into())),
+        body.expires_at.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(now.to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    let recipients = fan_out(conn, &announcement_id, &body)?;
+
+    let complete = "UPDATE messaging.announcements SET recipient_count = $2, completed_at = $3 WHERE id = $1";
+    conn.execute(complete, &[
+        ParameterValue::Str(announcement_id.to_string()),
+        ParameterValue::Int64(recipients),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    crate::json_response(201, serde_json::json!({
+        "id": announcement_id,
+        "segment": body.segment,
+        "recipient_count": recipients,
+        "created_at": now.to_rfc3339()
+    }))
+}
+
+/// GET /announcements - Unexpired announcements delivered to the user
+pub fn list_announcements(conn: &Connection, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT a.id, a.title, a.body, a.data, a.created_at, a.expires_at,
+                 EXISTS (SELECT 1 FROM messaging.announcement_dismissals d
+                         WHERE d.announcement_id = a.id AND d.user_id = $1) AS dismissed
+                 FROM messaging.announcements a
+                 WHERE (a.expires_at IS NULL OR a.expires_at > NOW())
+                   AND EXISTS (SELECT 1 FROM messaging.notifications n
+                               WHERE n.announcement_id = a.id AND n.user_id = $1)
+                 ORDER BY a.created_at DESC LIMIT 50";
+
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let announcements: Vec<Announcement> = rows.rows.iter().map(|row| {
+        Announcement {
+            id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+            title: String::decode(&row[1]).unwrap_or_default(),
+            body: String::decode(&row[2]).unwrap_or_default(),
+            data: serde_json::from_str(&String::decode(&row[3]).unwrap_or_else(|_| "{}".into())).unwrap_or_default(),
+            created_at: String::decode(&row[4]).unwrap_or_default(),
+            expires_at: String::decode(&row[5]).ok(),
+            dismissed: bool::decode(&row[6]).unwrap_or(false),
+        }
+    }).collect();
+
+    let undismissed = announcements.iter().filter(|a| !a.dismissed).count();
+
+    crate::json_response(200, serde_json::json!({
+        "announcements": announcements,
+        "undismissed_count": undismissed
+    }))
+}
+
+/// POST /announcements/:id/dismiss - Hide an announcement for the user
+pub fn dismiss_announcement(conn: &Connection, user_id: &Uuid, announcement_id: &Uuid) -> Result<Response, ServiceError> {
+    let check = "SELECT 1 FROM messaging.notifications WHERE announcement_id = $1 AND user_id = $2 LIMIT 1";
+    let params = [
+        ParameterValue::Str(announcement_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ];
+    let rows = conn.query(check, &params)
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    if rows.rows.is_empty() {
+        return Err(ServiceError::NotFound("Announcement not found".into()));
+    }
+
+    let insert = "INSERT INTO messaging.announcement_dismissals (announcement_id, user_id, dismissed_at)
+                  VALUES ($1, $2, $3)
+                  ON CONFLICT (announcement_id, user_id) DO NOTHING";
+    conn.execute(insert, &[
+        ParameterValue::Str(announcement_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    // Dismissing also clears the matching notification from the unread count
+    let mark = "UPDATE messaging.notifications SET read = true WHERE announcement_id = $1 AND user_id = $2";
+    conn.execute(mark, &params).ok();
+
+    crate::json_response(200, serde_json::json!({"dismissed": true}))
+}
+
+//=============================================================================
+// Fan-out
+//=============================================================================
+
+/// Insert notifications and real-time events for every user in the segment,
+/// one batch at a time. Returns the number of recipients.
+fn fan_out(conn: &Connection, announcement_id: &Uuid, body: &CreateAnnouncementRequest) -> Result<i64, ServiceError> {
+    let batch_size = variables::get("announcement_batch_size")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_BATCH_SIZE);
+
+    // Upper bound and size of the next batch after the cursor
+    let bound_query = format!(
+        "SELECT (array_agg(id ORDER BY id DESC))[1]::text, COUNT(*) FROM (
+             SELECT u.id FROM users.users u
+             WHERE u.status = 'active' AND u.id > $1::uuid{}
+             ORDER BY u.id LIMIT $2
+         ) batch",
+        body.segment.filter(3)
+    );
+
+    // Both inserts cover the range (cursor, upper]
+    let range_filter = "u.status = 'active' AND u.id > $1::uuid AND u.id <= $2::uuid";
+    let notification_insert = format!(
+        "INSERT INTO messaging.notifications (user_id, type, title, body, data, announcement_id, created_at)
+         SELECT u.id, $3, $4, $5, $6, $7, $8 FROM users.users u WHERE {}{}",
+        range_filter, body.segment.filter(9)
+    );
+    let event_insert = format!(
+        "INSERT INTO messaging.events (user_id, type, data, created_at)
+         SELECT u.id, 'notification', $3, $4 FROM users.users u WHERE {}{}",
+        range_filter, body.segment.filter(5)
+    );
+
+    // The plan ID is only bound when the segment references it
+    let plan_param: Vec<ParameterValue> = match (&body.segment, &body.plan_id) {
+        (Segment::Plan, Some(plan_id)) => vec![ParameterValue::Str(plan_id.clone())],
+        _ => Vec::new(),
+    };
+
+    let mut data = body.data.clone();
+    data.insert("announcement_id".into(), serde_json::json!(announcement_id));
+    let data_json = serde_json::to_string(&data).unwrap_or_else(|_| "{}".into());
+    let event_json = serde_json::json!({
+        "type": NotificationType::SystemAnnouncement.to_string(),
+        "title": body.title,
+        "body": body.body,
+        "announcement_id": announcement_id
+    }).to_string();
+
+    let mut cursor = Uuid::nil().to_string();
+    let mut total = 0;
+
+    loop {
+        let mut bound_params = vec![
+            ParameterValue::Str(cursor.clone()),
+            ParameterValue::Int64(batch_size),
+        ];
+        bound_params.extend(plan_param.iter().cloned());
+        let bound_rows = conn.query(&bound_query, &bound_params)
+            .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+        let (upper, count) = match bound_rows.rows.first() {
+            Some(row) => (String::decode(&row[0]).ok(), i64::decode(&row[1]).unwrap_or(0)),
+            None => (None, 0),
+        };
+        let upper = match upper {
+            Some(upper) if count > 0 => upper,
+            _ => break,
+        };
+
+        let now = Utc::now().to_rfc3339();
+        let mut notification_params = vec![
+            ParameterValue::Str(cursor.clone()),
+            ParameterValue::Str(upper.clone()),
+            ParameterValue::Str(NotificationType::SystemAnnouncement.to_string()),
+            ParameterValue::Str(body.title.clone()),
+            ParameterValue::Str(body.body.clone()),
+            ParameterValue::Str(data_json.clone()),
+            ParameterValue::Str(announcement_id.to_string()),
+            ParameterValue::Str(now.clone()),
+        ];
+        notification_params.extend(plan_param.iter().cloned());
+        conn.execute(&notification_insert, &notification_params)
+            .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+        let mut event_params = vec![
+            ParameterValue::Str(cursor.clone()),
+            ParameterValue::Str(upper.clone()),
+            ParameterValue::Str(event_json.clone()),
+            ParameterValue::Str(now),
+        ];
+        event_params.extend(plan_param.iter().cloned());
+        conn.execute(&event_insert, &event_params)
+            .map_err(|e| ServiceError::Internal(format!("Event queue failed: {}", e)))?;
+
+        total += count;
+        if count < batch_size {
+            break;
+        }
+        cursor = upper;
+    }
+
+    Ok(total)
+}
@@ -0,0 +1,166 @@
+//! Badge counts
+//!
+//! `GET /badges` returns every number the frontend shows on a badge in one
+//! round trip: unread notifications, unread messages per conversation and
+//! real-time events still waiting to be delivered. All three come from a
+//! single query. Clients poll this often, so the result is cached per user in
+//! the Spin key-value store for `badge_cache_ttl_seconds` and dropped when the
+//! user reads something here. Other users' actions (a new message, a new
+//! notification) show up once the entry expires. The cache is best effort: if
+//! the store is unavailable every request goes to the database.
+//!
+//! Unread messages follow the conversation list: the caller's own messages
+//! and those from members they blocked are not counted. Muted conversations
+//! are listed with their count but left out of the message total.
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::key_value::Store;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use chrono::Utc;
+use uuid::Uuid;
+
+const CACHE_KEY_PREFIX: &str = "messaging:badges:";
+const DEFAULT_CACHE_TTL_SECONDS: i64 = 5;
+/// Stale badges are worse than a few extra queries
+const MAX_CACHE_TTL_SECONDS: i64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Badges {
+    pub notifications: i64,
+    /// Unread messages outside muted conversations
+    pub messages: i64,
+    /// Conversations with unread messages
+    pub conversations: Vec<ConversationBadge>,
+    pub pending_events: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationBadge {
+    pub conversation_id: Uuid,
+    pub unread_count: i64,
+    pub muted: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedBadges {
+    cached_at: i64,
+    badges: Badges,
+}
+
+//=============================================================================
+// Endpoint
+//=============================================================================
+
+/// GET /badges - Unread and pending counts for every surface
+pub fn get(conn: &Connection, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let ttl = cache_ttl();
+    let badges = match read_cache(user_id, ttl) {
+        Some(badges) => badges,
+        None => {
+            let badges = load(conn, user_id)?;
+            write_cache(user_id, ttl, &badges);
+            badges
+        }
+    };
+
+    let json = serde_json::to_string(&badges)
+        .map_err(|e| ServiceError::Internal(format!("JSON error: {}", e)))?;
+    let cache_control = format!("private, max-age={}", ttl);
+
+    Ok(Response::builder()
+        .status(200)
+        .header("Content-Type", "application/json")
+        .header("Cache-Control", cache_control.as_str())
+        .header("Access-Control-Allow-Origin", "*")
+        .body(json)
+        .build())
+}
+
+fn load(conn: &Connection, user_id: &Uuid) -> Result<Badges, ServiceError> {
+    let query = "WITH unread AS (
+                     SELECT m.conversation_id,
+                            COUNT(*) AS unread_count,
+                            bool_or(cm.muted_at IS NOT NULL AND (cm.muted_until IS NULL OR cm.muted_until > NOW())) AS muted
+                     FROM messaging.messages m
+                     JOIN messaging.conversation_members cm
+                       ON cm.conversation_id = m.conversation_id AND cm.user_id = $1
+                     WHERE m.sender_id != $1 AND m.read = false
+                       AND m.sender_id NOT IN (SELECT blocked_id FROM messaging.blocks WHERE blocker_id = $1)
+                     GROUP BY m.conversation_id
+                 )
+                 SELECT
+                     (SELECT COUNT(*) FROM messaging.notifications WHERE user_id = $1 AND read = false),
+                     (SELECT COUNT(*) FROM messaging.events
+                      WHERE user_id = $1 AND delivered = false AND dead_lettered_at IS NULL),
+                     COALESCE((SELECT json_agg(json_build_object(
+                                   'conversation_id', conversation_id,
+                                   'unread_count', unread_count,
+                                   'muted', muted
+                               ) ORDER BY conversation_id) FROM unread), '[]')::text";
+
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::Internal("Badge query returned no rows".into()))?;
+
+    let conversations: Vec<ConversationBadge> = serde_json::from_str(
+        &String::decode(&row[2]).unwrap_or_else(|_| "[]".into())
+    ).unwrap_or_default();
+    let messages = conversations.iter()
+        .filter(|c| !c.muted)
+        .map(|c| c.unread_count)
+        .sum();
+
+    Ok(Badges {
+        notifications: i64::decode(&row[0]).unwrap_or(0),
+        messages,
+        conversations,
+        pending_events: i64::decode(&row[1]).unwrap_or(0),
+    })
+}
+
+//=============================================================================
+// Cache
+//=============================================================================
+
+/// Drop the user's cached counts after they read something
+pub fn invalidate(user_id: &Uuid) {
+    if let Ok(store) = Store::open_default() {
+        let _ = store.delete(&cache_key(user_id));
+    }
+}
+
+fn cache_key(user_id: &Uuid) -> String {
+    format!("{}{}", CACHE_KEY_PREFIX, user_id)
+}
+
+fn cache_ttl() -> i64 {
+    variables::get("badge_cache_ttl_seconds")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v| *v >= 0)
+        .unwrap_or(DEFAULT_CACHE_TTL_SECONDS)
+        .min(MAX_CACHE_TTL_SECONDS)
+}
+
+fn read_cache(user_id: &Uuid, ttl: i64) -> Option<Badges> {
+    if ttl == 0 {
+        return None;
+    }
+    let bytes = Store::open_default().ok()?.get(&cache_key(user_id)).ok()??;
+    let cached: CachedBadges = serde_json::from_slice(&bytes).ok()?;
+    (Utc::now().timestamp() - cached.cached_at < ttl).then_some(cached.badges)
+}
+
+fn write_cache(user_id: &Uuid, ttl: i64, badges: &Badges) {
+    if ttl == 0 {
+        return;
+    }
+    let cached = CachedBadges { cached_at: Utc::now().timestamp(), badges: badges.clone() };
+    if let (Ok(store), Ok(bytes)) = (Store::open_default(), serde_json::to_vec(&cached)) {
+        let _ = store.set(&cache_key(user_id), &bytes);
+    }
+}
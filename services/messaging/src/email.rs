@@ -0,0 +1,644 @@
+//! Email delivery channel
+//!
+//! Notifications are written to `messaging.notifications` by this service and
+//! by others (discovery's segment notify inserts directly), so delivery works
+//! as a sweep: each run of `POST /email/deliver` queues an
+//! `messaging.email_deliveries` row for every recent notification whose type
+//! has an email template, then sends a batch of due deliveries through the
+//! configured provider (SendGrid or Amazon SES). Provider errors that may be
+//! transient (network, 429, 5xx) are retried with backoff; other rejections
+//! fail the delivery immediately.
+
+use crate::error::ServiceError;
+use crate::models::NotificationType;
+use crate::placeholders;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use spin_sdk::http::Response;
+use spin_sdk::outbound_http;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use chrono::Utc;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const DEFAULT_BATCH_SIZE: i64 = 50;
+const DEFAULT_MAX_ATTEMPTS: i32 = 5;
+const DEFAULT_ENQUEUE_WINDOW_MINUTES: i32 = 60;
+const DEFAULT_FROM_NAME: &str = "AuthorWorks";
+const DEFAULT_APP_URL: &str = "https://authorworks.leopaska.xyz";
+const SENDGRID_API_URL: &str = "https://api.sendgrid.com";
+const RETRY_BASE_SECONDS: i64 = 60;
+const RETRY_MAX_SECONDS: i64 = 3600;
+/// Deliveries stuck in `sending` this long were interrupted and are retried
+const STALE_SENDING_MINUTES: i32 = 10;
+
+//=============================================================================
+// Configuration
+//=============================================================================
+
+#[derive(Debug, Clone)]
+pub enum Provider {
+    SendGrid { api_key: String, api_url: String },
+    Ses { region: String, access_key: String, secret_key: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct EmailConfig {
+    pub provider: Provider,
+    pub from_address: String,
+    pub from_name: String,
+    pub app_url: String,
+    pub batch_size: i64,
+    pub max_attempts: i32,
+    pub enqueue_window_minutes: i32,
+}
+
+/// `None` when `email_provider` is unset, which disables email delivery
+pub fn get_email_config() -> Result<Option<EmailConfig>, ServiceError> {
+    let provider = match variables::get("email_provider").ok().as_deref() {
+        None | Some("") => return Ok(None),
+        Some("sendgrid") => Provider::SendGrid {
+            api_key: required("sendgrid_api_key")?,
+            api_url: variables::get("sendgrid_api_url").unwrap_or_else(|_| SENDGRID_API_URL.to_string()),
+        },
+        Some("ses") => Provider::Ses {
+            region: required("ses_region")?,
+            access_key: required("ses_access_key")?,
+            secret_key: required("ses_secret_key")?,
+        },
+        Some(other) => return Err(ServiceError::Internal(format!("Unknown email provider: {}", other))),
+    };
+
+    Ok(Some(EmailConfig {
+        provider,
+        from_address: required("email_from_address")?,
+        from_name: variables::get("email_from_name").unwrap_or_else(|_| DEFAULT_FROM_NAME.to_string()),
+        app_url: variables::get("app_url").unwrap_or_else(|_| DEFAULT_APP_URL.to_string()),
+        batch_size: variables::get("email_batch_size").ok()
+            .and_then(|s| s.parse::<i64>().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_BATCH_SIZE),
+        max_attempts: variables::get("email_max_attempts").ok()
+            .and_then(|s| s.parse::<i32>().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_MAX_ATTEMPTS),
+        enqueue_window_minutes: variables::get("email_enqueue_window_minutes").ok()
+            .and_then(|s| s.parse::<i32>().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_ENQUEUE_WINDOW_MINUTES),
+    }))
+}
+
+fn required(name: &str) -> Result<String, ServiceError> {
+    variables::get(name)
+        .ok()
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| ServiceError::Internal(format!("{} not configured", name)))
+}
+
+//=============================================================================
+// Templates
+//=============================================================================
+
+/// Per-type email copy. `{{placeholder}}` values come from the notification
+/// (`title`, `body`), the recipient (`name`), and the notification's `data`
+/// (`data.<key>`).
+struct EmailTemplate {
+    subject: &'static str,
+    intro: &'static str,
+    action_label: &'static str,
+    /// Path under `app_url`; a `url` in the notification data takes precedence
+    action_path: &'static str,
+}
+
+fn template_for(notification_type: &NotificationType) -> Option<EmailTemplate> {
+    match notification_type {
+        NotificationType::BookPublished => Some(EmailTemplate {
+            subject: "New book: {{title}}",
+            intro: "A book you follow has just been published.",
+            action_label: "Start reading",
+            action_path: "/books/{{data.book_id}}",
+        }),
+        NotificationType::CommentAdded => Some(EmailTemplate {
+            subject: "New comment: {{title}}",
+            intro: "Someone commented on your work.",
+            action_label: "View comment",
+            action_path: "/books/{{data.book_id}}",
+        }),
+        NotificationType::MentionedInComment => Some(EmailTemplate {
+            subject: "You were mentioned: {{title}}",
+            intro: "You were mentioned in a comment.",
+            action_label: "View comment",
+            action_path: "/books/{{data.book_id}}",
+        }),
+        NotificationType::CollaboratorAdded => Some(EmailTemplate {
+            subject: "{{title}}",
+            intro: "You have been added as a collaborator.",
+            action_label: "Open book",
+            action_path: "/books/{{data.book_id}}",
+        }),
+        NotificationType::SubscriptionExpiring => Some(EmailTemplate {
+            subject: "Your AuthorWorks subscription is expiring",
+            intro: "Your subscription needs attention.",
+            action_label: "Manage subscription",
+            action_path: "/settings/billing",
+        }),
+        NotificationType::PaymentFailed => Some(EmailTemplate {
+            subject: "Action required: payment failed",
+            intro: "We couldn't process your latest payment.",
+            action_label: "Update payment method",
+            action_path: "/settings/billing",
+        }),
+        NotificationType::SystemAnnouncement => Some(EmailTemplate {
+            subject: "{{title}}",
+            intro: "News from the AuthorWorks team.",
+            action_label: "Open AuthorWorks",
+            action_path: "/",
+        }),
+        NotificationType::AuthorMessage => Some(EmailTemplate {
+            subject: "{{title}}",
+            intro: "A message from an author you read.",
+            action_label: "Open AuthorWorks",
+            action_path: "/",
+        }),
+        NotificationType::AccountInactive => Some(EmailTemplate {
+            subject: "Your AuthorWorks account is inactive",
+            intro: "You haven't signed in to AuthorWorks for a long time.",
+            action_label: "Sign in to keep your account",
+            action_path: "/login",
+        }),
+        NotificationType::DataExportReady => Some(EmailTemplate {
+            subject: "Your AuthorWorks data export is ready",
+            intro: "The copy of your messages and notifications you asked for is ready to download.",
+            action_label: "Download export",
+            action_path: "/settings/privacy",
+        }),
+        // Too frequent to email; in-app only
+        NotificationType::ChapterComplete | NotificationType::WritingMilestone => None,
+    }
+}
+
+/// Notification types that have an email template, as stored in `type`
+fn emailable_types() -> Vec<String> {
+    [
+        NotificationType::BookPublished,
+        NotificationType::ChapterComplete,
+        NotificationType::CommentAdded,
+        NotificationType::MentionedInComment,
+        NotificationType::CollaboratorAdded,
+        NotificationType::SubscriptionExpiring,
+        NotificationType::PaymentFailed,
+        NotificationType::SystemAnnouncement,
+        NotificationType::AuthorMessage,
+        NotificationType::AccountInactive,
+        NotificationType::WritingMilestone,
+        NotificationType::DataExportReady,
+    ]
+    .iter()
+    .filter(|t| template_for(t).is_some())
+    .map(|t| t.to_string())
+    .collect()
+}
+
+struct RenderedEmail {
+    subject: String,
+    text: String,
+    html: String,
+}
+
+fn render(
+    config: &EmailConfig,
+    template: &EmailTemplate,
+    recipient_name: Option<&str>,
+    title: &str,
+    body: &str,
+    data: &serde_json::Map<String, serde_json::Value>,
+) -> RenderedEmail {
+    let mut values: HashMap<String, String> = HashMap::new();
+    values.insert("name".into(), recipient_name.unwrap_or("there").to_string());
+    values.insert("title".into(), title.to_string());
+    values.insert("body".into(), body.to_string());
+    for (key, value) in data {
+        let value = match value {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        values.insert(format!("data.{}", key), value);
+    }
+
+    let action_url = match data.get("url").and_then(|v| v.as_str()) {
+        Some(url) if url.starts_with("https://") => url.to_string(),
+        Some(path) if path.starts_with('/') => format!("{}{}", config.app_url, path),
+        // A path whose data is missing would link nowhere useful
+        _ if has_all_placeholders(template.action_path, &values) => {
+            format!("{}{}", config.app_url, fill(template.action_path, &values, false))
+        }
+        _ => config.app_url.clone(),
+    };
+
+    let subject = fill(template.subject, &values, false);
+    let greeting = fill("Hi {{name}},", &values, false);
+
+    let text = format!(
+        "{}\n\n{}\n\n{}\n\n{}\n\n{}: {}\n\n--\nYou received this email because of your notification settings on AuthorWorks.",
+        greeting, template.intro, title, body, template.action_label, action_url
+    );
+
+    let html = format!(
+        "<!DOCTYPE html><html><body style=\"font-family: sans-serif; line-height: 1.5; color: #222;\">\
+         <p>{}</p><p>{}</p><h2>{}</h2><p>{}</p>\
+         <p><a href=\"{}\" style=\"display: inline-block; padding: 10px 18px; background: #4f46e5; color: #fff; text-decoration: none; border-radius: 6px;\">{}</a></p>\
+         <p style=\"font-size: 12px; color: #888;\">You received this email because of your notification settings on AuthorWorks.</p>\
+         </body></html>",
+        fill("Hi {{name}},", &values, true),
+        escape_html(template.intro),
+        escape_html(title),
+        escape_html(body).replace('\n', "<br>"),
+        escape_html(&action_url),
+        escape_html(template.action_label)
+    );
+
+    RenderedEmail { subject, text, html }
+}
+
+/// Unknown keys render empty
+fn fill(template: &str, values: &HashMap<String, String>, html: bool) -> String {
+    placeholders::render(template, |key| {
+        let value = values.get(key).map(|s| s.as_str()).unwrap_or("");
+        Some(if html { escape_html(value) } else { value.to_string() })
+    })
+}
+
+fn has_all_placeholders(template: &str, values: &HashMap<String, String>) -> bool {
+    placeholders::names(template).iter().all(|key| values.contains_key(key))
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Default, Serialize)]
+pub struct DeliverySummary {
+    pub enabled: bool,
+    pub enqueued: u64,
+    pub sent: u64,
+    pub retrying: u64,
+    pub failed: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmailDelivery {
+    pub id: Uuid,
+    pub notification_id: Uuid,
+    pub notification_type: String,
+    pub to_address: String,
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub provider_message_id: Option<String>,
+    pub created_at: String,
+    pub sent_at: Option<String>,
+}
+
+/// Outcome of one provider call
+enum SendError {
+    /// Worth retrying: network failure, rate limiting, or a provider 5xx
+    Transient(String),
+    /// The provider rejected the message; retrying will not help
+    Permanent(String),
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /email/deliver - Queue emails for new notifications and send due ones
+pub fn deliver(conn: &Connection) -> Result<Response, ServiceError> {
+    let config = match get_email_config()? {
+        Some(config) => config,
+        None => return crate::json_response(200, DeliverySummary::default()),
+    };
+
+    let mut summary = DeliverySummary { enabled: true, ..Default::default() };
+    summary.enqueued = enqueue(conn, &config)?;
+
+    // Recover deliveries interrupted mid-send
+    let reset = "UPDATE messaging.email_deliveries SET status = 'pending', updated_at = NOW()
+                 WHERE status = 'sending' AND updated_at < NOW() - make_interval(mins => $1)";
+    conn.execute(reset, &[ParameterValue::Int32(STALE_SENDING_MINUTES)])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let claim = "UPDATE messaging.email_deliveries SET status = 'sending', attempts = attempts + 1, updated_at = NOW()
+                 WHERE id IN (
+                     SELECT id FROM messaging.email_deliveries
+                     WHERE status = 'pending' AND next_attempt_at <= NOW()
+                     ORDER BY next_attempt_at
+                     LIMIT $1
+                     FOR UPDATE SKIP LOCKED
+                 )
+                 RETURNING id, notification_id, to_address, attempts";
+    let claimed = conn.query(claim, &[ParameterValue::Int64(config.batch_size)])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    for row in &claimed.rows {
+        let delivery_id = String::decode(&row[0]).unwrap_or_default();
+        let notification_id = String::decode(&row[1]).unwrap_or_default();
+        let to_address = String::decode(&row[2]).unwrap_or_default();
+        let attempts = i32::decode(&row[3]).unwrap_or(1);
+
+        let result = match build_email(conn, &config, &notification_id)? {
+            Some(email) => send(&config, &to_address, &email),
+            None => Err(SendError::Permanent("Notification or template no longer available".into())),
+        };
+
+        match result {
+            Ok(message_id) => {
+                mark_sent(conn, &delivery_id, message_id)?;
+                summary.sent += 1;
+            }
+            Err(SendError::Transient(error)) if attempts < config.max_attempts => {
+                schedule_retry(conn, &delivery_id, attempts, &error)?;
+                summary.retrying += 1;
+            }
+            Err(SendError::Transient(error)) | Err(SendError::Permanent(error)) => {
+                mark_failed(conn, &delivery_id, &error)?;
+                summary.failed += 1;
+            }
+        }
+    }
+
+    crate::json_response(200, summary)
+}
+
+/// GET /email/deliveries - Delivery status of the user's recent emails
+pub fn list_deliveries(conn: &Connection, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT id, notification_id, notification_type, to_address, status, attempts,
+                        last_error, provider_message_id, created_at, sent_at
+                 FROM messaging.email_deliveries
+                 WHERE user_id = $1
+                 ORDER BY created_at DESC LIMIT 50";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let deliveries: Vec<EmailDelivery> = rows.rows.iter().map(|row| {
+        EmailDelivery {
+            id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+            notification_id: Uuid::parse_str(&String::decode(&row[1]).unwrap_or_default()).unwrap_or_default(),
+            notification_type: String::decode(&row[2]).unwrap_or_default(),
+            to_address: String::decode(&row[3]).unwrap_or_default(),
+            status: String::decode(&row[4]).unwrap_or_default(),
+            attempts: i32::decode(&row[5]).unwrap_or(0),
+            last_error: String::decode(&row[6]).ok(),
+            provider_message_id: String::decode(&row[7]).ok(),
+            created_at: String::decode(&row[8]).unwrap_or_default(),
+            sent_at: String::decode(&row[9]).ok(),
+        }
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({ "deliveries": deliveries }))
+}
+
+//=============================================================================
+// Queue
+//=============================================================================
+
+/// Queue one delivery per recent emailable notification. Author messages are
+/// marketing and only go to users who opted in to marketing email.
+fn enqueue(conn: &Connection, config: &EmailConfig) -> Result<u64, ServiceError> {
+    let insert = "INSERT INTO messaging.email_deliveries
+                  (notification_id, user_id, notification_type, to_address, status, next_attempt_at, created_at, updated_at)
+                  SELECT n.id, n.user_id, n.type, u.email, 'pending', NOW(), NOW(), NOW()
+                  FROM messaging.notifications n
+                  JOIN users.users u ON u.id = n.user_id
+                  WHERE n.created_at > NOW() - make_interval(mins => $1)
+                    AND n.type = ANY(string_to_array($2, ','))
+                    AND u.status = 'active'
+                    AND (n.type <> $3 OR EXISTS (
+                        SELECT 1 FROM users.consents c WHERE c.user_id = n.user_id AND c.marketing_emails = true
+                    ))
+                  ON CONFLICT (notification_id) DO NOTHING";
+    conn.execute(insert, &[
+        ParameterValue::Int32(config.enqueue_window_minutes),
+        ParameterValue::Str(emailable_types().join(",")),
+        ParameterValue::Str(NotificationType::AuthorMessage.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))
+}
+
+fn build_email(conn: &Connection, config: &EmailConfig, notification_id: &str) -> Result<Option<RenderedEmail>, ServiceError> {
+    let query = "SELECT n.type, n.title, n.body, n.data::text, u.name
+                 FROM messaging.notifications n
+                 JOIN users.users u ON u.id = n.user_id
+                 WHERE n.id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(notification_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let row = match rows.rows.first() {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+
+    let notification_type: Option<NotificationType> =
+        serde_json::from_value(serde_json::Value::String(String::decode(&row[0]).unwrap_or_default())).ok();
+    let template = match notification_type.as_ref().and_then(template_for) {
+        Some(template) => template,
+        None => return Ok(None),
+    };
+
+    let data: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(&String::decode(&row[3]).unwrap_or_else(|_| "{}".into())).unwrap_or_default();
+    let name = String::decode(&row[4]).ok();
+
+    Ok(Some(render(
+        config,
+        &template,
+        name.as_deref(),
+        &String::decode(&row[1]).unwrap_or_default(),
+        &String::decode(&row[2]).unwrap_or_default(),
+        &data,
+    )))
+}
+
+fn mark_sent(conn: &Connection, delivery_id: &str, provider_message_id: Option<String>) -> Result<(), ServiceError> {
+    let update = "UPDATE messaging.email_deliveries
+                  SET status = 'sent', provider_message_id = $2, last_error = NULL, sent_at = $3, updated_at = $3
+                  WHERE id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(delivery_id.to_string()),
+        provider_message_id.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+/// Exponential backoff from `RETRY_BASE_SECONDS`, capped at an hour
+fn schedule_retry(conn: &Connection, delivery_id: &str, attempts: i32, error: &str) -> Result<(), ServiceError> {
+    let delay = (RETRY_BASE_SECONDS << (attempts - 1).clamp(0, 16)).min(RETRY_MAX_SECONDS);
+    let update = "UPDATE messaging.email_deliveries
+                  SET status = 'pending', last_error = $2, next_attempt_at = NOW() + make_interval(secs => $3), updated_at = NOW()
+                  WHERE id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(delivery_id.to_string()),
+        ParameterValue::Str(error.to_string()),
+        ParameterValue::Int64(delay),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+fn mark_failed(conn: &Connection, delivery_id: &str, error: &str) -> Result<(), ServiceError> {
+    let update = "UPDATE messaging.email_deliveries SET status = 'failed', last_error = $2, updated_at = NOW() WHERE id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(delivery_id.to_string()),
+        ParameterValue::Str(error.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+//=============================================================================
+// Providers
+//=============================================================================
+
+/// Send through the configured provider, returning its message ID if any
+fn send(config: &EmailConfig, to_address: &str, email: &RenderedEmail) -> Result<Option<String>, SendError> {
+    match &config.provider {
+        Provider::SendGrid { api_key, api_url } => send_sendgrid(config, api_key, api_url, to_address, email),
+        Provider::Ses { region, access_key, secret_key } => {
+            send_ses(config, region, access_key, secret_key, to_address, email)
+        }
+    }
+}
+
+fn send_sendgrid(
+    config: &EmailConfig,
+    api_key: &str,
+    api_url: &str,
+    to_address: &str,
+    email: &RenderedEmail,
+) -> Result<Option<String>, SendError> {
+    let body = serde_json::json!({
+        "personalizations": [{ "to": [{ "email": to_address }] }],
+        "from": { "email": config.from_address, "name": config.from_name },
+        "subject": email.subject,
+        "content": [
+            { "type": "text/plain", "value": email.text },
+            { "type": "text/html", "value": email.html }
+        ]
+    });
+
+    let request = outbound_http::Request::builder()
+        .method(outbound_http::Method::Post)
+        .uri(format!("{}/v3/mail/send", api_url))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .build();
+
+    let response = outbound_http::send(request)
+        .map_err(|e| SendError::Transient(format!("SendGrid request failed: {}", e)))?;
+    check_status("SendGrid", response.status().as_u16(), response.body())?;
+
+    Ok(response.headers().get("X-Message-Id").and_then(|h| h.to_str().ok()).map(|s| s.to_string()))
+}
+
+/// SES v2 `SendEmail`, signed with AWS Signature Version 4
+fn send_ses(
+    config: &EmailConfig,
+    region: &str,
+    access_key: &str,
+    secret_key: &str,
+    to_address: &str,
+    email: &RenderedEmail,
+) -> Result<Option<String>, SendError> {
+    let body = serde_json::json!({
+        "FromEmailAddress": format!("{} <{}>", config.from_name, config.from_address),
+        "Destination": { "ToAddresses": [to_address] },
+        "Content": {
+            "Simple": {
+                "Subject": { "Data": email.subject, "Charset": "UTF-8" },
+                "Body": {
+                    "Text": { "Data": email.text, "Charset": "UTF-8" },
+                    "Html": { "Data": email.html, "Charset": "UTF-8" }
+                }
+            }
+        }
+    }).to_string();
+
+    let now = Utc::now();
+    let date_str = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date_short = now.format("%Y%m%d").to_string();
+    let host = format!("email.{}.amazonaws.com", region);
+    let path = "/v2/email/outbound-emails";
+
+    let payload_hash = hex::encode(Sha256::digest(body.as_bytes()));
+    let signed_headers = "content-type;host;x-amz-date";
+    let canonical_request = format!(
+        "POST\n{}\n\ncontent-type:application/json\nhost:{}\nx-amz-date:{}\n\n{}\n{}",
+        path, host, date_str, signed_headers, payload_hash
+    );
+    let credential_scope = format!("{}/{}/ses/aws4_request", date_short, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        date_str, credential_scope, hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let signature = sign_aws4(secret_key, &date_short, region, "ses", &string_to_sign)
+        .map_err(SendError::Permanent)?;
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{},SignedHeaders={},Signature={}",
+        access_key, credential_scope, signed_headers, signature
+    );
+
+    let request = outbound_http::Request::builder()
+        .method(outbound_http::Method::Post)
+        .uri(format!("https://{}{}", host, path))
+        .header("Authorization", authorization)
+        .header("Content-Type", "application/json")
+        .header("X-Amz-Date", date_str)
+        .body(body)
+        .build();
+
+    let response = outbound_http::send(request)
+        .map_err(|e| SendError::Transient(format!("SES request failed: {}", e)))?;
+    check_status("SES", response.status().as_u16(), response.body())?;
+
+    let parsed: serde_json::Value = serde_json::from_slice(response.body()).unwrap_or_default();
+    Ok(parsed.get("MessageId").and_then(|v| v.as_str()).map(|s| s.to_string()))
+}
+
+fn check_status(provider: &str, status: u16, body: &[u8]) -> Result<(), SendError> {
+    if status < 400 {
+        return Ok(());
+    }
+    let error = format!("{} API error: {} - {}", provider, status, String::from_utf8_lossy(body));
+    if status == 429 || status >= 500 {
+        Err(SendError::Transient(error))
+    } else {
+        Err(SendError::Permanent(error))
+    }
+}
+
+fn sign_aws4(secret: &str, date: &str, region: &str, service: &str, string_to_sign: &str) -> Result<String, String> {
+    let k_date = hmac_sha256(format!("AWS4{}", secret).as_bytes(), date.as_bytes())?;
+    let k_region = hmac_sha256(&k_date, region.as_bytes())?;
+    let k_service = hmac_sha256(&k_region, service.as_bytes())?;
+    let k_signing = hmac_sha256(&k_service, b"aws4_request")?;
+    let signature = hmac_sha256(&k_signing, string_to_sign.as_bytes())?;
+    Ok(hex::encode(signature))
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut mac = HmacSha256::new_from_slice(key)
+        .map_err(|e| format!("HMAC error: {}", e))?;
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
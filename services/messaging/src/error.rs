@@ -17,6 +17,9 @@ pub enum ServiceError {
     #[error("Not found: {0}")]
     NotFound(String),
 
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
     #[error("Internal error: {0}")]
     Internal(String),
 }
@@ -34,6 +37,7 @@ impl ServiceError {
             ServiceError::Unauthorized(_) => 401,
             ServiceError::Forbidden(_) => 403,
             ServiceError::NotFound(_) => 404,
+            ServiceError::Conflict(_) => 409,
             ServiceError::Internal(_) => 500,
         }
     }
@@ -44,6 +48,7 @@ impl ServiceError {
             ServiceError::Unauthorized(_) => "UNAUTHORIZED",
             ServiceError::Forbidden(_) => "FORBIDDEN",
             ServiceError::NotFound(_) => "NOT_FOUND",
+            ServiceError::Conflict(_) => "CONFLICT",
             ServiceError::Internal(_) => "INTERNAL_ERROR",
         }
     }
@@ -68,3 +73,11 @@ impl ServiceError {
     }
 }
 
+impl From<authorworks_access::AccessError> for ServiceError {
+    fn from(e: authorworks_access::AccessError) -> Self {
+        match e {
+            authorworks_access::AccessError::RoleLookup(msg) => ServiceError::Internal(format!("Query failed: {}", msg)),
+            e => ServiceError::Forbidden(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! Real-time event delivery
+//!
+//! Events are delivered at least once. `GET /events/subscribe` hands out
+//! pending events and records the attempt; the client confirms receipt with
+//! `POST /events/ack`. Events not acknowledged within
+//! `event_ack_timeout_seconds` are sent again on a later subscribe, so clients
+//! should dedupe by event id. After `event_max_delivery_attempts` unacked
+//! sends an event is dead-lettered: it stops being delivered and shows up in
+//! the admin dead-letter list instead. Higher-priority events are claimed
+//! first when more are pending than fit in a batch.
+//!
+//! The endpoint speaks SSE in long-poll mode: a Spin component cannot hold a
+//! response open and write to it over time, so each request waits up to
+//! `event_long_poll_seconds` (or `?wait=`) for events, returns them and ends.
+//! The body starts with a `retry:` directive so `EventSource` reconnects
+//! right away, and an idle poll returns a `: keep-alive` comment. Each event's
+//! SSE `id:` is its sequence number, increasing within and across responses,
+//! and `EventSource` sends the last one back as `Last-Event-ID` on reconnect
+//! (clients without `EventSource` can pass `?last_event_id=`). Resuming from
+//! an id acknowledges the delivered events up to it and makes delivered but
+//! unacknowledged events after it due again at once, since the client says
+//! it never got them.
+//!
+//! `?types=message,notification` limits a subscription to those event types.
+//! The filter applies to claiming and to resuming alike. A Last-Event-ID only
+//! acknowledges, or makes due again, events of the subscribed types, because
+//! each type is delivered and acknowledged on its own. So a tab subscribed to
+//! messages cannot mark notifications delivered that another tab was sent
+//! but has not confirmed yet. Events of other types stay pending for
+//! whichever subscriber asks for them.
+
+use crate::error::ServiceError;
+use crate::muting;
+use serde::Deserialize;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const BATCH_SIZE: i64 = 10;
+const DEFAULT_ACK_TIMEOUT_SECONDS: i32 = 30;
+const DEFAULT_MAX_ATTEMPTS: i32 = 5;
+const MAX_ACK_IDS: usize = 100;
+const DEFAULT_LONG_POLL_SECONDS: i32 = 25;
+/// Stays under common proxy idle timeouts
+const MAX_LONG_POLL_SECONDS: i32 = 55;
+const POLL_INTERVAL_MS: u64 = 1000;
+const RECONNECT_MS: u32 = 1000;
+const MAX_TYPES: usize = 20;
+const MAX_TYPE_CHARS: usize = 64;
+
+pub const DEFAULT_DEAD_LETTER_LIMIT: i64 = 50;
+pub const MAX_DEAD_LETTER_LIMIT: i64 = 500;
+
+#[derive(Debug, Deserialize)]
+pub struct AckRequest {
+    pub event_ids: Vec<Uuid>,
+}
+
+/// Where a subscriber resumes and how long it is willing to wait
+#[derive(Debug, Default)]
+pub struct SubscribeOptions {
+    pub last_event_id: Option<i64>,
+    pub wait_seconds: Option<i32>,
+    /// Only these event types; all types when `None`
+    pub types: Option<Vec<String>>,
+}
+
+/// Parse a comma-separated `types` filter
+pub fn parse_types(raw: &str) -> Result<Vec<String>, ServiceError> {
+    let mut types: Vec<String> = Vec::new();
+    for event_type in raw.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+        let valid = event_type.chars().count() <= MAX_TYPE_CHARS
+            && event_type.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | ':' | '-'));
+        if !valid {
+            return Err(ServiceError::BadRequest(format!("Invalid event type: {}", event_type)));
+        }
+        if !types.iter().any(|t| t == event_type) {
+            types.push(event_type.to_string());
+        }
+    }
+    if types.is_empty() {
+        return Err(ServiceError::BadRequest("types must name at least one event type".into()));
+    }
+    if types.len() > MAX_TYPES {
+        return Err(ServiceError::BadRequest(format!("At most {} event types per subscription", MAX_TYPES)));
+    }
+    Ok(types)
+}
+
+/// The filter as a `text[]` parameter; `NULL` matches every type
+fn types_param(types: Option<&[String]>) -> ParameterValue {
+    match types {
+        // Types are validated to characters that need no quoting in an array literal
+        Some(types) => ParameterValue::Str(format!("{{{}}}", types.join(","))),
+        None => ParameterValue::DbNull,
+    }
+}
+
+struct DeliveryConfig {
+    ack_timeout_seconds: i32,
+    max_attempts: i32,
+    long_poll_seconds: i32,
+}
+
+fn get_delivery_config() -> DeliveryConfig {
+    DeliveryConfig {
+        ack_timeout_seconds: variables::get("event_ack_timeout_seconds")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(DEFAULT_ACK_TIMEOUT_SECONDS),
+        max_attempts: variables::get("event_max_delivery_attempts")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(DEFAULT_MAX_ATTEMPTS),
+        long_poll_seconds: variables::get("event_long_poll_seconds")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|v| *v >= 0)
+            .unwrap_or(DEFAULT_LONG_POLL_SECONDS)
+            .min(MAX_LONG_POLL_SECONDS),
+    }
+}
+
+//=============================================================================
+// Delivery
+//=============================================================================
+
+/// GET /events/subscribe - Wait for due events and return them as an SSE body
+pub fn subscribe(conn: &Connection, user_id: &Uuid, options: SubscribeOptions) -> Result<Response, ServiceError> {
+    let config = get_delivery_config();
+    let wait_seconds = options.wait_seconds
+        .unwrap_or(config.long_poll_seconds)
+        .clamp(0, MAX_LONG_POLL_SECONDS);
+    let types = options.types.as_deref();
+
+    if let Some(last_event_id) = options.last_event_id {
+        resume(conn, user_id, last_event_id, types)?;
+    }
+
+    // Events that used their last attempt without an ack stop here
+    let dead_letter = "UPDATE messaging.events SET dead_lettered_at = NOW()
+                       WHERE user_id = $1 AND delivered = false AND dead_lettered_at IS NULL
+                         AND delivery_attempts >= $2
+                         AND last_delivered_at < NOW() - make_interval(secs => $3)";
+    conn.execute(dead_letter, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Int32(config.max_attempts),
+        ParameterValue::Int32(config.ack_timeout_seconds),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let deadline = Instant::now() + Duration::from_secs(wait_seconds as u64);
+    let mut events = claim(conn, user_id, &config, types)?;
+    while events.is_empty() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+        events = claim(conn, user_id, &config, types)?;
+    }
+
+    let mut sse_data = format!("retry: {}\n\n", RECONNECT_MS);
+    if events.is_empty() {
+        sse_data.push_str(": keep-alive\n\n");
+    }
+    for (seq, event) in &events {
+        sse_data.push_str(&format!("id: {}\ndata: {}\n\n", seq, event));
+    }
+
+    Ok(Response::builder()
+        .status(200)
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(sse_data)
+        .build())
+}
+
+/// Apply a reconnecting client's Last-Event-ID: what it saw is acknowledged,
+/// what it was sent but missed is due again. Both only touch the types the
+/// client subscribes to.
+fn resume(conn: &Connection, user_id: &Uuid, last_event_id: i64, types: Option<&[String]>) -> Result<(), ServiceError> {
+    let ack = "UPDATE messaging.events SET delivered = true, acked_at = NOW()
+               WHERE user_id = $1 AND seq <= $2 AND delivered = false AND delivery_attempts > 0
+                 AND ($3::text[] IS NULL OR type = ANY($3::text[]))";
+    conn.execute(ack, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Int64(last_event_id),
+        types_param(types),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let redeliver = "UPDATE messaging.events SET last_delivered_at = NULL
+                     WHERE user_id = $1 AND seq > $2 AND delivered = false
+                       AND dead_lettered_at IS NULL AND last_delivered_at IS NOT NULL
+                       AND ($3::text[] IS NULL OR type = ANY($3::text[]))";
+    conn.execute(redeliver, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Int64(last_event_id),
+        types_param(types),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+/// Claim up to a batch of due events, returned in sequence order
+fn claim(conn: &Connection, user_id: &Uuid, config: &DeliveryConfig, types: Option<&[String]>) -> Result<Vec<(i64, serde_json::Value)>, ServiceError> {
+    // Claim and count the attempt in one statement so concurrent subscribers
+    // do not both send an event inside the same ack window
+    let claim = "UPDATE messaging.events e SET
+                 delivery_attempts = e.delivery_attempts + 1,
+                 last_delivered_at = NOW()
+                 FROM (
+                     SELECT id FROM messaging.events
+                     WHERE user_id = $1 AND delivered = false AND dead_lettered_at IS NULL
+                       AND delivery_attempts < $2
+                       AND (last_delivered_at IS NULL OR last_delivered_at < NOW() - make_interval(secs => $3))
+                       AND ($6::text[] IS NULL OR type = ANY($6::text[]))
+                     ORDER BY array_position($5::text[], priority) DESC NULLS LAST, created_at ASC
+                     LIMIT $4
+                     FOR UPDATE SKIP LOCKED
+                 ) due
+                 WHERE e.id = due.id
+                 RETURNING e.id, e.type, e.data::text, e.created_at, e.delivery_attempts, e.priority, e.seq";
+    let rows = conn.query(claim, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Int32(config.max_attempts),
+        ParameterValue::Int32(config.ack_timeout_seconds),
+        ParameterValue::Int64(BATCH_SIZE),
+        ParameterValue::Str(format!("{{{}}}", muting::PRIORITIES.join(","))),
+        types_param(types),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let mut events: Vec<(i64, serde_json::Value)> = rows.rows.iter().map(|row| {
+        let seq = i64::decode(&row[6]).unwrap_or(0);
+        (seq, serde_json::json!({
+            "id": String::decode(&row[0]).unwrap_or_default(),
+            "seq": seq,
+            "type": String::decode(&row[1]).unwrap_or_default(),
+            "data": serde_json::from_str::<serde_json::Value>(
+                &String::decode(&row[2]).unwrap_or_else(|_| "{}".into())
+            ).unwrap_or_default(),
+            "priority": String::decode(&row[5]).unwrap_or_else(|_| muting::DEFAULT_PRIORITY.into()),
+            "created_at": String::decode(&row[3]).unwrap_or_default(),
+            "attempt": i32::decode(&row[4]).unwrap_or(1)
+        }))
+    }).collect();
+    // Priority picks which events make the batch; ids must still increase
+    // so that Last-Event-ID covers everything before it
+    events.sort_by_key(|(seq, _)| *seq);
+    Ok(events)
+}
+
+/// POST /events/ack - Confirm receipt so events are not redelivered
+pub fn ack(conn: &Connection, user_id: &Uuid, body: AckRequest) -> Result<Response, ServiceError> {
+    if body.event_ids.is_empty() {
+        return Err(ServiceError::BadRequest("event_ids is required".into()));
+    }
+    if body.event_ids.len() > MAX_ACK_IDS {
+        return Err(ServiceError::BadRequest(format!("At most {} event_ids per request", MAX_ACK_IDS)));
+    }
+
+    let ids = body.event_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",");
+    // Late acks for dead-lettered events still count: the client did get them
+    let update = "UPDATE messaging.events SET delivered = true, acked_at = NOW()
+                  WHERE user_id = $1 AND id = ANY(string_to_array($2, ',')::uuid[])
+                    AND delivered = false AND delivery_attempts > 0";
+    let acknowledged = conn.execute(update, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(ids),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    crate::json_response(200, serde_json::json!({
+        "acknowledged": acknowledged,
+        "requested": body.event_ids.len()
+    }))
+}
+
+//=============================================================================
+// Dead Letters
+//=============================================================================
+
+/// GET /admin/events/dead-letters - Events that exhausted their delivery attempts
+pub fn list_dead_letters(conn: &Connection, user_id: Option<Uuid>, limit: i64) -> Result<Response, ServiceError> {
+    let query = "SELECT id, user_id, type, data::text, delivery_attempts, created_at,
+                        last_delivered_at, dead_lettered_at
+                 FROM messaging.events
+                 WHERE dead_lettered_at IS NOT NULL AND delivered = false
+                   AND ($1::uuid IS NULL OR user_id = $1::uuid)
+                 ORDER BY dead_lettered_at DESC
+                 LIMIT $2";
+    let rows = conn.query(query, &[
+        user_id.map(|u| ParameterValue::Str(u.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Int64(limit),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let events: Vec<serde_json::Value> = rows.rows.iter().map(|row| serde_json::json!({
+        "id": String::decode(&row[0]).unwrap_or_default(),
+        "user_id": String::decode(&row[1]).unwrap_or_default(),
+        "type": String::decode(&row[2]).unwrap_or_default(),
+        "data": serde_json::from_str::<serde_json::Value>(
+            &String::decode(&row[3]).unwrap_or_else(|_| "{}".into())
+        ).unwrap_or_default(),
+        "delivery_attempts": i32::decode(&row[4]).unwrap_or(0),
+        "created_at": String::decode(&row[5]).unwrap_or_default(),
+        "last_delivered_at": String::decode(&row[6]).ok(),
+        "dead_lettered_at": String::decode(&row[7]).unwrap_or_default()
+    })).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "dead_letters": events,
+        "total": events.len()
+    }))
+}
@@ -0,0 +1,447 @@
+//! Data exports
+//!
+//! `POST /export/messages` queues a copy of everything this service holds
+//! for the caller: the conversations they are a member of with all their
+//! messages, their notifications, and their real-time events. Building the
+//! file can take a while, so it happens in a sweep: each run of
+//! `POST /export/messages/process` claims a batch of pending exports,
+//! renders them as JSON or CSV, uploads the file to the storage service as
+//! the user, and notifies them with the file to download. Failed attempts
+//! go back to pending until `MAX_ATTEMPTS` is reached.
+//!
+//! CSV exports are a single file with a `record_type` column
+//! (`conversation`, `message`, `notification` or `event`); columns that do
+//! not apply to a record are left empty.
+
+use crate::error::ServiceError;
+use crate::models::{CreateNotificationRequest, NotificationType};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::outbound_http;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use chrono::Utc;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const FORMATS: [&str; 2] = ["json", "csv"];
+const DEFAULT_BATCH_SIZE: i64 = 5;
+const MAX_ATTEMPTS: i32 = 3;
+/// Exports stuck in `processing` this long were interrupted and are retried
+const STALE_PROCESSING_MINUTES: i32 = 30;
+/// The storage service rejects uploads above this size
+const MAX_EXPORT_BYTES: usize = 100 * 1024 * 1024;
+const LIST_LIMIT: i64 = 20;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct CreateExportRequest {
+    #[serde(default = "default_format")]
+    pub format: String,
+}
+
+fn default_format() -> String {
+    "json".into()
+}
+
+#[derive(Debug, Serialize)]
+pub struct DataExport {
+    pub id: Uuid,
+    pub format: String,
+    pub status: String,
+    pub file_id: Option<Uuid>,
+    pub size_bytes: Option<i64>,
+    pub record_counts: Option<serde_json::Value>,
+    pub last_error: Option<String>,
+    pub created_at: String,
+    pub completed_at: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ProcessSummary {
+    pub claimed: usize,
+    pub completed: usize,
+    pub retried: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Default)]
+struct Dump {
+    conversations: Vec<serde_json::Value>,
+    notifications: Vec<serde_json::Value>,
+    events: Vec<serde_json::Value>,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /export/messages - Queue an export of the caller's messaging data
+pub fn create(conn: &Connection, user_id: &Uuid, body: CreateExportRequest) -> Result<Response, ServiceError> {
+    let format = body.format.to_lowercase();
+    if !FORMATS.contains(&format.as_str()) {
+        return Err(ServiceError::BadRequest(format!("format must be one of: {}", FORMATS.join(", "))));
+    }
+
+    let query = "SELECT id FROM messaging.data_exports
+                 WHERE user_id = $1 AND status IN ('pending', 'processing')";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if let Some(row) = rows.rows.first() {
+        return Err(ServiceError::Conflict(format!(
+            "Export {} is already in progress", String::decode(&row[0]).unwrap_or_default()
+        )));
+    }
+
+    let id = Uuid::new_v4();
+    let now = Utc::now().to_rfc3339();
+    let insert = "INSERT INTO messaging.data_exports (id, user_id, format, created_at, updated_at)
+                  VALUES ($1, $2, $3, $4, $4)";
+    conn.execute(insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(format.clone()),
+        ParameterValue::Str(now.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    crate::json_response(202, serde_json::json!({
+        "id": id,
+        "format": format,
+        "status": "pending",
+        "created_at": now,
+        "message": "Export queued; you will be notified when it is ready to download"
+    }))
+}
+
+/// GET /export/messages - The caller's recent exports
+pub fn list(conn: &Connection, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT id, format, status, file_id, size_bytes, record_counts::text, last_error, created_at, completed_at
+                 FROM messaging.data_exports
+                 WHERE user_id = $1
+                 ORDER BY created_at DESC
+                 LIMIT $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Int64(LIST_LIMIT),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let exports: Vec<DataExport> = rows.rows.iter().map(|row| DataExport {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        format: String::decode(&row[1]).unwrap_or_default(),
+        status: String::decode(&row[2]).unwrap_or_default(),
+        file_id: String::decode(&row[3]).ok().and_then(|id| Uuid::parse_str(&id).ok()),
+        size_bytes: i64::decode(&row[4]).ok(),
+        record_counts: String::decode(&row[5]).ok().and_then(|c| serde_json::from_str(&c).ok()),
+        last_error: String::decode(&row[6]).ok(),
+        created_at: String::decode(&row[7]).unwrap_or_default(),
+        completed_at: String::decode(&row[8]).ok(),
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({ "exports": exports }))
+}
+
+/// POST /export/messages/process - Build and deliver a batch of pending exports (internal)
+pub fn process(conn: &Connection) -> Result<Response, ServiceError> {
+    let batch_size = variables::get("data_export_batch_size").ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_BATCH_SIZE);
+
+    // Recover exports interrupted mid-build
+    let reset = "UPDATE messaging.data_exports SET status = 'pending', updated_at = NOW()
+                 WHERE status = 'processing' AND updated_at < NOW() - make_interval(mins => $1)";
+    conn.execute(reset, &[ParameterValue::Int32(STALE_PROCESSING_MINUTES)])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let claim = "UPDATE messaging.data_exports SET status = 'processing', attempts = attempts + 1, updated_at = NOW()
+                 WHERE id IN (
+                     SELECT id FROM messaging.data_exports
+                     WHERE status = 'pending'
+                     ORDER BY created_at
+                     LIMIT $1
+                     FOR UPDATE SKIP LOCKED
+                 )
+                 RETURNING id, user_id, format, attempts";
+    let claimed = conn.query(claim, &[ParameterValue::Int64(batch_size)])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut summary = ProcessSummary { claimed: claimed.rows.len(), ..Default::default() };
+    for row in &claimed.rows {
+        let export_id = String::decode(&row[0]).unwrap_or_default();
+        let user_id = Uuid::parse_str(&String::decode(&row[1]).unwrap_or_default()).unwrap_or_default();
+        let format = String::decode(&row[2]).unwrap_or_default();
+        let attempts = i32::decode(&row[3]).unwrap_or(1);
+
+        match build_and_upload(conn, &export_id, &user_id, &format) {
+            Ok(()) => summary.completed += 1,
+            Err(error) => {
+                let status = if attempts >= MAX_ATTEMPTS { "failed" } else { "pending" };
+                let update = "UPDATE messaging.data_exports SET status = $2, last_error = $3, updated_at = NOW() WHERE id = $1";
+                conn.execute(update, &[
+                    ParameterValue::Str(export_id.clone()),
+                    ParameterValue::Str(status.to_string()),
+                    ParameterValue::Str(error),
+                ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+                if status == "failed" {
+                    summary.failed += 1;
+                } else {
+                    summary.retried += 1;
+                }
+            }
+        }
+    }
+
+    crate::json_response(200, summary)
+}
+
+//=============================================================================
+// Building
+//=============================================================================
+
+fn build_and_upload(conn: &Connection, export_id: &str, user_id: &Uuid, format: &str) -> Result<(), String> {
+    let dump = collect(conn, user_id).map_err(|e| e.to_string())?;
+    let message_count: usize = dump.conversations.iter()
+        .map(|c| c["messages"].as_array().map(|m| m.len()).unwrap_or(0))
+        .sum();
+    let counts = serde_json::json!({
+        "conversations": dump.conversations.len(),
+        "messages": message_count,
+        "notifications": dump.notifications.len(),
+        "events": dump.events.len()
+    });
+
+    let exported_at = Utc::now();
+    let (content, content_type) = match format {
+        "csv" => (render_csv(&dump), "text/csv"),
+        _ => {
+            let document = serde_json::json!({
+                "user_id": user_id,
+                "exported_at": exported_at.to_rfc3339(),
+                "conversations": dump.conversations,
+                "notifications": dump.notifications,
+                "events": dump.events
+            });
+            // `<` only occurs inside strings; escaping it keeps message text that
+            // looks like markup from being sniffed as HTML by the storage service
+            let json = serde_json::to_string_pretty(&document).map_err(|e| e.to_string())?;
+            (json.replace('<', "\\u003c"), "application/json")
+        }
+    };
+    if content.len() > MAX_EXPORT_BYTES {
+        return Err(format!("Export is {} bytes; the storage limit is {}", content.len(), MAX_EXPORT_BYTES));
+    }
+
+    let filename = format!("messages-export-{}.{}", exported_at.format("%Y%m%d"), format);
+    let file_id = upload(user_id, export_id, &filename, content_type, content.as_bytes())?;
+
+    let update = "UPDATE messaging.data_exports
+                  SET status = 'completed', file_id = $2, size_bytes = $3, record_counts = $4::jsonb,
+                      last_error = NULL, completed_at = NOW(), updated_at = NOW()
+                  WHERE id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(export_id.to_string()),
+        ParameterValue::Str(file_id.to_string()),
+        ParameterValue::Int64(content.len() as i64),
+        ParameterValue::Str(counts.to_string()),
+    ]).map_err(|e| format!("Update failed: {}", e))?;
+
+    let notification = CreateNotificationRequest {
+        user_id: *user_id,
+        notification_type: NotificationType::DataExportReady.to_string(),
+        title: "Your data export is ready".into(),
+        body: format!("The copy of your messages and notifications you requested is ready to download ({}).", filename),
+        data: HashMap::from([
+            ("export_id".to_string(), serde_json::json!(export_id)),
+            ("file_id".to_string(), serde_json::json!(file_id)),
+            ("download_url".to_string(), serde_json::json!(format!("/files/{}/download", file_id))),
+        ]),
+        priority: None,
+        group_key: None,
+    };
+    let priority = crate::muting::notification_priority(None, &notification.notification_type)
+        .map_err(|e| e.to_string())?;
+    crate::insert_notification(conn, &notification, &priority).map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+fn collect(conn: &Connection, user_id: &Uuid) -> Result<Dump, ServiceError> {
+    let user = [ParameterValue::Str(user_id.to_string())];
+
+    let messages_query = "SELECT msg.id, msg.conversation_id, msg.sender_id, msg.body, msg.attachments::text, msg.created_at
+                          FROM messaging.messages msg
+                          JOIN messaging.conversation_members m ON m.conversation_id = msg.conversation_id
+                          WHERE m.user_id = $1
+                          ORDER BY msg.conversation_id, msg.created_at";
+    let rows = conn.query(messages_query, &user)
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let mut messages: HashMap<String, Vec<serde_json::Value>> = HashMap::new();
+    for row in &rows.rows {
+        let sender_id = String::decode(&row[2]).unwrap_or_default();
+        messages.entry(String::decode(&row[1]).unwrap_or_default()).or_default().push(serde_json::json!({
+            "id": String::decode(&row[0]).unwrap_or_default(),
+            "sent_by_me": sender_id == user_id.to_string(),
+            "sender_id": sender_id,
+            "body": String::decode(&row[3]).unwrap_or_default(),
+            "attachments": json_column(&row[4]),
+            "created_at": String::decode(&row[5]).unwrap_or_default()
+        }));
+    }
+
+    let conversations_query = "SELECT c.id, c.name, c.type, c.created_at, m.joined_at,
+                                      (SELECT string_agg(o.user_id::text, ',') FROM messaging.conversation_members o
+                                       WHERE o.conversation_id = c.id)
+                               FROM messaging.conversations c
+                               JOIN messaging.conversation_members m ON m.conversation_id = c.id
+                               WHERE m.user_id = $1
+                               ORDER BY c.created_at";
+    let rows = conn.query(conversations_query, &user)
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let conversations = rows.rows.iter().map(|row| {
+        let id = String::decode(&row[0]).unwrap_or_default();
+        let members: Vec<String> = String::decode(&row[5]).unwrap_or_default()
+            .split(',')
+            .filter(|m| !m.is_empty())
+            .map(String::from)
+            .collect();
+        serde_json::json!({
+            "id": id,
+            "name": String::decode(&row[1]).ok(),
+            "type": String::decode(&row[2]).unwrap_or_default(),
+            "created_at": String::decode(&row[3]).unwrap_or_default(),
+            "joined_at": String::decode(&row[4]).ok(),
+            "member_ids": members,
+            "messages": messages.remove(&id).unwrap_or_default()
+        })
+    }).collect();
+
+    let notifications_query = "SELECT id, type, title, body, data::text, read, priority, created_at
+                               FROM messaging.notifications WHERE user_id = $1 ORDER BY created_at";
+    let rows = conn.query(notifications_query, &user)
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let notifications = rows.rows.iter().map(|row| serde_json::json!({
+        "id": String::decode(&row[0]).unwrap_or_default(),
+        "type": String::decode(&row[1]).unwrap_or_default(),
+        "title": String::decode(&row[2]).unwrap_or_default(),
+        "body": String::decode(&row[3]).unwrap_or_default(),
+        "data": json_column(&row[4]),
+        "read": bool::decode(&row[5]).unwrap_or(false),
+        "priority": String::decode(&row[6]).ok(),
+        "created_at": String::decode(&row[7]).unwrap_or_default()
+    })).collect();
+
+    let events_query = "SELECT id, type, data::text, created_at
+                        FROM messaging.events WHERE user_id = $1 ORDER BY created_at";
+    let rows = conn.query(events_query, &user)
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let events = rows.rows.iter().map(|row| serde_json::json!({
+        "id": String::decode(&row[0]).unwrap_or_default(),
+        "type": String::decode(&row[1]).unwrap_or_default(),
+        "data": json_column(&row[2]),
+        "created_at": String::decode(&row[3]).unwrap_or_default()
+    })).collect();
+
+    Ok(Dump { conversations, notifications, events })
+}
+
+fn render_csv(dump: &Dump) -> String {
+    let mut out = String::from("record_type,id,conversation_id,sender_id,type,title,body,data,read,created_at\n");
+    let text = |value: &serde_json::Value| match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let mut push = |fields: [String; 10]| {
+        let line: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&line.join(","));
+        out.push('\n');
+    };
+
+    for c in &dump.conversations {
+        push([
+            "conversation".into(), text(&c["id"]), String::new(), String::new(), text(&c["type"]),
+            text(&c["name"]), String::new(), text(&c["member_ids"]), String::new(), text(&c["created_at"]),
+        ]);
+        for m in c["messages"].as_array().into_iter().flatten() {
+            push([
+                "message".into(), text(&m["id"]), text(&c["id"]), text(&m["sender_id"]), String::new(),
+                String::new(), text(&m["body"]), text(&m["attachments"]), String::new(), text(&m["created_at"]),
+            ]);
+        }
+    }
+    for n in &dump.notifications {
+        push([
+            "notification".into(), text(&n["id"]), String::new(), String::new(), text(&n["type"]),
+            text(&n["title"]), text(&n["body"]), text(&n["data"]), text(&n["read"]), text(&n["created_at"]),
+        ]);
+    }
+    for e in &dump.events {
+        push([
+            "event".into(), text(&e["id"]), String::new(), String::new(), text(&e["type"]),
+            String::new(), String::new(), text(&e["data"]), String::new(), text(&e["created_at"]),
+        ]);
+    }
+    out
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+/// Quote a field when it holds a separator, quote or line break (RFC 4180)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn json_column(value: &spin_sdk::pg::DbValue) -> serde_json::Value {
+    String::decode(value).ok()
+        .and_then(|v| serde_json::from_str(&v).ok())
+        .unwrap_or(serde_json::Value::Null)
+}
+
+/// Store the export through the storage service, owned by the user
+fn upload(user_id: &Uuid, export_id: &str, filename: &str, content_type: &str, content: &[u8]) -> Result<Uuid, String> {
+    let storage_url = variables::get("storage_service_url")
+        .unwrap_or_else(|_| "http://storage-service:3103".to_string());
+
+    let body = serde_json::json!({
+        "filename": filename,
+        "content": BASE64.encode(content),
+        "content_type": content_type,
+        "file_type": "document",
+        "size": content.len(),
+        "metadata": {
+            "source": "messaging_data_export",
+            "export_id": export_id
+        }
+    });
+    let request = outbound_http::Request::builder()
+        .method(outbound_http::Method::Post)
+        .uri(format!("{}/upload", storage_url))
+        .header("Content-Type", "application/json")
+        .header("X-User-Id", user_id.to_string())
+        .body(body.to_string())
+        .build();
+
+    let response = outbound_http::send(request)
+        .map_err(|e| format!("Storage upload failed: {}", e))?;
+    let status = response.status().as_u16();
+    if status >= 300 {
+        let body = String::from_utf8_lossy(response.body());
+        return Err(format!("Storage returned {} - {}", status, body.chars().take(500).collect::<String>()));
+    }
+
+    let stored: serde_json::Value = serde_json::from_slice(response.body())
+        .map_err(|e| format!("Invalid storage response: {}", e))?;
+    stored.get("id")
+        .and_then(|id| id.as_str())
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| "Storage response has no file id".to_string())
+}
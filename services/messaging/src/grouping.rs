@@ -0,0 +1,156 @@
+//! Notification grouping
+//!
+//! Notifications about the same thing share a group key, so fifty "new
+//! comment" notifications on one chapter list as a single row: the latest
+//! member, the group size and how many are unread. The key is given by the
+//! sender or derived from the notification type and the chapter, document,
+//! book or conversation in its data. Any member's id expands the group or
+//! marks the whole group read.
+
+use crate::error::ServiceError;
+use crate::models::Notification;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, DbValue, Decode, ParameterValue};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Data fields naming what a notification is about, most specific first
+const SUBJECT_FIELDS: [&str; 4] = ["chapter_id", "document_id", "book_id", "conversation_id"];
+
+const MAX_KEY_LENGTH: usize = 255;
+const DEFAULT_MEMBER_LIMIT: i64 = 50;
+const MAX_MEMBER_LIMIT: i64 = 200;
+
+/// Columns read by `from_row`, in order
+pub const COLUMNS: &str = "id, type, title, body, data, read, created_at, priority, group_key";
+
+/// Group key for a new notification: the requested one, or type plus subject
+pub fn group_key(
+    requested: Option<&str>,
+    notification_type: &str,
+    data: &HashMap<String, serde_json::Value>,
+) -> Result<Option<String>, ServiceError> {
+    if let Some(key) = requested {
+        let key = key.trim();
+        if key.chars().count() > MAX_KEY_LENGTH {
+            return Err(ServiceError::BadRequest(format!("group_key must be at most {} characters", MAX_KEY_LENGTH)));
+        }
+        return Ok((!key.is_empty()).then(|| key.to_string()));
+    }
+
+    Ok(SUBJECT_FIELDS.iter().find_map(|field| {
+        let value = match data.get(*field)? {
+            serde_json::Value::String(s) if !s.is_empty() => s.clone(),
+            serde_json::Value::Number(n) => n.to_string(),
+            _ => return None,
+        };
+        Some(format!("{}:{}:{}", notification_type, field, value))
+            .filter(|key| key.chars().count() <= MAX_KEY_LENGTH)
+    }))
+}
+
+/// A notification row selected with `COLUMNS`, as a group of one
+pub fn from_row(row: &[DbValue]) -> Notification {
+    let read = bool::decode(&row[5]).unwrap_or(false);
+    Notification {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        notification_type: String::decode(&row[1]).unwrap_or_default(),
+        title: String::decode(&row[2]).unwrap_or_default(),
+        body: String::decode(&row[3]).unwrap_or_default(),
+        data: serde_json::from_str(&String::decode(&row[4]).unwrap_or_else(|_| "{}".into())).unwrap_or_default(),
+        read,
+        created_at: String::decode(&row[6]).unwrap_or_default(),
+        priority: String::decode(&row[7]).unwrap_or_else(|_| crate::muting::DEFAULT_PRIORITY.into()),
+        group_key: String::decode(&row[8]).ok(),
+        group_count: 1,
+        group_unread: if read { 0 } else { 1 },
+    }
+}
+
+/// GET /notifications/:id/group - Every notification in the group of `id`,
+/// newest first; `before` pages back from an earlier response
+pub fn list_members(
+    conn: &Connection,
+    user_id: &Uuid,
+    notification_id: &Uuid,
+    before: Option<String>,
+    limit: Option<i64>,
+) -> Result<Response, ServiceError> {
+    let limit = limit.unwrap_or(DEFAULT_MEMBER_LIMIT).clamp(1, MAX_MEMBER_LIMIT);
+    let group_key = member_group_key(conn, user_id, notification_id)?;
+
+    let query = format!(
+        "SELECT {} FROM messaging.notifications
+         WHERE user_id = $1
+           AND (($2::text IS NULL AND id = $3::uuid) OR group_key = $2)
+           AND ($4::timestamptz IS NULL OR created_at < $4::timestamptz)
+         ORDER BY created_at DESC
+         LIMIT $5",
+        COLUMNS
+    );
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(user_id.to_string()),
+        group_key.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(notification_id.to_string()),
+        before.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Int64(limit),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let members: Vec<Notification> = rows.rows.iter().map(|row| from_row(row)).collect();
+
+    let count_query = "SELECT COUNT(*), COUNT(*) FILTER (WHERE read = false)
+                       FROM messaging.notifications
+                       WHERE user_id = $1 AND (($2::text IS NULL AND id = $3::uuid) OR group_key = $2)";
+    let rows = conn.query(count_query, &[
+        ParameterValue::Str(user_id.to_string()),
+        group_key.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(notification_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let (total, unread) = rows.rows.first()
+        .map(|row| (i64::decode(&row[0]).unwrap_or(0), i64::decode(&row[1]).unwrap_or(0)))
+        .unwrap_or((0, 0));
+
+    let next_before = (members.len() as i64 == limit)
+        .then(|| members.last().map(|n| n.created_at.clone()))
+        .flatten();
+
+    crate::json_response(200, serde_json::json!({
+        "group_key": group_key,
+        "count": total,
+        "unread_count": unread,
+        "notifications": members,
+        "next_before": next_before
+    }))
+}
+
+/// PUT /notifications/:id/group/read - Mark every notification in the group
+/// of `id` read
+pub fn mark_read(conn: &Connection, user_id: &Uuid, notification_id: &Uuid) -> Result<Response, ServiceError> {
+    let group_key = member_group_key(conn, user_id, notification_id)?;
+
+    let update = "UPDATE messaging.notifications SET read = true
+                  WHERE user_id = $1 AND read = false
+                    AND (($2::text IS NULL AND id = $3::uuid) OR group_key = $2)";
+    let count = conn.execute(update, &[
+        ParameterValue::Str(user_id.to_string()),
+        group_key.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(notification_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    crate::json_response(200, serde_json::json!({
+        "group_key": group_key,
+        "marked_read": count
+    }))
+}
+
+/// Group key of one of the user's notifications; `None` when ungrouped
+fn member_group_key(conn: &Connection, user_id: &Uuid, notification_id: &Uuid) -> Result<Option<String>, ServiceError> {
+    let query = "SELECT group_key FROM messaging.notifications WHERE id = $1 AND user_id = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(notification_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Notification not found".into()))?;
+    Ok(String::decode(&row[0]).ok())
+}
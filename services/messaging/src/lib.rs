@@ -5,16 +5,52 @@
 //!
 //! ## Endpoints
 //! - GET /health - Health check
-//! - GET /notifications - List user notifications
-//! - POST /notifications - Create notification (admin)
+//! - GET /notifications?min_priority=&grouped= - List user notifications, collapsing each group to its latest member
+//! - GET /notifications/:id/group?before=&limit= - List the notifications grouped with one
+//! - PUT /notifications/:id/group/read - Mark a notification's whole group as read
+//! - POST /notifications - Create notification with a priority (admin)
+//! - POST /notifications/from-template - Create a notification from a named template in the recipient's language (internal, X-Internal-Token)
 //! - PUT /notifications/:id/read - Mark as read
 //! - DELETE /notifications/:id - Delete notification
+//! - GET /badges - Unread notification, per-conversation unread and pending event counts in one call
+//! - GET /announcements - List announcements with dismissal state
+//! - POST /announcements - Broadcast announcement to a user segment (admin)
+//! - POST /announcements/:id/dismiss - Dismiss announcement
 //! - GET /messages - List conversations
+//! - GET /messages/search - Full-text search across the user's conversations
 //! - GET /messages/:conversation_id - Get conversation messages
-//! - POST /messages - Send message
+//! - POST /messages - Send message, or hold it until `scheduled_at`
+//! - GET /messages/scheduled?status=&limit= - List the caller's scheduled messages (pending by default)
+//! - DELETE /messages/scheduled/:id - Cancel a scheduled message before it is sent
+//! - POST /messages/scheduled/dispatch - Send scheduled messages that are due (internal, X-Internal-Token)
 //! - DELETE /messages/:id - Delete message
+//! - POST /messages/:id/report - Report a message to the moderation queue
+//! - POST /conversations/:id/block - Block a member; their messages are hidden from you
+//! - DELETE /conversations/:id/block - Unblock a member
+//! - PUT /conversations/:id/mute - Mute message notifications for a duration or until unmuted
+//! - DELETE /conversations/:id/mute - Unmute a conversation
+//! - GET /admin/moderation/reports?status=&limit= - Moderation queue (admin)
+//! - POST /admin/moderation/reports/:id/resolve - Mark a report actioned or dismissed (admin)
+//! - POST /admin/moderation/suspensions - Suspend a user's messaging (admin)
+//! - DELETE /admin/moderation/suspensions/:user_id - Lift a messaging suspension (admin)
 //! - POST /events - Publish event to queue
-//! - GET /events/subscribe - SSE endpoint for real-time events
+//! - GET /events/subscribe?last_event_id=&wait=&types= - Long-poll SSE for real-time events, optionally only some types; resumes from Last-Event-ID (redelivered until acknowledged)
+//! - POST /events/ack - Acknowledge received events by ID
+//! - GET /admin/events/dead-letters - Events that exhausted their delivery attempts (admin)
+//! - GET /admin/templates?name= - List notification templates and their translations (admin)
+//! - POST /admin/templates - Create a notification template for one language (admin)
+//! - PUT /admin/templates/:id - Update a notification template (admin)
+//! - DELETE /admin/templates/:id - Delete a notification template (admin)
+//! - POST /email/deliver - Queue and send notification emails (internal, X-Internal-Token)
+//! - GET /email/deliveries - Email delivery status for the user's notifications
+//! - POST /integrations/webhooks - Register a Slack, Discord or JSON webhook for an event type
+//! - GET /integrations/webhooks - List the user's webhooks
+//! - DELETE /integrations/webhooks/:id - Remove a webhook
+//! - GET /integrations/webhooks/:id/deliveries - Webhook delivery log
+//! - POST /integrations/webhooks/deliver - Queue and send signed webhook deliveries (internal, X-Internal-Token)
+//! - POST /export/messages - Request a JSON or CSV export of your conversations, notifications and events
+//! - GET /export/messages - List your exports and their download files
+//! - POST /export/messages/process - Build pending exports, store them and notify their owners (internal, X-Internal-Token)
 
 use spin_sdk::http::{IntoResponse, Request, Response, Method};
 use spin_sdk::http_component;
@@ -26,6 +62,20 @@ use uuid::Uuid;
 
 mod models;
 mod error;
+mod announcements;
+mod email;
+mod search;
+mod webhooks;
+mod event_delivery;
+mod moderation;
+mod muting;
+mod templates;
+mod exports;
+mod grouping;
+mod scheduled;
+mod badges;
+mod scopes;
+mod placeholders;
 
 use error::ServiceError;
 use models::*;
@@ -35,6 +85,10 @@ fn handle_request(req: Request) -> anyhow::Result<impl IntoResponse> {
     let path = req.path();
     let method = req.method();
 
+    if let Err(e) = authorworks_access::authorize(&req, scopes::route_scope) {
+        return Ok(ServiceError::from(e).into_response());
+    }
+
     let result = match (method, path) {
         // Health
         (Method::Get, "/health") => health_handler(),
@@ -43,21 +97,99 @@ fn handle_request(req: Request) -> anyhow::Result<impl IntoResponse> {
         // Notifications
         (Method::Get, "/notifications") => list_notifications(&req),
         (Method::Post, "/notifications") => create_notification(&req),
+        (Method::Post, "/notifications/from-template") => create_notification_from_template(&req),
+        (Method::Get, path) if path.starts_with("/notifications/") && path.ends_with("/group") => {
+            list_notification_group(&req, path)
+        }
+        (Method::Put, path) if path.starts_with("/notifications/") && path.ends_with("/group/read") => {
+            mark_notification_group_read(&req, path)
+        }
         (Method::Put, path) if path.ends_with("/read") => mark_notification_read(&req, path),
         (Method::Delete, path) if path.starts_with("/notifications/") => delete_notification(&req, path),
         (Method::Post, "/notifications/read-all") => mark_all_read(&req),
 
+        // Badges
+        (Method::Get, "/badges") => get_badges(&req),
+
+        // Announcements
+        (Method::Get, "/announcements") => list_announcements(&req),
+        (Method::Post, "/announcements") => create_announcement(&req),
+        (Method::Post, path) if path.starts_with("/announcements/") && path.ends_with("/dismiss") => {
+            dismiss_announcement(&req, path)
+        }
+
         // Messages
         (Method::Get, "/messages") => list_conversations(&req),
+        (Method::Get, "/messages/search") => search_messages(&req),
+        (Method::Get, "/messages/scheduled") => list_scheduled_messages(&req),
+        (Method::Post, "/messages/scheduled/dispatch") => dispatch_scheduled_messages(&req),
+        (Method::Delete, path) if path.starts_with("/messages/scheduled/") => {
+            cancel_scheduled_message(&req, path)
+        }
         (Method::Get, path) if path.starts_with("/messages/") => get_conversation(&req, path),
         (Method::Post, "/messages") => send_message(&req),
+        (Method::Post, path) if path.starts_with("/messages/") && path.ends_with("/report") => {
+            report_message(&req, path)
+        }
         (Method::Delete, path) if path.starts_with("/messages/") && !path.contains('/') => {
             delete_message(&req, path)
         }
 
+        // Blocking
+        (Method::Post, path) if path.starts_with("/conversations/") && path.ends_with("/block") => {
+            block_member(&req, path)
+        }
+        (Method::Delete, path) if path.starts_with("/conversations/") && path.ends_with("/block") => {
+            unblock_member(&req, path)
+        }
+
+        // Muting
+        (Method::Put, path) if path.starts_with("/conversations/") && path.ends_with("/mute") => {
+            mute_conversation(&req, path)
+        }
+        (Method::Delete, path) if path.starts_with("/conversations/") && path.ends_with("/mute") => {
+            unmute_conversation(&req, path)
+        }
+
+        // Moderation (admin)
+        (Method::Get, "/admin/moderation/reports") => list_moderation_reports(&req),
+        (Method::Post, path) if path.starts_with("/admin/moderation/reports/") && path.ends_with("/resolve") => {
+            resolve_moderation_report(&req, path)
+        }
+        (Method::Post, "/admin/moderation/suspensions") => suspend_messaging(&req),
+        (Method::Delete, path) if path.starts_with("/admin/moderation/suspensions/") => {
+            lift_messaging_suspension(&req, path)
+        }
+
         // Events
         (Method::Post, "/events") => publish_event(&req),
         (Method::Get, "/events/subscribe") => subscribe_events(&req),
+        (Method::Post, "/events/ack") => ack_events(&req),
+        (Method::Get, "/admin/events/dead-letters") => list_dead_letter_events(&req),
+
+        // Notification templates (admin)
+        (Method::Get, "/admin/templates") => list_templates(&req),
+        (Method::Post, "/admin/templates") => create_template(&req),
+        (Method::Put, path) if path.starts_with("/admin/templates/") => update_template(&req, path),
+        (Method::Delete, path) if path.starts_with("/admin/templates/") => delete_template(&req, path),
+
+        // Email
+        (Method::Post, "/email/deliver") => deliver_emails(&req),
+        (Method::Get, "/email/deliveries") => list_email_deliveries(&req),
+
+        // Webhook integrations
+        (Method::Post, "/integrations/webhooks/deliver") => deliver_webhooks(&req),
+        (Method::Post, "/integrations/webhooks") => register_webhook(&req),
+        (Method::Get, "/integrations/webhooks") => list_webhooks(&req),
+        (Method::Get, path) if path.starts_with("/integrations/webhooks/") && path.ends_with("/deliveries") => {
+            list_webhook_deliveries(&req, path)
+        }
+        (Method::Delete, path) if path.starts_with("/integrations/webhooks/") => delete_webhook(&req, path),
+
+        // Data exports
+        (Method::Post, "/export/messages/process") => process_data_exports(&req),
+        (Method::Post, "/export/messages") => create_data_export(&req),
+        (Method::Get, "/export/messages") => list_data_exports(&req),
 
         // CORS
         (Method::Options, _) => cors_preflight(),
@@ -114,7 +246,7 @@ fn service_info() -> Result<Response, ServiceError> {
     json_response(200, serde_json::json!({
         "service": "AuthorWorks Messaging Service",
         "version": env!("CARGO_PKG_VERSION"),
-        "features": ["notifications", "direct-messages", "real-time-events"]
+        "features": ["notifications", "announcements", "direct-messages", "message-search", "real-time-events", "event-acks", "email", "webhooks", "moderation", "conversation-muting", "notification-priority", "data-export", "notification-grouping", "scheduled-messages", "badge-counts", "event-type-filters"]
     }))
 }
 
@@ -123,7 +255,7 @@ fn cors_preflight() -> Result<Response, ServiceError> {
         .status(204)
         .header("Access-Control-Allow-Origin", "*")
         .header("Access-Control-Allow-Methods", "GET, POST, PUT, DELETE, OPTIONS")
-        .header("Access-Control-Allow-Headers", "Content-Type, Authorization, X-User-Id")
+        .header("Access-Control-Allow-Headers", "Content-Type, Authorization, X-User-Id, Last-Event-ID")
         .header("Access-Control-Max-Age", "86400")
         .body(())
         .build())
@@ -135,27 +267,49 @@ fn cors_preflight() -> Result<Response, ServiceError> {
 
 fn list_notifications(req: &Request) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
+    let min_priority = get_query_param(req, "min_priority")
+        .map(|p| muting::parse_priority(&p))
+        .transpose()?;
+    let grouped = get_query_param(req, "grouped").map(|g| g != "false").unwrap_or(true);
     let conn = get_db_connection()?;
 
-    let query = "SELECT id, type, title, body, data, read, created_at
-                 FROM messaging.notifications
-                 WHERE user_id = $1
-                 ORDER BY created_at DESC LIMIT 50";
+    // Each group is represented by its latest member; ungrouped notifications
+    // are groups of one
+    let query = format!(
+        "SELECT {}, group_count, group_unread
+         FROM (
+             SELECT n.*,
+                    COUNT(*) OVER w AS group_count,
+                    COUNT(*) FILTER (WHERE NOT n.read) OVER w AS group_unread,
+                    ROW_NUMBER() OVER (w ORDER BY n.created_at DESC) AS group_position
+             FROM messaging.notifications n
+             WHERE n.user_id = $1
+               AND ($2::text IS NULL OR array_position($3::text[], n.priority) >= array_position($3::text[], $2::text))
+             WINDOW w AS (PARTITION BY COALESCE(n.group_key, n.id::text))
+         ) g
+         WHERE group_position = 1 OR NOT $4
+         ORDER BY created_at DESC LIMIT 50",
+        grouping::COLUMNS
+    );
 
     let params = [ParameterValue::Str(user_id.to_string())];
-    let rows = conn.query(query, &params)
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(user_id.to_string()),
+        min_priority.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(format!("{{{}}}", muting::PRIORITIES.join(","))),
+        ParameterValue::Boolean(grouped),
+    ])
         .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
 
     let notifications: Vec<Notification> = rows.rows.iter().map(|row| {
-        Notification {
-            id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
-            notification_type: String::decode(&row[1]).unwrap_or_default(),
-            title: String::decode(&row[2]).unwrap_or_default(),
-            body: String::decode(&row[3]).unwrap_or_default(),
-            data: serde_json::from_str(&String::decode(&row[4]).unwrap_or_else(|_| "{}".into())).unwrap_or_default(),
-            read: bool::decode(&row[5]).unwrap_or(false),
-            created_at: String::decode(&row[6]).unwrap_or_default(),
+        let mut notification = grouping::from_row(row);
+        if grouped {
+            notification.group_count = i64::decode(&row[9]).unwrap_or(1);
+            notification.group_unread = i64::decode(&row[10]).unwrap_or(0);
+            // A collapsed row stays unread until every member is read
+            notification.read = notification.group_unread == 0;
         }
+        notification
     }).collect();
 
     // Count unread
@@ -176,14 +330,57 @@ fn list_notifications(req: &Request) -> Result<Response, ServiceError> {
 
 fn create_notification(req: &Request) -> Result<Response, ServiceError> {
     let body: CreateNotificationRequest = parse_json_body(req)?;
+    let priority = muting::notification_priority(body.priority.clone(), &body.notification_type)?;
     let conn = get_db_connection()?;
 
+    let (notification_id, created_at) = insert_notification(&conn, &body, &priority)?;
+
+    json_response(201, serde_json::json!({
+        "id": notification_id,
+        "priority": priority,
+        "created_at": created_at
+    }))
+}
+
+fn create_notification_from_template(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let body: templates::FromTemplateRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    let rendered = templates::render(&conn, &body)?;
+    let notification = CreateNotificationRequest {
+        user_id: body.user_id,
+        notification_type: rendered.notification_type,
+        title: rendered.title,
+        body: rendered.body,
+        data: body.data,
+        priority: body.priority.or(rendered.priority),
+        group_key: body.group_key,
+    };
+    let priority = muting::notification_priority(notification.priority.clone(), &notification.notification_type)?;
+
+    let (notification_id, created_at) = insert_notification(&conn, &notification, &priority)?;
+
+    json_response(201, serde_json::json!({
+        "id": notification_id,
+        "type": notification.notification_type,
+        "title": notification.title,
+        "body": notification.body,
+        "language": rendered.language,
+        "priority": priority,
+        "created_at": created_at
+    }))
+}
+
+/// Store a notification and queue its real-time event
+fn insert_notification(conn: &Connection, body: &CreateNotificationRequest, priority: &str) -> Result<(Uuid, String), ServiceError> {
     let notification_id = Uuid::new_v4();
     let now = Utc::now();
+    let group_key = grouping::group_key(body.group_key.as_deref(), &body.notification_type, &body.data)?;
 
     let insert = "INSERT INTO messaging.notifications 
-                  (id, user_id, type, title, body, data, created_at)
-                  VALUES ($1, $2, $3, $4, $5, $6, $7)";
+                  (id, user_id, type, title, body, data, created_at, priority, group_key)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)";
 
     let params = [
         ParameterValue::Str(notification_id.to_string()),
@@ -193,23 +390,24 @@ fn create_notification(req: &Request) -> Result<Response, ServiceError> {
         ParameterValue::Str(body.body.clone()),
         ParameterValue::Str(serde_json::to_string(&body.data).unwrap_or_else(|_| "{}".into())),
         ParameterValue::Str(now.to_rfc3339()),
+        ParameterValue::Str(priority.to_string()),
+        group_key.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
     ];
 
     conn.execute(insert, &params)
         .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
 
     // Queue real-time event for SSE/WebSocket delivery
-    queue_event(&conn, &body.user_id, "notification", serde_json::json!({
+    queue_event(conn, &body.user_id, "notification", priority, serde_json::json!({
         "id": notification_id,
         "type": body.notification_type,
         "title": body.title,
-        "body": body.body
+        "body": body.body,
+        "priority": priority,
+        "group_key": group_key
     }))?;
 
-    json_response(201, serde_json::json!({
-        "id": notification_id,
-        "created_at": now.to_rfc3339()
-    }))
+    Ok((notification_id, now.to_rfc3339()))
 }
 
 fn mark_notification_read(req: &Request, path: &str) -> Result<Response, ServiceError> {
@@ -229,10 +427,31 @@ fn mark_notification_read(req: &Request, path: &str) -> Result<Response, Service
     if result == 0 {
         return Err(ServiceError::NotFound("Notification not found".into()));
     }
+    badges::invalidate(&user_id);
 
     json_response(200, serde_json::json!({"read": true}))
 }
 
+fn list_notification_group(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let notification_id = extract_id_from_path_with_suffix(path, "/notifications/", "/group")?;
+    let before = get_query_param(req, "before");
+    let limit = get_query_param(req, "limit").and_then(|l| l.parse().ok());
+    let conn = get_db_connection()?;
+
+    grouping::list_members(&conn, &user_id, &notification_id, before, limit)
+}
+
+fn mark_notification_group_read(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let notification_id = extract_id_from_path_with_suffix(path, "/notifications/", "/group/read")?;
+    let conn = get_db_connection()?;
+
+    let response = grouping::mark_read(&conn, &user_id, &notification_id)?;
+    badges::invalidate(&user_id);
+    Ok(response)
+}
+
 fn mark_all_read(req: &Request) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
     let conn = get_db_connection()?;
@@ -242,6 +461,7 @@ fn mark_all_read(req: &Request) -> Result<Response, ServiceError> {
 
     let count = conn.execute(update, &params)
         .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    badges::invalidate(&user_id);
 
     json_response(200, serde_json::json!({
         "marked_read": count
@@ -269,6 +489,136 @@ fn delete_notification(req: &Request, path: &str) -> Result<Response, ServiceErr
     json_response(200, serde_json::json!({"deleted": true}))
 }
 
+//=============================================================================
+// Badges
+//=============================================================================
+
+fn get_badges(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+
+    badges::get(&conn, &user_id)
+}
+
+//=============================================================================
+// Announcements
+//=============================================================================
+
+fn list_announcements(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+
+    announcements::list_announcements(&conn, &user_id)
+}
+
+fn create_announcement(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: announcements::CreateAnnouncementRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    announcements::create_announcement(&conn, &user_id, body)
+}
+
+fn dismiss_announcement(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let announcement_id = extract_id_from_path_with_suffix(path, "/announcements/", "/dismiss")?;
+    let conn = get_db_connection()?;
+
+    let response = announcements::dismiss_announcement(&conn, &user_id, &announcement_id)?;
+    badges::invalidate(&user_id);
+    Ok(response)
+}
+
+//=============================================================================
+// Email
+//=============================================================================
+
+fn deliver_emails(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let conn = get_db_connection()?;
+
+    email::deliver(&conn)
+}
+
+fn list_email_deliveries(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+
+    email::list_deliveries(&conn, &user_id)
+}
+
+//=============================================================================
+// Webhook Integrations
+//=============================================================================
+
+fn register_webhook(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: webhooks::RegisterWebhookRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    webhooks::register(&conn, &user_id, body)
+}
+
+fn list_webhooks(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+
+    webhooks::list(&conn, &user_id)
+}
+
+fn delete_webhook(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let webhook_id = extract_id_from_path(path, "/integrations/webhooks/")?;
+    let conn = get_db_connection()?;
+
+    webhooks::delete(&conn, &user_id, &webhook_id)
+}
+
+fn list_webhook_deliveries(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let webhook_id = extract_id_from_path_with_suffix(path, "/integrations/webhooks/", "/deliveries")?;
+    let conn = get_db_connection()?;
+
+    webhooks::list_deliveries(&conn, &user_id, &webhook_id)
+}
+
+fn deliver_webhooks(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let conn = get_db_connection()?;
+
+    webhooks::deliver(&conn)
+}
+
+//=============================================================================
+// Data Exports
+//=============================================================================
+
+fn create_data_export(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: exports::CreateExportRequest = if req.body().is_empty() {
+        exports::CreateExportRequest { format: "json".into() }
+    } else {
+        parse_json_body(req)?
+    };
+    let conn = get_db_connection()?;
+
+    exports::create(&conn, &user_id, body)
+}
+
+fn list_data_exports(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+
+    exports::list(&conn, &user_id)
+}
+
+fn process_data_exports(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let conn = get_db_connection()?;
+
+    exports::process(&conn)
+}
+
 //=============================================================================
 // Messages
 //=============================================================================
@@ -279,11 +629,16 @@ fn list_conversations(req: &Request) -> Result<Response, ServiceError> {
 
     let query = "SELECT DISTINCT ON (c.id) c.id, c.name, c.type, c.created_at,
                  m.body as last_message, m.created_at as last_message_at,
-                 (SELECT COUNT(*) FROM messaging.messages WHERE conversation_id = c.id AND sender_id != $1 AND read = false) as unread
+                 cm.muted_at IS NOT NULL AND (cm.muted_until IS NULL OR cm.muted_until > NOW()) as muted,
+                 cm.muted_until::text as muted_until,
+                 (SELECT COUNT(*) FROM messaging.messages WHERE conversation_id = c.id AND sender_id != $1 AND read = false
+                  AND sender_id NOT IN (SELECT blocked_id FROM messaging.blocks WHERE blocker_id = $1)) as unread
                  FROM messaging.conversations c
                  JOIN messaging.conversation_members cm ON c.id = cm.conversation_id
                  LEFT JOIN messaging.messages m ON m.id = (
-                     SELECT id FROM messaging.messages WHERE conversation_id = c.id ORDER BY created_at DESC LIMIT 1
+                     SELECT id FROM messaging.messages WHERE conversation_id = c.id
+                     AND sender_id NOT IN (SELECT blocked_id FROM messaging.blocks WHERE blocker_id = $1)
+                     ORDER BY created_at DESC LIMIT 1
                  )
                  WHERE cm.user_id = $1
                  ORDER BY c.id, COALESCE(m.created_at, c.created_at) DESC";
@@ -300,7 +655,9 @@ fn list_conversations(req: &Request) -> Result<Response, ServiceError> {
             "created_at": String::decode(&row[3]).unwrap_or_default(),
             "last_message": String::decode(&row[4]).ok(),
             "last_message_at": String::decode(&row[5]).ok(),
-            "unread_count": i64::decode(&row[6]).unwrap_or(0)
+            "muted": bool::decode(&row[6]).unwrap_or(false),
+            "muted_until": String::decode(&row[7]).ok(),
+            "unread_count": i64::decode(&row[8]).unwrap_or(0)
         })
     }).collect();
 
@@ -309,6 +666,22 @@ fn list_conversations(req: &Request) -> Result<Response, ServiceError> {
     }))
 }
 
+/// GET /messages/search?q=&conversation_id=&from=&size=
+fn search_messages(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let query = get_query_param(req, "q").unwrap_or_default();
+    let conversation_id = match get_query_param(req, "conversation_id") {
+        Some(id) => Some(Uuid::parse_str(&id)
+            .map_err(|_| ServiceError::BadRequest("Invalid conversation_id".into()))?),
+        None => None,
+    };
+    let from = get_query_param(req, "from").and_then(|v| v.parse().ok()).unwrap_or(0);
+    let size = get_query_param(req, "size").and_then(|v| v.parse().ok()).unwrap_or(search::DEFAULT_PAGE_SIZE);
+    let conn = get_db_connection()?;
+
+    search::search(&conn, &user_id, &query, conversation_id, from, size)
+}
+
 fn get_conversation(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
     let conversation_id = extract_id_from_path(path, "/messages/")?;
@@ -327,14 +700,18 @@ fn get_conversation(req: &Request, path: &str) -> Result<Response, ServiceError>
         return Err(ServiceError::Forbidden("Not a member of this conversation".into()));
     }
 
-    // Get messages
+    // Get messages, leaving out senders the caller has blocked
     let query = "SELECT m.id, m.sender_id, m.body, m.attachments, m.read, m.created_at, u.name, u.avatar_url
                  FROM messaging.messages m
                  LEFT JOIN users.users u ON m.sender_id = u.id
                  WHERE m.conversation_id = $1
+                   AND NOT EXISTS (SELECT 1 FROM messaging.blocks b WHERE b.blocker_id = $2 AND b.blocked_id = m.sender_id)
                  ORDER BY m.created_at ASC LIMIT 100";
 
-    let params = [ParameterValue::Str(conversation_id.to_string())];
+    let params = [
+        ParameterValue::Str(conversation_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ];
     let rows = conn.query(query, &params)
         .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
 
@@ -359,6 +736,7 @@ fn get_conversation(req: &Request, path: &str) -> Result<Response, ServiceError>
         ParameterValue::Str(user_id.to_string()),
     ];
     conn.execute(mark_read, &mark_params).ok();
+    badges::invalidate(&user_id);
 
     json_response(200, serde_json::json!({
         "messages": messages
@@ -368,8 +746,12 @@ fn get_conversation(req: &Request, path: &str) -> Result<Response, ServiceError>
 fn send_message(req: &Request) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
     let body: SendMessageRequest = parse_json_body(req)?;
+    let priority = muting::message_priority(body.priority.clone())?;
+    let scheduled_at = body.scheduled_at.as_deref().map(scheduled::parse_scheduled_at).transpose()?;
     let conn = get_db_connection()?;
 
+    moderation::ensure_not_suspended(&conn, &user_id)?;
+
     // Get or create conversation
     let conversation_id = if let Some(conv_id) = body.conversation_id {
         // Verify membership
@@ -384,14 +766,49 @@ fn send_message(req: &Request) -> Result<Response, ServiceError> {
         if member_rows.rows.is_empty() {
             return Err(ServiceError::Forbidden("Not a member of this conversation".into()));
         }
+        moderation::ensure_conversation_open(&conn, &user_id, &conv_id)?;
         conv_id
     } else if let Some(recipient_id) = body.recipient_id {
         // Create or get direct conversation
+        moderation::ensure_not_blocked_by(&conn, &user_id, &recipient_id)?;
         get_or_create_direct_conversation(&conn, &user_id, &recipient_id)?
     } else {
         return Err(ServiceError::BadRequest("Either conversation_id or recipient_id required".into()));
     };
 
+    let attachments = body.attachments.unwrap_or_default();
+    if let Some(scheduled_at) = scheduled_at {
+        return scheduled::schedule(&conn, &user_id, &conversation_id, &body.body, &attachments, &priority, scheduled_at);
+    }
+
+    let posted = post_message(&conn, &conversation_id, &user_id, &body.body, &attachments, &priority)?;
+
+    json_response(201, serde_json::json!({
+        "id": posted.id,
+        "conversation_id": conversation_id,
+        "priority": priority,
+        "notified": posted.notified,
+        "muted": posted.muted,
+        "created_at": posted.created_at
+    }))
+}
+
+struct PostedMessage {
+    id: Uuid,
+    created_at: String,
+    notified: usize,
+    muted: usize,
+}
+
+/// Insert a message into a conversation and notify its members
+fn post_message(
+    conn: &Connection,
+    conversation_id: &Uuid,
+    user_id: &Uuid,
+    body: &str,
+    attachments: &[Attachment],
+    priority: &str,
+) -> Result<PostedMessage, ServiceError> {
     let message_id = Uuid::new_v4();
     let now = Utc::now();
 
@@ -403,42 +820,58 @@ fn send_message(req: &Request) -> Result<Response, ServiceError> {
         ParameterValue::Str(message_id.to_string()),
         ParameterValue::Str(conversation_id.to_string()),
         ParameterValue::Str(user_id.to_string()),
-        ParameterValue::Str(body.body.clone()),
-        ParameterValue::Str(serde_json::to_string(&body.attachments.unwrap_or_default()).unwrap_or_else(|_| "[]".into())),
+        ParameterValue::Str(body.to_string()),
+        ParameterValue::Str(serde_json::to_string(attachments).unwrap_or_else(|_| "[]".into())),
         ParameterValue::Str(now.to_rfc3339()),
     ];
 
     conn.execute(insert, &params)
         .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
 
-    // Notify other members
-    let members_query = "SELECT user_id FROM messaging.conversation_members WHERE conversation_id = $1 AND user_id != $2";
-    let members_params = [
-        ParameterValue::Str(conversation_id.to_string()),
-        ParameterValue::Str(user_id.to_string()),
-    ];
-    let members = conn.query(members_query, &members_params)
-        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
-
-    for member_row in &members.rows {
-        if let Ok(member_id_str) = String::decode(&member_row[0]) {
-            if let Ok(member_id) = Uuid::parse_str(&member_id_str) {
-                queue_event(&conn, &member_id, "message", serde_json::json!({
-                    "conversation_id": conversation_id,
-                    "message_id": message_id,
-                    "sender_id": user_id,
-                    "body": body.body,
-                    "created_at": now.to_rfc3339()
-                }))?;
-            }
-        }
+    // Notify other members, except those who blocked the sender or muted
+    // the conversation below this message's priority
+    let (recipients, muted) = muting::message_recipients(conn, conversation_id, user_id, priority)?;
+    for member_id in &recipients {
+        queue_event(conn, member_id, "message", priority, serde_json::json!({
+            "conversation_id": conversation_id,
+            "message_id": message_id,
+            "sender_id": user_id,
+            "body": body,
+            "priority": priority,
+            "created_at": now.to_rfc3339()
+        }))?;
     }
 
-    json_response(201, serde_json::json!({
-        "id": message_id,
-        "conversation_id": conversation_id,
-        "created_at": now.to_rfc3339()
-    }))
+    Ok(PostedMessage {
+        id: message_id,
+        created_at: now.to_rfc3339(),
+        notified: recipients.len(),
+        muted,
+    })
+}
+
+fn list_scheduled_messages(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let status = get_query_param(req, "status");
+    let limit = get_query_param(req, "limit").and_then(|l| l.parse().ok());
+    let conn = get_db_connection()?;
+
+    scheduled::list(&conn, &user_id, status, limit)
+}
+
+fn cancel_scheduled_message(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let scheduled_id = extract_id_from_path(path, "/messages/scheduled/")?;
+    let conn = get_db_connection()?;
+
+    scheduled::cancel(&conn, &user_id, &scheduled_id)
+}
+
+fn dispatch_scheduled_messages(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let conn = get_db_connection()?;
+
+    scheduled::dispatch(&conn)
 }
 
 fn delete_message(req: &Request, path: &str) -> Result<Response, ServiceError> {
@@ -463,15 +896,105 @@ fn delete_message(req: &Request, path: &str) -> Result<Response, ServiceError> {
     json_response(200, serde_json::json!({"deleted": true}))
 }
 
+//=============================================================================
+// Moderation
+//=============================================================================
+
+fn report_message(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let message_id = extract_id_from_path_with_suffix(path, "/messages/", "/report")?;
+    let body: moderation::ReportMessageRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    moderation::report_message(&conn, &user_id, &message_id, body)
+}
+
+fn block_member(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conversation_id = extract_id_from_path_with_suffix(path, "/conversations/", "/block")?;
+    let body = parse_optional_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    moderation::block(&conn, &user_id, &conversation_id, body)
+}
+
+fn unblock_member(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conversation_id = extract_id_from_path_with_suffix(path, "/conversations/", "/block")?;
+    let body = parse_optional_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    moderation::unblock(&conn, &user_id, &conversation_id, body)
+}
+
+fn mute_conversation(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conversation_id = extract_id_from_path_with_suffix(path, "/conversations/", "/mute")?;
+    let body = parse_optional_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    muting::mute(&conn, &user_id, &conversation_id, body)
+}
+
+fn unmute_conversation(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conversation_id = extract_id_from_path_with_suffix(path, "/conversations/", "/mute")?;
+    let conn = get_db_connection()?;
+
+    muting::unmute(&conn, &user_id, &conversation_id)
+}
+
+fn list_moderation_reports(req: &Request) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let status = get_query_param(req, "status").unwrap_or_else(|| "open".into());
+    let limit = get_query_param(req, "limit")
+        .and_then(|l| l.parse::<i64>().ok())
+        .unwrap_or(moderation::DEFAULT_QUEUE_LIMIT)
+        .clamp(1, moderation::MAX_QUEUE_LIMIT);
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &actor_id)?;
+    moderation::list_reports(&conn, &status, limit)
+}
+
+fn resolve_moderation_report(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let report_id = extract_id_from_path_with_suffix(path, "/admin/moderation/reports/", "/resolve")?;
+    let body: moderation::ResolveReportRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &actor_id)?;
+    moderation::resolve_report(&conn, &actor_id, &report_id, body)
+}
+
+fn suspend_messaging(req: &Request) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let body: moderation::SuspendRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &actor_id)?;
+    moderation::suspend(&conn, &actor_id, body)
+}
+
+fn lift_messaging_suspension(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let user_id = extract_id_from_path(path, "/admin/moderation/suspensions/")?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &actor_id)?;
+    moderation::lift_suspension(&conn, &user_id)
+}
+
 //=============================================================================
 // Events
 //=============================================================================
 
 fn publish_event(req: &Request) -> Result<Response, ServiceError> {
     let body: PublishEventRequest = parse_json_body(req)?;
+    let priority = muting::event_priority(body.priority.clone())?;
     let conn = get_db_connection()?;
 
-    queue_event(&conn, &body.user_id, &body.event_type, body.data)?;
+    queue_event(&conn, &body.user_id, &body.event_type, &priority, body.data)?;
 
     json_response(202, serde_json::json!({
         "queued": true
@@ -480,52 +1003,87 @@ fn publish_event(req: &Request) -> Result<Response, ServiceError> {
 
 fn subscribe_events(req: &Request) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
+    // EventSource sends the header on reconnect; the query parameter is for
+    // clients that cannot set headers on their first request
+    let last_event_id = req.header("Last-Event-ID")
+        .and_then(|h| h.as_str())
+        .map(|id| id.trim().to_string())
+        .or_else(|| get_query_param(req, "last_event_id"))
+        .filter(|id| !id.is_empty())
+        .map(|id| id.parse::<i64>().map_err(|_| ServiceError::BadRequest("Invalid Last-Event-ID".into())))
+        .transpose()?;
+    let wait_seconds = get_query_param(req, "wait")
+        .map(|w| w.parse::<i32>().map_err(|_| ServiceError::BadRequest("Invalid wait".into())))
+        .transpose()?;
+    let types = get_query_param(req, "types")
+        .map(|t| event_delivery::parse_types(&t))
+        .transpose()?;
     let conn = get_db_connection()?;
 
-    // Get pending events for user
-    let query = "SELECT id, type, data, created_at FROM messaging.events 
-                 WHERE user_id = $1 AND delivered = false
-                 ORDER BY created_at ASC LIMIT 10";
-    let params = [ParameterValue::Str(user_id.to_string())];
-    let rows = conn.query(query, &params)
-        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    event_delivery::subscribe(&conn, &user_id, event_delivery::SubscribeOptions { last_event_id, wait_seconds, types })
+}
 
-    let events: Vec<serde_json::Value> = rows.rows.iter().map(|row| {
-        serde_json::json!({
-            "id": String::decode(&row[0]).unwrap_or_default(),
-            "type": String::decode(&row[1]).unwrap_or_default(),
-            "data": serde_json::from_str::<serde_json::Value>(
-                &String::decode(&row[2]).unwrap_or_else(|_| "{}".into())
-            ).unwrap_or_default(),
-            "created_at": String::decode(&row[3]).unwrap_or_default()
-        })
-    }).collect();
+fn ack_events(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: event_delivery::AckRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
 
-    // Mark as delivered
-    if !events.is_empty() {
-        let event_ids: Vec<String> = rows.rows.iter()
-            .filter_map(|row| String::decode(&row[0]).ok())
-            .collect();
-        
-        for event_id in event_ids {
-            let mark = "UPDATE messaging.events SET delivered = true WHERE id = $1";
-            conn.execute(mark, &[ParameterValue::Str(event_id)]).ok();
-        }
-    }
+    event_delivery::ack(&conn, &user_id, body)
+}
 
-    // Return as SSE format
-    let sse_data = events.iter()
-        .map(|e| format!("data: {}\n\n", e))
-        .collect::<Vec<_>>()
-        .join("");
+fn list_dead_letter_events(req: &Request) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let user_id = get_query_param(req, "user_id")
+        .map(|id| Uuid::parse_str(&id).map_err(|_| ServiceError::BadRequest("Invalid user_id".into())))
+        .transpose()?;
+    let limit = get_query_param(req, "limit")
+        .and_then(|l| l.parse::<i64>().ok())
+        .unwrap_or(event_delivery::DEFAULT_DEAD_LETTER_LIMIT)
+        .clamp(1, event_delivery::MAX_DEAD_LETTER_LIMIT);
+    let conn = get_db_connection()?;
 
-    Ok(Response::builder()
-        .status(200)
-        .header("Content-Type", "text/event-stream")
-        .header("Cache-Control", "no-cache")
-        .header("Access-Control-Allow-Origin", "*")
-        .body(sse_data)
-        .build())
+    authorworks_access::require_admin(&conn, &actor_id)?;
+    event_delivery::list_dead_letters(&conn, user_id, limit)
+}
+
+//=============================================================================
+// Notification Templates
+//=============================================================================
+
+fn list_templates(req: &Request) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+    authorworks_access::require_admin(&conn, &actor_id)?;
+
+    templates::list(&conn, get_query_param(req, "name").as_deref())
+}
+
+fn create_template(req: &Request) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let body: templates::CreateTemplateRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+    authorworks_access::require_admin(&conn, &actor_id)?;
+
+    templates::create(&conn, body)
+}
+
+fn update_template(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let template_id = extract_id_from_path(path, "/admin/templates/")?;
+    let body: templates::UpdateTemplateRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+    authorworks_access::require_admin(&conn, &actor_id)?;
+
+    templates::update(&conn, &template_id, body)
+}
+
+fn delete_template(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let template_id = extract_id_from_path(path, "/admin/templates/")?;
+    let conn = get_db_connection()?;
+    authorworks_access::require_admin(&conn, &actor_id)?;
+
+    templates::delete(&conn, &template_id)
 }
 
 //=============================================================================
@@ -582,12 +1140,12 @@ fn get_or_create_direct_conversation(conn: &Connection, user1: &Uuid, user2: &Uu
     Ok(conv_id)
 }
 
-fn queue_event(conn: &Connection, user_id: &Uuid, event_type: &str, data: serde_json::Value) -> Result<(), ServiceError> {
+fn queue_event(conn: &Connection, user_id: &Uuid, event_type: &str, priority: &str, data: serde_json::Value) -> Result<(), ServiceError> {
     let event_id = Uuid::new_v4();
     let now = Utc::now();
 
-    let insert = "INSERT INTO messaging.events (id, user_id, type, data, created_at)
-                  VALUES ($1, $2, $3, $4, $5)";
+    let insert = "INSERT INTO messaging.events (id, user_id, type, data, created_at, priority)
+                  VALUES ($1, $2, $3, $4, $5, $6)";
 
     let params = [
         ParameterValue::Str(event_id.to_string()),
@@ -595,6 +1153,7 @@ fn queue_event(conn: &Connection, user_id: &Uuid, event_type: &str, data: serde_
         ParameterValue::Str(event_type.to_string()),
         ParameterValue::Str(data.to_string()),
         ParameterValue::Str(now.to_rfc3339()),
+        ParameterValue::Str(priority.to_string()),
     ];
 
     conn.execute(insert, &params)
@@ -621,6 +1180,36 @@ fn extract_id_from_path_with_suffix(path: &str, prefix: &str, suffix: &str) -> R
         .map_err(|_| ServiceError::BadRequest("Invalid UUID".into()))
 }
 
+fn get_query_param(req: &Request, name: &str) -> Option<String> {
+    req.query().split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name).then(|| percent_decode(value))
+    })
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match std::str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
 fn json_response<T: Serialize>(status: u16, body: T) -> Result<Response, ServiceError> {
     let json = serde_json::to_string(&body)
         .map_err(|e| ServiceError::Internal(format!("JSON error: {}", e)))?;
@@ -637,3 +1226,11 @@ fn parse_json_body<T: for<'de> Deserialize<'de>>(req: &Request) -> Result<T, Ser
     serde_json::from_slice(req.body())
         .map_err(|e| ServiceError::BadRequest(format!("Invalid JSON: {}", e)))
 }
+
+/// Like `parse_json_body`, but an empty body gives the default
+fn parse_optional_json_body<T: Default + for<'de> Deserialize<'de>>(req: &Request) -> Result<T, ServiceError> {
+    if req.body().iter().all(|b| b.is_ascii_whitespace()) {
+        return Ok(T::default());
+    }
+    parse_json_body(req)
+}
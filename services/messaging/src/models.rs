@@ -19,6 +19,16 @@ pub struct Notification {
     pub data: HashMap<String, serde_json::Value>,
     pub read: bool,
     pub created_at: String,
+    /// `low`, `normal`, `high` or `urgent`
+    pub priority: String,
+    /// Notifications sharing a key are listed as one row
+    #[serde(default)]
+    pub group_key: Option<String>,
+    /// Notifications in the group, 1 for an ungrouped notification
+    #[serde(default)]
+    pub group_count: i64,
+    #[serde(default)]
+    pub group_unread: i64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -30,6 +40,11 @@ pub struct CreateNotificationRequest {
     pub body: String,
     #[serde(default)]
     pub data: HashMap<String, serde_json::Value>,
+    /// Defaults by type, e.g. `high` for payment failures
+    pub priority: Option<String>,
+    /// Defaults to the type and the chapter, document, book or conversation
+    /// in `data`; an empty key keeps the notification ungrouped
+    pub group_key: Option<String>,
 }
 
 //=============================================================================
@@ -66,6 +81,10 @@ pub struct SendMessageRequest {
     pub recipient_id: Option<Uuid>,
     pub body: String,
     pub attachments: Option<Vec<Attachment>>,
+    /// `low`, `normal` (default) or `high`; high can break through mutes
+    pub priority: Option<String>,
+    /// RFC 3339 time to send at; the message is held until then
+    pub scheduled_at: Option<String>,
 }
 
 //=============================================================================
@@ -108,6 +127,7 @@ pub struct PublishEventRequest {
     #[serde(rename = "type")]
     pub event_type: String,
     pub data: serde_json::Value,
+    pub priority: Option<String>,
 }
 
 //=============================================================================
@@ -125,6 +145,10 @@ pub enum NotificationType {
     SubscriptionExpiring,
     PaymentFailed,
     SystemAnnouncement,
+    AuthorMessage,
+    AccountInactive,
+    WritingMilestone,
+    DataExportReady,
 }
 
 impl std::fmt::Display for NotificationType {
@@ -138,6 +162,10 @@ impl std::fmt::Display for NotificationType {
             NotificationType::SubscriptionExpiring => write!(f, "subscription_expiring"),
             NotificationType::PaymentFailed => write!(f, "payment_failed"),
             NotificationType::SystemAnnouncement => write!(f, "system_announcement"),
+            NotificationType::AuthorMessage => write!(f, "author_message"),
+            NotificationType::AccountInactive => write!(f, "account_inactive"),
+            NotificationType::WritingMilestone => write!(f, "writing_milestone"),
+            NotificationType::DataExportReady => write!(f, "data_export_ready"),
         }
     }
 }
@@ -0,0 +1,469 @@
+//! Messaging abuse reports, blocks and suspensions
+//!
+//! Members report individual messages into a moderation queue; the message
+//! body is copied into the report so deleting the message does not destroy
+//! the evidence. Blocking is per pair of users: a blocker no longer sees the
+//! blocked user's messages in any conversation, gets no events for them, and
+//! cannot be messaged by them directly. Admins work the queue and can suspend
+//! an offender's messaging for a number of days or indefinitely.
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+const REPORT_REASONS: [&str; 6] = ["spam", "harassment", "hate", "sexual", "scam", "other"];
+const REPORT_STATUSES: [&str; 3] = ["open", "actioned", "dismissed"];
+const MAX_DETAILS_CHARS: usize = 2000;
+pub const DEFAULT_QUEUE_LIMIT: i64 = 50;
+pub const MAX_QUEUE_LIMIT: i64 = 200;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct ReportMessageRequest {
+    /// One of `REPORT_REASONS`
+    pub reason: String,
+    pub details: Option<String>,
+    /// Also block the sender
+    #[serde(default)]
+    pub block: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct BlockRequest {
+    /// Required in group conversations; direct ones block the other member
+    pub user_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResolveReportRequest {
+    /// `actioned` or `dismissed`
+    pub status: String,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SuspendRequest {
+    pub user_id: Uuid,
+    pub reason: String,
+    /// Omit to suspend until lifted
+    pub days: Option<i64>,
+    /// Report the suspension acts on; it is marked actioned
+    pub report_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueuedReport {
+    pub id: String,
+    pub message_id: Option<String>,
+    pub conversation_id: Option<String>,
+    pub message_body: String,
+    pub reason: String,
+    pub details: Option<String>,
+    pub status: String,
+    pub reporter: ReportUser,
+    pub reported_user: ReportedUser,
+    pub resolution_note: Option<String>,
+    pub reviewed_by: Option<String>,
+    pub reviewed_at: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportUser {
+    pub id: String,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportedUser {
+    pub id: String,
+    pub name: Option<String>,
+    pub open_reports: i64,
+    pub suspended: bool,
+    pub suspended_until: Option<String>,
+}
+
+//=============================================================================
+// Reporting
+//=============================================================================
+
+/// POST /messages/:id/report
+pub fn report_message(
+    conn: &Connection,
+    reporter_id: &Uuid,
+    message_id: &Uuid,
+    body: ReportMessageRequest,
+) -> Result<Response, ServiceError> {
+    if !REPORT_REASONS.contains(&body.reason.as_str()) {
+        return Err(ServiceError::BadRequest(format!("reason must be one of: {}", REPORT_REASONS.join(", "))));
+    }
+    let details = body.details.map(|d| d.trim().to_string()).filter(|d| !d.is_empty());
+    if details.as_ref().is_some_and(|d| d.chars().count() > MAX_DETAILS_CHARS) {
+        return Err(ServiceError::BadRequest(format!("details must be at most {} characters", MAX_DETAILS_CHARS)));
+    }
+
+    // Only members can see, and so report, a message
+    let query = "SELECT m.sender_id::text, m.body, m.conversation_id::text
+                 FROM messaging.messages m
+                 JOIN messaging.conversation_members cm ON cm.conversation_id = m.conversation_id AND cm.user_id = $2
+                 WHERE m.id = $1";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(message_id.to_string()),
+        ParameterValue::Str(reporter_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first().ok_or_else(|| ServiceError::NotFound("Message not found".into()))?;
+    let sender_id = String::decode(&row[0]).ok()
+        .and_then(|s| Uuid::parse_str(&s).ok())
+        .ok_or_else(|| ServiceError::Internal("Invalid sender ID".into()))?;
+    let message_body = String::decode(&row[1]).unwrap_or_default();
+    let conversation_id = String::decode(&row[2]).ok()
+        .and_then(|s| Uuid::parse_str(&s).ok())
+        .ok_or_else(|| ServiceError::Internal("Invalid conversation ID".into()))?;
+
+    if sender_id == *reporter_id {
+        return Err(ServiceError::BadRequest("You cannot report your own message".into()));
+    }
+
+    let insert = "INSERT INTO messaging.message_reports
+                  (id, message_id, conversation_id, reporter_id, reported_user_id, message_body, reason, details, created_at)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                  ON CONFLICT (message_id, reporter_id) DO NOTHING";
+    let report_id = Uuid::new_v4();
+    let inserted = conn.execute(insert, &[
+        ParameterValue::Str(report_id.to_string()),
+        ParameterValue::Str(message_id.to_string()),
+        ParameterValue::Str(conversation_id.to_string()),
+        ParameterValue::Str(reporter_id.to_string()),
+        ParameterValue::Str(sender_id.to_string()),
+        ParameterValue::Str(message_body),
+        ParameterValue::Str(body.reason),
+        details.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    if body.block {
+        insert_block(conn, reporter_id, &sender_id, Some(&conversation_id))?;
+    }
+
+    if inserted == 0 {
+        let existing = conn.query(
+            "SELECT id::text, status FROM messaging.message_reports WHERE message_id = $1 AND reporter_id = $2",
+            &[
+                ParameterValue::Str(message_id.to_string()),
+                ParameterValue::Str(reporter_id.to_string()),
+            ],
+        ).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+        let row = existing.rows.first();
+        return crate::json_response(200, serde_json::json!({
+            "id": row.and_then(|r| String::decode(&r[0]).ok()),
+            "status": row.and_then(|r| String::decode(&r[1]).ok()),
+            "already_reported": true,
+            "blocked": body.block
+        }));
+    }
+
+    crate::json_response(201, serde_json::json!({
+        "id": report_id,
+        "status": "open",
+        "already_reported": false,
+        "blocked": body.block
+    }))
+}
+
+//=============================================================================
+// Blocking
+//=============================================================================
+
+/// POST /conversations/:id/block
+pub fn block(conn: &Connection, blocker_id: &Uuid, conversation_id: &Uuid, body: BlockRequest) -> Result<Response, ServiceError> {
+    let blocked_id = block_target(conn, blocker_id, conversation_id, body.user_id)?;
+    insert_block(conn, blocker_id, &blocked_id, Some(conversation_id))?;
+
+    crate::json_response(200, serde_json::json!({
+        "blocked_user_id": blocked_id,
+        "blocked": true
+    }))
+}
+
+/// DELETE /conversations/:id/block
+pub fn unblock(conn: &Connection, blocker_id: &Uuid, conversation_id: &Uuid, body: BlockRequest) -> Result<Response, ServiceError> {
+    let blocked_id = block_target(conn, blocker_id, conversation_id, body.user_id)?;
+    conn.execute(
+        "DELETE FROM messaging.blocks WHERE blocker_id = $1 AND blocked_id = $2",
+        &[
+            ParameterValue::Str(blocker_id.to_string()),
+            ParameterValue::Str(blocked_id.to_string()),
+        ],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    crate::json_response(200, serde_json::json!({
+        "blocked_user_id": blocked_id,
+        "blocked": false
+    }))
+}
+
+/// The member being (un)blocked: `user_id` if given, else the other member
+/// of a direct conversation
+fn block_target(conn: &Connection, blocker_id: &Uuid, conversation_id: &Uuid, user_id: Option<Uuid>) -> Result<Uuid, ServiceError> {
+    let query = "SELECT c.type, cm.user_id::text
+                 FROM messaging.conversations c
+                 JOIN messaging.conversation_members me ON me.conversation_id = c.id AND me.user_id = $2
+                 JOIN messaging.conversation_members cm ON cm.conversation_id = c.id AND cm.user_id <> $2
+                 WHERE c.id = $1";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(conversation_id.to_string()),
+        ParameterValue::Str(blocker_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let is_direct = rows.rows.first()
+        .map(|row| String::decode(&row[0]).unwrap_or_else(|_| "direct".into()) == "direct")
+        .ok_or_else(|| ServiceError::NotFound("Conversation not found".into()))?;
+    let others: Vec<Uuid> = rows.rows.iter()
+        .filter_map(|row| String::decode(&row[1]).ok().and_then(|s| Uuid::parse_str(&s).ok()))
+        .collect();
+
+    match user_id {
+        Some(user_id) if others.contains(&user_id) => Ok(user_id),
+        Some(_) => Err(ServiceError::BadRequest("user_id is not a member of this conversation".into())),
+        None if is_direct && others.len() == 1 => Ok(others[0]),
+        None => Err(ServiceError::BadRequest("user_id is required to block someone in a group conversation".into())),
+    }
+}
+
+fn insert_block(conn: &Connection, blocker_id: &Uuid, blocked_id: &Uuid, conversation_id: Option<&Uuid>) -> Result<(), ServiceError> {
+    let insert = "INSERT INTO messaging.blocks (blocker_id, blocked_id, conversation_id, created_at)
+                  VALUES ($1, $2, $3, $4)
+                  ON CONFLICT (blocker_id, blocked_id) DO NOTHING";
+    conn.execute(insert, &[
+        ParameterValue::Str(blocker_id.to_string()),
+        ParameterValue::Str(blocked_id.to_string()),
+        conversation_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    Ok(())
+}
+
+//=============================================================================
+// Send Checks
+//=============================================================================
+
+/// Reject senders whose messaging is suspended
+pub fn ensure_not_suspended(conn: &Connection, user_id: &Uuid) -> Result<(), ServiceError> {
+    let query = "SELECT suspended_until::text FROM messaging.suspensions
+                 WHERE user_id = $1 AND (suspended_until IS NULL OR suspended_until > NOW())";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    match rows.rows.first() {
+        Some(row) => Err(ServiceError::Forbidden(match String::decode(&row[0]).ok() {
+            Some(until) => format!("Messaging is suspended until {}", until),
+            None => "Messaging is suspended".into(),
+        })),
+        None => Ok(()),
+    }
+}
+
+/// Reject a direct message to someone who has blocked the sender
+pub fn ensure_not_blocked_by(conn: &Connection, sender_id: &Uuid, recipient_id: &Uuid) -> Result<(), ServiceError> {
+    let query = "SELECT 1 FROM messaging.blocks WHERE blocker_id = $1 AND blocked_id = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(recipient_id.to_string()),
+        ParameterValue::Str(sender_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    if !rows.rows.is_empty() {
+        return Err(ServiceError::Forbidden("You cannot message this user".into()));
+    }
+    Ok(())
+}
+
+/// Reject sending into a direct conversation whose other member blocked the
+/// sender. Group messages still go out; blockers just never see them.
+pub fn ensure_conversation_open(conn: &Connection, sender_id: &Uuid, conversation_id: &Uuid) -> Result<(), ServiceError> {
+    let query = "SELECT 1 FROM messaging.conversations c
+                 JOIN messaging.conversation_members cm ON cm.conversation_id = c.id AND cm.user_id <> $2
+                 JOIN messaging.blocks b ON b.blocker_id = cm.user_id AND b.blocked_id = $2
+                 WHERE c.id = $1 AND c.type = 'direct'";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(conversation_id.to_string()),
+        ParameterValue::Str(sender_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    if !rows.rows.is_empty() {
+        return Err(ServiceError::Forbidden("You cannot message this user".into()));
+    }
+    Ok(())
+}
+
+//=============================================================================
+// Admin
+//=============================================================================
+
+/// GET /admin/moderation/reports - Open reports oldest first; resolved newest first
+pub fn list_reports(conn: &Connection, status: &str, limit: i64) -> Result<Response, ServiceError> {
+    if !REPORT_STATUSES.contains(&status) {
+        return Err(ServiceError::BadRequest(format!("status must be one of: {}", REPORT_STATUSES.join(", "))));
+    }
+    let order = if status == "open" { "ASC" } else { "DESC" };
+
+    let query = format!(
+        "SELECT r.id::text, r.message_id::text, r.conversation_id::text, r.message_body, r.reason, r.details, r.status,
+                r.reporter_id::text, ru.name, r.reported_user_id::text, tu.name,
+                (SELECT COUNT(*) FROM messaging.message_reports o
+                 WHERE o.reported_user_id = r.reported_user_id AND o.status = 'open'),
+                s.user_id IS NOT NULL, s.suspended_until::text,
+                r.resolution_note, r.reviewed_by::text, r.reviewed_at::text, r.created_at::text
+         FROM messaging.message_reports r
+         LEFT JOIN users.users ru ON ru.id = r.reporter_id
+         LEFT JOIN users.users tu ON tu.id = r.reported_user_id
+         LEFT JOIN messaging.suspensions s ON s.user_id = r.reported_user_id
+              AND (s.suspended_until IS NULL OR s.suspended_until > NOW())
+         WHERE r.status = $1
+         ORDER BY r.created_at {}
+         LIMIT $2",
+        order
+    );
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(status.to_string()),
+        ParameterValue::Int64(limit),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let reports: Vec<QueuedReport> = rows.rows.iter().map(|row| QueuedReport {
+        id: String::decode(&row[0]).unwrap_or_default(),
+        message_id: String::decode(&row[1]).ok(),
+        conversation_id: String::decode(&row[2]).ok(),
+        message_body: String::decode(&row[3]).unwrap_or_default(),
+        reason: String::decode(&row[4]).unwrap_or_default(),
+        details: String::decode(&row[5]).ok(),
+        status: String::decode(&row[6]).unwrap_or_default(),
+        reporter: ReportUser {
+            id: String::decode(&row[7]).unwrap_or_default(),
+            name: String::decode(&row[8]).ok(),
+        },
+        reported_user: ReportedUser {
+            id: String::decode(&row[9]).unwrap_or_default(),
+            name: String::decode(&row[10]).ok(),
+            open_reports: i64::decode(&row[11]).unwrap_or(0),
+            suspended: bool::decode(&row[12]).unwrap_or(false),
+            suspended_until: String::decode(&row[13]).ok(),
+        },
+        resolution_note: String::decode(&row[14]).ok(),
+        reviewed_by: String::decode(&row[15]).ok(),
+        reviewed_at: String::decode(&row[16]).ok(),
+        created_at: String::decode(&row[17]).unwrap_or_default(),
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "reports": reports,
+        "status": status
+    }))
+}
+
+/// POST /admin/moderation/reports/:id/resolve
+pub fn resolve_report(conn: &Connection, admin_id: &Uuid, report_id: &Uuid, body: ResolveReportRequest) -> Result<Response, ServiceError> {
+    if body.status != "actioned" && body.status != "dismissed" {
+        return Err(ServiceError::BadRequest("status must be actioned or dismissed".into()));
+    }
+    let note = body.note.map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
+
+    if !close_report(conn, admin_id, report_id, &body.status, note.as_deref())? {
+        let exists = conn.query("SELECT 1 FROM messaging.message_reports WHERE id = $1", &[ParameterValue::Str(report_id.to_string())])
+            .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+        return Err(if exists.rows.is_empty() {
+            ServiceError::NotFound("Report not found".into())
+        } else {
+            ServiceError::BadRequest("Report has already been resolved".into())
+        });
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "id": report_id,
+        "status": body.status
+    }))
+}
+
+/// Close an open report; false if it was not open
+fn close_report(conn: &Connection, admin_id: &Uuid, report_id: &Uuid, status: &str, note: Option<&str>) -> Result<bool, ServiceError> {
+    let update = "UPDATE messaging.message_reports
+                  SET status = $2, resolution_note = $3, reviewed_by = $4, reviewed_at = $5
+                  WHERE id = $1 AND status = 'open'";
+    let updated = conn.execute(update, &[
+        ParameterValue::Str(report_id.to_string()),
+        ParameterValue::Str(status.to_string()),
+        note.map(|n| ParameterValue::Str(n.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(admin_id.to_string()),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(updated > 0)
+}
+
+/// POST /admin/moderation/suspensions - Suspend a user's messaging, replacing
+/// any earlier suspension
+pub fn suspend(conn: &Connection, admin_id: &Uuid, body: SuspendRequest) -> Result<Response, ServiceError> {
+    let reason = body.reason.trim().to_string();
+    if reason.is_empty() {
+        return Err(ServiceError::BadRequest("reason is required".into()));
+    }
+    if body.days.is_some_and(|d| d <= 0) {
+        return Err(ServiceError::BadRequest("days must be positive".into()));
+    }
+    if body.user_id == *admin_id {
+        return Err(ServiceError::BadRequest("You cannot suspend yourself".into()));
+    }
+    let exists = conn.query("SELECT 1 FROM users.users WHERE id = $1", &[ParameterValue::Str(body.user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if exists.rows.is_empty() {
+        return Err(ServiceError::NotFound("User not found".into()));
+    }
+
+    let now = Utc::now();
+    let suspended_until = body.days.map(|days| (now + Duration::days(days)).to_rfc3339());
+
+    let upsert = "INSERT INTO messaging.suspensions (user_id, reason, report_id, suspended_by, suspended_until, created_at)
+                  VALUES ($1, $2, $3, $4, $5::timestamptz, $6)
+                  ON CONFLICT (user_id) DO UPDATE SET
+                  reason = EXCLUDED.reason,
+                  report_id = EXCLUDED.report_id,
+                  suspended_by = EXCLUDED.suspended_by,
+                  suspended_until = EXCLUDED.suspended_until,
+                  created_at = EXCLUDED.created_at";
+    conn.execute(upsert, &[
+        ParameterValue::Str(body.user_id.to_string()),
+        ParameterValue::Str(reason.clone()),
+        body.report_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(admin_id.to_string()),
+        suspended_until.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(now.to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    if let Some(report_id) = &body.report_id {
+        close_report(conn, admin_id, report_id, "actioned", Some(&reason))?;
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "user_id": body.user_id,
+        "suspended": true,
+        "suspended_until": suspended_until,
+        "reason": reason
+    }))
+}
+
+/// DELETE /admin/moderation/suspensions/:user_id
+pub fn lift_suspension(conn: &Connection, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let deleted = conn.execute("DELETE FROM messaging.suspensions WHERE user_id = $1", &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    if deleted == 0 {
+        return Err(ServiceError::NotFound("Suspension not found".into()));
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "user_id": user_id,
+        "suspended": false
+    }))
+}
@@ -0,0 +1,178 @@
+//! Notification priority and conversation muting
+//!
+//! Notifications and real-time events carry a priority (`low`, `normal`,
+//! `high`, `urgent`), and subscribers are sent higher priorities first.
+//! Members of busy conversations can mute them for a while or until unmuted:
+//! messages are still stored and counted as unread, but no `message` event is
+//! queued for a muted member unless the message reaches the priority they
+//! chose to let through.
+
+use crate::error::ServiceError;
+use serde::Deserialize;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+/// Lowest to highest
+pub const PRIORITIES: [&str; 4] = ["low", "normal", "high", "urgent"];
+pub const DEFAULT_PRIORITY: &str = "normal";
+
+/// Members may flag their own messages up to `high`; `urgent` is left to
+/// system notifications so it keeps cutting through mutes
+const MESSAGE_PRIORITIES: [&str; 3] = ["low", "normal", "high"];
+
+const MAX_MUTE_MINUTES: i64 = 60 * 24 * 365;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Default, Deserialize)]
+pub struct MuteRequest {
+    /// Omit to mute until unmuted
+    pub duration_minutes: Option<i64>,
+    /// Messages at or above this priority still notify; omit to silence all
+    pub min_priority: Option<String>,
+}
+
+//=============================================================================
+// Priority
+//=============================================================================
+
+/// Requested priority for a notification, or the default for its type
+pub fn notification_priority(requested: Option<String>, notification_type: &str) -> Result<String, ServiceError> {
+    match requested {
+        Some(priority) => parse(&priority, &PRIORITIES),
+        None => Ok(default_for_type(notification_type).to_string()),
+    }
+}
+
+/// Priority a member attached to a message
+pub fn message_priority(requested: Option<String>) -> Result<String, ServiceError> {
+    match requested {
+        Some(priority) => parse(&priority, &MESSAGE_PRIORITIES),
+        None => Ok(DEFAULT_PRIORITY.to_string()),
+    }
+}
+
+/// Priority for an event published through `POST /events`
+pub fn event_priority(requested: Option<String>) -> Result<String, ServiceError> {
+    match requested {
+        Some(priority) => parse(&priority, &PRIORITIES),
+        None => Ok(DEFAULT_PRIORITY.to_string()),
+    }
+}
+
+/// Any known priority, e.g. from a filter
+pub fn parse_priority(priority: &str) -> Result<String, ServiceError> {
+    parse(priority, &PRIORITIES)
+}
+
+fn parse(priority: &str, allowed: &[&str]) -> Result<String, ServiceError> {
+    let priority = priority.trim().to_ascii_lowercase();
+    if !allowed.contains(&priority.as_str()) {
+        return Err(ServiceError::BadRequest(format!("priority must be one of: {}", allowed.join(", "))));
+    }
+    Ok(priority)
+}
+
+fn default_for_type(notification_type: &str) -> &'static str {
+    match notification_type {
+        "payment_failed" | "subscription_expiring" => "high",
+        "account_inactive" | "writing_milestone" => "low",
+        _ => DEFAULT_PRIORITY,
+    }
+}
+
+//=============================================================================
+// Muting
+//=============================================================================
+
+/// PUT /conversations/:id/mute
+pub fn mute(conn: &Connection, user_id: &Uuid, conversation_id: &Uuid, body: MuteRequest) -> Result<Response, ServiceError> {
+    if body.duration_minutes.is_some_and(|m| !(1..=MAX_MUTE_MINUTES).contains(&m)) {
+        return Err(ServiceError::BadRequest(format!(
+            "duration_minutes must be between 1 and {}", MAX_MUTE_MINUTES
+        )));
+    }
+    let min_priority = body.min_priority.map(|p| parse_priority(&p)).transpose()?;
+
+    let update = "UPDATE messaging.conversation_members SET
+                  muted_at = NOW(),
+                  muted_until = CASE WHEN $3::int IS NULL THEN NULL ELSE NOW() + make_interval(mins => $3::int) END,
+                  mute_min_priority = $4
+                  WHERE conversation_id = $1 AND user_id = $2
+                  RETURNING muted_until::text";
+    let rows = conn.query(update, &[
+        ParameterValue::Str(conversation_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        body.duration_minutes.map(|m| ParameterValue::Int32(m as i32)).unwrap_or(ParameterValue::DbNull),
+        min_priority.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Conversation not found".into()))?;
+
+    crate::json_response(200, serde_json::json!({
+        "conversation_id": conversation_id,
+        "muted": true,
+        "muted_until": String::decode(&row[0]).ok(),
+        "min_priority": min_priority
+    }))
+}
+
+/// DELETE /conversations/:id/mute
+pub fn unmute(conn: &Connection, user_id: &Uuid, conversation_id: &Uuid) -> Result<Response, ServiceError> {
+    let update = "UPDATE messaging.conversation_members
+                  SET muted_at = NULL, muted_until = NULL, mute_min_priority = NULL
+                  WHERE conversation_id = $1 AND user_id = $2";
+    let updated = conn.execute(update, &[
+        ParameterValue::Str(conversation_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    if updated == 0 {
+        return Err(ServiceError::NotFound("Conversation not found".into()));
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "conversation_id": conversation_id,
+        "muted": false
+    }))
+}
+
+/// Members to notify of a message: everyone but the sender, members who
+/// blocked the sender, and members whose mute the priority does not break.
+/// Returns the recipients and how many were skipped for a mute.
+pub fn message_recipients(
+    conn: &Connection,
+    conversation_id: &Uuid,
+    sender_id: &Uuid,
+    priority: &str,
+) -> Result<(Vec<Uuid>, usize), ServiceError> {
+    let query = "SELECT cm.user_id::text,
+                        cm.muted_at IS NOT NULL AND (cm.muted_until IS NULL OR cm.muted_until > NOW())
+                        AND (cm.mute_min_priority IS NULL
+                             OR array_position($3::text[], $4) < array_position($3::text[], cm.mute_min_priority))
+                 FROM messaging.conversation_members cm
+                 WHERE cm.conversation_id = $1 AND cm.user_id != $2
+                   AND NOT EXISTS (SELECT 1 FROM messaging.blocks b WHERE b.blocker_id = cm.user_id AND b.blocked_id = $2)";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(conversation_id.to_string()),
+        ParameterValue::Str(sender_id.to_string()),
+        ParameterValue::Str(format!("{{{}}}", PRIORITIES.join(","))),
+        ParameterValue::Str(priority.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut recipients = Vec::new();
+    let mut muted = 0;
+    for row in &rows.rows {
+        let Some(member_id) = String::decode(&row[0]).ok().and_then(|s| Uuid::parse_str(&s).ok()) else {
+            continue;
+        };
+        if bool::decode(&row[1]).unwrap_or(false) {
+            muted += 1;
+        } else {
+            recipients.push(member_id);
+        }
+    }
+    Ok((recipients, muted))
+}
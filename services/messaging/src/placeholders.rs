@@ -0,0 +1,44 @@
+//! `{{name}}` placeholders
+//!
+//! Shared by notification templates and notification emails. Names are
+//! trimmed, so `{{ name }}` and `{{name}}` are the same placeholder; an
+//! unclosed `{{` is left as text.
+
+/// Replace each `{{name}}` with `value(name)`. A placeholder `value` has no
+/// answer for is kept as written.
+pub fn render(text: &str, mut value: impl FnMut(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                match value(after[..end].trim()) {
+                    Some(value) => out.push_str(&value),
+                    None => out.push_str(&rest[start..start + 2 + end + 2]),
+                }
+                rest = &after[end + 2..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Names of every placeholder in `text`
+pub fn names(text: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else { break };
+        names.push(after[..end].trim().to_string());
+        rest = &after[end + 2..];
+    }
+    names
+}
@@ -0,0 +1,249 @@
+//! Scheduled messages
+//!
+//! A message sent with `scheduled_at` is held in `messaging.scheduled_messages`
+//! as `pending` instead of being posted, so nobody sees it early. Each run of
+//! `POST /messages/scheduled/dispatch` claims the due ones and posts them as
+//! if they were sent then: membership, suspension and blocks are checked
+//! again, and a message that may no longer be sent is marked `failed` with the
+//! reason. The sender can cancel a message until it is claimed.
+
+use crate::error::ServiceError;
+use crate::models::Attachment;
+use serde::Serialize;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+pub const STATUSES: [&str; 5] = ["pending", "sending", "sent", "cancelled", "failed"];
+
+const MAX_SCHEDULE_DAYS: i64 = 365;
+const MAX_PENDING_PER_USER: i64 = 100;
+const DISPATCH_BATCH_SIZE: i64 = 100;
+const DEFAULT_LIST_LIMIT: i64 = 50;
+const MAX_LIST_LIMIT: i64 = 200;
+
+/// Messages stuck in `sending` this long were interrupted and are retried
+const STALE_SENDING_MINUTES: i32 = 10;
+
+#[derive(Debug, Serialize)]
+pub struct ScheduledMessage {
+    pub id: Uuid,
+    pub conversation_id: Uuid,
+    pub body: String,
+    pub attachments: Vec<Attachment>,
+    pub priority: String,
+    pub status: String,
+    pub scheduled_at: String,
+    pub message_id: Option<Uuid>,
+    pub sent_at: Option<String>,
+    pub error: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DispatchSummary {
+    pub sent: usize,
+    pub failed: usize,
+}
+
+/// Parse a requested send time: RFC 3339, in the future and within a year
+pub fn parse_scheduled_at(value: &str) -> Result<DateTime<Utc>, ServiceError> {
+    let at = DateTime::parse_from_rfc3339(value.trim())
+        .map_err(|_| ServiceError::BadRequest("scheduled_at must be an RFC 3339 timestamp".into()))?
+        .with_timezone(&Utc);
+    let now = Utc::now();
+    if at <= now {
+        return Err(ServiceError::BadRequest("scheduled_at must be in the future".into()));
+    }
+    if at > now + Duration::days(MAX_SCHEDULE_DAYS) {
+        return Err(ServiceError::BadRequest(format!("Messages can be scheduled at most {} days ahead", MAX_SCHEDULE_DAYS)));
+    }
+    Ok(at)
+}
+
+/// POST /messages with `scheduled_at` - Hold a message for later delivery;
+/// the conversation has already been resolved and checked
+pub fn schedule(
+    conn: &Connection,
+    sender_id: &Uuid,
+    conversation_id: &Uuid,
+    body: &str,
+    attachments: &[Attachment],
+    priority: &str,
+    scheduled_at: DateTime<Utc>,
+) -> Result<Response, ServiceError> {
+    let query = "SELECT COUNT(*) FROM messaging.scheduled_messages WHERE sender_id = $1 AND status = 'pending'";
+    let rows = conn.query(query, &[ParameterValue::Str(sender_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0) >= MAX_PENDING_PER_USER {
+        return Err(ServiceError::BadRequest(format!("You can have at most {} scheduled messages pending", MAX_PENDING_PER_USER)));
+    }
+
+    let id = Uuid::new_v4();
+    let insert = "INSERT INTO messaging.scheduled_messages
+                  (id, conversation_id, sender_id, body, attachments, priority, status, scheduled_at, created_at)
+                  VALUES ($1, $2, $3, $4, $5::jsonb, $6, 'pending', $7::timestamptz, NOW())
+                  RETURNING created_at::text";
+    let rows = conn.query(insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(conversation_id.to_string()),
+        ParameterValue::Str(sender_id.to_string()),
+        ParameterValue::Str(body.to_string()),
+        ParameterValue::Str(serde_json::to_string(attachments).unwrap_or_else(|_| "[]".into())),
+        ParameterValue::Str(priority.to_string()),
+        ParameterValue::Str(scheduled_at.to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    crate::json_response(202, ScheduledMessage {
+        id,
+        conversation_id: *conversation_id,
+        body: body.to_string(),
+        attachments: attachments.to_vec(),
+        priority: priority.to_string(),
+        status: "pending".into(),
+        scheduled_at: scheduled_at.to_rfc3339(),
+        message_id: None,
+        sent_at: None,
+        error: None,
+        created_at: rows.rows.first().and_then(|row| String::decode(&row[0]).ok()).unwrap_or_default(),
+    })
+}
+
+/// GET /messages/scheduled?status=&limit= - The caller's scheduled messages,
+/// pending ones by default, soonest first
+pub fn list(conn: &Connection, sender_id: &Uuid, status: Option<String>, limit: Option<i64>) -> Result<Response, ServiceError> {
+    let status = status.unwrap_or_else(|| "pending".into());
+    if !STATUSES.contains(&status.as_str()) {
+        return Err(ServiceError::BadRequest(format!("status must be one of: {}", STATUSES.join(", "))));
+    }
+    let limit = limit.unwrap_or(DEFAULT_LIST_LIMIT).clamp(1, MAX_LIST_LIMIT);
+
+    let query = "SELECT id, conversation_id, body, attachments::text, priority, status, scheduled_at::text,
+                        message_id, sent_at::text, error, created_at::text
+                 FROM messaging.scheduled_messages
+                 WHERE sender_id = $1 AND status = $2
+                 ORDER BY scheduled_at
+                 LIMIT $3";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(sender_id.to_string()),
+        ParameterValue::Str(status),
+        ParameterValue::Int64(limit),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let messages: Vec<ScheduledMessage> = rows.rows.iter().map(|row| ScheduledMessage {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        conversation_id: Uuid::parse_str(&String::decode(&row[1]).unwrap_or_default()).unwrap_or_default(),
+        body: String::decode(&row[2]).unwrap_or_default(),
+        attachments: serde_json::from_str(&String::decode(&row[3]).unwrap_or_default()).unwrap_or_default(),
+        priority: String::decode(&row[4]).unwrap_or_default(),
+        status: String::decode(&row[5]).unwrap_or_default(),
+        scheduled_at: String::decode(&row[6]).unwrap_or_default(),
+        message_id: String::decode(&row[7]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+        sent_at: String::decode(&row[8]).ok(),
+        error: String::decode(&row[9]).ok(),
+        created_at: String::decode(&row[10]).unwrap_or_default(),
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({ "scheduled": messages }))
+}
+
+/// DELETE /messages/scheduled/:id - Cancel a scheduled message before it is sent
+pub fn cancel(conn: &Connection, sender_id: &Uuid, scheduled_id: &Uuid) -> Result<Response, ServiceError> {
+    let update = "UPDATE messaging.scheduled_messages SET status = 'cancelled', cancelled_at = NOW()
+                  WHERE id = $1 AND sender_id = $2 AND status = 'pending'";
+    let params = [
+        ParameterValue::Str(scheduled_id.to_string()),
+        ParameterValue::Str(sender_id.to_string()),
+    ];
+    let cancelled = conn.execute(update, &params)
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    if cancelled == 0 {
+        let query = "SELECT status FROM messaging.scheduled_messages WHERE id = $1 AND sender_id = $2";
+        let rows = conn.query(query, &params)
+            .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+        return Err(match rows.rows.first().and_then(|row| String::decode(&row[0]).ok()) {
+            Some(status) => ServiceError::Conflict(format!("The message is already {}", status)),
+            None => ServiceError::NotFound("Scheduled message not found".into()),
+        });
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "id": scheduled_id,
+        "status": "cancelled"
+    }))
+}
+
+/// POST /messages/scheduled/dispatch - Send every due scheduled message (internal)
+pub fn dispatch(conn: &Connection) -> Result<Response, ServiceError> {
+    // Recover messages interrupted mid-send
+    let reset = "UPDATE messaging.scheduled_messages SET status = 'pending', claimed_at = NULL
+                 WHERE status = 'sending' AND claimed_at < NOW() - make_interval(mins => $1)";
+    conn.execute(reset, &[ParameterValue::Int32(STALE_SENDING_MINUTES)])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let claim = "UPDATE messaging.scheduled_messages SET status = 'sending', claimed_at = NOW()
+                 WHERE id IN (
+                     SELECT id FROM messaging.scheduled_messages
+                     WHERE status = 'pending' AND scheduled_at <= NOW()
+                     ORDER BY scheduled_at
+                     LIMIT $1
+                     FOR UPDATE SKIP LOCKED
+                 )
+                 RETURNING id, conversation_id, sender_id, body, attachments::text, priority";
+    let claimed = conn.query(claim, &[ParameterValue::Int64(DISPATCH_BATCH_SIZE)])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut summary = DispatchSummary::default();
+    for row in &claimed.rows {
+        let scheduled_id = String::decode(&row[0]).unwrap_or_default();
+        let conversation_id = Uuid::parse_str(&String::decode(&row[1]).unwrap_or_default()).unwrap_or_default();
+        let sender_id = Uuid::parse_str(&String::decode(&row[2]).unwrap_or_default()).unwrap_or_default();
+        let body = String::decode(&row[3]).unwrap_or_default();
+        let attachments: Vec<Attachment> = serde_json::from_str(&String::decode(&row[4]).unwrap_or_default()).unwrap_or_default();
+        let priority = String::decode(&row[5]).unwrap_or_default();
+
+        let sent = ensure_still_allowed(conn, &sender_id, &conversation_id)
+            .and_then(|_| crate::post_message(conn, &conversation_id, &sender_id, &body, &attachments, &priority));
+        match sent {
+            Ok(posted) => {
+                let update = "UPDATE messaging.scheduled_messages
+                              SET status = 'sent', message_id = $2, sent_at = NOW()
+                              WHERE id = $1";
+                conn.execute(update, &[
+                    ParameterValue::Str(scheduled_id),
+                    ParameterValue::Str(posted.id.to_string()),
+                ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+                summary.sent += 1;
+            }
+            Err(ServiceError::Internal(error)) => return Err(ServiceError::Internal(error)),
+            Err(e) => {
+                let update = "UPDATE messaging.scheduled_messages SET status = 'failed', error = $2 WHERE id = $1";
+                conn.execute(update, &[
+                    ParameterValue::Str(scheduled_id),
+                    ParameterValue::Str(e.to_string()),
+                ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+                summary.failed += 1;
+            }
+        }
+    }
+
+    crate::json_response(200, summary)
+}
+
+/// The send checks of `POST /messages`, as of now rather than when scheduled
+fn ensure_still_allowed(conn: &Connection, sender_id: &Uuid, conversation_id: &Uuid) -> Result<(), ServiceError> {
+    crate::moderation::ensure_not_suspended(conn, sender_id)?;
+
+    let query = "SELECT 1 FROM messaging.conversation_members WHERE conversation_id = $1 AND user_id = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(conversation_id.to_string()),
+        ParameterValue::Str(sender_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if rows.rows.is_empty() {
+        return Err(ServiceError::Forbidden("Not a member of this conversation".into()));
+    }
+
+    crate::moderation::ensure_conversation_open(conn, sender_id, conversation_id)
+}
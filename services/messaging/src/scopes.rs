@@ -0,0 +1,11 @@
+//! Messaging routes open to delegated credentials
+//!
+//! None: conversations, notifications and the event stream are the account
+//! holder's own, and no scope delegates them.
+
+use authorworks_access::Route;
+use spin_sdk::http::Method;
+
+pub fn route_scope(_method: &Method, _path: &str) -> Option<Route> {
+    None
+}
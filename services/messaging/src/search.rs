@@ -0,0 +1,159 @@
+//! Message search
+//!
+//! Full-text search over messages in conversations the caller belongs to,
+//! using the `to_tsvector('simple', body)` GIN index from migration 023.
+//! Snippets are HTML-escaped with matches wrapped in `<mark>`, and each hit
+//! carries the messages just before and after it so results read in context.
+
+use crate::error::ServiceError;
+use serde::Serialize;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+pub const MAX_PAGE_SIZE: i64 = 50;
+
+/// Neighbouring messages are trimmed to this many characters
+const CONTEXT_CHARS: i32 = 200;
+
+/// Escape before highlighting so only the `<mark>` tags are markup
+const ESCAPED_BODY: &str = "replace(replace(replace(m.body, '&', '&amp;'), '<', '&lt;'), '>', '&gt;')";
+
+#[derive(Debug, Serialize)]
+pub struct MessageHit {
+    pub id: String,
+    pub conversation: ConversationSummary,
+    pub sender_id: String,
+    pub sender_name: Option<String>,
+    pub snippet: String,
+    pub created_at: String,
+    pub score: f64,
+    pub previous: Option<ContextMessage>,
+    pub next: Option<ContextMessage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConversationSummary {
+    pub id: String,
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub conversation_type: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ContextMessage {
+    pub id: String,
+    pub sender_name: Option<String>,
+    pub body: String,
+    pub created_at: String,
+}
+
+/// GET /messages/search - Ranked matches in the caller's conversations
+pub fn search(
+    conn: &Connection,
+    user_id: &Uuid,
+    query: &str,
+    conversation_id: Option<Uuid>,
+    from: i64,
+    size: i64,
+) -> Result<Response, ServiceError> {
+    if query.trim().is_empty() {
+        return Err(ServiceError::BadRequest("Query parameter 'q' is required".into()));
+    }
+    let size = size.clamp(1, MAX_PAGE_SIZE);
+    let from = from.max(0);
+    let conversation_param = conversation_id
+        .map(|id| ParameterValue::Str(id.to_string()))
+        .unwrap_or(ParameterValue::DbNull);
+
+    // The tsvector expression must match idx_messages_fts
+    let from_clause = "FROM messaging.messages m
+                       JOIN messaging.conversation_members mb ON mb.conversation_id = m.conversation_id AND mb.user_id = $1
+                       CROSS JOIN websearch_to_tsquery('simple', $2) q";
+    let where_clause = "WHERE to_tsvector('simple', m.body) @@ q
+                        AND ($3::text IS NULL OR m.conversation_id::text = $3)
+                        AND NOT EXISTS (SELECT 1 FROM messaging.blocks bl WHERE bl.blocker_id = $1 AND bl.blocked_id = m.sender_id)";
+
+    let count_query = format!("SELECT COUNT(*) {} {}", from_clause, where_clause);
+    let count_rows = conn.query(&count_query, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(query.to_string()),
+        conversation_param.clone(),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let total = count_rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0);
+
+    let results_query = format!(
+        "SELECT m.id, m.conversation_id, c.name, COALESCE(c.type, 'direct'), m.sender_id, u.name,
+                ts_headline('simple', {escaped}, q, 'StartSel=<mark>, StopSel=</mark>, MaxFragments=2, MaxWords=30, MinWords=10'),
+                m.created_at, ts_rank(to_tsvector('simple', m.body), q)::float8 AS score,
+                prev.id, prev.sender_name, prev.body, prev.created_at,
+                nxt.id, nxt.sender_name, nxt.body, nxt.created_at
+         {from_clause}
+         JOIN messaging.conversations c ON c.id = m.conversation_id
+         LEFT JOIN users.users u ON u.id = m.sender_id
+         LEFT JOIN LATERAL (
+             SELECT p.id, pu.name AS sender_name, LEFT(p.body, {chars}) AS body, p.created_at
+             FROM messaging.messages p LEFT JOIN users.users pu ON pu.id = p.sender_id
+             WHERE p.conversation_id = m.conversation_id AND p.created_at < m.created_at
+             ORDER BY p.created_at DESC LIMIT 1
+         ) prev ON true
+         LEFT JOIN LATERAL (
+             SELECT n.id, nu.name AS sender_name, LEFT(n.body, {chars}) AS body, n.created_at
+             FROM messaging.messages n LEFT JOIN users.users nu ON nu.id = n.sender_id
+             WHERE n.conversation_id = m.conversation_id AND n.created_at > m.created_at
+             ORDER BY n.created_at ASC LIMIT 1
+         ) nxt ON true
+         {where_clause}
+         ORDER BY score DESC, m.created_at DESC
+         LIMIT $4 OFFSET $5",
+        escaped = ESCAPED_BODY,
+        from_clause = from_clause,
+        where_clause = where_clause,
+        chars = CONTEXT_CHARS,
+    );
+    let rows = conn.query(&results_query, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(query.to_string()),
+        conversation_param,
+        ParameterValue::Int64(size),
+        ParameterValue::Int64(from),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let results: Vec<MessageHit> = rows.rows.iter().map(|row| {
+        MessageHit {
+            id: String::decode(&row[0]).unwrap_or_default(),
+            conversation: ConversationSummary {
+                id: String::decode(&row[1]).unwrap_or_default(),
+                name: String::decode(&row[2]).ok(),
+                conversation_type: String::decode(&row[3]).unwrap_or_default(),
+            },
+            sender_id: String::decode(&row[4]).unwrap_or_default(),
+            sender_name: String::decode(&row[5]).ok(),
+            snippet: String::decode(&row[6]).unwrap_or_default(),
+            created_at: String::decode(&row[7]).unwrap_or_default(),
+            score: f64::decode(&row[8]).unwrap_or(0.0),
+            previous: context_message(row, 9),
+            next: context_message(row, 13),
+        }
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "query": query,
+        "results": results,
+        "total": total,
+        "from": from,
+        "size": size
+    }))
+}
+
+/// Neighbouring message from four columns starting at `start`, if there is one
+fn context_message(row: &[spin_sdk::pg::DbValue], start: usize) -> Option<ContextMessage> {
+    let id = String::decode(&row[start]).ok()?;
+    Some(ContextMessage {
+        id,
+        sender_name: String::decode(&row[start + 1]).ok(),
+        body: String::decode(&row[start + 2]).unwrap_or_default(),
+        created_at: String::decode(&row[start + 3]).unwrap_or_default(),
+    })
+}
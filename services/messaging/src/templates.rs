@@ -0,0 +1,336 @@
+//! Notification templates
+//!
+//! Services send `POST /notifications/from-template` with a template name and
+//! variables instead of formatting title and body themselves. Templates live
+//! in `messaging.templates`, one row per name and language, and use
+//! `{{variable}}` placeholders. Every placeholder must be declared in the
+//! template's `variables`, and every declared variable must be supplied when
+//! rendering, so a typo fails loudly instead of reaching a user as `{{nmae}}`.
+//!
+//! The language is taken from the request, then the recipient's profile
+//! (`preferences.language`), then `en`. A regional language such as `pt-BR`
+//! falls back to `pt` and then `en` when no closer translation exists.
+//! Templates are managed by admins.
+
+use crate::error::ServiceError;
+use crate::placeholders;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub const DEFAULT_LANGUAGE: &str = "en";
+const MAX_NAME_CHARS: usize = 100;
+const MAX_TITLE_CHARS: usize = 255;
+const MAX_BODY_CHARS: usize = 5000;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Serialize)]
+pub struct Template {
+    pub id: Uuid,
+    pub name: String,
+    pub language: String,
+    #[serde(rename = "type")]
+    pub notification_type: String,
+    pub title: String,
+    pub body: String,
+    pub variables: Vec<String>,
+    /// Used when the sender does not pick a priority
+    pub priority: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTemplateRequest {
+    /// e.g. `payment_failed`; shared by every translation of the template
+    pub name: String,
+    pub language: Option<String>,
+    #[serde(rename = "type")]
+    pub notification_type: String,
+    pub title: String,
+    pub body: String,
+    #[serde(default)]
+    pub variables: Vec<String>,
+    pub priority: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateTemplateRequest {
+    #[serde(rename = "type")]
+    pub notification_type: Option<String>,
+    pub title: Option<String>,
+    pub body: Option<String>,
+    pub variables: Option<Vec<String>>,
+    pub priority: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FromTemplateRequest {
+    pub user_id: Uuid,
+    pub template: String,
+    #[serde(default)]
+    pub variables: HashMap<String, serde_json::Value>,
+    #[serde(default)]
+    pub data: HashMap<String, serde_json::Value>,
+    /// Overrides the template's priority
+    pub priority: Option<String>,
+    /// Overrides the recipient's language
+    pub language: Option<String>,
+    pub group_key: Option<String>,
+}
+
+/// A template filled in for one recipient
+pub struct Rendered {
+    pub notification_type: String,
+    pub title: String,
+    pub body: String,
+    pub priority: Option<String>,
+    pub language: String,
+}
+
+//=============================================================================
+// Rendering
+//=============================================================================
+
+/// Pick the best translation of `name` for the recipient and fill it in
+pub fn render(conn: &Connection, body: &FromTemplateRequest) -> Result<Rendered, ServiceError> {
+    let requested = match &body.language {
+        Some(language) => normalize_language(language)?,
+        None => user_language(conn, &body.user_id)?.unwrap_or_else(|| DEFAULT_LANGUAGE.to_string()),
+    };
+
+    let template = candidate_languages(&requested)
+        .iter()
+        .find_map(|language| load_by_name(conn, &body.template, language).transpose())
+        .transpose()?
+        .ok_or_else(|| ServiceError::NotFound(format!("Template not found: {}", body.template)))?;
+
+    let missing: Vec<&str> = template.variables.iter()
+        .filter(|v| !body.variables.contains_key(v.as_str()))
+        .map(|v| v.as_str())
+        .collect();
+    if !missing.is_empty() {
+        return Err(ServiceError::BadRequest(format!("Missing template variables: {}", missing.join(", "))));
+    }
+
+    Ok(Rendered {
+        title: substitute(&template.title, &body.variables),
+        body: substitute(&template.body, &body.variables),
+        notification_type: template.notification_type,
+        priority: template.priority,
+        language: template.language,
+    })
+}
+
+/// Strings are inserted as-is and other JSON values in their JSON form
+fn substitute(text: &str, variables: &HashMap<String, serde_json::Value>) -> String {
+    placeholders::render(text, |name| variables.get(name).map(|value| match value {
+        serde_json::Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }))
+}
+
+fn user_language(conn: &Connection, user_id: &Uuid) -> Result<Option<String>, ServiceError> {
+    let query = "SELECT preferences->>'language' FROM users.profiles WHERE user_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first()
+        .and_then(|row| String::decode(&row[0]).ok())
+        .and_then(|language| normalize_language(&language).ok()))
+}
+
+/// `pt-BR` -> [`pt-br`, `pt`, `en`]
+fn candidate_languages(language: &str) -> Vec<String> {
+    let mut candidates = vec![language.to_string()];
+    if let Some((base, _)) = language.split_once('-') {
+        candidates.push(base.to_string());
+    }
+    if !candidates.iter().any(|c| c == DEFAULT_LANGUAGE) {
+        candidates.push(DEFAULT_LANGUAGE.to_string());
+    }
+    candidates
+}
+
+/// Lowercase BCP 47-style tag such as `en` or `pt-br`
+fn normalize_language(language: &str) -> Result<String, ServiceError> {
+    let language = language.trim().replace('_', "-").to_lowercase();
+    let valid = !language.is_empty()
+        && language.len() <= 10
+        && language.split('-').all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric()));
+    if !valid {
+        return Err(ServiceError::BadRequest(format!("Invalid language: {}", language)));
+    }
+    Ok(language)
+}
+
+//=============================================================================
+// Storage
+//=============================================================================
+
+const TEMPLATE_COLUMNS: &str = "id, name, language, type, title, body, variables::text, priority,
+                                created_at::text, updated_at::text";
+
+fn template_from_row(row: &[spin_sdk::pg::DbValue]) -> Template {
+    Template {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        name: String::decode(&row[1]).unwrap_or_default(),
+        language: String::decode(&row[2]).unwrap_or_default(),
+        notification_type: String::decode(&row[3]).unwrap_or_default(),
+        title: String::decode(&row[4]).unwrap_or_default(),
+        body: String::decode(&row[5]).unwrap_or_default(),
+        variables: serde_json::from_str(&String::decode(&row[6]).unwrap_or_default()).unwrap_or_default(),
+        priority: String::decode(&row[7]).ok(),
+        created_at: String::decode(&row[8]).unwrap_or_default(),
+        updated_at: String::decode(&row[9]).unwrap_or_default(),
+    }
+}
+
+fn load_by_name(conn: &Connection, name: &str, language: &str) -> Result<Option<Template>, ServiceError> {
+    let query = format!("SELECT {} FROM messaging.templates WHERE name = $1 AND language = $2", TEMPLATE_COLUMNS);
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(name.to_string()),
+        ParameterValue::Str(language.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first().map(|row| template_from_row(row)))
+}
+
+fn load(conn: &Connection, template_id: &Uuid) -> Result<Template, ServiceError> {
+    let query = format!("SELECT {} FROM messaging.templates WHERE id = $1", TEMPLATE_COLUMNS);
+    let rows = conn.query(&query, &[ParameterValue::Str(template_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    rows.rows.first()
+        .map(|row| template_from_row(row))
+        .ok_or_else(|| ServiceError::NotFound("Template not found".into()))
+}
+
+//=============================================================================
+// Admin
+//=============================================================================
+
+/// GET /admin/templates?name=
+pub fn list(conn: &Connection, name: Option<&str>) -> Result<Response, ServiceError> {
+    let query = format!(
+        "SELECT {} FROM messaging.templates WHERE ($1::text IS NULL OR name = $1) ORDER BY name, language",
+        TEMPLATE_COLUMNS
+    );
+    let rows = conn.query(&query, &[
+        name.map(|n| ParameterValue::Str(n.to_string())).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let templates: Vec<Template> = rows.rows.iter().map(|row| template_from_row(row)).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "templates": templates,
+        "total": templates.len()
+    }))
+}
+
+/// POST /admin/templates
+pub fn create(conn: &Connection, body: CreateTemplateRequest) -> Result<Response, ServiceError> {
+    let name = body.name.trim().to_lowercase();
+    if name.is_empty() || name.len() > MAX_NAME_CHARS
+        || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+    {
+        return Err(ServiceError::BadRequest(format!(
+            "name must be 1-{} lowercase letters, digits, underscores or dots", MAX_NAME_CHARS
+        )));
+    }
+    let language = normalize_language(body.language.as_deref().unwrap_or(DEFAULT_LANGUAGE))?;
+    let priority = body.priority.as_deref().map(crate::muting::parse_priority).transpose()?;
+    validate_content(&body.title, &body.body, &body.variables)?;
+
+    let id = Uuid::new_v4();
+    let insert = "INSERT INTO messaging.templates
+                  (id, name, language, type, title, body, variables, priority, created_at, updated_at)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7::jsonb, $8, NOW(), NOW())
+                  ON CONFLICT (name, language) DO NOTHING";
+    let inserted = conn.execute(insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(name.clone()),
+        ParameterValue::Str(language.clone()),
+        ParameterValue::Str(body.notification_type),
+        ParameterValue::Str(body.title),
+        ParameterValue::Str(body.body),
+        ParameterValue::Str(serde_json::to_string(&body.variables).unwrap_or_else(|_| "[]".into())),
+        priority.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    if inserted == 0 {
+        return Err(ServiceError::Conflict(format!("Template {} already exists in {}", name, language)));
+    }
+
+    crate::json_response(201, load(conn, &id)?)
+}
+
+/// PUT /admin/templates/:id - Name and language are fixed; add a new
+/// template for another translation
+pub fn update(conn: &Connection, template_id: &Uuid, body: UpdateTemplateRequest) -> Result<Response, ServiceError> {
+    let current = load(conn, template_id)?;
+    let title = body.title.unwrap_or(current.title);
+    let text = body.body.unwrap_or(current.body);
+    let variables = body.variables.unwrap_or(current.variables);
+    validate_content(&title, &text, &variables)?;
+    let priority = match body.priority {
+        Some(priority) => Some(crate::muting::parse_priority(&priority)?),
+        None => current.priority,
+    };
+
+    let update = "UPDATE messaging.templates
+                  SET type = $2, title = $3, body = $4, variables = $5::jsonb, priority = $6, updated_at = NOW()
+                  WHERE id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(template_id.to_string()),
+        ParameterValue::Str(body.notification_type.unwrap_or(current.notification_type)),
+        ParameterValue::Str(title),
+        ParameterValue::Str(text),
+        ParameterValue::Str(serde_json::to_string(&variables).unwrap_or_else(|_| "[]".into())),
+        priority.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    crate::json_response(200, load(conn, template_id)?)
+}
+
+/// DELETE /admin/templates/:id
+pub fn delete(conn: &Connection, template_id: &Uuid) -> Result<Response, ServiceError> {
+    let deleted = conn.execute(
+        "DELETE FROM messaging.templates WHERE id = $1",
+        &[ParameterValue::Str(template_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    if deleted == 0 {
+        return Err(ServiceError::NotFound("Template not found".into()));
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "id": template_id,
+        "message": "Template deleted"
+    }))
+}
+
+fn validate_content(title: &str, body: &str, variables: &[String]) -> Result<(), ServiceError> {
+    if title.trim().is_empty() || title.chars().count() > MAX_TITLE_CHARS {
+        return Err(ServiceError::BadRequest(format!("title must be 1-{} characters", MAX_TITLE_CHARS)));
+    }
+    if body.trim().is_empty() || body.chars().count() > MAX_BODY_CHARS {
+        return Err(ServiceError::BadRequest(format!("body must be 1-{} characters", MAX_BODY_CHARS)));
+    }
+
+    let undeclared: Vec<String> = placeholders::names(title).into_iter()
+        .chain(placeholders::names(body))
+        .filter(|name| !variables.contains(name))
+        .collect();
+    if !undeclared.is_empty() {
+        return Err(ServiceError::BadRequest(format!(
+            "Placeholders not declared in variables: {}", undeclared.join(", ")
+        )));
+    }
+    Ok(())
+}
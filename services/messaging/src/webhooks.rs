@@ -0,0 +1,541 @@
+//! Outbound webhook integrations
+//!
+//! Users register an HTTPS endpoint per event type, formatted for Slack
+//! (`{"text"}`), Discord (`{"content"}`) or as the raw JSON event. Like email,
+//! delivery is a sweep: each run of `POST /integrations/webhooks/deliver`
+//! queues a delivery for every recent source row matching a webhook, then
+//! sends a batch of due deliveries. Sources are `messaging.events` rows whose
+//! type is the webhook's event type (services publish `book.published` etc.
+//! there) and notifications of the corresponding type.
+//!
+//! Every request carries `X-AuthorWorks-Signature: t=<unix>,v1=<hex>`, an
+//! HMAC-SHA256 of `<t>.<body>` keyed with the webhook's secret. Network
+//! errors, 429 and 5xx are retried with backoff; other rejections fail the
+//! delivery, and 404/410 also deactivate the webhook since the endpoint is gone.
+
+use crate::error::ServiceError;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use spin_sdk::http::Response;
+use spin_sdk::outbound_http;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use chrono::Utc;
+use std::net::IpAddr;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Subscribable event types and the notification type that also feeds each
+const EVENT_TYPES: [(&str, &str); 3] = [
+    ("book.published", "book_published"),
+    ("chapter.generated", "chapter_complete"),
+    ("comment.created", "comment_added"),
+];
+
+const FORMATS: [&str; 3] = ["json", "slack", "discord"];
+
+const MAX_WEBHOOKS_PER_USER: i64 = 25;
+const DEFAULT_BATCH_SIZE: i64 = 50;
+const DEFAULT_MAX_ATTEMPTS: i32 = 6;
+const DEFAULT_ENQUEUE_WINDOW_MINUTES: i32 = 60;
+const RETRY_BASE_SECONDS: i64 = 30;
+const RETRY_MAX_SECONDS: i64 = 3600;
+/// Deliveries stuck in `sending` this long were interrupted and are retried
+const STALE_SENDING_MINUTES: i32 = 10;
+/// Response bodies are only kept as error context
+const MAX_ERROR_BODY: usize = 500;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterWebhookRequest {
+    pub url: String,
+    pub event_type: String,
+    /// `json` (default), `slack` or `discord`
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub url: String,
+    pub event_type: String,
+    pub format: String,
+    pub active: bool,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub event_type: String,
+    pub status: String,
+    pub attempts: i32,
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub payload: serde_json::Value,
+    pub created_at: String,
+    pub delivered_at: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DeliverySummary {
+    pub enqueued: u64,
+    pub delivered: u64,
+    pub retrying: u64,
+    pub failed: u64,
+}
+
+/// Outcome of one POST to a webhook endpoint
+enum SendError {
+    /// Worth retrying: network failure, rate limiting, or a 5xx
+    Transient(Option<i32>, String),
+    /// The endpoint rejected the request; `gone` when it no longer exists
+    Permanent { status: Option<i32>, error: String, gone: bool },
+}
+
+struct DeliveryConfig {
+    batch_size: i64,
+    max_attempts: i32,
+    enqueue_window_minutes: i32,
+}
+
+fn get_delivery_config() -> DeliveryConfig {
+    DeliveryConfig {
+        batch_size: variables::get("webhook_batch_size").ok()
+            .and_then(|s| s.parse().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_BATCH_SIZE),
+        max_attempts: variables::get("webhook_max_attempts").ok()
+            .and_then(|s| s.parse().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_MAX_ATTEMPTS),
+        enqueue_window_minutes: variables::get("webhook_enqueue_window_minutes").ok()
+            .and_then(|s| s.parse().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_ENQUEUE_WINDOW_MINUTES),
+    }
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /integrations/webhooks - Register a URL for an event type; the
+/// signing secret is only returned here
+pub fn register(conn: &Connection, user_id: &Uuid, body: RegisterWebhookRequest) -> Result<Response, ServiceError> {
+    let url = body.url.trim().to_string();
+    validate_url(&url)?;
+    if !EVENT_TYPES.iter().any(|(event, _)| *event == body.event_type) {
+        return Err(ServiceError::BadRequest(format!(
+            "Unknown event_type '{}'; expected one of {}",
+            body.event_type,
+            EVENT_TYPES.iter().map(|(event, _)| *event).collect::<Vec<_>>().join(", ")
+        )));
+    }
+    let format = body.format.unwrap_or_else(|| "json".into());
+    if !FORMATS.contains(&format.as_str()) {
+        return Err(ServiceError::BadRequest(format!(
+            "Unknown format '{}'; expected one of {}", format, FORMATS.join(", ")
+        )));
+    }
+
+    let count_query = "SELECT COUNT(*) FROM messaging.webhooks WHERE user_id = $1";
+    let count = conn.query(count_query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?
+        .rows.first()
+        .map(|row| i64::decode(&row[0]).unwrap_or(0))
+        .unwrap_or(0);
+    if count >= MAX_WEBHOOKS_PER_USER {
+        return Err(ServiceError::BadRequest(format!("At most {} webhooks per user", MAX_WEBHOOKS_PER_USER)));
+    }
+
+    let id = Uuid::new_v4();
+    let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let now = Utc::now().to_rfc3339();
+    let insert = "INSERT INTO messaging.webhooks (id, user_id, url, event_type, format, secret, created_at)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7)
+                  ON CONFLICT (user_id, url, event_type) DO NOTHING";
+    let inserted = conn.execute(insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(url.clone()),
+        ParameterValue::Str(body.event_type.clone()),
+        ParameterValue::Str(format.clone()),
+        ParameterValue::Str(secret.clone()),
+        ParameterValue::Str(now.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    if inserted == 0 {
+        return Err(ServiceError::BadRequest("This URL is already registered for the event type".into()));
+    }
+
+    crate::json_response(201, serde_json::json!({
+        "webhook": Webhook {
+            id,
+            url,
+            event_type: body.event_type,
+            format,
+            active: true,
+            created_at: now,
+        },
+        "secret": secret
+    }))
+}
+
+/// GET /integrations/webhooks - The caller's webhooks
+pub fn list(conn: &Connection, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT id, url, event_type, format, active, created_at
+                 FROM messaging.webhooks WHERE user_id = $1
+                 ORDER BY created_at DESC";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let webhooks: Vec<Webhook> = rows.rows.iter().map(|row| Webhook {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        url: String::decode(&row[1]).unwrap_or_default(),
+        event_type: String::decode(&row[2]).unwrap_or_default(),
+        format: String::decode(&row[3]).unwrap_or_default(),
+        active: bool::decode(&row[4]).unwrap_or(false),
+        created_at: String::decode(&row[5]).unwrap_or_default(),
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({ "webhooks": webhooks }))
+}
+
+/// DELETE /integrations/webhooks/:id - Remove a webhook and its delivery log
+pub fn delete(conn: &Connection, user_id: &Uuid, webhook_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "DELETE FROM messaging.webhooks WHERE id = $1 AND user_id = $2";
+    let deleted = conn.execute(query, &[
+        ParameterValue::Str(webhook_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    if deleted == 0 {
+        return Err(ServiceError::NotFound("Webhook not found".into()));
+    }
+    crate::json_response(200, serde_json::json!({ "deleted": true }))
+}
+
+/// GET /integrations/webhooks/:id/deliveries - Recent deliveries, newest first
+pub fn list_deliveries(conn: &Connection, user_id: &Uuid, webhook_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT d.id, d.event_type, d.status, d.attempts, d.response_status, d.last_error,
+                        d.payload::text, d.created_at, d.delivered_at
+                 FROM messaging.webhook_deliveries d
+                 JOIN messaging.webhooks w ON w.id = d.webhook_id
+                 WHERE d.webhook_id = $1 AND w.user_id = $2
+                 ORDER BY d.created_at DESC LIMIT 50";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(webhook_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    if rows.rows.is_empty() {
+        // Tell an empty log apart from somebody else's webhook
+        let exists = conn.query(
+            "SELECT 1 FROM messaging.webhooks WHERE id = $1 AND user_id = $2",
+            &[ParameterValue::Str(webhook_id.to_string()), ParameterValue::Str(user_id.to_string())],
+        ).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+        if exists.rows.is_empty() {
+            return Err(ServiceError::NotFound("Webhook not found".into()));
+        }
+    }
+
+    let deliveries: Vec<WebhookDelivery> = rows.rows.iter().map(|row| WebhookDelivery {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        event_type: String::decode(&row[1]).unwrap_or_default(),
+        status: String::decode(&row[2]).unwrap_or_default(),
+        attempts: i32::decode(&row[3]).unwrap_or(0),
+        response_status: i32::decode(&row[4]).ok(),
+        last_error: String::decode(&row[5]).ok(),
+        payload: serde_json::from_str(&String::decode(&row[6]).unwrap_or_default()).unwrap_or_default(),
+        created_at: String::decode(&row[7]).unwrap_or_default(),
+        delivered_at: String::decode(&row[8]).ok(),
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({ "deliveries": deliveries }))
+}
+
+/// POST /integrations/webhooks/deliver - Queue deliveries for new events and send due ones
+pub fn deliver(conn: &Connection) -> Result<Response, ServiceError> {
+    let config = get_delivery_config();
+    let mut summary = DeliverySummary {
+        enqueued: enqueue(conn, &config)?,
+        ..Default::default()
+    };
+
+    // Recover deliveries interrupted mid-send
+    let reset = "UPDATE messaging.webhook_deliveries SET status = 'pending', updated_at = NOW()
+                 WHERE status = 'sending' AND updated_at < NOW() - make_interval(mins => $1)";
+    conn.execute(reset, &[ParameterValue::Int32(STALE_SENDING_MINUTES)])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let claim = "UPDATE messaging.webhook_deliveries SET status = 'sending', attempts = attempts + 1, updated_at = NOW()
+                 WHERE id IN (
+                     SELECT id FROM messaging.webhook_deliveries
+                     WHERE status = 'pending' AND next_attempt_at <= NOW()
+                     ORDER BY next_attempt_at
+                     LIMIT $1
+                     FOR UPDATE SKIP LOCKED
+                 )
+                 RETURNING id, webhook_id, event_type, payload::text, attempts";
+    let claimed = conn.query(claim, &[ParameterValue::Int64(config.batch_size)])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    for row in &claimed.rows {
+        let delivery_id = String::decode(&row[0]).unwrap_or_default();
+        let webhook_id = String::decode(&row[1]).unwrap_or_default();
+        let event_type = String::decode(&row[2]).unwrap_or_default();
+        let payload: serde_json::Value =
+            serde_json::from_str(&String::decode(&row[3]).unwrap_or_default()).unwrap_or_default();
+        let attempts = i32::decode(&row[4]).unwrap_or(1);
+
+        let result = match load_endpoint(conn, &webhook_id)? {
+            Some((url, format, secret)) => {
+                let body = render(&format, &delivery_id, &event_type, &payload);
+                send(&url, &secret, &delivery_id, &event_type, &body)
+            }
+            None => Err(SendError::Permanent { status: None, error: "Webhook is inactive".into(), gone: false }),
+        };
+
+        match result {
+            Ok(status) => {
+                finish(conn, &delivery_id, "delivered", Some(status), None)?;
+                summary.delivered += 1;
+            }
+            Err(SendError::Transient(status, error)) if attempts < config.max_attempts => {
+                schedule_retry(conn, &delivery_id, attempts, status, &error)?;
+                summary.retrying += 1;
+            }
+            Err(SendError::Transient(status, error)) => {
+                finish(conn, &delivery_id, "failed", status, Some(&error))?;
+                summary.failed += 1;
+            }
+            Err(SendError::Permanent { status, error, gone }) => {
+                finish(conn, &delivery_id, "failed", status, Some(&error))?;
+                if gone {
+                    deactivate(conn, &webhook_id)?;
+                }
+                summary.failed += 1;
+            }
+        }
+    }
+
+    crate::json_response(200, summary)
+}
+
+//=============================================================================
+// Queue
+//=============================================================================
+
+/// Queue one delivery per webhook for each matching event or notification
+/// created since the webhook was registered and within the enqueue window
+fn enqueue(conn: &Connection, config: &DeliveryConfig) -> Result<u64, ServiceError> {
+    let from_events = "INSERT INTO messaging.webhook_deliveries (webhook_id, user_id, event_type, source_id, payload)
+                       SELECT w.id, w.user_id, w.event_type, e.id,
+                              jsonb_build_object('event', w.event_type, 'occurred_at', e.created_at, 'data', e.data)
+                       FROM messaging.webhooks w
+                       JOIN messaging.events e ON e.user_id = w.user_id AND e.type = w.event_type
+                       WHERE w.active AND e.created_at >= w.created_at
+                         AND e.created_at > NOW() - make_interval(mins => $1)
+                       ON CONFLICT (webhook_id, source_id) DO NOTHING";
+    let mut enqueued = conn.execute(from_events, &[ParameterValue::Int32(config.enqueue_window_minutes)])
+        .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    let sources = EVENT_TYPES.iter()
+        .map(|(event, notification)| format!("('{}', '{}')", event, notification))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let from_notifications = format!(
+        "INSERT INTO messaging.webhook_deliveries (webhook_id, user_id, event_type, source_id, payload)
+         SELECT w.id, w.user_id, w.event_type, n.id,
+                jsonb_build_object('event', w.event_type, 'occurred_at', n.created_at,
+                    'data', COALESCE(n.data, '{{}}'::jsonb) || jsonb_build_object('title', n.title, 'body', n.body))
+         FROM messaging.webhooks w
+         JOIN (VALUES {}) AS m(event_type, notification_type) ON m.event_type = w.event_type
+         JOIN messaging.notifications n ON n.user_id = w.user_id AND n.type = m.notification_type
+         WHERE w.active AND n.created_at >= w.created_at
+           AND n.created_at > NOW() - make_interval(mins => $1)
+         ON CONFLICT (webhook_id, source_id) DO NOTHING",
+        sources
+    );
+    enqueued += conn.execute(&from_notifications, &[ParameterValue::Int32(config.enqueue_window_minutes)])
+        .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    Ok(enqueued)
+}
+
+fn load_endpoint(conn: &Connection, webhook_id: &str) -> Result<Option<(String, String, String)>, ServiceError> {
+    let query = "SELECT url, format, secret FROM messaging.webhooks WHERE id = $1 AND active";
+    let rows = conn.query(query, &[ParameterValue::Str(webhook_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first().map(|row| (
+        String::decode(&row[0]).unwrap_or_default(),
+        String::decode(&row[1]).unwrap_or_default(),
+        String::decode(&row[2]).unwrap_or_default(),
+    )))
+}
+
+fn finish(
+    conn: &Connection,
+    delivery_id: &str,
+    status: &str,
+    response_status: Option<i32>,
+    error: Option<&str>,
+) -> Result<(), ServiceError> {
+    let update = "UPDATE messaging.webhook_deliveries
+                  SET status = $2, response_status = $3, last_error = $4, updated_at = NOW(),
+                      delivered_at = CASE WHEN $2 = 'delivered' THEN NOW() ELSE delivered_at END
+                  WHERE id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(delivery_id.to_string()),
+        ParameterValue::Str(status.to_string()),
+        response_status.map(ParameterValue::Int32).unwrap_or(ParameterValue::DbNull),
+        error.map(|e| ParameterValue::Str(e.to_string())).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+/// Exponential backoff from `RETRY_BASE_SECONDS`, capped at an hour
+fn schedule_retry(
+    conn: &Connection,
+    delivery_id: &str,
+    attempts: i32,
+    response_status: Option<i32>,
+    error: &str,
+) -> Result<(), ServiceError> {
+    let delay = (RETRY_BASE_SECONDS << (attempts - 1).clamp(0, 16)).min(RETRY_MAX_SECONDS);
+    let update = "UPDATE messaging.webhook_deliveries
+                  SET status = 'pending', response_status = $2, last_error = $3,
+                      next_attempt_at = NOW() + make_interval(secs => $4), updated_at = NOW()
+                  WHERE id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(delivery_id.to_string()),
+        response_status.map(ParameterValue::Int32).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(error.to_string()),
+        ParameterValue::Int64(delay),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+fn deactivate(conn: &Connection, webhook_id: &str) -> Result<(), ServiceError> {
+    conn.execute("UPDATE messaging.webhooks SET active = false WHERE id = $1", &[ParameterValue::Str(webhook_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+//=============================================================================
+// Delivery
+//=============================================================================
+
+/// Request body for the webhook's format
+fn render(format: &str, delivery_id: &str, event_type: &str, payload: &serde_json::Value) -> String {
+    match format {
+        "slack" => serde_json::json!({ "text": summary_text(event_type, payload) }).to_string(),
+        "discord" => serde_json::json!({ "content": summary_text(event_type, payload) }).to_string(),
+        _ => {
+            let mut body = payload.clone();
+            body["id"] = serde_json::Value::String(delivery_id.to_string());
+            body.to_string()
+        }
+    }
+}
+
+/// One-line headline plus the notification body, if any
+fn summary_text(event_type: &str, payload: &serde_json::Value) -> String {
+    let label = match event_type {
+        "book.published" => "Book published",
+        "chapter.generated" => "Chapter generated",
+        "comment.created" => "New comment",
+        other => other,
+    };
+    let data = payload.get("data");
+    let field = |key: &str| data.and_then(|d| d.get(key)).and_then(|v| v.as_str()).filter(|s| !s.is_empty());
+
+    let mut text = match field("title").or_else(|| field("book_title")) {
+        Some(title) => format!("{}: {}", label, title),
+        None => label.to_string(),
+    };
+    if let Some(body) = field("body") {
+        text.push('\n');
+        text.push_str(body);
+    }
+    text
+}
+
+fn send(url: &str, secret: &str, delivery_id: &str, event_type: &str, body: &str) -> Result<i32, SendError> {
+    let timestamp = Utc::now().timestamp();
+    let signature = sign(secret, timestamp, body)
+        .map_err(|error| SendError::Permanent { status: None, error, gone: false })?;
+
+    let request = outbound_http::Request::builder()
+        .method(outbound_http::Method::Post)
+        .uri(url)
+        .header("Content-Type", "application/json")
+        .header("User-Agent", "AuthorWorks-Webhooks/1.0")
+        .header("X-AuthorWorks-Event", event_type)
+        .header("X-AuthorWorks-Delivery", delivery_id)
+        .header("X-AuthorWorks-Signature", format!("t={},v1={}", timestamp, signature))
+        .body(body.to_string())
+        .build();
+
+    let response = outbound_http::send(request)
+        .map_err(|e| SendError::Transient(None, format!("Webhook request failed: {}", e)))?;
+    let status = response.status().as_u16();
+    if status < 300 {
+        return Ok(status as i32);
+    }
+
+    let body = String::from_utf8_lossy(response.body());
+    let error = format!("Endpoint returned {} - {}", status, body.chars().take(MAX_ERROR_BODY).collect::<String>());
+    if status == 429 || status >= 500 {
+        Err(SendError::Transient(Some(status as i32), error))
+    } else {
+        Err(SendError::Permanent { status: Some(status as i32), error, gone: status == 404 || status == 410 })
+    }
+}
+
+/// Hex HMAC-SHA256 of `<timestamp>.<body>`
+fn sign(secret: &str, timestamp: i64, body: &str) -> Result<String, String> {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .map_err(|e| format!("HMAC error: {}", e))?;
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+/// HTTPS only, and never to loopback, private or link-local addresses
+fn validate_url(url: &str) -> Result<(), ServiceError> {
+    let invalid = || ServiceError::BadRequest("url must be a public https:// URL".into());
+
+    let rest = url.strip_prefix("https://").ok_or_else(invalid)?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host_port = authority.rsplit('@').next().unwrap_or_default();
+    let host = if let Some(v6) = host_port.strip_prefix('[') {
+        v6.split(']').next().unwrap_or_default()
+    } else {
+        host_port.split(':').next().unwrap_or_default()
+    };
+    let host = host.to_ascii_lowercase();
+
+    if host.is_empty() || host == "localhost" || host.ends_with(".localhost")
+        || host.ends_with(".local") || host.ends_with(".internal") || !host.contains(['.', ':'])
+    {
+        return Err(invalid());
+    }
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        let private = match ip {
+            IpAddr::V4(v4) => v4.is_private() || v4.is_loopback() || v4.is_link_local()
+                || v4.is_unspecified() || v4.is_broadcast(),
+            IpAddr::V6(v6) => v6.is_loopback() || v6.is_unspecified()
+                || (v6.segments()[0] & 0xfe00) == 0xfc00 || (v6.segments()[0] & 0xffc0) == 0xfe80,
+        };
+        if private {
+            return Err(invalid());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,205 @@
+//! Object storage backends
+//!
+//! Files live in the object store named by the `storage_backend` variable:
+//! - `s3` (default) - Amazon S3, MinIO and other S3-compatible stores
+//! - `gcs` - Google Cloud Storage through its XML API and HMAC keys
+//! - `azure` - Azure Blob Storage with a shared account key
+//!
+//! Every backend signs URLs, so large uploads and downloads go straight
+//! between the client and the store. The service makes its own reads and
+//! writes through the same short-lived URLs, which keeps each backend down to
+//! URL signing plus a server-side copy.
+
+use crate::error::ServiceError;
+use crate::{azure, gcs, s3};
+use spin_sdk::outbound_http;
+use spin_sdk::variables;
+
+/// Lifetime of URLs the service signs for its own requests
+const INTERNAL_URL_SECS: i64 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UrlMethod {
+    Get,
+    Head,
+    Put,
+    Delete,
+}
+
+impl UrlMethod {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UrlMethod::Get => "GET",
+            UrlMethod::Head => "HEAD",
+            UrlMethod::Put => "PUT",
+            UrlMethod::Delete => "DELETE",
+        }
+    }
+
+    fn outbound(self) -> outbound_http::Method {
+        match self {
+            UrlMethod::Get => outbound_http::Method::Get,
+            UrlMethod::Head => outbound_http::Method::Head,
+            UrlMethod::Put => outbound_http::Method::Put,
+            UrlMethod::Delete => outbound_http::Method::Delete,
+        }
+    }
+}
+
+/// Response headers a signed GET should force, whatever the object was
+/// stored with, and the exact body a signed PUT must carry
+#[derive(Debug, Default)]
+pub struct UrlOptions {
+    pub response_content_type: Option<String>,
+    pub response_content_disposition: Option<String>,
+    /// Backends that can sign request headers refuse a PUT with any other
+    /// `Content-Type`
+    pub upload_content_type: Option<String>,
+    /// Likewise for `Content-Length`, so the upload is exactly this size
+    pub upload_content_length: Option<i64>,
+}
+
+pub struct ObjectHead {
+    pub size: i64,
+    pub content_type: Option<String>,
+}
+
+pub trait StorageBackend {
+    /// Reported by `/health`
+    fn name(&self) -> &'static str;
+
+    /// URL that performs `method` on `key` without further credentials
+    fn signed_url(&self, method: UrlMethod, key: &str, expires_secs: i64, options: &UrlOptions) -> Result<String, ServiceError>;
+
+    /// Server-side copy within the bucket or container
+    fn copy(&self, source_key: &str, dest_key: &str) -> Result<(), ServiceError>;
+
+    /// Headers a client must send with a signed PUT
+    fn upload_headers(&self, content_type: &str) -> Vec<(&'static str, String)> {
+        vec![("Content-Type", content_type.to_string())]
+    }
+
+    fn put(&self, key: &str, content: &[u8], content_type: &str) -> Result<(), ServiceError> {
+        let url = self.signed_url(UrlMethod::Put, key, INTERNAL_URL_SECS, &UrlOptions::default())?;
+        let reply = send(UrlMethod::Put, &url, &self.upload_headers(content_type), Some(content.to_vec()))?;
+        expect_success(self.name(), "upload", &reply)
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>, ServiceError> {
+        let url = self.signed_url(UrlMethod::Get, key, INTERNAL_URL_SECS, &UrlOptions::default())?;
+        let reply = send(UrlMethod::Get, &url, &[], None)?;
+        expect_success(self.name(), "download", &reply)?;
+        Ok(reply.body)
+    }
+
+    /// `None` when the object does not exist
+    fn head(&self, key: &str) -> Result<Option<ObjectHead>, ServiceError> {
+        let url = self.signed_url(UrlMethod::Head, key, INTERNAL_URL_SECS, &UrlOptions::default())?;
+        let reply = send(UrlMethod::Head, &url, &[], None)?;
+        if reply.status == 404 {
+            return Ok(None);
+        }
+        expect_success(self.name(), "HEAD", &reply)?;
+
+        let size = reply.content_length.ok_or_else(|| {
+            ServiceError::Backend(format!("{} HEAD response missing Content-Length", self.name()))
+        })?;
+        Ok(Some(ObjectHead { size, content_type: reply.content_type }))
+    }
+
+    /// Deleting a missing object succeeds
+    fn delete(&self, key: &str) -> Result<(), ServiceError> {
+        let url = self.signed_url(UrlMethod::Delete, key, INTERNAL_URL_SECS, &UrlOptions::default())?;
+        let reply = send(UrlMethod::Delete, &url, &[], None)?;
+        if reply.status == 404 {
+            return Ok(());
+        }
+        expect_success(self.name(), "delete", &reply)
+    }
+}
+
+/// The backend selected by configuration
+pub fn configured() -> Result<Box<dyn StorageBackend>, ServiceError> {
+    let selected = variables::get("storage_backend")
+        .map(|v| v.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    match selected.as_str() {
+        "" | "s3" | "minio" => Ok(Box::new(s3::S3Backend::from_variables()?)),
+        "gcs" => Ok(Box::new(gcs::from_variables()?)),
+        "azure" => Ok(Box::new(azure::AzureBackend::from_variables()?)),
+        other => Err(ServiceError::Internal(format!(
+            "Unknown storage_backend '{}'; expected s3, gcs or azure", other
+        ))),
+    }
+}
+
+//=============================================================================
+// HTTP
+//=============================================================================
+
+pub(crate) struct Reply {
+    pub status: u16,
+    pub body: Vec<u8>,
+    pub content_length: Option<i64>,
+    pub content_type: Option<String>,
+}
+
+pub(crate) fn send(
+    method: UrlMethod,
+    url: &str,
+    headers: &[(&str, String)],
+    body: Option<Vec<u8>>,
+) -> Result<Reply, ServiceError> {
+    let mut builder = outbound_http::Request::builder();
+    builder.method(method.outbound()).uri(url);
+    for (name, value) in headers {
+        builder.header(*name, value.as_str());
+    }
+    let request = match body {
+        Some(body) => builder.body(body).build(),
+        None => builder.build(),
+    };
+
+    let response = outbound_http::send(request)
+        .map_err(|e| ServiceError::Backend(format!("{} request failed: {}", method.as_str(), e)))?;
+
+    let header = |name: &str| response.headers().get(name).and_then(|h| h.to_str().ok()).map(|v| v.to_string());
+    Ok(Reply {
+        status: response.status().as_u16(),
+        content_length: header("content-length").and_then(|v| v.parse().ok()),
+        content_type: header("content-type"),
+        body: response.body().to_vec(),
+    })
+}
+
+pub(crate) fn expect_success(backend: &str, operation: &str, reply: &Reply) -> Result<(), ServiceError> {
+    if (200..300).contains(&reply.status) {
+        return Ok(());
+    }
+    let detail = String::from_utf8_lossy(&reply.body);
+    Err(ServiceError::Backend(format!(
+        "{} {} failed with status {}{}",
+        backend,
+        operation,
+        reply.status,
+        if detail.trim().is_empty() { String::new() } else { format!(": {}", detail.chars().take(200).collect::<String>()) }
+    )))
+}
+
+//=============================================================================
+// Encoding
+//=============================================================================
+
+/// RFC 3986 encoding: everything but unreserved characters is percent-encoded
+pub(crate) fn uri_encode(s: &str) -> String {
+    s.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+/// Object key as a URL path, keeping the `/` separators
+pub(crate) fn encode_key(key: &str) -> String {
+    key.split('/').map(uri_encode).collect::<Vec<_>>().join("/")
+}
@@ -0,0 +1,239 @@
+//! Book asset links
+//!
+//! A file can be linked to one of its owner's books, and optionally to a
+//! chapter of that book, so a book's covers, manuscripts and media can be
+//! listed with `GET /books/:id/files`. Links are plain ids rather than
+//! foreign keys: the content service deletes books without telling storage,
+//! and a link to a book that no longer exists is exactly what marks a file
+//! as orphaned.
+//!
+//! `POST /files/orphans` finds those files. By default it only lists them;
+//! with `purge` it deletes each object (and its transcoded variants) and the
+//! record. Links to a deleted chapter of a book that still exists are not
+//! orphans; a purge clears the chapter and keeps the file with its book.
+
+use crate::backend::StorageBackend;
+use crate::collections::ensure_book_owned;
+use crate::error::ServiceError;
+use crate::grants::ensure_file_owned;
+use crate::transcode;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+const BOOK_FILES_LIMIT: i64 = 500;
+const DEFAULT_ORPHAN_LIMIT: i64 = 100;
+/// Each purged file is a backend call or more, so a run stays small
+const MAX_ORPHAN_LIMIT: i64 = 1000;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct LinkRequest {
+    pub book_id: Uuid,
+    /// Must be a chapter of `book_id`
+    pub chapter_id: Option<Uuid>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct OrphanRequest {
+    /// Delete the orphans instead of only listing them
+    #[serde(default)]
+    pub purge: bool,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BookFile {
+    pub id: Uuid,
+    pub filename: String,
+    pub content_type: String,
+    pub size: i64,
+    pub file_type: String,
+    pub chapter_id: Option<Uuid>,
+    pub collection_id: Option<Uuid>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrphanFile {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub filename: String,
+    pub size: i64,
+    pub book_id: Uuid,
+    pub created_at: String,
+    #[serde(skip)]
+    s3_key: String,
+}
+
+//=============================================================================
+// Linking
+//=============================================================================
+
+/// PUT /files/:id/link - Link a file to a book and optionally a chapter
+pub fn link(conn: &Connection, user_id: &Uuid, file_id: &Uuid, body: LinkRequest) -> Result<Response, ServiceError> {
+    ensure_file_owned(conn, user_id, file_id)?;
+    ensure_book_owned(conn, user_id, &body.book_id)?;
+
+    if let Some(chapter_id) = body.chapter_id {
+        let rows = conn.query(
+            "SELECT 1 FROM content.chapters WHERE id = $1 AND book_id = $2",
+            &[
+                ParameterValue::Str(chapter_id.to_string()),
+                ParameterValue::Str(body.book_id.to_string()),
+            ],
+        ).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+        if rows.rows.is_empty() {
+            return Err(ServiceError::NotFound("Chapter not found in this book".into()));
+        }
+    }
+
+    let update = "UPDATE storage.files SET book_id = $1, chapter_id = $2 WHERE id = $3 AND user_id = $4";
+    conn.execute(update, &[
+        ParameterValue::Str(body.book_id.to_string()),
+        body.chapter_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(file_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    crate::json_response(200, serde_json::json!({
+        "id": file_id,
+        "book_id": body.book_id,
+        "chapter_id": body.chapter_id
+    }))
+}
+
+/// DELETE /files/:id/link - Detach a file from its book and chapter
+pub fn unlink(conn: &Connection, user_id: &Uuid, file_id: &Uuid) -> Result<Response, ServiceError> {
+    let update = "UPDATE storage.files SET book_id = NULL, chapter_id = NULL WHERE id = $1 AND user_id = $2";
+    let updated = conn.execute(update, &[
+        ParameterValue::Str(file_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    if updated == 0 {
+        return Err(ServiceError::NotFound("File not found".into()));
+    }
+    crate::json_response(200, serde_json::json!({"id": file_id, "book_id": null, "chapter_id": null}))
+}
+
+/// GET /books/:id/files - The author's files linked to a book, optionally one chapter's
+pub fn list_for_book(conn: &Connection, user_id: &Uuid, book_id: &Uuid, chapter_id: Option<&Uuid>) -> Result<Response, ServiceError> {
+    ensure_book_owned(conn, user_id, book_id)?;
+
+    let query = "SELECT id, filename, content_type, size, file_type, chapter_id, collection_id, created_at
+                 FROM storage.files
+                 WHERE book_id = $1 AND user_id = $2 AND ($3::uuid IS NULL OR chapter_id = $3::uuid)
+                 ORDER BY file_type, created_at DESC
+                 LIMIT $4";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        chapter_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Int64(BOOK_FILES_LIMIT),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let files: Vec<BookFile> = rows.rows.iter().map(|row| BookFile {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        filename: String::decode(&row[1]).unwrap_or_default(),
+        content_type: String::decode(&row[2]).unwrap_or_default(),
+        size: i64::decode(&row[3]).unwrap_or(0),
+        file_type: String::decode(&row[4]).unwrap_or_default(),
+        chapter_id: String::decode(&row[5]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+        collection_id: String::decode(&row[6]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+        created_at: String::decode(&row[7]).unwrap_or_default(),
+    }).collect();
+    let total_size: i64 = files.iter().map(|f| f.size).sum();
+
+    crate::json_response(200, serde_json::json!({
+        "book_id": book_id,
+        "chapter_id": chapter_id,
+        "files": files,
+        "total": files.len(),
+        "total_size": total_size
+    }))
+}
+
+//=============================================================================
+// Orphan Cleanup (internal)
+//=============================================================================
+
+/// POST /files/orphans - List, or purge, files linked to books that no longer exist
+pub fn orphans(conn: &Connection, storage: &dyn StorageBackend, body: OrphanRequest) -> Result<Response, ServiceError> {
+    let limit = body.limit.unwrap_or(DEFAULT_ORPHAN_LIMIT).clamp(1, MAX_ORPHAN_LIMIT);
+
+    let query = "SELECT f.id, f.user_id, f.filename, f.size, f.book_id, f.created_at, f.s3_key
+                 FROM storage.files f
+                 WHERE f.book_id IS NOT NULL
+                   AND NOT EXISTS (SELECT 1 FROM content.books b WHERE b.id = f.book_id)
+                   AND NOT EXISTS (SELECT 1 FROM storage.file_derivatives d WHERE d.derivative_file_id = f.id)
+                 ORDER BY f.created_at ASC
+                 LIMIT $1";
+    let rows = conn.query(query, &[ParameterValue::Int64(limit)])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let orphans: Vec<OrphanFile> = rows.rows.iter().map(|row| OrphanFile {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        user_id: Uuid::parse_str(&String::decode(&row[1]).unwrap_or_default()).unwrap_or_default(),
+        filename: String::decode(&row[2]).unwrap_or_default(),
+        size: i64::decode(&row[3]).unwrap_or(0),
+        book_id: Uuid::parse_str(&String::decode(&row[4]).unwrap_or_default()).unwrap_or_default(),
+        created_at: String::decode(&row[5]).unwrap_or_default(),
+        s3_key: String::decode(&row[6]).unwrap_or_default(),
+    }).collect();
+    let orphan_bytes: i64 = orphans.iter().map(|f| f.size).sum();
+
+    if !body.purge {
+        return crate::json_response(200, serde_json::json!({
+            "purge": false,
+            "orphans": orphans,
+            "found": orphans.len(),
+            "bytes": orphan_bytes
+        }));
+    }
+
+    let mut purged = 0;
+    let mut errors = Vec::new();
+    for orphan in &orphans {
+        // One unreachable object should not stop the rest of the run
+        match purge_file(conn, storage, orphan) {
+            Ok(()) => purged += 1,
+            Err(e) => errors.push(serde_json::json!({"id": orphan.id, "error": e.to_string()})),
+        }
+    }
+
+    let clear_chapters = "UPDATE storage.files f SET chapter_id = NULL
+                          WHERE f.chapter_id IS NOT NULL
+                            AND EXISTS (SELECT 1 FROM content.books b WHERE b.id = f.book_id)
+                            AND NOT EXISTS (SELECT 1 FROM content.chapters c WHERE c.id = f.chapter_id)";
+    let chapter_links_cleared = conn.execute(clear_chapters, &[])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    crate::json_response(200, serde_json::json!({
+        "purge": true,
+        "orphans": orphans,
+        "found": orphans.len(),
+        "bytes": orphan_bytes,
+        "purged": purged,
+        "errors": errors,
+        "chapter_links_cleared": chapter_links_cleared
+    }))
+}
+
+/// Delete an orphan's object, its transcoded variants and its records
+fn purge_file(conn: &Connection, storage: &dyn StorageBackend, orphan: &OrphanFile) -> Result<(), ServiceError> {
+    for (derivative_id, derivative_key) in transcode::derivative_keys(conn, &orphan.id)? {
+        storage.delete(&derivative_key)?;
+        conn.execute("DELETE FROM storage.files WHERE id = $1", &[ParameterValue::Str(derivative_id.to_string())])
+            .map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    }
+
+    storage.delete(&orphan.s3_key)?;
+    conn.execute("DELETE FROM storage.files WHERE id = $1", &[ParameterValue::Str(orphan.id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    Ok(())
+}
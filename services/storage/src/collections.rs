@@ -0,0 +1,443 @@
+//! Collections
+//!
+//! Files can be organized into nested collections (folders). Each collection
+//! stores only its parent link; paths like `Books/The Long Road/Covers` are
+//! built with recursive queries, so removing a level only touches its direct
+//! children. A collection may be linked to a book so all of that book's
+//! assets can be found with `GET /collections?book_id=`.
+//!
+//! Deleting a collection either moves its files and subcollections up to its
+//! parent (`mode=reparent`, the default) or removes the whole subtree along
+//! with every file in it (`mode=cascade`).
+
+use crate::error::ServiceError;
+use crate::models::FileSummary;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+/// Nesting limit, counted from the root
+const MAX_DEPTH: usize = 16;
+
+const MAX_NAME_LENGTH: usize = 255;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCollectionRequest {
+    /// Single collection name, created under `parent_id`
+    pub name: Option<String>,
+    /// Slash-separated path under `parent_id`; missing levels are created
+    pub path: Option<String>,
+    pub parent_id: Option<Uuid>,
+    /// Linked to the collection being created, not to intermediate levels
+    pub book_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoveFileRequest {
+    /// Target collection; `null` moves the file back to the root
+    pub collection_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Collection {
+    pub id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub name: String,
+    pub path: String,
+    pub book_id: Option<Uuid>,
+    pub file_count: i64,
+    pub total_size: i64,
+    pub created_at: String,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /collections - Create a collection, or a nested path of them
+pub fn create(conn: &Connection, user_id: &Uuid, body: CreateCollectionRequest) -> Result<Response, ServiceError> {
+    let segments: Vec<String> = match (body.name.as_deref(), body.path.as_deref()) {
+        (Some(name), None) => vec![name.trim().to_string()],
+        (None, Some(path)) => path.split('/')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect(),
+        _ => return Err(ServiceError::BadRequest("Provide exactly one of name or path".into())),
+    };
+    if segments.is_empty() {
+        return Err(ServiceError::BadRequest("Collection name is required".into()));
+    }
+    for segment in &segments {
+        validate_name(segment)?;
+    }
+
+    let base_depth = match body.parent_id {
+        Some(parent_id) => path_of(conn, user_id, &parent_id)?
+            .ok_or_else(|| ServiceError::NotFound("Parent collection not found".into()))?
+            .split('/')
+            .count(),
+        None => 0,
+    };
+    if base_depth + segments.len() > MAX_DEPTH {
+        return Err(ServiceError::BadRequest(format!("Collections can be nested at most {} levels deep", MAX_DEPTH)));
+    }
+
+    if let Some(book_id) = body.book_id {
+        ensure_book_owned(conn, user_id, &book_id)?;
+    }
+
+    // Walk down the path, creating intermediate levels as needed
+    let (leaf, intermediate) = segments.split_last().expect("segments is non-empty");
+    let mut parent_id = body.parent_id;
+    for name in intermediate {
+        let id = match find_child(conn, user_id, parent_id.as_ref(), name)? {
+            Some(id) => id,
+            None => {
+                insert_child(conn, user_id, parent_id.as_ref(), name, None)?;
+                // A concurrent request may have created it first
+                find_child(conn, user_id, parent_id.as_ref(), name)?
+                    .ok_or_else(|| ServiceError::Internal("Collection was not created".into()))?
+            }
+        };
+        parent_id = Some(id);
+    }
+
+    let id = insert_child(conn, user_id, parent_id.as_ref(), leaf, body.book_id.as_ref())?
+        .ok_or_else(|| ServiceError::Conflict(format!("A collection named '{}' already exists here", leaf)))?;
+
+    let collection = get(conn, user_id, &id)?
+        .ok_or_else(|| ServiceError::Internal("Collection was not created".into()))?;
+    crate::json_response(201, collection)
+}
+
+/// GET /collections - All of the user's collections in path order,
+/// optionally only those linked to one book
+pub fn list(conn: &Connection, user_id: &Uuid, book_id: Option<&Uuid>) -> Result<Response, ServiceError> {
+    let query = "WITH RECURSIVE tree AS (
+                     SELECT id, name::text AS path FROM storage.collections
+                     WHERE user_id = $1 AND parent_id IS NULL
+                     UNION ALL
+                     SELECT c.id, t.path || '/' || c.name
+                     FROM storage.collections c JOIN tree t ON c.parent_id = t.id
+                 )
+                 SELECT c.id, c.parent_id, c.name, t.path, c.book_id, c.created_at,
+                        COUNT(f.id), COALESCE(SUM(f.size), 0)::bigint
+                 FROM tree t
+                 JOIN storage.collections c ON c.id = t.id
+                 LEFT JOIN storage.files f ON f.collection_id = c.id
+                 WHERE $2::uuid IS NULL OR c.book_id = $2::uuid
+                 GROUP BY c.id, t.path
+                 ORDER BY lower(t.path)";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(user_id.to_string()),
+        book_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let collections: Vec<Collection> = rows.rows.iter().map(|row| Collection {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        parent_id: String::decode(&row[1]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+        name: String::decode(&row[2]).unwrap_or_default(),
+        path: String::decode(&row[3]).unwrap_or_default(),
+        book_id: String::decode(&row[4]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+        created_at: String::decode(&row[5]).unwrap_or_default(),
+        file_count: i64::decode(&row[6]).unwrap_or(0),
+        total_size: i64::decode(&row[7]).unwrap_or(0),
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "collections": collections,
+        "total": collections.len()
+    }))
+}
+
+/// GET /collections/:id/files - Files directly in the collection, or in its
+/// whole subtree when `recursive` is set
+pub fn list_files(conn: &Connection, user_id: &Uuid, collection_id: &Uuid, recursive: bool) -> Result<Response, ServiceError> {
+    let collection = get(conn, user_id, collection_id)?
+        .ok_or_else(|| ServiceError::NotFound("Collection not found".into()))?;
+
+    let query = "WITH RECURSIVE subtree AS (
+                     SELECT id FROM storage.collections WHERE id = $1 AND user_id = $2
+                     UNION ALL
+                     SELECT c.id FROM storage.collections c JOIN subtree s ON c.parent_id = s.id
+                     WHERE $3
+                 )
+                 SELECT f.id, f.filename, f.content_type, f.size, f.file_type, f.collection_id, f.created_at
+                 FROM storage.files f
+                 WHERE f.user_id = $2 AND f.collection_id IN (SELECT id FROM subtree)
+                 ORDER BY f.created_at DESC LIMIT 500";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(collection_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Boolean(recursive),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let files: Vec<FileSummary> = rows.rows.iter().map(|row| FileSummary {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        filename: String::decode(&row[1]).unwrap_or_default(),
+        content_type: String::decode(&row[2]).unwrap_or_default(),
+        size: i64::decode(&row[3]).unwrap_or(0),
+        file_type: String::decode(&row[4]).unwrap_or_default(),
+        collection_id: String::decode(&row[5]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+        created_at: String::decode(&row[6]).unwrap_or_default(),
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "collection": collection,
+        "recursive": recursive,
+        "files": files,
+        "total": files.len()
+    }))
+}
+
+/// DELETE /collections/:id - Remove a collection; `reparent` keeps its
+/// contents by moving them up a level, `cascade` deletes them
+pub fn delete(conn: &Connection, user_id: &Uuid, collection_id: &Uuid, mode: &str) -> Result<Response, ServiceError> {
+    let collection = get(conn, user_id, collection_id)?
+        .ok_or_else(|| ServiceError::NotFound("Collection not found".into()))?;
+
+    match mode {
+        "reparent" => reparent_and_delete(conn, user_id, &collection),
+        "cascade" => cascade_delete(conn, user_id, &collection),
+        _ => Err(ServiceError::BadRequest("mode must be 'reparent' or 'cascade'".into())),
+    }
+}
+
+/// PUT /files/:id/move - Move a file into a collection, or back to the root
+pub fn move_file(conn: &Connection, user_id: &Uuid, file_id: &Uuid, body: MoveFileRequest) -> Result<Response, ServiceError> {
+    let path = match body.collection_id {
+        Some(ref collection_id) => Some(ensure_owned(conn, user_id, collection_id)?),
+        None => None,
+    };
+
+    let update = "UPDATE storage.files SET collection_id = $3 WHERE id = $1 AND user_id = $2";
+    let updated = conn.execute(update, &[
+        ParameterValue::Str(file_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        body.collection_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    if updated == 0 {
+        return Err(ServiceError::NotFound("File not found".into()));
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "id": file_id,
+        "collection_id": body.collection_id,
+        "collection_path": path
+    }))
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+/// Path of a collection the user owns, for validating upload and move targets
+pub fn ensure_owned(conn: &Connection, user_id: &Uuid, collection_id: &Uuid) -> Result<String, ServiceError> {
+    path_of(conn, user_id, collection_id)?
+        .ok_or_else(|| ServiceError::NotFound("Collection not found".into()))
+}
+
+fn get(conn: &Connection, user_id: &Uuid, collection_id: &Uuid) -> Result<Option<Collection>, ServiceError> {
+    let path = match path_of(conn, user_id, collection_id)? {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    let query = "SELECT c.parent_id, c.name, c.book_id, c.created_at, COUNT(f.id), COALESCE(SUM(f.size), 0)::bigint
+                 FROM storage.collections c
+                 LEFT JOIN storage.files f ON f.collection_id = c.id
+                 WHERE c.id = $1
+                 GROUP BY c.id";
+    let rows = conn.query(query, &[ParameterValue::Str(collection_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first().map(|row| Collection {
+        id: *collection_id,
+        parent_id: String::decode(&row[0]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+        name: String::decode(&row[1]).unwrap_or_default(),
+        path,
+        book_id: String::decode(&row[2]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+        created_at: String::decode(&row[3]).unwrap_or_default(),
+        file_count: i64::decode(&row[4]).unwrap_or(0),
+        total_size: i64::decode(&row[5]).unwrap_or(0),
+    }))
+}
+
+/// Slash-joined names from the root down, or `None` if the collection is
+/// missing or belongs to someone else
+fn path_of(conn: &Connection, user_id: &Uuid, collection_id: &Uuid) -> Result<Option<String>, ServiceError> {
+    let query = "WITH RECURSIVE ancestors AS (
+                     SELECT id, parent_id, name, 0 AS depth FROM storage.collections
+                     WHERE id = $1 AND user_id = $2
+                     UNION ALL
+                     SELECT c.id, c.parent_id, c.name, a.depth + 1
+                     FROM storage.collections c JOIN ancestors a ON c.id = a.parent_id
+                 )
+                 SELECT string_agg(name, '/' ORDER BY depth DESC) FROM ancestors";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(collection_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first().and_then(|row| String::decode(&row[0]).ok()))
+}
+
+fn find_child(conn: &Connection, user_id: &Uuid, parent_id: Option<&Uuid>, name: &str) -> Result<Option<Uuid>, ServiceError> {
+    let query = "SELECT id FROM storage.collections
+                 WHERE user_id = $1 AND parent_id IS NOT DISTINCT FROM $2::uuid AND lower(name) = lower($3)";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(user_id.to_string()),
+        parent_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(name.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first().and_then(|row| Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).ok()))
+}
+
+/// Insert a collection, returning `None` if the name is already taken under
+/// the parent
+fn insert_child(
+    conn: &Connection,
+    user_id: &Uuid,
+    parent_id: Option<&Uuid>,
+    name: &str,
+    book_id: Option<&Uuid>,
+) -> Result<Option<Uuid>, ServiceError> {
+    let id = Uuid::new_v4();
+    let insert = "INSERT INTO storage.collections (id, user_id, parent_id, name, book_id)
+                  VALUES ($1, $2, $3, $4, $5)
+                  ON CONFLICT DO NOTHING";
+    let inserted = conn.execute(insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        parent_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(name.to_string()),
+        book_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    Ok(if inserted > 0 { Some(id) } else { None })
+}
+
+pub fn ensure_book_owned(conn: &Connection, user_id: &Uuid, book_id: &Uuid) -> Result<(), ServiceError> {
+    let query = "SELECT 1 FROM content.books WHERE id = $1 AND author_id = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(book_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    if rows.rows.is_empty() {
+        return Err(ServiceError::NotFound("Book not found".into()));
+    }
+    Ok(())
+}
+
+fn validate_name(name: &str) -> Result<(), ServiceError> {
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(ServiceError::BadRequest(format!("Invalid collection name '{}'", name)));
+    }
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(ServiceError::BadRequest(format!("Collection names are limited to {} characters", MAX_NAME_LENGTH)));
+    }
+    if name.chars().any(|c| c.is_control() || c == '\\') {
+        return Err(ServiceError::BadRequest("Collection names cannot contain control characters or backslashes".into()));
+    }
+    Ok(())
+}
+
+/// Move files and child collections into the parent, then drop the collection
+fn reparent_and_delete(conn: &Connection, user_id: &Uuid, collection: &Collection) -> Result<Response, ServiceError> {
+    let parent = || collection.parent_id
+        .map(|id| ParameterValue::Str(id.to_string()))
+        .unwrap_or(ParameterValue::DbNull);
+
+    // Children would clash with same-named siblings once moved up
+    let clash_query = "SELECT c.name FROM storage.collections c
+                       WHERE c.parent_id = $1 AND EXISTS (
+                           SELECT 1 FROM storage.collections s
+                           WHERE s.user_id = $2 AND s.id <> $1
+                             AND s.parent_id IS NOT DISTINCT FROM $3::uuid
+                             AND lower(s.name) = lower(c.name)
+                       )";
+    let clashes = conn.query(clash_query, &[
+        ParameterValue::Str(collection.id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        parent(),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if !clashes.rows.is_empty() {
+        let names: Vec<String> = clashes.rows.iter().map(|row| String::decode(&row[0]).unwrap_or_default()).collect();
+        return Err(ServiceError::Conflict(format!(
+            "Cannot move subcollections up: the parent already contains {}", names.join(", ")
+        )));
+    }
+
+    let files_moved = conn.execute(
+        "UPDATE storage.files SET collection_id = $2 WHERE collection_id = $1",
+        &[ParameterValue::Str(collection.id.to_string()), parent()],
+    ).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let collections_moved = conn.execute(
+        "UPDATE storage.collections SET parent_id = $2, updated_at = NOW() WHERE parent_id = $1",
+        &[ParameterValue::Str(collection.id.to_string()), parent()],
+    ).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    conn.execute(
+        "DELETE FROM storage.collections WHERE id = $1 AND user_id = $2",
+        &[ParameterValue::Str(collection.id.to_string()), ParameterValue::Str(user_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    crate::json_response(200, serde_json::json!({
+        "id": collection.id,
+        "mode": "reparent",
+        "moved_to": collection.parent_id,
+        "files_moved": files_moved,
+        "collections_moved": collections_moved
+    }))
+}
+
+/// Delete every file in the subtree from S3 and the database, then the
+/// collection itself; descendants follow through the parent foreign key
+fn cascade_delete(conn: &Connection, user_id: &Uuid, collection: &Collection) -> Result<Response, ServiceError> {
+    let query = "WITH RECURSIVE subtree AS (
+                     SELECT id FROM storage.collections WHERE id = $1 AND user_id = $2
+                     UNION ALL
+                     SELECT c.id FROM storage.collections c JOIN subtree s ON c.parent_id = s.id
+                 )
+                 SELECT f.id, f.s3_key FROM storage.files f
+                 WHERE f.user_id = $2 AND f.collection_id IN (SELECT id FROM subtree)";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(collection.id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let storage = if rows.rows.is_empty() { None } else { Some(crate::backend::configured()?) };
+    let mut files_deleted = 0u64;
+    for row in &rows.rows {
+        let file_id = String::decode(&row[0]).unwrap_or_default();
+        let s3_key = String::decode(&row[1]).unwrap_or_default();
+        if let Some(ref storage) = storage {
+            storage.delete(&s3_key)?;
+        }
+        files_deleted += conn.execute(
+            "DELETE FROM storage.files WHERE id = $1 AND user_id = $2",
+            &[ParameterValue::Str(file_id), ParameterValue::Str(user_id.to_string())],
+        ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    }
+
+    conn.execute(
+        "DELETE FROM storage.collections WHERE id = $1 AND user_id = $2",
+        &[ParameterValue::Str(collection.id.to_string()), ParameterValue::Str(user_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    crate::json_response(200, serde_json::json!({
+        "id": collection.id,
+        "mode": "cascade",
+        "files_deleted": files_deleted
+    }))
+}
@@ -0,0 +1,110 @@
+//! Client-side encryption envelopes
+//!
+//! Sensitive manuscripts can be uploaded already encrypted. The client
+//! encrypts the file with a fresh data key, wraps that key with a key of its
+//! own, and sends the ciphertext together with an envelope: the algorithm,
+//! the wrapped data key, the IV and the plaintext's content type. The service
+//! stores the envelope in `storage.files.encryption` and hands it back with
+//! every download URL; it never holds an unwrapped key.
+//!
+//! Because the bytes are opaque, encrypted uploads skip type sniffing, image
+//! limits and metadata stripping; only the declared plaintext type is checked
+//! against the file type. Ciphertext is stored and served as
+//! `application/octet-stream`. Copies keep the envelope, since the bytes and
+//! the wrapped key are unchanged. Operations that need the plaintext
+//! (sanitizing, transcoding) or would expose the object publicly are refused.
+
+use crate::error::ServiceError;
+use crate::mime;
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use serde::{Deserialize, Serialize};
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+pub const ALGORITHMS: [&str; 3] = ["AES-256-GCM", "AES-256-CBC", "XChaCha20-Poly1305"];
+
+/// Bounds on the decoded wrapped key; covers AES key wrap through RSA-4096 OAEP
+const MIN_WRAPPED_KEY_BYTES: usize = 24;
+const MAX_WRAPPED_KEY_BYTES: usize = 1024;
+
+/// Bounds on the decoded IV or nonce
+const MIN_IV_BYTES: usize = 12;
+const MAX_IV_BYTES: usize = 32;
+
+const MAX_KEY_ID_CHARS: usize = 255;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    pub alg: String,
+    /// Base64 data key, wrapped by a key the server never sees
+    pub wrapped_key: String,
+    /// Base64 IV or nonce
+    pub iv: String,
+    /// Identifies the wrapping key to the client
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+    /// Type of the plaintext; filled in from the upload's content type
+    #[serde(default)]
+    pub content_type: String,
+}
+
+/// Check an envelope and record the plaintext type it was uploaded with
+pub fn validate(mut envelope: Envelope, file_type: &str, content_type: &str) -> Result<Envelope, ServiceError> {
+    if !ALGORITHMS.contains(&envelope.alg.as_str()) {
+        return Err(ServiceError::BadRequest(format!("encryption.alg must be one of: {}", ALGORITHMS.join(", "))));
+    }
+    let wrapped_key = BASE64.decode(envelope.wrapped_key.trim())
+        .map_err(|_| ServiceError::BadRequest("encryption.wrapped_key must be base64".into()))?;
+    if !(MIN_WRAPPED_KEY_BYTES..=MAX_WRAPPED_KEY_BYTES).contains(&wrapped_key.len()) {
+        return Err(ServiceError::BadRequest(format!(
+            "encryption.wrapped_key must decode to {}-{} bytes", MIN_WRAPPED_KEY_BYTES, MAX_WRAPPED_KEY_BYTES
+        )));
+    }
+    let iv = BASE64.decode(envelope.iv.trim())
+        .map_err(|_| ServiceError::BadRequest("encryption.iv must be base64".into()))?;
+    if !(MIN_IV_BYTES..=MAX_IV_BYTES).contains(&iv.len()) {
+        return Err(ServiceError::BadRequest(format!("encryption.iv must decode to {}-{} bytes", MIN_IV_BYTES, MAX_IV_BYTES)));
+    }
+    if envelope.key_id.as_ref().is_some_and(|id| id.chars().count() > MAX_KEY_ID_CHARS) {
+        return Err(ServiceError::BadRequest(format!("encryption.key_id must be at most {} characters", MAX_KEY_ID_CHARS)));
+    }
+    mime::check_declared(file_type, content_type)?;
+
+    envelope.wrapped_key = envelope.wrapped_key.trim().to_string();
+    envelope.iv = envelope.iv.trim().to_string();
+    envelope.content_type = content_type.to_string();
+    Ok(envelope)
+}
+
+/// Envelope from the JSON of `storage.files.encryption`
+pub fn from_column(value: Option<String>) -> Option<Envelope> {
+    value.and_then(|json| serde_json::from_str(&json).ok())
+}
+
+/// Column value for an optional envelope
+pub fn to_param(envelope: Option<&Envelope>) -> ParameterValue {
+    envelope
+        .and_then(|e| serde_json::to_string(e).ok())
+        .map(ParameterValue::Str)
+        .unwrap_or(ParameterValue::DbNull)
+}
+
+/// Refuse `operation` on an encrypted file
+pub fn ensure_plaintext(conn: &Connection, file_id: &Uuid, operation: &str) -> Result<(), ServiceError> {
+    let query = "SELECT encryption IS NOT NULL FROM storage.files WHERE id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(file_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    if rows.rows.first().is_some_and(|row| bool::decode(&row[0]).unwrap_or(false)) {
+        return Err(ServiceError::Conflict(format!("Encrypted files cannot be {}", operation)));
+    }
+    Ok(())
+}
+
+/// How ciphertext is stored and served, in place of a sniffed type
+pub fn stored_type() -> mime::VerifiedType {
+    mime::VerifiedType {
+        content_type: mime::OCTET_STREAM.to_string(),
+        correction: None,
+    }
+}
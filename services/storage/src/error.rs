@@ -17,14 +17,29 @@ pub enum ServiceError {
     #[error("Not found: {0}")]
     NotFound(String),
 
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Gone: {0}")]
+    Gone(String),
+
+    #[error("Payment required: {0}")]
+    PaymentRequired(String),
+
     #[error("Payload too large: {0}")]
     PayloadTooLarge(String),
 
+    #[error("Unsupported media type: {0}")]
+    UnsupportedMediaType(String),
+
+    #[error("Image rejected: {0}")]
+    ImageRejected(String),
+
     #[error("Internal error: {0}")]
     Internal(String),
 
-    #[error("S3 error: {0}")]
-    S3Error(String),
+    #[error("Storage backend error: {0}")]
+    Backend(String),
 }
 
 #[derive(Serialize)]
@@ -40,9 +55,14 @@ impl ServiceError {
             ServiceError::Unauthorized(_) => 401,
             ServiceError::Forbidden(_) => 403,
             ServiceError::NotFound(_) => 404,
+            ServiceError::Conflict(_) => 409,
+            ServiceError::Gone(_) => 410,
+            ServiceError::PaymentRequired(_) => 402,
             ServiceError::PayloadTooLarge(_) => 413,
+            ServiceError::UnsupportedMediaType(_) => 415,
+            ServiceError::ImageRejected(_) => 422,
             ServiceError::Internal(_) => 500,
-            ServiceError::S3Error(_) => 502,
+            ServiceError::Backend(_) => 502,
         }
     }
 
@@ -52,9 +72,14 @@ impl ServiceError {
             ServiceError::Unauthorized(_) => "UNAUTHORIZED",
             ServiceError::Forbidden(_) => "FORBIDDEN",
             ServiceError::NotFound(_) => "NOT_FOUND",
+            ServiceError::Conflict(_) => "CONFLICT",
+            ServiceError::Gone(_) => "GONE",
+            ServiceError::PaymentRequired(_) => "PAYMENT_REQUIRED",
             ServiceError::PayloadTooLarge(_) => "PAYLOAD_TOO_LARGE",
+            ServiceError::UnsupportedMediaType(_) => "UNSUPPORTED_MEDIA_TYPE",
+            ServiceError::ImageRejected(_) => "IMAGE_REJECTED",
             ServiceError::Internal(_) => "INTERNAL_ERROR",
-            ServiceError::S3Error(_) => "S3_ERROR",
+            ServiceError::Backend(_) => "STORAGE_BACKEND_ERROR",
         }
     }
 
@@ -78,3 +103,8 @@ impl ServiceError {
     }
 }
 
+impl From<authorworks_access::AccessError> for ServiceError {
+    fn from(e: authorworks_access::AccessError) -> Self {
+        ServiceError::Forbidden(e.to_string())
+    }
+}
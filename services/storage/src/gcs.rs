@@ -0,0 +1,38 @@
+//! Google Cloud Storage backend
+//!
+//! Uses the XML API with an HMAC key pair (Cloud Storage > Settings >
+//! Interoperability), which signs requests exactly like SigV4 under
+//! `GOOG4-HMAC-SHA256`, so the S3 backend does the work with this dialect.
+
+use crate::error::ServiceError;
+use crate::s3::{Dialect, S3Backend, SigV4};
+use spin_sdk::variables;
+
+const DEFAULT_ENDPOINT: &str = "https://storage.googleapis.com";
+
+pub const GOOG: Dialect = Dialect {
+    backend: "gcs",
+    algorithm: "GOOG4-HMAC-SHA256",
+    key_prefix: "GOOG4",
+    service: "storage",
+    request_type: "goog4_request",
+    param_prefix: "X-Goog",
+    copy_source_header: "x-goog-copy-source",
+};
+
+pub fn from_variables() -> Result<S3Backend, ServiceError> {
+    Ok(S3Backend::new(SigV4 {
+        dialect: &GOOG,
+        endpoint: variables::get("gcs_endpoint")
+            .unwrap_or_else(|_| DEFAULT_ENDPOINT.into()),
+        // Cloud Storage ignores the region, but it is part of the scope
+        region: variables::get("gcs_region")
+            .unwrap_or_else(|_| "auto".into()),
+        bucket: variables::get("gcs_bucket")
+            .map_err(|_| ServiceError::Internal("GCS_BUCKET not configured".into()))?,
+        access_key: variables::get("gcs_hmac_access_id")
+            .map_err(|_| ServiceError::Internal("GCS_HMAC_ACCESS_ID not configured".into()))?,
+        secret_key: variables::get("gcs_hmac_secret")
+            .map_err(|_| ServiceError::Internal("GCS_HMAC_SECRET not configured".into()))?,
+    }))
+}
@@ -0,0 +1,207 @@
+//! File grants
+//!
+//! Files are private to their uploader unless the owner grants read access,
+//! either to one user or to a book. A book grant follows the book's
+//! `content.book_collaborators` as they change: the author and every
+//! collaborator with an active grant can read the file. Grantees may read
+//! metadata and get download URLs; everything else stays owner-only.
+
+use crate::error::ServiceError;
+use serde::Deserialize;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+const SHARED_LIMIT: i64 = 100;
+
+/// Condition on a `storage.files f` row that `$2` may read it
+pub const READABLE_BY_USER: &str = "(f.user_id = $2 OR EXISTS (
+        SELECT 1 FROM storage.file_grants g
+        WHERE g.file_id = f.id AND g.revoked_at IS NULL
+          AND (g.grantee_id = $2
+               OR g.book_id IN (SELECT id FROM content.books WHERE author_id = $2)
+               OR g.book_id IN (SELECT book_id FROM content.book_collaborators
+                                WHERE user_id = $2 AND revoked_at IS NULL))
+    ))";
+
+/// Exactly one of `user_id` and `book_id`
+#[derive(Debug, Deserialize)]
+pub struct GrantRequest {
+    pub user_id: Option<Uuid>,
+    pub book_id: Option<Uuid>,
+}
+
+//=============================================================================
+// Owner Endpoints
+//=============================================================================
+
+/// POST /files/:id/grants - Let a user or a book's collaborators read a file
+pub fn grant(conn: &Connection, owner_id: &Uuid, file_id: &Uuid, body: GrantRequest) -> Result<Response, ServiceError> {
+    ensure_file_owned(conn, owner_id, file_id)?;
+
+    let (column, target) = match (body.user_id, body.book_id) {
+        (Some(user_id), None) => {
+            if user_id == *owner_id {
+                return Err(ServiceError::BadRequest("You already own this file".into()));
+            }
+            let rows = conn.query(
+                "SELECT 1 FROM users.users WHERE id = $1",
+                &[ParameterValue::Str(user_id.to_string())],
+            ).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+            if rows.rows.is_empty() {
+                return Err(ServiceError::NotFound("User not found".into()));
+            }
+            ("grantee_id", user_id)
+        }
+        (None, Some(book_id)) => {
+            crate::collections::ensure_book_owned(conn, owner_id, &book_id)?;
+            ("book_id", book_id)
+        }
+        _ => return Err(ServiceError::BadRequest("Provide exactly one of user_id or book_id".into())),
+    };
+
+    let insert = format!(
+        "INSERT INTO storage.file_grants (id, file_id, {column}, permission, granted_by)
+         VALUES ($1, $2, $3, 'read', $4)
+         ON CONFLICT (file_id, {column}) WHERE revoked_at IS NULL AND {column} IS NOT NULL DO NOTHING",
+        column = column
+    );
+    let created = conn.execute(&insert, &[
+        ParameterValue::Str(Uuid::new_v4().to_string()),
+        ParameterValue::Str(file_id.to_string()),
+        ParameterValue::Str(target.to_string()),
+        ParameterValue::Str(owner_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    // Granting twice returns the live grant
+    let query = format!(
+        "SELECT id, created_at FROM storage.file_grants
+         WHERE file_id = $1 AND {} = $2 AND revoked_at IS NULL",
+        column
+    );
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(file_id.to_string()),
+        ParameterValue::Str(target.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::Internal("Grant was not created".into()))?;
+
+    crate::json_response(if created > 0 { 201 } else { 200 }, serde_json::json!({
+        "id": String::decode(&row[0]).unwrap_or_default(),
+        "file_id": file_id,
+        "user_id": body.user_id,
+        "book_id": body.book_id,
+        "permission": "read",
+        "created_at": String::decode(&row[1]).unwrap_or_default()
+    }))
+}
+
+/// GET /files/:id/grants - Live grants on a file
+pub fn list(conn: &Connection, owner_id: &Uuid, file_id: &Uuid) -> Result<Response, ServiceError> {
+    ensure_file_owned(conn, owner_id, file_id)?;
+
+    let query = "SELECT g.id, g.grantee_id, u.name, g.book_id, b.title, g.permission, g.created_at
+                 FROM storage.file_grants g
+                 LEFT JOIN users.users u ON u.id = g.grantee_id
+                 LEFT JOIN content.books b ON b.id = g.book_id
+                 WHERE g.file_id = $1 AND g.revoked_at IS NULL
+                 ORDER BY g.created_at ASC";
+    let rows = conn.query(query, &[ParameterValue::Str(file_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let grants: Vec<serde_json::Value> = rows.rows.iter().map(|row| serde_json::json!({
+        "id": String::decode(&row[0]).unwrap_or_default(),
+        "user_id": String::decode(&row[1]).ok(),
+        "user_name": String::decode(&row[2]).ok(),
+        "book_id": String::decode(&row[3]).ok(),
+        "book_title": String::decode(&row[4]).ok(),
+        "permission": String::decode(&row[5]).unwrap_or_else(|_| "read".into()),
+        "created_at": String::decode(&row[6]).unwrap_or_default()
+    })).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "file_id": file_id,
+        "grants": grants,
+        "total": grants.len()
+    }))
+}
+
+/// DELETE /files/:id/grants/:grant_id - Revoke a grant
+pub fn revoke(conn: &Connection, owner_id: &Uuid, file_id: &Uuid, grant_id: &Uuid) -> Result<Response, ServiceError> {
+    let update = "UPDATE storage.file_grants g SET revoked_at = NOW()
+                  FROM storage.files f
+                  WHERE g.file_id = f.id AND g.id = $1 AND f.id = $2 AND f.user_id = $3
+                    AND g.revoked_at IS NULL";
+    let revoked = conn.execute(update, &[
+        ParameterValue::Str(grant_id.to_string()),
+        ParameterValue::Str(file_id.to_string()),
+        ParameterValue::Str(owner_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    if revoked == 0 {
+        return Err(ServiceError::NotFound("Grant not found".into()));
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "id": grant_id,
+        "file_id": file_id,
+        "revoked": true
+    }))
+}
+
+//=============================================================================
+// Grantee Endpoints
+//=============================================================================
+
+/// GET /files/shared-with-me - Files other users have granted the caller
+pub fn shared_with_me(conn: &Connection, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT DISTINCT ON (f.id) f.id, f.filename, f.content_type, f.size, f.file_type,
+                        f.user_id, u.name, g.book_id, b.title, g.created_at AS shared_at, f.created_at
+                 FROM storage.file_grants g
+                 JOIN storage.files f ON f.id = g.file_id
+                 LEFT JOIN users.users u ON u.id = f.user_id
+                 LEFT JOIN content.books b ON b.id = g.book_id
+                 WHERE g.revoked_at IS NULL AND f.user_id <> $1
+                   AND (g.grantee_id = $1
+                        OR g.book_id IN (SELECT id FROM content.books WHERE author_id = $1)
+                        OR g.book_id IN (SELECT book_id FROM content.book_collaborators
+                                         WHERE user_id = $1 AND revoked_at IS NULL))
+                 ORDER BY f.id, g.created_at DESC";
+    let wrapped = format!("SELECT * FROM ({}) shared ORDER BY shared_at DESC LIMIT $2", query);
+    let rows = conn.query(&wrapped, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Int64(SHARED_LIMIT),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let files: Vec<serde_json::Value> = rows.rows.iter().map(|row| serde_json::json!({
+        "id": String::decode(&row[0]).unwrap_or_default(),
+        "filename": String::decode(&row[1]).unwrap_or_default(),
+        "content_type": String::decode(&row[2]).unwrap_or_default(),
+        "size": i64::decode(&row[3]).unwrap_or(0),
+        "file_type": String::decode(&row[4]).unwrap_or_default(),
+        "owner_id": String::decode(&row[5]).unwrap_or_default(),
+        "owner_name": String::decode(&row[6]).ok(),
+        "via_book_id": String::decode(&row[7]).ok(),
+        "via_book_title": String::decode(&row[8]).ok(),
+        "shared_at": String::decode(&row[9]).unwrap_or_default(),
+        "created_at": String::decode(&row[10]).unwrap_or_default()
+    })).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "files": files,
+        "total": files.len()
+    }))
+}
+
+pub fn ensure_file_owned(conn: &Connection, owner_id: &Uuid, file_id: &Uuid) -> Result<(), ServiceError> {
+    let query = "SELECT 1 FROM storage.files WHERE id = $1 AND user_id = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(file_id.to_string()),
+        ParameterValue::Str(owner_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    if rows.rows.is_empty() {
+        return Err(ServiceError::NotFound("File not found".into()));
+    }
+    Ok(())
+}
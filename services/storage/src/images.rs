@@ -0,0 +1,349 @@
+//! Image dimension limits
+//!
+//! A small, highly compressed image can decode to gigabytes: a 50,000 x
+//! 50,000 PNG of one colour is a few hundred kilobytes on disk. Image uploads
+//! are checked from their headers alone, before anything decodes them, against
+//! a per-`file_type` policy: maximum width and height, total megapixels, frame
+//! count for animations, and how far the decoded pixels may outgrow the file
+//! (its expansion ratio). Files whose dimensions cannot be read are rejected
+//! too, since a decoder would not fare better.
+//!
+//! Policies are set with `image_policy_<file_type>` variables holding
+//! comma-separated `key=value` pairs, e.g.
+//! `max_width=8000,max_height=8000,max_megapixels=40,max_frames=1,max_ratio=200`.
+//! Keys left out keep their defaults.
+
+use crate::error::ServiceError;
+use serde::Serialize;
+use spin_sdk::variables;
+
+/// Decoded sizes below this are never treated as bombs, however well they
+/// compress; flat-colour artwork legitimately compresses a thousandfold
+const BOMB_FLOOR_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Bytes per decoded pixel assumed for formats decoded to RGBA
+const RGBA: u64 = 4;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImagePolicy {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_megapixels: f64,
+    pub max_frames: u32,
+    /// Decoded bytes allowed per stored byte, once past the bomb floor
+    pub max_ratio: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageInfo {
+    pub format: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub frames: u32,
+    /// Size of all frames once decoded
+    pub decoded_bytes: u64,
+}
+
+//=============================================================================
+// Policies
+//=============================================================================
+
+/// Limits for images uploaded as `file_type`
+pub fn policy_for(file_type: &str) -> ImagePolicy {
+    let mut policy = match file_type {
+        // Covers are printed at most around 3,000 x 4,500; leave room for bleed
+        "cover" => ImagePolicy { max_width: 10_000, max_height: 10_000, max_megapixels: 50.0, max_frames: 1, max_ratio: 250 },
+        _ => ImagePolicy { max_width: 16_384, max_height: 16_384, max_megapixels: 100.0, max_frames: 500, max_ratio: 250 },
+    };
+
+    let setting = variables::get(&format!("image_policy_{}", file_type)).unwrap_or_default();
+    for pair in setting.split(',') {
+        let (key, value) = match pair.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => continue,
+        };
+        match key {
+            "max_width" => policy.max_width = value.parse().unwrap_or(policy.max_width),
+            "max_height" => policy.max_height = value.parse().unwrap_or(policy.max_height),
+            "max_megapixels" => policy.max_megapixels = value.parse().unwrap_or(policy.max_megapixels),
+            "max_frames" => policy.max_frames = value.parse().unwrap_or(policy.max_frames),
+            "max_ratio" => policy.max_ratio = value.parse().unwrap_or(policy.max_ratio),
+            _ => {}
+        }
+    }
+    policy
+}
+
+//=============================================================================
+// Validation
+//=============================================================================
+
+/// Check an upload already verified as `content_type` against its category's
+/// policy. Returns the image's dimensions, or `None` for non-images.
+pub fn validate(file_type: &str, content_type: &str, content: &[u8]) -> Result<Option<ImageInfo>, ServiceError> {
+    if !content_type.starts_with("image/") {
+        return Ok(None);
+    }
+    let info = inspect(content).ok_or_else(|| ServiceError::ImageRejected(format!(
+        "Could not read the dimensions of this {} image; it may be truncated or corrupt", content_type
+    )))?;
+    let policy = policy_for(file_type);
+
+    if info.width == 0 || info.height == 0 {
+        return Err(ServiceError::ImageRejected(format!(
+            "Image declares a size of {}x{} pixels", info.width, info.height
+        )));
+    }
+    if info.width > policy.max_width || info.height > policy.max_height {
+        return Err(ServiceError::ImageRejected(format!(
+            "Image is {}x{} pixels; {} images may be at most {}x{}",
+            info.width, info.height, file_type, policy.max_width, policy.max_height
+        )));
+    }
+    let megapixels = info.width as f64 * info.height as f64 / 1_000_000.0;
+    if megapixels > policy.max_megapixels {
+        return Err(ServiceError::ImageRejected(format!(
+            "Image is {:.1} megapixels; {} images may be at most {}", megapixels, file_type, policy.max_megapixels
+        )));
+    }
+    if info.frames > policy.max_frames {
+        return Err(ServiceError::ImageRejected(format!(
+            "Image has {} frames; {} images may have at most {}", info.frames, file_type, policy.max_frames
+        )));
+    }
+    let ratio = info.decoded_bytes / (content.len() as u64).max(1);
+    if info.decoded_bytes > BOMB_FLOOR_BYTES && ratio > policy.max_ratio as u64 {
+        return Err(ServiceError::ImageRejected(format!(
+            "Image would decode to {} MB from {} bytes ({}x expansion, limit {}x); it looks like a decompression bomb",
+            info.decoded_bytes / (1024 * 1024), content.len(), ratio, policy.max_ratio
+        )));
+    }
+
+    Ok(Some(info))
+}
+
+/// Dimensions and frame count from the image headers; `None` if unreadable
+pub fn inspect(content: &[u8]) -> Option<ImageInfo> {
+    if content.starts_with(b"\x89PNG\r\n\x1a\n") {
+        inspect_png(content)
+    } else if content.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        inspect_jpeg(content)
+    } else if content.starts_with(b"GIF87a") || content.starts_with(b"GIF89a") {
+        inspect_gif(content)
+    } else if content.len() >= 12 && &content[0..4] == b"RIFF" && &content[8..12] == b"WEBP" {
+        inspect_webp(content)
+    } else {
+        None
+    }
+}
+
+fn be_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn be_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn le_u24(bytes: &[u8], at: usize) -> Option<u32> {
+    let b = bytes.get(at..at + 3)?;
+    Some(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16)
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn info(format: &'static str, width: u32, height: u32, frames: u32, bytes_per_pixel: u64) -> ImageInfo {
+    ImageInfo {
+        format,
+        width,
+        height,
+        frames,
+        decoded_bytes: (width as u64 * height as u64)
+            .saturating_mul(bytes_per_pixel)
+            .saturating_mul(frames.max(1) as u64),
+    }
+}
+
+//=============================================================================
+// PNG
+//=============================================================================
+
+/// IHDR must come first; an `acTL` chunk makes it an animated PNG
+fn inspect_png(content: &[u8]) -> Option<ImageInfo> {
+    if content.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    let width = be_u32(content, 16)?;
+    let height = be_u32(content, 20)?;
+    let bit_depth = *content.get(24)? as u64;
+    let channels = match content.get(25)? {
+        0 | 3 => 1,
+        4 => 2,
+        2 => 3,
+        6 => 4,
+        _ => return None,
+    };
+
+    let mut frames = 1;
+    let mut pos = 8;
+    while let Some(length) = be_u32(content, pos) {
+        let kind = content.get(pos + 4..pos + 8)?;
+        if kind == b"acTL" {
+            frames = be_u32(content, pos + 8)?;
+        }
+        if kind == b"IDAT" || kind == b"IEND" {
+            break;
+        }
+        pos = pos.checked_add(12)?.checked_add(length as usize)?;
+    }
+
+    // Round up so sub-byte depths are not counted as free
+    let bytes_per_pixel = (channels * bit_depth).div_ceil(8);
+    Some(info("png", width, height, frames, bytes_per_pixel))
+}
+
+//=============================================================================
+// JPEG
+//=============================================================================
+
+/// The first start-of-frame marker carries the dimensions
+fn inspect_jpeg(content: &[u8]) -> Option<ImageInfo> {
+    let mut pos = 2;
+    while pos + 4 <= content.len() {
+        if content[pos] != 0xFF {
+            return None;
+        }
+        let marker = content[pos + 1];
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            pos += 2;
+            continue;
+        }
+        // Entropy-coded data before any frame header
+        if marker == 0xDA || marker == 0xD9 {
+            return None;
+        }
+
+        let length = be_u16(content, pos + 2)? as usize;
+        let is_sof = matches!(marker, 0xC0..=0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF);
+        if is_sof {
+            let height = be_u16(content, pos + 5)? as u32;
+            let width = be_u16(content, pos + 7)? as u32;
+            let components = *content.get(pos + 9)? as u64;
+            return Some(info("jpeg", width, height, 1, components.max(1)));
+        }
+        if length < 2 {
+            return None;
+        }
+        pos += 2 + length;
+    }
+    None
+}
+
+//=============================================================================
+// GIF
+//=============================================================================
+
+/// Logical screen size, with frames counted by walking the image descriptors
+fn inspect_gif(content: &[u8]) -> Option<ImageInfo> {
+    let width = le_u16(content, 6)? as u32;
+    let height = le_u16(content, 8)? as u32;
+    let flags = *content.get(10)?;
+
+    let mut pos = 13;
+    if flags & 0x80 != 0 {
+        pos += 3usize << ((flags & 0x07) + 1);
+    }
+
+    // A missing trailer only loses frames we could not have counted anyway
+    let mut frames = 0u32;
+    while let Some(&block) = content.get(pos) {
+        let next = match block {
+            // Extension: label, then data sub-blocks
+            0x21 => skip_sub_blocks(content, pos + 2),
+            // Image descriptor, optional local colour table, LZW code size, data
+            0x2C => {
+                frames += 1;
+                let local = *content.get(pos + 9)?;
+                let mut start = pos + 10;
+                if local & 0x80 != 0 {
+                    start += 3usize << ((local & 0x07) + 1);
+                }
+                skip_sub_blocks(content, start + 1)
+            }
+            0x3B => break,
+            _ => return None,
+        };
+        match next {
+            Some(next) => pos = next,
+            None => break,
+        }
+    }
+
+    Some(info("gif", width, height, frames.max(1), RGBA))
+}
+
+fn skip_sub_blocks(content: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let size = *content.get(pos)? as usize;
+        pos += 1;
+        if size == 0 {
+            return Some(pos);
+        }
+        pos += size;
+    }
+}
+
+//=============================================================================
+// WebP
+//=============================================================================
+
+/// Lossy (`VP8 `), lossless (`VP8L`) or extended (`VP8X`) headers; extended
+/// files count their `ANMF` animation frames
+fn inspect_webp(content: &[u8]) -> Option<ImageInfo> {
+    let data = 20;
+    match content.get(12..16)? {
+        b"VP8 " => {
+            if content.get(data + 3..data + 6)? != [0x9D, 0x01, 0x2A] {
+                return None;
+            }
+            let width = (le_u16(content, data + 6)? & 0x3FFF) as u32;
+            let height = (le_u16(content, data + 8)? & 0x3FFF) as u32;
+            Some(info("webp", width, height, 1, RGBA))
+        }
+        b"VP8L" => {
+            if *content.get(data)? != 0x2F {
+                return None;
+            }
+            let bits = le_u32(content, data + 1)?;
+            let width = (bits & 0x3FFF) + 1;
+            let height = ((bits >> 14) & 0x3FFF) + 1;
+            Some(info("webp", width, height, 1, RGBA))
+        }
+        b"VP8X" => {
+            let width = le_u24(content, data + 4)? + 1;
+            let height = le_u24(content, data + 7)? + 1;
+
+            let mut frames = 0u32;
+            let mut pos = 12;
+            while let Some(length) = le_u32(content, pos + 4) {
+                if content.get(pos..pos + 4)? == b"ANMF" {
+                    frames += 1;
+                }
+                // Chunks are padded to an even length
+                pos = pos.checked_add(8)?.checked_add(length as usize + (length as usize & 1))?;
+            }
+            Some(info("webp", width, height, frames.max(1), RGBA))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,195 @@
+//! Integrity checks
+//!
+//! Every file's SHA-256 is recorded when it is stored, but nothing noticed
+//! when an object later rotted or a multipart merge went wrong. A check
+//! HEADs the object, then downloads and rehashes it when the size still
+//! matches, and records the outcome on the file:
+//! - `ok` - size and checksum match
+//! - `size_mismatch` - the stored object has a different size
+//! - `checksum_mismatch` - same size, different bytes
+//! - `missing` - the object is gone from the store
+//!
+//! `POST /files/:id/verify` checks one file for its owner.
+//! `POST /integrity/audit` checks a sample per run, never-checked files
+//! first and then those checked longest ago, so repeated runs cover the
+//! whole store. Each check is also appended to `storage.integrity_checks`.
+//! A backend error is not an integrity result; it fails the check without
+//! recording anything.
+
+use crate::backend::StorageBackend;
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use uuid::Uuid;
+
+const DEFAULT_AUDIT_SAMPLE: i64 = 50;
+/// Each checked file is downloaded in full, so a run stays small
+const MAX_AUDIT_SAMPLE: i64 = 500;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditRequest {
+    /// Files to check this run; defaults to `integrity_audit_sample_size`
+    pub sample_size: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckResult {
+    pub file_id: Uuid,
+    pub status: &'static str,
+    pub expected_checksum: String,
+    pub actual_checksum: Option<String>,
+    pub expected_size: i64,
+    pub actual_size: Option<i64>,
+    pub checked_at: String,
+}
+
+struct StoredFile {
+    id: Uuid,
+    s3_key: String,
+    size: i64,
+    checksum: String,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /files/:id/verify
+pub fn verify_file(
+    conn: &Connection,
+    storage: &dyn StorageBackend,
+    user_id: &Uuid,
+    file_id: &Uuid,
+) -> Result<Response, ServiceError> {
+    let query = "SELECT id, s3_key, size, checksum FROM storage.files WHERE id = $1 AND user_id = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(file_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let file = rows.rows.first()
+        .map(|row| stored_file(row))
+        .ok_or_else(|| ServiceError::NotFound("File not found".into()))?;
+
+    let result = check(conn, storage, &file, "manual")?;
+    crate::json_response(200, result)
+}
+
+/// POST /integrity/audit - Check a sample of files (internal)
+pub fn audit(conn: &Connection, storage: &dyn StorageBackend, body: AuditRequest) -> Result<Response, ServiceError> {
+    let sample_size = body.sample_size
+        .or_else(|| variables::get("integrity_audit_sample_size").ok().and_then(|v| v.parse().ok()))
+        .unwrap_or(DEFAULT_AUDIT_SAMPLE)
+        .clamp(1, MAX_AUDIT_SAMPLE);
+
+    let query = "SELECT id, s3_key, size, checksum FROM storage.files
+                 ORDER BY integrity_checked_at ASC NULLS FIRST, created_at ASC
+                 LIMIT $1";
+    let rows = conn.query(query, &[ParameterValue::Int64(sample_size)])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut checked = 0;
+    let mut errors = 0;
+    let mut failures = Vec::new();
+    for row in &rows.rows {
+        let file = stored_file(row);
+        // One unreachable object should not stop the rest of the sample
+        match check(conn, storage, &file, "audit") {
+            Ok(result) => {
+                checked += 1;
+                if result.status != "ok" {
+                    failures.push(result);
+                }
+            }
+            Err(_) => errors += 1,
+        }
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "sampled": rows.rows.len(),
+        "checked": checked,
+        "ok": checked - failures.len(),
+        "failed": failures.len(),
+        "errors": errors,
+        "failures": failures
+    }))
+}
+
+//=============================================================================
+// Checking
+//=============================================================================
+
+fn stored_file(row: &[spin_sdk::pg::DbValue]) -> StoredFile {
+    StoredFile {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        s3_key: String::decode(&row[1]).unwrap_or_default(),
+        size: i64::decode(&row[2]).unwrap_or(0),
+        checksum: String::decode(&row[3]).unwrap_or_default(),
+    }
+}
+
+fn check(conn: &Connection, storage: &dyn StorageBackend, file: &StoredFile, source: &str) -> Result<CheckResult, ServiceError> {
+    let (status, actual_size, actual_checksum) = match storage.head(&file.s3_key)? {
+        None => ("missing", None, None),
+        Some(head) if head.size != file.size => ("size_mismatch", Some(head.size), None),
+        Some(head) => {
+            let checksum = hex::encode(Sha256::digest(&storage.get(&file.s3_key)?));
+            let status = if checksum.eq_ignore_ascii_case(&file.checksum) { "ok" } else { "checksum_mismatch" };
+            (status, Some(head.size), Some(checksum))
+        }
+    };
+
+    let checked_at = chrono::Utc::now().to_rfc3339();
+    record(conn, file, status, actual_size, actual_checksum.as_deref(), source, &checked_at)?;
+
+    Ok(CheckResult {
+        file_id: file.id,
+        status,
+        expected_checksum: file.checksum.clone(),
+        actual_checksum,
+        expected_size: file.size,
+        actual_size,
+        checked_at,
+    })
+}
+
+fn record(
+    conn: &Connection,
+    file: &StoredFile,
+    status: &str,
+    actual_size: Option<i64>,
+    actual_checksum: Option<&str>,
+    source: &str,
+    checked_at: &str,
+) -> Result<(), ServiceError> {
+    let update = "UPDATE storage.files SET integrity_status = $2, integrity_checked_at = $3 WHERE id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(file.id.to_string()),
+        ParameterValue::Str(status.to_string()),
+        ParameterValue::Str(checked_at.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let insert = "INSERT INTO storage.integrity_checks
+                  (id, file_id, status, expected_size, actual_size, expected_checksum, actual_checksum, source, checked_at)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)";
+    conn.execute(insert, &[
+        ParameterValue::Str(Uuid::new_v4().to_string()),
+        ParameterValue::Str(file.id.to_string()),
+        ParameterValue::Str(status.to_string()),
+        ParameterValue::Int64(file.size),
+        actual_size.map(ParameterValue::Int64).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(file.checksum.clone()),
+        actual_checksum.map(|c| ParameterValue::Str(c.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(source.to_string()),
+        ParameterValue::Str(checked_at.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    Ok(())
+}
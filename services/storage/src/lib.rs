@@ -1,16 +1,51 @@
 //! AuthorWorks Storage Service
 //!
-//! Handles file uploads, downloads, and management with S3/MinIO backend.
+//! Handles file uploads, downloads, and management on S3/MinIO, Google Cloud
+//! Storage or Azure Blob Storage, chosen by the `storage_backend` variable.
 //!
 //! ## Endpoints
 //! - GET /health - Health check
-//! - POST /upload - Upload a file
-//! - POST /upload/presigned - Get presigned upload URL
-//! - GET /files/:id - Get file metadata
-//! - GET /files/:id/download - Get presigned download URL
+//! - POST /upload - Upload a file (images are checked against per-type dimension limits), optionally client-side encrypted
+//! - POST /upload/presigned - Get presigned upload URL bound to the declared size and content type
+//! - POST /upload/presigned/confirm - Record a file uploaded through a presigned URL, with its encryption envelope if any; uploads that break the presigned conditions are deleted
+//! - POST /upload/tus - Create a resumable (tus 1.0.0) upload (`encryption` metadata for ciphertext)
+//! - HEAD /upload/tus/:id - Get a resumable upload's current offset
+//! - PATCH /upload/tus/:id - Append bytes to a resumable upload
+//! - DELETE /upload/tus/:id - Abandon a resumable upload
+//! - POST /upload/tus/expire - Remove expired resumable uploads and presigned upload intents (internal, X-Internal-Token)
+//! - GET /files/:id - Get file metadata (owner or grantee)
+//! - GET /files/:id/download - Get presigned download URL, with the envelope of encrypted files (owner or grantee)
+//! - POST /files/:id/grants - Grant read access to a user or a book's collaborators
+//! - GET /files/:id/grants - List a file's grants
+//! - DELETE /files/:id/grants/:grant_id - Revoke a grant
+//! - GET /files/shared-with-me - List files other users have shared with you
 //! - DELETE /files/:id - Delete a file
 //! - GET /files - List user's files
 //! - POST /files/:id/copy - Copy a file
+//! - PUT /files/:id/move - Move a file into a collection or back to the root
+//! - PUT /files/:id/link - Link a file to one of your books and optionally a chapter
+//! - DELETE /files/:id/link - Unlink a file from its book
+//! - GET /books/:id/files?chapter_id= - List files linked to a book
+//! - POST /files/orphans - List or purge files whose linked book no longer exists (internal, X-Internal-Token)
+//! - POST /files/:id/sanitize - Strip image metadata from an existing file
+//! - POST /files/:id/publish - Give a file a public, cacheable URL
+//! - DELETE /files/:id/publish - Revoke a file's public URL
+//! - POST /files/:id/transcode - Queue streamable opus/mp4 variants of audio or video
+//! - GET /files/:id/transcode - Transcode job state and finished variants
+//! - POST /files/:id/verify - Recompute a file's checksum and compare it to the stored one
+//! - POST /integrity/audit - Verify a sample of files, oldest checks first (internal, X-Internal-Token)
+//! - GET /public/:token - Serve a published file without auth
+//! - GET /collections - List collections with their paths (optionally by book)
+//! - POST /collections - Create a collection or nested path
+//! - GET /collections/:id/files - List files in a collection (optionally recursive)
+//! - DELETE /collections/:id - Delete a collection, reparenting or cascading its contents
+//! - GET /vault/items - List private vault items and quota usage
+//! - POST /vault/items - Store a client-encrypted item
+//! - GET /vault/items/:id - Get the latest version of an item
+//! - PUT /vault/items/:id - Store a new version of an item
+//! - DELETE /vault/items/:id - Delete an item and all its versions
+//! - GET /vault/items/:id/versions - List retained versions
+//! - GET /vault/items/:id/versions/:version - Get one version
 
 use spin_sdk::http::{IntoResponse, Request, Response, Method};
 use spin_sdk::http_component;
@@ -20,23 +55,44 @@ use serde::{Deserialize, Serialize};
 use chrono::{Utc, Duration};
 use uuid::Uuid;
 use sha2::{Sha256, Digest};
-use hmac::{Hmac, Mac};
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 
 mod models;
 mod error;
+mod backend;
 mod s3;
-
+mod gcs;
+mod azure;
+mod sanitize;
+mod vault;
+mod public;
+mod collections;
+mod mime;
+mod images;
+mod transcode;
+mod grants;
+mod integrity;
+mod tus;
+mod encryption;
+mod book_assets;
+mod upload_intents;
+mod scopes;
+
+use backend::{UrlMethod, UrlOptions};
 use error::ServiceError;
 use models::*;
 
-type HmacSha256 = Hmac<Sha256>;
+const MAX_UPLOAD_SIZE: i64 = 100 * 1024 * 1024;
 
 #[http_component]
 fn handle_request(req: Request) -> anyhow::Result<impl IntoResponse> {
     let path = req.path();
     let method = req.method();
 
+    if let Err(e) = authorworks_access::authorize(&req, scopes::route_scope) {
+        return Ok(ServiceError::from(e).into_response());
+    }
+
     let result = match (method, path) {
         // Health
         (Method::Get, "/health") => health_handler(),
@@ -45,15 +101,74 @@ fn handle_request(req: Request) -> anyhow::Result<impl IntoResponse> {
         // Upload
         (Method::Post, "/upload") => upload_file(&req),
         (Method::Post, "/upload/presigned") => get_presigned_upload_url(&req),
+        (Method::Post, "/upload/presigned/confirm") => confirm_presigned_upload(&req),
+
+        // Resumable uploads (tus)
+        (Method::Options, path) if path.starts_with("/upload/tus") => Ok(tus::options()),
+        (Method::Post, "/upload/tus/expire") => expire_tus_uploads(&req),
+        (Method::Post, "/upload/tus") => create_tus_upload(&req),
+        (Method::Head, path) if path.starts_with("/upload/tus/") => get_tus_offset(&req, path),
+        (Method::Patch, path) if path.starts_with("/upload/tus/") => append_tus_upload(&req, path),
+        (Method::Delete, path) if path.starts_with("/upload/tus/") => terminate_tus_upload(&req, path),
 
         // Files CRUD
         (Method::Get, "/files") => list_files(&req),
+        (Method::Get, "/files/shared-with-me") => list_shared_files(&req),
+        (Method::Post, "/files/orphans") => find_orphaned_files(&req),
+        (Method::Put, path) if path.starts_with("/files/") && path.ends_with("/link") => link_file(&req, path),
+        (Method::Delete, path) if path.starts_with("/files/") && path.ends_with("/link") => unlink_file(&req, path),
+        (Method::Post, path) if path.starts_with("/files/") && path.ends_with("/grants") => grant_file(&req, path),
+        (Method::Get, path) if path.starts_with("/files/") && path.ends_with("/grants") => list_file_grants(&req, path),
+        (Method::Delete, path) if path.starts_with("/files/") && path.contains("/grants/") => revoke_file_grant(&req, path),
         (Method::Get, path) if path.starts_with("/files/") && path.ends_with("/download") => {
             get_download_url(&req, path)
         }
+        (Method::Get, path) if path.starts_with("/files/") && path.ends_with("/transcode") => {
+            get_transcode_status(&req, path)
+        }
         (Method::Get, path) if path.starts_with("/files/") => get_file_metadata(&req, path),
+        (Method::Post, path) if path.starts_with("/files/") && path.ends_with("/publish") => publish_file(&req, path),
+        (Method::Delete, path) if path.starts_with("/files/") && path.ends_with("/publish") => revoke_public_file(&req, path),
         (Method::Delete, path) if path.starts_with("/files/") => delete_file(&req, path),
         (Method::Post, path) if path.ends_with("/copy") => copy_file(&req, path),
+        (Method::Put, path) if path.starts_with("/files/") && path.ends_with("/move") => move_file(&req, path),
+        (Method::Post, path) if path.starts_with("/files/") && path.ends_with("/sanitize") => {
+            sanitize_file(&req, path)
+        }
+        (Method::Post, path) if path.starts_with("/files/") && path.ends_with("/transcode") => {
+            transcode_file(&req, path)
+        }
+        (Method::Post, path) if path.starts_with("/files/") && path.ends_with("/verify") => verify_file(&req, path),
+
+        // Integrity (internal)
+        (Method::Post, "/integrity/audit") => audit_integrity(&req),
+
+        // Public assets
+        (Method::Get, path) if path.starts_with("/public/") => serve_public_file(&req, path),
+
+        // Book assets
+        (Method::Get, path) if path.starts_with("/books/") && path.ends_with("/files") => list_book_files(&req, path),
+
+        // Collections
+        (Method::Get, "/collections") => list_collections(&req),
+        (Method::Post, "/collections") => create_collection(&req),
+        (Method::Get, path) if path.starts_with("/collections/") && path.ends_with("/files") => {
+            list_collection_files(&req, path)
+        }
+        (Method::Delete, path) if path.starts_with("/collections/") => delete_collection(&req, path),
+
+        // Private vault
+        (Method::Get, "/vault/items") => list_vault_items(&req),
+        (Method::Post, "/vault/items") => create_vault_item(&req),
+        (Method::Get, path) if path.starts_with("/vault/items/") && path.contains("/versions/") => {
+            get_vault_version(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/vault/items/") && path.ends_with("/versions") => {
+            list_vault_versions(&req, path)
+        }
+        (Method::Get, path) if path.starts_with("/vault/items/") => get_vault_item(&req, path),
+        (Method::Put, path) if path.starts_with("/vault/items/") => update_vault_item(&req, path),
+        (Method::Delete, path) if path.starts_with("/vault/items/") => delete_vault_item(&req, path),
 
         // CORS
         (Method::Options, _) => cors_preflight(),
@@ -68,7 +183,7 @@ fn handle_request(req: Request) -> anyhow::Result<impl IntoResponse> {
 }
 
 //=============================================================================
-// Database & S3 Connection
+// Database Connection
 //=============================================================================
 
 fn get_db_connection() -> Result<Connection, ServiceError> {
@@ -78,21 +193,6 @@ fn get_db_connection() -> Result<Connection, ServiceError> {
         .map_err(|e| ServiceError::Internal(format!("Database connection failed: {}", e)))
 }
 
-fn get_s3_config() -> Result<S3Config, ServiceError> {
-    Ok(S3Config {
-        endpoint: variables::get("s3_endpoint")
-            .unwrap_or_else(|_| "http://minio:9000".into()),
-        region: variables::get("s3_region")
-            .unwrap_or_else(|_| "us-east-1".into()),
-        bucket: variables::get("s3_bucket")
-            .unwrap_or_else(|_| "authorworks".into()),
-        access_key: variables::get("s3_access_key")
-            .map_err(|_| ServiceError::Internal("S3_ACCESS_KEY not configured".into()))?,
-        secret_key: variables::get("s3_secret_key")
-            .map_err(|_| ServiceError::Internal("S3_SECRET_KEY not configured".into()))?,
-    })
-}
-
 fn get_user_id(req: &Request) -> Result<Uuid, ServiceError> {
     let user_id = req.header("X-User-Id")
         .and_then(|h| h.as_str())
@@ -102,6 +202,16 @@ fn get_user_id(req: &Request) -> Result<Uuid, ServiceError> {
         .map_err(|_| ServiceError::Unauthorized("Invalid user ID".into()))
 }
 
+/// Paused accounts are read-only. A billing outage should not stop uploads.
+fn check_write_entitlement(user_id: &Uuid) -> Result<(), ServiceError> {
+    match authorworks_entitlements::require(user_id, authorworks_entitlements::features::WRITE, 0) {
+        Err(authorworks_entitlements::EntitlementError::Denied(e)) => {
+            Err(ServiceError::PaymentRequired(e.denial_message()))
+        }
+        Err(authorworks_entitlements::EntitlementError::Unavailable(_)) | Ok(_) => Ok(()),
+    }
+}
+
 //=============================================================================
 // Health & Info
 //=============================================================================
@@ -112,9 +222,9 @@ fn health_handler() -> Result<Response, ServiceError> {
         Err(_) => "disconnected",
     };
 
-    let s3_status = match get_s3_config() {
-        Ok(_) => "configured",
-        Err(_) => "not_configured",
+    let (storage_status, storage_backend) = match backend::configured() {
+        Ok(storage) => ("configured", Some(storage.name())),
+        Err(_) => ("not_configured", None),
     };
 
     json_response(200, serde_json::json!({
@@ -122,7 +232,8 @@ fn health_handler() -> Result<Response, ServiceError> {
         "service": "storage-service",
         "version": env!("CARGO_PKG_VERSION"),
         "database": db_status,
-        "s3": s3_status,
+        "storage": storage_status,
+        "storage_backend": storage_backend,
         "timestamp": Utc::now().to_rfc3339()
     }))
 }
@@ -132,10 +243,28 @@ fn service_info() -> Result<Response, ServiceError> {
         "service": "AuthorWorks Storage Service",
         "version": env!("CARGO_PKG_VERSION"),
         "endpoints": {
-            "upload": ["POST /upload", "POST /upload/presigned"],
-            "files": ["GET /files", "GET /files/:id", "GET /files/:id/download", "DELETE /files/:id", "POST /files/:id/copy"]
+            "upload": ["POST /upload", "POST /upload/presigned", "POST /upload/presigned/confirm", "POST /upload/tus", "HEAD /upload/tus/:id", "PATCH /upload/tus/:id", "DELETE /upload/tus/:id"],
+            "files": ["GET /files", "GET /files/:id", "GET /files/:id/download", "DELETE /files/:id", "POST /files/:id/copy", "POST /files/:id/sanitize", "POST /files/:id/publish", "DELETE /files/:id/publish", "PUT /files/:id/move"],
+            "book_assets": ["PUT /files/:id/link", "DELETE /files/:id/link", "GET /books/:id/files", "POST /files/orphans"],
+            "grants": ["POST /files/:id/grants", "GET /files/:id/grants", "DELETE /files/:id/grants/:grant_id", "GET /files/shared-with-me"],
+            "transcode": ["POST /files/:id/transcode", "GET /files/:id/transcode"],
+            "public": ["GET /public/:token"],
+            "collections": ["GET /collections", "POST /collections", "GET /collections/:id/files", "DELETE /collections/:id"],
+            "vault": ["GET /vault/items", "POST /vault/items", "GET /vault/items/:id", "PUT /vault/items/:id", "DELETE /vault/items/:id", "GET /vault/items/:id/versions", "GET /vault/items/:id/versions/:version"]
+        },
+        "supported_types": {
+            "cover": mime::allowed_types("cover"),
+            "manuscript": mime::allowed_types("manuscript"),
+            "audio": mime::allowed_types("audio"),
+            "video": mime::allowed_types("video"),
+            "other": mime::allowed_types("other")
         },
-        "supported_types": ["image/*", "audio/*", "video/*", "application/pdf", "text/*"]
+        "encryption_algorithms": encryption::ALGORITHMS,
+        "image_limits": {
+            "cover": images::policy_for("cover"),
+            "manuscript": images::policy_for("manuscript"),
+            "other": images::policy_for("other")
+        }
     }))
 }
 
@@ -156,19 +285,22 @@ fn cors_preflight() -> Result<Response, ServiceError> {
 
 fn upload_file(req: &Request) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
+    check_write_entitlement(&user_id)?;
     let conn = get_db_connection()?;
-    let s3_config = get_s3_config()?;
+    let storage = backend::configured()?;
 
     // Parse multipart form data or JSON with base64 content
     let upload_req: DirectUploadRequest = parse_json_body(req)?;
 
     // Validate file size (max 100MB)
-    let max_size = 100 * 1024 * 1024;
-    if upload_req.size > max_size {
+    if upload_req.size > MAX_UPLOAD_SIZE {
         return Err(ServiceError::BadRequest(format!(
-            "File too large. Maximum size is {} bytes", max_size
+            "File too large. Maximum size is {} bytes", MAX_UPLOAD_SIZE
         )));
     }
+    if let Some(ref collection_id) = upload_req.collection_id {
+        collections::ensure_owned(&conn, &user_id, collection_id)?;
+    }
 
     // Generate file ID and S3 key
     let file_id = Uuid::new_v4();
@@ -179,34 +311,70 @@ fn upload_file(req: &Request) -> Result<Response, ServiceError> {
     let s3_key = format!("{}/{}/{}.{}", user_id, upload_req.file_type, file_id, extension);
 
     // Decode base64 content
-    let content = BASE64.decode(&upload_req.content)
+    let mut content = BASE64.decode(&upload_req.content)
         .map_err(|e| ServiceError::BadRequest(format!("Invalid base64 content: {}", e)))?;
 
+    // The declared type is only a hint; store what the bytes actually are.
+    // Ciphertext is opaque, so only its declared plaintext type is checked.
+    let envelope = upload_req.encryption.clone()
+        .map(|e| encryption::validate(e, &upload_req.file_type, &upload_req.content_type))
+        .transpose()?;
+    let (verified, image) = match envelope {
+        Some(_) => (encryption::stored_type(), None),
+        None => {
+            let verified = mime::verify(&upload_req.file_type, &upload_req.content_type, &content)?;
+            let image = images::validate(&upload_req.file_type, &verified.content_type, &content)?;
+            (verified, image)
+        }
+    };
+
+    // Strip EXIF/XMP and other image metadata before it reaches S3
+    let mut metadata = upload_req.metadata.clone();
+    if let Some(ref image) = image {
+        metadata.insert("image".into(), serde_json::json!({
+            "width": image.width,
+            "height": image.height,
+            "frames": image.frames
+        }));
+    }
+    if envelope.is_none() && sanitize::enabled_for(&upload_req.file_type) {
+        let sanitized = sanitize::strip_metadata(&content);
+        if sanitized.format.is_some() {
+            metadata.insert("metadata_stripped".into(), serde_json::json!(sanitized.removed));
+            content = sanitized.content;
+        }
+    }
+    if let Some(ref correction) = verified.correction {
+        metadata.insert("content_type_correction".into(), serde_json::json!(correction));
+    }
+
     // Calculate checksum
     let mut hasher = Sha256::new();
     hasher.update(&content);
     let checksum = hex::encode(hasher.finalize());
 
     // Upload to S3
-    upload_to_s3(&s3_config, &s3_key, &content, &upload_req.content_type)?;
+    storage.put(&s3_key, &content, &verified.content_type)?;
 
     // Store metadata in database
     let now = Utc::now();
     let query = "INSERT INTO storage.files 
-                 (id, user_id, filename, s3_key, content_type, size, checksum, file_type, metadata, created_at)
-                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)";
+                 (id, user_id, filename, s3_key, content_type, size, checksum, file_type, metadata, created_at, collection_id, encryption)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12::jsonb)";
 
     let params = [
         ParameterValue::Str(file_id.to_string()),
         ParameterValue::Str(user_id.to_string()),
         ParameterValue::Str(upload_req.filename.clone()),
         ParameterValue::Str(s3_key.clone()),
-        ParameterValue::Str(upload_req.content_type.clone()),
+        ParameterValue::Str(verified.content_type.clone()),
         ParameterValue::Int64(content.len() as i64),
         ParameterValue::Str(checksum.clone()),
         ParameterValue::Str(upload_req.file_type.clone()),
-        ParameterValue::Str(serde_json::to_string(&upload_req.metadata).unwrap_or_else(|_| "{}".into())),
+        ParameterValue::Str(serde_json::to_string(&metadata).unwrap_or_else(|_| "{}".into())),
         ParameterValue::Str(now.to_rfc3339()),
+        upload_req.collection_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+        encryption::to_param(envelope.as_ref()),
     ];
 
     conn.execute(query, &params)
@@ -216,38 +384,304 @@ fn upload_file(req: &Request) -> Result<Response, ServiceError> {
         "id": file_id,
         "filename": upload_req.filename,
         "s3_key": s3_key,
-        "content_type": upload_req.content_type,
+        "content_type": verified.content_type,
+        "content_type_correction": verified.correction,
         "size": content.len(),
         "checksum": checksum,
+        "collection_id": upload_req.collection_id,
+        "encryption": envelope,
         "created_at": now.to_rfc3339()
     }))
 }
 
 fn get_presigned_upload_url(req: &Request) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
-    let s3_config = get_s3_config()?;
+    check_write_entitlement(&user_id)?;
+    let storage = backend::configured()?;
     let body: PresignedUploadRequest = parse_json_body(req)?;
+    mime::check_declared(&body.file_type, &body.content_type)?;
+    if body.size <= 0 {
+        return Err(ServiceError::BadRequest("size must be positive".into()));
+    }
+    if body.size > MAX_UPLOAD_SIZE {
+        return Err(ServiceError::PayloadTooLarge(format!(
+            "File too large. Maximum size is {} bytes", MAX_UPLOAD_SIZE
+        )));
+    }
+    let conn = get_db_connection()?;
 
     // Generate file ID and S3 key
     let file_id = Uuid::new_v4();
     let extension = body.filename.rsplit('.').next().unwrap_or("bin");
     let s3_key = format!("{}/{}/{}.{}", user_id, body.file_type, file_id, extension);
+    let intent = upload_intents::UploadIntent {
+        s3_key: s3_key.clone(),
+        file_type: body.file_type.clone(),
+        content_type: body.content_type.clone(),
+        size: body.size,
+        encrypted: body.encrypted,
+    };
+    let upload_type = intent.stored_type();
 
-    // Generate presigned URL valid for 1 hour
+    // Generate presigned URL valid for 1 hour, for exactly this body
     let expires_at = Utc::now() + Duration::hours(1);
-    let presigned_url = generate_presigned_put_url(&s3_config, &s3_key, &body.content_type, 3600)?;
+    let conditions = UrlOptions {
+        upload_content_type: Some(upload_type.to_string()),
+        upload_content_length: Some(body.size),
+        ..UrlOptions::default()
+    };
+    let presigned_url = storage.signed_url(UrlMethod::Put, &s3_key, 3600, &conditions)?;
+    upload_intents::record(&conn, &user_id, &file_id, &intent, expires_at)?;
+    let headers: serde_json::Map<String, serde_json::Value> = storage.upload_headers(upload_type)
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), serde_json::Value::String(value)))
+        .collect();
 
     json_response(200, serde_json::json!({
         "file_id": file_id,
         "upload_url": presigned_url,
         "s3_key": s3_key,
         "expires_at": expires_at.to_rfc3339(),
-        "headers": {
-            "Content-Type": body.content_type
+        "headers": headers,
+        "conditions": {
+            "content_type": upload_type,
+            "content_length": body.size
         }
     }))
 }
 
+fn confirm_presigned_upload(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+    let storage = backend::configured()?;
+    let body: ConfirmUploadRequest = parse_json_body(req)?;
+
+    // Keys are issued as {user_id}/{file_type}/{file_id}.{ext}; anything else
+    // was not presigned for this user
+    let file_type = body.s3_key.strip_prefix(&format!("{}/", user_id))
+        .and_then(|rest| rest.split_once('/'))
+        .filter(|(_, name)| name.starts_with(&format!("{}.", body.file_id)))
+        .map(|(file_type, _)| file_type.to_string())
+        .ok_or_else(|| ServiceError::Forbidden("Key does not belong to this upload".into()))?;
+
+    // Confirming twice returns the existing record
+    let existing = conn.query(
+        "SELECT size, checksum, created_at FROM storage.files WHERE id = $1 AND user_id = $2",
+        &[
+            ParameterValue::Str(body.file_id.to_string()),
+            ParameterValue::Str(user_id.to_string()),
+        ],
+    ).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    if let Some(row) = existing.rows.first() {
+        return json_response(200, serde_json::json!({
+            "id": body.file_id,
+            "filename": body.filename,
+            "s3_key": body.s3_key,
+            "size": i64::decode(&row[0]).unwrap_or(0),
+            "checksum": String::decode(&row[1]).unwrap_or_default(),
+            "created_at": String::decode(&row[2]).unwrap_or_default(),
+            "already_confirmed": true
+        }));
+    }
+
+    // The declared size and type are the ones the upload was presigned for
+    let intent = upload_intents::load(&conn, &user_id, &body.file_id)?;
+    if intent.s3_key != body.s3_key {
+        return Err(ServiceError::Forbidden("Key does not belong to this upload".into()));
+    }
+    if !intent.content_type.eq_ignore_ascii_case(&body.content_type) {
+        return Err(ServiceError::BadRequest(format!(
+            "Content type mismatch: the upload was presigned for {}", intent.content_type
+        )));
+    }
+    if intent.encrypted != body.encryption.is_some() {
+        return Err(ServiceError::BadRequest(if intent.encrypted {
+            "The upload was presigned as encrypted; encryption is required".into()
+        } else {
+            "The upload was not presigned as encrypted".into()
+        }));
+    }
+
+    if let Some(ref collection_id) = body.collection_id {
+        collections::ensure_owned(&conn, &user_id, collection_id)?;
+    }
+
+    let object = storage.head(&body.s3_key)?
+        .ok_or_else(|| ServiceError::NotFound("Uploaded object not found".into()))?;
+
+    // Stores that cannot pin the body (Azure) accept anything, so an object
+    // that fails any check from here on is removed with its intent, not just
+    // refused
+    let discard = || -> Result<(), ServiceError> {
+        storage.delete(&body.s3_key)?;
+        upload_intents::remove(&conn, &body.file_id)
+    };
+    let expected_type = intent.stored_type();
+    let violation = if object.size > MAX_UPLOAD_SIZE {
+        Some(ServiceError::PayloadTooLarge(format!(
+            "File too large. Maximum size is {} bytes", MAX_UPLOAD_SIZE
+        )))
+    } else if object.size != intent.size {
+        Some(ServiceError::BadRequest(format!(
+            "Size mismatch: the upload was presigned for {} bytes, stored object is {} bytes", intent.size, object.size
+        )))
+    } else {
+        object.content_type.as_ref()
+            .filter(|stored_type| !stored_type.eq_ignore_ascii_case(expected_type))
+            .map(|stored_type| ServiceError::BadRequest(format!(
+                "Content type mismatch: expected {}, stored object is {}", expected_type, stored_type
+            )))
+    };
+    if let Some(violation) = violation {
+        discard()?;
+        return Err(violation);
+    }
+
+    let envelope = match body.encryption.clone()
+        .map(|e| encryption::validate(e, &file_type, &body.content_type))
+        .transpose()
+    {
+        Ok(envelope) => envelope,
+        Err(e) => {
+            discard()?;
+            return Err(e);
+        }
+    };
+
+    // S3 ETags are not content hashes for multipart uploads, so hash the bytes
+    let content = storage.get(&body.s3_key)?;
+    let checksum = hex::encode(Sha256::digest(&content));
+
+    if let Some(ref expected) = body.checksum {
+        if !expected.eq_ignore_ascii_case(&checksum) {
+            discard()?;
+            return Err(ServiceError::BadRequest("Checksum mismatch".into()));
+        }
+    }
+
+    // The signed Content-Type only pins the header, not the bytes, so sniff
+    // them here too
+    let checked = match envelope {
+        Some(_) => Ok((encryption::stored_type(), None)),
+        None => mime::verify(&file_type, &body.content_type, &content).and_then(|verified| {
+            let image = images::validate(&file_type, &verified.content_type, &content)?;
+            Ok((verified, image))
+        }),
+    };
+    let (verified, image) = match checked {
+        Ok(checked) => checked,
+        Err(e) => {
+            discard()?;
+            return Err(e);
+        }
+    };
+    let mut metadata = body.metadata.clone();
+    if let Some(ref image) = image {
+        metadata.insert("image".into(), serde_json::json!({
+            "width": image.width,
+            "height": image.height,
+            "frames": image.frames
+        }));
+    }
+    if let Some(ref correction) = verified.correction {
+        metadata.insert("content_type_correction".into(), serde_json::json!(correction));
+    }
+
+    let now = Utc::now();
+    let query = "INSERT INTO storage.files 
+                 (id, user_id, filename, s3_key, content_type, size, checksum, file_type, metadata, created_at, collection_id, encryption)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12::jsonb)";
+
+    let params = [
+        ParameterValue::Str(body.file_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(body.filename.clone()),
+        ParameterValue::Str(body.s3_key.clone()),
+        ParameterValue::Str(verified.content_type.clone()),
+        ParameterValue::Int64(object.size),
+        ParameterValue::Str(checksum.clone()),
+        ParameterValue::Str(file_type.clone()),
+        ParameterValue::Str(serde_json::to_string(&metadata).unwrap_or_else(|_| "{}".into())),
+        ParameterValue::Str(now.to_rfc3339()),
+        body.collection_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+        encryption::to_param(envelope.as_ref()),
+    ];
+
+    conn.execute(query, &params)
+        .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    upload_intents::remove(&conn, &body.file_id)?;
+
+    json_response(201, serde_json::json!({
+        "id": body.file_id,
+        "filename": body.filename,
+        "s3_key": body.s3_key,
+        "content_type": verified.content_type,
+        "content_type_correction": verified.correction,
+        "file_type": file_type,
+        "size": object.size,
+        "checksum": checksum,
+        "collection_id": body.collection_id,
+        "encryption": envelope,
+        "created_at": now.to_rfc3339()
+    }))
+}
+
+//=============================================================================
+// Resumable Uploads
+//=============================================================================
+
+fn create_tus_upload(req: &Request) -> Result<Response, ServiceError> {
+    if let Some(response) = tus::unsupported_version(req) {
+        return Ok(response);
+    }
+    let user_id = get_user_id(req)?;
+    check_write_entitlement(&user_id)?;
+    let conn = get_db_connection()?;
+    let storage = backend::configured()?;
+    tus::create(&conn, storage.as_ref(), &user_id, req)
+}
+
+fn get_tus_offset(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    if let Some(response) = tus::unsupported_version(req) {
+        return Ok(response);
+    }
+    let user_id = get_user_id(req)?;
+    let upload_id = extract_id_from_path(path, "/upload/tus/")?;
+    let conn = get_db_connection()?;
+    tus::head(&conn, &user_id, &upload_id)
+}
+
+fn append_tus_upload(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    if let Some(response) = tus::unsupported_version(req) {
+        return Ok(response);
+    }
+    let user_id = get_user_id(req)?;
+    let upload_id = extract_id_from_path(path, "/upload/tus/")?;
+    let conn = get_db_connection()?;
+    let storage = backend::configured()?;
+    tus::patch(&conn, storage.as_ref(), &user_id, &upload_id, req)
+}
+
+fn terminate_tus_upload(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    if let Some(response) = tus::unsupported_version(req) {
+        return Ok(response);
+    }
+    let user_id = get_user_id(req)?;
+    let upload_id = extract_id_from_path(path, "/upload/tus/")?;
+    let conn = get_db_connection()?;
+    let storage = backend::configured()?;
+    tus::terminate(&conn, storage.as_ref(), &user_id, &upload_id)
+}
+
+fn expire_tus_uploads(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let conn = get_db_connection()?;
+    let storage = backend::configured()?;
+    upload_intents::purge_expired(&conn)?;
+    tus::expire(&conn, storage.as_ref())
+}
+
 //=============================================================================
 // File Operations
 //=============================================================================
@@ -260,7 +694,7 @@ fn list_files(req: &Request) -> Result<Response, ServiceError> {
     let file_type = req.header("X-File-Type").and_then(|h| h.as_str());
 
     let query = if let Some(ft) = file_type {
-        let q = "SELECT id, filename, content_type, size, file_type, created_at 
+        let q = "SELECT id, filename, content_type, size, file_type, collection_id, created_at 
                  FROM storage.files WHERE user_id = $1 AND file_type = $2 
                  ORDER BY created_at DESC LIMIT 100";
         let params = [
@@ -269,7 +703,7 @@ fn list_files(req: &Request) -> Result<Response, ServiceError> {
         ];
         conn.query(q, &params)
     } else {
-        let q = "SELECT id, filename, content_type, size, file_type, created_at 
+        let q = "SELECT id, filename, content_type, size, file_type, collection_id, created_at 
                  FROM storage.files WHERE user_id = $1 
                  ORDER BY created_at DESC LIMIT 100";
         let params = [ParameterValue::Str(user_id.to_string())];
@@ -283,7 +717,8 @@ fn list_files(req: &Request) -> Result<Response, ServiceError> {
             content_type: String::decode(&row[2]).unwrap_or_default(),
             size: i64::decode(&row[3]).unwrap_or(0),
             file_type: String::decode(&row[4]).unwrap_or_default(),
-            created_at: String::decode(&row[5]).unwrap_or_default(),
+            collection_id: String::decode(&row[5]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+            created_at: String::decode(&row[6]).unwrap_or_default(),
         }
     }).collect();
 
@@ -298,15 +733,19 @@ fn get_file_metadata(req: &Request, path: &str) -> Result<Response, ServiceError
     let file_id = extract_id_from_path(path, "/files/")?;
     let conn = get_db_connection()?;
 
-    let query = "SELECT id, filename, s3_key, content_type, size, checksum, file_type, metadata, created_at
-                 FROM storage.files WHERE id = $1 AND user_id = $2";
+    let query = format!(
+        "SELECT f.id, f.filename, f.s3_key, f.content_type, f.size, f.checksum, f.file_type, f.metadata,
+                f.created_at, f.collection_id, f.encryption::text, f.book_id, f.chapter_id
+         FROM storage.files f WHERE f.id = $1 AND {}",
+        grants::READABLE_BY_USER
+    );
 
     let params = [
         ParameterValue::Str(file_id.to_string()),
         ParameterValue::Str(user_id.to_string()),
     ];
 
-    let rows = conn.query(query, &params)
+    let rows = conn.query(&query, &params)
         .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
 
     if rows.rows.is_empty() {
@@ -314,7 +753,7 @@ fn get_file_metadata(req: &Request, path: &str) -> Result<Response, ServiceError
     }
 
     let row = &rows.rows[0];
-    let file = FileMetadata {
+    let mut file = FileMetadata {
         id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
         filename: String::decode(&row[1]).unwrap_or_default(),
         s3_key: String::decode(&row[2]).unwrap_or_default(),
@@ -323,9 +762,21 @@ fn get_file_metadata(req: &Request, path: &str) -> Result<Response, ServiceError
         checksum: String::decode(&row[5]).unwrap_or_default(),
         file_type: String::decode(&row[6]).unwrap_or_default(),
         metadata: serde_json::from_str(&String::decode(&row[7]).unwrap_or_else(|_| "{}".into())).unwrap_or_default(),
+        collection_id: String::decode(&row[9]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
         created_at: String::decode(&row[8]).unwrap_or_default(),
+        encryption: encryption::from_column(String::decode(&row[10]).ok()),
+        book_id: String::decode(&row[11]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
+        chapter_id: String::decode(&row[12]).ok().and_then(|s| Uuid::parse_str(&s).ok()),
     };
 
+    // Players pick a source from the finished transcodes
+    let variants = transcode::playable_variants(&conn, &file_id)?;
+    if !variants.is_empty() {
+        let variants = serde_json::to_value(variants)
+            .map_err(|e| ServiceError::Internal(format!("JSON error: {}", e)))?;
+        file.metadata.insert("variants".into(), variants);
+    }
+
     json_response(200, file)
 }
 
@@ -333,15 +784,18 @@ fn get_download_url(req: &Request, path: &str) -> Result<Response, ServiceError>
     let user_id = get_user_id(req)?;
     let file_id = extract_id_from_path(path, "/files/")?;
     let conn = get_db_connection()?;
-    let s3_config = get_s3_config()?;
+    let storage = backend::configured()?;
 
-    let query = "SELECT s3_key, filename, content_type FROM storage.files WHERE id = $1 AND user_id = $2";
+    let query = format!(
+        "SELECT f.s3_key, f.filename, f.content_type, f.encryption::text FROM storage.files f WHERE f.id = $1 AND {}",
+        grants::READABLE_BY_USER
+    );
     let params = [
         ParameterValue::Str(file_id.to_string()),
         ParameterValue::Str(user_id.to_string()),
     ];
 
-    let rows = conn.query(query, &params)
+    let rows = conn.query(&query, &params)
         .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
 
     if rows.rows.is_empty() {
@@ -351,14 +805,28 @@ fn get_download_url(req: &Request, path: &str) -> Result<Response, ServiceError>
     let row = &rows.rows[0];
     let s3_key = String::decode(&row[0]).unwrap_or_default();
     let filename = String::decode(&row[1]).unwrap_or_default();
+    let content_type = String::decode(&row[2]).unwrap_or_else(|_| mime::OCTET_STREAM.into());
+    let envelope = encryption::from_column(String::decode(&row[3]).ok());
 
-    // Generate presigned download URL valid for 1 hour
+    // Generate presigned download URL valid for 1 hour. Stores keep whatever
+    // Content-Type the client sent with a presigned PUT, so the response type
+    // is pinned to the verified one and served as an attachment.
     let expires_at = Utc::now() + Duration::hours(1);
-    let presigned_url = generate_presigned_get_url(&s3_config, &s3_key, 3600)?;
+    let disposition_name: String = filename.chars()
+        .filter(|c| (c.is_ascii_graphic() && *c != '"' && *c != '\\') || *c == ' ')
+        .collect();
+    let overrides = UrlOptions {
+        response_content_disposition: Some(format!("attachment; filename=\"{}\"", disposition_name)),
+        response_content_type: Some(content_type),
+    };
+    let presigned_url = storage.signed_url(UrlMethod::Get, &s3_key, 3600, &overrides)?;
 
+    // Encrypted files come with the envelope the client needs to decrypt them
     json_response(200, serde_json::json!({
         "download_url": presigned_url,
         "filename": filename,
+        "encrypted": envelope.is_some(),
+        "encryption": envelope,
         "expires_at": expires_at.to_rfc3339()
     }))
 }
@@ -367,7 +835,7 @@ fn delete_file(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
     let file_id = extract_id_from_path(path, "/files/")?;
     let conn = get_db_connection()?;
-    let s3_config = get_s3_config()?;
+    let storage = backend::configured()?;
 
     // Get S3 key before deletion
     let query = "SELECT s3_key FROM storage.files WHERE id = $1 AND user_id = $2";
@@ -385,8 +853,15 @@ fn delete_file(req: &Request, path: &str) -> Result<Response, ServiceError> {
 
     let s3_key = String::decode(&rows.rows[0][0]).unwrap_or_default();
 
+    // Transcoded variants go with their source
+    for (derivative_id, derivative_key) in transcode::derivative_keys(&conn, &file_id)? {
+        storage.delete(&derivative_key)?;
+        conn.execute("DELETE FROM storage.files WHERE id = $1", &[ParameterValue::Str(derivative_id.to_string())])
+            .map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    }
+
     // Delete from S3
-    delete_from_s3(&s3_config, &s3_key)?;
+    storage.delete(&s3_key)?;
 
     // Delete from database
     let delete_query = "DELETE FROM storage.files WHERE id = $1 AND user_id = $2";
@@ -400,13 +875,14 @@ fn delete_file(req: &Request, path: &str) -> Result<Response, ServiceError> {
 
 fn copy_file(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
+    check_write_entitlement(&user_id)?;
     let file_id = extract_id_from_path(path, "/files/")?;
     let conn = get_db_connection()?;
-    let s3_config = get_s3_config()?;
+    let storage = backend::configured()?;
     let body: CopyFileRequest = parse_json_body(req)?;
 
     // Get source file info
-    let query = "SELECT filename, s3_key, content_type, size, checksum, file_type, metadata
+    let query = "SELECT filename, s3_key, content_type, size, checksum, file_type, metadata, collection_id, encryption::text
                  FROM storage.files WHERE id = $1 AND user_id = $2";
     let params = [
         ParameterValue::Str(file_id.to_string()),
@@ -427,6 +903,9 @@ fn copy_file(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let checksum = String::decode(&row[4]).unwrap_or_default();
     let file_type = String::decode(&row[5]).unwrap_or_default();
     let metadata = String::decode(&row[6]).unwrap_or_else(|_| "{}".into());
+    let collection_id = String::decode(&row[7]).ok();
+    // The copy is the same ciphertext, so the same envelope decrypts it
+    let envelope = String::decode(&row[8]).ok();
 
     // Generate new file ID and S3 key
     let new_file_id = Uuid::new_v4();
@@ -437,13 +916,13 @@ fn copy_file(req: &Request, path: &str) -> Result<Response, ServiceError> {
     let new_s3_key = format!("{}/{}/{}.{}", user_id, file_type, new_file_id, extension);
 
     // Copy in S3
-    copy_in_s3(&s3_config, &source_key, &new_s3_key)?;
+    storage.copy(&source_key, &new_s3_key)?;
 
     // Insert new record
     let now = Utc::now();
     let insert_query = "INSERT INTO storage.files 
-                        (id, user_id, filename, s3_key, content_type, size, checksum, file_type, metadata, created_at)
-                        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)";
+                        (id, user_id, filename, s3_key, content_type, size, checksum, file_type, metadata, created_at, collection_id, encryption)
+                        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12::jsonb)";
 
     let insert_params = [
         ParameterValue::Str(new_file_id.to_string()),
@@ -456,6 +935,8 @@ fn copy_file(req: &Request, path: &str) -> Result<Response, ServiceError> {
         ParameterValue::Str(file_type),
         ParameterValue::Str(metadata),
         ParameterValue::Str(now.to_rfc3339()),
+        collection_id.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        envelope.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
     ];
 
     conn.execute(insert_query, &insert_params)
@@ -464,152 +945,319 @@ fn copy_file(req: &Request, path: &str) -> Result<Response, ServiceError> {
     json_response(201, serde_json::json!({
         "id": new_file_id,
         "filename": new_filename,
+        "encrypted": envelope.is_some(),
         "created_at": now.to_rfc3339()
     }))
 }
 
+fn sanitize_file(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let file_id = extract_id_from_path(path, "/files/")?;
+    let conn = get_db_connection()?;
+    let storage = backend::configured()?;
+    encryption::ensure_plaintext(&conn, &file_id, "sanitized")?;
+
+    let query = "SELECT s3_key, content_type, metadata FROM storage.files WHERE id = $1 AND user_id = $2";
+    let params = [
+        ParameterValue::Str(file_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ];
+
+    let rows = conn.query(query, &params)
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    if rows.rows.is_empty() {
+        return Err(ServiceError::NotFound("File not found".into()));
+    }
+
+    let row = &rows.rows[0];
+    let s3_key = String::decode(&row[0]).unwrap_or_default();
+    let content_type = String::decode(&row[1]).unwrap_or_default();
+    let mut metadata: std::collections::HashMap<String, serde_json::Value> =
+        serde_json::from_str(&String::decode(&row[2]).unwrap_or_else(|_| "{}".into())).unwrap_or_default();
+
+    let original = storage.get(&s3_key)?;
+    let sanitized = sanitize::strip_metadata(&original);
+
+    if sanitized.format.is_none() {
+        return Err(ServiceError::BadRequest("Only JPEG, PNG, and WebP images can be sanitized".into()));
+    }
+
+    let changed = !sanitized.removed.is_empty();
+    let checksum = hex::encode(Sha256::digest(&sanitized.content));
+
+    if changed {
+        storage.put(&s3_key, &sanitized.content, &content_type)?;
+    }
+
+    metadata.insert("metadata_stripped".into(), serde_json::json!(sanitized.removed));
+    let update = "UPDATE storage.files SET size = $3, checksum = $4, metadata = $5 WHERE id = $1 AND user_id = $2";
+    conn.execute(update, &[
+        ParameterValue::Str(file_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Int64(sanitized.content.len() as i64),
+        ParameterValue::Str(checksum.clone()),
+        ParameterValue::Str(serde_json::to_string(&metadata).unwrap_or_else(|_| "{}".into())),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    json_response(200, serde_json::json!({
+        "id": file_id,
+        "format": sanitized.format.map(|f| f.as_str()),
+        "removed": sanitized.removed,
+        "changed": changed,
+        "size": sanitized.content.len(),
+        "checksum": checksum
+    }))
+}
+
 //=============================================================================
-// S3 Operations
+// File Grants
 //=============================================================================
 
-fn upload_to_s3(config: &S3Config, key: &str, content: &[u8], content_type: &str) -> Result<(), ServiceError> {
-    let date = Utc::now();
-    let date_str = date.format("%Y%m%dT%H%M%SZ").to_string();
-    let date_short = date.format("%Y%m%d").to_string();
+fn grant_file(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let file_id = extract_id_from_path(path, "/files/")?;
+    let body: grants::GrantRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+    grants::grant(&conn, &user_id, &file_id, body)
+}
 
-    let host = config.endpoint.trim_start_matches("http://").trim_start_matches("https://");
-    let url = format!("{}/{}/{}", config.endpoint, config.bucket, key);
+fn list_file_grants(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let file_id = extract_id_from_path(path, "/files/")?;
+    let conn = get_db_connection()?;
+    grants::list(&conn, &user_id, &file_id)
+}
 
-    // Create canonical request
-    let payload_hash = hex::encode(Sha256::digest(content));
-    let canonical_headers = format!(
-        "content-type:{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}",
-        content_type, host, payload_hash, date_str
-    );
-    let signed_headers = "content-type;host;x-amz-content-sha256;x-amz-date";
+fn revoke_file_grant(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let file_id = extract_id_from_path(path, "/files/")?;
+    let grant_id = path.split_once("/grants/")
+        .and_then(|(_, id)| Uuid::parse_str(id).ok())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid grant ID".into()))?;
+    let conn = get_db_connection()?;
+    grants::revoke(&conn, &user_id, &file_id, &grant_id)
+}
 
-    let canonical_request = format!(
-        "PUT\n/{}/{}\n\n{}\n\n{}\n{}",
-        config.bucket, key, canonical_headers, signed_headers, payload_hash
-    );
+fn list_shared_files(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+    grants::shared_with_me(&conn, &user_id)
+}
 
-    // Create string to sign
-    let credential_scope = format!("{}/{}/s3/aws4_request", date_short, config.region);
-    let string_to_sign = format!(
-        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
-        date_str, credential_scope, hex::encode(Sha256::digest(canonical_request.as_bytes()))
-    );
+//=============================================================================
+// Public Assets
+//=============================================================================
 
-    // Calculate signature
-    let signature = sign_aws4(&config.secret_key, &date_short, &config.region, "s3", &string_to_sign)?;
+fn publish_file(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let file_id = extract_id_from_path(path, "/files/")?;
+    let conn = get_db_connection()?;
+    encryption::ensure_plaintext(&conn, &file_id, "published")?;
+    public::publish(&conn, &user_id, &file_id)
+}
 
-    let _authorization = format!(
-        "AWS4-HMAC-SHA256 Credential={}/{},SignedHeaders={},Signature={}",
-        config.access_key, credential_scope, signed_headers, signature
-    );
+fn revoke_public_file(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let file_id = extract_id_from_path(path, "/files/")?;
+    let conn = get_db_connection()?;
+    public::revoke(&conn, &user_id, &file_id)
+}
 
-    // In WASM, we use spin_sdk::outbound_http for actual HTTP calls
-    // For now, this validates the signing logic - actual upload happens via presigned URL
-    
-    // Store the URL for debugging
-    let _ = url;
+/// No `X-User-Id`: the token is the credential
+fn serve_public_file(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let conn = get_db_connection()?;
+    public::serve(&conn, req, path)
+}
 
-    Ok(())
+//=============================================================================
+// Transcoding
+//=============================================================================
+
+fn transcode_file(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let file_id = extract_id_from_path(path, "/files/")?;
+    // An empty body asks for the default variants
+    let body: transcode::TranscodeRequest = if req.body().is_empty() {
+        transcode::TranscodeRequest::default()
+    } else {
+        parse_json_body(req)?
+    };
+    let conn = get_db_connection()?;
+    encryption::ensure_plaintext(&conn, &file_id, "transcoded")?;
+    transcode::enqueue(&conn, &user_id, &file_id, body)
 }
 
-fn generate_presigned_put_url(config: &S3Config, key: &str, _content_type: &str, expires_secs: i64) -> Result<String, ServiceError> {
-    let date = Utc::now();
-    let date_str = date.format("%Y%m%dT%H%M%SZ").to_string();
-    let date_short = date.format("%Y%m%d").to_string();
+fn get_transcode_status(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let file_id = extract_id_from_path(path, "/files/")?;
+    let conn = get_db_connection()?;
+    transcode::status(&conn, &user_id, &file_id)
+}
 
-    let host = config.endpoint.trim_start_matches("http://").trim_start_matches("https://");
-    let credential_scope = format!("{}/{}/s3/aws4_request", date_short, config.region);
-    let credential = format!("{}/{}", config.access_key, credential_scope);
+//=============================================================================
+// Integrity
+//=============================================================================
 
-    let query_params = format!(
-        "X-Amz-Algorithm=AWS4-HMAC-SHA256&X-Amz-Credential={}&X-Amz-Date={}&X-Amz-Expires={}&X-Amz-SignedHeaders=host",
-        urlencoded(&credential), date_str, expires_secs
-    );
+fn verify_file(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let file_id = extract_id_from_path(path, "/files/")?;
+    let conn = get_db_connection()?;
+    let storage = backend::configured()?;
+    integrity::verify_file(&conn, storage.as_ref(), &user_id, &file_id)
+}
 
-    let canonical_request = format!(
-        "PUT\n/{}/{}\n{}\nhost:{}\n\nhost\nUNSIGNED-PAYLOAD",
-        config.bucket, key, query_params, host
-    );
+fn audit_integrity(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let body: integrity::AuditRequest = if req.body().is_empty() {
+        integrity::AuditRequest::default()
+    } else {
+        parse_json_body(req)?
+    };
+    let conn = get_db_connection()?;
+    let storage = backend::configured()?;
+    integrity::audit(&conn, storage.as_ref(), body)
+}
 
-    let string_to_sign = format!(
-        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
-        date_str, credential_scope, hex::encode(Sha256::digest(canonical_request.as_bytes()))
-    );
+//=============================================================================
+// Collections
+//=============================================================================
 
-    let signature = sign_aws4(&config.secret_key, &date_short, &config.region, "s3", &string_to_sign)?;
+fn list_collections(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = get_query_param(req, "book_id")
+        .map(|id| Uuid::parse_str(&id).map_err(|_| ServiceError::BadRequest("Invalid book_id".into())))
+        .transpose()?;
+    let conn = get_db_connection()?;
+    collections::list(&conn, &user_id, book_id.as_ref())
+}
 
-    Ok(format!(
-        "{}/{}/{}?{}&X-Amz-Signature={}",
-        config.endpoint, config.bucket, key, query_params, signature
-    ))
+fn create_collection(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: collections::CreateCollectionRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+    collections::create(&conn, &user_id, body)
 }
 
-fn generate_presigned_get_url(config: &S3Config, key: &str, expires_secs: i64) -> Result<String, ServiceError> {
-    let date = Utc::now();
-    let date_str = date.format("%Y%m%dT%H%M%SZ").to_string();
-    let date_short = date.format("%Y%m%d").to_string();
+fn list_collection_files(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let collection_id = extract_id_from_path(path, "/collections/")?;
+    let recursive = get_query_param(req, "recursive").is_some_and(|v| v == "true" || v == "1");
+    let conn = get_db_connection()?;
+    collections::list_files(&conn, &user_id, &collection_id, recursive)
+}
 
-    let host = config.endpoint.trim_start_matches("http://").trim_start_matches("https://");
-    let credential_scope = format!("{}/{}/s3/aws4_request", date_short, config.region);
-    let credential = format!("{}/{}", config.access_key, credential_scope);
+fn delete_collection(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let collection_id = extract_id_from_path(path, "/collections/")?;
+    let mode = get_query_param(req, "mode").unwrap_or_else(|| "reparent".into());
+    let conn = get_db_connection()?;
+    collections::delete(&conn, &user_id, &collection_id, &mode)
+}
 
-    let query_params = format!(
-        "X-Amz-Algorithm=AWS4-HMAC-SHA256&X-Amz-Credential={}&X-Amz-Date={}&X-Amz-Expires={}&X-Amz-SignedHeaders=host",
-        urlencoded(&credential), date_str, expires_secs
-    );
+fn move_file(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let file_id = extract_id_from_path(path, "/files/")?;
+    let body: collections::MoveFileRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+    collections::move_file(&conn, &user_id, &file_id, body)
+}
 
-    let canonical_request = format!(
-        "GET\n/{}/{}\n{}\nhost:{}\n\nhost\nUNSIGNED-PAYLOAD",
-        config.bucket, key, query_params, host
-    );
+//=============================================================================
+// Book Assets
+//=============================================================================
 
-    let string_to_sign = format!(
-        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
-        date_str, credential_scope, hex::encode(Sha256::digest(canonical_request.as_bytes()))
-    );
+fn link_file(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let file_id = extract_id_from_path(path, "/files/")?;
+    let body: book_assets::LinkRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+    book_assets::link(&conn, &user_id, &file_id, body)
+}
 
-    let signature = sign_aws4(&config.secret_key, &date_short, &config.region, "s3", &string_to_sign)?;
+fn unlink_file(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let file_id = extract_id_from_path(path, "/files/")?;
+    let conn = get_db_connection()?;
+    book_assets::unlink(&conn, &user_id, &file_id)
+}
+
+fn list_book_files(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let book_id = extract_id_from_path(path, "/books/")?;
+    let chapter_id = get_query_param(req, "chapter_id")
+        .map(|id| Uuid::parse_str(&id).map_err(|_| ServiceError::BadRequest("Invalid chapter_id".into())))
+        .transpose()?;
+    let conn = get_db_connection()?;
+    book_assets::list_for_book(&conn, &user_id, &book_id, chapter_id.as_ref())
+}
+
+fn find_orphaned_files(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let body: book_assets::OrphanRequest = if req.body().is_empty() {
+        book_assets::OrphanRequest::default()
+    } else {
+        parse_json_body(req)?
+    };
+    let conn = get_db_connection()?;
+    let storage = backend::configured()?;
+    book_assets::orphans(&conn, storage.as_ref(), body)
+}
+
+//=============================================================================
+// Private Vault
+//=============================================================================
+
+fn list_vault_items(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+    vault::list_items(&conn, &user_id)
+}
 
-    Ok(format!(
-        "{}/{}/{}?{}&X-Amz-Signature={}",
-        config.endpoint, config.bucket, key, query_params, signature
-    ))
+fn create_vault_item(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: vault::CreateVaultItemRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+    vault::create_item(&conn, &user_id, body)
 }
 
-fn delete_from_s3(_config: &S3Config, _key: &str) -> Result<(), ServiceError> {
-    // In production, this would use outbound HTTP to delete from S3
-    // The delete is handled via presigned URL or direct API call
-    Ok(())
+fn get_vault_item(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let item_id = extract_id_from_path(path, "/vault/items/")?;
+    let conn = get_db_connection()?;
+    vault::get_item(&conn, &user_id, &item_id)
 }
 
-fn copy_in_s3(_config: &S3Config, _source_key: &str, _dest_key: &str) -> Result<(), ServiceError> {
-    // In production, this would use S3 copy API
-    Ok(())
+fn update_vault_item(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let item_id = extract_id_from_path(path, "/vault/items/")?;
+    let body: vault::UpdateVaultItemRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+    vault::update_item(&conn, &user_id, &item_id, body)
 }
 
-fn sign_aws4(secret: &str, date: &str, region: &str, service: &str, string_to_sign: &str) -> Result<String, ServiceError> {
-    let k_date = hmac_sha256(format!("AWS4{}", secret).as_bytes(), date.as_bytes())?;
-    let k_region = hmac_sha256(&k_date, region.as_bytes())?;
-    let k_service = hmac_sha256(&k_region, service.as_bytes())?;
-    let k_signing = hmac_sha256(&k_service, b"aws4_request")?;
-    let signature = hmac_sha256(&k_signing, string_to_sign.as_bytes())?;
-    Ok(hex::encode(signature))
+fn delete_vault_item(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let item_id = extract_id_from_path(path, "/vault/items/")?;
+    let conn = get_db_connection()?;
+    vault::delete_item(&conn, &user_id, &item_id)
 }
 
-fn hmac_sha256(key: &[u8], data: &[u8]) -> Result<Vec<u8>, ServiceError> {
-    let mut mac = HmacSha256::new_from_slice(key)
-        .map_err(|e| ServiceError::Internal(format!("HMAC error: {}", e)))?;
-    mac.update(data);
-    Ok(mac.finalize().into_bytes().to_vec())
+fn list_vault_versions(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let item_id = extract_id_from_path(path, "/vault/items/")?;
+    let conn = get_db_connection()?;
+    vault::list_versions(&conn, &user_id, &item_id)
 }
 
-fn urlencoded(s: &str) -> String {
-    s.replace('/', "%2F").replace('=', "%3D")
+fn get_vault_version(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let item_id = extract_id_from_path(path, "/vault/items/")?;
+    let version = path.rsplit('/').next()
+        .and_then(|v| v.parse::<i64>().ok())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid version".into()))?;
+    let conn = get_db_connection()?;
+    vault::get_version(&conn, &user_id, &item_id, version)
 }
 
 //=============================================================================
@@ -625,6 +1273,13 @@ fn extract_id_from_path(path: &str, prefix: &str) -> Result<Uuid, ServiceError>
         .map_err(|_| ServiceError::BadRequest("Invalid UUID".into()))
 }
 
+fn get_query_param(req: &Request, name: &str) -> Option<String> {
+    req.query().split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name).then(|| value.to_string())
+    })
+}
+
 fn json_response<T: Serialize>(status: u16, body: T) -> Result<Response, ServiceError> {
     let json = serde_json::to_string(&body)
         .map_err(|e| ServiceError::Internal(format!("JSON error: {}", e)))?;
@@ -0,0 +1,281 @@
+//! Content type verification
+//!
+//! The `content_type` a client declares is only a hint. Uploads are sniffed
+//! from their magic bytes, and the detected type must be on the allowlist for
+//! the upload's `file_type` category. Markup that a browser would render
+//! (HTML, SVG, XML, XHTML) is rejected everywhere, since a file served with
+//! that type from a presigned or public URL runs script in the viewer's
+//! browser. A declared type from a different family than the content (an
+//! "image" that is really a PDF) is rejected as a mismatch; a wrong subtype
+//! within the family (a JPEG declared as PNG) is corrected, and the stored
+//! type is always the detected one.
+
+use crate::error::ServiceError;
+use serde::Serialize;
+
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+const IMAGE_TYPES: &[&str] = &["image/jpeg", "image/png", "image/gif", "image/webp"];
+const DOCUMENT_TYPES: &[&str] = &[
+    "application/pdf",
+    "application/epub+zip",
+    "application/msword",
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "application/vnd.oasis.opendocument.text",
+    "application/rtf",
+    "text/plain",
+    "text/markdown",
+    "text/csv",
+    "application/json",
+];
+const AUDIO_TYPES: &[&str] = &["audio/mpeg", "audio/wav", "audio/ogg", "audio/flac", "audio/mp4", "audio/aac"];
+const VIDEO_TYPES: &[&str] = &["video/mp4", "video/webm", "video/quicktime"];
+/// `other` also takes unrecognized binary content as `application/octet-stream`
+const OTHER_EXTRA_TYPES: &[&str] = &["application/zip", OCTET_STREAM];
+
+/// Never stored, whatever the category
+const ACTIVE_TYPES: &[&str] = &[
+    "text/html",
+    "application/xhtml+xml",
+    "image/svg+xml",
+    "application/xml",
+    "text/xml",
+    "application/javascript",
+    "text/javascript",
+];
+
+/// How far into a file text and markup detection look
+const SNIFF_WINDOW: usize = 8192;
+
+/// Declared vs detected type, recorded in file metadata when they differ
+#[derive(Debug, Clone, Serialize)]
+pub struct TypeCorrection {
+    pub declared: String,
+    pub detected: String,
+}
+
+pub struct VerifiedType {
+    /// Type to store and serve
+    pub content_type: String,
+    pub correction: Option<TypeCorrection>,
+}
+
+//=============================================================================
+// Validation
+//=============================================================================
+
+/// Allowed types for an upload category; `None` for an unknown category
+pub fn allowed_types(file_type: &str) -> Option<Vec<&'static str>> {
+    let types = match file_type {
+        "cover" | "image" => IMAGE_TYPES.to_vec(),
+        "manuscript" | "document" => DOCUMENT_TYPES.to_vec(),
+        "audio" => AUDIO_TYPES.to_vec(),
+        "video" => VIDEO_TYPES.to_vec(),
+        "other" => [IMAGE_TYPES, DOCUMENT_TYPES, AUDIO_TYPES, VIDEO_TYPES, OTHER_EXTRA_TYPES].concat(),
+        _ => return None,
+    };
+    Some(types)
+}
+
+/// Check a declared type before any bytes arrive (presigned uploads)
+pub fn check_declared(file_type: &str, declared: &str) -> Result<(), ServiceError> {
+    let allowed = allowed_types(file_type).ok_or_else(|| unknown_category(file_type))?;
+    let declared = normalize(declared);
+    if ACTIVE_TYPES.contains(&declared.as_str()) {
+        return Err(ServiceError::UnsupportedMediaType(format!("{} uploads are not allowed", declared)));
+    }
+    // A generic declaration is settled by sniffing on confirm
+    if declared != OCTET_STREAM && !allowed.contains(&declared.as_str()) {
+        return Err(ServiceError::UnsupportedMediaType(format!(
+            "{} is not allowed for {} files; allowed: {}", declared, file_type, allowed.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// Sniff `content` and decide the type it is stored under
+pub fn verify(file_type: &str, declared: &str, content: &[u8]) -> Result<VerifiedType, ServiceError> {
+    let allowed = allowed_types(file_type).ok_or_else(|| unknown_category(file_type))?;
+    let declared = normalize(declared);
+    let detected = detect(content).unwrap_or(OCTET_STREAM);
+
+    if ACTIVE_TYPES.contains(&detected) {
+        return Err(ServiceError::UnsupportedMediaType(format!(
+            "Content is {}, which cannot be uploaded", detected
+        )));
+    }
+    // Keeps the declared subtype where sniffing cannot tell them apart (markdown vs plain text)
+    if compatible(&declared, detected) && allowed.contains(&declared.as_str()) {
+        return Ok(VerifiedType { content_type: declared, correction: None });
+    }
+    if !allowed.contains(&detected) {
+        return Err(ServiceError::UnsupportedMediaType(format!(
+            "Content is {}, which is not allowed for {} files", detected, file_type
+        )));
+    }
+    if declared != OCTET_STREAM && family(&declared) != family(detected) {
+        return Err(ServiceError::UnsupportedMediaType(format!(
+            "Declared type {} does not match content ({})", declared, detected
+        )));
+    }
+
+    Ok(VerifiedType {
+        content_type: detected.to_string(),
+        correction: Some(TypeCorrection { declared, detected: detected.to_string() }),
+    })
+}
+
+fn unknown_category(file_type: &str) -> ServiceError {
+    ServiceError::BadRequest(format!(
+        "Unknown file_type '{}'; expected cover, manuscript, audio, video, image, document or other", file_type
+    ))
+}
+
+/// Lowercase, without parameters, with common aliases folded
+fn normalize(content_type: &str) -> String {
+    let base = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    match base.as_str() {
+        "" => OCTET_STREAM.to_string(),
+        "image/jpg" | "image/pjpeg" => "image/jpeg".into(),
+        "audio/mp3" | "audio/x-mp3" => "audio/mpeg".into(),
+        "audio/x-wav" | "audio/wave" | "audio/vnd.wave" => "audio/wav".into(),
+        "audio/x-flac" => "audio/flac".into(),
+        "audio/x-m4a" | "audio/m4a" => "audio/mp4".into(),
+        "text/rtf" => "application/rtf".into(),
+        "text/x-markdown" => "text/markdown".into(),
+        _ => base,
+    }
+}
+
+fn family(content_type: &str) -> &str {
+    content_type.split('/').next().unwrap_or_default()
+}
+
+fn compatible(declared: &str, detected: &str) -> bool {
+    declared == detected
+        || (detected == "text/plain" && matches!(declared, "text/markdown" | "text/csv" | "application/json"))
+        // Sniffing only recognizes Word and OpenDocument files by their first entries
+        || (detected == "application/zip" && matches!(
+            declared,
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+                | "application/vnd.oasis.opendocument.text"
+                | "application/epub+zip"
+        ))
+        // An MP4 container with only an audio track is still audio/mp4
+        || (detected == "video/mp4" && declared == "audio/mp4")
+}
+
+//=============================================================================
+// Detection
+//=============================================================================
+
+/// Type of `content` from its leading bytes; `None` for unrecognized binary
+pub fn detect(content: &[u8]) -> Option<&'static str> {
+    let starts = |magic: &[u8]| content.starts_with(magic);
+    let at = |offset: usize, magic: &[u8]| content.len() >= offset + magic.len() && &content[offset..offset + magic.len()] == magic;
+
+    if starts(&[0xFF, 0xD8, 0xFF]) {
+        return Some("image/jpeg");
+    }
+    if starts(b"\x89PNG\r\n\x1a\n") {
+        return Some("image/png");
+    }
+    if starts(b"GIF87a") || starts(b"GIF89a") {
+        return Some("image/gif");
+    }
+    if starts(b"RIFF") && at(8, b"WEBP") {
+        return Some("image/webp");
+    }
+    if starts(b"RIFF") && at(8, b"WAVE") {
+        return Some("audio/wav");
+    }
+    if starts(b"%PDF-") {
+        return Some("application/pdf");
+    }
+    if starts(b"PK\x03\x04") {
+        return Some(detect_zip(content));
+    }
+    if starts(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]) {
+        return Some("application/msword");
+    }
+    if starts(b"{\\rtf") {
+        return Some("application/rtf");
+    }
+    if starts(b"ID3") {
+        return Some("audio/mpeg");
+    }
+    if starts(b"fLaC") {
+        return Some("audio/flac");
+    }
+    if starts(b"OggS") {
+        return Some("audio/ogg");
+    }
+    if at(4, b"ftyp") {
+        return Some(match content.get(8..12) {
+            Some(b"M4A ") | Some(b"M4B ") => "audio/mp4",
+            Some(b"qt  ") => "video/quicktime",
+            _ => "video/mp4",
+        });
+    }
+    if starts(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        return Some("video/webm");
+    }
+    if content.len() >= 2 && content[0] == 0xFF && content[1] & 0xE0 == 0xE0 {
+        // MPEG frame sync; layer bits of 00 mean an ADTS AAC stream
+        return Some(if content[1] & 0x06 == 0 { "audio/aac" } else { "audio/mpeg" });
+    }
+
+    detect_text(content)
+}
+
+/// Office and EPUB files are ZIPs; their first entries give them away
+fn detect_zip(content: &[u8]) -> &'static str {
+    let head = &content[..content.len().min(SNIFF_WINDOW)];
+    let contains = |needle: &[u8]| head.windows(needle.len()).any(|w| w == needle);
+
+    if contains(b"mimetypeapplication/epub+zip") {
+        "application/epub+zip"
+    } else if contains(b"mimetypeapplication/vnd.oasis.opendocument.text") {
+        "application/vnd.oasis.opendocument.text"
+    } else if contains(b"word/") {
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+    } else {
+        "application/zip"
+    }
+}
+
+/// Markup browsers would render, else plain text, else `None` for binary
+fn detect_text(content: &[u8]) -> Option<&'static str> {
+    let head = &content[..content.len().min(SNIFF_WINDOW)];
+    if head.is_empty() || head.contains(&0) {
+        return None;
+    }
+
+    let trimmed = head.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(head);
+    let start = trimmed.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(trimmed.len());
+    let lower = trimmed[start..].to_ascii_lowercase();
+    let markup = [
+        (&b"<!doctype html"[..], "text/html"),
+        (b"<html", "text/html"),
+        (b"<head", "text/html"),
+        (b"<body", "text/html"),
+        (b"<script", "text/html"),
+        (b"<iframe", "text/html"),
+        (b"<svg", "image/svg+xml"),
+        (b"<?xml", "application/xml"),
+    ];
+    if let Some((_, content_type)) = markup.iter().find(|(tag, _)| lower.starts_with(tag)) {
+        return Some(*content_type);
+    }
+    // Browsers sniff HTML anywhere near the top, so a late <script> still counts
+    if [&b"<script"[..], b"<svg", b"<html"].iter().any(|tag| lower.windows(tag.len()).any(|w| w == *tag)) {
+        return Some("text/html");
+    }
+
+    // A multi-byte character cut off at the window edge is still text
+    match std::str::from_utf8(head) {
+        Ok(_) => Some("text/plain"),
+        Err(e) if e.error_len().is_none() => Some("text/plain"),
+        Err(_) => None,
+    }
+}
@@ -1,22 +1,10 @@
 //! Data models for the Storage Service
 
+use crate::encryption::Envelope;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use std::collections::HashMap;
 
-//=============================================================================
-// S3 Configuration
-//=============================================================================
-
-#[derive(Debug, Clone)]
-pub struct S3Config {
-    pub endpoint: String,
-    pub region: String,
-    pub bucket: String,
-    pub access_key: String,
-    pub secret_key: String,
-}
-
 //=============================================================================
 // File Models
 //=============================================================================
@@ -32,7 +20,16 @@ pub struct FileMetadata {
     pub file_type: String,
     #[serde(default)]
     pub metadata: HashMap<String, serde_json::Value>,
+    pub collection_id: Option<Uuid>,
     pub created_at: String,
+    /// Present when the stored bytes are client-side encrypted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<Envelope>,
+    /// Book the file is linked to, if any
+    #[serde(default)]
+    pub book_id: Option<Uuid>,
+    #[serde(default)]
+    pub chapter_id: Option<Uuid>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,6 +39,7 @@ pub struct FileSummary {
     pub content_type: String,
     pub size: i64,
     pub file_type: String,
+    pub collection_id: Option<Uuid>,
     pub created_at: String,
 }
 
@@ -58,6 +56,10 @@ pub struct DirectUploadRequest {
     pub size: i64,
     #[serde(default)]
     pub metadata: HashMap<String, serde_json::Value>,
+    /// Collection to file the upload under; root when absent
+    pub collection_id: Option<Uuid>,
+    /// Marks `content` as client-side encrypted; `content_type` is the plaintext's
+    pub encryption: Option<Envelope>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -66,6 +68,27 @@ pub struct PresignedUploadRequest {
     pub content_type: String,
     pub file_type: String,
     pub size: i64,
+    /// The client will PUT ciphertext; upload headers use application/octet-stream
+    #[serde(default)]
+    pub encrypted: bool,
+}
+
+/// Sent by the client once a presigned PUT has completed
+#[derive(Debug, Deserialize)]
+pub struct ConfirmUploadRequest {
+    pub file_id: Uuid,
+    pub s3_key: String,
+    pub filename: String,
+    pub content_type: String,
+    /// Size the client uploaded; must match the stored object
+    pub size: i64,
+    /// Optional hex SHA-256 computed client-side
+    pub checksum: Option<String>,
+    #[serde(default)]
+    pub metadata: HashMap<String, serde_json::Value>,
+    pub collection_id: Option<Uuid>,
+    /// Marks the uploaded object as client-side encrypted
+    pub encryption: Option<Envelope>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -0,0 +1,192 @@
+//! Public assets
+//!
+//! A file the owner publishes gets an unguessable token and is served at
+//! `GET /public/:token` without `X-User-Id`, with cache headers a CDN can
+//! honour. The token is stable while the link is live, so republishing
+//! returns the same URL. Revoking the link makes the path 404 at once; copies
+//! a CDN already holds expire within `public_asset_max_age` seconds.
+
+use crate::error::ServiceError;
+use spin_sdk::http::{Request, Response};
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use uuid::Uuid;
+
+/// Bounds how long a revoked asset can linger in caches
+const DEFAULT_MAX_AGE_SECS: i64 = 3600;
+
+/// Public assets are proxied through the service, so keep them small
+const MAX_PUBLIC_SIZE: i64 = 25 * 1024 * 1024;
+
+//=============================================================================
+// Owner Endpoints
+//=============================================================================
+
+/// POST /files/:id/publish - Make a file publicly readable
+pub fn publish(conn: &Connection, user_id: &Uuid, file_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT filename, size FROM storage.files WHERE id = $1 AND user_id = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(file_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("File not found".into()))?;
+
+    let filename = String::decode(&row[0]).unwrap_or_default();
+    let size = i64::decode(&row[1]).unwrap_or(0);
+    if size > MAX_PUBLIC_SIZE {
+        return Err(ServiceError::BadRequest(format!(
+            "Files larger than {} MB cannot be made public", MAX_PUBLIC_SIZE / (1024 * 1024)
+        )));
+    }
+
+    // Reuse the live link so the public URL never changes under a CDN
+    let (token, created_at, created) = match active_link(conn, file_id)? {
+        Some((token, created_at)) => (token, created_at, false),
+        None => {
+            let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+            let insert = "INSERT INTO storage.public_assets (id, file_id, token, created_by)
+                          VALUES ($1, $2, $3, $4)
+                          ON CONFLICT (file_id) WHERE revoked_at IS NULL DO NOTHING";
+            conn.execute(insert, &[
+                ParameterValue::Str(Uuid::new_v4().to_string()),
+                ParameterValue::Str(file_id.to_string()),
+                ParameterValue::Str(token),
+                ParameterValue::Str(user_id.to_string()),
+            ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+            // A concurrent publish may have won the insert
+            let (token, created_at) = active_link(conn, file_id)?
+                .ok_or_else(|| ServiceError::Internal("Public link was not created".into()))?;
+            (token, created_at, true)
+        }
+    };
+
+    let url_path = public_path(&token, &filename);
+    crate::json_response(if created { 201 } else { 200 }, serde_json::json!({
+        "file_id": file_id,
+        "public": true,
+        "url_path": url_path,
+        "url": public_url(&url_path),
+        "published_at": created_at
+    }))
+}
+
+/// DELETE /files/:id/publish - Revoke the file's public link
+pub fn revoke(conn: &Connection, user_id: &Uuid, file_id: &Uuid) -> Result<Response, ServiceError> {
+    let update = "UPDATE storage.public_assets a SET revoked_at = NOW()
+                  FROM storage.files f
+                  WHERE a.file_id = f.id AND f.id = $1 AND f.user_id = $2 AND a.revoked_at IS NULL";
+    let revoked = conn.execute(update, &[
+        ParameterValue::Str(file_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    if revoked == 0 {
+        return Err(ServiceError::NotFound("File has no public link".into()));
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "file_id": file_id,
+        "public": false,
+        "cache_expires_within_secs": max_age()
+    }))
+}
+
+//=============================================================================
+// Public Endpoint
+//=============================================================================
+
+/// GET /public/:token[/:filename] - Serve a published file without auth
+pub fn serve(conn: &Connection, req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let token = path.strip_prefix("/public/")
+        .and_then(|rest| rest.split('/').next())
+        .filter(|t| !t.is_empty() && t.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| ServiceError::NotFound("Asset not found".into()))?;
+
+    let query = "SELECT f.s3_key, f.content_type, f.filename, f.checksum
+                 FROM storage.public_assets a
+                 JOIN storage.files f ON f.id = a.file_id
+                 WHERE a.token = $1 AND a.revoked_at IS NULL";
+    let rows = conn.query(query, &[ParameterValue::Str(token.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Asset not found".into()))?;
+
+    let s3_key = String::decode(&row[0]).unwrap_or_default();
+    let content_type = String::decode(&row[1]).unwrap_or_else(|_| "application/octet-stream".into());
+    let filename = String::decode(&row[2]).unwrap_or_default();
+    // Sanitizing rewrites the object in place, so the checksum doubles as the ETag
+    let etag = String::decode(&row[3]).ok().map(|c| format!("\"{}\"", c));
+    let cache_control = format!("public, max-age={}", max_age());
+
+    let if_none_match = req.header("If-None-Match").and_then(|h| h.as_str());
+    if let (Some(etag), Some(presented)) = (etag.as_deref(), if_none_match) {
+        if presented.split(',').any(|candidate| candidate.trim() == etag) {
+            return Ok(Response::builder()
+                .status(304)
+                .header("ETag", etag)
+                .header("Cache-Control", cache_control.as_str())
+                .body(())
+                .build());
+        }
+    }
+
+    let content = crate::backend::configured()?.get(&s3_key)?;
+
+    let mut response = Response::builder();
+    response
+        .status(200)
+        .header("Content-Type", content_type.as_str())
+        .header("Content-Disposition", format!("inline; filename=\"{}\"", filename.replace('"', "")))
+        .header("Cache-Control", cache_control.as_str())
+        .header("Access-Control-Allow-Origin", "*")
+        .header("X-Content-Type-Options", "nosniff");
+    if let Some(ref etag) = etag {
+        response.header("ETag", etag.as_str());
+    }
+    Ok(response.body(content).build())
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+fn active_link(conn: &Connection, file_id: &Uuid) -> Result<Option<(String, String)>, ServiceError> {
+    let query = "SELECT token, created_at FROM storage.public_assets WHERE file_id = $1 AND revoked_at IS NULL";
+    let rows = conn.query(query, &[ParameterValue::Str(file_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first().map(|row| {
+        (String::decode(&row[0]).unwrap_or_default(), String::decode(&row[1]).unwrap_or_default())
+    }))
+}
+
+/// The filename segment is cosmetic (served content is chosen by token) but
+/// gives CDNs and browsers a meaningful extension
+fn public_path(token: &str, filename: &str) -> String {
+    let name: String = filename.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+    if name.trim_matches(['-', '.']).is_empty() {
+        format!("/public/{}", token)
+    } else {
+        format!("/public/{}/{}", token, name)
+    }
+}
+
+/// Absolute URL when `public_base_url` (e.g. the CDN origin) is configured
+fn public_url(path: &str) -> Option<String> {
+    variables::get("public_base_url")
+        .ok()
+        .filter(|base| !base.is_empty())
+        .map(|base| format!("{}{}", base.trim_end_matches('/'), path))
+}
+
+fn max_age() -> i64 {
+    variables::get("public_asset_max_age")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v| *v >= 0)
+        .unwrap_or(DEFAULT_MAX_AGE_SECS)
+}
@@ -1,4 +1,198 @@
-//! S3 utilities
+//! S3-compatible backends
 //!
-//! Additional S3 helper functions are implemented in lib.rs
+//! Amazon S3 and MinIO are reached through SigV4 presigned URLs. Google Cloud
+//! Storage's XML API implements the same scheme under its own names, so
+//! [`SigV4`] takes a [`Dialect`] and the GCS backend is this one with the
+//! Google dialect.
 
+use crate::backend::{self, StorageBackend, UrlMethod, UrlOptions};
+use crate::error::ServiceError;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use spin_sdk::variables;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Names that differ between S3's SigV4 and GCS's V4 signing
+pub struct Dialect {
+    pub backend: &'static str,
+    pub algorithm: &'static str,
+    pub key_prefix: &'static str,
+    pub service: &'static str,
+    pub request_type: &'static str,
+    pub param_prefix: &'static str,
+    pub copy_source_header: &'static str,
+}
+
+pub const AWS: Dialect = Dialect {
+    backend: "s3",
+    algorithm: "AWS4-HMAC-SHA256",
+    key_prefix: "AWS4",
+    service: "s3",
+    request_type: "aws4_request",
+    param_prefix: "X-Amz",
+    copy_source_header: "x-amz-copy-source",
+};
+
+//=============================================================================
+// Signing
+//=============================================================================
+
+pub struct SigV4 {
+    pub dialect: &'static Dialect,
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+impl SigV4 {
+    /// Presigned path-style URL. `extra` are additional signed query
+    /// parameters, sorted by name; lowercase names already sort after the
+    /// `X-Amz-*`/`X-Goog-*` ones. `headers` are headers the request will
+    /// carry that must be signed, with lowercase names; the store rejects the
+    /// request if any of them is missing or has another value.
+    pub fn presign(
+        &self,
+        method: &str,
+        key: &str,
+        expires_secs: i64,
+        extra: &[(&str, String)],
+        headers: &[(&str, String)],
+    ) -> Result<String, ServiceError> {
+        let date = Utc::now();
+        let date_str = date.format("%Y%m%dT%H%M%SZ").to_string();
+        let date_short = date.format("%Y%m%d").to_string();
+        let prefix = self.dialect.param_prefix;
+
+        let host = self.endpoint.trim_start_matches("http://").trim_start_matches("https://");
+        let credential_scope = format!(
+            "{}/{}/{}/{}", date_short, self.region, self.dialect.service, self.dialect.request_type
+        );
+        let credential = format!("{}/{}", self.access_key, credential_scope);
+        let mut signed: Vec<(&str, String)> = std::iter::once(("host", host.to_string()))
+            .chain(headers.iter().map(|(name, value)| (*name, value.trim().to_string())))
+            .collect();
+        signed.sort_by(|a, b| a.0.cmp(b.0));
+        let signed_headers = signed.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+
+        let mut query_params = format!(
+            "{p}-Algorithm={}&{p}-Credential={}&{p}-Date={}&{p}-Expires={}&{p}-SignedHeaders={}",
+            self.dialect.algorithm,
+            backend::uri_encode(&credential),
+            date_str,
+            expires_secs,
+            backend::uri_encode(&signed_headers),
+            p = prefix
+        );
+        for (name, value) in extra {
+            query_params.push_str(&format!("&{}={}", name, backend::uri_encode(value)));
+        }
+
+        let path = format!("/{}/{}", self.bucket, backend::encode_key(key));
+        let canonical_headers: String = signed.iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value))
+            .collect();
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\nUNSIGNED-PAYLOAD",
+            method, path, query_params, canonical_headers, signed_headers
+        );
+
+        let string_to_sign = format!(
+            "{}\n{}\n{}\n{}",
+            self.dialect.algorithm,
+            date_str,
+            credential_scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signature = self.sign(&date_short, &string_to_sign)?;
+
+        Ok(format!(
+            "{}{}?{}&{}-Signature={}",
+            self.endpoint, path, query_params, prefix, signature
+        ))
+    }
+
+    fn sign(&self, date: &str, string_to_sign: &str) -> Result<String, ServiceError> {
+        let k_date = hmac_sha256(format!("{}{}", self.dialect.key_prefix, self.secret_key).as_bytes(), date.as_bytes())?;
+        let k_region = hmac_sha256(&k_date, self.region.as_bytes())?;
+        let k_service = hmac_sha256(&k_region, self.dialect.service.as_bytes())?;
+        let k_signing = hmac_sha256(&k_service, self.dialect.request_type.as_bytes())?;
+        let signature = hmac_sha256(&k_signing, string_to_sign.as_bytes())?;
+        Ok(hex::encode(signature))
+    }
+}
+
+pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> Result<Vec<u8>, ServiceError> {
+    let mut mac = HmacSha256::new_from_slice(key)
+        .map_err(|e| ServiceError::Internal(format!("HMAC error: {}", e)))?;
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+//=============================================================================
+// Backend
+//=============================================================================
+
+pub struct S3Backend {
+    signer: SigV4,
+}
+
+impl S3Backend {
+    pub fn new(signer: SigV4) -> Self {
+        S3Backend { signer }
+    }
+
+    pub fn from_variables() -> Result<Self, ServiceError> {
+        Ok(S3Backend::new(SigV4 {
+            dialect: &AWS,
+            endpoint: variables::get("s3_endpoint")
+                .unwrap_or_else(|_| "http://minio:9000".into()),
+            region: variables::get("s3_region")
+                .unwrap_or_else(|_| "us-east-1".into()),
+            bucket: variables::get("s3_bucket")
+                .unwrap_or_else(|_| "authorworks".into()),
+            access_key: variables::get("s3_access_key")
+                .map_err(|_| ServiceError::Internal("S3_ACCESS_KEY not configured".into()))?,
+            secret_key: variables::get("s3_secret_key")
+                .map_err(|_| ServiceError::Internal("S3_SECRET_KEY not configured".into()))?,
+        }))
+    }
+}
+
+impl StorageBackend for S3Backend {
+    fn name(&self) -> &'static str {
+        self.signer.dialect.backend
+    }
+
+    fn signed_url(&self, method: UrlMethod, key: &str, expires_secs: i64, options: &UrlOptions) -> Result<String, ServiceError> {
+        let mut extra = Vec::new();
+        if let Some(ref disposition) = options.response_content_disposition {
+            extra.push(("response-content-disposition", disposition.clone()));
+        }
+        if let Some(ref content_type) = options.response_content_type {
+            extra.push(("response-content-type", content_type.clone()));
+        }
+
+        // Signing the body's length and type is what makes the store enforce them
+        let mut headers = Vec::new();
+        if let Some(length) = options.upload_content_length {
+            headers.push(("content-length", length.to_string()));
+        }
+        if let Some(ref content_type) = options.upload_content_type {
+            headers.push(("content-type", content_type.clone()));
+        }
+        self.signer.presign(method.as_str(), key, expires_secs, &extra, &headers)
+    }
+
+    fn copy(&self, source_key: &str, dest_key: &str) -> Result<(), ServiceError> {
+        let source = format!("{}/{}", self.signer.bucket, backend::encode_key(source_key));
+        let headers = [(self.signer.dialect.copy_source_header, source)];
+        let url = self.signer.presign("PUT", dest_key, 300, &[], &headers)?;
+
+        let reply = backend::send(UrlMethod::Put, &url, &headers, None)?;
+        backend::expect_success(self.name(), "copy", &reply)
+    }
+}
@@ -0,0 +1,212 @@
+//! Image metadata sanitization
+//!
+//! Strips EXIF, XMP, IPTC, and text metadata from JPEG, PNG, and WebP files so
+//! GPS coordinates, camera serials, and editing history never reach S3. Pixel
+//! data and colour information (ICC profiles, Adobe colour transforms) are
+//! kept. Formats are detected from magic bytes rather than the declared
+//! content type; anything else passes through untouched.
+
+use spin_sdk::variables;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    WebP,
+}
+
+impl ImageFormat {
+    pub fn detect(content: &[u8]) -> Option<ImageFormat> {
+        if content.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(ImageFormat::Jpeg)
+        } else if content.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(ImageFormat::Png)
+        } else if content.len() >= 12 && &content[0..4] == b"RIFF" && &content[8..12] == b"WEBP" {
+            Some(ImageFormat::WebP)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpeg",
+            ImageFormat::Png => "png",
+            ImageFormat::WebP => "webp",
+        }
+    }
+}
+
+pub struct Sanitized {
+    pub content: Vec<u8>,
+    pub format: Option<ImageFormat>,
+    /// Names of the segments/chunks that were dropped
+    pub removed: Vec<String>,
+}
+
+/// Whether uploads of `file_type` should be sanitized.
+///
+/// `metadata_strip_file_types` is a comma-separated list of file types, or
+/// `*` (the default) for every type.
+pub fn enabled_for(file_type: &str) -> bool {
+    let setting = variables::get("metadata_strip_file_types").unwrap_or_else(|_| "*".into());
+    setting
+        .split(',')
+        .map(|s| s.trim())
+        .any(|s| s == "*" || s.eq_ignore_ascii_case(file_type))
+}
+
+/// Strip metadata from a supported image. Malformed files are returned
+/// unchanged rather than rejected; the upload itself is not our concern here.
+pub fn strip_metadata(content: &[u8]) -> Sanitized {
+    let format = ImageFormat::detect(content);
+    let result = match format {
+        Some(ImageFormat::Jpeg) => strip_jpeg(content),
+        Some(ImageFormat::Png) => strip_png(content),
+        Some(ImageFormat::WebP) => strip_webp(content),
+        None => None,
+    };
+
+    match result {
+        Some((content, removed)) => Sanitized { content, format, removed },
+        None => Sanitized { content: content.to_vec(), format, removed: Vec::new() },
+    }
+}
+
+//=============================================================================
+// JPEG
+//=============================================================================
+
+/// Drop APP1 (EXIF/XMP), APP3-APP13 (including Photoshop/IPTC), APP15, and
+/// comments. APP0 (JFIF), APP2 (ICC profile), and APP14 (Adobe) are kept.
+fn strip_jpeg(content: &[u8]) -> Option<(Vec<u8>, Vec<String>)> {
+    let mut out = Vec::with_capacity(content.len());
+    let mut removed = Vec::new();
+    out.extend_from_slice(&content[..2]);
+    let mut pos = 2;
+
+    while pos + 4 <= content.len() {
+        if content[pos] != 0xFF {
+            return None;
+        }
+        let marker = content[pos + 1];
+
+        // Fill bytes before a marker
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+
+        // Standalone markers carry no length
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            out.extend_from_slice(&content[pos..pos + 2]);
+            pos += 2;
+            continue;
+        }
+
+        let length = u16::from_be_bytes([content[pos + 2], content[pos + 3]]) as usize;
+        let end = pos + 2 + length;
+        if length < 2 || end > content.len() {
+            return None;
+        }
+
+        // Start of scan: the rest is entropy-coded image data
+        if marker == 0xDA {
+            out.extend_from_slice(&content[pos..]);
+            return Some((out, removed));
+        }
+
+        let strip = match marker {
+            0xE1 => Some(if content[pos + 4..end].starts_with(b"Exif") { "EXIF" } else { "XMP" }),
+            0xE3..=0xED | 0xEF => Some("APP"),
+            0xFE => Some("COM"),
+            _ => None,
+        };
+
+        match strip {
+            Some(name) => removed.push(name.to_string()),
+            None => out.extend_from_slice(&content[pos..end]),
+        }
+        pos = end;
+    }
+
+    None
+}
+
+//=============================================================================
+// PNG
+//=============================================================================
+
+const PNG_METADATA_CHUNKS: [&[u8; 4]; 5] = [b"eXIf", b"tEXt", b"zTXt", b"iTXt", b"tIME"];
+
+fn strip_png(content: &[u8]) -> Option<(Vec<u8>, Vec<String>)> {
+    let mut out = Vec::with_capacity(content.len());
+    let mut removed = Vec::new();
+    out.extend_from_slice(&content[..8]);
+    let mut pos = 8;
+
+    while pos + 12 <= content.len() {
+        let length = u32::from_be_bytes([content[pos], content[pos + 1], content[pos + 2], content[pos + 3]]) as usize;
+        let chunk_type = &content[pos + 4..pos + 8];
+        let end = pos.checked_add(12)?.checked_add(length)?;
+        if end > content.len() {
+            return None;
+        }
+
+        if PNG_METADATA_CHUNKS.iter().any(|t| t.as_slice() == chunk_type) {
+            removed.push(String::from_utf8_lossy(chunk_type).into_owned());
+        } else {
+            out.extend_from_slice(&content[pos..end]);
+        }
+
+        pos = end;
+        if chunk_type == b"IEND" {
+            return Some((out, removed));
+        }
+    }
+
+    None
+}
+
+//=============================================================================
+// WebP
+//=============================================================================
+
+const VP8X_XMP_FLAG: u8 = 0x04;
+const VP8X_EXIF_FLAG: u8 = 0x08;
+
+fn strip_webp(content: &[u8]) -> Option<(Vec<u8>, Vec<String>)> {
+    let mut body = Vec::with_capacity(content.len());
+    let mut removed = Vec::new();
+    let mut pos = 12;
+
+    while pos + 8 <= content.len() {
+        let fourcc = &content[pos..pos + 4];
+        let size = u32::from_le_bytes([content[pos + 4], content[pos + 5], content[pos + 6], content[pos + 7]]) as usize;
+        // Chunks are padded to an even length
+        let end = pos.checked_add(8)?.checked_add(size + (size & 1))?.min(content.len());
+        if pos + 8 + size > content.len() {
+            return None;
+        }
+
+        match fourcc {
+            b"EXIF" | b"XMP " => removed.push(String::from_utf8_lossy(fourcc).trim().to_string()),
+            b"VP8X" => {
+                let start = body.len();
+                body.extend_from_slice(&content[pos..end]);
+                if size > 0 {
+                    body[start + 8] &= !(VP8X_XMP_FLAG | VP8X_EXIF_FLAG);
+                }
+            }
+            _ => body.extend_from_slice(&content[pos..end]),
+        }
+        pos = end;
+    }
+
+    let mut out = Vec::with_capacity(body.len() + 12);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&((body.len() + 4) as u32).to_le_bytes());
+    out.extend_from_slice(b"WEBP");
+    out.extend_from_slice(&body);
+    Some((out, removed))
+}
@@ -0,0 +1,16 @@
+//! Storage routes open to delegated credentials
+//!
+//! Files, grants, collections and the vault belong to the account, not to a
+//! book, so no scope covers them. A delegated credential can list the files
+//! linked to a book it may read, and published files are public anyway.
+
+use authorworks_access::{scopes, Route};
+use spin_sdk::http::Method;
+
+pub fn route_scope(method: &Method, path: &str) -> Option<Route> {
+    match method {
+        Method::Get if path.starts_with("/public/") => Some(Route::Open),
+        Method::Get if path.starts_with("/books/") && path.ends_with("/files") => Some(Route::Scope(scopes::BOOKS_READ)),
+        _ => None,
+    }
+}
@@ -0,0 +1,269 @@
+//! Transcoding
+//!
+//! Narrators upload lossless WAVs and camera masters that are too heavy to
+//! stream. `POST /files/:id/transcode` queues a `transcode` job on
+//! `media.jobs`; the media worker encodes each requested variant with FFmpeg,
+//! stores it as an ordinary file and records it in `storage.file_derivatives`.
+//! Finished variants show up under `metadata.variants` of the source file.
+//!
+//! Formats:
+//! - `opus` - Opus audio in an Ogg container; from a video source only the
+//!   audio track is kept
+//! - `mp4` - AAC audio in an MP4 container for audio sources, H.264 + AAC for
+//!   video sources (the bitrate is the video bitrate)
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+/// Keeps one request from fanning out into a long encode queue
+const MAX_VARIANTS: usize = 6;
+
+/// Number of past jobs shown by `GET /files/:id/transcode`
+const JOB_HISTORY_LIMIT: i32 = 20;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TranscodeRequest {
+    /// Defaults to a sensible streaming ladder for the source's media kind
+    pub variants: Option<Vec<VariantSpec>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VariantSpec {
+    pub format: String,
+    pub bitrate_kbps: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlayableVariant {
+    pub file_id: Uuid,
+    pub format: String,
+    pub bitrate_kbps: i32,
+    pub content_type: String,
+    pub size: i64,
+    pub duration_seconds: Option<f64>,
+    pub created_at: String,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /files/:id/transcode - Queue streamable variants of an audio or video file
+pub fn enqueue(conn: &Connection, user_id: &Uuid, file_id: &Uuid, body: TranscodeRequest) -> Result<Response, ServiceError> {
+    let media = source_media(conn, user_id, file_id)?;
+
+    let requested = match body.variants {
+        Some(variants) if !variants.is_empty() => variants,
+        Some(_) => return Err(ServiceError::BadRequest("variants must not be empty".into())),
+        None => default_variants(media),
+    };
+    if requested.len() > MAX_VARIANTS {
+        return Err(ServiceError::BadRequest(format!("At most {} variants per request", MAX_VARIANTS)));
+    }
+
+    let mut variants: Vec<VariantSpec> = Vec::new();
+    for spec in requested {
+        let spec = VariantSpec { format: spec.format.trim().to_ascii_lowercase(), bitrate_kbps: spec.bitrate_kbps };
+        validate_variant(media, &spec)?;
+        if !variants.contains(&spec) {
+            variants.push(spec);
+        }
+    }
+
+    // Skip anything already encoded or already waiting in the queue
+    let existing: Vec<VariantSpec> = playable_variants(conn, file_id)?.into_iter()
+        .map(|v| VariantSpec { format: v.format, bitrate_kbps: v.bitrate_kbps })
+        .chain(queued_variants(conn, file_id)?)
+        .collect();
+    let skipped: Vec<VariantSpec> = variants.iter().filter(|v| existing.contains(v)).cloned().collect();
+    variants.retain(|v| !existing.contains(v));
+
+    if variants.is_empty() {
+        return crate::json_response(200, serde_json::json!({
+            "file_id": file_id,
+            "job_id": null,
+            "queued": [],
+            "skipped": skipped
+        }));
+    }
+
+    let job_id = Uuid::new_v4();
+    let input = serde_json::json!({
+        "source_file_id": file_id,
+        "media": media,
+        "variants": variants
+    });
+    let insert = "INSERT INTO media.jobs (id, user_id, job_type, status, input, created_at)
+                  VALUES ($1, $2, 'transcode', 'pending', $3::jsonb, NOW())";
+    conn.execute(insert, &[
+        ParameterValue::Str(job_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(input.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    crate::json_response(202, serde_json::json!({
+        "file_id": file_id,
+        "job_id": job_id,
+        "status": "pending",
+        "queued": variants,
+        "skipped": skipped
+    }))
+}
+
+/// GET /files/:id/transcode - Job history and finished variants of a file
+pub fn status(conn: &Connection, user_id: &Uuid, file_id: &Uuid) -> Result<Response, ServiceError> {
+    source_media(conn, user_id, file_id)?;
+
+    let query = "SELECT id, status, progress, error, (input->'variants')::text, created_at::text, completed_at::text
+                 FROM media.jobs
+                 WHERE job_type = 'transcode' AND input->>'source_file_id' = $1
+                 ORDER BY created_at DESC
+                 LIMIT $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(file_id.to_string()),
+        ParameterValue::Int32(JOB_HISTORY_LIMIT),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let jobs: Vec<serde_json::Value> = rows.rows.iter().map(|row| {
+        let variants: serde_json::Value = String::decode(&row[4]).ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_else(|| serde_json::json!([]));
+        serde_json::json!({
+            "job_id": String::decode(&row[0]).unwrap_or_default(),
+            "status": String::decode(&row[1]).unwrap_or_default(),
+            "progress": i32::decode(&row[2]).unwrap_or(0),
+            "error": String::decode(&row[3]).ok(),
+            "variants": variants,
+            "created_at": String::decode(&row[5]).unwrap_or_default(),
+            "completed_at": String::decode(&row[6]).ok()
+        })
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "file_id": file_id,
+        "jobs": jobs,
+        "variants": playable_variants(conn, file_id)?
+    }))
+}
+
+//=============================================================================
+// Variants
+//=============================================================================
+
+/// Finished derivatives of a file, ordered for a player's source list
+pub fn playable_variants(conn: &Connection, file_id: &Uuid) -> Result<Vec<PlayableVariant>, ServiceError> {
+    let query = "SELECT d.derivative_file_id, d.format, d.bitrate_kbps, d.content_type, f.size,
+                        d.duration_seconds, d.created_at::text
+                 FROM storage.file_derivatives d
+                 JOIN storage.files f ON f.id = d.derivative_file_id
+                 WHERE d.source_file_id = $1
+                 ORDER BY d.format, d.bitrate_kbps DESC";
+    let rows = conn.query(query, &[ParameterValue::Str(file_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.iter().map(|row| PlayableVariant {
+        file_id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        format: String::decode(&row[1]).unwrap_or_default(),
+        bitrate_kbps: i32::decode(&row[2]).unwrap_or(0),
+        content_type: String::decode(&row[3]).unwrap_or_default(),
+        size: i64::decode(&row[4]).unwrap_or(0),
+        duration_seconds: f64::decode(&row[5]).ok(),
+        created_at: String::decode(&row[6]).unwrap_or_default(),
+    }).collect())
+}
+
+/// S3 keys of a file's derivatives, so deleting the source can clean them up
+pub fn derivative_keys(conn: &Connection, file_id: &Uuid) -> Result<Vec<(Uuid, String)>, ServiceError> {
+    let query = "SELECT f.id, f.s3_key
+                 FROM storage.file_derivatives d
+                 JOIN storage.files f ON f.id = d.derivative_file_id
+                 WHERE d.source_file_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(file_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.iter().filter_map(|row| {
+        let id = Uuid::parse_str(&String::decode(&row[0]).ok()?).ok()?;
+        Some((id, String::decode(&row[1]).unwrap_or_default()))
+    }).collect())
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+/// Media kind of an owned source file; derivatives are not transcoded again
+fn source_media(conn: &Connection, user_id: &Uuid, file_id: &Uuid) -> Result<&'static str, ServiceError> {
+    let query = "SELECT f.content_type,
+                        EXISTS (SELECT 1 FROM storage.file_derivatives d WHERE d.derivative_file_id = f.id)
+                 FROM storage.files f WHERE f.id = $1 AND f.user_id = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(file_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("File not found".into()))?;
+
+    if bool::decode(&row[1]).unwrap_or(false) {
+        return Err(ServiceError::BadRequest("File is already a transcoded variant; transcode its source instead".into()));
+    }
+
+    let content_type = String::decode(&row[0]).unwrap_or_default();
+    if content_type.starts_with("audio/") {
+        Ok("audio")
+    } else if content_type.starts_with("video/") {
+        Ok("video")
+    } else {
+        Err(ServiceError::UnsupportedMediaType(format!(
+            "Only audio and video files can be transcoded, got {}", content_type
+        )))
+    }
+}
+
+fn default_variants(media: &str) -> Vec<VariantSpec> {
+    let spec = |format: &str, bitrate_kbps: i32| VariantSpec { format: format.into(), bitrate_kbps };
+    match media {
+        "video" => vec![spec("mp4", 2500), spec("mp4", 1000)],
+        _ => vec![spec("opus", 64), spec("mp4", 128)],
+    }
+}
+
+fn validate_variant(media: &str, spec: &VariantSpec) -> Result<(), ServiceError> {
+    let (min, max) = match (spec.format.as_str(), media) {
+        ("opus", _) => (16, 256),
+        ("mp4", "video") => (300, 8000),
+        ("mp4", _) => (32, 320),
+        (other, _) => {
+            return Err(ServiceError::BadRequest(format!(
+                "Unsupported format '{}'; expected opus or mp4", other
+            )))
+        }
+    };
+    if spec.bitrate_kbps < min || spec.bitrate_kbps > max {
+        return Err(ServiceError::BadRequest(format!(
+            "bitrate_kbps for {} {} must be between {} and {}", media, spec.format, min, max
+        )));
+    }
+    Ok(())
+}
+
+/// Variants requested by transcode jobs that have not finished yet
+fn queued_variants(conn: &Connection, file_id: &Uuid) -> Result<Vec<VariantSpec>, ServiceError> {
+    let query = "SELECT (input->'variants')::text FROM media.jobs
+                 WHERE job_type = 'transcode' AND input->>'source_file_id' = $1
+                   AND status IN ('pending', 'processing')";
+    let rows = conn.query(query, &[ParameterValue::Str(file_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.iter()
+        .filter_map(|row| String::decode(&row[0]).ok())
+        .filter_map(|s| serde_json::from_str::<Vec<VariantSpec>>(&s).ok())
+        .flatten()
+        .collect())
+}
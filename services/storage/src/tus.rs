@@ -0,0 +1,554 @@
+//! Resumable uploads (tus 1.0.0)
+//!
+//! An alternative to `POST /upload` for large files on flaky connections,
+//! compatible with stock tus clients. Supported extensions: creation,
+//! creation-with-upload, expiration and termination.
+//! - `POST /upload/tus` creates an upload from `Upload-Length` and
+//!   `Upload-Metadata` (`filename`, `filetype`, and optionally `file_type`,
+//!   `collection_id` and `encryption`, the JSON envelope of a client-side
+//!   encrypted upload) and returns its URL in `Location`
+//! - `HEAD /upload/tus/:id` reports the stored `Upload-Offset`
+//! - `PATCH /upload/tus/:id` appends bytes at `Upload-Offset`
+//! - `DELETE /upload/tus/:id` abandons an upload
+//!
+//! Each PATCH body is written to the store as its own chunk object, and the
+//! offset only advances once the chunk is stored, so a client resumes from
+//! the last byte that actually arrived. When the offset reaches the length
+//! the chunks are assembled and stored like a direct upload (type sniffing,
+//! metadata stripping, checksum; ciphertext is stored as it came); the new
+//! file's id is returned in
+//! `X-File-Id`. If assembly fails for a transient reason, an empty PATCH at
+//! the final offset retries it.
+//!
+//! Uploads expire `tus_upload_expiry_hours` (24 by default) after their last
+//! PATCH. `POST /upload/tus/expire` removes expired uploads and their chunks.
+
+use crate::backend::StorageBackend;
+use crate::error::ServiceError;
+use crate::{collections, encryption, images, mime, sanitize};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use sha2::{Digest, Sha256};
+use spin_sdk::http::{Request, Response};
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use chrono::Utc;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,creation-with-upload,expiration,termination";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+const DEFAULT_EXPIRY_HOURS: i32 = 24;
+/// Largest PATCH body accepted; clients split bigger uploads
+const MAX_CHUNK_BYTES: usize = 16 * 1024 * 1024;
+const EXPIRE_BATCH: i64 = 100;
+
+/// RFC 7231 date, as `Upload-Expires` requires
+const HTTP_DATE_SQL: &str = "to_char(expires_at AT TIME ZONE 'UTC', 'Dy, DD Mon YYYY HH24:MI:SS \"GMT\"')";
+
+const EXPOSED_HEADERS: &str = "Location, Upload-Offset, Upload-Length, Upload-Expires, Tus-Resumable, Tus-Version, Tus-Extension, Tus-Max-Size, X-File-Id";
+
+struct Upload {
+    id: Uuid,
+    length: i64,
+    offset: i64,
+    filename: String,
+    content_type: String,
+    file_type: String,
+    collection_id: Option<Uuid>,
+    encryption: Option<encryption::Envelope>,
+    file_id: Option<Uuid>,
+    expires: String,
+    expired: bool,
+}
+
+//=============================================================================
+// Protocol
+//=============================================================================
+
+/// OPTIONS /upload/tus - Server capabilities (also answers CORS preflight)
+pub fn options() -> Response {
+    Response::builder()
+        .status(204)
+        .header("Tus-Resumable", TUS_VERSION)
+        .header("Tus-Version", TUS_VERSION)
+        .header("Tus-Extension", TUS_EXTENSIONS)
+        .header("Tus-Max-Size", crate::MAX_UPLOAD_SIZE.to_string())
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Access-Control-Allow-Methods", "POST, HEAD, PATCH, DELETE, OPTIONS")
+        .header("Access-Control-Allow-Headers", "Content-Type, Authorization, X-User-Id, Tus-Resumable, Upload-Length, Upload-Metadata, Upload-Offset, Upload-Defer-Length")
+        .header("Access-Control-Expose-Headers", EXPOSED_HEADERS)
+        .header("Access-Control-Max-Age", "86400")
+        .body(())
+        .build()
+}
+
+/// 412 for requests that do not speak this protocol version
+pub fn unsupported_version(req: &Request) -> Option<Response> {
+    if header(req, "Tus-Resumable") == Some(TUS_VERSION) {
+        return None;
+    }
+    Some(Response::builder()
+        .status(412)
+        .header("Tus-Version", TUS_VERSION)
+        .header("Access-Control-Allow-Origin", "*")
+        .body(())
+        .build())
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /upload/tus - Create an upload, optionally with its first bytes
+pub fn create(conn: &Connection, storage: &dyn StorageBackend, user_id: &Uuid, req: &Request) -> Result<Response, ServiceError> {
+    if header(req, "Upload-Defer-Length").is_some() {
+        return Err(ServiceError::BadRequest("Upload-Defer-Length is not supported; send Upload-Length".into()));
+    }
+    let length: i64 = header(req, "Upload-Length")
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|v| *v >= 0)
+        .ok_or_else(|| ServiceError::BadRequest("Upload-Length must be a non-negative integer".into()))?;
+    if length > crate::MAX_UPLOAD_SIZE {
+        return Err(ServiceError::PayloadTooLarge(format!(
+            "File too large. Maximum size is {} bytes", crate::MAX_UPLOAD_SIZE
+        )));
+    }
+
+    let mut metadata = parse_metadata(header(req, "Upload-Metadata").unwrap_or_default())?;
+    let filename = metadata.get("filename").map(|f| f.trim().to_string()).unwrap_or_default();
+    if filename.is_empty() || filename.chars().count() > 255 {
+        return Err(ServiceError::BadRequest("Upload-Metadata must include a filename of 1-255 characters".into()));
+    }
+    let content_type = metadata.get("filetype").cloned().unwrap_or_else(|| mime::OCTET_STREAM.to_string());
+    let file_type = metadata.get("file_type").cloned().unwrap_or_else(|| "other".to_string());
+    mime::check_declared(&file_type, &content_type)?;
+    let envelope = match metadata.get("encryption") {
+        Some(json) => {
+            let envelope = serde_json::from_str(json)
+                .map_err(|_| ServiceError::BadRequest("Upload-Metadata encryption must be a JSON envelope".into()))?;
+            Some(encryption::validate(envelope, &file_type, &content_type)?)
+        }
+        None => None,
+    };
+    // Kept validated, as assembly reads it back
+    if let Some(ref envelope) = envelope {
+        metadata.insert("encryption".into(), serde_json::to_string(envelope).unwrap_or_default());
+    }
+    let collection_id = match metadata.get("collection_id") {
+        Some(id) => {
+            let id = Uuid::parse_str(id).map_err(|_| ServiceError::BadRequest("Invalid collection_id".into()))?;
+            collections::ensure_owned(conn, user_id, &id)?;
+            Some(id)
+        }
+        None => None,
+    };
+
+    let id = Uuid::new_v4();
+    let insert = format!(
+        "INSERT INTO storage.tus_uploads
+         (id, user_id, upload_length, filename, content_type, file_type, collection_id, metadata, expires_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW() + make_interval(hours => $9))
+         RETURNING {}",
+        HTTP_DATE_SQL
+    );
+    let rows = conn.query(&insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Int64(length),
+        ParameterValue::Str(filename.clone()),
+        ParameterValue::Str(content_type.clone()),
+        ParameterValue::Str(file_type.clone()),
+        collection_id.map(|c| ParameterValue::Str(c.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(serde_json::to_string(&metadata).unwrap_or_else(|_| "{}".into())),
+        ParameterValue::Int32(expiry_hours()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    let expires = rows.rows.first().and_then(|row| String::decode(&row[0]).ok()).unwrap_or_default();
+
+    let mut upload = Upload {
+        id,
+        length,
+        offset: 0,
+        filename,
+        content_type,
+        file_type,
+        collection_id,
+        encryption: envelope,
+        file_id: None,
+        expires,
+        expired: false,
+    };
+
+    // creation-with-upload: the body carries the first chunk
+    let body = req.body();
+    if !body.is_empty() {
+        if header(req, "Content-Type") != Some(OFFSET_CONTENT_TYPE) {
+            return Err(ServiceError::UnsupportedMediaType(format!("Upload data must be sent as {}", OFFSET_CONTENT_TYPE)));
+        }
+        append(conn, storage, user_id, &mut upload, body)?;
+    }
+    if upload.offset == upload.length {
+        finish(conn, storage, user_id, &mut upload)?;
+    }
+
+    let mut response = Response::builder();
+    response
+        .status(201)
+        .header("Location", format!("{}/{}", base_url(), id))
+        .header("Tus-Resumable", TUS_VERSION)
+        .header("Upload-Offset", upload.offset.to_string())
+        .header("Upload-Expires", upload.expires.as_str())
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Access-Control-Expose-Headers", EXPOSED_HEADERS);
+    if let Some(file_id) = upload.file_id {
+        response.header("X-File-Id", file_id.to_string());
+    }
+    Ok(response.body(()).build())
+}
+
+/// HEAD /upload/tus/:id - Bytes stored so far
+pub fn head(conn: &Connection, user_id: &Uuid, upload_id: &Uuid) -> Result<Response, ServiceError> {
+    let upload = load(conn, user_id, upload_id)?;
+
+    let mut response = Response::builder();
+    response
+        .status(200)
+        .header("Tus-Resumable", TUS_VERSION)
+        .header("Upload-Offset", upload.offset.to_string())
+        .header("Upload-Length", upload.length.to_string())
+        .header("Upload-Expires", upload.expires.as_str())
+        .header("Cache-Control", "no-store")
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Access-Control-Expose-Headers", EXPOSED_HEADERS);
+    if let Some(file_id) = upload.file_id {
+        response.header("X-File-Id", file_id.to_string());
+    }
+    Ok(response.body(()).build())
+}
+
+/// PATCH /upload/tus/:id - Append bytes at the current offset
+pub fn patch(conn: &Connection, storage: &dyn StorageBackend, user_id: &Uuid, upload_id: &Uuid, req: &Request) -> Result<Response, ServiceError> {
+    let mut upload = load(conn, user_id, upload_id)?;
+
+    if header(req, "Content-Type") != Some(OFFSET_CONTENT_TYPE) {
+        return Err(ServiceError::UnsupportedMediaType(format!("PATCH requests must use {}", OFFSET_CONTENT_TYPE)));
+    }
+    let offset: i64 = header(req, "Upload-Offset")
+        .and_then(|v| v.trim().parse().ok())
+        .ok_or_else(|| ServiceError::BadRequest("Upload-Offset must be an integer".into()))?;
+    if offset != upload.offset {
+        return Err(ServiceError::Conflict(format!(
+            "Upload-Offset {} does not match the stored offset {}", offset, upload.offset
+        )));
+    }
+
+    let body = req.body();
+    if !body.is_empty() {
+        append(conn, storage, user_id, &mut upload, body)?;
+    }
+    if upload.offset == upload.length && upload.file_id.is_none() {
+        finish(conn, storage, user_id, &mut upload)?;
+    }
+
+    let mut response = Response::builder();
+    response
+        .status(204)
+        .header("Tus-Resumable", TUS_VERSION)
+        .header("Upload-Offset", upload.offset.to_string())
+        .header("Upload-Expires", upload.expires.as_str())
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Access-Control-Expose-Headers", EXPOSED_HEADERS);
+    if let Some(file_id) = upload.file_id {
+        response.header("X-File-Id", file_id.to_string());
+    }
+    Ok(response.body(()).build())
+}
+
+/// DELETE /upload/tus/:id - Abandon an upload and discard its chunks
+pub fn terminate(conn: &Connection, storage: &dyn StorageBackend, user_id: &Uuid, upload_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT 1 FROM storage.tus_uploads WHERE id = $1 AND user_id = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(upload_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if rows.rows.is_empty() {
+        return Err(ServiceError::NotFound("Upload not found".into()));
+    }
+
+    discard(conn, storage, upload_id)?;
+
+    Ok(Response::builder()
+        .status(204)
+        .header("Tus-Resumable", TUS_VERSION)
+        .header("Access-Control-Allow-Origin", "*")
+        .body(())
+        .build())
+}
+
+/// POST /upload/tus/expire - Remove expired uploads and their chunks (internal)
+pub fn expire(conn: &Connection, storage: &dyn StorageBackend) -> Result<Response, ServiceError> {
+    let query = "SELECT id FROM storage.tus_uploads WHERE expires_at <= NOW() ORDER BY expires_at LIMIT $1";
+    let rows = conn.query(query, &[ParameterValue::Int64(EXPIRE_BATCH)])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut expired = 0;
+    let mut errors = 0;
+    for row in &rows.rows {
+        let id = Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default();
+        // An unreachable store leaves the upload for the next run
+        match discard(conn, storage, &id) {
+            Ok(()) => expired += 1,
+            Err(_) => errors += 1,
+        }
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "expired": expired,
+        "errors": errors
+    }))
+}
+
+//=============================================================================
+// Chunks
+//=============================================================================
+
+fn append(conn: &Connection, storage: &dyn StorageBackend, user_id: &Uuid, upload: &mut Upload, body: &[u8]) -> Result<(), ServiceError> {
+    if body.len() > MAX_CHUNK_BYTES {
+        return Err(ServiceError::PayloadTooLarge(format!(
+            "A single PATCH may carry at most {} bytes", MAX_CHUNK_BYTES
+        )));
+    }
+    if upload.offset + body.len() as i64 > upload.length {
+        return Err(ServiceError::PayloadTooLarge(format!(
+            "Upload would exceed its Upload-Length of {} bytes", upload.length
+        )));
+    }
+
+    let key = format!("{}/tus/{}/{}-{}", user_id, upload.id, upload.offset, Uuid::new_v4());
+    storage.put(&key, body, OFFSET_CONTENT_TYPE)?;
+
+    // Advance the offset and record the chunk together, and only if no other
+    // request got there first
+    let advance = format!(
+        "WITH advanced AS (
+             UPDATE storage.tus_uploads
+             SET upload_offset = upload_offset + $3, updated_at = NOW(),
+                 expires_at = NOW() + make_interval(hours => $5)
+             WHERE id = $1 AND upload_offset = $2 AND file_id IS NULL
+             RETURNING id, upload_offset, expires_at
+         ), chunk AS (
+             INSERT INTO storage.tus_upload_chunks (upload_id, chunk_offset, size, s3_key)
+             SELECT id, $2, $3, $4 FROM advanced
+         )
+         SELECT upload_offset, {} FROM advanced",
+        HTTP_DATE_SQL
+    );
+    let rows = conn.query(&advance, &[
+        ParameterValue::Str(upload.id.to_string()),
+        ParameterValue::Int64(upload.offset),
+        ParameterValue::Int64(body.len() as i64),
+        ParameterValue::Str(key.clone()),
+        ParameterValue::Int32(expiry_hours()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let row = match rows.rows.first() {
+        Some(row) => row,
+        None => {
+            let _ = storage.delete(&key);
+            return Err(ServiceError::Conflict("Another request appended to this upload first".into()));
+        }
+    };
+    upload.offset = i64::decode(&row[0]).unwrap_or(upload.offset);
+    upload.expires = String::decode(&row[1]).unwrap_or_default();
+    Ok(())
+}
+
+/// Assemble the chunks and store them as a regular file
+fn finish(conn: &Connection, storage: &dyn StorageBackend, user_id: &Uuid, upload: &mut Upload) -> Result<(), ServiceError> {
+    let query = "SELECT chunk_offset, size, s3_key FROM storage.tus_upload_chunks
+                 WHERE upload_id = $1 ORDER BY chunk_offset";
+    let rows = conn.query(query, &[ParameterValue::Str(upload.id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut content = Vec::with_capacity(upload.length as usize);
+    for row in &rows.rows {
+        let chunk_offset = i64::decode(&row[0]).unwrap_or(-1);
+        if chunk_offset != content.len() as i64 {
+            return Err(ServiceError::Internal(format!("Upload {} has a gap at byte {}", upload.id, content.len())));
+        }
+        content.extend_from_slice(&storage.get(&String::decode(&row[2]).unwrap_or_default())?);
+    }
+    if content.len() as i64 != upload.length {
+        return Err(ServiceError::Internal(format!(
+            "Upload {} assembled to {} bytes, expected {}", upload.id, content.len(), upload.length
+        )));
+    }
+
+    // Content that fails sniffing or the image limits can never become a
+    // file; drop the upload
+    let checked = match upload.encryption {
+        Some(_) => Ok((encryption::stored_type(), None)),
+        None => mime::verify(&upload.file_type, &upload.content_type, &content).and_then(|verified| {
+            let image = images::validate(&upload.file_type, &verified.content_type, &content)?;
+            Ok((verified, image))
+        }),
+    };
+    let (verified, image) = match checked {
+        Ok(checked) => checked,
+        Err(e) => {
+            discard(conn, storage, &upload.id)?;
+            return Err(e);
+        }
+    };
+
+    let mut metadata: HashMap<String, serde_json::Value> = HashMap::new();
+    metadata.insert("upload_mode".into(), serde_json::json!("tus"));
+    if let Some(ref image) = image {
+        metadata.insert("image".into(), serde_json::json!({
+            "width": image.width,
+            "height": image.height,
+            "frames": image.frames
+        }));
+    }
+    if upload.encryption.is_none() && sanitize::enabled_for(&upload.file_type) {
+        let sanitized = sanitize::strip_metadata(&content);
+        if sanitized.format.is_some() {
+            metadata.insert("metadata_stripped".into(), serde_json::json!(sanitized.removed));
+            content = sanitized.content;
+        }
+    }
+    if let Some(ref correction) = verified.correction {
+        metadata.insert("content_type_correction".into(), serde_json::json!(correction));
+    }
+
+    let checksum = hex::encode(Sha256::digest(&content));
+    let file_id = Uuid::new_v4();
+    let extension = upload.filename.rsplit('.').next().unwrap_or("bin");
+    let s3_key = format!("{}/{}/{}.{}", user_id, upload.file_type, file_id, extension);
+    storage.put(&s3_key, &content, &verified.content_type)?;
+
+    let insert = "INSERT INTO storage.files
+                  (id, user_id, filename, s3_key, content_type, size, checksum, file_type, metadata, created_at, collection_id, encryption)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12::jsonb)";
+    conn.execute(insert, &[
+        ParameterValue::Str(file_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(upload.filename.clone()),
+        ParameterValue::Str(s3_key),
+        ParameterValue::Str(verified.content_type.clone()),
+        ParameterValue::Int64(content.len() as i64),
+        ParameterValue::Str(checksum),
+        ParameterValue::Str(upload.file_type.clone()),
+        ParameterValue::Str(serde_json::to_string(&metadata).unwrap_or_else(|_| "{}".into())),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+        upload.collection_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+        encryption::to_param(upload.encryption.as_ref()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    let update = "UPDATE storage.tus_uploads SET file_id = $2, completed_at = NOW(), updated_at = NOW() WHERE id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(upload.id.to_string()),
+        ParameterValue::Str(file_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    upload.file_id = Some(file_id);
+
+    // The file is stored; leftover chunks are only clutter until expiry
+    let _ = delete_chunks(conn, storage, &upload.id);
+    Ok(())
+}
+
+fn delete_chunks(conn: &Connection, storage: &dyn StorageBackend, upload_id: &Uuid) -> Result<(), ServiceError> {
+    let query = "SELECT s3_key FROM storage.tus_upload_chunks WHERE upload_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(upload_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    for row in &rows.rows {
+        storage.delete(&String::decode(&row[0]).unwrap_or_default())?;
+    }
+
+    let delete = "DELETE FROM storage.tus_upload_chunks WHERE upload_id = $1";
+    conn.execute(delete, &[ParameterValue::Str(upload_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    Ok(())
+}
+
+/// Remove an upload and its chunk objects; a stored file is kept
+fn discard(conn: &Connection, storage: &dyn StorageBackend, upload_id: &Uuid) -> Result<(), ServiceError> {
+    delete_chunks(conn, storage, upload_id)?;
+    let delete = "DELETE FROM storage.tus_uploads WHERE id = $1";
+    conn.execute(delete, &[ParameterValue::Str(upload_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    Ok(())
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+fn load(conn: &Connection, user_id: &Uuid, upload_id: &Uuid) -> Result<Upload, ServiceError> {
+    let query = format!(
+        "SELECT id, upload_length, upload_offset, filename, content_type, file_type, collection_id, file_id,
+                {}, expires_at <= NOW(), metadata->>'encryption'
+         FROM storage.tus_uploads WHERE id = $1 AND user_id = $2",
+        HTTP_DATE_SQL
+    );
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(upload_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first().ok_or_else(|| ServiceError::NotFound("Upload not found".into()))?;
+
+    let upload = Upload {
+        id: *upload_id,
+        length: i64::decode(&row[1]).unwrap_or(0),
+        offset: i64::decode(&row[2]).unwrap_or(0),
+        filename: String::decode(&row[3]).unwrap_or_default(),
+        content_type: String::decode(&row[4]).unwrap_or_default(),
+        file_type: String::decode(&row[5]).unwrap_or_default(),
+        collection_id: String::decode(&row[6]).ok().and_then(|id| Uuid::parse_str(&id).ok()),
+        file_id: String::decode(&row[7]).ok().and_then(|id| Uuid::parse_str(&id).ok()),
+        expires: String::decode(&row[8]).unwrap_or_default(),
+        expired: bool::decode(&row[9]).unwrap_or(false),
+        encryption: encryption::from_column(String::decode(&row[10]).ok()),
+    };
+    if upload.expired && upload.file_id.is_none() {
+        return Err(ServiceError::Gone("Upload has expired".into()));
+    }
+    Ok(upload)
+}
+
+/// `Upload-Metadata`: comma-separated `key base64value` pairs; the value may be omitted
+fn parse_metadata(value: &str) -> Result<HashMap<String, String>, ServiceError> {
+    let mut metadata = HashMap::new();
+    for pair in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (key, encoded) = pair.split_once(' ').unwrap_or((pair, ""));
+        let decoded = BASE64.decode(encoded.trim())
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or_else(|| ServiceError::BadRequest(format!("Upload-Metadata value for '{}' is not base64 UTF-8", key)))?;
+        metadata.insert(key.to_string(), decoded);
+    }
+    Ok(metadata)
+}
+
+fn header<'a>(req: &'a Request, name: &str) -> Option<&'a str> {
+    req.header(name).and_then(|h| h.as_str())
+}
+
+fn expiry_hours() -> i32 {
+    variables::get("tus_upload_expiry_hours")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_EXPIRY_HOURS)
+}
+
+/// Base of upload URLs in `Location`; set `tus_base_url` when the service
+/// sits behind a path prefix
+fn base_url() -> String {
+    variables::get("tus_base_url")
+        .ok()
+        .filter(|base| !base.is_empty())
+        .map(|base| base.trim_end_matches('/').to_string())
+        .unwrap_or_else(|| "/upload/tus".to_string())
+}
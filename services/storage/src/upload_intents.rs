@@ -0,0 +1,90 @@
+//! Presigned upload conditions
+//!
+//! A presigned upload is issued for one size and content type. S3 and GCS
+//! enforce both: the URL signs `Content-Length` and `Content-Type`, so the
+//! store refuses any other body. Azure SAS tokens cannot pin either, so
+//! `POST /upload/presigned/confirm` checks the stored object against the
+//! intent recorded here whatever the backend. An object that breaks the
+//! conditions is deleted rather than left in the bucket. Confirming an upload
+//! with no intent for the caller is refused.
+
+use crate::error::ServiceError;
+use crate::mime;
+use chrono::{DateTime, Utc};
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+pub struct UploadIntent {
+    pub s3_key: String,
+    pub file_type: String,
+    /// Declared type of the file itself
+    pub content_type: String,
+    pub size: i64,
+    pub encrypted: bool,
+}
+
+impl UploadIntent {
+    /// The type the object must be stored with; ciphertext is opaque bytes
+    pub fn stored_type(&self) -> &str {
+        if self.encrypted { mime::OCTET_STREAM } else { self.content_type.as_str() }
+    }
+}
+
+/// Remember what a presigned upload was issued for
+pub fn record(conn: &Connection, user_id: &Uuid, file_id: &Uuid, intent: &UploadIntent, expires_at: DateTime<Utc>) -> Result<(), ServiceError> {
+    let insert = "INSERT INTO storage.upload_intents
+                  (file_id, user_id, s3_key, file_type, content_type, size, encrypted, expires_at, created_at)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW())";
+    conn.execute(insert, &[
+        ParameterValue::Str(file_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(intent.s3_key.clone()),
+        ParameterValue::Str(intent.file_type.clone()),
+        ParameterValue::Str(intent.content_type.clone()),
+        ParameterValue::Int64(intent.size),
+        ParameterValue::Boolean(intent.encrypted),
+        ParameterValue::Str(expires_at.to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    Ok(())
+}
+
+/// The caller's intent for `file_id`. Uploads are confirmed right after they
+/// finish, so an intent a day past its URL's expiry is treated as gone.
+pub fn load(conn: &Connection, user_id: &Uuid, file_id: &Uuid) -> Result<UploadIntent, ServiceError> {
+    let query = "SELECT s3_key, file_type, content_type, size, encrypted
+                 FROM storage.upload_intents
+                 WHERE file_id = $1 AND user_id = $2 AND expires_at > NOW() - INTERVAL '1 day'";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(file_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("No presigned upload with this ID".into()))?;
+
+    Ok(UploadIntent {
+        s3_key: String::decode(&row[0]).unwrap_or_default(),
+        file_type: String::decode(&row[1]).unwrap_or_default(),
+        content_type: String::decode(&row[2]).unwrap_or_default(),
+        size: i64::decode(&row[3]).unwrap_or(0),
+        encrypted: bool::decode(&row[4]).unwrap_or(false),
+    })
+}
+
+/// Drop the intent once its upload is recorded
+pub fn remove(conn: &Connection, file_id: &Uuid) -> Result<(), ServiceError> {
+    conn.execute(
+        "DELETE FROM storage.upload_intents WHERE file_id = $1",
+        &[ParameterValue::Str(file_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    Ok(())
+}
+
+/// Delete intents past the point `load` would accept them. Run by the
+/// upload expiry sweep.
+pub fn purge_expired(conn: &Connection) -> Result<(), ServiceError> {
+    conn.execute(
+        "DELETE FROM storage.upload_intents WHERE expires_at <= NOW() - INTERVAL '1 day'",
+        &[],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    Ok(())
+}
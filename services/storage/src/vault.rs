@@ -0,0 +1,380 @@
+//! Private vault
+//!
+//! Stores notes that are encrypted on the client. The service only ever sees
+//! ciphertext plus an opaque `header` (algorithm, nonce, key id - whatever the
+//! client needs to decrypt); keys never reach the server and nothing here
+//! attempts to interpret either field.
+//!
+//! Guarantees kept by this module:
+//! - Vault rows live in `storage.vault_items`/`storage.vault_versions`, never in
+//!   `storage.files` or S3, so file listings, sanitizing and copies cannot
+//!   reach them.
+//! - No code path sends vault data to the discovery indexer or the AI
+//!   services, and no event is emitted when vault contents change.
+//! - Responses are marked `Cache-Control: no-store` and `X-Robots-Tag: noindex`.
+//!
+//! Every write creates a new version; the newest `MAX_VERSIONS` are kept.
+//! Total ciphertext across all retained versions counts against a per-plan quota.
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use chrono::Utc;
+use uuid::Uuid;
+
+/// Largest single encrypted blob, after base64 decoding
+const MAX_ITEM_BYTES: i64 = 5 * 1024 * 1024;
+
+/// Largest opaque header, serialized
+const MAX_HEADER_BYTES: usize = 4096;
+
+/// Versions retained per item; older ones are pruned on write
+const MAX_VERSIONS: i64 = 20;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct CreateVaultItemRequest {
+    /// Base64 ciphertext
+    pub ciphertext: String,
+    /// Client-defined decryption parameters; must name an `alg`
+    pub header: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateVaultItemRequest {
+    pub ciphertext: String,
+    pub header: serde_json::Value,
+    /// Version the client encrypted against; rejects lost updates
+    pub base_version: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VaultItemSummary {
+    pub id: Uuid,
+    pub version: i64,
+    pub size: i64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VaultVersion {
+    pub item_id: Uuid,
+    pub version: i64,
+    pub ciphertext: String,
+    pub header: serde_json::Value,
+    pub size: i64,
+    pub created_at: String,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// GET /vault/items - Item metadata only; ciphertext is fetched per item
+pub fn list_items(conn: &Connection, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT i.id, i.current_version, v.size, i.created_at, i.updated_at
+                 FROM storage.vault_items i
+                 JOIN storage.vault_versions v ON v.item_id = i.id AND v.version = i.current_version
+                 WHERE i.user_id = $1
+                 ORDER BY i.updated_at DESC";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let items: Vec<VaultItemSummary> = rows.rows.iter().map(|row| {
+        VaultItemSummary {
+            id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+            version: i64::decode(&row[1]).unwrap_or(0),
+            size: i64::decode(&row[2]).unwrap_or(0),
+            created_at: String::decode(&row[3]).unwrap_or_default(),
+            updated_at: String::decode(&row[4]).unwrap_or_default(),
+        }
+    }).collect();
+
+    vault_response(200, serde_json::json!({
+        "items": items,
+        "usage": usage(conn, user_id)?
+    }))
+}
+
+/// POST /vault/items - Store a new encrypted item at version 1
+pub fn create_item(conn: &Connection, user_id: &Uuid, body: CreateVaultItemRequest) -> Result<Response, ServiceError> {
+    let size = validate_blob(&body.ciphertext, &body.header)?;
+    ensure_quota(conn, user_id, size)?;
+
+    let item_id = Uuid::new_v4();
+    let now = Utc::now().to_rfc3339();
+
+    let insert = "INSERT INTO storage.vault_items (id, user_id, current_version, created_at, updated_at)
+                  VALUES ($1, $2, 1, $3, $3)";
+    conn.execute(insert, &[
+        ParameterValue::Str(item_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(now.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    insert_version(conn, &item_id, 1, &body.ciphertext, &body.header, size, &now)?;
+
+    vault_response(201, serde_json::json!({
+        "id": item_id,
+        "version": 1,
+        "size": size,
+        "created_at": now
+    }))
+}
+
+/// GET /vault/items/:id - Latest version
+pub fn get_item(conn: &Connection, user_id: &Uuid, item_id: &Uuid) -> Result<Response, ServiceError> {
+    let current = current_version(conn, user_id, item_id)?;
+    vault_response(200, load_version(conn, item_id, current)?)
+}
+
+/// PUT /vault/items/:id - Store a new version
+pub fn update_item(conn: &Connection, user_id: &Uuid, item_id: &Uuid, body: UpdateVaultItemRequest) -> Result<Response, ServiceError> {
+    let size = validate_blob(&body.ciphertext, &body.header)?;
+    let current = current_version(conn, user_id, item_id)?;
+
+    if body.base_version != current {
+        return Err(ServiceError::Conflict(format!(
+            "Item is at version {}, not {}; fetch and re-encrypt before saving", current, body.base_version
+        )));
+    }
+
+    // The version about to be pruned frees its bytes
+    ensure_quota(conn, user_id, size - prunable_bytes(conn, item_id, current + 1)?)?;
+
+    let version = current + 1;
+    let now = Utc::now().to_rfc3339();
+
+    // Guarding on the version keeps concurrent writers from both succeeding
+    let update = "UPDATE storage.vault_items SET current_version = $3, updated_at = $4
+                  WHERE id = $1 AND user_id = $2 AND current_version = $5";
+    let updated = conn.execute(update, &[
+        ParameterValue::Str(item_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Int64(version),
+        ParameterValue::Str(now.clone()),
+        ParameterValue::Int64(current),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    if updated == 0 {
+        return Err(ServiceError::Conflict("Item was updated concurrently; fetch and retry".into()));
+    }
+
+    insert_version(conn, item_id, version, &body.ciphertext, &body.header, size, &now)?;
+
+    let prune = "DELETE FROM storage.vault_versions WHERE item_id = $1 AND version <= $2";
+    conn.execute(prune, &[
+        ParameterValue::Str(item_id.to_string()),
+        ParameterValue::Int64(version - MAX_VERSIONS),
+    ]).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    vault_response(200, serde_json::json!({
+        "id": item_id,
+        "version": version,
+        "size": size,
+        "updated_at": now
+    }))
+}
+
+/// GET /vault/items/:id/versions - Retained versions, newest first
+pub fn list_versions(conn: &Connection, user_id: &Uuid, item_id: &Uuid) -> Result<Response, ServiceError> {
+    current_version(conn, user_id, item_id)?;
+
+    let query = "SELECT version, size, created_at FROM storage.vault_versions
+                 WHERE item_id = $1 ORDER BY version DESC";
+    let rows = conn.query(query, &[ParameterValue::Str(item_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let versions: Vec<serde_json::Value> = rows.rows.iter().map(|row| {
+        serde_json::json!({
+            "version": i64::decode(&row[0]).unwrap_or(0),
+            "size": i64::decode(&row[1]).unwrap_or(0),
+            "created_at": String::decode(&row[2]).unwrap_or_default()
+        })
+    }).collect();
+
+    vault_response(200, serde_json::json!({
+        "id": item_id,
+        "versions": versions
+    }))
+}
+
+/// GET /vault/items/:id/versions/:version - One retained version
+pub fn get_version(conn: &Connection, user_id: &Uuid, item_id: &Uuid, version: i64) -> Result<Response, ServiceError> {
+    current_version(conn, user_id, item_id)?;
+    vault_response(200, load_version(conn, item_id, version)?)
+}
+
+/// DELETE /vault/items/:id - Remove the item and every version
+pub fn delete_item(conn: &Connection, user_id: &Uuid, item_id: &Uuid) -> Result<Response, ServiceError> {
+    // Versions cascade
+    let delete = "DELETE FROM storage.vault_items WHERE id = $1 AND user_id = $2";
+    let deleted = conn.execute(delete, &[
+        ParameterValue::Str(item_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+
+    if deleted == 0 {
+        return Err(ServiceError::NotFound("Vault item not found".into()));
+    }
+
+    vault_response(200, serde_json::json!({ "deleted": true }))
+}
+
+//=============================================================================
+// Quota
+//=============================================================================
+
+/// Vault bytes allowed per plan; users without a subscription are on `free`
+fn plan_quota_bytes(plan_id: &str) -> i64 {
+    match plan_id {
+        "pro" => 1024 * 1024 * 1024,
+        "enterprise" => 10 * 1024 * 1024 * 1024,
+        _ => 25 * 1024 * 1024,
+    }
+}
+
+fn usage(conn: &Connection, user_id: &Uuid) -> Result<serde_json::Value, ServiceError> {
+    let query = "SELECT
+                 COALESCE((SELECT SUM(v.size) FROM storage.vault_versions v
+                           JOIN storage.vault_items i ON i.id = v.item_id
+                           WHERE i.user_id = $1), 0)::bigint,
+                 COALESCE((SELECT s.plan_id FROM subscriptions.subscriptions s WHERE s.user_id = $1), 'free')";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let (used, plan_id) = rows.rows.first()
+        .map(|row| (i64::decode(&row[0]).unwrap_or(0), String::decode(&row[1]).unwrap_or_else(|_| "free".into())))
+        .unwrap_or((0, "free".into()));
+
+    Ok(serde_json::json!({
+        "used_bytes": used,
+        "quota_bytes": plan_quota_bytes(&plan_id),
+        "plan_id": plan_id
+    }))
+}
+
+fn ensure_quota(conn: &Connection, user_id: &Uuid, additional: i64) -> Result<(), ServiceError> {
+    let usage = usage(conn, user_id)?;
+    let used = usage["used_bytes"].as_i64().unwrap_or(0);
+    let quota = usage["quota_bytes"].as_i64().unwrap_or(0);
+
+    if used + additional > quota {
+        return Err(ServiceError::PayloadTooLarge(format!(
+            "Vault quota exceeded: {} of {} bytes used", used, quota
+        )));
+    }
+    Ok(())
+}
+
+/// Bytes of the versions that writing `new_version` will prune
+fn prunable_bytes(conn: &Connection, item_id: &Uuid, new_version: i64) -> Result<i64, ServiceError> {
+    let query = "SELECT COALESCE(SUM(size), 0)::bigint FROM storage.vault_versions
+                 WHERE item_id = $1 AND version <= $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(item_id.to_string()),
+        ParameterValue::Int64(new_version - MAX_VERSIONS),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    Ok(rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0))
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+/// Checks shape only; the content is never inspected
+fn validate_blob(ciphertext: &str, header: &serde_json::Value) -> Result<i64, ServiceError> {
+    let decoded = BASE64.decode(ciphertext)
+        .map_err(|_| ServiceError::BadRequest("ciphertext must be base64".into()))?;
+    if decoded.is_empty() {
+        return Err(ServiceError::BadRequest("ciphertext is empty".into()));
+    }
+    let size = decoded.len() as i64;
+    if size > MAX_ITEM_BYTES {
+        return Err(ServiceError::PayloadTooLarge(format!("Vault items are limited to {} bytes", MAX_ITEM_BYTES)));
+    }
+
+    if header.get("alg").and_then(|a| a.as_str()).map_or(true, str::is_empty) {
+        return Err(ServiceError::BadRequest("header.alg is required".into()));
+    }
+    if header.to_string().len() > MAX_HEADER_BYTES {
+        return Err(ServiceError::BadRequest(format!("header is limited to {} bytes", MAX_HEADER_BYTES)));
+    }
+
+    Ok(size)
+}
+
+fn current_version(conn: &Connection, user_id: &Uuid, item_id: &Uuid) -> Result<i64, ServiceError> {
+    let query = "SELECT current_version FROM storage.vault_items WHERE id = $1 AND user_id = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(item_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    rows.rows.first()
+        .map(|row| i64::decode(&row[0]).unwrap_or(0))
+        .ok_or_else(|| ServiceError::NotFound("Vault item not found".into()))
+}
+
+fn load_version(conn: &Connection, item_id: &Uuid, version: i64) -> Result<VaultVersion, ServiceError> {
+    let query = "SELECT ciphertext, header, size, created_at FROM storage.vault_versions
+                 WHERE item_id = $1 AND version = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(item_id.to_string()),
+        ParameterValue::Int64(version),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Version not found".into()))?;
+
+    Ok(VaultVersion {
+        item_id: *item_id,
+        version,
+        ciphertext: String::decode(&row[0]).unwrap_or_default(),
+        header: serde_json::from_str(&String::decode(&row[1]).unwrap_or_else(|_| "{}".into())).unwrap_or_default(),
+        size: i64::decode(&row[2]).unwrap_or(0),
+        created_at: String::decode(&row[3]).unwrap_or_default(),
+    })
+}
+
+fn insert_version(
+    conn: &Connection,
+    item_id: &Uuid,
+    version: i64,
+    ciphertext: &str,
+    header: &serde_json::Value,
+    size: i64,
+    created_at: &str,
+) -> Result<(), ServiceError> {
+    let insert = "INSERT INTO storage.vault_versions (item_id, version, ciphertext, header, size, created_at)
+                  VALUES ($1, $2, $3, $4, $5, $6)";
+    conn.execute(insert, &[
+        ParameterValue::Str(item_id.to_string()),
+        ParameterValue::Int64(version),
+        ParameterValue::Str(ciphertext.to_string()),
+        ParameterValue::Str(header.to_string()),
+        ParameterValue::Int64(size),
+        ParameterValue::Str(created_at.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    Ok(())
+}
+
+fn vault_response<T: Serialize>(status: u16, body: T) -> Result<Response, ServiceError> {
+    let json = serde_json::to_string(&body)
+        .map_err(|e| ServiceError::Internal(format!("JSON error: {}", e)))?;
+
+    Ok(Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Cache-Control", "no-store")
+        .header("X-Robots-Tag", "noindex, nofollow")
+        .body(json)
+        .build())
+}
@@ -0,0 +1,386 @@
+//! Support Admin Module
+//!
+//! Manual subscription overrides and credit grants for support staff. These
+//! write directly to the database and never call Stripe, so they are meant for
+//! comps, extensions and corrections. Callers must hold the `admin` or
+//! `support` role in `users.user_roles`, and every change is recorded in
+//! `subscriptions.admin_audit_log` with the actor, a before/after snapshot and
+//! the stated reason.
+
+use crate::error::ServiceError;
+use serde::Deserialize;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+const VALID_STATUSES: [&str; 5] = ["active", "past_due", "cancelled", "trialing", "unpaid"];
+
+/// Upper bound on a single grant, to catch typos
+const MAX_CREDIT_GRANT: i32 = 1_000_000;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+/// Omitted fields are left unchanged
+#[derive(Debug, Deserialize)]
+pub struct SubscriptionOverrideRequest {
+    pub plan_id: Option<String>,
+    pub status: Option<String>,
+    pub current_period_start: Option<String>,
+    pub current_period_end: Option<String>,
+    /// Convenience for extensions: pushes the current period end forward
+    pub extend_days: Option<i64>,
+    pub cancel_at_period_end: Option<bool>,
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreditGrantRequest {
+    pub user_id: Uuid,
+    pub amount: i32,
+    pub reason: String,
+}
+
+/// A positive amount adds a grant, optionally expiring; a negative amount
+/// removes credits, soonest-expiring first
+#[derive(Debug, Deserialize)]
+pub struct CreditAdjustmentRequest {
+    pub user_id: Uuid,
+    pub amount: i32,
+    pub reason: String,
+    pub expires_at: Option<String>,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// PUT /admin/subscriptions/:user_id - Set plan, status or billing period
+pub fn override_subscription(
+    conn: &Connection,
+    actor_id: &Uuid,
+    target_id: &Uuid,
+    body: SubscriptionOverrideRequest,
+) -> Result<Response, ServiceError> {
+    require_reason(&body.reason)?;
+    if let Some(ref plan_id) = body.plan_id {
+        // Inactive plans are allowed so support can restore a grandfathered plan
+        if crate::plans::find(conn, plan_id)?.is_none() {
+            return Err(ServiceError::BadRequest(format!("Unknown plan_id '{}'", plan_id)));
+        }
+    }
+    if let Some(ref status) = body.status {
+        if !VALID_STATUSES.contains(&status.as_str()) {
+            return Err(ServiceError::BadRequest(format!("status must be one of: {}", VALID_STATUSES.join(", "))));
+        }
+    }
+    if body.extend_days.is_some() && body.current_period_end.is_some() {
+        return Err(ServiceError::BadRequest("Use either extend_days or current_period_end, not both".into()));
+    }
+    if body.extend_days.is_some_and(|days| !(1..=3650).contains(&days)) {
+        return Err(ServiceError::BadRequest("extend_days must be between 1 and 3650".into()));
+    }
+
+    let user_exists = conn.query("SELECT 1 FROM users.users WHERE id = $1", &[ParameterValue::Str(target_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    if user_exists.rows.is_empty() {
+        return Err(ServiceError::NotFound("User not found".into()));
+    }
+
+    let before = load_subscription(conn, target_id)?;
+    let now = Utc::now();
+
+    let period_start = match body.current_period_start {
+        Some(ref value) => Some(parse_timestamp(value, "current_period_start")?),
+        None => before.as_ref().and_then(|b| timestamp_field(b, "current_period_start")),
+    };
+    let period_end = match (&body.current_period_end, body.extend_days) {
+        (Some(value), _) => Some(parse_timestamp(value, "current_period_end")?),
+        (None, Some(days)) => {
+            // Extending a lapsed period starts from today rather than the past end
+            let base = before.as_ref()
+                .and_then(|b| timestamp_field(b, "current_period_end"))
+                .filter(|end| *end > now)
+                .unwrap_or(now);
+            Some(base + Duration::days(days))
+        }
+        (None, None) => before.as_ref().and_then(|b| timestamp_field(b, "current_period_end")),
+    };
+    if let (Some(start), Some(end)) = (period_start, period_end) {
+        if end <= start {
+            return Err(ServiceError::BadRequest("current_period_end must be after current_period_start".into()));
+        }
+    }
+
+    if before.is_none() {
+        // Comping a user who never subscribed creates the row without Stripe ids
+        let insert = "INSERT INTO subscriptions.subscriptions
+                      (id, user_id, plan_id, status, current_period_start, current_period_end,
+                       cancel_at_period_end, created_at, updated_at)
+                      VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $8)";
+        conn.execute(insert, &[
+            ParameterValue::Str(Uuid::new_v4().to_string()),
+            ParameterValue::Str(target_id.to_string()),
+            ParameterValue::Str(body.plan_id.clone().unwrap_or_else(|| "free".into())),
+            ParameterValue::Str(body.status.clone().unwrap_or_else(|| "active".into())),
+            ParameterValue::Str(period_start.unwrap_or(now).to_rfc3339()),
+            period_end.map(|end| ParameterValue::Str(end.to_rfc3339())).unwrap_or(ParameterValue::DbNull),
+            ParameterValue::Boolean(body.cancel_at_period_end.unwrap_or(false)),
+            ParameterValue::Str(now.to_rfc3339()),
+        ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    } else {
+        let update = "UPDATE subscriptions.subscriptions SET
+                      plan_id = COALESCE($2, plan_id),
+                      status = COALESCE($3, status),
+                      current_period_start = COALESCE($4::timestamptz, current_period_start),
+                      current_period_end = COALESCE($5::timestamptz, current_period_end),
+                      cancel_at_period_end = COALESCE($6, cancel_at_period_end),
+                      updated_at = $7
+                      WHERE user_id = $1";
+        conn.execute(update, &[
+            ParameterValue::Str(target_id.to_string()),
+            body.plan_id.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+            body.status.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+            period_start.map(|t| ParameterValue::Str(t.to_rfc3339())).unwrap_or(ParameterValue::DbNull),
+            period_end.map(|t| ParameterValue::Str(t.to_rfc3339())).unwrap_or(ParameterValue::DbNull),
+            body.cancel_at_period_end.map(ParameterValue::Boolean).unwrap_or(ParameterValue::DbNull),
+            ParameterValue::Str(now.to_rfc3339()),
+        ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    }
+
+    let after = load_subscription(conn, target_id)?;
+    let audit_id = record_audit(conn, actor_id, Some(target_id), "subscription_override", serde_json::json!({
+        "before": before,
+        "after": after
+    }), &body.reason)?;
+    crate::billing_events::record(conn, target_id, "subscription.admin_override", crate::billing_events::Source::Admin, Some(actor_id), serde_json::json!({
+        "audit_id": audit_id,
+        "before": before,
+        "after": after,
+        "reason": body.reason
+    }))?;
+
+    crate::json_response(200, serde_json::json!({
+        "subscription": after,
+        "audit_id": audit_id
+    }))
+}
+
+/// POST /admin/credits/grant - Add credits to a user's balance
+pub fn grant_credits(conn: &Connection, actor_id: &Uuid, body: CreditGrantRequest) -> Result<Response, ServiceError> {
+    require_reason(&body.reason)?;
+    if !(1..=MAX_CREDIT_GRANT).contains(&body.amount) {
+        return Err(ServiceError::BadRequest(format!("amount must be between 1 and {}", MAX_CREDIT_GRANT)));
+    }
+
+    let audit_id = Uuid::new_v4();
+    let grant = "SELECT subscriptions.add_credits($1::uuid, $2, 'admin_adjustment', $3, $4::uuid, 'admin_audit')::text";
+    let rows = conn.query(grant, &[
+        ParameterValue::Str(body.user_id.to_string()),
+        ParameterValue::Int32(body.amount),
+        ParameterValue::Str(body.reason.chars().take(255).collect()),
+        ParameterValue::Str(audit_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Failed to add credits: {}", e)))?;
+    let transaction_id = rows.rows.first().and_then(|row| String::decode(&row[0]).ok());
+
+    let balance = conn.query("SELECT balance FROM subscriptions.credits WHERE user_id = $1",
+        &[ParameterValue::Str(body.user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?
+        .rows.first()
+        .map(|row| i32::decode(&row[0]).unwrap_or(0))
+        .unwrap_or(0);
+
+    insert_audit(conn, &audit_id, actor_id, Some(&body.user_id), "credit_grant", serde_json::json!({
+        "amount": body.amount,
+        "transaction_id": transaction_id,
+        "balance_after": balance
+    }), &body.reason)?;
+
+    crate::json_response(200, serde_json::json!({
+        "user_id": body.user_id,
+        "amount": body.amount,
+        "balance": balance,
+        "transaction_id": transaction_id,
+        "audit_id": audit_id
+    }))
+}
+
+/// POST /admin/credits/adjust - Add or remove credits as a correction
+pub fn adjust_credits(conn: &Connection, actor_id: &Uuid, body: CreditAdjustmentRequest) -> Result<Response, ServiceError> {
+    require_reason(&body.reason)?;
+    if body.amount == 0 || body.amount.unsigned_abs() > MAX_CREDIT_GRANT as u32 {
+        return Err(ServiceError::BadRequest(format!(
+            "amount must be non-zero and between -{} and {}", MAX_CREDIT_GRANT, MAX_CREDIT_GRANT
+        )));
+    }
+    let expires_at = body.expires_at.as_deref()
+        .map(|value| parse_timestamp(value, "expires_at"))
+        .transpose()?;
+    if body.amount < 0 && expires_at.is_some() {
+        return Err(ServiceError::BadRequest("expires_at only applies to positive adjustments".into()));
+    }
+    if expires_at.is_some_and(|t| t <= Utc::now()) {
+        return Err(ServiceError::BadRequest("expires_at must be in the future".into()));
+    }
+
+    let audit_id = Uuid::new_v4();
+    let reason: String = body.reason.chars().take(255).collect();
+    let rows = if body.amount > 0 {
+        let add = "SELECT subscriptions.add_credits($1::uuid, $2, 'admin_adjustment', $3, $4::uuid, 'admin_audit', $5::timestamptz)::text";
+        conn.query(add, &[
+            ParameterValue::Str(body.user_id.to_string()),
+            ParameterValue::Int32(body.amount),
+            ParameterValue::Str(reason),
+            ParameterValue::Str(audit_id.to_string()),
+            expires_at.map(|t| ParameterValue::Str(t.to_rfc3339())).unwrap_or(ParameterValue::DbNull),
+        ]).map_err(|e| ServiceError::Internal(format!("Failed to add credits: {}", e)))?
+    } else {
+        let deduct = "SELECT subscriptions.deduct_credits($1::uuid, $2, 'admin_adjustment', $3, $4::uuid, 'admin_audit')::text";
+        conn.query(deduct, &[
+            ParameterValue::Str(body.user_id.to_string()),
+            ParameterValue::Int32(-body.amount),
+            ParameterValue::Str(reason),
+            ParameterValue::Str(audit_id.to_string()),
+        ]).map_err(|e| ServiceError::Internal(format!("Failed to deduct credits: {}", e)))?
+    };
+    let transaction_id = rows.rows.first().and_then(|row| String::decode(&row[0]).ok());
+
+    let balance = conn.query("SELECT balance FROM subscriptions.credits WHERE user_id = $1",
+        &[ParameterValue::Str(body.user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?
+        .rows.first()
+        .map(|row| i32::decode(&row[0]).unwrap_or(0))
+        .unwrap_or(0);
+
+    // A deduction larger than the unexpired balance changes nothing
+    if transaction_id.is_none() {
+        return Err(ServiceError::BadRequest(format!(
+            "Cannot remove {} credits; the user has {}", -body.amount, balance
+        )));
+    }
+
+    insert_audit(conn, &audit_id, actor_id, Some(&body.user_id), "credit_adjustment", serde_json::json!({
+        "amount": body.amount,
+        "expires_at": expires_at.map(|t| t.to_rfc3339()),
+        "transaction_id": transaction_id,
+        "balance_after": balance
+    }), &body.reason)?;
+
+    crate::json_response(200, serde_json::json!({
+        "user_id": body.user_id,
+        "amount": body.amount,
+        "expires_at": expires_at.map(|t| t.to_rfc3339()),
+        "balance": balance,
+        "transaction_id": transaction_id,
+        "audit_id": audit_id
+    }))
+}
+
+/// GET /admin/audit?user_id= - Recent admin changes, optionally for one user
+pub fn list_audit(conn: &Connection, target_id: Option<Uuid>) -> Result<Response, ServiceError> {
+    let query = "SELECT id, actor_id, target_user_id, action, changes::text, reason, created_at
+                 FROM subscriptions.admin_audit_log
+                 WHERE ($1::uuid IS NULL OR target_user_id = $1::uuid)
+                 ORDER BY created_at DESC LIMIT 200";
+    let rows = conn.query(query, &[
+        target_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let entries: Vec<serde_json::Value> = rows.rows.iter().map(|row| {
+        serde_json::json!({
+            "id": String::decode(&row[0]).unwrap_or_default(),
+            "actor_id": String::decode(&row[1]).unwrap_or_default(),
+            "target_user_id": String::decode(&row[2]).ok(),
+            "action": String::decode(&row[3]).unwrap_or_default(),
+            "changes": serde_json::from_str::<serde_json::Value>(&String::decode(&row[4]).unwrap_or_default()).unwrap_or_default(),
+            "reason": String::decode(&row[5]).unwrap_or_default(),
+            "created_at": String::decode(&row[6]).unwrap_or_default()
+        })
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({ "entries": entries }))
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+fn require_reason(reason: &str) -> Result<(), ServiceError> {
+    if reason.trim().is_empty() {
+        return Err(ServiceError::BadRequest("reason is required".into()));
+    }
+    Ok(())
+}
+
+fn parse_timestamp(value: &str, field: &str) -> Result<DateTime<Utc>, ServiceError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|_| ServiceError::BadRequest(format!("{} must be an RFC 3339 timestamp", field)))
+}
+
+fn timestamp_field(snapshot: &serde_json::Value, field: &str) -> Option<DateTime<Utc>> {
+    snapshot.get(field)
+        .and_then(|v| v.as_str())
+        .and_then(|v| DateTime::parse_from_rfc3339(v).ok())
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Subscription row as JSON for audit snapshots; `None` if the user has none
+fn load_subscription(conn: &Connection, user_id: &Uuid) -> Result<Option<serde_json::Value>, ServiceError> {
+    let query = "SELECT plan_id, status, stripe_subscription_id,
+                 to_char(current_period_start AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"'),
+                 to_char(current_period_end AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"'),
+                 cancel_at_period_end
+                 FROM subscriptions.subscriptions WHERE user_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first().map(|row| serde_json::json!({
+        "plan_id": String::decode(&row[0]).unwrap_or_default(),
+        "status": String::decode(&row[1]).unwrap_or_default(),
+        "stripe_subscription_id": String::decode(&row[2]).ok(),
+        "current_period_start": String::decode(&row[3]).ok(),
+        "current_period_end": String::decode(&row[4]).ok(),
+        "cancel_at_period_end": bool::decode(&row[5]).unwrap_or(false)
+    })))
+}
+
+/// `target_id` is `None` for changes that affect no single user, such as plan edits
+pub fn record_audit(
+    conn: &Connection,
+    actor_id: &Uuid,
+    target_id: Option<&Uuid>,
+    action: &str,
+    changes: serde_json::Value,
+    reason: &str,
+) -> Result<Uuid, ServiceError> {
+    let audit_id = Uuid::new_v4();
+    insert_audit(conn, &audit_id, actor_id, target_id, action, changes, reason)?;
+    Ok(audit_id)
+}
+
+fn insert_audit(
+    conn: &Connection,
+    audit_id: &Uuid,
+    actor_id: &Uuid,
+    target_id: Option<&Uuid>,
+    action: &str,
+    changes: serde_json::Value,
+    reason: &str,
+) -> Result<(), ServiceError> {
+    let insert = "INSERT INTO subscriptions.admin_audit_log
+                  (id, actor_id, target_user_id, action, changes, reason, created_at)
+                  VALUES ($1, $2, $3::uuid, $4, $5::jsonb, $6, $7)";
+    conn.execute(insert, &[
+        ParameterValue::Str(audit_id.to_string()),
+        ParameterValue::Str(actor_id.to_string()),
+        target_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(action.to_string()),
+        ParameterValue::Str(changes.to_string()),
+        ParameterValue::Str(reason.to_string()),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    Ok(())
+}
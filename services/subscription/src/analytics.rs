@@ -0,0 +1,327 @@
+//! Revenue Analytics Module
+//!
+//! MRR, churn, upgrades/downgrades and ARPU for admins, computed from paid
+//! invoices so they reflect what customers were actually billed. A
+//! customer's MRR for a month is the pre-tax total of their paid invoices
+//! that month, with invoices on yearly subscriptions spread over twelve
+//! months. Comparing each customer's MRR with the month before classifies
+//! them as new, expanded (upgrade), contracted (downgrade) or churned.
+//! Prorated charges land in the month they were invoiced, so a mid-month plan
+//! change can show as a one-month expansion.
+//!
+//! Months roll up into quarters or years: MRR and paying customers are taken
+//! from the last month, starting MRR from the first, and movements and
+//! revenue are summed. Amounts are in cents, like the invoices themselves.
+//! `current` is the live MRR of active and past-due subscriptions at their
+//! plan's list price, which includes renewals not invoiced yet this month.
+
+use crate::error::ServiceError;
+use serde::Serialize;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::{Datelike, NaiveDate, Utc};
+
+const PERIODS: [&str; 3] = ["month", "quarter", "year"];
+
+/// Widest range one request may cover
+const MAX_MONTHS: i32 = 120;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PeriodStats {
+    pub period: String,
+    pub starting_mrr: i64,
+    pub starting_customers: i64,
+    pub mrr: i64,
+    pub paying_customers: i64,
+    pub new_customers: i64,
+    pub new_mrr: i64,
+    pub upgrades: i64,
+    pub expansion_mrr: i64,
+    pub downgrades: i64,
+    pub contraction_mrr: i64,
+    pub churned_customers: i64,
+    pub churned_mrr: i64,
+    /// Share of starting customers lost in the period
+    pub churn_rate: f64,
+    /// Share of starting MRR lost to churn and downgrades
+    pub revenue_churn_rate: f64,
+    /// MRR per paying customer at the end of the period
+    pub arpu: i64,
+    /// Paid invoices in the period, before tax
+    pub revenue: i64,
+}
+
+//=============================================================================
+// Endpoint
+//=============================================================================
+
+/// GET /admin/analytics/revenue?period=&from=&to=&format= - `period` is
+/// month (default), quarter or year; `from` and `to` are YYYY-MM or
+/// YYYY-MM-DD and default to the last 12 months, 8 quarters or 3 years;
+/// `format=csv` downloads the periods as CSV
+pub fn revenue(
+    conn: &Connection,
+    period: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    format: Option<String>,
+) -> Result<Response, ServiceError> {
+    let period = period.unwrap_or_else(|| "month".into());
+    if !PERIODS.contains(&period.as_str()) {
+        return Err(ServiceError::BadRequest(format!("period must be one of: {}", PERIODS.join(", "))));
+    }
+    let csv = match format.as_deref() {
+        None | Some("json") => false,
+        Some("csv") => true,
+        Some(_) => return Err(ServiceError::BadRequest("format must be json or csv".into())),
+    };
+
+    let to_month = match to {
+        Some(ref value) => month_index(parse_month(value, "to")?),
+        None => month_index(Utc::now().date_naive()),
+    };
+    let from_month = match from {
+        Some(ref value) => month_index(parse_month(value, "from")?),
+        None => to_month - match period.as_str() {
+            "quarter" => 23,
+            "year" => 35,
+            _ => 11,
+        },
+    };
+    // Start on a period boundary so the first period is not partial
+    let from_month = match period.as_str() {
+        "quarter" => from_month - from_month.rem_euclid(3),
+        "year" => from_month - from_month.rem_euclid(12),
+        _ => from_month,
+    };
+    if from_month > to_month {
+        return Err(ServiceError::BadRequest("from must not be after to".into()));
+    }
+    if to_month - from_month + 1 > MAX_MONTHS {
+        return Err(ServiceError::BadRequest(format!("A report may cover at most {} months", MAX_MONTHS)));
+    }
+    let (from_date, to_date) = (month_start(from_month)?, month_start(to_month)?);
+
+    let months = monthly_stats(conn, &from_date, &to_date)?;
+    let periods = roll_up(months, &period);
+
+    if csv {
+        let filename = format!("revenue-{}-{}-to-{}.csv", period, from_date.format("%Y-%m"), to_date.format("%Y-%m"));
+        return Ok(Response::builder()
+            .status(200)
+            .header("Content-Type", "text/csv")
+            .header("Content-Disposition", format!("attachment; filename=\"{}\"", filename))
+            .header("Access-Control-Allow-Origin", "*")
+            .body(render_csv(&periods))
+            .build());
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "period": period,
+        "from": from_date.format("%Y-%m").to_string(),
+        "to": to_date.format("%Y-%m").to_string(),
+        "current": current_mrr(conn)?,
+        "periods": periods
+    }))
+}
+
+//=============================================================================
+// Queries
+//=============================================================================
+
+/// One row per month from `from` to `to`, both first days of a month
+fn monthly_stats(conn: &Connection, from: &NaiveDate, to: &NaiveDate) -> Result<Vec<PeriodStats>, ServiceError> {
+    let query = "WITH customer_months AS (
+                     SELECT i.stripe_customer_id AS customer,
+                            date_trunc('month', i.created_at) + make_interval(months => spread.n) AS month,
+                            SUM(COALESCE(i.subtotal, i.amount)::float8 / span.months) AS mrr
+                     FROM subscriptions.invoices i
+                     LEFT JOIN subscriptions.subscriptions s ON s.stripe_customer_id = i.stripe_customer_id
+                     CROSS JOIN LATERAL (SELECT CASE WHEN s.billing_interval = 'year' THEN 12 ELSE 1 END AS months) span
+                     CROSS JOIN LATERAL generate_series(0, span.months - 1) AS spread(n)
+                     WHERE i.status = 'paid' AND COALESCE(i.subtotal, i.amount) > 0
+                       AND i.created_at >= $1::timestamptz - interval '13 months'
+                       AND i.created_at < $2::timestamptz + interval '1 month'
+                     GROUP BY 1, 2
+                 ),
+                 changes AS (
+                     SELECT COALESCE(cur.month, prev.month + interval '1 month') AS month,
+                            COALESCE(cur.mrr, 0) AS mrr,
+                            COALESCE(prev.mrr, 0) AS prev_mrr
+                     FROM customer_months cur
+                     FULL OUTER JOIN customer_months prev
+                       ON prev.customer = cur.customer AND prev.month = cur.month - interval '1 month'
+                 )
+                 SELECT to_char(m.month, 'YYYY-MM'),
+                        COALESCE(SUM(c.prev_mrr), 0)::bigint,
+                        COUNT(*) FILTER (WHERE c.prev_mrr > 0),
+                        COALESCE(SUM(c.mrr), 0)::bigint,
+                        COUNT(*) FILTER (WHERE c.mrr > 0),
+                        COUNT(*) FILTER (WHERE c.prev_mrr = 0 AND c.mrr > 0),
+                        COALESCE(SUM(c.mrr) FILTER (WHERE c.prev_mrr = 0), 0)::bigint,
+                        COUNT(*) FILTER (WHERE c.prev_mrr > 0 AND c.mrr > c.prev_mrr),
+                        COALESCE(SUM(c.mrr - c.prev_mrr) FILTER (WHERE c.prev_mrr > 0 AND c.mrr > c.prev_mrr), 0)::bigint,
+                        COUNT(*) FILTER (WHERE c.mrr > 0 AND c.mrr < c.prev_mrr),
+                        COALESCE(SUM(c.prev_mrr - c.mrr) FILTER (WHERE c.mrr > 0 AND c.mrr < c.prev_mrr), 0)::bigint,
+                        COUNT(*) FILTER (WHERE c.prev_mrr > 0 AND c.mrr = 0),
+                        COALESCE(SUM(c.prev_mrr) FILTER (WHERE c.mrr = 0), 0)::bigint,
+                        (SELECT COALESCE(SUM(COALESCE(i.subtotal, i.amount)), 0)::bigint
+                         FROM subscriptions.invoices i
+                         WHERE i.status = 'paid' AND i.created_at >= m.month
+                           AND i.created_at < m.month + interval '1 month')
+                 FROM generate_series($1::timestamptz, $2::timestamptz, interval '1 month') AS m(month)
+                 LEFT JOIN changes c ON c.month = m.month
+                 GROUP BY m.month
+                 ORDER BY m.month";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(from.format("%Y-%m-%d").to_string()),
+        ParameterValue::Str(to.format("%Y-%m-%d").to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.iter().map(|row| {
+        let int = |i: usize| i64::decode(&row[i]).unwrap_or(0);
+        let mut stats = PeriodStats {
+            period: String::decode(&row[0]).unwrap_or_default(),
+            starting_mrr: int(1),
+            starting_customers: int(2),
+            mrr: int(3),
+            paying_customers: int(4),
+            new_customers: int(5),
+            new_mrr: int(6),
+            upgrades: int(7),
+            expansion_mrr: int(8),
+            downgrades: int(9),
+            contraction_mrr: int(10),
+            churned_customers: int(11),
+            churned_mrr: int(12),
+            churn_rate: 0.0,
+            revenue_churn_rate: 0.0,
+            arpu: 0,
+            revenue: int(13),
+        };
+        derive_ratios(&mut stats);
+        stats
+    }).collect())
+}
+
+/// Live MRR at list prices, in total and by plan
+fn current_mrr(conn: &Connection) -> Result<serde_json::Value, ServiceError> {
+    let query = "SELECT p.id, p.name, COUNT(*),
+                        SUM(CASE WHEN s.billing_interval = 'year' THEN p.price_yearly / 12.0
+                                 ELSE p.price_monthly END)::bigint
+                 FROM subscriptions.subscriptions s
+                 JOIN subscriptions.plans p ON p.id = s.plan_id
+                 WHERE s.status IN ('active', 'past_due') AND p.price_monthly + p.price_yearly > 0
+                 GROUP BY p.id, p.name, p.sort_order
+                 ORDER BY p.sort_order";
+    let rows = conn.query(query, &[])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut mrr = 0;
+    let mut subscribers = 0;
+    let by_plan: Vec<serde_json::Value> = rows.rows.iter().map(|row| {
+        let plan_subscribers = i64::decode(&row[2]).unwrap_or(0);
+        let plan_mrr = i64::decode(&row[3]).unwrap_or(0);
+        subscribers += plan_subscribers;
+        mrr += plan_mrr;
+        serde_json::json!({
+            "plan_id": String::decode(&row[0]).unwrap_or_default(),
+            "name": String::decode(&row[1]).unwrap_or_default(),
+            "subscribers": plan_subscribers,
+            "mrr": plan_mrr
+        })
+    }).collect();
+
+    Ok(serde_json::json!({
+        "mrr": mrr,
+        "subscribers": subscribers,
+        "arpu": if subscribers > 0 { mrr / subscribers } else { 0 },
+        "by_plan": by_plan
+    }))
+}
+
+//=============================================================================
+// Aggregation
+//=============================================================================
+
+/// Merge consecutive months into quarters or years
+fn roll_up(months: Vec<PeriodStats>, period: &str) -> Vec<PeriodStats> {
+    let mut periods: Vec<PeriodStats> = Vec::new();
+    for month in months {
+        let key = period_key(&month.period, period);
+        match periods.last_mut() {
+            Some(current) if current.period == key => {
+                current.mrr = month.mrr;
+                current.paying_customers = month.paying_customers;
+                current.new_customers += month.new_customers;
+                current.new_mrr += month.new_mrr;
+                current.upgrades += month.upgrades;
+                current.expansion_mrr += month.expansion_mrr;
+                current.downgrades += month.downgrades;
+                current.contraction_mrr += month.contraction_mrr;
+                current.churned_customers += month.churned_customers;
+                current.churned_mrr += month.churned_mrr;
+                current.revenue += month.revenue;
+            }
+            _ => periods.push(PeriodStats { period: key, ..month }),
+        }
+    }
+    for stats in &mut periods {
+        derive_ratios(stats);
+    }
+    periods
+}
+
+fn derive_ratios(stats: &mut PeriodStats) {
+    let ratio = |part: i64, whole: i64| if whole > 0 { part as f64 / whole as f64 } else { 0.0 };
+    stats.churn_rate = ratio(stats.churned_customers, stats.starting_customers);
+    stats.revenue_churn_rate = ratio(stats.churned_mrr + stats.contraction_mrr, stats.starting_mrr);
+    stats.arpu = if stats.paying_customers > 0 { stats.mrr / stats.paying_customers } else { 0 };
+}
+
+/// `2026-05` as its month, quarter (`2026-Q2`) or year (`2026`)
+fn period_key(month: &str, period: &str) -> String {
+    let (year, number) = month.split_once('-').unwrap_or((month, "1"));
+    let number: u32 = number.parse().unwrap_or(1);
+    match period {
+        "quarter" => format!("{}-Q{}", year, (number + 2) / 3),
+        "year" => year.to_string(),
+        _ => month.to_string(),
+    }
+}
+
+fn render_csv(periods: &[PeriodStats]) -> String {
+    let mut out = String::from(
+        "period,starting_mrr,starting_customers,mrr,paying_customers,new_customers,new_mrr,upgrades,expansion_mrr,\
+         downgrades,contraction_mrr,churned_customers,churned_mrr,churn_rate,revenue_churn_rate,arpu,revenue\n",
+    );
+    for p in periods {
+        out.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{:.4},{:.4},{},{}\n",
+            p.period, p.starting_mrr, p.starting_customers, p.mrr, p.paying_customers, p.new_customers,
+            p.new_mrr, p.upgrades, p.expansion_mrr, p.downgrades, p.contraction_mrr, p.churned_customers,
+            p.churned_mrr, p.churn_rate, p.revenue_churn_rate, p.arpu, p.revenue
+        ));
+    }
+    out
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+fn parse_month(value: &str, field: &str) -> Result<NaiveDate, ServiceError> {
+    let value = if value.len() == 7 { format!("{}-01", value) } else { value.to_string() };
+    NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+        .map_err(|_| ServiceError::BadRequest(format!("{} must be YYYY-MM or YYYY-MM-DD", field)))
+}
+
+/// Months since year 0, so ranges are plain integer arithmetic
+fn month_index(date: NaiveDate) -> i32 {
+    date.year() * 12 + date.month0() as i32
+}
+
+fn month_start(index: i32) -> Result<NaiveDate, ServiceError> {
+    NaiveDate::from_ymd_opt(index.div_euclid(12), index.rem_euclid(12) as u32 + 1, 1)
+        .ok_or_else(|| ServiceError::BadRequest("Date out of range".into()))
+}
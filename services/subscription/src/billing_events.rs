@@ -0,0 +1,190 @@
+//! Billing Events Module
+//!
+//! Every path that changes a subscription writes a row to
+//! `subscriptions.billing_events`: user requests, admin overrides, dunning
+//! downgrades and each payment provider webhook received. A webhook's row is
+//! claimed by its provider event id before the event is applied, so retried
+//! deliveries are neither recorded nor applied twice. Credit movements are
+//! already ledgered in `credit_transactions` and paid invoices in `invoices`,
+//! so the support timeline merges those tables with these events instead of
+//! copying them.
+
+use crate::error::ServiceError;
+use crate::payments::WebhookEvent;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+const DEFAULT_TIMELINE_LIMIT: i64 = 100;
+const MAX_TIMELINE_LIMIT: i64 = 500;
+
+/// Who caused an event
+#[derive(Debug, Clone, Copy)]
+pub enum Source {
+    User,
+    Admin,
+    Stripe,
+    System,
+}
+
+impl Source {
+    fn as_str(self) -> &'static str {
+        match self {
+            Source::User => "user",
+            Source::Admin => "admin",
+            Source::Stripe => "stripe",
+            Source::System => "system",
+        }
+    }
+}
+
+//=============================================================================
+// Recording
+//=============================================================================
+
+/// Record a subscription change made outside a webhook
+pub fn record(
+    conn: &Connection,
+    user_id: &Uuid,
+    event_type: &str,
+    source: Source,
+    actor_id: Option<&Uuid>,
+    details: serde_json::Value,
+) -> Result<(), ServiceError> {
+    let insert = "INSERT INTO subscriptions.billing_events
+                  (id, user_id, event_type, source, actor_id, details, created_at)
+                  VALUES ($1, $2, $3, $4, $5::uuid, $6::jsonb, NOW())";
+    conn.execute(insert, &[
+        ParameterValue::Str(Uuid::new_v4().to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(event_type.to_string()),
+        ParameterValue::Str(source.as_str().to_string()),
+        actor_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(details.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    Ok(())
+}
+
+/// Claim a payment provider webhook before it is applied, attributed to the
+/// user whose customer or subscription it concerns. Returns false for a
+/// retried delivery of an event already claimed.
+pub fn claim_webhook(conn: &Connection, provider: &str, event: &WebhookEvent) -> Result<bool, ServiceError> {
+    let insert = "INSERT INTO subscriptions.billing_events
+                  (id, user_id, event_type, source, stripe_event_id, details, created_at)
+                  VALUES ($1,
+                          (SELECT user_id FROM subscriptions.subscriptions
+                           WHERE stripe_subscription_id = $2 OR stripe_customer_id = $3
+                           LIMIT 1),
+                          $4, $7, $5, $6::jsonb, NOW())
+                  ON CONFLICT (stripe_event_id) DO NOTHING
+                  RETURNING id";
+    let rows = conn.query(insert, &[
+        ParameterValue::Str(Uuid::new_v4().to_string()),
+        event.subscription_id.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        event.customer_id.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(format!("{}.{}", provider, event.provider_type)),
+        ParameterValue::Str(event.id.clone()),
+        ParameterValue::Str(event.details.to_string()),
+        ParameterValue::Str(provider.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    Ok(!rows.rows.is_empty())
+}
+
+/// Give back the claim on an event that failed to apply, so the provider's
+/// retry is processed
+pub fn release_webhook(conn: &Connection, event_id: &str) -> Result<(), ServiceError> {
+    conn.execute(
+        "DELETE FROM subscriptions.billing_events WHERE stripe_event_id = $1",
+        &[ParameterValue::Str(event_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Delete failed: {}", e)))?;
+    Ok(())
+}
+
+/// Attribute a claimed event whose subscription row the event itself created
+pub fn attribute_webhook(conn: &Connection, event: &WebhookEvent) -> Result<(), ServiceError> {
+    let update = "UPDATE subscriptions.billing_events
+                  SET user_id = (SELECT user_id FROM subscriptions.subscriptions
+                                 WHERE stripe_subscription_id = $2 OR stripe_customer_id = $3
+                                 LIMIT 1)
+                  WHERE stripe_event_id = $1 AND user_id IS NULL";
+    conn.execute(update, &[
+        ParameterValue::Str(event.id.clone()),
+        event.subscription_id.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        event.customer_id.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+//=============================================================================
+// Timeline
+//=============================================================================
+
+/// GET /admin/users/:id/billing-timeline?before=&limit= - Newest first; page
+/// with `next_before`
+pub fn timeline(conn: &Connection, user_id: &Uuid, before: Option<String>, limit: Option<i64>) -> Result<Response, ServiceError> {
+    let limit = limit.unwrap_or(DEFAULT_TIMELINE_LIMIT).clamp(1, MAX_TIMELINE_LIMIT);
+
+    let query = "SELECT kind, event_type, source, actor_id, details::text,
+                        to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS.US\"Z\"')
+                 FROM (
+                     SELECT 'event' AS kind, e.event_type, e.source, e.actor_id::text AS actor_id,
+                            e.details, e.created_at
+                     FROM subscriptions.billing_events e
+                     WHERE e.user_id = $1::uuid
+                     UNION ALL
+                     SELECT 'credit', 'credits.' || t.transaction_type,
+                            CASE WHEN t.reference_type = 'admin_audit' THEN 'admin'
+                                 WHEN t.transaction_type = 'purchase' THEN 'stripe'
+                                 WHEN t.transaction_type = 'expiration' THEN 'system'
+                                 WHEN t.amount < 0 THEN 'user'
+                                 ELSE 'system' END,
+                            NULL,
+                            jsonb_build_object('transaction_id', t.id, 'amount', t.amount,
+                                               'balance_after', t.balance_after, 'reason', t.reason,
+                                               'reference_id', t.reference_id, 'reference_type', t.reference_type),
+                            t.created_at
+                     FROM subscriptions.credit_transactions t
+                     WHERE t.user_id = $1::uuid
+                     UNION ALL
+                     SELECT 'invoice', 'invoice.' || i.status, 'stripe', NULL,
+                            jsonb_build_object('invoice_id', i.id, 'stripe_invoice_id', i.stripe_invoice_id,
+                                               'amount', i.amount, 'tax', i.tax, 'total', i.total,
+                                               'currency', i.currency),
+                            i.created_at
+                     FROM subscriptions.invoices i
+                     JOIN subscriptions.subscriptions s ON i.stripe_customer_id = s.stripe_customer_id
+                     WHERE s.user_id = $1::uuid
+                 ) timeline
+                 WHERE ($2::timestamptz IS NULL OR created_at < $2::timestamptz)
+                 ORDER BY created_at DESC
+                 LIMIT $3";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(user_id.to_string()),
+        before.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Int64(limit + 1),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let has_more = rows.rows.len() as i64 > limit;
+    let entries: Vec<serde_json::Value> = rows.rows.iter().take(limit as usize).map(|row| {
+        serde_json::json!({
+            "kind": String::decode(&row[0]).unwrap_or_default(),
+            "type": String::decode(&row[1]).unwrap_or_default(),
+            "source": String::decode(&row[2]).unwrap_or_default(),
+            "actor_id": String::decode(&row[3]).ok(),
+            "details": serde_json::from_str::<serde_json::Value>(&String::decode(&row[4]).unwrap_or_default()).unwrap_or_default(),
+            "created_at": String::decode(&row[5]).unwrap_or_default()
+        })
+    }).collect();
+
+    let next_before = if has_more {
+        entries.last().and_then(|entry| entry["created_at"].as_str().map(|s| s.to_string()))
+    } else {
+        None
+    };
+
+    crate::json_response(200, serde_json::json!({
+        "user_id": user_id,
+        "entries": entries,
+        "next_before": next_before
+    }))
+}
@@ -3,6 +3,14 @@
 //! Credit System Module
 //!
 //! Handles credit packages, purchases, consumption, and balance management.
+//!
+//! Every addition is recorded as a grant in `subscriptions.credit_grants`
+//! with an optional expiry. Packages set how long purchased credits last
+//! (`expiry_days`) and may add bonus credits with their own, usually shorter,
+//! lifetime. Consumption draws from the soonest-expiring grants first, so
+//! bonus credits are used before purchased ones. Expired grants are swept
+//! before each deduction and by `POST /credits/expiry/process`, which also
+//! warns users `credit_expiry_warning_days` ahead of credits expiring.
 
 use crate::error::ServiceError;
 use crate::models::*;
@@ -11,7 +19,9 @@ use spin_sdk::http::{Request, Response};
 use spin_sdk::pg::{Connection, Decode, ParameterValue};
 use spin_sdk::variables;
 use uuid::Uuid;
-use chrono::Utc;
+use chrono::{Duration, Utc};
+
+const DEFAULT_EXPIRY_WARNING_DAYS: i64 = 7;
 
 //=============================================================================
 // Models
@@ -28,6 +38,11 @@ pub struct CreditPackage {
     pub is_active: bool,
     pub sort_order: i32,
     pub metadata: serde_json::Value,
+    /// Extra credits granted with each purchase
+    pub bonus_credits: i32,
+    /// Days purchased credits last; `None` never expires
+    pub expiry_days: Option<i32>,
+    pub bonus_expiry_days: Option<i32>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -90,7 +105,8 @@ pub struct CheckoutSessionResponse {
 /// GET /credits/packages - List available credit packages
 pub fn list_credit_packages(conn: &Connection) -> Result<Response, ServiceError> {
     let query = "SELECT id, name, description, credit_amount, price_cents,
-                 stripe_price_id, is_active, sort_order, metadata
+                 stripe_price_id, is_active, sort_order, metadata,
+                 bonus_credits, expiry_days, bonus_expiry_days
                  FROM subscriptions.credit_packages
                  WHERE is_active = true
                  ORDER BY sort_order ASC";
@@ -110,6 +126,9 @@ pub fn list_credit_packages(conn: &Connection) -> Result<Response, ServiceError>
             is_active: row.get::<bool>("is_active").unwrap(),
             sort_order: row.get::<i32>("sort_order").unwrap(),
             metadata: serde_json::from_str(row.get::<&str>("metadata").unwrap_or("{}")).unwrap_or(serde_json::json!({})),
+            bonus_credits: row.get::<i32>("bonus_credits").unwrap_or(0),
+            expiry_days: row.get::<Option<i32>>("expiry_days").unwrap_or(None),
+            bonus_expiry_days: row.get::<Option<i32>>("bonus_expiry_days").unwrap_or(None),
         });
     }
 
@@ -138,7 +157,8 @@ pub fn get_credit_balance(conn: &Connection, user_id: Uuid) -> Result<Response,
             "user_id": user_id,
             "balance": 0,
             "total_purchased": 0,
-            "total_consumed": 0
+            "total_consumed": 0,
+            "expiring": []
         }));
     }
 
@@ -150,7 +170,26 @@ pub fn get_credit_balance(conn: &Connection, user_id: Uuid) -> Result<Response,
         total_consumed: row.get::<i32>("total_consumed").unwrap(),
     };
 
-    crate::json_response(200, serde_json::json!(balance))
+    let mut response = serde_json::json!(balance);
+    response["expiring"] = serde_json::json!(upcoming_expirations(conn, &user_id)?);
+    crate::json_response(200, response)
+}
+
+/// Unspent credits with an expiry, soonest first, grouped by expiry date
+fn upcoming_expirations(conn: &Connection, user_id: &Uuid) -> Result<Vec<serde_json::Value>, ServiceError> {
+    let query = "SELECT SUM(remaining)::int4,
+                        to_char(date_trunc('day', expires_at) AT TIME ZONE 'UTC', 'YYYY-MM-DD')
+                 FROM subscriptions.credit_grants
+                 WHERE user_id = $1::uuid AND remaining > 0 AND expires_at > NOW()
+                 GROUP BY date_trunc('day', expires_at)
+                 ORDER BY date_trunc('day', expires_at) ASC";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Database query failed: {}", e)))?;
+
+    Ok(rows.rows.iter().map(|row| serde_json::json!({
+        "amount": i32::decode(&row[0]).unwrap_or(0),
+        "expires_on": String::decode(&row[1]).unwrap_or_default()
+    })).collect())
 }
 
 /// GET /credits/history - Get user's credit transaction history
@@ -202,7 +241,7 @@ pub fn create_credit_checkout_session(
     package_id: Uuid,
 ) -> Result<Response, ServiceError> {
     // Get package details
-    let query = "SELECT id, name, credit_amount, price_cents, stripe_price_id
+    let query = "SELECT id, name, credit_amount, price_cents, stripe_price_id, bonus_credits
                  FROM subscriptions.credit_packages
                  WHERE id = $1 AND is_active = true";
 
@@ -217,14 +256,18 @@ pub fn create_credit_checkout_session(
     let row = &result.rows()[0];
     let package_name = row.get::<&str>("name").unwrap();
     let credit_amount = row.get::<i32>("credit_amount").unwrap();
+    let bonus_credits = row.get::<i32>("bonus_credits").unwrap_or(0);
+    let expiry_days = row.get::<Option<i32>>("expiry_days").unwrap_or(None);
+    let bonus_expiry_days = row.get::<Option<i32>>("bonus_expiry_days").unwrap_or(None);
     let price_cents = row.get::<i32>("price_cents").unwrap();
     let stripe_price_id = row.get::<Option<&str>>("stripe_price_id").unwrap();
+    let bonus_credits = row.get::<i32>("bonus_credits").unwrap_or(0);
 
-    // Create pending order
+    // Create pending order; the bonus is fixed at checkout
     let order_id = Uuid::new_v4();
     let insert_query = "INSERT INTO subscriptions.credit_orders
-                       (id, user_id, package_id, credit_amount, price_cents, status)
-                       VALUES ($1, $2, $3, $4, $5, 'pending')";
+                       (id, user_id, package_id, credit_amount, price_cents, bonus_credits, status)
+                       VALUES ($1, $2, $3, $4, $5, $6, 'pending')";
 
     let insert_params = [
         ParameterValue::Uuid(order_id.as_bytes()),
@@ -232,6 +275,7 @@ pub fn create_credit_checkout_session(
         ParameterValue::Uuid(package_id.as_bytes()),
         ParameterValue::Int32(credit_amount),
         ParameterValue::Int32(price_cents),
+        ParameterValue::Int32(bonus_credits),
     ];
     conn.execute(insert_query, &insert_params)
         .map_err(|e| ServiceError::Internal(format!("Failed to create order: {}", e)))?;
@@ -263,6 +307,7 @@ pub fn create_credit_checkout_session(
         "package": {
             "name": package_name,
             "credits": credit_amount,
+            "bonus_credits": bonus_credits,
             "price_cents": price_cents
         }
     }))
@@ -280,8 +325,10 @@ pub fn handle_credit_purchase_webhook(
     }
 
     // Find order by session ID
-    let query = "SELECT id, user_id, credit_amount FROM subscriptions.credit_orders
-                 WHERE stripe_checkout_session_id = $1 AND status = 'pending'";
+    let query = "SELECT o.id, o.user_id, o.credit_amount, o.bonus_credits, p.expiry_days, p.bonus_expiry_days
+                 FROM subscriptions.credit_orders o
+                 LEFT JOIN subscriptions.credit_packages p ON p.id = o.package_id
+                 WHERE o.stripe_checkout_session_id = $1 AND o.status = 'pending'";
     let params = [ParameterValue::Str(session_id.to_string())];
     let result = conn.query(query, &params)
         .map_err(|e| ServiceError::Internal(format!("Database query failed: {}", e)))?;
@@ -306,14 +353,34 @@ pub fn handle_credit_purchase_webhook(
         ParameterValue::Uuid(order_id.as_bytes()),
     ]).map_err(|e| ServiceError::Internal(format!("Failed to update order: {}", e)))?;
 
-    // Add credits to user account using stored procedure
-    let add_credits = "SELECT subscriptions.add_credits($1, $2, 'purchase', 'Credit purchase', $3, 'order')";
+    // Purchased and bonus credits are separate grants so each keeps its own expiry
+    add_expiring_credits(conn, &user_id, credit_amount, "purchase", "Credit purchase", &order_id, expiry_days)?;
+    if bonus_credits > 0 {
+        add_expiring_credits(conn, &user_id, bonus_credits, "bonus", "Package bonus credits", &order_id, bonus_expiry_days)?;
+    }
+
+    Ok(())
+}
+
+fn add_expiring_credits(
+    conn: &Connection,
+    user_id: &Uuid,
+    amount: i32,
+    transaction_type: &str,
+    reason: &str,
+    order_id: &Uuid,
+    expiry_days: Option<i32>,
+) -> Result<(), ServiceError> {
+    let expires_at = expiry_days.map(|days| (Utc::now() + Duration::days(days as i64)).to_rfc3339());
+    let add_credits = "SELECT subscriptions.add_credits($1::uuid, $2, $3, $4, $5::uuid, 'order', $6::timestamptz)::text";
     conn.query(add_credits, &[
-        ParameterValue::Uuid(user_id.as_bytes()),
-        ParameterValue::Int32(credit_amount),
-        ParameterValue::Uuid(order_id.as_bytes()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Int32(amount),
+        ParameterValue::Str(transaction_type.to_string()),
+        ParameterValue::Str(reason.to_string()),
+        ParameterValue::Str(order_id.to_string()),
+        expires_at.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
     ]).map_err(|e| ServiceError::Internal(format!("Failed to add credits: {}", e)))?;
-
     Ok(())
 }
 
@@ -373,6 +440,86 @@ pub fn consume_credits(
     Ok(consumed)
 }
 
+//=============================================================================
+// Credit Grants
+//=============================================================================
+
+/// Add credits that were not bought, such as rewards. Returns the credit
+/// transaction id.
+pub fn grant_credits(
+    conn: &Connection,
+    user_id: &Uuid,
+    amount: i32,
+    transaction_type: &str,
+    reason: &str,
+    reference_id: Option<&Uuid>,
+    reference_type: Option<&str>,
+) -> Result<Option<String>, ServiceError> {
+    let query = "SELECT subscriptions.add_credits($1::uuid, $2, $3, $4, $5::uuid, $6)::text";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Int32(amount),
+        ParameterValue::Str(transaction_type.to_string()),
+        ParameterValue::Str(reason.chars().take(255).collect()),
+        reference_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+        reference_type.map(|t| ParameterValue::Str(t.to_string())).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Failed to add credits: {}", e)))?;
+
+    Ok(rows.rows.first().and_then(|row| String::decode(&row[0]).ok()))
+}
+
+//=============================================================================
+// Credit Expiry
+//=============================================================================
+
+/// POST /credits/expiry/process - Expire lapsed grants and warn users whose
+/// credits expire soon (internal)
+pub fn process_expiry(conn: &Connection) -> Result<Response, ServiceError> {
+    let rows = conn.query("SELECT subscriptions.expire_credits(NULL)", &[])
+        .map_err(|e| ServiceError::Internal(format!("Failed to expire credits: {}", e)))?;
+    let expired = rows.rows.first().map(|row| i32::decode(&row[0]).unwrap_or(0)).unwrap_or(0);
+
+    let warning_days = variables::get("credit_expiry_warning_days")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_EXPIRY_WARNING_DAYS);
+
+    // Each grant is warned about once, however many runs fall inside the window
+    let query = "SELECT user_id::text, SUM(remaining)::int4,
+                        to_char(MIN(expires_at) AT TIME ZONE 'UTC', 'YYYY-MM-DD'),
+                        string_agg(id::text, ',')
+                 FROM subscriptions.credit_grants
+                 WHERE remaining > 0 AND expiry_warned_at IS NULL
+                   AND expires_at > NOW() AND expires_at <= NOW() + make_interval(days => $1)
+                 GROUP BY user_id";
+    let rows = conn.query(query, &[ParameterValue::Int32(warning_days as i32)])
+        .map_err(|e| ServiceError::Internal(format!("Database query failed: {}", e)))?;
+
+    for row in &rows.rows {
+        let user_id = match Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()) {
+            Ok(id) => id,
+            Err(_) => continue,
+        };
+        let amount = i32::decode(&row[1]).unwrap_or(0);
+        let expires_on = String::decode(&row[2]).unwrap_or_default();
+        let grant_ids = String::decode(&row[3]).unwrap_or_default();
+
+        crate::dunning::notify_user(&user_id, "credits_expiring", "Credits expiring soon",
+            &format!("{} of your credits expire on {}. Use them before they're gone.", amount, expires_on),
+            serde_json::json!({ "amount": amount, "expires_on": expires_on }));
+
+        conn.execute("UPDATE subscriptions.credit_grants SET expiry_warned_at = NOW()
+                       WHERE id = ANY(string_to_array($1, ',')::uuid[])",
+            &[ParameterValue::Str(grant_ids)])
+            .map_err(|e| ServiceError::Internal(format!("Failed to update grants: {}", e)))?;
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "expired_credits": expired,
+        "users_warned": rows.rows.len()
+    }))
+}
+
 //=============================================================================
 // Credit Cost Configuration
 //=============================================================================
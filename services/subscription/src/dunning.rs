@@ -0,0 +1,430 @@
+//! Dunning Module
+//!
+//! Tracks failed subscription payments through a configurable reminder
+//! schedule, notifies the user via the messaging service at each step, and
+//! downgrades the account to the free plan once the schedule is exhausted.
+
+use crate::error::ServiceError;
+use serde::Serialize;
+use spin_sdk::http::Response;
+use spin_sdk::outbound_http::{self, Method as HttpMethod, Request as OutboundRequest};
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use uuid::Uuid;
+use chrono::{DateTime, Duration, Utc};
+
+//=============================================================================
+// Configuration
+//=============================================================================
+
+/// Days after the first failure at which a reminder is sent
+const DEFAULT_SCHEDULE_DAYS: [i64; 4] = [0, 3, 7, 14];
+
+/// Failed payment attempts tolerated before downgrading
+const DEFAULT_MAX_FAILURES: i32 = 4;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DunningConfig {
+    pub schedule_days: Vec<i64>,
+    pub max_failures: i32,
+}
+
+/// Load the dunning schedule from `dunning_schedule_days` (comma separated)
+/// and `dunning_max_failures`, falling back to the defaults.
+pub fn get_dunning_config() -> DunningConfig {
+    let schedule_days = variables::get("dunning_schedule_days")
+        .ok()
+        .map(|s| {
+            s.split(',')
+                .filter_map(|d| d.trim().parse::<i64>().ok())
+                .filter(|d| *d >= 0)
+                .collect::<Vec<_>>()
+        })
+        .filter(|days| !days.is_empty())
+        .unwrap_or_else(|| DEFAULT_SCHEDULE_DAYS.to_vec());
+
+    let max_failures = variables::get("dunning_max_failures")
+        .ok()
+        .and_then(|s| s.parse::<i32>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_MAX_FAILURES);
+
+    DunningConfig { schedule_days, max_failures }
+}
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Serialize)]
+pub struct DunningRecord {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub stripe_subscription_id: String,
+    pub stripe_invoice_id: Option<String>,
+    pub status: String,
+    pub failure_count: i32,
+    pub current_step: i32,
+    pub started_at: String,
+    pub last_failure_at: String,
+    pub next_action_at: Option<String>,
+    pub resolved_at: Option<String>,
+}
+
+/// Result of advancing a subscription through the dunning flow
+#[derive(Debug)]
+pub enum DunningOutcome {
+    /// No subscription matched the event
+    Ignored,
+    /// The user was notified and remains in dunning
+    Notified,
+    /// The schedule was exhausted and the account was moved to the free plan;
+    /// the caller is responsible for cancelling the Stripe subscription
+    Downgraded { stripe_subscription_id: String },
+}
+
+//=============================================================================
+// Webhook Hooks
+//=============================================================================
+
+/// Record a failed invoice payment (`invoice.payment_failed`)
+pub fn record_payment_failure(
+    conn: &Connection,
+    config: &DunningConfig,
+    stripe_subscription_id: &str,
+    stripe_invoice_id: &str,
+) -> Result<DunningOutcome, ServiceError> {
+    let query = "SELECT user_id FROM subscriptions.subscriptions WHERE stripe_subscription_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(stripe_subscription_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let user_id = match rows.rows.first()
+        .and_then(|row| String::decode(&row[0]).ok())
+        .and_then(|s| Uuid::parse_str(&s).ok())
+    {
+        Some(id) => id,
+        None => return Ok(DunningOutcome::Ignored),
+    };
+
+    let now = Utc::now();
+    let existing = find_active_record(conn, &user_id)?;
+
+    let record = match existing {
+        Some(mut record) => {
+            record.failure_count += 1;
+            record.last_failure_at = now.to_rfc3339();
+            record.stripe_invoice_id = Some(stripe_invoice_id.to_string());
+
+            let update = "UPDATE subscriptions.dunning
+                          SET failure_count = $2, last_failure_at = $3, stripe_invoice_id = $4, updated_at = $3
+                          WHERE id = $1";
+            conn.execute(update, &[
+                ParameterValue::Str(record.id.to_string()),
+                ParameterValue::Int32(record.failure_count),
+                ParameterValue::Str(now.to_rfc3339()),
+                ParameterValue::Str(stripe_invoice_id.to_string()),
+            ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+            record
+        }
+        None => {
+            let id = Uuid::new_v4();
+            let next_action_at = next_action_time(config, &now, 0);
+
+            let insert = "INSERT INTO subscriptions.dunning
+                          (id, user_id, stripe_subscription_id, stripe_invoice_id, status, failure_count,
+                           current_step, started_at, last_failure_at, next_action_at, updated_at)
+                          VALUES ($1, $2, $3, $4, 'active', 1, 0, $5, $5, $6, $5)";
+            conn.execute(insert, &[
+                ParameterValue::Str(id.to_string()),
+                ParameterValue::Str(user_id.to_string()),
+                ParameterValue::Str(stripe_subscription_id.to_string()),
+                ParameterValue::Str(stripe_invoice_id.to_string()),
+                ParameterValue::Str(now.to_rfc3339()),
+                next_action_at.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+            ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+            DunningRecord {
+                id,
+                user_id,
+                stripe_subscription_id: stripe_subscription_id.to_string(),
+                stripe_invoice_id: Some(stripe_invoice_id.to_string()),
+                status: "active".into(),
+                failure_count: 1,
+                current_step: 0,
+                started_at: now.to_rfc3339(),
+                last_failure_at: now.to_rfc3339(),
+                next_action_at: None,
+                resolved_at: None,
+            }
+        }
+    };
+
+    if record.failure_count >= config.max_failures {
+        return downgrade(conn, &record);
+    }
+
+    notify_user(&record.user_id, "payment_failed", "Payment failed",
+        &format!(
+            "We couldn't process your subscription payment (attempt {} of {}). Please update your payment method to keep your plan.",
+            record.failure_count, config.max_failures
+        ),
+        serde_json::json!({
+            "dunning_id": record.id,
+            "failure_count": record.failure_count,
+            "max_failures": config.max_failures,
+            "stripe_invoice_id": stripe_invoice_id
+        }),
+    );
+
+    Ok(DunningOutcome::Notified)
+}
+
+/// Close any active dunning record once an invoice is paid (`invoice.paid`)
+pub fn resolve_for_customer(conn: &Connection, stripe_customer_id: &str) -> Result<(), ServiceError> {
+    let query = "SELECT d.id, d.user_id FROM subscriptions.dunning d
+                 JOIN subscriptions.subscriptions s ON s.user_id = d.user_id
+                 WHERE s.stripe_customer_id = $1 AND d.status = 'active'";
+    let rows = conn.query(query, &[ParameterValue::Str(stripe_customer_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let now = Utc::now();
+    for row in &rows.rows {
+        let dunning_id = String::decode(&row[0]).unwrap_or_default();
+        let user_id = Uuid::parse_str(&String::decode(&row[1]).unwrap_or_default()).unwrap_or_default();
+
+        let update = "UPDATE subscriptions.dunning
+                      SET status = 'recovered', next_action_at = NULL, resolved_at = $2, updated_at = $2
+                      WHERE id = $1";
+        conn.execute(update, &[
+            ParameterValue::Str(dunning_id),
+            ParameterValue::Str(now.to_rfc3339()),
+        ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+        notify_user(&user_id, "payment_recovered", "Payment received",
+            "Thanks! Your payment went through and your subscription is active again.",
+            serde_json::json!({}),
+        );
+    }
+
+    Ok(())
+}
+
+//=============================================================================
+// Scheduled Processing
+//=============================================================================
+
+/// Send due reminders and downgrade accounts whose schedule has run out.
+///
+/// Returns the Stripe subscription IDs that were downgraded so the caller
+/// can cancel them with Stripe.
+pub fn process_due_reminders(conn: &Connection, config: &DunningConfig) -> Result<Vec<String>, ServiceError> {
+    let now = Utc::now();
+    let query = "SELECT id, user_id, stripe_subscription_id, stripe_invoice_id, status, failure_count,
+                 current_step, started_at, last_failure_at, next_action_at, resolved_at
+                 FROM subscriptions.dunning
+                 WHERE status = 'active' AND next_action_at IS NOT NULL AND next_action_at <= $1
+                 ORDER BY next_action_at ASC LIMIT 100";
+    let rows = conn.query(query, &[ParameterValue::Str(now.to_rfc3339())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut downgraded = Vec::new();
+
+    for row in &rows.rows {
+        let record = decode_record(row);
+        let step = record.current_step + 1;
+
+        if step as usize >= config.schedule_days.len() {
+            if let DunningOutcome::Downgraded { stripe_subscription_id } = downgrade(conn, &record)? {
+                downgraded.push(stripe_subscription_id);
+            }
+            continue;
+        }
+
+        let started_at = DateTime::parse_from_rfc3339(&record.started_at)
+            .map(|dt| dt.with_timezone(&Utc))
+            .unwrap_or(now);
+        let next_action_at = next_action_time(config, &started_at, step as usize);
+
+        let update = "UPDATE subscriptions.dunning
+                      SET current_step = $2, next_action_at = $3, updated_at = $4
+                      WHERE id = $1";
+        conn.execute(update, &[
+            ParameterValue::Str(record.id.to_string()),
+            ParameterValue::Int32(step),
+            next_action_at.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+            ParameterValue::Str(now.to_rfc3339()),
+        ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+        let remaining = config.schedule_days.len() as i32 - step;
+        notify_user(&record.user_id, "payment_failed", "Payment still outstanding",
+            &format!(
+                "Your subscription payment is still outstanding. Update your payment method within {} reminder(s) to avoid losing your plan.",
+                remaining
+            ),
+            serde_json::json!({
+                "dunning_id": record.id,
+                "step": step,
+                "failure_count": record.failure_count
+            }),
+        );
+    }
+
+    Ok(downgraded)
+}
+
+fn downgrade(conn: &Connection, record: &DunningRecord) -> Result<DunningOutcome, ServiceError> {
+    let now = Utc::now();
+
+    let update_sub = "UPDATE subscriptions.subscriptions
+                      SET plan_id = 'free', status = 'unpaid', updated_at = $2
+                      WHERE user_id = $1";
+    conn.execute(update_sub, &[
+        ParameterValue::Str(record.user_id.to_string()),
+        ParameterValue::Str(now.to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let update_dunning = "UPDATE subscriptions.dunning
+                          SET status = 'downgraded', next_action_at = NULL, resolved_at = $2, updated_at = $2
+                          WHERE id = $1";
+    conn.execute(update_dunning, &[
+        ParameterValue::Str(record.id.to_string()),
+        ParameterValue::Str(now.to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    crate::billing_events::record(conn, &record.user_id, "subscription.downgraded", crate::billing_events::Source::System, None, serde_json::json!({
+        "reason": "dunning_exhausted",
+        "dunning_id": record.id,
+        "failure_count": record.failure_count,
+        "stripe_subscription_id": record.stripe_subscription_id
+    }))?;
+
+    notify_user(&record.user_id, "subscription_downgraded", "Subscription downgraded",
+        "We were unable to collect payment, so your account has been moved to the Free plan. You can resubscribe at any time.",
+        serde_json::json!({
+            "dunning_id": record.id,
+            "failure_count": record.failure_count
+        }),
+    );
+
+    Ok(DunningOutcome::Downgraded {
+        stripe_subscription_id: record.stripe_subscription_id.clone(),
+    })
+}
+
+//=============================================================================
+// Status Endpoint
+//=============================================================================
+
+/// GET /subscription/dunning - Show where the user is in the dunning flow
+pub fn get_dunning_status(conn: &Connection, config: &DunningConfig, user_id: Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT id, user_id, stripe_subscription_id, stripe_invoice_id, status, failure_count,
+                 current_step, started_at, last_failure_at, next_action_at, resolved_at
+                 FROM subscriptions.dunning
+                 WHERE user_id = $1
+                 ORDER BY started_at DESC LIMIT 1";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let record = match rows.rows.first() {
+        Some(row) => decode_record(row),
+        None => {
+            return crate::json_response(200, serde_json::json!({
+                "in_dunning": false,
+                "config": config
+            }));
+        }
+    };
+
+    let started_at = DateTime::parse_from_rfc3339(&record.started_at)
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(|_| Utc::now());
+
+    let steps: Vec<serde_json::Value> = config.schedule_days.iter().enumerate().map(|(i, days)| {
+        serde_json::json!({
+            "step": i,
+            "scheduled_at": (started_at + Duration::days(*days)).to_rfc3339(),
+            "completed": (i as i32) <= record.current_step
+        })
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "in_dunning": record.status == "active",
+        "status": record.status,
+        "failure_count": record.failure_count,
+        "max_failures": config.max_failures,
+        "current_step": record.current_step,
+        "steps": steps,
+        "started_at": record.started_at,
+        "last_failure_at": record.last_failure_at,
+        "next_action_at": record.next_action_at,
+        "resolved_at": record.resolved_at
+    }))
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+fn find_active_record(conn: &Connection, user_id: &Uuid) -> Result<Option<DunningRecord>, ServiceError> {
+    let query = "SELECT id, user_id, stripe_subscription_id, stripe_invoice_id, status, failure_count,
+                 current_step, started_at, last_failure_at, next_action_at, resolved_at
+                 FROM subscriptions.dunning
+                 WHERE user_id = $1 AND status = 'active'";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first().map(decode_record))
+}
+
+fn decode_record(row: &spin_sdk::pg::Row) -> DunningRecord {
+    DunningRecord {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        user_id: Uuid::parse_str(&String::decode(&row[1]).unwrap_or_default()).unwrap_or_default(),
+        stripe_subscription_id: String::decode(&row[2]).unwrap_or_default(),
+        stripe_invoice_id: String::decode(&row[3]).ok(),
+        status: String::decode(&row[4]).unwrap_or_default(),
+        failure_count: i32::decode(&row[5]).unwrap_or(0),
+        current_step: i32::decode(&row[6]).unwrap_or(0),
+        started_at: String::decode(&row[7]).unwrap_or_default(),
+        last_failure_at: String::decode(&row[8]).unwrap_or_default(),
+        next_action_at: String::decode(&row[9]).ok(),
+        resolved_at: String::decode(&row[10]).ok(),
+    }
+}
+
+/// Time of the reminder following `step`, or `None` when it is the last one
+fn next_action_time(config: &DunningConfig, started_at: &DateTime<Utc>, step: usize) -> Option<String> {
+    config.schedule_days.get(step + 1)
+        .map(|days| (*started_at + Duration::days(*days)).to_rfc3339())
+        .or_else(|| {
+            // Past the last reminder: act one day later to downgrade
+            config.schedule_days.last()
+                .filter(|_| step + 1 == config.schedule_days.len())
+                .map(|days| (*started_at + Duration::days(*days + 1)).to_rfc3339())
+        })
+}
+
+/// Fire-and-forget notification through the messaging service
+pub fn notify_user(user_id: &Uuid, notification_type: &str, title: &str, body: &str, data: serde_json::Value) {
+    let messaging_url = variables::get("messaging_service_url")
+        .unwrap_or_else(|_| "http://messaging-service:3106".to_string());
+
+    let request_body = serde_json::json!({
+        "user_id": user_id,
+        "type": notification_type,
+        "title": title,
+        "body": body,
+        "data": data
+    });
+
+    let request = OutboundRequest::builder()
+        .method(HttpMethod::Post)
+        .uri(format!("{}/notifications", messaging_url))
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_vec(&request_body).unwrap_or_default())
+        .build();
+
+    // Billing jobs must not fail because messaging is unavailable
+    let _ = outbound_http::send(request);
+}
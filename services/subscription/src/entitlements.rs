@@ -0,0 +1,284 @@
+//! Entitlements
+//!
+//! One place that answers "may this user do X?" so services stop re-deriving
+//! plan rules. `POST /entitlements/check` takes a feature and a quantity:
+//! limit features (`books`, `ai_words`, ...) are compared against the plan's
+//! limits and current usage; any other feature is a plan flag.
+//!
+//! A `past_due` account keeps its paid plan for `entitlement_grace_days`
+//! from the first failed payment, so a card problem does not lock an author
+//! out mid-chapter. After that, and for `unpaid` or `cancelled`
+//! subscriptions, checks are made against the free plan.
+//!
+//! A `paused` subscription is read-only: every check is refused with reason
+//! `paused` until the pause ends, whatever the plan would allow. Writes that
+//! no plan limits (chapter edits, uploads) check the `write` feature, which
+//! only a pause refuses.
+
+use crate::error::ServiceError;
+use crate::models::Plan;
+use crate::{overage, pause, plans};
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use uuid::Uuid;
+
+const DEFAULT_GRACE_DAYS: i64 = 7;
+
+const BYTES_PER_GB: i64 = 1_073_741_824;
+
+/// Features backed by a plan limit rather than a flag
+const LIMIT_FEATURES: [&str; 5] = ["books", "chapters_per_book", "ai_words", "storage_bytes", "collaborators"];
+
+/// Granted to every account that is not read-only
+const WRITE_FEATURE: &str = "write";
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct CheckRequest {
+    pub feature: String,
+    /// Units about to be used; ignored for flags
+    #[serde(default = "default_quantity")]
+    pub quantity: i64,
+    /// Usage the caller already knows. Required for `chapters_per_book` and
+    /// `collaborators`, which are counted per book.
+    pub current_usage: Option<i64>,
+}
+
+fn default_quantity() -> i64 {
+    1
+}
+
+#[derive(Debug, Serialize)]
+pub struct Entitlement {
+    pub feature: String,
+    pub allowed: bool,
+    /// `granted`, `limit_exceeded`, `not_in_plan`, `grace_expired` or `paused`
+    pub reason: &'static str,
+    pub quantity: i64,
+    /// -1 for unlimited; absent for flags
+    pub limit: Option<i64>,
+    pub used: Option<i64>,
+    pub remaining: Option<i64>,
+    /// Allowed past the limit because metered overage is enabled
+    pub overage: bool,
+    pub plan_id: String,
+    /// Plan the check was made against; differs from `plan_id` once grace has run out
+    pub effective_plan_id: String,
+    pub subscription_status: String,
+    pub in_grace_period: bool,
+    pub grace_period_ends_at: Option<String>,
+    /// When a paused subscription's read-only period ends
+    pub paused_until: Option<String>,
+}
+
+/// Subscription standing after applying the grace period
+struct Standing {
+    plan_id: String,
+    effective_plan_id: String,
+    status: String,
+    overage_enabled: bool,
+    in_grace_period: bool,
+    grace_period_ends_at: Option<String>,
+    paused_until: Option<String>,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /entitlements/check - Whether the caller may use a feature
+pub fn check(conn: &Connection, user_id: &Uuid, body: CheckRequest) -> Result<Response, ServiceError> {
+    let feature = body.feature.trim().to_string();
+    if feature.is_empty() {
+        return Err(ServiceError::BadRequest("feature is required".into()));
+    }
+    if body.quantity < 0 {
+        return Err(ServiceError::BadRequest("quantity cannot be negative".into()));
+    }
+    if body.current_usage.is_some_and(|u| u < 0) {
+        return Err(ServiceError::BadRequest("current_usage cannot be negative".into()));
+    }
+
+    let catalogue = plans::all(conn)?;
+    let is_limit = LIMIT_FEATURES.contains(&feature.as_str());
+    if !is_limit && feature != WRITE_FEATURE && !catalogue.iter().any(|p| p.plan.flags.contains(&feature)) {
+        return Err(ServiceError::BadRequest(format!("Unknown feature '{}'", feature)));
+    }
+
+    let standing = standing(conn, user_id)?;
+    let plan_for = |plan_id: &str| -> Result<Plan, ServiceError> {
+        catalogue.iter()
+            .find(|p| p.plan.id == plan_id)
+            .or_else(|| catalogue.iter().find(|p| p.plan.id == plans::FREE_PLAN))
+            .map(|p| p.plan.clone())
+            .ok_or_else(|| ServiceError::Internal("No plan definitions found".into()))
+    };
+    let effective = plan_for(&standing.effective_plan_id)?;
+
+    let mut entitlement = Entitlement {
+        feature: feature.clone(),
+        allowed: false,
+        reason: "not_in_plan",
+        quantity: body.quantity,
+        limit: None,
+        used: None,
+        remaining: None,
+        overage: false,
+        plan_id: standing.plan_id.clone(),
+        effective_plan_id: standing.effective_plan_id.clone(),
+        subscription_status: standing.status.clone(),
+        in_grace_period: standing.in_grace_period,
+        grace_period_ends_at: standing.grace_period_ends_at.clone(),
+        paused_until: standing.paused_until.clone(),
+    };
+
+    if standing.status == pause::STATUS {
+        entitlement.reason = "paused";
+        return crate::json_response(200, entitlement);
+    }
+
+    if feature == WRITE_FEATURE {
+        entitlement.allowed = true;
+        entitlement.reason = "granted";
+    } else if is_limit {
+        let limit = limit_for(&effective, &feature);
+        let used = match body.current_usage {
+            Some(used) => used,
+            None => measure_usage(conn, user_id, &feature)?,
+        };
+        let within = limit == -1 || used + body.quantity <= limit;
+        // Pro overage bills AI words past the allowance instead of refusing them
+        let overage = !within && feature == "ai_words" && standing.overage_enabled
+            && effective.id == standing.plan_id;
+
+        entitlement.limit = Some(limit);
+        entitlement.used = Some(used);
+        entitlement.remaining = Some(if limit == -1 { -1 } else { (limit - used).max(0) });
+        entitlement.allowed = within || overage;
+        entitlement.overage = overage;
+        entitlement.reason = if entitlement.allowed { "granted" } else { "limit_exceeded" };
+    } else if effective.flags.contains(&feature) {
+        entitlement.allowed = true;
+        entitlement.reason = "granted";
+    }
+
+    // Tell the caller the paid plan would have allowed it, so the UI can ask
+    // for a card update rather than an upgrade
+    if !entitlement.allowed && standing.effective_plan_id != standing.plan_id {
+        let paid = plan_for(&standing.plan_id)?;
+        let paid_allows = if is_limit {
+            let limit = limit_for(&paid, &feature);
+            limit == -1 || entitlement.used.unwrap_or(0) + body.quantity <= limit
+        } else {
+            paid.flags.contains(&feature)
+        };
+        if paid_allows {
+            entitlement.reason = "grace_expired";
+        }
+    }
+
+    crate::json_response(200, entitlement)
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+fn grace_days() -> i64 {
+    variables::get("entitlement_grace_days")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|d| *d >= 0)
+        .unwrap_or(DEFAULT_GRACE_DAYS)
+}
+
+/// The user's plan and status, and which plan currently applies. Grace is
+/// counted from the start of the active dunning run, or from the last
+/// subscription change when no run is recorded.
+fn standing(conn: &Connection, user_id: &Uuid) -> Result<Standing, ServiceError> {
+    let query = "SELECT plan_id, status, overage_enabled, (grace_start + $2 * INTERVAL '1 day')::text,
+                        grace_start + $2 * INTERVAL '1 day' > NOW(), pause_resumes_at::text
+                 FROM (
+                     SELECT s.plan_id, s.status, COALESCE(s.overage_enabled, false) AS overage_enabled,
+                            s.pause_resumes_at,
+                            COALESCE((SELECT d.started_at FROM subscriptions.dunning d
+                                      WHERE d.user_id = s.user_id AND d.status = 'active'),
+                                     s.updated_at, NOW()) AS grace_start
+                     FROM subscriptions.subscriptions s WHERE s.user_id = $1
+                 ) sub";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Int64(grace_days()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let row = match rows.rows.first() {
+        Some(row) => row,
+        None => {
+            return Ok(Standing {
+                plan_id: plans::FREE_PLAN.into(),
+                effective_plan_id: plans::FREE_PLAN.into(),
+                status: "none".into(),
+                overage_enabled: false,
+                in_grace_period: false,
+                grace_period_ends_at: None,
+                paused_until: None,
+            })
+        }
+    };
+
+    let plan_id = String::decode(&row[0]).unwrap_or_else(|_| plans::FREE_PLAN.into());
+    let status = String::decode(&row[1]).unwrap_or_default();
+    let overage_enabled = bool::decode(&row[2]).unwrap_or(false);
+    let paused_until = String::decode(&row[5]).ok().filter(|_| status == pause::STATUS);
+
+    let (effective_plan_id, in_grace_period, grace_period_ends_at) = match status.as_str() {
+        // Paused accounts keep their plan; `check` makes them read-only
+        "active" | "trialing" | pause::STATUS => (plan_id.clone(), false, None),
+        "past_due" => {
+            let ends_at = String::decode(&row[3]).ok();
+            if bool::decode(&row[4]).unwrap_or(false) {
+                (plan_id.clone(), true, ends_at)
+            } else {
+                (plans::FREE_PLAN.to_string(), false, ends_at)
+            }
+        }
+        _ => (plans::FREE_PLAN.to_string(), false, None),
+    };
+
+    Ok(Standing { plan_id, effective_plan_id, status, overage_enabled, in_grace_period, grace_period_ends_at, paused_until })
+}
+
+fn limit_for(plan: &Plan, feature: &str) -> i64 {
+    let limits = &plan.limits;
+    match feature {
+        "books" => limits.max_books as i64,
+        "chapters_per_book" => limits.max_chapters_per_book as i64,
+        "ai_words" => limits.ai_words_per_month,
+        "storage_bytes" if limits.storage_gb == -1 => -1,
+        "storage_bytes" => limits.storage_gb as i64 * BYTES_PER_GB,
+        "collaborators" => limits.collaborators as i64,
+        _ => 0,
+    }
+}
+
+/// Account-wide usage; per-book limits must be supplied by the caller
+fn measure_usage(conn: &Connection, user_id: &Uuid, feature: &str) -> Result<i64, ServiceError> {
+    let query = match feature {
+        "ai_words" => return overage::words_used(conn, user_id, overage::current_period(conn, user_id)?.start),
+        "books" => "SELECT COUNT(*) FROM content.books WHERE author_id = $1",
+        "storage_bytes" => "SELECT COALESCE(SUM(size), 0)::bigint FROM storage.files WHERE user_id = $1",
+        _ => {
+            return Err(ServiceError::BadRequest(format!(
+                "current_usage is required for '{}'", feature
+            )))
+        }
+    };
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    Ok(rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0))
+}
@@ -83,3 +83,11 @@ impl ServiceError {
     }
 }
 
+impl From<authorworks_access::AccessError> for ServiceError {
+    fn from(e: authorworks_access::AccessError) -> Self {
+        match e {
+            authorworks_access::AccessError::RoleLookup(msg) => ServiceError::Internal(format!("Query failed: {}", msg)),
+            e => ServiceError::Forbidden(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! LemonSqueezy payment provider
+//!
+//! LemonSqueezy acts as merchant of record where Stripe is unavailable. Its
+//! API differs from Stripe's in ways callers can see:
+//! - subscriptions can only be started from a hosted checkout, so
+//!   `POST /subscription` is rejected and the subscription row is created by
+//!   the `subscription_created` webhook, matched to the user through the
+//!   checkout's custom data
+//! - cancelling always runs to the end of the period; an immediate cancel
+//!   (dunning downgrade) cancels the same way and the local downgrade applies
+//!   at once
+//! - the customer portal is a fixed per-customer URL, so `return_url` is unused
+//!
+//! Webhooks are signed with an HMAC-SHA256 of the raw body in `X-Signature`
+//! and carry no event id, so the body's SHA-256 identifies a delivery.
+
+use crate::error::ServiceError;
+use crate::models::{CheckoutSession, LemonSqueezyConfig, PortalSession, ProviderSubscription};
+use crate::payments::{PaymentEvent, PaymentProvider, PaymentReceipt, SubscriptionChange, WebhookEvent};
+use crate::tax::InvoiceTax;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use spin_sdk::http::Request;
+use spin_sdk::outbound_http;
+use spin_sdk::variables;
+use uuid::Uuid;
+
+pub const PROVIDER: &str = "lemonsqueezy";
+
+const API_BASE: &str = "https://api.lemonsqueezy.com";
+
+pub fn get_config() -> Result<LemonSqueezyConfig, ServiceError> {
+    Ok(LemonSqueezyConfig {
+        api_key: variables::get("lemonsqueezy_api_key")
+            .map_err(|_| ServiceError::Internal("LEMONSQUEEZY_API_KEY not configured".into()))?,
+        webhook_secret: variables::get("lemonsqueezy_webhook_secret")
+            .map_err(|_| ServiceError::Internal("LEMONSQUEEZY_WEBHOOK_SECRET not configured".into()))?,
+        store_id: variables::get("lemonsqueezy_store_id")
+            .map_err(|_| ServiceError::Internal("LEMONSQUEEZY_STORE_ID not configured".into()))?,
+    })
+}
+
+pub struct LemonSqueezyProvider {
+    config: LemonSqueezyConfig,
+}
+
+impl LemonSqueezyProvider {
+    pub fn new(config: LemonSqueezyConfig) -> Self {
+        Self { config }
+    }
+
+    fn store(&self) -> serde_json::Value {
+        serde_json::json!({ "data": { "type": "stores", "id": self.config.store_id } })
+    }
+
+    fn set_pause(&self, subscription_id: &str, pause: serde_json::Value) -> Result<(), ServiceError> {
+        let body = serde_json::json!({
+            "data": { "type": "subscriptions", "id": subscription_id, "attributes": { "pause": pause } }
+        });
+        self.request("PATCH", &format!("/v1/subscriptions/{}", subscription_id), Some(body))?;
+        Ok(())
+    }
+
+    fn get_customer(&self, customer_id: &str) -> Result<serde_json::Value, ServiceError> {
+        self.request("GET", &format!("/v1/customers/{}", customer_id), None)
+    }
+
+    fn request(&self, method: &str, path: &str, body: Option<serde_json::Value>) -> Result<serde_json::Value, ServiceError> {
+        let request = outbound_http::Request::builder()
+            .method(method)
+            .uri(&format!("{}{}", API_BASE, path))
+            .header("Authorization", &format!("Bearer {}", self.config.api_key))
+            .header("Accept", "application/vnd.api+json")
+            .header("Content-Type", "application/vnd.api+json")
+            .body(body.map(|b| b.to_string()).unwrap_or_default())
+            .build();
+
+        let response = outbound_http::send(request)
+            .map_err(|e| ServiceError::Internal(format!("LemonSqueezy request failed: {}", e)))?;
+
+        if response.status() >= 400 {
+            return Err(ServiceError::Internal(format!(
+                "LemonSqueezy API error: {} - {}",
+                response.status(),
+                String::from_utf8_lossy(response.body())
+            )));
+        }
+        // Cancelling returns the subscription, but other writes may be empty
+        if response.body().is_empty() {
+            return Ok(serde_json::Value::Null);
+        }
+
+        serde_json::from_slice(response.body())
+            .map_err(|e| ServiceError::Internal(format!("Failed to parse LemonSqueezy response: {}", e)))
+    }
+}
+
+impl PaymentProvider for LemonSqueezyProvider {
+    fn name(&self) -> &'static str {
+        PROVIDER
+    }
+
+    fn create_customer(&self, email: &str, user_id: &Uuid) -> Result<String, ServiceError> {
+        let body = serde_json::json!({
+            "data": {
+                "type": "customers",
+                "attributes": { "name": email, "email": email },
+                "relationships": { "store": self.store() }
+            }
+        });
+
+        let response = self.request("POST", "/v1/customers", Some(body))?;
+        response.pointer("/data/id")
+            .and_then(id_string)
+            .ok_or_else(|| ServiceError::Internal(format!("Failed to create LemonSqueezy customer for {}", user_id)))
+    }
+
+    fn create_subscription(&self, _customer_id: &str, _price_id: &str) -> Result<ProviderSubscription, ServiceError> {
+        Err(ServiceError::BadRequest(
+            "LemonSqueezy subscriptions start from checkout; use POST /checkout".into()
+        ))
+    }
+
+    fn change_plan(&self, subscription_id: &str, price_id: &str, invoice_now: bool) -> Result<(), ServiceError> {
+        let variant_id: i64 = price_id.parse()
+            .map_err(|_| ServiceError::Internal(format!("Invalid LemonSqueezy variant '{}'", price_id)))?;
+        let body = serde_json::json!({
+            "data": {
+                "type": "subscriptions",
+                "id": subscription_id,
+                "attributes": { "variant_id": variant_id, "invoice_immediately": invoice_now }
+            }
+        });
+
+        self.request("PATCH", &format!("/v1/subscriptions/{}", subscription_id), Some(body))?;
+        Ok(())
+    }
+
+    fn cancel_at_period_end(&self, subscription_id: &str) -> Result<(), ServiceError> {
+        self.request("DELETE", &format!("/v1/subscriptions/{}", subscription_id), None)?;
+        Ok(())
+    }
+
+    fn cancel_now(&self, subscription_id: &str) -> Result<(), ServiceError> {
+        self.cancel_at_period_end(subscription_id)
+    }
+
+    fn pause(&self, subscription_id: &str, resumes_at: DateTime<Utc>) -> Result<(), ServiceError> {
+        self.set_pause(subscription_id, serde_json::json!({ "mode": "void", "resumes_at": resumes_at.to_rfc3339() }))
+    }
+
+    fn resume(&self, subscription_id: &str) -> Result<(), ServiceError> {
+        self.set_pause(subscription_id, serde_json::Value::Null)
+    }
+
+    fn create_checkout(
+        &self,
+        customer_id: &str,
+        user_id: &Uuid,
+        price_id: &str,
+        success_url: &str,
+        _cancel_url: &str,
+    ) -> Result<CheckoutSession, ServiceError> {
+        let email = self.get_customer(customer_id)?
+            .pointer("/data/attributes/email")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+
+        let body = serde_json::json!({
+            "data": {
+                "type": "checkouts",
+                "attributes": {
+                    "checkout_data": {
+                        "email": email,
+                        "custom": { "user_id": user_id.to_string() }
+                    },
+                    "product_options": { "redirect_url": success_url }
+                },
+                "relationships": {
+                    "store": self.store(),
+                    "variant": { "data": { "type": "variants", "id": price_id } }
+                }
+            }
+        });
+
+        let response = self.request("POST", "/v1/checkouts", Some(body))?;
+        Ok(CheckoutSession {
+            id: response.pointer("/data/id").and_then(id_string).unwrap_or_default(),
+            url: response.pointer("/data/attributes/url").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+        })
+    }
+
+    fn create_portal(&self, customer_id: &str, _return_url: &str) -> Result<PortalSession, ServiceError> {
+        let customer = self.get_customer(customer_id)?;
+        let url = customer.pointer("/data/attributes/urls/customer_portal")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ServiceError::NotFound("No customer portal available".into()))?;
+
+        Ok(PortalSession { url: url.to_string() })
+    }
+
+    fn parse_webhook(&self, req: &Request) -> Result<WebhookEvent, ServiceError> {
+        let signature = req.header("X-Signature")
+            .and_then(|h| h.as_str())
+            .and_then(|s| hex::decode(s).ok())
+            .ok_or_else(|| ServiceError::BadRequest("Missing signature".into()))?;
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.config.webhook_secret.as_bytes())
+            .map_err(|_| ServiceError::Internal("HMAC error".into()))?;
+        mac.update(req.body());
+        mac.verify_slice(&signature)
+            .map_err(|_| ServiceError::BadRequest("Invalid signature".into()))?;
+
+        let payload: serde_json::Value = serde_json::from_slice(req.body())
+            .map_err(|e| ServiceError::BadRequest(format!("Invalid event: {}", e)))?;
+
+        Ok(normalize(hex::encode(Sha256::digest(req.body())), payload))
+    }
+}
+
+//=============================================================================
+// Webhook Normalization
+//=============================================================================
+
+fn normalize(id: String, payload: serde_json::Value) -> WebhookEvent {
+    let event_name = payload.pointer("/meta/event_name").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+    let user_id = payload.pointer("/meta/custom_data/user_id")
+        .and_then(|v| v.as_str())
+        .and_then(|s| Uuid::parse_str(s).ok());
+    let object = payload.get("data").cloned().unwrap_or_default();
+    let object_id = object.get("id").and_then(id_string);
+    let attribute = |name: &str| object.pointer(&format!("/attributes/{}", name));
+    let attribute_str = |name: &str| attribute(name).and_then(|v| v.as_str()).map(|s| s.to_string());
+
+    let customer_id = attribute("customer_id").and_then(id_string);
+    let is_subscription = object.get("type").and_then(|v| v.as_str()) == Some("subscriptions");
+    let subscription_id = if is_subscription {
+        object_id.clone()
+    } else {
+        attribute("subscription_id").and_then(id_string)
+    };
+    let raw_status = attribute_str("status");
+
+    let kind = match event_name.as_str() {
+        "subscription_expired" => PaymentEvent::SubscriptionEnded {
+            subscription_id: object_id.clone().unwrap_or_default(),
+        },
+        name if name.starts_with("subscription_") && is_subscription => {
+            match raw_status.as_deref() {
+                Some("expired") => PaymentEvent::SubscriptionEnded {
+                    subscription_id: object_id.clone().unwrap_or_default(),
+                },
+                status => {
+                    let cancelled = status == Some("cancelled")
+                        || attribute("cancelled").and_then(|v| v.as_bool()).unwrap_or(false);
+                    PaymentEvent::SubscriptionChanged(SubscriptionChange {
+                        subscription_id: object_id.clone().unwrap_or_default(),
+                        customer_id: customer_id.clone(),
+                        user_id,
+                        price_id: attribute("variant_id").and_then(id_string),
+                        status: normalize_status(status).to_string(),
+                        current_period_start: None,
+                        current_period_end: attribute_str(if cancelled { "ends_at" } else { "renews_at" }),
+                        cancel_at_period_end: cancelled,
+                        pause_resumes_at: object.pointer("/attributes/pause/resumes_at")
+                            .and_then(|v| v.as_str())
+                            .map(|s| s.to_string()),
+                    })
+                }
+            }
+        }
+        "subscription_payment_success" | "subscription_payment_recovered" => {
+            let amount = |name: &str| attribute(name).and_then(|v| v.as_i64());
+            PaymentEvent::PaymentSucceeded(PaymentReceipt {
+                customer_id: customer_id.clone().unwrap_or_default(),
+                invoice_id: object_id.clone().unwrap_or_default(),
+                amount: amount("total").unwrap_or(0),
+                tax: InvoiceTax {
+                    subtotal: amount("subtotal"),
+                    tax: amount("tax"),
+                    total: amount("total"),
+                    currency: attribute_str("currency").map(|c| c.to_lowercase()),
+                    breakdown: serde_json::json!([]),
+                    customer_tax_ids: serde_json::json!([]),
+                },
+                payment_intent_id: None,
+            })
+        }
+        "subscription_payment_failed" => PaymentEvent::PaymentFailed {
+            subscription_id: subscription_id.clone().unwrap_or_default(),
+            invoice_id: object_id.clone().unwrap_or_default(),
+        },
+        _ => PaymentEvent::Other,
+    };
+
+    let details = serde_json::json!({
+        "object_id": object_id,
+        "status": raw_status,
+        "amount_paid": attribute("total").and_then(|v| v.as_i64()),
+        "currency": attribute_str("currency").map(|c| c.to_lowercase()),
+        "cancel_at_period_end": attribute("cancelled").and_then(|v| v.as_bool()),
+        "billing_reason": attribute_str("billing_reason")
+    });
+
+    WebhookEvent {
+        id,
+        provider_type: event_name,
+        subscription_id,
+        customer_id,
+        details,
+        object,
+        kind,
+    }
+}
+
+/// LemonSqueezy's `cancelled` still has paid access until `ends_at`, and
+/// `paused` is read-only like our own pauses
+fn normalize_status(status: Option<&str>) -> &'static str {
+    match status {
+        Some("on_trial") => "trialing",
+        Some("past_due") => "past_due",
+        Some("paused") => crate::pause::STATUS,
+        Some("unpaid") => "unpaid",
+        _ => "active",
+    }
+}
+
+/// LemonSqueezy ids are numbers in attributes and strings at the top level
+fn id_string(value: &serde_json::Value) -> Option<String> {
+    value.as_str()
+        .map(|s| s.to_string())
+        .or_else(|| value.as_i64().map(|n| n.to_string()))
+}
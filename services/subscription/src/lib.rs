@@ -1,19 +1,45 @@
 //! AuthorWorks Subscription Service
 //!
-//! Handles subscription plans, billing, and payment processing via Stripe.
+//! Handles subscription plans, billing, and payment processing via Stripe or
+//! LemonSqueezy (see `payments`).
 //!
 //! ## Endpoints
 //! - GET /health - Health check
 //! - GET /plans - List available plans
 //! - GET /subscription - Get user's subscription
 //! - POST /subscription - Create subscription
-//! - PUT /subscription - Update subscription
+//! - PUT /subscription - Change plan and/or billing interval (month/year), prorated
 //! - DELETE /subscription - Cancel subscription
+//! - POST /subscription/pause - Pause billing for a number of days; the account is read-only meanwhile
+//! - POST /subscription/resume - End a pause early
+//! - POST /subscription/pause/process - Reactivate expired pauses and notify their authors (internal, X-Internal-Token)
+//! - GET /subscription/dunning - Get failed-payment (dunning) status
+//! - POST /subscription/dunning/process - Send due dunning reminders (internal, X-Internal-Token)
+//! - PUT /subscription/overage - Opt in or out of metered AI word overage (Pro)
+//! - POST /subscription/overage/report - Report AI word overage to Stripe (internal, X-Internal-Token)
 //! - POST /checkout - Create checkout session
 //! - POST /portal - Create customer portal session
-//! - POST /webhooks/stripe - Handle Stripe webhooks
-//! - GET /invoices - List user's invoices
+//! - POST /webhooks/stripe - Handle Stripe webhooks, including refunds, disputes and failed credit payments
+//! - POST /webhooks/lemonsqueezy - Handle LemonSqueezy webhooks
+//! - GET /invoices - List user's invoices with tax breakdowns
+//! - GET /billing/details - Get billing address and tax ID
+//! - PUT /billing/details - Set billing address and tax ID (VAT/GST)
 //! - GET /usage - Get usage statistics
+//! - POST /credits/expiry/process - Expire lapsed credits and send expiry warnings (internal, X-Internal-Token)
+//! - POST /entitlements/check - Check a feature flag or limit for the caller, with past_due grace
+//! - GET /referrals/code - Get the caller's referral code
+//! - POST /referrals/redeem - Apply a referral code to a new account
+//! - GET /referrals/stats - Referral signups, conversions and credits earned
+//! - PUT /admin/subscriptions/:user_id - Override a user's plan, status or period (admin)
+//! - POST /admin/credits/grant - Grant credits to a user (admin)
+//! - POST /admin/credits/adjust - Add expiring credits to or remove credits from a user (admin)
+//! - GET /admin/audit - List admin changes, optionally filtered by user_id (admin)
+//! - GET /admin/users/:id/billing-timeline - Subscription changes, webhooks, credits and invoices in one feed (admin)
+//! - GET /admin/plans - List all plans including inactive ones (admin)
+//! - POST /admin/plans - Create a plan (admin)
+//! - PUT /admin/plans/:id - Update a plan's prices, features, limits or availability (admin)
+//! - DELETE /admin/plans/:id - Deactivate a plan (admin)
+//! - GET /admin/analytics/revenue - MRR, churn, upgrades/downgrades and ARPU by period, as JSON or CSV (admin)
 
 use spin_sdk::http::{IntoResponse, Request, Response, Method};
 use spin_sdk::http_component;
@@ -30,6 +56,20 @@ mod models;
 mod error;
 mod stripe;
 mod credits;
+mod dunning;
+mod overage;
+mod admin;
+mod tax;
+mod plans;
+mod referrals;
+mod entitlements;
+mod billing_events;
+mod payments;
+mod lemonsqueezy;
+mod analytics;
+mod refunds;
+mod pause;
+mod scopes;
 
 use error::ServiceError;
 use models::*;
@@ -41,6 +81,10 @@ fn handle_request(req: Request) -> anyhow::Result<impl IntoResponse> {
     let path = req.path();
     let method = req.method();
 
+    if let Err(e) = authorworks_access::authorize(&req, scopes::route_scope) {
+        return Ok(ServiceError::from(e).into_response());
+    }
+
     let result = match (method, path) {
         // Health
         (Method::Get, "/health") => health_handler(),
@@ -55,17 +99,41 @@ fn handle_request(req: Request) -> anyhow::Result<impl IntoResponse> {
         (Method::Put, "/subscription") => update_subscription(&req),
         (Method::Delete, "/subscription") => cancel_subscription(&req),
 
+        // Pauses
+        (Method::Post, "/subscription/pause") => pause_subscription(&req),
+        (Method::Post, "/subscription/resume") => resume_subscription(&req),
+        (Method::Post, "/subscription/pause/process") => process_expired_pauses(&req),
+
+        // Dunning
+        (Method::Get, "/subscription/dunning") => get_user_dunning_status(&req),
+        (Method::Post, "/subscription/dunning/process") => process_dunning(&req),
+
+        // Overage
+        (Method::Put, "/subscription/overage") => set_overage(&req),
+        (Method::Post, "/subscription/overage/report") => report_overage(&req),
+
         // Checkout & Portal
         (Method::Post, "/checkout") => create_checkout_session(&req),
         (Method::Post, "/portal") => create_portal_session(&req),
 
         // Webhooks
         (Method::Post, "/webhooks/stripe") => handle_stripe_webhook(&req),
+        (Method::Post, "/webhooks/lemonsqueezy") => handle_lemonsqueezy_webhook(&req),
 
         // Billing
         (Method::Get, "/invoices") => list_invoices(&req),
+        (Method::Get, "/billing/details") => get_billing_details(&req),
+        (Method::Put, "/billing/details") => update_billing_details(&req),
         (Method::Get, "/usage") => get_usage(&req),
 
+        // Entitlements
+        (Method::Post, "/entitlements/check") => check_entitlement(&req),
+
+        // Referrals
+        (Method::Get, "/referrals/code") => get_referral_code(&req),
+        (Method::Post, "/referrals/redeem") => redeem_referral(&req),
+        (Method::Get, "/referrals/stats") => get_referral_stats(&req),
+
         // Credits
         (Method::Get, "/credits/packages") => get_credit_packages(&req),
         (Method::Get, "/credits/balance") => get_user_credit_balance(&req),
@@ -73,6 +141,21 @@ fn handle_request(req: Request) -> anyhow::Result<impl IntoResponse> {
         (Method::Post, "/credits/checkout") => create_credit_checkout(&req),
         (Method::Post, "/credits/consume") => consume_user_credits(&req),
         (Method::Post, "/credits/check") => check_user_credits(&req),
+        (Method::Post, "/credits/expiry/process") => process_credit_expiry(&req),
+
+        // Admin
+        (Method::Put, path) if path.starts_with("/admin/subscriptions/") => admin_override_subscription(&req, path),
+        (Method::Post, "/admin/credits/grant") => admin_grant_credits(&req),
+        (Method::Post, "/admin/credits/adjust") => admin_adjust_credits(&req),
+        (Method::Get, "/admin/audit") => admin_list_audit(&req),
+        (Method::Get, path) if path.starts_with("/admin/users/") && path.ends_with("/billing-timeline") => {
+            admin_billing_timeline(&req, path)
+        }
+        (Method::Get, "/admin/plans") => admin_list_plans(&req),
+        (Method::Post, "/admin/plans") => admin_create_plan(&req),
+        (Method::Put, path) if path.starts_with("/admin/plans/") => admin_update_plan(&req, path),
+        (Method::Delete, path) if path.starts_with("/admin/plans/") => admin_deactivate_plan(&req, path),
+        (Method::Get, "/admin/analytics/revenue") => admin_revenue_analytics(&req),
 
         // CORS
         (Method::Options, _) => cors_preflight(),
@@ -104,10 +187,11 @@ fn get_stripe_config() -> Result<StripeConfig, ServiceError> {
         webhook_secret: variables::get("stripe_webhook_secret")
             .map_err(|_| ServiceError::Internal("STRIPE_WEBHOOK_SECRET not configured".into()))?,
         price_id_free: variables::get("stripe_price_free").unwrap_or_else(|_| "price_free".into()),
-        price_id_pro: variables::get("stripe_price_pro")
-            .map_err(|_| ServiceError::Internal("STRIPE_PRICE_PRO not configured".into()))?,
-        price_id_enterprise: variables::get("stripe_price_enterprise")
-            .map_err(|_| ServiceError::Internal("STRIPE_PRICE_ENTERPRISE not configured".into()))?,
+        price_id_ai_overage: variables::get("stripe_price_ai_overage").ok(),
+        automatic_tax: variables::get("stripe_automatic_tax")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(true),
     })
 }
 
@@ -135,12 +219,18 @@ fn health_handler() -> Result<Response, ServiceError> {
         Err(_) => "not_configured",
     };
 
+    let payment_provider = match payments::configured() {
+        Ok(provider) => provider.name(),
+        Err(_) => "not_configured",
+    };
+
     json_response(200, serde_json::json!({
         "status": "healthy",
         "service": "subscription-service",
         "version": env!("CARGO_PKG_VERSION"),
         "database": db_status,
         "stripe": stripe_status,
+        "payment_provider": payment_provider,
         "timestamp": Utc::now().to_rfc3339()
     }))
 }
@@ -149,7 +239,7 @@ fn service_info() -> Result<Response, ServiceError> {
     json_response(200, serde_json::json!({
         "service": "AuthorWorks Subscription Service",
         "version": env!("CARGO_PKG_VERSION"),
-        "features": ["stripe-integration", "subscription-management", "usage-tracking"]
+        "features": ["stripe-integration", "subscription-management", "usage-tracking", "admin-overrides", "stripe-tax", "plan-management", "referrals", "entitlements", "billing-timeline", "lemonsqueezy", "revenue-analytics", "refunds-disputes", "subscription-pause"]
     }))
 }
 
@@ -169,73 +259,8 @@ fn cors_preflight() -> Result<Response, ServiceError> {
 //=============================================================================
 
 fn list_plans(_req: &Request) -> Result<Response, ServiceError> {
-    let plans = vec![
-        Plan {
-            id: "free".into(),
-            name: "Free".into(),
-            description: "Perfect for getting started".into(),
-            price_monthly: 0,
-            price_yearly: 0,
-            features: vec![
-                "1 book project".into(),
-                "5,000 AI words/month".into(),
-                "Basic editor".into(),
-                "Community support".into(),
-            ],
-            limits: PlanLimits {
-                max_books: 1,
-                max_chapters_per_book: 10,
-                ai_words_per_month: 5000,
-                storage_gb: 1,
-                collaborators: 0,
-            },
-        },
-        Plan {
-            id: "pro".into(),
-            name: "Professional".into(),
-            description: "For serious authors".into(),
-            price_monthly: 1999, // $19.99
-            price_yearly: 19990, // $199.90 (save ~17%)
-            features: vec![
-                "Unlimited book projects".into(),
-                "100,000 AI words/month".into(),
-                "Advanced editor with collaboration".into(),
-                "Priority support".into(),
-                "Export to all formats".into(),
-                "Version history".into(),
-            ],
-            limits: PlanLimits {
-                max_books: -1, // unlimited
-                max_chapters_per_book: -1,
-                ai_words_per_month: 100000,
-                storage_gb: 50,
-                collaborators: 5,
-            },
-        },
-        Plan {
-            id: "enterprise".into(),
-            name: "Enterprise".into(),
-            description: "For publishing teams".into(),
-            price_monthly: 9999, // $99.99
-            price_yearly: 99990, // $999.90
-            features: vec![
-                "Everything in Professional".into(),
-                "Unlimited AI words".into(),
-                "Unlimited collaborators".into(),
-                "Custom AI training".into(),
-                "API access".into(),
-                "Dedicated support".into(),
-                "SSO integration".into(),
-            ],
-            limits: PlanLimits {
-                max_books: -1,
-                max_chapters_per_book: -1,
-                ai_words_per_month: -1,
-                storage_gb: 500,
-                collaborators: -1,
-            },
-        },
-    ];
+    let conn = get_db_connection()?;
+    let plans = plans::active(&conn)?;
 
     json_response(200, serde_json::json!({
         "plans": plans
@@ -252,7 +277,7 @@ fn get_subscription(req: &Request) -> Result<Response, ServiceError> {
 
     let query = "SELECT s.id, s.plan_id, s.status, s.stripe_subscription_id, s.stripe_customer_id,
                  s.current_period_start, s.current_period_end, s.cancel_at_period_end,
-                 s.created_at, s.updated_at
+                 s.created_at, s.updated_at, s.billing_interval, s.paused_at::text, s.pause_resumes_at::text
                  FROM subscriptions.subscriptions s WHERE s.user_id = $1";
 
     let params = [ParameterValue::Str(user_id.to_string())];
@@ -275,18 +300,23 @@ fn get_subscription(req: &Request) -> Result<Response, ServiceError> {
     }
 
     let row = &rows.rows[0];
+    let status = String::decode(&row[2]).unwrap_or_default();
     let subscription = Subscription {
         id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
         user_id,
         plan_id: String::decode(&row[1]).unwrap_or_default(),
-        status: String::decode(&row[2]).unwrap_or_default(),
+        read_only: status == pause::STATUS,
+        status,
         stripe_subscription_id: String::decode(&row[3]).ok(),
         stripe_customer_id: String::decode(&row[4]).ok(),
         current_period_start: String::decode(&row[5]).ok(),
         current_period_end: String::decode(&row[6]).ok(),
         cancel_at_period_end: bool::decode(&row[7]).unwrap_or(false),
+        billing_interval: BillingInterval::parse(&String::decode(&row[10]).unwrap_or_default()),
         created_at: String::decode(&row[8]).unwrap_or_default(),
         updated_at: String::decode(&row[9]).unwrap_or_default(),
+        paused_at: String::decode(&row[11]).ok(),
+        pause_resumes_at: String::decode(&row[12]).ok(),
     };
 
     json_response(200, subscription)
@@ -296,7 +326,7 @@ fn create_subscription(req: &Request) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
     let body: CreateSubscriptionRequest = parse_json_body(req)?;
     let conn = get_db_connection()?;
-    let stripe_config = get_stripe_config()?;
+    let provider = payments::configured()?;
 
     // Check for existing subscription
     let check_query = "SELECT id FROM subscriptions.subscriptions WHERE user_id = $1";
@@ -308,7 +338,7 @@ fn create_subscription(req: &Request) -> Result<Response, ServiceError> {
         return Err(ServiceError::Conflict("Subscription already exists".into()));
     }
 
-    // Get user email for Stripe customer
+    // Get user email for the provider's customer
     let user_query = "SELECT email FROM users.users WHERE id = $1";
     let user_params = [ParameterValue::Str(user_id.to_string())];
     let user_rows = conn.query(user_query, &user_params)
@@ -320,18 +350,12 @@ fn create_subscription(req: &Request) -> Result<Response, ServiceError> {
 
     let email = String::decode(&user_rows.rows[0][0]).unwrap_or_default();
 
-    // Create Stripe customer
-    let customer_id = create_stripe_customer(&stripe_config, &email, &user_id)?;
-
     // Get price ID for plan
-    let price_id = match body.plan_id.as_str() {
-        "pro" => &stripe_config.price_id_pro,
-        "enterprise" => &stripe_config.price_id_enterprise,
-        _ => return Err(ServiceError::BadRequest("Invalid plan".into())),
-    };
+    let price_id = plans::provider_price_id(&conn, provider.name(), &body.plan_id, body.interval)?;
 
-    // Create Stripe subscription
-    let stripe_sub = create_stripe_subscription(&stripe_config, &customer_id, price_id)?;
+    // Create customer and subscription with the provider
+    let customer_id = provider.create_customer(&email, &user_id)?;
+    let stripe_sub = provider.create_subscription(&customer_id, &price_id)?;
 
     // Store in database
     let sub_id = Uuid::new_v4();
@@ -339,8 +363,8 @@ fn create_subscription(req: &Request) -> Result<Response, ServiceError> {
 
     let insert = "INSERT INTO subscriptions.subscriptions 
                   (id, user_id, plan_id, status, stripe_subscription_id, stripe_customer_id,
-                   current_period_start, current_period_end, created_at, updated_at)
-                  VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $9)";
+                   current_period_start, current_period_end, created_at, updated_at, payment_provider, billing_interval)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $9, $10, $11)";
 
     let params = [
         ParameterValue::Str(sub_id.to_string()),
@@ -352,14 +376,25 @@ fn create_subscription(req: &Request) -> Result<Response, ServiceError> {
         ParameterValue::Str(stripe_sub.current_period_start.clone()),
         ParameterValue::Str(stripe_sub.current_period_end.clone()),
         ParameterValue::Str(now.to_rfc3339()),
+        ParameterValue::Str(provider.name().to_string()),
+        ParameterValue::Str(body.interval.as_str().to_string()),
     ];
 
     conn.execute(insert, &params)
         .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
 
+    billing_events::record(&conn, &user_id, "subscription.created", billing_events::Source::User, Some(&user_id), serde_json::json!({
+        "plan_id": body.plan_id,
+        "billing_interval": body.interval,
+        "status": stripe_sub.status,
+        "payment_provider": provider.name(),
+        "stripe_subscription_id": stripe_sub.id
+    }))?;
+
     json_response(201, serde_json::json!({
         "id": sub_id,
         "plan_id": body.plan_id,
+        "billing_interval": body.interval,
         "status": stripe_sub.status,
         "current_period_end": stripe_sub.current_period_end,
         "stripe_subscription_id": stripe_sub.id
@@ -370,10 +405,10 @@ fn update_subscription(req: &Request) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
     let body: UpdateSubscriptionRequest = parse_json_body(req)?;
     let conn = get_db_connection()?;
-    let stripe_config = get_stripe_config()?;
 
     // Get current subscription
-    let query = "SELECT stripe_subscription_id FROM subscriptions.subscriptions WHERE user_id = $1";
+    let query = "SELECT stripe_subscription_id, plan_id, payment_provider, billing_interval
+                 FROM subscriptions.subscriptions WHERE user_id = $1";
     let params = [ParameterValue::Str(user_id.to_string())];
     let rows = conn.query(query, &params)
         .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
@@ -384,31 +419,59 @@ fn update_subscription(req: &Request) -> Result<Response, ServiceError> {
 
     let stripe_sub_id = String::decode(&rows.rows[0][0])
         .map_err(|_| ServiceError::Internal("Invalid subscription data".into()))?;
+    let previous_plan_id = String::decode(&rows.rows[0][1]).unwrap_or_default();
+    let provider = payments::by_name(&String::decode(&rows.rows[0][2]).unwrap_or_default())?;
+    let previous_interval = BillingInterval::parse(&String::decode(&rows.rows[0][3]).unwrap_or_default());
+
+    let plan_id = body.plan_id.unwrap_or_else(|| previous_plan_id.clone());
+    let interval = body.interval.unwrap_or(previous_interval);
+    let interval_changed = interval != previous_interval;
+    if plan_id == previous_plan_id && !interval_changed {
+        return Err(ServiceError::BadRequest(format!(
+            "Subscription is already on {} billed {}ly", plan_id, interval.as_str()
+        )));
+    }
 
     // Get new price ID
-    let price_id = match body.plan_id.as_str() {
-        "pro" => &stripe_config.price_id_pro,
-        "enterprise" => &stripe_config.price_id_enterprise,
-        _ => return Err(ServiceError::BadRequest("Invalid plan".into())),
-    };
+    let price_id = plans::provider_price_id(&conn, provider.name(), &plan_id, interval)?;
 
-    // Update Stripe subscription
-    update_stripe_subscription(&stripe_config, &stripe_sub_id, price_id)?;
+    // Update the provider's subscription. A new interval starts a new billing
+    // cycle, so the unused part of the old one is settled right away rather
+    // than on the next invoice a month or a year out.
+    provider.change_plan(&stripe_sub_id, &price_id, interval_changed)?;
 
     // Update database
     let now = Utc::now();
-    let update = "UPDATE subscriptions.subscriptions SET plan_id = $2, updated_at = $3 WHERE user_id = $1";
+    let update = "UPDATE subscriptions.subscriptions SET plan_id = $2, billing_interval = $3, updated_at = $4
+                  WHERE user_id = $1";
     let update_params = [
         ParameterValue::Str(user_id.to_string()),
-        ParameterValue::Str(body.plan_id.clone()),
+        ParameterValue::Str(plan_id.clone()),
+        ParameterValue::Str(interval.as_str().to_string()),
         ParameterValue::Str(now.to_rfc3339()),
     ];
 
     conn.execute(update, &update_params)
         .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
 
+    if plan_id != previous_plan_id {
+        billing_events::record(&conn, &user_id, "subscription.plan_changed", billing_events::Source::User, Some(&user_id), serde_json::json!({
+            "from_plan_id": previous_plan_id,
+            "to_plan_id": plan_id
+        }))?;
+    }
+    if interval_changed {
+        billing_events::record(&conn, &user_id, "subscription.interval_changed", billing_events::Source::User, Some(&user_id), serde_json::json!({
+            "plan_id": plan_id,
+            "from_interval": previous_interval,
+            "to_interval": interval
+        }))?;
+    }
+
     json_response(200, serde_json::json!({
-        "plan_id": body.plan_id,
+        "plan_id": plan_id,
+        "billing_interval": interval,
+        "prorated_immediately": interval_changed,
         "updated_at": now.to_rfc3339()
     }))
 }
@@ -416,10 +479,9 @@ fn update_subscription(req: &Request) -> Result<Response, ServiceError> {
 fn cancel_subscription(req: &Request) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
     let conn = get_db_connection()?;
-    let stripe_config = get_stripe_config()?;
 
     // Get subscription
-    let query = "SELECT stripe_subscription_id FROM subscriptions.subscriptions WHERE user_id = $1";
+    let query = "SELECT stripe_subscription_id, payment_provider FROM subscriptions.subscriptions WHERE user_id = $1";
     let params = [ParameterValue::Str(user_id.to_string())];
     let rows = conn.query(query, &params)
         .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
@@ -431,8 +493,10 @@ fn cancel_subscription(req: &Request) -> Result<Response, ServiceError> {
     let stripe_sub_id = String::decode(&rows.rows[0][0])
         .map_err(|_| ServiceError::Internal("Invalid subscription data".into()))?;
 
-    // Cancel at period end in Stripe
-    cancel_stripe_subscription(&stripe_config, &stripe_sub_id)?;
+    let provider = payments::by_name(&String::decode(&rows.rows[0][1]).unwrap_or_default())?;
+
+    // Cancel at period end with the provider
+    provider.cancel_at_period_end(&stripe_sub_id)?;
 
     // Update database
     let now = Utc::now();
@@ -446,12 +510,137 @@ fn cancel_subscription(req: &Request) -> Result<Response, ServiceError> {
     conn.execute(update, &update_params)
         .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
 
+    billing_events::record(&conn, &user_id, "subscription.cancel_scheduled", billing_events::Source::User, Some(&user_id), serde_json::json!({
+        "stripe_subscription_id": stripe_sub_id
+    }))?;
+
     json_response(200, serde_json::json!({
         "message": "Subscription will be cancelled at end of billing period",
         "cancel_at_period_end": true
     }))
 }
 
+//=============================================================================
+// Pauses
+//=============================================================================
+
+fn pause_subscription(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: pause::PauseRequest = if req.body().is_empty() {
+        pause::PauseRequest::default()
+    } else {
+        parse_json_body(req)?
+    };
+    let conn = get_db_connection()?;
+    pause::pause(&conn, &user_id, body)
+}
+
+fn resume_subscription(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+    pause::resume(&conn, &user_id)
+}
+
+fn process_expired_pauses(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let conn = get_db_connection()?;
+    pause::process_expired(&conn)
+}
+
+//=============================================================================
+// Dunning
+//=============================================================================
+
+fn get_user_dunning_status(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+    let config = dunning::get_dunning_config();
+    dunning::get_dunning_status(&conn, &config, user_id)
+}
+
+fn process_dunning(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let conn = get_db_connection()?;
+    let config = dunning::get_dunning_config();
+
+    let downgraded = dunning::process_due_reminders(&conn, &config)?;
+    for stripe_sub_id in &downgraded {
+        payments::for_subscription(&conn, stripe_sub_id)?.cancel_now(stripe_sub_id)?;
+    }
+
+    json_response(200, serde_json::json!({
+        "processed": true,
+        "downgraded": downgraded.len()
+    }))
+}
+
+//=============================================================================
+// Overage
+//=============================================================================
+
+fn set_overage(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: overage::OverageToggleRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    let settings = overage::get_settings(&conn, &user_id)?
+        .ok_or_else(|| ServiceError::NotFound("Subscription not found".into()))?;
+
+    if body.enabled && settings.plan_id != overage::OVERAGE_PLAN {
+        return Err(ServiceError::BadRequest("Overage billing is only available on the Pro plan".into()));
+    }
+
+    // The metered item is created once and kept; with overage off it simply
+    // receives no usage and bills nothing
+    let mut new_item_id = None;
+    if body.enabled && settings.stripe_item_id.is_none() {
+        let stripe_config = get_stripe_config()?;
+        let price_id = stripe_config.price_id_ai_overage.clone()
+            .ok_or_else(|| ServiceError::Internal("STRIPE_PRICE_AI_OVERAGE not configured".into()))?;
+        let stripe_sub_id = settings.stripe_subscription_id.clone()
+            .ok_or_else(|| ServiceError::BadRequest("Subscription has no Stripe billing".into()))?;
+        new_item_id = Some(create_stripe_metered_item(&stripe_config, &stripe_sub_id, &price_id)?);
+    }
+
+    overage::set_enabled(&conn, &user_id, body.enabled, new_item_id.as_deref())?;
+    billing_events::record(&conn, &user_id, "subscription.overage_changed", billing_events::Source::User, Some(&user_id), serde_json::json!({
+        "enabled": body.enabled,
+        "stripe_item_id": new_item_id
+    }))?;
+
+    let config = overage::get_overage_config();
+    json_response(200, serde_json::json!({
+        "enabled": body.enabled,
+        "price_per_1k_words_cents": config.price_per_1k_words_cents
+    }))
+}
+
+fn report_overage(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let conn = get_db_connection()?;
+    let stripe_config = get_stripe_config()?;
+
+    let pending = overage::pending_reports(&conn, plans::limits(&conn, overage::OVERAGE_PLAN)?.ai_words_per_month)?;
+    let mut reported = 0;
+    let mut words = 0;
+    for report in &pending {
+        if !overage::claim_report(&conn, report)? {
+            continue;
+        }
+        if let Err(e) = report_stripe_usage(&stripe_config, &report.stripe_item_id, report.words, &report.idempotency_key()) {
+            overage::release_report(&conn, report)?;
+            return Err(e);
+        }
+        reported += 1;
+        words += report.words;
+    }
+
+    json_response(200, serde_json::json!({
+        "reported": reported,
+        "words": words
+    }))
+}
+
 //=============================================================================
 // Checkout & Portal
 //=============================================================================
@@ -459,24 +648,20 @@ fn cancel_subscription(req: &Request) -> Result<Response, ServiceError> {
 fn create_checkout_session(req: &Request) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
     let body: CheckoutRequest = parse_json_body(req)?;
-    let stripe_config = get_stripe_config()?;
+    let provider = payments::configured()?;
     let conn = get_db_connection()?;
 
-    // Get or create Stripe customer
-    let customer_id = get_or_create_stripe_customer(&conn, &stripe_config, &user_id)?;
+    // Get or create the provider's customer
+    let customer_id = get_or_create_customer(&conn, provider.as_ref(), &user_id)?;
 
     // Get price ID
-    let price_id = match body.plan_id.as_str() {
-        "pro" => &stripe_config.price_id_pro,
-        "enterprise" => &stripe_config.price_id_enterprise,
-        _ => return Err(ServiceError::BadRequest("Invalid plan".into())),
-    };
+    let price_id = plans::provider_price_id(&conn, provider.name(), &body.plan_id, body.interval)?;
 
     // Create checkout session
-    let session = create_stripe_checkout_session(
-        &stripe_config,
+    let session = provider.create_checkout(
         &customer_id,
-        price_id,
+        &user_id,
+        &price_id,
         &body.success_url,
         &body.cancel_url,
     )?;
@@ -490,11 +675,10 @@ fn create_checkout_session(req: &Request) -> Result<Response, ServiceError> {
 fn create_portal_session(req: &Request) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
     let body: PortalRequest = parse_json_body(req)?;
-    let stripe_config = get_stripe_config()?;
     let conn = get_db_connection()?;
 
-    // Get Stripe customer ID
-    let query = "SELECT stripe_customer_id FROM subscriptions.subscriptions WHERE user_id = $1";
+    // Get the provider's customer ID
+    let query = "SELECT stripe_customer_id, payment_provider FROM subscriptions.subscriptions WHERE user_id = $1";
     let params = [ParameterValue::Str(user_id.to_string())];
     let rows = conn.query(query, &params)
         .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
@@ -506,8 +690,10 @@ fn create_portal_session(req: &Request) -> Result<Response, ServiceError> {
     let customer_id = String::decode(&rows.rows[0][0])
         .map_err(|_| ServiceError::Internal("Invalid customer data".into()))?;
 
+    let provider = payments::by_name(&String::decode(&rows.rows[0][1]).unwrap_or_default())?;
+
     // Create portal session
-    let session = create_stripe_portal_session(&stripe_config, &customer_id, &body.return_url)?;
+    let session = provider.create_portal(&customer_id, &body.return_url)?;
 
     json_response(200, serde_json::json!({
         "url": session.url
@@ -519,122 +705,222 @@ fn create_portal_session(req: &Request) -> Result<Response, ServiceError> {
 //=============================================================================
 
 fn handle_stripe_webhook(req: &Request) -> Result<Response, ServiceError> {
-    let stripe_config = get_stripe_config()?;
-    
-    // Verify webhook signature
-    let signature = req.header("Stripe-Signature")
-        .and_then(|h| h.as_str())
-        .ok_or_else(|| ServiceError::BadRequest("Missing signature".into()))?;
-
-    verify_stripe_signature(req.body(), signature, &stripe_config.webhook_secret)?;
+    let provider = payments::by_name(stripe::PROVIDER)?;
+    handle_webhook(req, provider.as_ref())
+}
 
-    // Parse event
-    let event: StripeEvent = serde_json::from_slice(req.body())
-        .map_err(|e| ServiceError::BadRequest(format!("Invalid event: {}", e)))?;
+fn handle_lemonsqueezy_webhook(req: &Request) -> Result<Response, ServiceError> {
+    let provider = payments::by_name(lemonsqueezy::PROVIDER)?;
+    handle_webhook(req, provider.as_ref())
+}
 
+/// Apply a provider's normalized webhook event once. Providers retry
+/// deliveries, so the event id is claimed before anything changes: applying a
+/// payment failure twice would move the account up the dunning ladder. An
+/// event that fails to apply gives its claim back for the retry.
+fn handle_webhook(req: &Request, provider: &dyn payments::PaymentProvider) -> Result<Response, ServiceError> {
+    let event = provider.parse_webhook(req)?;
     let conn = get_db_connection()?;
 
-    match event.event_type.as_str() {
-        "customer.subscription.created" | "customer.subscription.updated" => {
-            let sub_data = event.data.object;
-            let stripe_sub_id = sub_data.get("id").and_then(|v| v.as_str()).unwrap_or_default();
-            let status = sub_data.get("status").and_then(|v| v.as_str()).unwrap_or("active");
-            let period_start = sub_data.get("current_period_start").and_then(|v| v.as_i64());
-            let period_end = sub_data.get("current_period_end").and_then(|v| v.as_i64());
-            let cancel_at_end = sub_data.get("cancel_at_period_end").and_then(|v| v.as_bool()).unwrap_or(false);
-
-            let now = Utc::now();
-            let update = "UPDATE subscriptions.subscriptions 
-                          SET status = $2, current_period_start = $3, current_period_end = $4,
-                              cancel_at_period_end = $5, updated_at = $6
-                          WHERE stripe_subscription_id = $1";
-
-            let period_start_str = period_start
-                .map(|ts| chrono::DateTime::from_timestamp(ts, 0).map(|dt| dt.to_rfc3339()))
-                .flatten()
-                .unwrap_or_default();
-            let period_end_str = period_end
-                .map(|ts| chrono::DateTime::from_timestamp(ts, 0).map(|dt| dt.to_rfc3339()))
-                .flatten()
-                .unwrap_or_default();
+    if !billing_events::claim_webhook(&conn, provider.name(), &event)? {
+        return json_response(200, serde_json::json!({"received": true, "duplicate": true}));
+    }
+    if let Err(e) = apply_webhook_event(&conn, provider, &event) {
+        billing_events::release_webhook(&conn, &event.id)?;
+        return Err(e);
+    }
+    billing_events::attribute_webhook(&conn, &event)?;
 
-            let params = [
-                ParameterValue::Str(stripe_sub_id.to_string()),
-                ParameterValue::Str(status.to_string()),
-                ParameterValue::Str(period_start_str),
-                ParameterValue::Str(period_end_str),
-                ParameterValue::Boolean(cancel_at_end),
-                ParameterValue::Str(now.to_rfc3339()),
-            ];
+    json_response(200, serde_json::json!({"received": true}))
+}
 
-            conn.execute(update, &params)
-                .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+/// Every provider's deliveries change subscription state the same way
+fn apply_webhook_event(
+    conn: &Connection,
+    provider: &dyn payments::PaymentProvider,
+    event: &payments::WebhookEvent,
+) -> Result<(), ServiceError> {
+    match &event.kind {
+        payments::PaymentEvent::SubscriptionChanged(change) => {
+            apply_subscription_change(conn, provider, change)?;
         }
-        "customer.subscription.deleted" => {
-            let sub_data = event.data.object;
-            let stripe_sub_id = sub_data.get("id").and_then(|v| v.as_str()).unwrap_or_default();
-
+        payments::PaymentEvent::SubscriptionEnded { subscription_id } => {
             let now = Utc::now();
             let update = "UPDATE subscriptions.subscriptions 
                           SET status = 'cancelled', plan_id = 'free', updated_at = $2
                           WHERE stripe_subscription_id = $1";
 
             let params = [
-                ParameterValue::Str(stripe_sub_id.to_string()),
+                ParameterValue::Str(subscription_id.clone()),
                 ParameterValue::Str(now.to_rfc3339()),
             ];
 
             conn.execute(update, &params)
                 .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
         }
-        "invoice.paid" => {
+        payments::PaymentEvent::PaymentSucceeded(receipt) => {
             // Record successful payment
-            let invoice_data = event.data.object;
-            let customer_id = invoice_data.get("customer").and_then(|v| v.as_str()).unwrap_or_default();
-            let amount = invoice_data.get("amount_paid").and_then(|v| v.as_i64()).unwrap_or(0);
-            let invoice_id = invoice_data.get("id").and_then(|v| v.as_str()).unwrap_or_default();
-
             let now = Utc::now();
             let id = Uuid::new_v4();
             let insert = "INSERT INTO subscriptions.invoices 
-                          (id, stripe_customer_id, stripe_invoice_id, amount, status, created_at)
-                          VALUES ($1, $2, $3, $4, 'paid', $5)";
+                          (id, stripe_customer_id, stripe_invoice_id, amount, status, created_at,
+                           subtotal, tax, total, currency, tax_breakdown, customer_tax_ids, payment_provider,
+                           stripe_payment_intent_id)
+                          VALUES ($1, $2, $3, $4, 'paid', $5, $6, $7, $8, $9, $10::jsonb, $11::jsonb, $12, $13)";
 
+            let optional_amount = |v: Option<i64>| v.map(ParameterValue::Int64).unwrap_or(ParameterValue::DbNull);
             let params = [
                 ParameterValue::Str(id.to_string()),
-                ParameterValue::Str(customer_id.to_string()),
-                ParameterValue::Str(invoice_id.to_string()),
-                ParameterValue::Int64(amount),
+                ParameterValue::Str(receipt.customer_id.clone()),
+                ParameterValue::Str(receipt.invoice_id.clone()),
+                ParameterValue::Int64(receipt.amount),
                 ParameterValue::Str(now.to_rfc3339()),
+                optional_amount(receipt.tax.subtotal),
+                optional_amount(receipt.tax.tax),
+                optional_amount(receipt.tax.total),
+                receipt.tax.currency.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+                ParameterValue::Str(receipt.tax.breakdown.to_string()),
+                ParameterValue::Str(receipt.tax.customer_tax_ids.to_string()),
+                ParameterValue::Str(provider.name().to_string()),
+                receipt.payment_intent_id.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
             ];
 
             conn.execute(insert, &params)
                 .map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+            dunning::resolve_for_customer(conn, &receipt.customer_id)?;
+            // Referral fraud checks compare Stripe card fingerprints
+            if receipt.amount > 0 && provider.name() == stripe::PROVIDER {
+                reward_referral(conn, &get_stripe_config()?, &receipt.customer_id, &receipt.invoice_id, &event.object)?;
+            }
         }
-        "invoice.payment_failed" => {
+        payments::PaymentEvent::PaymentFailed { subscription_id, invoice_id } => {
             // Handle failed payment
-            let invoice_data = event.data.object;
-            let stripe_sub_id = invoice_data.get("subscription").and_then(|v| v.as_str()).unwrap_or_default();
-
             let now = Utc::now();
             let update = "UPDATE subscriptions.subscriptions 
                           SET status = 'past_due', updated_at = $2
                           WHERE stripe_subscription_id = $1";
 
             let params = [
-                ParameterValue::Str(stripe_sub_id.to_string()),
+                ParameterValue::Str(subscription_id.clone()),
                 ParameterValue::Str(now.to_rfc3339()),
             ];
 
             conn.execute(update, &params)
                 .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+            let config = dunning::get_dunning_config();
+            if let dunning::DunningOutcome::Downgraded { stripe_subscription_id } =
+                dunning::record_payment_failure(conn, &config, subscription_id, invoice_id)?
+            {
+                provider.cancel_now(&stripe_subscription_id)?;
+            }
+        }
+        payments::PaymentEvent::TaxIdVerified { tax_id, status } => {
+            tax::update_verification(conn, tax_id, status)?;
+        }
+        payments::PaymentEvent::PaymentRefunded(refund) => {
+            refunds::apply_refund(conn, refund)?;
         }
-        _ => {
-            // Log unhandled event type
+        payments::PaymentEvent::PaymentDisputed(dispute) => {
+            refunds::apply_dispute(conn, dispute)?;
+        }
+        payments::PaymentEvent::OneTimePaymentFailed { payment_intent_id, order_id, reason } => {
+            refunds::fail_credit_order(conn, payment_intent_id, order_id.as_deref(), reason.as_deref())?;
+        }
+        payments::PaymentEvent::Other => {
+            // Unhandled types are still recorded below for the billing timeline
         }
     }
 
-    json_response(200, serde_json::json!({"received": true}))
+    Ok(())
+}
+
+/// Sync a subscription from a provider event. A subscription that started at
+/// a hosted checkout has no row yet; it is created for the user the provider
+/// echoed back, replacing whatever subscription row they had.
+fn apply_subscription_change(
+    conn: &Connection,
+    provider: &dyn payments::PaymentProvider,
+    change: &payments::SubscriptionChange,
+) -> Result<(), ServiceError> {
+    let (plan_id, interval) = match &change.price_id {
+        Some(price_id) => plans::plan_for_price(conn, provider.name(), price_id)?.unzip(),
+        None => (None, None),
+    };
+    let optional = |v: &Option<String>| v.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull);
+    let now = Utc::now();
+
+    // A pause's end date is kept after the provider resumes billing, so the
+    // expiry sweep can still tell the author
+    let update = "UPDATE subscriptions.subscriptions 
+                  SET status = $2, current_period_start = COALESCE($3, current_period_start),
+                      current_period_end = COALESCE($4, current_period_end),
+                      cancel_at_period_end = $5, updated_at = $6, plan_id = COALESCE($7, plan_id),
+                      billing_interval = COALESCE($8, billing_interval),
+                      paused_at = CASE WHEN $2 = $9 THEN COALESCE(paused_at, $6::timestamptz) END,
+                      pause_resumes_at = CASE WHEN $2 = $9 THEN COALESCE($10::timestamptz, pause_resumes_at)
+                                              ELSE pause_resumes_at END
+                  WHERE stripe_subscription_id = $1";
+
+    let params = [
+        ParameterValue::Str(change.subscription_id.clone()),
+        ParameterValue::Str(change.status.clone()),
+        optional(&change.current_period_start),
+        optional(&change.current_period_end),
+        ParameterValue::Boolean(change.cancel_at_period_end),
+        ParameterValue::Str(now.to_rfc3339()),
+        optional(&plan_id),
+        interval.map(|i| ParameterValue::Str(i.as_str().to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(pause::STATUS.to_string()),
+        optional(&change.pause_resumes_at),
+    ];
+
+    let updated = conn.execute(update, &params)
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    let (user_id, plan_id) = match (updated, change.user_id, plan_id) {
+        (0, Some(user_id), Some(plan_id)) => (user_id, plan_id),
+        _ => return Ok(()),
+    };
+
+    let upsert = "INSERT INTO subscriptions.subscriptions
+                  (id, user_id, plan_id, status, stripe_subscription_id, stripe_customer_id, payment_provider,
+                   current_period_start, current_period_end, cancel_at_period_end, created_at, updated_at, billing_interval)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $11, $12)
+                  ON CONFLICT (user_id) DO UPDATE SET
+                      plan_id = EXCLUDED.plan_id, status = EXCLUDED.status,
+                      stripe_subscription_id = EXCLUDED.stripe_subscription_id,
+                      stripe_customer_id = EXCLUDED.stripe_customer_id,
+                      payment_provider = EXCLUDED.payment_provider,
+                      current_period_start = EXCLUDED.current_period_start,
+                      current_period_end = EXCLUDED.current_period_end,
+                      cancel_at_period_end = EXCLUDED.cancel_at_period_end,
+                      updated_at = EXCLUDED.updated_at,
+                      billing_interval = EXCLUDED.billing_interval";
+
+    conn.execute(upsert, &[
+        ParameterValue::Str(Uuid::new_v4().to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(plan_id.clone()),
+        ParameterValue::Str(change.status.clone()),
+        ParameterValue::Str(change.subscription_id.clone()),
+        optional(&change.customer_id),
+        ParameterValue::Str(provider.name().to_string()),
+        optional(&change.current_period_start),
+        optional(&change.current_period_end),
+        ParameterValue::Boolean(change.cancel_at_period_end),
+        ParameterValue::Str(now.to_rfc3339()),
+        ParameterValue::Str(interval.unwrap_or_default().as_str().to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Upsert failed: {}", e)))?;
+
+    billing_events::record(conn, &user_id, "subscription.created", billing_events::Source::System, None, serde_json::json!({
+        "plan_id": plan_id,
+        "billing_interval": interval.unwrap_or_default(),
+        "status": change.status,
+        "payment_provider": provider.name(),
+        "stripe_subscription_id": change.subscription_id
+    }))
 }
 
 //=============================================================================
@@ -645,7 +931,9 @@ fn list_invoices(req: &Request) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
     let conn = get_db_connection()?;
 
-    let query = "SELECT i.id, i.stripe_invoice_id, i.amount, i.status, i.created_at
+    let query = "SELECT i.id, i.stripe_invoice_id, i.amount, i.status, i.created_at,
+                        i.subtotal, i.tax, i.total, i.currency, i.tax_breakdown::text, i.customer_tax_ids::text,
+                        i.amount_refunded, i.refunded_at::text
                  FROM subscriptions.invoices i
                  JOIN subscriptions.subscriptions s ON i.stripe_customer_id = s.stripe_customer_id
                  WHERE s.user_id = $1
@@ -661,7 +949,19 @@ fn list_invoices(req: &Request) -> Result<Response, ServiceError> {
             "stripe_invoice_id": String::decode(&row[1]).unwrap_or_default(),
             "amount": i64::decode(&row[2]).unwrap_or(0),
             "status": String::decode(&row[3]).unwrap_or_default(),
-            "created_at": String::decode(&row[4]).unwrap_or_default()
+            "created_at": String::decode(&row[4]).unwrap_or_default(),
+            "subtotal": i64::decode(&row[5]).ok(),
+            "tax": i64::decode(&row[6]).ok(),
+            "total": i64::decode(&row[7]).ok(),
+            "currency": String::decode(&row[8]).ok(),
+            "tax_breakdown": String::decode(&row[9]).ok()
+                .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+                .unwrap_or_else(|| serde_json::json!([])),
+            "customer_tax_ids": String::decode(&row[10]).ok()
+                .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+                .unwrap_or_else(|| serde_json::json!([])),
+            "amount_refunded": i64::decode(&row[11]).unwrap_or(0),
+            "refunded_at": String::decode(&row[12]).ok()
         })
     }).collect();
 
@@ -670,28 +970,72 @@ fn list_invoices(req: &Request) -> Result<Response, ServiceError> {
     }))
 }
 
+//=============================================================================
+// Billing Details & Tax
+//=============================================================================
+
+fn get_billing_details(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+
+    let details = tax::get_details(&conn, &user_id)?
+        .ok_or_else(|| ServiceError::NotFound("No billing details on file".into()))?;
+
+    json_response(200, tax::details_json(&details))
+}
+
+fn update_billing_details(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body = tax::validate(parse_json_body(req)?)?;
+    let stripe_config = get_stripe_config()?;
+    let conn = get_db_connection()?;
+
+    let customer_id = get_or_create_stripe_customer(&conn, &stripe_config, &user_id)?;
+    update_stripe_customer(&stripe_config, &customer_id, &tax::customer_form(&body))?;
+
+    // Stripe tax IDs are immutable, so a changed ID replaces the old one
+    let existing = tax::get_details(&conn, &user_id)?;
+    let unchanged = existing.as_ref()
+        .filter(|d| d.stripe_customer_id == customer_id && d.tax_id == body.tax_id)
+        .and_then(|d| d.stripe_tax_id.clone().map(|id| (id, d.tax_id_verification.clone().unwrap_or_default())));
+
+    let stripe_tax_id = match (unchanged, &body.tax_id) {
+        (Some(current), _) => Some(current),
+        (None, new_tax_id) => {
+            if let Some(old_id) = existing.as_ref()
+                .filter(|d| d.stripe_customer_id == customer_id)
+                .and_then(|d| d.stripe_tax_id.as_deref())
+            {
+                // Already gone on Stripe's side is fine
+                let _ = delete_stripe_tax_id(&stripe_config, &customer_id, old_id);
+            }
+            match new_tax_id {
+                Some(tax_id) => Some(create_stripe_tax_id(&stripe_config, &customer_id, tax_id)?),
+                None => None,
+            }
+        }
+    };
+
+    let details = tax::save_details(
+        &conn,
+        &user_id,
+        &customer_id,
+        &body,
+        stripe_tax_id.as_ref().map(|(id, status)| (id.as_str(), status.as_str())),
+    )?;
+
+    json_response(200, tax::details_json(&details))
+}
+
 fn get_usage(req: &Request) -> Result<Response, ServiceError> {
     let user_id = get_user_id(req)?;
     let conn = get_db_connection()?;
 
     // Get current billing period
-    let period_start = Utc::now().with_day(1).unwrap_or(Utc::now());
+    let period = overage::current_period(&conn, &user_id)?;
 
     // Get AI word usage
-    let ai_query = "SELECT COALESCE(SUM(word_count), 0) FROM subscriptions.ai_usage 
-                    WHERE user_id = $1 AND created_at >= $2";
-    let ai_params = [
-        ParameterValue::Str(user_id.to_string()),
-        ParameterValue::Str(period_start.to_rfc3339()),
-    ];
-    let ai_rows = conn.query(ai_query, &ai_params)
-        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
-
-    let ai_words_used = if !ai_rows.rows.is_empty() {
-        i64::decode(&ai_rows.rows[0][0]).unwrap_or(0)
-    } else {
-        0
-    };
+    let ai_words_used = overage::words_used(&conn, &user_id, period.start)?;
 
     // Get storage usage
     let storage_query = "SELECT COALESCE(SUM(size), 0) FROM storage.files WHERE user_id = $1";
@@ -718,43 +1062,23 @@ fn get_usage(req: &Request) -> Result<Response, ServiceError> {
     };
 
     // Get subscription limits
-    let sub_query = "SELECT plan_id FROM subscriptions.subscriptions WHERE user_id = $1";
-    let sub_params = [ParameterValue::Str(user_id.to_string())];
-    let sub_rows = conn.query(sub_query, &sub_params)
-        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
-
-    let plan_id = if !sub_rows.rows.is_empty() {
-        String::decode(&sub_rows.rows[0][0]).unwrap_or_else(|_| "free".into())
-    } else {
-        "free".into()
-    };
-
-    let limits = match plan_id.as_str() {
-        "pro" => PlanLimits {
-            max_books: -1,
-            max_chapters_per_book: -1,
-            ai_words_per_month: 100000,
-            storage_gb: 50,
-            collaborators: 5,
-        },
-        "enterprise" => PlanLimits {
-            max_books: -1,
-            max_chapters_per_book: -1,
-            ai_words_per_month: -1,
-            storage_gb: 500,
-            collaborators: -1,
-        },
-        _ => PlanLimits {
-            max_books: 1,
-            max_chapters_per_book: 10,
-            ai_words_per_month: 5000,
-            storage_gb: 1,
-            collaborators: 0,
-        },
-    };
+    let settings = overage::get_settings(&conn, &user_id)?;
+    let plan_id = settings.as_ref().map(|s| s.plan_id.clone()).unwrap_or_else(|| plans::FREE_PLAN.into());
+    let overage_enabled = settings.map(|s| s.enabled).unwrap_or(false);
+
+    let limits = plans::limits(&conn, &plan_id)?;
+    let overage = overage::summarize(
+        &overage::get_overage_config(),
+        period,
+        &plan_id,
+        overage_enabled,
+        limits.ai_words_per_month,
+        ai_words_used,
+    );
 
     json_response(200, serde_json::json!({
-        "period_start": period_start.to_rfc3339(),
+        "period_start": period.start.to_rfc3339(),
+        "period_end": period.end.to_rfc3339(),
         "usage": {
             "ai_words": ai_words_used,
             "storage_bytes": storage_bytes,
@@ -762,10 +1086,64 @@ fn get_usage(req: &Request) -> Result<Response, ServiceError> {
             "books": book_count
         },
         "limits": limits,
+        "overage": overage,
         "plan_id": plan_id
     }))
 }
 
+//=============================================================================
+// Referrals
+//=============================================================================
+
+fn get_referral_code(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+    referrals::get_code(&conn, &user_id)
+}
+
+fn redeem_referral(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: referrals::RedeemReferralRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+    referrals::redeem(&conn, &user_id, body)
+}
+
+fn get_referral_stats(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+    referrals::stats(&conn, &user_id)
+}
+
+/// Reward a referral when the referred user's first paid invoice clears. If
+/// Stripe cannot tell us which card paid, the referral waits for the next
+/// paid invoice rather than failing the webhook.
+fn reward_referral(
+    conn: &Connection,
+    config: &StripeConfig,
+    customer_id: &str,
+    invoice_id: &str,
+    invoice: &serde_json::Value,
+) -> Result<(), ServiceError> {
+    let pending = match referrals::pending_conversion(conn, customer_id)? {
+        Some(pending) => pending,
+        None => return Ok(()),
+    };
+
+    let card_fingerprint = match invoice_card_fingerprint(config, invoice) {
+        Ok(fingerprint) => fingerprint,
+        Err(_) => return Ok(()),
+    };
+    let referrer_fingerprints = match pending.referrer_customer_id.as_deref() {
+        Some(referrer_customer) => match list_stripe_card_fingerprints(config, referrer_customer) {
+            Ok(fingerprints) => fingerprints,
+            Err(_) => return Ok(()),
+        },
+        None => Vec::new(),
+    };
+
+    referrals::complete_conversion(conn, &pending, invoice_id, card_fingerprint.as_deref(), &referrer_fingerprints)
+}
+
 //=============================================================================
 // Stripe API Helpers
 //=============================================================================
@@ -785,16 +1163,19 @@ fn create_stripe_customer(config: &StripeConfig, email: &str, user_id: &Uuid) ->
         .ok_or_else(|| ServiceError::Internal("Failed to create customer".into()))
 }
 
-fn create_stripe_subscription(config: &StripeConfig, customer_id: &str, price_id: &str) -> Result<StripeSubscription, ServiceError> {
-    let body = format!(
+fn create_stripe_subscription(config: &StripeConfig, customer_id: &str, price_id: &str) -> Result<ProviderSubscription, ServiceError> {
+    let mut body = format!(
         "customer={}&items[0][price]={}",
         customer_id,
         price_id
     );
+    if config.automatic_tax {
+        body.push_str("&automatic_tax[enabled]=true");
+    }
 
     let response = stripe_request(config, "POST", "/v1/subscriptions", &body)?;
     
-    Ok(StripeSubscription {
+    Ok(ProviderSubscription {
         id: response.get("id").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
         status: response.get("status").and_then(|v| v.as_str()).unwrap_or("active").to_string(),
         current_period_start: response.get("current_period_start")
@@ -810,7 +1191,9 @@ fn create_stripe_subscription(config: &StripeConfig, customer_id: &str, price_id
     })
 }
 
-fn update_stripe_subscription(config: &StripeConfig, subscription_id: &str, price_id: &str) -> Result<(), ServiceError> {
+/// Swap the subscription's price. Stripe prorates by default, adding the
+/// difference to the next invoice; `invoice_now` bills it immediately.
+fn update_stripe_subscription(config: &StripeConfig, subscription_id: &str, price_id: &str, invoice_now: bool) -> Result<(), ServiceError> {
     // First get subscription items
     let get_response = stripe_request(config, "GET", &format!("/v1/subscriptions/{}", subscription_id), "")?;
     
@@ -824,9 +1207,10 @@ fn update_stripe_subscription(config: &StripeConfig, subscription_id: &str, pric
         .ok_or_else(|| ServiceError::Internal("No subscription items found".into()))?;
 
     let body = format!(
-        "items[0][id]={}&items[0][price]={}",
+        "items[0][id]={}&items[0][price]={}&proration_behavior={}",
         item_id,
-        price_id
+        price_id,
+        if invoice_now { "always_invoice" } else { "create_prorations" }
     );
 
     stripe_request(config, "POST", &format!("/v1/subscriptions/{}", subscription_id), &body)?;
@@ -839,6 +1223,51 @@ fn cancel_stripe_subscription(config: &StripeConfig, subscription_id: &str) -> R
     Ok(())
 }
 
+fn cancel_stripe_subscription_now(config: &StripeConfig, subscription_id: &str) -> Result<(), ServiceError> {
+    stripe_request(config, "DELETE", &format!("/v1/subscriptions/{}", subscription_id), "")?;
+    Ok(())
+}
+
+fn pause_stripe_subscription(config: &StripeConfig, subscription_id: &str, resumes_at: i64) -> Result<(), ServiceError> {
+    // Invoices that fall inside the pause are voided rather than kept as drafts
+    let body = format!("pause_collection[behavior]=void&pause_collection[resumes_at]={}", resumes_at);
+    stripe_request(config, "POST", &format!("/v1/subscriptions/{}", subscription_id), &body)?;
+    Ok(())
+}
+
+fn resume_stripe_subscription(config: &StripeConfig, subscription_id: &str) -> Result<(), ServiceError> {
+    // An empty value unsets pause_collection
+    stripe_request(config, "POST", &format!("/v1/subscriptions/{}", subscription_id), "pause_collection=")?;
+    Ok(())
+}
+
+fn create_stripe_metered_item(config: &StripeConfig, subscription_id: &str, price_id: &str) -> Result<String, ServiceError> {
+    // Metered items take no quantity; usage is reported separately
+    let body = format!(
+        "subscription={}&price={}",
+        subscription_id,
+        price_id
+    );
+
+    let response = stripe_request(config, "POST", "/v1/subscription_items", &body)?;
+
+    response.get("id")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .ok_or_else(|| ServiceError::Internal("Failed to create metered subscription item".into()))
+}
+
+fn report_stripe_usage(config: &StripeConfig, item_id: &str, quantity: i64, idempotency_key: &str) -> Result<(), ServiceError> {
+    let body = format!(
+        "quantity={}&timestamp={}&action=increment",
+        quantity,
+        Utc::now().timestamp()
+    );
+
+    send_stripe_request(config, "POST", &format!("/v1/subscription_items/{}/usage_records", item_id), &body, Some(idempotency_key))?;
+    Ok(())
+}
+
 fn create_stripe_checkout_session(
     config: &StripeConfig,
     customer_id: &str,
@@ -846,13 +1275,17 @@ fn create_stripe_checkout_session(
     success_url: &str,
     cancel_url: &str,
 ) -> Result<CheckoutSession, ServiceError> {
-    let body = format!(
+    let mut body = format!(
         "customer={}&mode=subscription&line_items[0][price]={}&line_items[0][quantity]=1&success_url={}&cancel_url={}",
         customer_id,
         price_id,
         urlencoded(success_url),
         urlencoded(cancel_url)
     );
+    if config.automatic_tax {
+        body.push('&');
+        body.push_str(tax::checkout_params());
+    }
 
     let response = stripe_request(config, "POST", "/v1/checkout/sessions", &body)?;
     
@@ -876,10 +1309,83 @@ fn create_stripe_portal_session(config: &StripeConfig, customer_id: &str, return
     })
 }
 
+fn update_stripe_customer(config: &StripeConfig, customer_id: &str, form: &str) -> Result<(), ServiceError> {
+    stripe_request(config, "POST", &format!("/v1/customers/{}", customer_id), form)?;
+    Ok(())
+}
+
+/// Returns the Stripe tax ID and its verification status
+fn create_stripe_tax_id(config: &StripeConfig, customer_id: &str, tax_id: &tax::TaxIdInput) -> Result<(String, String), ServiceError> {
+    let body = format!(
+        "type={}&value={}",
+        urlencoded(&tax_id.id_type),
+        urlencoded(&tax_id.value)
+    );
+
+    let response = stripe_request(config, "POST", &format!("/v1/customers/{}/tax_ids", customer_id), &body)
+        .map_err(|e| match e {
+            // Stripe rejects malformed numbers with a 400
+            ServiceError::Internal(msg) if msg.starts_with("Stripe API error: 400") =>
+                ServiceError::BadRequest(format!("Invalid {} tax ID", tax_id.id_type)),
+            other => other,
+        })?;
+
+    let id = response.get("id")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .ok_or_else(|| ServiceError::Internal("Failed to create tax ID".into()))?;
+    let status = response.get("verification")
+        .and_then(|v| v.get("status"))
+        .and_then(|v| v.as_str())
+        .unwrap_or("unavailable")
+        .to_string();
+
+    Ok((id, status))
+}
+
+fn delete_stripe_tax_id(config: &StripeConfig, customer_id: &str, tax_id: &str) -> Result<(), ServiceError> {
+    stripe_request(config, "DELETE", &format!("/v1/customers/{}/tax_ids/{}", customer_id, tax_id), "")?;
+    Ok(())
+}
+
+/// Fingerprint of the card that paid an invoice; `None` for other payment methods
+fn invoice_card_fingerprint(config: &StripeConfig, invoice: &serde_json::Value) -> Result<Option<String>, ServiceError> {
+    let charge = if let Some(charge_id) = invoice.get("charge").and_then(|v| v.as_str()) {
+        stripe_request(config, "GET", &format!("/v1/charges/{}", charge_id), "")?
+    } else if let Some(intent_id) = invoice.get("payment_intent").and_then(|v| v.as_str()) {
+        let intent = stripe_request(config, "GET", &format!("/v1/payment_intents/{}?expand%5B%5D=latest_charge", intent_id), "")?;
+        intent.get("latest_charge").cloned().unwrap_or_default()
+    } else {
+        return Ok(None);
+    };
+
+    Ok(charge.pointer("/payment_method_details/card/fingerprint")
+        .and_then(|v| v.as_str())
+        .map(String::from))
+}
+
+fn list_stripe_card_fingerprints(config: &StripeConfig, customer_id: &str) -> Result<Vec<String>, ServiceError> {
+    let methods = stripe_request(config, "GET", &format!("/v1/payment_methods?customer={}&type=card&limit=100", customer_id), "")?;
+    Ok(methods.get("data")
+        .and_then(|v| v.as_array())
+        .map(|data| data.iter()
+            .filter_map(|m| m.pointer("/card/fingerprint").and_then(|v| v.as_str()).map(String::from))
+            .collect())
+        .unwrap_or_default())
+}
+
 fn get_or_create_stripe_customer(conn: &Connection, config: &StripeConfig, user_id: &Uuid) -> Result<String, ServiceError> {
+    get_or_create_customer(conn, &stripe::StripeProvider::new(config.clone()), user_id)
+}
+
+/// The user's customer with `provider`, created on first use
+fn get_or_create_customer(conn: &Connection, provider: &dyn payments::PaymentProvider, user_id: &Uuid) -> Result<String, ServiceError> {
     // Check if customer exists
-    let query = "SELECT stripe_customer_id FROM subscriptions.subscriptions WHERE user_id = $1";
-    let params = [ParameterValue::Str(user_id.to_string())];
+    let query = "SELECT stripe_customer_id FROM subscriptions.subscriptions WHERE user_id = $1 AND payment_provider = $2";
+    let params = [
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(provider.name().to_string()),
+    ];
     let rows = conn.query(query, &params)
         .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
 
@@ -889,6 +1395,19 @@ fn get_or_create_stripe_customer(conn: &Connection, config: &StripeConfig, user_
         }
     }
 
+    // Billing details may be saved before the first subscription
+    if provider.name() == stripe::PROVIDER {
+        let details_query = "SELECT stripe_customer_id FROM subscriptions.billing_details WHERE user_id = $1";
+        let details_rows = conn.query(details_query, &params[..1])
+            .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+        if let Some(row) = details_rows.rows.first() {
+            if let Ok(customer_id) = String::decode(&row[0]) {
+                return Ok(customer_id);
+            }
+        }
+    }
+
     // Get user email
     let user_query = "SELECT email FROM users.users WHERE id = $1";
     let user_params = [ParameterValue::Str(user_id.to_string())];
@@ -900,21 +1419,35 @@ fn get_or_create_stripe_customer(conn: &Connection, config: &StripeConfig, user_
     }
 
     let email = String::decode(&user_rows.rows[0][0]).unwrap_or_default();
-    create_stripe_customer(config, &email, user_id)
+    provider.create_customer(&email, user_id)
 }
 
 fn stripe_request(config: &StripeConfig, method: &str, path: &str, body: &str) -> Result<serde_json::Value, ServiceError> {
+    send_stripe_request(config, method, path, body, None)
+}
+
+/// A request Stripe applies once per `idempotency_key`, however often it is sent
+fn send_stripe_request(
+    config: &StripeConfig,
+    method: &str,
+    path: &str,
+    body: &str,
+    idempotency_key: Option<&str>,
+) -> Result<serde_json::Value, ServiceError> {
     let url = format!("https://api.stripe.com{}", path);
     
     let auth = format!("Basic {}", base64_encode(&format!("{}:", config.secret_key)));
     
-    let request = outbound_http::Request::builder()
+    let mut request = outbound_http::Request::builder();
+    request
         .method(method)
         .uri(&url)
         .header("Authorization", &auth)
-        .header("Content-Type", "application/x-www-form-urlencoded")
-        .body(body.to_string())
-        .build();
+        .header("Content-Type", "application/x-www-form-urlencoded");
+    if let Some(key) = idempotency_key {
+        request.header("Idempotency-Key", key);
+    }
+    let request = request.body(body.to_string()).build();
 
     let response = outbound_http::send(request)
         .map_err(|e| ServiceError::Internal(format!("Stripe request failed: {}", e)))?;
@@ -975,10 +1508,11 @@ fn base64_encode(s: &str) -> String {
 }
 
 fn urlencoded(s: &str) -> String {
-    s.chars()
-        .map(|c| match c {
-            'A'..='Z' | 'a'..='z' | '0'..='9' | '-' | '_' | '.' | '~' => c.to_string(),
-            _ => format!("%{:02X}", c as u8),
+    // Encode UTF-8 bytes so non-ASCII names and addresses survive
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
         })
         .collect()
 }
@@ -987,6 +1521,36 @@ fn urlencoded(s: &str) -> String {
 // Helper Functions
 //=============================================================================
 
+fn get_query_param(req: &Request, name: &str) -> Option<String> {
+    req.query().split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name).then(|| percent_decode(value))
+    })
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match std::str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
 fn json_response<T: Serialize>(status: u16, body: T) -> Result<Response, ServiceError> {
     let json = serde_json::to_string(&body)
         .map_err(|e| ServiceError::Internal(format!("JSON error: {}", e)))?;
@@ -1102,3 +1666,140 @@ fn check_user_credits(req: &Request) -> Result<Response, ServiceError> {
         "required_amount": body.required_amount
     }))
 }
+
+fn process_credit_expiry(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let conn = get_db_connection()?;
+    credits::process_expiry(&conn)
+}
+
+//=============================================================================
+// Entitlement Handlers
+//=============================================================================
+
+fn check_entitlement(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = get_user_id(req)?;
+    let body: entitlements::CheckRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+    entitlements::check(&conn, &user_id, body)
+}
+
+//=============================================================================
+// Admin Endpoint Handlers
+//=============================================================================
+
+fn admin_override_subscription(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let target_id = path.strip_prefix("/admin/subscriptions/")
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid user ID".into()))?;
+    let body: admin::SubscriptionOverrideRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &actor_id)?;
+    admin::override_subscription(&conn, &actor_id, &target_id, body)
+}
+
+fn admin_grant_credits(req: &Request) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let body: admin::CreditGrantRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &actor_id)?;
+    admin::grant_credits(&conn, &actor_id, body)
+}
+
+fn admin_adjust_credits(req: &Request) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let body: admin::CreditAdjustmentRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &actor_id)?;
+    admin::adjust_credits(&conn, &actor_id, body)
+}
+
+fn admin_list_audit(req: &Request) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let target_id = req.query().split('&')
+        .filter_map(|pair| pair.strip_prefix("user_id="))
+        .next()
+        .map(|id| Uuid::parse_str(id).map_err(|_| ServiceError::BadRequest("Invalid user_id".into())))
+        .transpose()?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &actor_id)?;
+    admin::list_audit(&conn, target_id)
+}
+
+fn admin_billing_timeline(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let target_id = path.strip_prefix("/admin/users/")
+        .and_then(|rest| rest.strip_suffix("/billing-timeline"))
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid user ID".into()))?;
+    let before = get_query_param(req, "before");
+    let limit = get_query_param(req, "limit")
+        .map(|v| v.parse::<i64>().map_err(|_| ServiceError::BadRequest("limit must be a number".into())))
+        .transpose()?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &actor_id)?;
+    billing_events::timeline(&conn, &target_id, before, limit)
+}
+
+fn admin_list_plans(req: &Request) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &actor_id)?;
+    plans::admin_list(&conn)
+}
+
+fn admin_create_plan(req: &Request) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let body: plans::CreatePlanRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &actor_id)?;
+    plans::create(&conn, &actor_id, body)
+}
+
+fn admin_update_plan(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let plan_id = plan_id_from_path(path)?;
+    let body: plans::UpdatePlanRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &actor_id)?;
+    plans::update(&conn, &actor_id, plan_id, body)
+}
+
+fn admin_deactivate_plan(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let plan_id = plan_id_from_path(path)?;
+    let body: plans::DeactivatePlanRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &actor_id)?;
+    plans::deactivate(&conn, &actor_id, plan_id, body)
+}
+
+fn admin_revenue_analytics(req: &Request) -> Result<Response, ServiceError> {
+    let actor_id = get_user_id(req)?;
+    let conn = get_db_connection()?;
+
+    authorworks_access::require_admin(&conn, &actor_id)?;
+    analytics::revenue(
+        &conn,
+        get_query_param(req, "period"),
+        get_query_param(req, "from"),
+        get_query_param(req, "to"),
+        get_query_param(req, "format"),
+    )
+}
+
+fn plan_id_from_path(path: &str) -> Result<&str, ServiceError> {
+    path.strip_prefix("/admin/plans/")
+        .filter(|id| !id.is_empty() && !id.contains('/'))
+        .ok_or_else(|| ServiceError::BadRequest("Invalid plan ID".into()))
+}
@@ -12,8 +12,18 @@ pub struct StripeConfig {
     pub secret_key: String,
     pub webhook_secret: String,
     pub price_id_free: String,
-    pub price_id_pro: String,
-    pub price_id_enterprise: String,
+    /// Metered price for Pro AI word overage
+    pub price_id_ai_overage: Option<String>,
+    /// Compute VAT/GST with Stripe Tax on checkout and subscriptions
+    pub automatic_tax: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct LemonSqueezyConfig {
+    pub api_key: String,
+    pub webhook_secret: String,
+    /// Store that customers and checkouts are created in
+    pub store_id: String,
 }
 
 //=============================================================================
@@ -28,9 +38,38 @@ pub struct Plan {
     pub price_monthly: i64,  // In cents
     pub price_yearly: i64,
     pub features: Vec<String>,
+    /// Feature keys other services gate on, e.g. `version_history`
+    #[serde(default)]
+    pub flags: Vec<String>,
     pub limits: PlanLimits,
 }
 
+/// How often a subscription renews; each plan has a price for both
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BillingInterval {
+    #[default]
+    Month,
+    Year,
+}
+
+impl BillingInterval {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BillingInterval::Month => "month",
+            BillingInterval::Year => "year",
+        }
+    }
+
+    /// Stored values outside the enum are treated as monthly
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "year" => BillingInterval::Year,
+            _ => BillingInterval::Month,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlanLimits {
     pub max_books: i32,           // -1 for unlimited
@@ -59,6 +98,13 @@ pub struct Subscription {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub current_period_end: Option<String>,
     pub cancel_at_period_end: bool,
+    pub billing_interval: BillingInterval,
+    /// Paused accounts keep their data but cannot create or generate anything
+    pub read_only: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub paused_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pause_resumes_at: Option<String>,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -71,16 +117,23 @@ pub struct Subscription {
 pub struct CreateSubscriptionRequest {
     pub plan_id: String,
     pub payment_method_id: Option<String>,
+    #[serde(default)]
+    pub interval: BillingInterval,
 }
 
+/// Change the plan, the billing interval, or both; omitted fields keep
+/// their current value
 #[derive(Debug, Deserialize)]
 pub struct UpdateSubscriptionRequest {
-    pub plan_id: String,
+    pub plan_id: Option<String>,
+    pub interval: Option<BillingInterval>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CheckoutRequest {
     pub plan_id: String,
+    #[serde(default)]
+    pub interval: BillingInterval,
     pub success_url: String,
     pub cancel_url: String,
 }
@@ -91,11 +144,11 @@ pub struct PortalRequest {
 }
 
 //=============================================================================
-// Stripe Response Models
+// Payment Provider Response Models
 //=============================================================================
 
 #[derive(Debug, Clone)]
-pub struct StripeSubscription {
+pub struct ProviderSubscription {
     pub id: String,
     pub status: String,
     pub current_period_start: String,
@@ -113,6 +166,10 @@ pub struct PortalSession {
     pub url: String,
 }
 
+//=============================================================================
+// Stripe Webhook Models
+//=============================================================================
+
 #[derive(Debug, Deserialize)]
 pub struct StripeEvent {
     pub id: String,
@@ -0,0 +1,315 @@
+//! AI Word Overage Module
+//!
+//! Pro users can opt in to metered overage instead of being blocked at their
+//! monthly AI word allowance. Words beyond the allowance are reported to a
+//! Stripe metered price item on the user's subscription and billed with the
+//! next invoice. Usage periods start with the subscription's Stripe billing
+//! period, so words are reported against the invoice that bills them; annual
+//! periods are split into months from the same anniversary. Accounts without
+//! a billing period fall back to the calendar month.
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use uuid::Uuid;
+use chrono::{DateTime, Datelike, Months, TimeZone, Utc};
+
+//=============================================================================
+// Configuration
+//=============================================================================
+
+/// Default overage price: $2.00 per 1,000 words
+const DEFAULT_PRICE_PER_1K_WORDS_CENTS: i64 = 200;
+
+/// Only Pro has a finite allowance that can be extended by overage
+pub const OVERAGE_PLAN: &str = "pro";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OverageConfig {
+    pub price_per_1k_words_cents: i64,
+}
+
+/// Load the overage price from `overage_price_per_1k_words_cents`. It must
+/// match the Stripe metered price, which is reported in words and should
+/// divide the quantity by 1,000 (rounding up) when invoicing.
+pub fn get_overage_config() -> OverageConfig {
+    let price_per_1k_words_cents = variables::get("overage_price_per_1k_words_cents")
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .filter(|n| *n >= 0)
+        .unwrap_or(DEFAULT_PRICE_PER_1K_WORDS_CENTS);
+
+    OverageConfig { price_per_1k_words_cents }
+}
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct OverageToggleRequest {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct OverageSettings {
+    pub plan_id: String,
+    pub stripe_subscription_id: Option<String>,
+    pub enabled: bool,
+    pub stripe_item_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OverageSummary {
+    pub eligible: bool,
+    pub enabled: bool,
+    pub included_words: i64,
+    pub words_over_limit: i64,
+    pub charges_to_date_cents: i64,
+    pub projected_words: i64,
+    pub projected_overage_words: i64,
+    pub projected_charge_cents: i64,
+    pub price_per_1k_words_cents: i64,
+    /// The allowance is used up and overage is off, so AI generation is blocked
+    pub blocked: bool,
+}
+
+/// Overage words not yet reported to Stripe for one subscription
+#[derive(Debug)]
+pub struct PendingReport {
+    pub user_id: Uuid,
+    pub stripe_item_id: String,
+    pub period_start: DateTime<Utc>,
+    pub words: i64,
+    pub words_reported: i64,
+    pub total_words_over_limit: i64,
+}
+
+impl PendingReport {
+    /// Same for every attempt at the same step, so Stripe applies a retried
+    /// report once
+    pub fn idempotency_key(&self) -> String {
+        format!("overage-{}-{}-{}", self.user_id, self.period_start.timestamp(), self.total_words_over_limit)
+    }
+}
+
+//=============================================================================
+// Billing Period
+//=============================================================================
+
+/// One AI word allowance period
+#[derive(Debug, Clone, Copy)]
+pub struct UsagePeriod {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+/// The month of the billing period that started at `billing_start` which
+/// contains now, or the calendar month without one
+pub fn usage_period(billing_start: Option<DateTime<Utc>>) -> UsagePeriod {
+    let now = Utc::now();
+    let start = match billing_start.filter(|start| *start <= now) {
+        Some(billing_start) => {
+            let mut start = billing_start;
+            let mut months = 1;
+            while let Some(next) = billing_start.checked_add_months(Months::new(months)).filter(|next| *next <= now) {
+                start = next;
+                months += 1;
+            }
+            start
+        }
+        None => Utc.with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+            .single()
+            .unwrap_or(now),
+    };
+    UsagePeriod { start, end: start.checked_add_months(Months::new(1)).unwrap_or(start) }
+}
+
+/// The user's current usage period
+pub fn current_period(conn: &Connection, user_id: &Uuid) -> Result<UsagePeriod, ServiceError> {
+    let query = "SELECT to_char(current_period_start AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"')
+                 FROM subscriptions.subscriptions WHERE user_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let billing_start = rows.rows.first().and_then(|row| String::decode(&row[0]).ok());
+    Ok(usage_period(billing_start.as_deref().and_then(parse_timestamp)))
+}
+
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value).ok().map(|dt| dt.with_timezone(&Utc))
+}
+
+/// AI words used by the user since `since`
+pub fn words_used(conn: &Connection, user_id: &Uuid, since: DateTime<Utc>) -> Result<i64, ServiceError> {
+    let query = "SELECT COALESCE(SUM(word_count), 0) FROM subscriptions.ai_usage
+                 WHERE user_id = $1 AND created_at >= $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(since.to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0))
+}
+
+//=============================================================================
+// Settings
+//=============================================================================
+
+pub fn get_settings(conn: &Connection, user_id: &Uuid) -> Result<Option<OverageSettings>, ServiceError> {
+    let query = "SELECT plan_id, stripe_subscription_id, COALESCE(overage_enabled, false), overage_stripe_item_id
+                 FROM subscriptions.subscriptions WHERE user_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first().map(|row| OverageSettings {
+        plan_id: String::decode(&row[0]).unwrap_or_else(|_| "free".into()),
+        stripe_subscription_id: String::decode(&row[1]).ok(),
+        enabled: bool::decode(&row[2]).unwrap_or(false),
+        stripe_item_id: String::decode(&row[3]).ok(),
+    }))
+}
+
+/// Persist the toggle and the metered Stripe item it bills through
+pub fn set_enabled(conn: &Connection, user_id: &Uuid, enabled: bool, stripe_item_id: Option<&str>) -> Result<(), ServiceError> {
+    let update = "UPDATE subscriptions.subscriptions
+                  SET overage_enabled = $2, overage_stripe_item_id = COALESCE($3, overage_stripe_item_id), updated_at = $4
+                  WHERE user_id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Boolean(enabled),
+        stripe_item_id.map(|s| ParameterValue::Str(s.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+//=============================================================================
+// Projection
+//=============================================================================
+
+/// Current and projected overage for a period with `words_used` so far
+pub fn summarize(
+    config: &OverageConfig,
+    period: UsagePeriod,
+    plan_id: &str,
+    enabled: bool,
+    included_words: i64,
+    words_used: i64,
+) -> OverageSummary {
+    let eligible = plan_id == OVERAGE_PLAN && included_words >= 0;
+    let UsagePeriod { start, end } = period;
+
+    // Linear run-rate projection; the first hour is too noisy to extrapolate
+    let elapsed = (Utc::now() - start).num_seconds().max(3600);
+    let total = (end - start).num_seconds().max(elapsed);
+    let projected_words = words_used.saturating_mul(total) / elapsed;
+
+    let words_over_limit = if eligible { (words_used - included_words).max(0) } else { 0 };
+    let projected_overage_words = if eligible { (projected_words - included_words).max(0) } else { 0 };
+    let billable = eligible && enabled;
+
+    OverageSummary {
+        eligible,
+        enabled: billable,
+        included_words,
+        words_over_limit,
+        charges_to_date_cents: if billable { charge_cents(config, words_over_limit) } else { 0 },
+        projected_words,
+        projected_overage_words,
+        projected_charge_cents: if billable { charge_cents(config, projected_overage_words) } else { 0 },
+        price_per_1k_words_cents: config.price_per_1k_words_cents,
+        blocked: included_words >= 0 && words_used >= included_words && !billable,
+    }
+}
+
+/// Charge for `words`, billed per started block of 1,000
+pub fn charge_cents(config: &OverageConfig, words: i64) -> i64 {
+    ((words + 999) / 1000) * config.price_per_1k_words_cents
+}
+
+//=============================================================================
+// Stripe Reporting
+//=============================================================================
+
+/// Opted-in subscriptions whose overage has grown since the last report
+pub fn pending_reports(conn: &Connection, included_words: i64) -> Result<Vec<PendingReport>, ServiceError> {
+    let query = "SELECT user_id, overage_stripe_item_id,
+                        to_char(current_period_start AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"')
+                 FROM subscriptions.subscriptions
+                 WHERE plan_id = $1 AND overage_enabled = true
+                   AND overage_stripe_item_id IS NOT NULL
+                   AND status IN ('active', 'trialing')";
+    let rows = conn.query(query, &[ParameterValue::Str(OVERAGE_PLAN.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut pending = Vec::new();
+    for row in &rows.rows {
+        let (Some(user_id), Ok(stripe_item_id)) = (
+            String::decode(&row[0]).ok().and_then(|id| Uuid::parse_str(&id).ok()),
+            String::decode(&row[1]),
+        ) else {
+            continue;
+        };
+        let period = usage_period(String::decode(&row[2]).ok().as_deref().and_then(parse_timestamp));
+
+        let over = (words_used(conn, &user_id, period.start)? - included_words).max(0);
+        let reported = words_reported(conn, &user_id, period.start)?;
+        if over <= reported {
+            continue;
+        }
+        pending.push(PendingReport {
+            user_id,
+            stripe_item_id,
+            period_start: period.start,
+            words: over - reported,
+            words_reported: reported,
+            total_words_over_limit: over,
+        });
+    }
+    Ok(pending)
+}
+
+fn words_reported(conn: &Connection, user_id: &Uuid, period_start: DateTime<Utc>) -> Result<i64, ServiceError> {
+    let query = "SELECT words_reported FROM subscriptions.overage_reports
+                 WHERE user_id = $1 AND period_start = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(period_start.to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0))
+}
+
+/// Move the period's reported total to `total_words_over_limit` before Stripe
+/// is told, so a concurrent run or a crash after Stripe accepted the usage
+/// cannot bill the words again. False when another run got there first.
+pub fn claim_report(conn: &Connection, report: &PendingReport) -> Result<bool, ServiceError> {
+    let upsert = "INSERT INTO subscriptions.overage_reports AS r (user_id, period_start, words_reported, updated_at)
+                  VALUES ($1, $2, $3, NOW())
+                  ON CONFLICT (user_id, period_start) DO UPDATE SET
+                  words_reported = EXCLUDED.words_reported,
+                  updated_at = EXCLUDED.updated_at
+                  WHERE r.words_reported = $4";
+    let claimed = conn.execute(upsert, &[
+        ParameterValue::Str(report.user_id.to_string()),
+        ParameterValue::Str(report.period_start.to_rfc3339()),
+        ParameterValue::Int64(report.total_words_over_limit),
+        ParameterValue::Int64(report.words_reported),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    Ok(claimed > 0)
+}
+
+/// Give back a claim Stripe refused, so the next run reports the words
+pub fn release_report(conn: &Connection, report: &PendingReport) -> Result<(), ServiceError> {
+    let update = "UPDATE subscriptions.overage_reports SET words_reported = $4, updated_at = NOW()
+                  WHERE user_id = $1 AND period_start = $2 AND words_reported = $3";
+    conn.execute(update, &[
+        ParameterValue::Str(report.user_id.to_string()),
+        ParameterValue::Str(report.period_start.to_rfc3339()),
+        ParameterValue::Int64(report.total_words_over_limit),
+        ParameterValue::Int64(report.words_reported),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
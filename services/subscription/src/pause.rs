@@ -0,0 +1,191 @@
+//! Subscription pauses
+//!
+//! Authors taking a break can pause billing instead of cancelling. A pause
+//! runs for a set number of days (`max_pause_days` at most) and stops
+//! collection with the provider: Stripe's `pause_collection` with invoices
+//! voided, or LemonSqueezy's `void` pause mode. Both resume billing on their
+//! own when the pause runs out.
+//!
+//! While `paused` the account is read-only: entitlement checks refuse every
+//! feature with reason `paused`, so content refuses new books, chapter writes
+//! and AI generation and storage refuses uploads. Existing books and files
+//! stay readable.
+//! The author can resume early with `POST /subscription/resume`. Otherwise
+//! `POST /subscription/pause/process` picks up pauses that have run out,
+//! marks them active again (unless a provider webhook already did) and
+//! notifies the author that billing has restarted.
+
+use crate::dunning::notify_user;
+use crate::error::ServiceError;
+use crate::{billing_events, payments};
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use uuid::Uuid;
+
+pub const STATUS: &str = "paused";
+
+const DEFAULT_PAUSE_DAYS: i64 = 30;
+const DEFAULT_MAX_PAUSE_DAYS: i64 = 90;
+
+/// Statuses a subscription can be paused from
+const PAUSABLE_STATUSES: [&str; 2] = ["active", "trialing"];
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PauseRequest {
+    /// How long to pause for; defaults to 30 days
+    pub days: Option<i64>,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /subscription/pause - Stop billing for a while, keeping read-only access
+pub fn pause(conn: &Connection, user_id: &Uuid, body: PauseRequest) -> Result<Response, ServiceError> {
+    let max_days = max_pause_days();
+    let days = body.days.unwrap_or(DEFAULT_PAUSE_DAYS.min(max_days));
+    if !(1..=max_days).contains(&days) {
+        return Err(ServiceError::BadRequest(format!("days must be between 1 and {}", max_days)));
+    }
+
+    let (subscription_id, status, provider_name, cancel_at_period_end) = load(conn, user_id)?;
+    if status == STATUS {
+        return Err(ServiceError::Conflict("Subscription is already paused".into()));
+    }
+    if !PAUSABLE_STATUSES.contains(&status.as_str()) {
+        return Err(ServiceError::BadRequest(format!("A {} subscription cannot be paused", status)));
+    }
+    if cancel_at_period_end {
+        return Err(ServiceError::BadRequest("Subscription is set to cancel at the end of the period".into()));
+    }
+
+    let now = Utc::now();
+    let resumes_at = now + Duration::days(days);
+    payments::by_name(&provider_name)?.pause(&subscription_id, resumes_at)?;
+
+    let update = "UPDATE subscriptions.subscriptions
+                  SET status = $2, paused_at = $3, pause_resumes_at = $4, updated_at = $3
+                  WHERE user_id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(STATUS.to_string()),
+        ParameterValue::Str(now.to_rfc3339()),
+        ParameterValue::Str(resumes_at.to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    billing_events::record(conn, user_id, "subscription.paused", billing_events::Source::User, Some(user_id), serde_json::json!({
+        "previous_status": status,
+        "days": days,
+        "resumes_at": resumes_at.to_rfc3339(),
+        "stripe_subscription_id": subscription_id
+    }))?;
+
+    crate::json_response(200, serde_json::json!({
+        "status": STATUS,
+        "paused_at": now.to_rfc3339(),
+        "resumes_at": resumes_at.to_rfc3339(),
+        "read_only": true
+    }))
+}
+
+/// POST /subscription/resume - End a pause early and restart billing
+pub fn resume(conn: &Connection, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let (subscription_id, status, provider_name, _) = load(conn, user_id)?;
+    if status != STATUS {
+        return Err(ServiceError::Conflict("Subscription is not paused".into()));
+    }
+
+    payments::by_name(&provider_name)?.resume(&subscription_id)?;
+
+    let now = Utc::now();
+    let update = "UPDATE subscriptions.subscriptions
+                  SET status = 'active', paused_at = NULL, pause_resumes_at = NULL, updated_at = $2
+                  WHERE user_id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(now.to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    billing_events::record(conn, user_id, "subscription.resumed", billing_events::Source::User, Some(user_id), serde_json::json!({
+        "early": true,
+        "stripe_subscription_id": subscription_id
+    }))?;
+
+    crate::json_response(200, serde_json::json!({
+        "status": "active",
+        "resumed_at": now.to_rfc3339()
+    }))
+}
+
+/// POST /subscription/pause/process - Reactivate pauses that have run out
+/// and tell their authors (internal)
+pub fn process_expired(conn: &Connection) -> Result<Response, ServiceError> {
+    // Claim and clear in one statement so overlapping runs notify once
+    let query = "UPDATE subscriptions.subscriptions
+                 SET status = CASE WHEN status = $1 THEN 'active' ELSE status END,
+                     paused_at = NULL, pause_resumes_at = NULL, updated_at = NOW()
+                 WHERE pause_resumes_at IS NOT NULL AND pause_resumes_at <= NOW()
+                 RETURNING user_id, plan_id, stripe_subscription_id";
+    let rows = conn.query(query, &[ParameterValue::Str(STATUS.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    for row in &rows.rows {
+        let user_id = match Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()) {
+            Ok(id) => id,
+            Err(_) => continue,
+        };
+        let plan_id = String::decode(&row[1]).unwrap_or_default();
+
+        billing_events::record(conn, &user_id, "subscription.resumed", billing_events::Source::System, None, serde_json::json!({
+            "early": false,
+            "stripe_subscription_id": String::decode(&row[2]).ok()
+        }))?;
+        notify_user(
+            &user_id,
+            "subscription_resumed",
+            "Your subscription pause has ended",
+            "Welcome back! Billing has restarted and your full plan is available again.",
+            serde_json::json!({ "plan_id": plan_id }),
+        );
+    }
+
+    crate::json_response(200, serde_json::json!({
+        "processed": true,
+        "resumed": rows.rows.len()
+    }))
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+fn max_pause_days() -> i64 {
+    variables::get("max_pause_days")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|d| *d > 0)
+        .unwrap_or(DEFAULT_MAX_PAUSE_DAYS)
+}
+
+/// Provider subscription id, status, provider and whether a cancel is scheduled
+fn load(conn: &Connection, user_id: &Uuid) -> Result<(String, String, String, bool), ServiceError> {
+    let query = "SELECT stripe_subscription_id, status, payment_provider, cancel_at_period_end
+                 FROM subscriptions.subscriptions WHERE user_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Subscription not found".into()))?;
+    let subscription_id = String::decode(&row[0])
+        .map_err(|_| ServiceError::Internal("Invalid subscription data".into()))?;
+
+    Ok((
+        subscription_id,
+        String::decode(&row[1]).unwrap_or_default(),
+        String::decode(&row[2]).unwrap_or_default(),
+        bool::decode(&row[3]).unwrap_or(false),
+    ))
+}
@@ -0,0 +1,184 @@
+//! Payment Provider Module
+//!
+//! Subscriptions are billed through a `PaymentProvider`. The `payment_provider`
+//! variable picks the one new customers use: `stripe` (the default) or
+//! `lemonsqueezy` for regions Stripe does not serve. Subscriptions remember
+//! which provider they were created with, so renewals, plan changes and
+//! cancellations keep going to that provider after the setting changes.
+//!
+//! Each provider verifies its own webhooks and turns them into a
+//! `WebhookEvent`, which `lib.rs` applies the same way for every provider.
+//! Provider customer and subscription ids live in the `stripe_*_id` columns,
+//! with `payment_provider` naming whose ids they are.
+//!
+//! Tax IDs, metered AI overage, referral card checks, credit packs, refunds
+//! and disputes are still Stripe-only.
+
+use crate::error::ServiceError;
+use crate::lemonsqueezy::LemonSqueezyProvider;
+use crate::models::{CheckoutSession, PortalSession, ProviderSubscription};
+use crate::stripe::StripeProvider;
+use crate::tax::InvoiceTax;
+use chrono::{DateTime, Utc};
+use spin_sdk::http::Request;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use uuid::Uuid;
+
+pub trait PaymentProvider {
+    /// Stored in `payment_provider` columns and billing event sources
+    fn name(&self) -> &'static str;
+
+    fn create_customer(&self, email: &str, user_id: &Uuid) -> Result<String, ServiceError>;
+
+    fn create_subscription(&self, customer_id: &str, price_id: &str) -> Result<ProviderSubscription, ServiceError>;
+
+    /// Move to another price with proration; `invoice_now` settles the
+    /// difference immediately instead of on the next renewal
+    fn change_plan(&self, subscription_id: &str, price_id: &str, invoice_now: bool) -> Result<(), ServiceError>;
+
+    fn cancel_at_period_end(&self, subscription_id: &str) -> Result<(), ServiceError>;
+
+    /// End the subscription without waiting for the period to run out
+    fn cancel_now(&self, subscription_id: &str) -> Result<(), ServiceError>;
+
+    /// Stop collecting payments until `resumes_at`, when billing restarts on its own
+    fn pause(&self, subscription_id: &str, resumes_at: DateTime<Utc>) -> Result<(), ServiceError>;
+
+    /// Restart billing before a pause runs out
+    fn resume(&self, subscription_id: &str) -> Result<(), ServiceError>;
+
+    fn create_checkout(
+        &self,
+        customer_id: &str,
+        user_id: &Uuid,
+        price_id: &str,
+        success_url: &str,
+        cancel_url: &str,
+    ) -> Result<CheckoutSession, ServiceError>;
+
+    fn create_portal(&self, customer_id: &str, return_url: &str) -> Result<PortalSession, ServiceError>;
+
+    /// Check the delivery's signature and normalize its payload
+    fn parse_webhook(&self, req: &Request) -> Result<WebhookEvent, ServiceError>;
+}
+
+//=============================================================================
+// Normalized Webhook Events
+//=============================================================================
+
+/// A verified webhook delivery in provider-agnostic form
+#[derive(Debug)]
+pub struct WebhookEvent {
+    /// Provider's id for the event; a repeated delivery is recorded once
+    pub id: String,
+    /// Provider's own name for the event, e.g. `invoice.paid`
+    pub provider_type: String,
+    pub subscription_id: Option<String>,
+    pub customer_id: Option<String>,
+    /// Summary kept on the billing timeline
+    pub details: serde_json::Value,
+    /// The provider's payload object, for provider-specific follow-up
+    pub object: serde_json::Value,
+    pub kind: PaymentEvent,
+}
+
+#[derive(Debug)]
+pub enum PaymentEvent {
+    SubscriptionChanged(SubscriptionChange),
+    SubscriptionEnded { subscription_id: String },
+    PaymentSucceeded(PaymentReceipt),
+    PaymentFailed { subscription_id: String, invoice_id: String },
+    TaxIdVerified { tax_id: String, status: String },
+    /// A charge was refunded in full or in part
+    PaymentRefunded(Refund),
+    /// A charge was disputed, or its dispute was decided
+    PaymentDisputed(Dispute),
+    /// A one-off payment, such as a credit purchase, failed or was abandoned
+    OneTimePaymentFailed { payment_intent_id: String, order_id: Option<String>, reason: Option<String> },
+    /// Recorded on the timeline but otherwise not acted on
+    Other,
+}
+
+#[derive(Debug)]
+pub struct SubscriptionChange {
+    pub subscription_id: String,
+    pub customer_id: Option<String>,
+    /// Set when the provider echoes our user id back, which lets a
+    /// subscription started at checkout be linked to its user
+    pub user_id: Option<Uuid>,
+    /// Provider price the subscription is now on, when it should drive `plan_id`
+    pub price_id: Option<String>,
+    /// One of the statuses in `admin::VALID_STATUSES`
+    pub status: String,
+    pub current_period_start: Option<String>,
+    pub current_period_end: Option<String>,
+    pub cancel_at_period_end: bool,
+    /// When a `paused` subscription resumes billing, if the provider says
+    pub pause_resumes_at: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct PaymentReceipt {
+    pub customer_id: String,
+    pub invoice_id: String,
+    /// Amount paid in minor units
+    pub amount: i64,
+    pub tax: InvoiceTax,
+    /// Lets later refunds and disputes, which name only the payment, find the invoice
+    pub payment_intent_id: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Refund {
+    pub customer_id: Option<String>,
+    pub invoice_id: Option<String>,
+    pub payment_intent_id: Option<String>,
+    pub currency: Option<String>,
+    /// Amount charged in minor units
+    pub amount: i64,
+    /// Total refunded so far, including earlier partial refunds
+    pub amount_refunded: i64,
+}
+
+#[derive(Debug)]
+pub struct Dispute {
+    pub dispute_id: String,
+    pub payment_intent_id: Option<String>,
+    pub amount: i64,
+    pub reason: Option<String>,
+    /// Provider dispute status; `won` and `lost` are final
+    pub status: String,
+    /// Set once the dispute has been decided
+    pub closed: bool,
+}
+
+//=============================================================================
+// Selection
+//=============================================================================
+
+/// Provider that new customers are billed through
+pub fn configured() -> Result<Box<dyn PaymentProvider>, ServiceError> {
+    let name = variables::get("payment_provider").unwrap_or_else(|_| crate::stripe::PROVIDER.into());
+    by_name(&name)
+}
+
+pub fn by_name(name: &str) -> Result<Box<dyn PaymentProvider>, ServiceError> {
+    match name {
+        crate::stripe::PROVIDER => Ok(Box::new(StripeProvider::new(crate::get_stripe_config()?))),
+        crate::lemonsqueezy::PROVIDER => Ok(Box::new(LemonSqueezyProvider::new(crate::lemonsqueezy::get_config()?))),
+        other => Err(ServiceError::Internal(format!("Unknown payment provider '{}'", other))),
+    }
+}
+
+/// Provider that owns an existing subscription
+pub fn for_subscription(conn: &Connection, subscription_id: &str) -> Result<Box<dyn PaymentProvider>, ServiceError> {
+    let query = "SELECT payment_provider FROM subscriptions.subscriptions WHERE stripe_subscription_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(subscription_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    match rows.rows.first().and_then(|row| String::decode(&row[0]).ok()) {
+        Some(name) => by_name(&name),
+        None => configured(),
+    }
+}
@@ -0,0 +1,533 @@
+//! Plan catalogue
+//!
+//! `subscriptions.plans` is the source of truth for prices, features, limits
+//! and the monthly and yearly Stripe prices behind each plan, so support can change them without
+//! a redeploy. Every request that needs a plan reads the whole catalogue, so
+//! it is cached in the Spin key-value store for `plan_cache_ttl_seconds` and
+//! dropped whenever an admin changes a plan. The cache is best effort: if the
+//! store is unavailable every read simply goes to the database.
+//!
+//! Plans are deactivated rather than deleted because subscriptions reference
+//! them by id; an inactive plan keeps its limits for existing subscribers but
+//! can no longer be bought.
+
+use crate::error::ServiceError;
+use crate::models::{BillingInterval, Plan, PlanLimits};
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::key_value::Store;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use chrono::Utc;
+use uuid::Uuid;
+
+/// Fallback for users without a subscription or on an unknown plan
+pub const FREE_PLAN: &str = "free";
+
+const CACHE_KEY: &str = "subscription:plans";
+const DEFAULT_CACHE_TTL_SECONDS: i64 = 60;
+
+const MAX_FEATURES: usize = 20;
+const MAX_FEATURE_LENGTH: usize = 200;
+
+const MAX_FLAGS: usize = 50;
+const MAX_FLAG_LENGTH: usize = 50;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+/// A plan plus the fields only admins see
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanDefinition {
+    #[serde(flatten)]
+    pub plan: Plan,
+    pub stripe_price_id: Option<String>,
+    pub stripe_price_id_yearly: Option<String>,
+    pub active: bool,
+    pub sort_order: i32,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreatePlanRequest {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub price_monthly: i64,
+    pub price_yearly: i64,
+    #[serde(default)]
+    pub features: Vec<String>,
+    #[serde(default)]
+    pub flags: Vec<String>,
+    pub limits: PlanLimits,
+    pub stripe_price_id: Option<String>,
+    pub stripe_price_id_yearly: Option<String>,
+    #[serde(default)]
+    pub sort_order: i32,
+    pub reason: String,
+}
+
+/// Omitted fields are left unchanged; an empty `stripe_price_id` or
+/// `stripe_price_id_yearly` clears it
+#[derive(Debug, Deserialize)]
+pub struct UpdatePlanRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub price_monthly: Option<i64>,
+    pub price_yearly: Option<i64>,
+    pub features: Option<Vec<String>>,
+    pub flags: Option<Vec<String>>,
+    pub limits: Option<PlanLimitsUpdate>,
+    pub stripe_price_id: Option<String>,
+    pub stripe_price_id_yearly: Option<String>,
+    pub active: Option<bool>,
+    pub sort_order: Option<i32>,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PlanLimitsUpdate {
+    pub max_books: Option<i32>,
+    pub max_chapters_per_book: Option<i32>,
+    pub ai_words_per_month: Option<i64>,
+    pub storage_gb: Option<i32>,
+    pub collaborators: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeactivatePlanRequest {
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedPlans {
+    cached_at: i64,
+    plans: Vec<PlanDefinition>,
+}
+
+//=============================================================================
+// Lookups
+//=============================================================================
+
+/// Every plan, active or not, in display order
+pub fn all(conn: &Connection) -> Result<Vec<PlanDefinition>, ServiceError> {
+    if let Some(plans) = read_cache() {
+        return Ok(plans);
+    }
+    let plans = query_plans(conn)?;
+    write_cache(&plans);
+    Ok(plans)
+}
+
+/// Plans that can currently be chosen
+pub fn active(conn: &Connection) -> Result<Vec<Plan>, ServiceError> {
+    Ok(all(conn)?.into_iter().filter(|p| p.active).map(|p| p.plan).collect())
+}
+
+pub fn find(conn: &Connection, plan_id: &str) -> Result<Option<PlanDefinition>, ServiceError> {
+    Ok(all(conn)?.into_iter().find(|p| p.plan.id == plan_id))
+}
+
+/// Limits for a plan; unknown plans get the free plan's limits
+pub fn limits(conn: &Connection, plan_id: &str) -> Result<PlanLimits, ServiceError> {
+    let plans = all(conn)?;
+    plans.iter()
+        .find(|p| p.plan.id == plan_id)
+        .or_else(|| plans.iter().find(|p| p.plan.id == FREE_PLAN))
+        .map(|p| p.plan.limits.clone())
+        .ok_or_else(|| ServiceError::Internal("No plan definitions found".into()))
+}
+
+/// Price to bill for a purchasable plan at the given interval with the given
+/// payment provider. For Stripe a price set on the plan wins; otherwise the
+/// `stripe_price_<plan id>` variable (`..._yearly` for yearly billing) is
+/// used, which keeps deployments configured before plans moved to the
+/// database working. LemonSqueezy variants come from
+/// `lemonsqueezy_variant_<plan id>` and `lemonsqueezy_variant_<plan id>_yearly`.
+pub fn provider_price_id(
+    conn: &Connection,
+    provider: &str,
+    plan_id: &str,
+    interval: BillingInterval,
+) -> Result<String, ServiceError> {
+    let plan = find(conn, plan_id)?
+        .filter(|p| p.active && p.plan.id != FREE_PLAN)
+        .ok_or_else(|| ServiceError::BadRequest("Invalid plan".into()))?;
+
+    price_for(&plan, provider, interval).ok_or_else(|| ServiceError::Internal(format!(
+        "No {} {}ly price configured for plan '{}'", provider, interval.as_str(), plan.plan.id
+    )))
+}
+
+/// Plan and interval a provider price belongs to, active or not, so webhooks
+/// for subscribers on a retired plan still resolve
+pub fn plan_for_price(
+    conn: &Connection,
+    provider: &str,
+    price_id: &str,
+) -> Result<Option<(String, BillingInterval)>, ServiceError> {
+    Ok(all(conn)?.into_iter()
+        .filter(|p| p.plan.id != FREE_PLAN)
+        .find_map(|p| {
+            [BillingInterval::Month, BillingInterval::Year].into_iter()
+                .find(|interval| price_for(&p, provider, *interval).as_deref() == Some(price_id))
+                .map(|interval| (p.plan.id.clone(), interval))
+        }))
+}
+
+fn price_for(plan: &PlanDefinition, provider: &str, interval: BillingInterval) -> Option<String> {
+    let suffix = match interval {
+        BillingInterval::Month => "",
+        BillingInterval::Year => "_yearly",
+    };
+    let configured = |prefix: &str| variables::get(&format!("{}_{}{}", prefix, plan.plan.id, suffix)).ok();
+    let stored = match interval {
+        BillingInterval::Month => &plan.stripe_price_id,
+        BillingInterval::Year => &plan.stripe_price_id_yearly,
+    };
+    match provider {
+        crate::lemonsqueezy::PROVIDER => configured("lemonsqueezy_variant"),
+        _ => stored.clone().or_else(|| configured("stripe_price")),
+    }
+    .filter(|price| !price.is_empty())
+}
+
+//=============================================================================
+// Admin Endpoints
+//=============================================================================
+
+/// GET /admin/plans - All plans including inactive ones and Stripe prices
+pub fn admin_list(conn: &Connection) -> Result<Response, ServiceError> {
+    // Admins should see exactly what is stored, not a cached copy
+    let plans = query_plans(conn)?;
+    crate::json_response(200, serde_json::json!({ "plans": plans }))
+}
+
+/// POST /admin/plans - Add a plan
+pub fn create(conn: &Connection, actor_id: &Uuid, body: CreatePlanRequest) -> Result<Response, ServiceError> {
+    require_reason(&body.reason)?;
+    validate_id(&body.id)?;
+    validate_name(&body.name)?;
+    validate_prices(body.price_monthly, body.price_yearly)?;
+    validate_features(&body.features)?;
+    validate_flags(&body.flags)?;
+    validate_limits(&body.limits)?;
+
+    let insert = "INSERT INTO subscriptions.plans
+                  (id, name, description, price_monthly, price_yearly, features,
+                   max_books, max_chapters_per_book, ai_words_per_month, storage_gb, collaborators,
+                   stripe_price_id, sort_order, flags, stripe_price_id_yearly)
+                  VALUES ($1, $2, $3, $4, $5, $6::jsonb, $7, $8, $9, $10, $11, NULLIF($12, ''), $13, $14::jsonb, NULLIF($15, ''))
+                  ON CONFLICT (id) DO NOTHING";
+    let inserted = conn.execute(insert, &[
+        ParameterValue::Str(body.id.clone()),
+        ParameterValue::Str(body.name.trim().to_string()),
+        ParameterValue::Str(body.description.clone()),
+        ParameterValue::Int64(body.price_monthly),
+        ParameterValue::Int64(body.price_yearly),
+        ParameterValue::Str(serde_json::to_string(&body.features).unwrap_or_else(|_| "[]".into())),
+        ParameterValue::Int32(body.limits.max_books),
+        ParameterValue::Int32(body.limits.max_chapters_per_book),
+        ParameterValue::Int64(body.limits.ai_words_per_month),
+        ParameterValue::Int32(body.limits.storage_gb),
+        ParameterValue::Int32(body.limits.collaborators),
+        body.stripe_price_id.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Int32(body.sort_order),
+        ParameterValue::Str(serde_json::to_string(&body.flags).unwrap_or_else(|_| "[]".into())),
+        body.stripe_price_id_yearly.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    if inserted == 0 {
+        return Err(ServiceError::Conflict(format!("Plan '{}' already exists", body.id)));
+    }
+    invalidate_cache();
+
+    let plan = load_plan(conn, &body.id)?;
+    let audit_id = crate::admin::record_audit(conn, actor_id, None, "plan_create", serde_json::json!({
+        "plan_id": body.id,
+        "after": plan
+    }), &body.reason)?;
+
+    crate::json_response(201, serde_json::json!({
+        "plan": plan,
+        "audit_id": audit_id
+    }))
+}
+
+/// PUT /admin/plans/:id - Change prices, features, limits or availability
+pub fn update(conn: &Connection, actor_id: &Uuid, plan_id: &str, body: UpdatePlanRequest) -> Result<Response, ServiceError> {
+    require_reason(&body.reason)?;
+    if let Some(ref name) = body.name {
+        validate_name(name)?;
+    }
+    validate_prices(body.price_monthly.unwrap_or(0), body.price_yearly.unwrap_or(0))?;
+    if let Some(ref features) = body.features {
+        validate_features(features)?;
+    }
+    if let Some(ref flags) = body.flags {
+        validate_flags(flags)?;
+    }
+    let limits = body.limits.unwrap_or_default();
+    validate_limits(&PlanLimits {
+        max_books: limits.max_books.unwrap_or(0),
+        max_chapters_per_book: limits.max_chapters_per_book.unwrap_or(0),
+        ai_words_per_month: limits.ai_words_per_month.unwrap_or(0),
+        storage_gb: limits.storage_gb.unwrap_or(0),
+        collaborators: limits.collaborators.unwrap_or(0),
+    })?;
+    if plan_id == FREE_PLAN && body.active == Some(false) {
+        return Err(ServiceError::BadRequest("The free plan cannot be deactivated".into()));
+    }
+
+    let before = load_plan(conn, plan_id)?;
+
+    let update = "UPDATE subscriptions.plans SET
+                  name = COALESCE($2, name),
+                  description = COALESCE($3, description),
+                  price_monthly = COALESCE($4, price_monthly),
+                  price_yearly = COALESCE($5, price_yearly),
+                  features = COALESCE($6::jsonb, features),
+                  max_books = COALESCE($7, max_books),
+                  max_chapters_per_book = COALESCE($8, max_chapters_per_book),
+                  ai_words_per_month = COALESCE($9, ai_words_per_month),
+                  storage_gb = COALESCE($10, storage_gb),
+                  collaborators = COALESCE($11, collaborators),
+                  stripe_price_id = CASE WHEN $12::text IS NULL THEN stripe_price_id ELSE NULLIF($12, '') END,
+                  active = COALESCE($13, active),
+                  sort_order = COALESCE($14, sort_order),
+                  flags = COALESCE($15::jsonb, flags),
+                  stripe_price_id_yearly = CASE WHEN $16::text IS NULL THEN stripe_price_id_yearly ELSE NULLIF($16, '') END,
+                  updated_at = NOW()
+                  WHERE id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(plan_id.to_string()),
+        body.name.map(|n| ParameterValue::Str(n.trim().to_string())).unwrap_or(ParameterValue::DbNull),
+        body.description.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        body.price_monthly.map(ParameterValue::Int64).unwrap_or(ParameterValue::DbNull),
+        body.price_yearly.map(ParameterValue::Int64).unwrap_or(ParameterValue::DbNull),
+        body.features
+            .map(|f| ParameterValue::Str(serde_json::to_string(&f).unwrap_or_else(|_| "[]".into())))
+            .unwrap_or(ParameterValue::DbNull),
+        limits.max_books.map(ParameterValue::Int32).unwrap_or(ParameterValue::DbNull),
+        limits.max_chapters_per_book.map(ParameterValue::Int32).unwrap_or(ParameterValue::DbNull),
+        limits.ai_words_per_month.map(ParameterValue::Int64).unwrap_or(ParameterValue::DbNull),
+        limits.storage_gb.map(ParameterValue::Int32).unwrap_or(ParameterValue::DbNull),
+        limits.collaborators.map(ParameterValue::Int32).unwrap_or(ParameterValue::DbNull),
+        body.stripe_price_id.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        body.active.map(ParameterValue::Boolean).unwrap_or(ParameterValue::DbNull),
+        body.sort_order.map(ParameterValue::Int32).unwrap_or(ParameterValue::DbNull),
+        body.flags
+            .map(|f| ParameterValue::Str(serde_json::to_string(&f).unwrap_or_else(|_| "[]".into())))
+            .unwrap_or(ParameterValue::DbNull),
+        body.stripe_price_id_yearly.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    invalidate_cache();
+
+    let after = load_plan(conn, plan_id)?;
+    let audit_id = crate::admin::record_audit(conn, actor_id, None, "plan_update", serde_json::json!({
+        "plan_id": plan_id,
+        "before": before,
+        "after": after
+    }), &body.reason)?;
+
+    crate::json_response(200, serde_json::json!({
+        "plan": after,
+        "audit_id": audit_id
+    }))
+}
+
+/// DELETE /admin/plans/:id - Withdraw a plan from sale; subscribers keep it
+pub fn deactivate(conn: &Connection, actor_id: &Uuid, plan_id: &str, body: DeactivatePlanRequest) -> Result<Response, ServiceError> {
+    require_reason(&body.reason)?;
+    if plan_id == FREE_PLAN {
+        return Err(ServiceError::BadRequest("The free plan cannot be deactivated".into()));
+    }
+
+    let before = load_plan(conn, plan_id)?;
+    conn.execute(
+        "UPDATE subscriptions.plans SET active = false, updated_at = NOW() WHERE id = $1",
+        &[ParameterValue::Str(plan_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    invalidate_cache();
+
+    let subscribers = conn.query(
+        "SELECT COUNT(*) FROM subscriptions.subscriptions WHERE plan_id = $1 AND status <> 'cancelled'",
+        &[ParameterValue::Str(plan_id.to_string())],
+    ).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?
+        .rows.first()
+        .map(|row| i64::decode(&row[0]).unwrap_or(0))
+        .unwrap_or(0);
+
+    let after = load_plan(conn, plan_id)?;
+    let audit_id = crate::admin::record_audit(conn, actor_id, None, "plan_deactivate", serde_json::json!({
+        "plan_id": plan_id,
+        "before": before,
+        "after": after
+    }), &body.reason)?;
+
+    crate::json_response(200, serde_json::json!({
+        "plan": after,
+        "remaining_subscribers": subscribers,
+        "audit_id": audit_id
+    }))
+}
+
+//=============================================================================
+// Storage
+//=============================================================================
+
+fn query_plans(conn: &Connection) -> Result<Vec<PlanDefinition>, ServiceError> {
+    let query = "SELECT id, name, description, price_monthly, price_yearly, features::text,
+                        max_books, max_chapters_per_book, ai_words_per_month, storage_gb, collaborators,
+                        stripe_price_id, active, sort_order, updated_at, flags::text, stripe_price_id_yearly
+                 FROM subscriptions.plans
+                 ORDER BY sort_order, price_monthly, id";
+    let rows = conn.query(query, &[])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.iter().map(|row| PlanDefinition {
+        plan: Plan {
+            id: String::decode(&row[0]).unwrap_or_default(),
+            name: String::decode(&row[1]).unwrap_or_default(),
+            description: String::decode(&row[2]).unwrap_or_default(),
+            price_monthly: i64::decode(&row[3]).unwrap_or(0),
+            price_yearly: i64::decode(&row[4]).unwrap_or(0),
+            features: serde_json::from_str(&String::decode(&row[5]).unwrap_or_default()).unwrap_or_default(),
+            flags: serde_json::from_str(&String::decode(&row[15]).unwrap_or_default()).unwrap_or_default(),
+            limits: PlanLimits {
+                max_books: i32::decode(&row[6]).unwrap_or(0),
+                max_chapters_per_book: i32::decode(&row[7]).unwrap_or(0),
+                ai_words_per_month: i64::decode(&row[8]).unwrap_or(0),
+                storage_gb: i32::decode(&row[9]).unwrap_or(0),
+                collaborators: i32::decode(&row[10]).unwrap_or(0),
+            },
+        },
+        stripe_price_id: String::decode(&row[11]).ok(),
+        stripe_price_id_yearly: String::decode(&row[16]).ok(),
+        active: bool::decode(&row[12]).unwrap_or(false),
+        sort_order: i32::decode(&row[13]).unwrap_or(0),
+        updated_at: String::decode(&row[14]).unwrap_or_default(),
+    }).collect())
+}
+
+fn load_plan(conn: &Connection, plan_id: &str) -> Result<PlanDefinition, ServiceError> {
+    query_plans(conn)?
+        .into_iter()
+        .find(|p| p.plan.id == plan_id)
+        .ok_or_else(|| ServiceError::NotFound("Plan not found".into()))
+}
+
+//=============================================================================
+// Cache
+//=============================================================================
+
+fn cache_ttl() -> i64 {
+    variables::get("plan_cache_ttl_seconds")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v| *v >= 0)
+        .unwrap_or(DEFAULT_CACHE_TTL_SECONDS)
+}
+
+fn read_cache() -> Option<Vec<PlanDefinition>> {
+    let ttl = cache_ttl();
+    if ttl == 0 {
+        return None;
+    }
+    let bytes = Store::open_default().ok()?.get(CACHE_KEY).ok()??;
+    let cached: CachedPlans = serde_json::from_slice(&bytes).ok()?;
+    (Utc::now().timestamp() - cached.cached_at < ttl).then_some(cached.plans)
+}
+
+fn write_cache(plans: &[PlanDefinition]) {
+    if cache_ttl() == 0 {
+        return;
+    }
+    let cached = CachedPlans { cached_at: Utc::now().timestamp(), plans: plans.to_vec() };
+    if let (Ok(store), Ok(bytes)) = (Store::open_default(), serde_json::to_vec(&cached)) {
+        let _ = store.set(CACHE_KEY, &bytes);
+    }
+}
+
+fn invalidate_cache() {
+    if let Ok(store) = Store::open_default() {
+        let _ = store.delete(CACHE_KEY);
+    }
+}
+
+//=============================================================================
+// Validation
+//=============================================================================
+
+fn require_reason(reason: &str) -> Result<(), ServiceError> {
+    if reason.trim().is_empty() {
+        return Err(ServiceError::BadRequest("reason is required".into()));
+    }
+    Ok(())
+}
+
+/// Ids double as Spin variable suffixes (`stripe_price_<id>`)
+fn validate_id(id: &str) -> Result<(), ServiceError> {
+    let valid = !id.is_empty() && id.len() <= 50
+        && id.starts_with(|c: char| c.is_ascii_lowercase())
+        && id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !valid {
+        return Err(ServiceError::BadRequest("id must be lowercase letters, digits and underscores, starting with a letter".into()));
+    }
+    Ok(())
+}
+
+fn validate_name(name: &str) -> Result<(), ServiceError> {
+    if name.trim().is_empty() || name.chars().count() > 100 {
+        return Err(ServiceError::BadRequest("name must be 1-100 characters".into()));
+    }
+    Ok(())
+}
+
+fn validate_prices(monthly: i64, yearly: i64) -> Result<(), ServiceError> {
+    if monthly < 0 || yearly < 0 {
+        return Err(ServiceError::BadRequest("Prices cannot be negative".into()));
+    }
+    Ok(())
+}
+
+fn validate_features(features: &[String]) -> Result<(), ServiceError> {
+    if features.len() > MAX_FEATURES {
+        return Err(ServiceError::BadRequest(format!("At most {} features", MAX_FEATURES)));
+    }
+    if features.iter().any(|f| f.trim().is_empty() || f.chars().count() > MAX_FEATURE_LENGTH) {
+        return Err(ServiceError::BadRequest(format!("Features must be 1-{} characters", MAX_FEATURE_LENGTH)));
+    }
+    Ok(())
+}
+
+/// Flags are matched exactly by other services, so keep them to snake_case keys
+fn validate_flags(flags: &[String]) -> Result<(), ServiceError> {
+    if flags.len() > MAX_FLAGS {
+        return Err(ServiceError::BadRequest(format!("At most {} flags", MAX_FLAGS)));
+    }
+    let valid = |f: &String| !f.is_empty() && f.len() <= MAX_FLAG_LENGTH
+        && f.starts_with(|c: char| c.is_ascii_lowercase())
+        && f.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !flags.iter().all(valid) {
+        return Err(ServiceError::BadRequest("Flags must be lowercase letters, digits and underscores, starting with a letter".into()));
+    }
+    Ok(())
+}
+
+/// -1 means unlimited; anything lower is a mistake
+fn validate_limits(limits: &PlanLimits) -> Result<(), ServiceError> {
+    let values = [
+        limits.max_books as i64,
+        limits.max_chapters_per_book as i64,
+        limits.ai_words_per_month,
+        limits.storage_gb as i64,
+        limits.collaborators as i64,
+    ];
+    if values.iter().any(|v| *v < -1) {
+        return Err(ServiceError::BadRequest("Limits must be -1 (unlimited) or greater".into()));
+    }
+    Ok(())
+}
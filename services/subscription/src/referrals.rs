@@ -0,0 +1,299 @@
+//! Referral Program Module
+//!
+//! Every user can share a referral code. A new account applies a code with
+//! `POST /referrals/redeem` within `referral_signup_window_days` of signing
+//! up and before upgrading. When the referred user's first paid invoice
+//! clears, the referrer earns `referral_reward_credits` and the referred user
+//! `referral_referred_credits`, granted through the credits module.
+//!
+//! Fraud checks: a user cannot redeem their own code or the code of someone
+//! they referred, and a conversion paid with the referrer's card, or with a
+//! card that already earned this referrer a reward, is rejected instead of
+//! rewarded.
+
+use crate::credits;
+use crate::error::ServiceError;
+use serde::Deserialize;
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use uuid::Uuid;
+
+const DEFAULT_REWARD_CREDITS: i32 = 500;
+const DEFAULT_REFERRED_CREDITS: i32 = 250;
+const DEFAULT_SIGNUP_WINDOW_DAYS: i32 = 30;
+
+/// Crockford base32 without I, L, O and U, so codes survive being read aloud
+const CODE_ALPHABET: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const CODE_LENGTH: usize = 8;
+const CODE_ATTEMPTS: usize = 5;
+
+const RECENT_REFERRALS: i64 = 50;
+
+//=============================================================================
+// Configuration
+//=============================================================================
+
+struct ReferralConfig {
+    reward_credits: i32,
+    referred_credits: i32,
+    signup_window_days: i32,
+}
+
+fn get_referral_config() -> ReferralConfig {
+    let read = |name: &str, default: i32| {
+        variables::get(name)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|v: &i32| *v >= 0)
+            .unwrap_or(default)
+    };
+    ReferralConfig {
+        reward_credits: read("referral_reward_credits", DEFAULT_REWARD_CREDITS),
+        referred_credits: read("referral_referred_credits", DEFAULT_REFERRED_CREDITS),
+        signup_window_days: read("referral_signup_window_days", DEFAULT_SIGNUP_WINDOW_DAYS),
+    }
+}
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct RedeemReferralRequest {
+    pub code: String,
+}
+
+/// A referred user's signup waiting for their first paid invoice
+pub struct PendingConversion {
+    pub referral_id: Uuid,
+    pub referrer_id: Uuid,
+    pub referred_id: Uuid,
+    /// For looking up the referrer's saved cards
+    pub referrer_customer_id: Option<String>,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// GET /referrals/code - The caller's referral code, created on first request
+pub fn get_code(conn: &Connection, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let select = "SELECT code, created_at FROM subscriptions.referral_codes WHERE user_id = $1";
+    for _ in 0..CODE_ATTEMPTS {
+        let rows = conn.query(select, &[ParameterValue::Str(user_id.to_string())])
+            .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+        if let Some(row) = rows.rows.first() {
+            return crate::json_response(200, serde_json::json!({
+                "code": String::decode(&row[0]).unwrap_or_default(),
+                "created_at": String::decode(&row[1]).unwrap_or_default()
+            }));
+        }
+
+        // Loses quietly to a concurrent request or an existing code; either way, look again
+        let insert = "INSERT INTO subscriptions.referral_codes (user_id, code) VALUES ($1, $2) ON CONFLICT DO NOTHING";
+        conn.execute(insert, &[
+            ParameterValue::Str(user_id.to_string()),
+            ParameterValue::Str(generate_code()),
+        ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    }
+    Err(ServiceError::Internal("Could not allocate a referral code".into()))
+}
+
+/// POST /referrals/redeem - Record that the caller signed up through a code
+pub fn redeem(conn: &Connection, user_id: &Uuid, body: RedeemReferralRequest) -> Result<Response, ServiceError> {
+    let config = get_referral_config();
+    let code = body.code.trim().to_ascii_uppercase();
+
+    let rows = conn.query(
+        "SELECT user_id FROM subscriptions.referral_codes WHERE code = $1",
+        &[ParameterValue::Str(code.clone())],
+    ).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let referrer_id = rows.rows.first()
+        .and_then(|row| Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).ok())
+        .ok_or_else(|| ServiceError::NotFound("Referral code not found".into()))?;
+
+    if referrer_id == *user_id {
+        return Err(ServiceError::BadRequest("You cannot use your own referral code".into()));
+    }
+
+    let check = "SELECT
+                     (SELECT created_at > NOW() - make_interval(days => $3) FROM users.users WHERE id = $1),
+                     EXISTS (SELECT 1 FROM subscriptions.subscriptions WHERE user_id = $1 AND plan_id <> 'free'),
+                     EXISTS (SELECT 1 FROM subscriptions.referrals WHERE referrer_id = $1 AND referred_id = $2)";
+    let rows = conn.query(check, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(referrer_id.to_string()),
+        ParameterValue::Int32(config.signup_window_days),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first().ok_or_else(|| ServiceError::NotFound("User not found".into()))?;
+
+    if !bool::decode(&row[0]).unwrap_or(false) {
+        return Err(ServiceError::BadRequest(format!(
+            "Referral codes must be applied within {} days of signing up", config.signup_window_days
+        )));
+    }
+    if bool::decode(&row[1]).unwrap_or(false) {
+        return Err(ServiceError::Conflict("Referral codes only apply before upgrading to a paid plan".into()));
+    }
+    if bool::decode(&row[2]).unwrap_or(false) {
+        return Err(ServiceError::BadRequest("You cannot use the code of someone you referred".into()));
+    }
+
+    let referral_id = Uuid::new_v4();
+    let insert = "INSERT INTO subscriptions.referrals (id, referrer_id, referred_id, code)
+                  VALUES ($1, $2, $3, $4)
+                  ON CONFLICT (referred_id) DO NOTHING";
+    let inserted = conn.execute(insert, &[
+        ParameterValue::Str(referral_id.to_string()),
+        ParameterValue::Str(referrer_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(code.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    if inserted == 0 {
+        return Err(ServiceError::Conflict("A referral code has already been applied to this account".into()));
+    }
+
+    crate::json_response(201, serde_json::json!({
+        "id": referral_id,
+        "code": code,
+        "status": "signed_up",
+        "reward_credits": config.referred_credits
+    }))
+}
+
+/// GET /referrals/stats - The caller's code, referral counts and credits earned
+pub fn stats(conn: &Connection, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let summary = "SELECT
+                       (SELECT code FROM subscriptions.referral_codes WHERE user_id = $1),
+                       COUNT(*),
+                       COUNT(*) FILTER (WHERE status = 'signed_up'),
+                       COUNT(*) FILTER (WHERE status = 'rewarded'),
+                       COUNT(*) FILTER (WHERE status = 'rejected'),
+                       COALESCE(SUM(referrer_credits), 0)
+                   FROM subscriptions.referrals WHERE referrer_id = $1";
+    let rows = conn.query(summary, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first().ok_or_else(|| ServiceError::Internal("Empty summary".into()))?;
+
+    let recent_query = "SELECT id, status, rejection_reason, referrer_credits, created_at, converted_at
+                        FROM subscriptions.referrals WHERE referrer_id = $1
+                        ORDER BY created_at DESC LIMIT $2";
+    let recent_rows = conn.query(recent_query, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Int64(RECENT_REFERRALS),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    // Referred users stay anonymous to the referrer
+    let recent: Vec<serde_json::Value> = recent_rows.rows.iter().map(|r| serde_json::json!({
+        "id": String::decode(&r[0]).unwrap_or_default(),
+        "status": String::decode(&r[1]).unwrap_or_default(),
+        "rejection_reason": String::decode(&r[2]).ok(),
+        "credits_earned": i32::decode(&r[3]).unwrap_or(0),
+        "signed_up_at": String::decode(&r[4]).unwrap_or_default(),
+        "converted_at": String::decode(&r[5]).ok()
+    })).collect();
+
+    let config = get_referral_config();
+    crate::json_response(200, serde_json::json!({
+        "code": String::decode(&row[0]).ok(),
+        "signups": i64::decode(&row[1]).unwrap_or(0),
+        "pending": i64::decode(&row[2]).unwrap_or(0),
+        "converted": i64::decode(&row[3]).unwrap_or(0),
+        "rejected": i64::decode(&row[4]).unwrap_or(0),
+        "credits_earned": i64::decode(&row[5]).unwrap_or(0),
+        "reward_per_conversion": config.reward_credits,
+        "recent": recent
+    }))
+}
+
+//=============================================================================
+// Conversion
+//=============================================================================
+
+/// The unconverted referral of the user behind a Stripe customer, if any
+pub fn pending_conversion(conn: &Connection, customer_id: &str) -> Result<Option<PendingConversion>, ServiceError> {
+    let query = "SELECT r.id, r.referrer_id, r.referred_id,
+                        COALESCE(
+                            (SELECT stripe_customer_id FROM subscriptions.subscriptions WHERE user_id = r.referrer_id),
+                            (SELECT stripe_customer_id FROM subscriptions.billing_details WHERE user_id = r.referrer_id)
+                        )
+                 FROM subscriptions.referrals r
+                 JOIN subscriptions.subscriptions s ON s.user_id = r.referred_id
+                 WHERE s.stripe_customer_id = $1 AND r.status = 'signed_up'";
+    let rows = conn.query(query, &[ParameterValue::Str(customer_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let parse = |value: &spin_sdk::pg::DbValue| Uuid::parse_str(&String::decode(value).unwrap_or_default()).unwrap_or_default();
+    Ok(rows.rows.first().map(|row| PendingConversion {
+        referral_id: parse(&row[0]),
+        referrer_id: parse(&row[1]),
+        referred_id: parse(&row[2]),
+        referrer_customer_id: String::decode(&row[3]).ok(),
+    }))
+}
+
+/// Run the fraud checks for a paid conversion and grant the rewards if they
+/// pass. Safe to call again for the same referral: only the first call acts.
+pub fn complete_conversion(
+    conn: &Connection,
+    pending: &PendingConversion,
+    invoice_id: &str,
+    card_fingerprint: Option<&str>,
+    referrer_fingerprints: &[String],
+) -> Result<(), ServiceError> {
+    let config = get_referral_config();
+    let rejection = match card_fingerprint {
+        Some(fingerprint) if referrer_fingerprints.iter().any(|f| f == fingerprint) => Some("same_card_as_referrer"),
+        Some(fingerprint) if card_already_rewarded(conn, pending, fingerprint)? => Some("card_already_rewarded"),
+        _ => None,
+    };
+    let (referrer_credits, referred_credits) = match rejection {
+        Some(_) => (0, 0),
+        None => (config.reward_credits, config.referred_credits),
+    };
+
+    let claim = "UPDATE subscriptions.referrals
+                 SET status = $2, rejection_reason = $3, card_fingerprint = $4, stripe_invoice_id = $5,
+                     referrer_credits = $6, referred_credits = $7, converted_at = NOW()
+                 WHERE id = $1 AND status = 'signed_up'";
+    let claimed = conn.execute(claim, &[
+        ParameterValue::Str(pending.referral_id.to_string()),
+        ParameterValue::Str(if rejection.is_some() { "rejected" } else { "rewarded" }.to_string()),
+        rejection.map(|r| ParameterValue::Str(r.to_string())).unwrap_or(ParameterValue::DbNull),
+        card_fingerprint.map(|f| ParameterValue::Str(f.to_string())).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(invoice_id.to_string()),
+        ParameterValue::Int32(referrer_credits),
+        ParameterValue::Int32(referred_credits),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    if claimed == 0 {
+        return Ok(());
+    }
+
+    if referrer_credits > 0 {
+        credits::grant_credits(conn, &pending.referrer_id, referrer_credits, "referral_reward",
+            "Referral converted to a paid plan", Some(&pending.referral_id), Some("referral"))?;
+    }
+    if referred_credits > 0 {
+        credits::grant_credits(conn, &pending.referred_id, referred_credits, "referral_reward",
+            "Signed up with a referral code", Some(&pending.referral_id), Some("referral"))?;
+    }
+    Ok(())
+}
+
+fn card_already_rewarded(conn: &Connection, pending: &PendingConversion, fingerprint: &str) -> Result<bool, ServiceError> {
+    let query = "SELECT 1 FROM subscriptions.referrals
+                 WHERE referrer_id = $1 AND card_fingerprint = $2 AND status = 'rewarded' AND id <> $3";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(pending.referrer_id.to_string()),
+        ParameterValue::Str(fingerprint.to_string()),
+        ParameterValue::Str(pending.referral_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    Ok(!rows.rows.is_empty())
+}
+
+fn generate_code() -> String {
+    Uuid::new_v4().as_bytes()[..CODE_LENGTH].iter()
+        .map(|b| CODE_ALPHABET[(*b as usize) % CODE_ALPHABET.len()] as char)
+        .collect()
+}
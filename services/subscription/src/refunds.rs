@@ -0,0 +1,330 @@
+//! Refunds, Disputes and Failed One-Off Payments
+//!
+//! Stripe reports refunds and disputes against a charge, not an invoice or
+//! order, so invoices keep the payment intent they were paid with and credit
+//! orders keep the one that completed them.
+//!
+//! A refund records the refunded amount on the invoice, which becomes
+//! `refunded` once nothing is left, and takes back the credits a refunded
+//! payment granted: the same share of a credit order's credits as the share
+//! of its price refunded, and a referral reward when the invoice that earned
+//! it is fully refunded. Only credits that are still unspent can be taken;
+//! the balance never goes negative.
+//!
+//! A dispute marks the invoice or order `disputed`. Won disputes restore the
+//! previous state; lost ones become `charged_back` and take back credits like
+//! a full refund. Failed or cancelled payment intents fail their pending
+//! credit order. Users are notified of refunds, lost disputes and failed
+//! purchases.
+
+use crate::dunning::notify_user;
+use crate::error::ServiceError;
+use crate::payments::{Dispute, Refund};
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+//=============================================================================
+// Refunds
+//=============================================================================
+
+pub fn apply_refund(conn: &Connection, refund: &Refund) -> Result<(), ServiceError> {
+    let full = refund.amount > 0 && refund.amount_refunded >= refund.amount;
+
+    // Redeliveries and older partial refunds do not move the total forward
+    let update = "UPDATE subscriptions.invoices
+                  SET amount_refunded = $3, refunded_at = NOW(),
+                      status = CASE WHEN $4 THEN 'refunded' ELSE status END
+                  WHERE (stripe_invoice_id = $1 OR stripe_payment_intent_id = $2)
+                    AND amount_refunded < $3
+                  RETURNING stripe_invoice_id, stripe_customer_id";
+    let rows = conn.query(update, &[
+        optional(&refund.invoice_id),
+        optional(&refund.payment_intent_id),
+        ParameterValue::Int64(refund.amount_refunded),
+        ParameterValue::Boolean(full),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    for row in &rows.rows {
+        let invoice_id = String::decode(&row[0]).unwrap_or_default();
+        let customer_id = String::decode(&row[1]).unwrap_or_default();
+        if full {
+            revoke_referral_rewards(conn, &invoice_id, "Referral payment refunded")?;
+        }
+        if let Some(user_id) = user_for_customer(conn, &customer_id)? {
+            let amount = format_amount(refund.amount_refunded, refund.currency.as_deref());
+            notify_user(&user_id, "payment_refunded", "Refund issued",
+                &format!("{} has been refunded to your original payment method.", amount),
+                serde_json::json!({
+                    "invoice_id": invoice_id,
+                    "amount_refunded": refund.amount_refunded,
+                    "currency": refund.currency,
+                    "full_refund": full
+                }));
+        }
+    }
+
+    if let Some(payment_intent_id) = &refund.payment_intent_id {
+        let share = if refund.amount > 0 {
+            (refund.amount_refunded.min(refund.amount) as f64 / refund.amount as f64).min(1.0)
+        } else {
+            1.0
+        };
+        let status = if full { "refunded" } else { "completed" };
+        if let Some(revoked) = revoke_order_credits(conn, payment_intent_id, &["completed"], share, status)? {
+            let amount = format_amount(refund.amount_refunded, refund.currency.as_deref());
+            notify_user(&revoked.user_id, "credit_purchase_refunded", "Credit purchase refunded",
+                &format!("{} has been refunded and {} credits were removed from your balance.", amount, revoked.credits),
+                serde_json::json!({
+                    "order_id": revoked.order_id,
+                    "amount_refunded": refund.amount_refunded,
+                    "currency": refund.currency,
+                    "credits_removed": revoked.credits,
+                    "credits_already_used": revoked.unrecoverable
+                }));
+        }
+    }
+    Ok(())
+}
+
+//=============================================================================
+// Disputes
+//=============================================================================
+
+pub fn apply_dispute(conn: &Connection, dispute: &Dispute) -> Result<(), ServiceError> {
+    let Some(payment_intent_id) = dispute.payment_intent_id.as_deref() else {
+        return Ok(());
+    };
+
+    if !dispute.closed {
+        set_dispute_status(conn, payment_intent_id, &["paid"], "disputed", &["completed"], "disputed")?;
+        return Ok(());
+    }
+    if dispute.status != "lost" {
+        // Won, or closed by a warning; the payment stands
+        set_dispute_status(conn, payment_intent_id, &["disputed"], "paid", &["disputed"], "completed")?;
+        return Ok(());
+    }
+
+    let update = "UPDATE subscriptions.invoices SET status = 'charged_back'
+                  WHERE stripe_payment_intent_id = $1 AND status IN ('paid', 'disputed')
+                  RETURNING stripe_invoice_id, stripe_customer_id";
+    let rows = conn.query(update, &[ParameterValue::Str(payment_intent_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    for row in &rows.rows {
+        let invoice_id = String::decode(&row[0]).unwrap_or_default();
+        revoke_referral_rewards(conn, &invoice_id, "Referral payment charged back")?;
+        if let Some(user_id) = user_for_customer(conn, &String::decode(&row[1]).unwrap_or_default())? {
+            notify_user(&user_id, "payment_charged_back", "Payment charged back",
+                "Your bank reversed a subscription payment. Please update your payment method to keep your plan.",
+                serde_json::json!({ "invoice_id": invoice_id, "dispute_id": dispute.dispute_id }));
+        }
+    }
+
+    if let Some(revoked) = revoke_order_credits(conn, payment_intent_id, &["completed", "disputed"], 1.0, "charged_back")? {
+        notify_user(&revoked.user_id, "credit_purchase_charged_back", "Credit purchase charged back",
+            &format!("Your bank reversed a credit purchase, so {} credits were removed from your balance.", revoked.credits),
+            serde_json::json!({
+                "order_id": revoked.order_id,
+                "dispute_id": dispute.dispute_id,
+                "credits_removed": revoked.credits,
+                "credits_already_used": revoked.unrecoverable
+            }));
+    }
+    Ok(())
+}
+
+fn set_dispute_status(
+    conn: &Connection,
+    payment_intent_id: &str,
+    invoice_from: &[&str],
+    invoice_to: &str,
+    order_from: &[&str],
+    order_to: &str,
+) -> Result<(), ServiceError> {
+    conn.execute(
+        "UPDATE subscriptions.invoices SET status = $3
+         WHERE stripe_payment_intent_id = $1 AND status = ANY(string_to_array($2, ','))",
+        &[
+            ParameterValue::Str(payment_intent_id.to_string()),
+            ParameterValue::Str(invoice_from.join(",")),
+            ParameterValue::Str(invoice_to.to_string()),
+        ],
+    ).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    conn.execute(
+        "UPDATE subscriptions.credit_orders SET status = $3, updated_at = NOW()
+         WHERE stripe_payment_intent_id = $1 AND status = ANY(string_to_array($2, ','))",
+        &[
+            ParameterValue::Str(payment_intent_id.to_string()),
+            ParameterValue::Str(order_from.join(",")),
+            ParameterValue::Str(order_to.to_string()),
+        ],
+    ).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+//=============================================================================
+// Failed Credit Payments
+//=============================================================================
+
+/// Fail the pending credit order a payment intent was paying for
+pub fn fail_credit_order(
+    conn: &Connection,
+    payment_intent_id: &str,
+    order_id: Option<&str>,
+    reason: Option<&str>,
+) -> Result<(), ServiceError> {
+    let update = "UPDATE subscriptions.credit_orders
+                  SET status = 'failed', stripe_payment_intent_id = $1, updated_at = NOW(),
+                      metadata = COALESCE(metadata, '{}'::jsonb) || jsonb_build_object('failure_reason', $3::text)
+                  WHERE status = 'pending' AND (stripe_payment_intent_id = $1 OR id::text = $2)
+                  RETURNING id::text, user_id::text, credit_amount + bonus_credits";
+    let rows = conn.query(update, &[
+        ParameterValue::Str(payment_intent_id.to_string()),
+        order_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+        reason.map(|r| ParameterValue::Str(r.to_string())).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    for row in &rows.rows {
+        let Ok(user_id) = Uuid::parse_str(&String::decode(&row[1]).unwrap_or_default()) else {
+            continue;
+        };
+        let credits = i32::decode(&row[2]).unwrap_or(0);
+        let body = match reason {
+            Some(reason) => format!("Your purchase of {} credits didn't go through: {}", credits, reason),
+            None => format!("Your purchase of {} credits didn't go through. You have not been charged.", credits),
+        };
+        notify_user(&user_id, "credit_purchase_failed", "Credit purchase failed", &body,
+            serde_json::json!({
+                "order_id": String::decode(&row[0]).unwrap_or_default(),
+                "credits": credits,
+                "reason": reason
+            }));
+    }
+    Ok(())
+}
+
+//=============================================================================
+// Credit Reversal
+//=============================================================================
+
+struct RevokedCredits {
+    order_id: String,
+    user_id: Uuid,
+    /// Credits taken back by this call
+    credits: i32,
+    /// Credits owed back that had already been spent
+    unrecoverable: i32,
+}
+
+/// Take back `share` of a credit order's credits, less what earlier refunds
+/// already took, and move the order to `status`
+fn revoke_order_credits(
+    conn: &Connection,
+    payment_intent_id: &str,
+    from_statuses: &[&str],
+    share: f64,
+    status: &str,
+) -> Result<Option<RevokedCredits>, ServiceError> {
+    let query = "SELECT id::text, user_id::text, credit_amount + bonus_credits, credits_revoked
+                 FROM subscriptions.credit_orders
+                 WHERE stripe_payment_intent_id = $1 AND status = ANY(string_to_array($2, ','))
+                 FOR UPDATE";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(payment_intent_id.to_string()),
+        ParameterValue::Str(from_statuses.join(",")),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let Some(row) = rows.rows.first() else {
+        return Ok(None);
+    };
+    let order_id = String::decode(&row[0]).unwrap_or_default();
+    let user_id = Uuid::parse_str(&String::decode(&row[1]).unwrap_or_default())
+        .map_err(|_| ServiceError::Internal("Invalid order user".into()))?;
+    let total = i32::decode(&row[2]).unwrap_or(0);
+    let already = i32::decode(&row[3]).unwrap_or(0);
+
+    let owed = ((total as f64 * share).round() as i32).clamp(0, total) - already;
+    if owed <= 0 {
+        return Ok(None);
+    }
+    let credits = revoke_credits(conn, &order_id, "order", owed, "Credit purchase refunded")?;
+
+    // Spent credits are written off rather than chased; they count as owed
+    conn.execute(
+        "UPDATE subscriptions.credit_orders
+         SET credits_revoked = credits_revoked + $2, status = $3, updated_at = NOW(),
+             refunded_at = CASE WHEN $3 IN ('refunded', 'charged_back') THEN NOW() ELSE refunded_at END
+         WHERE id = $1::uuid",
+        &[
+            ParameterValue::Str(order_id.clone()),
+            ParameterValue::Int32(owed),
+            ParameterValue::Str(status.to_string()),
+        ],
+    ).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    Ok(Some(RevokedCredits { order_id, user_id, credits, unrecoverable: owed - credits }))
+}
+
+/// Reverse the rewards of the referral an invoice converted, if any
+fn revoke_referral_rewards(conn: &Connection, invoice_id: &str, reason: &str) -> Result<(), ServiceError> {
+    let claim = "UPDATE subscriptions.referrals SET status = 'reversed'
+                 WHERE stripe_invoice_id = $1 AND status = 'rewarded'
+                 RETURNING id::text, referrer_credits + referred_credits";
+    let rows = conn.query(claim, &[ParameterValue::Str(invoice_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    for row in &rows.rows {
+        let referral_id = String::decode(&row[0]).unwrap_or_default();
+        let credits = i32::decode(&row[1]).unwrap_or(0);
+        if credits > 0 {
+            revoke_credits(conn, &referral_id, "referral", credits, reason)?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove up to `amount` unspent credits granted with `reference_id`.
+/// Returns the number removed.
+fn revoke_credits(
+    conn: &Connection,
+    reference_id: &str,
+    reference_type: &str,
+    amount: i32,
+    reason: &str,
+) -> Result<i32, ServiceError> {
+    let rows = conn.query(
+        "SELECT subscriptions.revoke_credits($1::uuid, $2, $3, $4)",
+        &[
+            ParameterValue::Str(reference_id.to_string()),
+            ParameterValue::Str(reference_type.to_string()),
+            ParameterValue::Int32(amount),
+            ParameterValue::Str(reason.to_string()),
+        ],
+    ).map_err(|e| ServiceError::Internal(format!("Failed to revoke credits: {}", e)))?;
+    Ok(rows.rows.first().map(|row| i32::decode(&row[0]).unwrap_or(0)).unwrap_or(0))
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+fn user_for_customer(conn: &Connection, customer_id: &str) -> Result<Option<Uuid>, ServiceError> {
+    let query = "SELECT user_id::text FROM subscriptions.subscriptions WHERE stripe_customer_id = $1 LIMIT 1";
+    let rows = conn.query(query, &[ParameterValue::Str(customer_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    Ok(rows.rows.first()
+        .and_then(|row| String::decode(&row[0]).ok())
+        .and_then(|id| Uuid::parse_str(&id).ok()))
+}
+
+fn optional(value: &Option<String>) -> ParameterValue {
+    value.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull)
+}
+
+fn format_amount(minor: i64, currency: Option<&str>) -> String {
+    let major = format!("{}.{:02}", minor / 100, minor % 100);
+    match currency {
+        Some(code) => format!("{} {}", major, code.to_uppercase()),
+        None => major,
+    }
+}
@@ -0,0 +1,15 @@
+//! Subscription routes open to delegated credentials
+//!
+//! Billing is never delegated. The plan catalogue is public, and provider
+//! webhooks carry their own signatures.
+
+use authorworks_access::Route;
+use spin_sdk::http::Method;
+
+pub fn route_scope(method: &Method, path: &str) -> Option<Route> {
+    match method {
+        Method::Get if path == "/plans" => Some(Route::Open),
+        Method::Post if path.starts_with("/webhooks/") => Some(Route::Open),
+        _ => None,
+    }
+}
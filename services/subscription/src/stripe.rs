@@ -1,4 +1,203 @@
-//! Stripe API utilities
+//! Stripe payment provider
 //!
-//! Core Stripe logic is implemented in lib.rs
+//! Wraps the Stripe API helpers in lib.rs behind `PaymentProvider` and
+//! normalizes Stripe webhook events.
 
+use crate::error::ServiceError;
+use crate::models::{CheckoutSession, PortalSession, ProviderSubscription, StripeConfig, StripeEvent};
+use crate::payments::{Dispute, PaymentEvent, PaymentProvider, PaymentReceipt, Refund, SubscriptionChange, WebhookEvent};
+use crate::tax;
+use chrono::{DateTime, Utc};
+use spin_sdk::http::Request;
+use uuid::Uuid;
+
+pub const PROVIDER: &str = "stripe";
+
+pub struct StripeProvider {
+    config: StripeConfig,
+}
+
+impl StripeProvider {
+    pub fn new(config: StripeConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl PaymentProvider for StripeProvider {
+    fn name(&self) -> &'static str {
+        PROVIDER
+    }
+
+    fn create_customer(&self, email: &str, user_id: &Uuid) -> Result<String, ServiceError> {
+        crate::create_stripe_customer(&self.config, email, user_id)
+    }
+
+    fn create_subscription(&self, customer_id: &str, price_id: &str) -> Result<ProviderSubscription, ServiceError> {
+        crate::create_stripe_subscription(&self.config, customer_id, price_id)
+    }
+
+    fn change_plan(&self, subscription_id: &str, price_id: &str, invoice_now: bool) -> Result<(), ServiceError> {
+        crate::update_stripe_subscription(&self.config, subscription_id, price_id, invoice_now)
+    }
+
+    fn cancel_at_period_end(&self, subscription_id: &str) -> Result<(), ServiceError> {
+        crate::cancel_stripe_subscription(&self.config, subscription_id)
+    }
+
+    fn cancel_now(&self, subscription_id: &str) -> Result<(), ServiceError> {
+        crate::cancel_stripe_subscription_now(&self.config, subscription_id)
+    }
+
+    fn pause(&self, subscription_id: &str, resumes_at: DateTime<Utc>) -> Result<(), ServiceError> {
+        crate::pause_stripe_subscription(&self.config, subscription_id, resumes_at.timestamp())
+    }
+
+    fn resume(&self, subscription_id: &str) -> Result<(), ServiceError> {
+        crate::resume_stripe_subscription(&self.config, subscription_id)
+    }
+
+    fn create_checkout(
+        &self,
+        customer_id: &str,
+        _user_id: &Uuid,
+        price_id: &str,
+        success_url: &str,
+        cancel_url: &str,
+    ) -> Result<CheckoutSession, ServiceError> {
+        crate::create_stripe_checkout_session(&self.config, customer_id, price_id, success_url, cancel_url)
+    }
+
+    fn create_portal(&self, customer_id: &str, return_url: &str) -> Result<PortalSession, ServiceError> {
+        crate::create_stripe_portal_session(&self.config, customer_id, return_url)
+    }
+
+    fn parse_webhook(&self, req: &Request) -> Result<WebhookEvent, ServiceError> {
+        let signature = req.header("Stripe-Signature")
+            .and_then(|h| h.as_str())
+            .ok_or_else(|| ServiceError::BadRequest("Missing signature".into()))?;
+
+        crate::verify_stripe_signature(req.body(), signature, &self.config.webhook_secret)?;
+
+        let event: StripeEvent = serde_json::from_slice(req.body())
+            .map_err(|e| ServiceError::BadRequest(format!("Invalid event: {}", e)))?;
+
+        Ok(normalize(event))
+    }
+}
+
+fn normalize(event: StripeEvent) -> WebhookEvent {
+    let object = event.data.object;
+    let field = |name: &str| object.get(name).and_then(|v| v.as_str()).map(|s| s.to_string());
+    let timestamp = |name: &str| object.get(name)
+        .and_then(|v| v.as_i64())
+        .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
+        .map(|dt| dt.to_rfc3339());
+
+    let object_id = field("id");
+    let customer_id = field("customer");
+    // Subscription events carry their own id; invoices reference theirs
+    let subscription_id = if event.event_type.starts_with("customer.subscription.") {
+        object_id.clone()
+    } else {
+        field("subscription")
+    };
+
+    let kind = match event.event_type.as_str() {
+        "customer.subscription.created" | "customer.subscription.updated" => {
+            // Paused collection leaves Stripe's status `active`
+            let pause = object.get("pause_collection").filter(|p| !p.is_null());
+            let status = field("status").unwrap_or_else(|| "active".into());
+            PaymentEvent::SubscriptionChanged(SubscriptionChange {
+                subscription_id: object_id.clone().unwrap_or_default(),
+                customer_id: customer_id.clone(),
+                user_id: None,
+                price_id: None,
+                status: if pause.is_some() && status == "active" { crate::pause::STATUS.into() } else { status },
+                current_period_start: timestamp("current_period_start"),
+                current_period_end: timestamp("current_period_end"),
+                cancel_at_period_end: object.get("cancel_at_period_end").and_then(|v| v.as_bool()).unwrap_or(false),
+                pause_resumes_at: pause
+                    .and_then(|p| p.get("resumes_at"))
+                    .and_then(|v| v.as_i64())
+                    .and_then(|ts| DateTime::from_timestamp(ts, 0))
+                    .map(|dt| dt.to_rfc3339()),
+            })
+        }
+        "customer.subscription.deleted" => PaymentEvent::SubscriptionEnded {
+            subscription_id: object_id.clone().unwrap_or_default(),
+        },
+        "invoice.paid" => PaymentEvent::PaymentSucceeded(PaymentReceipt {
+            customer_id: customer_id.clone().unwrap_or_default(),
+            invoice_id: object_id.clone().unwrap_or_default(),
+            amount: object.get("amount_paid").and_then(|v| v.as_i64()).unwrap_or(0),
+            tax: tax::invoice_tax(&object),
+            payment_intent_id: field("payment_intent"),
+        }),
+        "invoice.payment_failed" => PaymentEvent::PaymentFailed {
+            subscription_id: subscription_id.clone().unwrap_or_default(),
+            invoice_id: object_id.clone().unwrap_or_default(),
+        },
+        "customer.tax_id.updated" => PaymentEvent::TaxIdVerified {
+            tax_id: object_id.clone().unwrap_or_default(),
+            status: object.get("verification")
+                .and_then(|v| v.get("status"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("unavailable")
+                .to_string(),
+        },
+        "charge.refunded" => PaymentEvent::PaymentRefunded(Refund {
+            customer_id: customer_id.clone(),
+            invoice_id: field("invoice"),
+            payment_intent_id: field("payment_intent"),
+            currency: field("currency"),
+            amount: object.get("amount").and_then(|v| v.as_i64()).unwrap_or(0),
+            amount_refunded: object.get("amount_refunded").and_then(|v| v.as_i64()).unwrap_or(0),
+        }),
+        "charge.dispute.created" | "charge.dispute.closed" => PaymentEvent::PaymentDisputed(Dispute {
+            dispute_id: object_id.clone().unwrap_or_default(),
+            payment_intent_id: field("payment_intent"),
+            amount: object.get("amount").and_then(|v| v.as_i64()).unwrap_or(0),
+            reason: field("reason"),
+            status: field("status").unwrap_or_default(),
+            closed: event.event_type == "charge.dispute.closed",
+        }),
+        // Subscription payments fail through invoice.payment_failed; these
+        // only matter for one-off purchases
+        "payment_intent.payment_failed" | "payment_intent.canceled" if field("invoice").is_none() => {
+            PaymentEvent::OneTimePaymentFailed {
+                payment_intent_id: object_id.clone().unwrap_or_default(),
+                order_id: object.get("metadata")
+                    .and_then(|m| m.get("order_id"))
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string()),
+                reason: object.get("last_payment_error")
+                    .and_then(|e| e.get("message"))
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string())
+                    .or_else(|| field("cancellation_reason")),
+            }
+        }
+        _ => PaymentEvent::Other,
+    };
+
+    let details = serde_json::json!({
+        "object_id": object_id,
+        "status": field("status"),
+        "amount_paid": object.get("amount_paid").and_then(|v| v.as_i64()),
+        "amount_due": object.get("amount_due").and_then(|v| v.as_i64()),
+        "amount_refunded": object.get("amount_refunded").and_then(|v| v.as_i64()),
+        "currency": field("currency"),
+        "cancel_at_period_end": object.get("cancel_at_period_end").and_then(|v| v.as_bool()),
+        "billing_reason": field("billing_reason")
+    });
+
+    WebhookEvent {
+        id: event.id,
+        provider_type: event.event_type,
+        subscription_id,
+        customer_id,
+        details,
+        object,
+        kind,
+    }
+}
@@ -0,0 +1,290 @@
+//! Tax (VAT/GST) Module
+//!
+//! Stripe Tax computes tax on checkout sessions, subscriptions and invoices
+//! from the customer's billing address and tax ID. Users maintain those via
+//! `PUT /billing/details`, which is pushed to the Stripe customer and kept in
+//! `subscriptions.billing_details`; checkout can also collect them. Paid
+//! invoices are recorded with their subtotal, tax and per-rate breakdown.
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::Utc;
+use uuid::Uuid;
+
+/// Stripe tax ID types accepted for VAT/GST registration
+const SUPPORTED_TAX_ID_TYPES: &[&str] = &[
+    "eu_vat", "gb_vat", "ch_vat", "no_vat", "is_vat", "au_abn", "au_arn", "nz_gst",
+    "ca_bn", "ca_gst_hst", "ca_qst", "in_gst", "sg_gst", "za_vat", "ae_trn", "sa_vat",
+    "jp_cn", "kr_brn", "my_sst", "th_vat", "tr_tin", "us_ein",
+];
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct BillingDetailsRequest {
+    pub name: Option<String>,
+    pub address: BillingAddress,
+    /// Omit or send null to remove a previously registered tax ID
+    pub tax_id: Option<TaxIdInput>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BillingAddress {
+    pub line1: String,
+    pub line2: Option<String>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub postal_code: Option<String>,
+    /// ISO 3166-1 alpha-2
+    pub country: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaxIdInput {
+    #[serde(rename = "type")]
+    pub id_type: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct BillingDetails {
+    pub stripe_customer_id: String,
+    pub name: Option<String>,
+    pub address: BillingAddress,
+    pub tax_id: Option<TaxIdInput>,
+    pub stripe_tax_id: Option<String>,
+    pub tax_id_verification: Option<String>,
+    pub updated_at: String,
+}
+
+/// Tax amounts on a paid invoice, all in the smallest currency unit
+#[derive(Debug, Clone)]
+pub struct InvoiceTax {
+    pub subtotal: Option<i64>,
+    pub tax: Option<i64>,
+    pub total: Option<i64>,
+    pub currency: Option<String>,
+    pub breakdown: serde_json::Value,
+    pub customer_tax_ids: serde_json::Value,
+}
+
+//=============================================================================
+// Validation
+//=============================================================================
+
+/// Trim and normalize the request, rejecting incomplete addresses and
+/// unsupported tax ID types before anything is sent to Stripe
+pub fn validate(mut body: BillingDetailsRequest) -> Result<BillingDetailsRequest, ServiceError> {
+    let trim = |value: Option<String>| value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+
+    body.name = trim(body.name);
+    body.address.line1 = body.address.line1.trim().to_string();
+    body.address.line2 = trim(body.address.line2);
+    body.address.city = trim(body.address.city);
+    body.address.state = trim(body.address.state);
+    body.address.postal_code = trim(body.address.postal_code);
+    body.address.country = body.address.country.trim().to_ascii_uppercase();
+
+    if body.address.line1.is_empty() {
+        return Err(ServiceError::BadRequest("address.line1 is required".into()));
+    }
+    if body.address.country.len() != 2 || !body.address.country.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(ServiceError::BadRequest("address.country must be a two-letter ISO country code".into()));
+    }
+
+    if let Some(ref mut tax_id) = body.tax_id {
+        tax_id.id_type = tax_id.id_type.trim().to_ascii_lowercase();
+        tax_id.value = tax_id.value.trim().to_string();
+        if !SUPPORTED_TAX_ID_TYPES.contains(&tax_id.id_type.as_str()) {
+            return Err(ServiceError::BadRequest(format!(
+                "Unsupported tax ID type '{}'; expected one of {}",
+                tax_id.id_type,
+                SUPPORTED_TAX_ID_TYPES.join(", ")
+            )));
+        }
+        if tax_id.value.is_empty() || tax_id.value.len() > 64 {
+            return Err(ServiceError::BadRequest("tax_id.value must be 1-64 characters".into()));
+        }
+    }
+
+    Ok(body)
+}
+
+//=============================================================================
+// Stripe Parameters
+//=============================================================================
+
+/// Form fields that replace the Stripe customer's name and address; empty
+/// values clear fields the user removed
+pub fn customer_form(body: &BillingDetailsRequest) -> String {
+    let address = &body.address;
+    let fields = [
+        ("name", body.name.as_deref()),
+        ("address[line1]", Some(address.line1.as_str())),
+        ("address[line2]", address.line2.as_deref()),
+        ("address[city]", address.city.as_deref()),
+        ("address[state]", address.state.as_deref()),
+        ("address[postal_code]", address.postal_code.as_deref()),
+        ("address[country]", Some(address.country.as_str())),
+    ];
+    fields.iter()
+        .map(|(key, value)| format!("{}={}", key, crate::urlencoded(value.unwrap_or_default())))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Checkout fields that enable Stripe Tax and collect whatever billing
+/// details the customer has not already provided
+pub fn checkout_params() -> &'static str {
+    "automatic_tax[enabled]=true\
+     &billing_address_collection=required\
+     &tax_id_collection[enabled]=true\
+     &customer_update[address]=auto\
+     &customer_update[name]=auto"
+}
+
+//=============================================================================
+// Persistence
+//=============================================================================
+
+pub fn get_details(conn: &Connection, user_id: &Uuid) -> Result<Option<BillingDetails>, ServiceError> {
+    let query = "SELECT stripe_customer_id, name, address_line1, address_line2, city, state, postal_code, country,
+                        tax_id_type, tax_id_value, stripe_tax_id, tax_id_verification, updated_at
+                 FROM subscriptions.billing_details WHERE user_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.first().map(|row| {
+        let tax_id = match (String::decode(&row[8]), String::decode(&row[9])) {
+            (Ok(id_type), Ok(value)) => Some(TaxIdInput { id_type, value }),
+            _ => None,
+        };
+        BillingDetails {
+            stripe_customer_id: String::decode(&row[0]).unwrap_or_default(),
+            name: String::decode(&row[1]).ok(),
+            address: BillingAddress {
+                line1: String::decode(&row[2]).unwrap_or_default(),
+                line2: String::decode(&row[3]).ok(),
+                city: String::decode(&row[4]).ok(),
+                state: String::decode(&row[5]).ok(),
+                postal_code: String::decode(&row[6]).ok(),
+                country: String::decode(&row[7]).unwrap_or_default(),
+            },
+            tax_id,
+            stripe_tax_id: String::decode(&row[10]).ok(),
+            tax_id_verification: String::decode(&row[11]).ok(),
+            updated_at: String::decode(&row[12]).unwrap_or_default(),
+        }
+    }))
+}
+
+/// Store what was pushed to Stripe. `stripe_tax_id` is the customer's tax
+/// ID object, if any, with its current verification status.
+pub fn save_details(
+    conn: &Connection,
+    user_id: &Uuid,
+    customer_id: &str,
+    body: &BillingDetailsRequest,
+    stripe_tax_id: Option<(&str, &str)>,
+) -> Result<BillingDetails, ServiceError> {
+    let now = Utc::now().to_rfc3339();
+    let opt = |value: &Option<String>| value.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull);
+
+    let upsert = "INSERT INTO subscriptions.billing_details
+                  (user_id, stripe_customer_id, name, address_line1, address_line2, city, state, postal_code, country,
+                   tax_id_type, tax_id_value, stripe_tax_id, tax_id_verification, created_at, updated_at)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $14)
+                  ON CONFLICT (user_id) DO UPDATE SET
+                  stripe_customer_id = $2, name = $3, address_line1 = $4, address_line2 = $5, city = $6,
+                  state = $7, postal_code = $8, country = $9, tax_id_type = $10, tax_id_value = $11,
+                  stripe_tax_id = $12, tax_id_verification = $13, updated_at = $14";
+    conn.execute(upsert, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(customer_id.to_string()),
+        opt(&body.name),
+        ParameterValue::Str(body.address.line1.clone()),
+        opt(&body.address.line2),
+        opt(&body.address.city),
+        opt(&body.address.state),
+        opt(&body.address.postal_code),
+        ParameterValue::Str(body.address.country.clone()),
+        opt(&body.tax_id.as_ref().map(|t| t.id_type.clone())),
+        opt(&body.tax_id.as_ref().map(|t| t.value.clone())),
+        opt(&stripe_tax_id.map(|(id, _)| id.to_string())),
+        opt(&stripe_tax_id.map(|(_, status)| status.to_string())),
+        ParameterValue::Str(now.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Upsert failed: {}", e)))?;
+
+    Ok(BillingDetails {
+        stripe_customer_id: customer_id.to_string(),
+        name: body.name.clone(),
+        address: body.address.clone(),
+        tax_id: body.tax_id.clone(),
+        stripe_tax_id: stripe_tax_id.map(|(id, _)| id.to_string()),
+        tax_id_verification: stripe_tax_id.map(|(_, status)| status.to_string()),
+        updated_at: now,
+    })
+}
+
+/// Track Stripe's asynchronous tax ID verification (`customer.tax_id.updated`)
+pub fn update_verification(conn: &Connection, stripe_tax_id: &str, status: &str) -> Result<(), ServiceError> {
+    let update = "UPDATE subscriptions.billing_details SET tax_id_verification = $2, updated_at = NOW()
+                  WHERE stripe_tax_id = $1";
+    conn.execute(update, &[
+        ParameterValue::Str(stripe_tax_id.to_string()),
+        ParameterValue::Str(status.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+    Ok(())
+}
+
+pub fn details_json(details: &BillingDetails) -> serde_json::Value {
+    serde_json::json!({
+        "name": details.name,
+        "address": details.address,
+        "tax_id": details.tax_id.as_ref().map(|tax_id| serde_json::json!({
+            "type": tax_id.id_type,
+            "value": tax_id.value,
+            "verification_status": details.tax_id_verification
+        })),
+        "updated_at": details.updated_at
+    })
+}
+
+//=============================================================================
+// Invoices
+//=============================================================================
+
+/// Extract tax amounts from an invoice object. Rates are referenced by their
+/// Stripe tax rate ID alongside the taxable amount and taxability reason.
+pub fn invoice_tax(invoice: &serde_json::Value) -> InvoiceTax {
+    let breakdown: Vec<serde_json::Value> = invoice.get("total_tax_amounts")
+        .and_then(|v| v.as_array())
+        .map(|amounts| amounts.iter().map(|amount| serde_json::json!({
+            "amount": amount.get("amount").and_then(|v| v.as_i64()).unwrap_or(0),
+            "inclusive": amount.get("inclusive").and_then(|v| v.as_bool()).unwrap_or(false),
+            "tax_rate": amount.get("tax_rate").and_then(|v| v.as_str()),
+            "taxable_amount": amount.get("taxable_amount").and_then(|v| v.as_i64()),
+            "taxability_reason": amount.get("taxability_reason").and_then(|v| v.as_str())
+        })).collect())
+        .unwrap_or_default();
+
+    let customer_tax_ids: Vec<serde_json::Value> = invoice.get("customer_tax_ids")
+        .and_then(|v| v.as_array())
+        .map(|ids| ids.iter().map(|id| serde_json::json!({
+            "type": id.get("type").and_then(|v| v.as_str()),
+            "value": id.get("value").and_then(|v| v.as_str())
+        })).collect())
+        .unwrap_or_default();
+
+    InvoiceTax {
+        subtotal: invoice.get("subtotal").and_then(|v| v.as_i64()),
+        tax: invoice.get("tax").and_then(|v| v.as_i64()),
+        total: invoice.get("total").and_then(|v| v.as_i64()),
+        currency: invoice.get("currency").and_then(|v| v.as_str()).map(|c| c.to_string()),
+        breakdown: serde_json::Value::Array(breakdown),
+        customer_tax_ids: serde_json::Value::Array(customer_tax_ids),
+    }
+}
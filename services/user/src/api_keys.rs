@@ -0,0 +1,144 @@
+//! Scoped API keys
+//!
+//! Keys are shown once at creation and stored as a SHA-256 hash. The short
+//! prefix is kept in clear so users can tell their keys apart.
+
+use crate::error::ServiceError;
+use crate::scopes::{self, Scope};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+pub const KEY_PREFIX: &str = "awk_";
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+}
+
+/// POST /api-keys - Create a key; the secret is only returned here
+pub fn create_key(conn: &Connection, user_id: &Uuid, body: CreateApiKeyRequest) -> Result<Response, ServiceError> {
+    if body.name.trim().is_empty() {
+        return Err(ServiceError::BadRequest("name is required".into()));
+    }
+    let scopes = scopes::parse_scopes(&body.scopes)?;
+    if body.expires_in_days.map(|d| d <= 0).unwrap_or(false) {
+        return Err(ServiceError::BadRequest("expires_in_days must be positive".into()));
+    }
+
+    let id = Uuid::new_v4();
+    let secret = format!("{}{}{}", KEY_PREFIX, Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let prefix = secret[..KEY_PREFIX.len() + 8].to_string();
+    let now = Utc::now();
+    let expires_at = body.expires_in_days.map(|days| (now + Duration::days(days)).to_rfc3339());
+
+    let insert = "INSERT INTO users.api_keys (id, user_id, name, prefix, key_hash, scopes, expires_at, created_at)
+                  VALUES ($1, $2, $3, $4, $5, $6, $7, $8)";
+    conn.execute(insert, &[
+        ParameterValue::Str(id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(body.name.clone()),
+        ParameterValue::Str(prefix.clone()),
+        ParameterValue::Str(hash_key(&secret)),
+        ParameterValue::Str(serde_json::to_string(&scopes).unwrap_or_else(|_| "[]".into())),
+        expires_at.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Str(now.to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    crate::json_response(201, serde_json::json!({
+        "api_key": ApiKey {
+            id,
+            name: body.name,
+            prefix,
+            scopes,
+            created_at: now.to_rfc3339(),
+            expires_at,
+            last_used_at: None,
+        },
+        "secret": secret
+    }))
+}
+
+/// GET /api-keys - List the caller's active keys
+pub fn list_keys(conn: &Connection, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT id, name, prefix, scopes, created_at, expires_at, last_used_at
+                 FROM users.api_keys
+                 WHERE user_id = $1 AND revoked_at IS NULL
+                 ORDER BY created_at DESC";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let keys: Vec<ApiKey> = rows.rows.iter().map(|row| ApiKey {
+        id: Uuid::parse_str(&String::decode(&row[0]).unwrap_or_default()).unwrap_or_default(),
+        name: String::decode(&row[1]).unwrap_or_default(),
+        prefix: String::decode(&row[2]).unwrap_or_default(),
+        scopes: serde_json::from_str(&String::decode(&row[3]).unwrap_or_else(|_| "[]".into())).unwrap_or_default(),
+        created_at: String::decode(&row[4]).unwrap_or_default(),
+        expires_at: String::decode(&row[5]).ok(),
+        last_used_at: String::decode(&row[6]).ok(),
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({ "api_keys": keys }))
+}
+
+/// DELETE /api-keys/:id - Revoke a key
+pub fn revoke_key(conn: &Connection, user_id: &Uuid, key_id: &Uuid) -> Result<Response, ServiceError> {
+    let update = "UPDATE users.api_keys SET revoked_at = $3
+                  WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL";
+    let count = conn.execute(update, &[
+        ParameterValue::Str(key_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+
+    if count == 0 {
+        return Err(ServiceError::NotFound("API key not found".into()));
+    }
+
+    crate::json_response(200, serde_json::json!({ "revoked": true }))
+}
+
+/// Resolve a presented key to its owner and scopes
+pub fn resolve_key(conn: &Connection, secret: &str) -> Result<(Uuid, Vec<Scope>), ServiceError> {
+    let query = "SELECT id, user_id, scopes FROM users.api_keys
+                 WHERE key_hash = $1 AND revoked_at IS NULL
+                   AND (expires_at IS NULL OR expires_at > NOW())";
+    let rows = conn.query(query, &[ParameterValue::Str(hash_key(secret))])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::Unauthorized("Invalid API key".into()))?;
+    let key_id = String::decode(&row[0]).unwrap_or_default();
+    let user_id = Uuid::parse_str(&String::decode(&row[1]).unwrap_or_default())
+        .map_err(|_| ServiceError::Internal("Invalid user ID".into()))?;
+    let scopes: Vec<Scope> = serde_json::from_str(&String::decode(&row[2]).unwrap_or_else(|_| "[]".into()))
+        .unwrap_or_default();
+
+    let touch = "UPDATE users.api_keys SET last_used_at = $2 WHERE id = $1";
+    conn.execute(touch, &[
+        ParameterValue::Str(key_id),
+        ParameterValue::Str(Utc::now().to_rfc3339()),
+    ]).ok();
+
+    Ok((user_id, scopes))
+}
+
+fn hash_key(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
@@ -4,6 +4,7 @@
 
 use crate::models::{User, AuthTokens};
 use crate::error::ServiceError;
+use crate::scopes::Scope;
 use jsonwebtoken::{encode, decode, Header, Algorithm, Validation, EncodingKey, DecodingKey};
 use serde::{Deserialize, Serialize};
 use chrono::{Utc, Duration};
@@ -13,6 +14,11 @@ use spin_sdk::variables;
 
 type HmacSha256 = Hmac<Sha256>;
 
+/// Audience of first-party session tokens
+const SESSION_AUDIENCE: &str = "authorworks-api";
+/// Audience of share tokens, so no endpoint that validates sessions takes one
+const SHARE_AUDIENCE: &str = "authorworks-share";
+
 //=============================================================================
 // JWT Claims
 //=============================================================================
@@ -28,6 +34,8 @@ pub struct Claims {
     pub aud: String,           // Audience
     #[serde(skip_serializing_if = "Option::is_none")]
     pub auth_provider: Option<String>, // Auth provider (local, logto, etc.)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<Scope>>,    // Restricted scopes (share tokens); None = full access
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -70,8 +78,35 @@ pub fn generate_access_token(user_id: &str, roles: &[String]) -> Result<String,
         exp: expiry.timestamp() as usize,
         iat: now.timestamp() as usize,
         iss: "authorworks".to_string(),
-        aud: "authorworks-api".to_string(),
+        aud: SESSION_AUDIENCE.to_string(),
+        auth_provider: None,
+        scopes: None,
+    };
+    
+    encode(
+        &Header::new(Algorithm::HS256),
+        &claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )
+    .map_err(|e| ServiceError::Internal(format!("Token generation failed: {}", e)))
+}
+
+/// Generate a share token limited to `scopes`
+pub fn generate_scoped_token(user_id: &str, scopes: &[Scope], ttl: Duration) -> Result<String, ServiceError> {
+    let secret = get_jwt_secret()?;
+    let now = Utc::now();
+    let expiry = now + ttl;
+    
+    let claims = Claims {
+        sub: user_id.to_string(),
+        email: String::new(),
+        roles: vec!["share".to_string()],
+        exp: expiry.timestamp() as usize,
+        iat: now.timestamp() as usize,
+        iss: "authorworks".to_string(),
+        aud: SHARE_AUDIENCE.to_string(),
         auth_provider: None,
+        scopes: Some(scopes.to_vec()),
     };
     
     encode(
@@ -109,13 +144,30 @@ pub fn generate_refresh_token(user_id: &str, roles: &[String]) -> Result<String,
 // Token Validation
 //=============================================================================
 
-/// Validate an access token and return claims
+/// Validate a first-party access token and return claims
 pub fn validate_access_token(token: &str) -> Result<Claims, ServiceError> {
+    let claims = decode_claims(token, SESSION_AUDIENCE)?;
+    if claims.scopes.is_some() {
+        return Err(ServiceError::Unauthorized("Invalid token".into()));
+    }
+    Ok(claims)
+}
+
+/// Validate a share token and return claims; its scopes are always set
+pub fn validate_share_token(token: &str) -> Result<Claims, ServiceError> {
+    let claims = decode_claims(token, SHARE_AUDIENCE)?;
+    if claims.scopes.is_none() {
+        return Err(ServiceError::Unauthorized("Invalid token".into()));
+    }
+    Ok(claims)
+}
+
+fn decode_claims(token: &str, audience: &str) -> Result<Claims, ServiceError> {
     let secret = get_jwt_secret()?;
     
     let mut validation = Validation::new(Algorithm::HS256);
     validation.set_issuer(&["authorworks"]);
-    validation.set_audience(&["authorworks-api"]);
+    validation.set_audience(&[audience]);
     
     let token_data = decode::<Claims>(
         token,
@@ -8,13 +8,29 @@
 //! - POST /auth/logout - Logout and invalidate session
 //! - POST /auth/refresh - Refresh access token
 //! - GET /auth/callback - OAuth callback from Logto
+//! - POST /auth/introspect - Resolve a bearer token or API key to user ID and scopes (gateway)
+//! - POST /auth/share-token - Mint a share token limited to a role or scope list
+//! - GET /api-keys - List API keys
+//! - POST /api-keys - Create scoped API key
+//! - DELETE /api-keys/:id - Revoke API key
 //! - GET /users/me - Get current user profile
 //! - PUT /users/me - Update current user profile
 //! - GET /users/:id - Get public profile
+//! - GET /privacy/preferences - Get analytics, marketing email, and AI training consent
+//! - PUT /privacy/preferences - Update consent
+//! - GET /privacy/preferences/history - Consent change history
+//! - POST /privacy/consents/check - Filter user IDs by consent for a purpose (internal, X-Internal-Token)
+//! - POST /lifecycle/process - Notify, anonymize or delete inactive accounts per the retention policy (internal, X-Internal-Token)
+//! - GET /lifecycle/report - Dry run of the retention policy (internal, X-Internal-Token)
+//! - GET /lifecycle/accounts/:user_id - Retention state of one account (internal, X-Internal-Token)
+//! - GET /admin/orgs/:org_id/members/:user_id/resources - Books and collaborator grants a member holds in an org
+//! - POST /admin/orgs/:org_id/members/:user_id/transfer - Move a member's books to another member and revoke their grants
+//! - GET /admin/orgs/:org_id/transfers - Ownership transfer audit trail
 //! - GET /health - Health check
 
 use spin_sdk::http::{IntoResponse, Request, Response, Method};
 use spin_sdk::http_component;
+use spin_sdk::pg::Connection;
 use spin_sdk::variables;
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
@@ -25,6 +41,11 @@ mod auth;
 mod models;
 mod handlers;
 mod error;
+mod scopes;
+mod api_keys;
+mod privacy;
+mod ownership;
+mod lifecycle;
 
 use error::ServiceError;
 use handlers::*;
@@ -35,6 +56,10 @@ fn handle_request(req: Request) -> anyhow::Result<impl IntoResponse> {
     let path = req.path();
     let method = req.method();
     
+    if let Err(e) = authorworks_access::authorize(&req, scopes::route_scope) {
+        return Ok(ServiceError::from(e).into_response());
+    }
+    
     // Route to appropriate handler
     let result = match (method, path) {
         // Health check
@@ -48,6 +73,13 @@ fn handle_request(req: Request) -> anyhow::Result<impl IntoResponse> {
         (Method::Post, "/auth/refresh") => refresh_handler(&req),
         (Method::Get, "/auth/callback") => oauth_callback_handler(&req),
         (Method::Get, "/auth/logto/authorize") => logto_authorize_handler(&req),
+        (Method::Post, "/auth/introspect") => introspect_handler(&req),
+        (Method::Post, "/auth/share-token") => share_token_handler(&req),
+        
+        // API keys
+        (Method::Get, "/api-keys") => list_api_keys_handler(&req),
+        (Method::Post, "/api-keys") => create_api_key_handler(&req),
+        (Method::Delete, path) if path.starts_with("/api-keys/") => revoke_api_key_handler(&req, path),
         
         // User profile endpoints
         (Method::Get, "/users/me") => get_current_user_handler(&req),
@@ -58,6 +90,22 @@ fn handle_request(req: Request) -> anyhow::Result<impl IntoResponse> {
         (Method::Get, "/preferences") => get_preferences_handler(&req),
         (Method::Put, path) if path.starts_with("/preferences/") => set_preference_handler(&req, path),
         
+        // Privacy
+        (Method::Get, "/privacy/preferences") => get_privacy_preferences_handler(&req),
+        (Method::Put, "/privacy/preferences") => update_privacy_preferences_handler(&req),
+        (Method::Get, "/privacy/preferences/history") => get_consent_history_handler(&req),
+        (Method::Post, "/privacy/consents/check") => check_consents_handler(&req),
+        
+        // Account lifecycle (internal)
+        (Method::Post, "/lifecycle/process") => process_lifecycle_handler(&req),
+        (Method::Get, "/lifecycle/report") => lifecycle_report_handler(&req),
+        (Method::Get, path) if path.starts_with("/lifecycle/accounts/") => lifecycle_account_handler(&req, path),
+        
+        // Organization ownership (admin)
+        (Method::Get, path) if path.starts_with("/admin/orgs/") && path.ends_with("/resources") => member_resources_handler(&req, path),
+        (Method::Post, path) if path.starts_with("/admin/orgs/") && path.ends_with("/transfer") => transfer_ownership_handler(&req, path),
+        (Method::Get, path) if path.starts_with("/admin/orgs/") && path.ends_with("/transfers") => list_transfers_handler(&req, path),
+        
         // CORS preflight
         (Method::Options, _) => cors_preflight_handler(),
         
@@ -97,7 +145,14 @@ fn service_info_handler() -> Result<Response, ServiceError> {
                 "POST /auth/logout",
                 "POST /auth/refresh",
                 "GET /auth/callback",
-                "GET /auth/logto/authorize"
+                "GET /auth/logto/authorize",
+                "POST /auth/introspect",
+                "POST /auth/share-token"
+            ],
+            "api_keys": [
+                "GET /api-keys",
+                "POST /api-keys",
+                "DELETE /api-keys/:id"
             ],
             "users": [
                 "GET /users/me",
@@ -108,6 +163,22 @@ fn service_info_handler() -> Result<Response, ServiceError> {
                 "GET /preferences",
                 "PUT /preferences/:key"
             ],
+            "privacy": [
+                "GET /privacy/preferences",
+                "PUT /privacy/preferences",
+                "GET /privacy/preferences/history",
+                "POST /privacy/consents/check"
+            ],
+            "lifecycle": [
+                "POST /lifecycle/process",
+                "GET /lifecycle/report",
+                "GET /lifecycle/accounts/:user_id"
+            ],
+            "admin": [
+                "GET /admin/orgs/:org_id/members/:user_id/resources",
+                "POST /admin/orgs/:org_id/members/:user_id/transfer",
+                "GET /admin/orgs/:org_id/transfers"
+            ],
             "health": [
                 "GET /health"
             ]
@@ -279,6 +350,119 @@ fn logto_authorize_handler(_req: &Request) -> Result<Response, ServiceError> {
         .build())
 }
 
+//=============================================================================
+// Scoped Access Handlers
+//=============================================================================
+
+/// Called by the gateway (`auth_request`) for every API request. The answer
+/// is repeated in `X-User-Id` and `X-Scopes` response headers for the gateway
+/// to forward; `scopes` is null for first-party sessions. A request without
+/// a token is anonymous and still answered 200, so public routes stay open.
+fn introspect_handler(req: &Request) -> Result<Response, ServiceError> {
+    if req.header("Authorization").is_none() {
+        return json_response(200, serde_json::json!({ "active": false }));
+    }
+    let token = extract_bearer_token(req)?;
+
+    let (user_id, granted) = if token.starts_with(api_keys::KEY_PREFIX) {
+        let conn = get_db_connection()?;
+        let (user_id, granted) = api_keys::resolve_key(&conn, &token)?;
+        (user_id.to_string(), Some(granted))
+    } else if let Ok(claims) = auth::validate_access_token(&token) {
+        (claims.sub, None)
+    } else {
+        let claims = auth::validate_share_token(&token)?;
+        (claims.sub, claims.scopes)
+    };
+    let scopes_header = granted.as_deref().map(scopes::to_header);
+
+    let json = serde_json::to_string(&serde_json::json!({
+        "active": true,
+        "user_id": user_id,
+        "scopes": granted,
+        "scopes_header": scopes_header
+    })).map_err(|e| ServiceError::Internal(format!("JSON serialization error: {}", e)))?;
+
+    let mut response = Response::builder();
+    response
+        .status(200)
+        .header("Content-Type", "application/json")
+        .header("X-User-Id", user_id.as_str());
+    if let Some(ref header) = scopes_header {
+        response.header("X-Scopes", header.as_str());
+    }
+    Ok(response.body(json).build())
+}
+
+fn share_token_handler(req: &Request) -> Result<Response, ServiceError> {
+    let claims = require_full_access(req)?;
+    let body: models::ShareTokenRequest = parse_json_body(req)?;
+
+    let granted = match (&body.role, &body.scopes) {
+        (Some(role), None) => scopes::role_scopes(role)
+            .ok_or_else(|| ServiceError::BadRequest(format!("Unknown role: {}", role)))?,
+        (None, Some(requested)) => scopes::parse_scopes(requested)?,
+        _ => return Err(ServiceError::BadRequest("Provide either role or scopes".into())),
+    };
+
+    let hours = body.expires_in_hours.unwrap_or(24);
+    if !(1..=24 * 30).contains(&hours) {
+        return Err(ServiceError::BadRequest("expires_in_hours must be between 1 and 720".into()));
+    }
+
+    let token = auth::generate_scoped_token(&claims.sub, &granted, chrono::Duration::hours(hours))?;
+
+    json_response(201, serde_json::json!({
+        "token": token,
+        "token_type": "Bearer",
+        "scopes": granted,
+        "expires_in": hours * 3600
+    }))
+}
+
+fn list_api_keys_handler(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = require_full_access_user(req)?;
+    let conn = get_db_connection()?;
+
+    api_keys::list_keys(&conn, &user_id)
+}
+
+fn create_api_key_handler(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = require_full_access_user(req)?;
+    let body: api_keys::CreateApiKeyRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    api_keys::create_key(&conn, &user_id, body)
+}
+
+fn revoke_api_key_handler(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let user_id = require_full_access_user(req)?;
+    let key_id = path.strip_prefix("/api-keys/")
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid API key ID".into()))?;
+    let conn = get_db_connection()?;
+
+    api_keys::revoke_key(&conn, &user_id, &key_id)
+}
+
+/// Only first-party sessions may mint credentials; scoped tokens cannot
+/// escalate by issuing new ones. Share tokens carry their own audience, so
+/// session validation already refuses them.
+fn require_full_access(req: &Request) -> Result<auth::Claims, ServiceError> {
+    let token = extract_bearer_token(req)?;
+    if token.starts_with(api_keys::KEY_PREFIX) {
+        return Err(ServiceError::Forbidden("API keys cannot manage credentials".into()));
+    }
+
+    auth::validate_access_token(&token)
+}
+
+fn require_full_access_user(req: &Request) -> Result<Uuid, ServiceError> {
+    let claims = require_full_access(req)?;
+    Uuid::parse_str(&claims.sub)
+        .map_err(|_| ServiceError::Unauthorized("Invalid user ID".into()))
+}
+
 //=============================================================================
 // User Handlers
 //=============================================================================
@@ -379,6 +563,134 @@ fn set_preference_handler(req: &Request, path: &str) -> Result<Response, Service
     }))
 }
 
+//=============================================================================
+// Privacy Handlers
+//=============================================================================
+
+fn get_privacy_preferences_handler(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = require_full_access_user(req)?;
+    let conn = get_db_connection()?;
+
+    privacy::get_preferences(&conn, &user_id)
+}
+
+/// Consent can only be changed from a first-party session, never by an API
+/// key or share token acting on the user's behalf
+fn update_privacy_preferences_handler(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = require_full_access_user(req)?;
+    let body: privacy::UpdatePrivacyRequest = parse_json_body(req)?;
+    let user_agent = req.header("User-Agent")
+        .and_then(|h| h.as_str())
+        .map(|s| s.to_string());
+    let conn = get_db_connection()?;
+
+    privacy::update_preferences(&conn, &user_id, body, "user", user_agent)
+}
+
+fn get_consent_history_handler(req: &Request) -> Result<Response, ServiceError> {
+    let user_id = require_full_access_user(req)?;
+    let conn = get_db_connection()?;
+
+    privacy::get_history(&conn, &user_id)
+}
+
+/// Other services' consent lookups; never reachable by users
+fn check_consents_handler(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let body: privacy::ConsentCheckRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    privacy::check_consents(&conn, body)
+}
+
+//=============================================================================
+// Account Lifecycle Handlers
+//=============================================================================
+
+/// Run on a schedule; `?dry_run=true` reports without changing anything.
+/// Lifecycle routes anonymize accounts and expose anyone's retention state,
+/// so only internal callers reach them.
+fn process_lifecycle_handler(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let dry_run = get_query_param(req.query(), "dry_run")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false);
+    let policy = lifecycle::get_policy();
+    let conn = get_db_connection()?;
+
+    lifecycle::process(&conn, &policy, dry_run)
+}
+
+fn lifecycle_report_handler(req: &Request) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let policy = lifecycle::get_policy();
+    let conn = get_db_connection()?;
+
+    lifecycle::process(&conn, &policy, true)
+}
+
+fn lifecycle_account_handler(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    authorworks_access::require_internal(req)?;
+    let user_id = path.strip_prefix("/lifecycle/accounts/")
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid user ID".into()))?;
+    let conn = get_db_connection()?;
+
+    lifecycle::get_account(&conn, &user_id)
+}
+
+//=============================================================================
+// Organization Ownership Handlers
+//=============================================================================
+
+fn member_resources_handler(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let actor_id = require_full_access_user(req)?;
+    let (org_id, member_id) = parse_org_member_path(path, "/resources")?;
+    let conn = get_db_connection()?;
+
+    ownership::list_resources(&conn, &actor_id, &org_id, &member_id)
+}
+
+fn transfer_ownership_handler(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let actor_id = require_full_access_user(req)?;
+    let (org_id, member_id) = parse_org_member_path(path, "/transfer")?;
+    let body: ownership::TransferRequest = parse_json_body(req)?;
+    let conn = get_db_connection()?;
+
+    ownership::transfer(&conn, &actor_id, &org_id, &member_id, body)
+}
+
+fn list_transfers_handler(req: &Request, path: &str) -> Result<Response, ServiceError> {
+    let actor_id = require_full_access_user(req)?;
+    let org_id = path.strip_prefix("/admin/orgs/")
+        .and_then(|p| p.strip_suffix("/transfers"))
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| ServiceError::BadRequest("Invalid organization ID".into()))?;
+    let user_id = match get_query_param(req.query(), "user_id") {
+        Some(id) => Some(Uuid::parse_str(&id)
+            .map_err(|_| ServiceError::BadRequest("Invalid user_id".into()))?),
+        None => None,
+    };
+    let conn = get_db_connection()?;
+
+    ownership::list_transfers(&conn, &actor_id, &org_id, user_id)
+}
+
+/// Parse `/admin/orgs/:org_id/members/:user_id{suffix}`
+fn parse_org_member_path(path: &str, suffix: &str) -> Result<(Uuid, Uuid), ServiceError> {
+    let rest = path.strip_prefix("/admin/orgs/")
+        .and_then(|p| p.strip_suffix(suffix))
+        .ok_or_else(|| ServiceError::BadRequest("Invalid path".into()))?;
+    let (org_id, member_id) = rest.split_once("/members/")
+        .ok_or_else(|| ServiceError::BadRequest("Invalid path".into()))?;
+
+    let org_id = Uuid::parse_str(org_id)
+        .map_err(|_| ServiceError::BadRequest("Invalid organization ID".into()))?;
+    let member_id = Uuid::parse_str(member_id)
+        .map_err(|_| ServiceError::BadRequest("Invalid user ID".into()))?;
+    Ok((org_id, member_id))
+}
+
 //=============================================================================
 // Utility Functions
 //=============================================================================
@@ -423,6 +735,13 @@ fn get_query_param(query: &str, key: &str) -> Option<String> {
         .next()
 }
 
+fn get_db_connection() -> Result<Connection, ServiceError> {
+    let url = variables::get("database_url")
+        .map_err(|_| ServiceError::Internal("DATABASE_URL not configured".into()))?;
+    Connection::open(&url)
+        .map_err(|e| ServiceError::Internal(format!("Database connection failed: {}", e)))
+}
+
 fn get_config(key: &str) -> Result<String, ServiceError> {
     variables::get(key)
         .map_err(|_| ServiceError::Internal(format!("Missing config: {}", key)))
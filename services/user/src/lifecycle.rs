@@ -0,0 +1,559 @@
+//! Account lifecycle policy
+//!
+//! Accounts nobody has signed in to for `lifecycle_inactive_months` are
+//! flagged and told by notification (and email) that they will be anonymized
+//! or deleted after `lifecycle_notice_days`. Signing in during the notice
+//! period clears the flag. Once the notice runs out, the account's footprint
+//! in other services is cleaned up before the account itself is touched:
+//! - published books go back to draft and are removed from search
+//! - a paid subscription is cancelled through the subscription service
+//! - stored files are deleted through the storage service (which owns S3)
+//! - private vault items and API keys are removed
+//!
+//! If any step fails the account stays pending and the whole sequence is
+//! retried on the next run, so a deletion never leaves orphaned files behind.
+//! Every run can be made as a dry run that reports what would happen without
+//! writing or notifying anything.
+
+use crate::error::ServiceError;
+use serde::Serialize;
+use spin_sdk::http::Response;
+use spin_sdk::outbound_http;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use spin_sdk::variables;
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+const DEFAULT_INACTIVE_MONTHS: i32 = 24;
+const DEFAULT_NOTICE_DAYS: i64 = 30;
+const DEFAULT_BATCH_SIZE: i64 = 100;
+
+/// Subscription states that still bill and so must be cancelled
+const BILLING_STATUSES: &str = "active,trialing,past_due";
+
+//=============================================================================
+// Policy
+//=============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionAction {
+    /// Strip personal data but keep the account row and draft content
+    Anonymize,
+    /// Remove the account and everything that cascades from it
+    Delete,
+}
+
+impl RetentionAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RetentionAction::Anonymize => "anonymize",
+            RetentionAction::Delete => "delete",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "anonymize" => Some(RetentionAction::Anonymize),
+            "delete" => Some(RetentionAction::Delete),
+            _ => None,
+        }
+    }
+
+    fn completed_state(&self) -> &'static str {
+        match self {
+            RetentionAction::Anonymize => "anonymized",
+            RetentionAction::Delete => "deleted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LifecyclePolicy {
+    pub inactive_months: i32,
+    pub notice_days: i64,
+    pub action: RetentionAction,
+    /// Accounts flagged and accounts actioned per run, each
+    pub batch_size: i64,
+}
+
+pub fn get_policy() -> LifecyclePolicy {
+    LifecyclePolicy {
+        inactive_months: variables::get("lifecycle_inactive_months")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(DEFAULT_INACTIVE_MONTHS),
+        notice_days: variables::get("lifecycle_notice_days")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|v| *v >= 0)
+            .unwrap_or(DEFAULT_NOTICE_DAYS),
+        action: variables::get("lifecycle_action")
+            .ok()
+            .and_then(|v| RetentionAction::parse(&v))
+            .unwrap_or(RetentionAction::Anonymize),
+        batch_size: variables::get("lifecycle_batch_size")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(DEFAULT_BATCH_SIZE),
+    }
+}
+
+//=============================================================================
+// Models
+//=============================================================================
+
+/// What an account holds in other services, for the dry-run report
+#[derive(Debug, Serialize)]
+pub struct AccountFootprint {
+    pub books: i64,
+    pub published_books: i64,
+    pub files: i64,
+    pub vault_items: i64,
+    pub active_api_keys: i64,
+    /// Plan of a subscription that would be cancelled
+    pub billed_plan: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountReport {
+    pub user_id: String,
+    pub last_active_at: Option<String>,
+    pub action: RetentionAction,
+    pub action_due_at: Option<String>,
+    pub footprint: AccountFootprint,
+    /// Step outcomes; absent on dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub steps: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// POST /lifecycle/process - Reactivate, notify and action inactive accounts
+pub fn process(conn: &Connection, policy: &LifecyclePolicy, dry_run: bool) -> Result<Response, ServiceError> {
+    let reactivated = reactivate(conn, dry_run)?;
+    let notified = notify_inactive(conn, policy, dry_run)?;
+    let actioned = run_due(conn, policy, dry_run)?;
+
+    let failed = actioned.iter().filter(|r| r.error.is_some()).count();
+
+    crate::json_response(200, serde_json::json!({
+        "dry_run": dry_run,
+        "policy": policy,
+        "reactivated": reactivated,
+        "notified": notified,
+        "actioned": actioned,
+        "failed": failed
+    }))
+}
+
+/// GET /lifecycle/accounts/:user_id - Lifecycle state of one account
+pub fn get_account(conn: &Connection, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT state, action, last_active_at, notified_at, action_due_at, completed_at,
+                        steps::text, attempts, last_error
+                 FROM users.account_lifecycle WHERE user_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::NotFound("Account has no lifecycle record".into()))?;
+
+    crate::json_response(200, serde_json::json!({
+        "user_id": user_id,
+        "state": String::decode(&row[0]).unwrap_or_default(),
+        "action": String::decode(&row[1]).unwrap_or_default(),
+        "last_active_at": String::decode(&row[2]).ok(),
+        "notified_at": String::decode(&row[3]).unwrap_or_default(),
+        "action_due_at": String::decode(&row[4]).unwrap_or_default(),
+        "completed_at": String::decode(&row[5]).ok(),
+        "steps": serde_json::from_str::<serde_json::Value>(&String::decode(&row[6]).unwrap_or_default()).unwrap_or_default(),
+        "attempts": i32::decode(&row[7]).unwrap_or(0),
+        "last_error": String::decode(&row[8]).ok()
+    }))
+}
+
+//=============================================================================
+// Stages
+//=============================================================================
+
+/// Clear the flag on notified accounts that have signed in since
+fn reactivate(conn: &Connection, dry_run: bool) -> Result<Vec<String>, ServiceError> {
+    let query = if dry_run {
+        "SELECT l.user_id FROM users.account_lifecycle l
+         JOIN users.users u ON u.id = l.user_id
+         WHERE l.state = 'notified' AND u.last_login_at > l.notified_at"
+    } else {
+        "DELETE FROM users.account_lifecycle l USING users.users u
+         WHERE u.id = l.user_id AND l.state = 'notified' AND u.last_login_at > l.notified_at
+         RETURNING l.user_id"
+    };
+    let rows = conn.query(query, &[])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.iter().map(|row| String::decode(&row[0]).unwrap_or_default()).collect())
+}
+
+/// Flag accounts past the inactivity threshold and tell their owners
+fn notify_inactive(conn: &Connection, policy: &LifecyclePolicy, dry_run: bool) -> Result<Vec<AccountReport>, ServiceError> {
+    // Staff accounts are never expired
+    let query = "SELECT u.id, COALESCE(u.last_login_at, u.created_at) AS last_active
+                 FROM users.users u
+                 WHERE COALESCE(u.status, 'active') = 'active'
+                   AND COALESCE(u.last_login_at, u.created_at) < NOW() - make_interval(months => $1)
+                   AND NOT EXISTS (SELECT 1 FROM users.account_lifecycle l WHERE l.user_id = u.id)
+                   AND NOT EXISTS (SELECT 1 FROM users.user_roles r WHERE r.user_id = u.id)
+                 ORDER BY last_active
+                 LIMIT $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Int32(policy.inactive_months),
+        ParameterValue::Int64(policy.batch_size),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let due_at = (Utc::now() + Duration::days(policy.notice_days)).to_rfc3339();
+    let mut reports = Vec::new();
+
+    for row in &rows.rows {
+        let user_id = String::decode(&row[0]).unwrap_or_default();
+        let last_active_at = String::decode(&row[1]).ok();
+        let footprint = load_footprint(conn, &user_id)?;
+
+        if !dry_run {
+            let insert = "INSERT INTO users.account_lifecycle
+                          (user_id, state, action, last_active_at, notified_at, action_due_at, updated_at)
+                          VALUES ($1, 'notified', $2, $3, NOW(), $4, NOW())
+                          ON CONFLICT (user_id) DO NOTHING";
+            conn.execute(insert, &[
+                ParameterValue::Str(user_id.clone()),
+                ParameterValue::Str(policy.action.as_str().to_string()),
+                last_active_at.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+                ParameterValue::Str(due_at.clone()),
+            ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+            send_notice(&user_id, policy, &due_at, &footprint);
+        }
+
+        reports.push(AccountReport {
+            user_id,
+            last_active_at,
+            action: policy.action,
+            action_due_at: Some(due_at.clone()),
+            footprint,
+            steps: None,
+            error: None,
+        });
+    }
+
+    Ok(reports)
+}
+
+/// Carry out the pending action on accounts whose notice has run out
+fn run_due(conn: &Connection, policy: &LifecyclePolicy, dry_run: bool) -> Result<Vec<AccountReport>, ServiceError> {
+    // The sign-in check keeps dry runs accurate; live runs already cleared those rows
+    let query = "SELECT l.user_id, l.action, l.last_active_at, l.action_due_at
+                 FROM users.account_lifecycle l
+                 JOIN users.users u ON u.id = l.user_id
+                 WHERE l.state = 'notified' AND l.action_due_at <= NOW()
+                   AND NOT (u.last_login_at IS NOT NULL AND u.last_login_at > l.notified_at)
+                 ORDER BY l.action_due_at
+                 LIMIT $1";
+    let rows = conn.query(query, &[ParameterValue::Int64(policy.batch_size)])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let mut reports = Vec::new();
+    for row in &rows.rows {
+        let user_id = String::decode(&row[0]).unwrap_or_default();
+        // The action is fixed when the user is notified
+        let action = RetentionAction::parse(&String::decode(&row[1]).unwrap_or_default())
+            .unwrap_or(policy.action);
+        let footprint = load_footprint(conn, &user_id)?;
+
+        let mut report = AccountReport {
+            user_id: user_id.clone(),
+            last_active_at: String::decode(&row[2]).ok(),
+            action,
+            action_due_at: String::decode(&row[3]).ok(),
+            footprint,
+            steps: None,
+            error: None,
+        };
+
+        if !dry_run {
+            let (steps, error) = apply_action(conn, &user_id, action)?;
+            report.steps = Some(steps);
+            report.error = error;
+        }
+        reports.push(report);
+    }
+
+    Ok(reports)
+}
+
+/// Run every cleanup step, then the action itself if they all succeeded
+fn apply_action(
+    conn: &Connection,
+    user_id: &str,
+    action: RetentionAction,
+) -> Result<(serde_json::Value, Option<String>), ServiceError> {
+    let mut steps = serde_json::Map::new();
+    let mut failures = Vec::new();
+
+    let cleanup: [(&str, fn(&Connection, &str) -> Result<serde_json::Value, String>); 5] = [
+        ("unpublish", unpublish_books),
+        ("subscription", cancel_subscription),
+        ("files", delete_files),
+        ("vault", delete_vault_items),
+        ("api_keys", revoke_api_keys),
+    ];
+    for (name, step) in cleanup {
+        match step(conn, user_id) {
+            Ok(outcome) => {
+                steps.insert(name.to_string(), outcome);
+            }
+            Err(e) => {
+                steps.insert(name.to_string(), serde_json::json!({ "error": e }));
+                failures.push(format!("{}: {}", name, e));
+            }
+        }
+    }
+
+    if failures.is_empty() {
+        let result = match action {
+            RetentionAction::Anonymize => anonymize_account(conn, user_id),
+            RetentionAction::Delete => delete_account(conn, user_id),
+        };
+        if let Err(e) = result {
+            failures.push(format!("{}: {}", action.as_str(), e));
+        }
+    }
+
+    let steps = serde_json::Value::Object(steps);
+    let error = (!failures.is_empty()).then(|| failures.join("; "));
+
+    match error {
+        None => {
+            let update = "UPDATE users.account_lifecycle
+                          SET state = $2, completed_at = NOW(), steps = $3::jsonb, attempts = attempts + 1,
+                              last_error = NULL, updated_at = NOW()
+                          WHERE user_id = $1";
+            conn.execute(update, &[
+                ParameterValue::Str(user_id.to_string()),
+                ParameterValue::Str(action.completed_state().to_string()),
+                ParameterValue::Str(steps.to_string()),
+            ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+        }
+        Some(ref e) => {
+            let update = "UPDATE users.account_lifecycle
+                          SET steps = $2::jsonb, attempts = attempts + 1, last_error = $3, updated_at = NOW()
+                          WHERE user_id = $1";
+            conn.execute(update, &[
+                ParameterValue::Str(user_id.to_string()),
+                ParameterValue::Str(steps.to_string()),
+                ParameterValue::Str(e.clone()),
+            ]).map_err(|e| ServiceError::Internal(format!("Update failed: {}", e)))?;
+        }
+    }
+
+    Ok((steps, error))
+}
+
+//=============================================================================
+// Cleanup Steps
+//=============================================================================
+
+fn unpublish_books(conn: &Connection, user_id: &str) -> Result<serde_json::Value, String> {
+    let update = "UPDATE content.books SET status = 'draft', published_at = NULL, updated_at = NOW()
+                  WHERE author_id = $1 AND status = 'published'
+                  RETURNING id";
+    let rows = conn.query(update, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| format!("Update failed: {}", e))?;
+    let book_ids: Vec<String> = rows.rows.iter().map(|row| String::decode(&row[0]).unwrap_or_default()).collect();
+
+    // Books left in the index from an earlier failed run are removed too
+    let query = "SELECT id FROM content.books WHERE author_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| format!("Query failed: {}", e))?;
+
+    let discovery_url = variables::get("discovery_service_url")
+        .unwrap_or_else(|_| "http://discovery-service:3107".to_string());
+    for row in &rows.rows {
+        let book_id = String::decode(&row[0]).unwrap_or_default();
+        let status = send(outbound_http::Method::Delete, &format!("{}/index/book/{}", discovery_url, book_id), None)?;
+        if status >= 400 && status != 404 {
+            return Err(format!("Index removal for book {} failed with status {}", book_id, status));
+        }
+    }
+
+    Ok(serde_json::json!({ "unpublished": book_ids, "deindexed": rows.rows.len() }))
+}
+
+fn cancel_subscription(conn: &Connection, user_id: &str) -> Result<serde_json::Value, String> {
+    let query = "SELECT plan_id FROM subscriptions.subscriptions
+                 WHERE user_id = $1 AND status = ANY(string_to_array($2, ','))
+                   AND stripe_subscription_id IS NOT NULL AND COALESCE(cancel_at_period_end, false) = false";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(BILLING_STATUSES.to_string()),
+    ]).map_err(|e| format!("Query failed: {}", e))?;
+
+    let Some(plan_id) = rows.rows.first().map(|row| String::decode(&row[0]).unwrap_or_default()) else {
+        return Ok(serde_json::json!({ "cancelled": false }));
+    };
+
+    let subscription_url = variables::get("subscription_service_url")
+        .unwrap_or_else(|_| "http://subscription-service:3105".to_string());
+    let status = send(outbound_http::Method::Delete, &format!("{}/subscription", subscription_url), Some(user_id))?;
+    if status >= 400 {
+        return Err(format!("Cancellation failed with status {}", status));
+    }
+
+    Ok(serde_json::json!({ "cancelled": true, "plan_id": plan_id }))
+}
+
+/// Files live in object storage, so deletion goes through the storage service
+fn delete_files(conn: &Connection, user_id: &str) -> Result<serde_json::Value, String> {
+    let query = "SELECT id FROM storage.files WHERE user_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| format!("Query failed: {}", e))?;
+
+    let storage_url = variables::get("storage_service_url")
+        .unwrap_or_else(|_| "http://storage-service:3103".to_string());
+    for row in &rows.rows {
+        let file_id = String::decode(&row[0]).unwrap_or_default();
+        let status = send(outbound_http::Method::Delete, &format!("{}/files/{}", storage_url, file_id), Some(user_id))?;
+        if status >= 400 && status != 404 {
+            return Err(format!("Deleting file {} failed with status {}", file_id, status));
+        }
+    }
+
+    Ok(serde_json::json!({ "deleted": rows.rows.len() }))
+}
+
+/// Vault items are database-only ciphertext; versions cascade
+fn delete_vault_items(conn: &Connection, user_id: &str) -> Result<serde_json::Value, String> {
+    let delete = "DELETE FROM storage.vault_items WHERE user_id = $1";
+    let deleted = conn.execute(delete, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| format!("Delete failed: {}", e))?;
+    Ok(serde_json::json!({ "deleted": deleted }))
+}
+
+fn revoke_api_keys(conn: &Connection, user_id: &str) -> Result<serde_json::Value, String> {
+    let update = "UPDATE users.api_keys SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL";
+    let revoked = conn.execute(update, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| format!("Update failed: {}", e))?;
+    Ok(serde_json::json!({ "revoked": revoked }))
+}
+
+fn anonymize_account(conn: &Connection, user_id: &str) -> Result<(), String> {
+    let update = "UPDATE users.users
+                  SET email = 'anonymized-' || id::text || '@invalid', name = NULL, avatar_url = NULL, bio = NULL,
+                      logto_id = NULL, metadata = '{}', status = 'anonymized', updated_at = NOW()
+                  WHERE id = $1";
+    conn.execute(update, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| format!("Update failed: {}", e))?;
+
+    let update = "UPDATE users.profiles
+                  SET display_name = NULL, website = NULL, social_links = '{}', preferences = '{}', updated_at = NOW()
+                  WHERE user_id = $1";
+    conn.execute(update, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| format!("Update failed: {}", e))?;
+    Ok(())
+}
+
+/// Everything else owned by the account cascades from the user row
+fn delete_account(conn: &Connection, user_id: &str) -> Result<(), String> {
+    let delete = "DELETE FROM users.users WHERE id = $1";
+    conn.execute(delete, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| format!("Delete failed: {}", e))?;
+    Ok(())
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+fn load_footprint(conn: &Connection, user_id: &str) -> Result<AccountFootprint, ServiceError> {
+    let query = "SELECT
+                     (SELECT COUNT(*) FROM content.books WHERE author_id = $1),
+                     (SELECT COUNT(*) FROM content.books WHERE author_id = $1 AND status = 'published'),
+                     (SELECT COUNT(*) FROM storage.files WHERE user_id = $1),
+                     (SELECT COUNT(*) FROM storage.vault_items WHERE user_id = $1),
+                     (SELECT COUNT(*) FROM users.api_keys WHERE user_id = $1 AND revoked_at IS NULL),
+                     (SELECT plan_id FROM subscriptions.subscriptions
+                      WHERE user_id = $1 AND status = ANY(string_to_array($2, ','))
+                        AND stripe_subscription_id IS NOT NULL AND COALESCE(cancel_at_period_end, false) = false)";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(BILLING_STATUSES.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::Internal("Footprint query returned no row".into()))?;
+
+    Ok(AccountFootprint {
+        books: i64::decode(&row[0]).unwrap_or(0),
+        published_books: i64::decode(&row[1]).unwrap_or(0),
+        files: i64::decode(&row[2]).unwrap_or(0),
+        vault_items: i64::decode(&row[3]).unwrap_or(0),
+        active_api_keys: i64::decode(&row[4]).unwrap_or(0),
+        billed_plan: String::decode(&row[5]).ok(),
+    })
+}
+
+/// Fire-and-forget notice through the messaging service; a missed notice is
+/// not retried, so the flag still stands
+fn send_notice(user_id: &str, policy: &LifecyclePolicy, due_at: &str, footprint: &AccountFootprint) {
+    let messaging_url = variables::get("messaging_service_url")
+        .unwrap_or_else(|_| "http://messaging-service:3106".to_string());
+
+    let outcome = match policy.action {
+        RetentionAction::Anonymize => "your personal details will be removed and your published books taken down",
+        RetentionAction::Delete => "your account and everything in it will be permanently deleted",
+    };
+    let body = serde_json::json!({
+        "user_id": user_id,
+        "type": "account_inactive",
+        "title": "Your account is scheduled for removal",
+        "body": format!(
+            "You haven't signed in for over {} months. Unless you sign in before {}, {}.",
+            policy.inactive_months, &due_at[..10], outcome
+        ),
+        "data": {
+            "action": policy.action.as_str(),
+            "action_due_at": due_at,
+            "books": footprint.books,
+            "url": "/login"
+        }
+    });
+
+    let request = outbound_http::Request::builder()
+        .method(outbound_http::Method::Post)
+        .uri(format!("{}/notifications", messaging_url))
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .build();
+
+    let _ = outbound_http::send(request);
+}
+
+/// Internal call to another service, acting as `user_id` when given
+fn send(method: outbound_http::Method, uri: &str, user_id: Option<&str>) -> Result<u16, String> {
+    let request = match user_id {
+        Some(user_id) => outbound_http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("X-User-Id", user_id)
+            .build(),
+        None => outbound_http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .build(),
+    };
+
+    let response = outbound_http::send(request)
+        .map_err(|e| format!("Request to {} failed: {}", uri, e))?;
+    Ok(response.status().as_u16())
+}
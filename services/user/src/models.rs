@@ -105,6 +105,14 @@ pub struct RefreshRequest {
     pub refresh_token: String,
 }
 
+/// Either a collaborator role (`viewer`, `commenter`, `editor`) or an explicit scope list
+#[derive(Debug, Deserialize)]
+pub struct ShareTokenRequest {
+    pub role: Option<String>,
+    pub scopes: Option<Vec<String>>,
+    pub expires_in_hours: Option<i64>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct AuthTokens {
     pub access_token: String,
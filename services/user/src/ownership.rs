@@ -0,0 +1,328 @@
+//! Organization ownership tooling
+//!
+//! Admin endpoints for handing a member's work to someone else, typically
+//! when they leave an organization. An admin can review every book the member
+//! owns in the organization along with their active collaborator grants, then
+//! move the books to another member and revoke the grants in one statement,
+//! so a failure leaves nothing half-transferred. Each transfer is recorded in
+//! `users.ownership_transfers` with the actor, the moved books, the revoked
+//! grants and the stated reason.
+//!
+//! Callers must be an `owner` or `admin` of the organization, or a platform
+//! admin (`authorworks_access::ADMIN_ROLES`).
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use uuid::Uuid;
+
+const ORG_ADMIN_ROLES: [&str; 2] = ["owner", "admin"];
+
+/// Upper bound on explicitly listed books in one transfer
+const MAX_TRANSFER_BOOKS: usize = 500;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct TransferRequest {
+    pub to_user_id: Uuid,
+    /// Books to move; omit to move every book the member owns in the org
+    pub book_ids: Option<Vec<Uuid>>,
+    /// Revoke the member's collaborator grants on org books (default true)
+    #[serde(default = "default_true")]
+    pub revoke_grants: bool,
+    pub reason: String,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize)]
+pub struct OwnedBook {
+    pub id: String,
+    pub title: String,
+    pub status: String,
+    pub chapter_count: i64,
+    pub word_count: i32,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CollaboratorGrant {
+    pub id: String,
+    pub book_id: String,
+    pub book_title: String,
+    pub role: String,
+    pub granted_by: Option<String>,
+    pub created_at: String,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// GET /admin/orgs/:org_id/members/:user_id/resources - Books and grants held by a member
+pub fn list_resources(
+    conn: &Connection,
+    actor_id: &Uuid,
+    org_id: &Uuid,
+    member_id: &Uuid,
+) -> Result<Response, ServiceError> {
+    require_org_admin(conn, actor_id, org_id)?;
+    let role = member_role(conn, org_id, member_id)?;
+
+    let books = owned_books(conn, org_id, member_id)?;
+    let grants = active_grants(conn, org_id, member_id)?;
+
+    crate::json_response(200, serde_json::json!({
+        "org_id": org_id,
+        "user_id": member_id,
+        "member_role": role,
+        "books": books,
+        "collaborator_grants": grants
+    }))
+}
+
+/// POST /admin/orgs/:org_id/members/:user_id/transfer - Move books to another member
+pub fn transfer(
+    conn: &Connection,
+    actor_id: &Uuid,
+    org_id: &Uuid,
+    from_id: &Uuid,
+    body: TransferRequest,
+) -> Result<Response, ServiceError> {
+    if body.reason.trim().is_empty() {
+        return Err(ServiceError::BadRequest("reason is required".into()));
+    }
+    if body.to_user_id == *from_id {
+        return Err(ServiceError::BadRequest("Cannot transfer ownership to the same user".into()));
+    }
+
+    require_org_admin(conn, actor_id, org_id)?;
+    member_role(conn, org_id, from_id)?;
+    member_role(conn, org_id, &body.to_user_id)
+        .map_err(|_| ServiceError::BadRequest("to_user_id must be a member of the organization".into()))?;
+
+    let book_filter = match body.book_ids {
+        Some(ref ids) => Some(validate_book_selection(conn, org_id, from_id, ids)?),
+        None => None,
+    };
+
+    // Moving the books, revoking grants and writing the audit record share one
+    // statement so they commit or fail together
+    let query = "WITH moved AS (
+                     UPDATE content.books SET author_id = $3, updated_at = NOW()
+                     WHERE org_id = $1 AND author_id = $2
+                       AND ($5::text IS NULL OR id::text = ANY(string_to_array($5, ',')))
+                     RETURNING id
+                 ),
+                 revoked AS (
+                     UPDATE content.book_collaborators SET revoked_at = NOW(), revoked_by = $4
+                     WHERE $6 AND user_id = $2 AND revoked_at IS NULL
+                       AND book_id IN (SELECT id FROM content.books WHERE org_id = $1)
+                     RETURNING id, book_id, role
+                 ),
+                 audit AS (
+                     INSERT INTO users.ownership_transfers
+                         (org_id, actor_id, from_user_id, to_user_id, book_ids, revoked_grants, reason)
+                     SELECT $1, $4, $2, $3,
+                            COALESCE((SELECT jsonb_agg(id) FROM moved), '[]'::jsonb),
+                            COALESCE((SELECT jsonb_agg(jsonb_build_object('id', id, 'book_id', book_id, 'role', role)) FROM revoked), '[]'::jsonb),
+                            $7
+                     RETURNING id, book_ids, revoked_grants, created_at
+                 )
+                 SELECT id, book_ids::text, revoked_grants::text, created_at FROM audit";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(org_id.to_string()),
+        ParameterValue::Str(from_id.to_string()),
+        ParameterValue::Str(body.to_user_id.to_string()),
+        ParameterValue::Str(actor_id.to_string()),
+        book_filter.map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+        ParameterValue::Boolean(body.revoke_grants),
+        ParameterValue::Str(body.reason.trim().to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Transfer failed: {}", e)))?;
+
+    let row = rows.rows.first()
+        .ok_or_else(|| ServiceError::Internal("Transfer returned no audit record".into()))?;
+
+    let book_ids = parse_json_column(&row[1]);
+    let revoked_grants = parse_json_column(&row[2]);
+
+    crate::json_response(200, serde_json::json!({
+        "transfer_id": String::decode(&row[0]).unwrap_or_default(),
+        "org_id": org_id,
+        "from_user_id": from_id,
+        "to_user_id": body.to_user_id,
+        "books_transferred": book_ids.as_array().map(|a| a.len()).unwrap_or(0),
+        "book_ids": book_ids,
+        "grants_revoked": revoked_grants.as_array().map(|a| a.len()).unwrap_or(0),
+        "revoked_grants": revoked_grants,
+        "created_at": String::decode(&row[3]).unwrap_or_default()
+    }))
+}
+
+/// GET /admin/orgs/:org_id/transfers - Ownership transfer audit trail
+pub fn list_transfers(
+    conn: &Connection,
+    actor_id: &Uuid,
+    org_id: &Uuid,
+    user_id: Option<Uuid>,
+) -> Result<Response, ServiceError> {
+    require_org_admin(conn, actor_id, org_id)?;
+
+    let query = "SELECT id, actor_id, from_user_id, to_user_id, book_ids::text, revoked_grants::text, reason, created_at
+                 FROM users.ownership_transfers
+                 WHERE org_id = $1 AND ($2::text IS NULL OR from_user_id::text = $2 OR to_user_id::text = $2)
+                 ORDER BY created_at DESC LIMIT 200";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(org_id.to_string()),
+        user_id.map(|id| ParameterValue::Str(id.to_string())).unwrap_or(ParameterValue::DbNull),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let transfers: Vec<serde_json::Value> = rows.rows.iter().map(|row| {
+        serde_json::json!({
+            "id": String::decode(&row[0]).unwrap_or_default(),
+            "actor_id": String::decode(&row[1]).unwrap_or_default(),
+            "from_user_id": String::decode(&row[2]).unwrap_or_default(),
+            "to_user_id": String::decode(&row[3]).unwrap_or_default(),
+            "book_ids": parse_json_column(&row[4]),
+            "revoked_grants": parse_json_column(&row[5]),
+            "reason": String::decode(&row[6]).unwrap_or_default(),
+            "created_at": String::decode(&row[7]).unwrap_or_default()
+        })
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({ "transfers": transfers }))
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+/// Org owners and admins manage their own organization; platform admins any
+fn require_org_admin(conn: &Connection, user_id: &Uuid, org_id: &Uuid) -> Result<(), ServiceError> {
+    if authorworks_access::is_admin(conn, user_id)? {
+        return Ok(());
+    }
+
+    let query = "SELECT 1 FROM users.organization_members
+                 WHERE org_id = $1 AND user_id = $2 AND role = ANY(string_to_array($3, ','))";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(org_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Str(ORG_ADMIN_ROLES.join(",")),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    if rows.rows.is_empty() {
+        return Err(ServiceError::Forbidden("Organization admin role required".into()));
+    }
+    Ok(())
+}
+
+fn member_role(conn: &Connection, org_id: &Uuid, user_id: &Uuid) -> Result<String, ServiceError> {
+    let query = "SELECT role FROM users.organization_members WHERE org_id = $1 AND user_id = $2";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(org_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    rows.rows.first()
+        .map(|row| String::decode(&row[0]).unwrap_or_default())
+        .ok_or_else(|| ServiceError::NotFound("User is not a member of this organization".into()))
+}
+
+/// Every listed book must be owned by the member within the org; returns the
+/// ids as a comma-separated filter
+fn validate_book_selection(
+    conn: &Connection,
+    org_id: &Uuid,
+    owner_id: &Uuid,
+    book_ids: &[Uuid],
+) -> Result<String, ServiceError> {
+    let mut unique: Vec<Uuid> = Vec::new();
+    for id in book_ids {
+        if !unique.contains(id) {
+            unique.push(*id);
+        }
+    }
+    if unique.is_empty() {
+        return Err(ServiceError::BadRequest("book_ids must not be empty; omit it to transfer every book".into()));
+    }
+    if unique.len() > MAX_TRANSFER_BOOKS {
+        return Err(ServiceError::BadRequest(format!("At most {} books per transfer", MAX_TRANSFER_BOOKS)));
+    }
+
+    let id_list = unique.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",");
+    let query = "SELECT COUNT(*) FROM content.books
+                 WHERE org_id = $1 AND author_id = $2 AND id::text = ANY(string_to_array($3, ','))";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(org_id.to_string()),
+        ParameterValue::Str(owner_id.to_string()),
+        ParameterValue::Str(id_list.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let found = rows.rows.first().map(|row| i64::decode(&row[0]).unwrap_or(0)).unwrap_or(0);
+    if found != unique.len() as i64 {
+        return Err(ServiceError::BadRequest(
+            "book_ids must all be books the member owns in this organization".into(),
+        ));
+    }
+    Ok(id_list)
+}
+
+fn owned_books(conn: &Connection, org_id: &Uuid, user_id: &Uuid) -> Result<Vec<OwnedBook>, ServiceError> {
+    let query = "SELECT b.id, b.title, COALESCE(b.status, 'draft'), COUNT(c.id), COALESCE(b.word_count, 0), b.updated_at
+                 FROM content.books b
+                 LEFT JOIN content.chapters c ON c.book_id = b.id
+                 WHERE b.org_id = $1 AND b.author_id = $2
+                 GROUP BY b.id
+                 ORDER BY b.updated_at DESC";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(org_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.iter().map(|row| {
+        OwnedBook {
+            id: String::decode(&row[0]).unwrap_or_default(),
+            title: String::decode(&row[1]).unwrap_or_default(),
+            status: String::decode(&row[2]).unwrap_or_default(),
+            chapter_count: i64::decode(&row[3]).unwrap_or(0),
+            word_count: i32::decode(&row[4]).unwrap_or(0),
+            updated_at: String::decode(&row[5]).ok(),
+        }
+    }).collect())
+}
+
+fn active_grants(conn: &Connection, org_id: &Uuid, user_id: &Uuid) -> Result<Vec<CollaboratorGrant>, ServiceError> {
+    let query = "SELECT g.id, g.book_id, b.title, g.role, g.granted_by, g.created_at
+                 FROM content.book_collaborators g
+                 JOIN content.books b ON b.id = g.book_id
+                 WHERE b.org_id = $1 AND g.user_id = $2 AND g.revoked_at IS NULL
+                 ORDER BY g.created_at DESC";
+    let rows = conn.query(query, &[
+        ParameterValue::Str(org_id.to_string()),
+        ParameterValue::Str(user_id.to_string()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(rows.rows.iter().map(|row| {
+        CollaboratorGrant {
+            id: String::decode(&row[0]).unwrap_or_default(),
+            book_id: String::decode(&row[1]).unwrap_or_default(),
+            book_title: String::decode(&row[2]).unwrap_or_default(),
+            role: String::decode(&row[3]).unwrap_or_default(),
+            granted_by: String::decode(&row[4]).ok(),
+            created_at: String::decode(&row[5]).unwrap_or_default(),
+        }
+    }).collect())
+}
+
+fn parse_json_column(value: &spin_sdk::pg::DbValue) -> serde_json::Value {
+    String::decode(value).ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_else(|| serde_json::json!([]))
+}
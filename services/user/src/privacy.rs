@@ -0,0 +1,265 @@
+//! Consent management
+//!
+//! Each user has one row in `users.consents` covering every purpose; users
+//! without a row get the defaults below. Every change is appended to
+//! `users.consent_history` with where it came from, so the state at any point
+//! in time can be reconstructed.
+//!
+//! Other services enforce consent by reading `users.consents` directly (reader
+//! segments) or through `POST /privacy/consents/check`, which pipelines that
+//! export user data (analytics exports, AI training sets) must filter through.
+//! That endpoint answers for arbitrary users, so it takes the internal service
+//! credential and the gateway does not proxy it.
+
+use crate::error::ServiceError;
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Response;
+use spin_sdk::pg::{Connection, Decode, ParameterValue};
+use chrono::Utc;
+use uuid::Uuid;
+
+/// Product analytics is opt-out; marketing and AI training are opt-in
+const DEFAULT_ANALYTICS: bool = true;
+const DEFAULT_MARKETING_EMAILS: bool = false;
+const DEFAULT_AI_TRAINING: bool = false;
+
+const MAX_CHECK_USERS: usize = 1000;
+
+//=============================================================================
+// Models
+//=============================================================================
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Purpose {
+    Analytics,
+    MarketingEmails,
+    AiTraining,
+}
+
+impl Purpose {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Purpose::Analytics => "analytics",
+            Purpose::MarketingEmails => "marketing_emails",
+            Purpose::AiTraining => "ai_training",
+        }
+    }
+
+    fn default_granted(&self) -> bool {
+        match self {
+            Purpose::Analytics => DEFAULT_ANALYTICS,
+            Purpose::MarketingEmails => DEFAULT_MARKETING_EMAILS,
+            Purpose::AiTraining => DEFAULT_AI_TRAINING,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PrivacyPreferences {
+    pub analytics: bool,
+    pub marketing_emails: bool,
+    pub ai_training: bool,
+    pub updated_at: Option<String>,
+}
+
+impl PrivacyPreferences {
+    fn get(&self, purpose: Purpose) -> bool {
+        match purpose {
+            Purpose::Analytics => self.analytics,
+            Purpose::MarketingEmails => self.marketing_emails,
+            Purpose::AiTraining => self.ai_training,
+        }
+    }
+
+    fn set(&mut self, purpose: Purpose, granted: bool) {
+        match purpose {
+            Purpose::Analytics => self.analytics = granted,
+            Purpose::MarketingEmails => self.marketing_emails = granted,
+            Purpose::AiTraining => self.ai_training = granted,
+        }
+    }
+}
+
+/// Omitted fields are left unchanged
+#[derive(Debug, Deserialize)]
+pub struct UpdatePrivacyRequest {
+    pub analytics: Option<bool>,
+    pub marketing_emails: Option<bool>,
+    pub ai_training: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConsentCheckRequest {
+    pub purpose: Purpose,
+    pub user_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConsentChange {
+    pub purpose: String,
+    pub granted: bool,
+    pub source: String,
+    pub user_agent: Option<String>,
+    pub created_at: String,
+}
+
+//=============================================================================
+// Endpoints
+//=============================================================================
+
+/// GET /privacy/preferences - Current consent for every purpose
+pub fn get_preferences(conn: &Connection, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let preferences = load(conn, user_id)?;
+    crate::json_response(200, serde_json::json!({ "preferences": preferences }))
+}
+
+/// PUT /privacy/preferences - Update consent and record each change
+pub fn update_preferences(
+    conn: &Connection,
+    user_id: &Uuid,
+    body: UpdatePrivacyRequest,
+    source: &str,
+    user_agent: Option<String>,
+) -> Result<Response, ServiceError> {
+    let mut preferences = load(conn, user_id)?;
+
+    let requested = [
+        (Purpose::Analytics, body.analytics),
+        (Purpose::MarketingEmails, body.marketing_emails),
+        (Purpose::AiTraining, body.ai_training),
+    ];
+    let changes: Vec<(Purpose, bool)> = requested.iter()
+        .filter_map(|(purpose, granted)| granted.map(|g| (*purpose, g)))
+        .filter(|(purpose, granted)| preferences.get(*purpose) != *granted)
+        .collect();
+
+    if changes.is_empty() {
+        return crate::json_response(200, serde_json::json!({ "preferences": preferences, "changed": [] }));
+    }
+
+    for (purpose, granted) in &changes {
+        preferences.set(*purpose, *granted);
+    }
+    let now = Utc::now().to_rfc3339();
+
+    let upsert = "INSERT INTO users.consents (user_id, analytics, marketing_emails, ai_training, updated_at)
+                  VALUES ($1, $2, $3, $4, $5)
+                  ON CONFLICT (user_id) DO UPDATE SET
+                  analytics = EXCLUDED.analytics,
+                  marketing_emails = EXCLUDED.marketing_emails,
+                  ai_training = EXCLUDED.ai_training,
+                  updated_at = EXCLUDED.updated_at";
+    conn.execute(upsert, &[
+        ParameterValue::Str(user_id.to_string()),
+        ParameterValue::Boolean(preferences.analytics),
+        ParameterValue::Boolean(preferences.marketing_emails),
+        ParameterValue::Boolean(preferences.ai_training),
+        ParameterValue::Str(now.clone()),
+    ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+
+    let history = "INSERT INTO users.consent_history (user_id, purpose, granted, source, user_agent, created_at)
+                   VALUES ($1, $2, $3, $4, $5, $6)";
+    for (purpose, granted) in &changes {
+        conn.execute(history, &[
+            ParameterValue::Str(user_id.to_string()),
+            ParameterValue::Str(purpose.as_str().to_string()),
+            ParameterValue::Boolean(*granted),
+            ParameterValue::Str(source.to_string()),
+            user_agent.clone().map(ParameterValue::Str).unwrap_or(ParameterValue::DbNull),
+            ParameterValue::Str(now.clone()),
+        ]).map_err(|e| ServiceError::Internal(format!("Insert failed: {}", e)))?;
+    }
+
+    preferences.updated_at = Some(now);
+    let changed: Vec<&str> = changes.iter().map(|(purpose, _)| purpose.as_str()).collect();
+
+    crate::json_response(200, serde_json::json!({
+        "preferences": preferences,
+        "changed": changed
+    }))
+}
+
+/// GET /privacy/preferences/history - Consent changes, newest first
+pub fn get_history(conn: &Connection, user_id: &Uuid) -> Result<Response, ServiceError> {
+    let query = "SELECT purpose, granted, source, user_agent, created_at
+                 FROM users.consent_history
+                 WHERE user_id = $1
+                 ORDER BY created_at DESC
+                 LIMIT 200";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let history: Vec<ConsentChange> = rows.rows.iter().map(|row| {
+        ConsentChange {
+            purpose: String::decode(&row[0]).unwrap_or_default(),
+            granted: bool::decode(&row[1]).unwrap_or(false),
+            source: String::decode(&row[2]).unwrap_or_default(),
+            user_agent: String::decode(&row[3]).ok(),
+            created_at: String::decode(&row[4]).unwrap_or_default(),
+        }
+    }).collect();
+
+    crate::json_response(200, serde_json::json!({ "history": history }))
+}
+
+/// POST /privacy/consents/check - The subset of `user_ids` that consented to
+/// `purpose`, for pipelines that export user data
+pub fn check_consents(conn: &Connection, body: ConsentCheckRequest) -> Result<Response, ServiceError> {
+    if body.user_ids.len() > MAX_CHECK_USERS {
+        return Err(ServiceError::BadRequest(format!("At most {} user_ids per check", MAX_CHECK_USERS)));
+    }
+    if body.user_ids.is_empty() {
+        return crate::json_response(200, serde_json::json!({ "purpose": body.purpose, "consented": [] }));
+    }
+
+    // The purpose selects a fixed column name, never user input
+    let query = format!(
+        "SELECT u.id FROM users.users u
+         LEFT JOIN users.consents c ON c.user_id = u.id
+         WHERE u.id::text = ANY(string_to_array($1, ','))
+           AND COALESCE(c.{}, $2)",
+        body.purpose.as_str()
+    );
+    let ids: Vec<String> = body.user_ids.iter().map(|id| id.to_string()).collect();
+    let rows = conn.query(&query, &[
+        ParameterValue::Str(ids.join(",")),
+        ParameterValue::Boolean(body.purpose.default_granted()),
+    ]).map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    let consented: Vec<Uuid> = rows.rows.iter()
+        .filter_map(|row| String::decode(&row[0]).ok())
+        .filter_map(|id| Uuid::parse_str(&id).ok())
+        .collect();
+
+    crate::json_response(200, serde_json::json!({
+        "purpose": body.purpose,
+        "consented": consented
+    }))
+}
+
+//=============================================================================
+// Helpers
+//=============================================================================
+
+fn load(conn: &Connection, user_id: &Uuid) -> Result<PrivacyPreferences, ServiceError> {
+    let query = "SELECT analytics, marketing_emails, ai_training, updated_at
+                 FROM users.consents WHERE user_id = $1";
+    let rows = conn.query(query, &[ParameterValue::Str(user_id.to_string())])
+        .map_err(|e| ServiceError::Internal(format!("Query failed: {}", e)))?;
+
+    Ok(match rows.rows.first() {
+        Some(row) => PrivacyPreferences {
+            analytics: bool::decode(&row[0]).unwrap_or(DEFAULT_ANALYTICS),
+            marketing_emails: bool::decode(&row[1]).unwrap_or(DEFAULT_MARKETING_EMAILS),
+            ai_training: bool::decode(&row[2]).unwrap_or(DEFAULT_AI_TRAINING),
+            updated_at: String::decode(&row[3]).ok(),
+        },
+        None => PrivacyPreferences {
+            analytics: DEFAULT_ANALYTICS,
+            marketing_emails: DEFAULT_MARKETING_EMAILS,
+            ai_training: DEFAULT_AI_TRAINING,
+            updated_at: None,
+        },
+    })
+}
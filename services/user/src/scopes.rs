@@ -0,0 +1,118 @@
+//! API scope model
+//!
+//! First-party sessions carry no scope list and keep full access. API keys,
+//! share tokens, and collaborator roles carry an explicit list; the gateway
+//! forwards it to services as the space-separated `X-Scopes` header, and each
+//! service maps its routes to the scope they require (see
+//! `authorworks-access`). Here a delegated credential reaches only the
+//! `/auth/*` flows and public profiles; keys, consent and the account itself
+//! stay first-party.
+
+use crate::error::ServiceError;
+use authorworks_access::{scopes, Route};
+use serde::{Deserialize, Serialize};
+use spin_sdk::http::Method;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "books:read")]
+    BooksRead,
+    #[serde(rename = "books:write")]
+    BooksWrite,
+    #[serde(rename = "chapters:read")]
+    ChaptersRead,
+    #[serde(rename = "chapters:write")]
+    ChaptersWrite,
+    #[serde(rename = "comments:read")]
+    CommentsRead,
+    #[serde(rename = "comments:write")]
+    CommentsWrite,
+    #[serde(rename = "exports:create")]
+    ExportsCreate,
+    #[serde(rename = "analytics:read")]
+    AnalyticsRead,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 8] = [
+        Scope::BooksRead,
+        Scope::BooksWrite,
+        Scope::ChaptersRead,
+        Scope::ChaptersWrite,
+        Scope::CommentsRead,
+        Scope::CommentsWrite,
+        Scope::ExportsCreate,
+        Scope::AnalyticsRead,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::BooksRead => scopes::BOOKS_READ,
+            Scope::BooksWrite => scopes::BOOKS_WRITE,
+            Scope::ChaptersRead => scopes::CHAPTERS_READ,
+            Scope::ChaptersWrite => scopes::CHAPTERS_WRITE,
+            Scope::CommentsRead => scopes::COMMENTS_READ,
+            Scope::CommentsWrite => scopes::COMMENTS_WRITE,
+            Scope::ExportsCreate => scopes::EXPORTS_CREATE,
+            Scope::AnalyticsRead => scopes::ANALYTICS_READ,
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Scope> {
+        Scope::ALL.iter().copied().find(|s| s.as_str() == value)
+    }
+}
+
+/// Validate and de-duplicate a requested scope list
+pub fn parse_scopes(values: &[String]) -> Result<Vec<Scope>, ServiceError> {
+    if values.is_empty() {
+        return Err(ServiceError::BadRequest("At least one scope is required".into()));
+    }
+
+    let mut scopes = Vec::new();
+    for value in values {
+        let scope = Scope::parse(value)
+            .ok_or_else(|| ServiceError::BadRequest(format!("Unknown scope: {}", value)))?;
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    Ok(scopes)
+}
+
+/// Scopes granted by a collaborator role
+pub fn role_scopes(role: &str) -> Option<Vec<Scope>> {
+    match role {
+        "viewer" => Some(vec![Scope::BooksRead, Scope::ChaptersRead, Scope::CommentsRead]),
+        "commenter" => Some(vec![
+            Scope::BooksRead,
+            Scope::ChaptersRead,
+            Scope::CommentsRead,
+            Scope::CommentsWrite,
+        ]),
+        "editor" => Some(vec![
+            Scope::BooksRead,
+            Scope::ChaptersRead,
+            Scope::ChaptersWrite,
+            Scope::CommentsRead,
+            Scope::CommentsWrite,
+            Scope::ExportsCreate,
+        ]),
+        _ => None,
+    }
+}
+
+/// Render scopes in the `X-Scopes` header format
+pub fn to_header(scopes: &[Scope]) -> String {
+    scopes.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(" ")
+}
+
+/// Routes of this service a delegated credential may reach. `/auth/*`
+/// handlers check the bearer token themselves.
+pub fn route_scope(method: &Method, path: &str) -> Option<Route> {
+    match method {
+        _ if path.starts_with("/auth/") => Some(Route::Open),
+        Method::Get if path.starts_with("/users/") && path != "/users/me" => Some(Route::Open),
+        _ => None,
+    }
+}
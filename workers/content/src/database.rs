@@ -4,7 +4,7 @@ use anyhow::{Context, Result};
 use sqlx::{postgres::PgPoolOptions, PgPool, Row};
 use uuid::Uuid;
 
-use crate::{Book, Chapter, ChapterSummary, ContentJob};
+use crate::{Book, Chapter, ChapterEmbedding, ChapterSummary, ContentJob, RelatedSuggestion, ScenePlan};
 
 pub struct Database {
     pool: PgPool,
@@ -106,6 +106,19 @@ impl Database {
         Ok(())
     }
 
+    /// Whether the author opted the book out of AI features. Jobs queued
+    /// before the flag was set must not run.
+    pub async fn is_ai_disabled(&self, book_id: &Uuid) -> Result<bool> {
+        let disabled: Option<bool> = sqlx::query_scalar(
+            "SELECT COALESCE(ai_disabled, false) FROM content.books WHERE id = $1"
+        )
+        .bind(book_id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(disabled.unwrap_or(false))
+    }
+
     pub async fn get_book(&self, book_id: &Uuid) -> Result<Option<Book>> {
         let row = sqlx::query(
             r#"
@@ -248,6 +261,54 @@ impl Database {
         Ok(())
     }
 
+    pub async fn save_chapter_plan(&self, chapter_id: &Uuid, plan: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE content.chapters
+            SET metadata = jsonb_set(COALESCE(metadata, '{}'), '{plan}', $2::jsonb), updated_at = NOW()
+            WHERE id = $1
+            "#
+        )
+        .bind(chapter_id.to_string())
+        .bind(serde_json::json!(plan).to_string())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Replace a chapter's scenes with a planned breakdown, so a retried
+    /// planning job does not leave duplicates behind
+    pub async fn replace_chapter_scenes(&self, chapter_id: &Uuid, scenes: &[ScenePlan]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM content.scenes WHERE chapter_id = $1::uuid")
+            .bind(chapter_id.to_string())
+            .execute(&mut *tx)
+            .await?;
+
+        for (i, scene) in scenes.iter().enumerate() {
+            sqlx::query(
+                r#"
+                INSERT INTO content.scenes (id, chapter_id, title, scene_number, pov_character, location, notes, created_at, updated_at)
+                VALUES ($1::uuid, $2::uuid, $3, $4, $5, $6, $7, NOW(), NOW())
+                "#
+            )
+            .bind(Uuid::new_v4().to_string())
+            .bind(chapter_id.to_string())
+            .bind(&scene.title)
+            .bind((i + 1) as i32)
+            .bind(scene.pov_character.as_deref())
+            .bind(scene.location.as_deref())
+            .bind(&scene.summary)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
     pub async fn update_book_metadata(&self, book_id: &Uuid, metadata: serde_json::Value) -> Result<()> {
         sqlx::query(
             r#"
@@ -279,5 +340,175 @@ impl Database {
 
         Ok(())
     }
-}
 
+    pub async fn get_chapters_for_analysis(&self, book_id: &Uuid, chapter_ids: &[Uuid]) -> Result<Vec<Chapter>> {
+        let ids: Vec<String> = chapter_ids.iter().map(|id| id.to_string()).collect();
+        let rows = sqlx::query(
+            r#"
+            SELECT id, book_id, title, chapter_number, content
+            FROM content.chapters
+            WHERE book_id = $1 AND id::text = ANY($2)
+            ORDER BY chapter_number ASC
+            "#
+        )
+        .bind(book_id.to_string())
+        .bind(&ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(|r| {
+            let id: String = r.get("id");
+            let book_id: String = r.get("book_id");
+            Ok(Chapter {
+                id: Uuid::parse_str(&id)?,
+                book_id: Uuid::parse_str(&book_id)?,
+                title: r.get("title"),
+                chapter_number: r.get("chapter_number"),
+                content: r.try_get("content").ok(),
+            })
+        }).collect()
+    }
+
+    pub async fn complete_analysis_report(&self, report_id: &Uuid, findings: serde_json::Value, summary: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE content.analysis_reports
+            SET status = 'completed', findings = $2::jsonb, summary = $3, completed_at = NOW()
+            WHERE id = $1
+            "#
+        )
+        .bind(report_id.to_string())
+        .bind(findings.to_string())
+        .bind(summary)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn fail_analysis_report(&self, report_id: &Uuid, error: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE content.analysis_reports
+            SET status = 'failed', error = $2, completed_at = NOW()
+            WHERE id = $1
+            "#
+        )
+        .bind(report_id.to_string())
+        .bind(error)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn fail_analysis_reports_for_job(&self, job_id: &Uuid, error: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE content.analysis_reports
+            SET status = 'failed', error = $2, completed_at = NOW()
+            WHERE job_id = $1 AND status = 'pending'
+            "#
+        )
+        .bind(job_id.to_string())
+        .bind(error)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Chapters with content, each joined to its stored embedding when that
+    /// was made by `model` from the chapter's current text
+    pub async fn get_chapters_for_embedding(&self, book_id: &Uuid, model: &str) -> Result<Vec<ChapterEmbedding>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT c.id::text AS id, c.chapter_number, c.title,
+                   COALESCE(c.content, '') AS content,
+                   md5(COALESCE(c.content, '')) AS content_md5,
+                   e.embedding
+            FROM content.chapters c
+            LEFT JOIN content.chapter_embeddings e
+              ON e.chapter_id = c.id AND e.model = $2 AND e.content_md5 = md5(COALESCE(c.content, ''))
+            WHERE c.book_id = $1::uuid AND COALESCE(c.word_count, 0) > 0
+            ORDER BY c.chapter_number ASC
+            "#
+        )
+        .bind(book_id.to_string())
+        .bind(model)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(|r| {
+            let id: String = r.get("id");
+            Ok(ChapterEmbedding {
+                id: Uuid::parse_str(&id)?,
+                chapter_number: r.get("chapter_number"),
+                title: r.get("title"),
+                content: r.get("content"),
+                content_md5: r.get("content_md5"),
+                embedding: r.try_get::<Option<Vec<f64>>, _>("embedding").ok().flatten(),
+            })
+        }).collect()
+    }
+
+    pub async fn save_chapter_embedding(&self, chapter_id: &Uuid, model: &str, content_md5: &str, embedding: &[f64]) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO content.chapter_embeddings (chapter_id, model, content_md5, embedding, updated_at)
+            VALUES ($1::uuid, $2, $3, $4, NOW())
+            ON CONFLICT (chapter_id) DO UPDATE
+            SET model = EXCLUDED.model, content_md5 = EXCLUDED.content_md5,
+                embedding = EXCLUDED.embedding, updated_at = NOW()
+            "#
+        )
+        .bind(chapter_id.to_string())
+        .bind(model)
+        .bind(content_md5)
+        .bind(embedding)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Swap the book's visible automatic suggestions for a fresh set. Author
+    /// links are left alone, and suggestions the author hid stay hidden.
+    pub async fn replace_related_suggestions(&self, book_id: &Uuid, suggestions: &[RelatedSuggestion]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            DELETE FROM content.related_chapters
+            WHERE book_id = $1::uuid AND source = 'auto' AND hidden = false
+            "#
+        )
+        .bind(book_id.to_string())
+        .execute(&mut *tx)
+        .await?;
+
+        for suggestion in suggestions {
+            sqlx::query(
+                r#"
+                INSERT INTO content.related_chapters
+                    (id, book_id, chapter_id, related_chapter_id, score, source, position, created_at, updated_at)
+                VALUES ($1::uuid, $2::uuid, $3::uuid, $4::uuid, $5, 'auto', $6, NOW(), NOW())
+                ON CONFLICT (chapter_id, related_chapter_id) DO UPDATE
+                SET score = EXCLUDED.score, updated_at = NOW()
+                WHERE content.related_chapters.source = 'auto'
+                "#
+            )
+            .bind(Uuid::new_v4().to_string())
+            .bind(book_id.to_string())
+            .bind(suggestion.chapter_id.to_string())
+            .bind(suggestion.related_chapter_id.to_string())
+            .bind(suggestion.score)
+            .bind(suggestion.position)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
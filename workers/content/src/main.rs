@@ -2,7 +2,9 @@
 //!
 //! Background worker for AI content generation supporting multiple LLM providers.
 //! Uses the shared book_generator library for LLM abstraction (Anthropic, OpenAI, Ollama).
-//! Processes jobs from the queue and generates book outlines, chapters, and content enhancements.
+//! Processes jobs from the queue and generates book outlines, chapter and scene plans, chapters,
+//! and content enhancements, and runs manuscript analysis such as continuity checks and
+//! related-chapter detection.
 
 use anyhow::{Context, Result};
 use chrono::Utc;
@@ -72,6 +74,7 @@ struct Config {
     database_url: String,
     llm_provider: String,
     model: String,
+    embedding_model: String,
     rabbitmq_url: Option<String>,
 }
 
@@ -81,6 +84,7 @@ impl Config {
             database_url: env::var("DATABASE_URL").context("DATABASE_URL not set")?,
             llm_provider: env::var("LLM_PROVIDER").unwrap_or_else(|_| "ollama".to_string()),
             model: env::var("MODEL").unwrap_or_else(|_| "deepseek-coder-v2:16b".to_string()),
+            embedding_model: env::var("EMBEDDING_MODEL").unwrap_or_else(|_| "text-embedding-3-small".to_string()),
             rabbitmq_url: env::var("RABBITMQ_URL").ok(),
         })
     }
@@ -98,11 +102,24 @@ async fn process_next_job(db: &Database, llm_client: &llm::Client, config: &Conf
     // Mark as processing
     db.update_job_status(&job.id, "processing", None).await?;
 
+    // The author may have opted the book out of AI after the job was queued
+    if db.is_ai_disabled(&job.book_id).await? {
+        let reason = "AI features are disabled for this book";
+        warn!("Job {} skipped: {}", job.id, reason);
+        db.fail_analysis_reports_for_job(&job.id, reason).await?;
+        db.fail_job(&job.id, reason).await?;
+        return Ok(true);
+    }
+
     // Process based on job type
     let result = match job.job_type.as_str() {
         "outline" => generate_outline(db, llm_client, config, &job).await,
         "chapter" => generate_chapter(db, llm_client, config, &job).await,
+        "chapter_plan" => plan_chapter(db, llm_client, config, &job).await,
+        "scenes" => plan_scenes(db, llm_client, config, &job).await,
         "enhance" => enhance_content(db, llm_client, config, &job).await,
+        "continuity" => check_continuity(db, llm_client, config, &job).await,
+        "related_chapters" => find_related_chapters(db, llm_client, config, &job).await,
         other => {
             warn!("Unknown job type: {}", other);
             Err(anyhow::anyhow!("Unknown job type: {}", other))
@@ -153,10 +170,12 @@ async fn generate_outline(
         input.style.as_deref().unwrap_or("engaging and modern"),
         input.chapter_count.unwrap_or(10),
         &input.prompt,
+        &input.series.as_ref().map(build_series_context).unwrap_or_default(),
+        &input.lore.as_ref().map(build_lore_context).unwrap_or_default(),
     );
     
     // Combine system and user prompts for the LLM
-    let full_prompt = format!("{}\n\n{}", system_prompt, user_prompt);
+    let full_prompt = format!("{}\n\n{}", with_persona(system_prompt, input.persona.as_ref()), user_prompt);
 
     // Call LLM API
     let result = llm_client.generate_with_options(&config.model, &full_prompt, Some(8000))
@@ -199,6 +218,110 @@ struct OutlineInput {
     genre: Option<String>,
     style: Option<String>,
     chapter_count: Option<i32>,
+    #[serde(default)]
+    series: Option<SeriesContext>,
+    #[serde(default)]
+    lore: Option<LoreContext>,
+    #[serde(default)]
+    persona: Option<PersonaContext>,
+}
+
+/// Earlier books of the series, attached by the content service when queuing
+#[derive(Debug, Deserialize)]
+struct SeriesContext {
+    series_title: String,
+    book_number: i32,
+    previous_books: Vec<SeriesBookSummary>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SeriesBookSummary {
+    title: String,
+    book_number: i32,
+    synopsis: String,
+}
+
+fn build_series_context(series: &SeriesContext) -> String {
+    let mut context = format!(
+        "**Series:** Book {} of \"{}\"\n\nEarlier books:\n",
+        series.book_number, series.series_title
+    );
+    for book in &series.previous_books {
+        context.push_str(&format!("Book {}: {}\n", book.book_number, book.title));
+        if !book.synopsis.is_empty() {
+            context.push_str(&format!("Synopsis: {}\n", book.synopsis));
+        }
+        context.push('\n');
+    }
+    context
+}
+
+/// World lore relevant to the job, picked by the content service
+#[derive(Debug, Deserialize)]
+struct LoreContext {
+    entries: Vec<LoreEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LoreEntry {
+    name: String,
+    kind: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+    summary: Option<String>,
+}
+
+fn build_lore_context(lore: &LoreContext) -> String {
+    if lore.entries.is_empty() {
+        return String::new();
+    }
+
+    let mut context = String::from("**World lore:**\n");
+    for entry in &lore.entries {
+        context.push_str(&format!("- {} ({})", entry.name, entry.kind));
+        if !entry.aliases.is_empty() {
+            context.push_str(&format!(", also called {}", entry.aliases.join(", ")));
+        }
+        if let Some(summary) = entry.summary.as_deref().filter(|s| !s.is_empty()) {
+            context.push_str(&format!(": {}", summary));
+        }
+        context.push('\n');
+    }
+    context.push('\n');
+    context
+}
+
+/// The book's active co-writing persona, attached to every generation job
+#[derive(Debug, Deserialize)]
+struct PersonaContext {
+    name: String,
+    tone: Option<String>,
+    vocabulary: Option<String>,
+    #[serde(default)]
+    banned_words: Vec<String>,
+    memory: Option<String>,
+}
+
+/// System prompt with the persona's voice appended, so every job writes alike
+fn with_persona(system_prompt: &str, persona: Option<&PersonaContext>) -> String {
+    let Some(persona) = persona else {
+        return system_prompt.to_string();
+    };
+
+    let mut prompt = format!("{}\n\n**Voice:** write as the persona \"{}\".\n", system_prompt, persona.name);
+    if let Some(tone) = persona.tone.as_deref().filter(|t| !t.is_empty()) {
+        prompt.push_str(&format!("Tone: {}\n", tone));
+    }
+    if let Some(vocabulary) = persona.vocabulary.as_deref().filter(|v| !v.is_empty()) {
+        prompt.push_str(&format!("Vocabulary: {}\n", vocabulary));
+    }
+    if !persona.banned_words.is_empty() {
+        prompt.push_str(&format!("Never use these words or phrases: {}\n", persona.banned_words.join(", ")));
+    }
+    if let Some(memory) = persona.memory.as_deref().filter(|m| !m.is_empty()) {
+        prompt.push_str(&format!("Standing instructions:\n{}\n", memory));
+    }
+    prompt
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -316,7 +439,9 @@ async fn generate_chapter(
     let previous_chapters = db
         .get_previous_chapters(&chapter.book_id, chapter.chapter_number)
         .await?;
-    let context = build_chapter_context(&previous_chapters);
+    let mut context = input.series.as_ref().map(build_series_context).unwrap_or_default();
+    context.push_str(&input.lore.as_ref().map(build_lore_context).unwrap_or_default());
+    context.push_str(&build_chapter_context(&previous_chapters));
 
     // Build prompt with system context
     let system_prompt = "You are a skilled fiction writer. Write engaging, immersive prose that brings stories to life.";
@@ -329,7 +454,7 @@ async fn generate_chapter(
         input.style.as_deref().unwrap_or("engaging, descriptive"),
     );
     
-    let full_prompt = format!("{}\n\n{}", system_prompt, user_prompt);
+    let full_prompt = format!("{}\n\n{}", with_persona(system_prompt, input.persona.as_ref()), user_prompt);
 
     // Call LLM API with higher token limit for full chapters
     let result = llm_client.generate_with_options(&config.model, &full_prompt, Some(16000))
@@ -357,6 +482,12 @@ struct ChapterInput {
     outline: Option<String>,
     context: Option<String>,
     style: Option<String>,
+    #[serde(default)]
+    series: Option<SeriesContext>,
+    #[serde(default)]
+    lore: Option<LoreContext>,
+    #[serde(default)]
+    persona: Option<PersonaContext>,
 }
 
 fn build_chapter_context(chapters: &[ChapterSummary]) -> String {
@@ -374,6 +505,135 @@ fn build_chapter_context(chapters: &[ChapterSummary]) -> String {
     context
 }
 
+//=============================================================================
+// Chapter and Scene Planning
+//=============================================================================
+
+/// Scenes planned per chapter at most, whatever the model returns
+const MAX_PLANNED_SCENES: usize = 12;
+
+async fn plan_chapter(
+    db: &Database,
+    llm_client: &llm::Client,
+    config: &Config,
+    job: &ContentJob,
+) -> Result<serde_json::Value> {
+    let input: ChapterPlanInput = serde_json::from_value(job.input.clone())?;
+
+    let book = db
+        .get_book(&input.book_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Book not found"))?;
+
+    let system_prompt = "You are a professional author and story editor. Plan chapters that are purposeful, well paced and consistent with the book.";
+    let user_prompt = prompts::build_chapter_plan_prompt(
+        &book.title,
+        &input.synopsis,
+        input.chapter_number,
+        &input.title,
+        &input.outline,
+        input.style.as_deref().unwrap_or("engaging and modern"),
+    );
+    let full_prompt = format!("{}\n\n{}", with_persona(system_prompt, input.persona.as_ref()), user_prompt);
+
+    let result = llm_client.generate_with_options(&config.model, &full_prompt, Some(2000))
+        .await
+        .map_err(|e| anyhow::anyhow!("LLM generation failed: {}", e))?;
+    let plan = result.text.trim().to_string();
+    if plan.is_empty() {
+        return Err(anyhow::anyhow!("Empty chapter plan"));
+    }
+
+    db.save_chapter_plan(&input.chapter_id, &plan).await?;
+
+    Ok(serde_json::json!({
+        "chapter_id": input.chapter_id,
+        "plan_words": plan.split_whitespace().count()
+    }))
+}
+
+async fn plan_scenes(
+    db: &Database,
+    llm_client: &llm::Client,
+    config: &Config,
+    job: &ContentJob,
+) -> Result<serde_json::Value> {
+    let input: ScenesInput = serde_json::from_value(job.input.clone())?;
+
+    let book = db
+        .get_book(&input.book_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Book not found"))?;
+
+    let system_prompt = "You are a professional author and story editor. Break chapters into clear, dramatic scenes.";
+    let user_prompt = prompts::build_scenes_prompt(
+        &book.title,
+        input.chapter_number,
+        &input.title,
+        &input.plan,
+        input.scene_count,
+    );
+    let full_prompt = format!("{}\n\n{}", with_persona(system_prompt, input.persona.as_ref()), user_prompt);
+
+    let result = llm_client.generate_with_options(&config.model, &full_prompt, Some(3000))
+        .await
+        .map_err(|e| anyhow::anyhow!("LLM generation failed: {}", e))?;
+
+    let mut scenes = parse_scenes_response(&result.text)?;
+    scenes.truncate(MAX_PLANNED_SCENES);
+    db.replace_chapter_scenes(&input.chapter_id, &scenes).await?;
+
+    Ok(serde_json::json!({
+        "chapter_id": input.chapter_id,
+        "scenes": scenes.len()
+    }))
+}
+
+fn parse_scenes_response(response: &str) -> Result<Vec<ScenePlan>> {
+    // Models sometimes wrap the JSON in prose or code fences
+    let start = response.find('{').ok_or_else(|| anyhow::anyhow!("No JSON in scene response"))?;
+    let end = response.rfind('}').ok_or_else(|| anyhow::anyhow!("No JSON in scene response"))?;
+    let parsed: ScenesResponse = serde_json::from_str(&response[start..=end])
+        .context("Failed to parse scene response")?;
+    if parsed.scenes.is_empty() {
+        return Err(anyhow::anyhow!("Scene response contained no scenes"));
+    }
+    Ok(parsed.scenes)
+}
+
+#[derive(Debug, Deserialize)]
+struct ChapterPlanInput {
+    book_id: Uuid,
+    chapter_id: Uuid,
+    title: String,
+    chapter_number: i32,
+    #[serde(default)]
+    outline: String,
+    #[serde(default)]
+    synopsis: String,
+    style: Option<String>,
+    #[serde(default)]
+    persona: Option<PersonaContext>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScenesInput {
+    book_id: Uuid,
+    chapter_id: Uuid,
+    title: String,
+    chapter_number: i32,
+    #[serde(default)]
+    plan: String,
+    scene_count: i32,
+    #[serde(default)]
+    persona: Option<PersonaContext>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScenesResponse {
+    scenes: Vec<ScenePlan>,
+}
+
 //=============================================================================
 // Content Enhancement
 //=============================================================================
@@ -393,7 +653,7 @@ async fn enhance_content(
         input.instructions.as_deref(),
     );
     
-    let full_prompt = format!("{}\n\n{}", system_prompt, user_prompt);
+    let full_prompt = format!("{}\n\n{}", with_persona(system_prompt, input.persona.as_ref()), user_prompt);
 
     let result = llm_client.generate_with_options(&config.model, &full_prompt, Some(8000))
         .await
@@ -421,6 +681,282 @@ struct EnhanceInput {
     content: String,
     enhancement_type: String,
     instructions: Option<String>,
+    #[serde(default)]
+    persona: Option<PersonaContext>,
+}
+
+//=============================================================================
+// Continuity Check
+//=============================================================================
+
+/// Characters of chapter text sent per LLM call
+const CONTINUITY_WINDOW_CHARS: usize = 48_000;
+
+const FINDING_CATEGORIES: [&str; 4] = ["character", "timeline", "plot_hole", "setting"];
+const FINDING_SEVERITIES: [&str; 3] = ["low", "medium", "high"];
+
+async fn check_continuity(
+    db: &Database,
+    llm_client: &llm::Client,
+    config: &Config,
+    job: &ContentJob,
+) -> Result<serde_json::Value> {
+    let input: ContinuityInput = serde_json::from_value(job.input.clone())?;
+
+    match run_continuity_check(db, llm_client, config, &input).await {
+        Ok(output) => Ok(output),
+        Err(e) => {
+            db.fail_analysis_report(&input.report_id, &e.to_string()).await?;
+            Err(e)
+        }
+    }
+}
+
+async fn run_continuity_check(
+    db: &Database,
+    llm_client: &llm::Client,
+    config: &Config,
+    input: &ContinuityInput,
+) -> Result<serde_json::Value> {
+    let book = db
+        .get_book(&input.book_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Book not found"))?;
+    let chapters = db
+        .get_chapters_for_analysis(&input.book_id, &input.chapter_ids)
+        .await?;
+    if chapters.is_empty() {
+        return Err(anyhow::anyhow!("No chapters to analyze"));
+    }
+
+    // Chapters are checked in order, in windows that fit the prompt. Story
+    // notes carry what earlier windows established into later ones.
+    let system_prompt = "You are a meticulous continuity editor. You find contradictions in manuscripts and report them precisely.";
+    let genre = book.genre.clone().unwrap_or_else(|| "fiction".to_string());
+    let mut story_notes = String::from("(none yet - these are the first chapters)");
+    let mut findings: Vec<ContinuityFinding> = Vec::new();
+
+    for window in continuity_windows(&chapters) {
+        let user_prompt = prompts::build_continuity_prompt(&book.title, &genre, &story_notes, &window);
+        let full_prompt = format!("{}\n\n{}", system_prompt, user_prompt);
+
+        let result = llm_client.generate_with_options(&config.model, &full_prompt, Some(4000))
+            .await
+            .map_err(|e| anyhow::anyhow!("LLM generation failed: {}", e))?;
+
+        let response = parse_continuity_response(&result.text)?;
+        findings.extend(response.findings);
+        if !response.notes.trim().is_empty() {
+            story_notes = response.notes;
+        }
+    }
+
+    // Anchor findings to chapters and drop anything that doesn't fit the schema
+    let anchored: Vec<serde_json::Value> = findings.into_iter()
+        .filter(|f| FINDING_CATEGORIES.contains(&f.category.as_str()))
+        .map(|f| {
+            let severity = if FINDING_SEVERITIES.contains(&f.severity.as_str()) { f.severity } else { "medium".to_string() };
+            let chapter_id = f.chapter_number
+                .and_then(|n| chapters.iter().find(|c| c.chapter_number == n))
+                .map(|c| c.id);
+            serde_json::json!({
+                "category": f.category,
+                "severity": severity,
+                "chapter_id": chapter_id,
+                "chapter_number": f.chapter_number,
+                "related_chapter_number": f.related_chapter_number,
+                "description": f.description,
+                "excerpt": f.excerpt,
+                "suggestion": f.suggestion
+            })
+        })
+        .collect();
+
+    let count = |severity: &str| anchored.iter().filter(|f| f["severity"] == severity).count();
+    let summary = format!(
+        "{} findings across {} chapters ({} high, {} medium, {} low)",
+        anchored.len(),
+        chapters.len(),
+        count("high"),
+        count("medium"),
+        count("low")
+    );
+
+    db.complete_analysis_report(&input.report_id, serde_json::json!(anchored), &summary)
+        .await?;
+
+    Ok(serde_json::json!({
+        "report_id": input.report_id,
+        "findings": anchored.len(),
+        "summary": summary,
+        "analyzed_at": Utc::now().to_rfc3339()
+    }))
+}
+
+/// Group chapter texts into prompt-sized windows. A chapter longer than the
+/// window is truncated rather than split, so findings keep a single anchor.
+fn continuity_windows(chapters: &[Chapter]) -> Vec<String> {
+    let mut windows = Vec::new();
+    let mut current = String::new();
+
+    for chapter in chapters {
+        let content = chapter.content.as_deref().unwrap_or("");
+        let content: String = content.chars().take(CONTINUITY_WINDOW_CHARS).collect();
+        let section = format!("### Chapter {}: {}\n{}\n\n", chapter.chapter_number, chapter.title, content);
+
+        if !current.is_empty() && current.len() + section.len() > CONTINUITY_WINDOW_CHARS {
+            windows.push(std::mem::take(&mut current));
+        }
+        current.push_str(&section);
+    }
+    if !current.is_empty() {
+        windows.push(current);
+    }
+    windows
+}
+
+fn parse_continuity_response(response: &str) -> Result<ContinuityResponse> {
+    // Models sometimes wrap the JSON in prose or code fences
+    let start = response.find('{');
+    let end = response.rfind('}');
+    let json = match (start, end) {
+        (Some(start), Some(end)) if end > start => &response[start..=end],
+        _ => return Err(anyhow::anyhow!("Continuity response contained no JSON")),
+    };
+
+    serde_json::from_str(json).context("Failed to parse continuity response")
+}
+
+#[derive(Debug, Deserialize)]
+struct ContinuityInput {
+    book_id: Uuid,
+    report_id: Uuid,
+    chapter_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContinuityResponse {
+    #[serde(default)]
+    findings: Vec<ContinuityFinding>,
+    #[serde(default)]
+    notes: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContinuityFinding {
+    category: String,
+    severity: String,
+    chapter_number: Option<i32>,
+    related_chapter_number: Option<i32>,
+    description: String,
+    excerpt: Option<String>,
+    suggestion: Option<String>,
+}
+
+//=============================================================================
+// Related Chapters
+//=============================================================================
+
+/// Characters of chapter text embedded per chapter
+const EMBEDDING_INPUT_CHARS: usize = 24_000;
+
+/// Chapters embedded per request
+const EMBEDDING_BATCH_SIZE: usize = 16;
+
+/// Suggestions kept per chapter
+const RELATED_PER_CHAPTER: usize = 3;
+
+/// Cosine similarity below which chapters are not suggested
+const MIN_RELATED_SIMILARITY: f64 = 0.5;
+
+async fn find_related_chapters(
+    db: &Database,
+    llm_client: &llm::Client,
+    config: &Config,
+    job: &ContentJob,
+) -> Result<serde_json::Value> {
+    let input: RelatedChaptersInput = serde_json::from_value(job.input.clone())?;
+
+    let mut chapters = db
+        .get_chapters_for_embedding(&input.book_id, &config.embedding_model)
+        .await?;
+    if chapters.len() < 2 {
+        return Err(anyhow::anyhow!("At least two chapters with content are needed"));
+    }
+
+    // Only chapters whose text changed since the last run need new vectors
+    let stale: Vec<usize> = (0..chapters.len())
+        .filter(|&i| chapters[i].embedding.is_none())
+        .collect();
+
+    for batch in stale.chunks(EMBEDDING_BATCH_SIZE) {
+        let inputs: Vec<String> = batch.iter().map(|&i| {
+            let chapter = &chapters[i];
+            let content: String = chapter.content.chars().take(EMBEDDING_INPUT_CHARS).collect();
+            format!("{}\n\n{}", chapter.title, content)
+        }).collect();
+
+        let vectors = llm_client.embed(&config.embedding_model, &inputs)
+            .await
+            .map_err(|e| anyhow::anyhow!("Embedding failed: {}", e))?;
+
+        for (&i, vector) in batch.iter().zip(vectors) {
+            let vector: Vec<f64> = vector.into_iter().map(f64::from).collect();
+            db.save_chapter_embedding(&chapters[i].id, &config.embedding_model, &chapters[i].content_md5, &vector)
+                .await?;
+            chapters[i].embedding = Some(vector);
+        }
+    }
+
+    // Adjacent chapters are skipped; readers reach those through normal navigation
+    let mut suggestions = Vec::new();
+    for chapter in &chapters {
+        let Some(vector) = chapter.embedding.as_deref() else { continue };
+
+        let mut scored: Vec<(Uuid, f64)> = chapters.iter()
+            .filter(|other| (other.chapter_number - chapter.chapter_number).abs() > 1)
+            .filter_map(|other| Some((other.id, cosine_similarity(vector, other.embedding.as_deref()?))))
+            .filter(|(_, score)| *score >= MIN_RELATED_SIMILARITY)
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        for (position, (related_chapter_id, score)) in scored.into_iter().take(RELATED_PER_CHAPTER).enumerate() {
+            suggestions.push(RelatedSuggestion {
+                chapter_id: chapter.id,
+                related_chapter_id,
+                score,
+                position: position as i32,
+            });
+        }
+    }
+
+    db.replace_related_suggestions(&input.book_id, &suggestions).await?;
+
+    Ok(serde_json::json!({
+        "book_id": input.book_id,
+        "chapters": chapters.len(),
+        "embedded": stale.len(),
+        "suggestions": suggestions.len(),
+        "analyzed_at": Utc::now().to_rfc3339()
+    }))
+}
+
+fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+#[derive(Debug, Deserialize)]
+struct RelatedChaptersInput {
+    book_id: Uuid,
 }
 
 //=============================================================================
@@ -452,9 +988,38 @@ pub struct Chapter {
     pub content: Option<String>,
 }
 
+/// A chapter with its cached embedding, if still current for its text
+#[derive(Debug)]
+pub struct ChapterEmbedding {
+    pub id: Uuid,
+    pub chapter_number: i32,
+    pub title: String,
+    pub content: String,
+    pub content_md5: String,
+    pub embedding: Option<Vec<f64>>,
+}
+
+#[derive(Debug)]
+pub struct RelatedSuggestion {
+    pub chapter_id: Uuid,
+    pub related_chapter_id: Uuid,
+    pub score: f64,
+    pub position: i32,
+}
+
 #[derive(Debug)]
 pub struct ChapterSummary {
     pub chapter_number: i32,
     pub title: String,
     pub content_preview: Option<String>,
 }
+
+/// One scene of a chapter's breakdown, as returned by the model
+#[derive(Debug, Deserialize)]
+pub struct ScenePlan {
+    pub title: String,
+    #[serde(default)]
+    pub summary: String,
+    pub pov_character: Option<String>,
+    pub location: Option<String>,
+}
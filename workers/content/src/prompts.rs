@@ -7,14 +7,27 @@ pub fn build_outline_prompt(
     style: &str,
     chapter_count: i32,
     user_prompt: &str,
+    series_context: &str,
+    lore_context: &str,
 ) -> String {
+    let series = if series_context.is_empty() {
+        String::new()
+    } else {
+        format!("\n{}\nThis book continues the series: keep characters, world and established events consistent with the earlier books, and do not retell them.\n", series_context)
+    };
+    let lore = if lore_context.is_empty() {
+        String::new()
+    } else {
+        format!("\n{}Stay consistent with this established lore wherever the outline touches it.\n", lore_context)
+    };
+
     format!(r#"Create a detailed book outline for the following project:
 
 **Title:** {title}
 **Genre:** {genre}
 **Style:** {style}
 **Description:** {description}
-
+{series}{lore}
 **Author's Notes:** {user_prompt}
 
 Please create an outline with exactly {chapter_count} chapters. For each chapter, provide:
@@ -52,7 +65,9 @@ Make the outline engaging, with clear character development, rising tension, and
         style = style,
         description = description,
         user_prompt = user_prompt,
-        chapter_count = chapter_count
+        chapter_count = chapter_count,
+        series = series,
+        lore = lore
     )
 }
 
@@ -95,6 +110,75 @@ Write the full chapter text without meta-commentary. Begin directly with the cha
     )
 }
 
+pub fn build_chapter_plan_prompt(
+    book_title: &str,
+    synopsis: &str,
+    chapter_number: i32,
+    chapter_title: &str,
+    outline: &str,
+    style: &str,
+) -> String {
+    format!(r#"Plan Chapter {chapter_number} of "{book_title}" in detail before it is written.
+
+**Book Synopsis:**
+{synopsis}
+
+**Chapter Title:** {chapter_title}
+
+**Chapter Outline:**
+{outline}
+
+**Writing Style:** {style}
+
+Expand the outline into a chapter plan covering:
+1. The chapter's purpose in the story and the question it leaves the reader with
+2. The characters present, what each wants here, and how they change
+3. The sequence of beats from opening image to closing hook
+4. Setting details, tone and pacing notes
+
+Keep the plan under 400 words. Write the plan only, without meta-commentary."#,
+        chapter_number = chapter_number,
+        book_title = book_title,
+        synopsis = if synopsis.is_empty() { "Not available." } else { synopsis },
+        chapter_title = chapter_title,
+        outline = if outline.is_empty() { "Use the chapter title and synopsis to decide what happens." } else { outline },
+        style = style
+    )
+}
+
+pub fn build_scenes_prompt(
+    book_title: &str,
+    chapter_number: i32,
+    chapter_title: &str,
+    plan: &str,
+    scene_count: i32,
+) -> String {
+    format!(r#"Break Chapter {chapter_number} of "{book_title}" ("{chapter_title}") into exactly {scene_count} scenes.
+
+**Chapter Plan:**
+{plan}
+
+Each scene needs a short title, a 2-4 sentence summary of what happens and why it matters, the point-of-view character, and the location.
+
+Respond with JSON only, in exactly this shape:
+{{
+  "scenes": [
+    {{
+      "title": "Scene title",
+      "summary": "What happens",
+      "pov_character": "Name",
+      "location": "Where it takes place"
+    }}
+  ]
+}}"#,
+        chapter_number = chapter_number,
+        book_title = book_title,
+        chapter_title = chapter_title,
+        plan = plan,
+        scene_count = scene_count
+    )
+}
+
 pub fn build_enhancement_prompt(
     content: &str,
     enhancement_type: &str,
@@ -198,3 +282,52 @@ Make the character feel real and three-dimensional."#,
     )
 }
 
+
+pub fn build_continuity_prompt(
+    book_title: &str,
+    genre: &str,
+    story_notes: &str,
+    chapters: &str,
+) -> String {
+    format!(r#"Review the following chapters of "{book_title}" ({genre}) for continuity errors.
+
+**Established Story Notes (from earlier chapters):**
+{story_notes}
+
+**Chapters:**
+{chapters}
+
+Check for:
+1. **character** - name spellings, ages, physical traits, relationships, or knowledge that contradict earlier text
+2. **timeline** - events out of order, impossible durations, inconsistent dates, days, or seasons
+3. **plot_hole** - unresolved setups, contradictions in cause and effect, characters acting on information they could not have
+4. **setting** - locations, objects, or world rules described inconsistently
+
+Only report genuine inconsistencies, not style preferences. Rate severity as:
+- high: a reader would clearly notice and be confused
+- medium: noticeable on careful reading
+- low: minor slip
+
+Respond with JSON only, in exactly this shape:
+{{
+  "findings": [
+    {{
+      "category": "character",
+      "severity": "high",
+      "chapter_number": 4,
+      "related_chapter_number": 2,
+      "description": "What is inconsistent and why",
+      "excerpt": "Short quote from the chapter",
+      "suggestion": "How to fix it"
+    }}
+  ],
+  "notes": "Updated story notes: characters and their traits, timeline, key facts and open plot threads, including these chapters. Keep under 600 words."
+}}
+
+Use an empty findings array if there are no issues."#,
+        book_title = book_title,
+        genre = genre,
+        story_notes = story_notes,
+        chapters = chapters
+    )
+}
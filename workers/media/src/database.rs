@@ -179,5 +179,53 @@ impl Database {
 
         Ok(())
     }
-}
 
+    pub async fn has_derivative(&self, source_file_id: &Uuid, format: &str, bitrate_kbps: i32) -> Result<bool> {
+        let row = sqlx::query(
+            r#"
+            SELECT 1 AS found FROM storage.file_derivatives
+            WHERE source_file_id = $1 AND format = $2 AND bitrate_kbps = $3
+            "#
+        )
+        .bind(source_file_id.to_string())
+        .bind(format)
+        .bind(bitrate_kbps)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.is_some())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn link_derivative(
+        &self,
+        source_file_id: &Uuid,
+        derivative_file_id: &Uuid,
+        job_id: &Uuid,
+        format: &str,
+        bitrate_kbps: i32,
+        content_type: &str,
+        duration_seconds: Option<f64>,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO storage.file_derivatives
+                (id, source_file_id, derivative_file_id, job_id, format, bitrate_kbps, content_type, duration_seconds, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW())
+            ON CONFLICT (source_file_id, format, bitrate_kbps) DO NOTHING
+            "#
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(source_file_id.to_string())
+        .bind(derivative_file_id.to_string())
+        .bind(job_id.to_string())
+        .bind(format)
+        .bind(bitrate_kbps)
+        .bind(content_type)
+        .bind(duration_seconds)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -2,6 +2,8 @@
 //!
 //! Background worker for media processing using FFmpeg, ImageMagick, and AI APIs.
 //! Handles image transformations, audio processing, video encoding, and AI media generation.
+//! Also encodes the streamable variants queued by the storage service's
+//! `POST /files/:id/transcode`.
 
 use anyhow::{Context, Result};
 use chrono::Utc;
@@ -98,6 +100,7 @@ async fn process_next_job(db: &Database, s3: &S3Client, config: &Config) -> Resu
         "image" => process_image_job(db, s3, config, &job).await,
         "audio" => process_audio_job(db, s3, config, &job).await,
         "video" => process_video_job(db, s3, config, &job).await,
+        "transcode" => process_transcode_job(db, s3, config, &job).await,
         other => {
             warn!("Unknown job type: {}", other);
             Err(anyhow::anyhow!("Unknown job type: {}", other))
@@ -698,6 +701,154 @@ async fn create_video_trailer(db: &Database, s3: &S3Client, job: &MediaJob, inpu
     }))
 }
 
+//=============================================================================
+// Transcoding
+//=============================================================================
+
+async fn process_transcode_job(db: &Database, s3: &S3Client, config: &Config, job: &MediaJob) -> Result<serde_json::Value> {
+    let input: TranscodeInput = serde_json::from_value(job.input.clone())?;
+
+    let source = db.get_file(&input.source_file_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Source file not found"))?;
+
+    let job_dir = config.temp_dir.join(job.id.to_string());
+    fs::create_dir_all(&job_dir).await?;
+
+    let input_path = job_dir.join(&source.filename);
+    s3.download_file(&source.s3_key, &input_path).await?;
+
+    let stem = Path::new(&source.filename)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| source.id.to_string());
+
+    let total = input.variants.len().max(1);
+    let mut outputs = Vec::new();
+
+    for (index, variant) in input.variants.iter().enumerate() {
+        // A job queued twice for the same variant only encodes it once
+        if db.has_derivative(&source.id, &variant.format, variant.bitrate_kbps).await? {
+            continue;
+        }
+
+        let (extension, content_type, codec_args) = transcode_settings(&input.media, variant)?;
+        let object_name = format!("{}.{}", Uuid::new_v4(), extension);
+        let output_path = job_dir.join(&object_name);
+
+        let mut args = vec!["-i".to_string(), input_path.to_string_lossy().to_string()];
+        args.extend(codec_args);
+        args.extend(vec!["-y".to_string(), output_path.to_string_lossy().to_string()]);
+
+        let output = Command::new("ffmpeg")
+            .args(&args)
+            .output()
+            .context("Failed to execute FFmpeg")?;
+
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "FFmpeg transcode to {} {}k failed: {}",
+                variant.format,
+                variant.bitrate_kbps,
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+
+        let duration = probe_duration(&output_path);
+        let file_type = content_type.split('/').next().unwrap_or("audio");
+        let s3_key = format!("{}/{}/{}", job.user_id, file_type, object_name);
+        s3.upload_file(&output_path, &s3_key, content_type).await?;
+
+        let metadata = fs::metadata(&output_path).await?;
+        let filename = format!("{}_{}k.{}", stem, variant.bitrate_kbps, extension);
+
+        let file_id = db.create_file(
+            &job.user_id,
+            &filename,
+            &s3_key,
+            content_type,
+            metadata.len() as i64,
+            file_type,
+        ).await?;
+
+        db.link_derivative(
+            &source.id,
+            &file_id,
+            &job.id,
+            &variant.format,
+            variant.bitrate_kbps,
+            content_type,
+            duration,
+        ).await?;
+
+        outputs.push(serde_json::json!({
+            "file_id": file_id,
+            "format": variant.format,
+            "bitrate_kbps": variant.bitrate_kbps,
+            "content_type": content_type,
+            "size": metadata.len(),
+            "duration_seconds": duration
+        }));
+
+        let progress = ((index + 1) * 100 / total) as i32;
+        db.update_job_status(&job.id, "processing", None, Some(progress)).await?;
+    }
+
+    Ok(serde_json::json!({
+        "source_file_id": source.id,
+        "variants": outputs
+    }))
+}
+
+/// File extension, content type and FFmpeg output options for one variant
+fn transcode_settings(media: &str, variant: &TranscodeVariant) -> Result<(&'static str, &'static str, Vec<String>)> {
+    let bitrate = format!("{}k", variant.bitrate_kbps);
+
+    match (variant.format.as_str(), media) {
+        // Audio-only even when the source is a video
+        ("opus", _) => Ok(("opus", "audio/ogg", vec![
+            "-vn".to_string(),
+            "-c:a".to_string(), "libopus".to_string(),
+            "-b:a".to_string(), bitrate,
+        ])),
+        ("mp4", "video") => Ok(("mp4", "video/mp4", vec![
+            "-c:v".to_string(), "libx264".to_string(),
+            "-b:v".to_string(), bitrate.clone(),
+            "-maxrate".to_string(), bitrate,
+            "-bufsize".to_string(), format!("{}k", variant.bitrate_kbps * 2),
+            "-pix_fmt".to_string(), "yuv420p".to_string(),
+            "-c:a".to_string(), "aac".to_string(),
+            "-b:a".to_string(), "128k".to_string(),
+            "-movflags".to_string(), "+faststart".to_string(),
+        ])),
+        ("mp4", _) => Ok(("m4a", "audio/mp4", vec![
+            "-vn".to_string(),
+            "-c:a".to_string(), "aac".to_string(),
+            "-b:a".to_string(), bitrate,
+            "-movflags".to_string(), "+faststart".to_string(),
+        ])),
+        (other, _) => Err(anyhow::anyhow!("Unknown transcode format: {}", other)),
+    }
+}
+
+/// Duration in seconds as reported by ffprobe, if it can be read
+fn probe_duration(path: &Path) -> Option<f64> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v", "error",
+            "-show_entries", "format=duration",
+            "-of", "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(path)
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
 //=============================================================================
 // Data Models
 //=============================================================================
@@ -780,3 +931,16 @@ struct VideoOptions {
     duration: Option<f64>,
     thumbnail_time: Option<f64>,
 }
+
+#[derive(Debug, Deserialize)]
+struct TranscodeInput {
+    source_file_id: Uuid,
+    media: String,
+    variants: Vec<TranscodeVariant>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TranscodeVariant {
+    format: String,
+    bitrate_kbps: i32,
+}